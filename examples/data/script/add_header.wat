@@ -0,0 +1,19 @@
+;; A script module adding the header `x-script: add_header` to every request.
+;;
+;; The module is instantiated for each request, so it uses a fixed buffer for the input, and
+;; returns the output stored in the data segment as `(ptr << 32) | len`.
+;;
+;; Modules can also be written in any language compiled to `wasm32-unknown-unknown`, e.g. Rust
+;; with `serde_json`, as long as they export the same functions.
+(module
+  (memory (export "memory") 1)
+
+  ;; {"action":"continue","mutations":[{"op":"set_header","name":"x-script","value":"add_header"}]}
+  (data (i32.const 0)
+    "{\"action\":\"continue\",\"mutations\":[{\"op\":\"set_header\",\"name\":\"x-script\",\"value\":\"add_header\"}]}")
+
+  (func (export "alloc") (param $len i32) (result i32)
+    (i32.const 1024))
+
+  (func (export "on_request") (param $ptr i32) (param $len i32) (result i64)
+    (i64.const 94)))
@@ -19,7 +19,7 @@ lazy_static! {
             ])
             .accept_compressions(vec![Gzip(None), Identity])
             .address(addr)
-            .build()
+            .build_unchecked()
     };
 }
 
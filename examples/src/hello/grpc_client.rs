@@ -8,7 +8,7 @@ lazy_static! {
         let addr: SocketAddr = "127.0.0.1:8080".parse().unwrap();
         volo_gen::proto_gen::hello::GreeterClientBuilder::new("hello")
             .address(addr)
-            .build()
+            .build_unchecked()
     };
 }
 
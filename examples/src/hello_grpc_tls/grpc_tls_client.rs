@@ -22,7 +22,8 @@ async fn main() {
     let client = volo_gen::proto_gen::hello::GreeterClientBuilder::new("hello")
         .tls_config(tls_config)
         .address(addr)
-        .build();
+        .build()
+        .unwrap();
 
     let req = volo_gen::proto_gen::hello::HelloRequest {
         name: FastStr::from_static_str("Volo"),
@@ -46,7 +46,7 @@ async fn main() -> Result<(), BoxError> {
             .address("127.0.0.1:8080".parse::<SocketAddr>().unwrap())
             .header("Test", "Test")?
             .fail_on_error_status(true);
-        builder.build()
+        builder.build()?
     };
 
     // set host and override the default one
@@ -99,7 +99,7 @@ async fn main() -> Result<(), BoxError> {
     );
 
     // an empty client
-    let client = ClientBuilder::new().build();
+    let client = ClientBuilder::new().build()?;
     println!(
         "{}",
         client
@@ -0,0 +1,84 @@
+//! Serving the volo-http router by a plain hyper server through the `tower::Service`.
+
+use std::{net::SocketAddr, time::Duration};
+
+use hyper::{body::Incoming, server::conn::http1};
+use hyper_util::{rt::TokioIo, service::TowerToHyperService};
+use tokio::{net::TcpListener, sync::watch, task::JoinSet};
+use tower::ServiceBuilder;
+use volo_http::{
+    server::{
+        embed::{PeerAddr, TowerService},
+        route::get,
+        Router,
+    },
+    Address,
+};
+
+async fn hello(peer: Option<Address>) -> String {
+    match peer {
+        Some(peer) => format!("hello, {peer}\n"),
+        None => "hello, stranger\n".to_owned(),
+    }
+}
+
+#[tokio::main(flavor = "multi_thread")]
+async fn main() {
+    tracing_subscriber::fmt::init();
+
+    let service = TowerService::new(Router::new().route("/", get(hello)));
+
+    let addr: SocketAddr = "[::]:8080".parse().unwrap();
+    let listener = TcpListener::bind(addr).await.unwrap();
+    println!("listening on {addr}");
+
+    let (shutdown_tx, shutdown_rx) = watch::channel(());
+    let mut conns = JoinSet::new();
+
+    loop {
+        let (stream, peer) = tokio::select! {
+            res = listener.accept() => match res {
+                Ok(conn) => conn,
+                Err(e) => {
+                    tracing::warn!("failed to accept: {e}");
+                    continue;
+                }
+            },
+            _ = tokio::signal::ctrl_c() => break,
+        };
+
+        // The peer is known only by the server owning the connections.
+        let peer = Address::from(peer);
+        let service = ServiceBuilder::new()
+            .map_request(move |mut req: http::Request<Incoming>| {
+                req.extensions_mut().insert(PeerAddr(peer.clone()));
+                req
+            })
+            .service(service.clone());
+
+        let mut shutdown_rx = shutdown_rx.clone();
+        conns.spawn(async move {
+            let conn = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), TowerToHyperService::new(service));
+            tokio::pin!(conn);
+            tokio::select! {
+                res = conn.as_mut() => {
+                    if let Err(e) = res {
+                        tracing::warn!("failed to serve connection: {e}");
+                    }
+                }
+                _ = shutdown_rx.changed() => {
+                    conn.as_mut().graceful_shutdown();
+                    let _ = conn.await;
+                }
+            }
+        });
+    }
+
+    println!("shutting down");
+    let _ = shutdown_tx.send(());
+    let _ = tokio::time::timeout(Duration::from_secs(10), async {
+        while conns.join_next().await.is_some() {}
+    })
+    .await;
+}
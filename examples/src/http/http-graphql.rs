@@ -0,0 +1,110 @@
+//! Serving GraphQL along with the REST routes, with the principal authenticated by a middleware
+//! injected into the resolvers.
+//!
+//! ```bash
+//! # open http://127.0.0.1:8080/graphiql for the playground, or
+//! curl http://127.0.0.1:8080/graphql -H 'authorization: Bearer volo' \
+//!     -H 'content-type: application/json' -d '{"query":"{ me { name } books { title } }"}'
+//! ```
+
+use std::{net::SocketAddr, sync::Mutex};
+
+use async_graphql::{Context, EmptySubscription, Object, Schema, SimpleObject};
+use http::header;
+use volo_http::{
+    context::ServerContext,
+    request::ServerRequest,
+    response::ServerResponse,
+    server::{
+        graphql::{graphiql, GraphQL},
+        middleware::{self, Next},
+        route::{any_service, get},
+        Router, Server,
+    },
+    Address,
+};
+
+#[derive(Clone, SimpleObject)]
+struct Principal {
+    name: String,
+}
+
+#[derive(Clone, SimpleObject)]
+struct Book {
+    title: String,
+}
+
+#[derive(Default)]
+struct Library {
+    books: Mutex<Vec<Book>>,
+}
+
+struct Query;
+
+#[Object]
+impl Query {
+    async fn me(&self, cx: &Context<'_>) -> Option<Principal> {
+        cx.data_opt::<Principal>().cloned()
+    }
+
+    async fn books(&self, cx: &Context<'_>) -> Vec<Book> {
+        cx.data_unchecked::<Library>().books.lock().unwrap().clone()
+    }
+}
+
+struct Mutation;
+
+#[Object]
+impl Mutation {
+    async fn add_book(&self, cx: &Context<'_>, title: String) -> async_graphql::Result<Book> {
+        if cx.data_opt::<Principal>().is_none() {
+            return Err("unauthorized".into());
+        }
+        let book = Book { title };
+        cx.data_unchecked::<Library>()
+            .books
+            .lock()
+            .unwrap()
+            .push(book.clone());
+        Ok(book)
+    }
+}
+
+/// Authenticates the bearer token, which is the name of the principal in this example.
+async fn auth(cx: &mut ServerContext, mut req: ServerRequest, next: Next) -> ServerResponse {
+    let name = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(ToOwned::to_owned);
+    if let Some(name) = name {
+        req.extensions_mut().insert(Principal { name });
+    }
+    next.run(cx, req).await
+}
+
+#[tokio::main(flavor = "multi_thread")]
+async fn main() {
+    tracing_subscriber::fmt::init();
+
+    let schema = Schema::build(Query, Mutation, EmptySubscription)
+        .data(Library::default())
+        .finish();
+
+    let app = Router::new()
+        .route("/", get(|| async { "hello, graphql\n" }))
+        .route(
+            "/graphql",
+            any_service(GraphQL::new(schema).extension::<Principal>()),
+        )
+        .route("/graphiql", get(|| async { graphiql("/graphql") }))
+        .layer(middleware::from_fn(auth));
+
+    let addr: SocketAddr = "[::]:8080".parse().unwrap();
+    let addr = Address::from(addr);
+
+    println!("Listening on {addr}");
+
+    Server::new(app).run(addr).await.unwrap();
+}
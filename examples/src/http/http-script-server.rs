@@ -0,0 +1,65 @@
+//! Run with `cargo run --bin http-script-server --features wasm-script`, and test it with:
+//!
+//! ```bash
+//! curl -v http://127.0.0.1:8080/
+//! curl -v http://127.0.0.1:8080/admin
+//! ```
+//!
+//! The modules in `examples/data/script` are reloaded every 5 seconds, so they can be changed
+//! while the server is running.
+
+use std::{net::SocketAddr, time::Duration};
+
+use volo::script::{
+    wasm::{WasmConfig, WasmHooks},
+    FnHook, ScriptHooks, Verdict,
+};
+use volo_http::{
+    request::ServerRequest,
+    server::{
+        layer::ScriptLayer,
+        route::{get, Router},
+        Server,
+    },
+};
+
+async fn index(req: ServerRequest) -> String {
+    format!("{:#?}\n", req.headers())
+}
+
+#[volo::main]
+async fn main() {
+    let subscriber = tracing_subscriber::FmtSubscriber::builder()
+        .with_max_level(tracing::Level::TRACE)
+        .finish();
+    tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+
+    let script_dir =
+        std::path::PathBuf::from_iter([std::env!("CARGO_MANIFEST_DIR"), "data/script"]);
+    let wasm = WasmHooks::load(script_dir, WasmConfig::default()).expect("failed to load modules");
+    let _watcher = wasm.watch(Duration::from_secs(5));
+
+    let hooks = ScriptHooks::new()
+        .hook(FnHook::new("admin", |req| {
+            if req.path.starts_with("/admin") {
+                return Ok(Verdict::reject(403, "admin is not allowed\n"));
+            }
+            Ok(Verdict::pass())
+        }))
+        .hook(wasm);
+
+    let app = Router::new()
+        .route("/", get(index))
+        .route("/admin", get(index));
+
+    let addr: SocketAddr = "[::]:8080".parse().unwrap();
+    let addr = volo::net::Address::from(addr);
+
+    println!("Listening on {addr}");
+
+    Server::new(app)
+        .layer(ScriptLayer::new(hooks))
+        .run(addr)
+        .await
+        .unwrap();
+}
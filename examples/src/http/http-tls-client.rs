@@ -19,7 +19,7 @@ async fn main() {
     let client = {
         let mut builder = Client::builder();
         builder.set_tls_config(connector);
-        builder.build()
+        builder.build().unwrap()
     };
 
     let resp = client
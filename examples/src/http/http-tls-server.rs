@@ -35,7 +35,7 @@ async fn main() {
 
     let app = Router::new()
         .route("/", get(index))
-        .layer(TimeoutLayer::new(Duration::from_secs(5)));
+        .layer(TimeoutLayer::from_duration(Duration::from_secs(5)));
 
     let addr: SocketAddr = "[::]:8080".parse().unwrap();
     let addr = volo::net::Address::from(addr);
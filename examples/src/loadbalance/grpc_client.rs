@@ -25,7 +25,7 @@ lazy_static! {
         volo_gen::proto_gen::hello::GreeterClientBuilder::new("hello")
             .load_balance(lb)
             .discover(discover)
-            .build()
+            .build_unchecked()
     };
 }
 
@@ -0,0 +1,116 @@
+//! The log id is generated by the first hop and propagated through http → grpc → thrift, so all
+//! the servers log the same one.
+//!
+//! ```bash
+//! cargo run --bin log-id
+//! ```
+
+use std::net::SocketAddr;
+
+use lazy_static::lazy_static;
+use volo::context::log_id;
+use volo_http::server::{
+    route::{get, Router},
+    Server,
+};
+
+const HTTP_ADDR: &str = "127.0.0.1:18080";
+const GRPC_ADDR: &str = "127.0.0.1:18081";
+const THRIFT_ADDR: &str = "127.0.0.1:18082";
+
+lazy_static! {
+    static ref GRPC_CLIENT: volo_gen::proto_gen::hello::GreeterClient = {
+        let addr: SocketAddr = GRPC_ADDR.parse().unwrap();
+        volo_gen::proto_gen::hello::GreeterClientBuilder::new("hello")
+            .address(addr)
+            .build_unchecked()
+    };
+    static ref THRIFT_CLIENT: volo_gen::thrift_gen::hello::HelloServiceClient = {
+        let addr: SocketAddr = THRIFT_ADDR.parse().unwrap();
+        volo_gen::thrift_gen::hello::HelloServiceClientBuilder::new("hello")
+            .address(addr)
+            .build_unchecked()
+    };
+}
+
+async fn hello() -> String {
+    tracing::info!(log_id = ?log_id::current(), "http server");
+    let req = volo_gen::proto_gen::hello::HelloRequest {
+        name: "volo".into(),
+    };
+    match GRPC_CLIENT.say_hello(req).await {
+        Ok(resp) => resp.into_inner().message.to_string(),
+        Err(e) => e.to_string(),
+    }
+}
+
+struct Greeter;
+
+impl volo_gen::proto_gen::hello::Greeter for Greeter {
+    async fn say_hello(
+        &self,
+        req: volo_grpc::Request<volo_gen::proto_gen::hello::HelloRequest>,
+    ) -> Result<volo_grpc::Response<volo_gen::proto_gen::hello::HelloReply>, volo_grpc::Status>
+    {
+        tracing::info!(log_id = ?log_id::current(), "grpc server");
+        let req = volo_gen::thrift_gen::hello::HelloRequest {
+            name: req.into_inner().name,
+            common: None,
+            common2: None,
+        };
+        let resp = THRIFT_CLIENT
+            .hello(req)
+            .await
+            .map_err(|e| volo_grpc::Status::internal(e.to_string()))?;
+        Ok(volo_grpc::Response::new(
+            volo_gen::proto_gen::hello::HelloReply {
+                message: resp.message,
+            },
+        ))
+    }
+}
+
+struct HelloService;
+
+impl volo_gen::thrift_gen::hello::HelloService for HelloService {
+    async fn hello(
+        &self,
+        req: volo_gen::thrift_gen::hello::HelloRequest,
+    ) -> Result<volo_gen::thrift_gen::hello::HelloResponse, volo_thrift::ServerError> {
+        tracing::info!(log_id = ?log_id::current(), "thrift server");
+        Ok(volo_gen::thrift_gen::hello::HelloResponse {
+            message: format!("Hello, {}!", req.name).into(),
+        })
+    }
+}
+
+#[volo::main]
+async fn main() {
+    tracing_subscriber::fmt::init();
+
+    let addr = volo::net::Address::from(THRIFT_ADDR.parse::<SocketAddr>().unwrap());
+    tokio::spawn(volo_gen::thrift_gen::hello::HelloServiceServer::new(HelloService).run(addr));
+
+    let addr = volo::net::Address::from(GRPC_ADDR.parse::<SocketAddr>().unwrap());
+    tokio::spawn(
+        volo_grpc::server::Server::new()
+            .add_service(
+                volo_grpc::server::ServiceBuilder::new(
+                    volo_gen::proto_gen::hello::GreeterServer::new(Greeter),
+                )
+                .build(),
+            )
+            .run(addr),
+    );
+
+    let addr = volo::net::Address::from(HTTP_ADDR.parse::<SocketAddr>().unwrap());
+    tokio::spawn(Server::new(Router::new().route("/", get(hello))).run(addr));
+
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    // the client is the first hop, which generates the log id
+    let resp = volo_http::client::get(format!("http://{HTTP_ADDR}/"))
+        .await
+        .unwrap();
+    println!("{}", resp.into_string().await.unwrap());
+}
@@ -8,13 +8,13 @@ lazy_static! {
         let addr: SocketAddr = "127.0.0.1:8080".parse().unwrap();
         volo_gen::proto_gen::hello::GreeterClientBuilder::new("hello")
             .address(addr)
-            .build()
+            .build_unchecked()
     };
     static ref ECHO_CLIENT: volo_gen::proto_gen::echo::EchoClient = {
         let addr: SocketAddr = "127.0.0.1:8080".parse().unwrap();
         volo_gen::proto_gen::echo::EchoClientBuilder::new("echo")
             .address(addr)
-            .build()
+            .build_unchecked()
     };
 }
 
@@ -9,7 +9,7 @@ lazy_static! {
         volo_gen::thrift_gen::hello::HelloServiceClientBuilder::new("hello")
             .address(addr)
             .multiplex(true)
-            .build()
+            .build_unchecked()
     };
 }
 
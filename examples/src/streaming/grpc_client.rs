@@ -10,7 +10,7 @@ lazy_static! {
         let addr: SocketAddr = "127.0.0.1:8080".parse().unwrap();
         volo_gen::proto_gen::streaming::StreamingClientBuilder::new("streaming")
             .address(addr)
-            .build()
+            .build_unchecked()
     };
 }
 
@@ -8,7 +8,7 @@ lazy_static! {
         let addr: SocketAddr = "127.0.0.1:8081".parse().unwrap();
         volo_gen::thrift_gen::echo::EchoServiceClientBuilder::new("hello")
             .address(addr)
-            .build()
+            .build_unchecked()
     };
 }
 
@@ -0,0 +1,102 @@
+//! The generated `{Service}Api` is object-safe, so the clients can be called through
+//! `Box<dyn {Service}Api>`.
+
+use std::net::SocketAddr;
+
+use tokio::net::TcpListener;
+use tokio_stream::StreamExt;
+use volo_gen::proto_gen::streaming::{
+    Streaming, StreamingApi, StreamingClientBuilder, StreamingRequest, StreamingResponse,
+    StreamingServer,
+};
+use volo_grpc::{
+    server::{Server, ServiceBuilder},
+    BoxStream, RecvStream, Request, Response, Status,
+};
+
+struct S;
+
+impl Streaming for S {
+    async fn unary(
+        &self,
+        req: Request<StreamingRequest>,
+    ) -> Result<Response<StreamingResponse>, Status> {
+        Ok(Response::new(StreamingResponse {
+            message: req.into_inner().message,
+        }))
+    }
+
+    async fn client_streaming(
+        &self,
+        _req: Request<RecvStream<StreamingRequest>>,
+    ) -> Result<Response<StreamingResponse>, Status> {
+        Err(Status::unimplemented("client streaming"))
+    }
+
+    async fn server_streaming(
+        &self,
+        req: Request<StreamingRequest>,
+    ) -> Result<Response<BoxStream<'static, Result<StreamingResponse, Status>>>, Status> {
+        let message = req.into_inner().message;
+        let resp = (0..3)
+            .map(|i| {
+                Ok(StreamingResponse {
+                    message: format!("{message}-{i}").into(),
+                })
+            })
+            .collect::<Vec<_>>();
+        Ok(Response::new(Box::pin(tokio_stream::iter(resp))))
+    }
+
+    async fn bidirectional_streaming(
+        &self,
+        _req: Request<RecvStream<StreamingRequest>>,
+    ) -> Result<Response<BoxStream<'static, Result<StreamingResponse, Status>>>, Status> {
+        Err(Status::unimplemented("bidirectional streaming"))
+    }
+}
+
+async fn serve() -> Box<dyn StreamingApi> {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr: SocketAddr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::new()
+            .add_service(ServiceBuilder::new(StreamingServer::new(S)).build())
+            .run(volo::net::DefaultIncoming::from(listener)),
+    );
+
+    let client = StreamingClientBuilder::new("streaming")
+        .address(addr)
+        .build()
+        .unwrap();
+    Box::new(client)
+}
+
+fn request(message: &'static str) -> Request<StreamingRequest> {
+    Request::new(StreamingRequest {
+        message: message.into(),
+    })
+}
+
+#[tokio::test]
+async fn unary() {
+    let client = serve().await;
+
+    let resp = client.unary(request("volo")).await.unwrap();
+    assert_eq!(resp.into_inner().message, "volo");
+}
+
+#[tokio::test]
+async fn server_streaming() {
+    let client = serve().await;
+
+    let messages = client
+        .server_streaming(request("volo"))
+        .await
+        .unwrap()
+        .into_inner()
+        .map(|resp| resp.unwrap().message)
+        .collect::<Vec<_>>()
+        .await;
+    assert_eq!(messages, ["volo-0", "volo-1", "volo-2"]);
+}
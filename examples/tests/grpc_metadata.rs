@@ -0,0 +1,150 @@
+//! The initial and the trailing metadata of the responses sent by the server, which are got by
+//! the client from the metadata of the unary responses, and from the [`StreamingResponse`] of the
+//! server streaming ones.
+//!
+//! [`StreamingResponse`]: volo_grpc::StreamingResponse
+
+use std::net::SocketAddr;
+
+use tokio::net::TcpListener;
+use tokio_stream::StreamExt;
+use volo_gen::proto_gen::streaming::{
+    Streaming, StreamingClient, StreamingClientBuilder, StreamingRequest, StreamingResponse,
+    StreamingServer,
+};
+use volo_grpc::{
+    metadata::MetadataValue,
+    server::{Server, ServiceBuilder},
+    BoxStream, RecvStream, Request, Response, Status,
+};
+
+struct S;
+
+impl Streaming for S {
+    async fn unary(
+        &self,
+        req: Request<StreamingRequest>,
+    ) -> Result<Response<StreamingResponse>, Status> {
+        let mut resp = Response::new(StreamingResponse {
+            message: req.into_inner().message,
+        });
+        resp.metadata_mut()
+            .insert("initial-key", MetadataValue::from_static("unary"));
+        resp.trailers()
+            .lock()
+            .insert("trailing-key", MetadataValue::from_static("unary"));
+        Ok(resp)
+    }
+
+    async fn client_streaming(
+        &self,
+        _req: Request<RecvStream<StreamingRequest>>,
+    ) -> Result<Response<StreamingResponse>, Status> {
+        Err(Status::unimplemented("client streaming"))
+    }
+
+    async fn server_streaming(
+        &self,
+        req: Request<StreamingRequest>,
+    ) -> Result<Response<BoxStream<'static, Result<StreamingResponse, Status>>>, Status> {
+        let message = req.into_inner().message;
+        let mut resp = Response::new(());
+        resp.metadata_mut()
+            .insert("initial-key", MetadataValue::from_static("streaming"));
+
+        // the trailers are updated by the stream after the messages
+        let trailers = resp.trailers();
+        let stream = async_stream::stream! {
+            let mut count = 0;
+            for part in message.split(' ') {
+                count += 1;
+                yield Ok::<_, Status>(StreamingResponse { message: part.to_owned().into() });
+            }
+            trailers
+                .lock()
+                .insert("count", count.to_string().parse().unwrap());
+        };
+        Ok(resp.map(|()| Box::pin(stream) as BoxStream<'static, _>))
+    }
+
+    async fn bidirectional_streaming(
+        &self,
+        _req: Request<RecvStream<StreamingRequest>>,
+    ) -> Result<Response<BoxStream<'static, Result<StreamingResponse, Status>>>, Status> {
+        Err(Status::unimplemented("bidirectional streaming"))
+    }
+}
+
+async fn serve() -> StreamingClient {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr: SocketAddr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::new()
+            .add_service(ServiceBuilder::new(StreamingServer::new(S)).build())
+            .run(volo::net::DefaultIncoming::from(listener)),
+    );
+
+    StreamingClientBuilder::new("streaming")
+        .address(addr)
+        .build()
+        .unwrap()
+}
+
+fn request(message: &'static str) -> StreamingRequest {
+    StreamingRequest {
+        message: message.into(),
+    }
+}
+
+#[tokio::test]
+async fn unary_metadata() {
+    let client = serve().await;
+
+    let resp = client.unary(request("volo")).await.unwrap();
+    // the trailers are merged into the metadata of the unary responses
+    let metadata = resp.metadata();
+    assert_eq!(metadata.get("initial-key").unwrap(), "unary");
+    assert_eq!(metadata.get("trailing-key").unwrap(), "unary");
+    assert_eq!(resp.into_inner().message, "volo");
+}
+
+#[tokio::test]
+async fn server_streaming_metadata() {
+    let client = serve().await;
+
+    let mut resp = client
+        .server_streaming(request("hello volo grpc"))
+        .await
+        .unwrap();
+    // the initial metadata is available before any message
+    assert_eq!(resp.metadata().get("initial-key").unwrap(), "streaming");
+
+    let mut messages = Vec::new();
+    while let Some(message) = resp.message().await.unwrap() {
+        messages.push(message.message.to_string());
+    }
+    assert_eq!(messages, ["hello", "volo", "grpc"]);
+
+    let trailers = resp.trailers().await.unwrap().unwrap();
+    assert_eq!(trailers.get("count").unwrap(), "3");
+    assert!(trailers.get("initial-key").is_none());
+}
+
+#[tokio::test]
+async fn server_streaming_as_response() {
+    let client = serve().await;
+
+    // converted to the `Response` returned by the client before the `StreamingResponse`
+    let resp: Response<RecvStream<StreamingResponse>> = client
+        .server_streaming(request("hello volo"))
+        .await
+        .unwrap()
+        .into();
+    assert_eq!(resp.metadata().get("initial-key").unwrap(), "streaming");
+    let messages = resp
+        .into_inner()
+        .map(|message| message.unwrap().message.to_string())
+        .collect::<Vec<_>>()
+        .await;
+    assert_eq!(messages, ["hello", "volo"]);
+}
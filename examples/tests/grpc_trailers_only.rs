@@ -0,0 +1,102 @@
+//! The errors of the streams ended before any message are received from the trailers-only
+//! responses, and the later ones from the trailers.
+
+use std::net::SocketAddr;
+
+use tokio::net::TcpListener;
+use tokio_stream::StreamExt;
+use volo_gen::proto_gen::streaming::{
+    Streaming, StreamingClient, StreamingClientBuilder, StreamingRequest, StreamingResponse,
+    StreamingServer,
+};
+use volo_grpc::{
+    server::{Server, ServiceBuilder},
+    BoxStream, Code, RecvStream, Request, Response, Status,
+};
+
+struct S;
+
+impl Streaming for S {
+    async fn unary(
+        &self,
+        _req: Request<StreamingRequest>,
+    ) -> Result<Response<StreamingResponse>, Status> {
+        Err(Status::unimplemented("unary"))
+    }
+
+    async fn client_streaming(
+        &self,
+        _req: Request<RecvStream<StreamingRequest>>,
+    ) -> Result<Response<StreamingResponse>, Status> {
+        Err(Status::unimplemented("client streaming"))
+    }
+
+    async fn server_streaming(
+        &self,
+        req: Request<StreamingRequest>,
+    ) -> Result<Response<BoxStream<'static, Result<StreamingResponse, Status>>>, Status> {
+        let message = req.into_inner().message;
+        let resp = match message.as_str() {
+            // fails before any message
+            "fail" => vec![Err(Status::not_found("nothing"))],
+            _ => vec![
+                Ok(StreamingResponse { message }),
+                Err(Status::aborted("after one")),
+            ],
+        };
+        Ok(Response::new(Box::pin(tokio_stream::iter(resp))))
+    }
+
+    async fn bidirectional_streaming(
+        &self,
+        _req: Request<RecvStream<StreamingRequest>>,
+    ) -> Result<Response<BoxStream<'static, Result<StreamingResponse, Status>>>, Status> {
+        Err(Status::unimplemented("bidirectional streaming"))
+    }
+}
+
+async fn serve() -> StreamingClient {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr: SocketAddr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::new()
+            .add_service(ServiceBuilder::new(StreamingServer::new(S)).build())
+            .run(volo::net::DefaultIncoming::from(listener)),
+    );
+
+    StreamingClientBuilder::new("streaming")
+        .address(addr)
+        .build()
+        .unwrap()
+}
+
+fn request(message: &'static str) -> StreamingRequest {
+    StreamingRequest {
+        message: message.into(),
+    }
+}
+
+#[tokio::test]
+async fn trailers_only_error() {
+    let client = serve().await;
+
+    // the status is carried by the headers, so the call itself fails
+    let status = client.server_streaming(request("fail")).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+    assert_eq!(status.message(), "nothing");
+}
+
+#[tokio::test]
+async fn error_in_trailers() {
+    let client = serve().await;
+
+    let mut stream = client
+        .server_streaming(request("volo"))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(stream.next().await.unwrap().unwrap().message, "volo");
+    let status = stream.next().await.unwrap().unwrap_err();
+    assert_eq!(status.code(), Code::Aborted);
+    assert_eq!(status.message(), "after one");
+}
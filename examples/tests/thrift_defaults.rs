@@ -0,0 +1,16 @@
+//! The `Default` of the generated thrift structs honors the default values in the IDL.
+
+use volo_gen::thrift_gen::defaults::{Order, Page};
+
+#[test]
+fn default_values() {
+    let page = Page::default();
+
+    assert_eq!(page.offset, -1);
+    assert_eq!(page.order, Order::DESC);
+    assert_eq!(page.fallback_order, Order::ASC);
+    assert_eq!(page.limit, Some(20));
+    // the fields without default values are still their `Default`
+    assert_eq!(page.cursor, None);
+    assert!(page.name.is_empty());
+}
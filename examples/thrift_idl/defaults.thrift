@@ -0,0 +1,15 @@
+namespace rs defaults
+
+enum Order {
+    ASC = 1,
+    DESC = 2,
+}
+
+struct Page {
+    1: required i32 offset = -1,
+    2: required Order order = Order.DESC,
+    3: required Order fallback_order = 1,
+    4: optional i32 limit = 20,
+    5: optional string cursor,
+    6: required string name,
+}
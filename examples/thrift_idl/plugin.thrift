@@ -0,0 +1,12 @@
+namespace rs plugin
+
+struct Version {
+    1: required i32 major,
+    2: required i32 minor,
+    3: optional string pre,
+}
+
+service VersionService {
+    Version Latest (1: string name),
+    Version LatestV1 (1: string name),
+}
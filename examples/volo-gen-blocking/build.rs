@@ -0,0 +1,9 @@
+fn main() {
+    volo_build::Builder::protobuf()
+        .add_service("../proto/streaming.proto")
+        .include_dirs(vec!["../proto".into()])
+        .filename("blocking_gen.rs".into())
+        .blocking_client(true)
+        .write()
+        .unwrap();
+}
@@ -0,0 +1,125 @@
+//! The generated `{Service}BlockingClient` makes the calls by blocking the current thread, which
+//! is either a plain thread or a thread of `spawn_blocking`.
+
+use std::net::SocketAddr;
+
+use tokio::{net::TcpListener, runtime::Handle};
+use volo_gen_blocking::blocking_gen::streaming::{
+    Streaming, StreamingBlockingClient, StreamingClient, StreamingClientBuilder, StreamingRequest,
+    StreamingResponse, StreamingServer,
+};
+use volo_grpc::{
+    client::blocking::BlockingRuntime,
+    server::{Server, ServiceBuilder},
+    BoxStream, RecvStream, Request, Response, Status,
+};
+
+struct S;
+
+impl Streaming for S {
+    async fn unary(
+        &self,
+        req: Request<StreamingRequest>,
+    ) -> Result<Response<StreamingResponse>, Status> {
+        Ok(Response::new(StreamingResponse {
+            message: req.into_inner().message,
+        }))
+    }
+
+    async fn client_streaming(
+        &self,
+        _req: Request<RecvStream<StreamingRequest>>,
+    ) -> Result<Response<StreamingResponse>, Status> {
+        Err(Status::unimplemented("client streaming"))
+    }
+
+    async fn server_streaming(
+        &self,
+        req: Request<StreamingRequest>,
+    ) -> Result<Response<BoxStream<'static, Result<StreamingResponse, Status>>>, Status> {
+        let message = req.into_inner().message;
+        let resp = (0..3)
+            .map(|i| {
+                Ok(StreamingResponse {
+                    message: format!("{message}-{i}").into(),
+                })
+            })
+            .collect::<Vec<_>>();
+        Ok(Response::new(Box::pin(tokio_stream::iter(resp))))
+    }
+
+    async fn bidirectional_streaming(
+        &self,
+        _req: Request<RecvStream<StreamingRequest>>,
+    ) -> Result<Response<BoxStream<'static, Result<StreamingResponse, Status>>>, Status> {
+        Err(Status::unimplemented("bidirectional streaming"))
+    }
+}
+
+async fn serve() -> StreamingClient {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr: SocketAddr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::new()
+            .add_service(ServiceBuilder::new(StreamingServer::new(S)).build())
+            .run(volo::net::DefaultIncoming::from(listener)),
+    );
+
+    StreamingClientBuilder::new("streaming")
+        .address(addr)
+        .build()
+        .unwrap()
+}
+
+fn request(message: &'static str) -> StreamingRequest {
+    StreamingRequest {
+        message: message.into(),
+    }
+}
+
+/// Makes a unary and a server streaming call by blocking.
+fn call(client: StreamingClient, runtime: BlockingRuntime) {
+    let client = StreamingBlockingClient::new(client, runtime);
+
+    let resp = client.unary(request("volo")).unwrap();
+    assert_eq!(resp.into_inner().message, "volo");
+
+    let messages = client
+        .server_streaming(request("volo"))
+        .unwrap()
+        .map(|resp| resp.unwrap().message)
+        .collect::<Vec<_>>();
+    assert_eq!(messages, ["volo-0", "volo-1", "volo-2"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn plain_thread() {
+    let client = serve().await;
+
+    // the calls are driven by the runtime owned by the blocking client
+    let thread = std::thread::spawn(move || {
+        call(client, BlockingRuntime::current_thread().unwrap());
+    });
+    tokio::task::block_in_place(|| thread.join()).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn spawn_blocking() {
+    let client = serve().await;
+
+    // the calls are driven by the runtime of the test, which serves them as well
+    let runtime = BlockingRuntime::from_handle(Handle::current());
+    tokio::task::spawn_blocking(move || call(client, runtime))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn current_thread_runtime() {
+    let client = serve().await;
+
+    // blocking the only thread of the runtime would stall the calls
+    let client = StreamingBlockingClient::new(client, BlockingRuntime::current_thread().unwrap());
+    let status = client.unary(request("volo")).unwrap_err();
+    assert_eq!(status.code(), volo_grpc::Code::FailedPrecondition);
+}
@@ -0,0 +1,7 @@
+fn main() {
+    volo_build::Builder::protobuf()
+        .add_service("../proto/common.proto")
+        .filename("common_gen.rs".into())
+        .write()
+        .unwrap();
+}
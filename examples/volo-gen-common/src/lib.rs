@@ -0,0 +1,5 @@
+mod gen {
+    include!(concat!(env!("OUT_DIR"), "/common_gen.rs"));
+}
+
+pub use gen::*;
@@ -0,0 +1,9 @@
+fn main() {
+    volo_build::Builder::protobuf()
+        .add_service("../proto/user.proto")
+        .include_dirs(vec!["../proto".into()])
+        .filename("user_gen.rs".into())
+        .extern_path(".common", "volo_gen_common::common_gen::common")
+        .write()
+        .unwrap();
+}
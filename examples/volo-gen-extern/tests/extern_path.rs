@@ -0,0 +1,87 @@
+//! The types of the package mapped by `extern_path` are the ones of `volo-gen-common`, so they
+//! are exchanged with the service of `volo-gen-extern` without any conversion.
+
+use std::net::SocketAddr;
+
+use tokio::net::TcpListener;
+use tokio_stream::StreamExt;
+use volo_gen_common::common_gen::common::{User, UserId};
+use volo_gen_extern::user_gen::user::{
+    ListUsersRequest, UserService, UserServiceClient, UserServiceClientBuilder, UserServiceServer,
+};
+use volo_grpc::{
+    server::{Server, ServiceBuilder},
+    BoxStream, Request, Response, Status,
+};
+
+struct S;
+
+/// Only accepts the type of `volo-gen-common`.
+fn user(id: UserId) -> User {
+    User {
+        id: id.id,
+        name: format!("user-{}", id.id).into(),
+    }
+}
+
+impl UserService for S {
+    async fn get_user(&self, req: Request<UserId>) -> Result<Response<User>, Status> {
+        Ok(Response::new(user(req.into_inner())))
+    }
+
+    async fn list_users(
+        &self,
+        req: Request<ListUsersRequest>,
+    ) -> Result<Response<BoxStream<'static, Result<User, Status>>>, Status> {
+        // the generated `ListUsersRequest` holds the type of `volo-gen-common` as well
+        let ids: Vec<UserId> = req.into_inner().ids;
+        let users = ids.into_iter().map(|id| Ok(user(id))).collect::<Vec<_>>();
+        Ok(Response::new(Box::pin(tokio_stream::iter(users))))
+    }
+}
+
+async fn serve() -> UserServiceClient {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr: SocketAddr = listener.local_addr().unwrap();
+    tokio::spawn(
+        Server::new()
+            .add_service(ServiceBuilder::new(UserServiceServer::new(S)).build())
+            .run(volo::net::DefaultIncoming::from(listener)),
+    );
+
+    UserServiceClientBuilder::new("user")
+        .address(addr)
+        .build()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn unary() {
+    let client = serve().await;
+
+    let resp: User = client
+        .get_user(UserId { id: 1 })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(resp.id, 1);
+    assert_eq!(&*resp.name, "user-1");
+}
+
+#[tokio::test]
+async fn server_streaming() {
+    let client = serve().await;
+
+    let req = ListUsersRequest {
+        ids: vec![UserId { id: 1 }, UserId { id: 2 }],
+    };
+    let users: Vec<User> = client
+        .list_users(req)
+        .await
+        .unwrap()
+        .into_inner()
+        .map(Result::unwrap)
+        .collect()
+        .await;
+    assert_eq!(users.iter().map(|u| u.id).collect::<Vec<_>>(), vec![1, 2]);
+}
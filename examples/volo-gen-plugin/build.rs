@@ -0,0 +1,41 @@
+use std::sync::Arc;
+
+use volo_build::{plugin::walk_item, rir, Context, DefId, Plugin};
+
+/// Derives `PartialOrd` of the structs, and deprecates the methods of the services in
+/// `DEPRECATED`.
+#[derive(Clone, Copy)]
+struct TestPlugin;
+
+const DEPRECATED: &[&str] = &["LatestV1"];
+
+impl Plugin for TestPlugin {
+    fn on_item(&mut self, cx: &Context, def_id: DefId, item: Arc<rir::Item>) {
+        match &*item {
+            rir::Item::Message(_) => {
+                cx.with_adjust_mut(def_id, |adj| {
+                    adj.add_attrs(&["#[derive(PartialOrd)]".into()])
+                });
+            }
+            rir::Item::Service(s) => {
+                for method in s.methods.iter().filter(|m| DEPRECATED.contains(&&*m.name)) {
+                    cx.with_adjust_mut(method.def_id, |adj| {
+                        adj.add_attrs(&["#[deprecated = \"use `latest` instead\"]".into()])
+                    });
+                }
+            }
+            _ => {}
+        }
+
+        walk_item(self, cx, def_id, item)
+    }
+}
+
+fn main() {
+    volo_build::Builder::thrift()
+        .add_service("../thrift_idl/plugin.thrift")
+        .filename("plugin_gen.rs".into())
+        .plugin(TestPlugin)
+        .write()
+        .unwrap();
+}
@@ -0,0 +1,25 @@
+mod gen {
+    include!(concat!(env!("OUT_DIR"), "/plugin_gen.rs"));
+}
+
+pub use gen::*;
+
+#[cfg(test)]
+mod tests {
+    use super::plugin_gen::plugin::Version;
+
+    #[test]
+    fn derived_by_plugin() {
+        let v1 = Version {
+            major: 1,
+            minor: 2,
+            pre: None,
+        };
+        let v2 = Version {
+            major: 1,
+            minor: 10,
+            pre: Some("rc.1".into()),
+        };
+        assert!(v1 < v2);
+    }
+}
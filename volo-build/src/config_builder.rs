@@ -15,6 +15,8 @@ use crate::{
 pub struct ConfigBuilder {
     filename: PathBuf,
     plugins: Vec<BoxClonePlugin>,
+    out_dir: Option<PathBuf>,
+    entry_name: Option<String>,
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -64,6 +66,13 @@ impl InnerBuilder {
         }
     }
 
+    fn out_dir(self, out_dir: &Path) -> Self {
+        match self {
+            InnerBuilder::Protobuf(inner) => InnerBuilder::Protobuf(inner.out_dir(out_dir)),
+            InnerBuilder::Thrift(inner) => InnerBuilder::Thrift(inner.out_dir(out_dir)),
+        }
+    }
+
     fn includes(self, includes: Vec<PathBuf>) -> Self {
         match self {
             InnerBuilder::Protobuf(inner) => InnerBuilder::Protobuf(inner.include_dirs(includes)),
@@ -152,9 +161,24 @@ impl ConfigBuilder {
         ConfigBuilder {
             filename,
             plugins: Vec::new(),
+            out_dir: None,
+            entry_name: None,
         }
     }
 
+    /// Writes the generated code into the `out_dir` instead of the `OUT_DIR` of the build
+    /// script, e.g. for regenerating the code outside of the build.
+    pub fn out_dir<P: AsRef<Path>>(mut self, out_dir: P) -> Self {
+        self.out_dir = Some(out_dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Generates only the entry of the `name`, all the entries are generated by default.
+    pub fn entry_name(mut self, name: impl Into<String>) -> Self {
+        self.entry_name = Some(name.into());
+        self
+    }
+
     pub fn plugin<P: pilota_build::ClonePlugin + 'static>(mut self, p: P) -> Self {
         self.plugins.push(BoxClonePlugin::new(p));
 
@@ -162,25 +186,40 @@ impl ConfigBuilder {
     }
 
     pub fn write(self) -> anyhow::Result<()> {
-        println!("cargo:rerun-if-changed={}", self.filename.display());
+        // the build scripts write to the `OUT_DIR`
+        if self.out_dir.is_none() {
+            println!("cargo:rerun-if-changed={}", self.filename.display());
+        }
         let mut f = open_config_file(self.filename.clone())?;
         let config = read_config_from_file(&mut f)?;
         config
             .entries
             .into_iter()
+            .filter(|(entry_name, _)| {
+                self.entry_name
+                    .as_ref()
+                    .map_or(true, |name| name == entry_name)
+            })
             .try_for_each(|(entry_name, entry)| {
                 let mut builder = match entry.protocol {
                     model::IdlProtocol::Thrift => InnerBuilder::thrift(),
                     model::IdlProtocol::Protobuf => InnerBuilder::protobuf(),
                 }
                 .filename(entry.filename.clone());
+                if let Some(out_dir) = &self.out_dir {
+                    builder = builder.out_dir(out_dir);
+                }
 
                 for p in self.plugins.iter() {
                     builder = builder.plugin(p.clone());
                 }
 
                 // download repos and get the repo paths
-                let target_dir = PathBuf::from(&*DEFAULT_DIR).join(entry_name);
+                let target_dir = match &self.out_dir {
+                    Some(out_dir) => out_dir.join("idl"),
+                    None => PathBuf::from(&*DEFAULT_DIR),
+                }
+                .join(entry_name);
                 let repo_dir_map = download_repos_to_target(&entry.repos, target_dir)?;
 
                 // get idl builders from services
@@ -0,0 +1,471 @@
+//! The descriptors of the generated services, see `volo::descriptor`.
+//!
+//! Each service has a `pub static {SERVICE}_METHODS: &[MethodDescriptor]` and a
+//! `pub static {SERVICE}_SERVICE: ServiceDescriptor` next to it, and the generated file has a
+//! `registry()` of all the services, which is added after the code is generated, as the services
+//! are generated one by one by the backends.
+//!
+//! The thrift methods also have the schemas of their arguments, which are generated as private
+//! statics next to the descriptors, e.g. `__ITEM_SERVICE_STRUCT_0`, so the recursive structs can
+//! refer to each other.
+//!
+//! The annotations of the thrift methods are read from the IDLs by a separate pass, as the
+//! parser of pilota only keeps the annotations known to it. The custom options of the protobuf
+//! methods are not kept by the parser either, so they have no annotations.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{Arc, RwLock},
+};
+
+use anyhow::anyhow;
+use heck::ToShoutySnakeCase;
+use itertools::Itertools;
+use pilota_thrift_parser::{parser::Parser as _, File, Item};
+use quote::ToTokens;
+use syn::Type;
+
+/// The descriptor of a method to be generated.
+pub(crate) struct MethodInfo {
+    pub name: String,
+    pub path: String,
+    pub client_streaming: bool,
+    pub server_streaming: bool,
+    pub request_type: String,
+    pub response_type: String,
+    pub annotations: Vec<(String, String)>,
+    /// The index of the struct of the arguments in the [`RequestSchemas`] of the service.
+    pub request_schema: Option<usize>,
+}
+
+/// The schemas of the arguments of the methods of a service and the types reachable from them,
+/// which refer to each other by their indexes.
+#[derive(Debug, Default)]
+pub(crate) struct RequestSchemas {
+    pub structs: Vec<StructInfo>,
+    pub enums: Vec<EnumInfo>,
+}
+
+#[derive(Debug)]
+pub(crate) struct StructInfo {
+    pub name: String,
+    pub fields: Vec<FieldInfo>,
+}
+
+#[derive(Debug)]
+pub(crate) struct FieldInfo {
+    pub id: i16,
+    pub required: bool,
+    pub ty: TypeInfo,
+}
+
+#[derive(Debug)]
+pub(crate) struct EnumInfo {
+    pub name: String,
+    pub values: Vec<i32>,
+}
+
+/// The type of a field, see `volo::descriptor::TypeSchema`.
+#[derive(Debug, Clone)]
+pub(crate) enum TypeInfo {
+    Value,
+    Struct(usize),
+    Enum(usize),
+    List(Box<TypeInfo>),
+    Set(Box<TypeInfo>),
+    Map(Box<TypeInfo>, Box<TypeInfo>),
+}
+
+impl RequestSchemas {
+    /// Generates the statics of the schemas, and the element types of the containers are
+    /// statics too, as the references in the initializers of the statics can't be promoted.
+    fn codegen(&self, stream: &mut String, prefix: &str) {
+        let mut types = Vec::new();
+        for (i, e) in self.enums.iter().enumerate() {
+            stream.push_str(&format!(
+                r#"
+                static __{prefix}_ENUM_{i}: ::volo::descriptor::EnumSchema = ::volo::descriptor::EnumSchema {{
+                    name: {:?},
+                    values: &[{}],
+                }};
+                "#,
+                e.name,
+                e.values.iter().join(", "),
+            ));
+        }
+        for (i, s) in self.structs.iter().enumerate() {
+            let fields = s
+                .fields
+                .iter()
+                .map(|f| {
+                    format!(
+                        "::volo::descriptor::FieldSchema {{ id: {}, required: {}, ty: {} }}",
+                        f.id,
+                        f.required,
+                        type_schema(&f.ty, prefix, &mut types),
+                    )
+                })
+                .join(",\n");
+            stream.push_str(&format!(
+                r#"
+                static __{prefix}_STRUCT_{i}: ::volo::descriptor::StructSchema = ::volo::descriptor::StructSchema {{
+                    name: {:?},
+                    fields: &[{fields}],
+                }};
+                "#,
+                s.name,
+            ));
+        }
+        for (i, ty) in types.iter().enumerate() {
+            stream.push_str(&format!(
+                "static __{prefix}_TYPE_{i}: ::volo::descriptor::TypeSchema = {ty};\n"
+            ));
+        }
+    }
+}
+
+/// Returns the expression of the type, where the element types are pushed to `types`.
+fn type_schema(ty: &TypeInfo, prefix: &str, types: &mut Vec<String>) -> String {
+    let mut element = |ty: &TypeInfo| {
+        let expr = type_schema(ty, prefix, types);
+        types.push(expr);
+        format!("&__{prefix}_TYPE_{}", types.len() - 1)
+    };
+    match ty {
+        TypeInfo::Value => "::volo::descriptor::TypeSchema::Value".into(),
+        TypeInfo::Struct(i) => {
+            format!("::volo::descriptor::TypeSchema::Struct(&__{prefix}_STRUCT_{i})")
+        }
+        TypeInfo::Enum(i) => format!("::volo::descriptor::TypeSchema::Enum(&__{prefix}_ENUM_{i})"),
+        TypeInfo::List(el) => format!("::volo::descriptor::TypeSchema::List({})", element(el)),
+        TypeInfo::Set(el) => format!("::volo::descriptor::TypeSchema::Set({})", element(el)),
+        TypeInfo::Map(k, v) => {
+            let k = element(k);
+            format!("::volo::descriptor::TypeSchema::Map({k}, {})", element(v))
+        }
+    }
+}
+
+/// Generates the descriptors of the service, where `service_name` is the name of the service in
+/// the generated code and `idl_name` is the one in the IDL.
+pub(crate) fn codegen_service(
+    stream: &mut String,
+    service_name: &str,
+    idl_name: &str,
+    methods: &[MethodInfo],
+    schemas: &RequestSchemas,
+) {
+    let prefix = service_name.to_shouty_snake_case();
+    schemas.codegen(stream, &prefix);
+    let methods = methods
+        .iter()
+        .map(|m| {
+            let annotations = m
+                .annotations
+                .iter()
+                .map(|(k, v)| format!("({k:?}, {v:?})"))
+                .join(", ");
+            let request_schema = match m.request_schema {
+                Some(i) => format!("Some(&__{prefix}_STRUCT_{i})"),
+                None => "None".into(),
+            };
+            format!(
+                r#"::volo::descriptor::MethodDescriptor {{
+                    service: {idl_name:?},
+                    name: {:?},
+                    path: {:?},
+                    client_streaming: {},
+                    server_streaming: {},
+                    request_type: {:?},
+                    response_type: {:?},
+                    annotations: &[{annotations}],
+                    request_schema: {request_schema},
+                }}"#,
+                m.name,
+                m.path,
+                m.client_streaming,
+                m.server_streaming,
+                m.request_type,
+                m.response_type,
+            )
+        })
+        .join(",\n");
+
+    stream.push_str(&format!(
+        r#"
+        pub static {prefix}_METHODS: &[::volo::descriptor::MethodDescriptor] = &[{methods}];
+
+        pub static {prefix}_SERVICE: ::volo::descriptor::ServiceDescriptor = ::volo::descriptor::ServiceDescriptor {{
+            name: {idl_name:?},
+            methods: {prefix}_METHODS,
+        }};
+        "#
+    ));
+}
+
+/// The annotations of the thrift methods by the names of the services and the methods in the
+/// IDLs, shared by the builder loading them and the backend generating the code.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MethodAnnotations {
+    inner: Arc<RwLock<HashMap<(String, String), Vec<(String, String)>>>>,
+}
+
+impl MethodAnnotations {
+    pub fn get(&self, service: &str, method: &str) -> Vec<(String, String)> {
+        self.inner
+            .read()
+            .unwrap()
+            .get(&(service.to_owned(), method.to_owned()))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Loads the annotations of the thrift IDLs and the IDLs included by them, and the others,
+    /// e.g. the protobuf IDLs, are skipped.
+    pub fn load(&self, idls: &[PathBuf], include_dirs: &[PathBuf]) -> anyhow::Result<()> {
+        let mut annotations = self.inner.write().unwrap();
+        let mut pending = idls
+            .iter()
+            .filter(|path| path.extension().is_some_and(|ext| ext == "thrift"))
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut loaded = Vec::new();
+        while let Some(path) = pending.pop() {
+            if loaded.contains(&path) {
+                continue;
+            }
+            let content = fs::read_to_string(&path)?;
+            let (_, file) = File::parse(&content)
+                .map_err(|err| anyhow!("failed to parse {}: {err}", path.display()))?;
+            for item in file.items {
+                match item {
+                    Item::Include(include) => {
+                        let include = include.path.0.to_string();
+                        if let Some(path) = resolve_include(&path, include_dirs, &include) {
+                            pending.push(path);
+                        }
+                    }
+                    Item::Service(service) => {
+                        for function in service.functions {
+                            let kvs = function
+                                .annotations
+                                .iter()
+                                .map(|a| (a.key.to_string(), a.value.0.to_string()))
+                                .collect::<Vec<_>>();
+                            if !kvs.is_empty() {
+                                annotations.insert(
+                                    (service.name.0.to_string(), function.name.0.to_string()),
+                                    kvs,
+                                );
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            loaded.push(path);
+        }
+        Ok(())
+    }
+}
+
+fn resolve_include(from: &Path, include_dirs: &[PathBuf], include: &str) -> Option<PathBuf> {
+    from.parent()
+        .into_iter()
+        .chain(include_dirs.iter().map(PathBuf::as_path))
+        .map(|dir| dir.join(include))
+        .find(|path| path.exists())
+}
+
+/// Adds the `registry()` of all the services generated in the file.
+pub(crate) fn write_registry(path: &Path) -> anyhow::Result<()> {
+    let code = fs::read_to_string(path)?;
+    fs::write(path, add_registry(&code)?)?;
+    let _ = Command::new("rustfmt")
+        .arg("--edition")
+        .arg("2021")
+        .arg(path)
+        .status();
+    Ok(())
+}
+
+pub(crate) fn add_registry(code: &str) -> anyhow::Result<String> {
+    let mut file = syn::parse_file(code)?;
+    // the items are in a single module of the file, e.g. `pub mod volo_gen`
+    let items = if matches!(file.items.as_slice(), [syn::Item::Mod(m)] if m.content.is_some()) {
+        match &mut file.items[0] {
+            syn::Item::Mod(syn::ItemMod {
+                content: Some((_, items)),
+                ..
+            }) => items,
+            _ => unreachable!(),
+        }
+    } else {
+        &mut file.items
+    };
+
+    let mut services = Vec::new();
+    collect_services(items, &mut Vec::new(), &mut services);
+    let services = services.iter().map(|path| format!("&{path}")).join(", ");
+    items.push(syn::parse_str(&format!(
+        r#"pub fn registry() -> &'static ::volo::descriptor::Registry {{
+            static REGISTRY: ::volo::descriptor::Registry = ::volo::descriptor::Registry::new(&[{services}]);
+            &REGISTRY
+        }}"#
+    ))?);
+
+    Ok(file.into_token_stream().to_string())
+}
+
+fn collect_services(items: &[syn::Item], modules: &mut Vec<String>, services: &mut Vec<String>) {
+    for item in items {
+        match item {
+            syn::Item::Static(s) if is_service_descriptor(&s.ty) => {
+                services.push(
+                    modules
+                        .iter()
+                        .cloned()
+                        .chain([s.ident.to_string()])
+                        .join("::"),
+                );
+            }
+            syn::Item::Mod(m) => {
+                if let Some((_, items)) = &m.content {
+                    modules.push(m.ident.to_string());
+                    collect_services(items, modules, services);
+                    modules.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn is_service_descriptor(ty: &Type) -> bool {
+    matches!(ty, Type::Path(p) if p.path.segments.last().is_some_and(|s| s.ident == "ServiceDescriptor"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_of_nested_services() {
+        let mut stream = String::from("pub mod volo_gen { pub mod hello { ");
+        codegen_service(
+            &mut stream,
+            "Greeter",
+            "hello.Greeter",
+            &[MethodInfo {
+                name: "SayHello".into(),
+                path: "/hello.Greeter/SayHello".into(),
+                client_streaming: false,
+                server_streaming: true,
+                request_type: "HelloRequest".into(),
+                response_type: "HelloReply".into(),
+                annotations: vec![("auth".into(), "public".into())],
+                request_schema: None,
+            }],
+            &RequestSchemas::default(),
+        );
+        stream.push_str("pub mod admin { ");
+        codegen_service(
+            &mut stream,
+            "ItemAdmin",
+            "ItemAdmin",
+            &[],
+            &RequestSchemas::default(),
+        );
+        stream.push_str("} } }");
+
+        let code = add_registry(&stream).unwrap();
+        let file = syn::parse_file(&code).unwrap();
+        let syn::Item::Mod(volo_gen) = &file.items[0] else {
+            panic!("expected the module");
+        };
+        let items = &volo_gen.content.as_ref().unwrap().1;
+        let registry = items.last().unwrap().to_token_stream().to_string();
+        assert!(registry.contains("pub fn registry"));
+        assert!(registry.contains("& hello :: GREETER_SERVICE"));
+        assert!(registry.contains("& hello :: admin :: ITEM_ADMIN_SERVICE"));
+        assert!(code.contains(r#""auth" , "public""#));
+        assert!(code.contains("server_streaming : true"));
+    }
+
+    #[test]
+    fn recursive_request_schemas() {
+        // struct Node { 1: required list<Node> children, 2: optional map<i32, Status> status }
+        let schemas = RequestSchemas {
+            structs: vec![
+                StructInfo {
+                    name: "ItemServiceGetItemArgsRecv".into(),
+                    fields: vec![FieldInfo {
+                        id: 1,
+                        required: false,
+                        ty: TypeInfo::Struct(1),
+                    }],
+                },
+                StructInfo {
+                    name: "Node".into(),
+                    fields: vec![
+                        FieldInfo {
+                            id: 1,
+                            required: true,
+                            ty: TypeInfo::List(Box::new(TypeInfo::Struct(1))),
+                        },
+                        FieldInfo {
+                            id: 2,
+                            required: false,
+                            ty: TypeInfo::Map(
+                                Box::new(TypeInfo::Value),
+                                Box::new(TypeInfo::Enum(0)),
+                            ),
+                        },
+                    ],
+                },
+            ],
+            enums: vec![EnumInfo {
+                name: "Status".into(),
+                values: vec![0, 1],
+            }],
+        };
+        let mut stream = String::new();
+        codegen_service(
+            &mut stream,
+            "ItemService",
+            "ItemService",
+            &[MethodInfo {
+                name: "GetItem".into(),
+                path: "GetItem".into(),
+                client_streaming: false,
+                server_streaming: false,
+                request_type: "ItemServiceGetItemArgsRecv".into(),
+                response_type: "ItemServiceGetItemResultSend".into(),
+                annotations: Vec::new(),
+                request_schema: Some(0),
+            }],
+            &schemas,
+        );
+
+        let code = syn::parse_file(&stream)
+            .unwrap()
+            .into_token_stream()
+            .to_string();
+        assert!(code.contains("request_schema : Some (& __ITEM_SERVICE_STRUCT_0)"));
+        assert!(code.contains(
+            "static __ITEM_SERVICE_TYPE_0 : :: volo :: descriptor :: TypeSchema = :: volo :: \
+             descriptor :: TypeSchema :: Struct (& __ITEM_SERVICE_STRUCT_1)"
+        ));
+        assert!(code.contains(
+            "ty : :: volo :: descriptor :: TypeSchema :: List (& __ITEM_SERVICE_TYPE_0)"
+        ));
+        assert!(code.contains(
+            "ty : :: volo :: descriptor :: TypeSchema :: Map (& __ITEM_SERVICE_TYPE_1 , & \
+             __ITEM_SERVICE_TYPE_2)"
+        ));
+        assert!(code.contains("values : & [0 , 1]"));
+    }
+}
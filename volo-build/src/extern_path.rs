@@ -0,0 +1,319 @@
+//! Mapping the IDL packages or namespaces to the existing Rust paths, so the generated code reuses
+//! the types generated in another crate instead of re-emitting them.
+//!
+//! For example, with `.extern_path(".common", "common_types")`, the types of the package `common`
+//! in the client and server signatures and in the request and response enums are rendered as
+//! `::common_types::Foo`, and the module of the package is replaced with a re-export of
+//! `::common_types`, so the crates exchange the same types at the API boundaries.
+
+use std::{
+    fmt::{self, Display},
+    fs,
+    path::Path,
+    process::Command,
+};
+
+use heck::ToSnakeCase;
+use pilota_build::{
+    ty::{CodegenTy, TyKind},
+    Context, DefId,
+};
+use quote::ToTokens;
+use syn::Item;
+use volo::FastStr;
+
+#[derive(Debug, Clone, Default)]
+pub struct ExternPaths {
+    /// The module path of the package relative to the root of the generated code, and the Rust
+    /// path replacing it.
+    paths: Vec<(Vec<String>, String)>,
+}
+
+impl ExternPaths {
+    /// Maps the package, e.g. `.common` or `common.v1`, to the Rust path, e.g. `common_types` or
+    /// `crate::common`.
+    ///
+    /// A Rust path without `crate`, `self` or `super` is treated as an external crate.
+    pub fn insert(&mut self, package: impl AsRef<str>, rust_path: impl AsRef<str>) {
+        let module = package
+            .as_ref()
+            .trim_start_matches('.')
+            .split('.')
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_snake_case())
+            .collect::<Vec<_>>();
+        let rust_path = rust_path.as_ref().trim_end_matches("::");
+        let rust_path = match rust_path.split("::").next() {
+            Some("" | "crate" | "self" | "super") => rust_path.to_owned(),
+            _ => format!("::{rust_path}"),
+        };
+        if let Err(e) = syn::parse_str::<syn::Path>(&rust_path) {
+            panic!("invalid rust path `{rust_path}` for extern path: {e}");
+        }
+
+        self.paths.retain(|(m, _)| *m != module);
+        self.paths.push((module, rust_path));
+        // the longest package is matched first
+        self.paths.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    /// Maps the global path of a generated item, e.g. `::common::Foo`, which is relative to the
+    /// root of the generated code.
+    pub fn map_global(&self, path: &str) -> Option<String> {
+        let segments = path
+            .strip_prefix("::")?
+            .split("::")
+            .map(ToOwned::to_owned)
+            .collect::<Vec<_>>();
+        self.map_segments(&segments)
+    }
+
+    fn map_segments(&self, segments: &[String]) -> Option<String> {
+        let (module, rust_path) = self
+            .paths
+            .iter()
+            .find(|(module, _)| segments.len() > module.len() && segments.starts_with(module))?;
+        Some(
+            std::iter::once(rust_path.as_str())
+                .chain(segments[module.len()..].iter().map(String::as_str))
+                .collect::<Vec<_>>()
+                .join("::"),
+        )
+    }
+
+    /// Maps the generated item, which is rendered by its extern path if it's in a mapped package.
+    fn map_item(&self, cx: &Context, did: DefId) -> Option<String> {
+        let segments = cx
+            .item_path(did)
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>();
+        self.map_segments(&segments)
+    }
+
+    /// The path of the item relative to the current one, or its extern path if it's mapped.
+    pub(crate) fn related_item_path(&self, cx: &Context, did: DefId) -> FastStr {
+        match self.map_item(cx, did) {
+            Some(path) => path.into(),
+            None => cx.cur_related_item_path(did),
+        }
+    }
+
+    /// The type of the generated code, where the items in the mapped packages are rendered by
+    /// their extern paths.
+    pub(crate) fn codegen_item_ty<'a>(&'a self, cx: &'a Context, kind: TyKind) -> ExternTy<'a> {
+        ExternTy {
+            extern_paths: self,
+            cx,
+            ty: cx.codegen_item_ty(kind),
+        }
+    }
+
+    /// Replaces the modules of the mapped packages in the generated file with the re-exports of
+    /// the extern paths, so the references to them in the generated items are still valid.
+    pub fn rewrite_file(&self, path: &Path) -> anyhow::Result<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        let code = fs::read_to_string(path)?;
+        fs::write(path, self.rewrite(&code)?)?;
+        // the formatting is lost after rewriting, and it's fine if `rustfmt` is not installed
+        let _ = Command::new("rustfmt")
+            .arg("--edition")
+            .arg("2021")
+            .arg(path)
+            .status();
+        Ok(())
+    }
+
+    pub(crate) fn rewrite(&self, code: &str) -> anyhow::Result<String> {
+        let mut file = syn::parse_file(code)?;
+        // the generated code is wrapped in a module named after the file
+        let items = if matches!(file.items.as_slice(), [Item::Mod(m)] if m.content.is_some()) {
+            match &mut file.items[0] {
+                Item::Mod(syn::ItemMod {
+                    content: Some((_, items)),
+                    ..
+                }) => items,
+                _ => unreachable!(),
+            }
+        } else {
+            &mut file.items
+        };
+        self.reexport_modules(items, &mut Vec::new())?;
+
+        Ok(file.into_token_stream().to_string())
+    }
+
+    fn reexport_modules(&self, items: &mut [Item], modules: &mut Vec<String>) -> syn::Result<()> {
+        for item in items {
+            let Item::Mod(syn::ItemMod {
+                ident,
+                content: Some((_, items)),
+                ..
+            }) = item
+            else {
+                continue;
+            };
+            modules.push(ident.to_string());
+            match self.paths.iter().find(|(module, _)| module == modules) {
+                Some((_, rust_path)) => {
+                    *items = vec![syn::parse_str(&format!("pub use {rust_path}::*;"))?]
+                }
+                None => self.reexport_modules(items, modules)?,
+            }
+            modules.pop();
+        }
+        Ok(())
+    }
+}
+
+/// The [`CodegenTy`] rendered by [`ExternPaths`], see [`ExternPaths::codegen_item_ty`].
+pub(crate) struct ExternTy<'a> {
+    extern_paths: &'a ExternPaths,
+    cx: &'a Context,
+    ty: CodegenTy,
+}
+
+impl ExternTy<'_> {
+    /// The path relative to the root of the generated code, which is mapped by
+    /// [`ExternPaths::map_global`] when used outside the generated code.
+    pub(crate) fn global_path(&self) -> FastStr {
+        self.ty.global_path()
+    }
+
+    fn fmt_ty(&self, ty: &CodegenTy, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match ty {
+            CodegenTy::Adt(def) => match self.extern_paths.map_item(self.cx, def.did) {
+                Some(path) => f.write_str(&path),
+                None => write!(f, "{ty}"),
+            },
+            CodegenTy::LazyStaticRef(ty) => self.fmt_ty(ty, f),
+            CodegenTy::StaticRef(ty) => {
+                f.write_str("&'static ")?;
+                self.fmt_ty(ty, f)
+            }
+            CodegenTy::Vec(ty) => {
+                f.write_str("::std::vec::Vec<")?;
+                self.fmt_ty(ty, f)?;
+                f.write_str(">")
+            }
+            CodegenTy::Array(ty, size) => {
+                f.write_str("[")?;
+                self.fmt_ty(ty, f)?;
+                write!(f, "; {size}]")
+            }
+            CodegenTy::Set(ty) => {
+                f.write_str("::pilota::AHashSet<")?;
+                self.fmt_ty(ty, f)?;
+                f.write_str(">")
+            }
+            CodegenTy::Map(k, v) => {
+                f.write_str("::pilota::AHashMap<")?;
+                self.fmt_ty(k, f)?;
+                f.write_str(", ")?;
+                self.fmt_ty(v, f)?;
+                f.write_str(">")
+            }
+            CodegenTy::Arc(ty) => {
+                f.write_str("::std::sync::Arc<")?;
+                self.fmt_ty(ty, f)?;
+                f.write_str(">")
+            }
+            _ => write!(f, "{ty}"),
+        }
+    }
+}
+
+impl Display for ExternTy<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_ty(&self.ty, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extern_paths() -> ExternPaths {
+        let mut paths = ExternPaths::default();
+        paths.insert(".common", "common_types");
+        paths.insert("shared.v1", "crate::shared");
+        paths
+    }
+
+    #[test]
+    fn map_global() {
+        let paths = extern_paths();
+        assert_eq!(
+            paths.map_global("::common::Foo").as_deref(),
+            Some("::common_types::Foo")
+        );
+        assert_eq!(
+            paths.map_global("::shared::v1::Bar").as_deref(),
+            Some("crate::shared::Bar")
+        );
+        assert_eq!(paths.map_global("::hello::Foo"), None);
+        assert_eq!(paths.map_global("::common"), None);
+    }
+
+    #[test]
+    fn rewrite() {
+        let code = r#"
+            pub mod volo_gen {
+                pub mod common {
+                    pub struct Foo {}
+                }
+                pub mod shared {
+                    pub mod v1 {
+                        pub struct Bar {}
+                    }
+                    pub mod v2 {
+                        pub struct Baz {}
+                    }
+                }
+                pub mod hello {
+                    pub struct Request {
+                        pub foo: super::common::Foo,
+                        pub bars: ::std::vec::Vec<super::shared::v1::Bar>,
+                        pub baz: super::shared::v2::Baz,
+                    }
+                }
+            }
+        "#;
+        let expected = syn::parse_file(
+            r#"
+            pub mod volo_gen {
+                pub mod common {
+                    pub use ::common_types::*;
+                }
+                pub mod shared {
+                    pub mod v1 {
+                        pub use crate::shared::*;
+                    }
+                    pub mod v2 {
+                        pub struct Baz {}
+                    }
+                }
+                pub mod hello {
+                    pub struct Request {
+                        pub foo: super::common::Foo,
+                        pub bars: ::std::vec::Vec<super::shared::v1::Bar>,
+                        pub baz: super::shared::v2::Baz,
+                    }
+                }
+            }
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            extern_paths().rewrite(code).unwrap(),
+            expected.into_token_stream().to_string()
+        );
+    }
+}
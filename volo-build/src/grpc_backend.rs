@@ -1,14 +1,202 @@
+use std::{collections::HashMap, fmt::Display, sync::Arc};
+
 use itertools::Itertools;
 use pilota_build::{
     db::RirDatabase,
     rir,
     rir::Method,
     tags::protobuf::{ClientStreaming, ServerStreaming},
+    ty::TyKind,
     CodegenBackend, Context, DefId, IdentName, Symbol,
 };
 use volo::FastStr;
 
-pub struct MkGrpcBackend;
+use crate::{
+    descriptor::{self, MethodInfo},
+    extern_path::{ExternPaths, ExternTy},
+    serde_plugin::{codegen_enum_names, SerdeEnumRepr},
+};
+
+#[derive(Default)]
+pub struct MkGrpcBackend {
+    extern_paths: ExternPaths,
+    composite_server: bool,
+    stream_sender: bool,
+    blocking_client: bool,
+    serde: Option<SerdeEnumRepr>,
+    well_known_types: bool,
+    raw_types_methods: Vec<String>,
+    codecs: HashMap<String, String>,
+}
+
+impl MkGrpcBackend {
+    pub fn new(extern_paths: ExternPaths) -> Self {
+        Self {
+            extern_paths,
+            composite_server: false,
+            stream_sender: false,
+            blocking_client: false,
+            serde: None,
+            well_known_types: false,
+            raw_types_methods: Vec::new(),
+            codecs: HashMap::new(),
+        }
+    }
+
+    /// Generates the `{Service}Composite` for each service.
+    ///
+    /// Default is `false`.
+    pub fn composite_server(mut self, composite_server: bool) -> Self {
+        self.composite_server = composite_server;
+        self
+    }
+
+    /// Generates the methods with streaming responses receiving a `volo_grpc::server::Sender`
+    /// instead of returning a stream.
+    ///
+    /// Default is `false`.
+    pub fn stream_sender(mut self, stream_sender: bool) -> Self {
+        self.stream_sender = stream_sender;
+        self
+    }
+
+    /// Generates the `{Service}BlockingClient` for each service in addition, see
+    /// `volo_grpc::client::blocking`.
+    ///
+    /// Default is `false`.
+    pub fn blocking_client(mut self, blocking_client: bool) -> Self {
+        self.blocking_client = blocking_client;
+        self
+    }
+
+    /// Generates the serde impls of the enums by names with [`SerdeEnumRepr::Name`], and the
+    /// other serde attributes are added by the [`SerdePlugin`](crate::serde_plugin::SerdePlugin).
+    ///
+    /// Default is `None`.
+    pub fn serde(mut self, serde: Option<SerdeEnumRepr>) -> Self {
+        self.serde = serde;
+        self
+    }
+
+    /// Maps the well-known types of the unary methods to the idiomatic Rust types in the
+    /// signatures of the service traits and the client methods, see [`WellKnown`].
+    ///
+    /// Default is `false`.
+    pub fn well_known_types(mut self, well_known_types: bool) -> Self {
+        self.well_known_types = well_known_types;
+        self
+    }
+
+    /// Keeps the raw types in the signatures of the methods, e.g. `/helloworld.Greeter/SayHello`,
+    /// with [`Self::well_known_types`].
+    pub fn raw_types_methods(mut self, methods: impl IntoIterator<Item = String>) -> Self {
+        self.raw_types_methods = methods.into_iter().collect();
+        self
+    }
+
+    /// Encodes the messages of the services by the custom codecs for `application/grpc+{subtype}`,
+    /// by the names of the services in the IDL, e.g. `helloworld.Greeter`, and the paths of the
+    /// types implementing `volo_grpc::codec::Codec` for all the messages of the services.
+    pub fn codecs(mut self, codecs: HashMap<String, String>) -> Self {
+        self.codecs = codecs;
+        self
+    }
+}
+
+/// The well-known types of protobuf mapped to the idiomatic Rust types in the signatures:
+///
+/// - `google.protobuf.Empty`: no argument in the requests, and `()` as the responses.
+/// - The wrappers, e.g. `google.protobuf.StringValue`: the scalars, e.g. `FastStr`.
+/// - `google.protobuf.Timestamp`: `std::time::SystemTime`.
+/// - `google.protobuf.Duration`: `std::time::Duration`.
+///
+/// The messages on the wire are still the generated types, which are converted at the boundary of
+/// the generated code, and the values out of range are saturated, see `volo_grpc::well_known`.
+/// Only the unary methods are mapped, and the streaming ones keep the raw types.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WellKnown {
+    Empty,
+    Wrapper(&'static str),
+    Timestamp,
+    Duration,
+}
+
+impl WellKnown {
+    /// Returns the well-known type by the path of the generated type, e.g.
+    /// `::google::protobuf::Empty`.
+    pub fn from_path(path: &str) -> Option<Self> {
+        let mut segments = path.rsplit("::");
+        let name = segments.next()?;
+        if segments.next()? != "protobuf" || segments.next()? != "google" {
+            return None;
+        }
+        Some(match name {
+            "Empty" => Self::Empty,
+            "StringValue" => Self::Wrapper("::pilota::FastStr"),
+            "BytesValue" => Self::Wrapper("::pilota::Bytes"),
+            "BoolValue" => Self::Wrapper("bool"),
+            "Int32Value" => Self::Wrapper("i32"),
+            "Int64Value" => Self::Wrapper("i64"),
+            "UInt32Value" => Self::Wrapper("u32"),
+            "UInt64Value" => Self::Wrapper("u64"),
+            "FloatValue" => Self::Wrapper("f32"),
+            "DoubleValue" => Self::Wrapper("f64"),
+            "Timestamp" => Self::Timestamp,
+            "Duration" => Self::Duration,
+            _ => return None,
+        })
+    }
+
+    /// The type in the signatures, which is `()` for `Empty`.
+    fn rust_ty(self) -> &'static str {
+        match self {
+            Self::Empty => "()",
+            Self::Wrapper(ty) => ty,
+            Self::Timestamp => "::std::time::SystemTime",
+            Self::Duration => "::std::time::Duration",
+        }
+    }
+
+    /// The expression converting the message `m` into the type in the signatures.
+    fn from_raw(self) -> &'static str {
+        match self {
+            Self::Empty => "()",
+            Self::Wrapper(_) => "m.value",
+            Self::Timestamp => {
+                "::volo_grpc::well_known::timestamp_to_system_time(m.seconds, m.nanos)"
+            }
+            Self::Duration => "::volo_grpc::well_known::duration_to_std(m.seconds, m.nanos)",
+        }
+    }
+
+    /// The expression converting the value `v` in the signatures into the message `raw_ty`.
+    fn into_raw(self, raw_ty: &str) -> String {
+        let default = format!("<{raw_ty} as ::std::default::Default>::default()");
+        match self {
+            Self::Empty => default,
+            Self::Wrapper(_) => format!("{{ let mut m = {default}; m.value = v; m }}"),
+            Self::Timestamp | Self::Duration => {
+                let convert = if self == Self::Timestamp {
+                    "system_time_to_timestamp"
+                } else {
+                    "std_to_duration"
+                };
+                format!(
+                    "{{ let (seconds, nanos) = ::volo_grpc::well_known::{convert}(v); let mut m = \
+                     {default}; m.seconds = seconds; m.nanos = nanos; m }}"
+                )
+            }
+        }
+    }
+
+    /// The default value of the type in the signatures used by the generated implementations.
+    fn default_value(self) -> &'static str {
+        match self {
+            Self::Timestamp => "::std::time::UNIX_EPOCH",
+            _ => "::std::default::Default::default()",
+        }
+    }
+}
 
 impl pilota_build::MakeBackend for MkGrpcBackend {
     type Target = VoloGrpcBackend;
@@ -16,6 +204,14 @@ impl pilota_build::MakeBackend for MkGrpcBackend {
     fn make_backend(self, context: Context) -> Self::Target {
         VoloGrpcBackend {
             inner: pilota_build::ProtobufBackend::new(context),
+            extern_paths: Arc::new(self.extern_paths),
+            composite_server: self.composite_server,
+            stream_sender: self.stream_sender,
+            blocking_client: self.blocking_client,
+            serde: self.serde,
+            well_known_types: self.well_known_types,
+            raw_types_methods: Arc::new(self.raw_types_methods),
+            codecs: Arc::new(self.codecs),
         }
     }
 }
@@ -23,18 +219,243 @@ impl pilota_build::MakeBackend for MkGrpcBackend {
 #[derive(Clone)]
 pub struct VoloGrpcBackend {
     inner: pilota_build::ProtobufBackend,
+    extern_paths: Arc<ExternPaths>,
+    composite_server: bool,
+    stream_sender: bool,
+    blocking_client: bool,
+    serde: Option<SerdeEnumRepr>,
+    well_known_types: bool,
+    raw_types_methods: Arc<Vec<String>>,
+    codecs: Arc<HashMap<String, String>>,
 }
 
 impl VoloGrpcBackend {
+    /// The path of the item used outside the generated code, e.g. in the code of `volo init`.
+    fn global_path(&self, path: String) -> String {
+        self.extern_paths
+            .map_global(&path)
+            .unwrap_or_else(|| format!("volo_gen{path}"))
+    }
+
+    /// The type of the generated code, with the items in the packages mapped by the extern paths
+    /// rendered by those paths.
+    fn codegen_item_ty(&self, kind: TyKind) -> ExternTy<'_> {
+        self.extern_paths.codegen_item_ty(self.cx(), kind)
+    }
+
+    /// Generates the `into_body_with` and `from_body_with` of the messages of the service for the
+    /// codecs other than protobuf, which are JSON with serde and the custom codec of the service,
+    /// where `tys` are the types of the messages.
+    fn codegen_codecs(
+        &self,
+        service: &str,
+        variants: &[impl Display],
+        paths: &[String],
+        tys: &[impl Display],
+    ) -> (String, String) {
+        let custom = self.codecs.get(service);
+        if self.serde.is_none() && custom.is_none() {
+            return Default::default();
+        }
+
+        let mut send_arms = String::new();
+        let mut recv_arms = String::new();
+        // with serde, the messages can also be encoded as JSON by `application/grpc+json`
+        if self.serde.is_some() {
+            let encode = variants
+                .iter()
+                .map(|v| {
+                    format!(
+                        "Self::{v}(s) => ::volo_grpc::codec::encode::encode_json(s, \
+                         compression_encoding),"
+                    )
+                })
+                .join("");
+            let decode = paths
+                .iter()
+                .zip(variants)
+                .map(|(p, v)| {
+                    format!(
+                        "Some(\"{p}\") => \
+                         ::std::result::Result::Ok(Self::{v}(::volo_grpc::RecvStream::new_json(\
+                         body, kind, compression_encoding, limits))),"
+                    )
+                })
+                .join("");
+            send_arms.push_str(&format!(
+                "::volo_grpc::codec::MessageCodec::Json => match self {{ {encode} }},"
+            ));
+            recv_arms.push_str(&format!(
+                r#"::volo_grpc::codec::MessageCodec::Json => match method {{
+                    {decode}
+                    _ => ::std::result::Result::Err(::volo_grpc::Status::new(::volo_grpc::Code::Unimplemented, "Method not found.")),
+                }},"#
+            ));
+        }
+        // the custom codec only encodes the messages of its own subtype
+        if let Some(codec) = custom {
+            let encode = variants
+                .iter()
+                .zip(tys)
+                .map(|(v, ty)| {
+                    format!(
+                        "Self::{v}(s) if subtype == <{codec} as \
+                         ::volo_grpc::codec::Codec<{ty}>>::SUBTYPE => \
+                         ::volo_grpc::codec::encode::encode_custom::<{codec}, _, _>(s, \
+                         compression_encoding),"
+                    )
+                })
+                .join("");
+            let decode = paths
+                .iter()
+                .zip(variants)
+                .zip(tys)
+                .map(|((p, v), ty)| {
+                    format!(
+                        "Some(\"{p}\") if subtype == <{codec} as \
+                         ::volo_grpc::codec::Codec<{ty}>>::SUBTYPE => \
+                         ::std::result::Result::Ok(Self::{v}(::volo_grpc::RecvStream::new_custom::\
+                         <{codec}>(body, kind, compression_encoding, limits))),"
+                    )
+                })
+                .join("");
+            send_arms.push_str(&format!(
+                r#"::volo_grpc::codec::MessageCodec::Custom(subtype) => match self {{
+                    {encode}
+                    #[allow(unreachable_patterns)]
+                    _ => ::volo_grpc::codec::encode::unsupported(codec),
+                }},"#
+            ));
+            recv_arms.push_str(&format!(
+                r#"::volo_grpc::codec::MessageCodec::Custom(subtype) => match method {{
+                    {decode}
+                    _ => ::std::result::Result::Err(codec.unsupported()),
+                }},"#
+            ));
+        }
+
+        (
+            format! {
+                r#"fn into_body_with(self, codec: ::volo_grpc::codec::MessageCodec, compression_encoding: ::std::option::Option<::volo_grpc::codec::compression::CompressionEncoding>) -> ::volo_grpc::BoxStream<'static, ::std::result::Result<::volo_grpc::codegen::Frame<::volo_grpc::codegen::Bytes>, ::volo_grpc::Status>> {{
+                    match codec {{
+                        {send_arms}
+                        ::volo_grpc::codec::MessageCodec::Proto => ::volo_grpc::SendEntryMessage::into_body(self, compression_encoding),
+                        #[allow(unreachable_patterns)]
+                        codec => ::volo_grpc::codec::encode::unsupported(codec),
+                    }}
+                }}"#
+            },
+            format! {
+                r#"fn from_body_with(method: ::std::option::Option<&str>, body: ::volo_grpc::body::BoxBody, kind: ::volo_grpc::codec::decode::Kind, codec: ::volo_grpc::codec::MessageCodec, compression_encoding: ::std::option::Option<::volo_grpc::codec::compression::CompressionEncoding>, limits: ::volo_grpc::codec::decode::DecodeLimits) -> ::std::result::Result<Self, ::volo_grpc::Status> {{
+                    match codec {{
+                        {recv_arms}
+                        ::volo_grpc::codec::MessageCodec::Proto => <Self as ::volo_grpc::RecvEntryMessage>::from_body(method, body, kind, compression_encoding, limits),
+                        #[allow(unreachable_patterns)]
+                        codec => ::std::result::Result::Err(codec.unsupported()),
+                    }}
+                }}"#
+            },
+        )
+    }
+
+    /// The path of the method, e.g. `/helloworld.Greeter/SayHello`.
+    fn method_path(&self, service_def_id: DefId, method: &Method) -> String {
+        let file_id = self.cx().node(service_def_id).unwrap().file_id;
+        let package = self.cx().file(file_id).unwrap().package.iter().join(".");
+        let service_name = match &*self.cx().expect_item(service_def_id) {
+            rir::Item::Service(s) => s.name.clone(),
+            _ => panic!("expected service"),
+        };
+        format!("/{package}.{service_name}/{}", method.name)
+    }
+
+    /// Returns the well-known type of the request or the response `ty` of the method to be mapped
+    /// in the signatures, see [`WellKnown`].
+    fn well_known(
+        &self,
+        service_def_id: DefId,
+        method: &Method,
+        ty: &pilota_build::ty::Ty,
+    ) -> Option<WellKnown> {
+        if !self.well_known_types
+            || self
+                .cx()
+                .node_contains_tag::<ClientStreaming>(method.def_id)
+            || self
+                .cx()
+                .node_contains_tag::<ServerStreaming>(method.def_id)
+            || self
+                .raw_types_methods
+                .contains(&self.method_path(service_def_id, method))
+        {
+            return None;
+        }
+        let ty = self.codegen_item_ty(ty.kind.clone());
+        WellKnown::from_path(&ty.global_path().to_string())
+    }
+
+    /// The request of the method in the service trait, which is omitted for
+    /// `google.protobuf.Empty` with [`MkGrpcBackend::well_known_types`].
+    fn trait_method_req_ty(
+        &self,
+        service_def_id: DefId,
+        method: &Method,
+        global_path: bool,
+    ) -> Option<FastStr> {
+        let ty = &method.args[0].ty;
+        match self.well_known(service_def_id, method, ty) {
+            Some(WellKnown::Empty) => None,
+            Some(wk) => Some(format!("::volo_grpc::Request<{}>", wk.rust_ty()).into()),
+            None => Some(
+                self.trait_input_ty(
+                    ty.clone(),
+                    self.cx()
+                        .node_contains_tag::<ClientStreaming>(method.def_id),
+                    global_path,
+                ),
+            ),
+        }
+    }
+
+    fn trait_input_param(
+        &self,
+        service_def_id: DefId,
+        method: &Method,
+        ident: &str,
+        global_path: bool,
+    ) -> Option<String> {
+        self.trait_method_req_ty(service_def_id, method, global_path)
+            .map(|ty| format!("{ident}: {ty}"))
+    }
+
+    /// The response of the method in the service trait, see [`Self::trait_method_req_ty`].
+    fn trait_method_resp_ty(
+        &self,
+        service_def_id: DefId,
+        method: &Method,
+        global_path: bool,
+    ) -> FastStr {
+        match self.well_known(service_def_id, method, &method.ret) {
+            Some(WellKnown::Empty) => "()".into(),
+            Some(wk) => format!("::volo_grpc::Response<{}>", wk.rust_ty()).into(),
+            None => self.trait_resp_ty(
+                method.ret.clone(),
+                self.cx()
+                    .node_contains_tag::<ServerStreaming>(method.def_id),
+                global_path,
+            ),
+        }
+    }
+
     fn trait_input_ty(
         &self,
         ty: pilota_build::ty::Ty,
         streaming: bool,
         global_path: bool,
     ) -> FastStr {
-        let ty = self.cx().codegen_item_ty(ty.kind);
+        let ty = self.codegen_item_ty(ty.kind);
         let ty_str = if global_path {
-            format!("volo_gen{}", ty.global_path())
+            self.global_path(ty.global_path().to_string())
         } else {
             format!("{}", ty)
         };
@@ -52,9 +473,19 @@ impl VoloGrpcBackend {
         streaming: bool,
         global_path: bool,
     ) -> FastStr {
-        let ret_ty = self.cx().codegen_item_ty(ty.kind);
+        let resp_ty = self.trait_resp_ty(ty, streaming, global_path);
+        format!("{resp_ty}, ::volo_grpc::Status").into()
+    }
+
+    fn trait_resp_ty(
+        &self,
+        ty: pilota_build::ty::Ty,
+        streaming: bool,
+        global_path: bool,
+    ) -> FastStr {
+        let ret_ty = self.codegen_item_ty(ty.kind);
         let ret_ty_str = if global_path {
-            format!("volo_gen{}", ret_ty.global_path())
+            self.global_path(ret_ty.global_path().to_string())
         } else {
             format!("{}", ret_ty)
         };
@@ -62,32 +493,42 @@ impl VoloGrpcBackend {
         if streaming {
             format!(
                 "::volo_grpc::Response<::volo_grpc::BoxStream<'static, \
-                 ::std::result::Result<{ret_ty_str}, ::volo_grpc::Status>>>, ::volo_grpc::Status"
+                 ::std::result::Result<{ret_ty_str}, ::volo_grpc::Status>>>"
             )
             .into()
         } else {
-            format!("::volo_grpc::Response<{ret_ty_str}>, ::volo_grpc::Status").into()
+            format!("::volo_grpc::Response<{ret_ty_str}>").into()
         }
     }
 
+    /// Returns if the responses of the method are sent by a `Sender`.
+    fn uses_sender(&self, method: &Method) -> bool {
+        self.stream_sender
+            && self
+                .cx()
+                .node_contains_tag::<ServerStreaming>(method.def_id)
+    }
+
+    fn trait_sender_ty(&self, ty: pilota_build::ty::Ty, global_path: bool) -> FastStr {
+        let ret_ty = self.codegen_item_ty(ty.kind);
+        let ret_ty_str = if global_path {
+            self.global_path(ret_ty.global_path().to_string())
+        } else {
+            format!("{}", ret_ty)
+        };
+        format!("::volo_grpc::server::Sender<{ret_ty_str}>").into()
+    }
+
     fn trait_result_ty(&self, streaming: bool) -> FastStr {
         if streaming {
             r#"
-					use ::volo_grpc::codegen::StreamExt;
-					let repeat = std::iter::repeat(Default::default());
-					let mut resp = ::volo_grpc::codegen::iter(repeat);
-					let (tx, rx) = ::volo_grpc::codegen::mpsc::channel(64);
-					tokio::spawn(async move {
-						while let Some(resp) = resp.next().await {
-							match tx.send(::std::result::Result::<_, ::volo_grpc::Status>::Ok(resp)).await {
-								::std::result::Result::Ok(_) => {}
-								::std::result::Result::Err(_) => {
-									break;
-								}
-							}
+					// the messages are sent by a bounded sender, which pends when the peer reads
+					// slowly, see `volo_grpc::server::sender`
+					::std::result::Result::Ok(::volo_grpc::Response::new(::volo_grpc::server::sender::spawn(|tx| async move {
+						loop {
+							tx.send(::std::result::Result::Ok(::std::default::Default::default())).await?;
 						}
-					});
-					::std::result::Result::Ok(::volo_grpc::Response::new(Box::pin(::volo_grpc::codegen::ReceiverStream::new(rx))))
+					})))
 				"#
             .into()
         } else {
@@ -95,8 +536,16 @@ impl VoloGrpcBackend {
         }
     }
 
-    fn client_input_ty(&self, ty: pilota_build::ty::Ty, streaming: bool) -> FastStr {
-        let ty = self.cx().codegen_item_ty(ty.kind);
+    fn client_input_ty(
+        &self,
+        ty: pilota_build::ty::Ty,
+        streaming: bool,
+        wk: Option<WellKnown>,
+    ) -> FastStr {
+        if let Some(wk) = wk {
+            return format!("impl ::volo_grpc::IntoRequest<{}>", wk.rust_ty()).into();
+        }
+        let ty = self.codegen_item_ty(ty.kind);
 
         if streaming {
             format!("impl ::volo_grpc::IntoStreamingRequest<Message = {ty}>").into()
@@ -105,13 +554,51 @@ impl VoloGrpcBackend {
         }
     }
 
-    fn client_output_ty(&self, ty: pilota_build::ty::Ty, streaming: bool) -> FastStr {
-        let ret_ty = self.cx().codegen_item_ty(ty.kind);
+    /// The type of the requests in the object-safe `{Service}Api`, which is a concrete one
+    /// accepted by the client methods.
+    fn api_input_ty(
+        &self,
+        ty: pilota_build::ty::Ty,
+        streaming: bool,
+        wk: Option<WellKnown>,
+    ) -> FastStr {
+        if let Some(wk) = wk {
+            return format!("::volo_grpc::Request<{}>", wk.rust_ty()).into();
+        }
+        let ty = self.codegen_item_ty(ty.kind);
+
+        if streaming {
+            format!("::volo_grpc::Request<::volo_grpc::BoxStream<'static, {ty}>>").into()
+        } else {
+            format!("::volo_grpc::Request<{ty}>").into()
+        }
+    }
+
+    fn client_output_ty(
+        &self,
+        ty: pilota_build::ty::Ty,
+        streaming: bool,
+        wk: Option<WellKnown>,
+    ) -> FastStr {
+        match wk {
+            Some(WellKnown::Empty) => {
+                return "::std::result::Result<(), ::volo_grpc::Status>".into();
+            }
+            Some(wk) => {
+                return format!(
+                    "::std::result::Result<::volo_grpc::Response<{}>, ::volo_grpc::Status>",
+                    wk.rust_ty()
+                )
+                .into();
+            }
+            None => {}
+        }
+        let ret_ty = self.codegen_item_ty(ty.kind);
 
         if streaming {
             format!(
-                "::std::result::Result<::volo_grpc::Response<impl ::futures::Stream<Item = \
-                 ::std::result::Result<{ret_ty},::volo_grpc::Status>>>, ::volo_grpc::Status>"
+                "::std::result::Result<::volo_grpc::StreamingResponse<{ret_ty}>, \
+                 ::volo_grpc::Status>"
             )
             .into()
         } else {
@@ -120,17 +607,46 @@ impl VoloGrpcBackend {
         }
     }
 
-    fn build_client_req(&self, _ty: pilota_build::ty::Ty, streaming: bool) -> FastStr {
-        if streaming {
-            "requests.into_streaming_request().map(|s| ::volo_grpc::codegen::StreamExt::map(s, |m| \
-             ::std::result::Result::Ok(m)))"
-                .to_string()
-                .into()
+    fn build_client_req(
+        &self,
+        req_enum_name: &Symbol,
+        variant_name: &Symbol,
+        ty: pilota_build::ty::Ty,
+        streaming: bool,
+        wk: Option<WellKnown>,
+    ) -> FastStr {
+        if let Some(wk) = wk {
+            let raw_ty = self.codegen_item_ty(ty.kind);
+            let req = match wk {
+                WellKnown::Empty => format!(
+                    "::volo_grpc::Request::new({})",
+                    wk.into_raw(&raw_ty.to_string())
+                ),
+                _ => format!(
+                    "requests.into_request().map(|v| {})",
+                    wk.into_raw(&raw_ty.to_string())
+                ),
+            };
+            format!(
+                "::volo_grpc::codegen::unary_request(::volo_grpc::codegen::inspect_request({req}, \
+                 &inspector)?, {req_enum_name}::{variant_name})"
+            )
+            .into()
+        } else if streaming {
+            // the `RequestStream` of a `RequestSender` fails the request when it is cancelled
+            format!(
+                "requests.into_streaming_request().map(|s| \
+                 {req_enum_name}::{variant_name}(::volo_grpc::codegen::inspect_send(\
+                 ::volo_grpc::codegen::request_stream(s), &inspector)))"
+            )
+            .into()
         } else {
-            "requests.into_request().map(|m| \
-             ::futures::stream::once(::futures::future::ready(::std::result::Result::Ok(m))))"
-                .to_string()
-                .into()
+            // unary requests can be replayed by the retry layer
+            format!(
+                "::volo_grpc::codegen::unary_request(::volo_grpc::codegen::inspect_request(\
+                 requests.into_request(), &inspector)?, {req_enum_name}::{variant_name})"
+            )
+            .into()
         }
     }
 
@@ -140,11 +656,12 @@ impl VoloGrpcBackend {
         variant_name: &Symbol,
         _ty: pilota_build::ty::Ty,
         streaming: bool,
+        wk: Option<WellKnown>,
     ) -> FastStr {
         let resp_stream = format!(
             r#"let (mut metadata, extensions, message_stream) = resp.into_parts();
             let mut message_stream = match message_stream {{
-                {resp_enum_name}::{variant_name}(stream) => stream,
+                {resp_enum_name}::{variant_name}(stream) => ::volo_grpc::codegen::inspect_recv(stream, &inspector),
                 #[allow(unreachable_patterns)]
                 _ => return ::std::result::Result::Err(::volo_grpc::Status::new(::volo_grpc::Code::Unimplemented, "Method not found.")),
             }};"#
@@ -153,9 +670,20 @@ impl VoloGrpcBackend {
         if streaming {
             format! {
                 r#"{resp_stream}
-                ::std::result::Result::Ok(::volo_grpc::Response::from_parts(metadata, extensions, message_stream))"#
+                ::std::result::Result::Ok(::volo_grpc::StreamingResponse::from_parts(metadata, extensions, message_stream))"#
             }
         } else {
+            let resp = match wk {
+                Some(WellKnown::Empty) => "::std::result::Result::Ok(())".to_string(),
+                Some(wk) => format!(
+                    "let m = message; ::std::result::Result::Ok(::volo_grpc::Response::\
+                     from_parts(metadata, extensions, {}))",
+                    wk.from_raw()
+                ),
+                None => "::std::result::Result::Ok(::volo_grpc::Response::from_parts(metadata, \
+                         extensions, message))"
+                    .to_string(),
+            };
             format! {
                 r#"{resp_stream}
                 let message = ::volo_grpc::codegen::StreamExt::try_next(&mut message_stream)
@@ -168,7 +696,7 @@ impl VoloGrpcBackend {
                 if let Some(trailers) = message_stream.trailers().await? {{
                     metadata.merge(trailers);
                 }}
-                ::std::result::Result::Ok(::volo_grpc::Response::from_parts(metadata, extensions, message))"#
+                {resp}"#
             }
         }.into()
     }
@@ -179,11 +707,12 @@ impl VoloGrpcBackend {
         variant_name: &Symbol,
         _ty: pilota_build::ty::Ty,
         streaming: bool,
+        wk: Option<WellKnown>,
     ) -> FastStr {
         let req_stream = format!(
             r#"let (mut metadata, extensions, message_stream) = req.into_parts();
             let mut message_stream = match message_stream {{
-                {req_enum_name}::{variant_name}(stream) => stream,
+                {req_enum_name}::{variant_name}(stream) => ::volo_grpc::codegen::inspect_recv(stream, &inspector),
                 #[allow(unreachable_patterns)]
                 _ => return ::std::result::Result::Err(::volo_grpc::Status::new(::volo_grpc::Code::Unimplemented, "Method not found.")),
             }};"#
@@ -194,6 +723,11 @@ impl VoloGrpcBackend {
                 let req = ::volo_grpc::Request::from_parts(metadata, extensions, message_stream);"#
             }
         } else {
+            let convert = match wk {
+                Some(WellKnown::Empty) => "::std::mem::drop(req);".to_string(),
+                Some(wk) => format!("let req = req.map(|m| {});", wk.from_raw()),
+                None => String::new(),
+            };
             format! {
                 r#"{req_stream}
                 ::futures::pin_mut!(message_stream);
@@ -203,33 +737,258 @@ impl VoloGrpcBackend {
                 if let Some(trailers) = message_stream.trailers().await? {{
                     metadata.merge(trailers);
                 }}
-                let req = ::volo_grpc::Request::from_parts(metadata, extensions, message);"#
+                let req = ::volo_grpc::Request::from_parts(metadata, extensions, message);
+                {convert}"#
             }
         }.into()
     }
 
-    fn build_server_call(&self, method: &Method) -> FastStr {
+    fn build_server_call(&self, method: &Method, wk: Option<WellKnown>) -> FastStr {
         let method_name = self.cx().rust_name(method.def_id);
-        format!("let resp = inner.{method_name}(req).await;").into()
+        if wk == Some(WellKnown::Empty) {
+            format!("let resp = inner.{method_name}().await;").into()
+        } else if self.uses_sender(method) {
+            format!(
+                "let resp = ::volo_grpc::server::sender::spawn(move |tx| async move {{ \
+                 inner.{method_name}(req, tx).await }});"
+            )
+            .into()
+        } else {
+            format!("let resp = inner.{method_name}(req).await;").into()
+        }
     }
 
     fn build_server_resp(
         &self,
         resp_enum_name: &Symbol,
         variant_name: &Symbol,
-        _ty: pilota_build::ty::Ty,
+        ty: pilota_build::ty::Ty,
         streaming: bool,
+        sender: bool,
+        wk: Option<WellKnown>,
     ) -> FastStr {
-        if streaming {
-            format!("resp.map(|r| r.map(|s|  {resp_enum_name}::{variant_name}(s)))").into()
+        if sender {
+            format!(
+                "::std::result::Result::Ok(::volo_grpc::Response::new({resp_enum_name}::\
+                 {variant_name}(::volo_grpc::codegen::inspect_send(resp, &inspector))))"
+            )
+            .into()
+        } else if streaming {
+            format!(
+                "resp.map(|r| r.map(|s| {resp_enum_name}::{variant_name}(\
+                 ::volo_grpc::codegen::inspect_send(s, &inspector))))"
+            )
+            .into()
         } else {
+            let convert = match wk {
+                Some(wk @ WellKnown::Empty) => format!(
+                    "let resp = resp.map(|()| ::volo_grpc::Response::new({}));",
+                    wk.into_raw(&self.codegen_item_ty(ty.kind).to_string())
+                ),
+                Some(wk) => format!(
+                    "let resp = resp.map(|r| r.map(|v| {}));",
+                    wk.into_raw(&self.codegen_item_ty(ty.kind).to_string())
+                ),
+                None => String::new(),
+            };
             format!(
-                "resp.map(|r| r.map(|m| {resp_enum_name}::{variant_name}(::std::boxed::Box::pin( \
-                 ::futures::stream::once(::futures::future::ok(m))))))"
+                "{convert}resp.map(|r| r.map(|m| \
+                 {resp_enum_name}::{variant_name}(::volo_grpc::codegen::inspect_send(\
+                 ::std::boxed::Box::pin(::futures::stream::once(::futures::future::ok(m))), \
+                 &inspector))))"
             )
             .into()
         }
     }
+
+    /// The `{Service}BlockingClient` wrapping the generic client with a runtime, where `methods`
+    /// are the blocking methods and `omitted` are the names of the client streaming methods.
+    fn codegen_blocking_client(
+        &self,
+        service_name: &Symbol,
+        generic_client_name: &str,
+        req_enum_name_send: &str,
+        resp_enum_name_recv: &str,
+        methods: &str,
+        omitted: &[String],
+    ) -> String {
+        let blocking_client_name = format!("{service_name}BlockingClient");
+        let omitted_doc = if omitted.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "///\n/// The client streaming methods {} are omitted, which can't be driven by \
+                 the blocking calls, so use the async client by [`Self::client`] instead.\n",
+                omitted.join(", ")
+            )
+        };
+
+        format! {
+            r#"/// The blocking facade of [`{generic_client_name}`], which blocks the current thread
+            /// on the runtime for each call, see `volo_grpc::client::blocking`.
+            {omitted_doc}#[derive(Clone)]
+            pub struct {blocking_client_name}<S> {{
+                client: {generic_client_name}<S>,
+                runtime: ::volo_grpc::client::blocking::BlockingRuntime,
+            }}
+
+            impl<S> {blocking_client_name}<S> where S: ::volo::service::Service<::volo_grpc::context::ClientContext, ::volo_grpc::Request<{req_enum_name_send}>, Response=::volo_grpc::Response<{resp_enum_name_recv}>, Error = ::volo_grpc::Status> + Sync + Send + 'static {{
+                pub fn new(client: {generic_client_name}<S>, runtime: ::volo_grpc::client::blocking::BlockingRuntime) -> Self {{
+                    Self {{ client, runtime }}
+                }}
+
+                /// Returns the async client.
+                pub fn client(&self) -> &{generic_client_name}<S> {{
+                    &self.client
+                }}
+
+                {methods}
+            }}"#
+        }
+    }
+
+    /// The per-method handler traits and the `{Service}Composite` implementing the service by
+    /// the registered handlers.
+    fn codegen_composite(&self, def_id: DefId, s: &rir::Service, package: &str) -> String {
+        let service_name = self.cx().rust_name(def_id);
+        let composite_name = format!("{service_name}Composite");
+        let part_name = format!("{service_name}Part");
+
+        let mut items = Vec::new();
+        let mut fields = Vec::new();
+        let mut field_inits = Vec::new();
+        let mut trait_methods = Vec::new();
+        for method in s.methods.iter() {
+            let method_name = self.cx().rust_name(method.def_id);
+            let variant_name = method_name.0.upper_camel_ident();
+            let handler_trait = format!("{service_name}{variant_name}");
+            let marker = format!("{handler_trait}Fn");
+            let path = format!("/{package}.{}/{}", s.name, method.name);
+
+            let req_ty = self.trait_method_req_ty(def_id, method, false);
+            let resp_ty = self.trait_method_resp_ty(def_id, method, false);
+            let trait_method = self.codegen_service_method(def_id, method);
+            // the methods with a `Sender` are registered with the request and the sender, and the
+            // ones without a request are registered with `()`
+            let (handler_req_ty, handler_resp_ty, handler_params) = if self.uses_sender(method) {
+                let req_ty = req_ty.clone().unwrap_or_default();
+                let sender_ty = self.trait_sender_ty(method.ret.clone(), false);
+                (
+                    format!("({req_ty}, {sender_ty})"),
+                    "()".to_string(),
+                    format!("req: {req_ty}, tx: {sender_ty}"),
+                )
+            } else if let Some(req_ty) = &req_ty {
+                (
+                    req_ty.to_string(),
+                    resp_ty.to_string(),
+                    format!("req: {req_ty}"),
+                )
+            } else {
+                ("()".to_string(), resp_ty.to_string(), String::new())
+            };
+            let (handler_args, handler_call_args) = if self.uses_sender(method) {
+                ("(req, tx)", "req, tx")
+            } else if req_ty.is_some() {
+                ("req", "req")
+            } else {
+                ("()", "")
+            };
+
+            items.push(format!(
+                r#"/// The marker of `{path}` for [`{composite_name}::register`].
+                pub struct {marker};
+
+                /// The handler of `{path}`.
+                pub trait {handler_trait}: ::core::marker::Send + ::core::marker::Sync + 'static {{
+                    {trait_method}
+                }}
+
+                impl<H: {handler_trait}> {part_name}<{marker}> for H {{
+                    fn register_into(self, composite: &mut {composite_name}) -> ::std::result::Result<(), ::volo_grpc::server::composite::DuplicateMethod> {{
+                        let handler = ::std::sync::Arc::new(self);
+                        composite.{method_name}.register(move |{handler_args}| {{
+                            let handler = handler.clone();
+                            async move {{ <H as {handler_trait}>::{method_name}(&handler, {handler_call_args}).await }}
+                        }})
+                    }}
+                }}"#
+            ));
+            fields.push(format!(
+                "{method_name}: ::volo_grpc::server::composite::Handler<{handler_req_ty}, \
+                 {handler_resp_ty}>,"
+            ));
+            field_inits.push(format!(
+                r#"{method_name}: ::volo_grpc::server::composite::Handler::new("{path}"),"#
+            ));
+            trait_methods.push(format!(
+                r#"async fn {method_name}(&self, {handler_params}) -> ::std::result::Result<{handler_resp_ty}, ::volo_grpc::Status> {{
+                    self.{method_name}.call({handler_args}).await
+                }}"#
+            ));
+        }
+
+        let items = items.join("\n");
+        let fields = fields.join("\n");
+        let field_inits = field_inits.join("\n");
+        let trait_methods = trait_methods.join("\n");
+
+        format!(
+            r#"
+            /// A part of [`{composite_name}`] implementing some of the methods, which is
+            /// implemented for the handlers of each method.
+            pub trait {part_name}<M> {{
+                fn register_into(self, composite: &mut {composite_name}) -> ::std::result::Result<(), ::volo_grpc::server::composite::DuplicateMethod>;
+            }}
+
+            {items}
+
+            /// The implementation of [`{service_name}`] composed from the handlers registered by
+            /// different structs, see `volo_grpc::server::composite`.
+            #[derive(Clone, Debug)]
+            pub struct {composite_name} {{
+                {fields}
+            }}
+
+            impl ::std::default::Default for {composite_name} {{
+                fn default() -> Self {{
+                    Self::new()
+                }}
+            }}
+
+            impl {composite_name} {{
+                pub fn new() -> Self {{
+                    Self {{
+                        {field_inits}
+                    }}
+                }}
+
+                /// Registers the handler of the method `M`, e.g.
+                /// `composite.register::<{service_name}XxxFn>(handlers)`.
+                ///
+                /// # Panics
+                ///
+                /// Panics if the method has been registered.
+                pub fn register<M>(self, handler: impl {part_name}<M>) -> Self {{
+                    match self.try_register(handler) {{
+                        ::std::result::Result::Ok(composite) => composite,
+                        ::std::result::Result::Err(e) => ::std::panic!("{{}}", e),
+                    }}
+                }}
+
+                /// Registers the handler of the method `M`, which fails if the method has been
+                /// registered.
+                pub fn try_register<M>(mut self, handler: impl {part_name}<M>) -> ::std::result::Result<Self, ::volo_grpc::server::composite::DuplicateMethod> {{
+                    handler.register_into(&mut self)?;
+                    ::std::result::Result::Ok(self)
+                }}
+            }}
+
+            impl {service_name} for {composite_name} {{
+                {trait_methods}
+            }}"#
+        )
+    }
 }
 
 impl CodegenBackend for VoloGrpcBackend {
@@ -272,25 +1031,31 @@ impl CodegenBackend for VoloGrpcBackend {
                     .cx()
                     .node_contains_tag::<ServerStreaming>(method.def_id);
                 let output_ty = &method.ret;
+                let req_wk = self.well_known(def_id, method, input_ty);
+                let resp_wk = self.well_known(def_id, method, output_ty);
 
                 let req = self.build_server_req(
                     &req_enum_name_recv.clone().into(),
                     &variant_name.clone().into(),
                     input_ty.clone(),
                     client_streaming,
+                    req_wk,
                 );
 
-                let call = self.build_server_call(method);
+                let call = self.build_server_call(method, req_wk);
 
                 let resp = self.build_server_resp(
                     &resp_enum_name_send.clone().into(),
                     &variant_name.into(),
                     output_ty.clone(),
                     server_streaming,
+                    self.uses_sender(method),
+                    resp_wk,
                 );
 
                 format! {
                     r#""{path}" => {{
+                    let inspector = ::volo_grpc::codegen::inspector(&*cx);
                     {req}
                     {call}
                     {resp}
@@ -308,44 +1073,59 @@ impl CodegenBackend for VoloGrpcBackend {
         let req_tys = s
             .methods
             .iter()
-            .map(|method| self.cx().codegen_item_ty(method.args[0].ty.kind.clone()))
+            .map(|method| self.codegen_item_ty(method.args[0].ty.kind.clone()))
             .collect::<Vec<_>>();
         let resp_tys = s
             .methods
             .iter()
-            .map(|method| self.cx().codegen_item_ty(method.ret.kind.clone()))
+            .map(|method| self.codegen_item_ty(method.ret.kind.clone()))
             .collect::<Vec<_>>();
 
         let mut client_methods = Vec::new();
         let mut oneshot_client_methods = Vec::new();
+        let mut api_methods = Vec::new();
+        let mut api_impl_methods = Vec::new();
+        let mut blocking_methods = Vec::new();
+        let mut blocking_omitted = Vec::new();
 
         s.methods.iter().for_each(|method| {
             let method_name = self.cx().rust_name(method.def_id);
+            let attrs = crate::adjusted_attrs(self.cx(), method.def_id);
 
             let path = format!("/{package}.{}/{}", s.name, method.name);
             let input_ty = &method.args[0].ty;
             let client_streaming = self.cx().node_contains_tag::<ClientStreaming>(method.def_id);
-            let req_ty = self.client_input_ty(input_ty.clone(), client_streaming);
+            let req_wk = self.well_known(def_id, method, input_ty);
+            let req_ty = self.client_input_ty(input_ty.clone(), client_streaming, req_wk);
+            // the request of `google.protobuf.Empty` is omitted with the well-known types
+            let req_param = if req_wk == Some(WellKnown::Empty) {
+                String::new()
+            } else {
+                format!("requests: {req_ty},")
+            };
 
             let output_ty = &method.ret;
             let server_streaming = self.cx().node_contains_tag::<ServerStreaming>(method.def_id);
+            let resp_wk = self.well_known(def_id, method, output_ty);
 
             let variant_name = self.cx().rust_name(method.def_id).0.upper_camel_ident();
 
-            let resp_ty = self.client_output_ty(output_ty.clone(), server_streaming);
+            let resp_ty = self.client_output_ty(output_ty.clone(), server_streaming, resp_wk);
 
-            let req = self.build_client_req(input_ty.clone(), client_streaming);
+            let req = self.build_client_req(&req_enum_name_send.clone().into(), &variant_name.clone().into(), input_ty.clone(), client_streaming, req_wk);
 
-            let resp = self.build_client_resp(&resp_enum_name_recv.clone().into(), &variant_name.clone().into(), output_ty.clone(), server_streaming);
+            let resp = self.build_client_resp(&resp_enum_name_recv.clone().into(), &variant_name.clone().into(), output_ty.clone(), server_streaming, resp_wk);
 
             client_methods.push(
                 format! {
-                    r#"pub async fn {method_name}(
+                    r#"{attrs}
+                    pub async fn {method_name}(
                         &self,
-                        requests: {req_ty},
+                        {req_param}
                     ) -> {resp_ty} {{
-                        let req = {req}.map(|message| {req_enum_name_send}::{variant_name}(::std::boxed::Box::pin(message) as _));
                         let mut cx = self.0.make_cx("{path}");
+                        let inspector = ::volo_grpc::codegen::inspector(&cx);
+                        let req = {req};
 
                         let resp = ::volo::Service::call(&self.0, &mut cx, req).await?;
                         {resp}
@@ -355,12 +1135,14 @@ impl CodegenBackend for VoloGrpcBackend {
 
             oneshot_client_methods.push(
                 format! {
-                    r#"pub async fn {method_name}(
+                    r#"{attrs}
+                    pub async fn {method_name}(
                         self,
-                        requests: {req_ty},
+                        {req_param}
                     ) -> {resp_ty} {{
-                        let req = {req}.map(|message| {req_enum_name_send}::{variant_name}(::std::boxed::Box::pin(message) as _));
                         let mut cx = self.0.make_cx("{path}");
+                        let inspector = ::volo_grpc::codegen::inspector(&cx);
+                        let req = {req};
 
                         let resp = ::volo::client::OneShotService::call(self.0, &mut cx, req).await?;
 
@@ -368,12 +1150,58 @@ impl CodegenBackend for VoloGrpcBackend {
                     }}"#
                 }
             );
+
+            let (api_param, api_arg) = if req_wk == Some(WellKnown::Empty) {
+                (String::new(), "")
+            } else {
+                let api_req_ty = self.api_input_ty(input_ty.clone(), client_streaming, req_wk);
+                (format!("requests: {api_req_ty},"), "requests")
+            };
+            let api_signature = format!(
+                "fn {method_name}<'a>(&'a self, {api_param}) -> \
+                 ::volo_grpc::codegen::BoxFuture<'a, {resp_ty}>"
+            );
+            if client_streaming {
+                blocking_omitted.push(format!("`{method_name}`"));
+            } else if server_streaming {
+                let ret_ty = self.codegen_item_ty(output_ty.kind.clone());
+                blocking_methods.push(format! {
+                    r#"{attrs}
+                    pub fn {method_name}(
+                        &self,
+                        {req_param}
+                    ) -> ::std::result::Result<::volo_grpc::client::blocking::BlockingStream<{ret_ty}>, ::volo_grpc::Status> {{
+                        let resp = self.runtime.block_on(self.client.{method_name}({api_arg}))??;
+                        ::std::result::Result::Ok(self.runtime.stream(resp))
+                    }}"#
+                });
+            } else {
+                blocking_methods.push(format! {
+                    r#"{attrs}
+                    pub fn {method_name}(
+                        &self,
+                        {req_param}
+                    ) -> {resp_ty} {{
+                        self.runtime.block_on(self.client.{method_name}({api_arg}))?
+                    }}"#
+                });
+            }
+
+            api_methods.push(format!("{api_signature};"));
+            api_impl_methods.push(format! {
+                r#"{api_signature} {{
+                    ::std::boxed::Box::pin({generic_client_name}::{method_name}(self, {api_arg}))
+                }}"#
+            });
         });
 
         let mk_client_name = format!("Mk{}", generic_client_name);
 
         let client_methods = client_methods.join("\n");
         let oneshot_client_methods = oneshot_client_methods.join("\n");
+        let api_methods = api_methods.join("\n");
+        let api_impl_methods = api_impl_methods.join("\n");
+        let api_name = format!("{service_name}Api");
 
         let req_enum_send_variants = crate::join_multi_strs!(
             "\n",
@@ -405,7 +1233,7 @@ impl CodegenBackend for VoloGrpcBackend {
         let req_recv_from_body = crate::join_multi_strs!(
             "",
             |paths, enum_variant_names| -> "Some(\"{paths}\") => {{
-                ::std::result::Result::Ok(Self::{enum_variant_names}(::volo_grpc::RecvStream::new(body, kind,compression_encoding)))
+                ::std::result::Result::Ok(Self::{enum_variant_names}(::volo_grpc::RecvStream::new(body, kind, compression_encoding, limits)))
             }},"
         );
 
@@ -419,10 +1247,15 @@ impl CodegenBackend for VoloGrpcBackend {
         let resp_recv_from_body = crate::join_multi_strs!(
             "",
             |paths, enum_variant_names| -> "Some(\"{paths}\") => {{
-                ::std::result::Result::Ok(Self::{enum_variant_names}(::volo_grpc::RecvStream::new(body, kind, compression_encoding)))
+                ::std::result::Result::Ok(Self::{enum_variant_names}(::volo_grpc::RecvStream::new(body, kind, compression_encoding, limits)))
             }}"
         );
 
+        let (req_send_with, req_recv_with) =
+            self.codegen_codecs(&name, &enum_variant_names, &paths, &req_tys);
+        let (resp_send_with, resp_recv_with) =
+            self.codegen_codecs(&name, &enum_variant_names, &paths, &resp_tys);
+
         stream.push_str(&format! {
             r#"pub enum {req_enum_name_send} {{
                 {req_enum_send_variants}
@@ -434,6 +1267,8 @@ impl CodegenBackend for VoloGrpcBackend {
                         {req_send_into_body}
                     }}
                 }}
+
+                {req_send_with}
             }}
 
             pub enum {req_enum_name_recv} {{
@@ -441,12 +1276,14 @@ impl CodegenBackend for VoloGrpcBackend {
             }}
 
             impl ::volo_grpc::RecvEntryMessage for {req_enum_name_recv} {{
-                fn from_body(method: ::std::option::Option<&str>, body: ::volo_grpc::codegen::hyper::body::Incoming, kind: ::volo_grpc::codec::decode::Kind,compression_encoding: ::std::option::Option<::volo_grpc::codec::compression::CompressionEncoding>) -> ::std::result::Result<Self, ::volo_grpc::Status> {{
+                fn from_body(method: ::std::option::Option<&str>, body: ::volo_grpc::body::BoxBody, kind: ::volo_grpc::codec::decode::Kind,compression_encoding: ::std::option::Option<::volo_grpc::codec::compression::CompressionEncoding>, limits: ::volo_grpc::codec::decode::DecodeLimits) -> ::std::result::Result<Self, ::volo_grpc::Status> {{
                     match method {{
                         {req_recv_from_body}
                         _ => ::std::result::Result::Err(::volo_grpc::Status::new(::volo_grpc::Code::Unimplemented, "Method not found.")),
                     }}
                 }}
+
+                {req_recv_with}
             }}
 
             pub enum {resp_enum_name_send} {{
@@ -459,6 +1296,8 @@ impl CodegenBackend for VoloGrpcBackend {
                         {resp_send_into_body}
                     }}
                 }}
+
+                {resp_send_with}
             }}
 
             pub enum {resp_enum_name_recv} {{
@@ -466,7 +1305,7 @@ impl CodegenBackend for VoloGrpcBackend {
             }}
 
             impl ::volo_grpc::RecvEntryMessage for {resp_enum_name_recv} {{
-                fn from_body(method: ::std::option::Option<&str>, body: ::volo_grpc::codegen::hyper::body::Incoming, kind: ::volo_grpc::codec::decode::Kind,compression_encoding: ::std::option::Option<::volo_grpc::codec::compression::CompressionEncoding>) -> ::std::result::Result<Self, ::volo_grpc::Status>
+                fn from_body(method: ::std::option::Option<&str>, body: ::volo_grpc::body::BoxBody, kind: ::volo_grpc::codec::decode::Kind,compression_encoding: ::std::option::Option<::volo_grpc::codec::compression::CompressionEncoding>, limits: ::volo_grpc::codec::decode::DecodeLimits) -> ::std::result::Result<Self, ::volo_grpc::Status>
                 where
                     Self: ::core::marker::Sized,
                 {{
@@ -475,6 +1314,8 @@ impl CodegenBackend for VoloGrpcBackend {
                         _ => ::std::result::Result::Err(::volo_grpc::Status::new(::volo_grpc::Code::Unimplemented, "Method not found.")),
                     }}
                 }}
+
+                {resp_recv_with}
             }}
 
             pub struct {client_builder_name} {{}}
@@ -521,6 +1362,16 @@ impl CodegenBackend for VoloGrpcBackend {
                 {oneshot_client_methods}
             }}
 
+            /// The object-safe interface of the clients, e.g. to hold the clients of different
+            /// service stacks as `Arc<dyn {api_name}>`.
+            pub trait {api_name}: ::std::marker::Send + ::std::marker::Sync {{
+                {api_methods}
+            }}
+
+            impl<S> {api_name} for {generic_client_name}<S> where S: ::volo::service::Service<::volo_grpc::context::ClientContext, ::volo_grpc::Request<{req_enum_name_send}>, Response=::volo_grpc::Response<{resp_enum_name_recv}>, Error = ::volo_grpc::Status> + Sync + Send + 'static {{
+                {api_impl_methods}
+            }}
+
             pub struct {server_name}<S> {{
                 inner: ::std::sync::Arc<S>,
             }}
@@ -568,64 +1419,122 @@ impl CodegenBackend for VoloGrpcBackend {
                 const NAME: &'static str = "{name}";
             }}"#
         });
+
+        if self.blocking_client {
+            stream.push_str(&self.codegen_blocking_client(
+                &service_name,
+                &generic_client_name,
+                &req_enum_name_send,
+                &resp_enum_name_recv,
+                &blocking_methods.join("\n"),
+                &blocking_omitted,
+            ));
+        }
+
+        if self.composite_server {
+            stream.push_str(&self.codegen_composite(def_id, s, &package));
+        }
+
+        let methods = s
+            .methods
+            .iter()
+            .map(|method| MethodInfo {
+                name: method.name.to_string(),
+                path: format!("/{package}.{}/{}", s.name, method.name),
+                client_streaming: self
+                    .cx()
+                    .node_contains_tag::<ClientStreaming>(method.def_id),
+                server_streaming: self
+                    .cx()
+                    .node_contains_tag::<ServerStreaming>(method.def_id),
+                request_type: self
+                    .codegen_item_ty(method.args[0].ty.kind.clone())
+                    .to_string(),
+                response_type: self.codegen_item_ty(method.ret.kind.clone()).to_string(),
+                // the custom options of the methods are not kept by the parser
+                annotations: Vec::new(),
+                request_schema: None,
+            })
+            .collect::<Vec<_>>();
+        descriptor::codegen_service(
+            stream,
+            &service_name,
+            &name,
+            &methods,
+            &descriptor::RequestSchemas::default(),
+        );
     }
 
-    fn codegen_service_method(&self, _service_def_id: DefId, method: &rir::Method) -> String {
-        let client_streaming = self
-            .cx()
-            .node_contains_tag::<ClientStreaming>(method.def_id);
+    fn codegen_service_method(&self, service_def_id: DefId, method: &rir::Method) -> String {
         let args = method
             .args
             .iter()
-            .map(|a| {
-                let ty = self.trait_input_ty(a.ty.clone(), client_streaming, false);
-
-                let ident = &a.name;
-                format!("{ident}: {ty}")
+            .filter_map(|a| {
+                self.trait_input_param(service_def_id, method, &a.name.to_string(), false)
             })
             .join(",");
 
-        let ret_ty = self.trait_output_ty(
-            method.ret.clone(),
-            self.cx()
-                .node_contains_tag::<ServerStreaming>(method.def_id),
-            false,
-        );
+        let (args, ret_ty) = if self.uses_sender(method) {
+            let sender_ty = self.trait_sender_ty(method.ret.clone(), false);
+            (
+                format!("{args}, tx: {sender_ty}"),
+                "(), ::volo_grpc::Status".into(),
+            )
+        } else {
+            let resp_ty = self.trait_method_resp_ty(service_def_id, method, false);
+            (args, format!("{resp_ty}, ::volo_grpc::Status"))
+        };
 
         let name = self.cx().rust_name(method.def_id);
+        let attrs = crate::adjusted_attrs(self.cx(), method.def_id);
 
         format!(
-            "fn {name}(&self, {args}) -> impl ::std::future::Future<Output = \
+            "{attrs}\nfn {name}(&self, {args}) -> impl ::std::future::Future<Output = \
              ::std::result::Result<{ret_ty}>> + Send;"
         )
     }
 
     fn codegen_service_method_with_global_path(
         &self,
-        _service_def_id: DefId,
+        service_def_id: DefId,
         method: &Method,
     ) -> String {
-        let client_streaming = self
-            .cx()
-            .node_contains_tag::<ClientStreaming>(method.def_id);
         let args = method
             .args
             .iter()
-            .map(|a| {
-                let ty = self.trait_input_ty(a.ty.clone(), client_streaming, true);
-
-                let ident = &a.name;
-                // args are unused, add _ to avoid unused variable warning
-                format!("_{ident}: {ty}")
+            // args are unused, add _ to avoid unused variable warning
+            .filter_map(|a| {
+                self.trait_input_param(service_def_id, method, &format!("_{}", a.name), true)
             })
             .join(",");
 
         let server_streaming = self
             .cx()
             .node_contains_tag::<ServerStreaming>(method.def_id);
-        let ret_ty = self.trait_output_ty(method.ret.clone(), server_streaming, true);
-
-        let default_result = self.trait_result_ty(server_streaming);
+        let (args, ret_ty, default_result) = if self.uses_sender(method) {
+            let sender_ty = self.trait_sender_ty(method.ret.clone(), true);
+            (
+                format!("{args}, _tx: {sender_ty}"),
+                "(), ::volo_grpc::Status".into(),
+                "::std::result::Result::Ok(())".into(),
+            )
+        } else {
+            let resp_ty = self.trait_method_resp_ty(service_def_id, method, true);
+            let default_result = match self.well_known(service_def_id, method, &method.ret) {
+                Some(WellKnown::Empty) => "::std::result::Result::Ok(())".into(),
+                Some(wk) => format!(
+                    "::std::result::Result::Ok(::volo_grpc::Response::new({}))",
+                    wk.default_value()
+                )
+                .into(),
+                None => self.trait_result_ty(server_streaming),
+            };
+            (
+                args,
+                format!("{resp_ty}, ::volo_grpc::Status"),
+                default_result,
+            )
+        };
 
         let name = self.cx().rust_name(method.def_id);
 
@@ -633,7 +1542,7 @@ impl CodegenBackend for VoloGrpcBackend {
             r#"
     async fn {name}(
         &self,
-        {args},
+        {args}
     ) -> ::std::result::Result<{ret_ty}>
     {{
         {default_result}
@@ -643,7 +1552,10 @@ impl CodegenBackend for VoloGrpcBackend {
     }
 
     fn codegen_enum_impl(&self, def_id: DefId, stream: &mut String, e: &rir::Enum) {
-        self.inner.codegen_enum_impl(def_id, stream, e)
+        self.inner.codegen_enum_impl(def_id, stream, e);
+        if self.serde == Some(SerdeEnumRepr::Name) {
+            codegen_enum_names(self.cx(), def_id, stream, e);
+        }
     }
 
     fn codegen_newtype_impl(&self, def_id: DefId, stream: &mut String, t: &rir::NewType) {
@@ -658,3 +1570,27 @@ impl CodegenBackend for VoloGrpcBackend {
         self.inner.cx()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::WellKnown;
+
+    #[test]
+    fn well_known_from_path() {
+        assert_eq!(
+            WellKnown::from_path("::google::protobuf::Empty"),
+            Some(WellKnown::Empty)
+        );
+        assert_eq!(
+            WellKnown::from_path("crate::volo_gen::google::protobuf::StringValue"),
+            Some(WellKnown::Wrapper("::pilota::FastStr"))
+        );
+        assert_eq!(
+            WellKnown::from_path("::google::protobuf::Timestamp"),
+            Some(WellKnown::Timestamp)
+        );
+        assert_eq!(WellKnown::from_path("::helloworld::Empty"), None);
+        assert_eq!(WellKnown::from_path("::google::protobuf::Any"), None);
+        assert_eq!(WellKnown::from_path("Empty"), None);
+    }
+}
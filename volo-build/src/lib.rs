@@ -4,6 +4,7 @@
 #![cfg_attr(not(doctest), doc = include_str!("../README.md"))]
 #![allow(clippy::mutable_key_type)]
 use std::{
+    collections::HashMap,
     path::{Path, PathBuf},
     str::FromStr,
 };
@@ -13,9 +14,13 @@ use itertools::Itertools;
 use pilota_build::{parser::Parser, IdlService};
 
 pub mod config_builder;
+mod descriptor;
+pub mod extern_path;
 pub mod grpc_backend;
 pub mod legacy;
+pub mod manifest;
 pub mod model;
+pub mod serde_plugin;
 pub mod thrift_backend;
 pub mod util;
 pub mod workspace;
@@ -31,32 +36,210 @@ pub struct Builder<MkB, P> {
     out_dir: Option<PathBuf>,
     filename: PathBuf,
     config_file_path: PathBuf,
+    extern_paths: extern_path::ExternPaths,
+    serde: Option<serde_plugin::SerdeEnumRepr>,
+    // the plugins of the users, which run after the built-in ones
+    plugins: Vec<Box<dyn Plugin>>,
+    // only used by the protobuf backend
+    composite_server: bool,
+    stream_sender: bool,
+    blocking_client: bool,
+    well_known_types: bool,
+    raw_types_methods: Vec<String>,
+    codecs: HashMap<String, String>,
+    // the annotations of the thrift methods in the descriptors
+    annotations: descriptor::MethodAnnotations,
+    include_dirs: Vec<PathBuf>,
 }
 
 impl Builder<thrift_backend::MkThriftBackend, parser::ThriftParser> {
     pub fn thrift() -> Self {
+        let annotations = descriptor::MethodAnnotations::default();
         Builder {
-            pilota_builder: pilota_build::Builder::thrift()
-                .with_backend(thrift_backend::MkThriftBackend),
+            pilota_builder: pilota_build::Builder::thrift().with_backend(
+                thrift_backend::MkThriftBackend::default().annotations(annotations.clone()),
+            ),
             out_dir: Default::default(),
             filename: "volo_gen.rs".into(),
             idls: Default::default(),
             config_file_path: "volo.yml".into(),
+            extern_paths: Default::default(),
+            serde: None,
+            plugins: Vec::new(),
+            composite_server: false,
+            stream_sender: false,
+            blocking_client: false,
+            well_known_types: false,
+            raw_types_methods: Vec::new(),
+            codecs: HashMap::new(),
+            annotations,
+            include_dirs: Vec::new(),
         }
     }
+
+    /// Maps the namespace to an existing Rust path, e.g. `.extern_path("common",
+    /// "common_types")`, so the types in it are referenced from the path instead of being
+    /// generated again.
+    pub fn extern_path(mut self, namespace: impl AsRef<str>, rust_path: impl AsRef<str>) -> Self {
+        self.extern_paths.insert(namespace, rust_path);
+        self.reset_thrift_backend()
+    }
+
+    /// Derives `Serialize` and `Deserialize` of serde for the generated types, see
+    /// [`serde_plugin`] for details.
+    ///
+    /// Default is `false`.
+    pub fn with_serde(mut self, with_serde: bool) -> Self {
+        self.serde = with_serde.then(|| self.serde.unwrap_or_default());
+        self.reset_thrift_backend()
+    }
+
+    /// Sets how the enums are serialized by serde, which also enables [`Self::with_serde`].
+    ///
+    /// Default is [`SerdeEnumRepr::Value`](serde_plugin::SerdeEnumRepr::Value).
+    pub fn serde_enum_repr(mut self, enum_repr: serde_plugin::SerdeEnumRepr) -> Self {
+        self.serde = Some(enum_repr);
+        self.reset_thrift_backend()
+    }
+
+    fn reset_thrift_backend(mut self) -> Self {
+        self.pilota_builder = self.pilota_builder.with_backend(
+            thrift_backend::MkThriftBackend::new(self.extern_paths.clone())
+                .serde(self.serde)
+                .annotations(self.annotations.clone()),
+        );
+        self
+    }
 }
 
 impl Builder<grpc_backend::MkGrpcBackend, parser::ProtobufParser> {
     pub fn protobuf() -> Self {
         Builder {
             pilota_builder: pilota_build::Builder::protobuf()
-                .with_backend(grpc_backend::MkGrpcBackend),
+                .with_backend(grpc_backend::MkGrpcBackend::default()),
             out_dir: Default::default(),
             filename: "volo_gen.rs".into(),
             idls: Default::default(),
             config_file_path: "volo.yml".into(),
+            extern_paths: Default::default(),
+            serde: None,
+            plugins: Vec::new(),
+            composite_server: false,
+            stream_sender: false,
+            blocking_client: false,
+            well_known_types: false,
+            raw_types_methods: Vec::new(),
+            codecs: HashMap::new(),
+            annotations: Default::default(),
+            include_dirs: Vec::new(),
         }
     }
+
+    /// Maps the package to an existing Rust path, e.g. `.extern_path(".common",
+    /// "common_types")`, so the types in it are referenced from the path instead of being
+    /// generated again.
+    pub fn extern_path(mut self, package: impl AsRef<str>, rust_path: impl AsRef<str>) -> Self {
+        self.extern_paths.insert(package, rust_path);
+        self.reset_grpc_backend()
+    }
+
+    /// Generates the `{Service}Composite` for each service, which composes the implementation
+    /// of a service from multiple structs registering different methods, see
+    /// `volo_grpc::server::composite`.
+    ///
+    /// Default is `false`.
+    pub fn composite_server(mut self, composite_server: bool) -> Self {
+        self.composite_server = composite_server;
+        self.reset_grpc_backend()
+    }
+
+    /// Generates the methods with streaming responses receiving a bounded
+    /// `volo_grpc::server::Sender` instead of returning a stream, so sending the messages applies
+    /// backpressure by the flow control of the connection, see `volo_grpc::server::sender`.
+    ///
+    /// Default is `false`.
+    pub fn stream_sender(mut self, stream_sender: bool) -> Self {
+        self.stream_sender = stream_sender;
+        self.reset_grpc_backend()
+    }
+
+    /// Generates the `{Service}BlockingClient` for each service in addition, which wraps the
+    /// client with a `volo_grpc::client::blocking::BlockingRuntime` and makes the unary and the
+    /// server streaming calls by blocking, e.g. for the callers can't run async at the call site.
+    ///
+    /// Default is `false`.
+    pub fn blocking_client(mut self, blocking_client: bool) -> Self {
+        self.blocking_client = blocking_client;
+        self.reset_grpc_backend()
+    }
+
+    /// Maps the well-known types of the unary methods to the idiomatic Rust types in the
+    /// signatures of the generated service traits and clients, e.g. no argument for
+    /// `google.protobuf.Empty` and `SystemTime` for `google.protobuf.Timestamp`, see
+    /// [`WellKnown`](grpc_backend::WellKnown).
+    ///
+    /// Default is `false`.
+    pub fn well_known_types(mut self, well_known_types: bool) -> Self {
+        self.well_known_types = well_known_types;
+        self.reset_grpc_backend()
+    }
+
+    /// Keeps the raw types in the signatures of the methods with [`Self::well_known_types`], by
+    /// the paths of the methods, e.g. `/helloworld.Greeter/SayHello`.
+    ///
+    /// The methods are opted out by the paths rather than by the options in the IDL, as the custom
+    /// options of the methods are not kept by the parser.
+    pub fn raw_types_methods<I, S>(mut self, methods: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.raw_types_methods = methods.into_iter().map(Into::into).collect();
+        self.reset_grpc_backend()
+    }
+
+    /// Encodes the messages of the service by a custom codec in addition to protobuf, e.g.
+    /// `.codec("helloworld.Greeter", "crate::codec::BincodeCodec")`, where the codec is the path
+    /// of a type implementing `volo_grpc::codec::Codec` for all the messages of the service.
+    ///
+    /// The messages are encoded by it for the content type `application/grpc+{subtype}` of the
+    /// codec, which is chosen by `MessageCodec::Custom` in the configs of the clients and the
+    /// servers.
+    pub fn codec(mut self, service: impl Into<String>, codec: impl Into<String>) -> Self {
+        self.codecs.insert(service.into(), codec.into());
+        self.reset_grpc_backend()
+    }
+
+    /// Derives `Serialize` and `Deserialize` of serde for the generated types, see
+    /// [`serde_plugin`] for details.
+    ///
+    /// Default is `false`.
+    pub fn with_serde(mut self, with_serde: bool) -> Self {
+        self.serde = with_serde.then(|| self.serde.unwrap_or_default());
+        self.reset_grpc_backend()
+    }
+
+    /// Sets how the enums are serialized by serde, which also enables [`Self::with_serde`].
+    ///
+    /// Default is [`SerdeEnumRepr::Value`](serde_plugin::SerdeEnumRepr::Value).
+    pub fn serde_enum_repr(mut self, enum_repr: serde_plugin::SerdeEnumRepr) -> Self {
+        self.serde = Some(enum_repr);
+        self.reset_grpc_backend()
+    }
+
+    fn reset_grpc_backend(mut self) -> Self {
+        self.pilota_builder = self.pilota_builder.with_backend(
+            grpc_backend::MkGrpcBackend::new(self.extern_paths.clone())
+                .composite_server(self.composite_server)
+                .stream_sender(self.stream_sender)
+                .blocking_client(self.blocking_client)
+                .well_known_types(self.well_known_types)
+                .raw_types_methods(self.raw_types_methods.clone())
+                .codecs(self.codecs.clone())
+                .serde(self.serde),
+        );
+        self
+    }
 }
 
 impl<MkB, Parser> Builder<MkB, Parser> {
@@ -69,8 +252,14 @@ impl<MkB, Parser> Builder<MkB, Parser> {
         self
     }
 
+    /// Registers a plugin of the codegen, which sees all the items, e.g. the messages and the
+    /// services, and runs after the built-in plugins, so it can adjust their output.
+    ///
+    /// The attributes added to the methods of the services by
+    /// [`Context::with_adjust_mut`] are emitted on the methods of the generated server traits
+    /// and clients, e.g. `#[deprecated]`.
     pub fn plugin<P: Plugin + 'static>(mut self, p: P) -> Self {
-        self.pilota_builder = self.pilota_builder.plugin(p);
+        self.plugins.push(Box::new(p));
 
         self
     }
@@ -91,11 +280,13 @@ impl<MkB, Parser> Builder<MkB, Parser> {
         self
     }
 
+    /// Whether to skip the items unused by the services, default is `true`.
     pub fn ignore_unused(mut self, ignore_unused: bool) -> Self {
         self.pilota_builder = self.pilota_builder.ignore_unused(ignore_unused);
         self
     }
 
+    /// Generates the items even if they're unused, by the paths of the IDLs and the names.
     pub fn touch(
         mut self,
         items: impl IntoIterator<Item = (PathBuf, Vec<impl Into<String>>)>,
@@ -104,6 +295,8 @@ impl<MkB, Parser> Builder<MkB, Parser> {
         self
     }
 
+    /// Keeps the unknown fields of the structs of the IDLs in the paths when decoding, and encodes
+    /// them back.
     pub fn keep_unknown_fields(
         mut self,
         keep_unknown_fields: impl IntoIterator<Item = PathBuf>,
@@ -141,6 +334,7 @@ where
     P: Parser,
 {
     pub fn include_dirs(mut self, include_dirs: Vec<PathBuf>) -> Self {
+        self.include_dirs.clone_from(&include_dirs);
         self.pilota_builder = self.pilota_builder.include_dirs(include_dirs);
         self
     }
@@ -161,19 +355,42 @@ where
             return Ok(());
         }
 
-        self.pilota_builder.compile_with_config(
+        let out_file = out_dir.join(self.filename);
+        // the generation owning the output file in the manifest
+        let generation = self.idls.iter().map(|idl| idl.display()).join(",");
+        self.annotations.load(&self.idls, &self.include_dirs)?;
+        let mut pilota_builder = self.pilota_builder;
+        if let Some(enum_repr) = self.serde {
+            pilota_builder = pilota_builder.plugin(serde_plugin::SerdePlugin::new(enum_repr));
+        }
+        for plugin in self.plugins {
+            pilota_builder = pilota_builder.plugin(plugin);
+        }
+        pilota_builder.compile_with_config(
             self.idls
                 .into_iter()
                 .map(IdlService::from_path)
                 .collect_vec(),
-            pilota_build::Output::File(out_dir.join(self.filename)),
+            pilota_build::Output::File(out_file.clone()),
         );
+        self.extern_paths.rewrite_file(&out_file)?;
+        descriptor::write_registry(&out_file)?;
+        for kept in manifest::record(&out_dir, &generation, &[out_file])? {
+            println!(
+                "cargo:warning=the stale generated file {} is kept since it's modified",
+                kept.display()
+            );
+        }
         Ok(())
     }
 
     pub fn init_service(self) -> anyhow::Result<(String, String)> {
         assert_eq!(self.idls.len(), 1);
-        self.pilota_builder.init_service(
+        let mut pilota_builder = self.pilota_builder;
+        for plugin in self.plugins {
+            pilota_builder = pilota_builder.plugin(plugin);
+        }
+        pilota_builder.init_service(
             self.idls
                 .into_iter()
                 .map(IdlService::from_path)
@@ -193,4 +410,11 @@ macro_rules! join_multi_strs {
 }
 
 pub(crate) use join_multi_strs;
+
+/// Returns the attributes added to the item by the plugins, e.g. to the methods of the services.
+pub(crate) fn adjusted_attrs(cx: &Context, def_id: DefId) -> String {
+    cx.adjust(def_id)
+        .map(|adj| adj.attrs().iter().join("\n"))
+        .unwrap_or_default()
+}
 use volo::FastStr;
@@ -0,0 +1,236 @@
+//! The manifest of the generated files in an output directory, which makes the output
+//! self-cleaning and verifiable.
+//!
+//! Each generation, e.g. the IDLs of a [`Builder`](crate::Builder), records the files it owns with
+//! their content hashes in the [`MANIFEST_FILE`] of the directory. When it's generated again, the
+//! files it owned before but not now, e.g. after renaming the output file or switching a branch,
+//! are deleted, so a stale file is never picked up by the build.
+//!
+//! The files not in any manifest are never deleted, nor the ones modified since generated, which
+//! are returned by [`record`] instead. [`verify`] checks the files on disk still match the
+//! manifest, e.g. by `volo doctor --verify <dir>` in CI.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context as _};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// The file name of the manifest in the output directory.
+pub const MANIFEST_FILE: &str = "volo-manifest.json";
+
+/// The files owned by each generation, see the [module level documentation](self).
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq, Eq)]
+pub struct Manifest {
+    /// The relative paths and the hashes of the files, by the generations.
+    generations: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl Manifest {
+    /// Loads the manifest of the directory, which is empty if not exists.
+    pub fn load(dir: &Path) -> anyhow::Result<Self> {
+        let path = dir.join(MANIFEST_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(&path)?;
+        serde_json::from_str(&content).with_context(|| format!("invalid {}", path.display()))
+    }
+
+    fn save(&self, dir: &Path) -> anyhow::Result<()> {
+        fs::write(dir.join(MANIFEST_FILE), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Returns the relative paths and the hashes of the files owned by the generation.
+    pub fn files(&self, generation: &str) -> Option<&BTreeMap<String, String>> {
+        self.generations.get(generation)
+    }
+}
+
+fn hash(path: &Path) -> anyhow::Result<String> {
+    let content = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    Ok(hex::encode(Sha256::digest(content)))
+}
+
+fn relative(dir: &Path, path: &Path) -> anyhow::Result<String> {
+    let path = path.strip_prefix(dir).unwrap_or(path);
+    if path.is_absolute() {
+        return Err(anyhow!(
+            "the generated file {} is not in {}",
+            path.display(),
+            dir.display()
+        ));
+    }
+    Ok(path.to_string_lossy().replace('\\', "/"))
+}
+
+/// Records the files written by the generation into the manifest of the directory, and deletes
+/// the ones it owned before but not now.
+///
+/// Returns the stale files kept since they're modified after generated.
+pub fn record(dir: &Path, generation: &str, files: &[PathBuf]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut manifest = Manifest::load(dir)?;
+
+    let mut owned = BTreeMap::new();
+    for file in files {
+        let file = relative(dir, file)?;
+        let hash = hash(&dir.join(&file))?;
+        owned.insert(file, hash);
+    }
+    let previous = manifest.generations.remove(generation).unwrap_or_default();
+    // the files may be moved to another generation
+    let others = manifest
+        .generations
+        .values()
+        .flat_map(|files| files.keys())
+        .collect::<BTreeSet<_>>();
+
+    let mut modified = Vec::new();
+    for (file, prev_hash) in previous.iter() {
+        if owned.contains_key(file) || others.contains(file) {
+            continue;
+        }
+        let path = dir.join(file);
+        if !path.exists() {
+            continue;
+        }
+        if hash(&path)? == *prev_hash {
+            fs::remove_file(&path)?;
+        } else {
+            modified.push(path);
+        }
+    }
+
+    manifest.generations.insert(generation.to_string(), owned);
+    manifest.save(dir)?;
+    Ok(modified)
+}
+
+/// Checks the files in the manifest of the directory exist with the same content as generated.
+pub fn verify(dir: &Path) -> anyhow::Result<()> {
+    if !dir.join(MANIFEST_FILE).exists() {
+        return Err(anyhow!("no {MANIFEST_FILE} in {}", dir.display()));
+    }
+    let manifest = Manifest::load(dir)?;
+
+    let mut mismatched = Vec::new();
+    for (file, expected) in manifest.generations.values().flatten() {
+        let path = dir.join(file);
+        if !path.exists() {
+            mismatched.push(format!("{file} (missing)"));
+        } else if hash(&path)? != *expected {
+            mismatched.push(format!("{file} (modified)"));
+        }
+    }
+    if !mismatched.is_empty() {
+        return Err(anyhow!(
+            "the generated files in {} don't match the manifest: {}",
+            dir.display(),
+            mismatched.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generate(dir: &Path, generation: &str, files: &[(&str, &str)]) -> Vec<PathBuf> {
+        let paths = files
+            .iter()
+            .map(|(name, content)| {
+                let path = dir.join(name);
+                fs::write(&path, content).unwrap();
+                path
+            })
+            .collect::<Vec<_>>();
+        record(dir, generation, &paths).unwrap()
+    }
+
+    /// The `.rs` files in the directory, i.e. picked up by the build.
+    fn on_disk(dir: &Path) -> BTreeSet<String> {
+        fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name.ends_with(".rs"))
+            .collect()
+    }
+
+    fn set(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn rename() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        fs::write(dir.join("user.rs"), "// mine").unwrap();
+
+        generate(
+            dir,
+            "echo.thrift",
+            &[("client_FooClient.rs", "foo"), ("mod.rs", "mod foo;")],
+        );
+        verify(dir).unwrap();
+
+        // the service is renamed
+        generate(
+            dir,
+            "echo.thrift",
+            &[("client_BarClient.rs", "bar"), ("mod.rs", "mod bar;")],
+        );
+        assert_eq!(
+            on_disk(dir),
+            set(&["client_BarClient.rs", "mod.rs", "user.rs"])
+        );
+        verify(dir).unwrap();
+        let manifest = Manifest::load(dir).unwrap();
+        let files = manifest.files("echo.thrift").unwrap();
+        assert_eq!(
+            files.keys().collect::<Vec<_>>(),
+            ["client_BarClient.rs", "mod.rs"]
+        );
+    }
+
+    #[test]
+    fn branch_switch() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+
+        // the other generations in the same directory are untouched
+        generate(dir, "hello.proto", &[("hello.rs", "hello")]);
+        let branch_a = [("a.rs", "a"), ("shared.rs", "shared v1")];
+        let branch_b = [("b.rs", "b"), ("shared.rs", "shared v2")];
+
+        generate(dir, "echo.thrift", &branch_a);
+        assert_eq!(on_disk(dir), set(&["a.rs", "hello.rs", "shared.rs"]));
+        generate(dir, "echo.thrift", &branch_b);
+        assert_eq!(on_disk(dir), set(&["b.rs", "hello.rs", "shared.rs"]));
+        assert_eq!(
+            fs::read_to_string(dir.join("shared.rs")).unwrap(),
+            "shared v2"
+        );
+        generate(dir, "echo.thrift", &branch_a);
+        assert_eq!(on_disk(dir), set(&["a.rs", "hello.rs", "shared.rs"]));
+        verify(dir).unwrap();
+
+        // a stale file modified after generated is kept and reported
+        fs::write(dir.join("a.rs"), "a, edited").unwrap();
+        let err = verify(dir).unwrap_err().to_string();
+        assert!(err.contains("a.rs (modified)"), "{err}");
+        let kept = generate(dir, "echo.thrift", &branch_b);
+        assert_eq!(kept, [dir.join("a.rs")]);
+        assert!(dir.join("a.rs").exists());
+        verify(dir).unwrap();
+
+        fs::remove_file(dir.join("hello.rs")).unwrap();
+        let err = verify(dir).unwrap_err().to_string();
+        assert!(err.contains("hello.rs (missing)"), "{err}");
+    }
+}
@@ -12,6 +12,33 @@ pub const DEFAULT_FILENAME: &str = "volo_gen.rs";
 #[derive(Default, Serialize, Deserialize, Debug, Clone)]
 pub struct SingleConfig {
     pub entries: HashMap<String, Entry>,
+    #[serde(default, skip_serializing_if = "Profile::is_empty")]
+    pub profile: Profile,
+}
+
+/// The runtime profile of the services, which is checked by `volo doctor` against the host.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    /// The total size of the connection pools of the clients.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pool_size: Option<u64>,
+    /// The max number of the connections accepted by the servers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_connections: Option<u64>,
+}
+
+impl Profile {
+    fn is_empty(&self) -> bool {
+        self.pool_size.is_none() && self.max_connections.is_none()
+    }
+
+    /// The number of the fds taken by the connections, `None` if none of them is configured.
+    pub fn required_fds(&self) -> Option<u64> {
+        if self.is_empty() {
+            return None;
+        }
+        Some(self.pool_size.unwrap_or_default() + self.max_connections.unwrap_or_default())
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
@@ -0,0 +1,148 @@
+//! The serde derives of the generated types, enabled by `with_serde` of the [`Builder`].
+//!
+//! The structs, newtypes and enums derive `Serialize` and `Deserialize`, so the crate of the
+//! generated code should depend on `serde`, and on `volo` with the `serde` feature for the
+//! helpers:
+//!
+//! - the optional fields are skipped when they are `None`, and are `None` when missing;
+//! - the binary fields are base64 strings;
+//! - the enums are their values by default, or their names with [`SerdeEnumRepr::Name`].
+//!
+//! The attributes of a type or a field are customized by the `pilota.serde_attribute`
+//! annotation, e.g.
+//!
+//! ```thrift
+//! struct UserInfo {
+//!     1: required i64 user_id,
+//!     2: optional string nick_name,
+//! } (pilota.serde_attribute = "#[serde(rename_all = \"camelCase\")]")
+//! ```
+//!
+//! [`Builder`]: crate::Builder
+
+use std::sync::Arc;
+
+use itertools::Itertools;
+use pilota_build::{
+    plugin::{walk_item, Plugin},
+    rir,
+    tags::SerdeAttribute,
+    ty::TyKind,
+    Context, DefId,
+};
+use volo::FastStr;
+
+const DERIVE: &str = "#[derive(::serde::Serialize, ::serde::Deserialize)]";
+const TRANSPARENT: &str = "#[serde(transparent)]";
+const SKIP_NONE: &str =
+    r#"#[serde(default, skip_serializing_if = "::std::option::Option::is_none")]"#;
+const BASE64: &str = r#"#[serde(with = "::volo::util::serde::base64")]"#;
+const BASE64_OPTION: &str = r#"#[serde(with = "::volo::util::serde::base64::option")]"#;
+
+/// How the enums with values, i.e. the thrift enums and the protobuf enums, are serialized.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SerdeEnumRepr {
+    /// The `i32` values.
+    #[default]
+    Value,
+    /// The names in the IDL, and the unknown values are still serialized as the values. Both the
+    /// names and the values are accepted when deserializing.
+    Name,
+}
+
+/// The plugin adding the serde attributes, see the [module level documentation](self).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SerdePlugin {
+    enum_repr: SerdeEnumRepr,
+}
+
+impl SerdePlugin {
+    pub fn new(enum_repr: SerdeEnumRepr) -> Self {
+        Self { enum_repr }
+    }
+}
+
+fn annotated(cx: &Context, def_id: DefId) -> Option<FastStr> {
+    cx.node_tags(def_id).and_then(|tags| {
+        tags.get::<SerdeAttribute>()
+            .map(|attr| attr.0.to_string().into())
+    })
+}
+
+impl Plugin for SerdePlugin {
+    fn on_item(&mut self, cx: &Context, def_id: DefId, item: Arc<rir::Item>) {
+        let attrs: &[&str] = match &*item {
+            rir::Item::Message(_) => &[DERIVE],
+            rir::Item::NewType(_) => &[DERIVE, TRANSPARENT],
+            // the enums by names are implemented by the backends, see `codegen_enum_names`
+            rir::Item::Enum(e) if e.repr.is_some() => match self.enum_repr {
+                SerdeEnumRepr::Value => &[DERIVE, TRANSPARENT],
+                SerdeEnumRepr::Name => &[],
+            },
+            // the thrift unions
+            rir::Item::Enum(_) => &[DERIVE],
+            _ => &[],
+        };
+        let attrs = attrs
+            .iter()
+            .map(|attr| FastStr::from_static_str(attr))
+            .chain(annotated(cx, def_id))
+            .collect::<Vec<_>>();
+        if !attrs.is_empty() {
+            cx.with_adjust_mut(def_id, |adj| adj.add_attrs(&attrs));
+        }
+
+        walk_item(self, cx, def_id, item)
+    }
+
+    fn on_field(&mut self, cx: &Context, def_id: DefId, f: Arc<rir::Field>) {
+        let mut attrs = Vec::new();
+        let optional = f.is_optional();
+        if optional {
+            attrs.push(FastStr::from_static_str(SKIP_NONE));
+        }
+        if matches!(f.ty.kind, TyKind::Bytes | TyKind::BytesVec) {
+            attrs.push(FastStr::from_static_str(if optional {
+                BASE64_OPTION
+            } else {
+                BASE64
+            }));
+        }
+        attrs.extend(annotated(cx, def_id));
+        if !attrs.is_empty() {
+            cx.with_adjust_mut(def_id, |adj| adj.add_attrs(&attrs));
+        }
+    }
+}
+
+/// The serde impls of the enums with values by [`SerdeEnumRepr::Name`].
+pub(crate) fn codegen_enum_names(cx: &Context, def_id: DefId, stream: &mut String, e: &rir::Enum) {
+    if e.repr.is_none() {
+        return;
+    }
+    let name = cx.rust_name(def_id);
+    let names = e
+        .variants
+        .iter()
+        .enumerate()
+        .map(|(i, v)| {
+            let value = v.discr.unwrap_or(i as i64);
+            format!(r#"("{}", {value})"#, v.name)
+        })
+        .join(", ");
+
+    stream.push_str(&format!(
+        r#"
+        impl ::serde::Serialize for {name} {{
+            fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error> {{
+                ::volo::util::serde::enum_name::serialize(self.0, &[{names}], serializer)
+            }}
+        }}
+
+        impl<'de> ::serde::Deserialize<'de> for {name} {{
+            fn deserialize<D: ::serde::Deserializer<'de>>(deserializer: D) -> ::std::result::Result<Self, D::Error> {{
+                ::volo::util::serde::enum_name::deserialize(deserializer, &[{names}]).map(Self)
+            }}
+        }}"#
+    ));
+}
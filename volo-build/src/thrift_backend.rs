@@ -1,21 +1,53 @@
+use std::{collections::HashMap, sync::Arc};
+
 use itertools::Itertools;
 use pilota_build::{
     codegen::thrift::DecodeHelper,
     db::RirDatabase,
-    rir::{self, Method},
+    rir::{self, FieldKind, Method},
     tags::RustWrapperArc,
-    ty::TyKind,
+    ty::{Ty, TyKind},
     CodegenBackend, Context, DefId, IdentName, Symbol, ThriftBackend,
 };
 use quote::format_ident;
 use volo::FastStr;
 
+use crate::{
+    descriptor::{
+        self, EnumInfo, FieldInfo, MethodAnnotations, MethodInfo, RequestSchemas, StructInfo,
+        TypeInfo,
+    },
+    extern_path::{ExternPaths, ExternTy},
+    serde_plugin::{codegen_enum_names, SerdeEnumRepr},
+};
+
 #[derive(Clone)]
 pub struct VoloThriftBackend {
     inner: ThriftBackend,
+    extern_paths: Arc<ExternPaths>,
+    serde: Option<SerdeEnumRepr>,
+    annotations: MethodAnnotations,
 }
 
 impl VoloThriftBackend {
+    /// The path of the item used outside the generated code, e.g. in the code of `volo init`.
+    fn global_path(&self, path: String) -> String {
+        self.extern_paths
+            .map_global(&path)
+            .unwrap_or_else(|| format!("volo_gen{path}"))
+    }
+
+    /// The type of the generated code, with the items in the packages mapped by the extern paths
+    /// rendered by those paths.
+    fn codegen_item_ty(&self, kind: TyKind) -> ExternTy<'_> {
+        self.extern_paths.codegen_item_ty(self.cx(), kind)
+    }
+
+    /// The path of the item relative to the current one, see [`ExternPaths::related_item_path`].
+    fn related_item_path(&self, did: DefId) -> FastStr {
+        self.extern_paths.related_item_path(self.cx(), did)
+    }
+
     fn codegen_service_anonymous_type(&self, stream: &mut String, def_id: DefId) {
         let service_name = self.cx().rust_name(def_id);
         let methods = self.cx().service_methods(def_id);
@@ -294,6 +326,156 @@ impl VoloThriftBackend {
         });
     }
 
+    /// Generates the descriptors of the service, including the methods of the services it
+    /// extends, whose annotations are the ones in the services declaring them.
+    fn codegen_service_descriptor(
+        &self,
+        stream: &mut String,
+        def_id: DefId,
+        service_name: &Symbol,
+        s: &rir::Service,
+    ) {
+        let mut schemas = RequestSchemas::default();
+        let mut seen = HashMap::new();
+        let methods = self
+            .cx()
+            .service_methods(def_id)
+            .iter()
+            .map(|m| {
+                let declared_by = match m.source {
+                    rir::MethodSource::Extend(def_id) => match &*self.cx().expect_item(def_id) {
+                        rir::Item::Service(s) => s.name.to_string(),
+                        _ => panic!("expected service"),
+                    },
+                    rir::MethodSource::Own => s.name.to_string(),
+                };
+                MethodInfo {
+                    name: m.name.to_string(),
+                    path: m.name.to_string(),
+                    client_streaming: false,
+                    server_streaming: false,
+                    request_type: self.method_args_path(service_name, m, false).to_string(),
+                    response_type: self.codegen_item_ty(m.ret.kind.clone()).to_string(),
+                    annotations: self.annotations.get(&declared_by, &m.name),
+                    request_schema: Some(self.args_schema(
+                        service_name,
+                        m,
+                        &mut schemas,
+                        &mut seen,
+                    )),
+                }
+            })
+            .collect::<Vec<_>>();
+        descriptor::codegen_service(stream, service_name, &s.name, &methods, &schemas);
+    }
+
+    /// Adds the schema of the arguments of the method, whose fields are the arguments, which are
+    /// all optional in the IDL.
+    fn args_schema(
+        &self,
+        service_name: &Symbol,
+        method: &Method,
+        schemas: &mut RequestSchemas,
+        seen: &mut HashMap<DefId, TypeInfo>,
+    ) -> usize {
+        let fields = method
+            .args
+            .iter()
+            .map(|a| FieldInfo {
+                id: a.id as i16,
+                required: false,
+                ty: self.type_schema(&a.ty, schemas, seen),
+            })
+            .collect();
+        schemas.structs.push(StructInfo {
+            name: self
+                .method_args_path(service_name, method, false)
+                .to_string(),
+            fields,
+        });
+        schemas.structs.len() - 1
+    }
+
+    /// Adds the schemas of the structs, unions and enums reachable from the type, where `seen`
+    /// keeps the ones added so the recursive types are added once.
+    fn type_schema(
+        &self,
+        ty: &Ty,
+        schemas: &mut RequestSchemas,
+        seen: &mut HashMap<DefId, TypeInfo>,
+    ) -> TypeInfo {
+        let path = match &ty.kind {
+            TyKind::Vec(el) => {
+                return TypeInfo::List(Box::new(self.type_schema(el, schemas, seen)))
+            }
+            TyKind::Set(el) => return TypeInfo::Set(Box::new(self.type_schema(el, schemas, seen))),
+            TyKind::Map(k, v) => {
+                return TypeInfo::Map(
+                    Box::new(self.type_schema(k, schemas, seen)),
+                    Box::new(self.type_schema(v, schemas, seen)),
+                )
+            }
+            TyKind::Arc(ty) => return self.type_schema(ty, schemas, seen),
+            TyKind::Path(path) => path,
+            _ => return TypeInfo::Value,
+        };
+        if let Some(info) = seen.get(&path.did) {
+            return info.clone();
+        }
+
+        match &*self.cx().expect_item(path.did) {
+            rir::Item::Enum(e) if e.repr.is_some() => {
+                schemas.enums.push(EnumInfo {
+                    name: e.name.to_string(),
+                    values: e
+                        .variants
+                        .iter()
+                        .filter_map(|v| v.discr.map(|d| d as i32))
+                        .collect(),
+                });
+                let info = TypeInfo::Enum(schemas.enums.len() - 1);
+                seen.insert(path.did, info.clone());
+                info
+            }
+            item @ (rir::Item::Message(_) | rir::Item::Enum(_)) => {
+                let index = schemas.structs.len();
+                schemas.structs.push(StructInfo {
+                    name: item.symbol_name().to_string(),
+                    fields: Vec::new(),
+                });
+                // added before the fields, which may refer to the struct itself
+                seen.insert(path.did, TypeInfo::Struct(index));
+                let fields = match item {
+                    rir::Item::Message(s) => s
+                        .fields
+                        .iter()
+                        .map(|f| FieldInfo {
+                            id: f.id as i16,
+                            required: f.kind == FieldKind::Required,
+                            ty: self.type_schema(&f.ty, schemas, seen),
+                        })
+                        .collect(),
+                    // the variants of a union are its optional fields
+                    rir::Item::Enum(e) => e
+                        .variants
+                        .iter()
+                        .filter_map(|v| Some((v.id?, v.fields.first()?)))
+                        .map(|(id, ty)| FieldInfo {
+                            id: id as i16,
+                            required: false,
+                            ty: self.type_schema(ty, schemas, seen),
+                        })
+                        .collect(),
+                    _ => unreachable!(),
+                };
+                schemas.structs[index].fields = fields;
+                TypeInfo::Struct(index)
+            }
+            rir::Item::NewType(t) => self.type_schema(&t.ty, schemas, seen),
+            _ => TypeInfo::Value,
+        }
+    }
+
     fn method_ty_path(&self, service_name: &Symbol, method: &Method, suffix: &str) -> FastStr {
         match method.source {
             rir::MethodSource::Extend(def_id) => {
@@ -353,7 +535,7 @@ impl pilota_build::CodegenBackend for VoloThriftBackend {
         self.inner.codegen_struct_impl(def_id, stream, s)
     }
 
-    fn codegen_service_impl(&self, def_id: DefId, stream: &mut String, _s: &rir::Service) {
+    fn codegen_service_impl(&self, def_id: DefId, stream: &mut String, s: &rir::Service) {
         let service_name = self.cx().rust_name(def_id);
         let server_name = format!("{service_name}Server");
         let generic_client_name = format!("{service_name}GenericClient");
@@ -371,11 +553,20 @@ impl pilota_build::CodegenBackend for VoloThriftBackend {
         let mut oneshot_client_methods = Vec::new();
 
         all_methods.iter().for_each(|m| {
+            check_oneway(&service_name, m);
             let name = self.cx().rust_name(m.def_id);
-            let resp_type = self.cx().codegen_item_ty(m.ret.kind.clone());
+            let mut attrs = crate::adjusted_attrs(self.cx(), m.def_id);
+            if m.oneway {
+                // the docs go before the attributes
+                attrs = format!(
+                    "/// Sends the oneway request without waiting for any response, i.e. \
+                     fire-and-forget, so it resolves once the request is sent.\n{attrs}"
+                );
+            }
+            let resp_type = self.codegen_item_ty(m.ret.kind.clone());
             let req_fields = m.args.iter().map(|a| {
                 let name = self.cx().rust_name(a.def_id).0.field_ident();
-                let ty = self.cx().codegen_item_ty(a.ty.kind.clone());
+                let ty = self.codegen_item_ty(a.ty.kind.clone());
                 let mut ty = format!("{ty}");
                 if let Some(RustWrapperArc(true)) = self.cx().tags(a.tags_id).as_ref().and_then(|tags| tags.get::<RustWrapperArc>()) {
                     ty = format!("::std::sync::Arc<{ty}>");
@@ -394,7 +585,7 @@ impl pilota_build::CodegenBackend for VoloThriftBackend {
             let req_field_names = m.args.iter().map(|a| self.cx().rust_name(a.def_id).0.field_ident()).join(",");
             let anonymous_args_send_name = self.method_args_path(&service_name, m, true);
             let exception = if let Some(p) = &m.exceptions {
-                self.cx().cur_related_item_path(p.did)
+                self.related_item_path(p.did)
             } else {
                 // only placeholder, should never be used
                 "std::convert::Infallible".into()
@@ -419,7 +610,8 @@ impl pilota_build::CodegenBackend for VoloThriftBackend {
                 resp_str = "::std::result::Result::Ok(::volo_thrift::MaybeException::Ok(resp))";
             }
             client_methods.push(format! {
-                r#"pub async fn {name}(&self {req_fields}) -> ::std::result::Result<{resp_type_str}, ::volo_thrift::ClientError> {{
+                r#"{attrs}
+                pub async fn {name}(&self {req_fields}) -> ::std::result::Result<{resp_type_str}, ::volo_thrift::ClientError> {{
                     let req = {req_send_name}::{enum_variant}({anonymous_args_send_name} {{
                         {req_field_names}
                     }});
@@ -441,7 +633,8 @@ impl pilota_build::CodegenBackend for VoloThriftBackend {
             });
 
             oneshot_client_methods.push(format! {
-                r#"pub async fn {name}(self {req_fields}) -> ::std::result::Result<{resp_type_str}, ::volo_thrift::ClientError> {{
+                r#"{attrs}
+                pub async fn {name}(self {req_fields}) -> ::std::result::Result<{resp_type_str}, ::volo_thrift::ClientError> {{
                     let req = {req_send_name}::{enum_variant}({anonymous_args_send_name} {{
                         {req_field_names}
                     }});
@@ -491,7 +684,7 @@ impl pilota_build::CodegenBackend for VoloThriftBackend {
                             .iter()
                             .map(|v| {
                                 let name = self.cx().rust_name(v.did);
-                                let exception = self.cx().cur_related_item_path(m.exceptions.as_ref().expect("must be exception here").did);
+                                let exception = self.related_item_path(m.exceptions.as_ref().expect("must be exception here").did);
                                 format!(
                                 "::std::result::Result::Ok(::volo_thrift::MaybeException::Exception({exception}::{name}(ex))) => {method_result_path}::{name}(ex),"
                             )
@@ -602,11 +795,13 @@ impl pilota_build::CodegenBackend for VoloThriftBackend {
             }}"#
         });
         self.codegen_service_anonymous_type(stream, def_id);
+        self.codegen_service_descriptor(stream, def_id, &service_name, s);
     }
 
-    fn codegen_service_method(&self, _service_def_id: DefId, method: &Method) -> String {
+    fn codegen_service_method(&self, service_def_id: DefId, method: &Method) -> String {
+        check_oneway(&self.cx().rust_name(service_def_id), method);
         let name = self.cx().rust_name(method.def_id);
-        let ret_ty = self.inner.codegen_item_ty(method.ret.kind.clone());
+        let ret_ty = self.codegen_item_ty(method.ret.kind.clone());
         let mut ret_ty = format!("{ret_ty}");
         if let Some(RustWrapperArc(true)) = self
             .cx()
@@ -620,19 +815,20 @@ impl pilota_build::CodegenBackend for VoloThriftBackend {
             .args
             .iter()
             .map(|a| {
-                let ty = self.inner.codegen_item_ty(a.ty.kind.clone());
+                let ty = self.codegen_item_ty(a.ty.kind.clone());
                 let ident = self.cx().rust_name(a.def_id).0.field_ident();
                 format!("{ident}: {ty}")
             })
             .join(",");
 
         if let Some(p) = &method.exceptions {
-            let exception = self.inner.cur_related_item_path(p.did);
+            let exception = self.related_item_path(p.did);
             ret_ty = format!("::volo_thrift::MaybeException<{ret_ty}, {exception}>");
         }
 
+        let attrs = crate::adjusted_attrs(self.cx(), method.def_id);
         format!(
-            "fn {name}(&self, {args}) -> impl ::std::future::Future<Output = \
+            "{attrs}\nfn {name}(&self, {args}) -> impl ::std::future::Future<Output = \
              ::core::result::Result<{ret_ty}, ::volo_thrift::ServerError>> + Send;"
         )
     }
@@ -644,12 +840,11 @@ impl pilota_build::CodegenBackend for VoloThriftBackend {
     ) -> String {
         let name = self.cx().rust_name(method.def_id);
         let mut ret_ty = self
-            .inner
             .codegen_item_ty(method.ret.kind.clone())
             .global_path()
             .to_string();
         if need_prepend_volo_gen_path(&method.ret.kind) {
-            ret_ty = format!("volo_gen{ret_ty}");
+            ret_ty = self.global_path(ret_ty);
         }
         if let Some(RustWrapperArc(true)) = self
             .cx()
@@ -663,14 +858,14 @@ impl pilota_build::CodegenBackend for VoloThriftBackend {
             .args
             .iter()
             .map(|a| {
-                let ty = self.inner.codegen_item_ty(a.ty.kind.clone()).global_path();
+                let ty = self.codegen_item_ty(a.ty.kind.clone()).global_path();
                 let ident = self.cx().rust_name(a.def_id);
-                format!("_{ident}: volo_gen{ty}")
+                format!("_{ident}: {}", self.global_path(ty.to_string()))
             })
             .join(",");
 
         if let Some(p) = &method.exceptions {
-            let exception = self.inner.cur_related_item_path(p.did);
+            let exception = self.related_item_path(p.did);
             ret_ty = format!("::volo_thrift::MaybeException<{ret_ty}, {exception}>");
         }
 
@@ -683,7 +878,10 @@ impl pilota_build::CodegenBackend for VoloThriftBackend {
     }
 
     fn codegen_enum_impl(&self, def_id: DefId, stream: &mut String, e: &rir::Enum) {
-        self.inner.codegen_enum_impl(def_id, stream, e)
+        self.inner.codegen_enum_impl(def_id, stream, e);
+        if self.serde == Some(SerdeEnumRepr::Name) {
+            codegen_enum_names(self.cx(), def_id, stream, e);
+        }
     }
 
     fn codegen_newtype_impl(&self, def_id: DefId, stream: &mut String, t: &rir::NewType) {
@@ -695,6 +893,25 @@ impl pilota_build::CodegenBackend for VoloThriftBackend {
     }
 }
 
+/// Checks the oneway method has no response, since nothing is sent back by the transport, so its
+/// handler returns `()` and its client resolves once the request is sent.
+fn check_oneway(service_name: &Symbol, method: &Method) {
+    if !method.oneway {
+        return;
+    }
+    assert!(
+        matches!(method.ret.kind, TyKind::Void),
+        "the oneway method `{service_name}.{}` must return void, since no response is sent",
+        method.name
+    );
+    assert!(
+        method.exceptions.is_none(),
+        "the oneway method `{service_name}.{}` must not throw exceptions, since no response is \
+         sent",
+        method.name
+    );
+}
+
 fn need_prepend_volo_gen_path(ty: &TyKind) -> bool {
     match ty {
         TyKind::Arc(t) => need_prepend_volo_gen_path(&t.kind),
@@ -712,7 +929,38 @@ fn rust_name(cx: &Context, def_id: DefId) -> FastStr {
     }
 }
 
-pub struct MkThriftBackend;
+#[derive(Default)]
+pub struct MkThriftBackend {
+    extern_paths: ExternPaths,
+    serde: Option<SerdeEnumRepr>,
+    annotations: MethodAnnotations,
+}
+
+impl MkThriftBackend {
+    pub fn new(extern_paths: ExternPaths) -> Self {
+        Self {
+            extern_paths,
+            serde: None,
+            annotations: MethodAnnotations::default(),
+        }
+    }
+
+    /// The annotations of the methods in the descriptors, which are loaded by the builder
+    /// before the code is generated.
+    pub(crate) fn annotations(mut self, annotations: MethodAnnotations) -> Self {
+        self.annotations = annotations;
+        self
+    }
+
+    /// Generates the serde impls of the enums by names with [`SerdeEnumRepr::Name`], and the
+    /// other serde attributes are added by the [`SerdePlugin`](crate::serde_plugin::SerdePlugin).
+    ///
+    /// Default is `None`.
+    pub fn serde(mut self, serde: Option<SerdeEnumRepr>) -> Self {
+        self.serde = serde;
+        self
+    }
+}
 
 impl pilota_build::MakeBackend for MkThriftBackend {
     type Target = VoloThriftBackend;
@@ -720,6 +968,46 @@ impl pilota_build::MakeBackend for MkThriftBackend {
     fn make_backend(self, context: Context) -> Self::Target {
         VoloThriftBackend {
             inner: ThriftBackend::new(context),
+            extern_paths: Arc::new(self.extern_paths),
+            serde: self.serde,
+            annotations: self.annotations,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    /// Generates the code of the thrift IDL, which panics if the IDL is rejected.
+    fn codegen(idl: &str) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("oneway.thrift");
+        fs::write(&path, idl).unwrap();
+        crate::Builder::thrift()
+            .add_service(&path)
+            .out_dir(dir.path())
+            .write()
+            .unwrap();
+    }
+
+    #[test]
+    fn oneway() {
+        codegen("service Echo { oneway void ping(1: string msg) }");
+    }
+
+    #[test]
+    #[should_panic(expected = "the oneway method `Echo.ping` must return void")]
+    fn oneway_with_response() {
+        codegen("service Echo { oneway string ping(1: string msg) }");
+    }
+
+    #[test]
+    #[should_panic(expected = "the oneway method `Echo.ping` must not throw exceptions")]
+    fn oneway_with_exceptions() {
+        codegen(
+            "exception Error { 1: string msg }
+            service Echo { oneway void ping(1: string msg) throws (1: Error e) }",
+        );
+    }
+}
@@ -4,19 +4,25 @@ use pilota_build::{IdlService, Plugin};
 use volo::FastStr;
 
 use crate::{
+    descriptor::MethodAnnotations,
     model::{GitSource, Source, WorkspaceConfig},
     util::{download_repos_to_target, strip_slash_prefix},
 };
 
 pub struct Builder<MkB, P> {
     pilota_builder: pilota_build::Builder<MkB, P>,
+    // the annotations of the thrift methods in the descriptors
+    annotations: MethodAnnotations,
 }
 
 impl Builder<crate::thrift_backend::MkThriftBackend, crate::parser::ThriftParser> {
     pub fn thrift() -> Self {
+        let annotations = MethodAnnotations::default();
         Self {
-            pilota_builder: pilota_build::Builder::thrift()
-                .with_backend(crate::thrift_backend::MkThriftBackend),
+            pilota_builder: pilota_build::Builder::thrift().with_backend(
+                crate::thrift_backend::MkThriftBackend::default().annotations(annotations.clone()),
+            ),
+            annotations,
         }
     }
 }
@@ -91,7 +97,13 @@ where
                     }
                 }
             })
-            .collect();
+            .collect::<Vec<_>>();
+
+        let idls = services.iter().map(|s| s.path.clone()).collect::<Vec<_>>();
+        if let Err(e) = self.annotations.load(&idls, &include_dirs) {
+            eprintln!("failed to load the annotations of the methods, err: {}", e);
+            std::process::exit(1);
+        }
 
         self.include_dirs(include_dirs)
             .ignore_unused(!config.common_option.touch_all)
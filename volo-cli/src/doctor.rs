@@ -0,0 +1,631 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    net::{TcpListener, ToSocketAddrs},
+    panic::AssertUnwindSafe,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use clap::Parser;
+use colored::Colorize;
+use serde::Serialize;
+use volo_build::{
+    config_builder::ConfigBuilder,
+    model::{SingleConfig, Source},
+    util::{open_config_file, read_config_from_file, DEFAULT_CONFIG_FILE},
+};
+
+use crate::{command::CliCommand, context::Context};
+
+/// The crates whose versions should be aligned in one workspace.
+const ALIGNED_CRATES: &[&str] = &[
+    "volo",
+    "volo-build",
+    "volo-grpc",
+    "volo-thrift",
+    "volo-http",
+    "pilota",
+    "pilota-build",
+    "pilota-thrift-parser",
+];
+
+#[derive(Parser, Debug)]
+#[command(about = "diagnose common environment and build setup issues")]
+pub struct Doctor {
+    #[arg(
+        long = "json",
+        help = "Output the report in json format, which is useful for CI."
+    )]
+    pub json: bool,
+
+    #[arg(
+        long = "resolve",
+        help = "Check that the given host names can be resolved, split by ','.",
+        value_delimiter = ','
+    )]
+    pub resolve: Vec<String>,
+
+    #[arg(
+        long = "verify",
+        help = "Check that the generated files in the given directories match their manifests, \
+                split by ','.",
+        value_delimiter = ','
+    )]
+    pub verify: Vec<PathBuf>,
+}
+
+#[derive(Serialize, Debug)]
+struct Check {
+    name: String,
+    ok: bool,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hint: Option<String>,
+}
+
+impl Check {
+    fn pass(name: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            ok: true,
+            message: message.into(),
+            hint: None,
+        }
+    }
+
+    fn fail(name: impl Into<String>, message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            ok: false,
+            message: message.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+impl CliCommand for Doctor {
+    fn run(&self, cx: Context) -> anyhow::Result<()> {
+        let mut checks = Vec::new();
+        checks.extend(check_versions());
+        // `open_config_file` will create the file if not exists, which is unwanted here.
+        let config_file = Path::new(DEFAULT_CONFIG_FILE);
+        let config = if config_file.exists() {
+            match read_config(config_file) {
+                Ok(config) => Some(config),
+                Err(check) => {
+                    checks.push(check);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        if let Some(config) = &config {
+            checks.extend(check_codegen(
+                config_file,
+                config,
+                &cx.entry_name,
+                &target_dir(),
+            ));
+        }
+        checks.extend(check_fd_limit(
+            config.and_then(|config| config.profile.required_fds()),
+        ));
+        checks.extend(check_network(&self.resolve));
+        checks.extend(check_manifests(&self.verify));
+
+        if self.json {
+            println!("{}", to_json(&checks)?);
+        } else {
+            for check in checks.iter() {
+                if check.ok {
+                    println!("{} {}: {}", "✓".green(), check.name.bold(), check.message);
+                } else {
+                    println!("{} {}: {}", "✗".red(), check.name.bold(), check.message);
+                    if let Some(hint) = &check.hint {
+                        println!("    {} {}", "hint:".yellow(), hint);
+                    }
+                }
+            }
+        }
+
+        let failed = checks.iter().filter(|c| !c.ok).count();
+        if failed > 0 {
+            return Err(anyhow::anyhow!("{failed} check(s) failed"));
+        }
+        Ok(())
+    }
+}
+
+/// The report in json format, which is an array of the checks.
+fn to_json(checks: &[Check]) -> serde_json::Result<String> {
+    serde_json::to_string_pretty(checks)
+}
+
+/// Checks that the generated files in the directories are not modified or missing since generated.
+fn check_manifests(dirs: &[PathBuf]) -> Vec<Check> {
+    dirs.iter()
+        .map(|dir| {
+            let name = format!("manifest/{}", dir.display());
+            match volo_build::manifest::verify(dir) {
+                Ok(()) => Check::pass(name, "the generated files match the manifest"),
+                Err(e) => Check::fail(
+                    name,
+                    e.to_string(),
+                    "regenerate the code, e.g. by `cargo clean` and build again",
+                ),
+            }
+        })
+        .collect()
+}
+
+/// Checks that there is only one version of each volo and pilota crate in the workspace, and the
+/// versions of pilota crates are compatible with each other.
+fn check_versions() -> Vec<Check> {
+    let output = match Command::new("cargo")
+        .args(["metadata", "--format-version", "1"])
+        .output()
+    {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            return vec![Check::fail(
+                "versions",
+                format!(
+                    "failed to run `cargo metadata`: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+                "run `volo doctor` in the root of a cargo workspace",
+            )]
+        }
+        Err(e) => {
+            return vec![Check::fail(
+                "versions",
+                format!("failed to run `cargo metadata`: {e}"),
+                "make sure `cargo` is installed and in the PATH",
+            )]
+        }
+    };
+
+    let metadata: serde_json::Value = match serde_json::from_slice(&output.stdout) {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            return vec![Check::fail(
+                "versions",
+                format!("failed to parse the output of `cargo metadata`: {e}"),
+                "make sure the version of `cargo` is up to date",
+            )]
+        }
+    };
+
+    let mut versions: BTreeMap<&str, BTreeSet<String>> = BTreeMap::new();
+    for package in metadata["packages"].as_array().into_iter().flatten() {
+        let (Some(name), Some(version)) = (package["name"].as_str(), package["version"].as_str())
+        else {
+            continue;
+        };
+        if let Some(name) = ALIGNED_CRATES.iter().find(|c| **c == name) {
+            versions
+                .entry(*name)
+                .or_default()
+                .insert(version.to_string());
+        }
+    }
+
+    let mut checks = Vec::new();
+    for (name, versions) in versions.iter() {
+        let check_name = format!("versions/{name}");
+        if versions.len() > 1 {
+            let versions = versions.iter().cloned().collect::<Vec<_>>().join(", ");
+            checks.push(Check::fail(
+                check_name,
+                format!("multiple versions found: {versions}"),
+                format!(
+                    "align the version requirements of `{name}` across the workspace, and run \
+                     `cargo update -p {name}`"
+                ),
+            ));
+        } else {
+            checks.push(Check::pass(
+                check_name,
+                versions.iter().next().cloned().unwrap_or_default(),
+            ));
+        }
+    }
+
+    // pilota crates are released together, their `major.minor` must be the same.
+    let minors = ["pilota", "pilota-build", "pilota-thrift-parser"]
+        .iter()
+        .filter_map(|name| versions.get(name))
+        .flatten()
+        .map(|v| v.split('.').take(2).collect::<Vec<_>>().join("."))
+        .collect::<BTreeSet<_>>();
+    if minors.len() > 1 {
+        let minors = minors.into_iter().collect::<Vec<_>>().join(", ");
+        checks.push(Check::fail(
+            "versions/pilota-alignment",
+            format!("incompatible pilota versions found: {minors}"),
+            "use the same `major.minor` version for `pilota`, `pilota-build` and \
+             `pilota-thrift-parser`, which should also match the one used by `volo-build`",
+        ));
+    }
+
+    checks
+}
+
+/// Reads the config, whose errors are reported as a failed check.
+fn read_config(config_file: &Path) -> Result<SingleConfig, Check> {
+    open_config_file(config_file)
+        .map_err(anyhow::Error::from)
+        .and_then(|mut f| read_config_from_file(&mut f).map_err(anyhow::Error::from))
+        .map_err(|e| {
+            Check::fail(
+                "codegen",
+                format!("failed to read {}: {e}", config_file.display()),
+                "fix the config file, or run `volo migrate` if it is in legacy format",
+            )
+        })
+}
+
+/// Checks that the idls of the entry are readable, and the generated code is the same as the one
+/// regenerated from the idls into a temporary directory.
+fn check_codegen(
+    config_file: &Path,
+    config: &SingleConfig,
+    entry_name: &str,
+    target_dir: &Path,
+) -> Vec<Check> {
+    let Some(entry) = config.entries.get(entry_name) else {
+        return vec![Check::fail(
+            "codegen",
+            format!("entry {entry_name} not found in {}", config_file.display()),
+            "specify the entry with `volo -n <entry> doctor`",
+        )];
+    };
+
+    let mut checks = Vec::new();
+    for service in entry.services.iter() {
+        let idl = &service.idl;
+        // git idls are downloaded during build, so only local idls can be checked here.
+        if !matches!(idl.source, Source::Local) {
+            continue;
+        }
+        let check_name = format!("codegen/{}", idl.path.display());
+        match idl.ensure_readable() {
+            Ok(()) => checks.push(Check::pass(check_name, "readable")),
+            Err(e) => checks.push(Check::fail(
+                check_name,
+                format!("idl is not readable: {e}"),
+                "check the `path` and `includes` of the service in the config file",
+            )),
+        }
+    }
+    if checks.iter().any(|c| !c.ok) {
+        return checks;
+    }
+
+    let generated = find_generated_files(target_dir, &entry.filename);
+    if generated.is_empty() {
+        checks.push(Check::fail(
+            format!("codegen/{}", entry.filename.display()),
+            "generated code not found",
+            "run `cargo build` to generate the code",
+        ));
+        return checks;
+    }
+
+    let regenerated = match regenerate(config_file, entry_name, &entry.filename) {
+        Ok(regenerated) => regenerated,
+        Err(e) => {
+            checks.push(Check::fail(
+                format!("codegen/{}", entry.filename.display()),
+                format!("failed to regenerate the code: {e}"),
+                "run `cargo build` to see the errors of the code generation",
+            ));
+            return checks;
+        }
+    };
+    for path in generated {
+        let check_name = format!("codegen/{}", path.display());
+        match std::fs::read_to_string(&path) {
+            Ok(code) if code == regenerated => {
+                checks.push(Check::pass(check_name, "up to date with the idls"))
+            }
+            Ok(code) => {
+                // the first different line, which is `0` when one is the prefix of the other
+                let line = code
+                    .lines()
+                    .zip(regenerated.lines())
+                    .position(|(a, b)| a != b)
+                    .unwrap_or_else(|| code.lines().count().min(regenerated.lines().count()));
+                checks.push(Check::fail(
+                    check_name,
+                    format!(
+                        "generated code is stale, which differs from the regenerated one at line \
+                         {}",
+                        line + 1
+                    ),
+                    "run `cargo build` to regenerate the code, and note that the plugins added in \
+                     `build.rs` are not applied by `volo doctor`",
+                ));
+            }
+            Err(e) => checks.push(Check::fail(
+                check_name,
+                format!("failed to read the generated code: {e}"),
+                "run `cargo clean` and build again",
+            )),
+        }
+    }
+
+    checks
+}
+
+/// Regenerates the code of the entry into a temporary directory, without touching the build
+/// outputs.
+fn regenerate(config_file: &Path, entry_name: &str, filename: &Path) -> anyhow::Result<String> {
+    let out_dir = tempfile::tempdir()?;
+    // the code generation panics on the invalid idls
+    std::panic::catch_unwind(AssertUnwindSafe(|| {
+        ConfigBuilder::new(config_file.to_path_buf())
+            .entry_name(entry_name)
+            .out_dir(out_dir.path())
+            .write()
+    }))
+    .map_err(|_| anyhow::anyhow!("the code generation panicked"))??;
+    Ok(std::fs::read_to_string(out_dir.path().join(filename))?)
+}
+
+fn target_dir() -> PathBuf {
+    PathBuf::from(std::env::var("CARGO_TARGET_DIR").unwrap_or_else(|_| "target".to_string()))
+}
+
+/// Finds the latest generated file of each profile in the build output directories, the older
+/// ones are left by the previous builds.
+fn find_generated_files(target_dir: &Path, filename: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for profile in ["debug", "release"] {
+        let Ok(builds) = std::fs::read_dir(target_dir.join(profile).join("build")) else {
+            continue;
+        };
+        let latest = builds
+            .flatten()
+            .map(|build| build.path().join("out").join(filename))
+            .filter_map(|file| {
+                let modified = std::fs::metadata(&file).and_then(|m| m.modified()).ok()?;
+                Some((modified, file))
+            })
+            .max();
+        files.extend(latest.map(|(_, file)| file));
+    }
+    files
+}
+
+/// Checks that the fd limit is enough for the connections in the `profile` of the config.
+fn check_fd_limit(required_fds: Option<u64>) -> Vec<Check> {
+    #[cfg(target_family = "unix")]
+    {
+        let limit = volo::net::probe::fd_limit();
+        let message = match limit {
+            Some(limit) => format!("soft limit of open files is {limit}"),
+            None => "soft limit of open files is unlimited".to_string(),
+        };
+        match required_fds {
+            Some(required) => match volo::net::probe::check_fd_limit(required) {
+                Ok(()) => vec![Check::pass("fd-limit", message)],
+                Err(limit) => vec![Check::fail(
+                    "fd-limit",
+                    format!(
+                        "soft limit of open files is {limit}, which is less than the {required} \
+                         connections in the profile"
+                    ),
+                    format!("raise the limit with `ulimit -n {required}` or in the service unit"),
+                )],
+            },
+            None => vec![Check::pass("fd-limit", message)],
+        }
+    }
+    #[cfg(not(target_family = "unix"))]
+    {
+        let _ = required_fds;
+        Vec::new()
+    }
+}
+
+/// Checks that the host can bind on ipv4 and ipv6, and can resolve the given names.
+fn check_network(names: &[String]) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    for (name, addr) in [("network/ipv4", "127.0.0.1:0"), ("network/ipv6", "[::1]:0")] {
+        match TcpListener::bind(addr) {
+            Ok(_) => checks.push(Check::pass(name, format!("can bind on {addr}"))),
+            Err(e) => checks.push(Check::fail(
+                name,
+                format!("failed to bind on {addr}: {e}"),
+                "listen on an address of the other ip stack, or enable it on the host",
+            )),
+        }
+    }
+
+    for name in names {
+        let check_name = format!("network/resolve/{name}");
+        // a port is required by `ToSocketAddrs`, it doesn't matter here
+        match (name.as_str(), 0).to_socket_addrs() {
+            Ok(addrs) => {
+                let addrs = addrs.map(|a| a.ip().to_string()).collect::<Vec<_>>();
+                if addrs.is_empty() {
+                    checks.push(Check::fail(
+                        check_name,
+                        "resolved to no address",
+                        "check the dns records of the name",
+                    ));
+                } else {
+                    checks.push(Check::pass(check_name, addrs.join(", ")));
+                }
+            }
+            Err(e) => checks.push(Check::fail(
+                check_name,
+                format!("failed to resolve: {e}"),
+                "check `/etc/resolv.conf` and the dns records of the name",
+            )),
+        }
+    }
+
+    checks
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    const IDL: &str = r#"
+namespace rs echo
+
+struct EchoRequest {
+    1: required string message,
+}
+
+struct EchoResponse {
+    1: required string message,
+}
+
+service EchoService {
+    EchoResponse echo(1: EchoRequest req),
+}
+"#;
+
+    /// A project with the config of an echo service, and the code generated into the `target` of
+    /// it as the build script does.
+    struct Project {
+        dir: tempfile::TempDir,
+    }
+
+    impl Project {
+        fn new() -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            let idl = dir.path().join("echo.thrift");
+            fs::write(&idl, IDL).unwrap();
+            fs::write(
+                dir.path().join(DEFAULT_CONFIG_FILE),
+                format!(
+                    r#"entries:
+  default:
+    filename: volo_gen.rs
+    protocol: thrift
+    services:
+    - idl:
+        source: local
+        path: {}
+profile:
+  pool_size: 16
+  max_connections: 16
+"#,
+                    idl.display()
+                ),
+            )
+            .unwrap();
+
+            let project = Self { dir };
+            ConfigBuilder::new(project.config_file())
+                .out_dir(project.out_dir())
+                .write()
+                .unwrap();
+            project
+        }
+
+        fn config_file(&self) -> PathBuf {
+            self.dir.path().join(DEFAULT_CONFIG_FILE)
+        }
+
+        fn target_dir(&self) -> PathBuf {
+            self.dir.path().join("target")
+        }
+
+        fn out_dir(&self) -> PathBuf {
+            self.target_dir()
+                .join("debug")
+                .join("build")
+                .join("volo-gen-0123456789abcdef")
+                .join("out")
+        }
+
+        fn check_codegen(&self) -> Vec<Check> {
+            let config = read_config(&self.config_file()).unwrap();
+            check_codegen(&self.config_file(), &config, "default", &self.target_dir())
+        }
+    }
+
+    #[test]
+    fn codegen_fresh() {
+        let project = Project::new();
+
+        let checks = project.check_codegen();
+        assert!(checks.iter().all(|c| c.ok), "{checks:?}");
+        assert!(checks
+            .iter()
+            .any(|c| c.name.ends_with("volo_gen.rs") && c.message == "up to date with the idls"));
+    }
+
+    #[test]
+    fn codegen_stale() {
+        let project = Project::new();
+        // the idl is changed after the code is generated
+        fs::write(
+            project.dir.path().join("echo.thrift"),
+            IDL.replace("1: required string message,", "1: required string msg,"),
+        )
+        .unwrap();
+
+        let checks = project.check_codegen();
+        let stale = checks.iter().find(|c| !c.ok).unwrap();
+        assert!(stale.name.ends_with("volo_gen.rs"));
+        assert!(stale.message.starts_with("generated code is stale"));
+    }
+
+    #[test]
+    fn codegen_not_generated() {
+        let project = Project::new();
+        fs::remove_dir_all(project.target_dir()).unwrap();
+
+        let checks = project.check_codegen();
+        let missing = checks.iter().find(|c| !c.ok).unwrap();
+        assert_eq!(missing.message, "generated code not found");
+    }
+
+    #[test]
+    fn fd_limit_from_profile() {
+        let project = Project::new();
+        let config = read_config(&project.config_file()).unwrap();
+        assert_eq!(config.profile.required_fds(), Some(32));
+    }
+
+    #[test]
+    fn json_output() {
+        let checks = vec![
+            Check::pass("codegen/echo.thrift", "readable"),
+            Check::fail(
+                "codegen/volo_gen.rs",
+                "generated code not found",
+                "run `cargo build` to generate the code",
+            ),
+        ];
+
+        let json: serde_json::Value = serde_json::from_str(&to_json(&checks).unwrap()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([
+                {
+                    "name": "codegen/echo.thrift",
+                    "ok": true,
+                    "message": "readable",
+                },
+                {
+                    "name": "codegen/volo_gen.rs",
+                    "ok": false,
+                    "message": "generated code not found",
+                    "hint": "run `cargo build` to generate the code",
+                },
+            ])
+        );
+    }
+}
@@ -3,8 +3,8 @@ use clap::{ArgAction, Parser};
 use volo_build::model::DEFAULT_ENTRY_NAME;
 
 use crate::{
-    command::CliCommand, context::Context, http::Http, idl::Idl, init::Init, migrate::Migrate,
-    repo::Repo,
+    command::CliCommand, context::Context, doctor::Doctor, http::Http, idl::Idl, init::Init,
+    migrate::Migrate, repo::Repo,
 };
 
 define_commands!(Subcommand {
@@ -12,7 +12,8 @@ define_commands!(Subcommand {
     Repo,
     Idl,
     Migrate,
-    Http
+    Http,
+    Doctor
 });
 
 #[derive(Parser, Debug)]
@@ -7,9 +7,18 @@ use std::{
 use bytes::Bytes;
 use futures::{ready, TryStreamExt};
 use http_body::{Body as HttpBody, Frame};
+use http_body_util::BodyExt;
 use pin_project::pin_project;
 
-use crate::{BoxStream, Code, Status};
+use crate::{response::Trailers, BoxStream, Code, Status};
+
+/// The type-erased body of the received requests and responses.
+pub type BoxBody = http_body_util::combinators::BoxBody<Bytes, Status>;
+
+/// Boxes the body received by hyper.
+pub fn boxed(body: hyper::body::Incoming) -> BoxBody {
+    BoxBody::new(body.map_err(|err| Status::from_error(Box::new(err))))
+}
 
 /// Similar to [`hyper::Body`], used when sending bodies to client.
 ///
@@ -20,6 +29,9 @@ pub struct Body {
     #[pin]
     bytes_stream: BoxStream<'static, Result<Frame<Bytes>, Status>>,
     is_end_stream: bool,
+    with_trailers: bool,
+    /// The custom trailing metadata sent with the status.
+    trailers: Option<Trailers>,
 }
 
 impl Body {
@@ -28,6 +40,8 @@ impl Body {
         Self {
             bytes_stream,
             is_end_stream: false,
+            with_trailers: true,
+            trailers: None,
         }
     }
 
@@ -35,6 +49,34 @@ impl Body {
         self.is_end_stream = true;
         self
     }
+
+    /// Sends the frames of the stream as they are, without converting the end or the error of the
+    /// stream into trailers.
+    pub(crate) fn without_trailers(mut self) -> Self {
+        self.with_trailers = false;
+        self
+    }
+
+    /// Sends the custom trailing metadata with the status.
+    pub(crate) fn trailers(mut self, trailers: Option<Trailers>) -> Self {
+        self.trailers = trailers;
+        self
+    }
+}
+
+/// Makes the trailers frame of the status and the custom trailing metadata.
+fn trailer_frame(trailers: &Option<Trailers>, status: Status) -> Result<Frame<Bytes>, Status> {
+    let mut headers = match trailers {
+        Some(trailers) => trailers.take().into_headers(),
+        None => http::HeaderMap::new(),
+    };
+    // the status overrides the custom ones of the same names
+    for (name, value) in status.to_header_map()? {
+        if let Some(name) = name {
+            headers.insert(name, value);
+        }
+    }
+    Ok(Frame::trailers(headers))
 }
 
 impl HttpBody for Body {
@@ -50,16 +92,25 @@ impl HttpBody for Body {
         if !*self_proj.is_end_stream {
             match ready!(self_proj.bytes_stream.try_poll_next_unpin(cx)) {
                 Some(Ok(data)) => Poll::Ready(Some(Ok(data))),
+                Some(Err(status)) if !*self_proj.with_trailers => {
+                    *self_proj.is_end_stream = true;
+                    Poll::Ready(Some(Err(status)))
+                }
+                None if !*self_proj.with_trailers => {
+                    *self_proj.is_end_stream = true;
+                    Poll::Ready(None)
+                }
                 Some(Err(status)) => {
                     tracing::debug!("[VOLO] failed to poll stream");
                     *self_proj.is_end_stream = true;
-                    Poll::Ready(Some(Ok(Frame::trailers(status.to_header_map()?))))
+                    Poll::Ready(Some(trailer_frame(self_proj.trailers, status)))
                 }
                 None => {
                     *self_proj.is_end_stream = true;
-                    Poll::Ready(Some(Ok(Frame::trailers(
-                        Status::new(Code::Ok, "").to_header_map()?,
-                    ))))
+                    Poll::Ready(Some(trailer_frame(
+                        self_proj.trailers,
+                        Status::new(Code::Ok, ""),
+                    )))
                 }
             }
         } else {
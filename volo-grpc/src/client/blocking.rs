@@ -0,0 +1,290 @@
+//! The runtime of the blocking clients generated with `blocking_client` of `volo-build`, for the
+//! callers which can't run async at the call site, e.g. scripts or FFI.
+//!
+//! ```rust,ignore
+//! use volo_grpc::client::blocking::BlockingRuntime;
+//!
+//! let client = GreeterBlockingClient::new(client, BlockingRuntime::current_thread()?);
+//! let resp = client.say_hello(req)?;
+//! for message in client.list_greetings(req)? {
+//!     println!("{:?}", message?);
+//! }
+//! ```
+//!
+//! A call blocks the current thread until it's done on the runtime, which is either a handle of
+//! a runtime running elsewhere, or a current-thread runtime owned by the client and driven by the
+//! blocked thread itself.
+//!
+//! Blocking a thread running async tasks stalls them all, so the calls are rejected by
+//! [`BlockingError::AsyncContext`] inside a current-thread runtime. Inside a multi-thread runtime,
+//! e.g. in `spawn_blocking`, the calls are made by `tokio::task::block_in_place`, which hands off
+//! the tasks of the worker first.
+
+use std::{
+    fmt,
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use futures::StreamExt;
+use tokio::runtime::{Handle, Runtime, RuntimeFlavor};
+
+use crate::{metadata::MetadataMap, Code, Status, StreamingResponse};
+
+/// The runtime making the calls of a blocking client, which is cheap to clone.
+#[derive(Clone)]
+pub struct BlockingRuntime(Inner);
+
+#[derive(Clone)]
+enum Inner {
+    Handle(Handle),
+    Owned(Arc<OwnedRuntime>),
+}
+
+impl BlockingRuntime {
+    /// Makes the calls on the runtime of the handle.
+    ///
+    /// The runtime should be a multi-thread one, or a current-thread one driven by another
+    /// thread, as the blocked thread doesn't drive it.
+    pub fn from_handle(handle: Handle) -> Self {
+        Self(Inner::Handle(handle))
+    }
+
+    /// Makes the calls on a new current-thread runtime owned by the clones.
+    pub fn current_thread() -> std::io::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        Ok(Self(Inner::Owned(Arc::new(OwnedRuntime(Some(runtime))))))
+    }
+
+    /// Runs the future on the runtime, blocking the current thread until it's done.
+    ///
+    /// Returns [`BlockingError::AsyncContext`] instead of blocking a current-thread runtime.
+    pub fn block_on<F: Future>(&self, future: F) -> Result<F::Output, BlockingError> {
+        match Handle::try_current() {
+            Err(_) => Ok(self.enter(future)),
+            Ok(current) if current.runtime_flavor() == RuntimeFlavor::MultiThread => {
+                Ok(tokio::task::block_in_place(|| self.enter(future)))
+            }
+            Ok(_) => Err(BlockingError::AsyncContext),
+        }
+    }
+
+    fn enter<F: Future>(&self, future: F) -> F::Output {
+        match &self.0 {
+            Inner::Handle(handle) => handle.block_on(future),
+            Inner::Owned(runtime) => runtime.0.as_ref().unwrap().block_on(future),
+        }
+    }
+
+    /// Receives the messages of the response by blocking on the runtime.
+    pub fn stream<T>(&self, response: StreamingResponse<T>) -> BlockingStream<T> {
+        BlockingStream {
+            runtime: self.clone(),
+            response: Some(response),
+        }
+    }
+}
+
+impl fmt::Debug for BlockingRuntime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Inner::Handle(handle) => f.debug_tuple("Handle").field(handle).finish(),
+            Inner::Owned(_) => f.write_str("CurrentThread"),
+        }
+    }
+}
+
+struct OwnedRuntime(Option<Runtime>);
+
+impl Drop for OwnedRuntime {
+    fn drop(&mut self) {
+        // dropping a runtime blocks, which panics in an async context
+        if let Some(runtime) = self.0.take() {
+            if Handle::try_current().is_ok() {
+                runtime.shutdown_background();
+            }
+        }
+    }
+}
+
+/// The error of the calls of a blocking client besides the [`Status`] of the calls, which is
+/// converted into a [`Status`] of [`Code::FailedPrecondition`] by the generated methods.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockingError {
+    /// The call is made inside a current-thread runtime, which would be stalled by blocking.
+    AsyncContext,
+}
+
+impl fmt::Display for BlockingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AsyncContext => f.write_str(
+                "the blocking client is called inside a current-thread async runtime, which \
+                 would be stalled by blocking; use the async client, or call it on another \
+                 thread",
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BlockingError {}
+
+impl From<BlockingError> for Status {
+    fn from(err: BlockingError) -> Self {
+        Status::new(Code::FailedPrecondition, err.to_string())
+    }
+}
+
+/// The messages of a server streaming response, received by blocking on the runtime.
+///
+/// The iteration ends after the first error, including the [`BlockingError`] converted.
+pub struct BlockingStream<T> {
+    runtime: BlockingRuntime,
+    // taken after the error
+    response: Option<StreamingResponse<T>>,
+}
+
+impl<T> BlockingStream<T> {
+    /// Get a reference to the initial metadata, which is `None` after an error.
+    pub fn metadata(&self) -> Option<&MetadataMap> {
+        self.response.as_ref().map(|r| r.metadata())
+    }
+
+    /// Receives the trailing metadata after the last message, see
+    /// [`StreamingResponse::trailers`].
+    pub fn trailers(&mut self) -> Result<Option<MetadataMap>, Status> {
+        let Some(response) = self.response.as_mut() else {
+            return Ok(None);
+        };
+        self.runtime.block_on(response.trailers())?
+    }
+
+    /// Consumes `self`, returning the async response unless after an error.
+    pub fn into_inner(self) -> Option<StreamingResponse<T>> {
+        self.response
+    }
+}
+
+impl<T> Iterator for BlockingStream<T> {
+    type Item = Result<T, Status>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let response = self.response.as_mut()?;
+        let item = match self.runtime.block_on(response.get_mut().next()) {
+            Ok(item) => item,
+            Err(err) => Some(Err(err.into())),
+        };
+        if let Some(Err(_)) = &item {
+            self.response = None;
+        }
+        item
+    }
+}
+
+impl<T> fmt::Debug for BlockingStream<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BlockingStream")
+            .field("runtime", &self.runtime)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http_body_util::StreamBody;
+
+    use super::*;
+    use crate::{
+        body::BoxBody,
+        codec::{
+            decode::{DecodeLimits, Kind},
+            encode::encode,
+        },
+        RecvStream,
+    };
+
+    fn response(messages: &[&str]) -> StreamingResponse<String> {
+        let messages = messages
+            .iter()
+            .map(|m| Ok(m.to_string()))
+            .collect::<Vec<Result<_, Status>>>();
+        let body = BoxBody::new(StreamBody::new(encode(
+            Box::pin(futures::stream::iter(messages)),
+            None,
+        )));
+        StreamingResponse::from_parts(
+            MetadataMap::new(),
+            Default::default(),
+            RecvStream::new(
+                body,
+                Kind::Response(http::StatusCode::OK),
+                None,
+                DecodeLimits::default(),
+            ),
+        )
+    }
+
+    /// Makes a unary call and receives a server streaming response by blocking.
+    fn call(runtime: &BlockingRuntime) {
+        let resp = runtime
+            .block_on(async {
+                tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+                "hello"
+            })
+            .unwrap();
+        assert_eq!(resp, "hello");
+
+        let messages = runtime
+            .stream(response(&["hi", "yo"]))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(messages, ["hi", "yo"]);
+    }
+
+    #[test]
+    fn plain_thread() {
+        let runtime = BlockingRuntime::current_thread().unwrap();
+        std::thread::scope(|s| {
+            s.spawn(|| call(&runtime));
+            s.spawn(|| call(&runtime));
+        });
+
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let runtime = BlockingRuntime::from_handle(rt.handle().clone());
+        std::thread::spawn(move || call(&runtime)).join().unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn spawn_blocking() {
+        let runtime = BlockingRuntime::current_thread().unwrap();
+        tokio::task::spawn_blocking(move || call(&runtime))
+            .await
+            .unwrap();
+
+        let runtime = BlockingRuntime::from_handle(Handle::current());
+        tokio::task::spawn_blocking(move || call(&runtime))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn async_context() {
+        let runtime = BlockingRuntime::current_thread().unwrap();
+        let err = runtime.block_on(async {}).unwrap_err();
+        assert_eq!(err, BlockingError::AsyncContext);
+        let status = Status::from(err);
+        assert_eq!(status.code(), Code::FailedPrecondition);
+        assert!(status.message().contains("current-thread"));
+
+        let mut stream = runtime.stream(response(&["hi"]));
+        let err = stream.next().unwrap().unwrap_err();
+        assert_eq!(err.code(), Code::FailedPrecondition);
+        assert!(stream.next().is_none());
+        // the owned runtime is dropped here without panicking
+    }
+}
@@ -15,7 +15,7 @@
 //!         volo_gen::volo::example::item::ItemServiceClientBuilder::new("volo-example-item")
 //!             .layer_inner(LogLayer)
 //!             .address(addr)
-//!             .build()
+//!             .build_unchecked()
 //!     };
 //! }
 //!
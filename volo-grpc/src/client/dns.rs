@@ -0,0 +1,548 @@
+//! A [`Discover`] resolving the callee by DNS, and re-resolving it periodically.
+//!
+//! The name of the callee, i.e. the service name passed to the `ClientBuilder`, is resolved as
+//! `host:port`, e.g. `hello.default.svc.cluster.local:8080`.
+//!
+//! With [`DnsResolver::srv`], the name is resolved by the SRV records instead, e.g.
+//! `_grpc._tcp.hello.default.svc.cluster.local`, and the ports, the weights and the priorities of
+//! the instances come from the records. The priorities are kept in the [`PRIORITY_TAG`] of the
+//! instances, so that [`WeightedRandomBalance`] only falls to the targets of a lower priority when
+//! the ones of the higher priorities are all down.
+//!
+//! [`WeightedRandomBalance`]: volo::loadbalance::random::WeightedRandomBalance
+//!
+//! After the first discovery of a name, the name is re-resolved when its records expire, and the
+//! load balancer is notified only if the set of addresses changes. If the resolution fails, e.g.
+//! with `NXDOMAIN`, the last resolved addresses are kept for a grace period before being removed.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use volo_grpc::client::dns::DnsResolver;
+//!
+//! let client = volo_gen::proto_gen::hello::GreeterClientBuilder::new("hello.svc:8080")
+//!     .discover(DnsResolver::default())
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant},
+};
+
+use async_broadcast::{InactiveReceiver, Receiver, Sender};
+use hickory_resolver::{
+    config::{ResolverConfig, ResolverOpts},
+    error::ResolveError,
+    AsyncResolver, TokioAsyncResolver,
+};
+use volo::{
+    context::Endpoint,
+    discovery::{diff_address, Change, Discover, Instance, PRIORITY_TAG},
+    loadbalance::error::LoadBalanceError,
+    net::Address,
+    FastStr,
+};
+
+const DEFAULT_PORT: u16 = 80;
+const DEFAULT_MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+const DEFAULT_MAX_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(30);
+const CHANNEL_CAPACITY: usize = 16;
+const DEFAULT_WEIGHT: u32 = 10;
+/// The prefix of the SRV name added when the name is not an SRV name.
+const SRV_PREFIX: &str = "_grpc._tcp.";
+
+/// A service discover resolving the name of the callee by DNS.
+///
+/// The clones of a [`DnsResolver`] share the re-resolving tasks, which stop after all the clones
+/// are dropped.
+#[derive(Clone)]
+pub struct DnsResolver {
+    resolver: TokioAsyncResolver,
+    config: RefreshConfig,
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RefreshConfig {
+    default_port: u16,
+    min_interval: Duration,
+    max_interval: Duration,
+    grace_period: Duration,
+    srv: bool,
+}
+
+impl Default for RefreshConfig {
+    fn default() -> Self {
+        Self {
+            default_port: DEFAULT_PORT,
+            min_interval: DEFAULT_MIN_REFRESH_INTERVAL,
+            max_interval: DEFAULT_MAX_REFRESH_INTERVAL,
+            grace_period: DEFAULT_GRACE_PERIOD,
+            srv: false,
+        }
+    }
+}
+
+struct Shared {
+    sender: Sender<Change<FastStr>>,
+    // keeps the channel open when there is no receiver
+    _receiver: InactiveReceiver<Change<FastStr>>,
+    /// The names being re-resolved.
+    watching: Mutex<HashSet<FastStr>>,
+}
+
+impl DnsResolver {
+    /// Builds a new [`DnsResolver`] through [`ResolverConfig`] and [`ResolverOpts`].
+    ///
+    /// For using the system config, create it by [`DnsResolver::default()`].
+    pub fn new(config: ResolverConfig, options: ResolverOpts) -> Self {
+        Self::with_resolver(AsyncResolver::tokio(config, options))
+    }
+
+    fn with_resolver(resolver: TokioAsyncResolver) -> Self {
+        let (mut sender, receiver) = async_broadcast::broadcast(CHANNEL_CAPACITY);
+        sender.set_overflow(true);
+        sender.set_await_active(false);
+        Self {
+            resolver,
+            config: RefreshConfig::default(),
+            shared: Arc::new(Shared {
+                sender,
+                _receiver: receiver.deactivate(),
+                watching: Mutex::new(HashSet::new()),
+            }),
+        }
+    }
+
+    /// Sets the port used when the name has no port.
+    ///
+    /// Default is `80`.
+    pub fn default_port(mut self, port: u16) -> Self {
+        self.config.default_port = port;
+        self
+    }
+
+    /// Sets the bounds of the interval between re-resolving a name, which is the TTL of the
+    /// records clamped into `[min, max]`.
+    ///
+    /// A failed resolution is retried after `min`.
+    ///
+    /// Default is from `1s` to `30s`.
+    pub fn refresh_interval(mut self, min: Duration, max: Duration) -> Self {
+        self.config.min_interval = min;
+        self.config.max_interval = max.max(min);
+        self
+    }
+
+    /// Sets how long the last resolved addresses are kept when the resolution keeps failing.
+    ///
+    /// Default is `30s`.
+    pub fn grace_period(mut self, grace_period: Duration) -> Self {
+        self.config.grace_period = grace_period;
+        self
+    }
+
+    /// Resolves the names by the SRV records.
+    ///
+    /// A name not starting with `_` is prefixed with `_grpc._tcp.`, and the port in the name is
+    /// ignored. The weight of an SRV record is used as the weight of each address of its target,
+    /// where `0` is taken as `1` so that the target can still be picked.
+    ///
+    /// Default is disabled.
+    pub fn srv(mut self, enabled: bool) -> Self {
+        self.config.srv = enabled;
+        self
+    }
+
+    /// Resolves the name, and returns the instances and when they expire.
+    async fn resolve(
+        resolver: &TokioAsyncResolver,
+        target: &Target,
+    ) -> Result<(Vec<Arc<Instance>>, Instant), ResolveError> {
+        match target {
+            Target::Host { host, port } => {
+                let lookup = resolver.lookup_ip(host.as_str()).await?;
+                let addrs = lookup.iter().map(|ip| SocketAddr::new(ip, *port)).collect();
+                Ok((instances(addrs), lookup.valid_until()))
+            }
+            Target::Srv(name) => Self::resolve_srv(resolver, name).await,
+        }
+    }
+
+    async fn resolve_srv(
+        resolver: &TokioAsyncResolver,
+        name: &str,
+    ) -> Result<(Vec<Arc<Instance>>, Instant), ResolveError> {
+        let lookup = resolver.srv_lookup(name).await?;
+        let mut expire = lookup.as_lookup().valid_until();
+        let mut instances = Vec::new();
+        let mut last_err = None;
+        for record in lookup.iter() {
+            let target = record.target().to_utf8();
+            let ips = match resolver.lookup_ip(target.as_str()).await {
+                Ok(ips) => ips,
+                Err(err) => {
+                    tracing::warn!(
+                        "[VOLO] DnsResolver: failed to resolve the target {} of {}: {}",
+                        target,
+                        name,
+                        err
+                    );
+                    last_err = Some(err);
+                    continue;
+                }
+            };
+            expire = expire.min(ips.valid_until());
+            let tags: HashMap<_, _> =
+                [(PRIORITY_TAG.into(), record.priority().to_string().into())].into();
+            for ip in ips.iter() {
+                instances.push(Arc::new(Instance {
+                    address: Address::Ip(SocketAddr::new(ip, record.port())),
+                    weight: u32::from(record.weight()).max(1),
+                    tags: tags.clone(),
+                }));
+            }
+        }
+        match last_err {
+            Some(err) if instances.is_empty() => Err(err),
+            _ => Ok((instances, expire)),
+        }
+    }
+
+    /// Starts re-resolving the name if it's not being re-resolved.
+    fn watch_name(&self, key: FastStr, target: Target, records: Records, expire: Instant) {
+        if !self.shared.watching.lock().unwrap().insert(key.clone()) {
+            return;
+        }
+        let refresher = Refresher {
+            resolver: self.resolver.clone(),
+            config: self.config,
+            shared: Arc::downgrade(&self.shared),
+            key,
+            target,
+            records,
+            expire,
+        };
+        tokio::spawn(refresher.run());
+    }
+}
+
+impl Default for DnsResolver {
+    fn default() -> Self {
+        Self::with_resolver(
+            AsyncResolver::tokio_from_system_conf().expect("failed to init dns resolver"),
+        )
+    }
+}
+
+/// The task re-resolving a name.
+struct Refresher {
+    resolver: TokioAsyncResolver,
+    config: RefreshConfig,
+    shared: Weak<Shared>,
+    key: FastStr,
+    target: Target,
+    records: Records,
+    /// When the records expire.
+    expire: Instant,
+}
+
+impl Refresher {
+    async fn run(mut self) {
+        loop {
+            let ttl = self.expire.saturating_duration_since(Instant::now());
+            tokio::time::sleep(ttl.clamp(self.config.min_interval, self.config.max_interval)).await;
+
+            let result = DnsResolver::resolve(&self.resolver, &self.target).await;
+            let Some(shared) = self.shared.upgrade() else {
+                return;
+            };
+            let next = match result {
+                Ok((instances, expire)) => {
+                    self.expire = expire;
+                    Some(instances)
+                }
+                Err(err) => {
+                    tracing::warn!(
+                        "[VOLO] DnsResolver: failed to resolve {}: {}",
+                        self.key,
+                        err
+                    );
+                    None
+                }
+            };
+            let change =
+                self.records
+                    .update(&self.key, next, Instant::now(), self.config.grace_period);
+            if let Some(change) = change {
+                tracing::info!(
+                    "[VOLO] DnsResolver: addresses of {} changed, added: {:?}, removed: {:?}",
+                    self.key,
+                    change.added,
+                    change.removed
+                );
+                let _ = shared.sender.try_broadcast(change);
+            }
+        }
+    }
+}
+
+/// The last good addresses of a name.
+#[derive(Debug)]
+struct Records {
+    instances: Vec<Arc<Instance>>,
+    failing_since: Option<Instant>,
+}
+
+impl Records {
+    fn new(instances: Vec<Arc<Instance>>) -> Self {
+        Self {
+            instances,
+            failing_since: None,
+        }
+    }
+
+    /// Updates the records by the result of a resolution at `now`, where `None` means the
+    /// resolution failed, and returns the change if the instances changed.
+    fn update(
+        &mut self,
+        key: &FastStr,
+        next: Option<Vec<Arc<Instance>>>,
+        now: Instant,
+        grace_period: Duration,
+    ) -> Option<Change<FastStr>> {
+        let next = match next {
+            Some(next) => {
+                self.failing_since = None;
+                next
+            }
+            None => {
+                let since = *self.failing_since.get_or_insert(now);
+                if now.duration_since(since) < grace_period {
+                    return None;
+                }
+                Vec::new()
+            }
+        };
+        let (mut change, changed) = diff_address(key.clone(), self.instances.clone(), next);
+        // the weights and the priorities of the SRV records may change with the same addresses
+        change.updated = change
+            .all
+            .iter()
+            .filter(|next| {
+                self.instances
+                    .iter()
+                    .any(|prev| prev.address == next.address && prev != *next)
+            })
+            .cloned()
+            .collect();
+        if !changed && change.updated.is_empty() {
+            return None;
+        }
+        self.instances.clone_from(&change.all);
+        Some(change)
+    }
+}
+
+fn instances(addrs: Vec<SocketAddr>) -> Vec<Arc<Instance>> {
+    addrs
+        .into_iter()
+        .map(|addr| {
+            Arc::new(Instance {
+                address: Address::Ip(addr),
+                weight: DEFAULT_WEIGHT,
+                tags: Default::default(),
+            })
+        })
+        .collect()
+}
+
+/// What a name is resolved to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Target {
+    Host { host: String, port: u16 },
+    Srv(String),
+}
+
+impl Target {
+    fn new(name: &str, config: &RefreshConfig) -> Self {
+        if config.srv {
+            let (host, _) = parse_name(name, config.default_port);
+            if host.starts_with('_') {
+                Self::Srv(host.to_owned())
+            } else {
+                Self::Srv(format!("{SRV_PREFIX}{host}"))
+            }
+        } else {
+            let (host, port) = parse_name(name, config.default_port);
+            Self::Host {
+                host: host.to_owned(),
+                port,
+            }
+        }
+    }
+}
+
+/// Splits the name into the host and the port, where an IPv6 address with the port must be
+/// enclosed in brackets.
+fn parse_name(name: &str, default_port: u16) -> (&str, u16) {
+    if let Some(rest) = name.strip_prefix('[') {
+        return match rest.split_once("]:") {
+            Some((host, port)) => (host, port.parse().unwrap_or(default_port)),
+            None => (rest.trim_end_matches(']'), default_port),
+        };
+    }
+    match name.split_once(':') {
+        Some((host, port)) if !port.contains(':') => (host, port.parse().unwrap_or(default_port)),
+        _ => (name, default_port),
+    }
+}
+
+impl Discover for DnsResolver {
+    type Key = FastStr;
+    type Error = LoadBalanceError;
+
+    async fn discover<'s>(
+        &'s self,
+        endpoint: &'s Endpoint,
+    ) -> Result<Vec<Arc<Instance>>, Self::Error> {
+        if let Some(address) = endpoint.address() {
+            return Ok(vec![Arc::new(Instance {
+                address,
+                weight: DEFAULT_WEIGHT,
+                tags: Default::default(),
+            })]);
+        }
+        let key = endpoint.service_name();
+        let target = Target::new(&key, &self.config);
+        let (instances, expire) = Self::resolve(&self.resolver, &target)
+            .await
+            .map_err(|err| LoadBalanceError::Discover(Box::new(err)))?;
+
+        let records = Records::new(instances.clone());
+        self.watch_name(key, target, records, expire);
+        Ok(instances)
+    }
+
+    fn key(&self, endpoint: &Endpoint) -> Self::Key {
+        endpoint.service_name()
+    }
+
+    fn watch(&self, _keys: Option<&[Self::Key]>) -> Option<Receiver<Change<Self::Key>>> {
+        Some(self.shared.sender.new_receiver())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addrs(addrs: &[&str]) -> Vec<Arc<Instance>> {
+        instances(addrs.iter().map(|a| a.parse().unwrap()).collect())
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(parse_name("hello.svc:8080", 80), ("hello.svc", 8080));
+        assert_eq!(parse_name("hello.svc", 80), ("hello.svc", 80));
+        assert_eq!(parse_name("[::1]:8080", 80), ("::1", 8080));
+        assert_eq!(parse_name("::1", 80), ("::1", 80));
+    }
+
+    #[test]
+    fn target() {
+        let config = RefreshConfig::default();
+        assert_eq!(
+            Target::new("hello.svc:8080", &config),
+            Target::Host {
+                host: "hello.svc".to_owned(),
+                port: 8080
+            }
+        );
+
+        let config = RefreshConfig {
+            srv: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            Target::new("hello.svc", &config),
+            Target::Srv("_grpc._tcp.hello.svc".to_owned())
+        );
+        assert_eq!(
+            Target::new("_http._tcp.hello.svc:8080", &config),
+            Target::Srv("_http._tcp.hello.svc".to_owned())
+        );
+    }
+
+    #[test]
+    fn update_weight() {
+        let key = FastStr::from_static_str("hello");
+        let grace = Duration::from_secs(10);
+        let now = Instant::now();
+        let mut records = Records::new(addrs(&["10.0.0.1:80", "10.0.0.2:80"]));
+
+        let mut next = addrs(&["10.0.0.1:80", "10.0.0.2:80"]);
+        next[1] = Arc::new(Instance {
+            weight: 20,
+            ..(*next[1]).clone()
+        });
+        let change = records
+            .update(&key, Some(next.clone()), now, grace)
+            .unwrap();
+        assert!(change.added.is_empty() && change.removed.is_empty());
+        assert_eq!(change.updated, vec![next[1].clone()]);
+        assert!(records.update(&key, Some(next), now, grace).is_none());
+    }
+
+    #[test]
+    fn update() {
+        let key = FastStr::from_static_str("hello");
+        let grace = Duration::from_secs(10);
+        let start = Instant::now();
+        let mut records = Records::new(addrs(&["10.0.0.1:80", "10.0.0.2:80"]));
+
+        // unchanged in a different order
+        let same = addrs(&["10.0.0.2:80", "10.0.0.1:80"]);
+        assert!(records.update(&key, Some(same), start, grace).is_none());
+
+        let change = records
+            .update(
+                &key,
+                Some(addrs(&["10.0.0.1:80", "10.0.0.3:80"])),
+                start,
+                grace,
+            )
+            .unwrap();
+        assert_eq!(change.all.len(), 2);
+        assert_eq!(change.added.len(), 1);
+        assert_eq!(change.removed.len(), 1);
+
+        // the failures are transient in the grace period
+        assert!(records.update(&key, None, start, grace).is_none());
+        let later = start + Duration::from_secs(5);
+        assert!(records.update(&key, None, later, grace).is_none());
+
+        // recovering resets the grace period
+        assert!(records
+            .update(
+                &key,
+                Some(addrs(&["10.0.0.1:80", "10.0.0.3:80"])),
+                later,
+                grace
+            )
+            .is_none());
+        let later = start + Duration::from_secs(12);
+        assert!(records.update(&key, None, later, grace).is_none());
+
+        let expired = later + grace;
+        let change = records.update(&key, None, expired, grace).unwrap();
+        assert!(change.all.is_empty());
+        assert_eq!(change.removed.len(), 2);
+        assert!(records.update(&key, None, expired, grace).is_none());
+    }
+}
@@ -1,12 +1,18 @@
 use std::{net::SocketAddr, str::FromStr};
 
 use metainfo::{Backward, Forward};
-use volo::{context::Context, Service};
+use volo::{
+    context::{
+        log_id::{self, LogIdConfig},
+        Context,
+    },
+    Service,
+};
 
 use crate::{
     context::ClientContext,
     metadata::{
-        KeyAndValueRef, MetadataKey, DESTINATION_METHOD, DESTINATION_SERVICE,
+        AsciiMetadataKey, KeyAndValueRef, MetadataKey, DESTINATION_METHOD, DESTINATION_SERVICE,
         HEADER_TRANS_REMOTE_ADDR, SOURCE_SERVICE,
     },
     Request, Response, Status,
@@ -41,6 +47,12 @@ where
         mut volo_req: Request<T>,
     ) -> Result<Self::Response, Self::Error> {
         let metadata = volo_req.metadata_mut();
+        if let Some(log_id) = log_id::outgoing(cx) {
+            let key = AsciiMetadataKey::from_bytes(LogIdConfig::global().header().as_bytes());
+            if let (Ok(key), Ok(value)) = (key, log_id.parse()) {
+                metadata.insert(key, value);
+            }
+        }
         _ = metainfo::METAINFO.with(|metainfo| {
             let metainfo = metainfo.borrow_mut();
 
@@ -5,8 +5,11 @@
 //!
 //! For users need to specify some options at call time, they may use ['callopt'][callopt].
 
+pub mod blocking;
 mod callopt;
+pub mod dns;
 mod meta;
+pub mod sender;
 
 use std::{cell::RefCell, marker::PhantomData, sync::Arc, time::Duration};
 
@@ -17,20 +20,23 @@ use motore::{
     service::{BoxCloneService, Service},
     ServiceExt,
 };
+pub use sender::RequestSender;
 use volo::{
     client::{MkClient, WithOptService},
-    context::{Endpoint, Role, RpcInfo},
+    config::ConfigError,
+    context::{Context, Endpoint, Role, RpcInfo},
     discovery::{Discover, DummyDiscover},
     loadbalance::{random::WeightedRandomBalance, MkLbLayer},
-    net::Address,
+    net::{dial::ConnectRetry, Address},
     FastStr,
 };
 
 use crate::{
-    codec::compression::CompressionEncoding,
+    codec::{compression::CompressionEncoding, MessageCodec},
     context::{ClientContext, Config},
+    inspect::{Inspection, MakeInspector},
     layer::loadbalance::LbConfig,
-    transport::ClientTransport,
+    transport::{self, ClientTransport},
     Request, Response, Status,
 };
 
@@ -246,6 +252,16 @@ impl<IL, OL, C, LB, T, U> ClientBuilder<IL, OL, C, LB, T, U> {
         self
     }
 
+    /// Sets the retry of the failed connects, e.g. when the connection is refused during the
+    /// deployment of the server.
+    ///
+    /// It only retries before any bytes are written, which is independent of the retry of the
+    /// calls. Default is no retry.
+    pub fn connect_retry(mut self, retry: ConnectRetry) -> Self {
+        self.rpc_config.connect_retry = Some(retry);
+        self
+    }
+
     /// Sets the timeout for the response.
     ///
     /// Default is no timeout.
@@ -288,6 +304,28 @@ impl<IL, OL, C, LB, T, U> ClientBuilder<IL, OL, C, LB, T, U> {
         self
     }
 
+    /// Sets the codec of the messages, e.g. [`MessageCodec::Json`] for debugging, which needs
+    /// the messages generated with serde, or a [`MessageCodec::Custom`] one, which needs the
+    /// messages generated with the codec. The responses are decoded by the codec told by their
+    /// content type.
+    ///
+    /// Default is [`MessageCodec::Proto`].
+    pub fn codec(mut self, codec: MessageCodec) -> Self {
+        self.rpc_config.codec = Some(codec);
+        self
+    }
+
+    /// Inspects each message sent and received by the calls, e.g. by the [`MessageMetrics`], whose
+    /// error aborts the stream with the status.
+    ///
+    /// Default is no inspection.
+    ///
+    /// [`MessageMetrics`]: crate::inspect::MessageMetrics
+    pub fn message_inspector(mut self, make: impl MakeInspector) -> Self {
+        self.rpc_config.inspection = Some(Inspection::new(make));
+        self
+    }
+
     pub fn mk_load_balance<NLB>(self, mk_load_balance: NLB) -> ClientBuilder<IL, OL, C, NLB, T, U> {
         ClientBuilder {
             http2_config: self.http2_config,
@@ -453,12 +491,51 @@ impl<IL, OL, C, LB, T, U> ClientBuilder<IL, OL, C, LB, T, U> {
     }
 
     /// Sets the [`ClientTlsConfig`] for the client.
+    ///
+    /// The TLS sessions are resumed by the reconnections, see
+    /// [`TlsConnectorBuilder::session_cache_size`], and the client certificate can be rotated
+    /// without restarting by a `volo::net::tls::ReloadableCertResolver` with rustls, e.g.
+    ///
+    /// ```rust,ignore
+    /// use volo::net::tls::{ClientTlsConfig, ReloadableCertResolver, TlsConnector};
+    ///
+    /// let resolver = ReloadableCertResolver::from_pem_file("client.pem", "client.key")?;
+    /// resolver.watch_files("client.pem", "client.key", Duration::from_secs(60));
+    /// let connector = TlsConnector::builder()
+    ///     .client_cert_resolver(resolver)
+    ///     .build()?;
+    /// let client = GreeterClientBuilder::new("hello")
+    ///     .tls_config(ClientTlsConfig::new("example.com", connector))
+    ///     .build();
+    /// ```
+    ///
+    /// [`TlsConnectorBuilder::session_cache_size`]: volo::net::tls::TlsConnectorBuilder::session_cache_size
     #[cfg(feature = "__tls")]
     #[cfg_attr(docsrs, doc(cfg(any(feature = "rustls", feature = "native-tls"))))]
     pub fn tls_config(mut self, tls_config: volo::net::tls::ClientTlsConfig) -> Self {
         self.tls_config = Some(tls_config);
         self
     }
+
+    /// Checks the configuration, which is called when the client is built.
+    fn check(&self, err: &mut ConfigError) {
+        self.http2_config.check(err);
+        self.rpc_config.check(err);
+        err.ensure_address("address", self.target.as_ref());
+        #[cfg(feature = "__tls")]
+        if let Some(tls_config) = &self.tls_config {
+            err.ensure(
+                !tls_config.server_name.is_empty(),
+                "tls_config",
+                "the server name is empty",
+            );
+            err.ensure(
+                tls_config.connector.has_root_certs(),
+                "tls_config",
+                "no root certificate is trusted, all the handshakes will fail",
+            );
+        }
+    }
 }
 
 impl<IL, OL, C, LB, T, U> ClientBuilder<IL, OL, C, LB, T, U>
@@ -491,8 +568,12 @@ where
     <OL::Service as Service<ClientContext, Request<T>>>::Error: Send + Into<Status>,
     T: 'static + Send,
 {
-    /// Builds a new [`Client`].
-    pub fn build(self) -> C::Target {
+    /// Builds a new [`Client`], or returns all the problems of the configuration.
+    pub fn build(self) -> Result<C::Target, ConfigError> {
+        let mut err = ConfigError::new();
+        self.check(&mut err);
+        err.into_result()?;
+
         #[cfg(not(feature = "__tls"))]
         let transport =
             MetaService::new(ClientTransport::new(&self.http2_config, &self.rpc_config));
@@ -513,7 +594,7 @@ where
         let transport = transport.map_err(|err| err.into());
         let transport = BoxCloneService::new(transport);
 
-        self.mk_client.mk_client(Client {
+        Ok(self.mk_client.mk_client(Client {
             inner: Arc::new(ClientInner {
                 callee_name: self.callee_name,
                 caller_name: self.caller_name,
@@ -521,7 +602,16 @@ where
                 target: self.target,
             }),
             transport,
-        })
+        }))
+    }
+
+    /// Builds a new [`Client`], which is useful for the static configurations.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the configuration is invalid, see [`ClientBuilder::build`].
+    pub fn build_unchecked(self) -> C::Target {
+        self.build().unwrap_or_else(|err| panic!("{err}"))
     }
 }
 
@@ -546,7 +636,11 @@ pub struct Client<S> {
 
 impl<S> Client<S> {
     pub fn make_cx(&self, path: &'static str) -> ClientContext {
-        ClientContext::new(self.make_rpc_info(path))
+        let mut cx = ClientContext::new(self.make_rpc_info(path));
+        if let Some(inspection) = &self.inner.rpc_config.inspection {
+            cx.extensions_mut().insert(inspection.clone());
+        }
+        cx
     }
 
     fn make_rpc_info(&self, method: &'static str) -> RpcInfo<Config> {
@@ -672,3 +766,128 @@ impl Default for Http2Config {
         }
     }
 }
+
+impl Http2Config {
+    pub(crate) fn check(&self, err: &mut ConfigError) {
+        transport::check_window_size(
+            err,
+            "http2_init_stream_window_size",
+            self.init_stream_window_size,
+        );
+        transport::check_window_size(
+            err,
+            "http2_init_connection_window_size",
+            self.init_connection_window_size,
+        );
+        transport::check_frame_size(err, self.max_frame_size);
+        transport::check_send_buf_size(err, self.max_send_buf_size);
+        err.ensure_non_zero("http2_keepalive_interval", self.http2_keepalive_interval);
+        err.ensure_non_zero(
+            "http2_keepalive_timeout",
+            Some(self.http2_keepalive_timeout),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::*;
+    use crate::codec::compression::{GzipConfig, Level};
+
+    fn problems<IL, OL, C, LB, T, U>(
+        builder: &ClientBuilder<IL, OL, C, LB, T, U>,
+    ) -> Vec<&'static str> {
+        let mut err = ConfigError::new();
+        builder.check(&mut err);
+        err.problems().iter().map(|p| p.field).collect()
+    }
+
+    #[test]
+    fn invalid_address() {
+        let builder = ClientBuilder::<_, _, _, _, (), ()>::new((), "echo")
+            .address(SocketAddr::from(([127, 0, 0, 1], 8080)));
+        assert!(problems(&builder).is_empty());
+
+        let builder = builder.address(SocketAddr::from(([127, 0, 0, 1], 0)));
+        assert_eq!(problems(&builder), ["address"]);
+    }
+
+    #[test]
+    fn disabled_compression() {
+        let builder = ClientBuilder::<_, _, _, _, (), ()>::new((), "echo")
+            .send_compressions(vec![CompressionEncoding::Gzip(None)])
+            .accept_compressions(vec![CompressionEncoding::Gzip(None)]);
+        assert_eq!(problems(&builder), ["send_compressions"]);
+
+        let builder = builder.send_compressions(vec![CompressionEncoding::Identity]);
+        assert_eq!(problems(&builder), ["send_compressions"]);
+    }
+
+    #[cfg(feature = "rustls")]
+    #[test]
+    fn tls_without_certs() {
+        use volo::net::tls::{ClientTlsConfig, TlsConnector};
+
+        let connector = TlsConnector::builder().build().unwrap();
+        let builder = ClientBuilder::<_, _, _, _, (), ()>::new((), "echo")
+            .tls_config(ClientTlsConfig::new("example.com", connector));
+        assert!(problems(&builder).is_empty());
+
+        let connector = TlsConnector::builder()
+            .enable_default_root_certs(false)
+            .build()
+            .unwrap();
+        let builder = builder.tls_config(ClientTlsConfig::new("example.com", connector));
+        assert_eq!(problems(&builder), ["tls_config"]);
+    }
+
+    #[test]
+    fn check_config() {
+        type Case = (fn(&mut Http2Config, &mut Config), &'static [&'static str]);
+        let cases: &[Case] = &[
+            (|_, _| {}, &[]),
+            (
+                |h2, _| {
+                    h2.init_stream_window_size = u32::MAX;
+                    h2.max_frame_size = 1024;
+                    h2.http2_keepalive_interval = Some(Duration::ZERO);
+                },
+                &[
+                    "http2_init_stream_window_size",
+                    "http2_max_frame_size",
+                    "http2_keepalive_interval",
+                ],
+            ),
+            (
+                |h2, config| {
+                    h2.max_send_buf_size = usize::MAX;
+                    config.connect_timeout = Some(Duration::ZERO);
+                    config.send_compressions = Some(Vec::new());
+                    config.accept_compressions =
+                        Some(vec![CompressionEncoding::Gzip(Some(GzipConfig {
+                            level: Level::new(10),
+                        }))]);
+                },
+                &[
+                    "http2_max_send_buf_size",
+                    "connect_timeout",
+                    "send_compressions",
+                    "accept_compressions",
+                ],
+            ),
+        ];
+        for (set, fields) in cases {
+            let mut h2 = Http2Config::default();
+            let mut config = Config::default();
+            set(&mut h2, &mut config);
+
+            let mut err = ConfigError::new();
+            h2.check(&mut err);
+            config.check(&mut err);
+            let problems = err.problems().iter().map(|p| p.field).collect::<Vec<_>>();
+            assert_eq!(problems, *fields, "{err}");
+        }
+    }
+}
@@ -0,0 +1,329 @@
+//! Sending the messages of a streaming request with the explicit flush and half-close.
+//!
+//! The generated methods with streaming requests accept any stream of the messages, which ends
+//! the request, i.e. half-closes the HTTP/2 stream, only when the stream ends. A [`RequestStream`]
+//! is such a stream, driven by its [`RequestSender`]:
+//!
+//! ```rust,ignore
+//! use volo_grpc::client::sender::RequestSender;
+//!
+//! let (tx, requests) = RequestSender::channel(16);
+//! let call = tokio::spawn(async move { client.upload(requests).await });
+//!
+//! tx.send(chunk).await?;
+//! // wait for the chunk to be handed to the transport instead of staying in the buffer
+//! tx.flush().await?;
+//! ...
+//! // half-close the request, so the server can produce the response
+//! tx.finish();
+//! let resp = call.await??;
+//! ```
+//!
+//! Half-closing only ends the requests, and the responses are received until the server ends
+//! them, however long it takes. Dropping the [`RequestSender`] without [`RequestSender::finish`]
+//! cancels the call instead, which resets the HTTP/2 stream, as does [`RequestSender::cancel`].
+//! To cancel the call after the half-close, drop the call or its response stream.
+//!
+//! The messages are buffered in a bounded channel, which is consumed by the request body only
+//! when the HTTP/2 stream has send capacity, so [`RequestSender::send`] pends once the buffer is
+//! full.
+
+use std::{
+    any::Any,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, AtomicU8, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use futures::{Stream, StreamExt};
+use tokio::sync::{mpsc, Notify};
+
+use crate::{BoxStream, Status};
+
+const OPEN: u8 = 0;
+const FINISHED: u8 = 1;
+const CANCELLED: u8 = 2;
+
+/// The progress of the messages shared by the sender and the stream.
+#[derive(Debug, Default)]
+struct Progress {
+    sent: AtomicU64,
+    delivered: AtomicU64,
+    notify: Notify,
+    /// Whether the sender is finished or cancelled.
+    end: AtomicU8,
+}
+
+/// The bounded handle sending the messages of a streaming request.
+///
+/// Dropping it without [`RequestSender::finish`] cancels the call.
+#[derive(Debug)]
+pub struct RequestSender<T> {
+    tx: mpsc::Sender<T>,
+    progress: Arc<Progress>,
+}
+
+impl<T: Send + 'static> RequestSender<T> {
+    /// Creates a [`RequestSender`] buffering at most `buffer` messages, and the [`RequestStream`]
+    /// to be passed to the generated method.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buffer` is `0`.
+    pub fn channel(buffer: usize) -> (Self, RequestStream<T>) {
+        let (tx, rx) = mpsc::channel(buffer);
+        let progress = Arc::new(Progress::default());
+        (
+            Self {
+                tx,
+                progress: progress.clone(),
+            },
+            RequestStream {
+                rx,
+                progress,
+                received: 0,
+            },
+        )
+    }
+
+    /// Sends a message, waiting for the buffer to have room.
+    ///
+    /// Fails with `CANCELLED` if the request stream has been dropped, e.g. the call has ended.
+    pub async fn send(&self, message: T) -> Result<(), Status> {
+        self.tx
+            .send(message)
+            .await
+            .map_err(|_| Status::cancelled("the request stream is closed"))?;
+        self.progress.sent.fetch_add(1, Ordering::Release);
+        Ok(())
+    }
+
+    /// Waits until all the messages sent have been encoded and handed to the transport.
+    ///
+    /// Fails with `CANCELLED` if the request stream is dropped before that.
+    pub async fn flush(&self) -> Result<(), Status> {
+        loop {
+            let notified = self.progress.notify.notified();
+            if self.progress.delivered.load(Ordering::Acquire)
+                >= self.progress.sent.load(Ordering::Acquire)
+            {
+                return Ok(());
+            }
+            if self.tx.is_closed() {
+                return Err(Status::cancelled("the request stream is closed"));
+            }
+            notified.await;
+        }
+    }
+
+    /// Half-closes the request after the messages sent, so the server knows there are no more
+    /// messages, and the responses are still received until the server ends them.
+    pub fn finish(self) {
+        self.end(FINISHED);
+    }
+
+    /// Half-closes the request, see [`RequestSender::finish`].
+    #[deprecated(note = "use `finish` to half-close the request, as dropping the sender cancels")]
+    pub fn close(self) {
+        self.finish()
+    }
+
+    /// Cancels the call, which fails the request stream with `CANCELLED` without sending the
+    /// messages buffered, so the HTTP/2 stream is reset.
+    pub fn cancel(self) {
+        self.end(CANCELLED);
+    }
+
+    /// Returns the number of messages that can be sent without waiting.
+    pub fn capacity(&self) -> usize {
+        self.tx.capacity()
+    }
+
+    /// Returns if the request stream has been dropped.
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+}
+
+impl<T> RequestSender<T> {
+    fn end(&self, end: u8) {
+        let _ = self
+            .progress
+            .end
+            .compare_exchange(OPEN, end, Ordering::AcqRel, Ordering::Acquire);
+    }
+}
+
+impl<T> Drop for RequestSender<T> {
+    fn drop(&mut self) {
+        self.end(CANCELLED);
+    }
+}
+
+/// The stream of the messages sent by a [`RequestSender`], which ends when the sender is finished.
+///
+/// Passed to the generated methods, it fails the request with `CANCELLED` when the sender is
+/// cancelled or dropped without finishing. Wrapped in other streams, it can only end, so the
+/// cancellation is then a half-close.
+#[derive(Debug)]
+pub struct RequestStream<T> {
+    rx: mpsc::Receiver<T>,
+    progress: Arc<Progress>,
+    received: u64,
+}
+
+impl<T> RequestStream<T> {
+    fn is_cancelled(&self) -> bool {
+        self.progress.end.load(Ordering::Acquire) == CANCELLED
+    }
+
+    /// Marks the messages received as delivered, since the next one is polled only after the
+    /// previous ones have been taken by the transport.
+    fn deliver(&self) {
+        if self.progress.delivered.load(Ordering::Relaxed) < self.received {
+            self.progress
+                .delivered
+                .store(self.received, Ordering::Release);
+            self.progress.notify.notify_waiters();
+        }
+    }
+}
+
+impl<T> Stream for RequestStream<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.deliver();
+        let poll = self.rx.poll_recv(cx);
+        if let Poll::Ready(Some(_)) = poll {
+            self.received += 1;
+        }
+        poll
+    }
+}
+
+/// The messages of a [`RequestStream`] failing with `CANCELLED` by the cancellation.
+struct Results<T> {
+    stream: RequestStream<T>,
+    cancelled: bool,
+}
+
+impl<T> Stream for Results<T> {
+    type Item = Result<T, Status>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.cancelled {
+            return Poll::Ready(None);
+        }
+        // the messages buffered are not sent after the cancellation
+        let poll = if self.stream.is_cancelled() {
+            Poll::Ready(None)
+        } else {
+            Pin::new(&mut self.stream).poll_next(cx)
+        };
+        match poll {
+            Poll::Ready(Some(message)) => Poll::Ready(Some(Ok(message))),
+            // the sender is dropped, which is a cancellation unless it is finished
+            Poll::Ready(None) if self.stream.is_cancelled() => {
+                self.cancelled = true;
+                Poll::Ready(Some(Err(Status::cancelled(
+                    "the request is cancelled by the sender",
+                ))))
+            }
+            poll => poll.map(|_| None),
+        }
+    }
+}
+
+/// Converts the streaming requests of the generated methods to the messages to be encoded, where
+/// a [`RequestStream`] fails when its sender is cancelled.
+pub fn request_stream<S, T>(stream: S) -> BoxStream<'static, Result<T, Status>>
+where
+    S: Stream<Item = T> + Send + Sync + 'static,
+    T: Send + 'static,
+{
+    let mut stream = Some(stream);
+    if let Some(stream) = (&mut stream as &mut dyn Any).downcast_mut::<Option<RequestStream<T>>>() {
+        return Box::pin(Results {
+            stream: stream.take().unwrap(),
+            cancelled: false,
+        });
+    }
+    Box::pin(stream.unwrap().map(Ok))
+}
+
+impl<T> Drop for RequestStream<T> {
+    fn drop(&mut self) {
+        self.rx.close();
+        self.progress.notify.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::Code;
+
+    #[tokio::test]
+    async fn flush_and_close() {
+        let (tx, mut stream) = RequestSender::channel(4);
+        tx.flush().await.unwrap();
+
+        tx.send(1).await.unwrap();
+        tx.send(2).await.unwrap();
+        let flush = tokio::spawn(async move { tx.flush().await.map(|_| tx) });
+
+        assert_eq!(stream.next().await, Some(1));
+        assert_eq!(stream.next().await, Some(2));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!flush.is_finished());
+
+        // the transport polls for the next message after taking the second one
+        let next = tokio::spawn(async move { (stream.next().await, stream) });
+        let tx = flush.await.unwrap().unwrap();
+
+        tx.finish();
+        assert_eq!(next.await.unwrap().0, None);
+
+        // the call has ended before the messages are taken
+        let (tx, stream) = RequestSender::channel(4);
+        tx.send(3).await.unwrap();
+        drop(stream);
+        assert_eq!(tx.flush().await.unwrap_err().code(), Code::Cancelled);
+        assert_eq!(tx.send(4).await.unwrap_err().code(), Code::Cancelled);
+    }
+
+    #[tokio::test]
+    async fn finish_and_cancel() {
+        // finishing ends the requests after the messages sent
+        let (tx, stream) = RequestSender::channel(4);
+        let mut requests = request_stream(stream);
+        tx.send(1).await.unwrap();
+        tx.finish();
+        assert_eq!(requests.next().await.unwrap().unwrap(), 1);
+        assert!(requests.next().await.is_none());
+
+        // dropping without finishing cancels, and the messages buffered are not sent
+        let (tx, stream) = RequestSender::channel(4);
+        let mut requests = request_stream(stream);
+        tx.send(2).await.unwrap();
+        drop(tx);
+        assert_eq!(
+            requests.next().await.unwrap().unwrap_err().code(),
+            Code::Cancelled
+        );
+        assert!(requests.next().await.is_none());
+
+        // the other streams can only end
+        let mut requests = request_stream(futures::stream::iter([3]));
+        assert_eq!(requests.next().await.unwrap().unwrap(), 3);
+        assert!(requests.next().await.is_none());
+    }
+}
@@ -1,6 +1,6 @@
 //! These codes are copied from `tonic/src/codec/compression.rs` and may be modified by us.
 
-use std::io;
+use std::io::{self, Read};
 
 use bytes::{Buf, BufMut, BytesMut};
 use flate2::bufread::{GzDecoder, GzEncoder, ZlibDecoder, ZlibEncoder};
@@ -217,27 +217,35 @@ pub(crate) fn compress(
     Ok(())
 }
 
-/// Decompress `len` bytes from `src_buf` into `dest_buf`.
+/// Decompresses the `src_buf` into the `dest_buf`, which stops once more than `limit` bytes are
+/// inflated, so a small compressed message can't allocate without limit. The caller tells the
+/// excess by the length of the `dest_buf` larger than the `limit`.
 pub(crate) fn decompress(
     encoding: CompressionEncoding,
     src_buf: &mut BytesMut,
     dest_buf: &mut BytesMut,
+    limit: Option<usize>,
 ) -> Result<(), io::Error> {
     let len = src_buf.len();
     let estimate_decompressed_len = len * 2;
-    let capacity = ((estimate_decompressed_len / BUFFER_SIZE) + 1) * BUFFER_SIZE;
+    let mut capacity = ((estimate_decompressed_len / BUFFER_SIZE) + 1) * BUFFER_SIZE;
+    // one more byte than the limit for telling the excess
+    let cap = limit.map_or(u64::MAX, |limit| limit as u64 + 1);
+    if let Some(limit) = limit {
+        capacity = capacity.min(limit + 1);
+    }
 
     dest_buf.reserve(capacity);
 
     match encoding {
         CompressionEncoding::Gzip(_) => {
-            let mut gz_decoder = GzDecoder::new(&src_buf[0..len]);
-            io::copy(&mut gz_decoder, &mut dest_buf.writer())?;
+            let gz_decoder = GzDecoder::new(&src_buf[0..len]);
+            io::copy(&mut gz_decoder.take(cap), &mut dest_buf.writer())?;
         }
 
         CompressionEncoding::Zlib(_) => {
-            let mut zlib_decoder = ZlibDecoder::new(&src_buf[0..len]);
-            io::copy(&mut zlib_decoder, &mut dest_buf.writer())?;
+            let zlib_decoder = ZlibDecoder::new(&src_buf[0..len]);
+            io::copy(&mut zlib_decoder.take(cap), &mut dest_buf.writer())?;
         }
         _ => {}
     };
@@ -276,8 +284,37 @@ mod tests {
         for encoding in encodings {
             compress_buf.clear();
             compress(encoding, &mut src, &mut compress_buf).expect("compress failed:");
-            decompress(encoding, &mut compress_buf, &mut de_data).expect("decompress failed:");
+            decompress(encoding, &mut compress_buf, &mut de_data, None)
+                .expect("decompress failed:");
             assert_eq!(test_data, de_data);
         }
     }
+
+    #[test]
+    fn decompress_with_limit() {
+        let mut src = BytesMut::new();
+        src.put_bytes(b'a', 1024 * 1024);
+        let mut compressed = BytesMut::new();
+        let mut de_data = BytesMut::new();
+
+        let encodings = [
+            CompressionEncoding::Gzip(Some(GzipConfig::default())),
+            CompressionEncoding::Zlib(Some(ZlibConfig::default())),
+        ];
+        for encoding in encodings {
+            compressed.clear();
+            compress(encoding, &mut src.clone(), &mut compressed).expect("compress failed:");
+
+            // the inflating stops right after the limit
+            de_data.clear();
+            decompress(encoding, &mut compressed.clone(), &mut de_data, Some(1024))
+                .expect("decompress failed:");
+            assert_eq!(de_data.len(), 1024 + 1);
+
+            de_data.clear();
+            decompress(encoding, &mut compressed, &mut de_data, Some(src.len()))
+                .expect("decompress failed:");
+            assert_eq!(de_data, src);
+        }
+    }
 }
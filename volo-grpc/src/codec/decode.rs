@@ -1,42 +1,91 @@
 use std::{
     fmt,
-    marker::PhantomData,
     pin::Pin,
     task::{Context, Poll},
 };
 
 use bytes::{Buf, BufMut, BytesMut};
 use futures::{future, Stream};
-use futures_util::ready;
-use http::StatusCode;
+use http::{HeaderMap, StatusCode};
 use http_body::Body;
-use hyper::body::Incoming;
 use pilota::prost::Message;
+use serde::de::DeserializeOwned;
 use tracing::{debug, trace};
+use volo::{
+    error::CodedError,
+    util::budget::{Budget, DEFAULT_YIELD_BUDGET},
+};
 
-use super::{DefaultDecoder, BUFFER_SIZE, PREFIX_LEN};
+use super::{Codec, CodecDecoder, DefaultDecoder, JsonDecoder, BUFFER_SIZE, PREFIX_LEN};
 use crate::{
+    body::BoxBody,
     codec::{
         compression::{decompress, CompressionEncoding},
         Decoder,
     },
+    inspect::CallInspector,
     metadata::MetadataMap,
     status::Code,
     Status,
 };
 
+/// Default maximum size of a single decoded message, same as grpc-go.
+pub const DEFAULT_MAX_DECODING_MESSAGE_SIZE: usize = 4 * 1024 * 1024; // 4MB
+
+/// Limits applied when decoding messages from a [`RecvStream`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeLimits {
+    /// The maximum size of a single message, `None` means no limit.
+    pub max_message_size: Option<usize>,
+    /// The maximum accumulated size of all messages received on the stream, `None` means no
+    /// limit.
+    pub max_total_size: Option<usize>,
+    /// The number of buffered messages decoded in a row before yielding to the scheduler, `0`
+    /// means never yield.
+    pub yield_budget: usize,
+}
+
+impl DecodeLimits {
+    /// Creates a [`DecodeLimits`] without any limit.
+    pub const fn unlimited() -> Self {
+        Self {
+            max_message_size: None,
+            max_total_size: None,
+            yield_budget: 0,
+        }
+    }
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self {
+            max_message_size: Some(DEFAULT_MAX_DECODING_MESSAGE_SIZE),
+            max_total_size: None,
+            yield_budget: DEFAULT_YIELD_BUDGET,
+        }
+    }
+}
+
 /// Streaming Received Request and Received Response.
 ///
 /// Provides an interface for receiving messages and trailers.
 pub struct RecvStream<T> {
-    body: Incoming,
-    decoder: DefaultDecoder<T>,
+    body: BoxBody,
+    /// Decodes a message by the codec, e.g. protobuf by [`RecvStream::new`] or JSON by
+    /// [`RecvStream::new_json`].
+    decode: fn(&mut BytesMut) -> Result<Option<T>, Status>,
     trailers: Option<MetadataMap>,
     buf: BytesMut,
     state: State,
     kind: Kind,
     compression_encoding: Option<CompressionEncoding>,
     decompress_buf: BytesMut,
+    limits: DecodeLimits,
+    received: usize,
+    budget: Budget,
+    yield_next: bool,
+    /// Inspects each message decoded, see [`inspect_recv`](crate::inspect::inspect_recv).
+    inspector: Option<(CallInspector, fn(&CallInspector, &T) -> Result<(), Status>)>,
 }
 
 impl<T> Unpin for RecvStream<T> {}
@@ -46,34 +95,120 @@ enum State {
     Header,
     Body(Option<CompressionEncoding>, usize),
     Error,
+    /// The end of the stream has been reached, and the trailers have been received.
+    End,
 }
 
+/// The kind of a [`RecvStream`], which tells how the end of the stream is checked.
 #[derive(Debug, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum Kind {
     Request,
     Response(StatusCode),
+    /// A trailers-only response, whose `grpc-status` is carried by the headers.
+    TrailersOnly(StatusCode, HeaderMap),
 }
 
 impl<T> RecvStream<T> {
     pub fn new(
-        body: Incoming,
+        body: BoxBody,
+        kind: Kind,
+        compression_encoding: Option<CompressionEncoding>,
+        limits: DecodeLimits,
+    ) -> Self
+    where
+        T: Message + Default,
+    {
+        Self::with_decode(body, kind, compression_encoding, limits, |src| {
+            DefaultDecoder::<T>::default().decode(src)
+        })
+    }
+
+    /// Creates a [`RecvStream`] decoding the messages as JSON for `application/grpc+json`.
+    pub fn new_json(
+        body: BoxBody,
         kind: Kind,
         compression_encoding: Option<CompressionEncoding>,
+        limits: DecodeLimits,
+    ) -> Self
+    where
+        T: DeserializeOwned,
+    {
+        Self::with_decode(body, kind, compression_encoding, limits, |src| {
+            JsonDecoder::<T>::default().decode(src)
+        })
+    }
+
+    /// Creates a [`RecvStream`] decoding the messages by the custom [`Codec`].
+    pub fn new_custom<C: Codec<T>>(
+        body: BoxBody,
+        kind: Kind,
+        compression_encoding: Option<CompressionEncoding>,
+        limits: DecodeLimits,
+    ) -> Self {
+        Self::with_decode(body, kind, compression_encoding, limits, |src| {
+            CodecDecoder::<C, T>::default().decode(src)
+        })
+    }
+
+    fn with_decode(
+        body: BoxBody,
+        kind: Kind,
+        compression_encoding: Option<CompressionEncoding>,
+        limits: DecodeLimits,
+        decode: fn(&mut BytesMut) -> Result<Option<T>, Status>,
     ) -> Self {
         RecvStream {
             body,
-            decoder: DefaultDecoder(PhantomData),
+            decode,
             trailers: None,
             buf: BytesMut::with_capacity(BUFFER_SIZE),
             state: State::Header,
             kind,
             compression_encoding,
             decompress_buf: BytesMut::new(),
+            budget: Budget::new(limits.yield_budget),
+            yield_next: false,
+            limits,
+            received: 0,
+            inspector: None,
+        }
+    }
+
+    /// Inspects each message decoded by the inspector, whose error aborts the stream.
+    pub(crate) fn inspect(&mut self, inspector: CallInspector)
+    where
+        T: Message + 'static,
+    {
+        self.inspector = Some((inspector, |inspector: &CallInspector, message: &T| {
+            inspector.on_recv(message)
+        }));
+    }
+
+    /// Checks the length prefix of a message against the limits before allocating for it.
+    fn check_limits(&self, len: usize) -> Result<(), Status> {
+        if let Some(max) = self.limits.max_message_size {
+            if len > max {
+                return Err(Status::new(
+                    Code::ResourceExhausted,
+                    format!("Received message larger than max ({len} vs. {max})"),
+                ));
+            }
         }
+        if let Some(max) = self.limits.max_total_size {
+            let total = self.received.saturating_add(len);
+            if total > max {
+                return Err(Status::new(
+                    Code::ResourceExhausted,
+                    format!("Received messages larger than max in total ({total} vs. {max})"),
+                ));
+            }
+        }
+        Ok(())
     }
 }
 
-impl<T: Message + Default> RecvStream<T> {
+impl<T> RecvStream<T> {
     /// Get the next message from the stream.
     async fn message(&mut self) -> Result<Option<T>, Status> {
         match future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await {
@@ -96,6 +231,10 @@ impl<T: Message + Default> RecvStream<T> {
         if let Some(trailers) = self.trailers.take() {
             return Ok(Some(trailers));
         }
+        if let State::End = self.state {
+            // the trailers have been taken, or there are no trailers
+            return Ok(None);
+        }
 
         let maybe_trailer = future::poll_fn(|cx| Pin::new(&mut self.body).poll_frame(cx)).await;
 
@@ -148,6 +287,11 @@ impl<T: Message + Default> RecvStream<T> {
                 }
             };
             let len = self.buf.get_u32() as usize;
+            if let Err(status) = self.check_limits(len) {
+                self.state = State::Error;
+                return Err(status);
+            }
+            self.received += len;
             self.buf.reserve(len);
 
             self.state = State::Body(compression_encoding, len);
@@ -162,20 +306,32 @@ impl<T: Message + Default> RecvStream<T> {
             let mut buf = self.buf.split_to(*len);
             let decode_result = if let Some(encoding) = compression_encoding {
                 self.decompress_buf.clear();
-                if let Err(err) = decompress(*encoding, &mut buf, &mut self.decompress_buf) {
-                    let message = if let Kind::Response(status) = self.kind {
-                        format!(
-                            "Error decompressing: {err}, while receiving response with status: \
-                             {status}"
-                        )
-                    } else {
-                        format!("Error decompressing: {err}, while sending request")
-                    };
+                let limit = self.limits.max_message_size;
+                if let Err(err) = decompress(*encoding, &mut buf, &mut self.decompress_buf, limit) {
+                    let message =
+                        if let Kind::Response(status) | Kind::TrailersOnly(status, _) = self.kind {
+                            format!(
+                                "Error decompressing: {err}, while receiving response with \
+                                 status: {status}"
+                            )
+                        } else {
+                            format!("Error decompressing: {err}, while sending request")
+                        };
                     return Err(Status::new(Code::Internal, message));
                 }
-                DefaultDecoder::<T>::decode(&mut self.decoder, &mut self.decompress_buf)
+                // the decompression stops right after the limit is exceeded
+                if let Some(max) = limit {
+                    if self.decompress_buf.len() > max {
+                        self.state = State::Error;
+                        return Err(Status::new(
+                            Code::ResourceExhausted,
+                            format!("Received message after decompression larger than max ({max})"),
+                        ));
+                    }
+                }
+                (self.decode)(&mut self.decompress_buf)
             } else {
-                DefaultDecoder::<T>::decode(&mut self.decoder, &mut buf)
+                (self.decode)(&mut buf)
             };
 
             return match decode_result {
@@ -192,19 +348,41 @@ impl<T: Message + Default> RecvStream<T> {
     }
 }
 
-impl<T: Message + Default> Stream for RecvStream<T> {
+impl<T> Stream for RecvStream<T> {
     type Item = Result<T, Status>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // a burst of messages buffered in the body is decoded without returning to the scheduler,
+        // so yield once the budget is exhausted
+        if self.yield_next {
+            self.yield_next = false;
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+
         let trailer_frame = loop {
-            if let State::Error = &self.state {
+            if let State::Error | State::End = &self.state {
                 return Poll::Ready(None);
             }
             if let Some(item) = self.decode_chunk()? {
+                if let Some((inspector, on_recv)) = &self.inspector {
+                    if let Err(status) = on_recv(inspector, &item) {
+                        self.state = State::Error;
+                        return Poll::Ready(Some(Err(status)));
+                    }
+                }
+                self.yield_next = self.budget.consume();
                 return Poll::Ready(Some(Ok(item)));
             }
 
-            match ready!(Pin::new(&mut self.body).poll_frame(cx)) {
+            let frame = match Pin::new(&mut self.body).poll_frame(cx) {
+                Poll::Ready(frame) => frame,
+                Poll::Pending => {
+                    self.budget.reset();
+                    return Poll::Pending;
+                }
+            };
+            match frame {
                 Some(Ok(frame)) => match frame.into_data() {
                     Ok(data) => self.buf.put(data),
                     Err(trailer) => {
@@ -214,10 +392,16 @@ impl<T: Message + Default> Stream for RecvStream<T> {
                 Some(Err(e)) => {
                     let err: crate::BoxError = e.into();
                     let status = Status::from_error(err);
+                    // the reset of the client only ends the requests, which is told from the
+                    // half-close by the `CancellationToken` of the call
                     if self.kind == Kind::Request && status.code() == Code::Cancelled {
                         return Poll::Ready(None);
                     }
-                    debug!("[VOLO] decoder inner stream error: {:?}", status);
+                    debug!(
+                        "[VOLO] decoder inner stream error: {:?}, code: {}",
+                        status,
+                        CodedError::code(&status)
+                    );
                     let _ = std::mem::replace(&mut self.state, State::Error);
                     return Poll::Ready(Some(Err(status)));
                 }
@@ -235,6 +419,28 @@ impl<T: Message + Default> Stream for RecvStream<T> {
             }
         };
 
+        if let Kind::TrailersOnly(status, headers) = &self.kind {
+            // The body of a trailers-only response must be empty, so the headers are the trailers.
+            if trailer_frame.is_some() {
+                debug!("[VOLO] unexpected trailers in trailers-only response");
+                return Poll::Ready(Some(Err(Status::new(
+                    Code::Internal,
+                    "Unexpected trailers in trailers-only response.".to_string(),
+                ))));
+            }
+            if let Err(e) = Status::infer_grpc_status(Some(headers), *status) {
+                self.state = State::Error;
+                return Poll::Ready(e.map(Err));
+            }
+            self.trailers = Some(MetadataMap::from_headers(headers.clone()));
+        }
+
+        if let Kind::Request = self.kind {
+            self.trailers = trailer_frame
+                .and_then(|frame| frame.into_trailers().ok())
+                .map(MetadataMap::from_headers);
+        }
+
         if let Kind::Response(status) = self.kind {
             let trailer = match trailer_frame.map(|frame| frame.into_trailers()) {
                 Some(Ok(trailer)) => Some(trailer),
@@ -250,6 +456,7 @@ impl<T: Message + Default> Stream for RecvStream<T> {
             };
 
             if let Err(e) = Status::infer_grpc_status(trailer.as_ref(), status) {
+                self.state = State::Error;
                 return if let Some(e) = e {
                     Some(Err(e)).into()
                 } else {
@@ -260,6 +467,7 @@ impl<T: Message + Default> Stream for RecvStream<T> {
             }
         }
 
+        self.state = State::End;
         Poll::Ready(None)
     }
 }
@@ -269,3 +477,111 @@ impl<T> fmt::Debug for RecvStream<T> {
         f.debug_struct("RecvStream").finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use futures::{executor::block_on, StreamExt};
+    use http_body::Frame;
+    use http_body_util::StreamBody;
+
+    use super::*;
+    use crate::codec::compression::{compress, GzipConfig, ZlibConfig};
+
+    fn recv_stream(
+        frames: Vec<Bytes>,
+        compression_encoding: Option<CompressionEncoding>,
+        limits: DecodeLimits,
+    ) -> RecvStream<String> {
+        let frames = frames
+            .into_iter()
+            .map(|data| Ok::<_, Status>(Frame::data(data)))
+            .collect::<Vec<_>>();
+        let body = BoxBody::new(StreamBody::new(futures::stream::iter(frames)));
+        RecvStream::new(
+            body,
+            Kind::Response(StatusCode::OK),
+            compression_encoding,
+            limits,
+        )
+    }
+
+    /// Frames the `payload` with the prefix of grpc.
+    fn message(compressed: bool, payload: &[u8]) -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u8(compressed as u8);
+        buf.put_u32(payload.len() as u32);
+        buf.put_slice(payload);
+        buf.freeze()
+    }
+
+    fn string_message(len: usize) -> BytesMut {
+        let mut buf = BytesMut::new();
+        "a".repeat(len).encode(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn uncompressed_message_larger_than_max() {
+        let limits = DecodeLimits {
+            max_message_size: Some(64),
+            ..Default::default()
+        };
+        let mut stream = recv_stream(
+            vec![
+                message(false, &string_message(16)),
+                message(false, &string_message(128)),
+            ],
+            None,
+            limits,
+        );
+
+        assert_eq!(block_on(stream.next()).unwrap().unwrap(), "a".repeat(16));
+        let status = block_on(stream.next()).unwrap().unwrap_err();
+        assert_eq!(status.code(), Code::ResourceExhausted);
+        assert!(block_on(stream.next()).is_none());
+    }
+
+    #[test]
+    fn compressed_message_larger_than_max() {
+        let encoding = CompressionEncoding::Gzip(Some(GzipConfig::default()));
+        let compress_message = |len| {
+            let mut compressed = BytesMut::new();
+            compress(encoding, &mut string_message(len), &mut compressed).unwrap();
+            message(true, &compressed)
+        };
+        let small = compress_message(16);
+        // highly compressible, the compressed one is much smaller than the limit
+        let large = compress_message(1024 * 1024);
+        assert!(large.len() < 64 * 1024);
+
+        let limits = DecodeLimits {
+            max_message_size: Some(64 * 1024),
+            ..Default::default()
+        };
+        let mut stream = recv_stream(vec![small, large], Some(encoding), limits);
+
+        assert_eq!(block_on(stream.next()).unwrap().unwrap(), "a".repeat(16));
+        let status = block_on(stream.next()).unwrap().unwrap_err();
+        assert_eq!(status.code(), Code::ResourceExhausted);
+        // the inflating stops right after the limit
+        assert_eq!(stream.decompress_buf.len(), 64 * 1024 + 1);
+        assert!(block_on(stream.next()).is_none());
+    }
+
+    #[test]
+    fn decompress_within_limit() {
+        let encoding = CompressionEncoding::Zlib(Some(ZlibConfig::default()));
+        let mut compressed = BytesMut::new();
+        compress(encoding, &mut string_message(1024), &mut compressed).unwrap();
+
+        let limits = DecodeLimits {
+            max_message_size: Some(2048),
+            ..Default::default()
+        };
+        let mut stream = recv_stream(vec![message(true, &compressed)], Some(encoding), limits);
+
+        assert_eq!(block_on(stream.next()).unwrap().unwrap(), "a".repeat(1024));
+        assert!(block_on(stream.next()).is_none());
+    }
+}
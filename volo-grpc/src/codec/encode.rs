@@ -1,9 +1,10 @@
 use bytes::{BufMut, Bytes, BytesMut};
-use futures::{Stream, StreamExt};
+use futures::{future, stream, Stream, StreamExt};
 use http_body::Frame;
 use pilota::prost::Message;
+use serde::Serialize;
 
-use super::{DefaultEncoder, PREFIX_LEN};
+use super::{Codec, CodecEncoder, DefaultEncoder, JsonEncoder, MessageCodec, PREFIX_LEN};
 use crate::{
     codec::{
         compression::{compress, CompressionEncoding},
@@ -19,6 +20,54 @@ pub fn encode<T, S>(
 where
     S: Stream<Item = Result<T, Status>> + Send + Sync + 'static,
     T: Message + 'static,
+{
+    encode_with(DefaultEncoder::default(), source, compression_encoding)
+}
+
+/// Encodes the messages as JSON for `application/grpc+json`, each of which is framed by the same
+/// prefix as protobuf.
+pub fn encode_json<T, S>(
+    source: S,
+    compression_encoding: Option<CompressionEncoding>,
+) -> BoxStream<'static, Result<Frame<Bytes>, Status>>
+where
+    S: Stream<Item = Result<T, Status>> + Send + Sync + 'static,
+    T: Serialize + 'static,
+{
+    encode_with(JsonEncoder::default(), source, compression_encoding)
+}
+
+/// Encodes the messages by the custom [`Codec`], each of which is framed by the same prefix as
+/// protobuf.
+pub fn encode_custom<C, T, S>(
+    source: S,
+    compression_encoding: Option<CompressionEncoding>,
+) -> BoxStream<'static, Result<Frame<Bytes>, Status>>
+where
+    C: Codec<T>,
+    S: Stream<Item = Result<T, Status>> + Send + Sync + 'static,
+    T: 'static,
+{
+    encode_with(
+        CodecEncoder::<C, T>::default(),
+        source,
+        compression_encoding,
+    )
+}
+
+/// Returns the body of the error that the codec is not supported by the messages.
+pub fn unsupported(codec: MessageCodec) -> BoxStream<'static, Result<Frame<Bytes>, Status>> {
+    Box::pin(stream::once(future::ready(Err(codec.unsupported()))))
+}
+
+fn encode_with<E, S>(
+    mut encoder: E,
+    source: S,
+    compression_encoding: Option<CompressionEncoding>,
+) -> BoxStream<'static, Result<Frame<Bytes>, Status>>
+where
+    E: Encoder<Error = Status> + Send + Sync + 'static,
+    S: Stream<Item = Result<E::Item, Status>> + Send + Sync + 'static,
 {
     Box::pin(async_stream::stream! {
         let mut buf = BytesMut::with_capacity(BUFFER_SIZE);
@@ -37,8 +86,6 @@ where
                     unsafe {
                         buf.advance_mut(PREFIX_LEN);
                     }
-                    let mut encoder=DefaultEncoder::default();
-
                     if let Some(config)=compression_encoding{
                         compressed_buf.clear();
                         encoder.encode(item, &mut compressed_buf)
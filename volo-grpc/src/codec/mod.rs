@@ -1,18 +1,53 @@
 //! Generic encoding and decoding.
 //!
 //! This module contains the generic `Encoder` and `Decoder` traits as well as
-//! the 'DefaultEncoder' and 'DefaultDecoder' implementations based on prost.
+//! the 'DefaultEncoder' and 'DefaultDecoder' implementations based on prost, and the
+//! 'JsonEncoder' and 'JsonDecoder' implementations based on serde for `application/grpc+json`.
+//!
+//! Other wire formats, e.g. flatbuffers, are plugged in by implementing [`Codec`] for the messages
+//! of a service, see [`MessageCodec::Custom`].
+//!
+//! # Flow control
+//!
+//! The messages are encoded and decoded lazily, so the HTTP/2 flow control applies to the
+//! streams end to end, and a slow consumer on either side makes the producer on the other side
+//! pend instead of buffering without a limit:
+//!
+//! - The body of the messages sent is polled by the connection only when the HTTP/2 stream has send
+//!   capacity, and each poll [`encode`](encode::encode)s only the next message, so a stream of
+//!   messages is not consumed faster than the peer grants the window.
+//! - The body of the messages received is read by [`RecvStream`](decode::RecvStream) only when the
+//!   next message is polled, and the window is released to the peer only after the data has been
+//!   read, so the peer stops sending when the messages are not consumed.
+//!
+//! The memory used by the messages of a stream in flight is therefore bounded by:
+//!
+//! - the stream window granted by the receiver, e.g. `http2_init_stream_window_size` of the server,
+//!   1 MiB by default, or the one of the client, while the connection window bounds the sum of all
+//!   the streams of a connection;
+//! - one encoded message, which may exceed the window, waiting for the capacity in the sender;
+//! - the messages buffered by the producer, e.g. [`DEFAULT_BUFFER`] messages of a
+//!   [`Sender`](crate::server::sender::Sender), or a
+//!   [`RequestSender`](crate::client::RequestSender) with its own bound;
+//! - one partially received message in the receiver, limited by the max decoding message size.
+//!
+//! [`DEFAULT_BUFFER`]: crate::server::sender::DEFAULT_BUFFER
 
 pub mod compression;
 pub mod decode;
 pub mod encode;
 
-use std::{io, marker::PhantomData, mem::size_of};
+use std::{fmt, io, marker::PhantomData, mem::size_of};
 
-use bytes::BytesMut;
+use bytes::{Buf, BufMut, BytesMut};
+use http::{header::CONTENT_TYPE, HeaderMap, HeaderValue};
 use pilota::prost::Message;
+use serde::{de::DeserializeOwned, Serialize};
 
-use crate::{status::Code::Internal, Status};
+use crate::{
+    status::Code::{Internal, Unimplemented},
+    Status,
+};
 
 const PREFIX_LEN: usize = size_of::<u32>() + size_of::<u8>();
 const BUFFER_SIZE: usize = 8 * 1024;
@@ -81,3 +116,276 @@ impl<T> Default for DefaultDecoder<T> {
         DefaultDecoder(PhantomData)
     }
 }
+
+#[derive(Debug, Clone)]
+pub struct JsonEncoder<T>(PhantomData<fn(T)>);
+
+impl<T: Serialize> Encoder for JsonEncoder<T> {
+    type Item = T;
+    type Error = Status;
+
+    fn encode(&mut self, item: Self::Item, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        serde_json::to_writer(dst.writer(), &item).map_err(|e| Status::new(Internal, e.to_string()))
+    }
+}
+
+impl<T> Default for JsonEncoder<T> {
+    fn default() -> Self {
+        JsonEncoder(PhantomData)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct JsonDecoder<T>(PhantomData<fn(T)>);
+
+impl<T: DeserializeOwned> Decoder for JsonDecoder<T> {
+    type Item = T;
+    type Error = Status;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let item = serde_json::from_slice(src).map_err(|e| Status::new(Internal, e.to_string()))?;
+        src.advance(src.len());
+        Ok(Some(item))
+    }
+}
+
+impl<T> Default for JsonDecoder<T> {
+    fn default() -> Self {
+        JsonDecoder(PhantomData)
+    }
+}
+
+/// A wire format of the messages of type `T`, e.g. flatbuffers, which replaces protobuf for the
+/// services generated with it, see `volo_build::Builder::codec`.
+///
+/// The messages are framed by the same 5-byte prefix as protobuf, and compressed in the same way,
+/// so only the bytes of each message are encoded and decoded by the codec.
+pub trait Codec<T>: Send + Sync + 'static {
+    /// The subtype of the content type, e.g. `flatbuffers` for `application/grpc+flatbuffers`.
+    const SUBTYPE: &'static str;
+
+    /// Encodes a message into the buffer.
+    fn encode(item: T, dst: &mut BytesMut) -> Result<(), Status>;
+
+    /// Decodes a message from all the bytes of the buffer.
+    fn decode(src: &mut BytesMut) -> Result<T, Status>;
+}
+
+/// The [`Encoder`] half of a [`Codec`].
+pub struct CodecEncoder<C, T>(PhantomData<fn(C, T)>);
+
+impl<C: Codec<T>, T> Encoder for CodecEncoder<C, T> {
+    type Item = T;
+    type Error = Status;
+
+    fn encode(&mut self, item: Self::Item, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        C::encode(item, dst)
+    }
+}
+
+impl<C, T> Default for CodecEncoder<C, T> {
+    fn default() -> Self {
+        CodecEncoder(PhantomData)
+    }
+}
+
+impl<C, T> fmt::Debug for CodecEncoder<C, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("CodecEncoder")
+            .field(&std::any::type_name::<C>())
+            .finish()
+    }
+}
+
+/// The [`Decoder`] half of a [`Codec`].
+pub struct CodecDecoder<C, T>(PhantomData<fn(C) -> T>);
+
+impl<C: Codec<T>, T> Decoder for CodecDecoder<C, T> {
+    type Item = T;
+    type Error = Status;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let item = C::decode(src)?;
+        src.advance(src.len());
+        Ok(Some(item))
+    }
+}
+
+impl<C, T> Default for CodecDecoder<C, T> {
+    fn default() -> Self {
+        CodecDecoder(PhantomData)
+    }
+}
+
+impl<C, T> fmt::Debug for CodecDecoder<C, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("CodecDecoder")
+            .field(&std::any::type_name::<C>())
+            .finish()
+    }
+}
+
+/// The protobuf [`Codec`] of the generated messages, which is the default one.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProtoCodec;
+
+impl<T: Message + Default> Codec<T> for ProtoCodec {
+    const SUBTYPE: &'static str = "proto";
+
+    fn encode(item: T, dst: &mut BytesMut) -> Result<(), Status> {
+        DefaultEncoder::default().encode(item, dst)
+    }
+
+    fn decode(src: &mut BytesMut) -> Result<T, Status> {
+        Message::decode(src).map_err(|e| Status::new(Internal, e.to_string()))
+    }
+}
+
+/// The JSON [`Codec`] of the messages generated with serde.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonCodec;
+
+impl<T: Serialize + DeserializeOwned> Codec<T> for JsonCodec {
+    const SUBTYPE: &'static str = "json";
+
+    fn encode(item: T, dst: &mut BytesMut) -> Result<(), Status> {
+        JsonEncoder::default().encode(item, dst)
+    }
+
+    fn decode(src: &mut BytesMut) -> Result<T, Status> {
+        serde_json::from_slice(src).map_err(|e| Status::new(Internal, e.to_string()))
+    }
+}
+
+/// The codec of the messages, which is told by the subtype of the content type, e.g.
+/// `application/grpc+json`.
+///
+/// The messages of all the codecs are framed by the same 5-byte prefix. The JSON codec is only
+/// supported by the services generated with serde, see `volo_build::Builder::with_serde`, and a
+/// custom codec only by the services generated with it, see `volo_build::Builder::codec`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum MessageCodec {
+    /// `application/grpc` or `application/grpc+proto`.
+    #[default]
+    Proto,
+    /// `application/grpc+json`.
+    Json,
+    /// A [`Codec`] of the subtype, e.g. `application/grpc+flatbuffers` of `flatbuffers`.
+    ///
+    /// The clients send the requests with it by `ClientBuilder::codec`, and the servers accept
+    /// it only for the services built with it by `ServiceBuilder::codec`.
+    Custom(&'static str),
+}
+
+impl MessageCodec {
+    /// Returns the custom codec of the [`Codec`].
+    pub fn custom<C: Codec<T>, T>() -> Self {
+        Self::Custom(C::SUBTYPE)
+    }
+
+    /// Returns the codec told by the content type in the headers, which is [`Self::Proto`] for
+    /// the unknown or missing content types.
+    pub fn from_content_type(headers: &HeaderMap) -> Self {
+        Self::from_content_type_with(headers, None)
+    }
+
+    /// Returns the codec told by the content type in the headers, which may also be the custom
+    /// one accepted.
+    pub fn from_content_type_with(headers: &HeaderMap, accepted: Option<Self>) -> Self {
+        let Some(content_type) = headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok()) else {
+            return Self::Proto;
+        };
+        let subtype = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .strip_prefix("application/grpc+");
+        match (subtype, accepted) {
+            (Some(s), _) if s.eq_ignore_ascii_case("json") => Self::Json,
+            (Some(s), Some(Self::Custom(custom))) if s.eq_ignore_ascii_case(custom) => {
+                Self::Custom(custom)
+            }
+            _ => Self::Proto,
+        }
+    }
+
+    /// Returns the content type of the codec.
+    pub fn content_type(&self) -> HeaderValue {
+        match self {
+            Self::Proto => HeaderValue::from_static("application/grpc"),
+            Self::Json => HeaderValue::from_static("application/grpc+json"),
+            Self::Custom(subtype) => HeaderValue::from_str(&format!("application/grpc+{subtype}"))
+                .expect("the subtype of the codec should be a valid header value"),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Proto => "proto",
+            Self::Json => "json",
+            Self::Custom(subtype) => subtype,
+        }
+    }
+
+    /// The status of the messages not supporting the codec.
+    pub fn unsupported(&self) -> Status {
+        Status::new(
+            Unimplemented,
+            format!(
+                "the {} codec is not supported by the messages",
+                self.as_str()
+            ),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codec_of_content_type() {
+        let codec = |content_type: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+            MessageCodec::from_content_type(&headers)
+        };
+        assert_eq!(codec("application/grpc"), MessageCodec::Proto);
+        assert_eq!(codec("application/grpc+proto"), MessageCodec::Proto);
+        assert_eq!(codec("application/grpc+json"), MessageCodec::Json);
+        assert_eq!(
+            codec("application/grpc+JSON; charset=utf-8"),
+            MessageCodec::Json
+        );
+        assert_eq!(
+            MessageCodec::from_content_type(&HeaderMap::new()),
+            MessageCodec::Proto
+        );
+
+        // the custom subtypes are only accepted by the codec configured
+        assert_eq!(codec("application/grpc+text"), MessageCodec::Proto);
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/grpc+text"),
+        );
+        let text = MessageCodec::Custom("text");
+        assert_eq!(
+            MessageCodec::from_content_type_with(&headers, Some(text)),
+            text
+        );
+        assert_eq!(
+            MessageCodec::from_content_type_with(&headers, Some(MessageCodec::Custom("bin"))),
+            MessageCodec::Proto
+        );
+        assert_eq!(text.content_type(), "application/grpc+text");
+
+        let mut buf = BytesMut::new();
+        JsonEncoder::default().encode(vec![1, 2], &mut buf).unwrap();
+        assert_eq!(&buf[..], b"[1,2]");
+        let decoded: Option<Vec<u32>> = JsonDecoder::default().decode(&mut buf).unwrap();
+        assert_eq!(decoded, Some(vec![1, 2]));
+        assert!(buf.is_empty());
+    }
+}
@@ -1,7 +1,14 @@
 //! Re-exports for volo-build.
 
 pub use bytes::Bytes;
+pub use futures::future::BoxFuture;
 pub use http_body::Frame;
 pub use hyper;
 pub use tokio::sync::mpsc;
 pub use tokio_stream::{iter, wrappers::ReceiverStream, StreamExt};
+
+pub use crate::{
+    client::sender::request_stream,
+    inspect::{inspect_recv, inspect_request, inspect_send, inspector},
+    layer::retry::unary_request,
+};
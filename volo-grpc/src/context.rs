@@ -1,9 +1,22 @@
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 pub use volo::context::*;
-use volo::newtype_impl_context;
+use volo::{
+    config::ConfigError,
+    net::dial::ConnectRetry,
+    newtype_impl_context,
+    retry::RetryPolicy,
+    route::{shadow_rpc_info, ShadowContext},
+};
 
-use crate::codec::compression::CompressionEncoding;
+use crate::{
+    codec::{
+        compression::{CompressionEncoding, GzipConfig, ZlibConfig},
+        MessageCodec,
+    },
+    inspect::Inspection,
+    server::cancel::CancellationToken,
+};
 
 pub struct ClientCxInner;
 
@@ -19,6 +32,12 @@ impl ClientContext {
     }
 }
 
+impl ShadowContext for ClientContext {
+    fn shadow(&self) -> Self {
+        Self::new(shadow_rpc_info(&self.rpc_info))
+    }
+}
+
 impl Default for ClientContext {
     fn default() -> Self {
         Self(RpcCx::new(RpcInfo::with_role(Role::Client), ClientCxInner))
@@ -39,7 +58,45 @@ impl std::ops::DerefMut for ClientContext {
     }
 }
 
-pub struct ServerCxInner;
+#[derive(Debug, Default)]
+pub struct ServerCxInner {
+    /// This is unstable now and may be changed in the future.
+    pub stats: ServerStats,
+}
+
+/// The timestamps of a request on the server.
+///
+/// This is unstable now and may be changed in the future.
+#[derive(Debug, Default, Clone)]
+pub struct ServerStats {
+    arrive_at: Option<SystemTime>,
+    process_start_at: Option<SystemTime>,
+}
+
+impl ServerStats {
+    /// When the headers of the request arrived.
+    pub fn arrive_at(&self) -> Option<SystemTime> {
+        self.arrive_at
+    }
+
+    pub fn record_arrive_at(&mut self) {
+        self.arrive_at = Some(SystemTime::now());
+    }
+
+    /// When the request is passed to the handler, after the first message is ready to be decoded.
+    pub fn process_start_at(&self) -> Option<SystemTime> {
+        self.process_start_at
+    }
+
+    pub fn record_process_start_at(&mut self) {
+        self.process_start_at = Some(SystemTime::now());
+    }
+
+    /// The delay from the arrival of the request to the start of processing it.
+    pub fn pre_process_delay(&self) -> Option<Duration> {
+        self.process_start_at?.duration_since(self.arrive_at?).ok()
+    }
+}
 
 /// A context for server to pass information such as `RpcInfo` and `Config` between middleware
 /// during the rpc call lifecycle.
@@ -49,7 +106,27 @@ newtype_impl_context!(ServerContext, Config, 0);
 
 impl Default for ServerContext {
     fn default() -> Self {
-        Self(RpcCx::new(RpcInfo::with_role(Role::Server), ServerCxInner))
+        Self(RpcCx::new(
+            RpcInfo::with_role(Role::Server),
+            ServerCxInner::default(),
+        ))
+    }
+}
+
+impl ServerContext {
+    /// Returns the [`CancellationToken`] of the call, which tells the half-close of the client from
+    /// the cancellation, see [`crate::server::cancel`].
+    pub fn cancellation_token(&self) -> Option<&CancellationToken> {
+        self.extensions().get::<CancellationToken>()
+    }
+
+    /// Returns if the client has sent all its messages, after which the call goes on until the
+    /// response stream ends.
+    ///
+    /// Waits for it by [`CancellationToken::half_closed`].
+    pub fn client_half_closed(&self) -> bool {
+        self.cancellation_token()
+            .is_some_and(CancellationToken::is_half_closed)
     }
 }
 
@@ -75,9 +152,27 @@ pub struct Config {
     pub(crate) read_timeout: Option<Duration>,
     /// Amount of time to wait reading response.
     pub(crate) write_timeout: Option<Duration>,
+    /// The retry of the failed connects.
+    pub(crate) connect_retry: Option<ConnectRetry>,
 
     pub(crate) accept_compressions: Option<Vec<CompressionEncoding>>,
     pub(crate) send_compressions: Option<Vec<CompressionEncoding>>,
+
+    /// Maximum size of a single received message.
+    pub(crate) max_decoding_message_size: Option<usize>,
+    /// Maximum accumulated size of all received messages in a stream.
+    pub(crate) max_decoding_total_size: Option<usize>,
+    /// Number of buffered messages decoded in a row before yielding to the scheduler.
+    pub(crate) yield_budget: Option<usize>,
+
+    /// The retry policy overriding the one of the [`RetryLayer`](volo::retry::RetryLayer).
+    pub(crate) retry_policy: Option<RetryPolicy>,
+
+    /// The codec of the request messages.
+    pub(crate) codec: Option<MessageCodec>,
+
+    /// Makes the inspectors of the messages of each call.
+    pub(crate) inspection: Option<Inspection>,
 }
 
 impl Reusable for Config {
@@ -85,16 +180,66 @@ impl Reusable for Config {
         self.connect_timeout = None;
         self.read_timeout = None;
         self.write_timeout = None;
+        self.connect_retry = None;
         if let Some(v) = self.accept_compressions.as_mut() {
             v.clear();
         }
         if let Some(v) = self.send_compressions.as_mut() {
             v.clear();
         }
+        self.max_decoding_message_size = None;
+        self.max_decoding_total_size = None;
+        self.yield_budget = None;
+        self.retry_policy = None;
+        self.codec = None;
+        self.inspection = None;
     }
 }
 
 impl Config {
+    /// Checks the timeouts and compressions set by the client builder.
+    pub(crate) fn check(&self, err: &mut ConfigError) {
+        err.ensure_non_zero("connect_timeout", self.connect_timeout);
+        err.ensure_non_zero("read_timeout", self.read_timeout);
+        err.ensure_non_zero("write_timeout", self.write_timeout);
+        for (field, encodings) in [
+            ("send_compressions", &self.send_compressions),
+            ("accept_compressions", &self.accept_compressions),
+        ] {
+            let Some(encodings) = encodings else {
+                continue;
+            };
+            err.ensure(
+                !encodings.is_empty(),
+                field,
+                "is empty, the compression is disabled without setting it",
+            );
+            for encoding in encodings {
+                match encoding {
+                    CompressionEncoding::Gzip(Some(GzipConfig { level }))
+                    | CompressionEncoding::Zlib(Some(ZlibConfig { level })) => err.ensure(
+                        level.level() <= 9,
+                        field,
+                        format!("the level {} is larger than 9", level.level()),
+                    ),
+                    // the messages are sent uncompressed by the `grpc-encoding` of it
+                    CompressionEncoding::Gzip(None) | CompressionEncoding::Zlib(None)
+                        if field == "send_compressions" =>
+                    {
+                        err.push(
+                            field,
+                            format!("{encoding:?} is disabled without the config of the level"),
+                        )
+                    }
+                    CompressionEncoding::Identity if field == "send_compressions" => {
+                        err.push(field, "`Identity` is not a compression")
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
     pub fn merge(&mut self, other: Self) {
         if let Some(t) = other.connect_timeout {
             self.connect_timeout = Some(t);
@@ -105,11 +250,44 @@ impl Config {
         if let Some(t) = other.write_timeout {
             self.write_timeout = Some(t);
         }
+        if let Some(r) = other.connect_retry {
+            self.connect_retry = Some(r);
+        }
         if let Some(e) = other.accept_compressions {
             self.accept_compressions = Some(e);
         }
         if let Some(e) = other.send_compressions {
             self.send_compressions = Some(e);
         }
+        if let Some(s) = other.max_decoding_message_size {
+            self.max_decoding_message_size = Some(s);
+        }
+        if let Some(s) = other.max_decoding_total_size {
+            self.max_decoding_total_size = Some(s);
+        }
+        if let Some(b) = other.yield_budget {
+            self.yield_budget = Some(b);
+        }
+        if let Some(p) = other.retry_policy {
+            self.retry_policy = Some(p);
+        }
+        if let Some(c) = other.codec {
+            self.codec = Some(c);
+        }
+        if let Some(i) = other.inspection {
+            self.inspection = Some(i);
+        }
+    }
+
+    pub fn retry_policy(&self) -> Option<RetryPolicy> {
+        self.retry_policy
+    }
+
+    /// Sets the retry policy, which overrides the one of the
+    /// [`RetryLayer`](volo::retry::RetryLayer).
+    ///
+    /// This can be set by the CallOpt to override the policy per method.
+    pub fn set_retry_policy(&mut self, policy: Option<RetryPolicy>) {
+        self.retry_policy = policy;
     }
 }
@@ -0,0 +1,387 @@
+//! The inspection of each message sent and received by the calls, e.g. the metrics of the
+//! messages or rejecting some of them in the middle of a stream, which the layers only seeing the
+//! whole streams can't do.
+//!
+//! ```rust,ignore
+//! let metrics = MessageMetrics::new();
+//! let client = ClientBuilder::new("chat")
+//!     .message_inspector(metrics.clone())
+//!     .address(addr)
+//!     .build()?;
+//! // ... after some calls
+//! println!("{} messages sent", metrics.messages_sent());
+//! ```
+//!
+//! An inspector is made by the [`MakeInspector`] installed on the client or server builder for
+//! each call, and sees the messages of both directions in order. An error returned by it aborts
+//! the stream with the status: the sent stream yields the status and ends, and the received one
+//! yields the status in place of the message.
+//!
+//! The inspection is done by the generated code, which leaves the streams untouched when no
+//! inspector is installed.
+
+use std::{
+    fmt,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task::{ready, Context, Poll},
+};
+
+use futures::Stream;
+use pilota::prost::Message;
+
+use crate::{codec::decode::RecvStream, BoxStream, Request, Status};
+
+/// Inspects the messages of a call, see the [module docs](self).
+pub trait MessageInspector: Send + 'static {
+    /// Inspects a message before it's encoded, and aborts the sending with the error.
+    fn on_send(&mut self, _message: &dyn Message) -> Result<(), Status> {
+        Ok(())
+    }
+
+    /// Inspects a message after it's decoded, and aborts the receiving with the error.
+    fn on_recv(&mut self, _message: &dyn Message) -> Result<(), Status> {
+        Ok(())
+    }
+}
+
+/// Makes a [`MessageInspector`] for each call of the method, or `None` to skip the inspection.
+///
+/// It's implemented for the closures returning the optional inspectors.
+pub trait MakeInspector: Send + Sync + 'static {
+    fn make_inspector(&self, method: &str) -> Option<Box<dyn MessageInspector>>;
+}
+
+impl<F, I> MakeInspector for F
+where
+    F: Fn(&str) -> Option<I> + Send + Sync + 'static,
+    I: MessageInspector,
+{
+    fn make_inspector(&self, method: &str) -> Option<Box<dyn MessageInspector>> {
+        self(method).map(|i| Box::new(i) as Box<dyn MessageInspector>)
+    }
+}
+
+/// The [`MakeInspector`] installed on the builders, which is inserted into the extensions of the
+/// contexts for the generated code.
+#[derive(Clone)]
+pub(crate) struct Inspection(Arc<dyn MakeInspector>);
+
+impl Inspection {
+    pub(crate) fn new(make: impl MakeInspector) -> Self {
+        Self(Arc::new(make))
+    }
+}
+
+impl fmt::Debug for Inspection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Inspection").finish()
+    }
+}
+
+/// The inspector of a call, shared by the sent and received streams.
+#[derive(Clone)]
+pub struct CallInspector(Arc<Mutex<Box<dyn MessageInspector>>>);
+
+impl CallInspector {
+    pub fn on_send(&self, message: &dyn Message) -> Result<(), Status> {
+        self.0.lock().unwrap().on_send(message)
+    }
+
+    pub fn on_recv(&self, message: &dyn Message) -> Result<(), Status> {
+        self.0.lock().unwrap().on_recv(message)
+    }
+}
+
+impl fmt::Debug for CallInspector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("CallInspector").finish()
+    }
+}
+
+/// Makes the inspector of the call by the [`MakeInspector`] installed, if any, which also
+/// observes the sizes of the messages for the metrics layer with the `metrics` feature.
+///
+/// This is used by the generated code.
+pub fn inspector<Cx: volo::context::Context>(cx: &Cx) -> Option<CallInspector> {
+    let inspector = cx
+        .extensions()
+        .get::<Inspection>()
+        .and_then(|inspection| inspection.0.make_inspector(cx.rpc_info().method()));
+    // the sizes of the messages of the metrics layer
+    #[cfg(feature = "metrics")]
+    let inspector = match cx.extensions().get::<volo::metrics::rpc::MessageSizes>() {
+        Some(sizes) => Some(Box::new(crate::layer::metrics::SizeInspector {
+            sizes: sizes.clone(),
+            role: cx.rpc_info().role(),
+            inner: inspector,
+        }) as Box<dyn MessageInspector>),
+        None => inspector,
+    };
+    inspector.map(|i| CallInspector(Arc::new(Mutex::new(i))))
+}
+
+/// Inspects the messages to be sent, which is the stream itself without the inspector.
+///
+/// This is used by the generated code.
+pub fn inspect_send<T>(
+    stream: BoxStream<'static, Result<T, Status>>,
+    inspector: &Option<CallInspector>,
+) -> BoxStream<'static, Result<T, Status>>
+where
+    T: Message + 'static,
+{
+    match inspector {
+        Some(inspector) => Box::pin(InspectSend {
+            stream,
+            inspector: inspector.clone(),
+            aborted: false,
+        }),
+        None => stream,
+    }
+}
+
+/// Inspects the messages received.
+///
+/// This is used by the generated code.
+pub fn inspect_recv<T>(
+    mut stream: RecvStream<T>,
+    inspector: &Option<CallInspector>,
+) -> RecvStream<T>
+where
+    T: Message + 'static,
+{
+    if let Some(inspector) = inspector {
+        stream.inspect(inspector.clone());
+    }
+    stream
+}
+
+/// Inspects the message of a unary request.
+///
+/// This is used by the generated code.
+pub fn inspect_request<M: Message>(
+    req: Request<M>,
+    inspector: &Option<CallInspector>,
+) -> Result<Request<M>, Status> {
+    if let Some(inspector) = inspector {
+        inspector.on_send(req.get_ref())?;
+    }
+    Ok(req)
+}
+
+struct InspectSend<T> {
+    stream: BoxStream<'static, Result<T, Status>>,
+    inspector: CallInspector,
+    aborted: bool,
+}
+
+impl<T: Message + 'static> Stream for InspectSend<T> {
+    type Item = Result<T, Status>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.aborted {
+            return Poll::Ready(None);
+        }
+        let item = ready!(self.stream.as_mut().poll_next(cx));
+        if let Some(Ok(message)) = &item {
+            if let Err(status) = self.inspector.on_send(message) {
+                self.aborted = true;
+                return Poll::Ready(Some(Err(status)));
+            }
+        }
+        Poll::Ready(item)
+    }
+}
+
+/// Counts the messages and their encoded bytes of all the calls.
+///
+/// The clones share the same counters.
+#[derive(Debug, Clone, Default)]
+pub struct MessageMetrics {
+    counters: Arc<[AtomicU64; 4]>,
+}
+
+impl MessageMetrics {
+    const MESSAGES_SENT: usize = 0;
+    const BYTES_SENT: usize = 1;
+    const MESSAGES_RECEIVED: usize = 2;
+    const BYTES_RECEIVED: usize = 3;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn messages_sent(&self) -> u64 {
+        self.get(Self::MESSAGES_SENT)
+    }
+
+    /// Returns the bytes of the messages sent before the compression.
+    pub fn bytes_sent(&self) -> u64 {
+        self.get(Self::BYTES_SENT)
+    }
+
+    pub fn messages_received(&self) -> u64 {
+        self.get(Self::MESSAGES_RECEIVED)
+    }
+
+    /// Returns the bytes of the messages received after the decompression.
+    pub fn bytes_received(&self) -> u64 {
+        self.get(Self::BYTES_RECEIVED)
+    }
+
+    fn get(&self, i: usize) -> u64 {
+        self.counters[i].load(Ordering::Relaxed)
+    }
+
+    fn record(&self, messages: usize, bytes: usize, message: &dyn Message) {
+        self.counters[messages].fetch_add(1, Ordering::Relaxed);
+        self.counters[bytes].fetch_add(message.encoded_len() as u64, Ordering::Relaxed);
+    }
+}
+
+impl MessageInspector for MessageMetrics {
+    fn on_send(&mut self, message: &dyn Message) -> Result<(), Status> {
+        self.record(Self::MESSAGES_SENT, Self::BYTES_SENT, message);
+        Ok(())
+    }
+
+    fn on_recv(&mut self, message: &dyn Message) -> Result<(), Status> {
+        self.record(Self::MESSAGES_RECEIVED, Self::BYTES_RECEIVED, message);
+        Ok(())
+    }
+}
+
+impl MakeInspector for MessageMetrics {
+    fn make_inspector(&self, _method: &str) -> Option<Box<dyn MessageInspector>> {
+        Some(Box::new(self.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{StreamExt, TryStreamExt};
+    use http_body_util::StreamBody;
+
+    use super::*;
+    use crate::{
+        body::BoxBody,
+        codec::{
+            decode::{DecodeLimits, Kind},
+            encode::encode,
+        },
+        context::{Context as _, ServerContext},
+        status::Code,
+    };
+
+    fn cx(make: impl MakeInspector) -> ServerContext {
+        let mut cx = ServerContext::default();
+        cx.rpc_info.set_method("/chat.Chat/Echo".into());
+        cx.extensions_mut().insert(Inspection::new(make));
+        cx
+    }
+
+    /// Sends the messages through the encoding and decoding, as a client sends them to a server.
+    fn transfer(
+        stream: BoxStream<'static, Result<String, Status>>,
+        kind: Kind,
+    ) -> RecvStream<String> {
+        let body = BoxBody::new(StreamBody::new(encode(stream, None)));
+        RecvStream::new(body, kind, None, DecodeLimits::default())
+    }
+
+    fn messages(messages: &[&'static str]) -> BoxStream<'static, Result<String, Status>> {
+        Box::pin(futures::stream::iter(
+            messages
+                .iter()
+                .map(|m| Ok(m.to_string()))
+                .collect::<Vec<_>>(),
+        ))
+    }
+
+    #[tokio::test]
+    async fn bidi_echo_metrics() {
+        let client_metrics = MessageMetrics::new();
+        let server_metrics = MessageMetrics::new();
+        let client = inspector(&cx(client_metrics.clone()));
+        let server = inspector(&cx(server_metrics.clone()));
+
+        let requests = inspect_send(messages(&["hi", "yo", "hello"]), &client);
+        let requests = inspect_recv(transfer(requests, Kind::Request), &server);
+        // the server echoes each request
+        let responses = inspect_send(Box::pin(requests), &server);
+        let responses = inspect_recv(
+            transfer(responses, Kind::Response(http::StatusCode::OK)),
+            &client,
+        );
+
+        let responses: Vec<_> = responses.try_collect().await.unwrap();
+        assert_eq!(responses, ["hi", "yo", "hello"]);
+        for metrics in [&client_metrics, &server_metrics] {
+            assert_eq!(metrics.messages_sent(), 3);
+            assert_eq!(metrics.messages_received(), 3);
+            // the tag and length of each string
+            assert_eq!(metrics.bytes_sent(), 9 + 3 * 2);
+            assert_eq!(metrics.bytes_received(), 9 + 3 * 2);
+        }
+    }
+
+    /// Rejects the messages longer than 2 bytes.
+    struct Short;
+
+    impl MessageInspector for Short {
+        fn on_send(&mut self, message: &dyn Message) -> Result<(), Status> {
+            if message.encoded_len() > 4 {
+                return Err(Status::invalid_argument("the message is too long"));
+            }
+            Ok(())
+        }
+
+        fn on_recv(&mut self, message: &dyn Message) -> Result<(), Status> {
+            self.on_send(message)
+        }
+    }
+
+    #[tokio::test]
+    async fn abort() {
+        let cx = cx(|_: &str| Some(Short));
+        let requests = inspect_send(messages(&["hi", "hello", "yo"]), &inspector(&cx));
+        let results: Vec<_> = requests.collect().await;
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].as_ref().unwrap(), "hi");
+        assert_eq!(
+            results[1].as_ref().unwrap_err().code(),
+            Code::InvalidArgument
+        );
+
+        let mut requests = inspect_recv(
+            transfer(messages(&["hi", "hello", "yo"]), Kind::Request),
+            &inspector(&cx),
+        );
+        assert_eq!(requests.next().await.unwrap().unwrap(), "hi");
+        assert_eq!(
+            requests.next().await.unwrap().unwrap_err().code(),
+            Code::InvalidArgument
+        );
+        assert!(requests.next().await.is_none());
+
+        let err = inspect_request(Request::new("hello".to_string()), &inspector(&cx)).unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+    }
+
+    #[test]
+    fn not_installed() {
+        let cx = ServerContext::default();
+        assert!(inspector(&cx).is_none());
+        // skipped for the methods by the closure
+        assert!(inspector(&self::cx(|_: &str| None::<Short>)).is_none());
+
+        let stream = messages(&["hi"]);
+        let ptr = &*stream as *const _ as *const ();
+        let stream = inspect_send(stream, &None);
+        assert_eq!(&*stream as *const _ as *const (), ptr);
+    }
+}
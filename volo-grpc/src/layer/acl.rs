@@ -0,0 +1,73 @@
+//! The gRPC rejection of [`volo::acl::AclLayer`].
+//!
+//! A request of an unauthenticated caller fails with [`Code::Unauthenticated`], and a request of
+//! a caller not allowed by the [`Acl`](volo::acl::Acl) fails with [`Code::PermissionDenied`].
+
+use volo::acl::{AclDenied, AclRejection};
+
+use crate::{context::ServerContext, Code, Status};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GrpcAclRejection;
+
+impl AclRejection<ServerContext, Status> for GrpcAclRejection {
+    fn reject(&self, _cx: &mut ServerContext, denied: AclDenied) -> Status {
+        let code = match denied {
+            AclDenied::Unauthenticated => Code::Unauthenticated,
+            AclDenied::Forbidden { .. } => Code::PermissionDenied,
+        };
+        Status::new(code, denied.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use volo::{
+        acl::{Acl, AclLayer},
+        context::identity::{PeerIdentityExt, TlsIdentity},
+        FastStr, Layer, Service,
+    };
+
+    use super::*;
+
+    #[derive(Clone)]
+    struct Echo;
+
+    impl Service<ServerContext, ()> for Echo {
+        type Response = ();
+        type Error = Status;
+
+        async fn call<'s, 'cx>(
+            &'s self,
+            _cx: &'cx mut ServerContext,
+            _req: (),
+        ) -> Result<Self::Response, Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn reject() {
+        let service = AclLayer::new(Acl::new().allow("echo"), GrpcAclRejection).layer(Echo);
+
+        let mut cx = ServerContext::default();
+        cx.peer_identity_mut().set_declared("echo".into());
+        let status = service.call(&mut cx, ()).await.unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated);
+
+        let mut cx = ServerContext::default();
+        cx.peer_identity_mut().set_tls(TlsIdentity {
+            spiffe_id: Some(FastStr::from_static_str("spiffe://example.org/sa/admin")),
+            ..Default::default()
+        });
+        let status = service.call(&mut cx, ()).await.unwrap_err();
+        assert_eq!(status.code(), Code::PermissionDenied);
+
+        let mut cx = ServerContext::default();
+        cx.peer_identity_mut().set_tls(TlsIdentity {
+            spiffe_id: Some(FastStr::from_static_str("spiffe://example.org/sa/echo")),
+            ..Default::default()
+        });
+        assert!(service.call(&mut cx, ()).await.is_ok());
+    }
+}
@@ -0,0 +1,37 @@
+//! The gRPC rejection of [`volo::circuit_breaker::CircuitBreakerLayer`].
+//!
+//! A rejected call fails with [`Code::Unavailable`] without being sent. In the
+//! [`Scope::Endpoint`](volo::circuit_breaker::Scope::Endpoint), it's retried on the other
+//! endpoints by the load balancing.
+
+use volo::circuit_breaker::{CircuitBreakerRejection, CircuitOpen};
+
+use crate::{context::ClientContext, Code, Status};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GrpcCircuitBreakerRejection;
+
+impl CircuitBreakerRejection<ClientContext, Status> for GrpcCircuitBreakerRejection {
+    fn reject(&self, _cx: &mut ClientContext, open: CircuitOpen) -> Status {
+        Status::new(Code::Unavailable, open.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn reject() {
+        let status = GrpcCircuitBreakerRejection.reject(
+            &mut ClientContext::default(),
+            CircuitOpen {
+                retry_after: Duration::from_millis(20),
+            },
+        );
+        assert_eq!(status.code(), Code::Unavailable);
+        assert_eq!(status.message(), "circuit open, retry after 20ms");
+    }
+}
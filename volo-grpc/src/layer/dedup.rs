@@ -0,0 +1,212 @@
+//! The gRPC protocol of [`volo::dedup::DedupLayer`], which should be added to the [`Server`] by
+//! [`Server::layer_front`].
+//!
+//! The idempotency key is read from a metadata. The body of a response is buffered until it ends
+//! or exceeds the limit, and the response is cached only if the whole body fits in the limit and
+//! its trailers carry [`Code::Ok`]. A duplicate request whose original one fails or is not
+//! cacheable fails with [`Code::Unavailable`] and a message starting with [`RETRY_LATER`].
+//!
+//! [`Server`]: crate::server::Server
+//! [`Server::layer_front`]: crate::server::Server::layer_front
+
+use bytes::Bytes;
+use futures::StreamExt;
+use http::HeaderMap;
+use http_body::Frame;
+use http_body_util::{BodyExt, BodyStream};
+use volo::{dedup::DedupProtocol, FastStr};
+
+use crate::{
+    body::Body, context::ServerContext, metadata::MetadataMap, Code, Request, Response, Status,
+};
+
+/// The prefix of the message of the status of a duplicate request to be retried later.
+pub const RETRY_LATER: &str = "[volo] duplicate request, retry later";
+
+/// The metadata key of the idempotency key by default.
+pub const DEFAULT_KEY_HEADER: &str = "x-idempotency-key";
+
+#[derive(Debug, Clone)]
+pub struct GrpcDedup {
+    header: FastStr,
+}
+
+impl Default for GrpcDedup {
+    fn default() -> Self {
+        Self::new(DEFAULT_KEY_HEADER)
+    }
+}
+
+impl GrpcDedup {
+    /// Reads the idempotency key from the metadata `header`.
+    pub fn new(header: impl Into<FastStr>) -> Self {
+        Self {
+            header: header.into(),
+        }
+    }
+}
+
+/// The response kept in the cache.
+#[derive(Debug, Clone)]
+pub struct CachedResponse {
+    metadata: MetadataMap,
+    data: Vec<Bytes>,
+    trailers: Option<HeaderMap>,
+}
+
+impl CachedResponse {
+    fn into_body(self) -> Body {
+        let frames = self
+            .data
+            .into_iter()
+            .map(Frame::data)
+            .chain(self.trailers.map(Frame::trailers))
+            .map(Ok);
+        Body::new(Box::pin(futures::stream::iter(frames))).without_trailers()
+    }
+}
+
+impl<T> DedupProtocol<ServerContext, Request<T>, Response<Body>, Status> for GrpcDedup {
+    type Cached = CachedResponse;
+
+    fn key(&self, _cx: &ServerContext, req: &Request<T>) -> Option<FastStr> {
+        req.metadata()
+            .get(self.header.as_str())
+            .and_then(|key| key.to_str().ok())
+            .filter(|key| !key.is_empty())
+            .map(FastStr::new)
+    }
+
+    async fn cache(
+        &self,
+        resp: Response<Body>,
+        max_size: usize,
+    ) -> (Response<Body>, Option<CachedResponse>) {
+        let (metadata, extensions, mut body) = resp.into_parts();
+        let mut cached = CachedResponse {
+            metadata: metadata.clone(),
+            data: Vec::new(),
+            trailers: None,
+        };
+        let mut size = 0;
+        let mut error = None;
+        let mut ended = false;
+        while size <= max_size {
+            match body.frame().await {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(data) => {
+                        size += data.len();
+                        cached.data.push(data);
+                    }
+                    Err(frame) => {
+                        if let Ok(trailers) = frame.into_trailers() {
+                            cached.trailers = Some(trailers);
+                        }
+                    }
+                },
+                Some(Err(status)) => {
+                    error = Some(status);
+                    break;
+                }
+                None => {
+                    ended = true;
+                    break;
+                }
+            }
+        }
+
+        let ok = ended
+            && size <= max_size
+            && cached
+                .trailers
+                .as_ref()
+                .and_then(|trailers| trailers.get("grpc-status"))
+                .is_some_and(|status| status.as_bytes() == b"0");
+
+        // sends the buffered frames, and then the rest of the body
+        let buffered = cached.clone().into_body();
+        let rest = if ended {
+            None
+        } else {
+            Some(BodyStream::new(body))
+        };
+        let stream = BodyStream::new(buffered)
+            .chain(futures::stream::iter(error.map(Err)))
+            .chain(futures::stream::iter(rest).flatten());
+        let resp = Response::from_parts(
+            metadata,
+            extensions,
+            Body::new(Box::pin(stream)).without_trailers(),
+        );
+        (resp, ok.then_some(cached))
+    }
+
+    fn restore(&self, _cx: &mut ServerContext, cached: &CachedResponse) -> Response<Body> {
+        let metadata = cached.metadata.clone();
+        Response::from_parts(metadata, Default::default(), cached.clone().into_body())
+    }
+
+    fn retry_later(&self, _cx: &mut ServerContext) -> Status {
+        Status::new(Code::Unavailable, RETRY_LATER)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn protocol(
+    ) -> impl DedupProtocol<ServerContext, Request<()>, Response<Body>, Status, Cached = CachedResponse>
+    {
+        GrpcDedup::default()
+    }
+
+    fn response(chunks: &'static [&'static str]) -> Response<Body> {
+        let frames = chunks
+            .iter()
+            .map(|chunk| Ok(Frame::data(Bytes::from_static(chunk.as_bytes()))));
+        let mut resp = Response::new(Body::new(Box::pin(futures::stream::iter(frames))));
+        resp.metadata_mut().insert("x-order", "1".parse().unwrap());
+        resp
+    }
+
+    async fn collect(resp: Response<Body>) -> (Bytes, Option<HeaderMap>) {
+        let collected = resp.into_inner().collect().await.unwrap();
+        let trailers = collected.trailers().cloned();
+        (collected.to_bytes(), trailers)
+    }
+
+    #[tokio::test]
+    async fn key_and_cache() {
+        let protocol = protocol();
+        let mut cx = ServerContext::default();
+
+        let mut req = Request::new(());
+        assert_eq!(protocol.key(&cx, &req), None);
+        req.metadata_mut()
+            .insert(DEFAULT_KEY_HEADER, "order-1".parse().unwrap());
+        assert_eq!(protocol.key(&cx, &req), Some("order-1".into()));
+
+        let (resp, cached) = protocol.cache(response(&["hello", "world"]), 10).await;
+        let (body, trailers) = collect(resp).await;
+        assert_eq!(body, "helloworld");
+        assert_eq!(trailers.unwrap().get("grpc-status").unwrap(), "0");
+
+        let restored = protocol.restore(&mut cx, &cached.unwrap());
+        assert_eq!(restored.metadata().get("x-order").unwrap(), "1");
+        let (body, trailers) = collect(restored).await;
+        assert_eq!(body, "helloworld");
+        assert_eq!(trailers.unwrap().get("grpc-status").unwrap(), "0");
+
+        // too large, but still sent as a whole
+        let (resp, cached) = protocol.cache(response(&["hello", "world"]), 8).await;
+        assert!(cached.is_none());
+        let (body, trailers) = collect(resp).await;
+        assert_eq!(body, "helloworld");
+        assert_eq!(trailers.unwrap().get("grpc-status").unwrap(), "0");
+
+        let status = protocol.retry_later(&mut cx);
+        assert_eq!(status.code(), Code::Unavailable);
+        assert!(status.message().starts_with(RETRY_LATER));
+    }
+}
@@ -55,7 +55,7 @@ impl<S> Layer<S> for GrpcTimeoutLayer {
 ///  Ok(Some(duration)) => if parse success.
 ///  Ok(None)           => if no success field.
 ///  Err(&HeaderValue)  => if parse timeout failed or wrong format.
-fn try_parse_client_timeout(
+pub(crate) fn try_parse_client_timeout(
     headers: &HeaderMap<HeaderValue>,
 ) -> Result<Option<Duration>, &HeaderValue> {
     const SECONDS_HOUR: u64 = 60 * 60;
@@ -86,6 +86,26 @@ fn try_parse_client_timeout(
     }
 }
 
+/// Encodes the timeout as the value of the `grpc-timeout` header, which has at most 8 digits.
+pub(crate) fn encode_timeout(timeout: Duration) -> HeaderValue {
+    const MAX_VALUE: u128 = 99_999_999;
+
+    let value = [
+        (timeout.as_nanos(), "n"),
+        (timeout.as_micros(), "u"),
+        (timeout.as_millis(), "m"),
+        (timeout.as_secs() as u128, "S"),
+        (timeout.as_secs() as u128 / 60, "M"),
+    ]
+    .into_iter()
+    .find(|(value, _)| *value <= MAX_VALUE)
+    .map_or_else(
+        || format!("{}H", (timeout.as_secs() / 3600).min(MAX_VALUE as u64)),
+        |(value, unit)| format!("{value}{unit}"),
+    );
+    HeaderValue::from_str(&value).expect("the timeout is a valid header value")
+}
+
 impl<Cx, S, ReqBody> Service<Cx, hyper::Request<ReqBody>> for GrpcTimeout<S>
 where
     Cx: Send,
@@ -219,6 +239,25 @@ mod tests {
         assert_eq!(Duration::from_nanos(82), parsed_duration);
     }
 
+    #[test]
+    fn test_encode() {
+        for timeout in [
+            Duration::from_nanos(82),
+            Duration::from_millis(1500),
+            Duration::from_secs(42),
+            Duration::from_secs(3 * 60 * 60),
+        ] {
+            let mut hm = HeaderMap::new();
+            hm.insert(GRPC_TIMEOUT_HEADER, encode_timeout(timeout));
+            assert_eq!(try_parse_client_timeout(&hm), Ok(Some(timeout)));
+        }
+        // the precision is lost only for the long timeouts
+        assert_eq!(
+            encode_timeout(Duration::from_millis(123_456_789)),
+            "123456S"
+        );
+    }
+
     #[test]
     fn test_corner_cases() {
         // error postfix
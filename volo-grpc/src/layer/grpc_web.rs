@@ -0,0 +1,359 @@
+//! Serving [gRPC-Web] requests along with the gRPC requests.
+//!
+//! The [`GrpcWebLayer`] detects the gRPC-Web requests by the `content-type` header:
+//! - `application/grpc-web` and `application/grpc-web+proto` are the binary variant, which share
+//!   the framing of gRPC.
+//! - `application/grpc-web-text` and `application/grpc-web-text+proto` are the text variant, whose
+//!   bodies are encoded by base64.
+//!
+//! The gRPC-Web requests are translated into gRPC requests before going to the inner services, so
+//! the generated services need no change. The trailers of the responses are encoded into the body
+//! as a frame flagged by `0x80`, since browsers can not read the HTTP trailers.
+//!
+//! Other requests are passed to the inner services as they are.
+//!
+//! Note that the server only speaks HTTP/2, so the browsers can only reach it through TLS, or
+//! through a proxy in front of the server.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use volo_grpc::{layer::grpc_web::GrpcWebLayer, server::Server};
+//!
+//! Server::new()
+//!     .layer_front(GrpcWebLayer::new())
+//!     .add_service(ServiceBuilder::new(GreeterServer::new(S)).build())
+//!     .run(addr)
+//!     .await
+//!     .unwrap();
+//! ```
+//!
+//! [gRPC-Web]: https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-WEB.md
+
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::{BufMut, Bytes, BytesMut};
+use futures::{ready, StreamExt};
+use http::{header::CONTENT_TYPE, HeaderMap, HeaderValue};
+use http_body::{Body as HttpBody, Frame};
+use http_body_util::BodyStream;
+use motore::{layer::Layer, Service};
+
+use crate::{
+    body::{Body, BoxBody},
+    Request, Response, Status,
+};
+
+const GRPC_CONTENT_TYPE: &str = "application/grpc";
+const GRPC_WEB_CONTENT_TYPE: &str = "application/grpc-web+proto";
+const GRPC_WEB_TEXT_CONTENT_TYPE: &str = "application/grpc-web-text+proto";
+
+/// The flag of the frame carrying the trailers.
+const TRAILERS_FLAG: u8 = 0x80;
+
+/// The variant of gRPC-Web.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Binary,
+    Text,
+}
+
+impl Encoding {
+    fn from_content_type(content_type: &HeaderValue) -> Option<Self> {
+        let content_type = content_type.to_str().ok()?;
+        let content_type = content_type
+            .split_once(';')
+            .map_or(content_type, |(ty, _)| ty)
+            .trim();
+        match content_type {
+            "application/grpc-web" | GRPC_WEB_CONTENT_TYPE => Some(Self::Binary),
+            "application/grpc-web-text" | GRPC_WEB_TEXT_CONTENT_TYPE => Some(Self::Text),
+            _ => None,
+        }
+    }
+
+    fn content_type(self) -> HeaderValue {
+        match self {
+            Self::Binary => HeaderValue::from_static(GRPC_WEB_CONTENT_TYPE),
+            Self::Text => HeaderValue::from_static(GRPC_WEB_TEXT_CONTENT_TYPE),
+        }
+    }
+
+    fn encode(self, data: Bytes) -> Bytes {
+        match self {
+            Self::Binary => data,
+            Self::Text => STANDARD.encode(data).into(),
+        }
+    }
+}
+
+/// A [`Layer`] that translates the gRPC-Web requests and responses, see the [module
+/// docs](self) for details.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GrpcWebLayer;
+
+impl GrpcWebLayer {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<S> Layer<S> for GrpcWebLayer {
+    type Service = GrpcWeb<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        GrpcWeb::new(inner)
+    }
+}
+
+/// The [`Service`] created by [`GrpcWebLayer`].
+#[derive(Debug, Clone)]
+pub struct GrpcWeb<S> {
+    inner: S,
+}
+
+impl<S> GrpcWeb<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+}
+
+impl<Cx, S> Service<Cx, Request<BoxBody>> for GrpcWeb<S>
+where
+    Cx: Send,
+    S: Service<Cx, Request<BoxBody>, Response = Response<Body>> + Send + Sync,
+    S::Error: Into<Status>,
+{
+    type Response = Response<Body>;
+    type Error = Status;
+
+    async fn call<'s, 'cx>(
+        &'s self,
+        cx: &'cx mut Cx,
+        req: Request<BoxBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let Some(encoding) = req
+            .metadata()
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(Encoding::from_content_type)
+        else {
+            return self.inner.call(cx, req).await.map_err(Into::into);
+        };
+
+        let (mut metadata, extensions, body) = req.into_parts();
+        metadata
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static(GRPC_CONTENT_TYPE));
+        let body = match encoding {
+            Encoding::Binary => body,
+            Encoding::Text => BoxBody::new(Base64Decode::new(body)),
+        };
+
+        let resp = match self
+            .inner
+            .call(cx, Request::from_parts(metadata, extensions, body))
+            .await
+        {
+            Ok(resp) => resp,
+            Err(err) => {
+                // trailers-only response, whose trailers are sent as headers
+                let status: Status = err.into();
+                let mut resp = Response::from_http(status.to_http());
+                resp.metadata_mut()
+                    .headers_mut()
+                    .insert(CONTENT_TYPE, encoding.content_type());
+                return Ok(resp);
+            }
+        };
+
+        let (mut metadata, extensions, body) = resp.into_parts();
+        metadata
+            .headers_mut()
+            .insert(CONTENT_TYPE, encoding.content_type());
+        Ok(Response::from_parts(
+            metadata,
+            extensions,
+            encode_body(body, encoding),
+        ))
+    }
+}
+
+/// Moves the trailers of the response body into a frame of the body.
+fn encode_body(body: Body, encoding: Encoding) -> Body {
+    let stream = BodyStream::new(body).map(move |frame| -> Result<_, Status> {
+        let data = frame?
+            .into_data()
+            .or_else(|frame| frame.into_trailers().map(|t| encode_trailers(&t)))
+            .unwrap_or_default();
+        Ok(Frame::data(encoding.encode(data)))
+    });
+    Body::new(Box::pin(stream)).without_trailers()
+}
+
+/// Encodes the trailers as a frame flagged by [`TRAILERS_FLAG`], with the trailers formatted
+/// like the HTTP/1 headers.
+fn encode_trailers(trailers: &HeaderMap) -> Bytes {
+    let mut block = Vec::new();
+    for (key, value) in trailers {
+        block.extend_from_slice(key.as_str().as_bytes());
+        block.extend_from_slice(b": ");
+        block.extend_from_slice(value.as_bytes());
+        block.extend_from_slice(b"\r\n");
+    }
+
+    let mut buf = BytesMut::with_capacity(5 + block.len());
+    buf.put_u8(TRAILERS_FLAG);
+    buf.put_u32(block.len() as u32);
+    buf.put_slice(&block);
+    buf.freeze()
+}
+
+/// The request body of the text variant, which decodes the base64 body into the gRPC frames.
+struct Base64Decode {
+    inner: BoxBody,
+    buf: BytesMut,
+}
+
+impl Base64Decode {
+    fn new(inner: BoxBody) -> Self {
+        Self {
+            inner,
+            buf: BytesMut::new(),
+        }
+    }
+}
+
+impl HttpBody for Base64Decode {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+        loop {
+            match ready!(Pin::new(&mut this.inner).poll_frame(cx)) {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(data) => {
+                        this.buf.extend_from_slice(&data);
+                        let decoded = decode_base64(&mut this.buf)?;
+                        if !decoded.is_empty() {
+                            return Poll::Ready(Some(Ok(Frame::data(decoded))));
+                        }
+                    }
+                    Err(frame) => return Poll::Ready(Some(Ok(frame))),
+                },
+                Some(Err(status)) => return Poll::Ready(Some(Err(status))),
+                None if this.buf.is_empty() => return Poll::Ready(None),
+                None => {
+                    this.buf.clear();
+                    return Poll::Ready(Some(Err(Status::invalid_argument(
+                        "invalid base64 body: unexpected end",
+                    ))));
+                }
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.buf.is_empty() && self.inner.is_end_stream()
+    }
+}
+
+/// Decodes the complete 4-byte groups in the buffer, and leaves the rest for the next data.
+///
+/// Each message may be encoded and padded separately, so the groups ending with padding are
+/// decoded one by one.
+fn decode_base64(buf: &mut BytesMut) -> Result<Bytes, Status> {
+    let len = buf.len() / 4 * 4;
+    let groups = buf.split_to(len);
+
+    let mut decoded = Vec::with_capacity(len / 4 * 3);
+    let mut start = 0;
+    for end in (4..=len).step_by(4) {
+        if end == len || groups[end - 1] == b'=' {
+            STANDARD
+                .decode_vec(&groups[start..end], &mut decoded)
+                .map_err(|err| Status::invalid_argument(format!("invalid base64 body: {err}")))?;
+            start = end;
+        }
+    }
+    Ok(decoded.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use http_body_util::{BodyExt, StreamBody};
+
+    use super::*;
+
+    fn body(chunks: &[&'static str]) -> BoxBody {
+        let frames: Vec<Result<_, Status>> = chunks
+            .iter()
+            .map(|chunk| Ok(Frame::data(Bytes::from_static(chunk.as_bytes()))))
+            .collect();
+        BoxBody::new(StreamBody::new(futures::stream::iter(frames)))
+    }
+
+    fn collect<B>(body: B) -> Result<Bytes, B::Error>
+    where
+        B: HttpBody,
+    {
+        futures::executor::block_on(body.collect()).map(|c| c.to_bytes())
+    }
+
+    #[test]
+    fn content_type() {
+        let encoding = |s| Encoding::from_content_type(&HeaderValue::from_static(s));
+        assert_eq!(encoding("application/grpc-web"), Some(Encoding::Binary));
+        assert_eq!(
+            encoding("application/grpc-web+proto"),
+            Some(Encoding::Binary)
+        );
+        assert_eq!(
+            encoding("application/grpc-web-text; charset=utf-8"),
+            Some(Encoding::Text)
+        );
+        assert_eq!(
+            encoding("application/grpc-web-text+proto"),
+            Some(Encoding::Text)
+        );
+        assert_eq!(encoding("application/grpc"), None);
+    }
+
+    #[test]
+    fn decode_text_body() {
+        // "hello" and "world" are encoded separately, and split at any position
+        let decoded = collect(Base64Decode::new(body(&["aGV", "sbG8=d29", "ybGQ="]))).unwrap();
+        assert_eq!(decoded, "helloworld");
+
+        assert!(collect(Base64Decode::new(body(&["aGVsbG8"]))).is_err());
+        assert!(collect(Base64Decode::new(body(&["a!==", "aGVs"]))).is_err());
+    }
+
+    #[test]
+    fn encode_trailers_frame() {
+        let data = Bytes::from_static(b"\x00\x00\x00\x00\x01a");
+        let message = data.clone();
+        let body = Body::new(Box::pin(futures::stream::once(async move {
+            Ok(Frame::data(message))
+        })));
+
+        let encoded = collect(encode_body(body, Encoding::Binary)).unwrap();
+        let mut expected = data.to_vec();
+        expected.extend_from_slice(b"\x80\x00\x00\x00\x10grpc-status: 0\r\n");
+        assert_eq!(encoded, expected);
+
+        let body = Body::new(Box::pin(futures::stream::once(async move {
+            Ok(Frame::data(data))
+        })));
+        let encoded = collect(encode_body(body, Encoding::Text)).unwrap();
+        assert_eq!(encoded, "AAAAAAFhgAAAABBncnBjLXN0YXR1czogMA0K");
+    }
+}
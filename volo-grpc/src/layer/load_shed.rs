@@ -0,0 +1,16 @@
+//! The gRPC rejection of [`volo::load_shed::LoadShedLayer`].
+//!
+//! A rejected request fails with [`Code::ResourceExhausted`] before its body is decoded.
+
+use volo::load_shed::{LoadShedRejection, Overloaded};
+
+use crate::{context::ServerContext, Code, Status};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GrpcLoadShedRejection;
+
+impl LoadShedRejection<ServerContext, Status> for GrpcLoadShedRejection {
+    fn reject(&self, _cx: &mut ServerContext, overloaded: Overloaded) -> Status {
+        Status::new(Code::ResourceExhausted, overloaded.to_string())
+    }
+}
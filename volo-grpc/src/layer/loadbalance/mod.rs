@@ -5,13 +5,13 @@ use tracing::warn;
 use volo::{
     context::Context,
     discovery::Discover,
-    loadbalance::{error::LoadBalanceError, LoadBalance, MkLbLayer},
+    loadbalance::{error::LoadBalanceError, outlier::OutlierFailure, LoadBalance, MkLbLayer},
     Layer,
 };
 
 use crate::Request;
 
-#[derive(Clone, Default, Copy)]
+#[derive(Clone, Default)]
 pub struct LoadBalanceLayer<D, LB> {
     discover: D,
     load_balance: LB,
@@ -80,7 +80,7 @@ where
     LB: LoadBalance<D>,
     S: Service<Cx, Request<T>> + 'static + Send + Sync,
     LoadBalanceError: Into<S::Error>,
-    S::Error: Debug,
+    S::Error: Debug + OutlierFailure,
     T: Send + 'static,
 {
     type Response = S::Response;
@@ -108,7 +108,14 @@ where
         if let Some(addr) = picker.next() {
             cx.rpc_info_mut().callee_mut().address = Some(addr.clone());
 
-            return match self.service.call(cx, req).await {
+            let result = self.service.call(cx, req).await;
+            self.discover.report(
+                &addr,
+                result
+                    .as_ref()
+                    .map_or_else(|err| !err.is_outlier_failure(), |_| true),
+            );
+            return match result {
                 Ok(resp) => Ok(resp),
                 Err(err) => {
                     warn!("[VOLO] call endpoint: {:?} error: {:?}", addr, err);
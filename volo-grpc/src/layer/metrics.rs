@@ -0,0 +1,209 @@
+//! The gRPC protocol of [`volo::metrics::rpc::RpcMetricsLayer`].
+//!
+//! The `status` label is the name of the gRPC code, e.g. `OK` or `NOT_FOUND`. The duration of the
+//! servers is measured from the arrival of the headers of the request.
+//!
+//! The sizes are the encoded lengths of the messages before the compression, observed for each
+//! message of the streams. They're only observed at the server side, as the messages of the
+//! clients are inspected by the generated code before the layers.
+//!
+//! The server layer should be added by [`Server::layer_front`], and the client layer by
+//! [`ClientBuilder::layer_outer`].
+//!
+//! ```rust,ignore
+//! use volo::metrics::{
+//!     rpc::{RpcMetrics, RpcMetricsLayer},
+//!     Registry,
+//! };
+//! use volo_grpc::layer::metrics::GrpcMetrics;
+//!
+//! let registry = Registry::new();
+//! Server::new()
+//!     .layer_front(
+//!         RpcMetricsLayer::new(RpcMetrics::server(&registry), GrpcMetrics)
+//!             .methods(volo_gen::registry().methods()),
+//!     )
+//!     .add_service(ServiceBuilder::new(GreeterServer::new(S)).build())
+//!     .run(addr)
+//!     .await
+//!     .unwrap();
+//! ```
+//!
+//! [`Server::layer_front`]: crate::server::Server::layer_front
+//! [`ClientBuilder::layer_outer`]: crate::client::ClientBuilder::layer_outer
+
+use std::time::SystemTime;
+
+use pilota::prost::Message;
+use volo::{
+    context::Role,
+    metrics::rpc::{MessageSizes, RpcMetricsProtocol},
+    FastStr,
+};
+
+use crate::{
+    context::{ClientContext, ServerContext},
+    inspect::MessageInspector,
+    Code, Response, Status,
+};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GrpcMetrics;
+
+fn status<U>(result: &Result<Response<U>, Status>) -> FastStr {
+    let code = match result {
+        Ok(_) => Code::Ok,
+        Err(status) => status.code(),
+    };
+    FastStr::from_static_str(code_name(code))
+}
+
+/// The name of the code in the gRPC spec.
+fn code_name(code: Code) -> &'static str {
+    match code {
+        Code::Ok => "OK",
+        Code::Cancelled => "CANCELLED",
+        Code::Unknown => "UNKNOWN",
+        Code::InvalidArgument => "INVALID_ARGUMENT",
+        Code::DeadlineExceeded => "DEADLINE_EXCEEDED",
+        Code::NotFound => "NOT_FOUND",
+        Code::AlreadyExists => "ALREADY_EXISTS",
+        Code::PermissionDenied => "PERMISSION_DENIED",
+        Code::ResourceExhausted => "RESOURCE_EXHAUSTED",
+        Code::FailedPrecondition => "FAILED_PRECONDITION",
+        Code::Aborted => "ABORTED",
+        Code::OutOfRange => "OUT_OF_RANGE",
+        Code::Unimplemented => "UNIMPLEMENTED",
+        Code::Internal => "INTERNAL",
+        Code::Unavailable => "UNAVAILABLE",
+        Code::DataLoss => "DATA_LOSS",
+        Code::Unauthenticated => "UNAUTHENTICATED",
+    }
+}
+
+impl<U> RpcMetricsProtocol<ServerContext, Response<U>, Status> for GrpcMetrics {
+    fn status(&self, _cx: &ServerContext, result: &Result<Response<U>, Status>) -> FastStr {
+        status(result)
+    }
+
+    fn arrive_at(&self, cx: &ServerContext) -> Option<SystemTime> {
+        cx.stats.arrive_at()
+    }
+}
+
+impl<U> RpcMetricsProtocol<ClientContext, Response<U>, Status> for GrpcMetrics {
+    fn status(&self, _cx: &ClientContext, result: &Result<Response<U>, Status>) -> FastStr {
+        status(result)
+    }
+}
+
+/// Observes the sizes of the messages of a call, before the inspector installed if any.
+pub(crate) struct SizeInspector {
+    pub(crate) sizes: MessageSizes,
+    pub(crate) role: Role,
+    pub(crate) inner: Option<Box<dyn MessageInspector>>,
+}
+
+impl MessageInspector for SizeInspector {
+    fn on_send(&mut self, message: &dyn Message) -> Result<(), Status> {
+        self.sizes.observe_sent(self.role, message.encoded_len());
+        match &mut self.inner {
+            Some(inner) => inner.on_send(message),
+            None => Ok(()),
+        }
+    }
+
+    fn on_recv(&mut self, message: &dyn Message) -> Result<(), Status> {
+        self.sizes
+            .observe_received(self.role, message.encoded_len());
+        match &mut self.inner {
+            Some(inner) => inner.on_recv(message),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use motore::{layer::Layer, service::Service};
+    use volo::{
+        context::Context,
+        descriptor::MethodDescriptor,
+        metrics::{
+            rpc::{RpcMetrics, RpcMetricsLayer},
+            Registry,
+        },
+    };
+
+    use super::*;
+    use crate::{inspect::inspector, Request};
+
+    /// Receives the message of the request, and fails with the code in it.
+    struct Handler;
+
+    impl Service<ServerContext, Request<(Code, String)>> for Handler {
+        type Response = Response<String>;
+        type Error = Status;
+
+        async fn call(
+            &self,
+            cx: &mut ServerContext,
+            req: Request<(Code, String)>,
+        ) -> Result<Self::Response, Self::Error> {
+            let (code, message) = req.into_inner();
+            let inspector = inspector(cx).unwrap();
+            inspector.on_recv(&message)?;
+            match code {
+                Code::Ok => {
+                    inspector.on_send(&message)?;
+                    Ok(Response::new(message))
+                }
+                code => Err(Status::new(code, "failed")),
+            }
+        }
+    }
+
+    static METHODS: &[MethodDescriptor] = &[MethodDescriptor {
+        service: "helloworld.Greeter",
+        name: "SayHello",
+        path: "/helloworld.Greeter/SayHello",
+        client_streaming: false,
+        server_streaming: false,
+        request_type: "HelloRequest",
+        response_type: "HelloReply",
+        annotations: &[],
+        request_schema: None,
+    }];
+
+    #[tokio::test]
+    async fn server() {
+        let registry = Registry::new();
+        let server = RpcMetricsLayer::new(RpcMetrics::server(&registry), GrpcMetrics)
+            .methods(METHODS)
+            .layer(Handler);
+
+        for code in [Code::Ok, Code::Ok, Code::NotFound] {
+            let mut cx = ServerContext::default();
+            cx.rpc_info_mut()
+                .set_method("/helloworld.Greeter/SayHello".into());
+            cx.stats.record_arrive_at();
+            // the encoded length is 6 with the tag and the length
+            let _ = server
+                .call(&mut cx, Request::new((code, "volo".to_owned())))
+                .await;
+        }
+
+        let text = registry.render();
+        let labels = r#"service="helloworld.Greeter",method="/helloworld.Greeter/SayHello",caller="",callee="""#;
+        for line in [
+            format!("rpc_server_requests_total{{{labels},status=\"OK\"}} 2\n"),
+            format!("rpc_server_requests_total{{{labels},status=\"NOT_FOUND\"}} 1\n"),
+            format!("rpc_server_errors_total{{{labels},status=\"NOT_FOUND\"}} 1\n"),
+            format!("rpc_server_request_size_bytes_sum{{{labels}}} 18\n"),
+            format!("rpc_server_response_size_bytes_sum{{{labels}}} 12\n"),
+            format!("rpc_server_duration_seconds_count{{{labels},status=\"OK\"}} 2\n"),
+        ] {
+            assert!(text.contains(&line), "missing {line} in {text}");
+        }
+    }
+}
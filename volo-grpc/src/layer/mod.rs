@@ -1,4 +1,21 @@
+pub mod acl;
+pub mod circuit_breaker;
 pub mod cross_origin;
+pub mod dedup;
 pub mod grpc_timeout;
+pub mod grpc_web;
+pub mod load_shed;
 pub mod loadbalance;
+#[cfg(feature = "metrics")]
+#[cfg_attr(docsrs, doc(cfg(feature = "metrics")))]
+pub mod metrics;
+#[cfg(feature = "otel")]
+#[cfg_attr(docsrs, doc(cfg(feature = "otel")))]
+pub mod otel;
+pub mod preset;
+pub mod rate_limit;
+pub mod retry;
+pub mod script;
 pub mod user_agent;
+
+pub use self::preset::production_defaults;
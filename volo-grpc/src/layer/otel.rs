@@ -0,0 +1,214 @@
+//! The gRPC protocol of [`volo::otel::OtelServerLayer`] and [`volo::otel::OtelClientLayer`], which
+//! propagates the trace context in the metadata.
+//!
+//! The server layer should be added by [`Server::layer_front`], and the client layer by
+//! [`ClientBuilder::layer_outer`].
+//!
+//! [`Server::layer_front`]: crate::server::Server::layer_front
+//! [`ClientBuilder::layer_outer`]: crate::client::ClientBuilder::layer_outer
+
+use tracing::Span;
+use volo::{
+    otel::{record_error, OtelProtocol},
+    FastStr,
+};
+
+use crate::{Code, Request, Response, Status};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GrpcOtel;
+
+impl<Cx, T, U> OtelProtocol<Cx, Request<T>, Response<U>, Status> for GrpcOtel {
+    fn system(&self) -> &'static str {
+        "grpc"
+    }
+
+    fn extract(&self, _cx: &Cx, req: &Request<T>, key: &str) -> Option<FastStr> {
+        req.metadata()
+            .get(key)
+            .and_then(|value| value.to_str().ok())
+            .map(FastStr::new)
+    }
+
+    fn inject(&self, _cx: &mut Cx, req: &mut Request<T>, key: &'static str, value: FastStr) {
+        if let Ok(value) = value.parse() {
+            req.metadata_mut().insert(key, value);
+        }
+    }
+
+    fn record(&self, span: &Span, result: &Result<Response<U>, Status>) {
+        let code = match result {
+            Ok(_) => Code::Ok,
+            Err(status) => status.code(),
+        };
+        span.record("rpc.grpc.status_code", i32::from(code));
+        if let Err(status) = result {
+            record_error(span, i32::from(code), status.message());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use motore::{layer::Layer, service::Service};
+    use opentelemetry::trace::{SpanKind, Status as SpanStatus, TracerProvider as _};
+    use opentelemetry_sdk::{
+        export::trace::SpanData, testing::trace::InMemorySpanExporter, trace::TracerProvider,
+    };
+    use tracing_subscriber::layer::SubscriberExt;
+    use volo::{
+        context::Context,
+        otel::{OtelClientLayer, OtelServerLayer, TRACEPARENT},
+    };
+
+    use super::*;
+    use crate::context::{ClientContext, ServerContext};
+
+    /// Echoes the metadata, or fails with the code in the message.
+    struct Handler;
+
+    impl Service<ServerContext, Request<Code>> for Handler {
+        type Response = Response<()>;
+        type Error = Status;
+
+        async fn call(
+            &self,
+            _cx: &mut ServerContext,
+            req: Request<Code>,
+        ) -> Result<Self::Response, Self::Error> {
+            match req.into_inner() {
+                Code::Ok => Ok(Response::new(())),
+                code => Err(Status::new(code, "failed")),
+            }
+        }
+    }
+
+    /// Sends the request of the client to the server in the same process, and keeps the
+    /// `traceparent` seen by the server.
+    struct Hop<S> {
+        server: S,
+        traceparent: Arc<Mutex<Option<String>>>,
+    }
+
+    impl<S> Service<ClientContext, Request<Code>> for Hop<S>
+    where
+        S: Service<ServerContext, Request<Code>, Response = Response<()>, Error = Status>
+            + Send
+            + Sync,
+    {
+        type Response = Response<()>;
+        type Error = Status;
+
+        async fn call(
+            &self,
+            cx: &mut ClientContext,
+            req: Request<Code>,
+        ) -> Result<Self::Response, Self::Error> {
+            *self.traceparent.lock().unwrap() = req
+                .metadata()
+                .get(TRACEPARENT)
+                .map(|value| value.to_str().unwrap().to_owned());
+            let mut server_cx = ServerContext::default();
+            server_cx
+                .rpc_info_mut()
+                .set_method(cx.rpc_info().method().clone());
+            self.server.call(&mut server_cx, req).await
+        }
+    }
+
+    fn client(
+        traceparent: Arc<Mutex<Option<String>>>,
+    ) -> impl Service<ClientContext, Request<Code>, Response = Response<()>, Error = Status> {
+        let server = OtelServerLayer::new(GrpcOtel).layer(Handler);
+        OtelClientLayer::new(GrpcOtel).layer(Hop {
+            server,
+            traceparent,
+        })
+    }
+
+    fn client_cx() -> ClientContext {
+        let mut cx = ClientContext::default();
+        cx.rpc_info_mut()
+            .set_method("/helloworld.Greeter/SayHello".into());
+        cx
+    }
+
+    fn find(spans: &[SpanData], kind: SpanKind) -> &SpanData {
+        spans.iter().find(|span| span.span_kind == kind).unwrap()
+    }
+
+    #[tokio::test]
+    async fn propagate_across_hop() {
+        let exporter = InMemorySpanExporter::default();
+        let provider = TracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let traceparent = Arc::new(Mutex::new(None));
+        let client = client(traceparent.clone());
+        client
+            .call(&mut client_cx(), Request::new(Code::Ok))
+            .await
+            .unwrap();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let client_span = find(&spans, SpanKind::Client);
+        let server_span = find(&spans, SpanKind::Server);
+        assert_eq!(
+            server_span.span_context.trace_id(),
+            client_span.span_context.trace_id()
+        );
+        assert_eq!(
+            server_span.parent_span_id,
+            client_span.span_context.span_id()
+        );
+        assert_eq!(
+            traceparent.lock().unwrap().as_deref(),
+            Some(
+                format!(
+                    "00-{}-{}-01",
+                    client_span.span_context.trace_id(),
+                    client_span.span_context.span_id()
+                )
+                .as_str()
+            )
+        );
+        assert_eq!(server_span.name, "helloworld.Greeter/SayHello");
+        assert!(server_span
+            .attributes
+            .iter()
+            .any(|kv| kv.key.as_str() == "rpc.system" && kv.value.as_str() == "grpc"));
+        assert_eq!(server_span.status, SpanStatus::Unset);
+
+        exporter.reset();
+        let status = client
+            .call(&mut client_cx(), Request::new(Code::Unavailable))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::Unavailable);
+        let spans = exporter.get_finished_spans().unwrap();
+        for kind in [SpanKind::Client, SpanKind::Server] {
+            let span = find(&spans, kind);
+            assert_eq!(span.status, SpanStatus::error("failed"));
+            assert!(span.attributes.iter().any(|kv| {
+                kv.key.as_str() == "rpc.grpc.status_code"
+                    && kv.value == i64::from(i32::from(Code::Unavailable)).into()
+            }));
+        }
+    }
+
+    #[tokio::test]
+    async fn no_subscriber() {
+        let traceparent = Arc::new(Mutex::new(None));
+        client(traceparent.clone())
+            .call(&mut client_cx(), Request::new(Code::Ok))
+            .await
+            .unwrap();
+        assert!(traceparent.lock().unwrap().is_none());
+    }
+}
@@ -0,0 +1,154 @@
+//! The gRPC protocol of [`volo::preset::Preset`].
+//!
+//! The size of a request is limited while its body is read, so a request message larger than the
+//! limit fails with [`Code::ResourceExhausted`] when it's decoded. A handler timed out fails with
+//! [`Code::DeadlineExceeded`], a panic with [`Code::Internal`], and a request rejected by the
+//! concurrency cap with [`Code::ResourceExhausted`].
+
+use std::{any::Any, time::Duration};
+
+use http_body_util::{BodyExt, LengthLimitError, Limited};
+use volo::{
+    catch_panic::PanicInfo,
+    load_shed::Overloaded,
+    preset::{Preset, PresetProtocol},
+};
+
+use crate::{body::BoxBody, Code, Request, Status};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GrpcPreset;
+
+/// Creates a [`Preset`] with the production defaults for the gRPC servers, see the versions of the
+/// defaults in [`volo::preset`].
+///
+/// # Example
+///
+/// ```rust,ignore
+/// Server::new()
+///     .layer_front(volo_grpc::layer::production_defaults())
+///     .add_service(ServiceBuilder::new(GreeterServer::new(S)).build())
+///     .run(addr)
+///     .await
+///     .unwrap();
+/// ```
+pub fn production_defaults() -> Preset<GrpcPreset> {
+    Preset::new(GrpcPreset)
+}
+
+impl<Cx, Resp> PresetProtocol<Cx, Request<BoxBody>, Resp, Status> for GrpcPreset {
+    fn limit_size(
+        &self,
+        _cx: &mut Cx,
+        req: Request<BoxBody>,
+        max_size: usize,
+    ) -> Result<Request<BoxBody>, Result<Resp, Status>> {
+        Ok(req.map(|body| {
+            BoxBody::new(Limited::new(body, max_size).map_err(move |err| {
+                if err.is::<LengthLimitError>() {
+                    Status::resource_exhausted(format!(
+                        "request message larger than max ({max_size} bytes)"
+                    ))
+                } else {
+                    Status::from_error(err)
+                }
+            }))
+        }))
+    }
+
+    fn timed_out(&self, _cx: &mut Cx, timeout: Duration) -> Result<Resp, Status> {
+        Err(Status::deadline_exceeded(format!(
+            "handler timed out in {timeout:?}"
+        )))
+    }
+
+    fn panicked(
+        &self,
+        _cx: &mut Cx,
+        _payload: Box<dyn Any + Send>,
+        panic_info: PanicInfo,
+    ) -> Result<Resp, Status> {
+        tracing::error!("[VOLO] panic in handler: {panic_info}");
+        Err(Status::internal("handler panicked"))
+    }
+
+    fn overloaded(&self, _cx: &mut Cx, overloaded: Overloaded) -> Result<Resp, Status> {
+        Err(Status::new(Code::ResourceExhausted, overloaded.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use http_body_util::Full;
+    use motore::{layer::Layer, service::Service};
+    use volo::preset::{DEFAULT_MAX_BODY_SIZE, DEFAULT_TIMEOUT};
+
+    use super::*;
+    use crate::{context::ServerContext, Response};
+
+    /// Reads the body, and panics or sleeps by its content.
+    struct Handler;
+
+    impl Service<ServerContext, Request<BoxBody>> for Handler {
+        type Response = Response<()>;
+        type Error = Status;
+
+        async fn call(
+            &self,
+            _cx: &mut ServerContext,
+            req: Request<BoxBody>,
+        ) -> Result<Self::Response, Self::Error> {
+            let body = req.into_inner().collect().await?.to_bytes();
+            match body.as_ref() {
+                b"panic" => panic!("boom"),
+                b"sleep" => tokio::time::sleep(DEFAULT_TIMEOUT * 2).await,
+                _ => {}
+            }
+            Ok(Response::new(()))
+        }
+    }
+
+    fn request(body: impl Into<Bytes>) -> Request<BoxBody> {
+        Request::new(BoxBody::new(
+            Full::new(body.into()).map_err(|never| match never {}),
+        ))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn production_defaults_verbatim() {
+        let service = production_defaults().layer(Handler);
+        let mut cx = ServerContext::default();
+
+        assert!(service.call(&mut cx, request("hello")).await.is_ok());
+
+        let oversized = vec![0u8; DEFAULT_MAX_BODY_SIZE + 1];
+        let status = service.call(&mut cx, request(oversized)).await.unwrap_err();
+        assert_eq!(status.code(), Code::ResourceExhausted);
+
+        let status = service.call(&mut cx, request("panic")).await.unwrap_err();
+        assert_eq!(status.code(), Code::Internal);
+
+        let status = service.call(&mut cx, request("sleep")).await.unwrap_err();
+        assert_eq!(status.code(), Code::DeadlineExceeded);
+    }
+
+    #[tokio::test]
+    async fn overridden() {
+        let service = production_defaults()
+            .max_concurrency(Some(0))
+            .layer(Handler);
+        let status = service
+            .call(&mut ServerContext::default(), request("hello"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::ResourceExhausted);
+
+        let service = production_defaults().max_body_size(None).layer(Handler);
+        let oversized = vec![0u8; DEFAULT_MAX_BODY_SIZE + 1];
+        assert!(service
+            .call(&mut ServerContext::default(), request(oversized))
+            .await
+            .is_ok());
+    }
+}
@@ -0,0 +1,47 @@
+//! The gRPC rejection of [`volo::rate_limit::RateLimitLayer`].
+//!
+//! A rejected request fails with [`Code::ResourceExhausted`] before its body is decoded, and the
+//! `retry-after-ms` metadata tells the client how long to wait. The [`GrpcRetryStrategy`] does not
+//! retry it, since it carries no `RetryInfo` detail.
+//!
+//! [`GrpcRetryStrategy`]: crate::layer::retry::GrpcRetryStrategy
+
+use volo::rate_limit::{RateLimitRejection, RateLimited};
+
+use crate::{context::ServerContext, metadata::MetadataValue, Code, Status};
+
+/// The metadata key of how long to wait before sending the next request in milliseconds.
+pub const RETRY_AFTER_MS: &str = "retry-after-ms";
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GrpcRateLimitRejection;
+
+impl RateLimitRejection<ServerContext, Status> for GrpcRateLimitRejection {
+    fn reject(&self, _cx: &mut ServerContext, rejected: RateLimited) -> Status {
+        let mut status = Status::new(Code::ResourceExhausted, rejected.to_string());
+        status.metadata_mut().insert(
+            RETRY_AFTER_MS,
+            MetadataValue::from(rejected.retry_after.as_millis() as u64),
+        );
+        status
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn reject() {
+        let status = GrpcRateLimitRejection.reject(
+            &mut ServerContext::default(),
+            RateLimited {
+                retry_after: Duration::from_millis(20),
+            },
+        );
+        assert_eq!(status.code(), Code::ResourceExhausted);
+        assert_eq!(status.metadata().get(RETRY_AFTER_MS).unwrap(), "20");
+    }
+}
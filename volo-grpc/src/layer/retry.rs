@@ -0,0 +1,461 @@
+//! The gRPC strategy of [`volo::retry::RetryLayer`].
+//!
+//! A call will be retried if it fails with [`Code::Unavailable`], which includes the failures of
+//! connecting. The server can push back by `grpc-retry-pushback-ms` in the trailers, a
+//! non-negative value delays the retry, and a negative or invalid value aborts it.
+//!
+//! The standard error details in `grpc-status-details-bin` are also honored, unless disabled by
+//! [`GrpcRetryStrategy::ignore_details`]:
+//!
+//! - `QuotaFailure`, or an `ErrorInfo` with a reason set by
+//!   [`GrpcRetryStrategy::non_retryable_reasons`], stops retrying immediately.
+//! - `RetryInfo` delays the retry by its `retry_delay`, bounded by the maximum backoff of the
+//!   policy and the deadline of the call. With it, [`Code::ResourceExhausted`] and
+//!   [`Code::Aborted`] are retryable as well.
+//!
+//! The stopping signals take precedence over the delaying ones, and the details take precedence
+//! over the pushback metadata. The signal deciding the classification is recorded as a
+//! [`RetrySignal`] in the extensions of the context, and the details of the final error can be
+//! read by [`Status::error_details`].
+//!
+//! Only unary requests can be retried, since the messages of a streaming request can not be
+//! replayed.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use volo::retry::{RetryLayer, RetryPolicy};
+//! use volo_grpc::{client::CallOpt, layer::retry::GrpcRetryStrategy};
+//!
+//! let client = volo_gen::proto_gen::hello::GreeterClientBuilder::new("hello")
+//!     .layer_outer(RetryLayer::new(GrpcRetryStrategy::default()).policy(RetryPolicy::new(2)))
+//!     .build()
+//!     .unwrap();
+//!
+//! // override the policy for a method
+//! let mut callopt = CallOpt::default();
+//! callopt.config.set_retry_policy(Some(RetryPolicy::disabled()));
+//! let resp = client.with_callopt(callopt).say_hello(req).await;
+//! ```
+
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use volo::{
+    context::Context,
+    retry::{Classification, Pushback, RetryPolicy, RetryStrategy},
+};
+
+use crate::{context::ClientContext, BoxStream, Code, Request, Status};
+
+/// The metadata key of the retry pushback in milliseconds.
+pub const GRPC_RETRY_PUSHBACK_MS: &str = "grpc-retry-pushback-ms";
+
+#[derive(Debug, Clone, Default)]
+pub struct GrpcRetryStrategy {
+    ignore_details: bool,
+    non_retryable_reasons: HashSet<String>,
+}
+
+impl GrpcRetryStrategy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Classifies the errors only by the code and the pushback metadata, ignoring the error
+    /// details.
+    pub fn ignore_details(mut self) -> Self {
+        self.ignore_details = true;
+        self
+    }
+
+    /// Sets the reasons of `ErrorInfo` that stop retrying.
+    ///
+    /// Default is empty.
+    pub fn non_retryable_reasons<I, R>(mut self, reasons: I) -> Self
+    where
+        I: IntoIterator<Item = R>,
+        R: Into<String>,
+    {
+        self.non_retryable_reasons = reasons.into_iter().map(Into::into).collect();
+        self
+    }
+
+    fn classify_status(&self, status: &Status) -> (Classification, RetrySignal) {
+        let pushback = status
+            .metadata()
+            .get(GRPC_RETRY_PUSHBACK_MS)
+            .map(|v| v.to_str().map(Pushback::parse).unwrap_or(Pushback::Abort));
+        let retryable = status.code() == Code::Unavailable;
+
+        if let Some(details) = status.error_details().filter(|_| !self.ignore_details) {
+            if details.quota_failure().is_some() {
+                return (Classification::Done, RetrySignal::QuotaFailure);
+            }
+            if let Some(info) = details
+                .error_info()
+                .filter(|info| self.non_retryable_reasons.contains(&info.reason))
+            {
+                return (
+                    Classification::Done,
+                    RetrySignal::ErrorInfo(info.reason.clone()),
+                );
+            }
+            let retryable_with_info =
+                retryable || matches!(status.code(), Code::ResourceExhausted | Code::Aborted);
+            if let Some(info) = details.retry_info().filter(|_| retryable_with_info) {
+                // the server refuses the retry explicitly
+                if pushback == Some(Pushback::Abort) {
+                    return (Classification::Done, RetrySignal::Pushback(Pushback::Abort));
+                }
+                return (
+                    Classification::RetryAfterHint(info.retry_delay),
+                    RetrySignal::RetryInfo(info.retry_delay),
+                );
+            }
+        }
+
+        let classification = Classification::new(retryable, pushback);
+        let signal = match pushback {
+            Some(pushback) if retryable => RetrySignal::Pushback(pushback),
+            _ => RetrySignal::Code(status.code()),
+        };
+        (classification, signal)
+    }
+}
+
+/// The signal that decided the classification of the last failed attempt, which is inserted
+/// into the extensions of the [`ClientContext`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RetrySignal {
+    /// Classified by the status code.
+    Code(Code),
+    /// The `grpc-retry-pushback-ms` metadata.
+    Pushback(Pushback),
+    /// The `RetryInfo` detail with its delay.
+    RetryInfo(Duration),
+    /// The `QuotaFailure` detail.
+    QuotaFailure,
+    /// The `ErrorInfo` detail with a non-retryable reason.
+    ErrorInfo(String),
+}
+
+impl<T, Resp> RetryStrategy<ClientContext, Request<T>, Resp, Status> for GrpcRetryStrategy
+where
+    T: 'static,
+{
+    fn policy(&self, cx: &ClientContext) -> Option<RetryPolicy> {
+        cx.rpc_info().config().retry_policy()
+    }
+
+    fn clone_request(&self, req: &Request<T>) -> Option<Request<T>> {
+        let replay = req.extensions().get::<Replay<T>>()?;
+        let message = (replay.0)()?;
+        Some(Request::from_parts(
+            req.metadata().clone(),
+            req.extensions().clone(),
+            message,
+        ))
+    }
+
+    fn classify(&self, cx: &mut ClientContext, result: &Result<Resp, Status>) -> Classification {
+        let Err(status) = result else {
+            return Classification::Done;
+        };
+        let (classification, signal) = self.classify_status(status);
+        cx.extensions_mut().insert(signal);
+        classification
+    }
+}
+
+/// Rebuilds the message of a unary request for retrying, which is inserted into the extensions
+/// of the request by the generated client.
+///
+/// It only holds a weak reference of the message, so no message will be cloned if the request is
+/// not going to be retried.
+struct Replay<T>(Arc<dyn Fn() -> Option<T> + Send + Sync>);
+
+impl<T> Clone for Replay<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+/// Makes the message stream of a unary request, which can be replayed by [`GrpcRetryStrategy`].
+///
+/// This is used by the generated code.
+pub fn unary_request<M, T>(
+    req: Request<M>,
+    f: fn(BoxStream<'static, Result<M, Status>>) -> T,
+) -> Request<T>
+where
+    M: Clone + Send + Sync + 'static,
+    T: 'static,
+{
+    let (metadata, mut extensions, message) = req.into_parts();
+    let message = Arc::new(message);
+    let weak = Arc::downgrade(&message);
+    extensions.insert(Replay::<T>(Arc::new(move || {
+        weak.upgrade().map(|m| f(take_or_clone(m)))
+    })));
+    Request::from_parts(metadata, extensions, f(take_or_clone(message)))
+}
+
+/// Takes the message if there is no replay holding it, otherwise clones it.
+fn take_or_clone<M>(message: Arc<M>) -> BoxStream<'static, Result<M, Status>>
+where
+    M: Clone + Send + Sync + 'static,
+{
+    Box::pin(futures::stream::once(futures::future::lazy(move |_| {
+        Ok(Arc::try_unwrap(message).unwrap_or_else(|m| (*m).clone()))
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::{
+        metadata::MetadataValue,
+        status::details::{
+            ErrorDetail, ErrorDetails, ErrorInfo, QuotaFailure, QuotaViolation, RetryInfo,
+        },
+    };
+
+    fn cx() -> ClientContext {
+        ClientContext::new(volo::context::RpcInfo::with_role(
+            volo::context::Role::Client,
+        ))
+    }
+
+    #[test]
+    fn replay_unary_request() {
+        let mut req = unary_request(Request::new(String::from("hello")), |s| s);
+        req.metadata_mut()
+            .insert("key", MetadataValue::from_static("value"));
+
+        let strategy = GrpcRetryStrategy::default();
+        let retry =
+            <GrpcRetryStrategy as RetryStrategy<ClientContext, _, (), Status>>::clone_request(
+                &strategy, &req,
+            )
+            .unwrap();
+        assert_eq!(
+            retry.metadata().get("key").unwrap().to_str().unwrap(),
+            "value"
+        );
+
+        let first = futures::executor::block_on(req.into_inner().next());
+        assert_eq!(first.unwrap().unwrap(), "hello");
+        let second = futures::executor::block_on(retry.into_inner().next());
+        assert_eq!(second.unwrap().unwrap(), "hello");
+
+        // streaming requests can not be replayed
+        let req: Request<BoxStream<'static, Result<String, Status>>> =
+            Request::new(Box::pin(futures::stream::empty()));
+        assert!(
+            <GrpcRetryStrategy as RetryStrategy<ClientContext, _, (), Status>>::clone_request(
+                &strategy, &req,
+            )
+            .is_none()
+        );
+    }
+
+    fn classify_with(
+        strategy: &GrpcRetryStrategy,
+        status: Status,
+    ) -> (Classification, RetrySignal) {
+        let mut cx = cx();
+        let classification = <GrpcRetryStrategy as RetryStrategy<
+            ClientContext,
+            Request<()>,
+            (),
+            Status,
+        >>::classify(strategy, &mut cx, &Err(status));
+        let signal = cx.extensions().get::<RetrySignal>().unwrap().clone();
+        (classification, signal)
+    }
+
+    fn classify(status: Status) -> Classification {
+        classify_with(&GrpcRetryStrategy::default(), status).0
+    }
+
+    fn with_details(code: Code, details: Vec<ErrorDetail>) -> Status {
+        Status::with_error_details(code, "", ErrorDetails { details })
+    }
+
+    fn retry_info(ms: u64) -> ErrorDetail {
+        ErrorDetail::RetryInfo(RetryInfo {
+            retry_delay: Duration::from_millis(ms),
+        })
+    }
+
+    fn quota_failure() -> ErrorDetail {
+        ErrorDetail::QuotaFailure(QuotaFailure {
+            violations: vec![QuotaViolation {
+                subject: "project:hello".to_owned(),
+                description: "daily limit".to_owned(),
+            }],
+        })
+    }
+
+    #[test]
+    fn classify_code() {
+        assert_eq!(classify(Status::unavailable("")), Classification::Retry);
+        assert_eq!(classify(Status::internal("")), Classification::Done);
+
+        let mut status = Status::unavailable("");
+        status
+            .metadata_mut()
+            .insert(GRPC_RETRY_PUSHBACK_MS, MetadataValue::from_static("100"));
+        assert_eq!(
+            classify(status),
+            Classification::RetryAfter(Duration::from_millis(100))
+        );
+
+        let mut status = Status::unavailable("");
+        status
+            .metadata_mut()
+            .insert(GRPC_RETRY_PUSHBACK_MS, MetadataValue::from_static("-1"));
+        assert_eq!(classify(status), Classification::Done);
+    }
+
+    #[test]
+    fn classify_retry_info() {
+        // the delay is bounded by the policy in the layer, see `retry_info_delay`
+        for ms in [5, 60_000] {
+            let (classification, signal) = classify_with(
+                &GrpcRetryStrategy::default(),
+                with_details(Code::Unavailable, vec![retry_info(ms)]),
+            );
+            assert_eq!(
+                classification,
+                Classification::RetryAfterHint(Duration::from_millis(ms))
+            );
+            assert_eq!(signal, RetrySignal::RetryInfo(Duration::from_millis(ms)));
+        }
+
+        // retryable with the retry info only
+        let status = with_details(Code::ResourceExhausted, vec![retry_info(5)]);
+        assert_eq!(
+            classify(status),
+            Classification::RetryAfterHint(Duration::from_millis(5))
+        );
+        assert_eq!(
+            classify(Status::resource_exhausted("")),
+            Classification::Done
+        );
+        let status = with_details(Code::Internal, vec![retry_info(5)]);
+        assert_eq!(
+            classify_with(&GrpcRetryStrategy::default(), status),
+            (Classification::Done, RetrySignal::Code(Code::Internal))
+        );
+
+        // over the pushback metadata
+        let mut status = with_details(Code::Unavailable, vec![retry_info(5)]);
+        status
+            .metadata_mut()
+            .insert(GRPC_RETRY_PUSHBACK_MS, MetadataValue::from_static("100"));
+        assert_eq!(
+            classify(status),
+            Classification::RetryAfterHint(Duration::from_millis(5))
+        );
+
+        // ignored
+        let strategy = GrpcRetryStrategy::default().ignore_details();
+        let status = with_details(Code::ResourceExhausted, vec![retry_info(5)]);
+        assert_eq!(
+            classify_with(&strategy, status),
+            (
+                Classification::Done,
+                RetrySignal::Code(Code::ResourceExhausted)
+            )
+        );
+    }
+
+    #[test]
+    fn classify_conflicting_signals() {
+        // stopping signals win over the retry info
+        let status = with_details(Code::Unavailable, vec![retry_info(5), quota_failure()]);
+        let (classification, signal) = classify_with(&GrpcRetryStrategy::default(), status);
+        assert_eq!(classification, Classification::Done);
+        assert_eq!(signal, RetrySignal::QuotaFailure);
+
+        let error_info = |reason: &str| {
+            ErrorDetail::ErrorInfo(ErrorInfo {
+                reason: reason.to_owned(),
+                ..Default::default()
+            })
+        };
+        let strategy = GrpcRetryStrategy::default().non_retryable_reasons(["API_DISABLED"]);
+        let status = with_details(
+            Code::Unavailable,
+            vec![error_info("API_DISABLED"), retry_info(5)],
+        );
+        assert_eq!(
+            classify_with(&strategy, status),
+            (
+                Classification::Done,
+                RetrySignal::ErrorInfo("API_DISABLED".to_owned())
+            )
+        );
+        let status = with_details(Code::Unavailable, vec![error_info("OTHER"), retry_info(5)]);
+        assert_eq!(
+            classify_with(&strategy, status).0,
+            Classification::RetryAfterHint(Duration::from_millis(5))
+        );
+
+        // the server refuses the retry
+        let mut status = with_details(Code::Unavailable, vec![retry_info(5)]);
+        status
+            .metadata_mut()
+            .insert(GRPC_RETRY_PUSHBACK_MS, MetadataValue::from_static("-1"));
+        assert_eq!(
+            classify_with(&GrpcRetryStrategy::default(), status),
+            (Classification::Done, RetrySignal::Pushback(Pushback::Abort))
+        );
+
+        // the details of the final error are kept
+        let status = with_details(Code::Unavailable, vec![quota_failure()]);
+        assert!(status.error_details().unwrap().quota_failure().is_some());
+    }
+
+    #[test]
+    fn retry_info_delay() {
+        use volo::{retry::RetryLayer, Layer, Service};
+
+        struct Server;
+
+        impl Service<ClientContext, Request<()>> for Server {
+            type Response = ();
+            type Error = Status;
+
+            async fn call<'s, 'cx>(
+                &'s self,
+                _cx: &'cx mut ClientContext,
+                _req: Request<()>,
+            ) -> Result<(), Status> {
+                Err(with_details(Code::Unavailable, vec![retry_info(60_000)]))
+            }
+        }
+
+        let svc = RetryLayer::new(GrpcRetryStrategy::default())
+            .policy(RetryPolicy::new(1).backoff(Duration::from_millis(1), Duration::from_millis(1)))
+            .layer(Server);
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let mut cx = cx();
+        let start = std::time::Instant::now();
+        let req = unary_request(Request::new(()), |_| ());
+        let result = rt.block_on(svc.call(&mut cx, req));
+        assert!(result.is_err());
+        // the delay of 60s is bounded by the maximum backoff
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(
+            cx.extensions().get::<RetrySignal>(),
+            Some(&RetrySignal::RetryInfo(Duration::from_secs(60)))
+        );
+    }
+}
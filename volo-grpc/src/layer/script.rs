@@ -0,0 +1,215 @@
+//! A server layer for invoking the [`ScriptHook`] with the metadata of each request.
+
+use std::sync::Arc;
+
+use http::header::{HeaderName, HeaderValue};
+use motore::{layer::Layer, Service};
+use volo::script::{run_hook, Mutation, ScriptHook, Verdict, ViewLimits};
+
+use crate::{
+    context::ServerContext,
+    metadata::MetadataMap,
+    status::{Code, Status},
+    Request,
+};
+
+/// A [`Layer`] for invoking the [`ScriptHook`] with the view of each request, and applying the
+/// mutations to the metadata or rejecting the request.
+///
+/// The view contains the method `POST`, the full method name as the path, and the ASCII metadata
+/// limited by the [`ViewLimits`]. The reserved headers of gRPC, e.g. `grpc-timeout` and
+/// `content-type`, are neither visible nor mutable, and [`Mutation::SetPath`] is ignored.
+///
+/// A rejection returns a [`Status`] with the code mapped from the HTTP status code.
+#[derive(Clone)]
+pub struct ScriptLayer<H> {
+    hook: Arc<H>,
+    limits: ViewLimits,
+}
+
+impl<H> ScriptLayer<H> {
+    pub fn new(hook: H) -> Self {
+        Self {
+            hook: Arc::new(hook),
+            limits: ViewLimits::default(),
+        }
+    }
+
+    /// Sets the limits of the metadata in the view.
+    ///
+    /// Default is [`ViewLimits::default`].
+    pub fn limits(mut self, limits: ViewLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+}
+
+impl<S, H> Layer<S> for ScriptLayer<H> {
+    type Service = Script<S, H>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        Script {
+            inner,
+            hook: self.hook,
+            limits: self.limits,
+        }
+    }
+}
+
+pub struct Script<S, H> {
+    inner: S,
+    hook: Arc<H>,
+    limits: ViewLimits,
+}
+
+impl<S, H> Clone for Script<S, H>
+where
+    S: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            hook: self.hook.clone(),
+            limits: self.limits.clone(),
+        }
+    }
+}
+
+impl<S, H, T> Service<ServerContext, Request<T>> for Script<S, H>
+where
+    S: Service<ServerContext, Request<T>, Error = Status> + Send + Sync,
+    H: ScriptHook,
+    T: Send,
+{
+    type Response = S::Response;
+    type Error = Status;
+
+    async fn call<'s, 'cx>(
+        &'s self,
+        cx: &'cx mut ServerContext,
+        mut req: Request<T>,
+    ) -> Result<Self::Response, Self::Error> {
+        let view = self.limits.view(
+            "POST",
+            cx.rpc_info.method().as_str(),
+            req.metadata()
+                .headers()
+                .iter()
+                .filter(|(name, _)| !is_reserved(name.as_str()))
+                .map(|(name, value)| (name.as_str(), value.as_bytes())),
+        );
+        match run_hook(&*self.hook, &view) {
+            Verdict::Continue { mutations } => {
+                for mutation in mutations {
+                    apply_mutation(req.metadata_mut(), mutation);
+                }
+            }
+            Verdict::Reject { status, message } => {
+                return Err(Status::new(code_of(status), message));
+            }
+        }
+        self.inner.call(cx, req).await
+    }
+}
+
+fn is_reserved(name: &str) -> bool {
+    name.starts_with("grpc-") || name.ends_with("-bin") || name == "content-type" || name == "te"
+}
+
+fn apply_mutation(metadata: &mut MetadataMap, mutation: Mutation) {
+    match mutation {
+        Mutation::SetHeader { name, value } => {
+            match (HeaderName::try_from(name), HeaderValue::try_from(value)) {
+                (Ok(name), Ok(value)) if !is_reserved(name.as_str()) => {
+                    metadata.headers_mut().insert(name, value);
+                }
+                _ => tracing::warn!("[VOLO] ScriptLayer: invalid metadata is ignored"),
+            }
+        }
+        Mutation::RemoveHeader { name } => {
+            if !is_reserved(&name) {
+                metadata.headers_mut().remove(name.as_str());
+            }
+        }
+        Mutation::SetPath { .. } => {
+            tracing::warn!("[VOLO] ScriptLayer: rewriting the path is not supported by gRPC");
+        }
+    }
+}
+
+fn code_of(status: u16) -> Code {
+    match status {
+        400 => Code::InvalidArgument,
+        401 => Code::Unauthenticated,
+        404 => Code::NotFound,
+        409 => Code::AlreadyExists,
+        412 => Code::FailedPrecondition,
+        429 => Code::ResourceExhausted,
+        501 => Code::Unimplemented,
+        503 => Code::Unavailable,
+        504 => Code::DeadlineExceeded,
+        500..=599 => Code::Internal,
+        _ => Code::PermissionDenied,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use volo::script::FnHook;
+
+    use super::*;
+
+    struct Echo;
+
+    impl Service<ServerContext, Request<()>> for Echo {
+        type Response = Option<String>;
+        type Error = Status;
+
+        async fn call<'s, 'cx>(
+            &'s self,
+            _: &'cx mut ServerContext,
+            req: Request<()>,
+        ) -> Result<Self::Response, Self::Error> {
+            Ok(req
+                .metadata()
+                .get("x-user")
+                .and_then(|v| v.to_str().ok())
+                .map(ToOwned::to_owned))
+        }
+    }
+
+    #[tokio::test]
+    async fn script_layer() {
+        let hook = FnHook::new("gateway", |req| {
+            assert!(req.header("grpc-timeout").is_none());
+            match req.header("x-token") {
+                None => Ok(Verdict::reject(401, "missing token")),
+                Some(_) => Ok(Verdict::mutate(vec![
+                    Mutation::SetHeader {
+                        name: "x-user".to_owned(),
+                        value: "alice".to_owned(),
+                    },
+                    Mutation::SetHeader {
+                        name: "grpc-timeout".to_owned(),
+                        value: "1n".to_owned(),
+                    },
+                ])),
+            }
+        });
+        let svc = ScriptLayer::new(hook).layer(Echo);
+
+        let mut cx = ServerContext::default();
+        cx.rpc_info.set_method("/test.Echo/Echo".into());
+        let status = svc.call(&mut cx, Request::new(())).await.unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated);
+        assert_eq!(status.message(), "missing token");
+
+        let mut req = Request::new(());
+        req.metadata_mut()
+            .insert("x-token", "secret".parse().unwrap());
+        req.metadata_mut()
+            .insert("grpc-timeout", "1S".parse().unwrap());
+        let user = svc.call(&mut cx, req).await.unwrap();
+        assert_eq!(user.as_deref(), Some("alice"));
+    }
+}
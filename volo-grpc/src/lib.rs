@@ -10,6 +10,7 @@ pub mod codec;
 #[doc(hidden)]
 pub mod codegen;
 pub mod context;
+pub mod inspect;
 pub mod layer;
 pub mod message;
 pub mod metadata;
@@ -17,7 +18,11 @@ pub mod request;
 pub mod response;
 pub mod server;
 pub mod status;
+#[cfg(feature = "tonic-compat")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tonic-compat")))]
+pub mod tonic_compat;
 pub mod transport;
+pub mod well_known;
 
 pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
 pub type BoxStream<'l, T> = std::pin::Pin<Box<dyn futures::Stream<Item = T> + Send + Sync + 'l>>;
@@ -26,7 +31,7 @@ pub use client::Client;
 pub use codec::decode::RecvStream;
 pub use message::{RecvEntryMessage, SendEntryMessage};
 pub use request::{IntoRequest, IntoStreamingRequest, Request};
-pub use response::Response;
+pub use response::{Response, StreamingResponse, Trailers};
 pub use status::{Code, Status};
 
 pub(crate) const BASE64_ENGINE: base64::engine::GeneralPurpose =
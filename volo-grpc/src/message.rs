@@ -1,21 +1,62 @@
 use bytes::Bytes;
 use http_body::Frame;
-use hyper::body::Incoming;
 
-use crate::codec::{compression::CompressionEncoding, decode::Kind};
+use crate::{
+    body::BoxBody,
+    codec::{
+        compression::CompressionEncoding,
+        decode::{DecodeLimits, Kind},
+        MessageCodec,
+    },
+};
 
 pub trait SendEntryMessage {
     fn into_body(
         self,
         compression_config: Option<CompressionEncoding>,
     ) -> crate::BoxStream<'static, Result<Frame<Bytes>, crate::Status>>;
+
+    /// Encodes the messages by the codec, which is only protobuf unless the messages are
+    /// generated with serde.
+    fn into_body_with(
+        self,
+        codec: MessageCodec,
+        compression_config: Option<CompressionEncoding>,
+    ) -> crate::BoxStream<'static, Result<Frame<Bytes>, crate::Status>>
+    where
+        Self: Sized,
+    {
+        match codec {
+            MessageCodec::Proto => self.into_body(compression_config),
+            codec => crate::codec::encode::unsupported(codec),
+        }
+    }
 }
 
 pub trait RecvEntryMessage: Sized {
     fn from_body(
         method: Option<&str>,
-        body: Incoming,
+        body: BoxBody,
         kind: Kind,
         compression_encoding: Option<CompressionEncoding>,
+        limits: DecodeLimits,
     ) -> Result<Self, crate::Status>;
+
+    /// Decodes the messages by the codec, which is only protobuf unless the messages are
+    /// generated with serde.
+    fn from_body_with(
+        method: Option<&str>,
+        body: BoxBody,
+        kind: Kind,
+        codec: MessageCodec,
+        compression_encoding: Option<CompressionEncoding>,
+        limits: DecodeLimits,
+    ) -> Result<Self, crate::Status> {
+        match codec {
+            MessageCodec::Proto => {
+                Self::from_body(method, body, kind, compression_encoding, limits)
+            }
+            codec => Err(codec.unsupported()),
+        }
+    }
 }
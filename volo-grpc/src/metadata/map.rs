@@ -2,6 +2,8 @@
 
 use std::marker::PhantomData;
 
+use base64::Engine;
+
 pub(crate) use self::{
     as_encoding_agnostic_metadata_key::AsEncodingAgnosticMetadataKey,
     as_metadata_key::AsMetadataKey, into_metadata_key::IntoMetadataKey,
@@ -15,6 +17,9 @@ use super::{
 #[derive(Clone, Debug, Default)]
 pub struct MetadataMap {
     headers: http::HeaderMap,
+    /// The `-bin` values which were already encoded when [`MetadataMap::headers_mut`] handed
+    /// out the raw map, any other `-bin` value found later was put raw and will be encoded.
+    encoded_bin: Option<Vec<(http::HeaderName, http::HeaderValue)>>,
 }
 
 /// `MetadataMap` entry iterator.
@@ -210,7 +215,10 @@ impl MetadataMap {
 
     /// Convert an HTTP HeaderMap to a MetadataMap
     pub fn from_headers(headers: http::HeaderMap) -> Self {
-        Self { headers }
+        Self {
+            headers,
+            encoded_bin: None,
+        }
     }
 
     /// Convert a MetadataMap into a HTTP HeaderMap
@@ -226,11 +234,13 @@ impl MetadataMap {
     ///
     /// assert_eq!(http_map.get("x-host").unwrap(), "example.com");
     /// ```
-    pub fn into_headers(self) -> http::HeaderMap {
+    pub fn into_headers(mut self) -> http::HeaderMap {
+        self.encode_raw_bin();
         self.headers
     }
 
     pub(crate) fn into_sanitized_headers(mut self) -> http::HeaderMap {
+        self.encode_raw_bin();
         for r in &Self::GRPC_RESERVED_HEADERS {
             self.headers.remove(*r);
         }
@@ -243,10 +253,46 @@ impl MetadataMap {
     }
 
     /// Get a mutable reference to the underlying HTTP HeaderMap
+    ///
+    /// The values put under the `-bin` keys through it are raw bytes, which are base64-encoded
+    /// before being sent or accessed by the binary API, even if they look like base64 already.
     pub fn headers_mut(&mut self) -> &mut http::HeaderMap {
+        if self.encoded_bin.is_none() {
+            self.encoded_bin = Some(
+                self.headers
+                    .iter()
+                    .filter(|(name, _)| Binary::is_valid_key(name.as_str()))
+                    .map(|(name, value)| (name.clone(), value.clone()))
+                    .collect(),
+            );
+        }
         &mut self.headers
     }
 
+    /// Base64-encodes the raw `-bin` values put by [`headers_mut`](Self::headers_mut), which is
+    /// done before the map is accessed by the binary API, so they are never mistaken for the
+    /// encoded ones.
+    fn encode_raw_bin(&mut self) {
+        let Some(mut encoded) = self.encoded_bin.take() else {
+            return;
+        };
+        for (name, value) in self.headers.iter_mut() {
+            if !Binary::is_valid_key(name.as_str()) {
+                continue;
+            }
+            match encoded.iter().position(|(n, v)| n == name && v == value) {
+                Some(i) => {
+                    encoded.swap_remove(i);
+                }
+                None => {
+                    *value =
+                        http::HeaderValue::try_from(crate::BASE64_ENGINE.encode(value.as_bytes()))
+                            .expect("base64 is a valid header value");
+                }
+            }
+        }
+    }
+
     /// Create an empty `MetadataMap` with the specified capacity.
     ///
     /// The returned map will allocate internal storage in order to hold about
@@ -268,6 +314,7 @@ impl MetadataMap {
     pub fn with_capacity(capacity: usize) -> Self {
         Self {
             headers: http::HeaderMap::with_capacity(capacity),
+            encoded_bin: None,
         }
     }
 
@@ -554,6 +601,7 @@ impl MetadataMap {
     where
         K: AsMetadataKey<Binary>,
     {
+        self.encode_raw_bin();
         key.get_mut(self)
     }
 
@@ -749,6 +797,7 @@ impl MetadataMap {
     /// }
     /// ```
     pub fn iter_mut(&mut self) -> IterMut<'_> {
+        self.encode_raw_bin();
         IterMut {
             inner: self.headers.iter_mut(),
         }
@@ -836,6 +885,7 @@ impl MetadataMap {
     /// }
     /// ```
     pub fn values_mut(&mut self) -> ValuesMut<'_> {
+        self.encode_raw_bin();
         ValuesMut {
             inner: self.headers.iter_mut(),
         }
@@ -937,6 +987,7 @@ impl MetadataMap {
     where
         K: AsMetadataKey<Binary>,
     {
+        self.encode_raw_bin();
         self.generic_entry::<Binary, K>(key)
     }
 
@@ -1055,6 +1106,7 @@ impl MetadataMap {
     where
         K: IntoMetadataKey<Binary>,
     {
+        self.encode_raw_bin();
         key.insert(self, val)
     }
 
@@ -1148,6 +1200,7 @@ impl MetadataMap {
     where
         K: IntoMetadataKey<Binary>,
     {
+        self.encode_raw_bin();
         key.append(self, value)
     }
 
@@ -1225,9 +1278,45 @@ impl MetadataMap {
         key.remove(self)
     }
 
-    pub fn merge(&mut self, other: MetadataMap) {
+    pub fn merge(&mut self, mut other: MetadataMap) {
+        self.encode_raw_bin();
+        other.encode_raw_bin();
         self.headers.extend(other.headers);
     }
+
+    /// Validates and normalizes the metadata to be sent by the gRPC spec, which is done by the
+    /// client before each call, so an invalid metadata put by [`headers_mut`](Self::headers_mut)
+    /// fails early with a clear message instead of an opaque transport error of a strict server.
+    ///
+    /// - The keys should only contain `0-9`, `a-z`, `-`, `_` and `.`.
+    /// - The raw values put under the `-bin` keys are base64-encoded.
+    /// - The values of the other keys should be printable ASCII.
+    ///
+    /// Returns `INVALID_ARGUMENT` for the invalid keys or values.
+    pub fn normalize(&mut self) -> Result<(), crate::Status> {
+        self.encode_raw_bin();
+        for (name, value) in self.headers.iter_mut() {
+            let key = name.as_str();
+            if !key
+                .bytes()
+                .all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'z' | b'-' | b'_' | b'.'))
+            {
+                return Err(crate::Status::invalid_argument(format!(
+                    "invalid metadata key `{key}`, which should only contain 0-9, a-z, `-`, `_` \
+                     and `.`"
+                )));
+            }
+            if !Binary::is_valid_key(key)
+                && !value.as_bytes().iter().all(|b| matches!(b, 0x20..=0x7e))
+            {
+                return Err(crate::Status::invalid_argument(format!(
+                    "invalid value of the metadata `{key}`, which should be printable ASCII, or \
+                     the key should end with `-bin` for a binary value"
+                )));
+            }
+        }
+        Ok(())
+    }
 }
 
 // ===== impl Iter =====
@@ -2662,6 +2751,68 @@ mod tests {
         assert!(found_x_word_bin);
     }
 
+    #[test]
+    fn test_normalize() {
+        let mut map = MetadataMap::new();
+        map.insert("x-word", "hello".parse().unwrap());
+        map.insert_bin("x-word-bin", MetadataValue::from_bytes(b"goodbye"));
+        map.headers_mut().insert(
+            "x-raw-bin",
+            http::HeaderValue::from_bytes(&[0xff, b'!', 0x80]).unwrap(),
+        );
+        map.normalize().unwrap();
+        assert_eq!(map.get("x-word").unwrap(), "hello");
+        assert_eq!(map.get_bin("x-word-bin").unwrap(), "goodbye");
+        assert_eq!(
+            map.get_bin("x-raw-bin").unwrap().to_bytes().unwrap(),
+            &[0xff, b'!', 0x80][..]
+        );
+
+        let mut map = MetadataMap::new();
+        map.headers_mut().insert(
+            "x-text",
+            http::HeaderValue::from_bytes(b"caf\xc3\xa9").unwrap(),
+        );
+        let status = map.normalize().unwrap_err();
+        assert_eq!(status.code(), crate::Code::InvalidArgument);
+        assert!(status.message().contains("x-text"));
+
+        let mut map = MetadataMap::new();
+        map.headers_mut()
+            .insert("x-key!", http::HeaderValue::from_static("value"));
+        let status = map.normalize().unwrap_err();
+        assert_eq!(status.code(), crate::Code::InvalidArgument);
+        assert!(status.message().contains("x-key!"));
+    }
+
+    #[test]
+    fn test_normalize_raw_base64_like() {
+        let mut map = MetadataMap::new();
+        map.insert_bin("x-word-bin", MetadataValue::from_bytes(b"goodbye"));
+        map.headers_mut()
+            .insert("x-raw-bin", http::HeaderValue::from_static("abcd"));
+        map.normalize().unwrap();
+        assert_eq!(map.get_bin("x-word-bin").unwrap(), "goodbye");
+        assert_eq!(
+            map.get_bin("x-raw-bin").unwrap().to_bytes().unwrap(),
+            &b"abcd"[..]
+        );
+
+        // the raw value is encoded before the binary API touches the map again
+        let mut map = MetadataMap::new();
+        map.headers_mut()
+            .insert("x-raw-bin", http::HeaderValue::from_static("abcd"));
+        map.append_bin("x-raw-bin", MetadataValue::from_bytes(b"efgh"));
+        let values = map
+            .get_all_bin("x-raw-bin")
+            .iter()
+            .map(|v| v.to_bytes().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(values, [&b"abcd"[..], &b"efgh"[..]]);
+        let headers = map.into_headers();
+        assert_eq!(headers.get("x-raw-bin").unwrap(), "YWJjZA");
+    }
+
     #[allow(dead_code)]
     fn value_drain_is_send_sync() {
         fn is_send_sync<T: Send + Sync>() {}
@@ -1,10 +1,17 @@
 //! These codes are copied from `tonic/src/response.rs` and may be modified by us.
 
-use std::fmt::Debug;
+use std::{
+    fmt::Debug,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
 
+use futures::{Stream, TryStreamExt};
 use http::Extensions;
+use parking_lot::{Mutex, MutexGuard};
 
-use crate::metadata::MetadataMap;
+use crate::{metadata::MetadataMap, RecvStream, Status};
 
 #[derive(Debug)]
 pub struct Response<T> {
@@ -89,6 +96,17 @@ impl<T> Response<T> {
         &mut self.extensions
     }
 
+    /// Returns the [`Trailers`] sent with the status after the messages, which can be updated until
+    /// the messages end, e.g. by a clone moved into the stream of the messages.
+    pub fn trailers(&mut self) -> Trailers {
+        if let Some(trailers) = self.extensions.get::<Trailers>() {
+            return trailers.clone();
+        }
+        let trailers = Trailers::default();
+        self.extensions.insert(trailers.clone());
+        trailers
+    }
+
     #[doc(hidden)]
     pub fn map<F, U>(self, f: F) -> Response<U>
     where
@@ -102,3 +120,178 @@ impl<T> Response<T> {
         }
     }
 }
+
+/// The custom trailing metadata of a response on the server, which is sent in the trailers with
+/// the status after the last message, e.g. a checksum computed while streaming the messages.
+///
+/// It's got by [`Response::trailers`], and the clones share the same metadata. The status always
+/// overrides the `grpc-status` and `grpc-message` set.
+///
+/// The client gets the metadata merged into the metadata of a unary response, or by
+/// [`StreamingResponse::trailers`] of a streaming one.
+#[derive(Debug, Clone, Default)]
+pub struct Trailers(Arc<Mutex<MetadataMap>>);
+
+impl Trailers {
+    /// Locks the metadata for updating.
+    pub fn lock(&self) -> MutexGuard<'_, MetadataMap> {
+        self.0.lock()
+    }
+
+    /// Takes the metadata, leaving it empty.
+    pub(crate) fn take(&self) -> MetadataMap {
+        std::mem::take(&mut *self.lock())
+    }
+}
+
+/// The response of a server streaming call.
+///
+/// The initial metadata is available as soon as the response is received, and the trailing
+/// metadata can be got by [`StreamingResponse::trailers`] after the last message.
+///
+/// It's returned by the server streaming methods of the generated clients, which returned a
+/// [`Response`] of the message stream before, and it can be converted into that [`Response`] by
+/// [`Into`] for the code written against it.
+#[derive(Debug)]
+pub struct StreamingResponse<T> {
+    metadata: MetadataMap,
+    extensions: Extensions,
+    stream: RecvStream<T>,
+}
+
+impl<T> StreamingResponse<T> {
+    pub fn from_parts(
+        metadata: MetadataMap,
+        extensions: Extensions,
+        stream: RecvStream<T>,
+    ) -> Self {
+        Self {
+            metadata,
+            extensions,
+            stream,
+        }
+    }
+
+    /// Get a reference to the initial metadata.
+    pub fn metadata(&self) -> &MetadataMap {
+        &self.metadata
+    }
+
+    /// Get a mutable reference to the initial metadata.
+    pub fn metadata_mut(&mut self) -> &mut MetadataMap {
+        &mut self.metadata
+    }
+
+    /// Returns a reference to the associated extensions.
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    /// Returns a mutable reference to the associated extensions.
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        &mut self.extensions
+    }
+
+    /// Get a mutable reference to the message stream.
+    pub fn get_mut(&mut self) -> &mut RecvStream<T> {
+        &mut self.stream
+    }
+
+    /// Consumes `self`, returning the message stream.
+    pub fn into_inner(self) -> RecvStream<T> {
+        self.stream
+    }
+
+    pub fn into_parts(self) -> (MetadataMap, Extensions, RecvStream<T>) {
+        (self.metadata, self.extensions, self.stream)
+    }
+}
+
+impl<T> StreamingResponse<T> {
+    /// Get the next message, or `None` if all the messages have been received.
+    pub async fn message(&mut self) -> Result<Option<T>, Status> {
+        self.stream.try_next().await
+    }
+
+    /// Get the trailing metadata.
+    ///
+    /// The remaining messages will be received and dropped if the stream has not ended, so this
+    /// should be called after the last message.
+    pub async fn trailers(&mut self) -> Result<Option<MetadataMap>, Status> {
+        self.stream.trailers().await
+    }
+}
+
+impl<T> From<StreamingResponse<T>> for Response<RecvStream<T>> {
+    fn from(resp: StreamingResponse<T>) -> Self {
+        let (metadata, extensions, stream) = resp.into_parts();
+        Response::from_parts(metadata, extensions, stream)
+    }
+}
+
+impl<T> Stream for StreamingResponse<T> {
+    type Item = Result<T, Status>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.stream).poll_next(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use http::HeaderMap;
+    use http_body::Frame;
+    use http_body_util::StreamBody;
+
+    use super::*;
+    use crate::{
+        body::BoxBody,
+        codec::decode::{DecodeLimits, Kind},
+        metadata::MetadataValue,
+    };
+
+    #[test]
+    fn streaming_response_metadata_and_trailers() {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", "0".parse().unwrap());
+        trailers.insert("trailing-key", "trailing-value".parse().unwrap());
+        // two messages of `String`, and the trailers
+        let frames: Vec<Result<_, Status>> = vec![
+            Ok(Frame::data(Bytes::from_static(
+                b"\x00\x00\x00\x00\x04\x0a\x02hi",
+            ))),
+            Ok(Frame::data(Bytes::from_static(
+                b"\x00\x00\x00\x00\x04\x0a\x02yo",
+            ))),
+            Ok(Frame::trailers(trailers)),
+        ];
+        let body = BoxBody::new(StreamBody::new(futures::stream::iter(frames)));
+
+        let mut metadata = MetadataMap::new();
+        metadata.insert("initial-key", MetadataValue::from_static("initial-value"));
+        let mut resp = StreamingResponse::from_parts(
+            metadata,
+            Extensions::new(),
+            RecvStream::<String>::new(
+                body,
+                Kind::Response(http::StatusCode::OK),
+                None,
+                DecodeLimits::default(),
+            ),
+        );
+
+        assert_eq!(resp.metadata().get("initial-key").unwrap(), "initial-value");
+        futures::executor::block_on(async {
+            assert_eq!(resp.message().await.unwrap().unwrap(), "hi");
+            assert_eq!(resp.message().await.unwrap().unwrap(), "yo");
+            assert!(resp.message().await.unwrap().is_none());
+            // polling after the end must not fail
+            assert!(resp.message().await.unwrap().is_none());
+
+            let trailers = resp.trailers().await.unwrap().unwrap();
+            assert_eq!(trailers.get("trailing-key").unwrap(), "trailing-value");
+            assert!(resp.trailers().await.unwrap().is_none());
+        });
+    }
+}
@@ -0,0 +1,494 @@
+//! The half-close and the cancellation of the calls on the server.
+//!
+//! A client half-closes a call after sending all its messages, and it still waits for the
+//! responses, e.g. a bidi call where the client sends a batch and the server streams the results
+//! for minutes. So the end of the request stream, i.e. `None` from the `RecvStream`, never ends
+//! the call, and the response stream is only ended by the method. A cancelled call, e.g. reset by
+//! the client, is the other end, when the method should stop.
+//!
+//! The [`CancellationToken`] of each call tells the two apart, which is in the extensions of the
+//! [`ServerContext`](crate::context::ServerContext) for the layers, see
+//! [`ServerContext::cancellation_token`](crate::context::ServerContext::cancellation_token), and
+//! of the request for the methods:
+//!
+//! ```rust,ignore
+//! async fn chat(&self, req: Request<RecvStream<ChatRequest>>, tx: Sender<ChatResponse>) -> Result<(), Status> {
+//!     let token = req.extensions().get::<CancellationToken>().cloned().unwrap();
+//!     let batch = req.into_inner().try_collect::<Vec<_>>().await?;
+//!     // the client has half-closed the call, and is waiting for the results
+//!     for result in process(batch) {
+//!         tokio::select! {
+//!             _ = token.cancelled() => return Ok(()),
+//!             sent = tx.send(Ok(result.await)) => sent?,
+//!         }
+//!     }
+//!     Ok(())
+//! }
+//! ```
+//!
+//! The half-close is observed when the request stream is read to its end, and the cancellation
+//! when the request stream fails by the reset, or the response stream is dropped before its end.
+
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use bytes::Bytes;
+use futures::Stream;
+use http_body::{Body, Frame, SizeHint};
+use tokio::sync::Notify;
+
+use crate::{body::BoxBody, BoxStream, Status};
+
+/// The state of the requests of a call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallState {
+    /// The client may still send messages.
+    Open,
+    /// The client has sent all its messages, and the call goes on until the method ends the
+    /// response stream.
+    HalfClosed,
+    /// The call is cancelled, e.g. reset by the client, whether it has half-closed or not.
+    Cancelled,
+}
+
+/// The token of a call telling the half-close of the client from the cancellation, see the
+/// [module docs](self).
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    half_closed: AtomicBool,
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn state(&self) -> CallState {
+        if self.is_cancelled() {
+            CallState::Cancelled
+        } else if self.is_half_closed() {
+            CallState::HalfClosed
+        } else {
+            CallState::Open
+        }
+    }
+
+    /// Returns if the client has half-closed the call, which stays `true` after the call is
+    /// cancelled.
+    pub fn is_half_closed(&self) -> bool {
+        self.inner.half_closed.load(Ordering::Acquire)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Marks that the client has sent all its messages.
+    pub fn half_close(&self) {
+        if !self.inner.half_closed.swap(true, Ordering::AcqRel) {
+            self.inner.notify.notify_waiters();
+        }
+    }
+
+    /// Cancels the call, e.g. by a layer giving up the call.
+    pub fn cancel(&self) {
+        if !self.inner.cancelled.swap(true, Ordering::AcqRel) {
+            self.inner.notify.notify_waiters();
+        }
+    }
+
+    /// Waits until the client half-closes the call or the call is cancelled, and returns the
+    /// state then.
+    pub async fn half_closed(&self) -> CallState {
+        self.wait(|token| token.state() != CallState::Open).await;
+        self.state()
+    }
+
+    /// Waits until the call is cancelled.
+    pub async fn cancelled(&self) {
+        self.wait(CancellationToken::is_cancelled).await
+    }
+
+    async fn wait(&self, done: impl Fn(&Self) -> bool) {
+        loop {
+            let notified = self.inner.notify.notified();
+            if done(self) {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// The body of the requests updating the [`CancellationToken`] by its end.
+pub(crate) struct ObservedBody {
+    inner: BoxBody,
+    token: CancellationToken,
+}
+
+impl ObservedBody {
+    pub(crate) fn new(inner: BoxBody, token: CancellationToken) -> Self {
+        Self { inner, token }
+    }
+}
+
+impl Body for ObservedBody {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let poll = Pin::new(&mut self.inner).poll_frame(cx);
+        match &poll {
+            Poll::Ready(None) => self.token.half_close(),
+            Poll::Ready(Some(Ok(frame))) if frame.is_trailers() => self.token.half_close(),
+            Poll::Ready(Some(Err(_))) => self.token.cancel(),
+            _ => {}
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// The stream of the responses cancelling the [`CancellationToken`] if it is dropped before its
+/// end, i.e. the transport gives up the call.
+pub(crate) struct CancelOnDrop<T> {
+    inner: BoxStream<'static, T>,
+    token: CancellationToken,
+    ended: bool,
+}
+
+impl<T> CancelOnDrop<T> {
+    pub(crate) fn new(inner: BoxStream<'static, T>, token: CancellationToken) -> Self {
+        Self {
+            inner,
+            token,
+            ended: false,
+        }
+    }
+}
+
+impl<T> Stream for CancelOnDrop<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let poll = self.inner.as_mut().poll_next(cx);
+        if let Poll::Ready(None) = poll {
+            self.ended = true;
+        }
+        poll
+    }
+}
+
+impl<T> Drop for CancelOnDrop<T> {
+    fn drop(&mut self) {
+        if !self.ended {
+            self.token.cancel();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, time::Duration};
+
+    use futures::{stream, StreamExt, TryStreamExt};
+    use hyper_util::{client::legacy::Client, rt::TokioExecutor};
+    use motore::service::Service;
+    use tokio::sync::mpsc;
+    use tonic::codec::ProstCodec;
+    use volo::{
+        context::{Context as _, Role, RpcInfo},
+        net::{incoming::DefaultIncoming, Address},
+        FastStr,
+    };
+
+    use super::*;
+    use crate::{
+        client::sender::{RequestSender, RequestStream},
+        codec::{
+            compression::CompressionEncoding,
+            decode::{DecodeLimits, Kind},
+            encode::encode,
+        },
+        codegen::request_stream,
+        context::{ClientContext, Config, ServerContext},
+        server::{sender, NamedService, Server, ServiceBuilder},
+        transport::ClientTransport,
+        Code, RecvEntryMessage, RecvStream, Request, Response, SendEntryMessage,
+    };
+
+    /// The messages sent of `/test.Chat/Chat`, as generated for the requests of the client and
+    /// the responses of the server.
+    enum Outgoing {
+        Chat(BoxStream<'static, Result<String, Status>>),
+    }
+
+    impl SendEntryMessage for Outgoing {
+        fn into_body(
+            self,
+            compression_encoding: Option<CompressionEncoding>,
+        ) -> BoxStream<'static, Result<Frame<Bytes>, Status>> {
+            match self {
+                Self::Chat(s) => encode(s, compression_encoding),
+            }
+        }
+    }
+
+    /// The messages received of `/test.Chat/Chat`.
+    enum Incoming {
+        Chat(RecvStream<String>),
+    }
+
+    impl RecvEntryMessage for Incoming {
+        fn from_body(
+            _method: Option<&str>,
+            body: BoxBody,
+            kind: Kind,
+            compression_encoding: Option<CompressionEncoding>,
+            limits: DecodeLimits,
+        ) -> Result<Self, Status> {
+            Ok(Self::Chat(RecvStream::new(
+                body,
+                kind,
+                compression_encoding,
+                limits,
+            )))
+        }
+    }
+
+    #[derive(Clone, Copy)]
+    enum Mode {
+        /// Streams the messages every second after the half-close of the client.
+        Stream(usize),
+        /// Streams the messages every second after the half-close until the cancellation.
+        Forever,
+        /// Responds to the first message without waiting for the half-close.
+        Early,
+    }
+
+    /// Reports the states of the calls when the requests are received and when the method ends.
+    #[derive(Clone)]
+    struct Chat {
+        mode: Mode,
+        states: mpsc::UnboundedSender<CallState>,
+    }
+
+    impl NamedService for Chat {
+        const NAME: &'static str = "test.Chat";
+    }
+
+    impl Service<ServerContext, Request<Incoming>> for Chat {
+        type Response = Response<Outgoing>;
+        type Error = Status;
+
+        async fn call<'s, 'cx>(
+            &'s self,
+            cx: &'cx mut ServerContext,
+            req: Request<Incoming>,
+        ) -> Result<Self::Response, Self::Error> {
+            let token = cx.cancellation_token().cloned().unwrap();
+            assert_eq!(
+                req.extensions().get::<CancellationToken>().unwrap().state(),
+                CallState::Open
+            );
+            let Incoming::Chat(mut requests) = req.into_inner();
+            let (mode, states) = (self.mode, self.states.clone());
+            let messages = sender::spawn(move |tx: sender::Sender<String>| async move {
+                if let Mode::Early = mode {
+                    let first = requests.next().await.unwrap()?;
+                    tx.send(Ok(format!("done, {first}"))).await?;
+                    let _ = states.send(token.state());
+                    return Ok(());
+                }
+
+                let batch = (&mut requests).try_collect::<Vec<_>>().await?;
+                let _ = states.send(token.state());
+                let mut sent = 0;
+                while !matches!(mode, Mode::Stream(n) if sent == n) {
+                    tokio::select! {
+                        _ = token.cancelled() => break,
+                        _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+                    }
+                    if tx
+                        .send(Ok(format!("{sent}: {}", batch.join(","))))
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                    sent += 1;
+                }
+                // the token may be cancelled after the response stream is dropped
+                if tx.is_closed() {
+                    token.cancelled().await;
+                }
+                let _ = states.send(token.state());
+                Ok(())
+            });
+            Ok(Response::new(Outgoing::Chat(messages)))
+        }
+    }
+
+    async fn serve(mode: Mode) -> (SocketAddr, mpsc::UnboundedReceiver<CallState>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (states, rx) = mpsc::unbounded_channel();
+        let server = Server::new()
+            .add_service(ServiceBuilder::new(Chat { mode, states }).build::<Incoming, Outgoing>());
+        tokio::spawn(server.run(DefaultIncoming::from(listener)));
+        (addr, rx)
+    }
+
+    #[derive(Clone, Copy, Debug)]
+    enum Peer {
+        Volo,
+        Tonic,
+    }
+
+    /// Calls `/test.Chat/Chat` by the client of the peer with the requests, and returns the
+    /// responses.
+    async fn chat(
+        peer: Peer,
+        addr: SocketAddr,
+        requests: RequestStream<String>,
+    ) -> stream::BoxStream<'static, Result<String, Status>> {
+        match peer {
+            Peer::Volo => {
+                let transport = ClientTransport::<Incoming>::new(
+                    &crate::client::Http2Config::default(),
+                    &Config::default(),
+                );
+                let mut cx = ClientContext::new(RpcInfo::with_role(Role::Client));
+                cx.rpc_info_mut()
+                    .callee_mut()
+                    .set_address(Address::from(addr));
+                cx.rpc_info_mut()
+                    .set_method(FastStr::from_static_str("/test.Chat/Chat"));
+                let req = Request::new(Outgoing::Chat(request_stream(requests)));
+                let Incoming::Chat(resp) = transport.call(&mut cx, req).await.unwrap().into_inner();
+                resp.boxed()
+            }
+            Peer::Tonic => {
+                let channel = Client::builder(TokioExecutor::new())
+                    .http2_only(true)
+                    .build_http::<tonic::body::BoxBody>();
+                let origin: http::Uri = format!("http://{addr}").parse().unwrap();
+                let mut grpc = tonic::client::Grpc::with_origin(channel, origin);
+                grpc.ready().await.unwrap();
+                let resp = grpc
+                    .streaming(
+                        tonic::Request::new(requests),
+                        http::uri::PathAndQuery::from_static("/test.Chat/Chat"),
+                        ProstCodec::<String, String>::default(),
+                    )
+                    .await
+                    .unwrap();
+                resp.into_inner()
+                    .map_err(|s| Status::new(Code::from(s.code() as i32), s.message()))
+                    .boxed()
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stream_after_half_close() {
+        for peer in [Peer::Volo, Peer::Tonic] {
+            let (addr, mut states) = serve(Mode::Stream(30)).await;
+            let (tx, requests) = RequestSender::channel(4);
+            let responses = chat(peer, addr, requests).await;
+            tx.send("a".to_owned()).await.unwrap();
+            tx.send("b".to_owned()).await.unwrap();
+            tx.finish();
+
+            // the server streams for 30s after the end of the requests
+            let start = tokio::time::Instant::now();
+            let responses = responses.try_collect::<Vec<_>>().await.unwrap();
+            assert!(start.elapsed() >= Duration::from_secs(30), "{peer:?}");
+            assert_eq!(responses.len(), 30, "{peer:?}");
+            assert_eq!(responses[29], "29: a,b");
+            assert_eq!(states.recv().await, Some(CallState::HalfClosed));
+            assert_eq!(states.recv().await, Some(CallState::HalfClosed));
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cancel_after_half_close() {
+        for peer in [Peer::Volo, Peer::Tonic] {
+            let (addr, mut states) = serve(Mode::Forever).await;
+            let (tx, requests) = RequestSender::channel(4);
+            let mut responses = chat(peer, addr, requests).await;
+            tx.send("a".to_owned()).await.unwrap();
+            tx.finish();
+
+            assert_eq!(responses.next().await.unwrap().unwrap(), "0: a");
+            assert_eq!(responses.next().await.unwrap().unwrap(), "1: a");
+            assert_eq!(states.recv().await, Some(CallState::HalfClosed));
+            // dropping the responses resets the call
+            drop(responses);
+            assert_eq!(states.recv().await, Some(CallState::Cancelled));
+        }
+    }
+
+    #[tokio::test]
+    async fn complete_before_half_close() {
+        for peer in [Peer::Volo, Peer::Tonic] {
+            let (addr, mut states) = serve(Mode::Early).await;
+            let (tx, requests) = RequestSender::channel(4);
+            let responses = chat(peer, addr, requests).await;
+            tx.send("a".to_owned()).await.unwrap();
+
+            // the call ends with the responses while the client may still send
+            let responses = responses.try_collect::<Vec<_>>().await.unwrap();
+            assert_eq!(responses, ["done, a"]);
+            assert_eq!(states.recv().await, Some(CallState::Open));
+            tx.finish();
+        }
+    }
+
+    #[tokio::test]
+    async fn token() {
+        let token = CancellationToken::new();
+        assert_eq!(token.state(), CallState::Open);
+
+        let half_closed = tokio::spawn({
+            let token = token.clone();
+            async move { token.half_closed().await }
+        });
+        let cancelled = tokio::spawn({
+            let token = token.clone();
+            async move { token.cancelled().await }
+        });
+        token.half_close();
+        assert_eq!(half_closed.await.unwrap(), CallState::HalfClosed);
+        assert!(!cancelled.is_finished());
+
+        // the client may cancel the call after the half-close
+        token.cancel();
+        cancelled.await.unwrap();
+        assert!(token.is_half_closed());
+        assert_eq!(token.state(), CallState::Cancelled);
+    }
+}
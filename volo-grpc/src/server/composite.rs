@@ -0,0 +1,175 @@
+//! Composing the implementation of a service from multiple parts.
+//!
+//! With the `composite_server` option of `volo-build`, the code generator emits a handler trait
+//! and a marker type for each method of the service, and a `{Service}Composite` builder, in
+//! which different structs register the handlers of different subsets of the methods:
+//!
+//! ```rust,ignore
+//! let composite = GreeterComposite::new()
+//!     .register::<GreeterSayHelloFn>(HelloHandlers::new(db.clone()))
+//!     .register::<GreeterSayByeFn>(ByeHandlers::new(db));
+//!
+//! Server::new()
+//!     .add_service(ServiceBuilder::new(GreeterServer::new(composite)).build())
+//!     .run(addr)
+//!     .await
+//!     .unwrap();
+//! ```
+//!
+//! The composite implements the service trait, so it is served in the same way as a single
+//! implementation. The methods without a registered handler return `UNIMPLEMENTED`, and
+//! registering a method twice panics when constructing the composite.
+//!
+//! The state shared between the parts is up to the user, e.g. by sharing an `Arc`.
+
+use std::{fmt, future::Future, sync::Arc};
+
+use futures::future::BoxFuture;
+
+use crate::Status;
+
+/// The error of registering a method which has been registered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateMethod {
+    pub method: &'static str,
+}
+
+impl fmt::Display for DuplicateMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "method {} is registered more than once", self.method)
+    }
+}
+
+impl std::error::Error for DuplicateMethod {}
+
+type BoxHandler<Req, Resp> =
+    Arc<dyn Fn(Req) -> BoxFuture<'static, Result<Resp, Status>> + Send + Sync>;
+
+/// The slot of a method in a composite, which is used by the generated code.
+pub struct Handler<Req, Resp> {
+    method: &'static str,
+    handler: Option<BoxHandler<Req, Resp>>,
+}
+
+impl<Req, Resp> Handler<Req, Resp> {
+    /// Creates an empty slot of the method, e.g. `/helloworld.Greeter/SayHello`.
+    pub fn new(method: &'static str) -> Self {
+        Self {
+            method,
+            handler: None,
+        }
+    }
+
+    /// Returns if a handler is registered.
+    pub fn is_registered(&self) -> bool {
+        self.handler.is_some()
+    }
+
+    /// Registers the handler of the method, which fails if a handler has been registered.
+    pub fn register<F, Fut>(&mut self, f: F) -> Result<(), DuplicateMethod>
+    where
+        F: Fn(Req) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Resp, Status>> + Send + 'static,
+    {
+        if self.handler.is_some() {
+            return Err(DuplicateMethod {
+                method: self.method,
+            });
+        }
+        self.handler = Some(Arc::new(move |req| Box::pin(f(req))));
+        Ok(())
+    }
+
+    /// Calls the registered handler, or returns `UNIMPLEMENTED` if there is none.
+    pub async fn call(&self, req: Req) -> Result<Resp, Status> {
+        match &self.handler {
+            Some(handler) => handler(req).await,
+            None => Err(Status::unimplemented(format!(
+                "method {} is not registered in the composite",
+                self.method
+            ))),
+        }
+    }
+}
+
+impl<Req, Resp> Clone for Handler<Req, Resp> {
+    fn clone(&self) -> Self {
+        Self {
+            method: self.method,
+            handler: self.handler.clone(),
+        }
+    }
+}
+
+impl<Req, Resp> fmt::Debug for Handler<Req, Resp> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Handler")
+            .field("method", &self.method)
+            .field("registered", &self.is_registered())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Code;
+
+    /// What the generated code looks like for a service with two methods.
+    #[derive(Clone)]
+    struct Composite {
+        get_user: Handler<u32, String>,
+        get_order: Handler<u32, String>,
+    }
+
+    impl Composite {
+        fn new() -> Self {
+            Self {
+                get_user: Handler::new("/shop.Shop/GetUser"),
+                get_order: Handler::new("/shop.Shop/GetOrder"),
+            }
+        }
+    }
+
+    struct Users;
+
+    impl Users {
+        async fn get_user(&self, id: u32) -> Result<String, Status> {
+            Ok(format!("user {id}"))
+        }
+    }
+
+    #[tokio::test]
+    async fn dispatch() {
+        let users = Arc::new(Users);
+        let mut composite = Composite::new();
+        composite
+            .get_user
+            .register(move |id| {
+                let users = users.clone();
+                async move { users.get_user(id).await }
+            })
+            .unwrap();
+
+        assert_eq!(composite.get_user.call(1).await.unwrap(), "user 1");
+        let status = composite.clone().get_order.call(1).await.unwrap_err();
+        assert_eq!(status.code(), Code::Unimplemented);
+        assert!(status.message().contains("/shop.Shop/GetOrder"));
+
+        composite
+            .get_order
+            .register(|id| async move { Ok(format!("order {id}")) })
+            .unwrap();
+        assert_eq!(composite.get_order.call(2).await.unwrap(), "order 2");
+        assert_eq!(
+            composite
+                .get_user
+                .register(|_| async { Ok(String::new()) })
+                .unwrap_err(),
+            DuplicateMethod {
+                method: "/shop.Shop/GetUser"
+            }
+        );
+        assert_eq!(composite.get_user.call(3).await.unwrap(), "user 3");
+    }
+}
@@ -0,0 +1,81 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use tokio::time::Instant;
+
+/// Tracks the requests being handled on a connection, for closing the connection after it has
+/// been idle for a while.
+#[derive(Debug)]
+pub(crate) struct IdleTracker {
+    timeout: Duration,
+    active: AtomicUsize,
+    last_active: Mutex<Instant>,
+}
+
+impl IdleTracker {
+    pub(crate) fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            active: AtomicUsize::new(0),
+            last_active: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Marks a request is being handled until the returned guard is dropped.
+    pub(crate) fn enter(self: &Arc<Self>) -> IdleGuard {
+        self.active.fetch_add(1, Ordering::AcqRel);
+        IdleGuard(self.clone())
+    }
+
+    /// Waits until there has been no request for the timeout.
+    pub(crate) async fn idle(&self) {
+        loop {
+            let now = Instant::now();
+            let deadline = if self.active.load(Ordering::Acquire) > 0 {
+                now + self.timeout
+            } else {
+                let deadline = *self.last_active.lock().unwrap() + self.timeout;
+                if deadline <= now {
+                    return;
+                }
+                deadline
+            };
+            tokio::time::sleep_until(deadline).await;
+        }
+    }
+}
+
+pub(crate) struct IdleGuard(Arc<IdleTracker>);
+
+impl Drop for IdleGuard {
+    fn drop(&mut self) {
+        *self.0.last_active.lock().unwrap() = Instant::now();
+        self.0.active.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn idle_after_requests() {
+        let tracker = Arc::new(IdleTracker::new(Duration::from_millis(50)));
+
+        let guard = tracker.enter();
+        let result = tokio::time::timeout(Duration::from_millis(100), tracker.idle()).await;
+        assert!(result.is_err());
+
+        drop(guard);
+        let start = Instant::now();
+        tokio::time::timeout(Duration::from_millis(200), tracker.idle())
+            .await
+            .unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+}
@@ -2,11 +2,20 @@ use std::{cell::RefCell, net::SocketAddr, str::FromStr, sync::Arc};
 
 use hyper::body::Incoming;
 use metainfo::{Backward, Forward};
-use volo::{context::Context, net::Address, FastStr, Service};
+use volo::{
+    context::{
+        identity::{PeerIdentityExt, TlsIdentity, TlsInfo},
+        log_id::{self, LogIdConfig},
+        Context, Deadline, DeadlineExt,
+    },
+    net::{listener::ListenerName, Address},
+    FastStr, Service,
+};
 
 use crate::{
-    body::Body,
+    body::{self, Body, BoxBody},
     context::ServerContext,
+    layer::grpc_timeout::try_parse_client_timeout,
     metadata::{
         KeyAndValueRef, MetadataKey, DESTINATION_SERVICE, HEADER_TRANS_REMOTE_ADDR, SOURCE_SERVICE,
     },
@@ -26,17 +35,55 @@ macro_rules! status_to_http {
 pub struct MetaService<S> {
     inner: S,
     peer_addr: Option<Address>,
+    tls_identity: Option<TlsIdentity>,
+    tls_info: Option<Arc<TlsInfo>>,
+    listener: Option<FastStr>,
 }
 
 impl<S> MetaService<S> {
     pub fn new(inner: S, peer_addr: Option<Address>) -> Self {
-        MetaService { inner, peer_addr }
+        MetaService {
+            inner,
+            peer_addr,
+            tls_identity: None,
+            tls_info: None,
+            listener: None,
+        }
+    }
+
+    /// Sets the identity told by the certificate of the peer, which is set into the
+    /// [`PeerIdentity`](volo::context::identity::PeerIdentity) of each request.
+    ///
+    /// Default is `None`.
+    pub fn tls_identity(mut self, tls_identity: Option<TlsIdentity>) -> Self {
+        self.tls_identity = tls_identity;
+        self
+    }
+
+    /// Sets the parameters negotiated by the TLS handshake of the connection, which is inserted
+    /// into the extensions of the context of each request, see [`TlsInfoExt`].
+    ///
+    /// Default is `None`.
+    ///
+    /// [`TlsInfoExt`]: volo::context::identity::TlsInfoExt
+    pub fn tls_info(mut self, tls_info: Option<Arc<TlsInfo>>) -> Self {
+        self.tls_info = tls_info;
+        self
+    }
+
+    /// Sets the name of the listener accepting the connection, which is inserted into the
+    /// extensions of the context of each request as [`ListenerName`].
+    ///
+    /// Default is `None`.
+    pub fn listener(mut self, listener: Option<FastStr>) -> Self {
+        self.listener = listener;
+        self
     }
 }
 
 impl<S> Service<ServerContext, hyper::Request<Incoming>> for MetaService<S>
 where
-    S: Service<ServerContext, Request<Incoming>, Response = Response<Body>>
+    S: Service<ServerContext, Request<BoxBody>, Response = Response<Body>>
         + Clone
         + Send
         + Sync
@@ -57,8 +104,33 @@ where
         metainfo::METAINFO
             .scope(RefCell::new(metainfo::MetaInfo::default()), async move {
                 cx.rpc_info.set_method(FastStr::new(req.uri().path()));
-
-                let mut volo_req = Request::from_http(req);
+                if let Some(tls_identity) = &self.tls_identity {
+                    cx.peer_identity_mut().set_tls(tls_identity.clone());
+                }
+                if let Some(tls_info) = &self.tls_info {
+                    cx.extensions_mut().insert(tls_info.clone());
+                }
+                if let Some(listener) = &self.listener {
+                    cx.extensions_mut().insert(ListenerName(listener.clone()));
+                }
+
+                // the deadline of the client, which is inherited by the downstream calls
+                let deadline = try_parse_client_timeout(req.headers())
+                    .ok()
+                    .flatten()
+                    .map(Deadline::after);
+                if let Some(deadline) = deadline {
+                    cx.set_deadline(deadline);
+                }
+
+                // the log id of the upstream, or generated at the first hop
+                let received = req
+                    .headers()
+                    .get(LogIdConfig::global().header().as_str())
+                    .and_then(|v| v.to_str().ok());
+                log_id::accept(cx, received);
+
+                let mut volo_req = Request::from_http(req.map(body::boxed));
 
                 let metadata = volo_req.metadata_mut();
 
@@ -68,6 +140,8 @@ where
                     // caller
                     if let Some(source_service) = metadata.remove(SOURCE_SERVICE) {
                         let source_service = Arc::<str>::from(source_service.to_str()?);
+                        cx.peer_identity_mut()
+                            .set_declared(source_service.clone().into());
                         let caller = cx.rpc_info_mut().caller_mut();
                         caller.set_service_name(source_service.into());
                         if let Some(ad) = metadata.remove(HEADER_TRANS_REMOTE_ADDR) {
@@ -115,7 +189,11 @@ where
                 });
                 status_to_http!(status);
 
-                let volo_resp = match self.inner.call(cx, volo_req).await {
+                let resp = match deadline {
+                    Some(deadline) => deadline.scope(self.inner.call(cx, volo_req)).await,
+                    None => self.inner.call(cx, volo_req).await,
+                };
+                let volo_resp = match resp {
                     Ok(resp) => resp,
                     Err(err) => {
                         return Ok(err.into().to_http());
@@ -142,10 +220,10 @@ where
                 let mut resp = hyper::Response::new(message);
                 *resp.headers_mut() = metadata.into_headers();
                 *resp.extensions_mut() = extensions;
-                resp.headers_mut().insert(
-                    http::header::CONTENT_TYPE,
-                    http::header::HeaderValue::from_static("application/grpc"),
-                );
+                // the content type may have been set by the layers, e.g. gRPC-Web
+                resp.headers_mut()
+                    .entry(http::header::CONTENT_TYPE)
+                    .or_insert(http::header::HeaderValue::from_static("application/grpc"));
 
                 Ok(resp)
             })
@@ -2,30 +2,41 @@
 //!
 //! This module contains the low level component to build a gRPC server.
 
+pub mod cancel;
+pub mod composite;
+mod idle;
 mod meta;
 mod router;
+pub mod sender;
 mod service;
 
-use std::{fmt, io, time::Duration};
+use std::{fmt, future, io, sync::Arc, time::Duration};
 
-use hyper::{body::Incoming as BodyIncoming, server::conn::http2};
+pub use cancel::{CallState, CancellationToken};
+use hyper::server::conn::http2;
 use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
 use motore::{
     layer::{Identity, Layer, Stack},
     service::Service,
     BoxError,
 };
+pub use sender::Sender;
 pub use service::ServiceBuilder;
 #[cfg(feature = "__tls")]
 use volo::net::tls::{Acceptor, ServerTlsConfig};
 use volo::{
+    config::ConfigError,
     net::{conn::Conn, incoming::Incoming},
+    server::ServerHandle,
     spawn,
 };
 
 pub use self::router::Router;
 use crate::{
-    body::Body, context::ServerContext, server::meta::MetaService, Request, Response, Status,
+    body::{Body, BoxBody},
+    context::ServerContext,
+    server::{idle::IdleTracker, meta::MetaService},
+    transport, Request, Response, Status,
 };
 
 /// A trait to provide a static reference to the service's
@@ -43,6 +54,7 @@ pub struct Server<L> {
     layer: L,
     http2_config: Http2Config,
     router: Router,
+    handle: ServerHandle,
 
     #[cfg(feature = "__tls")]
     tls_config: Option<ServerTlsConfig>,
@@ -61,6 +73,7 @@ impl Server<Identity> {
             layer: Identity::new(),
             http2_config: Http2Config::default(),
             router: Router::new(),
+            handle: ServerHandle::new(),
 
             #[cfg(feature = "__tls")]
             tls_config: None,
@@ -140,6 +153,36 @@ impl<L> Server<L> {
         self
     }
 
+    /// Sets the maximum age of the connections, after which the connections will be closed
+    /// gracefully by sending GOAWAY, and the clients will reconnect for the new requests.
+    ///
+    /// This is useful for rebalancing the long-lived connections, e.g. after scaling out the
+    /// servers behind an L4 load balancer.
+    ///
+    /// Default is no limit (`None`).
+    pub fn max_connection_age(mut self, age: impl Into<Option<Duration>>) -> Self {
+        self.http2_config.max_connection_age = age.into();
+        self
+    }
+
+    /// Sets the grace period for the in-flight requests to finish after the connection reaches
+    /// the [`Server::max_connection_age`], after which the connection will be closed forcibly.
+    ///
+    /// Default is to wait for all the in-flight requests (`None`).
+    pub fn max_connection_age_grace(mut self, grace: impl Into<Option<Duration>>) -> Self {
+        self.http2_config.max_connection_age_grace = grace.into();
+        self
+    }
+
+    /// Sets the maximum time a connection can be idle, i.e. without any request being handled,
+    /// after which the connection will be closed gracefully by sending GOAWAY.
+    ///
+    /// Default is no limit (`None`).
+    pub fn max_connection_idle(mut self, idle: impl Into<Option<Duration>>) -> Self {
+        self.http2_config.max_connection_idle = idle.into();
+        self
+    }
+
     /// Sets the maximum frame size to use for HTTP2.
     ///
     /// Passing `None` will do nothing.
@@ -200,6 +243,7 @@ impl<L> Server<L> {
             layer: Stack::new(layer, self.layer),
             http2_config: self.http2_config,
             router: self.router,
+            handle: self.handle,
             #[cfg(feature = "__tls")]
             tls_config: self.tls_config,
         }
@@ -221,15 +265,21 @@ impl<L> Server<L> {
             layer: Stack::new(self.layer, layer),
             http2_config: self.http2_config,
             router: self.router,
+            handle: self.handle,
             #[cfg(feature = "__tls")]
             tls_config: self.tls_config,
         }
     }
 
+    /// Returns the handle to shut down the server after it runs, see [`ServerHandle`].
+    pub fn handle(&self) -> ServerHandle {
+        self.handle.clone()
+    }
+
     /// Adds a new service to the router.
     pub fn add_service<S>(self, s: S) -> Self
     where
-        S: Service<ServerContext, Request<BodyIncoming>, Response = Response<Body>, Error = Status>
+        S: Service<ServerContext, Request<BoxBody>, Response = Response<Body>, Error = Status>
             + NamedService
             + Clone
             + Send
@@ -240,11 +290,25 @@ impl<L> Server<L> {
             layer: self.layer,
             http2_config: self.http2_config,
             router: self.router.add_service(s),
+            handle: self.handle,
             #[cfg(feature = "__tls")]
             tls_config: self.tls_config,
         }
     }
 
+    /// Checks the configuration, which is called when the server starts running.
+    fn check(&self, err: &mut ConfigError) {
+        self.http2_config.check(err);
+        #[cfg(feature = "__tls")]
+        if let Some(tls_config) = &self.tls_config {
+            err.ensure(
+                tls_config.acceptor.has_certs(),
+                "tls_config",
+                "no certificate to serve the connections",
+            );
+        }
+    }
+
     /// The main entry point for the server.
     /// Runs server with a stop signal to control graceful shutdown.
     pub async fn run_with_shutdown<
@@ -257,13 +321,17 @@ impl<L> Server<L> {
     ) -> Result<(), BoxError>
     where
         L: Layer<Router>,
-        L::Service: Service<ServerContext, Request<BodyIncoming>, Response = Response<Body>>
+        L::Service: Service<ServerContext, Request<BoxBody>, Response = Response<Body>>
             + Clone
             + Send
             + Sync
             + 'static,
-        <L::Service as Service<ServerContext, Request<BodyIncoming>>>::Error: Into<Status> + Send,
+        <L::Service as Service<ServerContext, Request<BoxBody>>>::Error: Into<Status> + Send,
     {
+        let mut err = ConfigError::new();
+        self.check(&mut err);
+        err.into_result()?;
+
         let mut incoming = incoming.make_incoming().await?;
         tracing::info!("[VOLO] server start at: {:?}", incoming);
 
@@ -272,18 +340,17 @@ impl<L> Server<L> {
             .service(self.router);
 
         tokio::pin!(signal);
-        let (tx, rx) = tokio::sync::watch::channel(());
+        // every connection holds a receiver, so the receivers are counted as the connections
+        let tx = Arc::new(tokio::sync::watch::channel(()).0);
+        let _serving = self.handle.serving({
+            let tx = tx.clone();
+            move || tx.receiver_count()
+        });
 
         loop {
-            tokio::select! {
-                _ = &mut signal => {
-                    drop(rx);
-                    tracing::info!("[VOLO] graceful shutdown");
-                    let _ = tx.send(());
-                    // Waits for receivers to drop.
-                    tx.closed().await;
-                    return Ok(());
-                },
+            let deadline = tokio::select! {
+                _ = &mut signal => None,
+                deadline = self.handle.requested() => deadline,
                 conn = incoming.accept() => {
                     let conn: Conn = match conn? {
                         Some(c) => c,
@@ -320,7 +387,17 @@ impl<L> Server<L> {
                     tracing::trace!("[VOLO] recv a connection from: {:?}", conn.info.peer_addr);
                     let peer_addr = conn.info.peer_addr.clone();
 
-                    let service = MetaService::new(service.clone(), peer_addr);
+                    #[cfg(feature = "__tls")]
+                    let tls_info = match self.tls_config.as_ref() {
+                        Some(tls_config) => tls_config.acceptor.tls_info(&conn.stream),
+                        None => conn.stream.tls_info(),
+                    };
+                    #[cfg(not(feature = "__tls"))]
+                    let tls_info = conn.stream.tls_info();
+                    let service = MetaService::new(service.clone(), peer_addr)
+                        .tls_identity(tls_info.as_ref().and_then(|info| info.identity().cloned()))
+                        .tls_info(tls_info)
+                        .listener(conn.info.listener.clone());
 
                     // init server
                     let mut server = http2::Builder::new(TokioExecutor::new());
@@ -335,18 +412,39 @@ impl<L> Server<L> {
                         .max_send_buf_size(self.http2_config.max_send_buf_size)
                         .max_header_list_size(self.http2_config.max_header_list_size);
 
-                    let mut watch = rx.clone();
+                    let max_age = self.http2_config.max_connection_age;
+                    let max_age_grace = self.http2_config.max_connection_age_grace;
+                    let idle = self.http2_config.max_connection_idle.map(|timeout| Arc::new(IdleTracker::new(timeout)));
+
+                    let mut watch = tx.subscribe();
                     spawn(async move {
+                        let tracker = idle.clone();
                         let mut http_conn = server.serve_connection(
                             TokioIo::new(conn),
                             hyper::service::service_fn(move |req| {
                                 let mut cx = ServerContext::default();
+                                cx.stats.record_arrive_at();
                                 let service = service.clone();
+                                let guard = tracker.as_ref().map(|tracker| tracker.enter());
                                 async move {
-                                    service.call(&mut cx, req).await
+                                    let resp = service.call(&mut cx, req).await;
+                                    drop(guard);
+                                    resp
                                 }
                             })
                         );
+                        let age = async {
+                            match max_age {
+                                Some(age) => tokio::time::sleep(age).await,
+                                None => future::pending().await,
+                            }
+                        };
+                        let idle = async {
+                            match &idle {
+                                Some(idle) => idle.idle().await,
+                                None => future::pending().await,
+                            }
+                        };
                         tokio::select! {
                             _ = watch.changed() => {
                                 tracing::trace!("[VOLO] closing a pending connection");
@@ -358,6 +456,30 @@ impl<L> Server<L> {
                                     tracing::debug!("[VOLO] connection error: {:?}", err);
                                 }
                             },
+                            _ = age => {
+                                tracing::trace!("[VOLO] closing a connection reaching the max age");
+                                http2::Connection::graceful_shutdown(Pin::new(&mut http_conn));
+                                let result = match max_age_grace {
+                                    Some(grace) => match tokio::time::timeout(grace, &mut http_conn).await {
+                                        Ok(result) => result,
+                                        Err(_) => {
+                                            tracing::debug!("[VOLO] closing a connection after the grace period");
+                                            return;
+                                        }
+                                    },
+                                    None => http_conn.await,
+                                };
+                                if let Err(err) = result {
+                                    tracing::debug!("[VOLO] connection error: {:?}", err);
+                                }
+                            },
+                            _ = idle => {
+                                tracing::trace!("[VOLO] closing an idle connection");
+                                http2::Connection::graceful_shutdown(Pin::new(&mut http_conn));
+                                if let Err(err) = http_conn.await {
+                                    tracing::debug!("[VOLO] connection error: {:?}", err);
+                                }
+                            },
                             result = &mut http_conn => {
                                 if let Err(err) = result {
                                     tracing::debug!("[VOLO] connection error: {:?}", err);
@@ -365,8 +487,28 @@ impl<L> Server<L> {
                             },
                         }
                     });
+                    continue;
                 },
+            };
+
+            tracing::info!("[VOLO] graceful shutdown");
+            let _ = tx.send(());
+            // Waits for receivers to drop.
+            match deadline {
+                Some(deadline) => {
+                    if tokio::time::timeout_at(deadline, tx.closed())
+                        .await
+                        .is_err()
+                    {
+                        tracing::info!(
+                            "[VOLO] graceful shutdown timed out, {} connections left",
+                            tx.receiver_count()
+                        );
+                    }
+                }
+                None => tx.closed().await,
             }
+            return Ok(());
         }
     }
 
@@ -374,12 +516,12 @@ impl<L> Server<L> {
     pub async fn run<A: volo::net::MakeIncoming>(self, incoming: A) -> Result<(), BoxError>
     where
         L: Layer<Router>,
-        L::Service: Service<ServerContext, Request<BodyIncoming>, Response = Response<Body>>
+        L::Service: Service<ServerContext, Request<BoxBody>, Response = Response<Body>>
             + Clone
             + Send
             + Sync
             + 'static,
-        <L::Service as Service<ServerContext, Request<BodyIncoming>>>::Error: Into<Status> + Send,
+        <L::Service as Service<ServerContext, Request<BoxBody>>>::Error: Into<Status> + Send,
     {
         self.run_with_shutdown(incoming, tokio::signal::ctrl_c())
             .await
@@ -413,6 +555,9 @@ pub struct Http2Config {
     pub(crate) max_frame_size: Option<u32>,
     pub(crate) max_send_buf_size: usize,
     pub(crate) max_header_list_size: u32,
+    pub(crate) max_connection_age: Option<Duration>,
+    pub(crate) max_connection_age_grace: Option<Duration>,
+    pub(crate) max_connection_idle: Option<Duration>,
 }
 
 impl Default for Http2Config {
@@ -427,6 +572,439 @@ impl Default for Http2Config {
             max_frame_size: None,
             max_send_buf_size: DEFAULT_MAX_SEND_BUF_SIZE,
             max_header_list_size: DEFAULT_SETTINGS_MAX_HEADER_LIST_SIZE,
+            max_connection_age: None,
+            max_connection_age_grace: None,
+            max_connection_idle: None,
+        }
+    }
+}
+
+impl Http2Config {
+    pub(crate) fn check(&self, err: &mut ConfigError) {
+        transport::check_window_size(
+            err,
+            "http2_init_stream_window_size",
+            self.init_stream_window_size,
+        );
+        transport::check_window_size(
+            err,
+            "http2_init_connection_window_size",
+            self.init_connection_window_size,
+        );
+        if let Some(size) = self.max_frame_size {
+            transport::check_frame_size(err, size);
         }
+        transport::check_send_buf_size(err, self.max_send_buf_size);
+        err.ensure_non_zero("http2_keepalive_interval", self.http2_keepalive_interval);
+        err.ensure_non_zero(
+            "http2_keepalive_timeout",
+            Some(self.http2_keepalive_timeout),
+        );
+        err.ensure_non_zero("max_connection_age", self.max_connection_age);
+        err.ensure_non_zero("max_connection_idle", self.max_connection_idle);
+        err.ensure(
+            self.max_connection_age_grace.is_none() || self.max_connection_age.is_some(),
+            "max_connection_age_grace",
+            "is set without `max_connection_age`",
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashSet,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    };
+
+    use bytes::Bytes;
+    use http_body::Frame;
+    use http_body_util::{BodyExt, Full};
+    use hyper_util::{client::legacy::Client, rt::TokioExecutor};
+    use volo::{
+        context::Context,
+        net::{
+            incoming::DefaultIncoming,
+            listener::{Listener, ListenerName, Listeners},
+            Address,
+        },
+    };
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Echo {
+        peers: Arc<Mutex<HashSet<Address>>>,
+        delays: Arc<Mutex<Vec<Duration>>>,
+        listeners: Arc<Mutex<Vec<ListenerName>>>,
+    }
+
+    impl NamedService for Echo {
+        const NAME: &'static str = "test.Echo";
+    }
+
+    impl Service<ServerContext, Request<BoxBody>> for Echo {
+        type Response = Response<Body>;
+        type Error = Status;
+
+        async fn call<'s, 'cx>(
+            &'s self,
+            cx: &'cx mut ServerContext,
+            _req: Request<BoxBody>,
+        ) -> Result<Self::Response, Self::Error> {
+            cx.stats.record_process_start_at();
+            if let Some(delay) = cx.stats.pre_process_delay() {
+                self.delays.lock().unwrap().push(delay);
+            }
+            if let Some(addr) = &cx.rpc_info.caller().address {
+                self.peers.lock().unwrap().insert(addr.clone());
+            }
+            if let Some(listener) = cx.extensions().get::<ListenerName>() {
+                self.listeners.lock().unwrap().push(listener.clone());
+            }
+            Ok(Response::new(Body::new(Box::pin(futures::stream::once(
+                async { Ok(Frame::data(Bytes::from_static(b"pong"))) },
+            )))))
+        }
+    }
+
+    /// Produces the messages of 1 KiB without an end, counting the ones sent.
+    #[derive(Clone, Default)]
+    struct Produce {
+        sent: Arc<AtomicUsize>,
+    }
+
+    impl NamedService for Produce {
+        const NAME: &'static str = "test.Produce";
+    }
+
+    impl Service<ServerContext, Request<BoxBody>> for Produce {
+        type Response = Response<Body>;
+        type Error = Status;
+
+        async fn call<'s, 'cx>(
+            &'s self,
+            _cx: &'cx mut ServerContext,
+            _req: Request<BoxBody>,
+        ) -> Result<Self::Response, Self::Error> {
+            let sent = self.sent.clone();
+            let messages = sender::spawn(move |tx: sender::Sender<String>| async move {
+                loop {
+                    tx.send(Ok("x".repeat(1024))).await?;
+                    sent.fetch_add(1, Ordering::Relaxed);
+                }
+            });
+            Ok(Response::new(Body::new(crate::codec::encode::encode(
+                messages, None,
+            ))))
+        }
+    }
+
+    /// Blocks the executor before calling the inner service.
+    #[derive(Clone)]
+    struct Block<S> {
+        inner: S,
+        delay: Duration,
+    }
+
+    impl<S> Service<ServerContext, Request<BoxBody>> for Block<S>
+    where
+        S: Service<ServerContext, Request<BoxBody>> + Send + Sync,
+    {
+        type Response = S::Response;
+        type Error = S::Error;
+
+        async fn call<'s, 'cx>(
+            &'s self,
+            cx: &'cx mut ServerContext,
+            req: Request<BoxBody>,
+        ) -> Result<Self::Response, Self::Error> {
+            std::thread::sleep(self.delay);
+            self.inner.call(cx, req).await
+        }
+    }
+
+    struct BlockLayer(Duration);
+
+    impl<S> Layer<S> for BlockLayer {
+        type Service = Block<S>;
+
+        fn layer(self, inner: S) -> Self::Service {
+            Block {
+                inner,
+                delay: self.0,
+            }
+        }
+    }
+
+    #[test]
+    fn check_config() {
+        type Case = (fn(&mut Http2Config), &'static [&'static str]);
+        let cases: &[Case] = &[
+            (|_| {}, &[]),
+            (
+                |h2| h2.max_frame_size = Some(1 << 24),
+                &["http2_max_frame_size"],
+            ),
+            (
+                |h2| {
+                    h2.init_connection_window_size = 1 << 31;
+                    h2.http2_keepalive_timeout = Duration::ZERO;
+                    h2.max_connection_idle = Some(Duration::ZERO);
+                    h2.max_connection_age_grace = Some(Duration::from_secs(1));
+                },
+                &[
+                    "http2_init_connection_window_size",
+                    "http2_keepalive_timeout",
+                    "max_connection_idle",
+                    "max_connection_age_grace",
+                ],
+            ),
+        ];
+        for (set, fields) in cases {
+            let mut h2 = Http2Config::default();
+            set(&mut h2);
+
+            let mut err = ConfigError::new();
+            h2.check(&mut err);
+            let problems = err.problems().iter().map(|p| p.field).collect::<Vec<_>>();
+            assert_eq!(problems, *fields, "{err}");
+        }
+    }
+
+    #[cfg(feature = "rustls")]
+    #[test]
+    fn tls_without_certs() {
+        use volo::net::tls::SniConfig;
+
+        let server = Server::new().tls_config(ServerTlsConfig::from_sni(SniConfig::new()));
+        let mut err = ConfigError::new();
+        server.check(&mut err);
+        let problems = err.problems().iter().map(|p| p.field).collect::<Vec<_>>();
+        assert_eq!(problems, ["tls_config"], "{err}");
+    }
+
+    #[tokio::test]
+    async fn reconnect_after_max_connection_age() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let echo = Echo::default();
+        let server = Server::new()
+            .max_connection_age(Duration::from_millis(100))
+            .max_connection_age_grace(Duration::from_secs(1))
+            .add_service(echo.clone());
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        tokio::spawn(
+            server.run_with_shutdown(DefaultIncoming::from(listener), async move {
+                let _ = rx.await;
+                Ok(())
+            }),
+        );
+
+        let client = Client::builder(TokioExecutor::new())
+            .http2_only(true)
+            .build_http::<Full<Bytes>>();
+        for _ in 0..10 {
+            let req = hyper::Request::post(format!("http://{addr}/test.Echo/Call"))
+                .header("content-type", "application/grpc")
+                .header("te", "trailers")
+                .header("source-service", "test")
+                .body(Full::new(Bytes::new()))
+                .unwrap();
+            let resp = client.request(req).await.unwrap();
+            assert_eq!(resp.status(), http::StatusCode::OK);
+            let body = resp.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(body, "pong");
+            tokio::time::sleep(Duration::from_millis(30)).await;
+        }
+
+        // no request is lost, and the client reconnected after the connections expired
+        assert!(echo.peers.lock().unwrap().len() >= 2);
+        let _ = tx.send(());
+    }
+
+    #[tokio::test]
+    async fn pre_process_delay() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let delay = Duration::from_millis(50);
+        let echo = Echo::default();
+        let server = Server::new()
+            .layer_front(BlockLayer(delay))
+            .add_service(echo.clone());
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        tokio::spawn(
+            server.run_with_shutdown(DefaultIncoming::from(listener), async move {
+                let _ = rx.await;
+                Ok(())
+            }),
+        );
+
+        let client = Client::builder(TokioExecutor::new())
+            .http2_only(true)
+            .build_http::<Full<Bytes>>();
+        for _ in 0..3 {
+            let req = hyper::Request::post(format!("http://{addr}/test.Echo/Call"))
+                .header("content-type", "application/grpc")
+                .header("te", "trailers")
+                .body(Full::new(Bytes::new()))
+                .unwrap();
+            let resp = client.request(req).await.unwrap();
+            assert_eq!(resp.status(), http::StatusCode::OK);
+        }
+
+        let delays = echo.delays.lock().unwrap().clone();
+        assert_eq!(delays.len(), 3);
+        for d in delays {
+            assert!(d >= delay && d < delay + Duration::from_millis(40), "{d:?}");
+        }
+        let _ = tx.send(());
+    }
+
+    #[tokio::test]
+    async fn flow_control_backpressure() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let produce = Produce::default();
+        let server = Server::new().add_service(produce.clone());
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        tokio::spawn(
+            server.run_with_shutdown(DefaultIncoming::from(listener), async move {
+                let _ = rx.await;
+                Ok(())
+            }),
+        );
+
+        let client = Client::builder(TokioExecutor::new())
+            .http2_only(true)
+            .http2_initial_stream_window_size(64 * 1024)
+            .build_http::<Full<Bytes>>();
+        let resp = client
+            .request(echo_req(&format!("http://{addr}/test.Produce/Call")))
+            .await
+            .unwrap();
+
+        // the client doesn't read, so the method pends once the window and the buffer are full
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let stalled = produce.sent.load(Ordering::Relaxed);
+        assert!(stalled <= 64 + sender::DEFAULT_BUFFER + 2, "{stalled}");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(produce.sent.load(Ordering::Relaxed), stalled);
+
+        // reading releases the window
+        let mut body = resp.into_body();
+        let mut read = 0;
+        while read < 256 * 1024 {
+            let frame = body.frame().await.unwrap().unwrap();
+            read += frame.into_data().map(|data| data.len()).unwrap_or_default();
+        }
+        assert!(produce.sent.load(Ordering::Relaxed) > stalled + 100);
+
+        drop(body);
+        let _ = tx.send(());
+    }
+
+    #[tokio::test]
+    async fn shutdown_by_handle() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = Server::new().add_service(Produce::default());
+        let handle = server.handle();
+        let server = tokio::spawn(server.run(DefaultIncoming::from(listener)));
+
+        let client = Client::builder(TokioExecutor::new())
+            .http2_only(true)
+            .build_http::<Full<Bytes>>();
+        let resp = client
+            .request(echo_req(&format!("http://{addr}/test.Produce/Call")))
+            .await
+            .unwrap();
+        assert_eq!(handle.draining(), 1);
+
+        // the stream never ends as the client doesn't read, so the connection is left behind
+        let start = tokio::time::Instant::now();
+        handle.shutdown_timeout(Duration::from_millis(100)).await;
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(handle.draining(), 1);
+        server.await.unwrap().unwrap();
+        drop(resp);
+    }
+
+    fn echo_req(uri: &str) -> hyper::Request<Full<Bytes>> {
+        hyper::Request::post(uri)
+            .header("content-type", "application/grpc")
+            .header("te", "trailers")
+            .body(Full::new(Bytes::new()))
+            .unwrap()
+    }
+
+    #[cfg(target_family = "unix")]
+    #[tokio::test]
+    async fn tcp_and_unix_listeners() {
+        let tcp = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = tcp.local_addr().unwrap();
+        let path = std::env::temp_dir().join(format!("volo-grpc-{}.sock", std::process::id()));
+        let uds = tokio::net::UnixListener::bind(&path).unwrap();
+
+        let echo = Echo::default();
+        let server = Server::new().add_service(echo.clone());
+        let listeners = Listeners::new()
+            .listener(Listener::from_incoming("external", tcp))
+            .listener(Listener::from_incoming("sidecar", uds));
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(server.run_with_shutdown(listeners, async move {
+            let _ = rx.await;
+            Ok(())
+        }));
+
+        let client = Client::builder(TokioExecutor::new())
+            .http2_only(true)
+            .build_http::<Full<Bytes>>();
+        let resp = client
+            .request(echo_req(&format!("http://{addr}/test.Echo/Call")))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        let stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+        let (mut sender, conn) =
+            hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(stream))
+                .await
+                .unwrap();
+        tokio::spawn(conn);
+        let resp = sender
+            .send_request(echo_req("http://localhost/test.Echo/Call"))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), http::StatusCode::OK);
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "pong");
+
+        assert_eq!(
+            *echo.listeners.lock().unwrap(),
+            vec![
+                ListenerName("external".into()),
+                ListenerName("sidecar".into())
+            ]
+        );
+
+        // the server shuts down with the connections of both listeners
+        drop(sender);
+        drop(client);
+        let _ = tx.send(());
+        tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let _ = std::fs::remove_file(&path);
     }
 }
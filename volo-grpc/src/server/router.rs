@@ -4,13 +4,16 @@ use std::{
 };
 
 use http_body::Body as HttpBody;
-use hyper::body::Incoming;
 use motore::{BoxCloneService, Service};
 use rustc_hash::FxHashMap;
 use volo::Unwrap;
 
 use super::NamedService;
-use crate::{body::Body, context::ServerContext, Request, Response, Status};
+use crate::{
+    body::{Body, BoxBody},
+    context::ServerContext,
+    Request, Response, Status,
+};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 struct RouteId(u32);
@@ -28,7 +31,7 @@ impl RouteId {
 }
 
 #[derive(Default)]
-pub struct Router<B = Incoming> {
+pub struct Router<B = BoxBody> {
     routes: FxHashMap<RouteId, BoxCloneService<ServerContext, Request<B>, Response<Body>, Status>>,
     node: matchit::Router<RouteId>,
 }
@@ -0,0 +1,189 @@
+//! Sending the messages of a streaming response by a bounded handle.
+//!
+//! With the `stream_sender` option of `volo-build`, the generated methods with streaming
+//! responses receive a [`Sender`] instead of returning a pre-built stream:
+//!
+//! ```rust,ignore
+//! impl Greeter for S {
+//!     async fn chat(
+//!         &self,
+//!         req: Request<RecvStream<ChatRequest>>,
+//!         tx: Sender<ChatResponse>,
+//!     ) -> Result<(), Status> {
+//!         let mut stream = req.into_inner();
+//!         while let Some(msg) = stream.message().await? {
+//!             tx.send(Ok(reply(msg))).await?;
+//!         }
+//!         Ok(())
+//!     }
+//! }
+//! ```
+//!
+//! The messages are buffered in a bounded channel, which is consumed by the response body only
+//! when the HTTP/2 stream has send capacity. So when the peer reads slowly and the flow-control
+//! window is used up, [`Sender::send`] pends once the buffer is full instead of buffering without
+//! a limit.
+//!
+//! The method is spawned as a task, and the response headers are sent without waiting for it.
+//! An error returned by the method is sent as the status at the end of the stream.
+
+use std::future::Future;
+
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::{BoxStream, Status};
+
+/// The default number of messages buffered in a [`Sender`].
+pub const DEFAULT_BUFFER: usize = 16;
+
+/// The bounded handle sending the messages of a streaming response.
+#[derive(Debug)]
+pub struct Sender<T> {
+    tx: mpsc::Sender<Result<T, Status>>,
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+        }
+    }
+}
+
+impl<T> Sender<T> {
+    /// Creates a [`Sender`] buffering at most `buffer` messages, and the stream of the messages
+    /// used as the response body.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buffer` is `0`.
+    pub fn channel(buffer: usize) -> (Self, BoxStream<'static, Result<T, Status>>)
+    where
+        T: Send + Sync + 'static,
+    {
+        let (tx, rx) = mpsc::channel(buffer);
+        (Self { tx }, Box::pin(ReceiverStream::new(rx)))
+    }
+
+    /// Sends a message, or a status which ends the stream, waiting for the buffer to have room.
+    ///
+    /// Fails with `CANCELLED` if the response stream has been dropped, e.g. the call is reset by
+    /// the peer, and the method should stop then.
+    pub async fn send(&self, item: Result<T, Status>) -> Result<(), Status> {
+        self.tx
+            .send(item)
+            .await
+            .map_err(|_| Status::cancelled("the response stream is closed"))
+    }
+
+    /// Returns the number of messages that can be sent without waiting.
+    pub fn capacity(&self) -> usize {
+        self.tx.capacity()
+    }
+
+    /// Returns the maximum number of messages buffered.
+    pub fn max_capacity(&self) -> usize {
+        self.tx.max_capacity()
+    }
+
+    /// Returns if the response stream has been dropped.
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+
+    /// Waits until the response stream is dropped.
+    pub async fn closed(&self) {
+        self.tx.closed().await
+    }
+}
+
+/// Spawns the method sending the messages by a [`Sender`] with [`DEFAULT_BUFFER`], and returns
+/// the stream of the messages, which is used by the generated code.
+pub fn spawn<T, F, Fut>(f: F) -> BoxStream<'static, Result<T, Status>>
+where
+    T: Send + Sync + 'static,
+    F: FnOnce(Sender<T>) -> Fut,
+    Fut: Future<Output = Result<(), Status>> + Send + 'static,
+{
+    let (tx, rx) = Sender::channel(DEFAULT_BUFFER);
+    let fut = f(tx.clone());
+    tokio::spawn(async move {
+        if let Err(status) = fut.await {
+            let _ = tx.send(Err(status)).await;
+        }
+    });
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::Code;
+
+    #[tokio::test]
+    async fn backpressure() {
+        let sent = Arc::new(AtomicUsize::new(0));
+        let mut stream = spawn({
+            let sent = sent.clone();
+            move |tx: Sender<u32>| async move {
+                for i in 0..1000 {
+                    tx.send(Ok(i)).await?;
+                    sent.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(Status::internal("done"))
+            }
+        });
+
+        // the slow peer doesn't read, so the method pends when the buffer is full
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(sent.load(Ordering::Relaxed), DEFAULT_BUFFER);
+
+        for i in 0..100 {
+            assert_eq!(stream.next().await.unwrap().unwrap(), i);
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(sent.load(Ordering::Relaxed), 100 + DEFAULT_BUFFER);
+
+        let mut received = 100;
+        while let Some(item) = stream.next().await {
+            match item {
+                Ok(i) => {
+                    assert_eq!(i, received);
+                    received += 1;
+                }
+                Err(status) => {
+                    assert_eq!(status.code(), Code::Internal);
+                    break;
+                }
+            }
+        }
+        assert_eq!(received, 1000);
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn closed() {
+        let (tx, stream) = Sender::<u32>::channel(2);
+        assert_eq!(tx.capacity(), 2);
+        tx.send(Ok(1)).await.unwrap();
+        assert_eq!(tx.capacity(), 1);
+        assert_eq!(tx.max_capacity(), 2);
+        assert!(!tx.is_closed());
+
+        drop(stream);
+        assert!(tx.is_closed());
+        tx.closed().await;
+        assert_eq!(tx.send(Ok(2)).await.unwrap_err().code(), Code::Cancelled);
+    }
+}
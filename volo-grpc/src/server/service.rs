@@ -1,22 +1,30 @@
-use std::marker::PhantomData;
+use std::{marker::PhantomData, pin::Pin, task::Poll};
 
-use hyper::body::Incoming;
+use futures::{future, StreamExt};
+use http_body_util::BodyExt;
 use motore::{
     layer::{Identity, Layer, Stack},
     service::Service,
 };
+use volo::util::budget::DEFAULT_YIELD_BUDGET;
 
-use super::NamedService;
+use super::{
+    cancel::{CancelOnDrop, CancellationToken, ObservedBody},
+    NamedService,
+};
 use crate::{
-    body::Body,
+    body::{Body, BoxBody},
     codec::{
         compression::{CompressionEncoding, ENCODING_HEADER},
-        decode::Kind,
+        decode::{DecodeLimits, Kind, DEFAULT_MAX_DECODING_MESSAGE_SIZE},
+        MessageCodec,
     },
-    context::{Config, ServerContext},
+    context::{Config, Context, ServerContext},
+    inspect::{Inspection, MakeInspector},
     message::{RecvEntryMessage, SendEntryMessage},
     metadata::MetadataValue,
-    Request, Response, Status,
+    response::Trailers,
+    BoxStream, Request, Response, Status,
 };
 
 #[derive(Clone)]
@@ -55,6 +63,58 @@ impl<S, L> ServiceBuilder<S, L> {
         self
     }
 
+    /// Sets the maximum size of a single message the service can receive.
+    ///
+    /// Messages exceeding the limit will be rejected with `ResourceExhausted`.
+    ///
+    /// Default is `4MB`.
+    pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
+        self.rpc_config.max_decoding_message_size = Some(limit);
+        self
+    }
+
+    /// Sets the maximum accumulated size of all messages the service can receive in one call,
+    /// which is useful for client-streaming calls.
+    ///
+    /// Calls exceeding the limit will be rejected with `ResourceExhausted`.
+    ///
+    /// Default is no limit.
+    pub fn max_decoding_total_size(mut self, limit: usize) -> Self {
+        self.rpc_config.max_decoding_total_size = Some(limit);
+        self
+    }
+
+    /// Sets the number of buffered messages decoded in a row from a streaming request before
+    /// yielding to the scheduler, so a fast client-streaming call does not starve the other
+    /// tasks on the same worker. `0` disables the yielding.
+    ///
+    /// Default is [`DEFAULT_YIELD_BUDGET`].
+    pub fn yield_budget(mut self, budget: usize) -> Self {
+        self.rpc_config.yield_budget = Some(budget);
+        self
+    }
+
+    /// Accepts the requests of the custom codec, e.g. [`MessageCodec::Custom`] of `flatbuffers`
+    /// for `application/grpc+flatbuffers`, whose service should be generated with the codec.
+    ///
+    /// The protobuf requests, and the JSON ones if the service is generated with serde, are always
+    /// accepted, and the responses are encoded by the codec of the requests.
+    pub fn codec(mut self, codec: MessageCodec) -> Self {
+        self.rpc_config.codec = Some(codec);
+        self
+    }
+
+    /// Inspects each message received and sent by the calls, e.g. by the [`MessageMetrics`], whose
+    /// error aborts the stream with the status.
+    ///
+    /// Default is no inspection.
+    ///
+    /// [`MessageMetrics`]: crate::inspect::MessageMetrics
+    pub fn message_inspector(mut self, make: impl MakeInspector) -> Self {
+        self.rpc_config.inspection = Some(Inspection::new(make));
+        self
+    }
+
     pub fn layer<O>(self, layer: O) -> ServiceBuilder<S, Stack<O, L>> {
         ServiceBuilder {
             layer: Stack::new(layer, self.layer),
@@ -117,7 +177,7 @@ impl<S, T, U> CodecService<S, T, U> {
     }
 }
 
-impl<S, T, U> Service<ServerContext, Request<Incoming>> for CodecService<S, T, U>
+impl<S, T, U> Service<ServerContext, Request<BoxBody>> for CodecService<S, T, U>
 where
     S: Service<ServerContext, Request<T>, Response = Response<U>> + Clone + Send + Sync + 'static,
     S::Error: Into<Status>,
@@ -130,9 +190,20 @@ where
     async fn call<'s, 'cx>(
         &'s self,
         cx: &'cx mut ServerContext,
-        req: Request<Incoming>,
+        req: Request<BoxBody>,
     ) -> Result<Self::Response, Self::Error> {
-        let (metadata, extensions, body) = req.into_parts();
+        let (metadata, mut extensions, body) = req.into_parts();
+        // the end of the requests is only the half-close of the client, which is told from the
+        // cancellation by the token
+        let token = CancellationToken::new();
+        cx.extensions_mut().insert(token.clone());
+        extensions.insert(token.clone());
+        if let Some(inspection) = &self.rpc_config.inspection {
+            cx.extensions_mut().insert(inspection.clone());
+        }
+        let body = ObservedBody::new(body, token.clone()).boxed();
+        // the response is encoded by the same codec as the request
+        let codec = MessageCodec::from_content_type_with(metadata.headers(), self.rpc_config.codec);
         let send_compression = CompressionEncoding::from_accept_encoding_header(
             metadata.headers(),
             &self.rpc_config.send_compressions,
@@ -143,18 +214,66 @@ where
             &self.rpc_config.accept_compressions,
         )?;
 
-        let message = T::from_body(
+        let message = T::from_body_with(
             Some(cx.rpc_info.method().as_str()),
             body,
             Kind::Request,
+            codec,
             recv_compression,
+            DecodeLimits {
+                max_message_size: Some(
+                    self.rpc_config
+                        .max_decoding_message_size
+                        .unwrap_or(DEFAULT_MAX_DECODING_MESSAGE_SIZE),
+                ),
+                max_total_size: self.rpc_config.max_decoding_total_size,
+                yield_budget: self.rpc_config.yield_budget.unwrap_or(DEFAULT_YIELD_BUDGET),
+            },
         )?;
 
         let volo_req = Request::from_parts(metadata, extensions, message);
 
+        cx.stats.record_process_start_at();
         let volo_resp = self.inner.call(cx, volo_req).await.map_err(Into::into)?;
 
-        let mut resp = volo_resp.map(|message| Body::new(message.into_body(send_compression)));
+        let (metadata, extensions, message) = volo_resp.into_parts();
+        let trailers = extensions.get::<Trailers>().cloned();
+        let body = message.into_body_with(codec, send_compression);
+
+        let mut body = body.peekable();
+
+        // The response is trailers-only if the stream has already ended with an error before
+        // any message when the handler returns. Otherwise the headers are sent at once and the
+        // status goes to the trailers, so the streams waiting for the requests won't hold the
+        // headers back.
+        let ended_with_error = future::poll_fn(|cx| {
+            Poll::Ready(matches!(
+                Pin::new(&mut body).poll_peek(cx),
+                Poll::Ready(Some(Err(_)))
+            ))
+        })
+        .await;
+        if ended_with_error {
+            if let Some(Err(mut status)) = body.next().await {
+                status.metadata_mut().merge(metadata);
+                if let Some(trailers) = trailers {
+                    status.metadata_mut().merge(trailers.take());
+                }
+                return Err(status);
+            }
+        }
+        let body: BoxStream<'static, _> = Box::pin(body);
+        let body = Box::pin(CancelOnDrop::new(body, token));
+
+        let mut resp =
+            Response::from_parts(metadata, extensions, Body::new(body).trailers(trailers));
+
+        if codec != MessageCodec::Proto {
+            resp.metadata_mut().insert(
+                "content-type",
+                MetadataValue::unchecked_from_header_value(codec.content_type()),
+            );
+        }
 
         if let Some(encoding) = send_compression {
             resp.metadata_mut().insert(
@@ -170,3 +289,319 @@ where
 impl<S: NamedService, T, U> NamedService for CodecService<S, T, U> {
     const NAME: &'static str = S::NAME;
 }
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use bytes::{BufMut, Bytes, BytesMut};
+    use http_body_util::{BodyExt, Full};
+    use pilota::prost::Message;
+
+    use super::*;
+    use crate::{
+        codec::{
+            encode::{encode, encode_custom, encode_json},
+            Codec,
+        },
+        metadata::MetadataMap,
+        RecvStream,
+    };
+
+    /// A custom codec encoding the strings as the raw UTF-8 bytes.
+    struct TextCodec;
+
+    impl Codec<String> for TextCodec {
+        const SUBTYPE: &'static str = "text";
+
+        fn encode(item: String, dst: &mut BytesMut) -> Result<(), Status> {
+            dst.put_slice(item.as_bytes());
+            Ok(())
+        }
+
+        fn decode(src: &mut BytesMut) -> Result<String, Status> {
+            String::from_utf8(src.split().to_vec())
+                .map_err(|e| Status::internal(format!("invalid text: {e}")))
+        }
+    }
+
+    /// The messages as generated with serde and [`TextCodec`].
+    enum EchoRequest {
+        Echo(RecvStream<String>),
+    }
+
+    impl RecvEntryMessage for EchoRequest {
+        fn from_body(
+            _method: Option<&str>,
+            body: BoxBody,
+            kind: Kind,
+            compression_encoding: Option<CompressionEncoding>,
+            limits: DecodeLimits,
+        ) -> Result<Self, Status> {
+            Ok(Self::Echo(RecvStream::new(
+                body,
+                kind,
+                compression_encoding,
+                limits,
+            )))
+        }
+
+        fn from_body_with(
+            method: Option<&str>,
+            body: BoxBody,
+            kind: Kind,
+            codec: MessageCodec,
+            compression_encoding: Option<CompressionEncoding>,
+            limits: DecodeLimits,
+        ) -> Result<Self, Status> {
+            match codec {
+                MessageCodec::Json => Ok(Self::Echo(RecvStream::new_json(
+                    body,
+                    kind,
+                    compression_encoding,
+                    limits,
+                ))),
+                MessageCodec::Custom(subtype)
+                    if subtype == <TextCodec as Codec<String>>::SUBTYPE =>
+                {
+                    Ok(Self::Echo(RecvStream::new_custom::<TextCodec>(
+                        body,
+                        kind,
+                        compression_encoding,
+                        limits,
+                    )))
+                }
+                MessageCodec::Proto => {
+                    Self::from_body(method, body, kind, compression_encoding, limits)
+                }
+                codec => Err(codec.unsupported()),
+            }
+        }
+    }
+
+    enum EchoResponse {
+        Echo(BoxStream<'static, Result<String, Status>>),
+    }
+
+    impl SendEntryMessage for EchoResponse {
+        fn into_body(
+            self,
+            compression_encoding: Option<CompressionEncoding>,
+        ) -> BoxStream<'static, Result<http_body::Frame<Bytes>, Status>> {
+            match self {
+                Self::Echo(s) => encode(s, compression_encoding),
+            }
+        }
+
+        fn into_body_with(
+            self,
+            codec: MessageCodec,
+            compression_encoding: Option<CompressionEncoding>,
+        ) -> BoxStream<'static, Result<http_body::Frame<Bytes>, Status>> {
+            match codec {
+                MessageCodec::Json => match self {
+                    Self::Echo(s) => encode_json(s, compression_encoding),
+                },
+                MessageCodec::Custom(subtype)
+                    if subtype == <TextCodec as Codec<String>>::SUBTYPE =>
+                {
+                    match self {
+                        Self::Echo(s) => encode_custom::<TextCodec, _, _>(s, compression_encoding),
+                    }
+                }
+                MessageCodec::Proto => self.into_body(compression_encoding),
+                codec => crate::codec::encode::unsupported(codec),
+            }
+        }
+    }
+
+    #[derive(Clone)]
+    struct Echo;
+
+    impl Service<ServerContext, Request<EchoRequest>> for Echo {
+        type Response = Response<EchoResponse>;
+        type Error = Status;
+
+        async fn call<'s, 'cx>(
+            &'s self,
+            _cx: &'cx mut ServerContext,
+            req: Request<EchoRequest>,
+        ) -> Result<Self::Response, Self::Error> {
+            let EchoRequest::Echo(requests) = req.into_inner();
+            let resp = requests.map(|name| Ok(format!("hello, {}", name?)));
+            Ok(Response::new(EchoResponse::Echo(Box::pin(resp))))
+        }
+    }
+
+    fn frame(message: &[u8]) -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u8(0);
+        buf.put_u32(message.len() as u32);
+        buf.put_slice(message);
+        buf.freeze()
+    }
+
+    #[tokio::test]
+    async fn json_unary() {
+        let svc = CodecService::<_, EchoRequest, EchoResponse>::new(Echo, Config::default());
+
+        let mut headers = http::HeaderMap::new();
+        headers.insert(
+            http::header::CONTENT_TYPE,
+            http::HeaderValue::from_static("application/grpc+json"),
+        );
+        let body = Full::new(frame(br#""volo""#))
+            .map_err(|e: Infallible| match e {})
+            .boxed();
+        let req = Request::from_parts(MetadataMap::from_headers(headers), Default::default(), body);
+
+        let resp = svc.call(&mut ServerContext::default(), req).await.unwrap();
+        assert_eq!(
+            resp.metadata().get("content-type").unwrap(),
+            "application/grpc+json"
+        );
+        let body = resp.into_inner().collect().await.unwrap().to_bytes();
+        assert_eq!(body, frame(br#""hello, volo""#));
+    }
+
+    #[tokio::test]
+    async fn custom_codec_streaming() {
+        let config = Config {
+            codec: Some(MessageCodec::Custom("text")),
+            ..Default::default()
+        };
+        let svc = CodecService::<_, EchoRequest, EchoResponse>::new(Echo, config);
+
+        let mut headers = http::HeaderMap::new();
+        headers.insert(
+            http::header::CONTENT_TYPE,
+            http::HeaderValue::from_static("application/grpc+text"),
+        );
+        let frames = [frame(b"volo"), frame(b"grpc")].concat();
+        let body = Full::new(Bytes::from(frames))
+            .map_err(|e: Infallible| match e {})
+            .boxed();
+        let req = Request::from_parts(MetadataMap::from_headers(headers), Default::default(), body);
+
+        let resp = svc.call(&mut ServerContext::default(), req).await.unwrap();
+        assert_eq!(
+            resp.metadata().get("content-type").unwrap(),
+            "application/grpc+text"
+        );
+        let body = resp.into_inner().collect().await.unwrap().to_bytes();
+        assert_eq!(
+            body,
+            [frame(b"hello, volo"), frame(b"hello, grpc")].concat()
+        );
+    }
+
+    #[tokio::test]
+    async fn custom_codec_not_configured() {
+        let svc = CodecService::<_, EchoRequest, EchoResponse>::new(Echo, Config::default());
+
+        let mut headers = http::HeaderMap::new();
+        headers.insert(
+            http::header::CONTENT_TYPE,
+            http::HeaderValue::from_static("application/grpc+text"),
+        );
+        // the subtype not configured is decoded as protobuf
+        let body = Full::new(frame(b""))
+            .map_err(|e: Infallible| match e {})
+            .boxed();
+        let req = Request::from_parts(MetadataMap::from_headers(headers), Default::default(), body);
+
+        let resp = svc.call(&mut ServerContext::default(), req).await.unwrap();
+        assert!(resp.metadata().get("content-type").is_none());
+        let body = resp.into_inner().collect().await.unwrap().to_bytes();
+        assert_eq!(body, frame(&"hello, ".to_owned().encode_to_vec()));
+    }
+
+    #[tokio::test]
+    async fn trailers_only_error() {
+        let svc = CodecService::<_, EchoRequest, EchoResponse>::new(Echo, Config::default());
+
+        // the stream ends with the error of decoding before any message
+        let body = Full::new(frame(b"\xff"))
+            .map_err(|e: Infallible| match e {})
+            .boxed();
+        let req = Request::from_parts(MetadataMap::new(), Default::default(), body);
+
+        let status = svc
+            .call(&mut ServerContext::default(), req)
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), crate::Code::Internal);
+    }
+
+    #[tokio::test]
+    async fn error_after_messages_in_trailers() {
+        let svc = CodecService::<_, EchoRequest, EchoResponse>::new(Echo, Config::default());
+
+        let frames = [frame(&"volo".to_owned().encode_to_vec()), frame(b"\xff")].concat();
+        let body = Full::new(Bytes::from(frames))
+            .map_err(|e: Infallible| match e {})
+            .boxed();
+        let req = Request::from_parts(MetadataMap::new(), Default::default(), body);
+
+        let resp = svc.call(&mut ServerContext::default(), req).await.unwrap();
+        let collected = resp.into_inner().collect().await.unwrap();
+        let trailers = collected.trailers().unwrap().clone();
+        assert_eq!(
+            collected.to_bytes(),
+            frame(&"hello, volo".to_owned().encode_to_vec())
+        );
+        assert_eq!(trailers.get("grpc-status").unwrap(), "13");
+    }
+
+    /// Echoes the requests and counts them in the trailers.
+    #[derive(Clone)]
+    struct Counted;
+
+    impl Service<ServerContext, Request<EchoRequest>> for Counted {
+        type Response = Response<EchoResponse>;
+        type Error = Status;
+
+        async fn call<'s, 'cx>(
+            &'s self,
+            _cx: &'cx mut ServerContext,
+            req: Request<EchoRequest>,
+        ) -> Result<Self::Response, Self::Error> {
+            let EchoRequest::Echo(requests) = req.into_inner();
+            let mut resp = Response::new(EchoResponse::Echo(Box::pin(futures::stream::empty())));
+            let trailers = resp.trailers();
+            let mut count = 0;
+            let stream = requests.map(move |name| {
+                count += 1;
+                trailers
+                    .lock()
+                    .insert("x-count", count.to_string().parse().unwrap());
+                // overridden by the status
+                trailers.lock().insert("grpc-status", "13".parse().unwrap());
+                Ok(format!("hello, {}", name?))
+            });
+            *resp.get_mut() = EchoResponse::Echo(Box::pin(stream));
+            Ok(resp)
+        }
+    }
+
+    #[tokio::test]
+    async fn custom_trailers() {
+        let svc = CodecService::<_, EchoRequest, EchoResponse>::new(Counted, Config::default());
+
+        let frames = [
+            frame(&"volo".to_owned().encode_to_vec()),
+            frame(&"grpc".to_owned().encode_to_vec()),
+        ]
+        .concat();
+        let body = Full::new(Bytes::from(frames))
+            .map_err(|e: Infallible| match e {})
+            .boxed();
+        let req = Request::from_parts(MetadataMap::new(), Default::default(), body);
+
+        let resp = svc.call(&mut ServerContext::default(), req).await.unwrap();
+        let collected = resp.into_inner().collect().await.unwrap();
+        let trailers = collected.trailers().unwrap();
+        assert_eq!(trailers.get("x-count").unwrap(), "2");
+        assert_eq!(trailers.get("grpc-status").unwrap(), "0");
+    }
+}
@@ -1,5 +1,7 @@
 //! These codes are copied from `tonic/src/status.rs` and may be modified by us.
 
+pub mod details;
+
 use std::{borrow::Cow, error::Error, fmt, sync::Arc};
 
 use base64::Engine;
@@ -8,9 +10,24 @@ use http::header::{HeaderMap, HeaderValue};
 use percent_encoding::{percent_decode, percent_encode, AsciiSet, CONTROLS};
 use tower::BoxError;
 use tracing::{debug, trace, warn};
-use volo::loadbalance::error::{LoadBalanceError, Retryable};
-
-use crate::{body::Body, metadata::MetadataMap, BASE64_ENGINE};
+use volo::{
+    error::{tagged, CodedError, ErrorCode},
+    loadbalance::{
+        error::{LoadBalanceError, Retryable},
+        outlier::OutlierFailure,
+    },
+    net::fingerprint::ProtocolMismatch,
+};
+
+use self::details::{DecodeError, ErrorDetails};
+use crate::{
+    body::Body,
+    metadata::{
+        errors::{InvalidMetadataKey, InvalidMetadataValue, InvalidMetadataValueBytes, ToStrError},
+        MetadataMap,
+    },
+    BASE64_ENGINE,
+};
 
 pub type BoxBody = http_body_util::combinators::BoxBody<Bytes, Status>;
 
@@ -172,6 +189,12 @@ impl Status {
 
     /// Create a new [`Status`] with the associated code and message.
 
+    /// Sets the underlying error, whose error code is kept, see [`CodedError`].
+    fn with_source(mut self, source: impl Error + Send + Sync + 'static) -> Self {
+        self.source = Some(Arc::new(source));
+        self
+    }
+
     pub fn new(code: Code, message: impl Into<String>) -> Self {
         Self {
             code,
@@ -421,6 +444,18 @@ impl Status {
         None
     }
 
+    /// Creates an `UNAVAILABLE` status of a peer speaking another protocol than gRPC, which is
+    /// kept as the source and can be taken back by [`Status::protocol_mismatch`].
+    pub fn from_protocol_mismatch(mismatch: ProtocolMismatch) -> Self {
+        Self::unavailable(mismatch.to_string()).with_source(mismatch)
+    }
+
+    /// Returns the [`ProtocolMismatch`] if the call failed because the peer speaks another
+    /// protocol than gRPC, e.g. it's an HTTP/1 server or requires TLS.
+    pub fn protocol_mismatch(&self) -> Option<&ProtocolMismatch> {
+        self.source.as_deref()?.downcast_ref()
+    }
+
     pub fn map_error<E>(err: E) -> Self
     where
         E: Into<Box<dyn Error + Send + Sync>>,
@@ -594,6 +629,27 @@ impl Status {
         Self::with_details_and_metadata(code, message, details, MetadataMap::new())
     }
 
+    /// Create a new `Status` with the associated code, message, and the standard error details
+    /// encoded as `google.rpc.Status`.
+    pub fn with_error_details(
+        code: Code,
+        message: impl Into<String>,
+        details: ErrorDetails,
+    ) -> Self {
+        let message = message.into();
+        let details = details.encode(code as i32, &message);
+        Self::with_details(code, message, details)
+    }
+
+    /// Decodes the standard error details, or returns `None` if there are no details or they are
+    /// not a valid `google.rpc.Status`.
+    pub fn error_details(&self) -> Option<ErrorDetails> {
+        if self.details.is_empty() {
+            return None;
+        }
+        ErrorDetails::decode(&self.details).ok()
+    }
+
     /// Create a new `Status` with the associated code, message, and custom metadata
     pub fn with_metadata(code: Code, message: impl Into<String>, metadata: MetadataMap) -> Self {
         Self::with_details_and_metadata(code, message, Bytes::new(), metadata)
@@ -734,27 +790,39 @@ impl From<std::io::Error> for Status {
     }
 }
 
+#[cfg(feature = "fault")]
+impl From<volo::fault::FaultError> for Status {
+    fn from(err: volo::fault::FaultError) -> Self {
+        match err {
+            volo::fault::FaultError::Dropped => Status::new(Code::Unavailable, err.to_string()),
+            volo::fault::FaultError::Injected { code, message } => {
+                Status::new(Code::from(code), message.as_str())
+            }
+        }
+    }
+}
+
 impl From<http::header::ToStrError> for Status {
     fn from(err: http::header::ToStrError) -> Self {
         Self::invalid_argument(err.to_string())
     }
 }
 
-impl From<crate::metadata::errors::InvalidMetadataKey> for Status {
-    fn from(err: crate::metadata::errors::InvalidMetadataKey) -> Self {
-        Self::invalid_argument(err.to_string())
+impl From<InvalidMetadataKey> for Status {
+    fn from(err: InvalidMetadataKey) -> Self {
+        Self::invalid_argument(err.to_string()).with_source(err)
     }
 }
 
-impl From<crate::metadata::errors::InvalidMetadataValue> for Status {
-    fn from(err: crate::metadata::errors::InvalidMetadataValue) -> Self {
-        Self::invalid_argument(err.to_string())
+impl From<InvalidMetadataValue> for Status {
+    fn from(err: InvalidMetadataValue) -> Self {
+        Self::invalid_argument(err.to_string()).with_source(err)
     }
 }
 
-impl From<crate::metadata::errors::ToStrError> for Status {
-    fn from(err: crate::metadata::errors::ToStrError) -> Self {
-        Self::invalid_argument(err.to_string())
+impl From<ToStrError> for Status {
+    fn from(err: ToStrError) -> Self {
+        Self::invalid_argument(err.to_string()).with_source(err)
     }
 }
 
@@ -766,7 +834,7 @@ impl From<BoxError> for Status {
 
 impl From<LoadBalanceError> for Status {
     fn from(err: LoadBalanceError) -> Self {
-        Self::unknown(err.to_string())
+        Self::unknown(err.to_string()).with_source(err)
     }
 }
 
@@ -785,6 +853,81 @@ impl Retryable for Status {
     }
 }
 
+/// The code of the status is the one of its source if the source is an error of volo, e.g. the
+/// [`LoadBalanceError`], or the one the message is tagged with, or the one of its [`Code`].
+///
+/// Note that [`Status::code`] returns the gRPC [`Code`], so the error code is got by
+/// `CodedError::code(&status)`.
+impl CodedError for Status {
+    fn code(&self) -> ErrorCode {
+        self.source
+            .as_deref()
+            .and_then(|source| volo::error::find_code(source, grpc_error_code))
+            .or_else(|| tagged(&self.message))
+            .unwrap_or_else(|| code::of(self.code))
+    }
+
+    fn is_retryable(&self) -> bool {
+        self.retryable()
+    }
+}
+
+/// Returns the code of the errors of this crate.
+fn grpc_error_code(err: &(dyn Error + 'static)) -> Option<ErrorCode> {
+    if let Some(status) = err.downcast_ref::<Status>() {
+        Some(CodedError::code(status))
+    } else if let Some(e) = err.downcast_ref::<InvalidMetadataKey>() {
+        Some(e.code())
+    } else if let Some(e) = err.downcast_ref::<InvalidMetadataValue>() {
+        Some(e.code())
+    } else if let Some(e) = err.downcast_ref::<InvalidMetadataValueBytes>() {
+        Some(e.code())
+    } else if let Some(e) = err.downcast_ref::<ToStrError>() {
+        Some(e.code())
+    } else {
+        err.downcast_ref::<DecodeError>().map(|e| e.code())
+    }
+}
+
+impl CodedError for InvalidMetadataKey {
+    fn code(&self) -> ErrorCode {
+        code::INVALID_METADATA_KEY
+    }
+}
+
+impl CodedError for InvalidMetadataValue {
+    fn code(&self) -> ErrorCode {
+        code::INVALID_METADATA_VALUE
+    }
+}
+
+impl CodedError for InvalidMetadataValueBytes {
+    fn code(&self) -> ErrorCode {
+        code::INVALID_METADATA_VALUE
+    }
+}
+
+impl CodedError for ToStrError {
+    fn code(&self) -> ErrorCode {
+        code::METADATA_VALUE_TO_STR
+    }
+}
+
+impl CodedError for DecodeError {
+    fn code(&self) -> ErrorCode {
+        code::INVALID_STATUS_DETAILS
+    }
+}
+
+impl OutlierFailure for Status {
+    fn is_outlier_failure(&self) -> bool {
+        matches!(
+            self.code,
+            Code::Unavailable | Code::Internal | Code::DataLoss | Code::DeadlineExceeded
+        )
+    }
+}
+
 impl fmt::Display for Status {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
@@ -905,6 +1048,67 @@ impl From<Code> for i32 {
     }
 }
 
+pub mod code {
+    //! The error codes of `volo-grpc`, see [`volo::error`].
+    //!
+    //! The codes of the statuses are the numbers of the gRPC codes.
+
+    use volo::error::{ErrorCode, Namespace};
+
+    use super::Code;
+
+    volo::error_codes! {
+        Namespace::Grpc;
+        /// The status is `OK`, which is not an error.
+        OK = 0,
+        /// The call was cancelled, e.g. by the peer.
+        CANCELLED = 1,
+        /// Unknown error, e.g. an error without a gRPC code.
+        UNKNOWN = 2,
+        /// The client specified an invalid argument.
+        INVALID_ARGUMENT = 3,
+        /// The deadline expired before the call could complete.
+        DEADLINE_EXCEEDED = 4,
+        /// Some requested entity was not found.
+        NOT_FOUND = 5,
+        /// Some entity that the call attempted to create already exists.
+        ALREADY_EXISTS = 6,
+        /// The caller does not have permission to execute the call.
+        PERMISSION_DENIED = 7,
+        /// Some resource has been exhausted, e.g. a rate limit.
+        RESOURCE_EXHAUSTED = 8,
+        /// The system is not in a state required for the call.
+        FAILED_PRECONDITION = 9,
+        /// The call was aborted, e.g. by a concurrency conflict.
+        ABORTED = 10,
+        /// The call was attempted past the valid range.
+        OUT_OF_RANGE = 11,
+        /// The method is not implemented or not supported.
+        UNIMPLEMENTED = 12,
+        /// Internal error, e.g. an HTTP/2 protocol error.
+        INTERNAL = 13,
+        /// The service is unavailable, e.g. the connection is refused or reset.
+        UNAVAILABLE = 14,
+        /// Unrecoverable data loss or corruption.
+        DATA_LOSS = 15,
+        /// The caller is not authenticated.
+        UNAUTHENTICATED = 16,
+        /// Invalid metadata key.
+        INVALID_METADATA_KEY = 101,
+        /// Invalid metadata value.
+        INVALID_METADATA_VALUE = 102,
+        /// The metadata value can't be converted to a string.
+        METADATA_VALUE_TO_STR = 103,
+        /// Malformed details of a status.
+        INVALID_STATUS_DETAILS = 104,
+    }
+
+    /// Returns the error code of the gRPC [`Code`].
+    pub fn of(code: Code) -> ErrorCode {
+        ErrorCode::new(Namespace::Grpc, code as u16)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1037,4 +1241,49 @@ mod tests {
 
         assert_eq!(status.details(), DETAILS);
     }
+
+    #[test]
+    fn error_codes() {
+        let status = |status: Status| CodedError::code(&status);
+        assert_eq!(status(Status::ok("")), code::OK);
+        assert_eq!(status(Status::cancelled("")), code::CANCELLED);
+        assert_eq!(status(Status::unavailable("")), code::UNAVAILABLE);
+        assert_eq!(status(Status::unauthenticated("")), code::UNAUTHENTICATED);
+        assert!(Status::unavailable("").is_retryable());
+        assert!(!Status::not_found("").is_retryable());
+
+        let io = std::io::Error::new(std::io::ErrorKind::ConnectionReset, "reset");
+        assert_eq!(status(io.into()), code::UNAVAILABLE);
+        let key = "a b"
+            .parse::<crate::metadata::AsciiMetadataKey>()
+            .unwrap_err();
+        assert_eq!(key.code(), code::INVALID_METADATA_KEY);
+        assert_eq!(status(key.into()), code::INVALID_METADATA_KEY);
+
+        // wrapping keeps the code
+        let lb = Status::from(LoadBalanceError::Retry);
+        assert_eq!(lb.code(), Code::Unknown);
+        assert_eq!(status(lb.clone()), volo::error::code::LOAD_BALANCE_RETRY);
+        assert_eq!(
+            status(Status::from_error(Box::new(
+                volo::config::ConfigError::new()
+            ))),
+            volo::error::code::CONFIG
+        );
+        let nested = Status::from_error(Box::new(lb));
+        assert_eq!(status(nested), volo::error::code::LOAD_BALANCE_RETRY);
+        // e.g. the message of the status from the peer
+        let tagged = Status::internal(volo::error::tag(code::INVALID_STATUS_DETAILS, "oops"));
+        assert_eq!(status(tagged), code::INVALID_STATUS_DETAILS);
+    }
+
+    #[test]
+    fn registry() {
+        volo::error::check_registry(
+            "volo-grpc",
+            code::REGISTRY,
+            concat!(env!("CARGO_MANIFEST_DIR"), "/ERROR_CODES.md"),
+        )
+        .unwrap();
+    }
 }
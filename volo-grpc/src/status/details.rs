@@ -0,0 +1,600 @@
+//! The standard error details of `google.rpc.Status`, carried in `grpc-status-details-bin`.
+//!
+//! The details used by the framework and the common ones are decoded, i.e. `RetryInfo`,
+//! `QuotaFailure`, `ErrorInfo` and `BadRequest`, the others are kept as [`ErrorDetail::Unknown`],
+//! and are encoded back as they were.
+
+use std::{collections::HashMap, fmt, time::Duration};
+
+use bytes::{Buf, BufMut, Bytes};
+use pilota::prost::{
+    encoding::{self, DecodeContext, WireType},
+    Message,
+};
+use volo::retry::Pushback;
+
+const TYPE_URL_PREFIX: &str = "type.googleapis.com/";
+const RETRY_INFO: &str = "google.rpc.RetryInfo";
+const QUOTA_FAILURE: &str = "google.rpc.QuotaFailure";
+const ERROR_INFO: &str = "google.rpc.ErrorInfo";
+const BAD_REQUEST: &str = "google.rpc.BadRequest";
+
+/// `google.rpc.RetryInfo`, telling how long to wait before retrying.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RetryInfo {
+    pub retry_delay: Duration,
+}
+
+impl From<&RetryInfo> for Pushback {
+    fn from(value: &RetryInfo) -> Self {
+        Pushback::Delay(value.retry_delay)
+    }
+}
+
+impl From<RetryInfo> for Pushback {
+    fn from(value: RetryInfo) -> Self {
+        Self::from(&value)
+    }
+}
+
+/// `google.rpc.QuotaFailure`, telling which quotas are exhausted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QuotaFailure {
+    pub violations: Vec<QuotaViolation>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QuotaViolation {
+    pub subject: String,
+    pub description: String,
+}
+
+/// `google.rpc.ErrorInfo`, the reason of the error.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ErrorInfo {
+    pub reason: String,
+    pub domain: String,
+    pub metadata: HashMap<String, String>,
+}
+
+/// `google.rpc.BadRequest`, telling which fields of the request are invalid.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BadRequest {
+    pub field_violations: Vec<FieldViolation>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FieldViolation {
+    /// The path to the field, e.g. `user.emails[0]`.
+    pub field: String,
+    pub description: String,
+}
+
+/// A detail of the status.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorDetail {
+    RetryInfo(RetryInfo),
+    QuotaFailure(QuotaFailure),
+    ErrorInfo(ErrorInfo),
+    BadRequest(BadRequest),
+    /// A detail of other types, as the type url and the encoded message of `google.protobuf.Any`.
+    Unknown {
+        type_url: String,
+        value: Bytes,
+    },
+}
+
+/// The details of a status, i.e. the `details` of `google.rpc.Status`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ErrorDetails {
+    pub details: Vec<ErrorDetail>,
+}
+
+/// The error of decoding malformed details.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeError(pilota::prost::DecodeError);
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid status details: {}", self.0)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl ErrorDetails {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, detail: ErrorDetail) -> Self {
+        self.details.push(detail);
+        self
+    }
+
+    pub fn retry_info(&self) -> Option<&RetryInfo> {
+        self.details.iter().find_map(|d| match d {
+            ErrorDetail::RetryInfo(info) => Some(info),
+            _ => None,
+        })
+    }
+
+    pub fn quota_failure(&self) -> Option<&QuotaFailure> {
+        self.details.iter().find_map(|d| match d {
+            ErrorDetail::QuotaFailure(failure) => Some(failure),
+            _ => None,
+        })
+    }
+
+    pub fn error_info(&self) -> Option<&ErrorInfo> {
+        self.details.iter().find_map(|d| match d {
+            ErrorDetail::ErrorInfo(info) => Some(info),
+            _ => None,
+        })
+    }
+
+    pub fn bad_request(&self) -> Option<&BadRequest> {
+        self.details.iter().find_map(|d| match d {
+            ErrorDetail::BadRequest(bad_request) => Some(bad_request),
+            _ => None,
+        })
+    }
+
+    /// Decodes the details from an encoded `google.rpc.Status`.
+    pub fn decode(buf: &[u8]) -> Result<Self, DecodeError> {
+        let status = RpcStatus::decode(buf).map_err(DecodeError)?;
+        let details = status
+            .details
+            .into_iter()
+            .map(decode_any)
+            .collect::<Result<_, _>>()
+            .map_err(DecodeError)?;
+        Ok(Self { details })
+    }
+
+    /// Encodes the details as a `google.rpc.Status` with the code and the message.
+    pub fn encode(&self, code: i32, message: &str) -> Bytes {
+        RpcStatus {
+            code,
+            message: message.to_owned(),
+            details: self.details.iter().map(encode_any).collect(),
+        }
+        .encode_to_vec()
+        .into()
+    }
+}
+
+fn decode_any(any: Any) -> Result<ErrorDetail, pilota::prost::DecodeError> {
+    let name = any.type_url.rsplit('/').next().unwrap_or_default();
+    let detail = match name {
+        RETRY_INFO => ErrorDetail::RetryInfo(RetryInfo::decode(any.value)?),
+        QUOTA_FAILURE => ErrorDetail::QuotaFailure(QuotaFailure::decode(any.value)?),
+        ERROR_INFO => ErrorDetail::ErrorInfo(ErrorInfo::decode(any.value)?),
+        BAD_REQUEST => ErrorDetail::BadRequest(BadRequest::decode(any.value)?),
+        _ => ErrorDetail::Unknown {
+            type_url: any.type_url,
+            value: any.value,
+        },
+    };
+    Ok(detail)
+}
+
+fn encode_any(detail: &ErrorDetail) -> Any {
+    let (name, value) = match detail {
+        ErrorDetail::RetryInfo(info) => (RETRY_INFO, info.encode_to_vec()),
+        ErrorDetail::QuotaFailure(failure) => (QUOTA_FAILURE, failure.encode_to_vec()),
+        ErrorDetail::ErrorInfo(info) => (ERROR_INFO, info.encode_to_vec()),
+        ErrorDetail::BadRequest(bad_request) => (BAD_REQUEST, bad_request.encode_to_vec()),
+        ErrorDetail::Unknown { type_url, value } => {
+            return Any {
+                type_url: type_url.clone(),
+                value: value.clone(),
+            };
+        }
+    };
+    Any {
+        type_url: format!("{TYPE_URL_PREFIX}{name}"),
+        value: value.into(),
+    }
+}
+
+/// `google.rpc.Status`.
+#[derive(Debug, Default)]
+struct RpcStatus {
+    code: i32,
+    message: String,
+    details: Vec<Any>,
+}
+
+/// `google.protobuf.Any`.
+#[derive(Debug, Default)]
+struct Any {
+    type_url: String,
+    value: Bytes,
+}
+
+/// `google.protobuf.Duration`.
+#[derive(Debug, Default)]
+struct ProtoDuration {
+    seconds: i64,
+    nanos: i32,
+}
+
+impl Message for RpcStatus {
+    fn encode_raw<B: BufMut>(&self, buf: &mut B) {
+        if self.code != 0 {
+            encoding::int32::encode(1, &self.code, buf);
+        }
+        encode_string(2, &self.message, buf);
+        encoding::message::encode_repeated(3, &self.details, buf);
+    }
+
+    fn merge_field<B: Buf>(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        buf: &mut B,
+        ctx: DecodeContext,
+    ) -> Result<(), pilota::prost::DecodeError> {
+        match tag {
+            1 => encoding::int32::merge(wire_type, &mut self.code, buf, ctx),
+            2 => encoding::string::merge(wire_type, &mut self.message, buf, ctx),
+            3 => encoding::message::merge_repeated(wire_type, &mut self.details, buf, ctx),
+            _ => encoding::skip_field(wire_type, tag, buf, ctx),
+        }
+    }
+
+    fn encoded_len(&self) -> usize {
+        (if self.code != 0 {
+            encoding::int32::encoded_len(1, &self.code)
+        } else {
+            0
+        }) + string_len(2, &self.message)
+            + encoding::message::encoded_len_repeated(3, &self.details)
+    }
+}
+
+impl Message for Any {
+    fn encode_raw<B: BufMut>(&self, buf: &mut B) {
+        encode_string(1, &self.type_url, buf);
+        if !self.value.is_empty() {
+            encoding::bytes::encode(2, &self.value, buf);
+        }
+    }
+
+    fn merge_field<B: Buf>(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        buf: &mut B,
+        ctx: DecodeContext,
+    ) -> Result<(), pilota::prost::DecodeError> {
+        match tag {
+            1 => encoding::string::merge(wire_type, &mut self.type_url, buf, ctx),
+            2 => encoding::bytes::merge(wire_type, &mut self.value, buf, ctx),
+            _ => encoding::skip_field(wire_type, tag, buf, ctx),
+        }
+    }
+
+    fn encoded_len(&self) -> usize {
+        string_len(1, &self.type_url)
+            + if self.value.is_empty() {
+                0
+            } else {
+                encoding::bytes::encoded_len(2, &self.value)
+            }
+    }
+}
+
+impl Message for ProtoDuration {
+    fn encode_raw<B: BufMut>(&self, buf: &mut B) {
+        if self.seconds != 0 {
+            encoding::int64::encode(1, &self.seconds, buf);
+        }
+        if self.nanos != 0 {
+            encoding::int32::encode(2, &self.nanos, buf);
+        }
+    }
+
+    fn merge_field<B: Buf>(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        buf: &mut B,
+        ctx: DecodeContext,
+    ) -> Result<(), pilota::prost::DecodeError> {
+        match tag {
+            1 => encoding::int64::merge(wire_type, &mut self.seconds, buf, ctx),
+            2 => encoding::int32::merge(wire_type, &mut self.nanos, buf, ctx),
+            _ => encoding::skip_field(wire_type, tag, buf, ctx),
+        }
+    }
+
+    fn encoded_len(&self) -> usize {
+        (if self.seconds != 0 {
+            encoding::int64::encoded_len(1, &self.seconds)
+        } else {
+            0
+        }) + if self.nanos != 0 {
+            encoding::int32::encoded_len(2, &self.nanos)
+        } else {
+            0
+        }
+    }
+}
+
+impl From<Duration> for ProtoDuration {
+    fn from(value: Duration) -> Self {
+        Self {
+            seconds: value.as_secs() as i64,
+            nanos: value.subsec_nanos() as i32,
+        }
+    }
+}
+
+impl From<ProtoDuration> for Duration {
+    fn from(value: ProtoDuration) -> Self {
+        // a negative delay means retrying immediately
+        Duration::new(
+            value.seconds.max(0) as u64,
+            value.nanos.clamp(0, 999_999_999) as u32,
+        )
+    }
+}
+
+impl Message for RetryInfo {
+    fn encode_raw<B: BufMut>(&self, buf: &mut B) {
+        encoding::message::encode(1, &ProtoDuration::from(self.retry_delay), buf);
+    }
+
+    fn merge_field<B: Buf>(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        buf: &mut B,
+        ctx: DecodeContext,
+    ) -> Result<(), pilota::prost::DecodeError> {
+        match tag {
+            1 => {
+                let mut delay = ProtoDuration::default();
+                encoding::message::merge(wire_type, &mut delay, buf, ctx)?;
+                self.retry_delay = delay.into();
+                Ok(())
+            }
+            _ => encoding::skip_field(wire_type, tag, buf, ctx),
+        }
+    }
+
+    fn encoded_len(&self) -> usize {
+        encoding::message::encoded_len(1, &ProtoDuration::from(self.retry_delay))
+    }
+}
+
+impl Message for QuotaFailure {
+    fn encode_raw<B: BufMut>(&self, buf: &mut B) {
+        encoding::message::encode_repeated(1, &self.violations, buf);
+    }
+
+    fn merge_field<B: Buf>(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        buf: &mut B,
+        ctx: DecodeContext,
+    ) -> Result<(), pilota::prost::DecodeError> {
+        match tag {
+            1 => encoding::message::merge_repeated(wire_type, &mut self.violations, buf, ctx),
+            _ => encoding::skip_field(wire_type, tag, buf, ctx),
+        }
+    }
+
+    fn encoded_len(&self) -> usize {
+        encoding::message::encoded_len_repeated(1, &self.violations)
+    }
+}
+
+impl Message for QuotaViolation {
+    fn encode_raw<B: BufMut>(&self, buf: &mut B) {
+        encode_string(1, &self.subject, buf);
+        encode_string(2, &self.description, buf);
+    }
+
+    fn merge_field<B: Buf>(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        buf: &mut B,
+        ctx: DecodeContext,
+    ) -> Result<(), pilota::prost::DecodeError> {
+        match tag {
+            1 => encoding::string::merge(wire_type, &mut self.subject, buf, ctx),
+            2 => encoding::string::merge(wire_type, &mut self.description, buf, ctx),
+            _ => encoding::skip_field(wire_type, tag, buf, ctx),
+        }
+    }
+
+    fn encoded_len(&self) -> usize {
+        string_len(1, &self.subject) + string_len(2, &self.description)
+    }
+}
+
+impl Message for ErrorInfo {
+    fn encode_raw<B: BufMut>(&self, buf: &mut B) {
+        encode_string(1, &self.reason, buf);
+        encode_string(2, &self.domain, buf);
+        encoding::hash_map::encode(
+            encoding::string::encode,
+            encoding::string::encoded_len,
+            encoding::string::encode,
+            encoding::string::encoded_len,
+            3,
+            &self.metadata,
+            buf,
+        );
+    }
+
+    fn merge_field<B: Buf>(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        buf: &mut B,
+        ctx: DecodeContext,
+    ) -> Result<(), pilota::prost::DecodeError> {
+        match tag {
+            1 => encoding::string::merge(wire_type, &mut self.reason, buf, ctx),
+            2 => encoding::string::merge(wire_type, &mut self.domain, buf, ctx),
+            3 => encoding::hash_map::merge(
+                encoding::string::merge,
+                encoding::string::merge,
+                &mut self.metadata,
+                buf,
+                ctx,
+            ),
+            _ => encoding::skip_field(wire_type, tag, buf, ctx),
+        }
+    }
+
+    fn encoded_len(&self) -> usize {
+        string_len(1, &self.reason)
+            + string_len(2, &self.domain)
+            + encoding::hash_map::encoded_len(
+                encoding::string::encoded_len,
+                encoding::string::encoded_len,
+                3,
+                &self.metadata,
+            )
+    }
+}
+
+impl Message for BadRequest {
+    fn encode_raw<B: BufMut>(&self, buf: &mut B) {
+        encoding::message::encode_repeated(1, &self.field_violations, buf);
+    }
+
+    fn merge_field<B: Buf>(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        buf: &mut B,
+        ctx: DecodeContext,
+    ) -> Result<(), pilota::prost::DecodeError> {
+        match tag {
+            1 => encoding::message::merge_repeated(wire_type, &mut self.field_violations, buf, ctx),
+            _ => encoding::skip_field(wire_type, tag, buf, ctx),
+        }
+    }
+
+    fn encoded_len(&self) -> usize {
+        encoding::message::encoded_len_repeated(1, &self.field_violations)
+    }
+}
+
+impl Message for FieldViolation {
+    fn encode_raw<B: BufMut>(&self, buf: &mut B) {
+        encode_string(1, &self.field, buf);
+        encode_string(2, &self.description, buf);
+    }
+
+    fn merge_field<B: Buf>(
+        &mut self,
+        tag: u32,
+        wire_type: WireType,
+        buf: &mut B,
+        ctx: DecodeContext,
+    ) -> Result<(), pilota::prost::DecodeError> {
+        match tag {
+            1 => encoding::string::merge(wire_type, &mut self.field, buf, ctx),
+            2 => encoding::string::merge(wire_type, &mut self.description, buf, ctx),
+            _ => encoding::skip_field(wire_type, tag, buf, ctx),
+        }
+    }
+
+    fn encoded_len(&self) -> usize {
+        string_len(1, &self.field) + string_len(2, &self.description)
+    }
+}
+
+/// Encodes the string field unless it's the default, i.e. empty.
+fn encode_string<B: BufMut>(tag: u32, value: &str, buf: &mut B) {
+    if !value.is_empty() {
+        encoding::string::encode(tag, &value, buf);
+    }
+}
+
+fn string_len(tag: u32, value: &str) -> usize {
+    if value.is_empty() {
+        0
+    } else {
+        encoding::string::encoded_len(tag, &value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let details = ErrorDetails::new()
+            .with(ErrorDetail::RetryInfo(RetryInfo {
+                retry_delay: Duration::from_millis(1500),
+            }))
+            .with(ErrorDetail::QuotaFailure(QuotaFailure {
+                violations: vec![QuotaViolation {
+                    subject: "project:hello".to_owned(),
+                    description: "daily limit".to_owned(),
+                }],
+            }))
+            .with(ErrorDetail::ErrorInfo(ErrorInfo {
+                reason: "API_DISABLED".to_owned(),
+                domain: "example.com".to_owned(),
+                metadata: [("service".to_owned(), "hello".to_owned())].into(),
+            }))
+            .with(ErrorDetail::BadRequest(BadRequest {
+                field_violations: vec![FieldViolation {
+                    field: "user.emails[0]".to_owned(),
+                    description: "invalid email".to_owned(),
+                }],
+            }))
+            .with(ErrorDetail::Unknown {
+                type_url: "type.googleapis.com/google.rpc.Help".to_owned(),
+                value: Bytes::from_static(b"\x0a\x00"),
+            });
+
+        let encoded = details.encode(8, "quota");
+        let decoded = ErrorDetails::decode(&encoded).unwrap();
+        assert_eq!(decoded, details);
+        assert_eq!(
+            decoded.retry_info().unwrap().retry_delay,
+            Duration::from_millis(1500)
+        );
+        assert_eq!(decoded.error_info().unwrap().reason, "API_DISABLED");
+        assert_eq!(decoded.quota_failure().unwrap().violations.len(), 1);
+        assert_eq!(
+            decoded.bad_request().unwrap().field_violations[0].field,
+            "user.emails[0]"
+        );
+        assert_eq!(
+            Pushback::from(decoded.retry_info().unwrap()),
+            Pushback::Delay(Duration::from_millis(1500))
+        );
+    }
+
+    #[test]
+    fn negative_retry_delay() {
+        let delay = ProtoDuration {
+            seconds: -1,
+            nanos: 0,
+        };
+        let mut info = Vec::new();
+        encoding::message::encode(1, &delay, &mut info);
+        let info = RetryInfo::decode(info.as_slice()).unwrap();
+        assert_eq!(info.retry_delay, Duration::ZERO);
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(ErrorDetails::decode(b"\x1a\x05\x0a").is_err());
+        assert_eq!(ErrorDetails::decode(b"").unwrap(), ErrorDetails::new());
+    }
+}
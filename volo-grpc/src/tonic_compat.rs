@@ -0,0 +1,401 @@
+//! Interoperating with tonic for migrating the services one by one.
+//!
+//! This module converts the [`Status`] and the [`MetadataMap`] from and to the ones of tonic,
+//! and bridges the clients and the servers of both:
+//!
+//! - [`TonicClient`] calls a tonic client as a volo service, so the volo handlers can keep using
+//!   the tonic clients not migrated yet;
+//! - [`TonicService`] mounts a tonic service on the volo-grpc server by the raw HTTP/2 request and
+//!   response, so the services of both can be served on the same port.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use volo_grpc::tonic_compat::{TonicClient, TonicService};
+//!
+//! // a tonic service served by the volo server
+//! volo_grpc::server::Server::new()
+//!     .add_service(TonicService::new(GreeterServer::new(MyGreeter)))
+//!     .add_service(ServiceBuilder::new(EchoServer::new(MyEcho)).build())
+//!     .run(addr)
+//!     .await?;
+//!
+//! // a tonic client called as a volo service
+//! let greeter = TonicClient::new(GreeterClient::new(channel), |mut client, req| async move {
+//!     client.say_hello(req).await
+//! });
+//! let resp = greeter.call(&mut cx, volo_grpc::Request::new(HelloRequest::default())).await?;
+//! ```
+//!
+//! # Unsupported
+//!
+//! - The extensions of the requests and the responses are not converted between the two, since they
+//!   are different types, so anything put into them by the layers is lost at the boundary.
+//! - [`TonicClient`] only supports the unary calls, the streaming calls should be converted by
+//!   hand.
+//! - The layers of the volo server only see the raw bodies of a [`TonicService`], so they can't
+//!   read the messages, and the codec options of the volo server, e.g. the compressions and the
+//!   message size limits, don't apply to it, which should be set on the tonic service instead.
+//! - The [`ServerContext`] is not passed to the tonic handlers, only the metadata is.
+
+use std::{
+    convert::Infallible,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use bytes::Bytes;
+use futures::Stream;
+use http_body::{Body as HttpBody, Frame};
+use motore::service::Service;
+use tower::ServiceExt;
+
+use crate::{
+    body::{Body, BoxBody},
+    context::{ClientContext, ServerContext},
+    metadata::MetadataMap,
+    server::NamedService,
+    BoxError, Code, Request, Response, Status,
+};
+
+impl From<MetadataMap> for tonic::metadata::MetadataMap {
+    fn from(metadata: MetadataMap) -> Self {
+        Self::from_headers(metadata.into_headers())
+    }
+}
+
+impl From<tonic::metadata::MetadataMap> for MetadataMap {
+    fn from(metadata: tonic::metadata::MetadataMap) -> Self {
+        Self::from_headers(metadata.into_headers())
+    }
+}
+
+impl From<Code> for tonic::Code {
+    fn from(code: Code) -> Self {
+        Self::from_i32(code as i32)
+    }
+}
+
+impl From<tonic::Code> for Code {
+    fn from(code: tonic::Code) -> Self {
+        Self::from_i32(code as i32)
+    }
+}
+
+/// Keeps the code, the message, the details and the metadata.
+impl From<Status> for tonic::Status {
+    fn from(status: Status) -> Self {
+        Self::with_details_and_metadata(
+            status.code().into(),
+            status.message(),
+            Bytes::copy_from_slice(status.details()),
+            status.metadata().clone().into(),
+        )
+    }
+}
+
+/// Keeps the code, the message, the details and the metadata.
+impl From<tonic::Status> for Status {
+    fn from(status: tonic::Status) -> Self {
+        Self::with_details_and_metadata(
+            status.code().into(),
+            status.message(),
+            Bytes::copy_from_slice(status.details()),
+            status.metadata().clone().into(),
+        )
+    }
+}
+
+/// Converts the request to the one of tonic, without the extensions.
+pub fn into_tonic_request<T>(req: Request<T>) -> tonic::Request<T> {
+    let (metadata, _, message) = req.into_parts();
+    tonic::Request::from_parts(metadata.into(), Default::default(), message)
+}
+
+/// Converts the request from the one of tonic, without the extensions.
+pub fn from_tonic_request<T>(req: tonic::Request<T>) -> Request<T> {
+    let (metadata, _, message) = req.into_parts();
+    Request::from_parts(metadata.into(), Default::default(), message)
+}
+
+/// Converts the response to the one of tonic, without the extensions.
+pub fn into_tonic_response<T>(resp: Response<T>) -> tonic::Response<T> {
+    let (metadata, _, message) = resp.into_parts();
+    tonic::Response::from_parts(metadata.into(), message, Default::default())
+}
+
+/// Converts the response from the one of tonic, without the extensions.
+pub fn from_tonic_response<T>(resp: tonic::Response<T>) -> Response<T> {
+    let (metadata, message, _) = resp.into_parts();
+    Response::from_parts(metadata.into(), Default::default(), message)
+}
+
+/// Calls a tonic client as a volo service of the unary calls.
+///
+/// The tonic client is cloned for each call, which is cheap for the generated clients on a
+/// `Channel`, and called by `call` with the request converted by [`into_tonic_request`].
+pub struct TonicClient<C, F> {
+    client: C,
+    call: Arc<F>,
+}
+
+impl<C, F> TonicClient<C, F> {
+    pub fn new(client: C, call: F) -> Self {
+        Self {
+            client,
+            call: Arc::new(call),
+        }
+    }
+}
+
+impl<C: Clone, F> Clone for TonicClient<C, F> {
+    fn clone(&self) -> Self {
+        Self {
+            client: self.client.clone(),
+            call: self.call.clone(),
+        }
+    }
+}
+
+impl<C, F, Fut, T, U> Service<ClientContext, Request<T>> for TonicClient<C, F>
+where
+    C: Clone + Send + Sync,
+    F: Fn(C, tonic::Request<T>) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<tonic::Response<U>, tonic::Status>> + Send,
+    T: Send,
+{
+    type Response = Response<U>;
+    type Error = Status;
+
+    async fn call<'s, 'cx>(
+        &'s self,
+        _cx: &'cx mut ClientContext,
+        req: Request<T>,
+    ) -> Result<Self::Response, Self::Error> {
+        (self.call)(self.client.clone(), into_tonic_request(req))
+            .await
+            .map(from_tonic_response)
+            .map_err(Into::into)
+    }
+}
+
+/// Mounts a tonic service on the volo-grpc server by the raw HTTP/2 request and response.
+///
+/// The body of the request is passed to the tonic service as it is, and the body of the response
+/// is sent with the trailers of the tonic service, so the status and its details are kept.
+#[derive(Clone)]
+pub struct TonicService<S> {
+    inner: S,
+}
+
+impl<S> TonicService<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S: tonic::server::NamedService> NamedService for TonicService<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+impl<S, B> Service<ServerContext, Request<BoxBody>> for TonicService<S>
+where
+    S: tower::Service<http::Request<BoxBody>, Response = http::Response<B>, Error = Infallible>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    S::Future: Send,
+    B: HttpBody<Data = Bytes> + Unpin + Send + 'static,
+    B::Error: Into<BoxError>,
+{
+    type Response = Response<Body>;
+    type Error = Status;
+
+    async fn call<'s, 'cx>(
+        &'s self,
+        cx: &'cx mut ServerContext,
+        req: Request<BoxBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let (metadata, extensions, body) = req.into_parts();
+        let mut http_req = http::Request::post(cx.rpc_info.method().as_str())
+            .version(http::Version::HTTP_2)
+            .body(body)
+            .map_err(|e| Status::internal(e.to_string()))?;
+        *http_req.headers_mut() = metadata.into_headers();
+        *http_req.extensions_mut() = extensions;
+
+        cx.stats.record_process_start_at();
+        let resp = match self.inner.clone().oneshot(http_req).await {
+            Ok(resp) => resp,
+            Err(never) => match never {},
+        };
+        let (parts, body) = resp.into_parts();
+        let body = Body::new(Box::pin(SyncBody(Mutex::new(body)))).without_trailers();
+        // the trailers-only responses of errors are kept as they are, since their bodies are
+        // empty and the status is in the headers
+        Ok(Response::from_parts(
+            MetadataMap::from_headers(parts.headers),
+            parts.extensions,
+            body,
+        ))
+    }
+}
+
+/// The body of tonic is not `Sync`, which is only polled by `&mut` here, so the lock is never
+/// contended.
+struct SyncBody<B>(Mutex<B>);
+
+impl<B> Stream for SyncBody<B>
+where
+    B: HttpBody<Data = Bytes> + Unpin,
+    B::Error: Into<BoxError>,
+{
+    type Item = Result<Frame<Bytes>, Status>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let body = self.0.get_mut().unwrap_or_else(|e| e.into_inner());
+        Pin::new(body)
+            .poll_frame(cx)
+            .map_err(|e| Status::from_error(e.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper_util::{client::legacy::Client, rt::TokioExecutor};
+    use tonic::codec::ProstCodec;
+    use volo::net::incoming::DefaultIncoming;
+
+    use super::*;
+    use crate::server::Server;
+
+    type BoxFuture<T, E> = Pin<Box<dyn Future<Output = Result<T, E>> + Send>>;
+
+    /// A tonic service as generated by `tonic-build`, echoing the message and the `x-key`.
+    #[derive(Clone)]
+    struct EchoServer;
+
+    impl tonic::server::NamedService for EchoServer {
+        const NAME: &'static str = "test.Echo";
+    }
+
+    impl<B> tower::Service<http::Request<B>> for EchoServer
+    where
+        B: HttpBody + Send + 'static,
+        B::Error: Into<BoxError> + Send + 'static,
+    {
+        type Response = http::Response<tonic::body::BoxBody>;
+        type Error = Infallible;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, req: http::Request<B>) -> Self::Future {
+            Box::pin(async move {
+                let mut grpc = tonic::server::Grpc::new(ProstCodec::<String, String>::default());
+                Ok(grpc.unary(Echo, req).await)
+            })
+        }
+    }
+
+    struct Echo;
+
+    impl tonic::server::UnaryService<String> for Echo {
+        type Response = String;
+        type Future = BoxFuture<tonic::Response<String>, tonic::Status>;
+
+        fn call(&mut self, req: tonic::Request<String>) -> Self::Future {
+            Box::pin(async move {
+                let key = req.metadata().get("x-key").cloned();
+                if req.get_ref() == "fail" {
+                    let mut metadata = tonic::metadata::MetadataMap::new();
+                    metadata.insert("x-reason", "asked".parse().unwrap());
+                    return Err(tonic::Status::with_details_and_metadata(
+                        tonic::Code::FailedPrecondition,
+                        "failed",
+                        Bytes::from_static(b"details"),
+                        metadata,
+                    ));
+                }
+                let mut resp = tonic::Response::new(req.into_inner());
+                if let Some(key) = key {
+                    resp.metadata_mut().insert("x-key", key);
+                }
+                Ok(resp)
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn round_trip() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Server::new().add_service(TonicService::new(EchoServer));
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        tokio::spawn(
+            server.run_with_shutdown(DefaultIncoming::from(listener), async move {
+                let _ = rx.await;
+                Ok(())
+            }),
+        );
+
+        // the tonic client of the tonic service served by volo, called from volo
+        let channel = Client::builder(TokioExecutor::new())
+            .http2_only(true)
+            .build_http::<tonic::body::BoxBody>();
+        let origin: http::Uri = format!("http://{addr}").parse().unwrap();
+        let client = TonicClient::new(
+            tonic::client::Grpc::with_origin(channel, origin),
+            |mut grpc, req: tonic::Request<String>| async move {
+                grpc.ready()
+                    .await
+                    .map_err(|e| tonic::Status::unavailable(e.to_string()))?;
+                grpc.unary(
+                    req,
+                    http::uri::PathAndQuery::from_static("/test.Echo/Echo"),
+                    ProstCodec::<String, String>::default(),
+                )
+                .await
+            },
+        );
+
+        let mut cx = ClientContext::new(volo::context::RpcInfo::with_role(
+            volo::context::Role::Client,
+        ));
+        let mut req = Request::new("hello".to_owned());
+        req.metadata_mut().insert("x-key", "value".parse().unwrap());
+        let resp = client.call(&mut cx, req).await.unwrap();
+        assert_eq!(resp.metadata().get("x-key").unwrap(), "value");
+        assert_eq!(resp.into_inner(), "hello");
+
+        let status = client
+            .call(&mut cx, Request::new("fail".to_owned()))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::FailedPrecondition);
+        assert_eq!(status.message(), "failed");
+        assert_eq!(status.details(), b"details");
+        assert_eq!(status.metadata().get("x-reason").unwrap(), "asked");
+
+        let _ = tx.send(());
+    }
+
+    #[test]
+    fn status() {
+        let status = Status::with_details_and_metadata(
+            Code::ResourceExhausted,
+            "quota",
+            Bytes::from_static(b"retry later"),
+            MetadataMap::new(),
+        );
+        let status = Status::from(tonic::Status::from(status));
+        assert_eq!(status.code(), Code::ResourceExhausted);
+        assert_eq!(status.message(), "quota");
+        assert_eq!(status.details(), b"retry later");
+    }
+}
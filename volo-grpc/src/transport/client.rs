@@ -10,16 +10,26 @@ use http_body_util::StreamBody;
 use hyper_util::rt::{TokioExecutor, TokioTimer};
 use motore::Service;
 use tower::{util::ServiceExt, Service as TowerService};
-use volo::net::Address;
+use volo::{
+    context::DeadlineExt,
+    net::{
+        fingerprint::{self, Protocol},
+        Address,
+    },
+    util::budget::DEFAULT_YIELD_BUDGET,
+};
 
 use super::connect::Connector;
 use crate::{
     client::Http2Config,
     codec::{
         compression::{CompressionEncoding, ACCEPT_ENCODING_HEADER, ENCODING_HEADER},
-        decode::Kind,
+        decode::{DecodeLimits, Kind},
+        MessageCodec,
     },
     context::{ClientContext, Config},
+    layer::grpc_timeout::encode_timeout,
+    metadata::GRPC_TIMEOUT_HEADER,
     Code, Request, Response, Status,
 };
 
@@ -31,6 +41,11 @@ pub struct ClientTransport<U> {
         Connector,
         StreamBody<crate::BoxStream<'static, Result<Frame<Bytes>, crate::Status>>>,
     >,
+    /// Whether to probe the target for a [`ProtocolMismatch`] after a call fails, which is only
+    /// done for plaintext since the probe doesn't speak TLS.
+    ///
+    /// [`ProtocolMismatch`]: volo::net::fingerprint::ProtocolMismatch
+    diagnose: bool,
     _marker: PhantomData<fn(U)>,
 }
 
@@ -38,6 +53,7 @@ impl<U> Clone for ClientTransport<U> {
     fn clone(&self) -> Self {
         Self {
             http_client: self.http_client.clone(),
+            diagnose: self.diagnose,
             _marker: self._marker,
         }
     }
@@ -51,7 +67,8 @@ impl<U> ClientTransport<U> {
             rpc_config.connect_timeout,
             rpc_config.read_timeout,
             rpc_config.write_timeout,
-        );
+        )
+        .with_connect_retry(rpc_config.connect_retry);
         let http_client = hyper_util::client::legacy::Client::builder(TokioExecutor::new())
             .timer(TokioTimer::new())
             .http2_only(true)
@@ -68,6 +85,7 @@ impl<U> ClientTransport<U> {
 
         ClientTransport {
             http_client,
+            diagnose: true,
             _marker: PhantomData,
         }
     }
@@ -83,7 +101,8 @@ impl<U> ClientTransport<U> {
             rpc_config.connect_timeout,
             rpc_config.read_timeout,
             rpc_config.write_timeout,
-        );
+        )
+        .with_connect_retry(rpc_config.connect_retry);
         let http_client = hyper_util::client::legacy::Client::builder(TokioExecutor::new())
             .timer(TokioTimer::new())
             .http2_only(true)
@@ -100,6 +119,7 @@ impl<U> ClientTransport<U> {
 
         ClientTransport {
             http_client,
+            diagnose: false,
             _marker: PhantomData,
         }
     }
@@ -126,7 +146,8 @@ where
             io::Error::new(std::io::ErrorKind::InvalidData, "address is required")
         })?;
 
-        let (metadata, extensions, message) = volo_req.into_parts();
+        let (mut metadata, extensions, message) = volo_req.into_parts();
+        metadata.normalize()?;
         let path = cx.rpc_info.method();
         let rpc_config = cx.rpc_info.config();
         let accept_compressions = &rpc_config.accept_compressions;
@@ -137,7 +158,8 @@ where
             .as_ref()
             .map(|config| config[0]);
 
-        let body = http_body_util::StreamBody::new(message.into_body(send_compression));
+        let codec = rpc_config.codec.unwrap_or_default();
+        let body = http_body_util::StreamBody::new(message.into_body_with(codec, send_compression));
 
         let mut req = http::Request::builder()
             .version(http::Version::HTTP_2)
@@ -149,8 +171,7 @@ where
         *req.headers_mut() = metadata.into_headers();
         req.headers_mut()
             .insert(TE, HeaderValue::from_static("trailers"));
-        req.headers_mut()
-            .insert(CONTENT_TYPE, HeaderValue::from_static("application/grpc"));
+        req.headers_mut().insert(CONTENT_TYPE, codec.content_type());
 
         // insert compression headers
         if let Some(send_compression) = send_compression {
@@ -168,33 +189,82 @@ where
             }
         }
 
-        let resp = http_client
+        // the remaining time of the call, e.g. the one inherited from the upstream, is sent to the
+        // server, and the call is cancelled when it elapses before the response
+        let deadline = cx.deadline();
+        if let Some(deadline) = deadline {
+            if deadline.is_elapsed() {
+                return Err(Status::deadline_exceeded(
+                    "deadline exceeded before sending",
+                ));
+            }
+            if !req.headers().contains_key(GRPC_TIMEOUT_HEADER) {
+                req.headers_mut()
+                    .insert(GRPC_TIMEOUT_HEADER, encode_timeout(deadline.remaining()));
+            }
+        }
+
+        let http_client = http_client
             .ready()
             .await
-            .map_err(|err| Status::from_error(err.into()))?
-            .call(req)
-            .await
             .map_err(|err| Status::from_error(err.into()))?;
+        let resp = match deadline {
+            Some(deadline) => deadline
+                .timeout(http_client.call(req))
+                .await
+                .ok_or_else(|| Status::deadline_exceeded("deadline exceeded"))?,
+            None => http_client.call(req).await,
+        };
+        let resp = match resp {
+            Ok(resp) => resp,
+            // the connection is established but the peer fails to speak HTTP/2 with us
+            Err(err) if self.diagnose && !err.is_connect() => {
+                let status = Status::from_error(err.into());
+                return Err(
+                    match fingerprint::diagnose(
+                        &target,
+                        "gRPC",
+                        Some(Protocol::Http2),
+                        fingerprint::DEFAULT_DIAGNOSE_TIMEOUT,
+                    )
+                    .await
+                    {
+                        Some(mismatch) => Status::from_protocol_mismatch(mismatch),
+                        None => status,
+                    },
+                );
+            }
+            Err(err) => return Err(Status::from_error(err.into())),
+        };
 
         let status_code = resp.status();
         let headers = resp.headers();
 
-        if let Some(status) = Status::from_header_map(headers) {
-            if status.code() != Code::Ok {
-                return Err(status);
-            }
-        }
+        // A `grpc-status` in the headers means this is a trailers-only response.
+        let kind = match Status::from_header_map(headers) {
+            Some(status) if status.code() != Code::Ok => return Err(status),
+            Some(_) => Kind::TrailersOnly(status_code, headers.clone()),
+            None => Kind::Response(status_code),
+        };
 
         let accept_compression =
             CompressionEncoding::from_encoding_header(headers, &rpc_config.accept_compressions)?;
 
+        let codec = MessageCodec::from_content_type_with(headers, rpc_config.codec);
+
         let (parts, body) = resp.into_parts();
 
-        let body = U::from_body(
+        let body = U::from_body_with(
             Some(path),
-            body,
-            Kind::Response(status_code),
+            crate::body::boxed(body),
+            kind,
+            codec,
             accept_compression,
+            DecodeLimits {
+                max_message_size: rpc_config.max_decoding_message_size,
+                max_total_size: rpc_config.max_decoding_total_size,
+                yield_budget: rpc_config.yield_budget.unwrap_or(DEFAULT_YIELD_BUDGET),
+            },
         )?;
         let resp = hyper::Response::from_parts(parts, body);
         Ok(Response::from_http(resp))
@@ -226,6 +296,90 @@ fn build_uri(addr: Address, path: &str) -> hyper::Uri {
 
 #[cfg(test)]
 mod tests {
+    use futures::stream;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+    use volo::{
+        context::{Context, Role, RpcInfo},
+        net::{fingerprint::Protocol, Address},
+        FastStr,
+    };
+
+    use super::*;
+    use crate::{body::BoxBody, codec::compression::CompressionEncoding};
+
+    struct Empty;
+
+    impl crate::message::SendEntryMessage for Empty {
+        fn into_body(
+            self,
+            _: Option<CompressionEncoding>,
+        ) -> crate::BoxStream<'static, Result<Frame<Bytes>, Status>> {
+            Box::pin(stream::empty())
+        }
+    }
+
+    impl crate::message::RecvEntryMessage for Empty {
+        fn from_body(
+            _: Option<&str>,
+            _: BoxBody,
+            _: Kind,
+            _: Option<CompressionEncoding>,
+            _: DecodeLimits,
+        ) -> Result<Self, Status> {
+            Ok(Empty)
+        }
+    }
+
+    /// Answers every connection with `answer` after reading the first bytes, like a server of
+    /// another protocol rejecting the HTTP/2 preface.
+    async fn stub(answer: &'static [u8]) -> Address {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut conn, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 64];
+                    let _ = conn.read(&mut buf).await;
+                    let _ = conn.write_all(answer).await;
+                });
+            }
+        });
+        Address::from(addr)
+    }
+
+    #[tokio::test]
+    async fn protocol_mismatch() {
+        let transport = ClientTransport::<Empty>::new(&Http2Config::default(), &Config::default());
+        for (answer, found) in [
+            (
+                &b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n"[..],
+                Protocol::Http1,
+            ),
+            (
+                &[0x15, 0x03, 0x03, 0x00, 0x02, 0x02, 0x46][..],
+                Protocol::Tls,
+            ),
+        ] {
+            let mut cx = ClientContext::new(RpcInfo::with_role(Role::Client));
+            cx.rpc_info_mut()
+                .callee_mut()
+                .set_address(stub(answer).await);
+            cx.rpc_info_mut()
+                .set_method(FastStr::from_static_str("/test.Echo/Echo"));
+            let status = transport
+                .call(&mut cx, Request::new(Empty))
+                .await
+                .err()
+                .unwrap();
+            assert_eq!(status.code(), Code::Unavailable);
+            let mismatch = status.protocol_mismatch().unwrap();
+            assert_eq!(mismatch.found(), found);
+            assert_eq!(mismatch.expected(), "gRPC");
+        }
+    }
 
     #[test]
     fn test_build_uri_ip() {
@@ -36,6 +36,7 @@ impl Connector {
             mt.set_connect_timeout(cfg.connect_timeout);
             mt.set_read_timeout(cfg.read_timeout);
             mt.set_write_timeout(cfg.write_timeout);
+            mt.set_connect_retry(cfg.connect_retry);
         }
         Self::Default(mt)
     }
@@ -48,6 +49,7 @@ impl Connector {
             mt.set_connect_timeout(cfg.connect_timeout);
             mt.set_read_timeout(cfg.read_timeout);
             mt.set_write_timeout(cfg.write_timeout);
+            mt.set_connect_retry(cfg.connect_retry);
         }
         Self::Tls(mt)
     }
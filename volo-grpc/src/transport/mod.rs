@@ -4,3 +4,33 @@ mod client;
 mod connect;
 
 pub use client::ClientTransport;
+use volo::config::ConfigError;
+
+/// The limits of the HTTP2 settings, see [RFC 9113](https://www.rfc-editor.org/rfc/rfc9113#section-6.5.2).
+const MIN_FRAME_SIZE: u32 = 1 << 14;
+const MAX_FRAME_SIZE: u32 = (1 << 24) - 1;
+const MAX_WINDOW_SIZE: u32 = (1 << 31) - 1;
+
+pub(crate) fn check_frame_size(err: &mut ConfigError, size: u32) {
+    err.ensure(
+        (MIN_FRAME_SIZE..=MAX_FRAME_SIZE).contains(&size),
+        "http2_max_frame_size",
+        format!("{size} is not between {MIN_FRAME_SIZE} and {MAX_FRAME_SIZE}"),
+    );
+}
+
+pub(crate) fn check_window_size(err: &mut ConfigError, field: &'static str, size: u32) {
+    err.ensure(
+        size <= MAX_WINDOW_SIZE,
+        field,
+        format!("{size} is larger than {MAX_WINDOW_SIZE}"),
+    );
+}
+
+pub(crate) fn check_send_buf_size(err: &mut ConfigError, size: usize) {
+    err.ensure(
+        size <= u32::MAX as usize,
+        "http2_max_send_buf_size",
+        format!("{size} is larger than {}", u32::MAX),
+    );
+}
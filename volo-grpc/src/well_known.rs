@@ -0,0 +1,153 @@
+//! Conversions between the well-known types of protobuf and the idiomatic Rust types, used by the
+//! code generated with `volo_build::Builder::well_known_types`.
+//!
+//! The values out of range are saturated instead of failing:
+//!
+//! - A `google.protobuf.Timestamp` is clamped to its valid range, i.e. from
+//!   `0001-01-01T00:00:00Z` to `9999-12-31T23:59:59.999999999Z`, and so is a [`SystemTime`]
+//!   converted into it. The `nanos` out of `[0, 999_999_999]` are clamped. A timestamp which can
+//!   not be represented by the [`SystemTime`] of the platform, e.g. before 1601 on Windows,
+//!   becomes the earliest time representable, which is the same in the other direction.
+//! - A negative `google.protobuf.Duration` becomes [`Duration::ZERO`], as [`Duration`] is
+//!   unsigned, and a [`Duration`] longer than the maximum of about 10,000 years becomes the
+//!   maximum.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The seconds of `0001-01-01T00:00:00Z` since the Unix epoch.
+pub const MIN_TIMESTAMP_SECONDS: i64 = -62_135_596_800;
+/// The seconds of `9999-12-31T23:59:59Z` since the Unix epoch.
+pub const MAX_TIMESTAMP_SECONDS: i64 = 253_402_300_799;
+/// The maximum seconds of a `google.protobuf.Duration`, about 10,000 years.
+pub const MAX_DURATION_SECONDS: i64 = 315_576_000_000;
+
+const MAX_NANOS: i32 = 999_999_999;
+
+/// Converts the `seconds` and `nanos` of a `google.protobuf.Timestamp` into a [`SystemTime`].
+pub fn timestamp_to_system_time(seconds: i64, nanos: i32) -> SystemTime {
+    let seconds = seconds.clamp(MIN_TIMESTAMP_SECONDS, MAX_TIMESTAMP_SECONDS);
+    let nanos = Duration::from_nanos(nanos.clamp(0, MAX_NANOS) as u64);
+    let time = if seconds >= 0 {
+        UNIX_EPOCH.checked_add(Duration::from_secs(seconds as u64) + nanos)
+    } else {
+        UNIX_EPOCH
+            .checked_sub(Duration::from_secs(seconds.unsigned_abs()))
+            .map(|time| time + nanos)
+    };
+    time.unwrap_or_else(earliest)
+}
+
+/// Converts a [`SystemTime`] into the `seconds` and `nanos` of a `google.protobuf.Timestamp`.
+pub fn system_time_to_timestamp(time: SystemTime) -> (i64, i32) {
+    let (seconds, nanos) = match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => (
+            i64::try_from(since.as_secs()).unwrap_or(i64::MAX),
+            since.subsec_nanos() as i32,
+        ),
+        Err(e) => {
+            // the nanos are always counted forward from the seconds
+            let before = e.duration();
+            let seconds = i64::try_from(before.as_secs()).map_or(i64::MIN, |s| -s);
+            match before.subsec_nanos() {
+                0 => (seconds, 0),
+                nanos => (seconds.saturating_sub(1), 1_000_000_000 - nanos as i32),
+            }
+        }
+    };
+    if seconds < MIN_TIMESTAMP_SECONDS {
+        (MIN_TIMESTAMP_SECONDS, 0)
+    } else if seconds > MAX_TIMESTAMP_SECONDS {
+        (MAX_TIMESTAMP_SECONDS, MAX_NANOS)
+    } else {
+        (seconds, nanos)
+    }
+}
+
+/// Converts the `seconds` and `nanos` of a `google.protobuf.Duration` into a [`Duration`].
+pub fn duration_to_std(seconds: i64, nanos: i32) -> Duration {
+    if seconds < 0 || (seconds == 0 && nanos < 0) {
+        return Duration::ZERO;
+    }
+    Duration::new(
+        seconds.min(MAX_DURATION_SECONDS) as u64,
+        nanos.clamp(0, MAX_NANOS) as u32,
+    )
+}
+
+/// Converts a [`Duration`] into the `seconds` and `nanos` of a `google.protobuf.Duration`.
+pub fn std_to_duration(duration: Duration) -> (i64, i32) {
+    match i64::try_from(duration.as_secs()) {
+        Ok(seconds) if seconds <= MAX_DURATION_SECONDS => (seconds, duration.subsec_nanos() as i32),
+        _ => (MAX_DURATION_SECONDS, MAX_NANOS),
+    }
+}
+
+/// The earliest [`SystemTime`] not before `0001-01-01T00:00:00Z` representable by the platform.
+fn earliest() -> SystemTime {
+    // steps back from the epoch, and halves the step when it is not representable
+    let mut remaining = Duration::from_secs(MIN_TIMESTAMP_SECONDS.unsigned_abs());
+    let mut step = remaining;
+    let mut earliest = UNIX_EPOCH;
+    while !remaining.is_zero() && step >= Duration::from_secs(1) {
+        let step_back = step.min(remaining);
+        match earliest.checked_sub(step_back) {
+            Some(time) => {
+                earliest = time;
+                remaining -= step_back;
+            }
+            None => step /= 2,
+        }
+    }
+    earliest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamp() {
+        let cases = [
+            (0, 0),
+            (1_700_000_000, 123),
+            (-1, 999_999_999),
+            (MAX_TIMESTAMP_SECONDS, MAX_NANOS),
+        ];
+        for (seconds, nanos) in cases {
+            let time = timestamp_to_system_time(seconds, nanos);
+            assert_eq!(system_time_to_timestamp(time), (seconds, nanos));
+        }
+        assert_eq!(
+            timestamp_to_system_time(-1, 500_000_000),
+            UNIX_EPOCH - Duration::from_millis(500)
+        );
+
+        // saturated
+        assert_eq!(
+            system_time_to_timestamp(timestamp_to_system_time(i64::MAX, i32::MAX)),
+            (MAX_TIMESTAMP_SECONDS, MAX_NANOS)
+        );
+        assert_eq!(timestamp_to_system_time(0, -1), UNIX_EPOCH);
+        let (seconds, _) = system_time_to_timestamp(timestamp_to_system_time(i64::MIN, 0));
+        assert!(seconds >= MIN_TIMESTAMP_SECONDS);
+    }
+
+    #[test]
+    fn duration() {
+        let duration = Duration::new(3, 500);
+        assert_eq!(std_to_duration(duration), (3, 500));
+        assert_eq!(duration_to_std(3, 500), duration);
+
+        // saturated
+        assert_eq!(duration_to_std(-1, 0), Duration::ZERO);
+        assert_eq!(duration_to_std(0, -1), Duration::ZERO);
+        assert_eq!(
+            duration_to_std(i64::MAX, 0),
+            Duration::from_secs(MAX_DURATION_SECONDS as u64)
+        );
+        assert_eq!(
+            std_to_duration(Duration::MAX),
+            (MAX_DURATION_SECONDS, MAX_NANOS)
+        );
+    }
+}
@@ -1,3 +1,5 @@
+#[cfg(feature = "mmap")]
+use std::sync::Arc;
 use std::{
     error::Error,
     fmt,
@@ -16,6 +18,9 @@ use motore::BoxError;
 use pin_project::pin_project;
 #[cfg(feature = "__json")]
 use serde::de::DeserializeOwned;
+use volo::error::{CodedError, ErrorCode};
+
+use crate::error::code;
 
 // The `futures_util::stream::BoxStream` does not have `Sync`
 type BoxStream<'a, T> = Pin<Box<dyn Stream<Item = T> + Send + Sync + 'a>>;
@@ -52,6 +57,36 @@ impl Body {
     {
         Self::Body(BoxBody::new(body.map_err(Into::into)))
     }
+
+    /// Creates a body of a shared immutable buffer, which is written to the connection without
+    /// copying, so the same buffer can be cloned cheaply for each response, e.g. a cached asset.
+    pub fn from_shared(bytes: Bytes) -> Self {
+        Self::Full(Full::new(bytes))
+    }
+
+    /// Creates a body of a memory-mapped file without reading it into the memory, whose pages
+    /// are loaded by the kernel on demand when written to the connection.
+    ///
+    /// The file should not be modified while it is mapped, see [`memmap2::Mmap`].
+    #[cfg(feature = "mmap")]
+    pub fn from_mmap(mmap: Arc<memmap2::Mmap>) -> Self {
+        Self::from_shared(mmap_bytes(mmap))
+    }
+}
+
+/// Converts the memory-mapped file to [`Bytes`] without copying, which keeps the mapping alive
+/// until all the clones of the [`Bytes`] are dropped.
+#[cfg(feature = "mmap")]
+pub fn mmap_bytes(mmap: Arc<memmap2::Mmap>) -> Bytes {
+    struct Owner(Arc<memmap2::Mmap>);
+
+    impl AsRef<[u8]> for Owner {
+        fn as_ref(&self) -> &[u8] {
+            &self.0
+        }
+    }
+
+    Bytes::from_owner(Owner(mmap))
 }
 
 impl http_body::Body for Body {
@@ -210,6 +245,23 @@ impl fmt::Display for ResponseConvertError {
 
 impl Error for ResponseConvertError {}
 
+impl CodedError for ResponseConvertError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::BodyCollectionError => code::BODY_COLLECTION,
+            Self::StringUtf8Error => code::INVALID_UTF8,
+            #[cfg(feature = "__json")]
+            Self::JsonDeserializeError(_) => code::INVALID_JSON,
+        }
+    }
+}
+
+impl From<Incoming> for Body {
+    fn from(value: Incoming) -> Self {
+        Self::from_body(value)
+    }
+}
+
 impl From<()> for Body {
     fn from(_: ()) -> Self {
         Self::empty()
@@ -6,7 +6,10 @@ use volo::context::Context;
 
 use crate::{
     context::ClientContext,
-    error::client::{status_error, timeout, ClientError},
+    error::{
+        client::{status_error, timeout, ClientError},
+        code,
+    },
     request::ClientRequest,
     response::ClientResponse,
 };
@@ -59,7 +62,7 @@ where
                 tokio::select! {
                     res = fut => res,
                     _ = sleep => {
-                        tracing::error!("[Volo-HTTP]: request timeout.");
+                        tracing::error!("[Volo-HTTP]: request timeout, code: {}", code::TIMEOUT);
                         return Err(timeout());
                     }
                 }
@@ -14,7 +14,11 @@ use motore::{
 use paste::paste;
 use volo::{
     client::MkClient,
-    context::Context,
+    config::ConfigError,
+    context::{
+        log_id::{self, LogIdConfig, LogIdExt},
+        Context,
+    },
     loadbalance::MkLbLayer,
     net::{
         dial::{DefaultMakeTransport, MakeTransport},
@@ -44,6 +48,8 @@ pub mod discover;
 #[doc(hidden)]
 pub mod loadbalance;
 mod meta;
+#[cfg(feature = "http2")]
+pub mod protocol;
 mod request_builder;
 mod transport;
 
@@ -82,6 +88,8 @@ struct BuilderConfig {
     fail_on_error_status: bool,
     #[cfg(feature = "__tls")]
     disable_tls: bool,
+    /// The target uses https while TLS related features are not enabled.
+    https_without_tls: bool,
 }
 
 impl Default for BuilderConfig {
@@ -92,6 +100,7 @@ impl Default for BuilderConfig {
             fail_on_error_status: false,
             #[cfg(feature = "__tls")]
             disable_tls: false,
+            https_without_tls: false,
         }
     }
 }
@@ -386,6 +395,7 @@ impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
     where
         A: Into<Address>,
     {
+        self.builder_config.https_without_tls = false;
         self.target = Target::from_address(
             address,
             #[cfg(feature = "__tls")]
@@ -405,6 +415,7 @@ impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
     where
         H: AsRef<str>,
     {
+        self.builder_config.https_without_tls = false;
         self.target = Target::from_host(
             host,
             None,
@@ -418,17 +429,13 @@ impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
     ///
     /// If there is no target specified when building a request, client will use this address.
     ///
-    /// # Panics
-    ///
-    /// This function will panic when TLS related features are not enable but the `https` is
-    /// `true`.
+    /// If TLS related features are not enabled but the `https` is `true`, [`ClientBuilder::build`]
+    /// will return an error.
     pub fn scheme_host_and_port<H>(&mut self, https: bool, host: H, port: Option<u16>) -> &mut Self
     where
         H: AsRef<str>,
     {
-        if cfg!(not(feature = "__tls")) && https {
-            panic!("tls is not enabled while target uses https");
-        }
+        self.builder_config.https_without_tls = cfg!(not(feature = "__tls")) && https;
         self.target = Target::from_host(
             host,
             port,
@@ -571,6 +578,14 @@ impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
         self
     }
 
+    /// Set the config of the protocols, e.g. speaking HTTP/2 to the cleartext origins, see
+    /// [`protocol`].
+    #[cfg(feature = "http2")]
+    pub fn set_protocol_config(&mut self, config: protocol::ProtocolConfig) -> &mut Self {
+        self.http_config.protocol = config;
+        self
+    }
+
     /// Set the maximum idle time for a connection.
     pub fn set_connect_timeout(&mut self, timeout: Duration) -> &mut Self {
         self.connector.set_connect_timeout(Some(timeout));
@@ -598,8 +613,8 @@ impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
         self
     }
 
-    /// Build the HTTP client.
-    pub fn build(mut self) -> C::Target
+    /// Builds the HTTP client, or returns all the problems of the configuration.
+    pub fn build(mut self) -> Result<C::Target, ConfigError>
     where
         IL: Layer<MetaService<ClientTransport>>,
         IL::Service: Send + Sync + 'static,
@@ -610,6 +625,21 @@ impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
         OL::Service: Send + Sync + 'static,
         C: MkClient<Client<OL::Service>>,
     {
+        let mut err = ConfigError::new();
+        self.check(&mut err);
+        let caller_name = if self.caller_name.is_empty() {
+            FastStr::from_static_str(PKG_NAME_WITH_VER)
+        } else {
+            self.caller_name
+        };
+        let user_agent = HeaderValue::from_str(caller_name.as_str());
+        err.ensure(
+            user_agent.is_ok(),
+            "caller_name",
+            "is not a valid header value",
+        );
+        err.into_result()?;
+
         let transport_config = ClientTransportConfig {
             stat_enable: self.builder_config.stat_enable,
             #[cfg(feature = "__tls")]
@@ -619,12 +649,16 @@ impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
             default_timeout: self.builder_config.timeout,
             fail_on_error_status: self.builder_config.fail_on_error_status,
         };
+        #[cfg(feature = "http2")]
+        let protocols = protocol::ProtocolPreferences::new(self.http_config.protocol.clone());
         let transport = ClientTransport::new(
             self.http_config,
             transport_config,
             self.connector,
             #[cfg(feature = "__tls")]
             self.tls_config.unwrap_or_default(),
+            #[cfg(feature = "http2")]
+            protocols.clone(),
         );
         let meta_service = MetaService::new(transport, meta_config);
         let service = self.outer_layer.layer(
@@ -633,17 +667,8 @@ impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
                 .layer(self.inner_layer.layer(meta_service)),
         );
 
-        let caller_name = if self.caller_name.is_empty() {
-            FastStr::from_static_str(PKG_NAME_WITH_VER)
-        } else {
-            self.caller_name
-        };
-
-        if !caller_name.is_empty() && self.headers.get(header::USER_AGENT).is_none() {
-            self.headers.insert(
-                header::USER_AGENT,
-                HeaderValue::from_str(caller_name.as_str()).expect("Invalid caller name"),
-            );
+        if let Ok(user_agent) = user_agent {
+            self.headers.entry(header::USER_AGENT).or_insert(user_agent);
         }
 
         let client_inner = ClientInner {
@@ -652,12 +677,80 @@ impl<IL, OL, C, LB> ClientBuilder<IL, OL, C, LB> {
             default_target: self.target,
             target_parser: self.target_parser,
             headers: self.headers,
+            #[cfg(feature = "http2")]
+            protocols,
         };
         let client = Client {
             service,
             inner: Arc::new(client_inner),
         };
-        self.mk_client.mk_client(client)
+        Ok(self.mk_client.mk_client(client))
+    }
+
+    /// Builds the HTTP client, which is useful for the static configurations.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the configuration is invalid, see [`ClientBuilder::build`].
+    pub fn build_unchecked(self) -> C::Target
+    where
+        IL: Layer<MetaService<ClientTransport>>,
+        IL::Service: Send + Sync + 'static,
+        LB: MkLbLayer,
+        LB::Layer: Layer<IL::Service>,
+        <LB::Layer as Layer<IL::Service>>::Service: Send + Sync,
+        OL: Layer<<LB::Layer as Layer<IL::Service>>::Service>,
+        OL::Service: Send + Sync + 'static,
+        C: MkClient<Client<OL::Service>>,
+    {
+        self.build().unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    fn check(&self, err: &mut ConfigError) {
+        err.ensure(
+            !self.builder_config.https_without_tls,
+            "target",
+            "uses https while tls is not enabled",
+        );
+        err.ensure_address("target", self.target.address());
+        #[cfg(feature = "__tls")]
+        if let Some(tls_config) = &self.tls_config {
+            err.ensure(
+                self.builder_config.disable_tls || tls_config.has_root_certs(),
+                "tls_config",
+                "no root certificate is trusted, all the handshakes will fail",
+            );
+        }
+        err.ensure(
+            self.http_config.max_headers != Some(0),
+            "max_headers",
+            "must be greater than zero",
+        );
+
+        let config = self.connector.config();
+        let timeout = self.builder_config.timeout;
+        err.ensure_non_zero("request_timeout", timeout);
+        err.ensure_non_zero("connect_timeout", config.connect_timeout);
+        err.ensure_non_zero("read_timeout", config.read_timeout);
+        err.ensure_non_zero("write_timeout", config.write_timeout);
+        err.ensure_within(
+            "connect_timeout",
+            config.connect_timeout,
+            "request_timeout",
+            timeout,
+        );
+        err.ensure_within(
+            "read_timeout",
+            config.read_timeout,
+            "request_timeout",
+            timeout,
+        );
+        err.ensure_within(
+            "write_timeout",
+            config.write_timeout,
+            "request_timeout",
+            timeout,
+        );
     }
 }
 
@@ -667,6 +760,8 @@ struct ClientInner {
     default_target: Target,
     target_parser: TargetParser,
     headers: HeaderMap,
+    #[cfg(feature = "http2")]
+    protocols: protocol::ProtocolPreferences,
 }
 
 #[derive(Clone)]
@@ -726,6 +821,12 @@ impl<S> Client<S> {
         &self.inner.default_target
     }
 
+    /// Get the protocols preferred by the origins, which can be inspected and cleared.
+    #[cfg(feature = "http2")]
+    pub fn protocol_preferences(&self) -> &protocol::ProtocolPreferences {
+        &self.inner.protocols
+    }
+
     /// Send a request to the target address.
     ///
     /// This is a low-level method and you should build the `uri` and `request`, and get the
@@ -743,7 +844,7 @@ impl<S> Client<S> {
     /// use volo_http::{body::Body, client::Client, request::ClientRequest};
     /// use volo_http::client::utils::Target;
     ///
-    /// let client = Client::builder().build();
+    /// let client = Client::builder().build().unwrap();
     /// let addr: SocketAddr = "[::]:8080".parse().unwrap();
     /// let addr = Address::from(addr);
     /// let resp = client
@@ -828,6 +929,22 @@ where
     ) -> Result<Self::Response, Self::Error> {
         req.headers_mut().extend(self.inner.headers.clone());
 
+        // the log id set in the headers manually is kept
+        let header = LogIdConfig::global().header().as_str();
+        match req.headers().get(header).and_then(|v| v.to_str().ok()) {
+            Some(log_id) => cx.set_log_id(FastStr::new(log_id)),
+            None => {
+                if let Some(log_id) = log_id::outgoing(cx) {
+                    if let (Ok(name), Ok(value)) = (
+                        HeaderName::from_bytes(header.as_bytes()),
+                        HeaderValue::from_str(&log_id),
+                    ) {
+                        req.headers_mut().insert(name, value);
+                    }
+                }
+            }
+        }
+
         let has_metainfo = METAINFO.try_with(|_| {}).is_ok();
 
         let fut = self.service.call(cx, req);
@@ -856,7 +973,12 @@ where
     U: TryInto<Uri>,
     U::Error: Into<BoxError>,
 {
-    ClientBuilder::new().build().get(uri)?.send().await
+    ClientBuilder::new()
+        .build()
+        .map_err(builder_error)?
+        .get(uri)?
+        .send()
+        .await
 }
 
 // The `httpbin.org` always responses a json data.
@@ -892,7 +1014,7 @@ mod client_tests {
     const USER_AGENT_VAL: &str = "volo-http-unit-test";
 
     fn client_types_check() {
-        let _: DefaultClient = ClientBuilder::new().build();
+        let _: DefaultClient = ClientBuilder::new().build().unwrap();
     }
 
     #[tokio::test]
@@ -911,7 +1033,7 @@ mod client_tests {
     async fn client_builder_with_header() {
         let mut builder = Client::builder();
         builder.header(header::USER_AGENT, USER_AGENT_VAL).unwrap();
-        let client = builder.build();
+        let client = builder.build().unwrap();
 
         let resp = client
             .get(HTTPBIN_GET)
@@ -931,7 +1053,7 @@ mod client_tests {
     async fn client_builder_with_host() {
         let mut builder = Client::builder();
         builder.host("httpbin.org");
-        let client = builder.build();
+        let client = builder.build().unwrap();
 
         let resp = client
             .get("/get")
@@ -960,7 +1082,7 @@ mod client_tests {
                 false,
             )
             .callee_name("httpbin.org");
-        let client = builder.build();
+        let client = builder.build().unwrap();
 
         let resp = client
             .get("/get")
@@ -980,7 +1102,7 @@ mod client_tests {
     async fn client_builder_with_https() {
         let mut builder = Client::builder();
         builder.scheme_host_and_port(true, "httpbin.org", None);
-        let client = builder.build();
+        let client = builder.build().unwrap();
 
         let resp = client
             .get("/get")
@@ -1004,7 +1126,7 @@ mod client_tests {
             .unwrap();
         let mut builder = Client::builder();
         builder.address(addr, true).callee_name("httpbin.org");
-        let client = builder.build();
+        let client = builder.build().unwrap();
 
         let resp = client
             .get("/get")
@@ -1023,7 +1145,7 @@ mod client_tests {
     async fn client_builder_with_port() {
         let mut builder = Client::builder();
         builder.scheme_host_and_port(false, "httpbin.org", Some(443));
-        let client = builder.build();
+        let client = builder.build().unwrap();
 
         let resp = client.get("/get").unwrap().send().await.unwrap();
         // Send HTTP request to the HTTPS port (443), `httpbin.org` will response `400 Bad
@@ -1035,7 +1157,7 @@ mod client_tests {
     async fn fail_on_status() {
         let mut builder = Client::builder();
         builder.host("httpbin.org").fail_on_error_status(true);
-        let client = builder.build();
+        let client = builder.build().unwrap();
         assert_eq!(
             format!(
                 "{}",
@@ -1057,7 +1179,7 @@ mod client_tests {
 
         let mut builder = Client::builder();
         builder.disable_tls(true);
-        let client = builder.build();
+        let client = builder.build().unwrap();
         assert_eq!(
             format!(
                 "{}",
@@ -1072,3 +1194,104 @@ mod client_tests {
         );
     }
 }
+
+#[cfg(test)]
+mod check_config {
+    use std::{net::SocketAddr, time::Duration};
+
+    use motore::layer::Identity;
+    #[cfg(feature = "rustls")]
+    use volo::net::tls::TlsConnector;
+
+    use super::{loadbalance::DefaultLB, ClientBuilder, DefaultMkClient};
+
+    type Builder = ClientBuilder<Identity, Identity, DefaultMkClient, DefaultLB>;
+    type Case = (fn(&mut Builder), &'static [&'static str]);
+
+    #[tokio::test]
+    async fn check_config() {
+        let cases: &[Case] = &[
+            (|_| {}, &[]),
+            (
+                |b| {
+                    b.set_connect_timeout(Duration::from_secs(1))
+                        .set_request_timeout(Duration::from_secs(2));
+                },
+                &[],
+            ),
+            (
+                |b| {
+                    b.set_read_timeout(Duration::ZERO);
+                },
+                &["read_timeout"],
+            ),
+            (
+                |b| {
+                    b.set_connect_timeout(Duration::from_secs(3))
+                        .set_write_timeout(Duration::from_secs(3))
+                        .set_request_timeout(Duration::from_secs(2));
+                },
+                &["connect_timeout", "write_timeout"],
+            ),
+            (
+                |b| {
+                    b.set_max_headers(0);
+                },
+                &["max_headers"],
+            ),
+            (
+                |b| {
+                    b.caller_name("bad\ncaller");
+                },
+                &["caller_name"],
+            ),
+            (
+                |b| {
+                    b.address(
+                        SocketAddr::from(([127, 0, 0, 1], 0)),
+                        #[cfg(feature = "__tls")]
+                        false,
+                    );
+                },
+                &["target"],
+            ),
+            #[cfg(feature = "rustls")]
+            (
+                |b| {
+                    b.set_tls_config(
+                        TlsConnector::builder()
+                            .enable_default_root_certs(false)
+                            .build()
+                            .unwrap(),
+                    );
+                },
+                &["tls_config"],
+            ),
+            #[cfg(not(feature = "__tls"))]
+            (
+                |b| {
+                    b.scheme_host_and_port(true, "example.com", None);
+                },
+                &["target"],
+            ),
+            #[cfg(not(feature = "__tls"))]
+            (
+                |b| {
+                    b.scheme_host_and_port(true, "example.com", None)
+                        .host("example.com");
+                },
+                &[],
+            ),
+        ];
+
+        for (i, (setup, fields)) in cases.iter().enumerate() {
+            let mut builder = ClientBuilder::new();
+            setup(&mut builder);
+            let problems = match builder.build() {
+                Ok(_) => Vec::new(),
+                Err(err) => err.problems().iter().map(|p| p.field).collect(),
+            };
+            assert_eq!(problems, *fields, "case {i}");
+        }
+    }
+}
@@ -0,0 +1,478 @@
+//! HTTP/2 of the client, with the remembered fallback to HTTP/1.1 for the misbehaving origins.
+//!
+//! With [`ProtocolConfig::http2_prior_knowledge`], the client speaks HTTP/2 to the cleartext
+//! origins directly (h2c). Some origins accept it but misbehave, so an origin is marked as
+//! preferring HTTP/1.1 for [`ProtocolConfig::fallback_ttl`] when a connection to it shows any of
+//! the failure signatures:
+//!
+//! - the handshake fails;
+//! - the connection goes away (`GOAWAY`) before the response, including the protocol errors of
+//!   the connection, e.g. an HTTP/1.1 origin answering the preface;
+//! - the streams are reset by the origin more than [`ProtocolConfig::reset_threshold`] times in
+//!   [`ProtocolConfig::reset_window`].
+//!
+//! The failed connection is dropped, and the request is retried on a new HTTP/1.1 connection if
+//! it's idempotent and has no body, or fails otherwise. HTTP/2 is probed again after the TTL.
+//!
+//! The preferences are kept by each client, see [`Client::protocol_preferences`], and can be
+//! cleared manually. The protocol of an origin can also be forced by [`ProtocolConfig::forced`],
+//! which never falls back.
+//!
+//! The origins are the `Host`s of the requests, or the names of the callees without it. The connections over
+//! TLS are always HTTP/1.1, since the client does not offer `h2` by ALPN.
+//!
+//! [`Client::protocol_preferences`]: super::Client::protocol_preferences
+
+use std::{
+    collections::{HashMap, VecDeque},
+    error::Error,
+    sync::Arc,
+    time::Duration,
+};
+
+use faststr::FastStr;
+use parking_lot::Mutex;
+use tokio::time::Instant;
+
+/// The HTTP versions of the connections.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Protocol {
+    Http1,
+    Http2,
+}
+
+/// The config of the protocols of the client, see the [module docs](self).
+#[derive(Clone, Debug)]
+pub struct ProtocolConfig {
+    /// Whether to speak HTTP/2 to the cleartext origins without the upgrade.
+    ///
+    /// Default is `false`.
+    pub http2_prior_knowledge: bool,
+    /// The protocols forced for the origins, which never fall back.
+    pub forced: HashMap<FastStr, Protocol>,
+    /// How long an origin prefers HTTP/1.1 after the fallback.
+    ///
+    /// Default is 5 minutes.
+    pub fallback_ttl: Duration,
+    /// The max number of the streams reset by an origin in [`Self::reset_window`] before the
+    /// fallback.
+    ///
+    /// Default is 3.
+    pub reset_threshold: usize,
+    /// Default is 10 seconds.
+    pub reset_window: Duration,
+}
+
+impl Default for ProtocolConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProtocolConfig {
+    pub fn new() -> Self {
+        Self {
+            http2_prior_knowledge: false,
+            forced: HashMap::new(),
+            fallback_ttl: Duration::from_secs(5 * 60),
+            reset_threshold: 3,
+            reset_window: Duration::from_secs(10),
+        }
+    }
+}
+
+/// A failure signature of the HTTP/2 connections.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Failure {
+    Handshake,
+    GoAway,
+    Reset,
+}
+
+impl Failure {
+    /// Returns the failure signature of the error of sending a request, if any.
+    pub(crate) fn of(err: &hyper::Error) -> Option<Self> {
+        let mut source: Option<&(dyn Error + 'static)> = Some(err);
+        while let Some(err) = source {
+            if let Some(err) = err.downcast_ref::<h2::Error>() {
+                if err.is_go_away() {
+                    return Some(Self::GoAway);
+                }
+                if err.is_reset() && err.is_remote() {
+                    return Some(Self::Reset);
+                }
+                return None;
+            }
+            source = err.source();
+        }
+        None
+    }
+}
+
+#[derive(Default)]
+struct Origin {
+    /// Prefers HTTP/1.1 until the instant.
+    h1_until: Option<Instant>,
+    /// The instants of the recent resets.
+    resets: VecDeque<Instant>,
+}
+
+/// The protocols preferred by the origins of a client, see the [module docs](self).
+///
+/// The clones share the same preferences.
+#[derive(Clone, Default)]
+pub struct ProtocolPreferences {
+    config: Arc<ProtocolConfig>,
+    origins: Arc<Mutex<HashMap<FastStr, Origin>>>,
+}
+
+impl ProtocolPreferences {
+    pub(crate) fn new(config: ProtocolConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            origins: Default::default(),
+        }
+    }
+
+    pub fn config(&self) -> &ProtocolConfig {
+        &self.config
+    }
+
+    /// Returns the origins preferring HTTP/1.1, with the time left before HTTP/2 is probed again.
+    pub fn h1_preferred(&self) -> Vec<(FastStr, Duration)> {
+        let now = Instant::now();
+        self.origins
+            .lock()
+            .iter()
+            .filter_map(|(origin, state)| {
+                let until = state.h1_until.filter(|until| *until > now)?;
+                Some((origin.clone(), until - now))
+            })
+            .collect()
+    }
+
+    pub fn is_h1_preferred(&self, origin: &str) -> bool {
+        let now = Instant::now();
+        self.origins
+            .lock()
+            .get(origin)
+            .and_then(|state| state.h1_until)
+            .is_some_and(|until| until > now)
+    }
+
+    /// Clears the preference of the origin, so HTTP/2 is probed by the next request.
+    pub fn clear_origin(&self, origin: &str) {
+        self.origins.lock().remove(origin);
+    }
+
+    /// Clears the preferences of all the origins.
+    pub fn clear(&self) {
+        self.origins.lock().clear();
+    }
+
+    /// Returns the protocol of a new connection to the origin, and whether it's forced.
+    pub(crate) fn select(&self, origin: &str, tls: bool) -> (Protocol, bool) {
+        if tls {
+            return (Protocol::Http1, false);
+        }
+        if let Some(protocol) = self.config.forced.get(origin) {
+            return (*protocol, true);
+        }
+        if !self.config.http2_prior_knowledge {
+            return (Protocol::Http1, false);
+        }
+        let mut origins = self.origins.lock();
+        if let Some(state) = origins.get_mut(origin) {
+            match state.h1_until {
+                Some(until) if until > Instant::now() => return (Protocol::Http1, false),
+                Some(_) => {
+                    tracing::info!("[Volo-HTTP] probing http2 of {origin} again");
+                    state.h1_until = None;
+                }
+                None => {}
+            }
+        }
+        (Protocol::Http2, false)
+    }
+
+    /// Records the failure of a connection to the origin, and returns whether the origin falls
+    /// back to HTTP/1.1.
+    pub(crate) fn record(&self, origin: &FastStr, failure: Failure) -> bool {
+        let now = Instant::now();
+        let mut origins = self.origins.lock();
+        let state = origins.entry(origin.clone()).or_default();
+        let fallback = match failure {
+            Failure::Reset => {
+                while state
+                    .resets
+                    .front()
+                    .is_some_and(|at| now.duration_since(*at) >= self.config.reset_window)
+                {
+                    state.resets.pop_front();
+                }
+                state.resets.push_back(now);
+                state.resets.len() > self.config.reset_threshold
+            }
+            Failure::Handshake | Failure::GoAway => true,
+        };
+        if fallback {
+            tracing::warn!(
+                "[Volo-HTTP] {origin} falls back to http1 for {:?} by {failure:?}",
+                self.config.fallback_ttl
+            );
+            state.h1_until = Some(now + self.config.fallback_ttl);
+            state.resets.clear();
+        }
+        fallback
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, time::Duration};
+
+    use http::{StatusCode, Version};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::{Failure, Protocol, ProtocolConfig, ProtocolPreferences};
+    use crate::{body::BodyConversion, client::DefaultClient, ClientBuilder};
+
+    fn h2c(config: ProtocolConfig) -> ProtocolPreferences {
+        ProtocolPreferences::new(ProtocolConfig {
+            http2_prior_knowledge: true,
+            ..config
+        })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fallback_and_reprobe() {
+        let prefs = h2c(ProtocolConfig {
+            fallback_ttl: Duration::from_secs(60),
+            ..Default::default()
+        });
+        let origin = "example.com".into();
+        assert_eq!(prefs.select(&origin, false), (Protocol::Http2, false));
+
+        assert!(prefs.record(&origin, Failure::GoAway));
+        assert_eq!(prefs.select(&origin, false), (Protocol::Http1, false));
+        assert!(prefs.is_h1_preferred(&origin));
+        assert_eq!(
+            prefs.h1_preferred(),
+            vec![(origin.clone(), Duration::from_secs(60))]
+        );
+
+        tokio::time::advance(Duration::from_secs(61)).await;
+        assert!(!prefs.is_h1_preferred(&origin));
+        assert_eq!(prefs.select(&origin, false), (Protocol::Http2, false));
+
+        assert!(prefs.record(&origin, Failure::Handshake));
+        prefs.clear();
+        assert!(prefs.h1_preferred().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reset_threshold() {
+        let prefs = h2c(ProtocolConfig {
+            reset_threshold: 2,
+            reset_window: Duration::from_secs(10),
+            ..Default::default()
+        });
+        let origin = "example.com".into();
+        assert!(!prefs.record(&origin, Failure::Reset));
+        assert!(!prefs.record(&origin, Failure::Reset));
+        // the resets out of the window are forgotten
+        tokio::time::advance(Duration::from_secs(11)).await;
+        assert!(!prefs.record(&origin, Failure::Reset));
+        assert!(!prefs.record(&origin, Failure::Reset));
+        assert!(prefs.record(&origin, Failure::Reset));
+        assert!(prefs.is_h1_preferred(&origin));
+
+        prefs.clear_origin(&origin);
+        assert!(!prefs.is_h1_preferred(&origin));
+    }
+
+    #[test]
+    fn forced_and_tls() {
+        let prefs = ProtocolPreferences::new(ProtocolConfig {
+            forced: [("h1.example.com".into(), Protocol::Http1)].into(),
+            ..Default::default()
+        });
+        assert_eq!(prefs.select("example.com", false), (Protocol::Http1, false));
+
+        let prefs = h2c(ProtocolConfig {
+            forced: [
+                ("h1.example.com".into(), Protocol::Http1),
+                ("h2.example.com".into(), Protocol::Http2),
+            ]
+            .into(),
+            ..Default::default()
+        });
+        assert_eq!(
+            prefs.select("h1.example.com", false),
+            (Protocol::Http1, true)
+        );
+        assert_eq!(
+            prefs.select("h2.example.com", false),
+            (Protocol::Http2, true)
+        );
+        assert_eq!(prefs.select("example.com", true), (Protocol::Http1, false));
+    }
+
+    #[derive(Clone, Copy)]
+    enum Origin {
+        WellBehaved,
+        /// Answers the preface of HTTP/2 as an HTTP/1.1 server.
+        H1Only,
+        /// Resets all the streams of HTTP/2.
+        Resetting,
+    }
+
+    async fn serve(origin: Origin) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(serve_conn(origin, stream));
+            }
+        });
+        addr
+    }
+
+    async fn serve_conn(origin: Origin, mut stream: TcpStream) {
+        let mut preface = [0u8; 3];
+        loop {
+            match stream.peek(&mut preface).await {
+                Ok(0) | Err(_) => return,
+                Ok(n) if n >= preface.len() => break,
+                Ok(_) => tokio::task::yield_now().await,
+            }
+        }
+        if &preface != b"PRI" {
+            let mut buf = vec![0u8; 4096];
+            let _ = stream.read(&mut buf).await;
+            let _ = stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nh1")
+                .await;
+            return;
+        }
+        match origin {
+            Origin::H1Only => {
+                let _ = stream
+                    .write_all(
+                        b"HTTP/1.1 400 Bad Request\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                    )
+                    .await;
+            }
+            Origin::WellBehaved | Origin::Resetting => {
+                let Ok(mut conn) = h2::server::handshake(stream).await else {
+                    return;
+                };
+                while let Some(Ok((_, mut respond))) = conn.accept().await {
+                    if let Origin::Resetting = origin {
+                        respond.send_reset(h2::Reason::INTERNAL_ERROR);
+                    } else {
+                        let _ = respond.send_response(http::Response::new(()), true);
+                    }
+                }
+            }
+        }
+    }
+
+    fn client(addr: SocketAddr, config: ProtocolConfig) -> DefaultClient {
+        let mut builder = ClientBuilder::new();
+        builder
+            .address(
+                addr,
+                #[cfg(feature = "__tls")]
+                false,
+            )
+            .set_protocol_config(ProtocolConfig {
+                http2_prior_knowledge: true,
+                ..config
+            });
+        builder.build().unwrap()
+    }
+
+    #[tokio::test]
+    async fn fallback_for_misbehaving_origin() {
+        let addr = serve(Origin::H1Only).await;
+        let client = client(
+            addr,
+            ProtocolConfig {
+                fallback_ttl: Duration::from_millis(200),
+                ..Default::default()
+            },
+        );
+        let origin = addr.to_string();
+
+        // the idempotent request is retried by HTTP/1.1
+        let resp = client.get("/").unwrap().send().await.unwrap();
+        assert_eq!(resp.version(), Version::HTTP_11);
+        assert_eq!(resp.into_string().await.unwrap(), "h1");
+        assert!(client.protocol_preferences().is_h1_preferred(&origin));
+
+        // and the following ones go to HTTP/1.1 directly
+        let resp = client.post("/").unwrap().data("data").unwrap().send().await;
+        assert_eq!(resp.unwrap().status(), StatusCode::OK);
+
+        // HTTP/2 is probed again after the TTL, and the request with a body is not retried
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(!client.protocol_preferences().is_h1_preferred(&origin));
+        let resp = client.post("/").unwrap().data("data").unwrap().send().await;
+        assert!(resp.is_err());
+        assert!(client.protocol_preferences().is_h1_preferred(&origin));
+    }
+
+    #[tokio::test]
+    async fn fallback_by_resets() {
+        let addr = serve(Origin::Resetting).await;
+        let client = client(
+            addr,
+            ProtocolConfig {
+                reset_threshold: 1,
+                ..Default::default()
+            },
+        );
+
+        // the first reset is below the threshold
+        assert!(client.get("/").unwrap().send().await.is_err());
+        let resp = client.get("/").unwrap().send().await.unwrap();
+        assert_eq!(resp.version(), Version::HTTP_11);
+        assert!(client
+            .protocol_preferences()
+            .is_h1_preferred(&addr.to_string()));
+    }
+
+    #[tokio::test]
+    async fn no_fallback_for_well_behaved_origin() {
+        let addr = serve(Origin::WellBehaved).await;
+        let client = client(addr, Default::default());
+
+        for _ in 0..3 {
+            let resp = client.get("/").unwrap().send().await.unwrap();
+            assert_eq!(resp.version(), Version::HTTP_2);
+            assert_eq!(resp.status(), StatusCode::OK);
+        }
+        let resp = client.post("/").unwrap().data("data").unwrap().send().await;
+        assert_eq!(resp.unwrap().version(), Version::HTTP_2);
+        assert!(client.protocol_preferences().h1_preferred().is_empty());
+    }
+
+    #[tokio::test]
+    async fn forced_http1() {
+        let addr = serve(Origin::WellBehaved).await;
+        let client = client(
+            addr,
+            ProtocolConfig {
+                forced: [(addr.to_string().into(), Protocol::Http1)].into(),
+                ..Default::default()
+            },
+        );
+        let resp = client.get("/").unwrap().send().await.unwrap();
+        assert_eq!(resp.version(), Version::HTTP_11);
+    }
+}
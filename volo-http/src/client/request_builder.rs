@@ -348,7 +348,7 @@ mod request_tests {
     async fn set_query() {
         let mut builder = Client::builder();
         builder.host("httpbin.org");
-        let client = builder.build();
+        let client = builder.build().unwrap();
         let query = HashMap::from([
             ("key".to_string(), "val".to_string()),
             ("key2".to_string(), "val2".to_string()),
@@ -1,7 +1,15 @@
 use std::error::Error;
 
+#[cfg(feature = "http2")]
+use faststr::FastStr;
 use http_body::Body;
+#[cfg(feature = "http2")]
+use http_body_util::Empty;
 use hyper::client::conn::http1;
+#[cfg(feature = "http2")]
+use hyper::client::conn::http2;
+#[cfg(feature = "http2")]
+use hyper_util::rt::TokioExecutor;
 use hyper_util::rt::TokioIo;
 use motore::{make::MakeConnection, service::Service};
 #[cfg(feature = "__tls")]
@@ -11,9 +19,14 @@ use volo::{
     net::{conn::Conn, dial::DefaultMakeTransport, Address},
 };
 
+#[cfg(feature = "http2")]
+use super::protocol::{Failure, Protocol, ProtocolPreferences};
 use crate::{
     context::ClientContext,
-    error::client::{no_address, request_error, ClientError},
+    error::{
+        client::{no_address, request_error, ClientError},
+        code,
+    },
     request::ClientRequest,
     response::ClientResponse,
 };
@@ -24,6 +37,10 @@ pub struct TlsTransport;
 #[derive(Clone)]
 pub struct ClientTransport {
     client: http1::Builder,
+    #[cfg(feature = "http2")]
+    http2: http2::Builder<TokioExecutor>,
+    #[cfg(feature = "http2")]
+    protocols: ProtocolPreferences,
     mk_conn: DefaultMakeTransport,
     config: ClientTransportConfig,
     #[cfg(feature = "__tls")]
@@ -36,6 +53,7 @@ impl ClientTransport {
         transport_config: ClientTransportConfig,
         mk_conn: DefaultMakeTransport,
         #[cfg(feature = "__tls")] tls_connector: volo::net::tls::TlsConnector,
+        #[cfg(feature = "http2")] protocols: ProtocolPreferences,
     ) -> Self {
         let mut builder = http1::Builder::new();
         builder
@@ -47,6 +65,10 @@ impl ClientTransport {
 
         Self {
             client: builder,
+            #[cfg(feature = "http2")]
+            http2: http2::Builder::new(TokioExecutor::new()),
+            #[cfg(feature = "http2")]
+            protocols,
             mk_conn,
             config: transport_config,
             #[cfg(feature = "__tls")]
@@ -56,7 +78,10 @@ impl ClientTransport {
 
     async fn connect_to(&self, address: Address) -> Result<Conn, ClientError> {
         self.mk_conn.make_connection(address).await.map_err(|err| {
-            tracing::error!("[Volo-HTTP] failed to make connection, error: {err}");
+            tracing::error!(
+                "[Volo-HTTP] failed to make connection, error: {err}, code: {}",
+                code::REQUEST
+            );
             request_error(err)
         })
     }
@@ -93,7 +118,10 @@ impl ClientTransport {
             .connect(target_name, tcp_stream)
             .await
             .map_err(|err| {
-                tracing::error!("[Volo-HTTP] failed to make tls connection, error: {err}");
+                tracing::error!(
+                    "[Volo-HTTP] failed to make tls connection, error: {err}, code: {}",
+                    code::REQUEST
+                );
                 request_error(err)
             })
     }
@@ -116,19 +144,134 @@ impl ClientTransport {
         B::Error: Into<Box<dyn Error + Send + Sync>> + 'static,
     {
         tracing::trace!("[Volo-HTTP] requesting {}", req.uri());
+        #[cfg(feature = "http2")]
+        {
+            let callee = cx.rpc_info().callee();
+            #[cfg(feature = "__tls")]
+            let tls = callee.contains::<TlsTransport>();
+            #[cfg(not(feature = "__tls"))]
+            let tls = false;
+            let origin = req
+                .headers()
+                .get(http::header::HOST)
+                .and_then(|host| host.to_str().ok())
+                .map(FastStr::new)
+                .unwrap_or_else(|| callee.service_name());
+            if let (Protocol::Http2, forced) = self.protocols.select(&origin, tls) {
+                return self.request_h2(cx, req, origin, forced).await;
+            }
+        }
+        self.request_h1(cx, req).await
+    }
+
+    async fn request_h1<B>(
+        &self,
+        cx: &ClientContext,
+        req: ClientRequest<B>,
+    ) -> Result<ClientResponse, ClientError>
+    where
+        B: Body + Send + 'static,
+        B::Data: Send,
+        B::Error: Into<Box<dyn Error + Send + Sync>> + 'static,
+    {
         let conn = self.make_connection(cx).await?;
         let io = TokioIo::new(conn);
         let (mut sender, conn) = self.client.handshake(io).await.map_err(|err| {
-            tracing::error!("[Volo-HTTP] failed to handshake, error: {err}");
+            tracing::error!(
+                "[Volo-HTTP] failed to handshake, error: {err}, code: {}",
+                code::REQUEST
+            );
             request_error(err)
         })?;
         tokio::spawn(conn);
         let resp = sender.send_request(req).await.map_err(|err| {
-            tracing::error!("[Volo-HTTP] failed to send request, error: {err}");
+            tracing::error!(
+                "[Volo-HTTP] failed to send request, error: {err}, code: {}",
+                code::REQUEST
+            );
             request_error(err)
         })?;
         Ok(resp)
     }
+
+    /// Sends the request by HTTP/2, and falls back to HTTP/1.1 by the failure signatures, see
+    /// [`protocol`](super::protocol).
+    #[cfg(feature = "http2")]
+    async fn request_h2<B>(
+        &self,
+        cx: &ClientContext,
+        mut req: ClientRequest<B>,
+        origin: FastStr,
+        forced: bool,
+    ) -> Result<ClientResponse, ClientError>
+    where
+        B: Body + Send + 'static,
+        B::Data: Send,
+        B::Error: Into<Box<dyn Error + Send + Sync>> + 'static,
+    {
+        let conn = self.make_connection(cx).await?;
+        let (mut sender, conn) = match self.http2.handshake(TokioIo::new(conn)).await {
+            Ok(handshake) => handshake,
+            Err(err) => {
+                tracing::error!(
+                    "[Volo-HTTP] failed to handshake http2, error: {err}, code: {}",
+                    code::REQUEST
+                );
+                if forced || !self.protocols.record(&origin, Failure::Handshake) {
+                    return Err(request_error(err));
+                }
+                // nothing is sent yet
+                return self.request_h1(cx, req).await;
+            }
+        };
+        tokio::spawn(conn);
+
+        // the copy of the request to be retried by HTTP/1.1, which is safe only if it's
+        // idempotent and has no body
+        let retry =
+            (!forced && req.method().is_idempotent() && req.body().is_end_stream()).then(|| {
+                let mut retry = ClientRequest::new(());
+                *retry.method_mut() = req.method().clone();
+                *retry.uri_mut() = req.uri().clone();
+                *retry.version_mut() = req.version();
+                *retry.headers_mut() = req.headers().clone();
+                retry
+            });
+        if req.uri().scheme().is_none() {
+            // the pseudo headers of HTTP/2 need the scheme and the authority
+            let authority = req
+                .headers()
+                .get(http::header::HOST)
+                .and_then(|host| host.to_str().ok())
+                .map(ToOwned::to_owned)
+                .or_else(|| cx.rpc_info().callee().address().map(|a| a.to_string()));
+            let mut parts = req.uri().clone().into_parts();
+            parts.scheme = Some(http::uri::Scheme::HTTP);
+            parts.authority = authority.and_then(|a| a.parse().ok());
+            if let Ok(uri) = http::Uri::from_parts(parts) {
+                *req.uri_mut() = uri;
+            }
+        }
+        *req.version_mut() = http::Version::HTTP_2;
+
+        let err = match sender.send_request(req.map(Box::pin)).await {
+            Ok(resp) => return Ok(resp),
+            Err(err) => err,
+        };
+        tracing::error!(
+            "[Volo-HTTP] failed to send request by http2, error: {err}, code: {}",
+            code::REQUEST
+        );
+        let fallback = !forced
+            && Failure::of(&err).is_some_and(|failure| self.protocols.record(&origin, failure));
+        match retry {
+            Some(retry) if fallback => {
+                self.request_h1(cx, retry.map(|()| Empty::<B::Data>::new()))
+                    .await
+            }
+            _ => Err(request_error(err)),
+        }
+    }
 }
 
 impl<B> Service<ClientContext, ClientRequest<B>> for ClientTransport
@@ -165,6 +308,9 @@ pub struct ClientConfig {
     pub title_case_headers: bool,
     pub preserve_header_case: bool,
     pub max_headers: Option<usize>,
+    /// The protocols of the connections, see [`protocol`](super::protocol).
+    #[cfg(feature = "http2")]
+    pub protocol: super::protocol::ProtocolConfig,
 }
 
 impl Default for ClientConfig {
@@ -179,6 +325,8 @@ impl ClientConfig {
             title_case_headers: false,
             preserve_header_case: false,
             max_headers: None,
+            #[cfg(feature = "http2")]
+            protocol: Default::default(),
         }
     }
 }
@@ -23,14 +23,20 @@ pub struct ServerContext(pub(crate) RpcCx<ServerCxInner, Config>);
 
 impl ServerContext {
     pub fn new(peer: Address) -> Self {
-        let mut cx = RpcCx::new(
+        let mut cx = Self::without_peer();
+        cx.rpc_info_mut().caller_mut().set_address(peer);
+        cx
+    }
+
+    /// Creates a context without the address of the peer, e.g. when the connection is not
+    /// accepted by the [`Server`](crate::server::Server).
+    pub fn without_peer() -> Self {
+        Self(RpcCx::new(
             RpcInfo::<Config>::with_role(Role::Server),
             ServerCxInner {
                 params: PathParamsVec::default(),
             },
-        );
-        cx.rpc_info_mut().caller_mut().set_address(peer);
-        Self(cx)
+        ))
     }
 }
 
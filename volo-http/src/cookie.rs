@@ -1,30 +1,112 @@
+//! Cookies of the requests and the responses.
+//!
+//! The [`CookieJar`] is extracted from the `Cookie` headers of a request, and the cookies added
+//! to or removed from it are sent back as the `Set-Cookie` headers when it is returned as a part
+//! of the response, e.g.
+//!
+//! ```rust,ignore
+//! use volo_http::cookie::{Cookie, CookieJar};
+//!
+//! async fn login(jar: CookieJar) -> (CookieJar, &'static str) {
+//!     let session = Cookie::build(("session", "xxx")).path("/").http_only(true).build();
+//!     (jar.add(session), "welcome")
+//! }
+//! ```
+//!
+//! The [`SignedCookieJar`] and the [`PrivateCookieJar`] sign or encrypt the values by the [`Key`]
+//! in the extensions of the context, which can be added by the [`Extension`] layer, and the
+//! tampered cookies are rejected when they are read.
+//!
+//! [`Extension`]: crate::extension::Extension
+
 use std::{convert::Infallible, ops::Deref};
 
-pub use cookie::{time::Duration, Cookie};
-use http::{header, request::Parts, HeaderMap};
+#[cfg(any(feature = "cookie-signed", feature = "cookie-private"))]
+pub use cookie::Key;
+pub use cookie::{time::Duration, Cookie, SameSite};
+use http::{header, HeaderMap, HeaderValue};
+#[cfg(feature = "server")]
+use http::{request::Parts, StatusCode};
+#[cfg(all(
+    feature = "server",
+    any(feature = "cookie-signed", feature = "cookie-private")
+))]
+use volo::context::Context;
 
-use crate::context::ServerContext;
 #[cfg(feature = "server")]
-use crate::server::extract::FromContext;
+use crate::{
+    context::ServerContext,
+    response::ServerResponse,
+    server::{extract::FromContext, IntoResponse},
+};
+
+/// Parses the `Cookie` headers as the original cookies of a jar, and the invalid ones are
+/// skipped.
+fn parse_cookies(headers: &HeaderMap) -> cookie::CookieJar {
+    let mut jar = cookie::CookieJar::new();
+    for cookie in headers
+        .get_all(header::COOKIE)
+        .into_iter()
+        .filter_map(|val| val.to_str().ok())
+        .flat_map(|val| val.split(';'))
+        .map(str::trim)
+        .filter(|cookie| !cookie.is_empty())
+        .filter_map(|cookie| Cookie::parse_encoded(cookie.to_owned()).ok())
+    {
+        jar.add_original(cookie);
+    }
+    jar
+}
+
+/// Returns the `Set-Cookie` headers of the added and the removed cookies, one header per cookie.
+///
+/// The removed cookies are sent with an empty value, `Max-Age=0` and an `Expires` in the past.
+fn set_cookies(jar: &cookie::CookieJar) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for cookie in jar.delta() {
+        // the name and the value are percent-encoded, so it's always a valid header value
+        if let Ok(value) = HeaderValue::try_from(cookie.encoded().to_string()) {
+            headers.append(header::SET_COOKIE, value);
+        }
+    }
+    headers
+}
 
+/// The cookies of a request, and the ones to be set by the response.
+#[derive(Debug, Default, Clone)]
 pub struct CookieJar {
     inner: cookie::CookieJar,
 }
 
 impl CookieJar {
+    /// Creates an empty jar, e.g. for setting the cookies without reading the request.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
     pub fn from_header(headers: &HeaderMap) -> Self {
-        let mut jar = cookie::CookieJar::new();
-        for cookie in headers
-            .get_all(header::COOKIE)
-            .into_iter()
-            .filter_map(|val| val.to_str().ok())
-            .flat_map(|val| val.split(';'))
-            .filter_map(|cookie| Cookie::parse_encoded(cookie.to_owned()).ok())
-        {
-            jar.add_original(cookie);
+        Self {
+            inner: parse_cookies(headers),
         }
+    }
 
-        Self { inner: jar }
+    /// Adds a cookie, which will be set by the response.
+    #[allow(clippy::should_implement_trait)]
+    pub fn add(mut self, cookie: impl Into<Cookie<'static>>) -> Self {
+        self.inner.add(cookie);
+        self
+    }
+
+    /// Removes a cookie, whose path and domain should be the same as when it is added, so the
+    /// client removes the right one.
+    pub fn remove(mut self, cookie: impl Into<Cookie<'static>>) -> Self {
+        self.inner.remove(cookie);
+        self
+    }
+
+    /// Returns the `Set-Cookie` headers of the added and the removed cookies.
+    pub fn set_cookie_headers(&self) -> HeaderMap {
+        set_cookies(&self.inner)
     }
 }
 
@@ -48,3 +130,262 @@ impl FromContext for CookieJar {
         Ok(Self::from_header(&parts.headers))
     }
 }
+
+macro_rules! keyed_jar {
+    (
+        $(#[$attr:meta])*
+        $feature:literal, $name:ident, $view:ident, $view_mut:ident, $verify:ident
+    ) => {
+        $(#[$attr])*
+        #[cfg(feature = $feature)]
+        #[cfg_attr(docsrs, doc(cfg(feature = $feature)))]
+        #[derive(Clone)]
+        pub struct $name {
+            inner: cookie::CookieJar,
+            key: Key,
+        }
+
+        #[cfg(feature = $feature)]
+        impl $name {
+            /// Creates an empty jar with the key.
+            pub fn new(key: Key) -> Self {
+                Self {
+                    inner: cookie::CookieJar::new(),
+                    key,
+                }
+            }
+
+            pub fn from_header(headers: &HeaderMap, key: Key) -> Self {
+                Self {
+                    inner: parse_cookies(headers),
+                    key,
+                }
+            }
+
+            /// Returns the cookie with its original value, or `None` if it doesn't exist or has
+            /// been tampered.
+            pub fn get(&self, name: &str) -> Option<Cookie<'static>> {
+                self.inner.$view(&self.key).get(name)
+            }
+
+            /// Returns the cookies which are not tampered, with their original values.
+            pub fn iter(&self) -> impl Iterator<Item = Cookie<'static>> + '_ {
+                let view = self.inner.$view(&self.key);
+                self.inner
+                    .iter()
+                    .filter_map(move |cookie| view.$verify(cookie.clone()))
+            }
+
+            /// Adds a cookie, whose value will be protected by the key.
+            #[allow(clippy::should_implement_trait)]
+            pub fn add(mut self, cookie: impl Into<Cookie<'static>>) -> Self {
+                self.inner.$view_mut(&self.key).add(cookie);
+                self
+            }
+
+            /// Removes a cookie, whose path and domain should be the same as when it is added.
+            pub fn remove(mut self, cookie: impl Into<Cookie<'static>>) -> Self {
+                self.inner.$view_mut(&self.key).remove(cookie);
+                self
+            }
+
+            /// Returns the `Set-Cookie` headers of the added and the removed cookies.
+            pub fn set_cookie_headers(&self) -> HeaderMap {
+                set_cookies(&self.inner)
+            }
+        }
+
+        #[cfg(feature = $feature)]
+        impl std::fmt::Debug for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                // the key must not be leaked
+                f.debug_struct(stringify!($name))
+                    .field("inner", &self.inner)
+                    .finish_non_exhaustive()
+            }
+        }
+
+        /// The [`Key`] is taken from the extensions of the context, and it is an internal error
+        /// if the key is missing.
+        #[cfg(all(feature = "server", feature = $feature))]
+        #[cfg_attr(docsrs, doc(cfg(all(feature = "server", feature = $feature))))]
+        impl FromContext for $name {
+            type Rejection = StatusCode;
+
+            async fn from_context(
+                cx: &mut ServerContext,
+                parts: &mut Parts,
+            ) -> Result<Self, Self::Rejection> {
+                let Some(key) = cx.extensions().get::<Key>() else {
+                    tracing::error!(
+                        "[Volo-HTTP] the key of the `{}` is missing in the extensions",
+                        stringify!($name)
+                    );
+                    return Err(StatusCode::INTERNAL_SERVER_ERROR);
+                };
+                Ok(Self::from_header(&parts.headers, key.clone()))
+            }
+        }
+
+        #[cfg(all(feature = "server", feature = $feature))]
+        impl crate::server::response::TryIntoResponseHeaders for $name {
+            type Error = Infallible;
+
+            fn try_into_response_headers(self) -> Result<HeaderMap, Self::Error> {
+                Ok(self.set_cookie_headers())
+            }
+        }
+
+        #[cfg(all(feature = "server", feature = $feature))]
+        impl IntoResponse for $name {
+            fn into_response(self) -> ServerResponse {
+                (self, StatusCode::OK).into_response()
+            }
+        }
+    };
+}
+
+keyed_jar! {
+    /// A [`CookieJar`] whose values are signed by HMAC-SHA256 with the [`Key`], so they can be
+    /// read by the client but not modified.
+    "cookie-signed", SignedCookieJar, signed, signed_mut, verify
+}
+
+keyed_jar! {
+    /// A [`CookieJar`] whose values are encrypted by AES-256-GCM with the [`Key`], so they can
+    /// neither be read nor modified by the client.
+    "cookie-private", PrivateCookieJar, private, private_mut, decrypt
+}
+
+#[cfg(feature = "server")]
+impl crate::server::response::TryIntoResponseHeaders for CookieJar {
+    type Error = Infallible;
+
+    fn try_into_response_headers(self) -> Result<HeaderMap, Self::Error> {
+        Ok(self.set_cookie_headers())
+    }
+}
+
+#[cfg(feature = "server")]
+impl IntoResponse for CookieJar {
+    fn into_response(self) -> ServerResponse {
+        (self, StatusCode::OK).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(cookies: &[&'static str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for cookie in cookies {
+            headers.append(header::COOKIE, HeaderValue::from_static(cookie));
+        }
+        headers
+    }
+
+    fn set_cookie(headers: &HeaderMap) -> Vec<&str> {
+        let mut values = headers
+            .get_all(header::SET_COOKIE)
+            .into_iter()
+            .map(|v| v.to_str().unwrap())
+            .collect::<Vec<_>>();
+        values.sort();
+        values
+    }
+
+    #[test]
+    fn parse() {
+        let jar = CookieJar::from_header(&headers(&["a=1; b=hello%20world;c=", "d=4", ";;"]));
+        assert_eq!(jar.get("a").unwrap().value(), "1");
+        assert_eq!(jar.get("b").unwrap().value(), "hello world");
+        assert_eq!(jar.get("c").unwrap().value(), "");
+        assert_eq!(jar.get("d").unwrap().value(), "4");
+        assert_eq!(jar.iter().count(), 4);
+        // nothing is changed
+        assert!(jar.set_cookie_headers().is_empty());
+    }
+
+    #[test]
+    fn set_and_remove() {
+        let jar = CookieJar::from_header(&headers(&["old=1"]))
+            .add(
+                Cookie::build(("session", "a b;c"))
+                    .domain("example.com")
+                    .path("/")
+                    .same_site(SameSite::Strict)
+                    .secure(true)
+                    .http_only(true)
+                    .max_age(Duration::hours(1))
+                    .build(),
+            )
+            .add(("theme", "dark"))
+            .remove(Cookie::build("old").path("/"));
+        let headers = jar.set_cookie_headers();
+        let values = set_cookie(&headers);
+        assert_eq!(values.len(), 3);
+        assert!(values[0].starts_with("old=; Path=/; Max-Age=0; Expires="));
+        assert_eq!(
+            values[1],
+            "session=a%20b%3Bc; HttpOnly; SameSite=Strict; Secure; Path=/; Domain=example.com; \
+             Max-Age=3600"
+        );
+        assert_eq!(values[2], "theme=dark");
+    }
+
+    #[cfg(feature = "cookie-signed")]
+    #[test]
+    fn signed() {
+        let key = Key::generate();
+        let jar = SignedCookieJar::new(key.clone()).add(("user", "alice"));
+        let value = set_cookie(&jar.set_cookie_headers())[0].to_owned();
+        assert_ne!(value, "user=alice");
+
+        let mut req = HeaderMap::new();
+        req.insert(header::COOKIE, HeaderValue::from_str(&value).unwrap());
+        let jar = SignedCookieJar::from_header(&req, key.clone());
+        assert_eq!(jar.get("user").unwrap().value(), "alice");
+
+        // tampered
+        let tampered = value.replace("alice", "admin");
+        req.insert(header::COOKIE, HeaderValue::from_str(&tampered).unwrap());
+        let jar = SignedCookieJar::from_header(&req, key.clone());
+        assert!(jar.get("user").is_none());
+        assert_eq!(jar.iter().count(), 0);
+
+        // signed by another key
+        req.insert(header::COOKIE, HeaderValue::from_str(&value).unwrap());
+        assert!(SignedCookieJar::from_header(&req, Key::generate())
+            .get("user")
+            .is_none());
+    }
+
+    #[cfg(feature = "cookie-private")]
+    #[test]
+    fn private() {
+        let key = Key::generate();
+        let jar = PrivateCookieJar::new(key.clone()).add(("user", "alice"));
+        let value = set_cookie(&jar.set_cookie_headers())[0].to_owned();
+        assert!(!value.contains("alice"));
+
+        let mut req = HeaderMap::new();
+        req.insert(header::COOKIE, HeaderValue::from_str(&value).unwrap());
+        assert_eq!(
+            PrivateCookieJar::from_header(&req, key.clone())
+                .get("user")
+                .unwrap()
+                .value(),
+            "alice"
+        );
+
+        let (name, encrypted) = value.split_once('=').unwrap();
+        let mut tampered = encrypted.as_bytes().to_vec();
+        tampered[0] = if tampered[0] == b'A' { b'B' } else { b'A' };
+        let tampered = format!("{name}={}", String::from_utf8(tampered).unwrap());
+        req.insert(header::COOKIE, HeaderValue::from_str(&tampered).unwrap());
+        assert!(PrivateCookieJar::from_header(&req, key)
+            .get("user")
+            .is_none());
+    }
+}
@@ -2,8 +2,12 @@ use std::{error::Error, fmt};
 
 use http::{StatusCode, Uri};
 use paste::paste;
+use volo::{
+    error::{CodedError, ErrorCode},
+    loadbalance::error::LoadBalanceError,
+};
 
-use super::BoxError;
+use super::{code, BoxError};
 use crate::body::ResponseConvertError;
 
 pub type Result<T> = std::result::Result<T, ClientError>;
@@ -69,7 +73,58 @@ impl fmt::Display for ClientError {
     }
 }
 
-impl Error for ClientError {}
+impl Error for ClientError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source.as_ref().map(|err| (&**err) as _)
+    }
+}
+
+/// The code of the error is the one of its source if the source is an error of volo, e.g. the
+/// [`Timeout`] or the [`LoadBalanceError`], or the one of its [`ErrorKind`].
+impl CodedError for ClientError {
+    fn code(&self) -> ErrorCode {
+        self.source
+            .as_deref()
+            .and_then(|source| volo::error::find_code(source, http_error_code))
+            .unwrap_or(match self.kind {
+                ErrorKind::Builder => code::BUILDER,
+                ErrorKind::Context => code::CONTEXT,
+                ErrorKind::Request => code::REQUEST,
+                ErrorKind::LoadBalance => code::LOAD_BALANCE,
+                ErrorKind::Status(_) => code::STATUS,
+                ErrorKind::Body => code::BODY,
+            })
+    }
+
+    fn is_retryable(&self) -> bool {
+        match self.kind {
+            ErrorKind::Request => true,
+            ErrorKind::Status(status) => matches!(
+                status,
+                StatusCode::BAD_GATEWAY
+                    | StatusCode::SERVICE_UNAVAILABLE
+                    | StatusCode::GATEWAY_TIMEOUT
+            ),
+            ErrorKind::LoadBalance => self
+                .source
+                .as_deref()
+                .and_then(|source| source.downcast_ref::<LoadBalanceError>())
+                .is_some_and(LoadBalanceError::is_retryable),
+            _ => false,
+        }
+    }
+}
+
+/// Returns the code of the errors of the client.
+fn http_error_code(err: &(dyn Error + 'static)) -> Option<ErrorCode> {
+    if let Some(e) = err.downcast_ref::<ClientError>() {
+        return Some(e.code());
+    }
+    if let Some(e) = err.downcast_ref::<ResponseConvertError>() {
+        return Some(e.code());
+    }
+    simple_error_code(err)
+}
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum ErrorKind {
@@ -140,7 +195,7 @@ impl std::fmt::Display for ErrorKind {
 }
 
 macro_rules! simple_error {
-    ($(#[$attr:meta])* $kind:ident => $name:ident => $msg:literal) => {
+    (@error $(#[$attr:meta])* $kind:ident => $name:ident => $msg:literal => $code:ident) => {
         $(#[$attr])*
         #[derive(Debug)]
         pub struct $name;
@@ -155,6 +210,13 @@ macro_rules! simple_error {
         $(#[$attr])*
         impl ::std::error::Error for $name {}
 
+        $(#[$attr])*
+        impl CodedError for $name {
+            fn code(&self) -> ErrorCode {
+                code::$code
+            }
+        }
+
         paste! {
             $(#[$attr])*
             pub(crate) fn [<$name:snake>]() -> ClientError {
@@ -162,10 +224,62 @@ macro_rules! simple_error {
             }
         }
     };
+    ($($(#[$attr:meta])* $kind:ident => $name:ident => $msg:literal => $code:ident;)+) => {
+        $(simple_error!(@error $(#[$attr])* $kind => $name => $msg => $code);)+
+
+        /// Returns the code of the simple errors.
+        fn simple_error_code(err: &(dyn Error + 'static)) -> Option<ErrorCode> {
+            $(
+                $(#[$attr])*
+                if let Some(e) = err.downcast_ref::<$name>() {
+                    return Some(e.code());
+                }
+            )+
+            None
+        }
+    };
 }
 
-simple_error!(Builder => NoAddress => "missing target address");
-simple_error!(Builder => BadScheme => "bad scheme");
-simple_error!(Builder => BadHostName => "bad host name");
-simple_error!(Request => Timeout => "request timeout");
-simple_error!(LoadBalance => NoAvailableEndpoint => "no available endpoint");
+simple_error! {
+    Builder => NoAddress => "missing target address" => NO_ADDRESS;
+    Builder => BadScheme => "bad scheme" => BAD_SCHEME;
+    Builder => BadHostName => "bad host name" => BAD_HOST_NAME;
+    Request => Timeout => "request timeout" => TIMEOUT;
+    LoadBalance => NoAvailableEndpoint => "no available endpoint" => NO_AVAILABLE_ENDPOINT;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes() {
+        let status = status_error(StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(status.code(), code::STATUS);
+        assert!(status.is_retryable());
+        assert!(!status_error(StatusCode::NOT_FOUND).is_retryable());
+        assert_eq!(builder_error("bad url").code(), code::BUILDER);
+        assert_eq!(context_error("oops").code(), code::CONTEXT);
+        assert_eq!(request_error("reset").code(), code::REQUEST);
+        assert!(request_error("reset").is_retryable());
+        assert_eq!(
+            ClientError::from(ResponseConvertError::StringUtf8Error).code(),
+            code::INVALID_UTF8
+        );
+
+        // the codes of the sources are kept
+        assert_eq!(no_address().code(), code::NO_ADDRESS);
+        assert_eq!(bad_scheme().code(), code::BAD_SCHEME);
+        assert_eq!(bad_host_name().code(), code::BAD_HOST_NAME);
+        assert_eq!(timeout().code(), code::TIMEOUT);
+        assert!(timeout().is_retryable());
+        assert_eq!(no_available_endpoint().code(), code::NO_AVAILABLE_ENDPOINT);
+        let lb = lb_error(LoadBalanceError::Discover("dns".into()));
+        assert_eq!(lb.code(), volo::error::code::LOAD_BALANCE_DISCOVER);
+        assert!(lb.is_retryable());
+        assert_eq!(
+            lb_error(no_available_endpoint()).code(),
+            code::NO_AVAILABLE_ENDPOINT
+        );
+    }
+}
@@ -15,3 +15,66 @@ pub mod server;
 pub use self::server::ExtractBodyError;
 
 pub type BoxError = Box<dyn Error + Send + Sync>;
+
+pub mod code {
+    //! The error codes of `volo-http`, see [`volo::error`].
+
+    use volo::error::Namespace;
+
+    volo::error_codes! {
+        Namespace::Http;
+        /// Failed to build the client or the request, e.g. an invalid URL.
+        BUILDER = 1,
+        /// Failed to process the context of the request.
+        CONTEXT = 2,
+        /// Failed to send the request, e.g. the connection is refused or reset.
+        REQUEST = 3,
+        /// The load balancer failed to pick an instance.
+        LOAD_BALANCE = 4,
+        /// The response has an error status.
+        STATUS = 5,
+        /// Failed to process the body of the response.
+        BODY = 6,
+        /// The target address is missing.
+        NO_ADDRESS = 7,
+        /// The scheme of the URL is not supported.
+        BAD_SCHEME = 8,
+        /// The host name of the URL is invalid.
+        BAD_HOST_NAME = 9,
+        /// The request timed out.
+        TIMEOUT = 10,
+        /// No endpoint is available for the request.
+        NO_AVAILABLE_ENDPOINT = 11,
+        /// Failed to collect the body.
+        BODY_COLLECTION = 101,
+        /// The content type of the request is invalid.
+        INVALID_CONTENT_TYPE = 102,
+        /// The body is not a valid UTF-8 string.
+        INVALID_UTF8 = 103,
+        /// The body is not a valid JSON of the type.
+        INVALID_JSON = 104,
+        /// The body is not a valid form of the type.
+        INVALID_FORM = 105,
+        /// The body is larger than the limit of spooling.
+        BODY_TOO_LARGE = 106,
+        /// Failed to spool the body to the temporary file.
+        SPOOL_IO = 107,
+        /// The GraphQL request is not well-formed.
+        INVALID_GRAPHQL = 108,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::code;
+
+    #[test]
+    fn registry() {
+        volo::error::check_registry(
+            "volo-http",
+            code::REGISTRY,
+            concat!(env!("CARGO_MANIFEST_DIR"), "/ERROR_CODES.md"),
+        )
+        .unwrap();
+    }
+}
@@ -1,7 +1,9 @@
 use std::{error::Error, fmt};
 
 use http::StatusCode;
+use volo::error::{CodedError, ErrorCode};
 
+use super::code;
 use crate::{response::ServerResponse, server::IntoResponse};
 
 #[derive(Debug)]
@@ -15,6 +17,9 @@ pub enum ExtractBodyError {
     #[cfg(feature = "form")]
     #[cfg_attr(docsrs, doc(cfg(feature = "form")))]
     Form(serde_urlencoded::de::Error),
+    #[cfg(feature = "spool")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "spool")))]
+    Spool(crate::server::spool::SpoolError),
 }
 
 impl fmt::Display for ExtractBodyError {
@@ -27,6 +32,38 @@ impl fmt::Display for ExtractBodyError {
             Self::Json(e) => write!(f, "json: {e}"),
             #[cfg(feature = "form")]
             Self::Form(e) => write!(f, "form: {e}"),
+            #[cfg(feature = "spool")]
+            Self::Spool(e) => write!(f, "body: {e}"),
+        }
+    }
+}
+
+impl Error for ExtractBodyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Common(e) => Some(e),
+            Self::String(e) => Some(e),
+            #[cfg(feature = "__json")]
+            Self::Json(e) => Some(e),
+            #[cfg(feature = "form")]
+            Self::Form(e) => Some(e),
+            #[cfg(feature = "spool")]
+            Self::Spool(e) => Some(e),
+        }
+    }
+}
+
+impl CodedError for ExtractBodyError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::Common(e) => e.code(),
+            Self::String(_) => code::INVALID_UTF8,
+            #[cfg(feature = "__json")]
+            Self::Json(_) => code::INVALID_JSON,
+            #[cfg(feature = "form")]
+            Self::Form(_) => code::INVALID_FORM,
+            #[cfg(feature = "spool")]
+            Self::Spool(e) => e.code(),
         }
     }
 }
@@ -40,6 +77,8 @@ impl IntoResponse for ExtractBodyError {
             Self::Json(_) => StatusCode::BAD_REQUEST,
             #[cfg(feature = "form")]
             Self::Form(_) => StatusCode::BAD_REQUEST,
+            #[cfg(feature = "spool")]
+            Self::Spool(e) => e.to_status_code(),
         };
 
         status.into_response()
@@ -64,6 +103,15 @@ impl fmt::Display for CommonRejectionError {
 
 impl Error for CommonRejectionError {}
 
+impl CodedError for CommonRejectionError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::BodyCollectionError => code::BODY_COLLECTION,
+            Self::InvalidContentType => code::INVALID_CONTENT_TYPE,
+        }
+    }
+}
+
 impl CommonRejectionError {
     pub fn to_status_code(self) -> StatusCode {
         match self {
@@ -86,3 +134,27 @@ pub fn body_collection_error() -> ExtractBodyError {
 pub fn invalid_content_type() -> ExtractBodyError {
     ExtractBodyError::Common(CommonRejectionError::InvalidContentType)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes() {
+        assert_eq!(body_collection_error().code(), code::BODY_COLLECTION);
+        assert_eq!(invalid_content_type().code(), code::INVALID_CONTENT_TYPE);
+        let utf8 = simdutf8::basic::from_utf8(b"\xff").unwrap_err();
+        assert_eq!(ExtractBodyError::String(utf8).code(), code::INVALID_UTF8);
+        #[cfg(feature = "spool")]
+        {
+            use crate::server::spool::SpoolError;
+
+            let too_large = SpoolError::TooLarge { limit: 1 };
+            assert_eq!(too_large.code(), code::BODY_TOO_LARGE);
+            assert_eq!(
+                ExtractBodyError::from(too_large).code(),
+                code::BODY_TOO_LARGE
+            );
+        }
+    }
+}
@@ -18,11 +18,15 @@ where
         cx: &mut ServerContext,
         _parts: &mut Parts,
     ) -> Result<Self, Self::Rejection> {
-        cx.extensions()
-            .get::<T>()
-            .cloned()
-            .map(Extension)
-            .ok_or(ExtensionRejection::NotExist)
+        let Some(ext) = cx.extensions().get::<T>() else {
+            tracing::error!(
+                "[Volo-HTTP] the extension `{}` is missing, is the `Extension` layer added to the \
+                 router?",
+                std::any::type_name::<T>()
+            );
+            return Err(ExtensionRejection::NotExist);
+        };
+        Ok(Extension(ext.clone()))
     }
 }
 
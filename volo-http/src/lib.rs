@@ -16,6 +16,9 @@ pub mod extension;
 #[cfg(feature = "__json")]
 #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
 pub mod json;
+#[cfg(feature = "otel")]
+#[cfg_attr(docsrs, doc(cfg(feature = "otel")))]
+pub mod otel;
 pub mod request;
 pub mod response;
 #[cfg(feature = "server")]
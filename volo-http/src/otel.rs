@@ -0,0 +1,110 @@
+//! The HTTP protocol of [`volo::otel::OtelServerLayer`] and [`volo::otel::OtelClientLayer`], which
+//! propagates the trace context in the headers.
+//!
+//! The spans are named by the methods of the requests, with the attributes `http.request.method`,
+//! `url.path` and `http.response.status_code`, and the responses with the status `5xx` are marked
+//! as failed.
+//!
+//! ```rust,ignore
+//! use volo::otel::{OtelClientLayer, OtelServerLayer};
+//! use volo_http::otel::HttpOtel;
+//!
+//! let client = ClientBuilder::new()
+//!     .layer_outer(OtelClientLayer::new(HttpOtel))
+//!     .build()?;
+//!
+//! Server::new(router)
+//!     .layer_front(OtelServerLayer::new(HttpOtel))
+//!     .run(addr)
+//!     .await
+//!     .unwrap();
+//! ```
+
+use std::fmt::Display;
+
+use http::{HeaderValue, Request, Response};
+use tracing::{field, Span};
+use volo::{
+    context::Context,
+    otel::{record_error, record_peer, OtelProtocol},
+    FastStr,
+};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HttpOtel;
+
+impl<Cx, B, RB, E> OtelProtocol<Cx, Request<B>, Response<RB>, E> for HttpOtel
+where
+    Cx: Context,
+    E: Display,
+{
+    fn system(&self) -> &'static str {
+        "http"
+    }
+
+    fn record_request(&self, span: &Span, cx: &Cx, req: &Request<B>) {
+        let method = req.method().as_str();
+        span.record("otel.name", method);
+        span.record("http.request.method", method);
+        span.record("url.path", req.uri().path());
+        record_peer(span, cx);
+    }
+
+    fn extract(&self, _cx: &Cx, req: &Request<B>, key: &str) -> Option<FastStr> {
+        req.headers()
+            .get(key)
+            .and_then(|value| value.to_str().ok())
+            .map(FastStr::new)
+    }
+
+    fn inject(&self, _cx: &mut Cx, req: &mut Request<B>, key: &'static str, value: FastStr) {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            req.headers_mut().insert(key, value);
+        }
+    }
+
+    fn record(&self, span: &Span, result: &Result<Response<RB>, E>) {
+        match result {
+            Ok(resp) => {
+                let status = resp.status();
+                span.record("http.response.status_code", status.as_u16());
+                if status.is_server_error() {
+                    span.record("otel.status_code", "ERROR");
+                    span.record("error.type", field::display(status.as_u16()));
+                }
+            }
+            Err(e) => record_error(span, "_OTHER", e),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod otel_tests {
+    use http::StatusCode;
+    use volo::otel::{extract, inject, TRACEPARENT};
+
+    use super::*;
+    use crate::context::ServerContext;
+
+    type Protocol = dyn OtelProtocol<ServerContext, Request<()>, Response<()>, String>;
+
+    #[test]
+    fn propagate_in_headers() {
+        let protocol: &Protocol = &HttpOtel;
+        let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let mut cx = ServerContext::default();
+        let mut req = Request::new(());
+        protocol.inject(&mut cx, &mut req, TRACEPARENT, traceparent.into());
+        assert_eq!(req.headers().get(TRACEPARENT).unwrap(), traceparent);
+
+        let span_context = extract(|key| protocol.extract(&cx, &req, key)).unwrap();
+        let mut injected = None;
+        inject(&span_context, |_, value| injected = Some(value));
+        assert_eq!(injected.as_deref(), Some(traceparent));
+
+        // no subscriber, so nothing is recorded but it should not panic
+        let mut resp = Response::new(());
+        *resp.status_mut() = StatusCode::BAD_GATEWAY;
+        protocol.record(&Span::none(), &Ok(resp));
+    }
+}
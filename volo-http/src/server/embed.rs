@@ -0,0 +1,210 @@
+//! Running the service as a [`tower::Service`] for embedding it in an existing server.
+//!
+//! The [`TowerService`] can be served by any server based on `tower` or `hyper`, e.g. by
+//! [`hyper_util::service::TowerToHyperService`][TowerToHyperService]. The embedding server owns
+//! the connections, so the address of the peer is unknown to volo-http unless the server inserts
+//! a [`PeerAddr`] into the extensions of the request.
+//!
+//! [TowerToHyperService]: https://docs.rs/hyper-util/latest/hyper_util/service/struct.TowerToHyperService.html
+//!
+//! # Example
+//!
+//! ```no_run
+//! use tower::ServiceBuilder;
+//! use volo::net::Address;
+//! use volo_http::server::{
+//!     embed::{PeerAddr, TowerService},
+//!     route::get,
+//!     Router,
+//! };
+//!
+//! async fn index(peer: Option<Address>) -> String {
+//!     format!("Hello, {peer:?}!")
+//! }
+//!
+//! let peer: Address = "127.0.0.1:12345"
+//!     .parse::<std::net::SocketAddr>()
+//!     .unwrap()
+//!     .into();
+//! let service = ServiceBuilder::new()
+//!     .map_request(move |mut req: http::Request<hyper::body::Incoming>| {
+//!         req.extensions_mut().insert(PeerAddr(peer.clone()));
+//!         req
+//!     })
+//!     .service(TowerService::new(Router::new().route("/", get(index))));
+//! ```
+
+use std::{
+    cell::RefCell,
+    convert::Infallible,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures::future::BoxFuture;
+use metainfo::{MetaInfo, METAINFO};
+use motore::service::Service;
+use volo::{context::Context as _, net::Address};
+
+use super::IntoResponse;
+use crate::{
+    context::{server::Config, ServerContext},
+    request::ServerRequest,
+    response::ServerResponse,
+};
+
+/// The address of the peer, which should be inserted into the extensions of the request by the
+/// embedding server.
+///
+/// It can be extracted by [`Address`] or `Option<Address>` in the handlers, and extracting
+/// [`Address`] without it fails with `500 Internal Server Error`.
+#[derive(Debug, Clone)]
+pub struct PeerAddr(pub Address);
+
+/// A wrapper running the volo-http service as a [`tower::Service`].
+///
+/// The service is always ready, and the errors of the service are converted into the response,
+/// so the [`tower::Service::Error`] is [`Infallible`].
+pub struct TowerService<S> {
+    inner: Arc<S>,
+    config: Config,
+}
+
+impl<S> Clone for TowerService<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            config: self.config.clone(),
+        }
+    }
+}
+
+impl<S> TowerService<S> {
+    /// Create a new [`TowerService`] by the service, e.g. a [`Router`](super::Router).
+    pub fn new(service: S) -> Self {
+        Self {
+            inner: Arc::new(service),
+            config: Config::default(),
+        }
+    }
+
+    /// Set the config of the [`ServerContext`] for each request.
+    ///
+    /// Default is [`Config::default`].
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+}
+
+impl<S, B, E> tower::Service<ServerRequest<B>> for TowerService<S>
+where
+    S: Service<ServerContext, ServerRequest<B>, Error = E> + Send + Sync + 'static,
+    S::Response: IntoResponse,
+    E: IntoResponse,
+    B: Send + 'static,
+{
+    type Response = ServerResponse;
+    type Error = Infallible;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: ServerRequest<B>) -> Self::Future {
+        let service = self.inner.clone();
+        let config = self.config.clone();
+        Box::pin(
+            METAINFO.scope(RefCell::new(MetaInfo::default()), async move {
+                let mut cx = match req.extensions().get::<PeerAddr>() {
+                    Some(PeerAddr(peer)) => ServerContext::new(peer.clone()),
+                    None => ServerContext::without_peer(),
+                };
+                cx.rpc_info_mut().set_config(config);
+                Ok(super::handle_request(service.as_ref(), &mut cx, req).await)
+            }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::{Method, StatusCode};
+    use tower::{ServiceBuilder, ServiceExt};
+    use volo::net::Address;
+
+    use super::{PeerAddr, TowerService};
+    use crate::{
+        body::{Body, BodyConversion},
+        server::{route::get, test_helpers::simple_req, Router},
+    };
+
+    async fn peer(addr: Address) -> String {
+        addr.to_string()
+    }
+
+    async fn maybe_peer(addr: Option<Address>) -> String {
+        addr.map(|addr| addr.to_string())
+            .unwrap_or_else(|| "unknown".to_owned())
+    }
+
+    fn router() -> Router<Body> {
+        Router::new()
+            .route("/peer", get(peer))
+            .route("/maybe_peer", get(maybe_peer))
+    }
+
+    #[tokio::test]
+    async fn with_peer_addr() {
+        let addr: Address = "127.0.0.1:8000"
+            .parse::<std::net::SocketAddr>()
+            .unwrap()
+            .into();
+        let service = ServiceBuilder::new()
+            .map_request(move |mut req: http::Request<Body>| {
+                req.extensions_mut().insert(PeerAddr(addr.clone()));
+                req
+            })
+            .service(TowerService::new(router()));
+
+        for uri in ["/peer", "/maybe_peer"] {
+            let resp = service
+                .clone()
+                .oneshot(simple_req(Method::GET, uri, Body::empty()))
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert_eq!(
+                resp.into_body().into_string().await.unwrap(),
+                "127.0.0.1:8000"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn without_peer_addr() {
+        let service = TowerService::new(router());
+
+        let resp = service
+            .clone()
+            .oneshot(simple_req(Method::GET, "/peer", Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let resp = service
+            .clone()
+            .oneshot(simple_req(Method::GET, "/maybe_peer", Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.into_body().into_string().await.unwrap(), "unknown");
+
+        let resp = service
+            .oneshot(simple_req(Method::GET, "/missing", Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+}
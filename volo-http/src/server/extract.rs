@@ -3,7 +3,7 @@ use std::{convert::Infallible, marker::PhantomData};
 use bytes::Bytes;
 use faststr::FastStr;
 use futures_util::Future;
-use http::{header, request::Parts, Method, Request, Uri};
+use http::{header, request::Parts, Method, Request, StatusCode, Uri};
 use http_body::Body;
 use http_body_util::BodyExt;
 use hyper::body::Incoming;
@@ -23,6 +23,12 @@ mod private {
     pub enum ViaRequest {}
 }
 
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` can't be extracted from the parts of the request",
+    label = "not an extractor of the parts",
+    note = "the extractors consuming the body, e.g. `String`, `Bytes`, `Form<T>` or `Json<T>`, \
+            must be the last argument of the handler, and a handler takes at most one of them"
+)]
 pub trait FromContext: Sized {
     type Rejection: IntoResponse;
 
@@ -32,6 +38,12 @@ pub trait FromContext: Sized {
     ) -> impl Future<Output = Result<Self, Self::Rejection>> + Send;
 }
 
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` can't be extracted from the request with the body `{B}`",
+    label = "not an extractor of the request",
+    note = "the last argument of a handler must implement `FromRequest`, which is implemented for \
+            all the `FromContext` extractors and the ones consuming the body"
+)]
 pub trait FromRequest<B = Incoming, M = private::ViaRequest>: Sized {
     type Rejection: IntoResponse;
 
@@ -99,18 +111,20 @@ where
     }
 }
 
+/// The address of the peer, which is missing if the service is embedded in another server
+/// without setting the [`PeerAddr`](crate::server::embed::PeerAddr), and `Option<Address>` should
+/// be used in that case.
 impl FromContext for Address {
-    type Rejection = Infallible;
+    type Rejection = StatusCode;
 
     async fn from_context(
         cx: &mut ServerContext,
         _parts: &mut Parts,
     ) -> Result<Address, Self::Rejection> {
-        Ok(cx
-            .rpc_info()
-            .caller()
-            .address()
-            .expect("server context does not have caller address"))
+        cx.rpc_info().caller().address().ok_or_else(|| {
+            tracing::warn!("[VOLO] server context does not have caller address");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
     }
 }
 
@@ -0,0 +1,690 @@
+//! Serving GraphQL by [async-graphql] following the [GraphQL over HTTP] spec.
+//!
+//! [`GraphQLRequest`] and [`GraphQLBatchRequest`] extract the operations from a `GET` request by
+//! its query string, or from a `POST` request by its body of `application/json` or
+//! `application/graphql`. [`GraphQLResponse`] responds the result in the [`ResponseFormat`]
+//! accepted by the client, and [`GraphQL`] glues them with an [`Executor`], e.g. a `Schema`, into
+//! a service of the router.
+//!
+//! With `application/graphql-response+json`, the responses of the requests failing before the
+//! execution, e.g. syntax or validation errors, are `400 Bad Request`, otherwise the responses
+//! are always `200 OK` as the legacy `application/json`. The requests that are not well-formed,
+//! e.g. invalid JSON, are rejected with `400 Bad Request` by [`GraphQLRejection`].
+//!
+//! Subscriptions are not supported yet.
+//!
+//! [async-graphql]: https://docs.rs/async-graphql
+//! [GraphQL over HTTP]: https://graphql.github.io/graphql-over-http/draft/
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use async_graphql::{EmptyMutation, EmptySubscription, Object, Schema};
+//! use volo_http::server::{
+//!     graphql::{graphiql, GraphQL},
+//!     route::{any_service, get, Router},
+//! };
+//!
+//! struct Query;
+//!
+//! #[Object]
+//! impl Query {
+//!     async fn hello(&self) -> &'static str {
+//!         "world"
+//!     }
+//! }
+//!
+//! let schema = Schema::new(Query, EmptyMutation, EmptySubscription);
+//! let router = Router::new()
+//!     .route("/graphql", any_service(GraphQL::new(schema)))
+//!     .route("/graphiql", get(|| async { graphiql("/graphql") }));
+//! ```
+
+use std::{convert::Infallible, error::Error, fmt, sync::Arc};
+
+use async_graphql::{
+    parser::types::OperationType, BatchRequest, BatchResponse, Executor, ParseRequestError,
+};
+use bytes::Bytes;
+use http::{
+    header::{self, HeaderMap, HeaderValue},
+    request::Parts,
+    Method, StatusCode,
+};
+use http_body::Body;
+use mime::Mime;
+use motore::service::Service;
+use volo::{
+    context::Context,
+    error::{CodedError, ErrorCode},
+};
+
+use super::{
+    extract::{FromContext, FromRequest},
+    IntoResponse,
+};
+use crate::{
+    context::ServerContext,
+    error::{code, server::ExtractBodyError},
+    request::ServerRequest,
+    response::ServerResponse,
+};
+
+/// The media type of the GraphQL documents in the `POST` bodies.
+pub const APPLICATION_GRAPHQL: &str = "application/graphql";
+
+/// The media type of the GraphQL responses defined by the GraphQL over HTTP spec.
+pub const APPLICATION_GRAPHQL_RESPONSE_JSON: &str = "application/graphql-response+json";
+
+/// A single GraphQL operation extracted from the request.
+///
+/// The batches are rejected, see [`GraphQLBatchRequest`] for accepting them.
+#[derive(Debug)]
+pub struct GraphQLRequest(pub async_graphql::Request);
+
+impl GraphQLRequest {
+    pub fn into_inner(self) -> async_graphql::Request {
+        self.0
+    }
+}
+
+/// A single GraphQL operation or a batch of them, i.e. a JSON array, extracted from the request.
+#[derive(Debug)]
+pub struct GraphQLBatchRequest(pub BatchRequest);
+
+impl GraphQLBatchRequest {
+    pub fn into_inner(self) -> BatchRequest {
+        self.0
+    }
+}
+
+impl<B> FromRequest<B> for GraphQLBatchRequest
+where
+    B: Body + Send,
+    B::Data: Send,
+    B::Error: Send,
+{
+    type Rejection = GraphQLRejection;
+
+    async fn from_request(
+        cx: &mut ServerContext,
+        parts: Parts,
+        body: B,
+    ) -> Result<Self, Self::Rejection> {
+        if parts.method == Method::GET || parts.method == Method::HEAD {
+            let request =
+                async_graphql::http::parse_query_string(parts.uri.query().unwrap_or_default())
+                    .map_err(GraphQLRejection::Query)?;
+            // mutations can only be sent by `POST`
+            if is_mutation(&request) {
+                return Err(GraphQLRejection::MethodNotAllowed);
+            }
+            return Ok(Self(BatchRequest::Single(request)));
+        }
+        if parts.method != Method::POST {
+            return Err(GraphQLRejection::MethodNotAllowed);
+        }
+
+        let Some(content_type) = content_type(&parts.headers) else {
+            return Err(GraphQLRejection::UnsupportedMediaType);
+        };
+        if content_type.essence_str() == mime::APPLICATION_JSON.essence_str() {
+            let bytes = Bytes::from_request(cx, parts, body).await?;
+            let batch = serde_json::from_slice(&bytes).map_err(GraphQLRejection::Json)?;
+            Ok(Self(batch))
+        } else if content_type.essence_str() == APPLICATION_GRAPHQL {
+            let query = String::from_request(cx, parts, body).await?;
+            Ok(Self(BatchRequest::Single(async_graphql::Request::new(
+                query,
+            ))))
+        } else {
+            Err(GraphQLRejection::UnsupportedMediaType)
+        }
+    }
+}
+
+impl<B> FromRequest<B> for GraphQLRequest
+where
+    B: Body + Send,
+    B::Data: Send,
+    B::Error: Send,
+{
+    type Rejection = GraphQLRejection;
+
+    async fn from_request(
+        cx: &mut ServerContext,
+        parts: Parts,
+        body: B,
+    ) -> Result<Self, Self::Rejection> {
+        let batch = GraphQLBatchRequest::from_request(cx, parts, body).await?;
+        match batch.0 {
+            BatchRequest::Single(request) => Ok(Self(request)),
+            BatchRequest::Batch(_) => Err(GraphQLRejection::Batch),
+        }
+    }
+}
+
+fn content_type(headers: &HeaderMap) -> Option<Mime> {
+    headers
+        .get(header::CONTENT_TYPE)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+}
+
+/// Returns whether the operation to execute is a mutation, and the invalid documents are left to
+/// the executor for reporting the errors in the response.
+fn is_mutation(request: &async_graphql::Request) -> bool {
+    let Ok(document) = async_graphql::parser::parse_query(&request.query) else {
+        return false;
+    };
+    let mut operations = document.operations.iter();
+    let operation = match &request.operation_name {
+        Some(name) => operations.find(|(n, _)| n.is_some_and(|n| n.as_str() == name)),
+        None => operations.next(),
+    };
+    operation.is_some_and(|(_, operation)| operation.node.ty == OperationType::Mutation)
+}
+
+/// The format of the GraphQL response accepted by the client, extracted from the `Accept` header.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseFormat {
+    /// `application/json`, which is always `200 OK` for the well-formed requests.
+    #[default]
+    Json,
+    /// `application/graphql-response+json`, which is `400 Bad Request` for the requests failing
+    /// before the execution.
+    GraphQLResponseJson,
+}
+
+impl ResponseFormat {
+    /// Prefers `application/graphql-response+json` if it is accepted, and falls back to
+    /// `application/json` otherwise, which is also used for the clients not sending `Accept`.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let accepted = headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|range| range.trim().parse::<Mime>().ok())
+            .any(|range| {
+                range.essence_str() == APPLICATION_GRAPHQL_RESPONSE_JSON
+                    && range
+                        .get_param("q")
+                        .map_or(true, |q| q.as_str().parse::<f32>().unwrap_or(1.0) > 0.0)
+            });
+        if accepted {
+            Self::GraphQLResponseJson
+        } else {
+            Self::Json
+        }
+    }
+
+    fn content_type(self) -> HeaderValue {
+        match self {
+            Self::Json => HeaderValue::from_static("application/json"),
+            Self::GraphQLResponseJson => {
+                HeaderValue::from_static("application/graphql-response+json; charset=utf-8")
+            }
+        }
+    }
+}
+
+impl FromContext for ResponseFormat {
+    type Rejection = Infallible;
+
+    async fn from_context(
+        _: &mut ServerContext,
+        parts: &mut Parts,
+    ) -> Result<Self, Self::Rejection> {
+        Ok(Self::from_headers(&parts.headers))
+    }
+}
+
+/// The response of the GraphQL operations in a [`ResponseFormat`].
+///
+/// The HTTP headers set by the resolvers are kept, and the `Cache-Control` computed by the
+/// executor is set if all the operations succeed.
+#[derive(Debug)]
+pub struct GraphQLResponse {
+    response: BatchResponse,
+    format: ResponseFormat,
+}
+
+impl GraphQLResponse {
+    pub fn new(response: impl Into<BatchResponse>) -> Self {
+        Self {
+            response: response.into(),
+            format: ResponseFormat::default(),
+        }
+    }
+
+    /// Sets the format of the response, which is [`ResponseFormat::Json`] by default.
+    pub fn format(mut self, format: ResponseFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    fn status(&self) -> StatusCode {
+        // the errors before the execution leave no data, and the batches are not in the spec
+        match (&self.response, self.format) {
+            (BatchResponse::Single(resp), ResponseFormat::GraphQLResponseJson)
+                if resp.data == async_graphql::Value::Null && !resp.errors.is_empty() =>
+            {
+                StatusCode::BAD_REQUEST
+            }
+            _ => StatusCode::OK,
+        }
+    }
+}
+
+impl From<async_graphql::Response> for GraphQLResponse {
+    fn from(response: async_graphql::Response) -> Self {
+        Self::new(response)
+    }
+}
+
+impl From<BatchResponse> for GraphQLResponse {
+    fn from(response: BatchResponse) -> Self {
+        Self::new(response)
+    }
+}
+
+impl IntoResponse for GraphQLResponse {
+    fn into_response(self) -> ServerResponse {
+        let Ok(body) = serde_json::to_vec(&self.response) else {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        };
+        let mut resp = ServerResponse::new(body.into());
+        *resp.status_mut() = self.status();
+        let headers = resp.headers_mut();
+        headers.insert(header::CONTENT_TYPE, self.format.content_type());
+        if self.response.is_ok() {
+            if let Some(cache_control) = self.response.cache_control().value() {
+                if let Ok(value) = HeaderValue::from_str(&cache_control) {
+                    headers.insert(header::CACHE_CONTROL, value);
+                }
+            }
+        }
+        for (name, value) in self.response.http_headers_iter() {
+            headers.append(name, value);
+        }
+        resp
+    }
+}
+
+/// The rejection of [`GraphQLRequest`] and [`GraphQLBatchRequest`] for the requests which are not
+/// well-formed.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum GraphQLRejection {
+    /// The method is neither `GET` nor `POST`, or a mutation is sent by `GET`.
+    MethodNotAllowed,
+    /// The content type of the `POST` body is missing or not supported.
+    UnsupportedMediaType,
+    /// Failed to receive the body.
+    Body(ExtractBodyError),
+    /// The `POST` body is not a valid JSON of the operations.
+    Json(serde_json::Error),
+    /// The query string of the `GET` request is invalid.
+    Query(ParseRequestError),
+    /// A batch is sent to the extractor of a single operation.
+    Batch,
+}
+
+impl fmt::Display for GraphQLRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid graphql request: ")?;
+        match self {
+            Self::MethodNotAllowed => f.write_str("method not allowed"),
+            Self::UnsupportedMediaType => f.write_str("unsupported content type"),
+            Self::Body(e) => write!(f, "{e}"),
+            Self::Json(e) => write!(f, "{e}"),
+            Self::Query(e) => write!(f, "{e}"),
+            Self::Batch => f.write_str("batch is not supported"),
+        }
+    }
+}
+
+impl Error for GraphQLRejection {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Body(e) => Some(e),
+            Self::Json(e) => Some(e),
+            Self::Query(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl CodedError for GraphQLRejection {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::UnsupportedMediaType => code::INVALID_CONTENT_TYPE,
+            Self::Body(e) => e.code(),
+            _ => code::INVALID_GRAPHQL,
+        }
+    }
+}
+
+impl GraphQLRejection {
+    pub fn to_status_code(&self) -> StatusCode {
+        match self {
+            Self::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            Self::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Self::Body(_) | Self::Json(_) | Self::Query(_) | Self::Batch => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl IntoResponse for GraphQLRejection {
+    fn into_response(self) -> ServerResponse {
+        if let Self::Body(e) = self {
+            return e.into_response();
+        }
+        let mut resp = self.to_status_code().into_response();
+        if let Self::MethodNotAllowed = self {
+            resp.headers_mut()
+                .insert(header::ALLOW, HeaderValue::from_static("GET, POST"));
+        }
+        resp
+    }
+}
+
+impl From<ExtractBodyError> for GraphQLRejection {
+    fn from(value: ExtractBodyError) -> Self {
+        Self::Body(value)
+    }
+}
+
+type InjectData = Arc<dyn Fn(&ServerContext, &Parts, BatchRequest) -> BatchRequest + Send + Sync>;
+
+/// The service executing the GraphQL operations of the requests by an [`Executor`], e.g. a
+/// `Schema`, see the [module docs](self).
+///
+/// Batches are accepted, and the format of the response follows the `Accept` header.
+#[derive(Clone)]
+pub struct GraphQL<E> {
+    executor: E,
+    data: Vec<InjectData>,
+}
+
+impl<E> GraphQL<E> {
+    pub fn new(executor: E) -> Self {
+        Self {
+            executor,
+            data: Vec::new(),
+        }
+    }
+
+    /// Injects the `T` in the extensions of the request or the context into the data of the
+    /// operations, e.g. the principal authenticated by a middleware or a state added by the
+    /// [`Extension`] layer, which can be read by `Context::data` in the resolvers.
+    ///
+    /// The extension of the request takes precedence over the one of the context.
+    ///
+    /// [`Extension`]: crate::extension::Extension
+    pub fn extension<T>(self) -> Self
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        self.data_fn(|cx, parts, batch| {
+            match parts
+                .extensions
+                .get::<T>()
+                .or_else(|| cx.extensions().get::<T>())
+            {
+                Some(data) => batch.data(data.clone()),
+                None => batch,
+            }
+        })
+    }
+
+    /// Injects the data into the operations by the function, which is called with the context and
+    /// the parts of the request before the execution.
+    pub fn data_fn<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&ServerContext, &Parts, BatchRequest) -> BatchRequest + Send + Sync + 'static,
+    {
+        self.data.push(Arc::new(f));
+        self
+    }
+}
+
+impl<E> fmt::Debug for GraphQL<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GraphQL")
+            .field("data", &self.data.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<E, B> Service<ServerContext, ServerRequest<B>> for GraphQL<E>
+where
+    E: Executor,
+    B: Body + Send,
+    B::Data: Send,
+    B::Error: Send,
+{
+    type Response = ServerResponse;
+    type Error = Infallible;
+
+    async fn call(
+        &self,
+        cx: &mut ServerContext,
+        req: ServerRequest<B>,
+    ) -> Result<Self::Response, Self::Error> {
+        let (mut parts, body) = req.into_parts();
+        let format = ResponseFormat::from_headers(&parts.headers);
+        let extensions = std::mem::take(&mut parts.extensions);
+        let mut batch = match GraphQLBatchRequest::from_request(cx, parts, body).await {
+            Ok(batch) => batch.0,
+            Err(rejection) => return Ok(rejection.into_response()),
+        };
+
+        if !self.data.is_empty() {
+            // the parts are consumed by the extractor, so only the extensions are kept
+            let (mut parts, _) = http::Request::new(()).into_parts();
+            parts.extensions = extensions;
+            for inject in &self.data {
+                batch = inject(cx, &parts, batch);
+            }
+        }
+
+        let response = self.executor.execute_batch(batch).await;
+        Ok(GraphQLResponse::new(response)
+            .format(format)
+            .into_response())
+    }
+}
+
+/// Responds the [GraphiQL] page sending the operations to `endpoint`.
+///
+/// [GraphiQL]: https://github.com/graphql/graphiql
+pub fn graphiql(endpoint: &str) -> ServerResponse {
+    let page = async_graphql::http::GraphiQLSource::build()
+        .endpoint(endpoint)
+        .finish();
+    let mut resp = ServerResponse::new(page.into());
+    resp.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("text/html; charset=utf-8"),
+    );
+    resp
+}
+
+#[cfg(test)]
+mod graphql_tests {
+    use async_graphql::{EmptySubscription, Object, Schema, SimpleObject};
+
+    use super::*;
+    use crate::{
+        body::Body,
+        server::test_helpers::{empty_cx, simple_req},
+    };
+
+    #[derive(Clone, SimpleObject)]
+    struct Principal {
+        name: String,
+    }
+
+    struct Query;
+
+    #[Object]
+    impl Query {
+        async fn hello(&self) -> &'static str {
+            "world"
+        }
+
+        async fn me(&self, cx: &async_graphql::Context<'_>) -> Option<Principal> {
+            cx.data_opt::<Principal>().cloned()
+        }
+    }
+
+    struct Mutation;
+
+    #[Object]
+    impl Mutation {
+        async fn bump(&self, by: i32) -> i32 {
+            by + 1
+        }
+    }
+
+    type TestSchema = Schema<Query, Mutation, EmptySubscription>;
+
+    fn service() -> GraphQL<TestSchema> {
+        GraphQL::new(Schema::new(Query, Mutation, EmptySubscription)).extension::<Principal>()
+    }
+
+    async fn call(req: ServerRequest<Body>) -> (StatusCode, HeaderMap, String) {
+        let resp = service().call(&mut empty_cx(), req).await.unwrap();
+        let (parts, body) = resp.into_parts();
+        (
+            parts.status,
+            parts.headers,
+            body.into_string().await.unwrap(),
+        )
+    }
+
+    fn post(content_type: &str, body: &str) -> ServerRequest<Body> {
+        let mut req = simple_req(Method::POST, "/graphql", Body::from(body.to_owned()));
+        req.headers_mut()
+            .insert(header::CONTENT_TYPE, content_type.parse().unwrap());
+        req
+    }
+
+    #[tokio::test]
+    async fn get_and_post() {
+        let (status, headers, body) = call(simple_req(
+            Method::GET,
+            "/graphql?query=%7Bhello%7D",
+            Body::empty(),
+        ))
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[header::CONTENT_TYPE], "application/json");
+        assert_eq!(body, r#"{"data":{"hello":"world"}}"#);
+
+        let (status, _, body) = call(post("application/json", r#"{"query":"{hello}"}"#)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#"{"data":{"hello":"world"}}"#);
+
+        let (status, _, body) = call(post("application/graphql", "{hello}")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#"{"data":{"hello":"world"}}"#);
+
+        let (status, _, body) = call(post(
+            "application/json; charset=utf-8",
+            r#"{"query":"mutation($by:Int!){bump(by:$by)}","variables":{"by":1}}"#,
+        ))
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#"{"data":{"bump":2}}"#);
+    }
+
+    #[tokio::test]
+    async fn batch() {
+        let (status, _, body) = call(post(
+            "application/json",
+            r#"[{"query":"{hello}"},{"query":"mutation{bump(by:2)}"}]"#,
+        ))
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#"[{"data":{"hello":"world"}},{"data":{"bump":3}}]"#);
+    }
+
+    #[tokio::test]
+    async fn status_codes() {
+        // malformed requests
+        let (status, headers, _) = call(simple_req(
+            Method::GET,
+            "/graphql?query=mutation%7Bbump(by%3A1)%7D",
+            Body::empty(),
+        ))
+        .await;
+        assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(headers[header::ALLOW], "GET, POST");
+        let (status, _, _) = call(simple_req(Method::PUT, "/graphql", Body::empty())).await;
+        assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+        let (status, _, _) = call(post("text/plain", "{hello}")).await;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let (status, _, _) = call(post("application/json", "{")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // errors before the execution are 200 in `application/json`
+        let (status, _, body) = call(post("application/json", r#"{"query":"{nope}"}"#)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("errors"), "{body}");
+
+        // but 400 in `application/graphql-response+json`
+        let mut req = post("application/json", r#"{"query":"{nope}"}"#);
+        req.headers_mut().insert(
+            header::ACCEPT,
+            HeaderValue::from_static("application/graphql-response+json, application/json"),
+        );
+        let (status, headers, _) = call(req).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            headers[header::CONTENT_TYPE],
+            "application/graphql-response+json; charset=utf-8"
+        );
+    }
+
+    #[tokio::test]
+    async fn inject_extension() {
+        let mut req = post("application/json", r#"{"query":"{me{name}}"}"#);
+        let (status, _, body) = call(req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#"{"data":{"me":null}}"#);
+
+        req = post("application/json", r#"{"query":"{me{name}}"}"#);
+        req.extensions_mut().insert(Principal {
+            name: "volo".to_owned(),
+        });
+        let (_, _, body) = call(req).await;
+        assert_eq!(body, r#"{"data":{"me":{"name":"volo"}}}"#);
+    }
+
+    #[test]
+    fn response_format() {
+        let format = |accept: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::ACCEPT, accept.parse().unwrap());
+            ResponseFormat::from_headers(&headers)
+        };
+        assert_eq!(
+            ResponseFormat::from_headers(&HeaderMap::new()),
+            ResponseFormat::Json
+        );
+        assert_eq!(format("application/json"), ResponseFormat::Json);
+        assert_eq!(
+            format("application/json;q=0.9, application/graphql-response+json"),
+            ResponseFormat::GraphQLResponseJson
+        );
+        assert_eq!(
+            format("application/graphql-response+json;q=0"),
+            ResponseFormat::Json
+        );
+    }
+}
@@ -21,6 +21,15 @@ use crate::{
     utils::macros::{all_the_tuples, all_the_tuples_with_special_case},
 };
 
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` is not a handler of the requests with the body `{B}`",
+    label = "not a handler",
+    note = "a handler is an `async fn` taking the extractors, whose output implements \
+            `IntoResponse` and whose future is `Send`",
+    note = "the extractors consuming the body, e.g. `String`, `Bytes`, `Form<T>` or `Json<T>`, \
+            must be the last argument, and a handler takes at most one of them",
+    note = "consider `#[volo_http::server::debug_handler]` on the handler for the precise errors"
+)]
 pub trait Handler<T, B, E>: Sized {
     fn handle(
         self,
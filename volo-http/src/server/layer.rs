@@ -1,9 +1,41 @@
-use std::{marker::PhantomData, time::Duration};
+use std::{
+    any::Any,
+    future::Future,
+    io,
+    marker::PhantomData,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{self, ready, Poll},
+    time::Duration,
+};
 
-use motore::{layer::Layer, service::Service};
+use bytes::Bytes;
+use http::{
+    header::{HeaderName, HeaderValue},
+    request::Parts,
+    uri::{PathAndQuery, Uri},
+    StatusCode,
+};
+use http_body::{Body as _, Frame, SizeHint};
+use motore::{layer::Layer, service::Service, BoxError};
+use pin_project::pin_project;
+use tokio::{
+    sync::oneshot,
+    time::{Instant, Sleep},
+};
+use volo::{
+    catch_panic::PanicInfo,
+    context::Context as _,
+    load_shed::Overloaded,
+    preset::{Preset, PresetProtocol},
+    script::{run_hook, Mutation, ScriptHook, Verdict, ViewLimits},
+};
 
-use super::{handler::HandlerWithoutRequest, IntoResponse};
-use crate::{context::ServerContext, request::ServerRequest, response::ServerResponse};
+use super::{handler::HandlerWithoutRequest, panic_handler, IntoResponse};
+use crate::{body::Body, context::ServerContext, request::ServerRequest, response::ServerResponse};
 
 #[derive(Clone)]
 pub struct FilterLayer<H, R, T> {
@@ -82,15 +114,79 @@ where
     }
 }
 
+/// The phase of a request in which the [`Timeout`] fires.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutPhase {
+    /// Reading the body of the request, see [`TimeoutLayer::read_body_timeout`].
+    ReadBody,
+    /// Processing the request by the inner service.
+    Process,
+}
+
+impl TimeoutPhase {
+    /// Returns the status code responded by default, i.e. `408 Request Timeout` for reading the
+    /// body, and `503 Service Unavailable` for processing.
+    pub fn status_code(self) -> StatusCode {
+        match self {
+            Self::ReadBody => StatusCode::REQUEST_TIMEOUT,
+            Self::Process => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+/// A [`Layer`] bounding the time of processing each request, and optionally of reading its
+/// body.
+///
+/// When a timeout fires, the future of the inner service is dropped, i.e. the handler is
+/// cancelled, and the response is made by the [`TimeoutHandler`] with the [`TimeoutPhase`]. The
+/// processing is timed from the end of reading the body if
+/// [`read_body_timeout`](Self::read_body_timeout) is set, or from the arrival of the request
+/// otherwise. A streaming response still being sent when the processing times out is aborted,
+/// which closes the connection instead of sending another status.
+///
+/// The [`TimeoutLayer`] of a route takes the place of the ones of the router and the server, so a
+/// route can have a longer timeout than the global one.
 #[derive(Clone)]
 pub struct TimeoutLayer<H> {
     duration: Duration,
+    read_body: Option<Duration>,
     handler: H,
 }
 
 impl<H> TimeoutLayer<H> {
+    /// Creates a [`TimeoutLayer`] responding by the handler, e.g. a closure taking the
+    /// [`ServerContext`] and returning a [`StatusCode`], which responds the same for both the
+    /// phases.
     pub fn new(duration: Duration, handler: H) -> Self {
-        Self { duration, handler }
+        Self {
+            duration,
+            read_body: None,
+            handler,
+        }
+    }
+
+    /// Sets the timeout of reading the body of the request, which is timed from the arrival of
+    /// the request until the body ends, and the processing is timed after it.
+    ///
+    /// Default is `None`, i.e. the reading is a part of the processing.
+    pub fn read_body_timeout(mut self, timeout: Duration) -> Self {
+        self.read_body = Some(timeout);
+        self
+    }
+}
+
+impl TimeoutLayer<DefaultTimeoutHandler> {
+    /// Creates a [`TimeoutLayer`] responding the [`TimeoutPhase::status_code`].
+    pub fn from_duration(duration: Duration) -> Self {
+        Self::new(duration, DefaultTimeoutHandler)
+    }
+}
+
+impl<F> TimeoutLayer<ByPhase<F>> {
+    /// Creates a [`TimeoutLayer`] responding by the closure taking the [`ServerContext`] and the
+    /// [`TimeoutPhase`].
+    pub fn by_phase(duration: Duration, f: F) -> Self {
+        Self::new(duration, ByPhase(f))
     }
 }
 
@@ -104,13 +200,15 @@ where
         Timeout {
             service: inner,
             duration: self.duration,
+            read_body: self.read_body,
             handler: self.handler,
         }
     }
 }
 
-trait TimeoutHandler<'r> {
-    fn call(self, cx: &'r ServerContext) -> ServerResponse;
+/// Makes the response of a request timed out by the [`Timeout`].
+pub trait TimeoutHandler<'r> {
+    fn call(self, cx: &'r ServerContext, phase: TimeoutPhase) -> ServerResponse;
 }
 
 impl<'r, F, R> TimeoutHandler<'r> for F
@@ -118,24 +216,56 @@ where
     F: FnOnce(&'r ServerContext) -> R + 'r,
     R: IntoResponse + 'r,
 {
-    fn call(self, cx: &'r ServerContext) -> ServerResponse {
+    fn call(self, cx: &'r ServerContext, _phase: TimeoutPhase) -> ServerResponse {
         self(cx).into_response()
     }
 }
 
+/// The [`TimeoutHandler`] responding the [`TimeoutPhase::status_code`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultTimeoutHandler;
+
+impl<'r> TimeoutHandler<'r> for DefaultTimeoutHandler {
+    fn call(self, _cx: &'r ServerContext, phase: TimeoutPhase) -> ServerResponse {
+        phase.status_code().into_response()
+    }
+}
+
+/// The [`TimeoutHandler`] of a closure taking the [`TimeoutPhase`], see
+/// [`TimeoutLayer::by_phase`].
+#[derive(Debug, Clone, Copy)]
+pub struct ByPhase<F>(F);
+
+impl<'r, F, R> TimeoutHandler<'r> for ByPhase<F>
+where
+    F: FnOnce(&'r ServerContext, TimeoutPhase) -> R + 'r,
+    R: IntoResponse + 'r,
+{
+    fn call(self, cx: &'r ServerContext, phase: TimeoutPhase) -> ServerResponse {
+        (self.0)(cx, phase).into_response()
+    }
+}
+
+/// Set by an inner [`Timeout`], e.g. of a route, to take the place of the outer one.
+#[derive(Clone)]
+struct TakenOver(Arc<AtomicBool>);
+
 #[derive(Clone)]
 pub struct Timeout<S, H> {
     service: S,
     duration: Duration,
+    read_body: Option<Duration>,
     handler: H,
 }
 
-impl<S, H> Service<ServerContext, ServerRequest> for Timeout<S, H>
+impl<S, H, B> Service<ServerContext, ServerRequest<B>> for Timeout<S, H>
 where
-    S: Service<ServerContext, ServerRequest> + Send + Sync + 'static,
+    S: Service<ServerContext, ServerRequest<Body>> + Send + Sync + 'static,
     S::Response: IntoResponse,
     S::Error: IntoResponse,
     H: for<'r> TimeoutHandler<'r> + Clone + Sync,
+    B: http_body::Body<Data = Bytes> + Send + Sync + 'static,
+    B::Error: Into<BoxError>,
 {
     type Response = ServerResponse;
     type Error = S::Error;
@@ -143,16 +273,580 @@ where
     async fn call(
         &self,
         cx: &mut ServerContext,
-        req: ServerRequest,
+        req: ServerRequest<B>,
+    ) -> Result<Self::Response, Self::Error> {
+        if let Some(TakenOver(outer)) = cx.extensions().get::<TakenOver>() {
+            outer.store(true, Ordering::Relaxed);
+        }
+        let taken_over = Arc::new(AtomicBool::new(false));
+        cx.extensions_mut().insert(TakenOver(taken_over.clone()));
+
+        let (parts, body) = req.into_parts();
+        let (body, read_end) = match self.read_body {
+            Some(_) if !body.is_end_stream() => {
+                let (tx, rx) = oneshot::channel::<()>();
+                let body = ReadBody {
+                    inner: body,
+                    reading: Some(tx),
+                };
+                (Body::from_body(body), Some(rx))
+            }
+            _ => (Body::from_body(body), None),
+        };
+
+        let (res, deadline) = {
+            let mut reading = read_end.is_some();
+            let mut read_end = read_end.unwrap_or_else(|| oneshot::channel().1);
+            // the timeouts are taken over by an inner `Timeout`
+            let active = || !taken_over.load(Ordering::Relaxed);
+
+            let service = self
+                .service
+                .call(cx, ServerRequest::from_parts(parts, body));
+            let read_timeout = tokio::time::sleep(self.read_body.unwrap_or_default());
+            let process_timeout = tokio::time::sleep(self.duration);
+            tokio::pin!(service, read_timeout, process_timeout);
+            let res = loop {
+                tokio::select! {
+                    res = &mut service => break Ok(res),
+                    _ = &mut read_end, if reading => {
+                        // the body ends, and the processing starts
+                        reading = false;
+                        process_timeout.as_mut().reset(Instant::now() + self.duration);
+                    }
+                    _ = &mut read_timeout, if reading && active() => {
+                        if active() {
+                            break Err(TimeoutPhase::ReadBody);
+                        }
+                    }
+                    _ = &mut process_timeout, if !reading && active() => {
+                        if active() {
+                            break Err(TimeoutPhase::Process);
+                        }
+                    }
+                }
+            };
+            (res, process_timeout.deadline())
+        };
+
+        match res {
+            Ok(resp) => {
+                let resp = resp?.into_response();
+                if taken_over.load(Ordering::Relaxed) || resp.body().is_end_stream() {
+                    return Ok(resp);
+                }
+                Ok(resp.map(|body| match body {
+                    Body::Full(_) => body,
+                    body => Body::from_body(DeadlineBody {
+                        inner: body,
+                        timeout: tokio::time::sleep_until(deadline),
+                    }),
+                }))
+            }
+            Err(phase) => {
+                tracing::debug!("[VOLO] Timeout: the request timed out in {phase:?}");
+                Ok(self.handler.clone().call(cx, phase))
+            }
+        }
+    }
+}
+
+/// The body of a request, which drops the sender when it ends, so the [`Timeout`] knows the
+/// reading is done.
+#[pin_project]
+struct ReadBody<B> {
+    #[pin]
+    inner: B,
+    reading: Option<oneshot::Sender<()>>,
+}
+
+impl<B> http_body::Body for ReadBody<B>
+where
+    B: http_body::Body,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let mut this = self.project();
+        let frame = ready!(this.inner.as_mut().poll_frame(cx));
+        if !matches!(frame, Some(Ok(_))) || this.inner.is_end_stream() {
+            this.reading.take();
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// The body of a streaming response, which fails at the deadline of the processing, so the
+/// connection is closed.
+#[pin_project]
+struct DeadlineBody {
+    #[pin]
+    inner: Body,
+    #[pin]
+    timeout: Sleep,
+}
+
+impl http_body::Body for DeadlineBody {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        if let Poll::Ready(frame) = this.inner.poll_frame(cx) {
+            return Poll::Ready(frame);
+        }
+        ready!(this.timeout.poll(cx));
+        Poll::Ready(Some(Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "the response body timed out",
+        )
+        .into())))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// The HTTP protocol of [`Preset`].
+///
+/// The size of a request is limited by its `Content-Length`, and a larger request is rejected with
+/// `413 Payload Too Large` before the handler is called. The body without `Content-Length`, e.g.
+/// the chunked one, is not limited by the preset, and should be limited where it's read. A handler
+/// timed out responds `408 Request Timeout`, a panic `500 Internal Server Error`, and a request
+/// rejected by the concurrency cap `503 Service Unavailable`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HttpPreset;
+
+/// Creates a [`Preset`] with the production defaults for the HTTP servers, see the versions of the
+/// defaults in [`volo::preset`].
+///
+/// # Example
+///
+/// ```rust,ignore
+/// Server::new(router)
+///     .layer(volo_http::server::layer::production_defaults())
+///     .run(addr)
+///     .await
+///     .unwrap();
+/// ```
+pub fn production_defaults() -> Preset<HttpPreset> {
+    Preset::new(HttpPreset)
+}
+
+impl<B, E> PresetProtocol<ServerContext, ServerRequest<B>, ServerResponse, E> for HttpPreset {
+    fn limit_size(
+        &self,
+        _cx: &mut ServerContext,
+        req: ServerRequest<B>,
+        max_size: usize,
+    ) -> Result<ServerRequest<B>, Result<ServerResponse, E>> {
+        let content_length = req
+            .headers()
+            .get(http::header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok());
+        match content_length {
+            Some(len) if len > max_size as u64 => {
+                Err(Ok(StatusCode::PAYLOAD_TOO_LARGE.into_response()))
+            }
+            _ => Ok(req),
+        }
+    }
+
+    fn timed_out(&self, _cx: &mut ServerContext, _timeout: Duration) -> Result<ServerResponse, E> {
+        Ok(StatusCode::REQUEST_TIMEOUT.into_response())
+    }
+
+    fn panicked(
+        &self,
+        cx: &mut ServerContext,
+        payload: Box<dyn Any + Send>,
+        panic_info: PanicInfo,
+    ) -> Result<ServerResponse, E> {
+        panic_handler::always_internal_error(cx, payload, panic_info)
+    }
+
+    fn overloaded(
+        &self,
+        _cx: &mut ServerContext,
+        _overloaded: Overloaded,
+    ) -> Result<ServerResponse, E> {
+        Ok(StatusCode::SERVICE_UNAVAILABLE.into_response())
+    }
+}
+
+/// A [`Layer`] for invoking the [`ScriptHook`] with the view of each request, and applying the
+/// mutations to the request or rejecting it.
+///
+/// The view contains the method, the path and query, and the headers limited by the
+/// [`ViewLimits`], but not the body. If the hook fails, the request continues unchanged.
+///
+/// A rejection responds with the status code of it, or `403 Forbidden` if the code is invalid.
+#[derive(Clone)]
+pub struct ScriptLayer<H> {
+    hook: Arc<H>,
+    limits: ViewLimits,
+}
+
+impl<H> ScriptLayer<H> {
+    pub fn new(hook: H) -> Self {
+        Self {
+            hook: Arc::new(hook),
+            limits: ViewLimits::default(),
+        }
+    }
+
+    /// Sets the limits of the headers in the view.
+    ///
+    /// Default is [`ViewLimits::default`].
+    pub fn limits(mut self, limits: ViewLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+}
+
+impl<S, H> Layer<S> for ScriptLayer<H>
+where
+    S: Send + Sync + 'static,
+{
+    type Service = Script<S, H>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        Script {
+            service: inner,
+            hook: self.hook,
+            limits: self.limits,
+        }
+    }
+}
+
+pub struct Script<S, H> {
+    service: S,
+    hook: Arc<H>,
+    limits: ViewLimits,
+}
+
+impl<S, H> Clone for Script<S, H>
+where
+    S: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            service: self.service.clone(),
+            hook: self.hook.clone(),
+            limits: self.limits.clone(),
+        }
+    }
+}
+
+impl<S, H, B> Service<ServerContext, ServerRequest<B>> for Script<S, H>
+where
+    S: Service<ServerContext, ServerRequest<B>> + Send + Sync + 'static,
+    S::Response: IntoResponse,
+    H: ScriptHook,
+    B: Send,
+{
+    type Response = ServerResponse;
+    type Error = S::Error;
+
+    async fn call<'s, 'cx>(
+        &'s self,
+        cx: &'cx mut ServerContext,
+        req: ServerRequest<B>,
     ) -> Result<Self::Response, Self::Error> {
-        let fut_service = self.service.call(cx, req);
-        let fut_timeout = tokio::time::sleep(self.duration);
+        let (mut parts, body) = req.into_parts();
+        let view = self.limits.view(
+            parts.method.as_str(),
+            parts.uri.path_and_query().map_or("/", PathAndQuery::as_str),
+            parts
+                .headers
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_bytes())),
+        );
+        match run_hook(&*self.hook, &view) {
+            Verdict::Continue { mutations } => {
+                for mutation in mutations {
+                    apply_mutation(&mut parts, mutation);
+                }
+            }
+            Verdict::Reject { status, message } => {
+                let status = StatusCode::from_u16(status).unwrap_or(StatusCode::FORBIDDEN);
+                return Ok((status, message).into_response());
+            }
+        }
+        self.service
+            .call(cx, ServerRequest::from_parts(parts, body))
+            .await
+            .map(IntoResponse::into_response)
+    }
+}
+
+fn apply_mutation(parts: &mut Parts, mutation: Mutation) {
+    match mutation {
+        Mutation::SetHeader { name, value } => {
+            match (HeaderName::try_from(name), HeaderValue::try_from(value)) {
+                (Ok(name), Ok(value)) => {
+                    parts.headers.insert(name, value);
+                }
+                _ => tracing::warn!("[VOLO] ScriptLayer: invalid header is ignored"),
+            }
+        }
+        Mutation::RemoveHeader { name } => {
+            parts.headers.remove(name.as_str());
+        }
+        Mutation::SetPath { path } => {
+            let mut uri = parts.uri.clone().into_parts();
+            uri.path_and_query = match PathAndQuery::try_from(path) {
+                Ok(path) => Some(path),
+                Err(e) => {
+                    tracing::warn!("[VOLO] ScriptLayer: invalid path is ignored: {e}");
+                    return;
+                }
+            };
+            match Uri::from_parts(uri) {
+                Ok(uri) => parts.uri = uri,
+                Err(e) => tracing::warn!("[VOLO] ScriptLayer: invalid path is ignored: {e}"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod layer_tests {
+    use std::{
+        sync::atomic::{AtomicBool, Ordering},
+        time::Duration,
+    };
+
+    use bytes::Bytes;
+    use http::{method::Method, status::StatusCode};
+    use http_body::Frame;
+    use http_body_util::BodyExt;
+    use motore::BoxError;
+    use volo::{
+        preset::{DEFAULT_MAX_BODY_SIZE, DEFAULT_TIMEOUT},
+        script::{FnHook, Mutation, ScriptError, Verdict},
+    };
+
+    use super::{production_defaults, ScriptLayer, TimeoutLayer, TimeoutPhase};
+    use crate::{
+        body::{Body, BodyConversion},
+        context::ServerContext,
+        request::ServerRequest,
+        server::route::{get, post, Router},
+        Server,
+    };
+
+    /// A body of the chunks, each of which is sent after the delay.
+    fn slow_body(chunks: &'static [(u64, &'static str)]) -> Body {
+        Body::from_stream(futures::stream::unfold(chunks, |chunks| async move {
+            let ((delay, chunk), rest) = chunks.split_first()?;
+            tokio::time::sleep(Duration::from_secs(*delay)).await;
+            Some((
+                Ok::<_, BoxError>(Frame::data(Bytes::from_static(chunk.as_bytes()))),
+                rest,
+            ))
+        }))
+    }
+
+    async fn echo(req: ServerRequest<Option<Body>>) -> String {
+        let user = req
+            .headers()
+            .get("x-user")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+        format!("{} {user}", req.uri())
+    }
+
+    #[tokio::test]
+    async fn script_layer() {
+        let hook = FnHook::new("gateway", |req| {
+            if req.header("x-fail").is_some() {
+                return Err(ScriptError::Runtime("boom".to_owned()));
+            }
+            match req.path.as_str() {
+                "/admin" => Ok(Verdict::reject(401, "login first")),
+                "/old?id=1" => Ok(Verdict::mutate(vec![
+                    Mutation::SetPath {
+                        path: "/new?id=1".to_owned(),
+                    },
+                    Mutation::SetHeader {
+                        name: "x-user".to_owned(),
+                        value: "alice".to_owned(),
+                    },
+                ])),
+                _ => Ok(Verdict::pass()),
+            }
+        });
+        let router: Router<Option<Body>> = Router::new()
+            .route("/new", get(echo))
+            .route("/old", get(echo));
+        let server = Server::new(router)
+            .layer(ScriptLayer::new(hook))
+            .into_test_server();
+
+        let resp = server.call_route(Method::GET, "/old?id=1", None).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.into_string().await.unwrap(),
+            "/new?id=1 alice".to_owned()
+        );
+
+        let resp = server.call_route(Method::GET, "/admin", None).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(resp.into_string().await.unwrap(), "login first".to_owned());
+
+        // the failing hook is skipped
+        let req = ServerRequest::builder()
+            .method(Method::GET)
+            .uri("/old")
+            .header("x-fail", "1")
+            .body(None)
+            .unwrap();
+        let resp = server.call_without_cx(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.into_string().await.unwrap(), "/old ".to_owned());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn production_defaults_verbatim() {
+        async fn panicking() -> &'static str {
+            panic!("boom")
+        }
+        async fn sleeping() -> &'static str {
+            tokio::time::sleep(DEFAULT_TIMEOUT * 2).await;
+            "awake"
+        }
+
+        let router: Router<Option<Body>> = Router::new()
+            .route("/echo", get(echo))
+            .route("/panic", get(panicking))
+            .route("/sleep", get(sleeping));
+        let server = Server::new(router)
+            .layer(production_defaults())
+            .into_test_server();
+
+        let resp = server.call_route(Method::GET, "/echo", None).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let req = ServerRequest::builder()
+            .method(Method::GET)
+            .uri("/echo")
+            .header(
+                http::header::CONTENT_LENGTH,
+                (DEFAULT_MAX_BODY_SIZE + 1).to_string(),
+            )
+            .body(None)
+            .unwrap();
+        let resp = server.call_without_cx(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let resp = server.call_route(Method::GET, "/panic", None).await;
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let resp = server.call_route(Method::GET, "/sleep", None).await;
+        assert_eq!(resp.status(), StatusCode::REQUEST_TIMEOUT);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn timeout_cancels_handler() {
+        static FINISHED: AtomicBool = AtomicBool::new(false);
 
-        tokio::select! {
-            resp = fut_service => resp.map(IntoResponse::into_response),
-            _ = fut_timeout => {
-                Ok((self.handler.clone()).call(cx))
-            },
+        async fn sleep(secs: u64) -> &'static str {
+            tokio::time::sleep(Duration::from_secs(secs)).await;
+            FINISHED.store(true, Ordering::Relaxed);
+            "awake"
         }
+        async fn stream() -> Body {
+            slow_body(&[(0, "a"), (10, "b")])
+        }
+
+        let router: Router<Body> = Router::new()
+            .route("/sleep", get(|| sleep(10)))
+            .route("/stream", get(stream))
+            // the timeout of the route takes the place of the global one
+            .route(
+                "/long",
+                get(|| sleep(10)).layer(TimeoutLayer::by_phase(
+                    Duration::from_secs(20),
+                    |_: &ServerContext, phase: TimeoutPhase| {
+                        (StatusCode::GATEWAY_TIMEOUT, format!("{phase:?}"))
+                    },
+                )),
+            );
+        let server = Server::new(router)
+            .layer(TimeoutLayer::from_duration(Duration::from_secs(1)))
+            .into_test_server();
+
+        let resp = server.call_route(Method::GET, "/sleep", "").await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        // the handler is dropped
+        tokio::time::sleep(Duration::from_secs(20)).await;
+        assert!(!FINISHED.load(Ordering::Relaxed));
+
+        let resp = server.call_route(Method::GET, "/long", "").await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(FINISHED.load(Ordering::Relaxed));
+
+        // the streaming response is aborted instead of responding another status
+        let resp = server.call_route(Method::GET, "/stream", "").await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let mut body = resp.into_body();
+        let frame = body.frame().await.unwrap().unwrap();
+        assert_eq!(frame.into_data().unwrap(), "a");
+        assert!(body.frame().await.unwrap().is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn timeout_read_body() {
+        async fn read(body: String) -> String {
+            body
+        }
+
+        let router: Router<Body> = Router::new().route("/read", post(read));
+        let server = Server::new(router)
+            .layer(
+                TimeoutLayer::from_duration(Duration::from_secs(1))
+                    .read_body_timeout(Duration::from_secs(5)),
+            )
+            .into_test_server();
+
+        // reading the body is not a part of the processing
+        let req = ServerRequest::builder()
+            .method(Method::POST)
+            .uri("/read")
+            .body(slow_body(&[(0, "a"), (3, "b")]))
+            .unwrap();
+        let resp = server.call_without_cx(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.into_string().await.unwrap(), "ab");
+
+        let req = ServerRequest::builder()
+            .method(Method::POST)
+            .uri("/read")
+            .body(slow_body(&[(0, "a"), (10, "b")]))
+            .unwrap();
+        let resp = server.call_without_cx(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::REQUEST_TIMEOUT);
     }
 }
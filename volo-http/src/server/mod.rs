@@ -1,12 +1,4 @@
-use std::{
-    cell::RefCell,
-    convert::Infallible,
-    sync::{
-        atomic::{AtomicUsize, Ordering},
-        Arc,
-    },
-    time::Duration,
-};
+use std::{cell::RefCell, convert::Infallible, sync::Arc, time::Duration};
 
 use futures::future::BoxFuture;
 use hyper::server::conn::http1;
@@ -19,13 +11,19 @@ use motore::{
 };
 use parking_lot::RwLock;
 use scopeguard::defer;
-use tokio::sync::Notify;
 use tracing::{info, trace};
 #[cfg(feature = "__tls")]
 use volo::net::{conn::ConnStream, tls::Acceptor, tls::ServerTlsConfig};
 use volo::{
-    context::Context,
-    net::{conn::Conn, incoming::Incoming, Address, MakeIncoming},
+    config::ConfigError,
+    context::{
+        identity::{PeerIdentityExt, TlsIdentity},
+        log_id::{self, LogIdConfig},
+        Context,
+    },
+    net::{conn::Conn, incoming::Incoming, listener::ListenerName, Address, MakeIncoming},
+    server::ServerHandle,
+    FastStr,
 };
 
 use crate::{
@@ -35,21 +33,43 @@ use crate::{
     response::ServerResponse,
 };
 
+#[cfg(feature = "tower")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tower")))]
+pub mod embed;
 pub mod extract;
+#[cfg(feature = "graphql")]
+#[cfg_attr(docsrs, doc(cfg(feature = "graphql")))]
+pub mod graphql;
 mod handler;
 pub mod layer;
 pub mod middleware;
+#[cfg(feature = "negotiate")]
+#[cfg_attr(docsrs, doc(cfg(feature = "negotiate")))]
+pub mod negotiate;
 pub mod panic_handler;
 pub mod param;
 pub mod response;
 pub mod route;
+pub mod shutdown;
+#[cfg(feature = "spool")]
+#[cfg_attr(docsrs, doc(cfg(feature = "spool")))]
+pub mod spool;
 #[cfg(test)]
 pub mod test_helpers;
+#[cfg(feature = "grpc-transcoding")]
+#[cfg_attr(docsrs, doc(cfg(feature = "grpc-transcoding")))]
+pub mod transcoding;
 pub mod utils;
 
+use self::shutdown::Shutdown;
+#[cfg(feature = "macros")]
+#[cfg_attr(docsrs, doc(cfg(feature = "macros")))]
+pub use volo_macros::debug_handler;
+
 pub use self::{
     response::{IntoResponse, Redirect},
     route::Router,
+    shutdown::ShutdownSignal,
 };
 
 #[doc(hidden)]
@@ -87,12 +107,17 @@ pub struct Server<S, L> {
     service: S,
     layer: L,
     server: http1::Builder,
+    max_headers: Option<usize>,
     config: Config,
     shutdown_hooks: Vec<Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>>,
+    shutdown_timeout: Duration,
+    handle: ServerHandle,
     #[cfg(feature = "__tls")]
     tls_config: Option<ServerTlsConfig>,
 }
 
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
 impl<S> Server<S, Identity> {
     /// Create a new server.
     pub fn new(service: S) -> Self {
@@ -100,8 +125,11 @@ impl<S> Server<S, Identity> {
             service,
             layer: Identity::new(),
             server: http1::Builder::new(),
+            max_headers: None,
             config: Config::default(),
             shutdown_hooks: Vec::new(),
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            handle: ServerHandle::new(),
             #[cfg(feature = "__tls")]
             tls_config: None,
         }
@@ -131,6 +159,26 @@ impl<S, L> Server<S, L> {
         self
     }
 
+    /// Set the timeout of graceful shutdown.
+    ///
+    /// When shutting down, the server stops accepting new connections and notifies the
+    /// [`ShutdownSignal`], then waits for the connections to be closed until the timeout, and the
+    /// remaining connections will be force closed.
+    ///
+    /// Default is 30 seconds.
+    pub fn set_shutdown_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Returns the handle to shut down the server after it runs, see [`ServerHandle`].
+    ///
+    /// The timeout of [`ServerHandle::shutdown_timeout`] takes the place of the one set by
+    /// [`Server::set_shutdown_timeout`].
+    pub fn handle(&self) -> ServerHandle {
+        self.handle.clone()
+    }
+
     /// Adds a new inner layer to the server.
     ///
     /// The layer's `Service` should be `Send + Sync + Clone + 'static`.
@@ -147,8 +195,11 @@ impl<S, L> Server<S, L> {
             service: self.service,
             layer: Stack::new(layer, self.layer),
             server: self.server,
+            max_headers: self.max_headers,
             config: self.config,
             shutdown_hooks: self.shutdown_hooks,
+            shutdown_timeout: self.shutdown_timeout,
+            handle: self.handle,
             #[cfg(feature = "__tls")]
             tls_config: self.tls_config,
         }
@@ -170,8 +221,11 @@ impl<S, L> Server<S, L> {
             service: self.service,
             layer: Stack::new(self.layer, layer),
             server: self.server,
+            max_headers: self.max_headers,
             config: self.config,
             shutdown_hooks: self.shutdown_hooks,
+            shutdown_timeout: self.shutdown_timeout,
+            handle: self.handle,
             #[cfg(feature = "__tls")]
             tls_config: self.tls_config,
         }
@@ -250,9 +304,28 @@ impl<S, L> Server<S, L> {
     /// Default is 100.
     pub fn set_max_headers(&mut self, max_headers: usize) -> &mut Self {
         self.server.max_headers(max_headers);
+        self.max_headers = Some(max_headers);
         self
     }
 
+    fn check(&self) -> Result<(), ConfigError> {
+        let mut err = ConfigError::new();
+        err.ensure(
+            self.max_headers != Some(0),
+            "max_headers",
+            "must be greater than zero",
+        );
+        #[cfg(feature = "__tls")]
+        if let Some(tls_config) = &self.tls_config {
+            err.ensure(
+                tls_config.acceptor.has_certs(),
+                "tls_config",
+                "no certificate to serve the connections",
+            );
+        }
+        err.into_result()
+    }
+
     /// The main entry point for the server.
     pub async fn run<MI, B, E>(self, mk_incoming: MI) -> Result<(), BoxError>
     where
@@ -265,26 +338,30 @@ impl<S, L> Server<S, L> {
         <L::Service as Service<ServerContext, ServerRequest>>::Response: IntoResponse,
         MI: MakeIncoming,
     {
+        self.check()?;
+
         let server = Arc::new(self.server);
         let service = Arc::new(self.layer.layer(self.service));
         let incoming = mk_incoming.make_incoming().await?;
         info!("[VOLO] server start at: {:?}", incoming);
 
-        // count connections, used for graceful shutdown
-        let conn_cnt = Arc::new(AtomicUsize::new(0));
         // flag for stopping serve
         let exit_flag = Arc::new(parking_lot::RwLock::new(false));
-        // notifier for stopping all inflight connections
-        let exit_notify = Arc::new(Notify::const_new());
-
-        let handler = tokio::spawn(serve(
+        // connection states and notifiers, used for graceful shutdown
+        let shutdown = Arc::new(Shutdown::new());
+        let _serving = self.handle.serving({
+            let shutdown = shutdown.clone();
+            move || shutdown.active()
+        });
+        let mut deadline = None;
+
+        let mut handler = tokio::spawn(serve(
             server,
             incoming,
             service,
             self.config,
             exit_flag.clone(),
-            conn_cnt.clone(),
-            exit_notify.clone(),
+            shutdown.clone(),
             #[cfg(feature = "__tls")]
             self.tls_config,
         ));
@@ -304,7 +381,8 @@ impl<S, L> Server<S, L> {
                 _ = sigint.recv() => {}
                 _ = sighup.recv() => {}
                 _ = sigterm.recv() => {}
-                _ = handler => {},
+                d = self.handle.requested() => deadline = d,
+                _ = &mut handler => {},
             }
         }
 
@@ -312,7 +390,8 @@ impl<S, L> Server<S, L> {
         #[cfg(target_family = "windows")]
         tokio::select! {
             _ = tokio::signal::ctrl_c() => {}
-            _ = handler => {},
+            d = self.handle.requested() => deadline = d,
+            _ = &mut handler => {},
         }
 
         if !self.shutdown_hooks.is_empty() {
@@ -325,26 +404,33 @@ impl<S, L> Server<S, L> {
 
         // received signal, graceful shutdown now
         info!("[VOLO] received signal, gracefully exiting now");
+        let deadline =
+            deadline.unwrap_or_else(|| tokio::time::Instant::now() + self.shutdown_timeout);
         *exit_flag.write() = true;
+        // stops accepting, and unlinks the socket file of the unix domain socket
+        handler.abort();
+        // Let the long-lived responses, e.g. SSE, send their final messages and end.
+        shutdown.notify_handlers();
 
         // Now we won't accept new connections.
         // And we want to send crrst reply to the peers in the short future.
-        if conn_cnt.load(Ordering::Relaxed) != 0 {
-            tokio::time::sleep(Duration::from_secs(2)).await;
+        if shutdown.active() != 0 {
+            tokio::time::sleep_until(
+                deadline.min(tokio::time::Instant::now() + Duration::from_secs(2)),
+            )
+            .await;
         }
-        exit_notify.notify_waiters();
+        shutdown.notify_connections();
+        info!("[VOLO] gracefully exiting, connections: {shutdown}");
 
         // wait for all connections to be closed
-        for _ in 0..28 {
-            if conn_cnt.load(Ordering::Relaxed) == 0 {
-                break;
-            }
-            trace!(
-                "[VOLO] gracefully exiting, remaining connection count: {}",
-                conn_cnt.load(Ordering::Relaxed)
-            );
-            tokio::time::sleep(Duration::from_secs(1)).await;
+        let timeout = deadline.saturating_duration_since(tokio::time::Instant::now());
+        if !shutdown.wait_for_drain(timeout).await {
+            shutdown.force_close();
+            // the connections will be closed as soon as they are notified
+            shutdown.wait_for_drain(Duration::from_secs(1)).await;
         }
+        info!("[VOLO] gracefully exited, connections: {shutdown}");
 
         Ok(())
     }
@@ -357,8 +443,7 @@ async fn serve<I, S, E>(
     service: S,
     config: Config,
     exit_flag: Arc<RwLock<bool>>,
-    conn_cnt: Arc<AtomicUsize>,
-    exit_notify: Arc<Notify>,
+    shutdown: Arc<Shutdown>,
     #[cfg(feature = "__tls")] tls_config: Option<ServerTlsConfig>,
 ) where
     I: Incoming,
@@ -407,35 +492,34 @@ async fn serve<I, S, E>(
         let hyper_service = HyperService {
             inner: service.clone(),
             peer,
+            tls_identity: conn.stream.tls_identity(),
+            listener: conn.info.listener.clone(),
             config: config.clone(),
+            shutdown: shutdown.signal(),
         };
 
         tokio::spawn(serve_conn(
             server.clone(),
             conn,
             hyper_service,
-            conn_cnt.clone(),
-            exit_notify.clone(),
+            shutdown.clone(),
         ));
     }
 }
 
-async fn serve_conn<S>(
-    server: Arc<http1::Builder>,
-    conn: Conn,
-    service: S,
-    conn_cnt: Arc<AtomicUsize>,
-    exit_notify: Arc<Notify>,
-) where
+async fn serve_conn<S>(server: Arc<http1::Builder>, conn: Conn, service: S, shutdown: Arc<Shutdown>)
+where
     S: hyper::service::HttpService<hyper::body::Incoming, ResBody = Body>,
 {
-    conn_cnt.fetch_add(1, Ordering::Relaxed);
+    shutdown.conn_opened();
     defer! {
-        conn_cnt.fetch_sub(1, Ordering::Relaxed);
+        shutdown.conn_closed();
     }
 
-    let notified = exit_notify.notified();
+    let notified = shutdown.drain_notified();
     tokio::pin!(notified);
+    let force = shutdown.force_notified();
+    tokio::pin!(force);
 
     let mut http_conn = server.serve_connection(TokioIo::new(conn), service);
 
@@ -446,10 +530,22 @@ async fn serve_conn<S>(
             hyper::server::conn::http1::Connection::graceful_shutdown(
                 Pin::new(&mut http_conn)
             );
-            // Continue to poll this connection until shutdown can finish.
-            let result = http_conn.await;
-            if let Err(err) = result {
-                tracing::debug!("[VOLO] connection error: {:?}", err);
+            shutdown.conn_draining();
+            defer! {
+                shutdown.conn_drained();
+            }
+            // Continue to poll this connection until shutdown can finish, or it is force closed
+            // after the shutdown timeout.
+            tokio::select! {
+                result = &mut http_conn => {
+                    if let Err(err) = result {
+                        tracing::debug!("[VOLO] connection error: {:?}", err);
+                    }
+                }
+                _ = &mut force => {
+                    tracing::trace!("[VOLO] force closing a connection");
+                    shutdown.conn_force_closed();
+                }
             }
         }
         result = &mut http_conn => {
@@ -464,7 +560,10 @@ async fn serve_conn<S>(
 struct HyperService<S> {
     inner: S,
     peer: Address,
+    tls_identity: Option<TlsIdentity>,
+    listener: Option<FastStr>,
     config: Config,
+    shutdown: ShutdownSignal,
 }
 
 impl<S, E> hyper::service::Service<ServerRequest> for HyperService<S>
@@ -483,8 +582,71 @@ where
             METAINFO.scope(RefCell::new(MetaInfo::default()), async move {
                 let mut cx = ServerContext::new(service.peer);
                 cx.rpc_info_mut().set_config(service.config);
-                Ok(service.inner.call(&mut cx, req).await.into_response())
+                cx.extensions_mut().insert(service.shutdown);
+                if let Some(tls_identity) = service.tls_identity {
+                    cx.peer_identity_mut().set_tls(tls_identity);
+                }
+                if let Some(listener) = service.listener {
+                    cx.extensions_mut().insert(ListenerName(listener));
+                }
+                Ok(handle_request(&service.inner, &mut cx, req).await)
             }),
         )
     }
 }
+
+/// Calls the service with the context, and converts the result into the response.
+///
+/// This is shared by the server and the [`TowerService`](embed::TowerService).
+async fn handle_request<S, B, E>(
+    service: &S,
+    cx: &mut ServerContext,
+    req: ServerRequest<B>,
+) -> ServerResponse
+where
+    S: Service<ServerContext, ServerRequest<B>, Error = E>,
+    S::Response: IntoResponse,
+    E: IntoResponse,
+{
+    // the log id of the upstream, or generated at the first hop
+    let received = req
+        .headers()
+        .get(LogIdConfig::global().header().as_str())
+        .and_then(|v| v.to_str().ok());
+    log_id::accept(cx, received);
+
+    #[cfg(feature = "negotiate")]
+    let accept = req.headers().get(http::header::ACCEPT).cloned();
+    let resp = service.call(cx, req).await.into_response();
+    #[cfg(feature = "negotiate")]
+    let resp = negotiate::finalize(cx, accept.as_ref(), resp);
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Server;
+    use crate::server::route::Router;
+
+    #[cfg(feature = "rustls")]
+    #[test]
+    fn tls_without_certs() {
+        use volo::net::tls::{ServerTlsConfig, SniConfig};
+
+        let router: Router = Router::new();
+        let server = Server::new(router).tls_config(ServerTlsConfig::from_sni(SniConfig::new()));
+        let err = server.check().unwrap_err();
+        let problems = err.problems().iter().map(|p| p.field).collect::<Vec<_>>();
+        assert_eq!(problems, ["tls_config"], "{err}");
+    }
+
+    #[test]
+    fn zero_max_headers() {
+        let router: Router = Router::new();
+        let mut server = Server::new(router);
+        server.set_max_headers(0);
+        let err = server.check().unwrap_err();
+        let problems = err.problems().iter().map(|p| p.field).collect::<Vec<_>>();
+        assert_eq!(problems, ["max_headers"], "{err}");
+    }
+}
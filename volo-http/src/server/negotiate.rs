@@ -0,0 +1,404 @@
+//! Content negotiation driven by the `Accept` header of the request.
+//!
+//! A handler returning [`Negotiate<T>`] lets the server serialize `T` into the representation
+//! most preferred by the client, following the precedence rules of [RFC 9110]: the most specific
+//! media range matching a content type decides its quality value, and the content type with the
+//! highest quality is used. A request without `Accept` accepts anything.
+//!
+//! `application/json` (with the feature `json`) and `application/x-www-form-urlencoded` (with
+//! the feature `form`) are supported by default, in the order of preference of the server. More
+//! encoders can be registered by an [`Encoders`] in the [`Extension`] layer, on the router or on
+//! the server.
+//!
+//! If none of the content types is acceptable, the server responds `406 Not Acceptable` with the
+//! supported types in the body.
+//!
+//! [RFC 9110]: https://www.rfc-editor.org/rfc/rfc9110#section-12.5.1
+//! [`Extension`]: crate::extension::Extension
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use volo_http::{
+//!     extension::Extension,
+//!     server::{
+//!         negotiate::{Encoders, Negotiate},
+//!         route::{get, Router},
+//!     },
+//! };
+//!
+//! #[derive(serde::Serialize)]
+//! struct User {
+//!     name: String,
+//! }
+//!
+//! async fn user() -> Negotiate<User> {
+//!     Negotiate(User {
+//!         name: "volo".to_owned(),
+//!     })
+//! }
+//!
+//! let encoders = Encoders::new().encoder("application/yaml", |value| {
+//!     Ok(serde_yaml::to_string(value)?.into_bytes())
+//! });
+//! let router = Router::new()
+//!     .route("/user", get(user))
+//!     .layer(Extension(encoders));
+//! ```
+
+use std::sync::{Arc, OnceLock};
+
+use http::{
+    header::{self, HeaderValue},
+    StatusCode,
+};
+use mime::Mime;
+use motore::BoxError;
+use serde::Serialize;
+use volo::context::Context;
+
+use super::IntoResponse;
+use crate::{body::Body, context::ServerContext, response::ServerResponse};
+
+/// A response serialized into the content type negotiated by the `Accept` header.
+///
+/// The status and the headers set along with it, e.g. by `(StatusCode::CREATED, Negotiate(t))`,
+/// are kept.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Negotiate<T>(pub T);
+
+impl<T> IntoResponse for Negotiate<T>
+where
+    T: Serialize + Send + Sync + 'static,
+{
+    fn into_response(self) -> ServerResponse {
+        let mut resp = ServerResponse::new(Body::empty());
+        resp.extensions_mut().insert(Pending(Arc::new(self.0)));
+        resp
+    }
+}
+
+/// The value of a [`Negotiate`] waiting to be encoded by the server.
+#[derive(Clone)]
+struct Pending(Arc<dyn erased_serde::Serialize + Send + Sync>);
+
+type EncodeFn =
+    Arc<dyn Fn(&dyn erased_serde::Serialize) -> Result<Vec<u8>, BoxError> + Send + Sync>;
+
+/// The encoders of [`Negotiate`], in the order of preference of the server.
+#[derive(Clone)]
+pub struct Encoders {
+    encoders: Vec<(Mime, HeaderValue, EncodeFn)>,
+}
+
+impl Default for Encoders {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Encoders {
+    /// Creates the encoders supported by default.
+    pub fn new() -> Self {
+        let encoders = Self::empty();
+        #[cfg(feature = "__json")]
+        let encoders = encoders.encoder(mime::APPLICATION_JSON.essence_str(), |value| {
+            Ok(crate::json::serialize(&value)?)
+        });
+        #[cfg(feature = "form")]
+        let encoders = encoders.encoder(
+            mime::APPLICATION_WWW_FORM_URLENCODED.essence_str(),
+            |value| Ok(serde_urlencoded::to_string(value)?.into_bytes()),
+        );
+        encoders
+    }
+
+    /// Creates the encoders without any content type.
+    pub fn empty() -> Self {
+        Self {
+            encoders: Vec::new(),
+        }
+    }
+
+    /// Registers an encoder of the content type, which replaces the one registered before, or is
+    /// the least preferred otherwise.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the content type is invalid or contains wildcards.
+    pub fn encoder<F>(mut self, content_type: &str, f: F) -> Self
+    where
+        F: Fn(&dyn erased_serde::Serialize) -> Result<Vec<u8>, BoxError> + Send + Sync + 'static,
+    {
+        let mime = content_type
+            .parse::<Mime>()
+            .unwrap_or_else(|_| panic!("invalid content type `{content_type}`"));
+        assert!(
+            mime.type_() != mime::STAR && mime.subtype() != mime::STAR,
+            "content type `{content_type}` contains wildcards"
+        );
+        let value = HeaderValue::from_str(content_type).expect("valid content type");
+        let f: EncodeFn = Arc::new(f);
+        match self.encoders.iter_mut().find(|(m, _, _)| *m == mime) {
+            Some(e) => *e = (mime, value, f),
+            None => self.encoders.push((mime, value, f)),
+        }
+        self
+    }
+
+    /// Returns the supported content types, in the order of preference.
+    pub fn content_types(&self) -> impl Iterator<Item = &str> {
+        self.encoders
+            .iter()
+            .map(|(_, v, _)| v.to_str().unwrap_or_default())
+    }
+
+    /// Returns the index of the encoder to use, or `None` if nothing is acceptable.
+    fn select(&self, accept: Option<&HeaderValue>) -> Option<usize> {
+        let ranges = match accept.map(HeaderValue::to_str) {
+            Some(Ok(accept)) if !accept.trim().is_empty() => parse_accept(accept),
+            // anything is acceptable
+            _ => return (!self.encoders.is_empty()).then_some(0),
+        };
+        let mut best: Option<(usize, u16)> = None;
+        for (i, (mime, _, _)) in self.encoders.iter().enumerate() {
+            let q = quality(&ranges, mime);
+            if q > best.map_or(0, |(_, best)| best) {
+                best = Some((i, q));
+            }
+        }
+        best.map(|(i, _)| i)
+    }
+
+    fn respond(
+        &self,
+        accept: Option<&HeaderValue>,
+        value: &Pending,
+        mut resp: ServerResponse,
+    ) -> ServerResponse {
+        resp.headers_mut()
+            .append(header::VARY, HeaderValue::from_static("accept"));
+        let Some(i) = self.select(accept) else {
+            let supported = self.content_types().collect::<Vec<_>>().join(", ");
+            *resp.status_mut() = StatusCode::NOT_ACCEPTABLE;
+            resp.headers_mut().insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("text/plain; charset=utf-8"),
+            );
+            *resp.body_mut() = Body::from(format!("supported types: {supported}\n"));
+            return resp;
+        };
+        let (_, content_type, encode) = &self.encoders[i];
+        match encode(value.0.as_ref()) {
+            Ok(body) => {
+                resp.headers_mut()
+                    .insert(header::CONTENT_TYPE, content_type.clone());
+                *resp.body_mut() = Body::from(body);
+                resp
+            }
+            Err(err) => {
+                tracing::warn!("[VOLO] failed to encode response as {content_type:?}: {err}");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }
+    }
+}
+
+/// A media range of `Accept` with its quality value in thousandths.
+struct MediaRange {
+    mime: Mime,
+    q: u16,
+}
+
+/// Parses the media ranges, skipping the invalid ones.
+fn parse_accept(accept: &str) -> Vec<MediaRange> {
+    accept
+        .split(',')
+        .filter_map(|range| {
+            let mime = range.trim().parse::<Mime>().ok()?;
+            let q = match mime.get_param("q") {
+                Some(q) => parse_quality(q.as_str())?,
+                None => 1000,
+            };
+            Some(MediaRange { mime, q })
+        })
+        .collect()
+}
+
+/// Parses a quality value, which is from `0` to `1` with at most 3 decimal places.
+fn parse_quality(q: &str) -> Option<u16> {
+    let (int, frac) = q.split_once('.').unwrap_or((q, ""));
+    if frac.len() > 3 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let frac = format!("{frac:0<3}").parse::<u16>().unwrap_or(0);
+    match int {
+        "0" => Some(frac),
+        "1" if frac == 0 => Some(1000),
+        _ => None,
+    }
+}
+
+/// Returns the quality of the content type, decided by the most specific range matching it.
+fn quality(ranges: &[MediaRange], mime: &Mime) -> u16 {
+    ranges
+        .iter()
+        .filter_map(|range| specificity(&range.mime, mime).map(|s| (s, range.q)))
+        .max_by_key(|(s, _)| *s)
+        .map_or(0, |(_, q)| q)
+}
+
+/// Returns how specific the range is if it matches the content type, where the parameters of
+/// the range except `q` must all be present in the content type.
+fn specificity(range: &Mime, mime: &Mime) -> Option<usize> {
+    if range.type_() == mime::STAR {
+        return (range.subtype() == mime::STAR).then_some(0);
+    }
+    if range.type_() != mime.type_() {
+        return None;
+    }
+    if range.subtype() == mime::STAR {
+        return Some(1);
+    }
+    if range.subtype() != mime.subtype() {
+        return None;
+    }
+    let mut params = 0;
+    for (name, value) in range.params().filter(|(name, _)| *name != "q") {
+        if mime.get_param(name)? != value {
+            return None;
+        }
+        params += 1;
+    }
+    Some(2 + params)
+}
+
+/// Encodes the [`Negotiate`] response by the `Accept` of the request and the [`Encoders`] in the
+/// context, or the default ones.
+pub(crate) fn finalize(
+    cx: &ServerContext,
+    accept: Option<&HeaderValue>,
+    mut resp: ServerResponse,
+) -> ServerResponse {
+    let Some(value) = resp.extensions_mut().remove::<Pending>() else {
+        return resp;
+    };
+    static DEFAULT: OnceLock<Encoders> = OnceLock::new();
+    let encoders = cx
+        .extensions()
+        .get::<Encoders>()
+        .unwrap_or_else(|| DEFAULT.get_or_init(Encoders::new));
+    encoders.respond(accept, &value, resp)
+}
+
+#[cfg(all(test, feature = "__json", feature = "form"))]
+mod tests {
+    use super::*;
+    use crate::{body::BodyConversion, server::test_helpers::empty_cx};
+
+    #[derive(Serialize)]
+    struct User {
+        name: &'static str,
+        age: u32,
+    }
+
+    async fn negotiate(
+        cx: &ServerContext,
+        accept: Option<&'static str>,
+    ) -> (StatusCode, String, String) {
+        let resp = Negotiate(User {
+            name: "volo",
+            age: 3,
+        })
+        .into_response();
+        let resp = finalize(cx, accept.map(HeaderValue::from_static).as_ref(), resp);
+        let status = resp.status();
+        let content_type = resp
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .map(|v| v.to_str().unwrap().to_owned())
+            .unwrap_or_default();
+        (
+            status,
+            content_type,
+            resp.into_body().into_string().await.unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn accept_any() {
+        let cx = empty_cx();
+        for accept in [None, Some("*/*"), Some("")] {
+            let (status, content_type, body) = negotiate(&cx, accept).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(content_type, "application/json");
+            assert_eq!(body, r#"{"name":"volo","age":3}"#);
+        }
+    }
+
+    #[tokio::test]
+    async fn quality_values() {
+        let cx = empty_cx();
+        let (_, content_type, body) = negotiate(
+            &cx,
+            Some("application/json;q=0.5, application/x-www-form-urlencoded;q=0.9"),
+        )
+        .await;
+        assert_eq!(content_type, "application/x-www-form-urlencoded");
+        assert_eq!(body, "name=volo&age=3");
+
+        // the most specific range decides
+        let (_, content_type, _) =
+            negotiate(&cx, Some("application/*;q=0.2, */*, application/json;q=0")).await;
+        assert_eq!(content_type, "application/x-www-form-urlencoded");
+
+        // the preference of the server breaks the tie
+        let (_, content_type, _) = negotiate(&cx, Some("text/html, application/*;q=0.8")).await;
+        assert_eq!(content_type, "application/json");
+
+        // parameters must match
+        let (status, _, _) = negotiate(&cx, Some("application/json;version=2")).await;
+        assert_eq!(status, StatusCode::NOT_ACCEPTABLE);
+    }
+
+    #[tokio::test]
+    async fn not_acceptable() {
+        let cx = empty_cx();
+        let (status, content_type, body) = negotiate(&cx, Some("text/html, */*;q=0")).await;
+        assert_eq!(status, StatusCode::NOT_ACCEPTABLE);
+        assert_eq!(content_type, "text/plain; charset=utf-8");
+        assert_eq!(
+            body,
+            "supported types: application/json, application/x-www-form-urlencoded\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn custom_encoder() {
+        let mut cx = empty_cx();
+        cx.extensions_mut().insert(
+            Encoders::empty().encoder("text/plain; charset=utf-8", |value| {
+                Ok(crate::json::serialize(&value)?)
+            }),
+        );
+        let (status, content_type, _) = negotiate(&cx, Some("text/plain; charset=utf-8")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(content_type, "text/plain; charset=utf-8");
+
+        let (status, _, body) = negotiate(&cx, Some("application/json")).await;
+        assert_eq!(status, StatusCode::NOT_ACCEPTABLE);
+        assert_eq!(body, "supported types: text/plain; charset=utf-8\n");
+    }
+
+    #[test]
+    fn quality_value() {
+        assert_eq!(parse_quality("1"), Some(1000));
+        assert_eq!(parse_quality("1.000"), Some(1000));
+        assert_eq!(parse_quality("0.5"), Some(500));
+        assert_eq!(parse_quality("0.125"), Some(125));
+        assert_eq!(parse_quality("0"), Some(0));
+        assert_eq!(parse_quality("1.5"), None);
+        assert_eq!(parse_quality("0.1234"), None);
+        assert_eq!(parse_quality("x"), None);
+    }
+}
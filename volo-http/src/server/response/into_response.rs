@@ -14,6 +14,12 @@ pub trait TryIntoResponseHeaders {
     fn try_into_response_headers(self) -> Result<HeaderMap, Self::Error>;
 }
 
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` can't be converted into a response",
+    label = "not `IntoResponse`",
+    note = "the output of a handler must implement `IntoResponse`, e.g. `String`, `StatusCode`, \
+            `Json<T>`, or a tuple or a `Result` of them"
+)]
 pub trait IntoResponse {
     fn into_response(self) -> ServerResponse;
 }
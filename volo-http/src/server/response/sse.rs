@@ -14,7 +14,9 @@ use pin_project::pin_project;
 use tokio::time::{Instant, Sleep};
 
 use super::IntoResponse;
-use crate::{body::Body, error::BoxError, response::ServerResponse};
+use crate::{
+    body::Body, error::BoxError, response::ServerResponse, server::shutdown::ShutdownSignal,
+};
 
 /// Response of [SSE][sse] (Server-Sent Events), inclusing a stream with SSE [`Event`]s.
 ///
@@ -22,6 +24,7 @@ use crate::{body::Body, error::BoxError, response::ServerResponse};
 pub struct Sse<S> {
     stream: S,
     keep_alive: Option<KeepAlive>,
+    shutdown: Option<(ShutdownSignal, Event)>,
 }
 
 impl<S> Sse<S> {
@@ -30,6 +33,7 @@ impl<S> Sse<S> {
         Self {
             stream,
             keep_alive: None,
+            shutdown: None,
         }
     }
 
@@ -38,6 +42,13 @@ impl<S> Sse<S> {
         self.keep_alive = Some(keep_alive);
         self
     }
+
+    /// Send the final [`Event`] and end the stream when the server begins shutting down, e.g. an
+    /// event named `shutdown`, so that the clients can reconnect to another server.
+    pub fn shutdown(mut self, signal: ShutdownSignal, event: Event) -> Self {
+        self.shutdown = Some((signal, event));
+        self
+    }
 }
 
 impl<S, E> IntoResponse for Sse<S>
@@ -55,6 +66,11 @@ where
             .body(Body::from_body(SseBody {
                 stream: self.stream,
                 keep_alive: self.keep_alive.map(KeepAliveStream::new),
+                shutdown: self.shutdown.map(|(signal, event)| SseShutdown {
+                    notified: Box::pin(signal.into_notified()),
+                    event: event.finalize(),
+                }),
+                finished: false,
             }))
             .expect("infallible")
     }
@@ -66,6 +82,13 @@ struct SseBody<S> {
     stream: S,
     #[pin]
     keep_alive: Option<KeepAliveStream>,
+    shutdown: Option<SseShutdown>,
+    finished: bool,
+}
+
+struct SseShutdown {
+    notified: Pin<Box<dyn Future<Output = ()> + Send + Sync>>,
+    event: Bytes,
 }
 
 impl<S, E> http_body::Body for SseBody<S>
@@ -80,6 +103,17 @@ where
         cx: &mut Context<'_>,
     ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
         let this = self.project();
+        if *this.finished {
+            return Poll::Ready(None);
+        }
+        // The server is shutting down, send the final event and end the stream
+        if let Some(shutdown) = this.shutdown {
+            if shutdown.notified.as_mut().poll(cx).is_ready() {
+                *this.finished = true;
+                let event = std::mem::take(&mut shutdown.event);
+                return Poll::Ready(Some(Ok(Frame::data(event))));
+            }
+        }
         // Firstly, we should poll SSE stream
         match this.stream.poll_next(cx) {
             Poll::Pending => {
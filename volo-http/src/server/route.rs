@@ -15,13 +15,13 @@ use std::{
     marker::PhantomData, str::FromStr,
 };
 
-use http::{Method, StatusCode, Uri};
+use http::{header, HeaderValue, Method, StatusCode, Uri};
 use hyper::body::Incoming;
 use motore::{layer::Layer, service::Service, ServiceExt};
 use paste::paste;
 
 use super::{handler::Handler, IntoResponse};
-use crate::{context::ServerContext, request::ServerRequest, response::ServerResponse};
+use crate::{body::Body, context::ServerContext, request::ServerRequest, response::ServerResponse};
 
 /// The route service used for [`Router`].
 pub type Route<B = Incoming, E = Infallible> =
@@ -183,10 +183,39 @@ where
     ///
     /// For more usage methods, please refer to:
     /// [`matchit`](https://docs.rs/matchit/0.8.0/matchit/).
+    ///
+    /// ## Register a Path More Than Once
+    ///
+    /// If the path has been registered, the given [`MethodRouter`] will be merged into the
+    /// existing one, and the router will panic if the same method is registered twice.
+    ///
+    /// ```no_run
+    /// use volo_http::server::route::{get, post, Router};
+    ///
+    /// async fn index() -> &'static str {
+    ///     "Hello, World"
+    /// }
+    ///
+    /// let router: Router = Router::new().route("/", get(index)).route("/", post(index));
+    /// ```
     pub fn route<S>(mut self, uri: S, method_router: MethodRouter<B, E>) -> Self
     where
         S: AsRef<str>,
     {
+        if let Some(route_id) = self.matcher.matches.get(uri.as_ref()).copied() {
+            match self.routes.remove(&route_id) {
+                Some(Endpoint::MethodRouter(mr)) => {
+                    self.routes
+                        .insert(route_id, Endpoint::MethodRouter(mr.merge(method_router)));
+                    return self;
+                }
+                _ => panic!(
+                    "Insert routing rule failed: {}",
+                    MatcherError::UriConflict(uri.as_ref().to_owned())
+                ),
+            }
+        }
+
         let route_id = self
             .matcher
             .insert(uri.as_ref())
@@ -328,9 +357,12 @@ where
 
     /// Merge another router to self.
     ///
+    /// If the two routers have routes with the same path, their [`MethodRouter`]s will be merged.
+    ///
     /// # Panics
     ///
-    /// - Panics if the two router have routes with the same path.
+    /// - Panics if the two router have routes with the same path and the same method.
+    /// - Panics if the two router have nested routers or services with the same path.
     ///
     /// # Examples
     ///
@@ -373,9 +405,22 @@ where
         } = other;
 
         for (path, route_id) in matcher.matches.drain() {
-            self.matcher
-                .insert_with_id(path, route_id)
-                .expect("Insert routing rule failed during merging router");
+            let Some(self_route_id) = self.matcher.matches.get(&path).copied() else {
+                self.matcher
+                    .insert_with_id(path, route_id)
+                    .expect("Insert routing rule failed during merging router");
+                continue;
+            };
+            match (self.routes.remove(&self_route_id), routes.remove(&route_id)) {
+                (Some(Endpoint::MethodRouter(mr)), Some(Endpoint::MethodRouter(other_mr))) => {
+                    self.routes
+                        .insert(self_route_id, Endpoint::MethodRouter(mr.merge(other_mr)));
+                }
+                _ => panic!(
+                    "Insert routing rule failed during merging router: {}",
+                    MatcherError::UriConflict(path)
+                ),
+            }
         }
         for (route_id, method_router) in routes.drain() {
             if self.routes.insert(route_id, method_router).is_some() {
@@ -612,6 +657,7 @@ pub struct MethodRouter<B = Incoming, E = Infallible> {
     connect: MethodEndpoint<B, E>,
     patch: MethodEndpoint<B, E>,
     fallback: Fallback<B, E>,
+    is_default_fallback: bool,
 }
 
 impl<B, E> Service<ServerContext, ServerRequest<B>> for MethodRouter<B, E>
@@ -624,7 +670,7 @@ where
     async fn call(
         &self,
         cx: &mut ServerContext,
-        req: ServerRequest<B>,
+        mut req: ServerRequest<B>,
     ) -> Result<Self::Response, Self::Error> {
         let handler = match *req.method() {
             Method::OPTIONS => Some(&self.options),
@@ -639,10 +685,26 @@ where
             _ => None,
         };
 
-        match handler {
-            Some(MethodEndpoint::Route(route)) => route.call(cx, req).await,
-            _ => self.fallback.call(cx, req).await,
+        if let Some(MethodEndpoint::Route(route)) = handler {
+            return route.call(cx, req).await;
+        }
+
+        // `HEAD` falls back to the `GET` handler with the body stripped.
+        if req.method() == Method::HEAD {
+            if let MethodEndpoint::Route(route) = &self.get {
+                let resp = route.call(cx, req).await?;
+                return Ok(resp.map(|_| Body::empty()));
+            }
+        }
+
+        // The default fallback needs the `Allow` header for `OPTIONS` and "405 Method Not
+        // Allowed".
+        if self.is_default_fallback {
+            req.extensions_mut()
+                .insert(AllowedMethods(self.allow_header()));
         }
+
+        self.fallback.call(cx, req).await
     }
 }
 
@@ -672,7 +734,82 @@ where
             trace: MethodEndpoint::None,
             connect: MethodEndpoint::None,
             patch: MethodEndpoint::None,
-            fallback: Fallback::from_status_code(StatusCode::METHOD_NOT_ALLOWED),
+            fallback: Fallback::from_service(RouteForMethodNotAllowed::new()),
+            is_default_fallback: true,
+        }
+    }
+
+    /// Build the `Allow` header by the registered methods.
+    ///
+    /// `HEAD` is allowed if `GET` is registered, and `OPTIONS` is always allowed because it will
+    /// be responded by the default fallback.
+    fn allow_header(&self) -> HeaderValue {
+        let mut methods = Vec::with_capacity(9);
+        for (method, endpoint) in [
+            ("GET", &self.get),
+            ("HEAD", &self.head),
+            ("POST", &self.post),
+            ("PUT", &self.put),
+            ("DELETE", &self.delete),
+            ("CONNECT", &self.connect),
+            ("OPTIONS", &self.options),
+            ("TRACE", &self.trace),
+            ("PATCH", &self.patch),
+        ] {
+            let allowed = match method {
+                "HEAD" => endpoint.is_route() || self.get.is_route(),
+                "OPTIONS" => true,
+                _ => endpoint.is_route(),
+            };
+            if allowed {
+                methods.push(method);
+            }
+        }
+        // SAFETY: The method names are all valid header value.
+        HeaderValue::from_str(&methods.join(", ")).unwrap()
+    }
+
+    /// Merge another method router into self.
+    ///
+    /// # Panics
+    ///
+    /// - Panics if the two method routers have routes with the same method.
+    /// - Panics if the two method routers both have customized fallbacks.
+    fn merge(self, other: Self) -> Self {
+        fn merge_endpoint<B, E>(
+            method: &str,
+            this: MethodEndpoint<B, E>,
+            other: MethodEndpoint<B, E>,
+        ) -> MethodEndpoint<B, E> {
+            match (this, other) {
+                (MethodEndpoint::Route(_), MethodEndpoint::Route(_)) => {
+                    panic!("Merge `MethodRouter` failed because both have routes for `{method}`")
+                }
+                (MethodEndpoint::None, endpoint) | (endpoint, MethodEndpoint::None) => endpoint,
+            }
+        }
+
+        let (fallback, is_default_fallback) =
+            match (self.is_default_fallback, other.is_default_fallback) {
+                (_, true) => (self.fallback, self.is_default_fallback),
+                (true, false) => (other.fallback, false),
+                (false, false) => {
+                    panic!("Merge `MethodRouter` failed because both have customized `fallback`")
+                }
+            };
+
+        Self {
+            options: merge_endpoint("OPTIONS", self.options, other.options),
+            get: merge_endpoint("GET", self.get, other.get),
+            post: merge_endpoint("POST", self.post, other.post),
+            put: merge_endpoint("PUT", self.put, other.put),
+            delete: merge_endpoint("DELETE", self.delete, other.delete),
+            head: merge_endpoint("HEAD", self.head, other.head),
+            trace: merge_endpoint("TRACE", self.trace, other.trace),
+            connect: merge_endpoint("CONNECT", self.connect, other.connect),
+            patch: merge_endpoint("PATCH", self.patch, other.patch),
+            fallback,
+            is_default_fallback,
         }
     }
 
@@ -697,6 +834,7 @@ where
             connect,
             patch,
             fallback,
+            is_default_fallback,
         } = self;
 
         let layer_fn = move |route: Route<B, E>| {
@@ -730,6 +868,7 @@ where
             connect,
             patch,
             fallback,
+            is_default_fallback,
         }
     }
 }
@@ -784,13 +923,15 @@ where
     /// If there is no method that the route can handle, method router will call the fallback
     /// handler.
     ///
-    /// Default is returning "405 Method Not Allowed".
+    /// Default is returning "405 Method Not Allowed" with the `Allow` header, or "204 No Content"
+    /// with the `Allow` header for `OPTIONS` requests.
     pub fn fallback<H, T>(mut self, handler: H) -> Self
     where
         for<'a> H: Handler<T, B, E> + Clone + Send + Sync + 'a,
         T: 'static,
     {
         self.fallback = Fallback::from_handler(handler);
+        self.is_default_fallback = false;
         self
     }
 
@@ -799,13 +940,15 @@ where
     /// If there is no method that the route can handle, method router will call the fallback
     /// service.
     ///
-    /// Default is returning "405 Method Not Allowed".
+    /// Default is returning "405 Method Not Allowed" with the `Allow` header, or "204 No Content"
+    /// with the `Allow` header for `OPTIONS` requests.
     pub fn fallback_service<S>(mut self, service: S) -> Self
     where
         for<'a> S: Service<ServerContext, ServerRequest<B>, Error = E> + Send + Sync + 'a,
         S::Response: IntoResponse,
     {
         self.fallback = Fallback::from_service(service);
+        self.is_default_fallback = false;
         self
     }
 }
@@ -861,6 +1004,7 @@ where
 {
     MethodRouter {
         fallback: Fallback::from_handler(handler),
+        is_default_fallback: false,
         ..Default::default()
     }
 }
@@ -875,6 +1019,7 @@ where
 {
     MethodRouter {
         fallback: Fallback::from_service(service),
+        is_default_fallback: false,
         ..Default::default()
     }
 }
@@ -909,6 +1054,10 @@ where
         ))
     }
 
+    fn is_route(&self) -> bool {
+        matches!(self, Self::Route(_))
+    }
+
     fn map<F, B2, E2>(self, f: F) -> MethodEndpoint<B2, E2>
     where
         F: FnOnce(Route<B, E>) -> Route<B2, E2> + Clone + 'static,
@@ -1076,12 +1225,57 @@ where
     }
 }
 
+/// The `Allow` header computed by [`MethodRouter`] for its default fallback.
+#[derive(Clone)]
+struct AllowedMethods(HeaderValue);
+
+/// The default fallback of [`MethodRouter`].
+///
+/// It responds "204 No Content" for `OPTIONS` requests and "405 Method Not Allowed" for others,
+/// both with the `Allow` header.
+struct RouteForMethodNotAllowed<B, E> {
+    _marker: PhantomData<fn(B, E)>,
+}
+
+impl<B, E> RouteForMethodNotAllowed<B, E> {
+    fn new() -> Self {
+        Self {
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<B, E> Service<ServerContext, ServerRequest<B>> for RouteForMethodNotAllowed<B, E>
+where
+    B: Send,
+{
+    type Response = ServerResponse;
+    type Error = E;
+
+    async fn call(
+        &self,
+        _: &mut ServerContext,
+        req: ServerRequest<B>,
+    ) -> Result<Self::Response, Self::Error> {
+        let status = if req.method() == Method::OPTIONS {
+            StatusCode::NO_CONTENT
+        } else {
+            StatusCode::METHOD_NOT_ALLOWED
+        };
+        let mut resp = status.into_response();
+        if let Some(AllowedMethods(allow)) = req.extensions().get::<AllowedMethods>() {
+            resp.headers_mut().insert(header::ALLOW, allow.clone());
+        }
+        Ok(resp)
+    }
+}
+
 #[cfg(test)]
 mod route_tests {
     use faststr::FastStr;
-    use http::{method::Method, status::StatusCode, uri::Uri};
+    use http::{header, method::Method, status::StatusCode, uri::Uri};
 
-    use super::{any, get, head, options, MethodRouter};
+    use super::{any, get, head, options, post, put, MethodRouter};
     use crate::{
         body::{Body, BodyConversion},
         server::{param::PathParamsVec, test_helpers::TestServer},
@@ -1122,8 +1316,15 @@ mod route_tests {
             }
         }
 
-        test_all_method(get(always_ok), |m| m == Method::GET).await;
-        test_all_method(head(always_ok), |m| m == Method::HEAD).await;
+        // `HEAD` falls back to `GET`, and `OPTIONS` is responded by the default fallback.
+        test_all_method(get(always_ok), |m| {
+            m == Method::GET || m == Method::HEAD || m == Method::OPTIONS
+        })
+        .await;
+        test_all_method(head(always_ok), |m| {
+            m == Method::HEAD || m == Method::OPTIONS
+        })
+        .await;
         test_all_method(any(always_ok), |_| true).await;
     }
 
@@ -1152,7 +1353,10 @@ mod route_tests {
             }
         }
 
-        test_all_method(get(always_ok).fallback(teapot), |m| m != Method::GET).await;
+        test_all_method(get(always_ok).fallback(teapot), |m| {
+            m != Method::GET && m != Method::HEAD
+        })
+        .await;
         test_all_method(options(always_ok).fallback(teapot), |m| {
             m != Method::OPTIONS
         })
@@ -1314,4 +1518,103 @@ mod route_tests {
             "/catch/514/1919/810\n114\n514/1919/810"
         );
     }
+
+    #[tokio::test]
+    async fn method_not_allowed() {
+        let router: Router<Option<Body>> = Router::new()
+            .route("/", get(always_ok))
+            .merge(Router::new().route("/", post(always_ok)))
+            .nest(
+                "/nest",
+                Router::new()
+                    .route("/foo", get(always_ok))
+                    .merge(Router::new().route("/foo", put(always_ok))),
+            )
+            .route("/options", get(always_ok).options(teapot))
+            .route("/fallback", get(always_ok).fallback(teapot));
+        let server = Server::new(router).into_test_server();
+
+        assert_eq!(
+            server.call_route(Method::POST, "/", None).await.status(),
+            StatusCode::OK
+        );
+        let resp = server.call_route(Method::DELETE, "/", None).await;
+        assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(
+            resp.headers().get(header::ALLOW).unwrap(),
+            "GET, HEAD, POST, OPTIONS"
+        );
+        let resp = server.call_route(Method::OPTIONS, "/", None).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            resp.headers().get(header::ALLOW).unwrap(),
+            "GET, HEAD, POST, OPTIONS"
+        );
+
+        assert_eq!(
+            server
+                .call_route(Method::PUT, "/nest/foo", None)
+                .await
+                .status(),
+            StatusCode::OK
+        );
+        let resp = server.call_route(Method::PATCH, "/nest/foo", None).await;
+        assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(
+            resp.headers().get(header::ALLOW).unwrap(),
+            "GET, HEAD, PUT, OPTIONS"
+        );
+
+        // Explicit `OPTIONS` handler and customized fallback are not affected.
+        assert_eq!(
+            server
+                .call_route(Method::OPTIONS, "/options", None)
+                .await
+                .status(),
+            StatusCode::IM_A_TEAPOT
+        );
+        let resp = server.call_route(Method::POST, "/fallback", None).await;
+        assert_eq!(resp.status(), StatusCode::IM_A_TEAPOT);
+        assert!(resp.headers().get(header::ALLOW).is_none());
+
+        // Unknown path is still "404 Not Found".
+        assert_eq!(
+            server
+                .call_route(Method::GET, "/unknown", None)
+                .await
+                .status(),
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn head_fallback_to_get() {
+        async fn hello() -> &'static str {
+            "Hello, World"
+        }
+
+        let router: Router<Option<Body>> = Router::new()
+            .route("/get", get(hello))
+            .route("/head", get(hello).head(teapot));
+        let server = Server::new(router).into_test_server();
+
+        let resp = server.call_route(Method::HEAD, "/get", None).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp.into_string().await.unwrap().is_empty());
+        assert_eq!(
+            server
+                .call_route(Method::HEAD, "/head", None)
+                .await
+                .status(),
+            StatusCode::IM_A_TEAPOT
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn merge_same_method() {
+        let _: Router<Option<Body>> = Router::new()
+            .route("/", get(always_ok))
+            .merge(Router::new().route("/", get(always_ok)));
+    }
 }
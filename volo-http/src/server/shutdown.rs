@@ -0,0 +1,314 @@
+//! Graceful shutdown for the long-lived responses, e.g. SSE and websocket.
+//!
+//! When the server begins draining, the [`ShutdownSignal`] will be notified, so the handlers can
+//! send a final message and end the responses cleanly. The server waits for the connections to be
+//! closed until the shutdown timeout, and then force closes the remaining ones.
+//!
+//! # Examples
+//!
+//! ```ignore
+//! use std::convert::Infallible;
+//!
+//! use futures::Stream;
+//! use volo_http::server::{
+//!     response::sse::{Event, Sse},
+//!     shutdown::ShutdownSignal,
+//! };
+//!
+//! async fn events(shutdown: ShutdownSignal) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+//!     Sse::new(futures::stream::pending())
+//!         .shutdown(shutdown, Event::new().event("shutdown"))
+//! }
+//! ```
+
+use std::{
+    convert::Infallible,
+    fmt,
+    future::Future,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use http::request::Parts;
+use tokio::{
+    sync::{futures::Notified, watch, Notify},
+    time::Instant,
+};
+use volo::context::Context;
+
+use super::extract::FromContext;
+use crate::context::ServerContext;
+
+/// The interval of checking whether all the connections are closed.
+const DRAIN_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A handle notified when the server begins draining.
+///
+/// It can be extracted in handlers, and a signal extracted outside a server, e.g. in tests, will
+/// never be notified.
+#[derive(Clone, Debug)]
+pub struct ShutdownSignal {
+    rx: watch::Receiver<bool>,
+}
+
+impl Default for ShutdownSignal {
+    /// Creates a signal that will never be notified.
+    fn default() -> Self {
+        let (_, rx) = watch::channel(false);
+        Self { rx }
+    }
+}
+
+impl ShutdownSignal {
+    /// Returns whether the server has begun draining.
+    pub fn is_shutdown(&self) -> bool {
+        *self.rx.borrow()
+    }
+
+    /// Waits until the server begins draining.
+    pub async fn notified(&self) {
+        Self::wait(self.rx.clone()).await
+    }
+
+    pub(crate) fn into_notified(self) -> impl Future<Output = ()> + Send + Sync + 'static {
+        Self::wait(self.rx)
+    }
+
+    async fn wait(mut rx: watch::Receiver<bool>) {
+        while !*rx.borrow_and_update() {
+            if rx.changed().await.is_err() {
+                // the sender has been dropped without notifying
+                futures::future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+impl FromContext for ShutdownSignal {
+    type Rejection = Infallible;
+
+    async fn from_context(
+        cx: &mut ServerContext,
+        _parts: &mut Parts,
+    ) -> Result<Self, Self::Rejection> {
+        Ok(cx
+            .extensions()
+            .get::<ShutdownSignal>()
+            .cloned()
+            .unwrap_or_default())
+    }
+}
+
+/// The states of the graceful shutdown shared by the server and the connections.
+pub(crate) struct Shutdown {
+    signal: watch::Sender<bool>,
+    drain: Notify,
+    force: Notify,
+    active: AtomicUsize,
+    draining: AtomicUsize,
+    force_closed: AtomicUsize,
+}
+
+impl Shutdown {
+    pub(crate) fn new() -> Self {
+        Self {
+            signal: watch::channel(false).0,
+            drain: Notify::new(),
+            force: Notify::new(),
+            active: AtomicUsize::new(0),
+            draining: AtomicUsize::new(0),
+            force_closed: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn signal(&self) -> ShutdownSignal {
+        ShutdownSignal {
+            rx: self.signal.subscribe(),
+        }
+    }
+
+    /// Notifies the handlers holding the [`ShutdownSignal`].
+    pub(crate) fn notify_handlers(&self) {
+        self.signal.send_replace(true);
+    }
+
+    /// Notifies the connections to shutdown gracefully.
+    pub(crate) fn notify_connections(&self) {
+        self.drain.notify_waiters();
+    }
+
+    /// Notifies the connections to close immediately.
+    pub(crate) fn force_close(&self) {
+        self.force.notify_waiters();
+    }
+
+    pub(crate) fn drain_notified(&self) -> Notified<'_> {
+        self.drain.notified()
+    }
+
+    pub(crate) fn force_notified(&self) -> Notified<'_> {
+        self.force.notified()
+    }
+
+    pub(crate) fn active(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn conn_opened(&self) {
+        self.active.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn conn_closed(&self) {
+        self.active.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn conn_draining(&self) {
+        self.draining.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn conn_drained(&self) {
+        self.draining.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn conn_force_closed(&self) {
+        self.force_closed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Waits for all the connections to be closed until the timeout, returns `false` if there are
+    /// still connections left.
+    pub(crate) async fn wait_for_drain(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if self.active() == 0 {
+                return true;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            tracing::trace!("[VOLO] gracefully exiting, connections: {self}");
+            tokio::time::sleep(DRAIN_CHECK_INTERVAL.min(deadline - now)).await;
+        }
+    }
+}
+
+impl fmt::Display for Shutdown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "active: {}, draining: {}, force closed: {}",
+            self.active(),
+            self.draining.load(Ordering::Relaxed),
+            self.force_closed.load(Ordering::Relaxed),
+        )
+    }
+}
+
+#[cfg(test)]
+mod shutdown_tests {
+    use std::{convert::Infallible, sync::Arc, time::Duration};
+
+    use async_stream::stream;
+    use futures::Stream;
+    use http_body_util::BodyExt;
+
+    use super::{Shutdown, ShutdownSignal};
+    use crate::{
+        body::Body,
+        server::{
+            response::sse::{Event, Sse},
+            IntoResponse,
+        },
+    };
+
+    fn ticks() -> impl Stream<Item = Result<Event, Infallible>> + Send + Sync {
+        stream! {
+            loop {
+                yield Ok(Event::new().event("tick"));
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }
+    }
+
+    /// Simulates a connection serving the body, which works like the `serve_conn` of the server.
+    fn serve(shutdown: Arc<Shutdown>, mut body: Body) -> tokio::task::JoinHandle<String> {
+        shutdown.conn_opened();
+        tokio::spawn(async move {
+            let force = shutdown.force_notified();
+            tokio::pin!(force);
+
+            let mut received = String::new();
+            loop {
+                tokio::select! {
+                    frame = body.frame() => match frame {
+                        Some(Ok(frame)) => {
+                            let data = frame.into_data().unwrap();
+                            received.push_str(std::str::from_utf8(&data).unwrap());
+                        }
+                        _ => break,
+                    },
+                    _ = &mut force => {
+                        shutdown.conn_force_closed();
+                        break;
+                    }
+                }
+            }
+            shutdown.conn_closed();
+            received
+        })
+    }
+
+    #[tokio::test]
+    async fn sse_ends_within_grace_period() {
+        let shutdown = Arc::new(Shutdown::new());
+        let body = Sse::new(ticks())
+            .shutdown(shutdown.signal(), Event::new().event("shutdown"))
+            .into_response()
+            .into_body();
+        let conn = serve(shutdown.clone(), body);
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        shutdown.notify_handlers();
+        assert!(shutdown.wait_for_drain(Duration::from_secs(1)).await);
+
+        let received = conn.await.unwrap();
+        assert!(received.starts_with("event: tick\n\n"));
+        assert!(received.ends_with("event: shutdown\n\n"));
+        assert_eq!(
+            shutdown.to_string(),
+            "active: 0, draining: 0, force closed: 0"
+        );
+    }
+
+    #[tokio::test]
+    async fn sse_force_closed_after_grace_period() {
+        let shutdown = Arc::new(Shutdown::new());
+        // the handler ignores the signal
+        let body = Sse::new(ticks()).into_response().into_body();
+        let conn = serve(shutdown.clone(), body);
+
+        shutdown.notify_handlers();
+        assert!(!shutdown.wait_for_drain(Duration::from_millis(100)).await);
+        assert_eq!(shutdown.active(), 1);
+
+        shutdown.force_close();
+        assert!(shutdown.wait_for_drain(Duration::from_secs(1)).await);
+        let received = conn.await.unwrap();
+        assert!(!received.contains("shutdown"));
+        assert_eq!(
+            shutdown.to_string(),
+            "active: 0, draining: 0, force closed: 1"
+        );
+    }
+
+    #[tokio::test]
+    async fn signal_outside_server_never_notified() {
+        let signal = ShutdownSignal::default();
+        assert!(!signal.is_shutdown());
+        assert!(
+            tokio::time::timeout(Duration::from_millis(10), signal.notified())
+                .await
+                .is_err()
+        );
+    }
+}
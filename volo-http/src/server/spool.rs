@@ -0,0 +1,547 @@
+//! Receiving large request bodies by spooling them into a temporary file.
+//!
+//! The [`BodySpool`] extractor receives the whole body before the handler is called, and computes
+//! the digest of it during receiving. The body is kept in memory if it is not larger than
+//! [`SpoolConfig::memory_threshold`], and is spilled to an anonymous temporary file otherwise,
+//! which is created by `O_TMPFILE` on Linux where it is available, or unlinked right after being
+//! created on the other platforms. So the file is always removed once it is closed, including
+//! when the request is cancelled during uploading.
+//!
+//! The limits are configured by a [`SpoolConfig`] in the [`Extension`] layer, on the router or on
+//! the server.
+//!
+//! [`Extension`]: crate::extension::Extension
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use volo_http::{
+//!     extension::Extension,
+//!     server::{
+//!         route::{post, Router},
+//!         spool::{BodySpool, SpoolConfig},
+//!     },
+//! };
+//!
+//! async fn upload(body: BodySpool) -> String {
+//!     let digest = format!("{:x}", body.digest());
+//!     let mut reader = body.into_reader();
+//!     // ... process the body by the reader
+//!     digest
+//! }
+//!
+//! let router = Router::new()
+//!     .route("/upload", post(upload))
+//!     .layer(Extension(
+//!         SpoolConfig::new()
+//!             .max_size(5 << 30)
+//!             .dir("/data/spool"),
+//!     ));
+//! ```
+
+use std::{
+    error::Error,
+    fmt, io,
+    path::PathBuf,
+    pin::{pin, Pin},
+    task::{Context, Poll},
+};
+
+use bytes::{Buf, Bytes, BytesMut};
+use http::{header, request::Parts, StatusCode};
+use http_body::Body;
+use http_body_util::BodyExt;
+use sha2::{digest::Output, Digest, Sha256};
+use tokio::{
+    fs::File,
+    io::{AsyncRead, AsyncSeek, AsyncSeekExt, AsyncWriteExt, BufWriter, ReadBuf, SeekFrom},
+};
+use volo::{
+    context::Context as _,
+    error::{CodedError, ErrorCode},
+};
+
+use super::{extract::FromRequest, IntoResponse};
+use crate::{
+    context::ServerContext,
+    error::{
+        code,
+        server::{body_collection_error, ExtractBodyError},
+    },
+    response::ServerResponse,
+};
+
+const DEFAULT_MEMORY_THRESHOLD: usize = 1024 * 1024;
+const DEFAULT_MAX_SIZE: u64 = 8 * 1024 * 1024 * 1024;
+const WRITE_BUFFER_SIZE: usize = 256 * 1024;
+
+/// The config of [`BodySpool`].
+#[derive(Debug, Clone)]
+pub struct SpoolConfig {
+    memory_threshold: usize,
+    max_size: u64,
+    dir: Option<PathBuf>,
+}
+
+impl Default for SpoolConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SpoolConfig {
+    pub fn new() -> Self {
+        Self {
+            memory_threshold: DEFAULT_MEMORY_THRESHOLD,
+            max_size: DEFAULT_MAX_SIZE,
+            dir: None,
+        }
+    }
+
+    /// Set the max size of the body kept in memory, and the larger body is spilled to a
+    /// temporary file.
+    ///
+    /// Default is 1 MiB.
+    pub fn memory_threshold(mut self, threshold: usize) -> Self {
+        self.memory_threshold = threshold;
+        self
+    }
+
+    /// Set the max size of the body, and the larger body is rejected with
+    /// `413 Payload Too Large`.
+    ///
+    /// Default is 8 GiB.
+    pub fn max_size(mut self, size: u64) -> Self {
+        self.max_size = size;
+        self
+    }
+
+    /// Set the directory of the temporary files.
+    ///
+    /// Default is [`std::env::temp_dir`].
+    pub fn dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = Some(dir.into());
+        self
+    }
+}
+
+/// The error of spooling the body.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SpoolError {
+    /// The body is larger than [`SpoolConfig::max_size`].
+    TooLarge { limit: u64 },
+    /// Failed to create or to write the temporary file.
+    Io(io::Error),
+}
+
+impl fmt::Display for SpoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooLarge { limit } => write!(f, "body is larger than {limit} bytes"),
+            Self::Io(e) => write!(f, "failed to write the temporary file: {e}"),
+        }
+    }
+}
+
+impl Error for SpoolError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::TooLarge { .. } => None,
+            Self::Io(e) => Some(e),
+        }
+    }
+}
+
+impl CodedError for SpoolError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::TooLarge { .. } => code::BODY_TOO_LARGE,
+            Self::Io(_) => code::SPOOL_IO,
+        }
+    }
+}
+
+impl SpoolError {
+    pub fn to_status_code(&self) -> StatusCode {
+        match self {
+            Self::TooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl IntoResponse for SpoolError {
+    fn into_response(self) -> ServerResponse {
+        self.to_status_code().into_response()
+    }
+}
+
+impl From<io::Error> for SpoolError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<SpoolError> for ExtractBodyError {
+    fn from(value: SpoolError) -> Self {
+        Self::Spool(value)
+    }
+}
+
+/// The received body of the request, which is in memory or in a temporary file.
+#[derive(Debug)]
+pub enum SpoolData {
+    Memory(Bytes),
+    File(File),
+}
+
+/// The whole body of the request received by spooling, along with its digest.
+///
+/// The digest is SHA-256 by default, and can be any other [`Digest`], e.g.
+/// `BodySpool<sha2::Sha512>`.
+pub struct BodySpool<D: Digest = Sha256> {
+    data: SpoolData,
+    len: u64,
+    digest: Output<D>,
+}
+
+impl<D: Digest> fmt::Debug for BodySpool<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BodySpool")
+            .field("len", &self.len)
+            .field("spilled", &self.is_spilled())
+            .finish()
+    }
+}
+
+impl<D: Digest> BodySpool<D> {
+    /// Receives the whole body by the config.
+    pub async fn receive<B>(body: B, config: &SpoolConfig) -> Result<Self, ExtractBodyError>
+    where
+        B: Body + Send,
+        B::Data: Send,
+    {
+        let mut body = pin!(body);
+        let mut hasher = D::new();
+        let mut len = 0u64;
+        let mut memory = BytesMut::new();
+        let mut file: Option<BufWriter<File>> = None;
+
+        while let Some(frame) = body.as_mut().frame().await {
+            let frame = frame.map_err(|_| body_collection_error())?;
+            let Ok(mut data) = frame.into_data() else {
+                // the trailers are ignored
+                continue;
+            };
+            let chunk = data.copy_to_bytes(data.remaining());
+            len += chunk.len() as u64;
+            if len > config.max_size {
+                return Err(SpoolError::TooLarge {
+                    limit: config.max_size,
+                }
+                .into());
+            }
+            hasher.update(&chunk);
+
+            match &mut file {
+                Some(file) => file.write_all(&chunk).await.map_err(SpoolError::Io)?,
+                None if memory.len() + chunk.len() <= config.memory_threshold => {
+                    memory.extend_from_slice(&chunk)
+                }
+                None => {
+                    let mut spilled =
+                        BufWriter::with_capacity(WRITE_BUFFER_SIZE, tempfile(config).await?);
+                    spilled.write_all(&memory).await.map_err(SpoolError::Io)?;
+                    spilled.write_all(&chunk).await.map_err(SpoolError::Io)?;
+                    memory = BytesMut::new();
+                    file = Some(spilled);
+                }
+            }
+        }
+
+        let data = match file {
+            Some(mut file) => {
+                file.flush().await.map_err(SpoolError::Io)?;
+                let mut file = file.into_inner();
+                file.seek(SeekFrom::Start(0))
+                    .await
+                    .map_err(SpoolError::Io)?;
+                SpoolData::File(file)
+            }
+            None => SpoolData::Memory(memory.freeze()),
+        };
+
+        Ok(Self {
+            data,
+            len,
+            digest: hasher.finalize(),
+        })
+    }
+
+    /// The length of the body.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns `true` if the body is spilled to a temporary file.
+    pub fn is_spilled(&self) -> bool {
+        matches!(self.data, SpoolData::File(_))
+    }
+
+    /// The digest of the body.
+    pub fn digest(&self) -> &Output<D> {
+        &self.digest
+    }
+
+    /// Returns the body if it is kept in memory.
+    pub fn bytes(&self) -> Option<&Bytes> {
+        match &self.data {
+            SpoolData::Memory(bytes) => Some(bytes),
+            SpoolData::File(_) => None,
+        }
+    }
+
+    /// Consumes the [`BodySpool`], returning the body in memory or in the temporary file, whose
+    /// cursor is at the start.
+    pub fn into_data(self) -> SpoolData {
+        self.data
+    }
+
+    /// Consumes the [`BodySpool`], returning a seekable reader of the body.
+    pub fn into_reader(self) -> SpoolReader {
+        match self.data {
+            SpoolData::Memory(bytes) => SpoolReader::Memory(io::Cursor::new(bytes)),
+            SpoolData::File(file) => SpoolReader::File(file),
+        }
+    }
+}
+
+impl<B, D> FromRequest<B> for BodySpool<D>
+where
+    B: Body + Send,
+    B::Data: Send,
+    B::Error: Send,
+    D: Digest + Send,
+{
+    type Rejection = ExtractBodyError;
+
+    async fn from_request(
+        cx: &mut ServerContext,
+        parts: Parts,
+        body: B,
+    ) -> Result<Self, Self::Rejection> {
+        let config = cx
+            .extensions()
+            .get::<SpoolConfig>()
+            .cloned()
+            .unwrap_or_default();
+
+        if let Some(Ok(Ok(len))) = parts
+            .headers
+            .get(header::CONTENT_LENGTH)
+            .map(|v| v.to_str().map(|c| c.parse::<u64>()))
+        {
+            if len > config.max_size {
+                return Err(SpoolError::TooLarge {
+                    limit: config.max_size,
+                }
+                .into());
+            }
+        }
+
+        Self::receive(body, &config).await
+    }
+}
+
+/// A seekable reader of the [`BodySpool`].
+#[derive(Debug)]
+pub enum SpoolReader {
+    Memory(io::Cursor<Bytes>),
+    File(File),
+}
+
+impl AsyncRead for SpoolReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Memory(cursor) => Pin::new(cursor).poll_read(cx, buf),
+            Self::File(file) => Pin::new(file).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncSeek for SpoolReader {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        match self.get_mut() {
+            Self::Memory(cursor) => Pin::new(cursor).start_seek(position),
+            Self::File(file) => Pin::new(file).start_seek(position),
+        }
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        match self.get_mut() {
+            Self::Memory(cursor) => Pin::new(cursor).poll_complete(cx),
+            Self::File(file) => Pin::new(file).poll_complete(cx),
+        }
+    }
+}
+
+async fn tempfile(config: &SpoolConfig) -> Result<File, SpoolError> {
+    let dir = config.dir.clone().unwrap_or_else(std::env::temp_dir);
+    let file = tokio::task::spawn_blocking(move || tempfile::tempfile_in(dir))
+        .await
+        .map_err(|e| SpoolError::Io(io::Error::other(e)))??;
+    Ok(File::from_std(file))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, path::Path, time::Duration};
+
+    use bytes::Bytes;
+    use futures::{stream, StreamExt};
+    use http::{Method, StatusCode};
+    use http_body::Frame;
+    use http_body_util::StreamBody;
+    use sha2::{Digest, Sha256};
+    use tokio::io::{AsyncReadExt, AsyncSeekExt, SeekFrom};
+    use volo::context::Context;
+
+    use super::{BodySpool, SpoolConfig, SpoolError};
+    use crate::{
+        body::Body,
+        error::server::ExtractBodyError,
+        server::{extract::FromRequest, test_helpers::empty_cx, IntoResponse},
+    };
+
+    fn chunks(n: usize, size: usize) -> Vec<Bytes> {
+        (0..n).map(|i| Bytes::from(vec![i as u8; size])).collect()
+    }
+
+    fn body(chunks: Vec<Bytes>) -> Body {
+        Body::from_body(StreamBody::new(stream::iter(
+            chunks
+                .into_iter()
+                .map(|chunk| Ok::<_, Infallible>(Frame::data(chunk))),
+        )))
+    }
+
+    fn sha256(chunks: &[Bytes]) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        chunks.iter().for_each(|chunk| hasher.update(chunk));
+        hasher.finalize().to_vec()
+    }
+
+    fn files_in(dir: &Path) -> usize {
+        std::fs::read_dir(dir).unwrap().count()
+    }
+
+    async fn read_all(spool: BodySpool) -> Vec<u8> {
+        let mut buf = Vec::new();
+        spool.into_reader().read_to_end(&mut buf).await.unwrap();
+        buf
+    }
+
+    #[tokio::test]
+    async fn spill_boundary() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = SpoolConfig::new().memory_threshold(1024).dir(dir.path());
+
+        // exactly the threshold is kept in memory
+        let data = chunks(4, 256);
+        let spool = BodySpool::<Sha256>::receive(body(data.clone()), &config)
+            .await
+            .unwrap();
+        assert!(!spool.is_spilled());
+        assert_eq!(spool.len(), 1024);
+        assert_eq!(spool.digest().to_vec(), sha256(&data));
+        assert_eq!(spool.bytes().unwrap(), &data.concat());
+
+        // one more byte is spilled
+        let mut data = chunks(4, 256);
+        data.push(Bytes::from_static(b"x"));
+        let spool = BodySpool::<Sha256>::receive(body(data.clone()), &config)
+            .await
+            .unwrap();
+        assert!(spool.is_spilled());
+        assert!(spool.bytes().is_none());
+        assert_eq!(spool.len(), 1025);
+        assert_eq!(spool.digest().to_vec(), sha256(&data));
+        // the anonymous file has no name in the directory
+        assert_eq!(files_in(dir.path()), 0);
+        assert_eq!(read_all(spool).await, data.concat());
+    }
+
+    #[tokio::test]
+    async fn seek_spilled() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = SpoolConfig::new().memory_threshold(16).dir(dir.path());
+
+        let data = chunks(8, 100);
+        let spool = BodySpool::<Sha256>::receive(body(data.clone()), &config)
+            .await
+            .unwrap();
+        let mut reader = spool.into_reader();
+        reader.seek(SeekFrom::Start(350)).await.unwrap();
+        let mut buf = [0u8; 100];
+        reader.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf[..], &data.concat()[350..450]);
+    }
+
+    #[tokio::test]
+    async fn too_large() {
+        let config = SpoolConfig::new().max_size(1000);
+        let res = BodySpool::<Sha256>::receive(body(chunks(4, 300)), &config).await;
+        let e = match res {
+            Err(ExtractBodyError::Spool(e @ SpoolError::TooLarge { limit: 1000 })) => e,
+            res => panic!("unexpected result: {res:?}"),
+        };
+        assert_eq!(e.into_response().status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // rejected by the content length before receiving
+        let mut cx = empty_cx();
+        cx.extensions_mut().insert(config);
+        let req = http::Request::builder()
+            .method(Method::POST)
+            .header(http::header::CONTENT_LENGTH, "1001")
+            .body(body(Vec::new()))
+            .unwrap();
+        let (parts, body) = req.into_parts();
+        let res = BodySpool::<Sha256>::from_request(&mut cx, parts, body).await;
+        assert_eq!(
+            res.unwrap_err().into_response().status(),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+    }
+
+    #[tokio::test]
+    async fn cancel_cleanup() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = SpoolConfig::new().memory_threshold(16).dir(dir.path());
+
+        // the body stalls after spilling
+        let stalled = Body::from_body(StreamBody::new(
+            stream::iter(
+                chunks(4, 100)
+                    .into_iter()
+                    .map(|chunk| Ok::<_, Infallible>(Frame::data(chunk))),
+            )
+            .chain(stream::pending()),
+        ));
+        let res = tokio::time::timeout(
+            Duration::from_millis(100),
+            BodySpool::<Sha256>::receive(stalled, &config),
+        )
+        .await;
+        assert!(res.is_err());
+        assert_eq!(files_in(dir.path()), 0);
+    }
+}
@@ -0,0 +1,357 @@
+//! Exposing the gRPC services over HTTP/JSON by transcoding, as the `google.api.http` rules with
+//! `body: "*"`.
+//!
+//! [`GrpcTranscoding`] is the service of a route calling a method of a gRPC service, e.g. a
+//! [`Router`](volo_grpc::server::Router) or a service built by
+//! [`ServiceBuilder`](volo_grpc::server::ServiceBuilder) of a generated server. The JSON body of
+//! the request is the request message, which is sent to the gRPC service by the
+//! `application/grpc+json` codec, so it's decoded by the serde derives of the messages, i.e. the
+//! code must be generated `with_serde`, and the response message is responded as JSON in the
+//! same way. An empty body is the default message, e.g. for the `GET` routes.
+//!
+//! The headers of the request are sent as the metadata, and the metadata of the response is
+//! responded as the headers. The gRPC errors are responded with the HTTP status of their code,
+//! see [`http_status`], and the JSON of the status, e.g. `{"code":5,"message":"no such item"}`.
+//!
+//! Only the unary methods are supported, and the path and query parameters are not bound to the
+//! fields of the messages yet.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use volo_grpc::server::ServiceBuilder;
+//! use volo_http::server::{
+//!     route::{post_service, Router},
+//!     transcoding::GrpcTranscoding,
+//! };
+//!
+//! // option (google.api.http) = { post: "/v1/items/get", body: "*" };
+//! let items = ServiceBuilder::new(ItemServiceServer::new(S)).build();
+//! let router = Router::new().route(
+//!     "/v1/items/get",
+//!     post_service(GrpcTranscoding::new(items, "/item.ItemService/GetItem")),
+//! );
+//! ```
+
+use std::convert::Infallible;
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use faststr::FastStr;
+use http::{
+    header::{self, HeaderMap, HeaderValue},
+    Extensions, StatusCode,
+};
+use http_body::Body;
+use http_body_util::{BodyExt, Full};
+use motore::service::Service;
+use volo::context::Context;
+use volo_grpc::{body::BoxBody, metadata::MetadataMap, Code, Status};
+
+use super::{extract::FromRequest, IntoResponse};
+use crate::{context::ServerContext, request::ServerRequest, response::ServerResponse};
+
+/// The content type of the messages sent to the gRPC services.
+const GRPC_JSON: &str = "application/grpc+json";
+
+/// The length of the prefix of each gRPC message, i.e. the compressed flag and the length.
+const PREFIX_LEN: usize = 5;
+
+/// The service calling a unary method of a gRPC service with the JSON body of the request, see
+/// the [module docs](self).
+#[derive(Clone, Debug)]
+pub struct GrpcTranscoding<S> {
+    service: S,
+    path: FastStr,
+}
+
+impl<S> GrpcTranscoding<S> {
+    /// Creates the service calling the method of the path, e.g. `/item.ItemService/GetItem`.
+    pub fn new(service: S, path: impl Into<FastStr>) -> Self {
+        Self {
+            service,
+            path: path.into(),
+        }
+    }
+}
+
+impl<S, B> Service<ServerContext, ServerRequest<B>> for GrpcTranscoding<S>
+where
+    S: Service<
+            volo_grpc::context::ServerContext,
+            volo_grpc::Request<BoxBody>,
+            Response = volo_grpc::Response<volo_grpc::body::Body>,
+            Error = Status,
+        > + Send
+        + Sync,
+    B: Body + Send,
+    B::Data: Send,
+    B::Error: Send,
+{
+    type Response = ServerResponse;
+    type Error = Infallible;
+
+    async fn call(
+        &self,
+        cx: &mut ServerContext,
+        req: ServerRequest<B>,
+    ) -> Result<Self::Response, Self::Error> {
+        let (parts, body) = req.into_parts();
+        if !is_json(&parts.headers) {
+            return Ok(StatusCode::UNSUPPORTED_MEDIA_TYPE.into_response());
+        }
+        let mut headers = parts.headers.clone();
+        let message = match Bytes::from_request(cx, parts, body).await {
+            Ok(message) if message.is_empty() => Bytes::from_static(b"{}"),
+            Ok(message) => message,
+            Err(err) => return Ok(err.into_response()),
+        };
+
+        for name in [
+            header::HOST,
+            header::CONNECTION,
+            header::CONTENT_LENGTH,
+            header::TRANSFER_ENCODING,
+        ] {
+            headers.remove(name);
+        }
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(GRPC_JSON));
+        let body = Full::new(frame(&message))
+            .map_err(|never| match never {})
+            .boxed();
+        let req = volo_grpc::Request::from_parts(
+            MetadataMap::from_headers(headers),
+            Extensions::new(),
+            body,
+        );
+
+        let mut grpc_cx = volo_grpc::context::ServerContext::default();
+        grpc_cx.rpc_info.set_method(self.path.clone());
+        if let Some(addr) = cx.rpc_info().caller().address() {
+            grpc_cx.rpc_info_mut().caller_mut().set_address(addr);
+        }
+
+        match self.service.call(&mut grpc_cx, req).await {
+            Ok(resp) => Ok(respond(resp).await.unwrap_or_else(status_response)),
+            Err(status) => Ok(status_response(status)),
+        }
+    }
+}
+
+/// Returns whether the body of the request is JSON, which is also assumed without the content
+/// type.
+fn is_json(headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers.get(header::CONTENT_TYPE) else {
+        return true;
+    };
+    content_type
+        .to_str()
+        .ok()
+        .and_then(|value| value.parse::<mime::Mime>().ok())
+        .is_some_and(|mime| {
+            mime.essence_str() == mime::APPLICATION_JSON.essence_str()
+                || mime.suffix() == Some(mime::JSON)
+        })
+}
+
+fn frame(message: &[u8]) -> Bytes {
+    let mut buf = BytesMut::with_capacity(PREFIX_LEN + message.len());
+    // uncompressed
+    buf.put_u8(0);
+    buf.put_u32(message.len() as u32);
+    buf.put_slice(message);
+    buf.freeze()
+}
+
+/// Returns the only message in the body of a unary response.
+fn unframe(mut body: Bytes) -> Result<Bytes, Status> {
+    if body.len() < PREFIX_LEN {
+        return Err(Status::internal("the unary method responded no message"));
+    }
+    if body.get_u8() != 0 {
+        return Err(Status::internal("the response message is compressed"));
+    }
+    let len = body.get_u32() as usize;
+    if body.len() != len {
+        return Err(Status::internal(
+            "the unary method responded more or less than one message",
+        ));
+    }
+    Ok(body)
+}
+
+async fn respond(
+    resp: volo_grpc::Response<volo_grpc::body::Body>,
+) -> Result<ServerResponse, Status> {
+    let (metadata, _, body) = resp.into_parts();
+    let collected = body.collect().await?;
+    if let Some(status) = collected.trailers().and_then(Status::from_header_map) {
+        if status.code() != Code::Ok {
+            return Err(status);
+        }
+    }
+    let message = unframe(collected.to_bytes())?;
+
+    let mut resp = ServerResponse::new(message.into());
+    let headers = resp.headers_mut();
+    for (name, value) in metadata.into_headers().iter() {
+        if name == header::CONTENT_TYPE || name.as_str().starts_with("grpc-") {
+            continue;
+        }
+        headers.append(name.clone(), value.clone());
+    }
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    Ok(resp)
+}
+
+/// Responds the status with the HTTP status of its code, and the JSON of the code and the
+/// message.
+fn status_response(status: Status) -> ServerResponse {
+    let body = serde_json::json!({
+        "code": status.code() as i32,
+        "message": status.message(),
+    });
+    let mut resp = ServerResponse::new(body.to_string().into());
+    *resp.status_mut() = http_status(status.code());
+    resp.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    resp
+}
+
+/// Returns the HTTP status of the gRPC code, which is the same as the mapping of the
+/// `google.api.http` gateways.
+pub fn http_status(code: Code) -> StatusCode {
+    match code {
+        Code::Ok => StatusCode::OK,
+        // the client closed the request
+        Code::Cancelled => StatusCode::from_u16(499).unwrap_or(StatusCode::REQUEST_TIMEOUT),
+        Code::InvalidArgument | Code::FailedPrecondition | Code::OutOfRange => {
+            StatusCode::BAD_REQUEST
+        }
+        Code::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+        Code::NotFound => StatusCode::NOT_FOUND,
+        Code::AlreadyExists | Code::Aborted => StatusCode::CONFLICT,
+        Code::PermissionDenied => StatusCode::FORBIDDEN,
+        Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+        Code::ResourceExhausted => StatusCode::TOO_MANY_REQUESTS,
+        Code::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+        Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        Code::Unknown | Code::Internal | Code::DataLoss => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+#[cfg(test)]
+mod transcoding_tests {
+    use futures::stream;
+    use http::Method;
+    use http_body::Frame;
+
+    use super::*;
+    use crate::{
+        body::{Body, BodyConversion},
+        server::test_helpers::{empty_cx, simple_req},
+    };
+
+    const PATH: &str = "/echo.Echo/Unary";
+
+    /// Echoes the `message` of the request, or fails for `missing`.
+    #[derive(Clone)]
+    struct Echo;
+
+    impl Service<volo_grpc::context::ServerContext, volo_grpc::Request<BoxBody>> for Echo {
+        type Response = volo_grpc::Response<volo_grpc::body::Body>;
+        type Error = Status;
+
+        async fn call(
+            &self,
+            cx: &mut volo_grpc::context::ServerContext,
+            req: volo_grpc::Request<BoxBody>,
+        ) -> Result<Self::Response, Self::Error> {
+            assert_eq!(cx.rpc_info.method(), PATH);
+            assert_eq!(
+                req.metadata()
+                    .get("content-type")
+                    .unwrap()
+                    .to_str()
+                    .unwrap(),
+                GRPC_JSON
+            );
+            let request_id = req.metadata().get("x-request-id").cloned();
+
+            let body = req.into_inner().collect().await?.to_bytes();
+            let request: serde_json::Value = serde_json::from_slice(&unframe(body)?).unwrap();
+            let message = match request["message"].as_str() {
+                Some("missing") => return Err(Status::not_found("no such message")),
+                message => message.unwrap_or_default().to_owned(),
+            };
+
+            let response = serde_json::json!({ "message": message }).to_string();
+            let mut trailers = HeaderMap::new();
+            trailers.insert("grpc-status", HeaderValue::from_static("0"));
+            let frames = vec![
+                Ok(Frame::data(frame(response.as_bytes()))),
+                Ok(Frame::trailers(trailers)),
+            ];
+            let mut resp = volo_grpc::Response::new(volo_grpc::body::Body::new(Box::pin(
+                stream::iter(frames),
+            )));
+            if let Some(request_id) = request_id {
+                resp.metadata_mut().insert("x-request-id", request_id);
+            }
+            Ok(resp)
+        }
+    }
+
+    async fn call(req: ServerRequest<Body>) -> (StatusCode, HeaderMap, String) {
+        let resp = GrpcTranscoding::new(Echo, PATH)
+            .call(&mut empty_cx(), req)
+            .await
+            .unwrap();
+        let (parts, body) = resp.into_parts();
+        (
+            parts.status,
+            parts.headers,
+            body.into_string().await.unwrap(),
+        )
+    }
+
+    fn post(content_type: &str, body: &str) -> ServerRequest<Body> {
+        let mut req = simple_req(Method::POST, "/v1/echo", Body::from(body.to_owned()));
+        req.headers_mut()
+            .insert(header::CONTENT_TYPE, content_type.parse().unwrap());
+        req
+    }
+
+    #[tokio::test]
+    async fn unary() {
+        let mut req = post("application/json", r#"{"message":"hello"}"#);
+        req.headers_mut()
+            .insert("x-request-id", HeaderValue::from_static("1"));
+        let (status, headers, body) = call(req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers[header::CONTENT_TYPE], "application/json");
+        assert_eq!(headers["x-request-id"], "1");
+        assert_eq!(body, r#"{"message":"hello"}"#);
+
+        // the empty body is the default message
+        let (status, _, body) = call(simple_req(Method::GET, "/v1/echo", Body::empty())).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#"{"message":""}"#);
+    }
+
+    #[tokio::test]
+    async fn status() {
+        let (status, headers, body) =
+            call(post("application/json", r#"{"message":"missing"}"#)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(headers[header::CONTENT_TYPE], "application/json");
+        assert_eq!(body, r#"{"code":5,"message":"no such message"}"#);
+
+        let (status, _, _) = call(post("text/plain", "hello")).await;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+}
@@ -0,0 +1,392 @@
+//! Serving the immutable assets from the memory without copying.
+//!
+//! The assets in an [`AssetCache`] are kept as shared [`Bytes`] with their `ETag`s computed when
+//! they are inserted, so each response only clones a reference to the same buffer, and the
+//! revalidations by `If-None-Match` are answered with `304 Not Modified` directly.
+//!
+//! # Examples
+//!
+//! ```ignore
+//! use volo_http::server::{route::Router, utils::AssetCache};
+//!
+//! let cache = AssetCache::new();
+//! cache.insert_dir("", "./static")?;
+//!
+//! let router: Router = Router::new().nest_service("/static/", cache.serve());
+//! ```
+//!
+//! With the `mmap` feature, the files can be memory-mapped instead of read into the memory by
+//! [`AssetCache::insert_file_mmap`], see [`Body::from_mmap`].
+//!
+//! # Sendfile
+//!
+//! The bodies are not sent by `sendfile(2)`, since hyper writes the frames of a body from the
+//! buffers in the userspace, and the HTTP/2 framing and TLS need the data in the userspace
+//! anyway. Serving from the shared buffers or the mapped pages avoids all the copies except the
+//! one into the socket buffer by the kernel.
+
+#![deny(missing_docs)]
+
+use std::{fmt, fs, io, marker::PhantomData, path::Path, sync::Arc};
+
+use ahash::AHashMap;
+use bytes::Bytes;
+use http::{
+    header::{self, HeaderValue},
+    status::StatusCode,
+};
+use motore::service::Service;
+use parking_lot::RwLock;
+
+use super::serve_dir::guess_mime;
+use crate::{
+    body::Body, context::ServerContext, request::ServerRequest, response::ServerResponse,
+    server::IntoResponse,
+};
+
+/// An immutable asset with its precomputed `ETag`.
+#[derive(Clone)]
+pub struct Asset {
+    bytes: Bytes,
+    etag: HeaderValue,
+    content_type: HeaderValue,
+}
+
+impl Asset {
+    /// Creates an [`Asset`] and computes its `ETag` from the content.
+    pub fn new(bytes: Bytes, content_type: HeaderValue) -> Self {
+        let etag = HeaderValue::try_from(format!("\"{:x}-{:016x}\"", bytes.len(), fnv1a(&bytes)))
+            .expect("the etag is a valid header value");
+        Self {
+            bytes,
+            etag,
+            content_type,
+        }
+    }
+
+    /// Returns the content shared by all the responses.
+    pub fn bytes(&self) -> &Bytes {
+        &self.bytes
+    }
+
+    /// Returns the strong `ETag` of the content.
+    pub fn etag(&self) -> &HeaderValue {
+        &self.etag
+    }
+
+    /// Returns the `Content-Type` of the asset.
+    pub fn content_type(&self) -> &HeaderValue {
+        &self.content_type
+    }
+
+    /// Returns whether the `If-None-Match` of the request matches the asset.
+    fn matches(&self, if_none_match: &HeaderValue) -> bool {
+        let Ok(tags) = if_none_match.to_str() else {
+            return false;
+        };
+        let etag = self.etag.as_bytes();
+        tags.split(',').map(str::trim).any(|tag| {
+            // the weak comparison is used for `If-None-Match`
+            tag == "*" || tag.strip_prefix("W/").unwrap_or(tag).as_bytes() == etag
+        })
+    }
+}
+
+impl fmt::Debug for Asset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Asset")
+            .field("len", &self.bytes.len())
+            .field("etag", &self.etag)
+            .field("content_type", &self.content_type)
+            .finish()
+    }
+}
+
+impl IntoResponse for Asset {
+    fn into_response(self) -> ServerResponse {
+        ServerResponse::builder()
+            .header(header::CONTENT_TYPE, self.content_type)
+            .header(header::CONTENT_LENGTH, self.bytes.len())
+            .header(header::ETAG, self.etag)
+            .body(Body::from_shared(self.bytes))
+            .unwrap()
+    }
+}
+
+/// The FNV-1a hash, which is stable across the processes, so the instances serving the same
+/// asset give the same `ETag`.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ u64::from(*b)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// A cache of the immutable assets keyed by their paths, which is shared by all its clones.
+#[derive(Clone, Default)]
+pub struct AssetCache {
+    assets: Arc<RwLock<AHashMap<String, Asset>>>,
+}
+
+impl AssetCache {
+    /// Creates an empty [`AssetCache`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserts the asset of the path, e.g. `js/app.js`, replacing the old one.
+    pub fn insert(
+        &self,
+        path: impl Into<String>,
+        bytes: Bytes,
+        content_type: HeaderValue,
+    ) -> Asset {
+        let asset = Asset::new(bytes, content_type);
+        self.assets.write().insert(path.into(), asset.clone());
+        asset
+    }
+
+    /// Inserts the file as the asset of the path, whose `Content-Type` is guessed by its
+    /// extension.
+    ///
+    /// The file is read into the memory.
+    pub fn insert_file(
+        &self,
+        path: impl Into<String>,
+        file: impl AsRef<Path>,
+    ) -> io::Result<Asset> {
+        let file = file.as_ref();
+        let content_type = guess_mime(file);
+        let bytes = Bytes::from(fs::read(file)?);
+        Ok(self.insert(path, bytes, content_type))
+    }
+
+    /// Inserts the memory-mapped file as the asset of the path, whose `Content-Type` is guessed
+    /// by its extension.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be truncated or modified, by this process or any other, as long as the
+    /// asset or any response of it is alive, otherwise reading the asset is undefined behaviour,
+    /// e.g. `SIGBUS` after the file is truncated.
+    #[cfg(feature = "mmap")]
+    pub unsafe fn insert_file_mmap(
+        &self,
+        path: impl Into<String>,
+        file: impl AsRef<Path>,
+    ) -> io::Result<Asset> {
+        let file = file.as_ref();
+        let content_type = guess_mime(file);
+        // SAFETY: the file is kept unmodified by the caller.
+        let mmap = unsafe { memmap2::Mmap::map(&fs::File::open(file)?)? };
+        let bytes = crate::body::mmap_bytes(Arc::new(mmap));
+        Ok(self.insert(path, bytes, content_type))
+    }
+
+    /// Inserts all the files in the directory recursively, whose paths are the relative paths
+    /// prefixed by `prefix`, and returns the number of the files.
+    pub fn insert_dir(&self, prefix: &str, dir: impl AsRef<Path>) -> io::Result<usize> {
+        let mut count = 0;
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+            let path = if prefix.is_empty() {
+                name.to_owned()
+            } else {
+                format!("{}/{name}", prefix.trim_end_matches('/'))
+            };
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                count += self.insert_dir(&path, entry.path())?;
+            } else if file_type.is_file() {
+                self.insert_file(path, entry.path())?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Returns the asset of the path.
+    pub fn get(&self, path: &str) -> Option<Asset> {
+        self.assets.read().get(path).cloned()
+    }
+
+    /// Removes the asset of the path.
+    pub fn remove(&self, path: &str) -> Option<Asset> {
+        self.assets.write().remove(path)
+    }
+
+    /// Returns the number of the assets.
+    pub fn len(&self) -> usize {
+        self.assets.read().len()
+    }
+
+    /// Returns whether there is no asset.
+    pub fn is_empty(&self) -> bool {
+        self.assets.read().is_empty()
+    }
+
+    /// Returns a service serving the assets by the path of the uri, which can be used like
+    /// [`ServeDir`](super::ServeDir).
+    pub fn serve<E>(&self) -> ServeAssets<E> {
+        ServeAssets {
+            cache: self.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl fmt::Debug for AssetCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AssetCache")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+/// The service created by [`AssetCache::serve`].
+pub struct ServeAssets<E> {
+    cache: AssetCache,
+    _marker: PhantomData<fn(E)>,
+}
+
+impl<E> Clone for ServeAssets<E> {
+    fn clone(&self) -> Self {
+        Self {
+            cache: self.cache.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<B, E> Service<ServerContext, ServerRequest<B>> for ServeAssets<E>
+where
+    B: Send,
+{
+    type Response = ServerResponse;
+    type Error = E;
+
+    async fn call(
+        &self,
+        _: &mut ServerContext,
+        req: ServerRequest<B>,
+    ) -> Result<Self::Response, Self::Error> {
+        let path = req.uri().path();
+        let path = path.strip_prefix('/').unwrap_or(path);
+        let Some(asset) = self.cache.get(path) else {
+            return Ok(StatusCode::NOT_FOUND.into_response());
+        };
+
+        if req
+            .headers()
+            .get(header::IF_NONE_MATCH)
+            .is_some_and(|v| asset.matches(v))
+        {
+            return Ok(ServerResponse::builder()
+                .status(StatusCode::NOT_MODIFIED)
+                .header(header::ETAG, asset.etag)
+                .body(Body::empty())
+                .unwrap());
+        }
+        Ok(asset.into_response())
+    }
+}
+
+#[cfg(test)]
+mod asset_cache_tests {
+    use http::{method::Method, StatusCode};
+    use http_body_util::BodyExt;
+
+    use super::*;
+    use crate::server::{Router, Server};
+
+    async fn data(resp: ServerResponse) -> Bytes {
+        resp.into_body()
+            .frame()
+            .await
+            .unwrap()
+            .unwrap()
+            .into_data()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn share_allocation() {
+        let cache = AssetCache::new();
+        let asset = cache.insert(
+            "app.js",
+            Bytes::from(vec![7u8; 1 << 20]),
+            HeaderValue::from_static("text/javascript"),
+        );
+
+        let responses = futures::future::join_all((0..8).map(|_| {
+            let cache = cache.clone();
+            tokio::spawn(async move { data(cache.get("app.js").unwrap().into_response()).await })
+        }))
+        .await;
+        for bytes in responses {
+            let bytes = bytes.unwrap();
+            assert_eq!(bytes.as_ptr(), asset.bytes().as_ptr());
+            assert_eq!(bytes.len(), 1 << 20);
+        }
+    }
+
+    #[tokio::test]
+    async fn serve() {
+        let cache = AssetCache::new();
+        let asset = cache.insert(
+            "css/app.css",
+            Bytes::from_static(b"body {}"),
+            HeaderValue::from_static("text/css"),
+        );
+
+        let router: Router<Option<Body>> = Router::new().nest_service("/static/", cache.serve());
+        let server = Server::new(router).into_test_server();
+
+        let resp = server
+            .call_route(Method::GET, "/static/css/app.css", None)
+            .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get(header::ETAG), Some(asset.etag()));
+        assert_eq!(
+            resp.headers().get(header::CONTENT_TYPE).unwrap(),
+            "text/css"
+        );
+
+        assert_eq!(
+            server
+                .call_route(Method::GET, "/static/css/none.css", None)
+                .await
+                .status(),
+            StatusCode::NOT_FOUND
+        );
+
+        let if_none_match = format!("W/{}, \"other\"", asset.etag().to_str().unwrap());
+        assert!(asset.matches(&HeaderValue::try_from(if_none_match).unwrap()));
+        assert!(!asset.matches(&HeaderValue::from_static("\"other\"")));
+        assert!(asset.matches(&HeaderValue::from_static("*")));
+    }
+
+    #[cfg(feature = "mmap")]
+    #[tokio::test]
+    async fn mmap() {
+        use std::io::Write;
+
+        let mut file = tempfile::Builder::new().suffix(".txt").tempfile().unwrap();
+        file.write_all(b"mapped").unwrap();
+
+        let cache = AssetCache::new();
+        // SAFETY: the file is not modified until it's removed at the end of the test.
+        let asset = unsafe { cache.insert_file_mmap("mapped.txt", file.path()) }.unwrap();
+        assert_eq!(
+            asset.content_type(),
+            &HeaderValue::from_static("text/plain")
+        );
+
+        let bytes = data(cache.get("mapped.txt").unwrap().into_response()).await;
+        assert_eq!(bytes.as_ptr(), asset.bytes().as_ptr());
+        assert_eq!(&bytes[..], b"mapped");
+    }
+}
@@ -1,6 +1,6 @@
 use std::{
     fs::File,
-    io,
+    io::{self, Seek, SeekFrom},
     path::Path,
     pin::Pin,
     task::{ready, Context, Poll},
@@ -11,7 +11,7 @@ use futures::Stream;
 use http::header::{self, HeaderValue};
 use http_body::{Frame, SizeHint};
 use pin_project::pin_project;
-use tokio::io::AsyncRead;
+use tokio::io::{AsyncRead, AsyncReadExt};
 use tokio_util::io::ReaderStream;
 
 use crate::{body::Body, response::ServerResponse, server::IntoResponse};
@@ -53,6 +53,18 @@ impl IntoResponse for FileResponse {
     }
 }
 
+/// Creates a body streaming `len` bytes of the file from the offset `start`.
+pub(super) fn file_body(mut file: File, start: u64, len: u64) -> io::Result<Body> {
+    if start > 0 {
+        file.seek(SeekFrom::Start(start))?;
+    }
+    let file = tokio::fs::File::from_std(file).take(len);
+    Ok(Body::from_body(FileBody {
+        reader: ReaderStream::with_capacity(file, BUF_SIZE),
+        size: len,
+    }))
+}
+
 #[pin_project]
 struct FileBody<R> {
     #[pin]
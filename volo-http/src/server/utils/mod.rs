@@ -1,5 +1,9 @@
+mod asset_cache;
 mod file_response;
 mod serve_dir;
+mod serve_file;
 
+pub use asset_cache::{Asset, AssetCache, ServeAssets};
 pub use file_response::FileResponse;
-pub use serve_dir::ServeDir;
+pub use serve_dir::{ServeDir, SymlinkPolicy};
+pub use serve_file::ServeFile;
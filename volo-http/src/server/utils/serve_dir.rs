@@ -18,6 +18,30 @@
 //!
 //! The `"."` means `ServeDir` will serve the CWD (current working directory) and then you can
 //! access any file in the directory.
+//!
+//! The files are answered as described in [`ServeFile`](super::ServeFile), with the `index.html`
+//! of a directory served for the directory itself.
+//!
+//! `ServeDir` can also be the fallback of a router, so that the files are served for any uri
+//! without a route, e.g. a single page application with its APIs:
+//!
+//! ```
+//! use volo_http::server::{
+//!     route::{get, Router},
+//!     utils::ServeDir,
+//! };
+//!
+//! let app: Router = Router::new()
+//!     .route("/api/user", get(|| async { "user" }))
+//!     .fallback_service(ServeDir::new("."));
+//! let router: Router = Router::new().nest("/app", app);
+//! ```
+//!
+//! # Security
+//!
+//! The path of the uri is percent-decoded, and a path with a `..` component, a backslash or a
+//! NUL is rejected with `403 Forbidden`. The symbolic links are followed only if they point into
+//! the serving directory by default, see [`SymlinkPolicy`].
 
 #![deny(missing_docs)]
 
@@ -29,16 +53,33 @@ use std::{
 
 use http::{header::HeaderValue, status::StatusCode};
 use motore::service::Service;
+use percent_encoding::percent_decode_str;
 
-use super::FileResponse;
+use super::serve_file::{serve, with_extension, ServeOptions};
 use crate::{
     context::ServerContext, request::ServerRequest, response::ServerResponse, server::IntoResponse,
 };
 
+/// How [`ServeDir`] handles the symbolic links in the requested path.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// Reject any path going through a symbolic link with `403 Forbidden`.
+    Deny,
+    /// Follow the symbolic links, but reject the path with `403 Forbidden` if it resolves out of
+    /// the serving directory.
+    #[default]
+    WithinRoot,
+    /// Follow the symbolic links wherever they point.
+    Follow,
+}
+
 /// [`ServeDir`] is a service for sending files from a given directory.
 pub struct ServeDir<E, F> {
     path: PathBuf,
     mime_getter: F,
+    index_file: Option<String>,
+    symlinks: SymlinkPolicy,
+    options: ServeOptions,
     _marker: PhantomData<fn(E)>,
 }
 
@@ -58,24 +99,146 @@ impl<E> ServeDir<E, fn(&Path) -> HeaderValue> {
         Self {
             path,
             mime_getter: guess_mime,
+            index_file: Some("index.html".to_owned()),
+            symlinks: SymlinkPolicy::default(),
+            options: ServeOptions::default(),
             _marker: PhantomData,
         }
     }
+}
 
+impl<E, F> ServeDir<E, F> {
     /// Set a function for getting mime from file path.
     ///
     /// By default, [`ServeDir`] will use `mime_guess` crate for guessing a mime through the file
     /// extension name.
-    pub fn mime_getter<F>(self, mime_getter: F) -> ServeDir<E, F>
+    pub fn mime_getter<F2>(self, mime_getter: F2) -> ServeDir<E, F2>
     where
-        F: Fn(&Path) -> HeaderValue,
+        F2: Fn(&Path) -> HeaderValue,
     {
         ServeDir {
             path: self.path,
             mime_getter,
+            index_file: self.index_file,
+            symlinks: self.symlinks,
+            options: self.options,
             _marker: self._marker,
         }
     }
+
+    /// Set the file served for a request of a directory, or `None` for answering it with `404
+    /// Not Found`.
+    ///
+    /// Default is `index.html`.
+    pub fn index_file(mut self, index_file: Option<&str>) -> Self {
+        self.index_file = index_file.map(ToOwned::to_owned);
+        self
+    }
+
+    /// Set how the symbolic links in the requested path are handled.
+    ///
+    /// Default is [`SymlinkPolicy::WithinRoot`].
+    pub fn symlinks(mut self, policy: SymlinkPolicy) -> Self {
+        self.symlinks = policy;
+        self
+    }
+
+    /// Set whether to send the `ETag` and handle `If-None-Match` and `If-Range` with it.
+    ///
+    /// Default is `true`.
+    pub fn etag(mut self, etag: bool) -> Self {
+        self.options.etag = etag;
+        self
+    }
+
+    /// Send the file with the extension `.br` instead if it exists and the client accepts `br`.
+    ///
+    /// Default is disabled.
+    pub fn precompressed_br(mut self) -> Self {
+        self.options.br = true;
+        self
+    }
+
+    /// Send the file with the extension `.gz` instead if it exists and the client accepts
+    /// `gzip`.
+    ///
+    /// Default is disabled.
+    pub fn precompressed_gzip(mut self) -> Self {
+        self.options.gzip = true;
+        self
+    }
+
+    /// Resolves the relative path in the serving directory by the [`SymlinkPolicy`].
+    fn resolve(&self, relative: &Path) -> Result<PathBuf, StatusCode> {
+        match self.symlinks {
+            SymlinkPolicy::Deny => {
+                let mut path = self.path.clone();
+                for component in relative.components() {
+                    path.push(component);
+                    let metadata =
+                        fs::symlink_metadata(&path).map_err(|_| StatusCode::NOT_FOUND)?;
+                    if metadata.file_type().is_symlink() {
+                        tracing::debug!("ServeDir: symlink denied: {}", path.display());
+                        return Err(StatusCode::FORBIDDEN);
+                    }
+                }
+                Ok(path)
+            }
+            SymlinkPolicy::WithinRoot => {
+                let path = fs::canonicalize(self.path.join(relative))
+                    .map_err(|_| StatusCode::NOT_FOUND)?;
+                // Reject file which is out of the serving directory
+                if path.strip_prefix(self.path.as_path()).is_err() {
+                    tracing::debug!("ServeDir: illegal path: {}", path.display());
+                    return Err(StatusCode::FORBIDDEN);
+                }
+                Ok(path)
+            }
+            SymlinkPolicy::Follow => Ok(self.path.join(relative)),
+        }
+    }
+}
+
+impl<E, F> ServeDir<E, F>
+where
+    F: Fn(&Path) -> HeaderValue,
+{
+    fn respond<B>(&self, req: &ServerRequest<B>) -> Result<ServerResponse, StatusCode> {
+        // Get relative path from uri
+        let path = req.uri().path();
+        let path = percent_decode_str(path)
+            .decode_utf8()
+            .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+        tracing::trace!("ServeDir: path: {path}");
+
+        let mut relative = relative_path(&path).ok_or_else(|| {
+            tracing::debug!("ServeDir: illegal path: {path}");
+            StatusCode::FORBIDDEN
+        })?;
+        let mut path = self.resolve(&relative)?;
+        if path.is_dir() {
+            // Serve the index file for the directory
+            let index_file = self.index_file.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+            relative.push(index_file);
+            path = self.resolve(&relative)?;
+        }
+
+        // Get mime and return it!
+        let content_type = (self.mime_getter)(&path);
+        Ok(serve(
+            req.method(),
+            req.headers(),
+            &path,
+            content_type,
+            &self.options,
+            |ext| {
+                self.resolve(&with_extension(&relative, ext))
+                    .ok()
+                    .filter(|path| path.is_file())
+            },
+        ))
+    }
 }
 
 impl<B, E, F> Service<ServerContext, ServerRequest<B>> for ServeDir<E, F>
@@ -91,36 +254,27 @@ where
         _: &mut ServerContext,
         req: ServerRequest<B>,
     ) -> Result<Self::Response, Self::Error> {
-        // Get relative path from uri
-        let path = req.uri().path();
-        let path = path.strip_prefix('/').unwrap_or(path);
-
-        tracing::trace!("ServeDir: path: {path}");
-
-        // Join to the serving directory and canonicalize it
-        let path = self.path.join(path);
-        let Ok(path) = fs::canonicalize(path) else {
-            return Ok(StatusCode::NOT_FOUND.into_response());
-        };
-
-        // Reject file which is out of the serving directory
-        if path.strip_prefix(self.path.as_path()).is_err() {
-            tracing::debug!("ServeDir: illegal path: {}", path.display());
-            return Ok(StatusCode::FORBIDDEN.into_response());
-        }
+        Ok(self
+            .respond(&req)
+            .unwrap_or_else(|status| status.into_response()))
+    }
+}
 
-        // Check metadata and permission
-        if !path.is_file() {
-            return Ok(StatusCode::NOT_FOUND.into_response());
+/// Converts the decoded path of the uri to a relative path, or `None` if it may escape from the
+/// serving directory.
+fn relative_path(path: &str) -> Option<PathBuf> {
+    let mut relative = PathBuf::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => return None,
+            c if c.contains(['\\', '\0']) => return None,
+            // e.g. `C:` on windows
+            c if cfg!(windows) && c.contains(':') => return None,
+            c => relative.push(c),
         }
-
-        // Get mime and return it!
-        let content_type = (self.mime_getter)(&path);
-        let Ok(resp) = FileResponse::new(path, content_type) else {
-            return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
-        };
-        Ok(resp.into_response())
     }
+    Some(relative)
 }
 
 pub fn guess_mime(path: &Path) -> HeaderValue {
@@ -132,12 +286,15 @@ pub fn guess_mime(path: &Path) -> HeaderValue {
 
 #[cfg(test)]
 mod serve_dir_tests {
-    use http::{method::Method, StatusCode};
+    use std::convert::Infallible;
+
+    use http::{header, method::Method, StatusCode};
+    use http_body_util::BodyExt;
 
-    use super::ServeDir;
+    use super::*;
     use crate::{
         body::Body,
-        server::{Router, Server},
+        server::{route::get as get_route, test_helpers::empty_cx, Router, Server},
     };
 
     #[tokio::test]
@@ -175,4 +332,96 @@ mod serve_dir_tests {
             StatusCode::FORBIDDEN
         );
     }
+
+    async fn get(
+        service: &ServeDir<Infallible, fn(&Path) -> HeaderValue>,
+        uri: &str,
+    ) -> StatusCode {
+        let req = ServerRequest::builder().uri(uri).body(()).unwrap();
+        service.call(&mut empty_cx(), req).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn traversal() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("public");
+        std::fs::create_dir_all(dir.join("docs")).unwrap();
+        std::fs::write(dir.join("docs/index.html"), b"<html></html>").unwrap();
+        std::fs::write(dir.join("a.txt"), b"a").unwrap();
+        std::fs::write(root.path().join("secret.txt"), b"secret").unwrap();
+        let service = ServeDir::new(&dir);
+
+        assert_eq!(get(&service, "/a.txt").await, StatusCode::OK);
+        assert_eq!(get(&service, "/%61.txt").await, StatusCode::OK);
+        for uri in [
+            "/../secret.txt",
+            "/docs/../../secret.txt",
+            "/%2e%2e/secret.txt",
+            "/%2E%2E%2fsecret.txt",
+            "/docs%2f..%2f..%2fsecret.txt",
+            "/..%5csecret.txt",
+            "/a.txt%00",
+        ] {
+            assert_eq!(get(&service, uri).await, StatusCode::FORBIDDEN, "{uri}");
+        }
+        assert_eq!(get(&service, "/%ff").await, StatusCode::BAD_REQUEST);
+        assert_eq!(get(&service, "/missing.txt").await, StatusCode::NOT_FOUND);
+
+        // the index file of a directory
+        assert_eq!(get(&service, "/docs/").await, StatusCode::OK);
+        assert_eq!(get(&service, "/docs").await, StatusCode::OK);
+        assert_eq!(get(&service, "/").await, StatusCode::NOT_FOUND);
+        let service = ServeDir::new(&dir).index_file(None);
+        assert_eq!(get(&service, "/docs/").await, StatusCode::NOT_FOUND);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[tokio::test]
+    async fn symlinks() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("public");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.txt"), b"a").unwrap();
+        std::fs::write(root.path().join("secret.txt"), b"secret").unwrap();
+        std::os::unix::fs::symlink(dir.join("a.txt"), dir.join("inner.txt")).unwrap();
+        std::os::unix::fs::symlink(root.path().join("secret.txt"), dir.join("outer.txt")).unwrap();
+
+        let service = ServeDir::new(&dir);
+        assert_eq!(get(&service, "/inner.txt").await, StatusCode::OK);
+        assert_eq!(get(&service, "/outer.txt").await, StatusCode::FORBIDDEN);
+
+        let service = ServeDir::new(&dir).symlinks(SymlinkPolicy::Deny);
+        assert_eq!(get(&service, "/a.txt").await, StatusCode::OK);
+        assert_eq!(get(&service, "/inner.txt").await, StatusCode::FORBIDDEN);
+        assert_eq!(get(&service, "/outer.txt").await, StatusCode::FORBIDDEN);
+
+        let service = ServeDir::new(&dir).symlinks(SymlinkPolicy::Follow);
+        assert_eq!(get(&service, "/outer.txt").await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn fallback() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("index.html"), b"<html></html>").unwrap();
+        std::fs::write(dir.path().join("app.js"), b"app").unwrap();
+
+        let app: Router<Option<Body>> = Router::new()
+            .route("/api", get_route(|| async { "api" }))
+            .fallback_service(ServeDir::new(dir.path()));
+        let router: Router<Option<Body>> = Router::new().nest("/app", app);
+        let server = Server::new(router).into_test_server();
+
+        let resp = server.call_route(Method::GET, "/app/api", None).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp = server.call_route(Method::GET, "/app/app.js", None).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.into_body().collect().await.unwrap().to_bytes(), "app");
+        let resp = server.call_route(Method::HEAD, "/app/", None).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get(header::CONTENT_LENGTH).unwrap(), "13");
+        let resp = server
+            .call_route(Method::GET, "/app/missing.js", None)
+            .await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
 }
@@ -0,0 +1,641 @@
+//! Service for serving a single file.
+//!
+//! This module includes [`ServeFile`], and the shared logic of [`ServeFile`] and
+//! [`ServeDir`](super::ServeDir) for answering a request with a file on the disk:
+//!
+//! - `GET` and `HEAD` are supported, other methods are answered with `405 Method Not Allowed`.
+//! - A strong `ETag` derived from the size and the modification time, and the `Last-Modified` are
+//!   sent, and the requests with a matching `If-None-Match` or `If-Modified-Since` are answered
+//!   with `304 Not Modified`.
+//! - A single byte range of `Range` is supported, answered with `206 Partial Content` or `416 Range
+//!   Not Satisfiable`, and guarded by `If-Range`. Multiple ranges are ignored, and the whole file
+//!   is sent.
+//! - With the precompressed files enabled, a `.br` or `.gz` file next to the requested one is sent
+//!   instead if the client accepts the encoding.
+//!
+//! # Examples
+//!
+//! ```
+//! use volo_http::server::{
+//!     route::{any_service, get, Router},
+//!     utils::ServeFile,
+//! };
+//!
+//! let router: Router = Router::new()
+//!     .route("/", get(|| async { "Hello, World" }))
+//!     .route(
+//!         "/favicon.ico",
+//!         any_service(ServeFile::new("./static/favicon.ico")),
+//!     );
+//! ```
+
+#![deny(missing_docs)]
+
+use std::{
+    ffi::OsStr,
+    fs::File,
+    io,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use http::{
+    header::{self, HeaderMap, HeaderValue},
+    method::Method,
+    status::StatusCode,
+};
+use motore::service::Service;
+
+use super::{file_response::file_body, serve_dir::guess_mime};
+use crate::{
+    body::Body, context::ServerContext, request::ServerRequest, response::ServerResponse,
+    server::IntoResponse,
+};
+
+const HTTP_DATE: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// [`ServeFile`] is a service for sending a given file.
+pub struct ServeFile<E> {
+    path: PathBuf,
+    content_type: HeaderValue,
+    options: ServeOptions,
+    _marker: PhantomData<fn(E)>,
+}
+
+impl<E> ServeFile<E> {
+    /// Create a new [`ServeFile`] service with the given path.
+    ///
+    /// The file is not opened until a request comes, so it can be created, replaced or removed
+    /// at any time.
+    pub fn new<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref().to_owned();
+        Self {
+            content_type: guess_mime(&path),
+            path,
+            options: ServeOptions::default(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Set the `Content-Type` of the file.
+    ///
+    /// Default is guessed by the `mime_guess` crate through the file extension name.
+    pub fn content_type(mut self, content_type: HeaderValue) -> Self {
+        self.content_type = content_type;
+        self
+    }
+
+    /// Set whether to send the `ETag` and handle `If-None-Match` and `If-Range` with it.
+    ///
+    /// Default is `true`.
+    pub fn etag(mut self, etag: bool) -> Self {
+        self.options.etag = etag;
+        self
+    }
+
+    /// Send the file with the extension `.br` instead if it exists and the client accepts `br`.
+    ///
+    /// Default is disabled.
+    pub fn precompressed_br(mut self) -> Self {
+        self.options.br = true;
+        self
+    }
+
+    /// Send the file with the extension `.gz` instead if it exists and the client accepts
+    /// `gzip`.
+    ///
+    /// Default is disabled.
+    pub fn precompressed_gzip(mut self) -> Self {
+        self.options.gzip = true;
+        self
+    }
+}
+
+impl<E> Clone for ServeFile<E> {
+    fn clone(&self) -> Self {
+        Self {
+            path: self.path.clone(),
+            content_type: self.content_type.clone(),
+            options: self.options,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<B, E> Service<ServerContext, ServerRequest<B>> for ServeFile<E>
+where
+    B: Send,
+{
+    type Response = ServerResponse;
+    type Error = E;
+
+    async fn call(
+        &self,
+        _: &mut ServerContext,
+        req: ServerRequest<B>,
+    ) -> Result<Self::Response, Self::Error> {
+        Ok(serve(
+            req.method(),
+            req.headers(),
+            &self.path,
+            self.content_type.clone(),
+            &self.options,
+            |ext| {
+                let path = with_extension(&self.path, ext);
+                path.is_file().then_some(path)
+            },
+        ))
+    }
+}
+
+/// The options shared by [`ServeFile`] and [`ServeDir`](super::ServeDir).
+#[derive(Clone, Copy, Debug)]
+pub(super) struct ServeOptions {
+    pub(super) etag: bool,
+    pub(super) br: bool,
+    pub(super) gzip: bool,
+}
+
+impl Default for ServeOptions {
+    fn default() -> Self {
+        Self {
+            etag: true,
+            br: false,
+            gzip: false,
+        }
+    }
+}
+
+/// Answers a request of the file at `path`.
+///
+/// `variant` returns the path of the precompressed file with the given extension if it exists
+/// and is allowed to be served.
+pub(super) fn serve<F>(
+    method: &Method,
+    headers: &HeaderMap,
+    path: &Path,
+    content_type: HeaderValue,
+    options: &ServeOptions,
+    variant: F,
+) -> ServerResponse
+where
+    F: Fn(&str) -> Option<PathBuf>,
+{
+    if method != Method::GET && method != Method::HEAD {
+        return ServerResponse::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header(header::ALLOW, HeaderValue::from_static("GET, HEAD"))
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    let accept_encoding = headers.get(header::ACCEPT_ENCODING);
+    let precompressed = [(options.br, "br", ".br"), (options.gzip, "gzip", ".gz")]
+        .into_iter()
+        .filter(|(enabled, encoding, _)| *enabled && accepts(accept_encoding, encoding))
+        .find_map(|(_, encoding, ext)| Some((variant(ext)?, encoding)));
+    let (path, encoding) = match &precompressed {
+        Some((path, encoding)) => (path.as_path(), Some(*encoding)),
+        None => (path, None),
+    };
+
+    let (file, size, modified) = match open(path) {
+        Ok(Some(opened)) => opened,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(err) => {
+            tracing::debug!("[VOLO] failed to open {}: {err}", path.display());
+            return match err.kind() {
+                io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+                io::ErrorKind::PermissionDenied => StatusCode::FORBIDDEN,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            }
+            .into_response();
+        }
+    };
+    let etag = match modified {
+        Some(modified) if options.etag => Some(etag(size, modified)),
+        _ => None,
+    };
+    let modified_secs = modified.and_then(secs);
+
+    let mut builder = ServerResponse::builder();
+    if let Some(etag) = &etag {
+        builder = builder.header(header::ETAG, etag.clone());
+    }
+    if let Some(secs) = modified_secs {
+        builder = builder.header(header::LAST_MODIFIED, http_date(secs));
+    }
+    if options.br || options.gzip {
+        builder = builder.header(header::VARY, HeaderValue::from_static("accept-encoding"));
+    }
+
+    if not_modified(headers, etag.as_ref(), modified_secs) {
+        return builder
+            .status(StatusCode::NOT_MODIFIED)
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    builder = builder
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    if let Some(encoding) = encoding {
+        builder = builder.header(header::CONTENT_ENCODING, HeaderValue::from_static(encoding));
+    }
+
+    // the range handling is only defined for `GET`
+    let range = headers
+        .get(header::RANGE)
+        .filter(|_| method == Method::GET)
+        .filter(|_| if_range(headers, etag.as_ref(), modified_secs))
+        .map_or(ByteRange::Full, |range| ByteRange::parse(range, size));
+    let (start, len) = match range {
+        ByteRange::Full => (0, size),
+        ByteRange::Partial(start, end) => {
+            builder = builder
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(header::CONTENT_RANGE, format!("bytes {start}-{end}/{size}"));
+            (start, end - start + 1)
+        }
+        ByteRange::Unsatisfiable => {
+            return builder
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{size}"))
+                .body(Body::empty())
+                .unwrap();
+        }
+    };
+
+    builder = builder.header(header::CONTENT_LENGTH, len);
+    if method == Method::HEAD {
+        return builder.body(Body::empty()).unwrap();
+    }
+    match file_body(file, start, len) {
+        Ok(body) => builder.body(body).unwrap(),
+        Err(err) => {
+            tracing::debug!("[VOLO] failed to read {}: {err}", path.display());
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Opens the file, and returns `None` if it's not a regular file.
+fn open(path: &Path) -> io::Result<Option<(File, u64, Option<SystemTime>)>> {
+    let file = File::open(path)?;
+    let metadata = file.metadata()?;
+    if !metadata.is_file() {
+        return Ok(None);
+    }
+    Ok(Some((file, metadata.len(), metadata.modified().ok())))
+}
+
+/// Appends the extension to the path, e.g. `index.html` with `.gz` is `index.html.gz`.
+pub(super) fn with_extension(path: &Path, ext: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(OsStr::new(ext));
+    PathBuf::from(path)
+}
+
+/// Returns whether the `Accept-Encoding` accepts the encoding explicitly with a non-zero quality.
+fn accepts(accept_encoding: Option<&HeaderValue>, encoding: &str) -> bool {
+    let Some(accept_encoding) = accept_encoding.and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+    accept_encoding.split(',').any(|item| {
+        let mut params = item.split(';').map(str::trim);
+        params
+            .next()
+            .is_some_and(|coding| coding.eq_ignore_ascii_case(encoding))
+            && params.all(|param| {
+                param
+                    .strip_prefix("q=")
+                    .map_or(true, |q| q.parse::<f32>().is_ok_and(|q| q > 0.0))
+            })
+    })
+}
+
+/// The strong `ETag` derived from the size and the modification time.
+fn etag(size: u64, modified: SystemTime) -> HeaderValue {
+    let nanos = modified
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    HeaderValue::try_from(format!("\"{size:x}-{nanos:x}\""))
+        .expect("the etag is a valid header value")
+}
+
+fn secs(time: SystemTime) -> Option<i64> {
+    i64::try_from(time.duration_since(UNIX_EPOCH).ok()?.as_secs()).ok()
+}
+
+fn http_date(secs: i64) -> HeaderValue {
+    let date = DateTime::<Utc>::from_timestamp(secs, 0).unwrap_or_default();
+    HeaderValue::try_from(date.format(HTTP_DATE).to_string())
+        .expect("the date is a valid header value")
+}
+
+fn parse_http_date(value: &HeaderValue) -> Option<i64> {
+    let date = NaiveDateTime::parse_from_str(value.to_str().ok()?, HTTP_DATE).ok()?;
+    Some(date.and_utc().timestamp())
+}
+
+/// Evaluates `If-None-Match`, or `If-Modified-Since` if there is no `If-None-Match`.
+fn not_modified(headers: &HeaderMap, etag: Option<&HeaderValue>, modified: Option<i64>) -> bool {
+    if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH) {
+        let (Some(etag), Ok(tags)) = (etag, if_none_match.to_str()) else {
+            return false;
+        };
+        return tags.split(',').map(str::trim).any(|tag| {
+            // the weak comparison is used for `If-None-Match`
+            tag == "*" || tag.strip_prefix("W/").unwrap_or(tag).as_bytes() == etag.as_bytes()
+        });
+    }
+    match (
+        headers
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(parse_http_date),
+        modified,
+    ) {
+        (Some(since), Some(modified)) => modified <= since,
+        _ => false,
+    }
+}
+
+/// Evaluates `If-Range`, and returns whether the `Range` should be applied.
+fn if_range(headers: &HeaderMap, etag: Option<&HeaderValue>, modified: Option<i64>) -> bool {
+    let Some(if_range) = headers.get(header::IF_RANGE) else {
+        return true;
+    };
+    let bytes = if_range.as_bytes();
+    if bytes.starts_with(b"\"") || bytes.starts_with(b"W/") {
+        // the strong comparison is used for `If-Range`, so a weak tag never matches
+        return etag.is_some_and(|etag| etag.as_bytes() == bytes);
+    }
+    match (parse_http_date(if_range), modified) {
+        (Some(date), Some(modified)) => date == modified,
+        _ => false,
+    }
+}
+
+/// The byte range requested by `Range`.
+#[derive(Debug, PartialEq, Eq)]
+enum ByteRange {
+    /// No range or an unsupported one, the whole file is sent.
+    Full,
+    /// The first and the last byte of a single range, both inclusive.
+    Partial(u64, u64),
+    /// The range is out of the file.
+    Unsatisfiable,
+}
+
+impl ByteRange {
+    fn parse(range: &HeaderValue, size: u64) -> Self {
+        let Some(spec) = range
+            .to_str()
+            .ok()
+            .and_then(|range| range.trim().split_once('='))
+            .filter(|(unit, _)| unit.trim().eq_ignore_ascii_case("bytes"))
+            .map(|(_, spec)| spec.trim())
+        else {
+            return ByteRange::Full;
+        };
+        // multiple ranges are not supported, and are ignored as the RFC allows
+        if spec.contains(',') {
+            return ByteRange::Full;
+        }
+        let Some((first, last)) = spec.split_once('-') else {
+            return ByteRange::Full;
+        };
+        let (first, last) = (first.trim(), last.trim());
+
+        if first.is_empty() {
+            // the suffix range, i.e. the last N bytes
+            return match last.parse::<u64>() {
+                Ok(0) => ByteRange::Unsatisfiable,
+                Ok(_) if size == 0 => ByteRange::Unsatisfiable,
+                Ok(n) => ByteRange::Partial(size.saturating_sub(n), size - 1),
+                Err(_) => ByteRange::Full,
+            };
+        }
+        let Ok(first) = first.parse::<u64>() else {
+            return ByteRange::Full;
+        };
+        let last = if last.is_empty() {
+            u64::MAX
+        } else {
+            match last.parse::<u64>() {
+                Ok(last) if last >= first => last,
+                _ => return ByteRange::Full,
+            }
+        };
+        if first >= size {
+            return ByteRange::Unsatisfiable;
+        }
+        ByteRange::Partial(first, last.min(size - 1))
+    }
+}
+
+#[cfg(test)]
+mod serve_file_tests {
+    use std::convert::Infallible;
+
+    use http::method::Method;
+    use http_body_util::BodyExt;
+
+    use super::*;
+    use crate::server::test_helpers::empty_cx;
+
+    async fn call(
+        service: &ServeFile<Infallible>,
+        method: Method,
+        headers: &[(header::HeaderName, &str)],
+    ) -> ServerResponse {
+        let mut req = ServerRequest::builder()
+            .method(method)
+            .uri("/")
+            .body(())
+            .unwrap();
+        for (name, value) in headers {
+            req.headers_mut()
+                .insert(name, HeaderValue::from_str(value).unwrap());
+        }
+        service.call(&mut empty_cx(), req).await.unwrap()
+    }
+
+    async fn data(resp: ServerResponse) -> Vec<u8> {
+        resp.into_body()
+            .collect()
+            .await
+            .unwrap()
+            .to_bytes()
+            .to_vec()
+    }
+
+    fn range(spec: &str, size: u64) -> ByteRange {
+        ByteRange::parse(&HeaderValue::from_str(spec).unwrap(), size)
+    }
+
+    #[test]
+    fn parse_range() {
+        assert_eq!(range("bytes=0-4", 10), ByteRange::Partial(0, 4));
+        assert_eq!(range("bytes=5-", 10), ByteRange::Partial(5, 9));
+        assert_eq!(range("bytes=8-100", 10), ByteRange::Partial(8, 9));
+        // suffix ranges
+        assert_eq!(range("bytes=-3", 10), ByteRange::Partial(7, 9));
+        assert_eq!(range("bytes=-30", 10), ByteRange::Partial(0, 9));
+        assert_eq!(range("bytes=-0", 10), ByteRange::Unsatisfiable);
+        assert_eq!(range("bytes=-1", 0), ByteRange::Unsatisfiable);
+        // out of bounds
+        assert_eq!(range("bytes=10-", 10), ByteRange::Unsatisfiable);
+        assert_eq!(range("bytes=10-20", 10), ByteRange::Unsatisfiable);
+        assert_eq!(range("bytes=0-", 0), ByteRange::Unsatisfiable);
+        // invalid or unsupported ones are ignored
+        assert_eq!(range("bytes=4-2", 10), ByteRange::Full);
+        assert_eq!(range("bytes=0-1,4-5", 10), ByteRange::Full);
+        assert_eq!(range("items=0-1", 10), ByteRange::Full);
+        assert_eq!(range("bytes=a-b", 10), ByteRange::Full);
+    }
+
+    #[test]
+    fn accept_encoding() {
+        let v = HeaderValue::from_static("gzip;q=0.5, br;q=0, deflate");
+        assert!(accepts(Some(&v), "gzip"));
+        assert!(!accepts(Some(&v), "br"));
+        assert!(!accepts(None, "gzip"));
+    }
+
+    #[tokio::test]
+    async fn serve_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.txt");
+        std::fs::write(&path, b"0123456789").unwrap();
+        let service = ServeFile::new(&path);
+
+        let resp = call(&service, Method::GET, &[]).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers().get(header::CONTENT_TYPE).unwrap(),
+            "text/plain"
+        );
+        assert_eq!(resp.headers().get(header::ACCEPT_RANGES).unwrap(), "bytes");
+        let etag = resp.headers().get(header::ETAG).unwrap().clone();
+        let last_modified = resp.headers().get(header::LAST_MODIFIED).unwrap().clone();
+        assert_eq!(data(resp).await, b"0123456789");
+
+        // HEAD has the headers only
+        let resp = call(&service, Method::HEAD, &[]).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get(header::CONTENT_LENGTH).unwrap(), "10");
+        assert_eq!(resp.headers().get(header::ETAG).unwrap(), &etag);
+        assert!(data(resp).await.is_empty());
+
+        let resp = call(&service, Method::POST, &[]).await;
+        assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+        // conditional requests
+        let resp = call(
+            &service,
+            Method::GET,
+            &[(header::IF_NONE_MATCH, etag.to_str().unwrap())],
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+        let resp = call(
+            &service,
+            Method::GET,
+            &[(header::IF_NONE_MATCH, "\"other\"")],
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp = call(
+            &service,
+            Method::GET,
+            &[(header::IF_MODIFIED_SINCE, last_modified.to_str().unwrap())],
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+        let resp = call(
+            &service,
+            Method::GET,
+            &[(header::IF_MODIFIED_SINCE, "Thu, 01 Jan 1970 00:00:00 GMT")],
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // ranges
+        let resp = call(&service, Method::GET, &[(header::RANGE, "bytes=-3")]).await;
+        assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            resp.headers().get(header::CONTENT_RANGE).unwrap(),
+            "bytes 7-9/10"
+        );
+        assert_eq!(resp.headers().get(header::CONTENT_LENGTH).unwrap(), "3");
+        assert_eq!(data(resp).await, b"789");
+        let resp = call(&service, Method::GET, &[(header::RANGE, "bytes=2-4")]).await;
+        assert_eq!(data(resp).await, b"234");
+        let resp = call(&service, Method::GET, &[(header::RANGE, "bytes=10-")]).await;
+        assert_eq!(resp.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(
+            resp.headers().get(header::CONTENT_RANGE).unwrap(),
+            "bytes */10"
+        );
+        // the range is ignored by HEAD
+        let resp = call(&service, Method::HEAD, &[(header::RANGE, "bytes=2-4")]).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // If-Range
+        let resp = call(
+            &service,
+            Method::GET,
+            &[
+                (header::RANGE, "bytes=2-4"),
+                (header::IF_RANGE, etag.to_str().unwrap()),
+            ],
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+        let resp = call(
+            &service,
+            Method::GET,
+            &[
+                (header::RANGE, "bytes=2-4"),
+                (header::IF_RANGE, "\"stale\""),
+            ],
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(data(resp).await, b"0123456789");
+    }
+
+    #[tokio::test]
+    async fn precompressed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.js");
+        std::fs::write(&path, b"plain").unwrap();
+        std::fs::write(dir.path().join("app.js.gz"), b"gzipped").unwrap();
+        let service = ServeFile::new(&path)
+            .precompressed_br()
+            .precompressed_gzip();
+
+        let resp = call(
+            &service,
+            Method::GET,
+            &[(header::ACCEPT_ENCODING, "br, gzip")],
+        )
+        .await;
+        assert_eq!(
+            resp.headers().get(header::CONTENT_ENCODING).unwrap(),
+            "gzip"
+        );
+        assert_eq!(resp.headers().get(header::VARY).unwrap(), "accept-encoding");
+        assert_eq!(data(resp).await, b"gzipped");
+
+        let resp = call(&service, Method::GET, &[(header::ACCEPT_ENCODING, "br")]).await;
+        assert!(resp.headers().get(header::CONTENT_ENCODING).is_none());
+        assert_eq!(data(resp).await, b"plain");
+    }
+}
@@ -0,0 +1,12 @@
+#![cfg(feature = "macros")]
+
+// The diagnostics differ between the compilers, so they're only checked with the toolchain pinned
+// by the `test-ui` job of the CI. After bumping it, regenerate the snapshots by:
+//
+// TRYBUILD=overwrite cargo +1.95.0 test -p volo-http --features full --test debug_handler
+#[rustversion::attr(not(stable(1.95)), ignore)]
+#[test]
+fn ui() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/debug_handler/*.rs");
+}
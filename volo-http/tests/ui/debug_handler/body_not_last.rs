@@ -0,0 +1,6 @@
+use volo_http::{http::Uri, server::debug_handler};
+
+#[debug_handler]
+async fn handler(_: String, _: Uri) {}
+
+fn main() {}
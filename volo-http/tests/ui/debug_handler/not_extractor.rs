@@ -0,0 +1,8 @@
+use volo_http::server::debug_handler;
+
+struct NotExtractor;
+
+#[debug_handler]
+async fn handler(_: NotExtractor) {}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use volo_http::server::debug_handler;
+
+struct NotResponse;
+
+#[debug_handler]
+async fn handler() -> NotResponse {
+    NotResponse
+}
+
+fn main() {}
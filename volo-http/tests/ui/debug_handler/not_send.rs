@@ -0,0 +1,12 @@
+use volo_http::server::debug_handler;
+
+async fn yield_now() {}
+
+#[debug_handler]
+async fn handler() {
+    let rc = std::rc::Rc::new(());
+    yield_now().await;
+    drop(rc);
+}
+
+fn main() {}
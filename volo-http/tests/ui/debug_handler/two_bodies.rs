@@ -0,0 +1,6 @@
+use volo_http::server::debug_handler;
+
+#[debug_handler]
+async fn handler(_: Vec<u8>, _: String) {}
+
+fn main() {}
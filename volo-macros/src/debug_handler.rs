@@ -0,0 +1,178 @@
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote, quote_spanned};
+use syn::{
+    parse::{Parse, ParseStream},
+    spanned::Spanned,
+    FnArg, Ident, ItemFn, ReturnType, Token, Type,
+};
+
+/// The max number of the arguments of a handler, see `all_the_tuples` of `volo-http`.
+const MAX_ARGS: usize = 16;
+
+/// The arguments of the attribute, e.g. `#[debug_handler(body = MyBody)]`.
+#[derive(Default)]
+struct Args {
+    body: Option<Type>,
+}
+
+impl Parse for Args {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut args = Args::default();
+        while !input.is_empty() {
+            let name: Ident = input.parse()?;
+            if name != "body" {
+                return Err(syn::Error::new(
+                    name.span(),
+                    "unknown argument, expected `body = Type`",
+                ));
+            }
+            if args.body.is_some() {
+                return Err(syn::Error::new(name.span(), "duplicated `body`"));
+            }
+            input.parse::<Token![=]>()?;
+            args.body = Some(input.parse()?);
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+        Ok(args)
+    }
+}
+
+pub(crate) fn expand(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let item_fn = match syn::parse2::<ItemFn>(item.clone()) {
+        Ok(item_fn) => item_fn,
+        // leaves the errors of the item to the compiler
+        Err(_) => return item,
+    };
+    let checks = syn::parse2::<Args>(attr)
+        .and_then(|args| checks(&args, &item_fn))
+        .unwrap_or_else(syn::Error::into_compile_error);
+
+    quote! {
+        #item_fn
+        #checks
+    }
+}
+
+fn checks(args: &Args, item_fn: &ItemFn) -> syn::Result<TokenStream> {
+    let sig = &item_fn.sig;
+    if sig.asyncness.is_none() {
+        return Err(syn::Error::new(
+            sig.fn_token.span,
+            "handlers must be `async fn`",
+        ));
+    }
+    if !sig.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            sig.generics.span(),
+            "`#[debug_handler]` doesn't support the generic handlers",
+        ));
+    }
+    if sig.inputs.len() > MAX_ARGS {
+        return Err(syn::Error::new(
+            sig.inputs.span(),
+            format!("handlers can take at most {MAX_ARGS} extractors"),
+        ));
+    }
+    let tys = sig
+        .inputs
+        .iter()
+        .map(|arg| match arg {
+            FnArg::Receiver(receiver) => Err(syn::Error::new(
+                receiver.span(),
+                "handlers must not take `self`",
+            )),
+            FnArg::Typed(pat_type) => Ok(&*pat_type.ty),
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+
+    let name = &sig.ident;
+    let body = match &args.body {
+        Some(body) => quote! { #body },
+        None => quote! { ::volo_http::hyper::body::Incoming },
+    };
+
+    // the extractors except the last one must be `FromContext`, and the last one can consume the
+    // body by `FromRequest`
+    let arg_checks = tys.iter().enumerate().map(|(i, ty)| {
+        let check = format_ident!("__volo_http_check_{}_arg_{}", name, i);
+        if i + 1 < tys.len() {
+            quote_spanned! {ty.span()=>
+                #[allow(warnings)]
+                fn #check() {
+                    fn check<T>()
+                    where
+                        T: ::volo_http::server::extract::FromContext + ::core::marker::Send,
+                    {
+                    }
+                    check::<#ty>();
+                }
+            }
+        } else {
+            quote_spanned! {ty.span()=>
+                #[allow(warnings)]
+                fn #check() {
+                    fn check<M, T>()
+                    where
+                        T: ::volo_http::server::extract::FromRequest<#body, M>
+                            + ::core::marker::Send,
+                    {
+                    }
+                    check::<_, #ty>();
+                }
+            }
+        }
+    });
+
+    let idents = (0..tys.len())
+        .map(|i| format_ident!("arg{}", i))
+        .collect::<Vec<_>>();
+    let output_span = match &sig.output {
+        ReturnType::Default => sig.paren_token.span.join(),
+        ReturnType::Type(_, ty) => ty.span(),
+    };
+    let output_check = format_ident!("__volo_http_check_{}_output", name);
+    let future_check = format_ident!("__volo_http_check_{}_future", name);
+    let output_check = quote_spanned! {output_span=>
+        #[allow(warnings)]
+        async fn #output_check(#(#idents: #tys),*) {
+            let output = #name(#(#idents),*).await;
+            fn check<T>(_: T)
+            where
+                T: ::volo_http::server::IntoResponse,
+            {
+            }
+            check(output);
+        }
+    };
+    let future_check = quote_spanned! {fn_span(item_fn)=>
+        #[allow(warnings)]
+        fn #future_check(#(#idents: #tys),*) {
+            let future = #name(#(#idents),*);
+            fn check<T>(_: T)
+            where
+                T: ::core::marker::Send,
+            {
+            }
+            check(future);
+        }
+    };
+
+    Ok(quote! {
+        const _: () = {
+            #(#arg_checks)*
+            #output_check
+            #future_check
+        };
+    })
+}
+
+/// The span of `async fn name`, where the errors of the future are reported.
+fn fn_span(item_fn: &ItemFn) -> Span {
+    let sig = &item_fn.sig;
+    sig.fn_token
+        .span
+        .join(sig.ident.span())
+        .unwrap_or_else(|| sig.ident.span())
+}
@@ -2,4 +2,48 @@
     html_logo_url = "https://github.com/cloudwego/volo/raw/main/.github/assets/logo.png?sanitize=true"
 )]
 #![cfg_attr(not(doctest), doc = include_str!("../README.md"))]
-//! reserved for now
+
+use proc_macro::TokenStream;
+
+mod debug_handler;
+
+/// Type checks a handler of `volo-http` in isolation, and reports the errors at the arguments or
+/// the output of it rather than at the router.
+///
+/// The errors of a handler not satisfying `Handler` are reported at the routing of it, where the
+/// compiler can't tell which argument is wrong. With the attribute, the handler is checked that:
+///
+/// - the arguments except the last one implement `FromContext`, i.e. the extractors consuming the
+///   body are the last argument, and there's at most one of them
+/// - the last argument implements `FromRequest`
+/// - the output implements `IntoResponse`
+/// - the future is `Send`
+///
+/// The handler itself is kept as is, and the checks are only type checked without any cost at
+/// runtime.
+///
+/// ```rust,ignore
+/// use volo_http::{
+///     http::Uri,
+///     server::{
+///         debug_handler,
+///         route::{get, Router},
+///     },
+/// };
+///
+/// #[debug_handler]
+/// async fn handler(body: String, uri: Uri) -> String {
+///     // error: `String` can't be extracted from the parts of the request
+///     unimplemented!()
+/// }
+///
+/// let router = Router::new().route("/", get(handler));
+/// ```
+///
+/// The handlers are checked with the body of `hyper::body::Incoming`, and another one can be
+/// given by `#[debug_handler(body = MyBody)]`. The generic handlers and the methods are not
+/// supported.
+#[proc_macro_attribute]
+pub fn debug_handler(attr: TokenStream, item: TokenStream) -> TokenStream {
+    debug_handler::expand(attr.into(), item.into()).into()
+}
@@ -0,0 +1,207 @@
+//! The fairness of the serve loops between the connections on the same worker: a hot connection
+//! keeps the buffer of the server full of pipelined requests, while many other connections send a
+//! request now and then.
+//!
+//! For the pingpong and the multiplex modes, it prints the throughput of the hot connection and
+//! the p99 latency of the other ones, with the yield budget disabled and with the default one:
+//!
+//! ```sh
+//! cargo bench -p volo-thrift --features multiplex --bench fairness
+//! ```
+
+use std::{
+    cell::RefCell,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use metainfo::{MetaInfo, METAINFO};
+use motore::Service;
+use pilota::thrift::TMessageType;
+use tokio::{net::TcpStream, sync::Semaphore};
+use volo::{
+    context::{Endpoint, Role, RpcInfo},
+    net::incoming::DefaultIncoming,
+    util::budget::DEFAULT_YIELD_BUDGET,
+};
+use volo_thrift::{
+    codec::{Decoder, DefaultMakeCodec, Encoder, MakeCodec},
+    context::{ClientContext, ServerContext},
+    server::Server,
+    ServerError, ThriftMessage,
+};
+
+/// The number of the mostly idle connections.
+const IDLE_CONNS: usize = 64;
+/// The interval between the requests of an idle connection.
+const IDLE_INTERVAL: Duration = Duration::from_millis(5);
+/// The requests sent by the hot connection ahead of their responses.
+const PIPELINE: usize = 256;
+const DURATION: Duration = Duration::from_secs(3);
+
+#[derive(Clone)]
+struct Echo;
+
+impl Service<ServerContext, Bytes> for Echo {
+    type Response = Bytes;
+    type Error = ServerError;
+
+    async fn call<'s, 'cx>(
+        &'s self,
+        _cx: &'cx mut ServerContext,
+        req: Bytes,
+    ) -> Result<Self::Response, Self::Error> {
+        Ok(req)
+    }
+}
+
+/// Starts a server on a single worker, so all the connections compete for it.
+fn serve(multiplex: bool, yield_budget: usize) -> SocketAddr {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async move {
+            let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+            let server = Server::new(Echo).yield_budget(yield_budget);
+            #[cfg(feature = "multiplex")]
+            let server = server.multiplex(multiplex);
+            #[cfg(not(feature = "multiplex"))]
+            let _ = multiplex;
+            let _ = server.run(DefaultIncoming::from(listener)).await;
+        });
+    });
+    addr
+}
+
+fn cx(seq_id: i32) -> ClientContext {
+    ClientContext::new(
+        seq_id,
+        RpcInfo::new(
+            Role::Client,
+            "echo".into(),
+            Endpoint::new("bench".into()),
+            Endpoint::new("echo".into()),
+            Default::default(),
+        ),
+        TMessageType::Call,
+    )
+}
+
+async fn connect(addr: SocketAddr) -> (impl Encoder, impl Decoder) {
+    let stream = TcpStream::connect(addr).await.unwrap();
+    stream.set_nodelay(true).unwrap();
+    let (rh, wh) = stream.into_split();
+    DefaultMakeCodec::default().make_codec(rh, wh)
+}
+
+/// Keeps `PIPELINE` requests in flight on one connection, and counts the responses.
+async fn hot(addr: SocketAddr, stop: Arc<AtomicBool>, responses: Arc<AtomicU64>) {
+    let (mut encoder, mut decoder) = connect(addr).await;
+    let in_flight = Arc::new(Semaphore::new(PIPELINE));
+    let reader = tokio::spawn({
+        let in_flight = in_flight.clone();
+        METAINFO.scope(RefCell::new(MetaInfo::default()), async move {
+            let mut cx = cx(0);
+            while let Ok(Some(_)) = decoder.decode::<Bytes, _>(&mut cx).await {
+                responses.fetch_add(1, Ordering::Relaxed);
+                in_flight.add_permits(1);
+            }
+        })
+    });
+    METAINFO
+        .scope(RefCell::new(MetaInfo::default()), async {
+            let mut seq_id = 0;
+            while !stop.load(Ordering::Relaxed) {
+                in_flight.acquire().await.unwrap().forget();
+                seq_id += 1;
+                let mut cx = cx(seq_id);
+                let req = ThriftMessage::mk_client_msg(&cx, Bytes::from_static(&[0]));
+                if encoder.encode(&mut cx, req).await.is_err() {
+                    break;
+                }
+            }
+        })
+        .await;
+    reader.abort();
+}
+
+/// Sends a request every `IDLE_INTERVAL`, and returns the latencies.
+async fn idle(addr: SocketAddr, stop: Arc<AtomicBool>) -> Vec<Duration> {
+    let (mut encoder, mut decoder) = connect(addr).await;
+    let mut latencies = Vec::new();
+    METAINFO
+        .scope(RefCell::new(MetaInfo::default()), async {
+            let mut seq_id = 0;
+            while !stop.load(Ordering::Relaxed) {
+                tokio::time::sleep(IDLE_INTERVAL).await;
+                seq_id += 1;
+                let mut cx = cx(seq_id);
+                let req = ThriftMessage::mk_client_msg(&cx, Bytes::from_static(&[0]));
+                let start = Instant::now();
+                encoder.encode(&mut cx, req).await.unwrap();
+                decoder.decode::<Bytes, _>(&mut cx).await.unwrap().unwrap();
+                latencies.push(start.elapsed());
+            }
+        })
+        .await;
+    latencies
+}
+
+/// Returns the requests per second of the hot connection and the p99 latency of the idle ones.
+fn run(multiplex: bool, yield_budget: usize) -> (f64, Duration) {
+    let addr = serve(multiplex, yield_budget);
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    rt.block_on(async move {
+        let stop = Arc::new(AtomicBool::new(false));
+        let responses = Arc::new(AtomicU64::new(0));
+        let idles = (0..IDLE_CONNS)
+            .map(|_| tokio::spawn(idle(addr, stop.clone())))
+            .collect::<Vec<_>>();
+        let hot = tokio::spawn(hot(addr, stop.clone(), responses.clone()));
+
+        tokio::time::sleep(DURATION).await;
+        stop.store(true, Ordering::Relaxed);
+        let throughput = responses.load(Ordering::Relaxed) as f64 / DURATION.as_secs_f64();
+        hot.await.unwrap();
+
+        let mut latencies = Vec::new();
+        for idle in idles {
+            latencies.extend(idle.await.unwrap());
+        }
+        latencies.sort_unstable();
+        let p99 = latencies[latencies.len() * 99 / 100];
+        (throughput, p99)
+    })
+}
+
+fn main() {
+    let modes = [("pingpong", false), ("multiplex", true)]
+        .into_iter()
+        .filter(|(_, multiplex)| !multiplex || cfg!(feature = "multiplex"));
+
+    println!(
+        "{:<10} {:>6} {:>12} {:>16}",
+        "mode", "budget", "hot req/s", "background p99"
+    );
+    for (name, multiplex) in modes {
+        for yield_budget in [0, DEFAULT_YIELD_BUDGET] {
+            let (throughput, p99) = run(multiplex, yield_budget);
+            let p99 = format!("{p99:?}");
+            println!("{name:<10} {yield_budget:>6} {throughput:>12.0} {p99:>16}");
+        }
+    }
+}
@@ -15,7 +15,7 @@
 //!         volo_gen::volo::example::item::ItemServiceClientBuilder::new("volo-example-item")
 //!             .layer(LogLayer)
 //!             .target(addr)
-//!             .build()
+//!             .build_unchecked()
 //!     };
 //! }
 //!
@@ -36,7 +36,7 @@
 use metainfo::{FastStrMap, TypeMap};
 use volo::net::Address;
 
-use crate::context::Config;
+use crate::context::{Config, ReceivedResponseMeta};
 
 #[derive(Debug, Default)]
 pub struct CallOpt {
@@ -53,6 +53,9 @@ pub struct CallOpt {
     pub caller_faststr_tags: FastStrMap,
     /// Sets the caller tags for the call.
     pub caller_tags: TypeMap,
+    /// Receives the string headers of TTHeader set by the server for the response, see
+    /// [`ResponseMeta`](crate::context::ResponseMeta).
+    pub response_meta: Option<ReceivedResponseMeta>,
 }
 
 impl CallOpt {
@@ -0,0 +1,83 @@
+//! The thrift rejection of [`volo::circuit_breaker::CircuitBreakerLayer`].
+//!
+//! A rejected call fails with an application exception, whose message starts with
+//! [`CIRCUIT_OPEN`], which can be recognized by [`is_circuit_open`]. The rejected calls are not
+//! counted as the failures of the callee by the outlier detection or another circuit breaker.
+
+use pilota::thrift::{ApplicationException, ApplicationExceptionKind};
+use volo::circuit_breaker::{CircuitBreakerRejection, CircuitOpen};
+
+use crate::{context::ClientContext, ClientError};
+
+/// The prefix of the message of the application exception of a rejected call.
+pub const CIRCUIT_OPEN: &str = "[volo] circuit open";
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThriftCircuitBreakerRejection;
+
+impl CircuitBreakerRejection<ClientContext, ClientError> for ThriftCircuitBreakerRejection {
+    fn reject(&self, _cx: &mut ClientContext, open: CircuitOpen) -> ClientError {
+        ClientError::Application(ApplicationException::new(
+            ApplicationExceptionKind::INTERNAL_ERROR,
+            format!(
+                "{CIRCUIT_OPEN}, retry after {}ms",
+                open.retry_after.as_millis()
+            ),
+        ))
+    }
+}
+
+/// Returns whether the exception is returned for a call rejected by the circuit breaker.
+pub fn is_circuit_open(e: &ApplicationException) -> bool {
+    e.to_string().contains(CIRCUIT_OPEN)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use motore::{layer::Layer, service::Service};
+    use pilota::thrift::{TMessageType, TransportException};
+    use volo::{
+        circuit_breaker::{CircuitBreaker, CircuitBreakerConfig, CircuitBreakerLayer},
+        context::{Context, Role, RpcInfo},
+    };
+
+    use super::*;
+
+    /// Fails with a transport error.
+    struct Failing;
+
+    impl Service<ClientContext, ()> for Failing {
+        type Response = ();
+        type Error = ClientError;
+
+        async fn call(&self, _cx: &mut ClientContext, _req: ()) -> Result<(), ClientError> {
+            Err(ClientError::Transport(TransportException::from(
+                std::io::Error::from(std::io::ErrorKind::ConnectionRefused),
+            )))
+        }
+    }
+
+    #[tokio::test]
+    async fn reject() {
+        let breaker = Arc::new(CircuitBreaker::new(
+            CircuitBreakerConfig::default().min_requests(2),
+        ));
+        let service =
+            CircuitBreakerLayer::new(breaker.clone(), ThriftCircuitBreakerRejection).layer(Failing);
+
+        let mut cx = ClientContext::new(1, RpcInfo::with_role(Role::Client), TMessageType::Call);
+        cx.rpc_info_mut()
+            .callee_mut()
+            .set_service_name("item".into());
+        for _ in 0..2 {
+            let err = service.call(&mut cx, ()).await.unwrap_err();
+            assert!(matches!(err, ClientError::Transport(_)));
+        }
+        let ClientError::Application(e) = service.call(&mut cx, ()).await.unwrap_err() else {
+            panic!("unexpected error");
+        };
+        assert!(is_circuit_open(&e));
+    }
+}
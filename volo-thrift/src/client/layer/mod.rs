@@ -1 +1,3 @@
+pub mod circuit_breaker;
+pub mod retry;
 pub mod timeout;
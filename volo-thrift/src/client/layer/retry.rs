@@ -0,0 +1,76 @@
+//! The thrift strategy of [`volo::retry::RetryLayer`].
+//!
+//! A call will be retried if:
+//! - it fails with a transport error, or
+//! - it fails with an application exception or biz error, and the server explicitly marks it as
+//!   retryable by sending a non-negative `retry-pushback-ms` in TTHeader.
+//!
+//! A negative `retry-pushback-ms` aborts the retry, and the requests rejected by the rate limit
+//! of the server are never retried, see [`is_rate_limited`].
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use volo::retry::{RetryLayer, RetryPolicy};
+//! use volo_thrift::client::{layer::retry::ThriftRetryStrategy, CallOpt};
+//!
+//! let client = volo_gen::volo::example::ItemServiceClientBuilder::new("item")
+//!     .layer_outer(RetryLayer::new(ThriftRetryStrategy).policy(RetryPolicy::new(2)))
+//!     .build()
+//!     .unwrap();
+//!
+//! // override the policy for a method
+//! let mut callopt = CallOpt::default();
+//! callopt.config.set_retry_policy(Some(RetryPolicy::disabled()));
+//! let resp = client.with_callopt(callopt).create_item(req).await;
+//! ```
+
+use volo::{
+    context::Context,
+    loadbalance::error::Retryable,
+    retry::{Classification, Pushback, RetryPolicy, RetryStrategy},
+};
+
+use crate::{
+    context::{ClientContext, ThriftContext},
+    server::layer::rate_limit::is_rate_limited,
+    ClientError,
+};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThriftRetryStrategy;
+
+impl<Req, Resp> RetryStrategy<ClientContext, Req, Resp, ClientError> for ThriftRetryStrategy
+where
+    Req: Clone,
+{
+    fn policy(&self, cx: &ClientContext) -> Option<RetryPolicy> {
+        cx.rpc_info().config().retry_policy()
+    }
+
+    fn clone_request(&self, req: &Req) -> Option<Req> {
+        Some(req.clone())
+    }
+
+    fn classify(
+        &self,
+        cx: &mut ClientContext,
+        result: &Result<Resp, ClientError>,
+    ) -> Classification {
+        let Err(err) = result else {
+            return Classification::Done;
+        };
+        if matches!(err, ClientError::Application(e) if is_rate_limited(e)) {
+            return Classification::Done;
+        }
+        let pushback = cx.stats().retry_pushback();
+        let retryable = err.retryable()
+            || (matches!(pushback, Some(Pushback::Delay(_)))
+                && matches!(err, ClientError::Application(_) | ClientError::Biz(_)));
+        Classification::new(retryable, pushback)
+    }
+
+    fn prepare(&self, cx: &mut ClientContext) {
+        cx.stats_mut().reset();
+    }
+}
@@ -1,8 +1,12 @@
 //! Applies a timeout to request
 //! if the inner service's call does not complete within specified timeout, the response will be
 //! aborted.
+//!
+//! The timeout is limited by the [`Deadline`] inherited from the upstream, see
+//! [`volo::context::Deadline`].
 use motore::{layer::Layer, service::Service};
 use tracing::warn;
+use volo::context::{Deadline, DeadlineExt};
 
 use crate::context::ClientContext;
 
@@ -25,29 +29,45 @@ where
         cx: &'cx mut ClientContext,
         req: Req,
     ) -> Result<Self::Response, Self::Error> {
-        match cx.rpc_info.config().rpc_timeout() {
-            Some(duration) => {
-                let start = std::time::Instant::now();
-                match tokio::time::timeout(duration, self.inner.call(cx, req)).await {
-                    Ok(r) => r.map_err(Into::into),
-                    Err(_) => {
-                        let msg = format!(
-                            "[VOLO] thrift rpc call timeout, rpcinfo: {:?}, elpased: {:?}, \
-                             timeout config: {:?}",
-                            cx.rpc_info,
-                            start.elapsed(),
-                            duration
-                        );
-                        warn!(msg);
-                        Err(crate::ApplicationException::new(
-                            crate::ApplicationExceptionKind::INTERNAL_ERROR,
-                            msg,
-                        )
-                        .into())
-                    }
-                }
+        // the earliest of the timeout of this attempt and the deadline of the whole call, e.g. the
+        // one inherited from the upstream
+        let timeout = cx.rpc_info.config().rpc_timeout();
+        let Some(deadline) = timeout
+            .map(Deadline::after)
+            .map(|deadline| deadline.earliest(cx.deadline()))
+            .or_else(|| cx.deadline())
+        else {
+            return self.inner.call(cx, req).await.map_err(Into::into);
+        };
+        // the transport sends the remaining time to the server
+        let remaining = deadline.remaining();
+        if !timeout.is_some_and(|timeout| timeout <= remaining) {
+            cx.rpc_info.config_mut().set_rpc_timeout(Some(remaining));
+        }
+
+        let start = std::time::Instant::now();
+        let result = if deadline.is_elapsed() {
+            None
+        } else {
+            deadline.timeout(self.inner.call(cx, req)).await
+        };
+        match result {
+            Some(r) => r.map_err(Into::into),
+            None => {
+                let msg = format!(
+                    "[VOLO] thrift rpc call timeout, rpcinfo: {:?}, elpased: {:?}, timeout \
+                     config: {:?}",
+                    cx.rpc_info,
+                    start.elapsed(),
+                    timeout
+                );
+                warn!(msg);
+                Err(crate::ApplicationException::new(
+                    crate::ApplicationExceptionKind::INTERNAL_ERROR,
+                    msg,
+                )
+                .into())
             }
-            None => self.inner.call(cx, req).await.map_err(Into::into),
         }
     }
 }
@@ -19,13 +19,15 @@ use pilota::thrift::TMessageType;
 use tokio::time::Duration;
 use volo::{
     client::WithOptService,
+    config::ConfigError,
     context::{Context, Endpoint, Role, RpcInfo},
     discovery::{Discover, DummyDiscover},
     loadbalance::{random::WeightedRandomBalance, LbConfig, MkLbLayer},
     net::{
-        dial::{DefaultMakeTransport, MakeTransport},
+        dial::{ConnectRetry, DefaultMakeTransport, MakeTransport},
         Address,
     },
+    readiness::ReadinessSource,
     FastStr,
 };
 
@@ -41,6 +43,8 @@ use crate::{
 
 mod callopt;
 pub use callopt::CallOpt;
+pub mod warmup;
+pub use warmup::{WarmupConfig, WarmupError, WarmupSummary};
 
 use self::layer::timeout::TimeoutLayer;
 
@@ -49,6 +53,8 @@ pub mod layer;
 pub struct ClientBuilder<IL, OL, MkClient, Req, Resp, MkT, MkC, LB> {
     config: Config,
     pool: Option<pool::Config>,
+    warmup: Option<WarmupConfig>,
+    warmup_resolve: Option<warmup::Resolve>,
     callee_name: FastStr,
     caller_name: FastStr,
     address: Option<Address>, // maybe address use Arc avoid memory alloc
@@ -83,6 +89,8 @@ impl<C, Req, Resp>
         ClientBuilder {
             config: Default::default(),
             pool: None,
+            warmup: None,
+            warmup_resolve: None,
             caller_name: "".into(),
             callee_name: FastStr::new(service_name),
             address: None,
@@ -113,6 +121,8 @@ impl<IL, OL, C, Req, Resp, MkT, MkC, LB, DISC>
         ClientBuilder {
             config: self.config,
             pool: self.pool,
+            warmup: self.warmup,
+            warmup_resolve: self.warmup_resolve,
             caller_name: self.caller_name,
             callee_name: self.callee_name,
             address: self.address,
@@ -139,6 +149,9 @@ impl<IL, OL, C, Req, Resp, MkT, MkC, LB, DISC>
         ClientBuilder {
             config: self.config,
             pool: self.pool,
+            warmup: self.warmup,
+            // the resolver of `warmup` is bound to the previous discover
+            warmup_resolve: None,
             caller_name: self.caller_name,
             callee_name: self.callee_name,
             address: self.address,
@@ -163,6 +176,21 @@ impl<IL, OL, C, Req, Resp, MkT, MkC, LB, DISC>
         self.mk_lb = self.mk_lb.retry_count(count);
         self
     }
+
+    /// Warms up the connections to the instances when the client is built, see [`warmup`].
+    ///
+    /// The instances are resolved by the current discover, so this should be called after
+    /// [`discover`](Self::discover) and [`mk_load_balance`](ClientBuilder::mk_load_balance), or
+    /// [`build`](ClientBuilder::build) fails unless the [`address`](ClientBuilder::address) is set.
+    /// Default is no warmup.
+    pub fn warmup(mut self, config: WarmupConfig) -> Self
+    where
+        DISC: Discover + Clone,
+    {
+        self.warmup = Some(config);
+        self.warmup_resolve = Some(warmup::resolve(self.mk_lb.get_discover().clone()));
+        self
+    }
 }
 
 impl<IL, OL, C, Req, Resp, MkT, MkC, LB> ClientBuilder<IL, OL, C, Req, Resp, MkT, MkC, LB> {
@@ -188,6 +216,16 @@ impl<IL, OL, C, Req, Resp, MkT, MkC, LB> ClientBuilder<IL, OL, C, Req, Resp, MkT
         self
     }
 
+    /// Sets the retry of the failed connects, e.g. when the connection is refused during the
+    /// deployment of the server.
+    ///
+    /// It only retries before any bytes are written, which is independent of the retry of the
+    /// calls. Default is no retry.
+    pub fn connect_retry(mut self, retry: ConnectRetry) -> Self {
+        self.config.set_connect_retry(Some(retry));
+        self
+    }
+
     /// Sets the read write timeout for the client(a.k.a. IO timeout).
     pub fn read_write_timeout(mut self, timeout: Option<Duration>) -> Self {
         self.config.set_read_write_timeout(timeout);
@@ -223,6 +261,9 @@ impl<IL, OL, C, Req, Resp, MkT, MkC, LB> ClientBuilder<IL, OL, C, Req, Resp, MkT
         ClientBuilder {
             config: self.config,
             pool: self.pool,
+            warmup: self.warmup,
+            // the resolver of `warmup` is bound to the previous discover
+            warmup_resolve: None,
             caller_name: self.caller_name,
             callee_name: self.callee_name,
             address: self.address,
@@ -257,6 +298,8 @@ impl<IL, OL, C, Req, Resp, MkT, MkC, LB> ClientBuilder<IL, OL, C, Req, Resp, MkT
         ClientBuilder {
             config: self.config,
             pool: self.pool,
+            warmup: self.warmup,
+            warmup_resolve: self.warmup_resolve,
             caller_name: self.caller_name,
             callee_name: self.callee_name,
             address: self.address,
@@ -285,6 +328,8 @@ impl<IL, OL, C, Req, Resp, MkT, MkC, LB> ClientBuilder<IL, OL, C, Req, Resp, MkT
         ClientBuilder {
             config: self.config,
             pool: self.pool,
+            warmup: self.warmup,
+            warmup_resolve: self.warmup_resolve,
             caller_name: self.caller_name,
             callee_name: self.callee_name,
             address: self.address,
@@ -334,6 +379,8 @@ impl<IL, OL, C, Req, Resp, MkT, MkC, LB> ClientBuilder<IL, OL, C, Req, Resp, MkT
         ClientBuilder {
             config: self.config,
             pool: self.pool,
+            warmup: self.warmup,
+            warmup_resolve: self.warmup_resolve,
             caller_name: self.caller_name,
             callee_name: self.callee_name,
             address: self.address,
@@ -373,6 +420,8 @@ impl<IL, OL, C, Req, Resp, MkT, MkC, LB> ClientBuilder<IL, OL, C, Req, Resp, MkT
         ClientBuilder {
             config: self.config,
             pool: self.pool,
+            warmup: self.warmup,
+            warmup_resolve: self.warmup_resolve,
             caller_name: self.caller_name,
             callee_name: self.callee_name,
             address: self.address,
@@ -412,6 +461,8 @@ impl<IL, OL, C, Req, Resp, MkT, MkC, LB> ClientBuilder<IL, OL, C, Req, Resp, MkT
         ClientBuilder {
             config: self.config,
             pool: self.pool,
+            warmup: self.warmup,
+            warmup_resolve: self.warmup_resolve,
             caller_name: self.caller_name,
             callee_name: self.callee_name,
             address: self.address,
@@ -451,6 +502,8 @@ impl<IL, OL, C, Req, Resp, MkT, MkC, LB> ClientBuilder<IL, OL, C, Req, Resp, MkT
         ClientBuilder {
             config: self.config,
             pool: self.pool,
+            warmup: self.warmup,
+            warmup_resolve: self.warmup_resolve,
             caller_name: self.caller_name,
             callee_name: self.callee_name,
             address: self.address,
@@ -470,13 +523,27 @@ impl<IL, OL, C, Req, Resp, MkT, MkC, LB> ClientBuilder<IL, OL, C, Req, Resp, MkT
         }
     }
 
+    /// Selects the transport mode of the client, which must be the same as the one of the server,
+    /// see [`Server::multiplex`](crate::server::Server::multiplex).
+    ///
+    /// - Pingpong (`false`, the default): a connection carries one call at a time, and the
+    ///   concurrent calls take different connections from the pool configured by
+    ///   [`pool_config`](Self::pool_config). This works with any thrift server.
+    /// - Multiplex (`true`): the concurrent calls share the pooled connections, and the responses
+    ///   are routed back to the callers by the seq id, so it takes far fewer connections but needs
+    ///   a server in the multiplex mode as well.
+    ///
+    /// Both modes dial the connections by the same transport and encode the messages by the same
+    /// codec, so the timeouts and the codec settings apply to either of them.
+    ///
+    /// This is only available with the `multiplex` feature.
     #[cfg(feature = "multiplex")]
-    /// Enable multiplexing for the client.
-    #[doc(hidden)]
     pub fn multiplex(self, multiplex: bool) -> ClientBuilder<IL, OL, C, Req, Resp, MkT, MkC, LB> {
         ClientBuilder {
             config: self.config,
             pool: self.pool,
+            warmup: self.warmup,
+            warmup_resolve: self.warmup_resolve,
             caller_name: self.caller_name,
             callee_name: self.callee_name,
             address: self.address,
@@ -499,6 +566,32 @@ impl<IL, OL, C, Req, Resp, MkT, MkC, LB> ClientBuilder<IL, OL, C, Req, Resp, MkT
     pub fn get_callee_name(&self) -> &FastStr {
         &self.callee_name
     }
+
+    /// Collects the problems of the configuration.
+    fn check(&self, err: &mut ConfigError) {
+        self.config.check(err);
+        err.ensure_address("address", self.address.as_ref());
+        if let Some(warmup) = &self.warmup {
+            err.ensure(
+                warmup.connections_per_endpoint > 0,
+                "warmup",
+                "`connections_per_endpoint` must be greater than zero",
+            );
+            // the warmed up connections are closed at once without being pooled
+            err.ensure(
+                !self.pool.as_ref().is_some_and(pool::Config::keeps_no_idle),
+                "pool",
+                "`max_idle_per_key` is zero, the connections warmed up can't be kept",
+            );
+        }
+        // the resolver is dropped by changing the discover or the load balance after `warmup`
+        err.ensure(
+            self.warmup.is_none() || self.address.is_some() || self.warmup_resolve.is_some(),
+            "warmup",
+            "the instances to warm up are unknown, `warmup` should be set after `discover` and \
+             `mk_load_balance`",
+        );
+    }
 }
 
 #[derive(Clone)]
@@ -570,7 +663,7 @@ where
         + Clone
         + Sync,
     Req: EntryMessage + Send + 'static + Sync + Clone,
-    Resp: EntryMessage + Send + 'static,
+    Resp: EntryMessage + Send + Sync + 'static,
     IL: Layer<MessageService<Resp, MkT, MkC>>,
     IL::Service:
         Service<ClientContext, Req, Response = Option<Resp>> + Sync + Clone + Send + 'static,
@@ -582,8 +675,49 @@ where
         Service<ClientContext, Req, Response = Option<Resp>> + 'static + Send + Clone + Sync,
     <OL::Service as Service<ClientContext, Req>>::Error: Send + Sync + Into<ClientError>,
 {
-    /// Build volo client.
-    pub fn build(mut self) -> C::Target {
+    /// Builds the client, or returns all the problems of the configuration.
+    ///
+    /// If the [`warmup`](Self::warmup) is set, it's started in the background when this is
+    /// called in a tokio runtime, see [`build_warmed`](Self::build_warmed) for waiting for it.
+    pub fn build(self) -> Result<C::Target, ConfigError> {
+        let (mk_client, client) = self.build_client()?;
+        if client.inner.warmup.is_some() {
+            warmup::spawn(client.inner.clone());
+        }
+        Ok(mk_client.mk_client(client))
+    }
+
+    /// Builds the client and waits for the [`warmup`](Self::warmup) of the connections.
+    ///
+    /// Fails if the configuration is invalid, or the warmup is
+    /// [`required`](WarmupConfig::required) and any connection failed to be established.
+    pub async fn build_warmed(self) -> Result<C::Target, WarmupError> {
+        let (mk_client, client) = self.build_client()?;
+        client.warmup().await?;
+        Ok(mk_client.mk_client(client))
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn build_client(
+        mut self,
+    ) -> Result<
+        (
+            C,
+            Client<
+                BoxCloneService<
+                    ClientContext,
+                    Req,
+                    Option<Resp>,
+                    <OL::Service as Service<ClientContext, Req>>::Error,
+                >,
+            >,
+        ),
+        ConfigError,
+    > {
+        let mut err = ConfigError::new();
+        self.check(&mut err);
+        err.into_result()?;
+
         if let Some(timeout) = self.config.connect_timeout() {
             self.make_transport.set_connect_timeout(Some(timeout));
         }
@@ -593,25 +727,39 @@ where
         if let Some(timeout) = self.config.read_write_timeout() {
             self.make_transport.set_write_timeout(Some(timeout));
         }
+        if let Some(retry) = self.config.connect_retry() {
+            self.make_transport.set_connect_retry(Some(retry));
+        }
+        #[cfg(not(feature = "multiplex"))]
+        let (inner, connector) = {
+            let client = pingpong::Client::new(self.make_transport, self.pool, self.make_codec);
+            let connector = warmup::connector(client.clone());
+            (client, connector)
+        };
+        #[cfg(feature = "multiplex")]
+        let (inner, connector) = if !self.multiplex {
+            let client = pingpong::Client::new(self.make_transport, self.pool, self.make_codec);
+            let connector = warmup::connector(client.clone());
+            (motore::utils::Either::A(client), connector)
+        } else {
+            let client = crate::transport::multiplex::Client::new(
+                self.make_transport,
+                self.pool,
+                self.make_codec,
+            );
+            let connector = warmup::connector(client.clone());
+            (motore::utils::Either::B(client), connector)
+        };
         let msg_svc = MessageService {
-            #[cfg(not(feature = "multiplex"))]
-            inner: pingpong::Client::new(self.make_transport, self.pool, self.make_codec),
-            #[cfg(feature = "multiplex")]
-            inner: if !self.multiplex {
-                motore::utils::Either::A(pingpong::Client::new(
-                    self.make_transport,
-                    self.pool,
-                    self.make_codec,
-                ))
-            } else {
-                motore::utils::Either::B(crate::transport::multiplex::Client::new(
-                    self.make_transport,
-                    self.pool,
-                    self.make_codec,
-                ))
-            },
+            inner,
             read_biz_error: self.enable_biz_error,
         };
+        let warmup = self.warmup.map(|config| warmup::Warmup {
+            config,
+            resolve: self.warmup_resolve,
+            connector,
+            established: Default::default(),
+        });
 
         let transport = if !self.disable_timeout_layer {
             BoxCloneService::new(self.outer_layer.layer(BoxCloneService::new(
@@ -623,16 +771,27 @@ where
             )))
         };
 
-        self.mk_client.mk_client(Client {
+        let client = Client {
             inner: Arc::new(ClientInner {
                 callee_name: self.callee_name,
                 config: self.config,
                 address: self.address,
                 caller_name: self.caller_name,
                 seq_id: AtomicI32::new(0),
+                warmup,
             }),
             transport,
-        })
+        };
+        Ok((self.mk_client, client))
+    }
+
+    /// Builds the client, which is useful for the static configurations.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the configuration is invalid, see [`ClientBuilder::build`].
+    pub fn build_unchecked(self) -> C::Target {
+        self.build().unwrap_or_else(|err| panic!("{err}"))
     }
 }
 
@@ -655,6 +814,7 @@ struct ClientInner {
     config: Config,
     address: Option<Address>,
     seq_id: AtomicI32,
+    warmup: Option<warmup::Warmup>,
 }
 
 impl<S> Client<S> {
@@ -718,6 +878,53 @@ impl<S> Client<S> {
         RpcInfo::new(Role::Client, method.into(), caller, callee, config)
     }
 
+    /// Warms up the connections by the [`WarmupConfig`] of the builder, see [`warmup`].
+    ///
+    /// Returns an empty summary if the warmup is not set, or [`WarmupError::Incomplete`] if it's
+    /// required and any connection failed to be established.
+    pub async fn warmup(&self) -> Result<WarmupSummary, WarmupError> {
+        let Some(warmup) = &self.inner.warmup else {
+            return Ok(WarmupSummary::default());
+        };
+        let summary = warmup.run_scoped(&self.inner).await;
+        if warmup.config.required && !summary.is_complete() {
+            return Err(WarmupError::Incomplete(summary));
+        }
+        Ok(summary)
+    }
+
+    /// Returns the number of the connections established by the last warmup.
+    pub fn warmed_connections(&self) -> usize {
+        self.inner.warmup.as_ref().map_or(0, |warmup| {
+            warmup
+                .established
+                .load(std::sync::atomic::Ordering::Relaxed)
+        })
+    }
+
+    /// Returns the readiness of the client for [`Readiness::register`], which is ready if the
+    /// instances are resolved and a connection is established to any of them by the
+    /// [`WarmupConfig`] of the builder, including its ping.
+    ///
+    /// It's never ready if the warmup is not set.
+    ///
+    /// [`Readiness::register`]: volo::readiness::Readiness::register
+    pub fn readiness(&self) -> impl ReadinessSource {
+        let inner = self.inner.clone();
+        move || {
+            let inner = inner.clone();
+            async move {
+                match &inner.warmup {
+                    Some(warmup) => warmup.probe(&inner).await,
+                    None => Err(format!(
+                        "the warmup of {} is not configured",
+                        inner.callee_name
+                    )),
+                }
+            }
+        }
+    }
+
     pub fn with_opt<Opt>(self, opt: Opt) -> Client<WithOptService<S, Opt>> {
         Client {
             transport: WithOptService::new(self.transport, opt),
@@ -792,3 +999,94 @@ impl_client!((self, &mut cx, req) => async move {
             .await
     }
 });
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use volo::discovery::StaticDiscover;
+
+    use super::*;
+
+    type Builder = ClientBuilder<
+        Identity,
+        Identity,
+        (),
+        (),
+        (),
+        DefaultMakeTransport,
+        DefaultMakeCodec<MakeTTHeaderCodec<MakeFramedCodec<MakeThriftCodec>>>,
+        LbConfig<WeightedRandomBalance<<DummyDiscover as Discover>::Key>, DummyDiscover>,
+    >;
+
+    fn problems<IL, OL, C, Req, Resp, MkT, MkC, LB>(
+        builder: &ClientBuilder<IL, OL, C, Req, Resp, MkT, MkC, LB>,
+    ) -> Vec<&'static str> {
+        let mut err = ConfigError::new();
+        builder.check(&mut err);
+        err.problems().iter().map(|p| p.field).collect()
+    }
+
+    fn discover() -> StaticDiscover {
+        StaticDiscover::from(vec![SocketAddr::from(([127, 0, 0, 1], 8080))])
+    }
+
+    #[test]
+    fn warmup_after_discover() {
+        let builder = Builder::new("echo", ())
+            .discover(discover())
+            .warmup(WarmupConfig::default());
+        assert!(problems(&builder).is_empty());
+    }
+
+    #[test]
+    fn warmup_before_discover() {
+        let builder = Builder::new("echo", ())
+            .warmup(WarmupConfig::default())
+            .discover(discover());
+        assert_eq!(problems(&builder), ["warmup"]);
+
+        let builder = Builder::new("echo", ())
+            .warmup(WarmupConfig::default())
+            .mk_load_balance(LbConfig::new(
+                WeightedRandomBalance::<<StaticDiscover as Discover>::Key>::new(),
+                discover(),
+            ));
+        assert_eq!(problems(&builder), ["warmup"]);
+
+        // the address is warmed up without the discover
+        let builder = Builder::new("echo", ())
+            .warmup(WarmupConfig::default())
+            .discover(discover())
+            .address(SocketAddr::from(([127, 0, 0, 1], 8080)));
+        assert!(problems(&builder).is_empty());
+    }
+
+    #[test]
+    fn invalid_address() {
+        let builder = Builder::new("echo", ()).address(SocketAddr::from(([127, 0, 0, 1], 0)));
+        assert_eq!(problems(&builder), ["address"]);
+
+        let builder = Builder::new("echo", ()).address(SocketAddr::from(([0, 0, 0, 0], 8080)));
+        assert_eq!(problems(&builder), ["address"]);
+    }
+
+    #[test]
+    fn zero_pool_with_warmup() {
+        let builder = Builder::new("echo", ())
+            .pool_config(pool::Config::default().max_idle_per_key(0))
+            .discover(discover());
+        assert!(problems(&builder).is_empty());
+
+        let builder = builder.warmup(WarmupConfig::default());
+        assert_eq!(problems(&builder), ["pool"]);
+
+        let builder = Builder::new("echo", ())
+            .discover(discover())
+            .warmup(WarmupConfig {
+                connections_per_endpoint: 0,
+                ..Default::default()
+            });
+        assert_eq!(problems(&builder), ["warmup"]);
+    }
+}
@@ -0,0 +1,519 @@
+//! Warming up the connections of a client before the first requests.
+//!
+//! The first requests of a new client pay for the discovery and the connection setup, which
+//! makes the latency bad after the cold start. With a [`WarmupConfig`], the client resolves the
+//! instances and establishes the pooled connections to each of them in advance:
+//!
+//! ```rust,ignore
+//! let client = ItemServiceClientBuilder::new("item")
+//!     .discover(discover)
+//!     .warmup(WarmupConfig {
+//!         connections_per_endpoint: 4,
+//!         ping_method: Some("ping".into()),
+//!         ..Default::default()
+//!     })
+//!     .build_warmed()
+//!     .await?;
+//! ```
+//!
+//! [`ClientBuilder::build`](super::ClientBuilder::build) starts the warmup in the background if
+//! it's called in a tokio runtime, while
+//! [`ClientBuilder::build_warmed`](super::ClientBuilder::build_warmed) waits for it, and fails if
+//! the warmup is [`required`](WarmupConfig::required) and not complete. It can also be run again
+//! by [`Client::warmup`](super::Client::warmup), e.g. after the instances change.
+//!
+//! The pingpong connections are established as many as configured, while a multiplex connection
+//! is shared by all the requests, so only one is established for each instance.
+
+use std::{
+    cell::RefCell,
+    fmt, io,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use bytes::Bytes;
+use futures::{future::BoxFuture, Future, StreamExt};
+use pilota::thrift::{TMessageType, TransportException};
+use volo::{
+    config::ConfigError,
+    context::{Endpoint, Role, RpcInfo},
+    discovery::Discover,
+    loadbalance::error::LoadBalanceError,
+    net::Address,
+    FastStr,
+};
+
+use super::ClientInner;
+use crate::{context::ClientContext, ClientError};
+
+/// The config of warming up the connections of a client, see the [module docs](self).
+#[derive(Clone, Debug)]
+pub struct WarmupConfig {
+    /// The number of the connections established to each instance.
+    ///
+    /// Default is 1.
+    pub connections_per_endpoint: usize,
+    /// The max number of the connections being established at the same time.
+    ///
+    /// Default is 16.
+    pub parallelism: usize,
+    /// The timeout of establishing each connection, including its ping.
+    ///
+    /// Default is 3 seconds.
+    pub timeout: Duration,
+    /// The method called with no arguments on each connection after it's established, which
+    /// primes the TLS session and the caches of the server.
+    ///
+    /// Any response of the server, including an application exception, e.g. for an unknown
+    /// method, is fine, only the transport errors fail the connection. Default is `None`.
+    pub ping_method: Option<FastStr>,
+    /// Whether [`ClientBuilder::build_warmed`](super::ClientBuilder::build_warmed) and
+    /// [`Client::warmup`](super::Client::warmup) fail if any connection fails to be established.
+    ///
+    /// Default is false.
+    pub required: bool,
+}
+
+impl Default for WarmupConfig {
+    fn default() -> Self {
+        Self {
+            connections_per_endpoint: 1,
+            parallelism: 16,
+            timeout: Duration::from_secs(3),
+            ping_method: None,
+            required: false,
+        }
+    }
+}
+
+/// The result of a warmup.
+#[derive(Debug, Default)]
+pub struct WarmupSummary {
+    /// The number of the instances resolved.
+    pub endpoints: usize,
+    /// The number of the connections established and put into the pool.
+    pub established: usize,
+    /// The failures of resolving the instances or establishing the connections.
+    pub failures: Vec<WarmupFailure>,
+}
+
+impl WarmupSummary {
+    /// Returns whether all the connections are established.
+    pub fn is_complete(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+impl fmt::Display for WarmupSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} connections established to {} endpoints, {} failed",
+            self.established,
+            self.endpoints,
+            self.failures.len()
+        )?;
+        if let Some(failure) = self.failures.first() {
+            write!(f, ", e.g. {failure}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A failure of a warmup.
+#[derive(Debug)]
+pub struct WarmupFailure {
+    /// The instance failed, or `None` if the instances failed to be resolved.
+    pub address: Option<Address>,
+    pub error: ClientError,
+}
+
+impl fmt::Display for WarmupFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.address {
+            Some(address) => write!(f, "{address}: {}", self.error),
+            None => write!(f, "discovery: {}", self.error),
+        }
+    }
+}
+
+/// The error of a required warmup.
+#[derive(Debug, thiserror::Error)]
+pub enum WarmupError {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error("warmup is not complete: {0}")]
+    Incomplete(WarmupSummary),
+}
+
+/// Puts a connection back into the pool.
+pub(crate) type Release = Box<dyn FnOnce() + Send>;
+
+/// Establishes the pooled connections of a transport.
+pub(crate) trait Connect: Clone + Send + Sync + 'static {
+    /// Takes a connection of the target from the pool or establishes a new one, and pings it by
+    /// the context if any.
+    fn connect(
+        &self,
+        target: Address,
+        ping: Option<ClientContext>,
+    ) -> impl Future<Output = Result<Release, ClientError>> + Send;
+}
+
+pub(crate) type Connector = Arc<
+    dyn Fn(Address, Option<ClientContext>) -> BoxFuture<'static, Result<Release, ClientError>>
+        + Send
+        + Sync,
+>;
+
+pub(crate) fn connector<C: Connect>(connect: C) -> Connector {
+    Arc::new(move |target, ping| {
+        let connect = connect.clone();
+        Box::pin(async move { connect.connect(target, ping).await })
+    })
+}
+
+/// Resolves the addresses of the instances of the callee.
+pub(crate) type Resolve =
+    Arc<dyn Fn(Endpoint) -> BoxFuture<'static, Result<Vec<Address>, ClientError>> + Send + Sync>;
+
+pub(crate) fn resolve<D: Discover + Clone>(discover: D) -> Resolve {
+    Arc::new(move |endpoint| {
+        let discover = discover.clone();
+        Box::pin(async move {
+            match discover.discover(&endpoint).await {
+                Ok(instances) => Ok(instances.iter().map(|i| i.address.clone()).collect()),
+                Err(err) => Err(ClientError::from(Into::<LoadBalanceError>::into(err))),
+            }
+        })
+    })
+}
+
+/// The arguments of the ping, i.e. an empty struct with only the field stop, which is the same
+/// in the binary and the compact protocols.
+pub(crate) fn ping_args() -> Bytes {
+    Bytes::from_static(&[0])
+}
+
+pub(crate) fn unexpected_eof(target: &Address) -> ClientError {
+    ClientError::Transport(TransportException::from(io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        format!("an unexpected end of file from server when warming up {target}"),
+    )))
+}
+
+/// The warmup of a built client.
+pub(crate) struct Warmup {
+    pub(crate) config: WarmupConfig,
+    pub(crate) resolve: Option<Resolve>,
+    pub(crate) connector: Connector,
+    pub(crate) established: AtomicUsize,
+}
+
+impl Warmup {
+    /// Resolves the addresses of the instances to warm up.
+    async fn targets(&self, client: &ClientInner) -> Result<Vec<Address>, ClientError> {
+        match (&client.address, &self.resolve) {
+            (Some(address), _) => Ok(vec![address.clone()]),
+            (None, Some(resolve)) => resolve(Endpoint::new(client.callee_name.clone())).await,
+            (None, None) => Err(ClientError::Transport(TransportException::from(
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "the instances are unknown, `warmup` should be set after `discover`",
+                ),
+            ))),
+        }
+    }
+
+    /// Establishes a connection to the target within the timeout, and pings it if configured.
+    async fn connect(
+        &self,
+        client: &ClientInner,
+        target: &Address,
+    ) -> Result<Release, ClientError> {
+        let ping = self
+            .config
+            .ping_method
+            .as_ref()
+            .map(|method| ping_cx(client, method, target));
+        let connect = (self.connector)(target.clone(), ping);
+        match tokio::time::timeout(self.config.timeout, connect).await {
+            Ok(result) => result,
+            Err(_) => Err(ClientError::Transport(TransportException::from(
+                io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("warming up {target} timed out"),
+                ),
+            ))),
+        }
+    }
+
+    pub(crate) async fn run(&self, client: &ClientInner) -> WarmupSummary {
+        let mut summary = WarmupSummary::default();
+        let targets = match self.targets(client).await {
+            Ok(targets) => targets,
+            Err(error) => {
+                summary.failures.push(WarmupFailure {
+                    address: None,
+                    error,
+                });
+                return summary;
+            }
+        };
+        summary.endpoints = targets.len();
+
+        let attempts = targets.iter().flat_map(|target| {
+            std::iter::repeat(target).take(self.config.connections_per_endpoint)
+        });
+        // the connections are held until all of them are established, so the later ones are
+        // not taken from the pool
+        let results = futures::stream::iter(attempts)
+            .map(|target| async move { (target, self.connect(client, target).await) })
+            .buffer_unordered(self.config.parallelism.max(1))
+            .collect::<Vec<_>>()
+            .await;
+
+        for (target, result) in results {
+            match result {
+                Ok(release) => {
+                    release();
+                    summary.established += 1;
+                }
+                Err(error) => summary.failures.push(WarmupFailure {
+                    address: Some(target.clone()),
+                    error,
+                }),
+            }
+        }
+        self.established
+            .store(summary.established, Ordering::Relaxed);
+        summary
+    }
+
+    /// Runs the warmup in the scope of a metainfo as the calls, which is used by the codecs.
+    pub(crate) async fn run_scoped(&self, client: &ClientInner) -> WarmupSummary {
+        scoped(self.run(client)).await
+    }
+
+    /// Checks the readiness of the client, i.e. the instances are resolved and a connection is
+    /// established to any of them, and returns the reason if not.
+    pub(crate) async fn probe(&self, client: &ClientInner) -> Result<(), String> {
+        let targets = self
+            .targets(client)
+            .await
+            .map_err(|e| format!("discovery: {e}"))?;
+        let mut failure = None;
+        for target in &targets {
+            match scoped(self.connect(client, target)).await {
+                Ok(release) => {
+                    release();
+                    return Ok(());
+                }
+                Err(error) => {
+                    failure.get_or_insert(WarmupFailure {
+                        address: Some(target.clone()),
+                        error,
+                    });
+                }
+            }
+        }
+        match failure {
+            Some(failure) => Err(format!(
+                "no connections established to {} instances, e.g. {failure}",
+                targets.len()
+            )),
+            None => Err("no instances resolved".to_owned()),
+        }
+    }
+}
+
+async fn scoped<F: Future>(future: F) -> F::Output {
+    if metainfo::METAINFO.try_with(|_| {}).is_ok() {
+        future.await
+    } else {
+        metainfo::METAINFO
+            .scope(RefCell::new(metainfo::MetaInfo::default()), future)
+            .await
+    }
+}
+
+/// Runs the warmup of the client in the background, if it's in a tokio runtime.
+pub(crate) fn spawn(client: Arc<ClientInner>) {
+    let Ok(handle) = tokio::runtime::Handle::try_current() else {
+        tracing::warn!(
+            "[VOLO] the warmup of {} is skipped out of a tokio runtime",
+            client.callee_name
+        );
+        return;
+    };
+    handle.spawn(async move {
+        let Some(warmup) = &client.warmup else {
+            return;
+        };
+        let summary = warmup.run_scoped(&client).await;
+        if summary.is_complete() {
+            tracing::debug!("[VOLO] warmup of {}: {summary}", client.callee_name);
+        } else {
+            tracing::warn!("[VOLO] warmup of {}: {summary}", client.callee_name);
+        }
+    });
+}
+
+fn ping_cx(client: &ClientInner, method: &FastStr, target: &Address) -> ClientContext {
+    let caller = Endpoint::new(client.caller_name.clone());
+    let mut callee = Endpoint::new(client.callee_name.clone());
+    callee.set_address(target.clone());
+    ClientContext::new(
+        client.seq_id.fetch_add(1, Ordering::Relaxed),
+        RpcInfo::new(Role::Client, method.clone(), caller, callee, client.config),
+        TMessageType::Call,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::SocketAddr,
+        sync::{atomic::AtomicI32, Mutex},
+    };
+
+    use super::*;
+    use crate::context::Config;
+
+    /// Holds the connections made, and fails the ones of the port 2.
+    #[derive(Clone, Default)]
+    struct Fake {
+        inflight: Arc<AtomicUsize>,
+        max_inflight: Arc<AtomicUsize>,
+        idle: Arc<AtomicUsize>,
+        pinged: Arc<Mutex<Vec<FastStr>>>,
+    }
+
+    impl Connect for Fake {
+        async fn connect(
+            &self,
+            target: Address,
+            ping: Option<ClientContext>,
+        ) -> Result<Release, ClientError> {
+            let inflight = self.inflight.fetch_add(1, Ordering::Relaxed) + 1;
+            self.max_inflight.fetch_max(inflight, Ordering::Relaxed);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            self.inflight.fetch_sub(1, Ordering::Relaxed);
+
+            if let Address::Ip(addr) = &target {
+                if addr.port() == 2 {
+                    return Err(unexpected_eof(&target));
+                }
+            }
+            if let Some(cx) = ping {
+                self.pinged
+                    .lock()
+                    .unwrap()
+                    .push(cx.rpc_info.method().clone());
+            }
+            let idle = self.idle.clone();
+            Ok(Box::new(move || {
+                idle.fetch_add(1, Ordering::Relaxed);
+            }))
+        }
+    }
+
+    fn client() -> ClientInner {
+        ClientInner {
+            callee_name: "callee".into(),
+            caller_name: "caller".into(),
+            config: Config::default(),
+            address: None,
+            seq_id: AtomicI32::new(0),
+            warmup: None,
+        }
+    }
+
+    fn addr(port: u16) -> Address {
+        Address::from(SocketAddr::from(([127, 0, 0, 1], port)))
+    }
+
+    #[tokio::test]
+    async fn summary() {
+        let fake = Fake::default();
+        let warmup = Warmup {
+            config: WarmupConfig {
+                connections_per_endpoint: 4,
+                parallelism: 3,
+                ping_method: Some("ping".into()),
+                ..Default::default()
+            },
+            resolve: Some(Arc::new(|_| {
+                Box::pin(async { Ok(vec![addr(1), addr(2), addr(3)]) })
+            })),
+            connector: connector(fake.clone()),
+            established: AtomicUsize::new(0),
+        };
+
+        let summary = warmup.run_scoped(&client()).await;
+        assert_eq!(summary.endpoints, 3);
+        assert_eq!(summary.established, 8);
+        assert_eq!(summary.failures.len(), 4);
+        assert!(summary.failures.iter().all(|f| f.address == Some(addr(2))));
+        assert!(!summary.is_complete());
+        assert_eq!(warmup.established.load(Ordering::Relaxed), 8);
+        assert_eq!(fake.idle.load(Ordering::Relaxed), 8);
+        assert_eq!(fake.max_inflight.load(Ordering::Relaxed), 3);
+        assert_eq!(*fake.pinged.lock().unwrap(), vec![FastStr::from("ping"); 8]);
+
+        // without the discover
+        let warmup = Warmup {
+            resolve: None,
+            ..warmup
+        };
+        let summary = warmup.run(&client()).await;
+        assert_eq!((summary.endpoints, summary.established), (0, 0));
+        assert!(summary.failures[0].address.is_none());
+
+        // the address takes precedence
+        let mut client = client();
+        client.address = Some(addr(1));
+        let summary = warmup.run(&client).await;
+        assert_eq!((summary.endpoints, summary.established), (1, 4));
+        assert!(summary.is_complete());
+    }
+
+    #[tokio::test]
+    async fn probe() {
+        let fake = Fake::default();
+        let instances = Arc::new(Mutex::new(vec![addr(2), addr(3)]));
+        let warmup = Warmup {
+            config: WarmupConfig::default(),
+            resolve: Some({
+                let instances = instances.clone();
+                Arc::new(move |_| {
+                    let instances = instances.lock().unwrap().clone();
+                    Box::pin(async move { Ok(instances) })
+                })
+            }),
+            connector: connector(fake.clone()),
+            established: AtomicUsize::new(0),
+        };
+
+        // ready by any instance, and the connection is put back
+        assert_eq!(warmup.probe(&client()).await, Ok(()));
+        assert_eq!(fake.idle.load(Ordering::Relaxed), 1);
+
+        *instances.lock().unwrap() = vec![addr(2)];
+        let reason = warmup.probe(&client()).await.unwrap_err();
+        assert!(
+            reason.starts_with("no connections established to 1 instances, e.g. 127.0.0.1:2"),
+            "{reason}"
+        );
+
+        instances.lock().unwrap().clear();
+        assert_eq!(
+            warmup.probe(&client()).await,
+            Err("no instances resolved".to_owned())
+        );
+    }
+}
@@ -5,7 +5,7 @@ use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt};
 use tracing::trace;
 use volo::{context::Role, util::buf_reader::BufReader};
 
-use super::{MakeZeroCopyCodec, ZeroCopyDecoder, ZeroCopyEncoder};
+use super::{capture_frame, MakeZeroCopyCodec, ZeroCopyDecoder, ZeroCopyEncoder};
 use crate::{context::ThriftContext, EntryMessage, ThriftMessage};
 
 /// Default limit according to thrift spec.
@@ -128,6 +128,7 @@ where
                 cx.stats_mut().record_read_end_at();
 
                 let mut buffer = buffer.freeze();
+                capture_frame(cx, &buffer);
                 // set has framed flag
                 cx.extensions_mut().insert(HasFramed);
                 // decode inner
@@ -33,7 +33,7 @@ use linkedbytes::LinkedBytes;
 use pilota::thrift::ThriftException;
 use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt};
 use tracing::{trace, warn};
-use volo::util::buf_reader::BufReader;
+use volo::{net::timestamp::RecvTimestamp, util::buf_reader::BufReader};
 
 use self::{framed::MakeFramedCodec, thrift::MakeThriftCodec, ttheader::MakeTTHeaderCodec};
 use super::{Decoder, Encoder, MakeCodec};
@@ -42,8 +42,27 @@ use crate::{context::ThriftContext, EntryMessage, ThriftMessage};
 pub mod framed;
 pub mod thrift;
 pub mod ttheader;
+pub mod validate;
 // mod mesh_header;
 
+/// This is used to tell the length-prefixed decoders to keep the frame in the context as a
+/// [`RawFrame`], which is inserted by the server when sampling is enabled.
+pub struct CaptureFrame;
+
+/// The payload of a length-prefixed frame (TTHeader or Framed), without the 4-byte length.
+///
+/// It shares the buffer with the decoded message, so no data is copied.
+#[derive(Debug, Clone)]
+pub struct RawFrame(pub Bytes);
+
+/// Keeps a handle of the frame in the context if it is asked by [`CaptureFrame`].
+#[inline]
+pub(crate) fn capture_frame<Cx: ThriftContext>(cx: &mut Cx, frame: &Bytes) {
+    if cx.extensions().contains::<CaptureFrame>() {
+        cx.extensions_mut().insert(RawFrame(frame.clone()));
+    }
+}
+
 /// [`ZeroCopyEncoder`] tries to encode a message without copying large data taking the advantage of
 /// [`LinkedBytes`], which can insert a [`Bytes`] into the middle of a [`BytesMut`] and uses writev.
 ///
@@ -164,7 +183,13 @@ impl<E: ZeroCopyEncoder, W: AsyncWrite + Unpin + Send + Sync + 'static> Encoder
         cx.stats_mut().record_write_end_at();
 
         match write_result {
-            Ok(()) => Ok(()),
+            Ok(()) => {
+                #[cfg(feature = "metrics")]
+                if let Some(sizes) = cx.extensions().get::<volo::metrics::rpc::MessageSizes>() {
+                    sizes.observe_sent(cx.rpc_info().role(), real_size);
+                }
+                Ok(())
+            }
             Err(mut e) => {
                 let msg = format!(
                     ", cx: {:?}, encode real size: {}, malloc size: {}",
@@ -204,6 +229,14 @@ impl<D: ZeroCopyDecoder, R: AsyncRead + Unpin + Send + Sync + 'static> Decoder
         }
 
         let start = std::time::Instant::now();
+        cx.stats_mut().record_arrive_at();
+        if let Some(t) = cx
+            .extensions()
+            .get::<RecvTimestamp>()
+            .and_then(RecvTimestamp::get)
+        {
+            cx.stats_mut().set_kernel_arrive_at(t.into());
+        }
         cx.stats_mut().record_decode_start_at();
         cx.stats_mut().record_read_start_at();
 
@@ -219,8 +252,28 @@ impl<D: ZeroCopyDecoder, R: AsyncRead + Unpin + Send + Sync + 'static> Decoder
         cx.stats_mut().record_decode_end_at();
         trace!("[VOLO] thrift codec decode message cost: {:?}", end - start);
 
+        // the request of the servers is decoded before the layers, see `ThriftMetrics`
+        #[cfg(feature = "metrics")]
+        if let (Ok(Some(_)), Some(size), Some(sizes)) = (
+            &res,
+            cx.stats().read_size(),
+            cx.extensions().get::<volo::metrics::rpc::MessageSizes>(),
+        ) {
+            sizes.observe_received(cx.rpc_info().role(), size);
+        }
+
         res
     }
+
+    async fn closed(&mut self) {
+        match self.reader.fill_buf().await {
+            Ok(buf) if !buf.is_empty() => {
+                // the next request is pipelined, which is left for the next `decode`
+                std::future::pending().await
+            }
+            _ => {}
+        }
+    }
 }
 
 /// `MkZC` is a shorthand for [`MakeZeroCopyCodec`].
@@ -298,7 +351,12 @@ where
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
+    use tokio::io::AsyncWriteExt;
+
     use super::DefaultMakeCodec;
+    use crate::codec::{Decoder, MakeCodec};
 
     #[test]
     fn test_mk_codec() {
@@ -306,4 +364,22 @@ mod tests {
         let _ttheader_framed = DefaultMakeCodec::ttheader_framed();
         let _buffered = DefaultMakeCodec::buffered();
     }
+
+    #[tokio::test]
+    async fn closed() {
+        let (mut client, server) = tokio::io::duplex(64);
+        let (_, mut decoder) = DefaultMakeCodec::framed().make_codec(server, tokio::io::sink());
+        let wait = Duration::from_millis(10);
+
+        assert!(tokio::time::timeout(wait, decoder.closed()).await.is_err());
+        // the pipelined data is left for the next decode
+        client.write_all(b"next").await.unwrap();
+        assert!(tokio::time::timeout(wait, decoder.closed()).await.is_err());
+        assert_eq!(decoder.reader.buffer(), b"next");
+
+        let (client, server) = tokio::io::duplex(64);
+        let (_, mut decoder) = DefaultMakeCodec::framed().make_codec(server, tokio::io::sink());
+        drop(client);
+        tokio::time::timeout(wait, decoder.closed()).await.unwrap();
+    }
 }
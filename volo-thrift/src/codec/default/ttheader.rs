@@ -4,7 +4,7 @@
 
 #![allow(clippy::mutable_key_type)]
 
-use std::{collections::HashMap, net::SocketAddr, time::Duration};
+use std::{collections::HashMap, fmt, io, net::SocketAddr, time::Duration};
 
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use linkedbytes::LinkedBytes;
@@ -12,15 +12,24 @@ use metainfo::{Backward, Forward};
 use num_enum::TryFromPrimitive;
 use pilota::thrift::{
     new_protocol_exception, ProtocolException, ProtocolExceptionKind, ThriftException,
+    TransportException,
 };
 use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt};
 use tracing::{trace, warn};
-use volo::{context::Role, util::buf_reader::BufReader, FastStr};
+use volo::{
+    context::{
+        identity::PeerIdentityExt,
+        log_id::{self, LogIdConfig},
+        Role,
+    },
+    util::buf_reader::BufReader,
+    FastStr,
+};
 
-use super::MakeZeroCopyCodec;
+use super::{capture_frame, MakeZeroCopyCodec};
 use crate::{
     codec::default::{ZeroCopyDecoder, ZeroCopyEncoder},
-    context::ThriftContext,
+    context::{ReceivedResponseMeta, ThriftContext},
     BizError, EntryMessage, ThriftMessage,
 };
 
@@ -29,11 +38,200 @@ use crate::{
 #[derive(Clone)]
 pub struct MakeTTHeaderCodec<Inner: MakeZeroCopyCodec> {
     inner: Inner,
+    limits: TTHeaderLimits,
+    eager_metainfo: bool,
+    response_meta_limits: ResponseMetaLimits,
 }
 
 impl<Inner: MakeZeroCopyCodec> MakeTTHeaderCodec<Inner> {
     pub fn new(inner: Inner) -> Self {
-        Self { inner }
+        Self {
+            inner,
+            limits: TTHeaderLimits::default(),
+            eager_metainfo: true,
+            response_meta_limits: ResponseMetaLimits::default(),
+        }
+    }
+
+    /// Sets the limits of the [`ResponseMeta`] sent by the server, see [`ResponseMetaLimits`].
+    ///
+    /// [`ResponseMeta`]: crate::context::ResponseMeta
+    pub fn with_response_meta_limits(mut self, limits: ResponseMetaLimits) -> Self {
+        self.response_meta_limits = limits;
+        self
+    }
+
+    /// Sets the limits of the received headers, see [`TTHeaderLimits`].
+    pub fn with_limits(mut self, limits: TTHeaderLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Sets whether the server copies the metainfo headers of the requests into the `METAINFO`
+    /// when decoding, which is `true` by default.
+    ///
+    /// When it's `false`, the headers are kept in the [`TTHeaderKvs`] of the context, and copied by
+    /// [`TTHeaderKvs::load_metainfo`] only when needed, e.g. by a layer before calling the
+    /// downstream, which saves the copies of the requests not propagating the metainfo.
+    ///
+    /// [`TTHeaderKvs`]: crate::context::TTHeaderKvs
+    /// [`TTHeaderKvs::load_metainfo`]: crate::context::TTHeaderKvs::load_metainfo
+    pub fn with_eager_metainfo(mut self, eager_metainfo: bool) -> Self {
+        self.eager_metainfo = eager_metainfo;
+        self
+    }
+}
+
+/// Default limits of the received headers, which are far beyond the normal ones.
+pub const DEFAULT_MAX_HEADER_SIZE: usize = 128 * 1024; // 128KB
+pub const DEFAULT_MAX_KVS: usize = 4096;
+pub const DEFAULT_MAX_VALUE_LEN: usize = 32 * 1024; // 32KB
+
+/// The limits of the received headers, which reject a header exceeding them by a
+/// [`TransportException`] of [`TTHeaderLimitExceeded`], instead of parsing it into each context.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TTHeaderLimits {
+    max_header_size: usize,
+    max_kvs: usize,
+    max_value_len: usize,
+}
+
+impl Default for TTHeaderLimits {
+    fn default() -> Self {
+        Self {
+            max_header_size: DEFAULT_MAX_HEADER_SIZE,
+            max_kvs: DEFAULT_MAX_KVS,
+            max_value_len: DEFAULT_MAX_VALUE_LEN,
+        }
+    }
+}
+
+impl TTHeaderLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the max size of the header in bytes, i.e. all the infos without the payload.
+    pub fn with_max_header_size(mut self, max_header_size: usize) -> Self {
+        self.max_header_size = max_header_size;
+        self
+    }
+
+    /// Sets the max count of the string and int key-values in total.
+    pub fn with_max_kvs(mut self, max_kvs: usize) -> Self {
+        self.max_kvs = max_kvs;
+        self
+    }
+
+    /// Sets the max length of a string or int value in bytes.
+    pub fn with_max_value_len(mut self, max_value_len: usize) -> Self {
+        self.max_value_len = max_value_len;
+        self
+    }
+
+    fn check(&self, limit: TTHeaderLimit, actual: usize) -> Result<(), ThriftException> {
+        let max = match limit {
+            TTHeaderLimit::HeaderSize => self.max_header_size,
+            TTHeaderLimit::Kvs => self.max_kvs,
+            TTHeaderLimit::ValueLen => self.max_value_len,
+        };
+        if actual <= max {
+            return Ok(());
+        }
+        let err = TTHeaderLimitExceeded { limit, actual, max };
+        warn!("[VOLO] {err}");
+        Err(ThriftException::Transport(TransportException::from(
+            io::Error::new(io::ErrorKind::InvalidData, err),
+        )))
+    }
+}
+
+/// The limit of [`TTHeaderLimits`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TTHeaderLimit {
+    HeaderSize,
+    Kvs,
+    ValueLen,
+}
+
+impl fmt::Display for TTHeaderLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::HeaderSize => "header size",
+            Self::Kvs => "key-value count",
+            Self::ValueLen => "value length",
+        })
+    }
+}
+
+/// The error of a received header exceeding the [`TTHeaderLimits`], which is the source of the
+/// [`TransportException`] returned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TTHeaderLimitExceeded {
+    pub limit: TTHeaderLimit,
+    pub actual: usize,
+    pub max: usize,
+}
+
+impl fmt::Display for TTHeaderLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ttheader {} {} exceeds the limit {}",
+            self.limit, self.actual, self.max
+        )
+    }
+}
+
+impl std::error::Error for TTHeaderLimitExceeded {}
+
+/// Default limits of the [`ResponseMeta`](crate::context::ResponseMeta) sent by the server.
+pub const DEFAULT_MAX_RESPONSE_META_KVS: usize = 64;
+pub const DEFAULT_MAX_RESPONSE_META_SIZE: usize = 16 * 1024; // 16KB
+
+/// The limits of the [`ResponseMeta`](crate::context::ResponseMeta) sent by the server, which are
+/// checked when the response starts being encoded.
+///
+/// The headers exceeding the limits are truncated in the order of the keys by default, or the
+/// response fails to be encoded if [`ResponseMetaLimits::truncate`] is `false`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ResponseMetaLimits {
+    pub(crate) max_kvs: usize,
+    pub(crate) max_size: usize,
+    pub(crate) truncate: bool,
+}
+
+impl Default for ResponseMetaLimits {
+    fn default() -> Self {
+        Self {
+            max_kvs: DEFAULT_MAX_RESPONSE_META_KVS,
+            max_size: DEFAULT_MAX_RESPONSE_META_SIZE,
+            truncate: true,
+        }
+    }
+}
+
+impl ResponseMetaLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the max count of the headers.
+    pub fn with_max_kvs(mut self, max_kvs: usize) -> Self {
+        self.max_kvs = max_kvs;
+        self
+    }
+
+    /// Sets the max size of the keys and values of the headers in total.
+    pub fn with_max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Sets whether to truncate the headers exceeding the limits, or fail the response.
+    pub fn truncate(mut self, truncate: bool) -> Self {
+        self.truncate = truncate;
+        self
     }
 }
 
@@ -44,7 +242,16 @@ impl<Inner: MakeZeroCopyCodec> MakeZeroCopyCodec for MakeTTHeaderCodec<Inner> {
 
     fn make_codec(&self) -> (Self::Encoder, Self::Decoder) {
         let (encoder, decoder) = self.inner.make_codec();
-        (TTHeaderEncoder::new(encoder), TTHeaderDecoder::new(decoder))
+        let decoder = TTHeaderDecoder {
+            inner: decoder,
+            limits: self.limits,
+            eager_metainfo: self.eager_metainfo,
+        };
+        let encoder = TTHeaderEncoder {
+            response_meta_limits: self.response_meta_limits,
+            ..TTHeaderEncoder::new(encoder)
+        };
+        (encoder, decoder)
     }
 }
 
@@ -56,11 +263,17 @@ struct BizErrorExtra(FastStr);
 #[derive(Clone)]
 pub struct TTHeaderDecoder<D: ZeroCopyDecoder> {
     inner: D,
+    limits: TTHeaderLimits,
+    eager_metainfo: bool,
 }
 
 impl<D: ZeroCopyDecoder> TTHeaderDecoder<D> {
     pub fn new(inner: D) -> Self {
-        Self { inner }
+        Self {
+            inner,
+            limits: TTHeaderLimits::default(),
+            eager_metainfo: true,
+        }
     }
 }
 
@@ -85,7 +298,7 @@ where
         if is_ttheader(&bytes[..HEADER_DETECT_LENGTH]) {
             let _size = bytes.get_u32() as usize;
             // decode ttheader
-            decode(cx, bytes)?;
+            decode(cx, bytes, &self.limits, self.eager_metainfo)?;
             // set has ttheader flag
             cx.extensions_mut().insert(HasTTHeader);
         }
@@ -119,9 +332,10 @@ where
                 cx.stats_mut().record_read_end_at();
 
                 let mut buffer = buffer.freeze();
+                capture_frame(cx, &buffer);
 
                 // decode ttheader
-                decode(cx, &mut buffer)?;
+                decode(cx, &mut buffer, &self.limits, self.eager_metainfo)?;
                 // set has ttheader flag
                 cx.extensions_mut().insert(HasTTHeader);
                 // decode inner
@@ -145,6 +359,7 @@ pub fn is_ttheader(buf: &[u8]) -> bool {
 pub struct TTHeaderEncoder<E: ZeroCopyEncoder> {
     inner: E,
     inner_size: usize, // used to cache the size
+    response_meta_limits: ResponseMetaLimits,
 }
 
 impl<E: ZeroCopyEncoder> TTHeaderEncoder<E> {
@@ -152,6 +367,7 @@ impl<E: ZeroCopyEncoder> TTHeaderEncoder<E> {
         Self {
             inner,
             inner_size: 0,
+            response_meta_limits: ResponseMetaLimits::default(),
         }
     }
 }
@@ -180,6 +396,10 @@ where
         cx: &mut Cx,
         msg: &ThriftMessage<Msg>,
     ) -> Result<(usize, usize), ThriftException> {
+        // the response is being encoded, whose meta can't be changed anymore
+        if let Some(kvs) = cx.ttheader_kvs_mut() {
+            kvs.response_meta_mut().seal(&self.response_meta_limits)?;
+        }
         let (real_size, malloc_size) = self.inner.size(cx, msg)?;
         self.inner_size = real_size;
         // only calc ttheader size if role is client or server has detected ttheader in decode
@@ -209,6 +429,24 @@ pub(crate) const HEADER_CONNECTION_READY_TO_RESET: &str = "crrst";
 pub(crate) const TT_HEADER_BIZ_STATUS_KEY: &str = "biz-status";
 pub(crate) const TT_HEADER_BIZ_MESSAGE_KEY: &str = "biz-message";
 pub(crate) const TT_HEADER_BIZ_EXTRA_KEY: &str = "biz-extra";
+// the retry pushback from server in milliseconds, a negative value means not to retry.
+pub(crate) const TT_HEADER_RETRY_PUSHBACK_KEY: &str = "retry-pushback-ms";
+
+/// Checks if the string header key is interpreted by volo or metainfo, which can't be set by the
+/// handlers.
+pub(crate) fn is_reserved_key(key: &str) -> bool {
+    matches!(
+        key,
+        HEADER_TRANS_REMOTE_ADDR
+            | HEADER_CONNECTION_READY_TO_RESET
+            | TT_HEADER_BIZ_STATUS_KEY
+            | TT_HEADER_BIZ_MESSAGE_KEY
+            | TT_HEADER_BIZ_EXTRA_KEY
+            | TT_HEADER_RETRY_PUSHBACK_KEY
+    ) || key.starts_with(metainfo::RPC_PREFIX_PERSISTENT)
+        || key.starts_with(metainfo::RPC_PREFIX_TRANSIENT)
+        || key.starts_with(metainfo::RPC_PREFIX_BACKWARD)
+}
 
 #[derive(TryFromPrimitive, Clone, Copy, Default)]
 #[repr(u8)]
@@ -220,7 +458,7 @@ pub enum ProtocolId {
     Protobuf = 4,
 }
 
-#[derive(PartialEq, Eq, Hash, Clone, Copy, TryFromPrimitive)]
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, TryFromPrimitive)]
 #[repr(u16)]
 pub enum IntMetaKey {
     FromService = 3,
@@ -275,6 +513,8 @@ pub(crate) fn encode<Cx: ThriftContext>(
     dst: &mut BytesMut,
     size: usize,
 ) -> Result<(), ThriftException> {
+    let log_id = outgoing_log_id(cx);
+    let log_id_config = LogIdConfig::global();
     metainfo::METAINFO.with(|metainfo| {
         let metainfo = metainfo.borrow_mut();
         let zero_index = dst.len();
@@ -316,12 +556,18 @@ pub(crate) fn encode<Cx: ThriftContext>(
 
         let has_string_kv = match role {
             Role::Client => {
-                metainfo.get_all_persistents().is_some() || metainfo.get_all_transients().is_some()
+                metainfo.get_all_persistents().is_some()
+                    || metainfo.get_all_transients().is_some()
+                    || log_id.is_some()
             }
             Role::Server => {
                 metainfo.get_all_backward_transients().is_some()
                     || cx.encode_conn_reset().unwrap_or(false)
                     || cx.stats().biz_error().is_some()
+                    || cx.stats().retry_pushback().is_some()
+                    || cx
+                        .ttheader_kvs()
+                        .is_some_and(|kvs| !kvs.response().is_empty())
             }
         };
 
@@ -358,6 +604,14 @@ pub(crate) fn encode<Cx: ThriftContext>(
                             string_kv_len += 1;
                         }
                     }
+                    if let Some(log_id) = &log_id {
+                        let key = log_id_config.ttheader_key();
+                        dst.put_u16(key.len() as u16);
+                        dst.put_slice(key.as_bytes());
+                        dst.put_u16(log_id.len() as u16);
+                        dst.put_slice(log_id.as_bytes());
+                        string_kv_len += 1;
+                    }
                 }
                 Role::Server => {
                     if let Some(at) = metainfo.get_all_backward_transients() {
@@ -411,6 +665,26 @@ pub(crate) fn encode<Cx: ThriftContext>(
                             string_kv_len += 1;
                         }
                     }
+
+                    if let Some(pushback) = cx.stats().retry_pushback() {
+                        let mut ibuf = itoa::Buffer::new();
+                        let pushback = ibuf.format(pushback.as_millis());
+                        dst.put_u16(TT_HEADER_RETRY_PUSHBACK_KEY.as_bytes().len() as u16);
+                        dst.put_slice(TT_HEADER_RETRY_PUSHBACK_KEY.as_bytes());
+                        dst.put_u16(pushback.len() as u16);
+                        dst.put_slice(pushback.as_bytes());
+                        string_kv_len += 1;
+                    }
+
+                    if let Some(kvs) = cx.ttheader_kvs() {
+                        for (key, value) in kvs.response() {
+                            dst.put_u16(key.len() as u16);
+                            dst.put_slice(key.as_bytes());
+                            dst.put_u16(value.len() as u16);
+                            dst.put_slice(value.as_bytes());
+                            string_kv_len += 1;
+                        }
+                    }
                 }
             }
 
@@ -490,6 +764,13 @@ pub(crate) fn encode<Cx: ThriftContext>(
                     dst.put_slice(addr.as_bytes());
                     int_kv_len += 1;
                 }
+
+                if let (Some(log_id), Some(key)) = (&log_id, log_id_config.ttheader_int_key()) {
+                    dst.put_u16(key);
+                    dst.put_u16(log_id.len() as u16);
+                    dst.put_slice(log_id.as_bytes());
+                    int_kv_len += 1;
+                }
             }
         };
 
@@ -543,6 +824,8 @@ pub(crate) fn encode<Cx: ThriftContext>(
 
 // this must be with sync to the encode impl
 pub(crate) fn encode_size<Cx: ThriftContext>(cx: &mut Cx) -> Result<usize, ThriftException> {
+    let log_id = outgoing_log_id(cx);
+    let log_id_config = LogIdConfig::global();
     let thrift_cx = cx;
     Ok(metainfo::METAINFO.with(|metainfo| {
         let metainfo = metainfo.borrow_mut();
@@ -575,11 +858,18 @@ pub(crate) fn encode_size<Cx: ThriftContext>(cx: &mut Cx) -> Result<usize, Thrif
 
         let has_string_kv = match role {
             Role::Client => {
-                metainfo.get_all_persistents().is_some() || metainfo.get_all_transients().is_some()
+                metainfo.get_all_persistents().is_some()
+                    || metainfo.get_all_transients().is_some()
+                    || log_id.is_some()
             }
             Role::Server => {
                 metainfo.get_all_backward_transients().is_some()
                     || thrift_cx.encode_conn_reset().unwrap_or(false)
+                    || thrift_cx.stats().biz_error().is_some()
+                    || thrift_cx.stats().retry_pushback().is_some()
+                    || thrift_cx
+                        .ttheader_kvs()
+                        .is_some_and(|kvs| !kvs.response().is_empty())
             }
         };
 
@@ -609,6 +899,12 @@ pub(crate) fn encode_size<Cx: ThriftContext>(cx: &mut Cx) -> Result<usize, Thrif
                             len += value.as_bytes().len();
                         }
                     }
+                    if let Some(log_id) = &log_id {
+                        len += 2;
+                        len += log_id_config.ttheader_key().len();
+                        len += 2;
+                        len += log_id.len();
+                    }
                 }
                 Role::Server => {
                     if let Some(at) = metainfo.get_all_backward_transients() {
@@ -652,6 +948,23 @@ pub(crate) fn encode_size<Cx: ThriftContext>(cx: &mut Cx) -> Result<usize, Thrif
                                 .insert(BizErrorExtra(extra.into()));
                         }
                     }
+                    if let Some(pushback) = thrift_cx.stats().retry_pushback() {
+                        len += 2;
+                        len += TT_HEADER_RETRY_PUSHBACK_KEY.as_bytes().len();
+                        len += 2;
+                        len += itoa::Buffer::new()
+                            .format(pushback.as_millis())
+                            .as_bytes()
+                            .len();
+                    }
+                    if let Some(kvs) = thrift_cx.ttheader_kvs() {
+                        for (key, value) in kvs.response() {
+                            len += 2;
+                            len += key.as_bytes().len();
+                            len += 2;
+                            len += value.as_bytes().len();
+                        }
+                    }
                 }
             }
         }
@@ -716,6 +1029,12 @@ pub(crate) fn encode_size<Cx: ThriftContext>(cx: &mut Cx) -> Result<usize, Thrif
                     len += 2;
                     len += addr.as_bytes().len();
                 }
+
+                if let (Some(log_id), Some(_)) = (&log_id, log_id_config.ttheader_int_key()) {
+                    len += 2;
+                    len += 2;
+                    len += log_id.len();
+                }
             }
         };
 
@@ -727,16 +1046,29 @@ pub(crate) fn encode_size<Cx: ThriftContext>(cx: &mut Cx) -> Result<usize, Thrif
     }))
 }
 
+/// The log id sent by the client, see [`log_id::outgoing`].
+fn outgoing_log_id<Cx: ThriftContext>(cx: &mut Cx) -> Option<FastStr> {
+    match cx.rpc_info().role() {
+        Role::Client => log_id::outgoing(cx),
+        Role::Server => None,
+    }
+}
+
 pub(crate) fn decode<Cx: ThriftContext>(
     cx: &mut Cx,
     src: &mut Bytes,
+    limits: &TTHeaderLimits,
+    eager_metainfo: bool,
 ) -> Result<(), ThriftException> {
+    let log_id_config = LogIdConfig::global();
+    let mut received_log_id = None;
     metainfo::METAINFO.with(|metainfo| {
             let metainfo = &mut *metainfo.borrow_mut();
             let _magic = src.get_u16();
             let _flags = src.get_u16();
             let _sequence_id = src.get_u32(); // TODO: seq id should be i32?
             let header_size = src.get_u16();
+            limits.check(TTHeaderLimit::HeaderSize, header_size as usize * 4)?;
             let protocol_id = src.get_u8();
             if let Ok(protocol_id) = ProtocolId::try_from_primitive(protocol_id) {
                 cx.extensions_mut().insert(protocol_id);
@@ -760,6 +1092,7 @@ pub(crate) fn decode<Cx: ThriftContext>(
             let mut headers = HashMap::new();
             let mut int_headers = HashMap::new();
             let mut _padding_num = 0usize;
+            let mut kv_count = 0usize;
 
             let mut remaining_header_size = (header_size as usize) * 4 - 2 /* protocol_id and transform_ids_num */ - transform_ids_num as usize;
 
@@ -774,6 +1107,8 @@ pub(crate) fn decode<Cx: ThriftContext>(
                     info::INFO_KEY_VALUE => {
                         remaining_header_size -= 2;
                         let kv_size = src.get_u16();
+                        kv_count += kv_size as usize;
+                        limits.check(TTHeaderLimit::Kvs, kv_count)?;
                         headers.reserve(kv_size as usize);
                         for _ in 0..kv_size {
                             remaining_header_size -= 2;
@@ -783,6 +1118,7 @@ pub(crate) fn decode<Cx: ThriftContext>(
 
                             remaining_header_size -= 2;
                             let value_len = src.get_u16();
+                            limits.check(TTHeaderLimit::ValueLen, value_len as usize)?;
                             remaining_header_size -= value_len as usize;
                             let value = src.split_to(value_len as usize);
 
@@ -795,14 +1131,20 @@ pub(crate) fn decode<Cx: ThriftContext>(
                     info::INFO_INT_KEY_VALUE => {
                         remaining_header_size -= 2;
                         let kv_size = src.get_u16();
+                        kv_count += kv_size as usize;
+                        limits.check(TTHeaderLimit::Kvs, kv_count)?;
                         int_headers.reserve(kv_size as usize);
 
                         for _ in 0..kv_size {
                             remaining_header_size -= 4;
                             let key = src.get_u16();
                             let value_len = src.get_u16() as usize;
+                            limits.check(TTHeaderLimit::ValueLen, value_len)?;
                             remaining_header_size -= value_len;
                             let value = src.split_to(value_len);
+                            if Some(key) == log_id_config.ttheader_int_key() {
+                                received_log_id = FastStr::from_bytes(value.clone()).ok();
+                            }
                             let key = match IntMetaKey::try_from(key) {
                                 Ok(k) => k,
                                 Err(e) => {
@@ -853,21 +1195,37 @@ pub(crate) fn decode<Cx: ThriftContext>(
 
                     set_biz_error_header(cx, &mut headers);
 
-                    // Search for backward metainfo.
+                    if let Some(pushback) = headers.remove(TT_HEADER_RETRY_PUSHBACK_KEY) {
+                        cx.stats_mut()
+                            .set_retry_pushback(volo::retry::Pushback::parse(&pushback));
+                    }
+
+                    // Search for backward metainfo, and the others are the response meta.
                     // We are not supposed to use headers, so we can use into_iter to avoid clone.
+                    let response_meta = cx.extensions().get::<ReceivedResponseMeta>();
                     for (k, v) in headers.into_iter() {
                         if k.starts_with(metainfo::RPC_PREFIX_BACKWARD) {
                             metainfo.strip_rpc_prefix_and_set_backward_downstream(k, v);
+                        } else if let Some(response_meta) = response_meta {
+                            if !is_reserved_key(&k) {
+                                response_meta.insert(k, v);
+                            }
                         }
                     }
                 }
                 Role::Server => {
+                    // the int key is preferred, and the log id is kept in the context instead of
+                    // the kvs
+                    let log_id = headers.remove(log_id_config.ttheader_key().as_str());
+                    if received_log_id.is_none() {
+                        received_log_id = log_id;
+                    }
+
                     // Caller
-                    let from_service = int_headers
-                        .remove_entry(&IntMetaKey::FromService)
-                        .map(|(_, v)| v);
+                    let from_service = int_headers.get(&IntMetaKey::FromService).cloned();
 
                     if let Some(from_service) = from_service {
+                        cx.peer_identity_mut().set_declared(from_service.clone());
                         let caller = cx.rpc_info_mut().caller_mut();
                         caller.set_service_name(from_service);
                         if let Some(ad) = headers.remove(HEADER_TRANS_REMOTE_ADDR) {
@@ -879,9 +1237,7 @@ pub(crate) fn decode<Cx: ThriftContext>(
                     }
 
                     // Callee
-                    let to_service = int_headers
-                        .remove_entry(&IntMetaKey::ToService)
-                        .map(|(_, v)| v);
+                    let to_service = int_headers.get(&IntMetaKey::ToService).cloned();
 
                     if let Some(to_service) = to_service {
                         cx.rpc_info_mut().callee_mut().set_service_name(to_service);
@@ -895,19 +1251,36 @@ pub(crate) fn decode<Cx: ThriftContext>(
                         cx.rpc_info_mut().config_mut().set_rpc_timeout(Some(rpc_timeout));
                     }
 
-                    // Search for forward metainfo.
+                    if let Some(kvs) = cx.ttheader_kvs_mut() {
+                        kvs.set_int_request(int_headers);
+                    }
+
+                    // Search for forward metainfo, and the others are kept in the context for
+                    // the handlers.
                     // We are not supposed to use headers, so we can use into_iter to avoid clone.
                     for (k, v) in headers.into_iter() {
-                        if k.starts_with(metainfo::RPC_PREFIX_PERSISTENT) {
+                        if k.starts_with(metainfo::RPC_PREFIX_BACKWARD) {
+                            continue;
+                        } else if eager_metainfo && k.starts_with(metainfo::RPC_PREFIX_PERSISTENT) {
                             metainfo.strip_rpc_prefix_and_set_persistent(k, v);
-                        } else if k.starts_with(metainfo::RPC_PREFIX_TRANSIENT) {
+                        } else if eager_metainfo && k.starts_with(metainfo::RPC_PREFIX_TRANSIENT) {
                             metainfo.strip_rpc_prefix_and_set_upstream(k, v);
+                        } else if let Some(kvs) = cx.ttheader_kvs_mut() {
+                            // the metainfo ones are kept for `TTHeaderKvs::load_metainfo` if not
+                            // eager
+                            kvs.insert_request(k, v);
                         }
                     }
                 }
             }
-            Ok(())
-        })
+            Ok::<(), ThriftException>(())
+        })?;
+
+    // out of the borrow of the metainfo, into which the log id is set
+    if cx.rpc_info().role() == Role::Server {
+        log_id::accept(cx, received_log_id.as_deref());
+    }
+    Ok(())
 }
 
 fn set_biz_error_header<Cx: ThriftContext>(
@@ -956,3 +1329,306 @@ fn set_biz_error_header<Cx: ThriftContext>(
 
     thrift_cx.stats_mut().set_biz_error(biz_error);
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use bytes::{Buf, Bytes, BytesMut};
+    use metainfo::{MetaInfo, METAINFO};
+    use pilota::thrift::TMessageType;
+    use volo::context::{
+        identity::PeerIdentityExt,
+        log_id::{LogId, LogIdExt},
+        Context, Role, RpcInfo,
+    };
+
+    use volo::client::Apply;
+
+    use super::{decode, encode, encode_size, IntMetaKey, ResponseMetaLimits, TTHeaderLimits};
+    use crate::{
+        client::CallOpt,
+        context::{
+            ClientContext, ReceivedResponseMeta, ResponseMetaError, ServerContext, ThriftContext,
+        },
+    };
+
+    fn encode_header<Cx: ThriftContext>(cx: &mut Cx, mi: MetaInfo) -> Bytes {
+        METAINFO.sync_scope(RefCell::new(mi), || {
+            let size = encode_size(cx).unwrap();
+            let mut dst = BytesMut::new();
+            encode(cx, &mut dst, 0).unwrap();
+            assert_eq!(dst.len(), size);
+            let mut header = dst.freeze();
+            // skip the length
+            header.advance(4);
+            header
+        })
+    }
+
+    fn decode_header(header: Bytes) -> (ServerContext, MetaInfo) {
+        decode_header_with(header, &TTHeaderLimits::default(), true).unwrap()
+    }
+
+    fn decode_header_with(
+        mut header: Bytes,
+        limits: &TTHeaderLimits,
+        eager_metainfo: bool,
+    ) -> Result<(ServerContext, MetaInfo), String> {
+        let mut cx = ServerContext::default();
+        let mi = METAINFO.sync_scope(RefCell::new(MetaInfo::default()), || {
+            decode(&mut cx, &mut header, limits, eager_metainfo).map_err(|e| e.to_string())?;
+            Ok::<_, String>(METAINFO.with(|mi| mi.take()))
+        })?;
+        assert!(header.is_empty());
+        Ok((cx, mi))
+    }
+
+    #[test]
+    fn metainfo_is_not_string_kv() {
+        let mut mi = MetaInfo::new();
+        mi.set_persistent("tenant", "t1");
+        mi.set_transient("hop", "h1");
+        let mut cx = ClientContext::new(1, RpcInfo::with_role(Role::Client), TMessageType::Call);
+
+        let (cx, mi) = decode_header(encode_header(&mut cx, mi));
+        let persistents = mi.get_all_persistents().unwrap();
+        assert_eq!(persistents.get("tenant").unwrap().as_str(), "t1");
+        assert_eq!(mi.get_upstream("hop").unwrap().as_str(), "h1");
+        assert!(cx.ttheader_kvs.request().is_empty());
+    }
+
+    #[test]
+    fn response_string_kvs() {
+        let mut cx = ServerContext::default();
+        cx.msg_type = Some(TMessageType::Reply);
+        assert!(cx.ttheader_kvs.insert_response("route", "gray"));
+        assert!(cx.ttheader_kvs.insert_response("tenant", "t1"));
+        // reserved by volo or metainfo
+        assert!(!cx.ttheader_kvs.insert_response("biz-status", "1"));
+        assert!(!cx
+            .ttheader_kvs
+            .insert_response(format!("{}status", metainfo::RPC_PREFIX_BACKWARD), "ok"));
+        let mut mi = MetaInfo::new();
+        mi.set_backward_transient("status", "ok");
+
+        // the server decoding the header keeps all the kvs which are not metainfo
+        let (decoded, _) = decode_header(encode_header(&mut cx, mi));
+        let kvs = decoded.ttheader_kvs.request();
+        assert_eq!(kvs.len(), 2);
+        assert_eq!(decoded.ttheader_kvs.get("route").unwrap().as_str(), "gray");
+        assert_eq!(decoded.ttheader_kvs.get("tenant").unwrap().as_str(), "t1");
+    }
+
+    #[test]
+    fn declared_caller() {
+        let mut cx = ClientContext::new(1, RpcInfo::with_role(Role::Client), TMessageType::Call);
+        cx.rpc_info_mut()
+            .caller_mut()
+            .set_service_name("echo".into());
+
+        let (cx, _) = decode_header(encode_header(&mut cx, MetaInfo::new()));
+        assert_eq!(cx.rpc_info().caller().service_name_ref(), "echo");
+        let identity = cx.peer_identity().unwrap();
+        assert_eq!(identity.declared_name().unwrap().as_str(), "echo");
+        assert!(!identity.is_authenticated());
+        assert_eq!(identity.service_name().unwrap().as_str(), "echo");
+    }
+
+    #[test]
+    fn log_id() {
+        let mut cx = ClientContext::new(1, RpcInfo::with_role(Role::Client), TMessageType::Call);
+        cx.set_log_id("20240102030405C0A8010A00002A");
+        let (server, mi) = decode_header(encode_header(&mut cx, MetaInfo::new()));
+        assert_eq!(
+            server.log_id().unwrap().as_str(),
+            "20240102030405C0A8010A00002A"
+        );
+        // kept for the downstream calls of the handler
+        assert_eq!(
+            mi.get::<LogId>().unwrap().as_str(),
+            "20240102030405C0A8010A00002A"
+        );
+        assert!(server.ttheader_kvs.request().is_empty());
+
+        // generated by the first hop, and the same one is sent by the retries
+        let mut cx = ClientContext::new(1, RpcInfo::with_role(Role::Client), TMessageType::Call);
+        let (first, _) = decode_header(encode_header(&mut cx, MetaInfo::new()));
+        let (retry, _) = decode_header(encode_header(&mut cx, MetaInfo::new()));
+        assert_eq!(first.log_id(), cx.log_id());
+        assert_eq!(retry.log_id(), cx.log_id());
+
+        // the log id of the request being served is sent to the downstream
+        let mut downstream =
+            ClientContext::new(2, RpcInfo::with_role(Role::Client), TMessageType::Call);
+        let (downstream, _) = decode_header(encode_header(&mut downstream, mi));
+        assert_eq!(
+            downstream.log_id().unwrap().as_str(),
+            "20240102030405C0A8010A00002A"
+        );
+    }
+
+    #[test]
+    fn limits() {
+        let mut mi = MetaInfo::new();
+        mi.set_persistent("tenant", "t1");
+        mi.set_transient("trace", "x".repeat(100));
+        let mut cx = ClientContext::new(1, RpcInfo::with_role(Role::Client), TMessageType::Call);
+        let header = encode_header(&mut cx, mi);
+        // the header size in 4 bytes after the magic, flags and sequence id
+        let header_size = u16::from_be_bytes([header[8], header[9]]) as usize * 4;
+        // the 2 string kvs, the log id and the int kvs of the client
+        let kvs = {
+            let (cx, _) = decode_header(header.clone());
+            3 + cx.ttheader_kvs.iter_int().count()
+        };
+
+        let check = |limits: TTHeaderLimits, limit: &str| {
+            let err = decode_header_with(header.clone(), &limits, true).unwrap_err();
+            assert!(err.contains(&format!("ttheader {limit}")), "{err}");
+        };
+        let limits = TTHeaderLimits::new()
+            .with_max_header_size(header_size)
+            .with_max_kvs(kvs)
+            .with_max_value_len(100);
+        decode_header_with(header.clone(), &limits, true).unwrap();
+        check(limits.with_max_header_size(header_size - 1), "header size");
+        check(limits.with_max_kvs(kvs - 1), "key-value count");
+        check(limits.with_max_value_len(99), "value length");
+    }
+
+    #[test]
+    fn lazy_metainfo() {
+        let mut mi = MetaInfo::new();
+        mi.set_persistent("tenant", "t1");
+        mi.set_transient("hop", "h1");
+        let mut cx = ClientContext::new(1, RpcInfo::with_role(Role::Client), TMessageType::Call);
+        cx.rpc_info_mut()
+            .caller_mut()
+            .set_service_name("echo".into());
+        let header = encode_header(&mut cx, mi);
+
+        let (cx, mi) = decode_header_with(header, &TTHeaderLimits::default(), false).unwrap();
+        assert!(mi.get_all_persistents().is_none());
+        assert!(mi.get_upstream("hop").is_none());
+        // kept in the context until loaded
+        assert_eq!(cx.ttheader_kvs.iter().count(), 2);
+        assert_eq!(
+            cx.ttheader_kvs
+                .get_int(IntMetaKey::FromService)
+                .unwrap()
+                .as_str(),
+            "echo"
+        );
+        assert_eq!(cx.rpc_info().caller().service_name_ref(), "echo");
+
+        let mi = METAINFO.sync_scope(RefCell::new(MetaInfo::default()), || {
+            cx.ttheader_kvs.load_metainfo();
+            METAINFO.with(|mi| mi.take())
+        });
+        let persistents = mi.get_all_persistents().unwrap();
+        assert_eq!(persistents.get("tenant").unwrap().as_str(), "t1");
+        assert_eq!(mi.get_upstream("hop").unwrap().as_str(), "h1");
+
+        // the propagation to the downstream is the same as the eager one
+        let mut downstream =
+            ClientContext::new(2, RpcInfo::with_role(Role::Client), TMessageType::Call);
+        let (downstream, mi) = decode_header(encode_header(&mut downstream, mi));
+        let persistents = mi.get_all_persistents().unwrap();
+        assert_eq!(persistents.get("tenant").unwrap().as_str(), "t1");
+        assert!(downstream.ttheader_kvs.request().is_empty());
+    }
+
+    /// Sends the response of the server to the client, which receives the response meta.
+    fn response_meta(
+        server: &mut ServerContext,
+        limits: &ResponseMetaLimits,
+    ) -> ReceivedResponseMeta {
+        server.msg_type = Some(TMessageType::Reply);
+        server
+            .ttheader_kvs
+            .response_meta_mut()
+            .seal(limits)
+            .unwrap();
+        let mut header = encode_header(server, MetaInfo::new());
+
+        let received = ReceivedResponseMeta::new();
+        let mut client =
+            ClientContext::new(1, RpcInfo::with_role(Role::Client), TMessageType::Call);
+        let callopt = CallOpt {
+            response_meta: Some(received.clone()),
+            ..Default::default()
+        };
+        callopt.apply(&mut client).unwrap();
+        METAINFO.sync_scope(RefCell::new(MetaInfo::default()), || {
+            decode(&mut client, &mut header, &TTHeaderLimits::default(), true).unwrap();
+        });
+        received
+    }
+
+    #[test]
+    fn response_meta_round_trip() {
+        let mut cx = ServerContext::default();
+        let meta = cx.response_meta_mut();
+        meta.insert("served-by", "i-1").unwrap();
+        meta.insert("cache", "hit").unwrap();
+        assert!(matches!(
+            meta.insert("biz-status", "1"),
+            Err(ResponseMetaError::Reserved(_))
+        ));
+        assert!(matches!(
+            meta.insert(format!("{}status", metainfo::RPC_PREFIX_BACKWARD), "ok"),
+            Err(ResponseMetaError::Reserved(_))
+        ));
+
+        let received = response_meta(&mut cx, &ResponseMetaLimits::default());
+        let received = received.take();
+        assert_eq!(received.len(), 2);
+        assert_eq!(received.get("served-by").unwrap().as_str(), "i-1");
+        assert_eq!(received.get("cache").unwrap().as_str(), "hit");
+
+        // set by the handler after the response started being encoded
+        let err = cx.response_meta_mut().insert("late", "1").unwrap_err();
+        assert!(matches!(err, ResponseMetaError::Sealed(_)));
+        assert_eq!(
+            err.to_string(),
+            "`late` is set after the response started being encoded"
+        );
+        assert!(!cx.ttheader_kvs.insert_response("late", "1"));
+    }
+
+    #[test]
+    fn response_meta_limits() {
+        let server = || {
+            let mut cx = ServerContext::default();
+            for key in ["a", "b", "c"] {
+                cx.response_meta_mut().insert(key, "1234").unwrap();
+            }
+            cx
+        };
+        let received = |limits| {
+            let mut keys = response_meta(&mut server(), &limits)
+                .take()
+                .into_keys()
+                .collect::<Vec<_>>();
+            keys.sort();
+            keys
+        };
+
+        // within the limits exactly
+        let limits = ResponseMetaLimits::new().with_max_kvs(3).with_max_size(15);
+        assert_eq!(received(limits), ["a", "b", "c"]);
+        // truncated in the order of the keys
+        assert_eq!(received(limits.with_max_kvs(2)), ["a", "b"]);
+        assert_eq!(received(limits.with_max_size(14)), ["a", "b"]);
+        assert!(received(limits.with_max_size(4)).is_empty());
+
+        let mut cx = server();
+        let err = cx
+            .ttheader_kvs
+            .response_meta_mut()
+            .seal(&limits.with_max_kvs(2).truncate(false))
+            .unwrap_err();
+        assert!(err.to_string().contains("exceeds the limits"), "{err}");
+    }
+}
@@ -0,0 +1,1004 @@
+//! Optional strict validation of the requests of the server at decode time.
+//!
+//! The generated decoders of thrift are lenient: the `required` fields are advisory, the fields
+//! with unknown ids are skipped, and the enums accept any value. [`RequestValidator`] walks the
+//! payload of a request against the [`StructDescriptor`] of the arguments of its method before it
+//! is decoded, and rejects it by a protocol exception naming the method, the struct and the field
+//! id if:
+//!
+//! - a `required` field is missing, when [`MethodValidation::required_fields`] is on;
+//! - a field id is not declared by the struct, when [`MethodValidation::field_ids`] is on, unless
+//!   [`MethodValidation::allow_unknown_fields`] is on, which should be set for the structs
+//!   generated with `keep_unknown_fields`;
+//! - an enum value is not declared, when [`MethodValidation::enum_values`] is on.
+//!
+//! The checks are toggled per method, and the methods not in the validator are not checked, so
+//! it's compatible with the old clients by default. The violations are counted per method by
+//! [`RequestValidator::violations`].
+//!
+//! The descriptors are built from the schemas generated by `volo-build` from the IDL by
+//! [`RequestValidator::service`], so only the checks of the methods are set by hand, e.g. by the
+//! annotations of the methods. Only the binary protocol with a length-prefixed transport
+//! (TTHeader or Framed) is validated, since the whole payload is needed before decoding:
+//!
+//! ```rust,ignore
+//! use volo_thrift::codec::default::{
+//!     framed::MakeFramedCodec,
+//!     thrift::MakeThriftCodec,
+//!     ttheader::MakeTTHeaderCodec,
+//!     validate::{MakeValidateCodec, RequestValidator},
+//!     DefaultMakeCodec,
+//! };
+//!
+//! let validator = RequestValidator::new().service(
+//!     &volo_gen::volo::example::ITEM_SERVICE_SERVICE,
+//!     |method, validation| match method.annotation("validate") {
+//!         Some("strict") => validation.required_fields(true).field_ids(true),
+//!         _ => validation,
+//!     },
+//! );
+//!
+//! let server = ItemServiceServer::new(S)
+//!     .make_codec(DefaultMakeCodec::new(MakeTTHeaderCodec::new(MakeFramedCodec::new(
+//!         MakeValidateCodec::new(MakeThriftCodec::new(), validator),
+//!     ))));
+//! ```
+
+use std::{
+    collections::HashMap,
+    fmt, ptr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use bytes::Bytes;
+use pilota::{
+    thrift::{ProtocolException, ProtocolExceptionKind, ThriftException},
+    FastStr,
+};
+use tokio::io::AsyncRead;
+use volo::{
+    descriptor::{EnumSchema, MethodDescriptor, ServiceDescriptor, StructSchema, TypeSchema},
+    util::buf_reader::BufReader,
+};
+
+use super::{MakeZeroCopyCodec, ZeroCopyDecoder};
+use crate::{context::ThriftContext, EntryMessage, ThriftMessage};
+
+/// The maximum depth of the nested structs and containers to be validated.
+const MAX_DEPTH: usize = 64;
+
+/// The declared fields of a struct, which is built by hand or from the [`StructSchema`]
+/// generated from the IDL.
+#[derive(Debug, Clone)]
+pub struct StructDescriptor {
+    name: FastStr,
+    fields: Vec<FieldDescriptor>,
+}
+
+#[derive(Debug, Clone)]
+struct FieldDescriptor {
+    id: i16,
+    required: bool,
+    ty: FieldType,
+}
+
+impl StructDescriptor {
+    pub fn new(name: impl Into<FastStr>) -> Self {
+        Self {
+            name: name.into(),
+            fields: Vec::new(),
+        }
+    }
+
+    /// Declares a `required` field.
+    pub fn required(self, id: i16, ty: FieldType) -> Self {
+        self.field(id, true, ty)
+    }
+
+    /// Declares an `optional` or default field.
+    pub fn optional(self, id: i16, ty: FieldType) -> Self {
+        self.field(id, false, ty)
+    }
+
+    fn field(mut self, id: i16, required: bool, ty: FieldType) -> Self {
+        self.fields.retain(|f| f.id != id);
+        self.fields.push(FieldDescriptor { id, required, ty });
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Builds the descriptor of the generated schema, including the structs and the enums of its
+    /// fields.
+    ///
+    /// The descriptors can't be recursive, so a field of a struct containing itself is not
+    /// validated below the struct, e.g. only the root of a tree is validated.
+    pub fn from_schema(schema: &'static StructSchema) -> Arc<Self> {
+        SchemaConverter::default().convert(schema)
+    }
+}
+
+/// Converts the schemas, where the structs converted are shared by the fields of them, and the
+/// structs being converted are on the `stack`.
+#[derive(Default)]
+struct SchemaConverter {
+    converted: Vec<(&'static StructSchema, Arc<StructDescriptor>)>,
+    enums: Vec<(&'static EnumSchema, Arc<EnumDescriptor>)>,
+    stack: Vec<&'static StructSchema>,
+}
+
+impl SchemaConverter {
+    fn convert(&mut self, schema: &'static StructSchema) -> Arc<StructDescriptor> {
+        if let Some((_, desc)) = self.converted.iter().find(|(s, _)| ptr::eq(*s, schema)) {
+            return desc.clone();
+        }
+        self.stack.push(schema);
+        let desc = schema
+            .fields
+            .iter()
+            .fold(StructDescriptor::new(schema.name), |desc, field| {
+                let ty = self.field_type(&field.ty);
+                desc.field(field.id, field.required, ty)
+            });
+        self.stack.pop();
+        let desc = Arc::new(desc);
+        self.converted.push((schema, desc.clone()));
+        desc
+    }
+
+    fn field_type(&mut self, ty: &'static TypeSchema) -> FieldType {
+        match ty {
+            TypeSchema::Value => FieldType::Value,
+            TypeSchema::Struct(s) if self.stack.iter().any(|on| ptr::eq(*on, *s)) => {
+                FieldType::Value
+            }
+            TypeSchema::Struct(s) => FieldType::Struct(self.convert(s)),
+            TypeSchema::Enum(e) => {
+                let desc = match self.enums.iter().find(|(on, _)| ptr::eq(*on, *e)) {
+                    Some((_, desc)) => desc.clone(),
+                    None => {
+                        let desc = Arc::new(EnumDescriptor::new(e.name, e.values.iter().copied()));
+                        self.enums.push((e, desc.clone()));
+                        desc
+                    }
+                };
+                FieldType::Enum(desc)
+            }
+            TypeSchema::List(ty) => FieldType::List(Box::new(self.field_type(ty))),
+            TypeSchema::Set(ty) => FieldType::Set(Box::new(self.field_type(ty))),
+            TypeSchema::Map(k, v) => {
+                FieldType::Map(Box::new(self.field_type(k)), Box::new(self.field_type(v)))
+            }
+        }
+    }
+}
+
+/// The declared values of an enum.
+#[derive(Debug, Clone)]
+pub struct EnumDescriptor {
+    name: FastStr,
+    values: Vec<i32>,
+}
+
+impl EnumDescriptor {
+    pub fn new(name: impl Into<FastStr>, values: impl IntoIterator<Item = i32>) -> Self {
+        Self {
+            name: name.into(),
+            values: values.into_iter().collect(),
+        }
+    }
+}
+
+/// The type of a field to be validated.
+#[derive(Debug, Clone)]
+pub enum FieldType {
+    /// A type whose value is not validated, e.g. the base types.
+    Value,
+    Struct(Arc<StructDescriptor>),
+    Enum(Arc<EnumDescriptor>),
+    List(Box<FieldType>),
+    Set(Box<FieldType>),
+    Map(Box<FieldType>, Box<FieldType>),
+}
+
+/// The descriptor of the arguments of a method and the checks of it, which are all off by
+/// default.
+#[derive(Debug, Clone)]
+pub struct MethodValidation {
+    args: Arc<StructDescriptor>,
+    required_fields: bool,
+    field_ids: bool,
+    allow_unknown_fields: bool,
+    enum_values: bool,
+}
+
+impl MethodValidation {
+    /// Creates the validation of a method whose arguments struct is `args`, whose fields are the
+    /// arguments by their ids in the IDL.
+    pub fn new(args: impl Into<Arc<StructDescriptor>>) -> Self {
+        Self {
+            args: args.into(),
+            required_fields: false,
+            field_ids: false,
+            allow_unknown_fields: false,
+            enum_values: false,
+        }
+    }
+
+    /// Rejects the requests missing `required` fields.
+    pub fn required_fields(mut self, on: bool) -> Self {
+        self.required_fields = on;
+        self
+    }
+
+    /// Rejects the requests with the field ids not declared by the structs.
+    pub fn field_ids(mut self, on: bool) -> Self {
+        self.field_ids = on;
+        self
+    }
+
+    /// Allows the unknown field ids even if [`field_ids`](Self::field_ids) is on, e.g. when the
+    /// unknown fields are preserved by the generated structs.
+    pub fn allow_unknown_fields(mut self, on: bool) -> Self {
+        self.allow_unknown_fields = on;
+        self
+    }
+
+    /// Rejects the requests with the enum values not declared.
+    pub fn enum_values(mut self, on: bool) -> Self {
+        self.enum_values = on;
+        self
+    }
+
+    fn is_off(&self) -> bool {
+        !self.required_fields
+            && !(self.field_ids && !self.allow_unknown_fields)
+            && !self.enum_values
+    }
+}
+
+/// A violation of the descriptors found in a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    MissingRequired {
+        struct_name: FastStr,
+        field_id: i16,
+    },
+    UnknownField {
+        struct_name: FastStr,
+        field_id: i16,
+    },
+    InvalidEnum {
+        struct_name: FastStr,
+        field_id: i16,
+        enum_name: FastStr,
+        value: i32,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingRequired {
+                struct_name,
+                field_id,
+            } => write!(
+                f,
+                "required field {field_id} of struct `{struct_name}` is missing"
+            ),
+            Self::UnknownField {
+                struct_name,
+                field_id,
+            } => write!(f, "field {field_id} is unknown to struct `{struct_name}`"),
+            Self::InvalidEnum {
+                struct_name,
+                field_id,
+                enum_name,
+                value,
+            } => write!(
+                f,
+                "field {field_id} of struct `{struct_name}` has value {value} not declared by \
+                 enum `{enum_name}`"
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct MethodEntry {
+    validation: MethodValidation,
+    violations: AtomicU64,
+}
+
+/// The table of the [`MethodValidation`]s of the methods, see the [module docs](self).
+#[derive(Debug, Default)]
+pub struct RequestValidator {
+    methods: HashMap<FastStr, MethodEntry>,
+}
+
+impl RequestValidator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the validation of the method.
+    pub fn method(mut self, method: impl Into<FastStr>, validation: MethodValidation) -> Self {
+        self.methods.insert(
+            method.into(),
+            MethodEntry {
+                validation,
+                violations: AtomicU64::new(0),
+            },
+        );
+        self
+    }
+
+    /// Sets the validations of the methods of a generated service, whose descriptors are built
+    /// from the schemas of their arguments, and whose checks are set by `toggles`.
+    ///
+    /// The methods without the schemas and the ones whose checks are all off are skipped.
+    pub fn service<F>(mut self, service: &ServiceDescriptor, toggles: F) -> Self
+    where
+        F: Fn(&'static MethodDescriptor, MethodValidation) -> MethodValidation,
+    {
+        let mut converter = SchemaConverter::default();
+        for method in service.methods {
+            let Some(schema) = method.request_schema else {
+                continue;
+            };
+            let validation = toggles(method, MethodValidation::new(converter.convert(schema)));
+            if !validation.is_off() {
+                self = self.method(method.path, validation);
+            }
+        }
+        self
+    }
+
+    /// Returns the number of the requests of the method rejected by the validation.
+    pub fn violations(&self, method: &str) -> u64 {
+        self.methods
+            .get(method)
+            .map_or(0, |entry| entry.violations.load(Ordering::Relaxed))
+    }
+
+    /// Validates a message of the binary protocol, including the message header.
+    ///
+    /// The messages other than the calls, of the methods not in the validator, or malformed are
+    /// not validated, and the malformed ones are left to the decoder to report.
+    pub fn validate(&self, message: &[u8]) -> Result<(), ProtocolException> {
+        let mut reader = Reader { buf: message };
+        let Some((method, message_type)) = reader.message_begin() else {
+            return Ok(());
+        };
+        // only the calls and the oneways are requests
+        if message_type != 1 && message_type != 4 {
+            return Ok(());
+        }
+        let Some(entry) = self.methods.get(method) else {
+            return Ok(());
+        };
+        let validation = &entry.validation;
+        if validation.is_off() {
+            return Ok(());
+        }
+        match reader.validate_struct(&validation.args, validation, 0) {
+            Some(Err(violation)) => {
+                entry.violations.fetch_add(1, Ordering::Relaxed);
+                tracing::warn!("[VOLO] invalid request of method `{method}`: {violation}");
+                Err(ProtocolException::new(
+                    ProtocolExceptionKind::InvalidData,
+                    format!("invalid request of method `{method}`: {violation}"),
+                ))
+            }
+            Some(Ok(())) | None => Ok(()),
+        }
+    }
+}
+
+mod ttype {
+    pub const STOP: u8 = 0;
+    pub const BOOL: u8 = 2;
+    pub const I8: u8 = 3;
+    pub const DOUBLE: u8 = 4;
+    pub const I16: u8 = 6;
+    pub const I32: u8 = 8;
+    pub const I64: u8 = 10;
+    pub const BINARY: u8 = 11;
+    pub const STRUCT: u8 = 12;
+    pub const MAP: u8 = 13;
+    pub const SET: u8 = 14;
+    pub const LIST: u8 = 15;
+    pub const UUID: u8 = 16;
+}
+
+/// Reads the binary protocol, where `None` means the payload is malformed.
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.buf.len() < n {
+            return None;
+        }
+        let (taken, rest) = self.buf.split_at(n);
+        self.buf = rest;
+        Some(taken)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn i16(&mut self) -> Option<i16> {
+        self.take(2).map(|b| i16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        self.take(4)
+            .map(|b| i32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn len(&mut self) -> Option<usize> {
+        usize::try_from(self.i32()?).ok()
+    }
+
+    fn str(&mut self) -> Option<&'a str> {
+        let len = self.len()?;
+        std::str::from_utf8(self.take(len)?).ok()
+    }
+
+    /// Returns the method name and the message type.
+    fn message_begin(&mut self) -> Option<(&'a str, u8)> {
+        let first = self.i32()?;
+        let (name, message_type) = if first < 0 {
+            // strict: version and type, name, seq id
+            let name = self.str()?;
+            (name, (first & 0xff) as u8)
+        } else {
+            // old: name, type, seq id
+            let name = std::str::from_utf8(self.take(first as usize)?).ok()?;
+            (name, self.u8()?)
+        };
+        self.i32()?;
+        Some((name, message_type))
+    }
+
+    fn skip(&mut self, ttype: u8, depth: usize) -> Option<()> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        match ttype {
+            ttype::BOOL | ttype::I8 => self.take(1).map(drop),
+            ttype::I16 => self.take(2).map(drop),
+            ttype::I32 => self.take(4).map(drop),
+            ttype::DOUBLE | ttype::I64 => self.take(8).map(drop),
+            ttype::UUID => self.take(16).map(drop),
+            ttype::BINARY => {
+                let len = self.len()?;
+                self.take(len).map(drop)
+            }
+            ttype::STRUCT => loop {
+                let field_type = self.u8()?;
+                if field_type == ttype::STOP {
+                    return Some(());
+                }
+                self.i16()?;
+                self.skip(field_type, depth + 1)?;
+            },
+            ttype::MAP => {
+                let (key_type, value_type) = (self.u8()?, self.u8()?);
+                for _ in 0..self.len()? {
+                    self.skip(key_type, depth + 1)?;
+                    self.skip(value_type, depth + 1)?;
+                }
+                Some(())
+            }
+            ttype::SET | ttype::LIST => {
+                let element_type = self.u8()?;
+                for _ in 0..self.len()? {
+                    self.skip(element_type, depth + 1)?;
+                }
+                Some(())
+            }
+            _ => None,
+        }
+    }
+
+    fn validate_struct(
+        &mut self,
+        desc: &StructDescriptor,
+        validation: &MethodValidation,
+        depth: usize,
+    ) -> Option<Result<(), Violation>> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        let mut seen = Vec::with_capacity(desc.fields.len());
+        loop {
+            let field_type = self.u8()?;
+            if field_type == ttype::STOP {
+                break;
+            }
+            let id = self.i16()?;
+            match desc.fields.iter().find(|f| f.id == id) {
+                Some(field) => {
+                    seen.push(id);
+                    let result = self.validate_value(
+                        &field.ty,
+                        field_type,
+                        (desc, id),
+                        validation,
+                        depth + 1,
+                    )?;
+                    if result.is_err() {
+                        return Some(result);
+                    }
+                }
+                None => {
+                    if validation.field_ids && !validation.allow_unknown_fields {
+                        return Some(Err(Violation::UnknownField {
+                            struct_name: desc.name.clone(),
+                            field_id: id,
+                        }));
+                    }
+                    self.skip(field_type, depth + 1)?;
+                }
+            }
+        }
+
+        if validation.required_fields {
+            if let Some(missing) = desc
+                .fields
+                .iter()
+                .find(|f| f.required && !seen.contains(&f.id))
+            {
+                return Some(Err(Violation::MissingRequired {
+                    struct_name: desc.name.clone(),
+                    field_id: missing.id,
+                }));
+            }
+        }
+        Some(Ok(()))
+    }
+
+    /// Validates a value of the field `id` of the struct `desc`, which is an element of the field
+    /// if it's a container.
+    fn validate_value(
+        &mut self,
+        ty: &FieldType,
+        wire_type: u8,
+        (desc, id): (&StructDescriptor, i16),
+        validation: &MethodValidation,
+        depth: usize,
+    ) -> Option<Result<(), Violation>> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        // the mismatched types are left to the decoder
+        match (ty, wire_type) {
+            (FieldType::Struct(desc), ttype::STRUCT) => {
+                self.validate_struct(desc, validation, depth)
+            }
+            (FieldType::Enum(e), ttype::I32) => {
+                let value = self.i32()?;
+                if validation.enum_values && !e.values.contains(&value) {
+                    return Some(Err(Violation::InvalidEnum {
+                        struct_name: desc.name.clone(),
+                        field_id: id,
+                        enum_name: e.name.clone(),
+                        value,
+                    }));
+                }
+                Some(Ok(()))
+            }
+            (FieldType::List(element), ttype::LIST) | (FieldType::Set(element), ttype::SET) => {
+                let element_type = self.u8()?;
+                for _ in 0..self.len()? {
+                    let result = self.validate_value(
+                        element,
+                        element_type,
+                        (desc, id),
+                        validation,
+                        depth + 1,
+                    )?;
+                    if result.is_err() {
+                        return Some(result);
+                    }
+                }
+                Some(Ok(()))
+            }
+            (FieldType::Map(key, value), ttype::MAP) => {
+                let (key_type, value_type) = (self.u8()?, self.u8()?);
+                for _ in 0..self.len()? {
+                    for (ty, wire_type) in [(key, key_type), (value, value_type)] {
+                        let result =
+                            self.validate_value(ty, wire_type, (desc, id), validation, depth + 1)?;
+                        if result.is_err() {
+                            return Some(result);
+                        }
+                    }
+                }
+                Some(Ok(()))
+            }
+            _ => self.skip(wire_type, depth).map(Ok),
+        }
+    }
+}
+
+/// [`MakeValidateCodec`] implements [`MakeZeroCopyCodec`] to validate the requests by the
+/// [`RequestValidator`] before they are decoded by the inner codec, which should be the
+/// [`MakeThriftCodec`](super::thrift::MakeThriftCodec) inside a length-prefixed codec.
+#[derive(Debug)]
+pub struct MakeValidateCodec<Inner> {
+    inner: Inner,
+    validator: Arc<RequestValidator>,
+}
+
+impl<Inner: Clone> Clone for MakeValidateCodec<Inner> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            validator: self.validator.clone(),
+        }
+    }
+}
+
+impl<Inner> MakeValidateCodec<Inner> {
+    pub fn new(inner: Inner, validator: impl Into<Arc<RequestValidator>>) -> Self {
+        Self {
+            inner,
+            validator: validator.into(),
+        }
+    }
+}
+
+impl<Inner: MakeZeroCopyCodec> MakeZeroCopyCodec for MakeValidateCodec<Inner> {
+    type Encoder = Inner::Encoder;
+    type Decoder = ValidateDecoder<Inner::Decoder>;
+
+    #[inline]
+    fn make_codec(&self) -> (Self::Encoder, Self::Decoder) {
+        let (encoder, decoder) = self.inner.make_codec();
+        (
+            encoder,
+            ValidateDecoder {
+                inner: decoder,
+                validator: self.validator.clone(),
+            },
+        )
+    }
+}
+
+/// The decoder created by [`MakeValidateCodec`].
+pub struct ValidateDecoder<D> {
+    inner: D,
+    validator: Arc<RequestValidator>,
+}
+
+impl<D: ZeroCopyDecoder> ZeroCopyDecoder for ValidateDecoder<D> {
+    #[inline]
+    fn decode<Msg: Send + EntryMessage, Cx: ThriftContext>(
+        &mut self,
+        cx: &mut Cx,
+        bytes: &mut Bytes,
+    ) -> Result<Option<ThriftMessage<Msg>>, ThriftException> {
+        // only the binary protocol is validated, see `thrift::detect`
+        if matches!(bytes.first(), Some(0x80 | 0x00)) {
+            self.validator.validate(bytes)?;
+        }
+        self.inner.decode(cx, bytes)
+    }
+
+    #[inline]
+    async fn decode_async<
+        Msg: Send + EntryMessage,
+        Cx: ThriftContext,
+        R: AsyncRead + Unpin + Send + Sync,
+    >(
+        &mut self,
+        cx: &mut Cx,
+        reader: &mut BufReader<R>,
+    ) -> Result<Option<ThriftMessage<Msg>>, ThriftException> {
+        // the payload is not available before decoding without a length-prefixed transport
+        self.inner.decode_async(cx, reader).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::{BufMut, BytesMut};
+
+    use super::*;
+
+    /// Writes the messages of the binary protocol by hand.
+    struct Writer(BytesMut);
+
+    impl Writer {
+        fn call(method: &str, message_type: u8) -> Self {
+            let mut buf = BytesMut::new();
+            buf.put_i32(0x8001_0000u32 as i32 | message_type as i32);
+            buf.put_i32(method.len() as i32);
+            buf.put_slice(method.as_bytes());
+            buf.put_i32(1);
+            Self(buf)
+        }
+
+        fn field(mut self, ttype: u8, id: i16) -> Self {
+            self.0.put_u8(ttype);
+            self.0.put_i16(id);
+            self
+        }
+
+        fn i32(mut self, v: i32) -> Self {
+            self.0.put_i32(v);
+            self
+        }
+
+        fn list(mut self, element_type: u8, len: i32) -> Self {
+            self.0.put_u8(element_type);
+            self.0.put_i32(len);
+            self
+        }
+
+        fn stop(mut self) -> Self {
+            self.0.put_u8(ttype::STOP);
+            self
+        }
+
+        fn finish(self) -> Bytes {
+            self.0.freeze()
+        }
+    }
+
+    fn validator(validation: fn(MethodValidation) -> MethodValidation) -> RequestValidator {
+        let status = Arc::new(EnumDescriptor::new("Status", [0, 1]));
+        let request = Arc::new(
+            StructDescriptor::new("GetItemRequest")
+                .required(1, FieldType::Value)
+                .optional(2, FieldType::Enum(status.clone()))
+                .optional(3, FieldType::List(Box::new(FieldType::Enum(status)))),
+        );
+        let args = StructDescriptor::new("GetItemArgs").required(1, FieldType::Struct(request));
+        RequestValidator::new().method("GetItem", validation(MethodValidation::new(args)))
+    }
+
+    /// `GetItem(GetItemRequest { 1: id, 2: status, 3: statuses, extra })`
+    fn request(id: bool, status: i32, statuses: &[i32], extra: Option<i16>) -> Bytes {
+        let mut w = Writer::call("GetItem", 1).field(ttype::STRUCT, 1);
+        if id {
+            w = w.field(ttype::I32, 1).i32(42);
+        }
+        w = w.field(ttype::I32, 2).i32(status);
+        w = w
+            .field(ttype::LIST, 3)
+            .list(ttype::I32, statuses.len() as i32);
+        for s in statuses {
+            w = w.i32(*s);
+        }
+        if let Some(extra) = extra {
+            w = w.field(ttype::I32, extra).i32(0);
+        }
+        w.stop().stop().finish()
+    }
+
+    fn message(result: Result<(), ProtocolException>) -> String {
+        result.unwrap_err().message().to_string()
+    }
+
+    #[test]
+    fn required_fields() {
+        let v = validator(|v| v.required_fields(true));
+        assert!(v.validate(&request(true, 0, &[], None)).is_ok());
+        assert_eq!(
+            message(v.validate(&request(false, 0, &[], None))),
+            "invalid request of method `GetItem`: required field 1 of struct `GetItemRequest` is \
+             missing"
+        );
+        assert_eq!(v.violations("GetItem"), 1);
+
+        // the toggle is off
+        let v = validator(|v| v);
+        assert!(v.validate(&request(false, 0, &[], None)).is_ok());
+        assert_eq!(v.violations("GetItem"), 0);
+    }
+
+    #[test]
+    fn field_ids() {
+        let v = validator(|v| v.field_ids(true));
+        assert!(v.validate(&request(true, 0, &[], None)).is_ok());
+        assert_eq!(
+            message(v.validate(&request(true, 0, &[], Some(1000)))),
+            "invalid request of method `GetItem`: field 1000 is unknown to struct `GetItemRequest`"
+        );
+        // the garbage id of the arguments
+        let garbage = Writer::call("GetItem", 1)
+            .field(ttype::I32, -7)
+            .i32(0)
+            .stop()
+            .finish();
+        assert_eq!(
+            message(v.validate(&garbage)),
+            "invalid request of method `GetItem`: field -7 is unknown to struct `GetItemArgs`"
+        );
+        assert_eq!(v.violations("GetItem"), 2);
+
+        let v = validator(|v| v.field_ids(true).allow_unknown_fields(true));
+        assert!(v.validate(&request(true, 0, &[], Some(1000))).is_ok());
+    }
+
+    #[test]
+    fn enum_values() {
+        let v = validator(|v| v.enum_values(true));
+        assert!(v.validate(&request(true, 1, &[0, 1], None)).is_ok());
+        assert_eq!(
+            message(v.validate(&request(true, 7, &[], None))),
+            "invalid request of method `GetItem`: field 2 of struct `GetItemRequest` has value 7 \
+             not declared by enum `Status`"
+        );
+        // the elements of a list
+        assert!(v.validate(&request(true, 0, &[1, 9], None)).is_err());
+        assert_eq!(v.violations("GetItem"), 2);
+    }
+
+    #[test]
+    fn not_validated() {
+        let v = validator(|v| v.required_fields(true).field_ids(true).enum_values(true));
+        // other methods
+        let other = Writer::call("Other", 1).field(ttype::I32, 9).i32(0).stop();
+        assert!(v.validate(&other.finish()).is_ok());
+        // replies
+        let reply = Writer::call("GetItem", 2).stop().finish();
+        assert!(v.validate(&reply).is_ok());
+        // malformed, which is left to the decoder
+        let truncated = request(false, 0, &[], None);
+        assert!(v.validate(&truncated[..truncated.len() - 4]).is_ok());
+        assert_eq!(v.violations("GetItem"), 0);
+    }
+
+    mod schema {
+        use volo::descriptor::*;
+
+        // as generated by `volo-build` for
+        // `GetItem(1: GetItemRequest req)` and `GetTree(1: Node root)`, where
+        // `struct Node { 1: required Status status, 2: list<Node> children }`
+        static STATUS: EnumSchema = EnumSchema {
+            name: "Status",
+            values: &[0, 1],
+        };
+        static REQUEST: StructSchema = StructSchema {
+            name: "GetItemRequest",
+            fields: &[
+                FieldSchema {
+                    id: 1,
+                    required: true,
+                    ty: TypeSchema::Value,
+                },
+                FieldSchema {
+                    id: 2,
+                    required: false,
+                    ty: TypeSchema::Enum(&STATUS),
+                },
+                FieldSchema {
+                    id: 3,
+                    required: false,
+                    ty: TypeSchema::List(&STATUS_TYPE),
+                },
+            ],
+        };
+        static NODE: StructSchema = StructSchema {
+            name: "Node",
+            fields: &[
+                FieldSchema {
+                    id: 1,
+                    required: true,
+                    ty: TypeSchema::Enum(&STATUS),
+                },
+                FieldSchema {
+                    id: 2,
+                    required: false,
+                    ty: TypeSchema::List(&NODE_TYPE),
+                },
+            ],
+        };
+        static STATUS_TYPE: TypeSchema = TypeSchema::Enum(&STATUS);
+        static NODE_TYPE: TypeSchema = TypeSchema::Struct(&NODE);
+        static GET_ITEM_ARGS: StructSchema = StructSchema {
+            name: "ItemServiceGetItemArgsRecv",
+            fields: &[FieldSchema {
+                id: 1,
+                required: false,
+                ty: TypeSchema::Struct(&REQUEST),
+            }],
+        };
+        static GET_TREE_ARGS: StructSchema = StructSchema {
+            name: "ItemServiceGetTreeArgsRecv",
+            fields: &[FieldSchema {
+                id: 1,
+                required: false,
+                ty: TypeSchema::Struct(&NODE),
+            }],
+        };
+
+        const fn method(name: &'static str, args: &'static StructSchema) -> MethodDescriptor {
+            MethodDescriptor {
+                service: "ItemService",
+                name,
+                path: name,
+                client_streaming: false,
+                server_streaming: false,
+                request_type: "",
+                response_type: "",
+                annotations: &[],
+                request_schema: Some(args),
+            }
+        }
+
+        static METHODS: &[MethodDescriptor] = &[
+            method("GetItem", &GET_ITEM_ARGS),
+            method("GetTree", &GET_TREE_ARGS),
+        ];
+        pub static SERVICE: ServiceDescriptor = ServiceDescriptor {
+            name: "ItemService",
+            methods: METHODS,
+        };
+    }
+
+    #[test]
+    fn generated_schemas() {
+        let v =
+            RequestValidator::new().service(&schema::SERVICE, |method, validation| {
+                match method.name {
+                    "GetItem" => validation.required_fields(true).enum_values(true),
+                    _ => validation,
+                }
+            });
+        assert!(v.validate(&request(true, 1, &[0, 1], None)).is_ok());
+        assert_eq!(
+            message(v.validate(&request(false, 0, &[], None))),
+            "invalid request of method `GetItem`: required field 1 of struct `GetItemRequest` is \
+             missing"
+        );
+        assert!(v.validate(&request(true, 0, &[1, 9], None)).is_err());
+        assert_eq!(v.violations("GetItem"), 2);
+        // the checks of `GetTree` are all off
+        assert!(v.methods.get("GetTree").is_none());
+
+        // the recursive struct is validated at the first level
+        let v = RequestValidator::new().service(&schema::SERVICE, |_, validation| {
+            validation.enum_values(true)
+        });
+        let node = |status: i32| {
+            Writer::call("GetTree", 1)
+                .field(ttype::STRUCT, 1)
+                .field(ttype::I32, 1)
+                .i32(0)
+                .field(ttype::LIST, 2)
+                .list(ttype::STRUCT, 1)
+                .field(ttype::I32, 1)
+                .i32(status)
+                .stop()
+                .stop()
+                .stop()
+                .finish()
+        };
+        assert!(v.validate(&node(7)).is_ok());
+        let root = Writer::call("GetTree", 1)
+            .field(ttype::STRUCT, 1)
+            .field(ttype::I32, 1)
+            .i32(7)
+            .stop()
+            .stop()
+            .finish();
+        assert_eq!(
+            message(v.validate(&root)),
+            "invalid request of method `GetTree`: field 1 of struct `Node` has value 7 not \
+             declared by enum `Status`"
+        );
+    }
+}
@@ -20,6 +20,14 @@ pub trait Decoder: Send + 'static {
         &mut self,
         cx: &mut Cx,
     ) -> impl Future<Output = Result<Option<ThriftMessage<Msg>>, ThriftException>> + Send;
+
+    /// Waits until the connection is closed by the peer without consuming any data, which is used
+    /// by the ping-pong server to cancel the request being handled.
+    ///
+    /// The default implementation never completes, which means the peer close is not detected.
+    fn closed(&mut self) -> impl Future<Output = ()> + Send {
+        std::future::pending()
+    }
 }
 
 /// [`Encoder`] writes a [`ThriftMessage`] to an [`AsyncWrite`] and flushes the data.
@@ -1,14 +1,31 @@
-use std::time::Duration;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use chrono::{DateTime, Local};
 use paste::paste;
-use pilota::thrift::TMessageIdentifier;
+use pilota::thrift::{
+    new_protocol_exception, ProtocolExceptionKind, TMessageIdentifier, ThriftException,
+};
+use tokio_util::sync::CancellationToken;
 use volo::{
+    config::ConfigError,
     context::{Context, Reusable, Role, RpcCx, RpcInfo},
+    net::dial::ConnectRetry,
     newtype_impl_context,
+    retry::{Pushback, RetryPolicy},
+    route::{shadow_rpc_info, ShadowContext},
+    FastStr,
 };
 
-use crate::{client::CallOpt, protocol::TMessageType, BizError};
+use crate::{
+    client::CallOpt,
+    codec::default::ttheader::{IntMetaKey, ResponseMetaLimits},
+    protocol::TMessageType,
+    BizError,
+};
 
 macro_rules! stat_impl {
     ($t: ident) => {
@@ -60,6 +77,11 @@ impl ServerTransportInfo {
 /// This is unstable now and may be changed in the future.
 #[derive(Debug, Default, Clone)]
 pub struct CommonStats {
+    // when the first bytes of the message are read, and when they are received by the kernel if
+    // the kernel timestamps are enabled, see `volo::net::timestamp`
+    arrive_at: Option<DateTime<Local>>,
+    kernel_arrive_at: Option<DateTime<Local>>,
+
     // if there's a length-prefixed transport, we can get the read time
     read_start_at: Option<DateTime<Local>>,
     read_end_at: Option<DateTime<Local>>,
@@ -78,9 +100,14 @@ pub struct CommonStats {
 
     // biz error
     biz_error: Option<BizError>,
+
+    // retry pushback
+    retry_pushback: Option<Pushback>,
 }
 
 impl CommonStats {
+    stat_impl!(arrive_at);
+    stat_impl!(kernel_arrive_at);
     stat_impl!(read_start_at);
     stat_impl!(read_end_at);
     stat_impl!(decode_start_at);
@@ -122,6 +149,19 @@ impl CommonStats {
         self.biz_error = Some(biz_error);
     }
 
+    /// The retry pushback sent by the server in TTHeader.
+    #[inline]
+    pub fn retry_pushback(&self) -> Option<Pushback> {
+        self.retry_pushback
+    }
+
+    /// Sets the retry pushback, which will be sent back to the client in TTHeader if set by the
+    /// server.
+    #[inline]
+    pub fn set_retry_pushback(&mut self, pushback: Pushback) {
+        self.retry_pushback = Some(pushback);
+    }
+
     #[inline]
     pub fn reset(&mut self) {
         *self = Self { ..Self::default() }
@@ -164,6 +204,263 @@ impl ClientStats {
     }
 }
 
+/// The string key-value headers of TTHeader which are not interpreted by volo, and the int ones
+/// of the request.
+///
+/// The headers with the prefixes of metainfo are not here, since they are set into the persistent,
+/// transient or backward metainfo, which are propagated to the downstream or the upstream, while
+/// these headers only live in the current request and response. Unless the metainfo isn't copied
+/// eagerly by [`MakeTTHeaderCodec::with_eager_metainfo`], then the forward ones are also here until
+/// [`TTHeaderKvs::load_metainfo`].
+///
+/// [`MakeTTHeaderCodec::with_eager_metainfo`]: crate::codec::default::ttheader::MakeTTHeaderCodec::with_eager_metainfo
+#[derive(Default, Clone, Debug)]
+pub struct TTHeaderKvs {
+    request: HashMap<FastStr, FastStr>,
+    int_request: HashMap<IntMetaKey, FastStr>,
+    response: ResponseMeta,
+}
+
+impl TTHeaderKvs {
+    /// Returns the value of the header in the request.
+    #[inline]
+    pub fn get(&self, key: &str) -> Option<&FastStr> {
+        self.request.get(key)
+    }
+
+    /// Returns all the headers in the request.
+    #[inline]
+    pub fn request(&self) -> &HashMap<FastStr, FastStr> {
+        &self.request
+    }
+
+    /// Iterates the headers in the request without cloning them.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.request.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Returns the value of the int header in the request, e.g. the [`IntMetaKey::FromService`].
+    #[inline]
+    pub fn get_int(&self, key: IntMetaKey) -> Option<&FastStr> {
+        self.int_request.get(&key)
+    }
+
+    /// Iterates the int headers known by volo in the request.
+    #[inline]
+    pub fn iter_int(&self) -> impl Iterator<Item = (IntMetaKey, &str)> {
+        self.int_request.iter().map(|(k, v)| (*k, v.as_str()))
+    }
+
+    /// Copies the forward metainfo headers of the request into the `METAINFO` of the current task,
+    /// which is only needed when they're not copied eagerly by the decoding.
+    pub fn load_metainfo(&self) {
+        metainfo::METAINFO.with(|metainfo| {
+            let metainfo = &mut *metainfo.borrow_mut();
+            for (k, v) in self.request.iter() {
+                if k.starts_with(metainfo::RPC_PREFIX_PERSISTENT) {
+                    metainfo.strip_rpc_prefix_and_set_persistent(k.clone(), v.clone());
+                } else if k.starts_with(metainfo::RPC_PREFIX_TRANSIENT) {
+                    metainfo.strip_rpc_prefix_and_set_upstream(k.clone(), v.clone());
+                }
+            }
+        })
+    }
+
+    /// Returns all the headers set for the response.
+    #[inline]
+    pub fn response(&self) -> &HashMap<FastStr, FastStr> {
+        &self.response.kvs
+    }
+
+    /// Returns the headers set for the response, see [`ResponseMeta`].
+    #[inline]
+    pub fn response_meta(&self) -> &ResponseMeta {
+        &self.response
+    }
+
+    /// Returns the headers to be set for the response, see [`ResponseMeta`].
+    #[inline]
+    pub fn response_meta_mut(&mut self) -> &mut ResponseMeta {
+        &mut self.response
+    }
+
+    /// Sets a header of the response, and returns `false` if the key is reserved by volo or
+    /// metainfo, or the response is being encoded, in which case the header is ignored.
+    pub fn insert_response(&mut self, key: impl Into<FastStr>, value: impl Into<FastStr>) -> bool {
+        match self.response.insert(key, value) {
+            Ok(()) => true,
+            Err(e) => {
+                tracing::warn!("[VOLO] ignore the ttheader response key: {e}");
+                false
+            }
+        }
+    }
+
+    /// Removes a header of the response.
+    #[inline]
+    pub fn remove_response(&mut self, key: &str) -> Option<FastStr> {
+        self.response.remove(key)
+    }
+
+    #[inline]
+    pub(crate) fn insert_request(&mut self, key: FastStr, value: FastStr) {
+        self.request.insert(key, value);
+    }
+
+    #[inline]
+    pub(crate) fn set_int_request(&mut self, int_request: HashMap<IntMetaKey, FastStr>) {
+        self.int_request = int_request;
+    }
+
+    #[inline]
+    pub fn reset(&mut self) {
+        self.request.clear();
+        self.int_request.clear();
+        self.response.reset();
+    }
+}
+
+/// The string headers of TTHeader set by the handler for the response, e.g. the instance serving
+/// the request or the status of the cache, which are received by the client in the
+/// [`ReceivedResponseMeta`] of its [`CallOpt`].
+///
+/// The headers are bounded by the [`ResponseMetaLimits`] of the codec, and can't be set once the
+/// response is being encoded.
+///
+/// [`ResponseMetaLimits`]: crate::codec::default::ttheader::ResponseMetaLimits
+#[derive(Default, Clone, Debug)]
+pub struct ResponseMeta {
+    kvs: HashMap<FastStr, FastStr>,
+    sealed: bool,
+}
+
+/// The error of setting a header of [`ResponseMeta`].
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum ResponseMetaError {
+    #[error("`{0}` is reserved by volo or metainfo")]
+    Reserved(FastStr),
+    #[error("`{0}` is set after the response started being encoded")]
+    Sealed(FastStr),
+}
+
+impl ResponseMeta {
+    /// Sets a header of the response, which fails if the key is reserved by volo or metainfo, or
+    /// the response is being encoded.
+    pub fn insert(
+        &mut self,
+        key: impl Into<FastStr>,
+        value: impl Into<FastStr>,
+    ) -> Result<(), ResponseMetaError> {
+        let key = key.into();
+        if self.sealed {
+            return Err(ResponseMetaError::Sealed(key));
+        }
+        if crate::codec::default::ttheader::is_reserved_key(&key) {
+            return Err(ResponseMetaError::Reserved(key));
+        }
+        self.kvs.insert(key, value.into());
+        Ok(())
+    }
+
+    #[inline]
+    pub fn get(&self, key: &str) -> Option<&FastStr> {
+        self.kvs.get(key)
+    }
+
+    #[inline]
+    pub fn remove(&mut self, key: &str) -> Option<FastStr> {
+        self.kvs.remove(key)
+    }
+
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.kvs.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.kvs.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.kvs.is_empty()
+    }
+
+    /// Applies the limits before the response is encoded, and rejects the headers set after.
+    pub(crate) fn seal(&mut self, limits: &ResponseMetaLimits) -> Result<(), ThriftException> {
+        self.sealed = true;
+        let size = self
+            .kvs
+            .iter()
+            .map(|(k, v)| k.len() + v.len())
+            .sum::<usize>();
+        if self.kvs.len() <= limits.max_kvs && size <= limits.max_size {
+            return Ok(());
+        }
+        let msg = format!(
+            "the response meta of {} kvs in {size} bytes exceeds the limits of {} kvs in {} bytes",
+            self.kvs.len(),
+            limits.max_kvs,
+            limits.max_size
+        );
+        if !limits.truncate {
+            return Err(new_protocol_exception(
+                ProtocolExceptionKind::SizeLimit,
+                msg,
+            ));
+        }
+        tracing::warn!("[VOLO] {msg}, truncated");
+        // keeps the headers in the order of the keys, which is stable across the requests
+        let mut keys = self.kvs.keys().cloned().collect::<Vec<_>>();
+        keys.sort();
+        let (mut kept, mut size) = (0, 0);
+        for key in keys {
+            let len = key.len() + self.kvs[&key].len();
+            if kept < limits.max_kvs && size + len <= limits.max_size {
+                kept += 1;
+                size += len;
+            } else {
+                self.kvs.remove(&key);
+            }
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn reset(&mut self) {
+        self.kvs.clear();
+        self.sealed = false;
+    }
+}
+
+/// The headers of [`ResponseMeta`] received by the client, which is set by
+/// [`CallOpt::response_meta`] for a call.
+///
+/// The clones share the same headers.
+#[derive(Default, Clone, Debug)]
+pub struct ReceivedResponseMeta(Arc<Mutex<HashMap<FastStr, FastStr>>>);
+
+impl ReceivedResponseMeta {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, key: &str) -> Option<FastStr> {
+        self.0.lock().unwrap().get(key).cloned()
+    }
+
+    /// Takes all the headers received.
+    pub fn take(&self) -> HashMap<FastStr, FastStr> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+
+    pub(crate) fn insert(&self, key: FastStr, value: FastStr) {
+        self.0.lock().unwrap().insert(key, value);
+    }
+}
+
 #[derive(Default, Clone, Debug)]
 pub struct PooledTransport {
     pub should_reuse: bool,
@@ -196,10 +493,13 @@ pub struct ServerCxInner {
     pub req_msg_type: Option<TMessageType>,
     pub msg_type: Option<TMessageType>,
     pub transport: ServerTransportInfo,
+    /// The string headers of TTHeader which are not interpreted by volo.
+    pub ttheader_kvs: TTHeaderKvs,
     /// This is unstable now and may be changed in the future.
     pub stats: ServerStats,
     /// This is unstable now and may be changed in the future.
     pub common_stats: CommonStats,
+    pub(crate) cancellation: CancellationToken,
 }
 
 #[derive(Debug)]
@@ -234,6 +534,16 @@ impl ClientContext {
     }
 }
 
+impl ShadowContext for ClientContext {
+    fn shadow(&self) -> Self {
+        Self::new(
+            self.seq_id,
+            shadow_rpc_info(&self.rpc_info),
+            self.message_type,
+        )
+    }
+}
+
 impl std::ops::Deref for ClientContext {
     type Target = RpcCx<ClientCxInner, Config>;
 
@@ -256,8 +566,64 @@ thread_local! {
     pub static CLIENT_CONTEXT_CACHE: std::cell::RefCell<Vec<ClientContext>> = std::cell::RefCell::new(Vec::with_capacity(128));
 }
 
+/// The default capacity of the per-thread cache of the server contexts.
+pub const DEFAULT_SERVER_CONTEXT_CACHE_CAPACITY: usize = 128;
+
 thread_local! {
-    pub(crate) static SERVER_CONTEXT_CACHE: std::cell::RefCell<Vec<ServerContext>> = std::cell::RefCell::new(Vec::with_capacity(128));
+    static SERVER_CONTEXT_CACHE: std::cell::RefCell<Vec<ServerContext>> = std::cell::RefCell::new(Vec::with_capacity(DEFAULT_SERVER_CONTEXT_CACHE_CAPACITY));
+}
+
+/// The function clearing the state of a server context when its request is done, see
+/// [`Server::context_reset`](crate::server::Server::context_reset).
+pub type ContextResetFn = Arc<dyn Fn(&mut ServerContext) + Send + Sync>;
+
+/// How the server contexts are reused by the ping-pong servers.
+///
+/// The contexts are cached per thread, and the cache is shared by all the servers on the thread.
+#[derive(Clone)]
+pub(crate) struct ServerContextCache {
+    capacity: usize,
+    reset: Option<ContextResetFn>,
+}
+
+impl Default for ServerContextCache {
+    fn default() -> Self {
+        Self {
+            capacity: DEFAULT_SERVER_CONTEXT_CACHE_CAPACITY,
+            reset: None,
+        }
+    }
+}
+
+impl ServerContextCache {
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+    }
+
+    pub(crate) fn set_reset(&mut self, reset: ContextResetFn) {
+        self.reset = Some(reset);
+    }
+
+    /// Takes a context from the cache, or creates a new one.
+    pub(crate) fn pop(&self) -> ServerContext {
+        SERVER_CONTEXT_CACHE.with(|cache| cache.borrow_mut().pop().unwrap_or_default())
+    }
+
+    /// Resets the context, and puts it back to the cache if the cache is not full.
+    pub(crate) fn push(&self, mut cx: ServerContext) {
+        // the custom reset runs first, so the state it clears is dropped even if the context is
+        // not cached
+        if let Some(reset) = &self.reset {
+            reset(&mut cx);
+        }
+        SERVER_CONTEXT_CACHE.with(|cache| {
+            let mut cache = cache.borrow_mut();
+            if cache.len() < self.capacity {
+                cx.reset(Default::default());
+                cache.push(cx);
+            }
+        });
+    }
 }
 
 #[derive(Debug)]
@@ -275,6 +641,40 @@ impl Default for ServerContext {
 
 newtype_impl_context!(ServerContext, Config, 0);
 
+impl ServerContext {
+    /// Returns the string headers of TTHeader to be set for the response, see [`ResponseMeta`].
+    #[inline]
+    pub fn response_meta_mut(&mut self) -> &mut ResponseMeta {
+        self.ttheader_kvs.response_meta_mut()
+    }
+
+    /// The token cancelled when the request is abandoned by the caller, which is triggered by the
+    /// connection being closed by the peer in the middle of the request, see
+    /// [`Server::cancel_on_peer_close`](crate::server::Server::cancel_on_peer_close).
+    ///
+    /// The handler future is dropped after that, so it is only useful to stop the work spawned
+    /// by the handler, e.g. `token.cancelled()` in the spawned tasks.
+    pub fn cancellation_token(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
+    /// The delay from the arrival of the request to the start of processing it, which includes
+    /// the time waiting in the socket buffer and the runtime, and the time reading and decoding it.
+    ///
+    /// The kernel receive timestamp is preferred if it is available, see
+    /// [`volo::net::timestamp`].
+    ///
+    /// This is unstable now and may be changed in the future.
+    pub fn pre_process_delay(&self) -> Option<Duration> {
+        let arrive_at = self
+            .common_stats
+            .kernel_arrive_at()
+            .or(self.common_stats.arrive_at())?;
+        let start_at = self.stats.process_start_at()?;
+        (start_at - arrive_at).to_std().ok()
+    }
+}
+
 impl std::ops::Deref for ServerContext {
     type Target = RpcCx<ServerCxInner, Config>;
 
@@ -303,6 +703,16 @@ pub trait ThriftContext: volo::context::Context<Config = Config> + Send + 'stati
     /// This is unstable now and may be changed in the future.
     #[doc(hidden)]
     fn stats_mut(&mut self) -> &mut CommonStats;
+    /// The string headers of TTHeader exposed to the handlers, which is only available at server
+    /// side.
+    #[doc(hidden)]
+    fn ttheader_kvs(&self) -> Option<&TTHeaderKvs> {
+        None
+    }
+    #[doc(hidden)]
+    fn ttheader_kvs_mut(&mut self) -> Option<&mut TTHeaderKvs> {
+        None
+    }
 }
 
 impl ThriftContext for ClientContext {
@@ -375,6 +785,16 @@ impl ThriftContext for ServerContext {
     fn stats_mut(&mut self) -> &mut CommonStats {
         &mut self.common_stats
     }
+
+    #[inline]
+    fn ttheader_kvs(&self) -> Option<&TTHeaderKvs> {
+        Some(&self.ttheader_kvs)
+    }
+
+    #[inline]
+    fn ttheader_kvs_mut(&mut self) -> Option<&mut TTHeaderKvs> {
+        Some(&mut self.ttheader_kvs)
+    }
 }
 
 const DEFAULT_RPC_TIMEOUT: Duration = Duration::from_secs(1);
@@ -386,6 +806,8 @@ pub struct Config {
     rpc_timeout: Option<Duration>,
     connect_timeout: Option<Duration>,
     read_write_timeout: Option<Duration>,
+    retry_policy: Option<RetryPolicy>,
+    connect_retry: Option<ConnectRetry>,
 }
 
 impl Config {
@@ -395,6 +817,8 @@ impl Config {
             rpc_timeout: None,
             connect_timeout: None,
             read_write_timeout: None,
+            retry_policy: None,
+            connect_retry: None,
         }
     }
 
@@ -449,6 +873,51 @@ impl Config {
         self.read_write_timeout = timeout;
     }
 
+    #[inline]
+    pub fn retry_policy(&self) -> Option<RetryPolicy> {
+        self.retry_policy
+    }
+
+    /// Sets the retry policy, which overrides the one of the
+    /// [`RetryLayer`](volo::retry::RetryLayer).
+    ///
+    /// This can be set by the CallOpt to override the policy per method.
+    #[inline]
+    pub fn set_retry_policy(&mut self, policy: Option<RetryPolicy>) {
+        self.retry_policy = policy;
+    }
+
+    #[inline]
+    pub fn connect_retry(&self) -> Option<ConnectRetry> {
+        self.connect_retry
+    }
+
+    /// Sets the retry of the failed connects, which only takes effect when set by the client
+    /// builder.
+    #[inline]
+    pub fn set_connect_retry(&mut self, retry: Option<ConnectRetry>) {
+        self.connect_retry = retry;
+    }
+
+    /// Checks the timeouts set by the client builder.
+    pub(crate) fn check(&self, err: &mut ConfigError) {
+        err.ensure_non_zero("rpc_timeout", self.rpc_timeout);
+        err.ensure_non_zero("connect_timeout", self.connect_timeout);
+        err.ensure_non_zero("read_write_timeout", self.read_write_timeout);
+        err.ensure_within(
+            "connect_timeout",
+            self.connect_timeout,
+            "rpc_timeout",
+            self.rpc_timeout,
+        );
+        err.ensure_within(
+            "read_write_timeout",
+            self.read_write_timeout,
+            "rpc_timeout",
+            self.rpc_timeout,
+        );
+    }
+
     #[inline]
     pub fn merge(&mut self, other: Self) {
         if let Some(t) = other.rpc_timeout {
@@ -460,6 +929,12 @@ impl Config {
         if let Some(t) = other.read_write_timeout {
             self.read_write_timeout = Some(t);
         }
+        if let Some(p) = other.retry_policy {
+            self.retry_policy = Some(p);
+        }
+        if let Some(r) = other.connect_retry {
+            self.connect_retry = Some(r);
+        }
     }
 }
 
@@ -468,6 +943,8 @@ impl Reusable for Config {
         self.rpc_timeout = None;
         self.connect_timeout = None;
         self.read_write_timeout = None;
+        self.retry_policy = None;
+        self.connect_retry = None;
     }
 }
 
@@ -495,14 +972,27 @@ impl ::volo::client::Apply<ClientContext> for CallOpt {
             callee.set_address(addr);
         }
         cx.rpc_info.config_mut().merge(self.config);
+        if let Some(response_meta) = self.response_meta {
+            cx.extensions_mut().insert(response_meta);
+        }
         Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Role, RpcInfo};
-    use crate::context::ClientContext;
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use volo::{config::ConfigError, context::Context};
+
+    use super::{Config, Role, RpcInfo, ServerContextCache};
+    use crate::context::{ClientContext, ServerContext};
 
     #[test]
     fn test_rpcinfo() {
@@ -514,4 +1004,109 @@ mod tests {
         .rpc_info;
         println!("{:?}", ri);
     }
+
+    #[test]
+    fn pre_process_delay() {
+        let mut cx = ServerContext::default();
+        assert_eq!(cx.pre_process_delay(), None);
+
+        let arrive_at = chrono::Local::now();
+        cx.common_stats.set_arrive_at(arrive_at);
+        cx.stats
+            .set_process_start_at(arrive_at + chrono::Duration::milliseconds(30));
+        assert_eq!(cx.pre_process_delay(), Some(Duration::from_millis(30)));
+
+        // the kernel timestamp is preferred
+        cx.common_stats
+            .set_kernel_arrive_at(arrive_at - chrono::Duration::milliseconds(20));
+        assert_eq!(cx.pre_process_delay(), Some(Duration::from_millis(50)));
+
+        cx.reset(Default::default());
+        assert_eq!(cx.pre_process_delay(), None);
+    }
+
+    #[test]
+    fn server_context_cache() {
+        struct Scoped(Arc<AtomicUsize>);
+
+        impl Drop for Scoped {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        std::thread::spawn(|| {
+            let resets = Arc::new(AtomicUsize::new(0));
+            let dropped = Arc::new(AtomicUsize::new(0));
+            let mut cache = ServerContextCache::default();
+            cache.set_capacity(1);
+            cache.set_reset(Arc::new({
+                let resets = resets.clone();
+                move |_: &mut ServerContext| {
+                    resets.fetch_add(1, Ordering::Relaxed);
+                }
+            }));
+
+            let mut cxs = Vec::new();
+            for _ in 0..2 {
+                let mut cx = cache.pop();
+                cx.extensions_mut().insert(Scoped(dropped.clone()));
+                cx.ttheader_kvs.insert_response("k", "v");
+                cxs.push(cx);
+            }
+            for cx in cxs {
+                cache.push(cx);
+            }
+            // the second one is dropped since the cache is full, and the extensions of the cached
+            // one are dropped by the reset
+            assert_eq!(resets.load(Ordering::Relaxed), 2);
+            assert_eq!(dropped.load(Ordering::Relaxed), 2);
+
+            let cx = cache.pop();
+            assert!(cx.extensions().get::<Scoped>().is_none());
+            assert!(cx.ttheader_kvs.response().is_empty());
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn check_config() {
+        let secs = Duration::from_secs;
+        // rpc_timeout, connect_timeout, read_write_timeout, the problematic fields
+        let cases: &[(
+            Option<Duration>,
+            Option<Duration>,
+            Option<Duration>,
+            &[&str],
+        )] = &[
+            (None, None, None, &[]),
+            (None, Some(secs(2)), Some(secs(3)), &[]),
+            (Some(secs(1)), Some(secs(1)), Some(secs(1)), &[]),
+            (Some(Duration::ZERO), None, None, &["rpc_timeout"]),
+            (
+                None,
+                Some(Duration::ZERO),
+                Some(Duration::ZERO),
+                &["connect_timeout", "read_write_timeout"],
+            ),
+            (
+                Some(secs(1)),
+                Some(secs(2)),
+                Some(secs(3)),
+                &["connect_timeout", "read_write_timeout"],
+            ),
+        ];
+        for (rpc, connect, read_write, fields) in cases {
+            let mut config = Config::new();
+            config.set_rpc_timeout(*rpc);
+            config.set_connect_timeout(*connect);
+            config.set_read_write_timeout(*read_write);
+
+            let mut err = ConfigError::new();
+            config.check(&mut err);
+            let problems = err.problems().iter().map(|p| p.field).collect::<Vec<_>>();
+            assert_eq!(problems, *fields, "{err}");
+        }
+    }
 }
@@ -6,7 +6,14 @@ pub use pilota::thrift::{
     TransportException,
 };
 use pilota::{AHashMap, FastStr};
-use volo::loadbalance::error::{LoadBalanceError, Retryable};
+pub use volo::net::fingerprint::ProtocolMismatch;
+use volo::{
+    error::{tag, tagged, CodedError, ErrorCode, Namespace},
+    loadbalance::{
+        error::{LoadBalanceError, Retryable},
+        outlier::OutlierFailure,
+    },
+};
 
 pub type ServerResult<T> = Result<T, ServerError>;
 pub type ClientResult<T> = Result<T, ClientError>;
@@ -28,9 +35,13 @@ impl From<anyhow::Error> for ServerError {
                     e.downcast::<BizError>()
                         .map(Into::into)
                         .unwrap_or_else(|e| {
+                            let msg = match volo::error::find_code(&*e, |_| None) {
+                                Some(code) => tag(code, &e),
+                                None => e.to_string(),
+                            };
                             ServerError::Application(ApplicationException::new(
                                 ApplicationExceptionKind::INTERNAL_ERROR,
-                                e.to_string(),
+                                msg,
                             ))
                         })
                 })
@@ -44,12 +55,18 @@ impl From<ClientError> for ServerError {
             ClientError::Application(e) => ServerError::Application(e),
             ClientError::Transport(e) => ServerError::Application(ApplicationException::new(
                 ApplicationExceptionKind::INTERNAL_ERROR,
-                e.to_string(),
+                tag(code::TRANSPORT, e),
             )),
             ClientError::Protocol(e) => ServerError::Application(ApplicationException::new(
                 ApplicationExceptionKind::PROTOCOL_ERROR,
-                e.to_string(),
+                tag(code::PROTOCOL, e),
             )),
+            ClientError::ProtocolMismatch(e) => {
+                ServerError::Application(ApplicationException::new(
+                    ApplicationExceptionKind::PROTOCOL_ERROR,
+                    tag(code::PROTOCOL_MISMATCH, e),
+                ))
+            }
             ClientError::Biz(e) => ServerError::Biz(e),
         }
     }
@@ -65,7 +82,7 @@ impl From<ProtocolException> for ServerError {
     fn from(e: ProtocolException) -> Self {
         ServerError::Application(ApplicationException::new(
             ApplicationExceptionKind::PROTOCOL_ERROR,
-            e.to_string(),
+            tag(code::PROTOCOL, e),
         ))
     }
 }
@@ -74,7 +91,7 @@ impl From<TransportException> for ServerError {
     fn from(e: TransportException) -> Self {
         ServerError::Application(ApplicationException::new(
             ApplicationExceptionKind::INTERNAL_ERROR,
-            e.to_string(),
+            tag(code::TRANSPORT, e),
         ))
     }
 }
@@ -88,6 +105,15 @@ impl ServerError {
     }
 }
 
+impl CodedError for ServerError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            ServerError::Application(e) => application_exception_code(e),
+            ServerError::Biz(e) => e.code(),
+        }
+    }
+}
+
 #[derive(Debug, thiserror::Error, Clone)]
 pub enum ClientError {
     #[error("application exception: {0}")]
@@ -98,6 +124,13 @@ pub enum ClientError {
     Protocol(#[from] ProtocolException),
     #[error("biz error: {0}")]
     Biz(#[from] BizError),
+    /// The peer speaks another protocol than thrift, e.g. the client is pointed at the port of
+    /// an HTTP or gRPC server, or of a server that requires TLS.
+    ///
+    /// This is only detected after a call has failed to decode the response, see
+    /// [`volo::net::fingerprint`].
+    #[error("protocol mismatch: {0}")]
+    ProtocolMismatch(#[from] ProtocolMismatch),
 }
 
 impl ClientError {
@@ -107,6 +140,8 @@ impl ClientError {
             ClientError::Transport(e) => e.append_msg(msg),
             ClientError::Protocol(e) => e.append_msg(msg),
             ClientError::Biz(e) => e.append_msg(msg),
+            // the mismatch is self-explanatory and carries no free-form message
+            ClientError::ProtocolMismatch(_) => {}
         }
     }
 }
@@ -120,12 +155,36 @@ impl Retryable for ClientError {
     }
 }
 
+impl CodedError for ClientError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            ClientError::Application(e) => application_exception_code(e),
+            ClientError::Transport(_) => code::TRANSPORT,
+            ClientError::Protocol(_) => code::PROTOCOL,
+            ClientError::Biz(e) => e.code(),
+            ClientError::ProtocolMismatch(_) => code::PROTOCOL_MISMATCH,
+        }
+    }
+
+    fn is_retryable(&self) -> bool {
+        self.retryable()
+    }
+}
+
+impl OutlierFailure for ClientError {
+    fn is_outlier_failure(&self) -> bool {
+        matches!(
+            self,
+            Self::Transport(_) | Self::Protocol(_) | Self::ProtocolMismatch(_)
+        )
+    }
+}
+
 impl From<LoadBalanceError> for ClientError {
-    // TODO: use specified error code
     fn from(err: LoadBalanceError) -> Self {
         ClientError::Application(ApplicationException::new(
             ApplicationExceptionKind::INTERNAL_ERROR,
-            err.to_string(),
+            tag(err.code(), &err),
         ))
     }
 }
@@ -146,6 +205,23 @@ impl From<std::io::Error> for ClientError {
     }
 }
 
+#[cfg(feature = "fault")]
+impl From<volo::fault::FaultError> for ClientError {
+    fn from(err: volo::fault::FaultError) -> Self {
+        match err {
+            volo::fault::FaultError::Dropped => {
+                io::Error::new(io::ErrorKind::ConnectionReset, err).into()
+            }
+            volo::fault::FaultError::Injected { .. } => {
+                ClientError::Application(ApplicationException::new(
+                    ApplicationExceptionKind::INTERNAL_ERROR,
+                    err.to_string(),
+                ))
+            }
+        }
+    }
+}
+
 #[derive(Debug, thiserror::Error, Clone, Default)]
 pub struct BizError {
     pub status_code: i32,
@@ -198,9 +274,44 @@ impl BizError {
     }
 }
 
+impl CodedError for BizError {
+    fn code(&self) -> ErrorCode {
+        code::BIZ
+    }
+}
+
 impl From<BizError> for ApplicationException {
     fn from(e: BizError) -> Self {
-        ApplicationException::new(ApplicationExceptionKind::INTERNAL_ERROR, e.to_string())
+        ApplicationException::new(ApplicationExceptionKind::INTERNAL_ERROR, tag(code::BIZ, e))
+    }
+}
+
+/// Returns the code of the exception, e.g. the one returned by the codecs.
+pub fn thrift_exception_code(e: &ThriftException) -> ErrorCode {
+    match e {
+        ThriftException::Application(e) => application_exception_code(e),
+        ThriftException::Transport(_) => code::TRANSPORT,
+        ThriftException::Protocol(_) => code::PROTOCOL,
+    }
+}
+
+/// Returns the code of the application exception, which is the one it is tagged with if it is
+/// converted from another error, or the one of its kind.
+pub fn application_exception_code(e: &ApplicationException) -> ErrorCode {
+    if let Some(code) = tagged(e.message()) {
+        return code;
+    }
+    let kind = e.kind();
+    if kind == ApplicationExceptionKind::UNKNOWN_METHOD {
+        code::UNKNOWN_METHOD
+    } else if kind == ApplicationExceptionKind::BAD_SEQUENCE_ID {
+        code::BAD_SEQUENCE_ID
+    } else if kind == ApplicationExceptionKind::INTERNAL_ERROR {
+        code::INTERNAL_ERROR
+    } else if kind == ApplicationExceptionKind::PROTOCOL_ERROR {
+        code::PROTOCOL_ERROR
+    } else {
+        code::APPLICATION
     }
 }
 
@@ -216,12 +327,14 @@ pub(crate) fn thrift_exception_to_application_exception(
 ) -> ApplicationException {
     match e {
         pilota::thrift::ThriftException::Application(e) => e,
-        pilota::thrift::ThriftException::Transport(e) => {
-            ApplicationException::new(ApplicationExceptionKind::INTERNAL_ERROR, e.to_string())
-        }
-        pilota::thrift::ThriftException::Protocol(e) => {
-            ApplicationException::new(ApplicationExceptionKind::PROTOCOL_ERROR, e.to_string())
-        }
+        pilota::thrift::ThriftException::Transport(e) => ApplicationException::new(
+            ApplicationExceptionKind::INTERNAL_ERROR,
+            tag(code::TRANSPORT, e),
+        ),
+        pilota::thrift::ThriftException::Protocol(e) => ApplicationException::new(
+            ApplicationExceptionKind::PROTOCOL_ERROR,
+            tag(code::PROTOCOL, e),
+        ),
     }
 }
 
@@ -229,3 +342,116 @@ pub enum MaybeException<T, E> {
     Ok(T),
     Exception(E),
 }
+
+pub mod code {
+    //! The error codes of `volo-thrift`, see [`volo::error`].
+
+    use super::Namespace;
+
+    volo::error_codes! {
+        Namespace::Thrift;
+        /// Transport exception, e.g. the connection is reset or closed.
+        TRANSPORT = 1,
+        /// Protocol exception, e.g. the message can't be decoded.
+        PROTOCOL = 2,
+        /// Business error returned by the handler.
+        BIZ = 3,
+        /// Application exception of the other kinds.
+        APPLICATION = 4,
+        /// Application exception of an unknown method.
+        UNKNOWN_METHOD = 5,
+        /// Application exception of a response whose sequence id doesn't match the request.
+        BAD_SEQUENCE_ID = 6,
+        /// Application exception of an internal error, e.g. the handler panicked.
+        INTERNAL_ERROR = 7,
+        /// Application exception of a protocol error, e.g. an unexpected message type.
+        PROTOCOL_ERROR = 8,
+        /// The peer speaks another protocol than thrift, e.g. HTTP/2 or TLS.
+        PROTOCOL_MISMATCH = 9,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app(kind: ApplicationExceptionKind) -> ApplicationException {
+        ApplicationException::new(kind, "oops")
+    }
+
+    #[test]
+    fn codes() {
+        let io = || io::Error::new(io::ErrorKind::ConnectionReset, "reset");
+        let transport = ClientError::from(io());
+        assert_eq!(transport.code(), code::TRANSPORT);
+        assert!(transport.is_retryable());
+        let protocol = ClientError::Protocol(new_protocol_exception(
+            ProtocolExceptionKind::InvalidData,
+            "oops",
+        ));
+        assert_eq!(protocol.code(), code::PROTOCOL);
+        assert!(!protocol.is_retryable());
+        let biz = BizError::new(1, "oops".into());
+        assert_eq!(biz.code(), code::BIZ);
+        assert_eq!(ClientError::from(biz.clone()).code(), code::BIZ);
+        assert_eq!(ServerError::from(biz).code(), code::BIZ);
+
+        for (kind, code) in [
+            (
+                ApplicationExceptionKind::UNKNOWN_METHOD,
+                code::UNKNOWN_METHOD,
+            ),
+            (
+                ApplicationExceptionKind::BAD_SEQUENCE_ID,
+                code::BAD_SEQUENCE_ID,
+            ),
+            (
+                ApplicationExceptionKind::INTERNAL_ERROR,
+                code::INTERNAL_ERROR,
+            ),
+            (
+                ApplicationExceptionKind::PROTOCOL_ERROR,
+                code::PROTOCOL_ERROR,
+            ),
+            (ApplicationExceptionKind::UNKNOWN, code::APPLICATION),
+        ] {
+            assert_eq!(ClientError::from(app(kind)).code(), code);
+            assert_eq!(ServerError::from(app(kind)).code(), code);
+        }
+    }
+
+    #[test]
+    fn conversions_keep_codes() {
+        let lb = ClientError::from(LoadBalanceError::Retry);
+        assert_eq!(lb.code(), volo::error::code::LOAD_BALANCE_RETRY);
+        // e.g. the server fails since it calls another service
+        assert_eq!(
+            ServerError::from(lb).code(),
+            volo::error::code::LOAD_BALANCE_RETRY
+        );
+
+        let transport = ClientError::from(io::Error::new(io::ErrorKind::BrokenPipe, "pipe"));
+        let server = ServerError::from(transport);
+        assert_eq!(server.code(), code::TRANSPORT);
+        assert_eq!(
+            ClientError::from(server_error_to_application_exception(server)).code(),
+            code::TRANSPORT
+        );
+
+        let biz: ApplicationException = BizError::new(1, "oops".into()).into();
+        assert_eq!(ClientError::from(biz).code(), code::BIZ);
+
+        let config = anyhow::Error::new(volo::config::ConfigError::new()).context("building");
+        assert_eq!(ServerError::from(config).code(), volo::error::code::CONFIG);
+    }
+
+    #[test]
+    fn registry() {
+        volo::error::check_registry(
+            "volo-thrift",
+            code::REGISTRY,
+            concat!(env!("CARGO_MANIFEST_DIR"), "/ERROR_CODES.md"),
+        )
+        .unwrap();
+    }
+}
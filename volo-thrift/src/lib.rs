@@ -6,6 +6,10 @@
 pub mod error;
 mod message;
 mod message_wrapper;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "otel")]
+pub mod otel;
 mod protocol;
 pub mod tracing;
 pub mod transport;
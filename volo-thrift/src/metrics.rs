@@ -0,0 +1,143 @@
+//! The Thrift protocol of [`volo::metrics::rpc::RpcMetricsLayer`].
+//!
+//! The `status` label is `ok`, or the [`ErrorCode`](volo::error::ErrorCode) of the error, e.g.
+//! `VOLO_THRIFT_0001` of the transport exceptions. The duration of the servers is measured from
+//! the arrival of the request, and the sizes are the ones of the messages read and written by the
+//! default codec.
+//!
+//! ```rust,ignore
+//! use volo::metrics::{
+//!     rpc::{RpcMetrics, RpcMetricsLayer},
+//!     Registry,
+//! };
+//! use volo_thrift::metrics::ThriftMetrics;
+//!
+//! let registry = Registry::new();
+//! let client = ItemServiceClientBuilder::new("item")
+//!     .layer_outer(RpcMetricsLayer::new(RpcMetrics::client(&registry), ThriftMetrics))
+//!     .build()?;
+//!
+//! Server::new(ItemServiceServer::new(S))
+//!     .layer_front(
+//!         RpcMetricsLayer::new(RpcMetrics::server(&registry), ThriftMetrics)
+//!             .methods(volo_gen::registry().methods()),
+//!     )
+//!     .run(addr)
+//!     .await
+//!     .unwrap();
+//! ```
+
+use std::time::SystemTime;
+
+use pilota::FastStr;
+use volo::{
+    context::{Context, Role},
+    error::CodedError,
+    metrics::rpc::RpcMetricsProtocol,
+};
+
+use crate::context::ThriftContext;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThriftMetrics;
+
+impl<Cx, Resp, E> RpcMetricsProtocol<Cx, Resp, E> for ThriftMetrics
+where
+    Cx: ThriftContext,
+    E: CodedError,
+{
+    fn status(&self, _cx: &Cx, result: &Result<Resp, E>) -> FastStr {
+        match result {
+            Ok(_) => FastStr::from_static_str("ok"),
+            Err(e) => FastStr::new(e.code().to_string()),
+        }
+    }
+
+    fn arrive_at(&self, cx: &Cx) -> Option<SystemTime> {
+        cx.stats().arrive_at().map(Into::into)
+    }
+
+    fn received_request_size(&self, cx: &Cx) -> Option<usize> {
+        if cx.rpc_info().role() == Role::Server {
+            cx.stats().read_size()
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Local;
+    use motore::{
+        layer::Layer,
+        service::{service_fn, Service},
+    };
+    use pilota::thrift::{ApplicationException, ApplicationExceptionKind};
+    use volo::metrics::{
+        rpc::{MessageSizes, RpcMetrics, RpcMetricsLayer},
+        Registry,
+    };
+
+    use super::*;
+    use crate::{context::ServerContext, ServerError};
+
+    async fn handler(cx: &mut ServerContext, ok: bool) -> Result<(), ServerError> {
+        // written by the codec after the layers
+        let sizes = cx.extensions().get::<MessageSizes>().unwrap().clone();
+        sizes.observe_sent(cx.rpc_info().role(), 78);
+        if ok {
+            Ok(())
+        } else {
+            Err(ApplicationException::new(ApplicationExceptionKind::UNKNOWN, "boom").into())
+        }
+    }
+
+    #[tokio::test]
+    async fn server() {
+        let registry = Registry::new();
+        let svc = RpcMetricsLayer::new(RpcMetrics::server(&registry), ThriftMetrics)
+            .methods(&METHODS[..])
+            .layer(service_fn(handler));
+
+        for ok in [true, true, false] {
+            let mut cx = ServerContext::default();
+            cx.rpc_info_mut().set_method("GetItem".into());
+            cx.rpc_info_mut()
+                .caller_mut()
+                .set_service_name("gateway".into());
+            cx.common_stats
+                .set_arrive_at(Local::now() - chrono::Duration::seconds(3));
+            cx.common_stats.set_read_size(56);
+            let _ = svc.call(&mut cx, ok).await;
+        }
+
+        let text = registry.render();
+        let labels = r#"service="Item",method="GetItem",caller="gateway",callee="""#;
+        for line in [
+            format!("rpc_server_requests_total{{{labels},status=\"ok\"}} 2\n"),
+            format!("rpc_server_requests_total{{{labels},status=\"VOLO_THRIFT_0004\"}} 1\n"),
+            format!("rpc_server_errors_total{{{labels},status=\"VOLO_THRIFT_0004\"}} 1\n"),
+            format!("rpc_server_in_flight_requests{{{labels}}} 0\n"),
+            format!("rpc_server_request_size_bytes_sum{{{labels}}} 168\n"),
+            format!("rpc_server_response_size_bytes_sum{{{labels}}} 234\n"),
+            // from the arrival 3s ago
+            format!("rpc_server_duration_seconds_bucket{{{labels},status=\"ok\",le=\"2.5\"}} 0\n"),
+        ] {
+            assert!(text.contains(&line), "missing {line} in {text}");
+        }
+    }
+
+    static METHODS: [volo::descriptor::MethodDescriptor; 1] =
+        [volo::descriptor::MethodDescriptor {
+            service: "Item",
+            name: "GetItem",
+            path: "GetItem",
+            client_streaming: false,
+            server_streaming: false,
+            request_type: "volo_gen::item::ItemServiceGetItemArgsRecv",
+            response_type: "volo_gen::item::ItemServiceGetItemResultSend",
+            annotations: &[],
+            request_schema: None,
+        }];
+}
@@ -0,0 +1,192 @@
+//! The Thrift protocol of [`volo::otel::OtelClientLayer`] and [`volo::otel::OtelServerLayer`],
+//! and the [`OtelSpanProvider`] starting the server spans in the transport.
+//!
+//! The client injects the trace context into the transient metainfo, which is sent as the string
+//! KVs of TTHeader with the prefix of metainfo. The server extracts it from the string KVs of
+//! TTHeader without the prefix first, which is sent by the other frameworks, and then from the
+//! metainfo, or the string KVs with the prefix if the metainfo isn't copied eagerly by
+//! [`MakeTTHeaderCodec::with_eager_metainfo`].
+//!
+//! The [`OtelSpanProvider`] is preferred at the server side, whose span covers the encoding of the
+//! response as well.
+//!
+//! ```rust,ignore
+//! use volo::otel::OtelClientLayer;
+//! use volo_thrift::otel::{OtelSpanProvider, ThriftOtel};
+//!
+//! let client = ItemServiceClientBuilder::new("item")
+//!     .layer_outer(OtelClientLayer::new(ThriftOtel))
+//!     .build()?;
+//!
+//! Server::new(ItemServiceServer::new(S))
+//!     .span_provider(OtelSpanProvider)
+//!     .run(addr)
+//!     .await
+//!     .unwrap();
+//! ```
+//!
+//! [`MakeTTHeaderCodec::with_eager_metainfo`]: crate::codec::default::ttheader::MakeTTHeaderCodec::with_eager_metainfo
+
+use std::fmt::Display;
+
+use metainfo::METAINFO;
+use pilota::{thrift::TMessageType, FastStr};
+use tracing::Span;
+use volo::{
+    error::CodedError,
+    otel::{record_error, record_rpc, server_span, set_remote_parent, OtelProtocol},
+};
+
+use crate::{
+    context::{ServerContext, ThriftContext},
+    tracing::SpanProvider,
+};
+
+/// The value of the attribute `rpc.system`.
+pub const SYSTEM: &str = "apache_thrift";
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThriftOtel;
+
+/// Reads the header from the string KVs of TTHeader, or the upstream metainfo.
+fn extract<Cx: ThriftContext>(cx: &Cx, key: &str) -> Option<FastStr> {
+    if let Some(kvs) = cx.ttheader_kvs() {
+        if let Some(value) = kvs.get(key) {
+            return Some(value.clone());
+        }
+        // the metainfo kept in the KVs
+        let transient = format!("{}{key}", metainfo::RPC_PREFIX_TRANSIENT);
+        if let Some(value) = kvs.get(&transient) {
+            return Some(value.clone());
+        }
+    }
+    METAINFO
+        .try_with(|mi| mi.borrow().get_upstream(key).map(|value| value.to_owned()))
+        .ok()
+        .flatten()
+}
+
+impl<Cx, Req, Resp, E> OtelProtocol<Cx, Req, Resp, E> for ThriftOtel
+where
+    Cx: ThriftContext,
+    E: CodedError + Display,
+{
+    fn system(&self) -> &'static str {
+        SYSTEM
+    }
+
+    fn extract(&self, cx: &Cx, _req: &Req, key: &str) -> Option<FastStr> {
+        extract(cx, key)
+    }
+
+    fn inject(&self, _cx: &mut Cx, _req: &mut Req, key: &'static str, value: FastStr) {
+        // the client always calls the layers in the scope of a metainfo
+        let _ = METAINFO.try_with(|mi| mi.borrow_mut().set_transient(key, value));
+    }
+
+    fn record(&self, span: &Span, result: &Result<Resp, E>) {
+        if let Err(e) = result {
+            record_error(span, e.code(), e);
+        }
+    }
+}
+
+/// A [`SpanProvider`] starting the server span of each request as the child of the context
+/// propagated by the client, see the [module docs](self).
+///
+/// The span is disabled if no subscriber is interested in it, which costs nearly nothing then.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OtelSpanProvider;
+
+impl SpanProvider for OtelSpanProvider {
+    fn on_serve(&self, cx: &ServerContext) -> Span {
+        let span = server_span();
+        if !span.is_disabled() {
+            record_rpc(&span, SYSTEM, cx);
+            set_remote_parent(&span, |key| extract(cx, key));
+        }
+        span
+    }
+
+    fn leave_serve(&self, cx: &ServerContext) {
+        // called in the span returned by `on_serve`
+        let span = Span::current();
+        if span.is_disabled() {
+            return;
+        }
+        if let Some(biz_error) = cx.common_stats.biz_error() {
+            record_error(&span, biz_error.status_code, &biz_error.status_message);
+        } else if cx.msg_type == Some(TMessageType::Exception) {
+            record_error(&span, "exception", "application exception");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use metainfo::MetaInfo;
+    use opentelemetry::trace::{SpanKind, TracerProvider as _};
+    use opentelemetry_sdk::{testing::trace::InMemorySpanExporter, trace::TracerProvider};
+    use tracing_subscriber::layer::SubscriberExt;
+    use volo::otel::{client_span, inject_span};
+
+    use super::*;
+
+    #[test]
+    fn extract_from_ttheader_or_metainfo() {
+        let mut cx = ServerContext::default();
+        assert_eq!(extract(&cx, "traceparent"), None);
+
+        let mut mi = MetaInfo::new();
+        mi.set_upstream("traceparent", "from-metainfo");
+        METAINFO.sync_scope(RefCell::new(mi), || {
+            assert_eq!(
+                extract(&cx, "traceparent").as_deref(),
+                Some("from-metainfo")
+            );
+
+            cx.ttheader_kvs
+                .insert_request("traceparent".into(), "from-ttheader".into());
+            assert_eq!(
+                extract(&cx, "traceparent").as_deref(),
+                Some("from-ttheader")
+            );
+        });
+    }
+
+    #[test]
+    fn child_of_upstream() {
+        let exporter = InMemorySpanExporter::default();
+        let provider = TracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        // the context sent by the other frameworks, or kept in the metainfo KVs
+        let prefixes = ["", metainfo::RPC_PREFIX_TRANSIENT];
+        for prefix in prefixes {
+            exporter.reset();
+            let upstream = client_span();
+            let mut cx = ServerContext::default();
+            inject_span(&upstream, |key, value| {
+                cx.ttheader_kvs
+                    .insert_request(format!("{prefix}{key}").into(), value)
+            });
+            drop(OtelSpanProvider.on_serve(&cx));
+            drop(upstream);
+
+            let spans = exporter.get_finished_spans().unwrap();
+            let find = |kind: SpanKind| spans.iter().find(|span| span.span_kind == kind).unwrap();
+            let (client, server) = (find(SpanKind::Client), find(SpanKind::Server));
+            assert_eq!(
+                server.span_context.trace_id(),
+                client.span_context.trace_id()
+            );
+            assert_eq!(server.parent_span_id, client.span_context.span_id());
+        }
+    }
+}
@@ -0,0 +1,221 @@
+//! Structured access logs of the server.
+//!
+//! When enabled by [`Server::access_log`][crate::server::Server::access_log], the
+//! [`AccessLogFormatter`] is called exactly once per request which has been decoded or failed to
+//! decode, after the response is sent, with an [`AccessLogEntry`] of a stable schema. Unlike the
+//! stat tracers, it is also called when the request is cancelled or the response fails to send.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use volo_thrift::server::access_log::AccessLogEntry;
+//!
+//! volo_gen::volo::example::ItemServiceServer::new(S)
+//!     .access_log(|entry: &AccessLogEntry| println!("{}", entry))
+//!     .run(addr)
+//!     .await
+//!     .unwrap();
+//! ```
+
+use std::{fmt, sync::Arc, time::Duration};
+
+use chrono::Local;
+use volo::{context::Context, error::ErrorCode, net::Address};
+
+use crate::context::ServerContext;
+
+/// The status of a request in the access logs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessStatus {
+    /// The request is handled successfully, and the response is sent if it's not oneway.
+    Ok,
+    /// The handler returned an error, which is sent as an exception.
+    Exception(ErrorCode),
+    /// The request failed to decode, so the method may be unknown.
+    DecodeError(ErrorCode),
+    /// The response failed to encode or send.
+    SendError(ErrorCode),
+    /// The request is cancelled since the peer closed the connection.
+    Cancelled,
+}
+
+impl AccessStatus {
+    /// Returns the code of the error, or `None` if there's no error.
+    pub fn code(&self) -> Option<ErrorCode> {
+        match self {
+            Self::Ok | Self::Cancelled => None,
+            Self::Exception(code) | Self::DecodeError(code) | Self::SendError(code) => Some(*code),
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Exception(_) => "exception",
+            Self::DecodeError(_) => "decode_error",
+            Self::SendError(_) => "send_error",
+            Self::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl fmt::Display for AccessStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An entry of the access logs, which is one per request.
+#[derive(Debug, Clone)]
+pub struct AccessLogEntry<'a> {
+    /// The method, which is `None` if the request failed to decode before the method is read.
+    pub method: Option<&'a str>,
+    pub status: AccessStatus,
+    /// From the request arriving to the response being sent, which is `None` if the timestamps
+    /// are not recorded.
+    pub duration: Option<Duration>,
+    /// The size of the request, which is only known for the length-prefixed transports.
+    pub request_size: Option<usize>,
+    /// The size of the response, which is only known for the length-prefixed transports.
+    pub response_size: Option<usize>,
+    pub peer_addr: Option<Address>,
+    /// The context for the fields out of the schema.
+    pub cx: &'a ServerContext,
+}
+
+impl<'a> AccessLogEntry<'a> {
+    pub(crate) fn new(cx: &'a ServerContext, status: AccessStatus) -> Self {
+        let method = cx.rpc_info().method();
+        let stats = &cx.common_stats;
+        let start = stats
+            .arrive_at()
+            .or_else(|| stats.read_start_at())
+            .or_else(|| stats.decode_start_at())
+            .or_else(|| cx.stats.process_start_at());
+        let end = stats
+            .write_end_at()
+            .or_else(|| stats.encode_end_at())
+            .or_else(|| cx.stats.process_end_at())
+            .unwrap_or_else(Local::now);
+        Self {
+            method: (!method.is_empty()).then_some(method.as_str()),
+            status,
+            duration: start.and_then(|start| (end - start).to_std().ok()),
+            request_size: stats.read_size(),
+            response_size: stats.write_size(),
+            peer_addr: cx.rpc_info().caller().address(),
+            cx,
+        }
+    }
+}
+
+/// Formats as `method=GetItem status=ok code=- duration_us=1234 req_size=56 resp_size=78
+/// peer=127.0.0.1:12345`, with `-` for the unknown fields.
+impl fmt::Display for AccessLogEntry<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn or_dash(f: &mut fmt::Formatter<'_>, v: Option<impl fmt::Display>) -> fmt::Result {
+            match v {
+                Some(v) => write!(f, "{v}"),
+                None => f.write_str("-"),
+            }
+        }
+
+        f.write_str("method=")?;
+        or_dash(f, self.method)?;
+        write!(f, " status={} code=", self.status)?;
+        or_dash(f, self.status.code())?;
+        f.write_str(" duration_us=")?;
+        or_dash(f, self.duration.map(|d| d.as_micros()))?;
+        f.write_str(" req_size=")?;
+        or_dash(f, self.request_size)?;
+        f.write_str(" resp_size=")?;
+        or_dash(f, self.response_size)?;
+        f.write_str(" peer=")?;
+        or_dash(f, self.peer_addr.as_ref())
+    }
+}
+
+/// Emits the access logs.
+///
+/// It is called on the connection tasks, so it should not block.
+pub trait AccessLogFormatter: Send + Sync + 'static {
+    fn log(&self, entry: &AccessLogEntry<'_>);
+}
+
+impl<F> AccessLogFormatter for F
+where
+    F: Fn(&AccessLogEntry<'_>) + Send + Sync + 'static,
+{
+    fn log(&self, entry: &AccessLogEntry<'_>) {
+        self(entry)
+    }
+}
+
+/// Emits the access logs as the `tracing` events at the `INFO` level with the target
+/// `volo_thrift::access_log`, whose fields are the ones of the [`AccessLogEntry`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TracingFormatter;
+
+impl AccessLogFormatter for TracingFormatter {
+    fn log(&self, entry: &AccessLogEntry<'_>) {
+        tracing::info!(
+            target: "volo_thrift::access_log",
+            method = entry.method.unwrap_or("-"),
+            status = entry.status.as_str(),
+            code = entry.status.code().map(tracing::field::display),
+            duration_us = entry.duration.map(|d| d.as_micros() as u64),
+            req_size = entry.request_size,
+            resp_size = entry.response_size,
+            peer = entry.peer_addr.as_ref().map(tracing::field::display),
+        );
+    }
+}
+
+pub(crate) type AccessLog = Option<Arc<dyn AccessLogFormatter>>;
+
+#[inline]
+pub(crate) fn log(access_log: &AccessLog, cx: &ServerContext, status: AccessStatus) {
+    if let Some(formatter) = access_log {
+        formatter.log(&AccessLogEntry::new(cx, status));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::error::code;
+
+    #[test]
+    fn entry() {
+        let mut cx = ServerContext::default();
+        let entry = AccessLogEntry::new(&cx, AccessStatus::DecodeError(code::PROTOCOL));
+        assert_eq!(entry.method, None);
+        assert_eq!(entry.duration, None);
+        assert_eq!(
+            entry.to_string(),
+            "method=- status=decode_error code=VOLO_THRIFT_0002 duration_us=- req_size=- \
+             resp_size=- peer=-"
+        );
+
+        cx.rpc_info_mut().set_method("GetItem".into());
+        let now = Local::now();
+        cx.common_stats
+            .set_arrive_at(now - chrono::Duration::milliseconds(3));
+        cx.common_stats.set_write_end_at(now);
+        cx.common_stats.set_read_size(56);
+        cx.common_stats.set_write_size(78);
+
+        let logged = Arc::new(Mutex::new(Vec::new()));
+        let access_log: AccessLog = Some(Arc::new({
+            let logged = logged.clone();
+            move |entry: &AccessLogEntry<'_>| logged.lock().unwrap().push(entry.to_string())
+        }));
+        log(&access_log, &cx, AccessStatus::Ok);
+        assert_eq!(
+            *logged.lock().unwrap(),
+            ["method=GetItem status=ok code=- duration_us=3000 req_size=56 resp_size=78 peer=-"]
+        );
+    }
+}
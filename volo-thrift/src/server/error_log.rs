@@ -0,0 +1,142 @@
+//! Classifying the errors of the connections for logging.
+//!
+//! The errors of decoding the requests and sending the responses are logged at the level decided
+//! by the [`ErrorLogFilter`] of the server, or not logged at all. By default, the routine
+//! disconnections of the clients, e.g. broken pipe and connection reset, are logged at `DEBUG`,
+//! and the others are logged at `ERROR`, see [`DefaultErrorLogFilter`].
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use pilota::thrift::ThriftException;
+//! use tracing::Level;
+//! use volo_thrift::server::error_log::is_disconnect;
+//!
+//! server.error_log_filter(|e: &ThriftException| match e {
+//!     _ if is_disconnect(e) => None,
+//!     ThriftException::Transport(_) => Some(Level::WARN),
+//!     _ => Some(Level::ERROR),
+//! });
+//! ```
+
+use std::{error::Error, io, sync::Arc};
+
+use pilota::thrift::ThriftException;
+use tracing::Level;
+
+/// Decides the level of logging an error of a connection, `None` for not logging it.
+pub trait ErrorLogFilter: Send + Sync + 'static {
+    fn level(&self, err: &ThriftException) -> Option<Level>;
+}
+
+impl<F> ErrorLogFilter for F
+where
+    F: Fn(&ThriftException) -> Option<Level> + Send + Sync + 'static,
+{
+    fn level(&self, err: &ThriftException) -> Option<Level> {
+        self(err)
+    }
+}
+
+/// Logs the disconnections at `DEBUG` and the others at `ERROR`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultErrorLogFilter;
+
+impl ErrorLogFilter for DefaultErrorLogFilter {
+    fn level(&self, err: &ThriftException) -> Option<Level> {
+        if is_disconnect(err) {
+            Some(Level::DEBUG)
+        } else {
+            Some(Level::ERROR)
+        }
+    }
+}
+
+/// Returns whether the error is caused by the peer closing the connection, i.e. a transport
+/// error of broken pipe, connection reset or aborted, or unexpected EOF.
+pub fn is_disconnect(err: &ThriftException) -> bool {
+    let ThriftException::Transport(e) = err else {
+        return false;
+    };
+    let mut source: Option<&(dyn Error + 'static)> = Some(e);
+    while let Some(err) = source {
+        if let Some(io) = err.downcast_ref::<io::Error>() {
+            return matches!(
+                io.kind(),
+                io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::UnexpectedEof
+            );
+        }
+        source = err.source();
+    }
+    false
+}
+
+pub(crate) type ErrorLog = Arc<dyn ErrorLogFilter>;
+
+pub(crate) fn default_error_log() -> ErrorLog {
+    Arc::new(DefaultErrorLogFilter)
+}
+
+/// Returns the level of logging the error, or `None` if it should not be logged.
+#[inline]
+pub(crate) fn should_log(filter: &ErrorLog, err: &ThriftException) -> Option<Level> {
+    filter.level(err)
+}
+
+/// Logs the error of a connection at the level decided by the [`ErrorLogFilter`].
+macro_rules! log_error {
+    ($filter:expr, $err:expr, $($arg:tt)+) => {
+        match $crate::server::error_log::should_log(&$filter, $err) {
+            Some(level) if level == ::tracing::Level::ERROR => ::tracing::error!($($arg)+),
+            Some(level) if level == ::tracing::Level::WARN => ::tracing::warn!($($arg)+),
+            Some(level) if level == ::tracing::Level::INFO => ::tracing::info!($($arg)+),
+            Some(level) if level == ::tracing::Level::DEBUG => ::tracing::debug!($($arg)+),
+            Some(_) => ::tracing::trace!($($arg)+),
+            None => {}
+        }
+    };
+}
+
+pub(crate) use log_error;
+
+#[cfg(test)]
+mod tests {
+    use pilota::thrift::{new_protocol_exception, ProtocolExceptionKind, TransportException};
+
+    use super::*;
+
+    #[test]
+    fn default_filter() {
+        let transport =
+            |kind| ThriftException::Transport(TransportException::from(io::Error::new(kind, "x")));
+        for kind in [
+            io::ErrorKind::BrokenPipe,
+            io::ErrorKind::ConnectionReset,
+            io::ErrorKind::UnexpectedEof,
+        ] {
+            assert!(is_disconnect(&transport(kind)));
+            assert_eq!(
+                DefaultErrorLogFilter.level(&transport(kind)),
+                Some(Level::DEBUG)
+            );
+        }
+        assert!(!is_disconnect(&transport(io::ErrorKind::Other)));
+        assert_eq!(
+            DefaultErrorLogFilter.level(&transport(io::ErrorKind::Other)),
+            Some(Level::ERROR)
+        );
+
+        let protocol = ThriftException::Protocol(new_protocol_exception(
+            ProtocolExceptionKind::InvalidData,
+            "oops",
+        ));
+        assert!(!is_disconnect(&protocol));
+        assert_eq!(DefaultErrorLogFilter.level(&protocol), Some(Level::ERROR));
+
+        let silent: ErrorLog = Arc::new(|_: &ThriftException| None);
+        assert_eq!(should_log(&silent, &protocol), None);
+    }
+}
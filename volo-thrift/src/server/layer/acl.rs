@@ -0,0 +1,46 @@
+//! The thrift rejection of [`volo::acl::AclLayer`].
+//!
+//! A denied request fails with an application exception, whose message starts with
+//! [`ACL_DENIED`].
+
+use pilota::thrift::{ApplicationException, ApplicationExceptionKind};
+use volo::acl::{AclDenied, AclRejection};
+
+use crate::{context::ServerContext, ServerError};
+
+/// The prefix of the message of the application exception of a denied request.
+pub const ACL_DENIED: &str = "[volo] acl denied";
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThriftAclRejection;
+
+impl AclRejection<ServerContext, ServerError> for ThriftAclRejection {
+    fn reject(&self, _cx: &mut ServerContext, denied: AclDenied) -> ServerError {
+        ServerError::Application(ApplicationException::new(
+            ApplicationExceptionKind::INTERNAL_ERROR,
+            format!("{ACL_DENIED}, {denied}"),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reject() {
+        let err = ThriftAclRejection.reject(
+            &mut ServerContext::default(),
+            AclDenied::Forbidden {
+                service: Some("echo".into()),
+                method: "Purge".into(),
+            },
+        );
+        let ServerError::Application(e) = err else {
+            panic!("unexpected error: {err:?}");
+        };
+        assert!(e
+            .to_string()
+            .contains("[volo] acl denied, peer echo is not allowed to call Purge"));
+    }
+}
@@ -0,0 +1,55 @@
+//! Sets the [`Deadline`] of each request from the RPC timeout sent by the client, so the
+//! downstream calls made by the handler inherit the remaining time, see
+//! [`volo::context::Deadline`].
+
+use motore::{layer::Layer, service::Service};
+use volo::context::{Deadline, DeadlineExt};
+
+use crate::context::ServerContext;
+
+#[derive(Clone, Copy, Default)]
+pub struct DeadlineLayer;
+
+impl DeadlineLayer {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<S> Layer<S> for DeadlineLayer {
+    type Service = DeadlineService<S>;
+
+    #[inline]
+    fn layer(self, inner: S) -> Self::Service {
+        DeadlineService { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct DeadlineService<S> {
+    inner: S,
+}
+
+impl<S, Req> Service<ServerContext, Req> for DeadlineService<S>
+where
+    S: Service<ServerContext, Req> + Send + 'static + Sync,
+    Req: Send + 'static,
+{
+    type Response = S::Response;
+
+    type Error = S::Error;
+
+    #[inline]
+    async fn call<'s, 'cx>(
+        &'s self,
+        cx: &'cx mut ServerContext,
+        req: Req,
+    ) -> Result<Self::Response, Self::Error> {
+        let Some(timeout) = cx.rpc_info.config().rpc_timeout() else {
+            return self.inner.call(cx, req).await;
+        };
+        let deadline = Deadline::after(timeout);
+        cx.set_deadline(deadline);
+        deadline.scope(self.inner.call(cx, req)).await
+    }
+}
@@ -0,0 +1,107 @@
+//! The thrift protocol of [`volo::dedup::DedupLayer`].
+//!
+//! The idempotency key is read from a TTHeader key, and the responses are cached by cloning them
+//! if their encoded size is not larger than the limit. A duplicate request whose original one
+//! fails or is not cacheable fails with an application exception, whose message starts with
+//! [`RETRY_LATER`], which can be recognized by [`is_retry_later`].
+
+use pilota::thrift::{ApplicationException, ApplicationExceptionKind, TBinaryProtocol};
+use pilota::FastStr;
+use volo::dedup::DedupProtocol;
+
+use crate::{context::ServerContext, EntryMessage, ServerError};
+
+/// The prefix of the message of the application exception of a duplicate request to be retried
+/// later.
+pub const RETRY_LATER: &str = "[volo] duplicate request, retry later";
+
+/// The TTHeader key of the idempotency key by default.
+pub const DEFAULT_KEY_HEADER: &str = "idempotency-key";
+
+#[derive(Debug, Clone)]
+pub struct ThriftDedup {
+    header: FastStr,
+}
+
+impl Default for ThriftDedup {
+    fn default() -> Self {
+        Self::new(DEFAULT_KEY_HEADER)
+    }
+}
+
+impl ThriftDedup {
+    /// Reads the idempotency key from the TTHeader key `header`.
+    pub fn new(header: impl Into<FastStr>) -> Self {
+        Self {
+            header: header.into(),
+        }
+    }
+}
+
+impl<Req, Resp> DedupProtocol<ServerContext, Req, Resp, ServerError> for ThriftDedup
+where
+    Resp: EntryMessage + Clone + Sync + 'static,
+{
+    type Cached = Resp;
+
+    fn key(&self, cx: &ServerContext, _req: &Req) -> Option<FastStr> {
+        cx.ttheader_kvs
+            .get(&self.header)
+            .filter(|key| !key.is_empty())
+            .cloned()
+    }
+
+    async fn cache(&self, resp: Resp, max_size: usize) -> (Resp, Option<Resp>) {
+        let size = resp.size(&mut TBinaryProtocol::new((), true));
+        let cached = (size <= max_size).then(|| resp.clone());
+        (resp, cached)
+    }
+
+    fn restore(&self, _cx: &mut ServerContext, cached: &Resp) -> Resp {
+        cached.clone()
+    }
+
+    fn retry_later(&self, _cx: &mut ServerContext) -> ServerError {
+        ServerError::Application(ApplicationException::new(
+            ApplicationExceptionKind::INTERNAL_ERROR,
+            RETRY_LATER,
+        ))
+    }
+}
+
+/// Returns whether the exception is returned for a duplicate request to be retried later.
+pub fn is_retry_later(e: &ApplicationException) -> bool {
+    e.to_string().contains(RETRY_LATER)
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+
+    fn protocol() -> impl DedupProtocol<ServerContext, (), Bytes, ServerError, Cached = Bytes> {
+        ThriftDedup::new("x-idempotency-key")
+    }
+
+    #[tokio::test]
+    async fn key_and_cache() {
+        let protocol = protocol();
+
+        let mut cx = ServerContext::default();
+        assert_eq!(protocol.key(&cx, &()), None);
+        cx.ttheader_kvs
+            .insert_request("x-idempotency-key".into(), "order-1".into());
+        assert_eq!(protocol.key(&cx, &()), Some("order-1".into()));
+
+        let (resp, cached) = protocol.cache(Bytes::from_static(b"hello"), 5).await;
+        assert_eq!(resp, cached.unwrap());
+        let (_, cached) = protocol.cache(Bytes::from_static(b"hello"), 4).await;
+        assert!(cached.is_none());
+
+        let ServerError::Application(e) = protocol.retry_later(&mut cx) else {
+            panic!("unexpected error");
+        };
+        assert!(is_retry_later(&e));
+    }
+}
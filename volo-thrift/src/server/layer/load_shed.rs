@@ -0,0 +1,34 @@
+//! The thrift rejection of [`volo::load_shed::LoadShedLayer`].
+//!
+//! A rejected request fails with an application exception, whose message starts with
+//! [`OVERLOADED`], which can be recognized by [`is_overloaded`]. Unlike the rate limited ones, the
+//! rejected requests may be retried on the other instances, since the overload is local to the
+//! instance.
+
+use pilota::thrift::{ApplicationException, ApplicationExceptionKind};
+use volo::load_shed::{LoadShedRejection, Overloaded};
+
+use crate::{context::ServerContext, ServerError};
+
+/// The prefix of the message of the application exception of a rejected request.
+pub const OVERLOADED: &str = "[volo] overloaded";
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThriftLoadShedRejection;
+
+impl LoadShedRejection<ServerContext, ServerError> for ThriftLoadShedRejection {
+    fn reject(&self, _cx: &mut ServerContext, overloaded: Overloaded) -> ServerError {
+        ServerError::Application(ApplicationException::new(
+            ApplicationExceptionKind::INTERNAL_ERROR,
+            format!(
+                "{OVERLOADED}, concurrency limit {} reached",
+                overloaded.limit
+            ),
+        ))
+    }
+}
+
+/// Returns whether the exception is returned for a request rejected by the load shedding.
+pub fn is_overloaded(e: &ApplicationException) -> bool {
+    e.to_string().contains(OVERLOADED)
+}
@@ -1 +1,9 @@
+pub mod acl;
 pub mod biz_error;
+pub mod deadline;
+pub mod dedup;
+pub mod load_shed;
+pub mod preset;
+pub mod rate_limit;
+
+pub use self::preset::production_defaults;
@@ -0,0 +1,145 @@
+//! The thrift protocol of [`volo::preset::Preset`].
+//!
+//! The size of a request is the size of the frame read by the codec, so a request larger than the
+//! limit is rejected after it's decoded, with an application exception of
+//! [`ApplicationExceptionKind::PROTOCOL_ERROR`]. To stop reading a large frame earlier, also set
+//! the max frame size of the codec. A handler timed out, a panic and a request rejected by the
+//! concurrency cap fail with application exceptions of
+//! [`ApplicationExceptionKind::INTERNAL_ERROR`].
+
+use std::{any::Any, time::Duration};
+
+use pilota::thrift::{ApplicationException, ApplicationExceptionKind};
+use volo::{
+    catch_panic::PanicInfo,
+    load_shed::Overloaded,
+    preset::{Preset, PresetProtocol},
+};
+
+use super::load_shed::OVERLOADED;
+use crate::{context::ServerContext, server::panic_handler, ServerError};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThriftPreset;
+
+/// Creates a [`Preset`] with the production defaults for the thrift servers, see the versions of
+/// the defaults in [`volo::preset`].
+///
+/// # Example
+///
+/// ```rust,ignore
+/// server
+///     .layer_front(volo_thrift::server::layer::production_defaults())
+///     .run(addr)
+///     .await
+///     .unwrap();
+/// ```
+pub fn production_defaults() -> Preset<ThriftPreset> {
+    Preset::new(ThriftPreset)
+}
+
+impl<Req, Resp> PresetProtocol<ServerContext, Req, Resp, ServerError> for ThriftPreset {
+    fn limit_size(
+        &self,
+        cx: &mut ServerContext,
+        req: Req,
+        max_size: usize,
+    ) -> Result<Req, Result<Resp, ServerError>> {
+        match cx.common_stats.read_size() {
+            Some(size) if size > max_size => {
+                Err(Err(ServerError::Application(ApplicationException::new(
+                    ApplicationExceptionKind::PROTOCOL_ERROR,
+                    format!("request of {size} bytes larger than max ({max_size} bytes)"),
+                ))))
+            }
+            _ => Ok(req),
+        }
+    }
+
+    fn timed_out(&self, _cx: &mut ServerContext, timeout: Duration) -> Result<Resp, ServerError> {
+        Err(ServerError::Application(ApplicationException::new(
+            ApplicationExceptionKind::INTERNAL_ERROR,
+            format!("handler timed out in {timeout:?}"),
+        )))
+    }
+
+    fn panicked(
+        &self,
+        cx: &mut ServerContext,
+        payload: Box<dyn Any + Send>,
+        panic_info: PanicInfo,
+    ) -> Result<Resp, ServerError> {
+        panic_handler::log_and_return_exception(cx, payload, panic_info)
+    }
+
+    fn overloaded(
+        &self,
+        _cx: &mut ServerContext,
+        overloaded: Overloaded,
+    ) -> Result<Resp, ServerError> {
+        Err(ServerError::Application(ApplicationException::new(
+            ApplicationExceptionKind::INTERNAL_ERROR,
+            format!(
+                "{OVERLOADED}, concurrency limit {} reached",
+                overloaded.limit
+            ),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use motore::{layer::Layer, service::Service};
+    use volo::preset::{DEFAULT_MAX_BODY_SIZE, DEFAULT_TIMEOUT};
+
+    use super::*;
+
+    /// Panics or sleeps by the request.
+    struct Handler;
+
+    impl Service<ServerContext, &'static str> for Handler {
+        type Response = ();
+        type Error = ServerError;
+
+        async fn call(
+            &self,
+            _cx: &mut ServerContext,
+            req: &'static str,
+        ) -> Result<Self::Response, Self::Error> {
+            match req {
+                "panic" => panic!("boom"),
+                "sleep" => tokio::time::sleep(DEFAULT_TIMEOUT * 2).await,
+                _ => {}
+            }
+            Ok(())
+        }
+    }
+
+    fn kind(err: ServerError) -> ApplicationExceptionKind {
+        match err {
+            ServerError::Application(e) => e.kind(),
+            err => panic!("unexpected error: {err:?}"),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn production_defaults_verbatim() {
+        let service = production_defaults().layer(Handler);
+
+        let mut cx = ServerContext::default();
+        cx.common_stats.set_read_size(64);
+        assert!(service.call(&mut cx, "hello").await.is_ok());
+
+        let mut cx = ServerContext::default();
+        cx.common_stats.set_read_size(DEFAULT_MAX_BODY_SIZE + 1);
+        let err = service.call(&mut cx, "hello").await.unwrap_err();
+        assert_eq!(kind(err), ApplicationExceptionKind::PROTOCOL_ERROR);
+
+        let mut cx = ServerContext::default();
+        let err = service.call(&mut cx, "panic").await.unwrap_err();
+        assert_eq!(kind(err), ApplicationExceptionKind::INTERNAL_ERROR);
+
+        let err = service.call(&mut cx, "sleep").await.unwrap_err();
+        assert_eq!(kind(err), ApplicationExceptionKind::INTERNAL_ERROR);
+    }
+}
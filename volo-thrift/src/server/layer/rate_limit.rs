@@ -0,0 +1,72 @@
+//! The thrift rejection of [`volo::rate_limit::RateLimitLayer`].
+//!
+//! A rejected request fails with an application exception, whose message starts with
+//! [`RATE_LIMITED`], and a negative `retry-pushback-ms` is sent back in TTHeader. The
+//! [`ThriftRetryStrategy`] recognizes the exception by [`is_rate_limited`] and never retries it.
+//!
+//! Since the TTHeader and the body are decoded together, the request has been decoded when it is
+//! rejected.
+//!
+//! [`ThriftRetryStrategy`]: crate::client::layer::retry::ThriftRetryStrategy
+
+use pilota::thrift::{ApplicationException, ApplicationExceptionKind};
+use volo::{
+    rate_limit::{RateLimitRejection, RateLimited},
+    retry::Pushback,
+};
+
+use crate::{
+    context::{ServerContext, ThriftContext},
+    ServerError,
+};
+
+/// The prefix of the message of the application exception of a rejected request.
+pub const RATE_LIMITED: &str = "[volo] rate limited";
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThriftRateLimitRejection;
+
+impl RateLimitRejection<ServerContext, ServerError> for ThriftRateLimitRejection {
+    fn reject(&self, cx: &mut ServerContext, rejected: RateLimited) -> ServerError {
+        cx.stats_mut().set_retry_pushback(Pushback::Abort);
+        ServerError::Application(ApplicationException::new(
+            ApplicationExceptionKind::INTERNAL_ERROR,
+            format!(
+                "{RATE_LIMITED}, retry after {}ms",
+                rejected.retry_after.as_millis()
+            ),
+        ))
+    }
+}
+
+/// Returns whether the exception is returned for a request rejected by the rate limit.
+pub fn is_rate_limited(e: &ApplicationException) -> bool {
+    e.to_string().contains(RATE_LIMITED)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn reject() {
+        let mut cx = ServerContext::default();
+        let err = ThriftRateLimitRejection.reject(
+            &mut cx,
+            RateLimited {
+                retry_after: Duration::from_millis(20),
+            },
+        );
+        assert_eq!(cx.stats().retry_pushback(), Some(Pushback::Abort));
+        let ServerError::Application(e) = err else {
+            panic!("unexpected error: {err:?}");
+        };
+        assert!(is_rate_limited(&e));
+        assert!(!is_rate_limited(&ApplicationException::new(
+            ApplicationExceptionKind::INTERNAL_ERROR,
+            "internal error",
+        )));
+    }
+}
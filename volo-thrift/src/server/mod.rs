@@ -14,15 +14,23 @@ use scopeguard::defer;
 use tokio::{
     io::{AsyncRead, AsyncWrite},
     sync::Notify,
+    time::Instant,
 };
 use tracing::{info, trace};
 use volo::{
+    config::ConfigError,
+    context::identity::TlsInfo,
     net::{
         conn::{OwnedReadHalf, OwnedWriteHalf},
+        conn_stats::{ConnGuard, ConnStats},
         incoming::Incoming,
+        listener::ListenerName,
+        timestamp::RecvTimestamp,
         Address,
     },
+    server::ServerHandle,
     service::BoxService,
+    util::budget::DEFAULT_YIELD_BUDGET,
 };
 
 use crate::{
@@ -30,14 +38,23 @@ use crate::{
         default::{framed::MakeFramedCodec, thrift::MakeThriftCodec, ttheader::MakeTTHeaderCodec},
         DefaultMakeCodec, MakeCodec,
     },
-    context::ServerContext,
-    server::layer::biz_error::BizErrorLayer,
+    context::{ServerContext, ServerContextCache, DEFAULT_SERVER_CONTEXT_CACHE_CAPACITY},
+    server::layer::{biz_error::BizErrorLayer, deadline::DeadlineLayer},
     tracing::{DefaultProvider, SpanProvider},
     EntryMessage,
 };
 
+pub mod access_log;
+pub mod error_log;
 mod layer;
 pub mod panic_handler;
+pub mod sampling;
+
+use self::{
+    access_log::{AccessLog, AccessLogFormatter},
+    error_log::{ErrorLog, ErrorLogFilter},
+    sampling::{SampleSink, Sampler, SamplingLayer},
+};
 
 /// This is unstable now and may be changed in the future.
 #[doc(hidden)]
@@ -48,10 +65,18 @@ pub struct Server<S, L, Req, MkC, SP> {
     layer: L,
     make_codec: MkC,
     stat_tracer: Vec<TraceFn>,
+    access_log: AccessLog,
+    error_log: ErrorLog,
+    capture_frame: bool,
+    cancel_on_peer_close: bool,
+    yield_budget: usize,
+    context_cache: ServerContextCache,
     #[cfg(feature = "multiplex")]
     multiplex: bool,
     span_provider: SP,
     shutdown_hooks: Vec<Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>>,
+    handle: ServerHandle,
+    conn_stats: Option<ConnStats>,
     _marker: PhantomData<Req>,
 }
 
@@ -73,10 +98,18 @@ impl<S, Req>
             service,
             layer: Identity::new(),
             stat_tracer: Vec::new(),
+            access_log: None,
+            error_log: error_log::default_error_log(),
+            capture_frame: false,
+            cancel_on_peer_close: false,
+            yield_budget: DEFAULT_YIELD_BUDGET,
+            context_cache: ServerContextCache::default(),
             #[cfg(feature = "multiplex")]
             multiplex: false,
             span_provider: DefaultProvider {},
             shutdown_hooks: Vec::new(),
+            handle: ServerHandle::new(),
+            conn_stats: None,
             _marker: PhantomData,
         }
     }
@@ -95,6 +128,11 @@ impl<S, L, Req, MkC, SP> Server<S, L, Req, MkC, SP> {
         self
     }
 
+    /// Returns the handle to shut down the server after it runs, see [`ServerHandle`].
+    pub fn handle(&self) -> ServerHandle {
+        self.handle.clone()
+    }
+
     /// Adds a new inner layer to the server.
     ///
     /// The layer's `Service` should be `Send + Sync + Clone + 'static`.
@@ -112,10 +150,18 @@ impl<S, L, Req, MkC, SP> Server<S, L, Req, MkC, SP> {
             service: self.service,
             make_codec: self.make_codec,
             stat_tracer: self.stat_tracer,
+            access_log: self.access_log,
+            error_log: self.error_log,
+            capture_frame: self.capture_frame,
+            cancel_on_peer_close: self.cancel_on_peer_close,
+            yield_budget: self.yield_budget,
+            context_cache: self.context_cache,
             #[cfg(feature = "multiplex")]
             multiplex: self.multiplex,
             span_provider: self.span_provider,
             shutdown_hooks: self.shutdown_hooks,
+            handle: self.handle,
+            conn_stats: self.conn_stats,
             _marker: PhantomData,
         }
     }
@@ -137,14 +183,126 @@ impl<S, L, Req, MkC, SP> Server<S, L, Req, MkC, SP> {
             service: self.service,
             make_codec: self.make_codec,
             stat_tracer: self.stat_tracer,
+            access_log: self.access_log,
+            error_log: self.error_log,
+            capture_frame: self.capture_frame,
+            cancel_on_peer_close: self.cancel_on_peer_close,
+            yield_budget: self.yield_budget,
+            context_cache: self.context_cache,
             #[cfg(feature = "multiplex")]
             multiplex: self.multiplex,
             span_provider: self.span_provider,
             shutdown_hooks: self.shutdown_hooks,
+            handle: self.handle,
+            conn_stats: self.conn_stats,
             _marker: PhantomData,
         }
     }
 
+    /// Samples the requests and hands the captured ones to the sink, see [`sampling`] for
+    /// details.
+    ///
+    /// The sampling is the front layer when it is added, and only works with the length-prefixed
+    /// transports in the ping-pong mode.
+    pub fn sampling<Sa, Si>(
+        self,
+        sampler: Sa,
+        sink: Si,
+    ) -> Server<S, Stack<L, SamplingLayer<Sa, Si>>, Req, MkC, SP>
+    where
+        Sa: Sampler,
+        Si: SampleSink,
+    {
+        let mut server = self.layer_front(SamplingLayer::new(sampler, sink));
+        server.capture_frame = true;
+        server
+    }
+
+    /// Cancels the handling request when the connection is closed by the peer, e.g. the caller
+    /// has given up the request after its deadline elapsed.
+    ///
+    /// The [`cancellation_token`](ServerContext::cancellation_token) of the request is cancelled
+    /// and the handler future is dropped, so the work of the abandoned requests is stopped early.
+    /// The ping-pong clients of volo close the connection when a call is dropped before its
+    /// response is received, which works as the cancel signal.
+    ///
+    /// This only works in the ping-pong mode, and the oneway requests are never cancelled, since
+    /// their callers may disconnect right after sending them. It should not be enabled for the
+    /// clients which half-close the connection after sending a request, whose requests would all
+    /// be cancelled.
+    ///
+    /// Default is `false`.
+    pub fn cancel_on_peer_close(mut self, cancel_on_peer_close: bool) -> Self {
+        self.cancel_on_peer_close = cancel_on_peer_close;
+        self
+    }
+
+    /// Sets the number of requests served on a connection before yielding to the scheduler.
+    ///
+    /// The requests pipelined on a connection are decoded from the buffer without returning to
+    /// the scheduler, so a busy connection may starve the other tasks on the same worker without
+    /// the yielding. `0` disables the yielding.
+    ///
+    /// Default is [`DEFAULT_YIELD_BUDGET`].
+    pub fn yield_budget(mut self, yield_budget: usize) -> Self {
+        self.yield_budget = yield_budget;
+        self
+    }
+
+    /// Sets the capacity of the per-thread cache of the contexts reused by the ping-pong
+    /// requests, and `0` disables the reusing.
+    ///
+    /// The cache is shared by all the servers on the thread, and each server only puts the
+    /// contexts back when the cache has less than its capacity.
+    ///
+    /// Default is [`DEFAULT_SERVER_CONTEXT_CACHE_CAPACITY`].
+    pub fn context_cache_capacity(mut self, capacity: usize) -> Self {
+        self.context_cache.set_capacity(capacity);
+        self
+    }
+
+    /// Sets the function clearing the state of the context when its request is done, e.g. the
+    /// state referenced by the extensions which must be released promptly.
+    ///
+    /// It is called before the built-in reset, which clears the `RpcInfo`, the extensions and
+    /// the other fields of the context, and it is called even if the context is not put back to
+    /// the cache. It only applies to the ping-pong mode.
+    pub fn context_reset<F>(mut self, reset: F) -> Self
+    where
+        F: Fn(&mut ServerContext) + Send + Sync + 'static,
+    {
+        self.context_cache.set_reset(Arc::new(reset));
+        self
+    }
+
+    /// Emits an access log for each request by the formatter, see [`access_log`] for details,
+    /// e.g. [`TracingFormatter`](access_log::TracingFormatter).
+    ///
+    /// Default is disabled.
+    pub fn access_log(mut self, formatter: impl AccessLogFormatter) -> Self {
+        self.access_log = Some(Arc::new(formatter));
+        self
+    }
+
+    /// Sets the filter deciding the level of logging the errors of decoding the requests and
+    /// sending the responses, see [`error_log`] for details.
+    ///
+    /// Default is [`DefaultErrorLogFilter`](error_log::DefaultErrorLogFilter), which logs the
+    /// disconnections of the clients at `DEBUG`.
+    pub fn error_log_filter(mut self, filter: impl ErrorLogFilter) -> Self {
+        self.error_log = Arc::new(filter);
+        self
+    }
+
+    /// Counts the connections and the TLS handshakes of the listeners into the stats, see
+    /// [`conn_stats`](volo::net::conn_stats).
+    ///
+    /// Default is disabled.
+    pub fn conn_stats(mut self, stats: ConnStats) -> Self {
+        self.conn_stats = Some(stats);
+        self
+    }
+
     /// This is unstable now and may be changed in the future.
     #[doc(hidden)]
     pub fn stat_tracer(mut self, trace_fn: TraceFn) -> Self {
@@ -166,14 +324,35 @@ impl<S, L, Req, MkC, SP> Server<S, L, Req, MkC, SP> {
             service: self.service,
             make_codec,
             stat_tracer: self.stat_tracer,
+            access_log: self.access_log,
+            error_log: self.error_log,
+            capture_frame: self.capture_frame,
+            cancel_on_peer_close: self.cancel_on_peer_close,
+            yield_budget: self.yield_budget,
+            context_cache: self.context_cache,
             #[cfg(feature = "multiplex")]
             multiplex: self.multiplex,
             span_provider: self.span_provider,
             shutdown_hooks: self.shutdown_hooks,
+            handle: self.handle,
+            conn_stats: self.conn_stats,
             _marker: PhantomData,
         }
     }
 
+    /// Checks the configuration, which is called when the server starts running.
+    fn check(&self) -> Result<(), ConfigError> {
+        #[allow(unused_mut)]
+        let mut err = ConfigError::new();
+        #[cfg(feature = "multiplex")]
+        err.ensure(
+            !(self.multiplex && self.capture_frame),
+            "sampling",
+            "is not supported in the multiplex mode",
+        );
+        err.into_result()
+    }
+
     /// The main entry point for the server.
     pub async fn run<MI: volo::net::incoming::MakeIncoming>(
         self,
@@ -191,11 +370,15 @@ impl<S, L, Req, MkC, SP> Server<S, L, Req, MkC, SP> {
         Req: EntryMessage + Send + 'static,
         SP: SpanProvider,
     {
+        self.check()?;
+
         // init server
-        // inject biz error layer first
+        // inject biz error layer first, and the deadline layer in front of all the layers
         let service = Arc::new(
-            self.layer
-                .layer(BoxService::new(BizErrorLayer::new().layer(self.service))),
+            DeadlineLayer::new().layer(
+                self.layer
+                    .layer(BoxService::new(BizErrorLayer::new().layer(self.service))),
+            ),
         );
         // TODO(lyf1999): type annotation is needed here, figure out why
         let stat_tracer: Arc<[TraceFn]> = Arc::from(self.stat_tracer);
@@ -212,9 +395,16 @@ impl<S, L, Req, MkC, SP> Server<S, L, Req, MkC, SP> {
         );
         let (exit_notify_inner, exit_flag_inner, exit_mark_inner) =
             (exit_notify.clone(), exit_flag.clone(), exit_mark.clone());
+        let handle = self.handle.clone();
+        let _serving = handle.serving({
+            let conn_cnt = conn_cnt.clone();
+            move || conn_cnt.load(Ordering::Relaxed)
+        });
+        // the deadline to drain the connections, which is the same as before for the signals
+        let mut deadline = None;
 
         // spawn accept loop
-        let handler = tokio::spawn(async move {
+        let mut handler = tokio::spawn(async move {
             let exit_flag = exit_flag_inner.clone();
             loop {
                 if *exit_flag.read() {
@@ -224,7 +414,12 @@ impl<S, L, Req, MkC, SP> Server<S, L, Req, MkC, SP> {
                     Ok(Some(conn)) => {
                         let peer_addr = conn.info.peer_addr;
                         trace!("[VOLO] accept connection from: {:?}", peer_addr);
+                        let conn_guard = self.conn_stats.as_ref().map(|s| s.accept(&conn.info));
+                        // only the TLS connections pay for the info of the handshake
+                        let tls_info = conn.stream.tls_info();
                         let (rh, wh) = conn.stream.into_split();
+                        let recv_timestamp = rh.recv_timestamp();
+                        let listener = conn.info.listener.map(ListenerName);
 
                         #[cfg(feature = "multiplex")]
                         if self.multiplex {
@@ -234,10 +429,17 @@ impl<S, L, Req, MkC, SP> Server<S, L, Req, MkC, SP> {
                                 service.clone(),
                                 self.make_codec.clone(),
                                 stat_tracer.clone(),
+                                self.access_log.clone(),
+                                self.error_log.clone(),
                                 exit_notify_inner.clone(),
                                 exit_mark_inner.clone(),
                                 conn_cnt.clone(),
+                                conn_guard,
                                 peer_addr,
+                                recv_timestamp,
+                                listener,
+                                tls_info,
+                                self.yield_budget,
                             ));
                         } else {
                             tokio::spawn(handle_conn(
@@ -246,10 +448,20 @@ impl<S, L, Req, MkC, SP> Server<S, L, Req, MkC, SP> {
                                 service.clone(),
                                 self.make_codec.clone(),
                                 stat_tracer.clone(),
+                                self.access_log.clone(),
+                                self.error_log.clone(),
                                 exit_notify_inner.clone(),
                                 exit_mark_inner.clone(),
                                 conn_cnt.clone(),
+                                conn_guard,
                                 peer_addr,
+                                recv_timestamp,
+                                listener,
+                                tls_info,
+                                self.capture_frame,
+                                self.cancel_on_peer_close,
+                                self.yield_budget,
+                                self.context_cache.clone(),
                                 self.span_provider.clone(),
                             ));
                         }
@@ -260,10 +472,20 @@ impl<S, L, Req, MkC, SP> Server<S, L, Req, MkC, SP> {
                             service.clone(),
                             self.make_codec.clone(),
                             stat_tracer.clone(),
+                            self.access_log.clone(),
+                            self.error_log.clone(),
                             exit_notify_inner.clone(),
                             exit_mark_inner.clone(),
                             conn_cnt.clone(),
+                            conn_guard,
                             peer_addr,
+                            recv_timestamp,
+                            listener,
+                            tls_info,
+                            self.capture_frame,
+                            self.cancel_on_peer_close,
+                            self.yield_budget,
+                            self.context_cache.clone(),
                             self.span_provider.clone(),
                         ));
                     }
@@ -289,7 +511,8 @@ impl<S, L, Req, MkC, SP> Server<S, L, Req, MkC, SP> {
                 _ = sigint.recv() => {}
                 _ = sighup.recv() => {}
                 _ = sigterm.recv() => {}
-                res = handler => {
+                d = handle.requested() => deadline = d,
+                res = &mut handler => {
                     match res {
                         Ok(res) => {
                             match res {
@@ -307,7 +530,8 @@ impl<S, L, Req, MkC, SP> Server<S, L, Req, MkC, SP> {
         #[cfg(target_family = "windows")]
         tokio::select! {
             _ = tokio::signal::ctrl_c() => {}
-            res = handler => {
+            d = handle.requested() => deadline = d,
+            res = &mut handler => {
                 match res {
                     Ok(res) => {
                         match res {
@@ -332,16 +556,19 @@ impl<S, L, Req, MkC, SP> Server<S, L, Req, MkC, SP> {
         info!("[VOLO] received signal, gracefully exiting now");
         *exit_flag.write() = true;
         exit_mark.store(true, Ordering::Relaxed);
+        // stops accepting, and unlinks the socket file of the unix domain socket
+        handler.abort();
 
+        let deadline = deadline.unwrap_or_else(|| Instant::now() + Duration::from_secs(30));
         // Now we won't accept new connections.
         // And we want to send crrst reply to the peers in the short future.
         if gconn_cnt.load(Ordering::Relaxed) != 0 {
-            tokio::time::sleep(Duration::from_secs(2)).await;
+            tokio::time::sleep_until(deadline.min(Instant::now() + Duration::from_secs(2))).await;
         }
         exit_notify.notify_waiters();
 
         // wait for all connections to be closed
-        for _ in 0..28 {
+        while Instant::now() < deadline {
             if gconn_cnt.load(Ordering::Relaxed) == 0 {
                 break;
             }
@@ -349,25 +576,44 @@ impl<S, L, Req, MkC, SP> Server<S, L, Req, MkC, SP> {
                 "[VOLO] gracefully exiting, remaining connection count: {}",
                 gconn_cnt.load(Ordering::Relaxed)
             );
-            tokio::time::sleep(Duration::from_secs(1)).await;
+            tokio::time::sleep_until(deadline.min(Instant::now() + Duration::from_millis(100)))
+                .await;
         }
         Ok(())
     }
 
-    #[cfg(feature = "multiplex")]
-    /// Use multiplexing to handle multiple requests in one connection.
+    /// Selects the transport mode of the server, which must be the same as the one of the clients,
+    /// see [`ClientBuilder::multiplex`](crate::client::ClientBuilder::multiplex).
     ///
-    /// Not recommend for most users.
-    #[doc(hidden)]
+    /// - Pingpong (`false`, the default): the requests of a connection are handled one by one,
+    ///   and each response is written before the next request is read. This works with any thrift
+    ///   client.
+    /// - Multiplex (`true`): the requests are dispatched to the spawned tasks once decoded, and
+    ///   the responses are written back as soon as they are completed, which may be out of the
+    ///   order of the requests, so the clients must match the responses by the seq id.
+    ///
+    /// Both modes decode the requests by the same codec. The [`sampling`](Self::sampling), which
+    /// captures the frames of the requests, is not supported in the multiplex mode.
+    ///
+    /// This is only available with the `multiplex` feature.
+    #[cfg(feature = "multiplex")]
     pub fn multiplex(self, multiplex: bool) -> Server<S, L, Req, MkC, SP> {
         Server {
             layer: self.layer,
             service: self.service,
             make_codec: self.make_codec,
             stat_tracer: self.stat_tracer,
+            access_log: self.access_log,
+            error_log: self.error_log,
+            capture_frame: self.capture_frame,
+            cancel_on_peer_close: self.cancel_on_peer_close,
+            yield_budget: self.yield_budget,
+            context_cache: self.context_cache,
             multiplex,
             span_provider: self.span_provider,
             shutdown_hooks: self.shutdown_hooks,
+            handle: self.handle,
+            conn_stats: self.conn_stats,
             _marker: PhantomData,
         }
     }
@@ -378,10 +624,18 @@ impl<S, L, Req, MkC, SP> Server<S, L, Req, MkC, SP> {
             service: self.service,
             make_codec: self.make_codec,
             stat_tracer: self.stat_tracer,
+            access_log: self.access_log,
+            error_log: self.error_log,
+            capture_frame: self.capture_frame,
+            cancel_on_peer_close: self.cancel_on_peer_close,
+            yield_budget: self.yield_budget,
+            context_cache: self.context_cache,
             #[cfg(feature = "multiplex")]
             multiplex: self.multiplex,
             span_provider: provider,
             shutdown_hooks: self.shutdown_hooks,
+            handle: self.handle,
+            conn_stats: self.conn_stats,
             _marker: PhantomData,
         }
     }
@@ -394,10 +648,20 @@ async fn handle_conn<R, W, Req, Svc, Resp, MkC, SP>(
     service: Svc,
     make_codec: MkC,
     stat_tracer: Arc<[TraceFn]>,
+    access_log: AccessLog,
+    error_log: ErrorLog,
     exit_notify: Arc<Notify>,
     exit_mark: Arc<std::sync::atomic::AtomicBool>,
     conn_cnt: Arc<std::sync::atomic::AtomicUsize>,
+    conn_guard: Option<ConnGuard>,
     peer_addr: Option<Address>,
+    recv_timestamp: Option<RecvTimestamp>,
+    listener: Option<ListenerName>,
+    tls_info: Option<Arc<TlsInfo>>,
+    capture_frame: bool,
+    cancel_on_peer_close: bool,
+    yield_budget: usize,
+    context_cache: ServerContextCache,
     span_provider: SP,
 ) where
     R: AsyncRead + Unpin + Send + Sync + 'static,
@@ -414,6 +678,8 @@ async fn handle_conn<R, W, Req, Svc, Resp, MkC, SP>(
     defer! {
         conn_cnt.fetch_sub(1, Ordering::Relaxed);
     }
+    // counts the connection as closed once it's served
+    let _conn_guard = conn_guard;
 
     let (encoder, decoder) = make_codec.make_codec(rh, wh);
 
@@ -428,7 +694,16 @@ async fn handle_conn<R, W, Req, Svc, Resp, MkC, SP>(
         exit_mark,
         &service,
         stat_tracer,
+        access_log,
+        error_log,
         peer_addr,
+        recv_timestamp,
+        listener,
+        tls_info,
+        capture_frame,
+        cancel_on_peer_close,
+        yield_budget,
+        context_cache,
         span_provider,
     )
     .await;
@@ -442,10 +717,17 @@ async fn handle_conn_multiplex<R, W, Req, Svc, Resp, MkC>(
     service: Svc,
     make_codec: MkC,
     stat_tracer: Arc<[TraceFn]>,
+    access_log: AccessLog,
+    error_log: ErrorLog,
     exit_notify: Arc<Notify>,
     exit_mark: Arc<std::sync::atomic::AtomicBool>,
     conn_cnt: Arc<std::sync::atomic::AtomicUsize>,
+    conn_guard: Option<ConnGuard>,
     peer_addr: Option<Address>,
+    recv_timestamp: Option<RecvTimestamp>,
+    listener: Option<ListenerName>,
+    tls_info: Option<Arc<TlsInfo>>,
+    yield_budget: usize,
 ) where
     R: AsyncRead + Unpin + Send + Sync + 'static,
     W: AsyncWrite + Unpin + Send + Sync + 'static,
@@ -459,6 +741,8 @@ async fn handle_conn_multiplex<R, W, Req, Svc, Resp, MkC>(
     defer! {
         conn_cnt.fetch_sub(1, Ordering::Relaxed);
     }
+    // counts the connection as closed once it's served
+    let _conn_guard = conn_guard;
     let (encoder, decoder) = make_codec.make_codec(rh, wh);
 
     info!(
@@ -472,7 +756,13 @@ async fn handle_conn_multiplex<R, W, Req, Svc, Resp, MkC>(
         exit_mark,
         service,
         stat_tracer,
+        access_log,
+        error_log,
         peer_addr,
+        recv_timestamp,
+        listener,
+        tls_info,
+        yield_budget,
     )
     .await;
     conn_cnt.fetch_sub(1, Ordering::Relaxed);
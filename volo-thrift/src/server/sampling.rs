@@ -0,0 +1,276 @@
+//! Sampling the requests for capturing and replaying.
+//!
+//! When the [`Sampler`] decides to capture a request, the raw frame of the request and some
+//! fields of the context are handed to the [`SampleSink`] as a [`CapturedRequest`], which can be
+//! stored and then replayed locally by [`replay`].
+//!
+//! The decoders keep the frame as [`Bytes`] and only a handle of it is kept in the context, so no
+//! data is copied for the requests that are not sampled. Only the length-prefixed transports
+//! (TTHeader and Framed) can be captured.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use volo_thrift::server::sampling::{CapturedRequest, SampleSink};
+//!
+//! struct Sink;
+//!
+//! impl SampleSink for Sink {
+//!     async fn capture(&self, captured: CapturedRequest) {
+//!         // store the captured request somewhere
+//!     }
+//! }
+//!
+//! volo_gen::volo::example::ItemServiceServer::new(S)
+//!     .sampling(
+//!         |method: &str, _peer: Option<&Address>| method == "GetItem" && rand::random::<u8>() == 0,
+//!         Sink,
+//!     )
+//!     .run(addr)
+//!     .await
+//!     .unwrap();
+//! ```
+
+use std::{future::Future, io::Cursor, sync::Arc, time::SystemTime};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use motore::{layer::Layer, service::Service};
+use pilota::FastStr;
+use volo::{context::Context, net::Address};
+
+use crate::{
+    codec::{default::RawFrame, Decoder, DefaultMakeCodec, MakeCodec},
+    context::ServerContext,
+    EntryMessage, ServerError, ThriftMessage,
+};
+
+/// Decides whether to capture a request by its method and the address of the peer.
+pub trait Sampler: Send + Sync + 'static {
+    fn sample(&self, method: &str, peer: Option<&Address>) -> bool;
+}
+
+impl<F> Sampler for F
+where
+    F: Fn(&str, Option<&Address>) -> bool + Send + Sync + 'static,
+{
+    fn sample(&self, method: &str, peer: Option<&Address>) -> bool {
+        self(method, peer)
+    }
+}
+
+/// Receives the captured requests.
+///
+/// It is called in a spawned task, so it will not slow down the requests.
+pub trait SampleSink: Send + Sync + 'static {
+    fn capture(&self, captured: CapturedRequest) -> impl Future<Output = ()> + Send;
+}
+
+/// A captured request.
+#[derive(Debug, Clone)]
+pub struct CapturedRequest {
+    /// The payload of the frame, see [`RawFrame`].
+    pub frame: Bytes,
+    pub method: FastStr,
+    /// The service name of the caller.
+    pub caller: FastStr,
+    pub peer: Option<Address>,
+    pub captured_at: SystemTime,
+}
+
+/// The layer added by [`Server::sampling`][crate::server::Server::sampling].
+#[derive(Clone)]
+pub struct SamplingLayer<Sa, Si> {
+    sampler: Arc<Sa>,
+    sink: Arc<Si>,
+}
+
+impl<Sa, Si> SamplingLayer<Sa, Si> {
+    pub fn new(sampler: Sa, sink: Si) -> Self {
+        Self {
+            sampler: Arc::new(sampler),
+            sink: Arc::new(sink),
+        }
+    }
+}
+
+impl<S, Sa, Si> Layer<S> for SamplingLayer<Sa, Si> {
+    type Service = SamplingService<S, Sa, Si>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        SamplingService {
+            inner,
+            sampler: self.sampler,
+            sink: self.sink,
+        }
+    }
+}
+
+/// The service created by [`SamplingLayer`].
+pub struct SamplingService<S, Sa, Si> {
+    inner: S,
+    sampler: Arc<Sa>,
+    sink: Arc<Si>,
+}
+
+impl<S: Clone, Sa, Si> Clone for SamplingService<S, Sa, Si> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            sampler: self.sampler.clone(),
+            sink: self.sink.clone(),
+        }
+    }
+}
+
+impl<Req, S, Sa, Si> Service<ServerContext, Req> for SamplingService<S, Sa, Si>
+where
+    Req: Send + 'static,
+    S: Service<ServerContext, Req> + Send + Sync + 'static,
+    Sa: Sampler,
+    Si: SampleSink,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call<'s, 'cx>(
+        &'s self,
+        cx: &'cx mut ServerContext,
+        req: Req,
+    ) -> Result<Self::Response, Self::Error> {
+        if let Some(RawFrame(frame)) = cx.extensions_mut().remove::<RawFrame>() {
+            let info = cx.rpc_info();
+            let peer = info.caller().address.as_ref();
+            if self.sampler.sample(info.method(), peer) {
+                let captured = CapturedRequest {
+                    frame,
+                    method: info.method().clone(),
+                    caller: info.caller().service_name(),
+                    peer: peer.cloned(),
+                    captured_at: SystemTime::now(),
+                };
+                let sink = self.sink.clone();
+                tokio::spawn(async move { sink.capture(captured).await });
+            }
+        }
+        self.inner.call(cx, req).await
+    }
+}
+
+/// Decodes the captured frame and calls the service with a context synthesized from the captured
+/// request.
+pub async fn replay<Req, S>(
+    service: &S,
+    captured: &CapturedRequest,
+) -> Result<S::Response, ServerError>
+where
+    Req: EntryMessage,
+    S: Service<ServerContext, Req>,
+    S::Error: Into<ServerError>,
+{
+    // add the length back, both TTHeader and Framed are prefixed by a 4-byte length
+    let mut buf = BytesMut::with_capacity(4 + captured.frame.len());
+    buf.put_u32(captured.frame.len() as u32);
+    buf.put_slice(&captured.frame);
+
+    let (_, mut decoder) =
+        DefaultMakeCodec::default().make_codec(Cursor::new(buf.freeze()), tokio::io::sink());
+    let mut cx = ServerContext::default();
+    cx.rpc_info_mut()
+        .caller_mut()
+        .set_service_name(captured.caller.clone());
+    if let Some(peer) = &captured.peer {
+        cx.rpc_info_mut().caller_mut().set_address(peer.clone());
+    }
+
+    match decoder.decode::<Req, _>(&mut cx).await? {
+        Some(ThriftMessage { data: Ok(req), .. }) => {
+            service.call(&mut cx, req).await.map_err(Into::into)
+        }
+        Some(ThriftMessage { data: Err(e), .. }) => Err(e.into()),
+        None => Err(pilota::thrift::new_protocol_exception(
+            pilota::thrift::ProtocolExceptionKind::InvalidData,
+            "empty captured frame",
+        )
+        .into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use motore::service::service_fn;
+
+    use super::*;
+    use crate::codec::default::{capture_frame, CaptureFrame};
+
+    /// Encodes a framed binary call of the method with the payload.
+    fn frame(method: &str, payload: &[u8]) -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u32(0x8001_0001);
+        buf.put_i32(method.len() as i32);
+        buf.put_slice(method.as_bytes());
+        buf.put_i32(1);
+        buf.put_slice(payload);
+        buf.freeze()
+    }
+
+    async fn echo(cx: &mut ServerContext, req: Bytes) -> Result<String, ServerError> {
+        Ok(format!(
+            "{}: {}",
+            cx.rpc_info().method(),
+            String::from_utf8_lossy(&req)
+        ))
+    }
+
+    struct Sink(tokio::sync::mpsc::UnboundedSender<CapturedRequest>);
+
+    impl SampleSink for Sink {
+        async fn capture(&self, captured: CapturedRequest) {
+            let _ = self.0.send(captured);
+        }
+    }
+
+    #[tokio::test]
+    async fn capture_and_replay() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let svc = SamplingLayer::new(
+            |method: &str, _: Option<&Address>| method == "get",
+            Sink(tx),
+        )
+        .layer(service_fn(echo));
+
+        let captured = frame("get", b"hello");
+        let mut cx = ServerContext::default();
+        cx.rpc_info_mut()
+            .set_method(FastStr::from_static_str("get"));
+        cx.extensions_mut().insert(RawFrame(captured.clone()));
+        assert_eq!(svc.call(&mut cx, Bytes::new()).await.unwrap(), "get: ");
+
+        // not sampled
+        let mut cx = ServerContext::default();
+        cx.rpc_info_mut()
+            .set_method(FastStr::from_static_str("set"));
+        cx.extensions_mut().insert(RawFrame(frame("set", b"world")));
+        svc.call(&mut cx, Bytes::new()).await.unwrap();
+
+        let received = rx.recv().await.unwrap();
+        assert_eq!(received.method, "get");
+        assert_eq!(received.frame, captured);
+        assert!(rx.try_recv().is_err());
+
+        let resp = replay::<Bytes, _>(&service_fn(echo), &received)
+            .await
+            .unwrap();
+        assert_eq!(resp, "get: hello");
+    }
+
+    #[test]
+    fn capture_frame_only_when_asked() {
+        let mut cx = ServerContext::default();
+        capture_frame(&mut cx, &Bytes::from_static(b"frame"));
+        assert!(!cx.extensions().contains::<RawFrame>());
+
+        cx.extensions_mut().insert(CaptureFrame);
+        capture_frame(&mut cx, &Bytes::from_static(b"frame"));
+        assert!(cx.extensions().contains::<RawFrame>());
+    }
+}
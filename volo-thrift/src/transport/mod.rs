@@ -5,4 +5,293 @@ pub mod multiplex;
 pub mod pingpong;
 pub mod pool;
 
-pub use pool::Config;
+use std::{error::Error, io};
+
+pub use pool::{Config, PoolStats};
+use volo::net::{fingerprint, Address};
+
+use crate::ClientError;
+
+/// Replaces the error of a failed call with a [`ClientError::ProtocolMismatch`] if the peer turns
+/// out to speak another protocol than thrift.
+///
+/// The peer is only probed if the error looks like the response could not be decoded, so that a
+/// healthy call never pays for it.
+pub(crate) async fn diagnose(target: &Address, err: ClientError) -> ClientError {
+    if !is_undecodable(&err) {
+        return err;
+    }
+    match fingerprint::diagnose(
+        target,
+        "thrift",
+        None,
+        fingerprint::DEFAULT_DIAGNOSE_TIMEOUT,
+    )
+    .await
+    {
+        Some(mismatch) => ClientError::ProtocolMismatch(mismatch),
+        None => err,
+    }
+}
+
+fn is_undecodable(err: &ClientError) -> bool {
+    let ClientError::Transport(e) = err else {
+        return matches!(err, ClientError::Protocol(_));
+    };
+    let mut source: Option<&(dyn Error + 'static)> = Some(e);
+    while let Some(err) = source {
+        if let Some(io) = err.downcast_ref::<io::Error>() {
+            return matches!(
+                io.kind(),
+                io::ErrorKind::InvalidData
+                    | io::ErrorKind::UnexpectedEof
+                    | io::ErrorKind::ConnectionReset
+            );
+        }
+        source = err.source();
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use bytes::Bytes;
+    use motore::Service;
+    use pilota::thrift::{ApplicationExceptionKind, TMessageType};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+    use volo::{
+        context::{Endpoint, Role, RpcInfo},
+        error::CodedError,
+        net::{dial::DefaultMakeTransport, fingerprint::Protocol},
+    };
+
+    use super::*;
+    use crate::{
+        codec::{default::DefaultMakeCodec, Decoder, Encoder, MakeCodec},
+        context::{ClientContext, ServerContext},
+        MessageMeta, ThriftMessage,
+    };
+
+    /// Answers every connection with `answer` after reading the first bytes, like a server of
+    /// another protocol rejecting the thrift request.
+    async fn stub(answer: &'static [u8]) -> Address {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut conn, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 64];
+                    let _ = conn.read(&mut buf).await;
+                    let _ = conn.write_all(answer).await;
+                });
+            }
+        });
+        Address::from(addr)
+    }
+
+    /// Answers the requests on every connection once `requests` of them are received, with
+    /// `seq_id` like a broken server or with their own seq ids if it's `None`, and counts the
+    /// accepted connections.
+    async fn seq_id_stub(requests: usize, seq_id: Option<i32>) -> (Address, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let conns = Arc::new(AtomicUsize::new(0));
+        let accepted = conns.clone();
+        tokio::spawn(async move {
+            while let Ok((conn, _)) = listener.accept().await {
+                accepted.fetch_add(1, Ordering::Relaxed);
+                tokio::spawn(async move {
+                    let (rh, wh) = conn.into_split();
+                    let (mut encoder, mut decoder) = DefaultMakeCodec::default().make_codec(rh, wh);
+                    let mut received = Vec::new();
+                    while received.len() < requests {
+                        let mut cx = ServerContext::default();
+                        let Ok(Some(req)) = decoder.decode::<Bytes, _>(&mut cx).await else {
+                            return;
+                        };
+                        received.push((cx, req.meta));
+                    }
+                    for (mut cx, meta) in received {
+                        cx.msg_type = Some(TMessageType::Reply);
+                        let resp = ThriftMessage {
+                            data: Ok(Bytes::from_static(&[0])),
+                            meta: MessageMeta {
+                                msg_type: TMessageType::Reply,
+                                method: meta.method,
+                                seq_id: seq_id.unwrap_or(meta.seq_id),
+                            },
+                        };
+                        if encoder.encode(&mut cx, resp).await.is_err() {
+                            return;
+                        }
+                    }
+                    // the connection is kept until the client closes it
+                    let _ = decoder
+                        .decode::<Bytes, _>(&mut ServerContext::default())
+                        .await;
+                });
+            }
+        });
+        (Address::from(addr), conns)
+    }
+
+    async fn send<S>(
+        client: &S,
+        target: Address,
+        seq_id: i32,
+    ) -> Result<Option<ThriftMessage<Bytes>>, ClientError>
+    where
+        S: Service<
+            ClientContext,
+            ThriftMessage<Bytes>,
+            Response = Option<ThriftMessage<Bytes>>,
+            Error = ClientError,
+        >,
+    {
+        let mut callee = Endpoint::new("stub".into());
+        callee.set_address(target);
+        let mut cx = ClientContext::new(
+            seq_id,
+            RpcInfo::new(
+                Role::Client,
+                "echo".into(),
+                Endpoint::new("test".into()),
+                callee,
+                Default::default(),
+            ),
+            TMessageType::Call,
+        );
+        let req = ThriftMessage::mk_client_msg(&cx, Bytes::from_static(&[0]));
+        metainfo::METAINFO
+            .scope(RefCell::new(metainfo::MetaInfo::default()), async {
+                client.call(&mut cx, req).await
+            })
+            .await
+    }
+
+    async fn call(target: Address) -> ClientError {
+        let client = pingpong::Client::<Bytes, _, _>::new(
+            DefaultMakeTransport::new(),
+            None,
+            DefaultMakeCodec::default(),
+        );
+        send(&client, target, 1).await.err().unwrap()
+    }
+
+    /// Returns the message of the `BAD_SEQUENCE_ID` exception failing the call.
+    fn bad_seq_id(res: Result<Option<ThriftMessage<Bytes>>, ClientError>) -> String {
+        match res {
+            Err(ClientError::Application(e)) => {
+                assert_eq!(e.kind(), ApplicationExceptionKind::BAD_SEQUENCE_ID);
+                e.message().to_string()
+            }
+            Err(e) => panic!("unexpected error: {e}"),
+            Ok(_) => panic!("unexpected response"),
+        }
+    }
+
+    #[tokio::test]
+    async fn protocol_mismatch() {
+        for (answer, found) in [
+            (
+                &b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n"[..],
+                Protocol::Http1,
+            ),
+            (
+                &[0x15, 0x03, 0x03, 0x00, 0x02, 0x02, 0x46][..],
+                Protocol::Tls,
+            ),
+            (
+                &[0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00][..],
+                Protocol::Http2,
+            ),
+        ] {
+            let err = call(stub(answer).await).await;
+            let ClientError::ProtocolMismatch(mismatch) = &err else {
+                panic!("unexpected error: {err}");
+            };
+            assert_eq!(mismatch.found(), found);
+            assert_eq!(mismatch.expected(), "thrift");
+            assert!(!mismatch.head().is_empty());
+            assert_eq!(err.code(), crate::error::code::PROTOCOL_MISMATCH);
+        }
+    }
+
+    #[tokio::test]
+    async fn pingpong_bad_seq_id() {
+        let (target, conns) = seq_id_stub(1, Some(99)).await;
+        let client = pingpong::Client::<Bytes, _, _>::new(
+            DefaultMakeTransport::new(),
+            None,
+            DefaultMakeCodec::default(),
+        );
+
+        for round in 1..=2 {
+            let msg = bad_seq_id(send(&client, target.clone(), 7).await);
+            assert_eq!(
+                msg,
+                "received a response with seq_id 99 while expecting seq_id 7"
+            );
+            // the connection out of sync is not put back into the pool
+            assert_eq!(conns.load(Ordering::Relaxed), round);
+        }
+    }
+
+    #[cfg(feature = "multiplex")]
+    #[tokio::test]
+    async fn multiplex_bad_seq_id() {
+        let (target, conns) = seq_id_stub(2, Some(99)).await;
+        let client = multiplex::Client::<Bytes, _, _>::new(
+            DefaultMakeTransport::new(),
+            None,
+            DefaultMakeCodec::default(),
+        );
+
+        for round in 1..=2 {
+            // both requests are pending on the same connection when the response arrives
+            let (first, second) = tokio::join!(
+                send(&client, target.clone(), 7),
+                send(&client, target.clone(), 8)
+            );
+            for (res, expected, other) in [(first, 7, 8), (second, 8, 7)] {
+                let msg = bad_seq_id(res);
+                assert!(msg.contains("seq_id 99"), "{msg}");
+                assert!(msg.contains(&format!("seq_id {expected}")), "{msg}");
+                assert!(!msg.contains(&format!("seq_id {other}")), "{msg}");
+            }
+            // the connection is evicted from the pool, and a new one is made for the next round
+            assert_eq!(conns.load(Ordering::Relaxed), round);
+        }
+    }
+
+    #[cfg(feature = "multiplex")]
+    #[tokio::test]
+    async fn multiplex_seq_id_collision() {
+        let (target, _) = seq_id_stub(2, None).await;
+        let client = multiplex::Client::<Bytes, _, _>::new(
+            DefaultMakeTransport::new(),
+            None,
+            DefaultMakeCodec::default(),
+        );
+
+        // the seq ids collide after wrapping around, and the latter one takes the next free one
+        let (first, second) = tokio::join!(
+            send(&client, target.clone(), i32::MAX),
+            send(&client, target.clone(), i32::MAX)
+        );
+        let mut seq_ids = [first, second].map(|res| res.unwrap().unwrap().meta.seq_id);
+        seq_ids.sort_unstable();
+        assert_eq!(seq_ids, [i32::MIN, i32::MAX]);
+    }
+}
@@ -4,6 +4,7 @@ use motore::service::{Service, UnaryService};
 use volo::net::{dial::MakeTransport, Address};
 
 use crate::{
+    client::warmup::{self, Connect, Release},
     codec::MakeCodec,
     context::ClientContext,
     protocol::TMessageType,
@@ -136,17 +137,24 @@ where
         })?;
         let oneway = cx.message_type == TMessageType::OneWay;
         cx.stats.record_make_transport_start_at();
-        let transport = self.make_transport.call((target, Ver::Multiplex)).await?;
+        let transport = self
+            .make_transport
+            .call((target.clone(), Ver::Multiplex))
+            .await?;
         cx.stats.record_make_transport_end_at();
-        let resp = transport.send(cx, req, oneway).await;
+        let resp = match transport.send(cx, req, oneway).await {
+            Err(err) => return Err(crate::transport::diagnose(&target, err).await),
+            resp => resp,
+        };
         if let Ok(None) = resp {
             if !oneway {
-                return Err(ClientError::Transport(
-                    pilota::thrift::TransportException::from(io::Error::new(
+                let err = ClientError::Transport(pilota::thrift::TransportException::from(
+                    io::Error::new(
                         io::ErrorKind::UnexpectedEof,
                         format!("an unexpected end of file from server, cx: {:?}", cx),
-                    )),
+                    ),
                 ));
+                return Err(crate::transport::diagnose(&target, err).await);
             }
         }
         if cx.transport.should_reuse && resp.is_ok() {
@@ -155,3 +163,31 @@ where
         resp
     }
 }
+
+impl<Resp, MkT, MkC> Connect for Client<Resp, MkT, MkC>
+where
+    Resp: EntryMessage + Send + Sync + 'static,
+    MkT: MakeTransport,
+    MkC: MakeCodec<MkT::ReadHalf, MkT::WriteHalf> + Sync,
+{
+    async fn connect(
+        &self,
+        target: Address,
+        ping: Option<ClientContext>,
+    ) -> Result<Release, ClientError> {
+        let transport = self
+            .make_transport
+            .call((target.clone(), Ver::Multiplex))
+            .await?;
+        if let Some(mut cx) = ping {
+            let msg = ThriftMessage::mk_client_msg(&cx, warmup::ping_args());
+            if transport.send(&mut cx, msg, false).await?.is_none() {
+                return Err(warmup::unexpected_eof(&target));
+            }
+            if !cx.transport.should_reuse {
+                return Ok(Box::new(|| {}));
+            }
+        }
+        Ok(Box::new(move || transport.reuse()))
+    }
+}
@@ -8,18 +8,33 @@ use motore::service::Service;
 use pilota::thrift::ThriftException;
 use tokio::sync::{futures::Notified, mpsc};
 use tracing::*;
-use volo::{context::Context, net::Address, volo_unreachable};
+use volo::{
+    context::{
+        identity::{PeerIdentityExt, TlsInfo},
+        Context,
+    },
+    error::CodedError,
+    net::{listener::ListenerName, timestamp::RecvTimestamp, Address},
+    util::budget::Budget,
+    volo_unreachable,
+};
 
 use crate::{
     codec::{Decoder, Encoder},
     context::ServerContext,
     protocol::TMessageType,
-    server_error_to_application_exception, thrift_exception_to_application_exception, DummyMessage,
-    EntryMessage, ServerError, ThriftMessage,
+    server::{
+        access_log::{self, AccessLog, AccessStatus},
+        error_log::{log_error, ErrorLog},
+    },
+    server_error_to_application_exception, thrift_exception_code,
+    thrift_exception_to_application_exception, DummyMessage, EntryMessage, ServerError,
+    ThriftMessage,
 };
 
 const CHANNEL_SIZE: usize = 1024;
 
+#[allow(clippy::too_many_arguments)]
 pub async fn serve<Svc, Req, Resp, E, D>(
     mut encoder: E,
     mut decoder: D,
@@ -27,7 +42,13 @@ pub async fn serve<Svc, Req, Resp, E, D>(
     exit_mark: Arc<std::sync::atomic::AtomicBool>,
     service: Svc,
     stat_tracer: Arc<[crate::server::TraceFn]>,
+    access_log: AccessLog,
+    error_log: ErrorLog,
     peer_addr: Option<Address>,
+    recv_timestamp: Option<RecvTimestamp>,
+    listener: Option<ListenerName>,
+    tls_info: Option<Arc<TlsInfo>>,
+    yield_budget: usize,
 ) where
     Svc: Service<ServerContext, Req, Response = Resp> + Send + Clone + 'static + Sync,
     Svc::Error: Into<ServerError> + Send,
@@ -44,15 +65,19 @@ pub async fn serve<Svc, Req, Resp, E, D>(
 
     tokio::spawn({
         let peer_addr = peer_addr.clone();
+        let access_log = access_log.clone();
+        let error_log = error_log.clone();
         async move {
+            let mut budget = Budget::new(yield_budget);
             metainfo::METAINFO
                 .scope(RefCell::new(MetaInfo::default()), async {
                     loop {
                         tokio::select! {
                             // receives a response, we need to send it back to client
-                            msg = send_rx.recv() => {
+                            // the budget is refilled once there's no response to send
+                            msg = budget.reset_on_pending(send_rx.recv()) => {
                                 match msg {
-                                    Some((mi, mut cx, msg)) => {
+                                    Some((mi, mut cx, msg, status)) => {
                                         if let Err(e) = metainfo::METAINFO
                                             .scope(
                                                 RefCell::new(mi),
@@ -61,15 +86,25 @@ pub async fn serve<Svc, Req, Resp, E, D>(
                                             .await
                                         {
                                             // log it
-                                            error!(
-                                                "[VOLO] server send response error: {:?}, cx: \
-                                                 {:?}, peer_addr: {:?}",
-                                                e, cx, peer_addr
+                                            let code = thrift_exception_code(&e);
+                                            log_error!(
+                                                error_log,
+                                                &e,
+                                                "[VOLO] server send response error: {:?}, code: \
+                                                 {}, cx: {:?}, peer_addr: {:?}",
+                                                e, code, cx, peer_addr
                                             );
                                             stat_tracer.iter().for_each(|f| f(&cx));
+                                            access_log::log(
+                                                &access_log,
+                                                &cx,
+                                                AccessStatus::SendError(code),
+                                            );
                                             return;
                                         }
                                         stat_tracer.iter().for_each(|f| f(&cx));
+                                        access_log::log(&access_log, &cx, status);
+                                        budget.tick().await;
                                     }
                                     None => {
                                         // log it
@@ -84,19 +119,29 @@ pub async fn serve<Svc, Req, Resp, E, D>(
                             // receives an error, we need to close the connection
                             error_msg = error_send_rx.recv() => {
                                 match error_msg {
-                                    Some((mut cx, msg)) => {
+                                    Some((mut cx, msg, code)) => {
                                         if let Err(e) = encoder
                                             .encode::<DummyMessage, ServerContext>(&mut cx, msg)
                                             .await
                                         {
                                             // log it
-                                            error!(
-                                                "[VOLO] server send error error: {:?}, cx: {:?}, \
-                                                 peer_addr: {:?}",
-                                                e, cx, peer_addr
+                                            log_error!(
+                                                error_log,
+                                                &e,
+                                                "[VOLO] server send error error: {:?}, code: {}, \
+                                                 cx: {:?}, peer_addr: {:?}",
+                                                e,
+                                                thrift_exception_code(&e),
+                                                cx,
+                                                peer_addr
                                             );
                                         }
                                         stat_tracer.iter().for_each(|f| f(&cx));
+                                        access_log::log(
+                                            &access_log,
+                                            &cx,
+                                            AccessStatus::DecodeError(code),
+                                        );
                                         return;
                                     }
                                     None => {
@@ -117,6 +162,7 @@ pub async fn serve<Svc, Req, Resp, E, D>(
         }
     });
 
+    let mut budget = Budget::new(yield_budget);
     metainfo::METAINFO
         .scope(RefCell::new(MetaInfo::default()), async {
             loop {
@@ -127,6 +173,18 @@ pub async fn serve<Svc, Req, Resp, E, D>(
                         .caller_mut()
                         .set_address(peer_addr.clone());
                 }
+                if let Some(recv_timestamp) = &recv_timestamp {
+                    cx.extensions_mut().insert(recv_timestamp.clone());
+                }
+                if let Some(listener) = &listener {
+                    cx.extensions_mut().insert(listener.clone());
+                }
+                if let Some(tls_info) = &tls_info {
+                    if let Some(identity) = tls_info.identity() {
+                        cx.peer_identity_mut().set_tls(identity.clone());
+                    }
+                    cx.extensions_mut().insert(tls_info.clone());
+                }
 
                 tokio::select! {
                     _ = &mut notified => {
@@ -136,8 +194,8 @@ pub async fn serve<Svc, Req, Resp, E, D>(
                         );
                         return;
                     }
-                    // receives a message
-                    msg = decoder.decode(&mut cx) => {
+                    // receives a message, and the budget is refilled once the read is pending
+                    msg = budget.reset_on_pending(decoder.decode(&mut cx)) => {
                         tracing::debug!(
                             "[VOLO] received message: {:?}, cx: {:?}, peer_addr: {:?}",
                             msg.as_ref().map(|msg| msg.as_ref().map(|msg| &msg.meta)),
@@ -158,19 +216,29 @@ pub async fn serve<Svc, Req, Resp, E, D>(
                                 return;
                             }
                             Err(e) => {
-                                error!(
-                                    "[VOLO] multiplex server decode error {:?}, peer_addr: {:?}",
-                                    e, peer_addr
+                                let code = thrift_exception_code(&e);
+                                log_error!(
+                                    error_log,
+                                    &e,
+                                    "[VOLO] multiplex server decode error {:?}, code: {}, \
+                                     peer_addr: {:?}",
+                                    e, code, peer_addr
                                 );
                                 cx.msg_type = Some(TMessageType::Exception);
-                                if !matches!(e, ThriftException::Transport(_)) {
+                                if matches!(e, ThriftException::Transport(_)) {
+                                    access_log::log(
+                                        &access_log,
+                                        &cx,
+                                        AccessStatus::DecodeError(code),
+                                    );
+                                } else {
                                     let msg = ThriftMessage::mk_server_resp(
                                         &cx,
                                         Err::<DummyMessage, _>(
                                             thrift_exception_to_application_exception(e),
                                         ),
                                     );
-                                    let _ = error_send_tx.send((cx, msg)).await;
+                                    let _ = error_send_tx.send((cx, msg, code)).await;
                                 }
                                 return;
                             }
@@ -180,6 +248,7 @@ pub async fn serve<Svc, Req, Resp, E, D>(
                         let svc = service.clone();
                         let exit_mark = exit_mark.clone();
                         let send_tx = send_tx.clone();
+                        let access_log = access_log.clone();
                         let mi = metainfo::METAINFO.with(|m| m.take());
                         tokio::spawn(async {
                             metainfo::METAINFO
@@ -193,6 +262,11 @@ pub async fn serve<Svc, Req, Resp, E, D>(
                                     }
                                     let req_msg_type =
                                         cx.req_msg_type.expect("`req_msg_type` should be set.");
+                                    let resp: Result<_, ServerError> = resp.map_err(Into::into);
+                                    let status = match &resp {
+                                        Ok(_) => AccessStatus::Ok,
+                                        Err(e) => AccessStatus::Exception(e.code()),
+                                    };
                                     if req_msg_type != TMessageType::OneWay {
                                         cx.msg_type = Some(match resp {
                                             Ok(_) => TMessageType::Reply,
@@ -200,16 +274,19 @@ pub async fn serve<Svc, Req, Resp, E, D>(
                                         });
                                         let msg = ThriftMessage::mk_server_resp(
                                             &cx,
-                                            resp.map_err(|e| {
-                                                server_error_to_application_exception(e.into())
-                                            }),
+                                            resp.map_err(server_error_to_application_exception),
                                         );
                                         let mi = metainfo::METAINFO.with(|m| m.take());
-                                        let _ = send_tx.send((mi, cx, msg)).await;
+                                        let _ = send_tx.send((mi, cx, msg, status)).await;
+                                    } else {
+                                        access_log::log(&access_log, &cx, status);
                                     }
                                 })
                                 .await;
                         });
+                        // the buffered requests are decoded without returning to the scheduler,
+                        // so yield every `yield_budget` requests
+                        budget.tick().await;
                     }
                 }
             }
@@ -15,12 +15,14 @@ use tokio::{
 };
 use volo::{
     context::{Role, RpcInfo},
+    error::{tag, CodedError},
     net::Address,
 };
 
 use crate::{
     codec::{Decoder, Encoder, MakeCodec},
     context::{ClientContext, ThriftContext},
+    thrift_exception_code,
     transport::pool::{Poolable, Reservation},
     ClientError, EntryMessage, ThriftMessage,
 };
@@ -50,6 +52,8 @@ pub struct ThriftTransport<E, Resp> {
     read_error: Arc<AtomicBool>,
     // read connection is closed
     read_closed: Arc<AtomicBool>,
+    // the server asked to reset the connection
+    conn_reset: Arc<AtomicBool>,
 }
 
 impl<E, Resp> Clone for ThriftTransport<E, Resp> {
@@ -61,6 +65,7 @@ impl<E, Resp> Clone for ThriftTransport<E, Resp> {
             write_error: self.write_error.clone(),
             read_error: self.read_error.clone(),
             read_closed: self.read_closed.clone(),
+            conn_reset: self.conn_reset.clone(),
         }
     }
 }
@@ -127,9 +132,11 @@ where
                         );
                         let res = read_half.try_next::<Resp>(&mut cx, target.clone()).await;
                         if let Err(e) = res {
+                            let code = e.code();
                             tracing::error!(
-                                "[VOLO] multiplex connection read error: {}, target: {}",
+                                "[VOLO] multiplex connection read error: {}, code: {}, target: {}",
                                 e,
+                                code,
                                 target
                             );
                             let mut tx_map = inner_tx_map.lock().await;
@@ -138,8 +145,11 @@ where
                                 let _ = tx.send(Err(ClientError::Application(
                                     ApplicationException::new(
                                         ApplicationExceptionKind::UNKNOWN,
-                                        format!(
-                                            "multiplex connection error: {e}, target: {target}"
+                                        tag(
+                                            code,
+                                            format_args!(
+                                                "multiplex connection error: {e}, target: {target}"
+                                            ),
                                         ),
                                     ),
                                 )));
@@ -170,12 +180,32 @@ where
                                 let _ = tx.send(Ok(Some((mi, cx, res))));
                             });
                         } else {
+                            // The response matches no pending request, which means the server is
+                            // broken or the connection is out of sync, so the responses on it
+                            // can't be routed reliably and the connection is reset.
+                            let mut pending = tx_map.keys().copied().collect::<Vec<_>>();
+                            pending.sort_unstable();
                             tracing::error!(
                                 "[VOLO] multiplex connection receive unexpected response, seq_id: \
-                                 {}, target: {}",
+                                 {}, pending seq_ids: {:?}, target: {}",
                                 seq_id,
+                                pending,
                                 target
                             );
+                            inner_read_error.store(true, std::sync::atomic::Ordering::Relaxed);
+                            for (expected, tx) in tx_map.drain() {
+                                let _ = tx.send(Err(ClientError::Application(
+                                    ApplicationException::new(
+                                        ApplicationExceptionKind::BAD_SEQUENCE_ID,
+                                        format!(
+                                            "multiplex connection received a response with seq_id \
+                                             {seq_id} while expecting seq_id {expected}, target: \
+                                             {target}"
+                                        ),
+                                    ),
+                                )));
+                            }
+                            return;
                         }
                     }
                 })
@@ -188,8 +218,16 @@ where
             write_error,
             read_error,
             read_closed,
+            conn_reset: Arc::new(AtomicBool::new(false)),
         }
     }
+
+    /// Marks the connection reset by the server, so that it is evicted from the pool and closed
+    /// after the pending requests are done.
+    pub(crate) fn set_conn_reset(&self) {
+        self.conn_reset
+            .store(true, std::sync::atomic::Ordering::Relaxed);
+    }
 }
 
 impl<E, Resp> ThriftTransport<E, Resp>
@@ -197,29 +235,48 @@ where
     E: Encoder,
     Resp: EntryMessage,
 {
+    /// The error of the read loop, which has drained the pending requests and exited.
+    fn read_failure(&self) -> Option<ClientError> {
+        let msg = if self.read_error.load(std::sync::atomic::Ordering::Relaxed) {
+            "multiplex connection error"
+        } else if self.read_closed.load(std::sync::atomic::Ordering::Relaxed) {
+            "multiplex connection closed"
+        } else {
+            return None;
+        };
+        Some(ClientError::Application(ApplicationException::new(
+            ApplicationExceptionKind::UNKNOWN,
+            msg.to_string(),
+        )))
+    }
+
     pub async fn send<Req: EntryMessage>(
         &self,
         cx: &mut ClientContext,
-        msg: ThriftMessage<Req>,
+        mut msg: ThriftMessage<Req>,
         oneway: bool,
     ) -> Result<Option<ThriftMessage<Resp>>, ClientError> {
         // check error and closed
-        if self.read_error.load(std::sync::atomic::Ordering::Relaxed) {
-            return Err(ClientError::Application(ApplicationException::new(
-                ApplicationExceptionKind::UNKNOWN,
-                "multiplex connection error".to_string(),
-            )));
-        }
-        if self.read_closed.load(std::sync::atomic::Ordering::Relaxed) {
-            return Err(ClientError::Application(ApplicationException::new(
-                ApplicationExceptionKind::UNKNOWN,
-                "multiplex connection closed".to_string(),
-            )));
+        if let Some(err) = self.read_failure() {
+            return Err(err);
         }
         let (tx, rx) = oneshot::channel();
         let mut tx_map = self.tx_map.lock().await;
-        let seq_id = msg.meta.seq_id;
+        // The reader sets the flags under the lock of `tx_map` before draining it and exiting, so
+        // they are checked again here, otherwise the sender would never be completed.
+        if let Some(err) = self.read_failure() {
+            return Err(err);
+        }
+        let mut seq_id = msg.meta.seq_id;
         if !oneway {
+            // The responses are routed by the seq id, so a pending one must not be replaced,
+            // which may happen after the seq id wraps around, and the request takes the next
+            // free one instead.
+            while tx_map.contains_key(&seq_id) {
+                seq_id = seq_id.wrapping_add(1);
+            }
+            msg.meta.seq_id = seq_id;
+            cx.seq_id = seq_id;
             tx_map.insert(seq_id, tx);
         }
         drop(tx_map);
@@ -261,6 +318,10 @@ where
                             m.borrow_mut().extend(mi);
                         });
                         // TODO: cx extend
+                        if !new_cx.transport.should_reuse {
+                            // the server is draining, stop sending new requests on it
+                            self.set_conn_reset();
+                        }
                         if let Some(t) = new_cx.common_stats.decode_start_at() {
                             cx.common_stats.set_decode_start_at(t);
                         }
@@ -308,29 +369,15 @@ where
     ) -> Result<Option<ThriftMessage<T>>, ClientError> {
         let thrift_msg = self.decoder.decode(cx).await.map_err(|e| {
             tracing::error!(
-                "[VOLO] transport[{}] decode error: {}, target: {}",
+                "[VOLO] transport[{}] decode error: {}, code: {}, target: {}",
                 self.id,
                 e,
+                thrift_exception_code(&e),
                 target
             );
             e
         })?;
 
-        // TODO: move this to recv
-        // if let Some(ThriftMessage { meta, .. }) = &thrift_msg {
-        //     if meta.seq_id != cx.seq_id {
-        //         tracing::error!(
-        //             "[VOLO] transport[{}] seq_id not match: {} != {}",
-        //             self.id,
-        //             meta.seq_id,
-        //             cx.seq_id,
-        //         );
-        //         return Err(Error::Application(ApplicationError::new(
-        //             ApplicationErrorKind::BadSequenceId,
-        //             "seq_id not match",
-        //         )));
-        //     }
-        // };
         Ok(thrift_msg)
     }
 }
@@ -351,7 +398,12 @@ where
     ) -> Result<(), ClientError> {
         self.encoder.encode(cx, msg).await.map_err(|mut e| {
             e.append_msg(&format!(", rpcinfo: {:?}", cx.rpc_info()));
-            tracing::error!("[VOLO] transport[{}] encode error: {:?}", self.id, e);
+            tracing::error!(
+                "[VOLO] transport[{}] encode error: {:?}, code: {}",
+                self.id,
+                e,
+                thrift_exception_code(&e)
+            );
             e
         })?;
 
@@ -364,6 +416,7 @@ impl<TTEncoder, Resp> Poolable for ThriftTransport<TTEncoder, Resp> {
         !self.write_error.load(std::sync::atomic::Ordering::Relaxed)
             && !self.read_error.load(std::sync::atomic::Ordering::Relaxed)
             && !self.read_closed.load(std::sync::atomic::Ordering::Relaxed)
+            && !self.conn_reset.load(std::sync::atomic::Ordering::Relaxed)
     }
 
     fn reserve(self) -> Reservation<Self> {
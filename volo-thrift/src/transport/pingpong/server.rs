@@ -8,13 +8,27 @@ use motore::service::Service;
 use pilota::thrift::ThriftException;
 use tokio::sync::futures::Notified;
 use tracing::*;
-use volo::{net::Address, volo_unreachable};
+use volo::{
+    context::{
+        identity::{PeerIdentityExt, TlsInfo},
+        Context,
+    },
+    error::CodedError,
+    net::{listener::ListenerName, timestamp::RecvTimestamp, Address},
+    util::budget::Budget,
+    volo_unreachable,
+};
 
 use crate::{
-    codec::{Decoder, Encoder},
-    context::{ServerContext, SERVER_CONTEXT_CACHE},
+    codec::{default::CaptureFrame, Decoder, Encoder},
+    context::{ServerContext, ServerContextCache},
     protocol::TMessageType,
-    server_error_to_application_exception, thrift_exception_to_application_exception,
+    server::{
+        access_log::{self, AccessLog, AccessStatus},
+        error_log::{log_error, ErrorLog},
+    },
+    server_error_to_application_exception, thrift_exception_code,
+    thrift_exception_to_application_exception,
     tracing::SpanProvider,
     DummyMessage, EntryMessage, ServerError, ThriftMessage,
 };
@@ -27,7 +41,16 @@ pub async fn serve<Svc, Req, Resp, E, D, SP>(
     exit_mark: Arc<std::sync::atomic::AtomicBool>,
     service: &Svc,
     stat_tracer: Arc<[crate::server::TraceFn]>,
+    access_log: AccessLog,
+    error_log: ErrorLog,
     peer_addr: Option<Address>,
+    recv_timestamp: Option<RecvTimestamp>,
+    listener: Option<ListenerName>,
+    tls_info: Option<Arc<TlsInfo>>,
+    capture_frame: bool,
+    cancel_on_peer_close: bool,
+    yield_budget: usize,
+    context_cache: ServerContextCache,
     span_provider: SP,
 ) where
     Svc: Service<ServerContext, Req, Response = Resp>,
@@ -39,18 +62,31 @@ pub async fn serve<Svc, Req, Resp, E, D, SP>(
     SP: SpanProvider,
 {
     tokio::pin!(notified);
+    let mut budget = Budget::new(yield_budget);
 
     metainfo::METAINFO
         .scope(RefCell::new(MetaInfo::default()), async {
             loop {
                 // new context
-                let mut cx = SERVER_CONTEXT_CACHE.with(|cache| {
-                    let mut cache = cache.borrow_mut();
-                    cache.pop().unwrap_or_default()
-                });
+                let mut cx = context_cache.pop();
                 if let Some(peer_addr) = &peer_addr {
                     cx.rpc_info.caller_mut().set_address(peer_addr.clone());
                 }
+                if capture_frame {
+                    cx.extensions_mut().insert(CaptureFrame);
+                }
+                if let Some(recv_timestamp) = &recv_timestamp {
+                    cx.extensions_mut().insert(recv_timestamp.clone());
+                }
+                if let Some(listener) = &listener {
+                    cx.extensions_mut().insert(listener.clone());
+                }
+                if let Some(tls_info) = &tls_info {
+                    if let Some(identity) = tls_info.identity() {
+                        cx.peer_identity_mut().set_tls(identity.clone());
+                    }
+                    cx.extensions_mut().insert(tls_info.clone());
+                }
 
                 let msg = tokio::select! {
                     _ = &mut notified => {
@@ -60,7 +96,8 @@ pub async fn serve<Svc, Req, Resp, E, D, SP>(
                         );
                         return;
                     },
-                    out = decoder.decode(&mut cx) => out
+                    // the budget is refilled once the read is pending, which returns to the scheduler
+                    out = budget.reset_on_pending(decoder.decode(&mut cx)) => out
                 };
                 debug!(
                     "[VOLO] received message: {:?}, cx: {:?}, peer_addr: {:?}",
@@ -78,10 +115,34 @@ pub async fn serve<Svc, Req, Resp, E, D, SP>(
                 };
 
                 let result = async {
-                    match msg {
+                    let status = match msg {
                         Ok(Some(ThriftMessage { data: Ok(req), .. })) => {
                             cx.stats.record_process_start_at();
-                            let resp = service.call(&mut cx, req).await;
+                            // the callers of the oneway requests may disconnect right after
+                            // sending them, which is not a cancellation
+                            let resp = if cancel_on_peer_close
+                                && cx.req_msg_type != Some(TMessageType::OneWay)
+                            {
+                                let cancellation = cx.cancellation_token().clone();
+                                tokio::select! {
+                                    biased;
+                                    resp = service.call(&mut cx, req) => resp,
+                                    _ = decoder.closed() => {
+                                        cancellation.cancel();
+                                        cx.stats.record_process_end_at();
+                                        debug!(
+                                            "[VOLO] connection closed by peer in the middle of \
+                                             the request, cancelled, cx: {:?}, peer_addr: {:?}",
+                                            cx, peer_addr
+                                        );
+                                        stat_tracer.iter().for_each(|f| f(&cx));
+                                        access_log::log(&access_log, &cx, AccessStatus::Cancelled);
+                                        return Err(());
+                                    }
+                                }
+                            } else {
+                                service.call(&mut cx, req).await
+                            };
                             cx.stats.record_process_end_at();
 
                             if exit_mark.load(Ordering::Relaxed) {
@@ -90,6 +151,11 @@ pub async fn serve<Svc, Req, Resp, E, D, SP>(
 
                             let req_msg_type =
                                 cx.req_msg_type.expect("`req_msg_type` should be set.");
+                            let resp: Result<_, ServerError> = resp.map_err(Into::into);
+                            let status = match &resp {
+                                Ok(_) => AccessStatus::Ok,
+                                Err(e) => AccessStatus::Exception(e.code()),
+                            };
 
                             if req_msg_type != TMessageType::OneWay {
                                 cx.msg_type = Some(match resp {
@@ -98,9 +164,7 @@ pub async fn serve<Svc, Req, Resp, E, D, SP>(
                                 });
                                 let msg = ThriftMessage::mk_server_resp(
                                     &cx,
-                                    resp.map_err(|e| {
-                                        server_error_to_application_exception(e.into())
-                                    }),
+                                    resp.map_err(server_error_to_application_exception),
                                 );
                                 if let Err(e) = async {
                                     let result = encoder.encode(&mut cx, msg).await;
@@ -110,15 +174,27 @@ pub async fn serve<Svc, Req, Resp, E, D, SP>(
                                 .instrument(span_provider.on_encode(tracing_cx))
                                 .await
                                 {
-                                    error!(
-                                        "[VOLO] server send response error: {:?}, cx: {:?}, \
-                                         peer_addr: {:?}",
-                                        e, cx, peer_addr
+                                    let code = thrift_exception_code(&e);
+                                    log_error!(
+                                        error_log,
+                                        &e,
+                                        "[VOLO] server send response error: {:?}, code: {}, cx: \
+                                         {:?}, peer_addr: {:?}",
+                                        e,
+                                        code,
+                                        cx,
+                                        peer_addr
                                     );
                                     stat_tracer.iter().for_each(|f| f(&cx));
+                                    access_log::log(
+                                        &access_log,
+                                        &cx,
+                                        AccessStatus::SendError(code),
+                                    );
                                     return Err(());
                                 }
                             }
+                            status
                         }
                         Ok(Some(ThriftMessage { data: Err(_), .. })) => {
                             volo_unreachable!();
@@ -132,10 +208,16 @@ pub async fn serve<Svc, Req, Resp, E, D, SP>(
                             return Err(());
                         }
                         Err(e) => {
-                            error!(
-                                "[VOLO] pingpong server decode error: {:?}, cx: {:?}, peer_addr: \
-                                 {:?}",
-                                e, cx, peer_addr
+                            let code = thrift_exception_code(&e);
+                            log_error!(
+                                error_log,
+                                &e,
+                                "[VOLO] pingpong server decode error: {:?}, code: {}, cx: {:?}, \
+                                 peer_addr: {:?}",
+                                e,
+                                code,
+                                cx,
+                                peer_addr
                             );
                             cx.msg_type = Some(TMessageType::Exception);
                             if !matches!(e, ThriftException::Transport(_)) {
@@ -146,31 +228,32 @@ pub async fn serve<Svc, Req, Resp, E, D, SP>(
                                     ),
                                 );
                                 if let Err(e) = encoder.encode(&mut cx, msg).await {
-                                    error!(
-                                        "[VOLO] server send error error: {:?}, cx: {:?}, \
-                                         peer_addr: {:?}",
-                                        e, cx, peer_addr
+                                    log_error!(
+                                        error_log,
+                                        &e,
+                                        "[VOLO] server send error error: {:?}, code: {}, cx: \
+                                         {:?}, peer_addr: {:?}",
+                                        e,
+                                        thrift_exception_code(&e),
+                                        cx,
+                                        peer_addr
                                     );
                                 }
                             }
                             stat_tracer.iter().for_each(|f| f(&cx));
+                            access_log::log(&access_log, &cx, AccessStatus::DecodeError(code));
                             return Err(());
                         }
-                    }
+                    };
                     stat_tracer.iter().for_each(|f| f(&cx));
+                    access_log::log(&access_log, &cx, status);
 
                     metainfo::METAINFO.with(|mi| {
                         mi.borrow_mut().clear();
                     });
 
                     span_provider.leave_serve(&cx);
-                    SERVER_CONTEXT_CACHE.with(|cache| {
-                        let mut cache = cache.borrow_mut();
-                        if cache.len() < cache.capacity() {
-                            cx.reset(Default::default());
-                            cache.push(cx);
-                        }
-                    });
+                    context_cache.push(cx);
                     Ok(())
                 }
                 .instrument(span_provider.on_serve(tracing_cx))
@@ -178,6 +261,9 @@ pub async fn serve<Svc, Req, Resp, E, D, SP>(
                 if result.is_err() {
                     break;
                 }
+                // the pipelined requests are decoded from the buffer without returning to the
+                // scheduler, so yield every `yield_budget` requests
+                budget.tick().await;
             }
         })
         .await;
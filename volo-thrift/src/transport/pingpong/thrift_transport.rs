@@ -7,6 +7,7 @@ use tokio::io::{AsyncRead, AsyncWrite};
 use crate::{
     codec::{Decoder, Encoder, MakeCodec},
     context::{ClientContext, ThriftContext},
+    thrift_exception_code,
     transport::pool::Poolable,
     ClientError, EntryMessage, ThriftMessage,
 };
@@ -93,7 +94,12 @@ where
         let thrift_msg = self.decoder.decode(cx).await.map_err(|e| {
             let mut e = e;
             e.append_msg(&format!(", cx: {:?}", cx));
-            tracing::error!("[VOLO] transport[{}] decode error: {}", self.id, e);
+            tracing::error!(
+                "[VOLO] transport[{}] decode error: {}, code: {}",
+                self.id,
+                e,
+                thrift_exception_code(&e)
+            );
             e
         })?;
 
@@ -106,9 +112,15 @@ where
                     cx.seq_id,
                     cx,
                 );
+                // the connection is out of sync, e.g. a late response of a previous request, so
+                // the following responses can't be trusted either
+                self.reusable = false;
                 return Err(ClientError::Application(ApplicationException::new(
                     ApplicationExceptionKind::BAD_SEQUENCE_ID,
-                    format!("seq_id not match, cx: {:?}", cx),
+                    format!(
+                        "received a response with seq_id {} while expecting seq_id {}",
+                        meta.seq_id, cx.seq_id
+                    ),
                 )));
             }
         };
@@ -133,7 +145,12 @@ where
     ) -> Result<(), ClientError> {
         self.encoder.encode(cx, msg).await.map_err(|mut e| {
             e.append_msg(&format!(", rpcinfo: {:?}", cx.rpc_info()));
-            tracing::error!("[VOLO] transport[{}] encode error: {:?}", self.id, e);
+            tracing::error!(
+                "[VOLO] transport[{}] encode error: {:?}, code: {}",
+                self.id,
+                e,
+                thrift_exception_code(&e)
+            );
             e
         })?;
 
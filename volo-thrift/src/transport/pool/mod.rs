@@ -11,7 +11,10 @@ use std::{
     hash::Hash,
     ops::{Deref, DerefMut},
     pin::Pin,
-    sync::{Arc, Mutex, Weak},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, Weak,
+    },
     task::{Context, Poll},
 };
 
@@ -80,20 +83,41 @@ pub enum Reservation<T> {
 pub struct Pool<K: Key, T: Poolable> {
     // share between threads
     inner: Arc<Mutex<Inner<K, T>>>,
+    stats: PoolStats,
 }
 
 impl<K: Key, T: Poolable> Clone for Pool<K, T> {
     fn clone(&self) -> Self {
         Pool {
             inner: self.inner.clone(),
+            stats: self.stats.clone(),
         }
     }
 }
 
+/// The config of the connection pool of a client, which keeps the connections of each endpoint.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let config = pool::Config::default()
+///     .max_idle_per_key(64)
+///     .idle_timeout(Duration::from_secs(30))
+///     .max_lifetime(Some(Duration::from_secs(600)));
+/// let stats = config.stats();
+/// let client = ClientBuilder::new("hello")
+///     .pool_config(config)
+///     .build()?;
+///
+/// // e.g. in the metrics reporter
+/// gauge!("thrift_pool_idle").set(stats.idle() as f64);
+/// ```
 #[derive(Clone, Debug)]
 pub struct Config {
     max_idle_per_key: usize,
     timeout: Duration,
+    max_lifetime: Option<Duration>,
+    stats: PoolStats,
 }
 
 impl Default for Config {
@@ -101,6 +125,8 @@ impl Default for Config {
         Config {
             max_idle_per_key: 10240,
             timeout: Duration::from_secs(15),
+            max_lifetime: None,
+            stats: PoolStats::default(),
         }
     }
 }
@@ -110,18 +136,101 @@ impl Config {
         Config {
             max_idle_per_key,
             timeout,
+            ..Default::default()
         }
     }
 
+    /// Sets the max number of the idle connections of each endpoint, the connections put back
+    /// into the pool when there are enough idle connections are closed.
+    ///
+    /// Default is 10240.
     pub fn max_idle_per_key(mut self, max_idle_per_key: usize) -> Self {
         self.max_idle_per_key = max_idle_per_key;
         self
     }
 
-    pub fn timeout(mut self, timeout: Duration) -> Self {
+    /// Whether no idle connection is kept, i.e. each connection is closed after its call.
+    pub(crate) fn keeps_no_idle(&self) -> bool {
+        self.max_idle_per_key == 0
+    }
+
+    #[deprecated(note = "use `idle_timeout` instead")]
+    pub fn timeout(self, timeout: Duration) -> Self {
+        self.idle_timeout(timeout)
+    }
+
+    /// Sets how long a connection can be idle in the pool before it is closed.
+    ///
+    /// Default is 15 seconds.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
         self.timeout = timeout;
         self
     }
+
+    /// Sets how long a connection can be used since it is established, after which it is not
+    /// reused any more and closed after the requests on it are done.
+    ///
+    /// This spreads the connections over the new instances of the endpoint after scaling, and
+    /// avoids all the connections being re-established at the same time. Default is `None`,
+    /// which means the connections are kept until they are idle for too long.
+    pub fn max_lifetime(mut self, max_lifetime: Option<Duration>) -> Self {
+        self.max_lifetime = max_lifetime;
+        self
+    }
+
+    /// Returns the stats of the pools created by this config, which are shared by all the
+    /// clients using this config.
+    pub fn stats(&self) -> PoolStats {
+        self.stats.clone()
+    }
+}
+
+/// The numbers of the connections of a connection pool, which can be reported as metrics.
+#[derive(Clone, Debug, Default)]
+pub struct PoolStats {
+    inner: Arc<StatsInner>,
+}
+
+#[derive(Debug, Default)]
+struct StatsInner {
+    active: AtomicUsize,
+    idle: AtomicUsize,
+}
+
+impl PoolStats {
+    /// The number of the connections taken from the pool and in use.
+    ///
+    /// A multiplex connection is counted once for each request using it.
+    pub fn active(&self) -> usize {
+        self.inner.active.load(Ordering::Relaxed)
+    }
+
+    /// The number of the idle connections kept in the pool.
+    pub fn idle(&self) -> usize {
+        self.inner.idle.load(Ordering::Relaxed)
+    }
+
+    fn add_idle(&self, n: usize) {
+        self.inner.idle.fetch_add(n, Ordering::Relaxed);
+    }
+
+    fn sub_idle(&self, n: usize) {
+        self.inner.idle.fetch_sub(n, Ordering::Relaxed);
+    }
+
+    fn checkout(&self) -> ActiveGuard {
+        self.inner.active.fetch_add(1, Ordering::Relaxed);
+        ActiveGuard(self.clone())
+    }
+}
+
+/// Counts a connection in use until it is dropped.
+struct ActiveGuard(PoolStats);
+
+impl Drop for ActiveGuard {
+    fn drop(&mut self) {
+        self.0.inner.active.fetch_sub(1, Ordering::Relaxed);
+    }
 }
 
 // This is because `Weak::new()` *allocates* space for `T`, even if it
@@ -162,11 +271,13 @@ impl Expiration {
 struct IdlePopper<'a, K: Key, T> {
     key: &'a K,
     list: &'a mut VecDeque<Idle<T>>,
+    stats: &'a PoolStats,
 }
 
 impl<'a, K: Key, T: Poolable + 'a> IdlePopper<'a, K, T> {
-    fn pop(self, expiration: &Expiration) -> Option<Idle<T>> {
+    fn pop(self, expiration: &Expiration, lifetime: &Expiration) -> Option<Idle<T>> {
         while let Some(entry) = self.list.pop_front() {
+            self.stats.sub_idle(1);
             // If the connection has been closed, or is older than our idle
             // timeout, simply drop it and keep looking...
             if !entry.inner.reusable() {
@@ -183,13 +294,22 @@ impl<'a, K: Key, T: Poolable + 'a> IdlePopper<'a, K, T> {
                 tracing::trace!("[VOLO] removing expired connection for {:?}", self.key);
                 continue;
             }
+            if lifetime.expires(entry.created_at) {
+                tracing::trace!(
+                    "[VOLO] removing connection exceeding max lifetime for {:?}",
+                    self.key
+                );
+                continue;
+            }
 
             let value = match entry.inner.reserve() {
                 Reservation::Shared(to_reinsert, to_return) => {
                     self.list.push_back(Idle {
                         idle_at: Instant::now(),
+                        created_at: entry.created_at,
                         inner: to_reinsert,
                     });
+                    self.stats.add_idle(1);
                     to_return
                 }
                 Reservation::Unique(unique) => unique,
@@ -197,6 +317,7 @@ impl<'a, K: Key, T: Poolable + 'a> IdlePopper<'a, K, T> {
 
             return Some(Idle {
                 idle_at: entry.idle_at,
+                created_at: entry.created_at,
                 inner: value,
             });
         }
@@ -215,17 +336,31 @@ impl<K: Key, T: Poolable + Send + 'static> Pool<K, T> {
             idle: HashMap::new(),
             waiters: HashMap::new(),
             timeout: cfg.timeout,
+            max_lifetime: cfg.max_lifetime,
             max_idle_per_key: cfg.max_idle_per_key,
+            stats: cfg.stats.clone(),
             _pool_drop_rx: rx,
         }));
 
+        let check_interval = match cfg.max_lifetime {
+            Some(max_lifetime) => cfg.timeout.min(max_lifetime),
+            None => cfg.timeout,
+        };
         let idle_task = IdleTask {
-            interval: interval(cfg.timeout),
+            interval: interval(check_interval),
             inner: Arc::downgrade(&inner),
             pool_drop_tx: tx,
         };
         tokio::spawn(idle_task);
-        Pool { inner }
+        Pool {
+            inner,
+            stats: cfg.stats,
+        }
+    }
+
+    /// Returns the stats of the pool.
+    pub fn stats(&self) -> &PoolStats {
+        &self.stats
     }
 
     /// Ensure that there is only ever 1 connecting task for Multiplex
@@ -270,16 +405,22 @@ impl<K: Key, T: Poolable + Send + 'static> Pool<K, T> {
             let mut inner = self.inner.lock().volo_unwrap();
             // 1. check the idle and opened connections
             let expiration = Expiration::new(Some(inner.timeout));
+            let lifetime = Expiration::new(inner.max_lifetime);
+            let inner = &mut *inner;
             let entry = inner.idle.get_mut(&key).and_then(|list| {
                 tracing::trace!("[VOLO] take? {:?}: expiration = {:?}", key, expiration.0);
                 {
-                    let popper = IdlePopper { key: &key, list };
-                    popper.pop(&expiration)
+                    let popper = IdlePopper {
+                        key: &key,
+                        list,
+                        stats: &inner.stats,
+                    };
+                    popper.pop(&expiration, &lifetime)
                 }
             });
 
             if let Some(t) = entry {
-                return Ok(self.reuse(&key, t.inner));
+                return Ok(self.reuse(&key, t.inner, t.created_at));
             }
             // 2. no valid idle then add caller into waiters and make connection
             let waiters = if let Some(waiter) = inner.waiters.get_mut(&key) {
@@ -320,14 +461,14 @@ impl<K: Key, T: Poolable + Send + 'static> Pool<K, T> {
 
         // waiter or make transport finished
         match future::select(rx, started::lazy(connector)).await {
-            Either::Left((Ok(v), fut)) => {
+            Either::Left((Ok((v, created_at)), fut)) => {
                 // check the make transport future has started
                 if fut.started() {
                     // complete the make transport and put into pool
                     tokio::spawn(fut);
                 }
                 // get connection from pool
-                Ok(self.reuse(&key, v))
+                Ok(self.reuse(&key, v, created_at))
             }
             Either::Right((Ok(v), _)) => {
                 tracing::debug!("[VOLO] get connection from pool for {:?}", key);
@@ -353,11 +494,12 @@ impl<K: Key, T: Poolable + Send + 'static> Pool<K, T> {
     }
 
     fn pooled(&self, mut connecting: Connecting<K, T>, value: T) -> Pooled<K, T> {
+        let created_at = Instant::now();
         let (value, pool_ref) = {
             match value.reserve() {
                 Reservation::Shared(to_insert, to_return) => {
                     let mut inner = self.inner.lock().unwrap();
-                    inner.put(connecting.key.clone(), to_insert);
+                    inner.put(connecting.key.clone(), to_insert, created_at);
                     inner.connected(&connecting.key);
                     connecting.pool = WeakOpt::none();
                     // Shared reservations don't need a reference to the pool,
@@ -372,10 +514,16 @@ impl<K: Key, T: Poolable + Send + 'static> Pool<K, T> {
                 }
             }
         };
-        Pooled::new(connecting.key.clone(), value, WeakOpt(pool_ref))
+        Pooled::new(
+            connecting.key.clone(),
+            value,
+            created_at,
+            self.stats.checkout(),
+            WeakOpt(pool_ref),
+        )
     }
 
-    fn reuse(&self, key: &K, value: T) -> Pooled<K, T> {
+    fn reuse(&self, key: &K, value: T, created_at: Instant) -> Pooled<K, T> {
         tracing::debug!("[VOLO] reuse idle connection for {:?}", key);
         // TODO: unhack this
         // In Pool::pooled(), which is used for inserting brand new connections,
@@ -388,7 +536,13 @@ impl<K: Key, T: Poolable + Send + 'static> Pool<K, T> {
         if !value.can_share() {
             pool_ref = Some(Arc::downgrade(&self.inner));
         }
-        Pooled::new(key.clone(), value, WeakOpt(pool_ref))
+        Pooled::new(
+            key.clone(),
+            value,
+            created_at,
+            self.stats.checkout(),
+            WeakOpt(pool_ref),
+        )
     }
 }
 
@@ -420,6 +574,7 @@ impl<K: Key, T: Poolable> Drop for Connecting<K, T> {
 struct Idle<T> {
     inner: T,
     idle_at: Instant,
+    created_at: Instant,
 }
 
 #[pin_project]
@@ -427,15 +582,25 @@ pub struct Pooled<K: Key, T: Poolable> {
     key: Option<K>,
     #[pin]
     t: Option<T>,
+    created_at: Instant,
+    _active: ActiveGuard,
     // shared transport no need pool ref
     pool: WeakOpt<Mutex<Inner<K, T>>>,
 }
 
 impl<K: Key, T: Poolable> Pooled<K, T> {
-    fn new(key: K, t: T, pool: WeakOpt<Mutex<Inner<K, T>>>) -> Self {
+    fn new(
+        key: K,
+        t: T,
+        created_at: Instant,
+        active: ActiveGuard,
+        pool: WeakOpt<Mutex<Inner<K, T>>>,
+    ) -> Self {
         Pooled {
             key: Some(key),
             t: Some(t),
+            created_at,
+            _active: active,
             pool,
         }
     }
@@ -452,7 +617,14 @@ impl<K: Key, T: Poolable> Pooled<K, T> {
         if let WeakOpt(Some(pool)) = self.pool {
             if let Some(pool) = pool.upgrade() {
                 if let Ok(mut pool) = pool.lock() {
-                    pool.put(key, inner);
+                    if Expiration::new(pool.max_lifetime).expires(self.created_at) {
+                        tracing::trace!(
+                            "[VOLO] closing connection exceeding max lifetime for {:?}",
+                            key
+                        );
+                        return;
+                    }
+                    pool.put(key, inner, self.created_at);
                 }
             }
         }
@@ -527,11 +699,14 @@ struct Inner<K: Key, T: Poolable> {
     // idle queue
     idle: HashMap<K, VecDeque<Idle<T>>>,
     // waiters wait for idle transport
-    waiters: HashMap<K, WaiterList<T>>,
+    waiters: HashMap<K, WaiterList<(T, Instant)>>,
     // idle timeout and check interval
     timeout: Duration,
+    // max lifetime since the connection is established
+    max_lifetime: Option<Duration>,
     // idle count per key
     max_idle_per_key: usize,
+    stats: PoolStats,
     // when rx dropped, then tx poll_closed will return Poll::Ready(())
     // then idle task exist
     _pool_drop_rx: oneshot::Receiver<()>,
@@ -541,8 +716,11 @@ impl<K: Key, T: Poolable> Inner<K, T> {
     // clear expired idle
     fn clear_expired(&mut self) {
         let timeout = self.timeout;
+        let lifetime = Expiration::new(self.max_lifetime);
         let now = Instant::now();
+        let stats = &self.stats;
         self.idle.retain(|key, values| {
+            let len = values.len();
             values.retain(|entry| {
                 // TODO: check has_idle && remove the (idle, waiters) key
                 if !entry.inner.reusable() {
@@ -553,16 +731,31 @@ impl<K: Key, T: Poolable> Inner<K, T> {
                     tracing::trace!("[VOLO] idle interval evicting expired for {:?}", key);
                     return false;
                 }
+                if lifetime.expires(entry.created_at) {
+                    tracing::trace!(
+                        "[VOLO] idle interval evicting connection exceeding max lifetime for {:?}",
+                        key
+                    );
+                    return false;
+                }
 
                 true
             });
+            stats.sub_idle(len - values.len());
             !values.is_empty()
         });
     }
 }
 
+impl<K: Key, T: Poolable> Drop for Inner<K, T> {
+    fn drop(&mut self) {
+        self.stats
+            .sub_idle(self.idle.values().map(VecDeque::len).sum());
+    }
+}
+
 impl<K: Key, T: Poolable> Inner<K, T> {
-    fn put(&mut self, key: K, t: T) {
+    fn put(&mut self, key: K, t: T, created_at: Instant) {
         // check the wait queue
         let mut value = Some(t);
         if let Some(waiters) = self.waiters.get_mut(&key) {
@@ -578,7 +771,7 @@ impl<K: Key, T: Poolable> Inner<K, T> {
                         }
                         Reservation::Unique(unique) => unique,
                     };
-                    match waiter.send(t) {
+                    match waiter.send((t, created_at)) {
                         Ok(()) => {
                             tracing::trace!("[VOLO] [pool put]: found waiter for {:?}", key);
                             if value.is_none() {
@@ -586,7 +779,7 @@ impl<K: Key, T: Poolable> Inner<K, T> {
                                 break;
                             }
                         }
-                        Err(t) => {
+                        Err((t, _)) => {
                             value = Some(t);
                         }
                     }
@@ -614,7 +807,9 @@ impl<K: Key, T: Poolable> Inner<K, T> {
                 idle.push_back(Idle {
                     inner: t,
                     idle_at: Instant::now(),
+                    created_at,
                 });
+                self.stats.add_idle(1);
             }
         }
     }
@@ -671,3 +866,103 @@ impl<K: Key, T: Poolable> Future for IdleTask<K, T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+
+    use super::*;
+
+    #[derive(Clone)]
+    struct Conn {
+        id: usize,
+        closed: Arc<AtomicBool>,
+    }
+
+    impl Poolable for Conn {
+        fn reusable(&self) -> bool {
+            !self.closed.load(Ordering::Relaxed)
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct MakeConn {
+        made: Arc<AtomicUsize>,
+    }
+
+    impl UnaryService<&'static str> for MakeConn {
+        type Response = Conn;
+        type Error = crate::ClientError;
+
+        async fn call(&self, _key: &'static str) -> Result<Self::Response, Self::Error> {
+            Ok(Conn {
+                id: self.made.fetch_add(1, Ordering::Relaxed),
+                closed: Arc::new(AtomicBool::new(false)),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn stats_and_reuse() {
+        let config = Config::default().max_idle_per_key(1);
+        let stats = config.stats();
+        let pool = Pool::<&'static str, Conn>::new(Some(config));
+        let mk = MakeConn::default();
+
+        let a = pool.get("a", Ver::PingPong, mk.clone()).await.unwrap();
+        let b = pool.get("a", Ver::PingPong, mk.clone()).await.unwrap();
+        assert_eq!((a.id, b.id), (0, 1));
+        assert_eq!((stats.active(), stats.idle()), (2, 0));
+
+        a.reuse();
+        // beyond `max_idle_per_key`
+        b.reuse();
+        assert_eq!((stats.active(), stats.idle()), (0, 1));
+
+        let a = pool.get("a", Ver::PingPong, mk.clone()).await.unwrap();
+        assert_eq!(a.id, 0);
+        assert_eq!((stats.active(), stats.idle()), (1, 0));
+
+        // a connection closed, e.g. reset by the server, is never put back
+        a.closed.store(true, Ordering::Relaxed);
+        a.reuse();
+        assert_eq!((stats.active(), stats.idle()), (0, 0));
+        assert_eq!(mk.made.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn max_lifetime() {
+        let config = Config::default().max_lifetime(Some(Duration::from_millis(50)));
+        let stats = config.stats();
+        let pool = Pool::<&'static str, Conn>::new(Some(config));
+        let mk = MakeConn::default();
+
+        pool.get("a", Ver::PingPong, mk.clone())
+            .await
+            .unwrap()
+            .reuse();
+        assert_eq!(stats.idle(), 1);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        // evicted when taken
+        let a = pool.get("a", Ver::PingPong, mk.clone()).await.unwrap();
+        assert_eq!(a.id, 1);
+        assert_eq!(stats.idle(), 0);
+
+        // not put back after the lifetime
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        a.reuse();
+        assert_eq!((stats.active(), stats.idle()), (0, 0));
+
+        // evicted by the interval task
+        pool.get("a", Ver::PingPong, mk.clone())
+            .await
+            .unwrap()
+            .reuse();
+        assert_eq!(stats.idle(), 1);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(stats.idle(), 0);
+
+        drop(pool);
+        assert_eq!(stats.idle(), 0);
+    }
+}
@@ -0,0 +1,58 @@
+use std::{hint::black_box, sync::Arc, thread, time::Instant};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use volo::metrics::{Registry, DEFAULT_BUCKETS};
+
+fn hot_path(c: &mut Criterion) {
+    let registry = Registry::new();
+    let counter = registry
+        .counter_vec("requests_total", "", &["method"])
+        .with(&["hello"]);
+    let histogram = registry
+        .histogram_vec("latency_seconds", "", &["method"], DEFAULT_BUCKETS)
+        .with(&["hello"]);
+
+    c.bench_function("counter_inc", |b| b.iter(|| counter.inc()));
+    c.bench_function("histogram_observe", |b| {
+        b.iter(|| histogram.observe(black_box(0.042)))
+    });
+
+    // all the threads incrementing the same counter
+    let threads = thread::available_parallelism().map_or(4, |n| n.get());
+    c.bench_function("counter_inc_contended", |b| {
+        b.iter_custom(|iters| {
+            let start = Instant::now();
+            thread::scope(|s| {
+                for _ in 0..threads {
+                    let counter = Arc::clone(&counter);
+                    s.spawn(move || {
+                        for _ in 0..iters {
+                            counter.inc();
+                        }
+                    });
+                }
+            });
+            start.elapsed() / threads as u32
+        })
+    });
+}
+
+fn render(c: &mut Criterion) {
+    let registry = Registry::new();
+    let requests = registry.counter_vec("requests_total", "", &["method", "caller"]);
+    for method in 0..32 {
+        for caller in 0..8 {
+            requests
+                .with(&[
+                    format!("method-{method}").as_str(),
+                    format!("caller-{caller}").as_str(),
+                ])
+                .inc();
+        }
+    }
+
+    c.bench_function("render", |b| b.iter(|| black_box(registry.render())));
+}
+
+criterion_group!(benches, hot_path, render);
+criterion_main!(benches);
@@ -0,0 +1,112 @@
+use std::sync::Arc;
+
+use motore::{layer::Layer, service::Service};
+
+use super::{Acl, AclRejection};
+use crate::context::{identity::PeerIdentityExt, Context};
+
+/// A layer that rejects the requests denied by the [`Acl`].
+///
+/// The identity of the caller is read from the [`PeerIdentity`] of the context, so this layer
+/// should be put inside the auth layers setting the token principal. The [`Acl`] is shared by all
+/// the clones of the service created by this layer.
+///
+/// [`PeerIdentity`]: crate::context::identity::PeerIdentity
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use volo::acl::{Acl, AclLayer};
+/// use volo_grpc::layer::acl::GrpcAclRejection;
+///
+/// let acl = Acl::new()
+///     .allow("admin")
+///     .allow_method("/hello.Greeter/SayHello", "greeter");
+///
+/// Server::new()
+///     .tls_config(tls_config)
+///     .layer_front(AclLayer::new(acl, GrpcAclRejection))
+///     .add_service(ServiceBuilder::new(GreeterServer::new(S)).build())
+///     .run(addr)
+///     .await
+///     .unwrap();
+/// ```
+#[derive(Clone)]
+pub struct AclLayer<R> {
+    acl: Arc<Acl>,
+    rejection: R,
+}
+
+impl<R> AclLayer<R> {
+    pub fn new(acl: impl Into<Arc<Acl>>, rejection: R) -> Self {
+        Self {
+            acl: acl.into(),
+            rejection,
+        }
+    }
+}
+
+impl<S, R> Layer<S> for AclLayer<R> {
+    type Service = AclService<S, R>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        AclService {
+            inner,
+            acl: self.acl,
+            rejection: Arc::new(self.rejection),
+        }
+    }
+}
+
+/// The service created by [`AclLayer`].
+pub struct AclService<S, R> {
+    inner: S,
+    acl: Arc<Acl>,
+    rejection: Arc<R>,
+}
+
+impl<S: Clone, R> Clone for AclService<S, R> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            acl: self.acl.clone(),
+            rejection: self.rejection.clone(),
+        }
+    }
+}
+
+impl<Cx, Req, S, R> Service<Cx, Req> for AclService<S, R>
+where
+    Cx: Context + Send + 'static,
+    Req: Send + 'static,
+    S: Service<Cx, Req> + Send + Sync + 'static,
+    R: AclRejection<Cx, S::Error> + Send + Sync,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call<'s, 'cx>(
+        &'s self,
+        cx: &'cx mut Cx,
+        req: Req,
+    ) -> Result<Self::Response, Self::Error> {
+        let identity = cx.peer_identity();
+        if let Some(identity) = identity.filter(|identity| !identity.is_consistent()) {
+            tracing::warn!(
+                "[VOLO] declared caller {:?} differs from the authenticated identity: {}",
+                identity.declared_name(),
+                identity.audit()
+            );
+        }
+        if let Err(denied) = self.acl.check(cx.rpc_info().method(), identity) {
+            tracing::debug!(
+                "[VOLO] request denied by acl: {denied}, identity: {}",
+                identity
+                    .map(|identity| identity.audit().to_string())
+                    .unwrap_or_default()
+            );
+            return Err(self.rejection.reject(cx, denied));
+        }
+        self.inner.call(cx, req).await
+    }
+}
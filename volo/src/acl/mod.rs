@@ -0,0 +1,171 @@
+//! Access control of the servers by the identity of the callers.
+//!
+//! The [`Acl`] decides whether a caller can call a method by the service name of its
+//! [`PeerIdentity`], which is proved by mTLS or a token, and is the same for all the protocols.
+//! The service name declared by the caller in the TTHeader or the gRPC metadata is not trusted
+//! unless [`Acl::allow_unauthenticated`] is set.
+//!
+//! The [`AclLayer`] rejects the requests by the protocol specific [`AclRejection`], which are
+//! provided in `volo-thrift` and `volo-grpc`.
+
+mod layer;
+
+use std::collections::{HashMap, HashSet};
+
+use faststr::FastStr;
+
+pub use self::layer::{AclLayer, AclService};
+use crate::context::identity::PeerIdentity;
+
+/// The error of a request denied by the [`Acl`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum AclDenied {
+    /// The caller is not authenticated by mTLS or a token.
+    #[error("peer is not authenticated")]
+    Unauthenticated,
+    /// The caller is not allowed to call the method, where `service` is the service name of the
+    /// caller, if there is one.
+    #[error("peer {} is not allowed to call {method}", .service.as_deref().unwrap_or("<unknown>"))]
+    Forbidden {
+        service: Option<FastStr>,
+        method: FastStr,
+    },
+}
+
+/// The rules of which callers can call which methods.
+///
+/// All the requests are denied by an empty [`Acl`].
+#[derive(Debug, Clone, Default)]
+pub struct Acl {
+    services: HashSet<FastStr>,
+    methods: HashMap<FastStr, HashSet<FastStr>>,
+    allow_unauthenticated: bool,
+}
+
+impl Acl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allows the service to call all the methods.
+    pub fn allow(mut self, service: impl Into<FastStr>) -> Self {
+        self.services.insert(service.into());
+        self
+    }
+
+    /// Allows the service to call the method.
+    pub fn allow_method(mut self, method: impl Into<FastStr>, service: impl Into<FastStr>) -> Self {
+        self.methods
+            .entry(method.into())
+            .or_default()
+            .insert(service.into());
+        self
+    }
+
+    /// Trusts the service names declared by the unauthenticated callers, which should only be
+    /// used when migrating to mTLS or tokens.
+    ///
+    /// Default is `false`.
+    pub fn allow_unauthenticated(mut self, allow: bool) -> Self {
+        self.allow_unauthenticated = allow;
+        self
+    }
+
+    /// Checks whether the caller with the identity can call the method.
+    pub fn check(&self, method: &str, identity: Option<&PeerIdentity>) -> Result<(), AclDenied> {
+        let authenticated = identity.is_some_and(PeerIdentity::is_authenticated);
+        if !authenticated && !self.allow_unauthenticated {
+            return Err(AclDenied::Unauthenticated);
+        }
+        let service = identity.and_then(PeerIdentity::service_name);
+        let allowed = service.as_ref().is_some_and(|service| {
+            self.services.contains(service)
+                || self
+                    .methods
+                    .get(method)
+                    .is_some_and(|services| services.contains(service))
+        });
+        if allowed {
+            Ok(())
+        } else {
+            Err(AclDenied::Forbidden {
+                service,
+                method: FastStr::new(method),
+            })
+        }
+    }
+}
+
+/// The protocol specific part of access control, which makes the error of a denied request.
+pub trait AclRejection<Cx, E> {
+    fn reject(&self, cx: &mut Cx, denied: AclDenied) -> E;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::identity::{TlsIdentity, TokenPrincipal};
+
+    fn tls(service: &str) -> PeerIdentity {
+        let mut identity = PeerIdentity::new();
+        identity.set_tls(TlsIdentity {
+            dns_names: vec![FastStr::new(service)],
+            ..Default::default()
+        });
+        identity
+    }
+
+    fn declared(service: &str) -> PeerIdentity {
+        let mut identity = PeerIdentity::new();
+        identity.set_declared(FastStr::new(service));
+        identity
+    }
+
+    #[test]
+    fn check() {
+        let acl = Acl::new()
+            .allow("admin")
+            .allow_method("/echo.Echo/Echo", "echo");
+
+        assert_eq!(
+            acl.check("/echo.Echo/Echo", None),
+            Err(AclDenied::Unauthenticated)
+        );
+        assert_eq!(
+            acl.check("/echo.Echo/Echo", Some(&declared("admin"))),
+            Err(AclDenied::Unauthenticated)
+        );
+        assert!(acl.check("/echo.Echo/Echo", Some(&tls("echo"))).is_ok());
+        assert!(acl.check("/echo.Echo/Purge", Some(&tls("admin"))).is_ok());
+        assert_eq!(
+            acl.check("/echo.Echo/Purge", Some(&tls("echo"))),
+            Err(AclDenied::Forbidden {
+                service: Some("echo".into()),
+                method: "/echo.Echo/Purge".into(),
+            })
+        );
+
+        // the declared name can not override the authenticated one
+        let mut identity = tls("echo");
+        identity.set_declared("admin".into());
+        assert!(acl.check("/echo.Echo/Purge", Some(&identity)).is_err());
+
+        // an authenticated user without a service name
+        let mut identity = PeerIdentity::new();
+        identity.set_token(TokenPrincipal::new("user-42"));
+        assert_eq!(
+            acl.check("/echo.Echo/Echo", Some(&identity))
+                .unwrap_err()
+                .to_string(),
+            "peer <unknown> is not allowed to call /echo.Echo/Echo"
+        );
+    }
+
+    #[test]
+    fn unauthenticated() {
+        let acl = Acl::new().allow("echo").allow_unauthenticated(true);
+        assert!(acl.check("Echo", Some(&declared("echo"))).is_ok());
+        assert!(acl.check("Echo", Some(&declared("admin"))).is_err());
+        assert!(acl.check("Echo", None).is_err());
+    }
+}
@@ -127,6 +127,11 @@ pub fn init_panic_hook() {
     });
 }
 
+/// Takes the information of the last panic of this thread captured by the panic hook.
+pub(crate) fn take_panic_info() -> Option<PanicInfo> {
+    PANIC_INFO.with(|info| info.borrow_mut().take())
+}
+
 pub trait Handler<S, Cx, Req>
 where
     S: crate::Service<Cx, Req> + Send + Sync + 'static,
@@ -213,9 +218,7 @@ where
             },
             Err(err) => err,
         };
-        let panic_info = PANIC_INFO
-            .with(|info| info.borrow_mut().take())
-            .expect("[Volo] panic_info missing when handling panic");
+        let panic_info = take_panic_info().expect("[Volo] panic_info missing when handling panic");
         self.panic_handler.handle(cx, payload, panic_info)
     }
 }
@@ -0,0 +1,106 @@
+use std::sync::Arc;
+
+use motore::{layer::Layer, service::Service};
+
+use super::{CircuitBreaker, CircuitBreakerRejection};
+use crate::{context::Context, loadbalance::outlier::OutlierFailure};
+
+/// A layer that rejects the calls to the callees whose circuits are open in the
+/// [`CircuitBreaker`], and reports the results of the other calls to it.
+///
+/// The [`CircuitBreaker`] is shared by all the clones of the service created by this layer.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use volo::circuit_breaker::{CircuitBreaker, CircuitBreakerLayer};
+/// use volo_grpc::layer::circuit_breaker::GrpcCircuitBreakerRejection;
+///
+/// let client = ClientBuilder::new("hello")
+///     .layer_outer(CircuitBreakerLayer::new(
+///         CircuitBreaker::default(),
+///         GrpcCircuitBreakerRejection,
+///     ))
+///     .build()?;
+/// ```
+#[derive(Clone)]
+pub struct CircuitBreakerLayer<R> {
+    breaker: Arc<CircuitBreaker>,
+    rejection: R,
+}
+
+impl<R> CircuitBreakerLayer<R> {
+    pub fn new(breaker: impl Into<Arc<CircuitBreaker>>, rejection: R) -> Self {
+        Self {
+            breaker: breaker.into(),
+            rejection,
+        }
+    }
+}
+
+impl<S, R> Layer<S> for CircuitBreakerLayer<R> {
+    type Service = CircuitBreakerService<S, R>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        CircuitBreakerService {
+            inner,
+            breaker: self.breaker,
+            rejection: Arc::new(self.rejection),
+        }
+    }
+}
+
+/// The service created by [`CircuitBreakerLayer`].
+pub struct CircuitBreakerService<S, R> {
+    inner: S,
+    breaker: Arc<CircuitBreaker>,
+    rejection: Arc<R>,
+}
+
+impl<S: Clone, R> Clone for CircuitBreakerService<S, R> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            breaker: self.breaker.clone(),
+            rejection: self.rejection.clone(),
+        }
+    }
+}
+
+impl<Cx, Req, S, R> Service<Cx, Req> for CircuitBreakerService<S, R>
+where
+    Cx: Context + Send + 'static,
+    Req: Send + 'static,
+    S: Service<Cx, Req> + Send + Sync + 'static,
+    S::Error: OutlierFailure,
+    R: CircuitBreakerRejection<Cx, S::Error> + Send + Sync,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call<'s, 'cx>(
+        &'s self,
+        cx: &'cx mut Cx,
+        req: Req,
+    ) -> Result<Self::Response, Self::Error> {
+        let permit = match self.breaker.acquire(cx.rpc_info().callee()) {
+            Ok(permit) => permit,
+            Err(open) => {
+                tracing::debug!(
+                    "[VOLO] call rejected by circuit breaker, rpcinfo: {:?}",
+                    cx.rpc_info()
+                );
+                return Err(self.rejection.reject(cx, open));
+            }
+        };
+        let result = self.inner.call(cx, req).await;
+        if let Some(permit) = permit {
+            let success = match &result {
+                Ok(_) => true,
+                Err(err) => !err.is_outlier_failure(),
+            };
+            self.breaker.report(permit, success);
+        }
+        result
+    }
+}
@@ -0,0 +1,522 @@
+//! Circuit breaking of the clients, which fails the calls fast while the callee keeps failing.
+//!
+//! The [`CircuitBreaker`] counts the results of the calls in a sliding window for each circuit,
+//! which is either a callee service or an endpoint of it, see [`Scope`]. When the ratio of the
+//! failures in the window exceeds the threshold, the circuit opens and the calls are rejected
+//! immediately for the open time. Then one probe call is admitted at a time: if it succeeds the
+//! circuit closes, otherwise it opens again.
+//!
+//! The failures are the errors of [`OutlierFailure`], i.e. the errors meaning the callee is
+//! unhealthy instead of the business errors. The [`CircuitBreakerLayer`] rejects the calls by the
+//! protocol specific [`CircuitBreakerRejection`], which are provided in `volo-thrift` and
+//! `volo-grpc`.
+//!
+//! In the [`Scope::Endpoint`], the layer must be an inner layer of the client, i.e. after the
+//! load balancing picks the address. The breaker can also eject the endpoint of an open circuit
+//! from the load balancing by the [`OutlierDetector`] of the
+//! [`OutlierEjection`][crate::loadbalance::policy::OutlierEjection] stage, so the following calls
+//! are sent to the other endpoints instead of failing.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use volo::{
+//!     circuit_breaker::{CircuitBreaker, CircuitBreakerConfig, CircuitBreakerLayer, Scope},
+//!     loadbalance::{
+//!         outlier::{OutlierDetection, OutlierDetector},
+//!         policy::{OutlierEjection, PolicyChain, PolicyDiscover},
+//!     },
+//! };
+//! use volo_thrift::client::layer::circuit_breaker::ThriftCircuitBreakerRejection;
+//!
+//! let detector = Arc::new(OutlierDetector::new(OutlierDetection::default()));
+//! let breaker = CircuitBreaker::new(CircuitBreakerConfig::default().scope(Scope::Endpoint))
+//!     .outlier_detector(detector.clone());
+//!
+//! let client = volo_gen::volo::example::ItemServiceClientBuilder::new("item")
+//!     .discover(PolicyDiscover::new(
+//!         discover,
+//!         PolicyChain::new().stage(OutlierEjection::new(detector)),
+//!     ))
+//!     .layer_inner(CircuitBreakerLayer::new(breaker, ThriftCircuitBreakerRejection))
+//!     .build()
+//!     .unwrap();
+//! ```
+//!
+//! [`OutlierFailure`]: crate::loadbalance::outlier::OutlierFailure
+
+mod layer;
+
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+use faststr::FastStr;
+
+pub use self::layer::{CircuitBreakerLayer, CircuitBreakerService};
+use crate::{
+    context::Endpoint,
+    event::{CircuitState, Event, EventBus},
+    loadbalance::outlier::OutlierDetector,
+    net::Address,
+};
+
+const DEFAULT_WINDOW: Duration = Duration::from_secs(10);
+const DEFAULT_MIN_REQUESTS: u32 = 20;
+const DEFAULT_ERROR_RATE: f64 = 0.5;
+const DEFAULT_OPEN_TIME: Duration = Duration::from_secs(5);
+
+/// The number of the buckets the window is divided into.
+const BUCKETS: usize = 10;
+
+/// What a circuit is kept for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Scope {
+    /// A circuit for each callee service, by the service name.
+    #[default]
+    Service,
+    /// A circuit for each endpoint of the callee, by the address.
+    ///
+    /// The calls without the address, e.g. when the layer is an outer layer of the client, are not
+    /// counted.
+    Endpoint,
+}
+
+/// The config of the [`CircuitBreaker`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CircuitBreakerConfig {
+    window: Duration,
+    min_requests: u32,
+    error_rate: f64,
+    open_time: Duration,
+    scope: Scope,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            window: DEFAULT_WINDOW,
+            min_requests: DEFAULT_MIN_REQUESTS,
+            error_rate: DEFAULT_ERROR_RATE,
+            open_time: DEFAULT_OPEN_TIME,
+            scope: Scope::default(),
+        }
+    }
+}
+
+impl CircuitBreakerConfig {
+    /// Sets the length of the sliding window the results are counted in.
+    ///
+    /// Default is `10s`.
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window.max(Duration::from_millis(BUCKETS as u64));
+        self
+    }
+
+    /// Sets the minimum number of the calls in the window to open the circuit, so a few failures
+    /// of a rarely called callee don't open it.
+    ///
+    /// Default is `20`.
+    pub fn min_requests(mut self, min_requests: u32) -> Self {
+        self.min_requests = min_requests.max(1);
+        self
+    }
+
+    /// Sets the ratio of the failures in the window to open the circuit, in `(0, 1]`.
+    ///
+    /// Default is `0.5`.
+    pub fn error_rate(mut self, error_rate: f64) -> Self {
+        self.error_rate = error_rate.clamp(f64::MIN_POSITIVE, 1.0);
+        self
+    }
+
+    /// Sets how long the circuit keeps open before a probe call is admitted.
+    ///
+    /// Default is `5s`.
+    pub fn open_time(mut self, open_time: Duration) -> Self {
+        self.open_time = open_time;
+        self
+    }
+
+    /// Sets what a circuit is kept for.
+    ///
+    /// Default is [`Scope::Service`].
+    pub fn scope(mut self, scope: Scope) -> Self {
+        self.scope = scope;
+        self
+    }
+}
+
+/// The error of a call rejected by an open circuit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("circuit open, retry after {}ms", .retry_after.as_millis())]
+pub struct CircuitOpen {
+    /// How long to wait for the circuit to admit a probe call.
+    pub retry_after: Duration,
+}
+
+/// The protocol specific part of circuit breaking, which makes the error of a rejected call.
+pub trait CircuitBreakerRejection<Cx, E> {
+    /// Makes the error returned for the rejected call.
+    fn reject(&self, cx: &mut Cx, open: CircuitOpen) -> E;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum CircuitKey {
+    Service(FastStr),
+    Endpoint(Address),
+}
+
+impl fmt::Display for CircuitKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Service(name) => f.write_str(name),
+            Self::Endpoint(addr) => addr.fmt(f),
+        }
+    }
+}
+
+/// The admission of a call by the [`CircuitBreaker`], whose result should be reported by
+/// [`CircuitBreaker::report`].
+#[derive(Debug)]
+pub struct Permit {
+    key: CircuitKey,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Bucket {
+    /// The index of the bucket since the epoch of the breaker.
+    index: u64,
+    successes: u32,
+    failures: u32,
+}
+
+#[derive(Debug, Default)]
+struct Circuit {
+    buckets: [Bucket; BUCKETS],
+    /// The nanoseconds since the epoch of the breaker.
+    open_until: Option<u64>,
+    probing_since: Option<u64>,
+}
+
+impl Circuit {
+    fn state(&self) -> CircuitState {
+        match (self.open_until, self.probing_since) {
+            (None, _) => CircuitState::Closed,
+            (Some(_), None) => CircuitState::Open,
+            (Some(_), Some(_)) => CircuitState::HalfOpen,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.buckets = Default::default();
+        self.probing_since = None;
+    }
+}
+
+/// Tracks the results of the calls of the circuits and decides which are open.
+///
+/// It is shared by all the clones of a client, and can also be shared by multiple clients of the
+/// same callees.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    epoch: Instant,
+    circuits: DashMap<CircuitKey, Circuit>,
+    events: Option<EventBus>,
+    outlier: Option<Arc<OutlierDetector>>,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self::new(CircuitBreakerConfig::default())
+    }
+}
+
+impl CircuitBreaker {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        Self {
+            config,
+            epoch: Instant::now(),
+            circuits: DashMap::new(),
+            events: None,
+            outlier: None,
+        }
+    }
+
+    /// Publishes the changes of the circuits as [`Event::CircuitStateChanged`] to the bus.
+    pub fn event_bus(mut self, bus: EventBus) -> Self {
+        self.events = Some(bus);
+        self
+    }
+
+    /// Ejects the endpoint of a circuit from the load balancing by the [`OutlierDetector`] when
+    /// the circuit opens, which only works in the [`Scope::Endpoint`].
+    ///
+    /// The detector should be the one of the
+    /// [`OutlierEjection`][crate::loadbalance::policy::OutlierEjection] stage of the discover of
+    /// the client.
+    pub fn outlier_detector(mut self, detector: Arc<OutlierDetector>) -> Self {
+        self.outlier = Some(detector);
+        self
+    }
+
+    pub fn config(&self) -> &CircuitBreakerConfig {
+        &self.config
+    }
+
+    /// Returns the state of the circuit of the callee.
+    pub fn state(&self, callee: &Endpoint) -> CircuitState {
+        self.key(callee)
+            .and_then(|key| self.circuits.get(&key).map(|circuit| circuit.state()))
+            .unwrap_or(CircuitState::Closed)
+    }
+
+    /// Admits a call to the callee, or rejects it if the circuit is open.
+    ///
+    /// Returns `None` if the call is not counted, see [`Scope::Endpoint`].
+    pub fn acquire(&self, callee: &Endpoint) -> Result<Option<Permit>, CircuitOpen> {
+        let Some(key) = self.key(callee) else {
+            return Ok(None);
+        };
+        self.acquire_at(key, self.now()).map(Some)
+    }
+
+    /// Reports the result of an admitted call.
+    pub fn report(&self, permit: Permit, success: bool) {
+        self.report_at(permit.key, success, self.now())
+    }
+
+    fn key(&self, callee: &Endpoint) -> Option<CircuitKey> {
+        match self.config.scope {
+            Scope::Service => Some(CircuitKey::Service(callee.service_name())),
+            Scope::Endpoint => callee.address.clone().map(CircuitKey::Endpoint),
+        }
+    }
+
+    fn now(&self) -> u64 {
+        self.epoch.elapsed().as_nanos() as u64
+    }
+
+    fn bucket_width(&self) -> u64 {
+        (self.config.window.as_nanos() as u64 / BUCKETS as u64).max(1)
+    }
+
+    fn acquire_at(&self, key: CircuitKey, now: u64) -> Result<Permit, CircuitOpen> {
+        let open_time = self.config.open_time.as_nanos() as u64;
+        {
+            let Some(mut circuit) = self.circuits.get_mut(&key) else {
+                return Ok(Permit { key });
+            };
+            let Some(until) = circuit.open_until else {
+                return Ok(Permit { key });
+            };
+            if now < until {
+                return Err(CircuitOpen {
+                    retry_after: Duration::from_nanos(until - now),
+                });
+            }
+            // the result of the probe may never be reported, e.g. the call is cancelled, so
+            // another probe is allowed after a while
+            if let Some(since) = circuit.probing_since {
+                if now.saturating_sub(since) < open_time {
+                    return Err(CircuitOpen {
+                        retry_after: Duration::from_nanos(since + open_time - now),
+                    });
+                }
+            }
+            let from = circuit.state();
+            circuit.probing_since = Some(now);
+            drop(circuit);
+            if from == CircuitState::Open {
+                self.publish(&key, from, CircuitState::HalfOpen);
+            }
+        }
+        Ok(Permit { key })
+    }
+
+    fn report_at(&self, key: CircuitKey, success: bool, now: u64) {
+        let open_until = now + self.config.open_time.as_nanos() as u64;
+        let changed = {
+            let mut circuit = self.circuits.entry(key.clone()).or_default();
+            let from = circuit.state();
+            match circuit.open_until {
+                // the results of the calls sent before the circuit opened
+                Some(until) if now < until => None,
+                // the result of the probe
+                Some(_) => {
+                    if success {
+                        circuit.reset();
+                        circuit.open_until = None;
+                        Some((from, CircuitState::Closed))
+                    } else {
+                        circuit.reset();
+                        circuit.open_until = Some(open_until);
+                        Some((from, CircuitState::Open))
+                    }
+                }
+                None if self.record(&mut circuit, success, now) => {
+                    circuit.reset();
+                    circuit.open_until = Some(open_until);
+                    Some((from, CircuitState::Open))
+                }
+                None => None,
+            }
+        };
+        let Some((from, to)) = changed else {
+            return;
+        };
+        if to == CircuitState::Open {
+            tracing::warn!("[VOLO] circuit of {key} opened");
+            if let (Some(outlier), CircuitKey::Endpoint(addr)) = (&self.outlier, &key) {
+                outlier.eject(addr);
+            }
+        }
+        if from != to {
+            self.publish(&key, from, to);
+        }
+    }
+
+    /// Records the result in the window, and returns whether the circuit should open.
+    fn record(&self, circuit: &mut Circuit, success: bool, now: u64) -> bool {
+        let index = now / self.bucket_width();
+        let bucket = &mut circuit.buckets[index as usize % BUCKETS];
+        if bucket.index != index {
+            *bucket = Bucket {
+                index,
+                ..Default::default()
+            };
+        }
+        if success {
+            bucket.successes += 1;
+            return false;
+        }
+        bucket.failures += 1;
+
+        let (successes, failures) = circuit
+            .buckets
+            .iter()
+            .filter(|bucket| index.saturating_sub(bucket.index) < BUCKETS as u64)
+            .fold((0u64, 0u64), |(successes, failures), bucket| {
+                (
+                    successes + bucket.successes as u64,
+                    failures + bucket.failures as u64,
+                )
+            });
+        let total = successes + failures;
+        total >= self.config.min_requests as u64
+            && failures as f64 >= total as f64 * self.config.error_rate
+    }
+
+    fn publish(&self, key: &CircuitKey, from: CircuitState, to: CircuitState) {
+        if let Some(events) = &self.events {
+            events.publish(Event::CircuitStateChanged {
+                key: key.to_string().into(),
+                from,
+                to,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::loadbalance::outlier::OutlierDetection;
+
+    use super::*;
+
+    const SECOND: u64 = 1_000_000_000;
+
+    fn addr(port: u16) -> Address {
+        Address::from(std::net::SocketAddr::from(([127, 0, 0, 1], port)))
+    }
+
+    fn breaker(scope: Scope) -> CircuitBreaker {
+        CircuitBreaker::new(
+            CircuitBreakerConfig::default()
+                .window(Duration::from_secs(10))
+                .min_requests(4)
+                .error_rate(0.5)
+                .open_time(Duration::from_secs(1))
+                .scope(scope),
+        )
+    }
+
+    fn call(breaker: &CircuitBreaker, key: &CircuitKey, success: bool, now: u64) -> bool {
+        match breaker.acquire_at(key.clone(), now) {
+            Ok(permit) => {
+                breaker.report_at(permit.key, success, now);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    #[test]
+    fn open_and_probe() {
+        let breaker = breaker(Scope::Service);
+        let key = CircuitKey::Service("item".into());
+
+        // not enough calls in the window
+        assert!(call(&breaker, &key, false, 0));
+        assert!(call(&breaker, &key, true, 0));
+        assert!(call(&breaker, &key, false, 0));
+        assert!(call(&breaker, &key, true, 0));
+        // 3 of the 5 calls failed
+        assert!(call(&breaker, &key, false, 0));
+        let open = breaker.acquire_at(key.clone(), SECOND / 2).unwrap_err();
+        assert_eq!(open.retry_after, Duration::from_nanos(SECOND / 2));
+
+        // only one probe at a time after the open time
+        let probe = breaker.acquire_at(key.clone(), SECOND).unwrap();
+        assert!(breaker.acquire_at(key.clone(), SECOND).is_err());
+
+        // the probe failed, and the circuit opens again
+        breaker.report_at(probe.key, false, SECOND);
+        assert!(breaker.acquire_at(key.clone(), SECOND * 3 / 2).is_err());
+
+        // the probe succeeded
+        assert!(call(&breaker, &key, true, SECOND * 2));
+        assert!(call(&breaker, &key, false, SECOND * 2));
+        assert!(call(&breaker, &key, false, SECOND * 2));
+    }
+
+    #[test]
+    fn sliding_window() {
+        let breaker = breaker(Scope::Service);
+        let key = CircuitKey::Service("item".into());
+
+        for _ in 0..3 {
+            assert!(call(&breaker, &key, false, 0));
+        }
+        // the failures slide out of the window
+        assert!(call(&breaker, &key, false, SECOND * 11));
+        assert!(call(&breaker, &key, true, SECOND * 11));
+    }
+
+    #[test]
+    fn endpoint_scope() {
+        let detector = Arc::new(OutlierDetector::new(OutlierDetection::default()));
+        let breaker = breaker(Scope::Endpoint).outlier_detector(detector.clone());
+        let (a, b) = (addr(8000), addr(8001));
+
+        let mut callee = Endpoint::new("item".into());
+        assert!(breaker.acquire(&callee).unwrap().is_none());
+
+        callee.set_address(a.clone());
+        for _ in 0..4 {
+            let permit = breaker.acquire(&callee).unwrap().unwrap();
+            breaker.report(permit, false);
+        }
+        assert_eq!(breaker.state(&callee), CircuitState::Open);
+        assert!(breaker.acquire(&callee).is_err());
+        assert!(detector.is_ejected(&a));
+
+        callee.set_address(b.clone());
+        assert!(breaker.acquire(&callee).unwrap().is_some());
+        assert!(!detector.is_ejected(&b));
+    }
+}
@@ -0,0 +1,192 @@
+//! The errors of the configurations of the clients and servers.
+
+use std::{fmt, time::Duration};
+
+use crate::net::Address;
+
+/// A problem of a field of the configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigProblem {
+    pub field: &'static str,
+    pub message: String,
+}
+
+/// All the problems of the configuration of a client or server, which are collected when the
+/// client is built or the server is run, so they can be fixed in one pass.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConfigError {
+    problems: Vec<ConfigProblem>,
+}
+
+impl ConfigError {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a problem of the field.
+    pub fn push(&mut self, field: &'static str, message: impl Into<String>) {
+        self.problems.push(ConfigProblem {
+            field,
+            message: message.into(),
+        });
+    }
+
+    /// Adds a problem of the field if `ok` is `false`.
+    pub fn ensure(&mut self, ok: bool, field: &'static str, message: impl Into<String>) {
+        if !ok {
+            self.push(field, message);
+        }
+    }
+
+    /// Adds a problem if the timeout is zero, which will fail all the requests.
+    pub fn ensure_non_zero(&mut self, field: &'static str, timeout: Option<Duration>) {
+        self.ensure(
+            timeout != Some(Duration::ZERO),
+            field,
+            "must be greater than zero",
+        );
+    }
+
+    /// Adds a problem if the timeout is longer than the one of the whole request, which makes the
+    /// former useless.
+    pub fn ensure_within(
+        &mut self,
+        field: &'static str,
+        timeout: Option<Duration>,
+        total_field: &'static str,
+        total: Option<Duration>,
+    ) {
+        if let (Some(timeout), Some(total)) = (timeout, total) {
+            self.ensure(
+                timeout <= total,
+                field,
+                format!("{timeout:?} is longer than `{total_field}` {total:?}"),
+            );
+        }
+    }
+
+    /// Adds a problem if the address can't be connected to, i.e. an ip address with the port `0`
+    /// or an unspecified ip, or an unnamed unix socket.
+    pub fn ensure_address(&mut self, field: &'static str, address: Option<&Address>) {
+        match address {
+            Some(Address::Ip(addr)) => {
+                self.ensure(addr.port() != 0, field, format!("{addr} has no port"));
+                self.ensure(
+                    !addr.ip().is_unspecified(),
+                    field,
+                    format!("{addr} is an unspecified address"),
+                );
+            }
+            #[cfg(target_family = "unix")]
+            Some(Address::Unix(addr)) => self.ensure(
+                addr.as_pathname().is_some(),
+                field,
+                "is an unnamed unix socket",
+            ),
+            None => {}
+        }
+    }
+
+    pub fn problems(&self) -> &[ConfigProblem] {
+        &self.problems
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// Returns `Ok` if there is no problem.
+    pub fn into_result(self) -> Result<(), Self> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid configuration")?;
+        for (i, problem) in self.problems.iter().enumerate() {
+            let sep = if i == 0 { ": " } else { "; " };
+            write!(f, "{sep}`{}` {}", problem.field, problem.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl crate::error::CodedError for ConfigError {
+    fn code(&self) -> crate::error::ErrorCode {
+        crate::error::code::CONFIG
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::*;
+
+    #[test]
+    fn collect_problems() {
+        let mut err = ConfigError::new();
+        err.ensure_non_zero("read_timeout", Some(Duration::from_secs(1)));
+        err.ensure_within(
+            "connect_timeout",
+            Some(Duration::from_secs(1)),
+            "rpc_timeout",
+            None,
+        );
+        assert!(err.clone().into_result().is_ok());
+
+        err.ensure_non_zero("write_timeout", Some(Duration::ZERO));
+        err.ensure_within(
+            "connect_timeout",
+            Some(Duration::from_secs(2)),
+            "rpc_timeout",
+            Some(Duration::from_secs(1)),
+        );
+        let fields = err.problems().iter().map(|p| p.field).collect::<Vec<_>>();
+        assert_eq!(fields, ["write_timeout", "connect_timeout"]);
+        assert_eq!(
+            err.to_string(),
+            "invalid configuration: `write_timeout` must be greater than zero; `connect_timeout` \
+             2s is longer than `rpc_timeout` 1s"
+        );
+    }
+
+    #[test]
+    fn invalid_address() {
+        let mut err = ConfigError::new();
+        err.ensure_address("address", None);
+        err.ensure_address(
+            "address",
+            Some(&Address::from(SocketAddr::from(([127, 0, 0, 1], 8080)))),
+        );
+        assert!(err.is_empty());
+
+        err.ensure_address(
+            "address",
+            Some(&Address::from(SocketAddr::from(([127, 0, 0, 1], 0)))),
+        );
+        err.ensure_address(
+            "address",
+            Some(&Address::from(SocketAddr::from(([0, 0, 0, 0], 8080)))),
+        );
+        let messages = err
+            .problems()
+            .iter()
+            .map(|p| p.message.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            [
+                "127.0.0.1:0 has no port",
+                "0.0.0.0:8080 is an unspecified address"
+            ]
+        );
+    }
+}
@@ -6,6 +6,17 @@ use metainfo::{FastStrMap, TypeMap};
 use super::net::Address;
 use crate::FastStr;
 
+mod deadline;
+pub mod identity;
+mod inherit;
+pub mod log_id;
+pub mod typed;
+
+pub use self::{
+    deadline::{Deadline, DeadlineExt},
+    inherit::{deadline, spawn_inherit, with_deadline, Inherit},
+};
+
 #[macro_export]
 macro_rules! newtype_impl_context {
     ($t:ident, $cf:ident, $inner: tt) => {
@@ -0,0 +1,153 @@
+//! The deadline of a request, which is the remaining time budget shared by the layers of a call
+//! and inherited by the downstream calls.
+//!
+//! The servers set the [`Deadline`] of each request from the timeout sent by the client, e.g. the
+//! `grpc-timeout` header or the RPC timeout of TTHeader, into the context, and run the handler
+//! within it by [`with_deadline`](super::with_deadline). The clients take the earliest of the
+//! inherited deadline and their own timeout, so a downstream call never outlives the upstream
+//! one, and send the remaining time to the next hop.
+//!
+//! The layers read it by [`DeadlineExt::deadline`] instead of their own timeouts, e.g. the retry
+//! layer never starts a retry after it.
+
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use super::Context;
+
+/// The instant after which a request is given up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Deadline(Instant);
+
+impl Deadline {
+    pub fn at(instant: Instant) -> Self {
+        Self(instant)
+    }
+
+    /// Returns the deadline of the timeout from now.
+    pub fn after(timeout: Duration) -> Self {
+        Self(Instant::now() + timeout)
+    }
+
+    /// Returns the deadline of the current task, see [`super::deadline`].
+    pub fn current() -> Option<Self> {
+        super::deadline().map(Self)
+    }
+
+    pub fn instant(&self) -> Instant {
+        self.0
+    }
+
+    /// Returns the time left before the deadline, which is zero if it has elapsed.
+    pub fn remaining(&self) -> Duration {
+        self.0.saturating_duration_since(Instant::now())
+    }
+
+    pub fn is_elapsed(&self) -> bool {
+        Instant::now() >= self.0
+    }
+
+    /// Returns the earlier one of the two deadlines.
+    pub fn earliest(self, other: Option<Self>) -> Self {
+        other.map_or(self, |other| self.min(other))
+    }
+
+    /// Runs the future with the deadline, so the downstream calls made by it inherit the
+    /// deadline, see [`super::with_deadline`].
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        super::with_deadline(self.0, future).await
+    }
+
+    /// Runs the future until the deadline, and returns `None` if it elapses first, in which case
+    /// the future is dropped.
+    pub async fn timeout<F: Future>(self, future: F) -> Option<F::Output> {
+        tokio::time::timeout_at(self.0.into(), future).await.ok()
+    }
+}
+
+/// Accesses the [`Deadline`] of the request in the context.
+pub trait DeadlineExt {
+    /// Returns the deadline in the context, or the one of the current task if the context has
+    /// none.
+    fn deadline(&self) -> Option<Deadline>;
+
+    /// Sets the deadline in the context, which is kept if there is an earlier one already.
+    fn set_deadline(&mut self, deadline: Deadline);
+}
+
+impl<Cx: Context + ?Sized> DeadlineExt for Cx {
+    fn deadline(&self) -> Option<Deadline> {
+        self.extensions()
+            .get::<Deadline>()
+            .copied()
+            .or_else(Deadline::current)
+    }
+
+    fn set_deadline(&mut self, deadline: Deadline) {
+        let deadline = deadline.earliest(self.extensions().get::<Deadline>().copied());
+        self.extensions_mut().insert(deadline);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{Deadline, DeadlineExt};
+    use crate::context::{Endpoint, Reusable, Role, RpcCx, RpcInfo};
+
+    #[derive(Debug, Default)]
+    struct Config;
+
+    impl Reusable for Config {
+        fn clear(&mut self) {}
+    }
+
+    fn cx() -> RpcCx<(), Config> {
+        RpcCx::new(
+            RpcInfo::new(
+                Role::Client,
+                "method".into(),
+                Endpoint::new("caller".into()),
+                Endpoint::new("callee".into()),
+                Config,
+            ),
+            (),
+        )
+    }
+
+    #[tokio::test]
+    async fn inherit_and_keep_earliest() {
+        let upstream = Deadline::after(Duration::from_millis(100));
+        upstream
+            .scope(async {
+                // the context of a nested call inherits the deadline of the task
+                let mut cx = cx();
+                assert_eq!(cx.deadline(), Some(upstream));
+
+                cx.set_deadline(Deadline::after(Duration::from_secs(1)));
+                assert_eq!(cx.deadline(), Some(upstream.earliest(cx.deadline())));
+                let earlier = Deadline::at(Instant::now());
+                cx.set_deadline(earlier);
+                assert_eq!(cx.deadline(), Some(earlier));
+                assert!(earlier.is_elapsed());
+                assert_eq!(earlier.remaining(), Duration::ZERO);
+            })
+            .await;
+        assert!(cx().deadline().is_none());
+    }
+
+    #[tokio::test]
+    async fn timeout() {
+        let deadline = Deadline::after(Duration::from_millis(10));
+        assert_eq!(deadline.timeout(async { 1 }).await, Some(1));
+        assert_eq!(
+            deadline
+                .timeout(tokio::time::sleep(Duration::from_secs(10)))
+                .await,
+            None
+        );
+    }
+}
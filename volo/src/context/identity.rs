@@ -0,0 +1,716 @@
+//! The identity of the peer of a request, unified across the protocols.
+//!
+//! The identity of a caller can be told by three sources, from the most trusted to the least:
+//!
+//! - [`IdentitySource::Tls`]: the SAN of the client certificate verified by mTLS, where the SPIFFE
+//!   id is preferred;
+//! - [`IdentitySource::Token`]: the principal of a token verified by an auth layer of the user;
+//! - [`IdentitySource::Declared`]: the caller service name declared by the peer itself, in the
+//!   TTHeader or the gRPC metadata, which can be forged by anyone.
+//!
+//! The servers fill the TLS and the declared sources into the [`PeerIdentity`] of each request,
+//! and the auth layers set the token source by [`PeerIdentity::set_token`]. Both are accessed by
+//! [`PeerIdentityExt`] with the same API for all the contexts of `volo-thrift`, `volo-grpc` and
+//! `volo-http`.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use volo::context::identity::PeerIdentityExt;
+//!
+//! async fn say_hello(cx: &mut ServerContext, req: Request<HelloRequest>) -> ... {
+//!     match cx.peer_identity() {
+//!         Some(identity) if identity.is_authenticated() => {
+//!             tracing::info!("call from {:?}", identity.service_name());
+//!         }
+//!         _ => return Err(Status::unauthenticated("who are you?")),
+//!     }
+//!     ...
+//! }
+//! ```
+
+use std::{fmt, sync::Arc};
+
+#[cfg(feature = "__tls")]
+use sha2::{Digest, Sha256};
+
+use super::Context;
+use crate::FastStr;
+
+/// The source of an identity, which is ordered by how much it is trusted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum IdentitySource {
+    /// Declared by the peer itself without any proof.
+    Declared,
+    /// Proved by a token verified by the auth layer.
+    Token,
+    /// Proved by the certificate verified in the TLS handshake.
+    Tls,
+}
+
+impl IdentitySource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Declared => "declared",
+            Self::Token => "token",
+            Self::Tls => "tls",
+        }
+    }
+}
+
+/// The identity told by the certificate of the peer.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsIdentity {
+    /// The first URI SAN with the `spiffe://` scheme.
+    pub spiffe_id: Option<FastStr>,
+    /// The DNS SANs.
+    pub dns_names: Vec<FastStr>,
+    /// The URI SANs, including the SPIFFE id.
+    pub uris: Vec<FastStr>,
+}
+
+impl TlsIdentity {
+    /// Parses the SANs of a DER encoded X.509 certificate.
+    ///
+    /// Returns `None` if the certificate is malformed. A certificate without SANs gives an empty
+    /// identity, whose [`service_name`](Self::service_name) is `None`.
+    pub fn from_der(cert: &[u8]) -> Option<Self> {
+        let mut identity = Self::default();
+        let Some(mut names) = der::subject_alt_names(cert)? else {
+            return Some(identity);
+        };
+        while !names.is_empty() {
+            let (tag, value, rest) = der::read(names)?;
+            names = rest;
+            let Ok(value) = std::str::from_utf8(value) else {
+                continue;
+            };
+            match tag {
+                der::DNS_NAME => identity.dns_names.push(FastStr::new(value)),
+                der::URI => {
+                    let uri = FastStr::new(value);
+                    if identity.spiffe_id.is_none() && value.starts_with("spiffe://") {
+                        identity.spiffe_id = Some(uri.clone());
+                    }
+                    identity.uris.push(uri);
+                }
+                _ => {}
+            }
+        }
+        Some(identity)
+    }
+
+    /// The service name of the peer.
+    ///
+    /// For a SPIFFE id like `spiffe://example.org/ns/default/sa/greeter`, it is the name following
+    /// `/sa/`, or the last segment of the path if there is no `/sa/`. Otherwise, it is the first
+    /// DNS SAN.
+    pub fn service_name(&self) -> Option<FastStr> {
+        if let Some(id) = &self.spiffe_id {
+            let path = id
+                .strip_prefix("spiffe://")
+                .and_then(|s| s.split_once('/'))
+                .map(|(_, path)| path)
+                .unwrap_or_default();
+            let segments = path
+                .split('/')
+                .filter(|s| !s.is_empty())
+                .collect::<Vec<_>>();
+            let name = match segments.iter().position(|s| *s == "sa") {
+                Some(i) if i + 1 < segments.len() => Some(segments[i + 1]),
+                _ => segments.last().copied(),
+            };
+            if let Some(name) = name {
+                return Some(FastStr::new(name));
+            }
+        }
+        self.dns_names.first().cloned()
+    }
+}
+
+/// The parameters negotiated by the TLS handshake of the connection of a request.
+///
+/// The servers insert it into the extensions of the context of each request on a TLS connection,
+/// and it can be accessed by [`TlsInfoExt`]. Nothing is inserted for a plaintext connection.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsInfo {
+    server_name: Option<FastStr>,
+    alpn_protocol: Option<Vec<u8>>,
+    peer_certificates: Vec<Vec<u8>>,
+    identity: Option<TlsIdentity>,
+    certificate: Option<FastStr>,
+}
+
+impl TlsInfo {
+    /// Creates the info, and parses the identity from the first one of `peer_certificates`.
+    pub fn new(
+        server_name: Option<FastStr>,
+        alpn_protocol: Option<Vec<u8>>,
+        peer_certificates: Vec<Vec<u8>>,
+    ) -> Self {
+        let identity = peer_certificates
+            .first()
+            .and_then(|cert| TlsIdentity::from_der(cert));
+        Self {
+            server_name,
+            alpn_protocol,
+            peer_certificates,
+            identity,
+            certificate: None,
+        }
+    }
+
+    /// Sets the name of the certificate presented by the server, see [`TlsInfo::certificate`].
+    pub fn with_certificate(mut self, certificate: impl Into<FastStr>) -> Self {
+        self.certificate = Some(certificate.into());
+        self
+    }
+
+    /// The server name indicated by the client, i.e. the SNI.
+    ///
+    /// This is always `None` with `native-tls`, which doesn't expose it.
+    pub fn server_name(&self) -> Option<&FastStr> {
+        self.server_name.as_ref()
+    }
+
+    /// The protocol negotiated by ALPN, e.g. `h2`.
+    pub fn alpn_protocol(&self) -> Option<&[u8]> {
+        self.alpn_protocol.as_deref()
+    }
+
+    /// The DER encoded certificate chain sent by the peer, with its own certificate first.
+    ///
+    /// This only has the certificate of the peer with `native-tls`.
+    pub fn peer_certificates(&self) -> &[Vec<u8>] {
+        &self.peer_certificates
+    }
+
+    /// The DER encoded certificate of the peer, if it has sent one.
+    pub fn peer_certificate(&self) -> Option<&[u8]> {
+        self.peer_certificates.first().map(Vec::as_slice)
+    }
+
+    /// The identity told by the certificate of the peer, which is parsed only once for the
+    /// connection.
+    pub fn identity(&self) -> Option<&TlsIdentity> {
+        self.identity.as_ref()
+    }
+
+    /// The name in the [`SniConfig`] of the certificate selected for the connection by a server,
+    /// which is the matched name like `*.example.com`, or [`DEFAULT_SNI_CERTIFICATE`] for the
+    /// default certificate.
+    ///
+    /// This is `None` if the server has only one certificate.
+    ///
+    /// [`SniConfig`]: crate::net::tls::SniConfig
+    /// [`DEFAULT_SNI_CERTIFICATE`]: crate::net::tls::DEFAULT_SNI_CERTIFICATE
+    pub fn certificate(&self) -> Option<&FastStr> {
+        self.certificate.as_ref()
+    }
+}
+
+/// Accesses the [`TlsInfo`] in the extensions of the context.
+pub trait TlsInfoExt {
+    /// Returns the TLS parameters of the connection, or `None` if it's plaintext.
+    fn tls_info(&self) -> Option<&TlsInfo>;
+}
+
+impl<Cx: Context + ?Sized> TlsInfoExt for Cx {
+    fn tls_info(&self) -> Option<&TlsInfo> {
+        // the info is shared by all the requests of the connection
+        self.extensions().get::<Arc<TlsInfo>>().map(AsRef::as_ref)
+    }
+}
+
+/// The principal of a token verified by the auth layer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenPrincipal {
+    /// The subject of the token, which may be sensitive, e.g. a user id.
+    pub subject: FastStr,
+    /// The service name of the peer, if the token is issued to a service.
+    pub service_name: Option<FastStr>,
+    /// The issuer of the token.
+    pub issuer: Option<FastStr>,
+}
+
+impl TokenPrincipal {
+    pub fn new(subject: impl Into<FastStr>) -> Self {
+        Self {
+            subject: subject.into(),
+            service_name: None,
+            issuer: None,
+        }
+    }
+
+    /// Sets the service name of the peer.
+    ///
+    /// Default is `None`.
+    pub fn service_name(mut self, service_name: impl Into<FastStr>) -> Self {
+        self.service_name = Some(service_name.into());
+        self
+    }
+
+    /// Sets the issuer of the token.
+    ///
+    /// Default is `None`.
+    pub fn issuer(mut self, issuer: impl Into<FastStr>) -> Self {
+        self.issuer = Some(issuer.into());
+        self
+    }
+}
+
+/// The identity of the peer of a request, which is kept in the extensions of the context.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PeerIdentity {
+    tls: Option<TlsIdentity>,
+    token: Option<TokenPrincipal>,
+    declared: Option<FastStr>,
+}
+
+impl PeerIdentity {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn tls(&self) -> Option<&TlsIdentity> {
+        self.tls.as_ref()
+    }
+
+    pub fn set_tls(&mut self, tls: TlsIdentity) {
+        self.tls = Some(tls);
+    }
+
+    pub fn token(&self) -> Option<&TokenPrincipal> {
+        self.token.as_ref()
+    }
+
+    /// Sets the principal of a verified token, which should only be called by the auth layers.
+    pub fn set_token(&mut self, token: TokenPrincipal) {
+        self.token = Some(token);
+    }
+
+    /// The caller service name declared by the peer, which is never trusted.
+    pub fn declared_name(&self) -> Option<&FastStr> {
+        self.declared.as_ref()
+    }
+
+    pub fn set_declared(&mut self, name: FastStr) {
+        self.declared = Some(name);
+    }
+
+    /// Returns whether the peer is proved by the TLS or a token.
+    pub fn is_authenticated(&self) -> bool {
+        self.tls.is_some() || self.token.is_some()
+    }
+
+    /// The most trusted source of the identity.
+    pub fn source(&self) -> Option<IdentitySource> {
+        if self.tls.is_some() {
+            Some(IdentitySource::Tls)
+        } else if self.token.is_some() {
+            Some(IdentitySource::Token)
+        } else if self.declared.is_some() {
+            Some(IdentitySource::Declared)
+        } else {
+            None
+        }
+    }
+
+    /// The service name of the peer, with the source it comes from.
+    ///
+    /// The name is taken from the most trusted source having one. The declared name is only used
+    /// when the peer is not authenticated, so an authenticated peer can never claim another name
+    /// by declaring it.
+    pub fn service_name_with_source(&self) -> Option<(FastStr, IdentitySource)> {
+        if let Some(name) = self.tls.as_ref().and_then(TlsIdentity::service_name) {
+            return Some((name, IdentitySource::Tls));
+        }
+        if let Some(name) = self.token.as_ref().and_then(|t| t.service_name.clone()) {
+            return Some((name, IdentitySource::Token));
+        }
+        if self.is_authenticated() {
+            return None;
+        }
+        self.declared
+            .clone()
+            .map(|name| (name, IdentitySource::Declared))
+    }
+
+    /// The service name of the peer, see [`service_name_with_source`].
+    ///
+    /// [`service_name_with_source`]: Self::service_name_with_source
+    pub fn service_name(&self) -> Option<FastStr> {
+        self.service_name_with_source().map(|(name, _)| name)
+    }
+
+    /// Returns whether the declared name, if any, agrees with the authenticated service name.
+    pub fn is_consistent(&self) -> bool {
+        match (&self.declared, self.service_name_with_source()) {
+            (Some(declared), Some((name, IdentitySource::Tls | IdentitySource::Token))) => {
+                *declared == name
+            }
+            _ => true,
+        }
+    }
+
+    /// Returns a record for audit logs, which is displayed as a JSON object.
+    ///
+    /// The subject of the token is never recorded. With the `rustls` or `native-tls` feature, it
+    /// is replaced by the prefix of its SHA-256, so the records can be correlated without leaking
+    /// it.
+    pub fn audit(&self) -> AuditRecord<'_> {
+        AuditRecord(self)
+    }
+}
+
+/// The record of a [`PeerIdentity`] for audit logs, created by [`PeerIdentity::audit`].
+pub struct AuditRecord<'a>(&'a PeerIdentity);
+
+impl fmt::Display for AuditRecord<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let identity = self.0;
+        f.write_str("{")?;
+        match identity.service_name_with_source() {
+            Some((name, source)) => {
+                write!(f, "\"service_name\":{}", Json(&name))?;
+                write!(f, ",\"source\":\"{}\"", source.as_str())?;
+            }
+            None => f.write_str("\"service_name\":null,\"source\":null")?,
+        }
+        write!(f, ",\"authenticated\":{}", identity.is_authenticated())?;
+        if let Some(tls) = &identity.tls {
+            f.write_str(",\"tls\":{\"spiffe_id\":")?;
+            match &tls.spiffe_id {
+                Some(id) => write!(f, "{}", Json(id))?,
+                None => f.write_str("null")?,
+            }
+            f.write_str(",\"dns_names\":[")?;
+            for (i, name) in tls.dns_names.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(",")?;
+                }
+                write!(f, "{}", Json(name))?;
+            }
+            f.write_str("]}")?;
+        }
+        if let Some(token) = &identity.token {
+            f.write_str(",\"token\":{")?;
+            #[cfg(feature = "__tls")]
+            {
+                let digest = Sha256::digest(token.subject.as_bytes());
+                f.write_str("\"subject_sha256\":\"")?;
+                for b in &digest[..8] {
+                    write!(f, "{b:02x}")?;
+                }
+                f.write_str("\"")?;
+            }
+            if let Some(issuer) = &token.issuer {
+                #[cfg(feature = "__tls")]
+                f.write_str(",")?;
+                write!(f, "\"issuer\":{}", Json(issuer))?;
+            }
+            f.write_str("}")?;
+        }
+        if let Some(declared) = &identity.declared {
+            write!(f, ",\"declared\":{}", Json(declared))?;
+        }
+        f.write_str("}")
+    }
+}
+
+/// A string displayed as a JSON string literal.
+struct Json<'a>(&'a str);
+
+impl fmt::Display for Json<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\"")?;
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+                c => write!(f, "{c}")?,
+            }
+        }
+        f.write_str("\"")
+    }
+}
+
+/// Accesses the [`PeerIdentity`] in the extensions of the context.
+pub trait PeerIdentityExt {
+    /// Returns the identity of the peer, or `None` if nothing is known about the peer.
+    fn peer_identity(&self) -> Option<&PeerIdentity>;
+
+    /// Returns the identity of the peer, which is inserted if there is none.
+    fn peer_identity_mut(&mut self) -> &mut PeerIdentity;
+}
+
+impl<Cx: Context + ?Sized> PeerIdentityExt for Cx {
+    fn peer_identity(&self) -> Option<&PeerIdentity> {
+        self.extensions().get::<PeerIdentity>()
+    }
+
+    fn peer_identity_mut(&mut self) -> &mut PeerIdentity {
+        if !self.extensions().contains::<PeerIdentity>() {
+            self.extensions_mut().insert(PeerIdentity::default());
+        }
+        self.extensions_mut()
+            .get_mut::<PeerIdentity>()
+            .expect("peer identity has been inserted")
+    }
+}
+
+/// A minimal DER reader for the SANs of a certificate.
+mod der {
+    const SEQUENCE: u8 = 0x30;
+    const OID: u8 = 0x06;
+    const BOOLEAN: u8 = 0x01;
+    const OCTET_STRING: u8 = 0x04;
+    const EXTENSIONS: u8 = 0xa3;
+    pub const DNS_NAME: u8 = 0x82;
+    pub const URI: u8 = 0x86;
+
+    /// 2.5.29.17, subjectAltName
+    const SAN_OID: &[u8] = &[0x55, 0x1d, 0x11];
+
+    /// Reads a TLV, returns the tag, the value and the rest.
+    pub fn read(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+        let (&tag, input) = input.split_first()?;
+        let (&first, mut input) = input.split_first()?;
+        let len = if first & 0x80 == 0 {
+            first as usize
+        } else {
+            let n = (first & 0x7f) as usize;
+            if n == 0 || n > std::mem::size_of::<usize>() || input.len() < n {
+                return None;
+            }
+            let (bytes, rest) = input.split_at(n);
+            input = rest;
+            bytes.iter().fold(0usize, |len, b| (len << 8) | *b as usize)
+        };
+        if input.len() < len {
+            return None;
+        }
+        let (value, rest) = input.split_at(len);
+        Some((tag, value, rest))
+    }
+
+    fn expect(input: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+        match read(input)? {
+            (t, value, rest) if t == tag => Some((value, rest)),
+            _ => None,
+        }
+    }
+
+    /// Returns the content of the `GeneralNames` of the SAN extension, or `Ok(None)` if the
+    /// certificate has no SAN.
+    pub fn subject_alt_names(cert: &[u8]) -> Option<Option<&[u8]>> {
+        let (cert, _) = expect(cert, SEQUENCE)?;
+        let (mut tbs, _) = expect(cert, SEQUENCE)?;
+        let mut extensions = None;
+        while !tbs.is_empty() {
+            let (tag, value, rest) = read(tbs)?;
+            if tag == EXTENSIONS {
+                extensions = Some(value);
+                break;
+            }
+            tbs = rest;
+        }
+        let Some(extensions) = extensions else {
+            return Some(None);
+        };
+        let (mut extensions, _) = expect(extensions, SEQUENCE)?;
+        while !extensions.is_empty() {
+            let (extension, rest) = expect(extensions, SEQUENCE)?;
+            extensions = rest;
+            let (oid, mut extension) = expect(extension, OID)?;
+            if oid != SAN_OID {
+                continue;
+            }
+            if let Some((_, rest)) = expect(extension, BOOLEAN) {
+                extension = rest;
+            }
+            let (value, _) = expect(extension, OCTET_STRING)?;
+            let (names, _) = expect(value, SEQUENCE)?;
+            return Some(Some(names));
+        }
+        Some(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::{Reusable, RpcCx, RpcInfo};
+
+    const SPIFFE_CERT: &[u8] = include_bytes!("../../../examples/data/tls/spiffe.der");
+
+    fn tls(spiffe_id: &'static str) -> TlsIdentity {
+        TlsIdentity {
+            spiffe_id: Some(FastStr::from_static_str(spiffe_id)),
+            dns_names: Vec::new(),
+            uris: vec![FastStr::from_static_str(spiffe_id)],
+        }
+    }
+
+    #[test]
+    fn parse_certificate() {
+        let identity = TlsIdentity::from_der(SPIFFE_CERT).unwrap();
+        assert_eq!(
+            identity.spiffe_id.as_deref(),
+            Some("spiffe://example.org/ns/default/sa/greeter")
+        );
+        assert_eq!(
+            identity.dns_names,
+            vec![FastStr::from_static_str("greeter.default.svc")]
+        );
+        assert_eq!(identity.service_name().as_deref(), Some("greeter"));
+
+        assert!(TlsIdentity::from_der(&SPIFFE_CERT[..SPIFFE_CERT.len() / 2]).is_none());
+        assert!(TlsIdentity::from_der(b"").is_none());
+    }
+
+    #[test]
+    fn tls_service_name() {
+        assert_eq!(
+            tls("spiffe://example.org/workload/echo")
+                .service_name()
+                .as_deref(),
+            Some("echo")
+        );
+        let dns = TlsIdentity {
+            dns_names: vec!["echo.example.com".into()],
+            ..Default::default()
+        };
+        assert_eq!(dns.service_name().as_deref(), Some("echo.example.com"));
+        assert_eq!(TlsIdentity::default().service_name(), None);
+    }
+
+    #[test]
+    fn precedence() {
+        let mut identity = PeerIdentity::new();
+        assert_eq!(identity.source(), None);
+        assert_eq!(identity.service_name(), None);
+
+        // the declared name is used only if nothing better is known
+        identity.set_declared("mallory".into());
+        assert!(!identity.is_authenticated());
+        assert_eq!(identity.source(), Some(IdentitySource::Declared));
+        assert_eq!(identity.service_name().as_deref(), Some("mallory"));
+        assert!(identity.is_consistent());
+
+        // a token without a service name hides the declared name
+        identity.set_token(TokenPrincipal::new("user-42"));
+        assert!(identity.is_authenticated());
+        assert_eq!(identity.source(), Some(IdentitySource::Token));
+        assert_eq!(identity.service_name(), None);
+
+        identity.set_token(TokenPrincipal::new("user-42").service_name("alice"));
+        assert_eq!(
+            identity.service_name_with_source(),
+            Some(("alice".into(), IdentitySource::Token))
+        );
+        assert!(!identity.is_consistent());
+
+        // the certificate wins over the token
+        identity.set_tls(tls("spiffe://example.org/ns/default/sa/bob"));
+        assert_eq!(identity.source(), Some(IdentitySource::Tls));
+        assert_eq!(
+            identity.service_name_with_source(),
+            Some(("bob".into(), IdentitySource::Tls))
+        );
+
+        identity.set_declared("bob".into());
+        assert!(identity.is_consistent());
+
+        assert!(IdentitySource::Tls > IdentitySource::Token);
+        assert!(IdentitySource::Token > IdentitySource::Declared);
+    }
+
+    #[test]
+    fn audit() {
+        let mut identity = PeerIdentity::new();
+        assert_eq!(
+            identity.audit().to_string(),
+            r#"{"service_name":null,"source":null,"authenticated":false}"#
+        );
+
+        identity.set_declared("say \"hi\"".into());
+        identity.set_token(
+            TokenPrincipal::new("alice@example.com")
+                .service_name("alice")
+                .issuer("https://auth.example.com"),
+        );
+        identity.set_tls(TlsIdentity::from_der(SPIFFE_CERT).unwrap());
+        let record = identity.audit().to_string();
+        assert!(!record.contains("alice@example.com"));
+        #[cfg(feature = "__tls")]
+        let hashed = {
+            let digest = Sha256::digest(b"alice@example.com");
+            let hashed = digest[..8]
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect::<String>();
+            format!("\"subject_sha256\":\"{hashed}\",")
+        };
+        #[cfg(not(feature = "__tls"))]
+        let hashed = "";
+        assert_eq!(
+            record,
+            format!(
+                "{{\"service_name\":\"greeter\",\"source\":\"tls\",\"authenticated\":true,\
+                 \"tls\":{{\"spiffe_id\":\"spiffe://example.org/ns/default/sa/greeter\",\
+                 \"dns_names\":[\"greeter.default.svc\"]}},\
+                 \"token\":{{{hashed}\"issuer\":\"https://auth.example.com\"}},\
+                 \"declared\":\"say \\\"hi\\\"\"}}"
+            )
+        );
+    }
+
+    #[test]
+    fn tls_info() {
+        let info = TlsInfo::new(
+            Some("greeter.default.svc".into()),
+            Some(b"h2".to_vec()),
+            vec![SPIFFE_CERT.to_vec()],
+        );
+        assert_eq!(info.peer_certificate(), Some(SPIFFE_CERT));
+        assert_eq!(
+            info.identity()
+                .and_then(TlsIdentity::service_name)
+                .as_deref(),
+            Some("greeter")
+        );
+        assert!(TlsInfo::new(None, None, Vec::new()).identity().is_none());
+
+        let mut cx = RpcCx::new(
+            RpcInfo::<Config>::with_role(crate::context::Role::Server),
+            (),
+        );
+        assert!(cx.tls_info().is_none());
+        cx.extensions_mut().insert(Arc::new(info.clone()));
+        assert_eq!(cx.tls_info(), Some(&info));
+    }
+
+    #[derive(Default)]
+    struct Config;
+
+    impl Reusable for Config {
+        fn clear(&mut self) {}
+    }
+
+    #[test]
+    fn context_ext() {
+        let mut cx = RpcCx::new(
+            RpcInfo::<Config>::with_role(crate::context::Role::Server),
+            (),
+        );
+        assert!(cx.peer_identity().is_none());
+        cx.peer_identity_mut().set_declared("echo".into());
+        cx.peer_identity_mut()
+            .set_token(TokenPrincipal::new("echo").service_name("echo"));
+        let identity = cx.peer_identity().unwrap();
+        assert_eq!(identity.declared_name().map(|s| s.as_str()), Some("echo"));
+        assert_eq!(identity.source(), Some(IdentitySource::Token));
+    }
+}
@@ -0,0 +1,280 @@
+//! Propagating the request context into the tasks spawned by a handler.
+//!
+//! The [`MetaInfo`] of a request lives in the [`METAINFO`] task-local, which is lost by
+//! `tokio::spawn`, and so are the tracing span and the deadline of the request. [`spawn_inherit`]
+//! snapshots them and re-installs them in the spawned task, so the downstream calls made by the
+//! spawned task carry the same metainfo as the calls made by the handler itself.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! async fn hello(&self, req: HelloRequest) -> Result<HelloResponse, ServerError> {
+//!     volo::context::spawn_inherit(async move {
+//!         // the persistent and transient metainfo of `hello` are sent to the downstream
+//!         let _ = CLIENT.audit(req.name).await;
+//!     });
+//!     ...
+//! }
+//! ```
+
+use std::{cell::RefCell, future::Future, sync::Arc, time::Instant};
+
+use metainfo::{Backward, Forward, MetaInfo, METAINFO};
+use tokio::task::JoinHandle;
+use tracing::{Instrument, Span};
+
+use crate::FastStr;
+
+tokio::task_local! {
+    static DEADLINE: Instant;
+}
+
+/// Returns the deadline of the current task set by [`with_deadline`], or inherited by
+/// [`spawn_inherit`].
+pub fn deadline() -> Option<Instant> {
+    DEADLINE.try_with(|deadline| *deadline).ok()
+}
+
+/// Runs the future with the deadline, which is kept if there is an earlier deadline already.
+pub async fn with_deadline<F: Future>(deadline: Instant, future: F) -> F::Output {
+    let deadline = self::deadline().map_or(deadline, |current| current.min(deadline));
+    DEADLINE.scope(deadline, future).await
+}
+
+/// Spawns a task inheriting the metainfo, the tracing span and the deadline of the current task.
+///
+/// This is a shortcut for `Inherit::capture().spawn(future)`.
+pub fn spawn_inherit<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    Inherit::capture().spawn(future)
+}
+
+/// A snapshot of the context of the current task, which can be re-installed in other tasks.
+///
+/// The snapshot is cheap to clone, so it can be captured once and used for spawning many tasks.
+#[derive(Clone)]
+pub struct Inherit {
+    /// The metainfo at the time of capture, shared by the current task and the spawned tasks.
+    parent: Option<Arc<MetaInfo>>,
+    entries: Arc<Entries>,
+    span: Span,
+    deadline: Option<Instant>,
+}
+
+impl Inherit {
+    /// Captures the context of the current task.
+    pub fn capture() -> Self {
+        let (parent, entries) = METAINFO
+            .try_with(|m| {
+                let prev = m.take();
+                let entries = Arc::new(Entries::capture(&prev));
+                let parent = Arc::new(prev);
+                // the values of the maps read by the codecs are not looked up in the parent, so
+                // they're copied back to keep the current task as it was
+                let mut current = MetaInfo::from(parent.clone());
+                entries.install_forward(&mut current);
+                entries.install_backward(&mut current);
+                m.replace(current);
+                (Some(parent), entries)
+            })
+            .unwrap_or_default();
+
+        Self {
+            parent,
+            entries,
+            span: Span::current(),
+            deadline: deadline(),
+        }
+    }
+
+    /// Overrides the deadline inherited by the tasks.
+    pub fn deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Removes the deadline inherited by the tasks.
+    pub fn without_deadline(mut self) -> Self {
+        self.deadline = None;
+        self
+    }
+
+    /// Runs the future in the captured context.
+    ///
+    /// Only the forward metainfo, i.e. the persistent, transient and upstream values, are
+    /// inherited, since the backward values are for the response of the current request.
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        let mut mi = match &self.parent {
+            Some(parent) => MetaInfo::from(parent.clone()),
+            None => MetaInfo::default(),
+        };
+        self.entries.install_forward(&mut mi);
+
+        let future = METAINFO.scope(RefCell::new(mi), future.instrument(self.span));
+        match self.deadline {
+            Some(deadline) => DEADLINE.scope(deadline, future).await,
+            None => future.await,
+        }
+    }
+
+    /// Spawns a task running the future in the captured context.
+    pub fn spawn<F>(self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        tokio::spawn(self.scope(future))
+    }
+}
+
+impl std::fmt::Debug for Inherit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Inherit")
+            .field("entries", &self.entries)
+            .field("span", &self.span)
+            .field("deadline", &self.deadline)
+            .finish_non_exhaustive()
+    }
+}
+
+type Pairs = Vec<(FastStr, FastStr)>;
+
+/// The string values of a [`MetaInfo`], which are only read from the metainfo itself rather than
+/// its parent when being encoded.
+///
+/// These are the values on the wire only, and the typed values set by
+/// [`TypedKey::set_in`](super::typed::TypedKey::set_in) are inherited by the parent.
+#[derive(Debug, Default)]
+struct Entries {
+    persistents: Pairs,
+    transients: Pairs,
+    upstreams: Pairs,
+    backward_transients: Pairs,
+    backward_downstreams: Pairs,
+}
+
+fn pairs<'a, I>(map: Option<I>) -> Pairs
+where
+    I: IntoIterator<Item = (&'a FastStr, &'a FastStr)>,
+{
+    map.into_iter()
+        .flatten()
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect()
+}
+
+impl Entries {
+    fn capture(mi: &MetaInfo) -> Self {
+        Self {
+            persistents: pairs(mi.get_all_persistents()),
+            transients: pairs(mi.get_all_transients()),
+            upstreams: pairs(mi.get_all_upstreams()),
+            backward_transients: pairs(mi.get_all_backward_transients()),
+            backward_downstreams: pairs(mi.get_all_backward_downstreams()),
+        }
+    }
+
+    fn install_forward(&self, mi: &mut MetaInfo) {
+        for (k, v) in &self.persistents {
+            mi.set_persistent(k.clone(), v.clone());
+        }
+        for (k, v) in &self.transients {
+            mi.set_transient(k.clone(), v.clone());
+        }
+        for (k, v) in &self.upstreams {
+            mi.set_upstream(k.clone(), v.clone());
+        }
+    }
+
+    fn install_backward(&self, mi: &mut MetaInfo) {
+        for (k, v) in &self.backward_transients {
+            mi.set_backward_transient(k.clone(), v.clone());
+        }
+        for (k, v) in &self.backward_downstreams {
+            mi.set_backward_downstream(k.clone(), v.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::context::typed::TypedKey;
+
+    crate::metainfo_key!(TENANT: String = "tenant");
+
+    fn request_metainfo() -> MetaInfo {
+        let mut mi = MetaInfo::new();
+        mi.set_persistent("trace", "t1");
+        mi.set_transient("hop", "h1");
+        mi.set_upstream("caller", "c1");
+        mi.set_backward_transient("status", "ok");
+        TENANT::set_in("volo".to_owned(), &mut mi);
+        mi
+    }
+
+    #[tokio::test]
+    async fn spawn_inherit_metainfo() {
+        let mi = request_metainfo();
+        METAINFO
+            .scope(RefCell::new(mi), async {
+                let spawned = spawn_inherit(async {
+                    METAINFO.with(|m| {
+                        let m = m.borrow();
+                        // the maps read by the client codecs when calling the downstream
+                        let persistents = m.get_all_persistents().unwrap();
+                        assert_eq!(persistents.get("trace").unwrap().as_str(), "t1");
+                        let transients = m.get_all_transients().unwrap();
+                        assert_eq!(transients.get("hop").unwrap().as_str(), "h1");
+                        assert_eq!(m.get_upstream("caller").unwrap().as_str(), "c1");
+                        assert!(m.get_all_backward_transients().is_none());
+                        assert_eq!(TENANT::get_in(&m).map(String::as_str), Some("volo"));
+                    });
+                });
+                spawned.await.unwrap();
+
+                // the current task is left as it was
+                METAINFO.with(|m| {
+                    let m = m.borrow();
+                    let persistents = m.get_all_persistents().unwrap();
+                    assert_eq!(persistents.get("trace").unwrap().as_str(), "t1");
+                    let backward = m.get_all_backward_transients().unwrap();
+                    assert_eq!(backward.get("status").unwrap().as_str(), "ok");
+                    assert_eq!(TENANT::get_in(&m).map(String::as_str), Some("volo"));
+                });
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn spawn_inherit_deadline() {
+        assert!(spawn_inherit(async { deadline() }).await.unwrap().is_none());
+
+        let at = Instant::now() + Duration::from_secs(1);
+        with_deadline(at, async {
+            // the earlier deadline is kept
+            with_deadline(at + Duration::from_secs(1), async {
+                assert_eq!(spawn_inherit(async { deadline() }).await.unwrap(), Some(at));
+            })
+            .await;
+
+            let inherit = Inherit::capture().without_deadline();
+            assert!(inherit.spawn(async { deadline() }).await.unwrap().is_none());
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn spawn_without_metainfo() {
+        let persistents =
+            spawn_inherit(async { METAINFO.with(|m| m.borrow().get_all_persistents().is_some()) })
+                .await
+                .unwrap();
+        assert!(!persistents);
+    }
+}
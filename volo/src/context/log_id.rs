@@ -0,0 +1,448 @@
+//! The log id correlating the logs of a request across the services, unified across the
+//! protocols.
+//!
+//! The log id is generated by the first hop, e.g. the edge server receiving a request without
+//! one, and sent to the next hops along with the downstream calls:
+//!
+//! - The servers read it from the request, i.e. the string or the int key of TTHeader, or the
+//!   header of gRPC and HTTP, or generate one if it's absent, and keep it in the context and in
+//!   the [`MetaInfo`] of the current task by [`accept`].
+//! - The clients send the one of the context, or the one of the current task, e.g. the request
+//!   being served or [`scope`], or generate one, by [`outgoing`].
+//!
+//! The keys and the format of the generated ones are set by [`LogIdConfig::install`] once for the
+//! process. The log id is read by [`LogIdExt::log_id`] with the same API for all the contexts, and
+//! recorded as the field `log_id` of the spans of `volo::otel` and of the current span when it's
+//! accepted or sent.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use volo::context::log_id::{LogIdConfig, LogIdExt, LogIdFormat};
+//!
+//! LogIdConfig::default()
+//!     .with_format(LogIdFormat::UuidV7)
+//!     .with_header("x-request-id")
+//!     .install()
+//!     .unwrap();
+//!
+//! async fn say_hello(cx: &mut ServerContext, req: Request<HelloRequest>) -> ... {
+//!     tracing::info!(log_id = ?cx.log_id(), "hello");
+//!     ...
+//! }
+//! ```
+
+use std::{
+    cell::RefCell,
+    fmt,
+    future::Future,
+    net::{IpAddr, Ipv4Addr, UdpSocket},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        OnceLock,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use metainfo::{MetaInfo, METAINFO};
+use tracing::Span;
+
+use super::Context;
+use crate::FastStr;
+
+/// The default header of the log id in gRPC and HTTP.
+pub const DEFAULT_HEADER: &str = "x-log-id";
+/// The default string key of the log id in TTHeader.
+pub const DEFAULT_TTHEADER_KEY: &str = "log_id";
+/// The longest log id accepted, and the longer ones are replaced by a generated one.
+pub const MAX_LEN: usize = 128;
+
+static CONFIG: OnceLock<LogIdConfig> = OnceLock::new();
+
+/// The format of the generated log ids.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogIdFormat {
+    /// The UTC time in `YYYYMMDDhhmmss`, the IP of the host in uppercase hex, i.e. 8 digits for
+    /// IPv4 and 32 for IPv6, and a counter of 6 uppercase hex digits, e.g.
+    /// `20240102030405C0A8010A00002A`.
+    #[default]
+    Timestamp,
+    /// A UUID of version 7, e.g. `01890a5d-ac96-774b-bcce-b302099a8057`.
+    UuidV7,
+}
+
+/// The keys and the format of the log ids, see the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct LogIdConfig {
+    format: LogIdFormat,
+    header: FastStr,
+    ttheader_key: FastStr,
+    ttheader_int_key: Option<u16>,
+    ip: Option<IpAddr>,
+    generate: bool,
+}
+
+impl Default for LogIdConfig {
+    fn default() -> Self {
+        Self {
+            format: LogIdFormat::default(),
+            header: FastStr::from_static_str(DEFAULT_HEADER),
+            ttheader_key: FastStr::from_static_str(DEFAULT_TTHEADER_KEY),
+            ttheader_int_key: None,
+            ip: None,
+            generate: true,
+        }
+    }
+}
+
+impl LogIdConfig {
+    /// Sets the format of the generated log ids.
+    ///
+    /// Default is [`LogIdFormat::Timestamp`].
+    pub fn with_format(mut self, format: LogIdFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Sets the header of gRPC and HTTP, which is lowercased.
+    ///
+    /// Default is [`DEFAULT_HEADER`].
+    pub fn with_header(mut self, header: impl AsRef<str>) -> Self {
+        self.header = FastStr::new(header.as_ref().to_ascii_lowercase());
+        self
+    }
+
+    /// Sets the string key of TTHeader.
+    ///
+    /// Default is [`DEFAULT_TTHEADER_KEY`].
+    pub fn with_ttheader_key(mut self, key: impl Into<FastStr>) -> Self {
+        self.ttheader_key = key.into();
+        self
+    }
+
+    /// Sets the int key of TTHeader, which is read before the string key, and sent along with
+    /// it.
+    ///
+    /// Default is `None`.
+    pub fn with_ttheader_int_key(mut self, key: Option<u16>) -> Self {
+        self.ttheader_int_key = key;
+        self
+    }
+
+    /// Sets the IP in the log ids of [`LogIdFormat::Timestamp`].
+    ///
+    /// Default is the IP of the interface routing to the private networks, or the loopback if
+    /// there is none.
+    pub fn with_ip(mut self, ip: IpAddr) -> Self {
+        self.ip = Some(ip);
+        self
+    }
+
+    /// Sets whether to generate the log ids of the requests without one, otherwise they have no
+    /// log ids unless set manually.
+    ///
+    /// Default is `true`.
+    pub fn generate(mut self, generate: bool) -> Self {
+        self.generate = generate;
+        self
+    }
+
+    /// Installs the config for the process, which fails if a config has been installed, or the
+    /// default one has been used by a request.
+    pub fn install(self) -> Result<(), Self> {
+        CONFIG.set(self)
+    }
+
+    /// Returns the config installed, or the default one.
+    pub fn global() -> &'static Self {
+        CONFIG.get_or_init(Self::default)
+    }
+
+    pub fn format(&self) -> LogIdFormat {
+        self.format
+    }
+
+    pub fn header(&self) -> &FastStr {
+        &self.header
+    }
+
+    pub fn ttheader_key(&self) -> &FastStr {
+        &self.ttheader_key
+    }
+
+    pub fn ttheader_int_key(&self) -> Option<u16> {
+        self.ttheader_int_key
+    }
+
+    /// Generates a log id of the format.
+    pub fn generate_id(&self) -> FastStr {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        match self.format {
+            LogIdFormat::Timestamp => {
+                static COUNTER: AtomicU32 = AtomicU32::new(0);
+                let secs = now.as_secs();
+                let (year, month, day) = civil_from_days((secs / 86400) as i64);
+                let (hour, minute, second) = (secs % 86400 / 3600, secs % 3600 / 60, secs % 60);
+                let ip = match self.ip.unwrap_or_else(local_ip) {
+                    IpAddr::V4(ip) => format!("{:08X}", u32::from(ip)),
+                    IpAddr::V6(ip) => format!("{:032X}", u128::from(ip)),
+                };
+                let counter = COUNTER.fetch_add(1, Ordering::Relaxed) & 0xff_ffff;
+                format!(
+                    "{year:04}{month:02}{day:02}{hour:02}{minute:02}{second:02}{ip}{counter:06X}"
+                )
+                .into()
+            }
+            LogIdFormat::UuidV7 => {
+                let ms = now.as_millis() as u128 & ((1 << 48) - 1);
+                let mut uuid = ms << 80 | (rand::random::<u128>() & ((1 << 80) - 1));
+                // the version 7 and the variant `10`
+                uuid = (uuid & !(0xf << 76)) | (0x7 << 76);
+                uuid = (uuid & !(0x3 << 62)) | (0x2 << 62);
+                let hex = format!("{uuid:032x}");
+                format!(
+                    "{}-{}-{}-{}-{}",
+                    &hex[..8],
+                    &hex[8..12],
+                    &hex[12..16],
+                    &hex[16..20],
+                    &hex[20..]
+                )
+                .into()
+            }
+        }
+    }
+}
+
+/// The days since the epoch to the date, see <http://howardhinnant.github.io/date_algorithms.html>.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn local_ip() -> IpAddr {
+    static IP: OnceLock<IpAddr> = OnceLock::new();
+    *IP.get_or_init(|| {
+        // connecting a UDP socket only looks up the route without sending anything
+        UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+            .and_then(|socket| {
+                socket.connect((Ipv4Addr::new(10, 255, 255, 255), 1))?;
+                socket.local_addr()
+            })
+            .map(|addr| addr.ip())
+            .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST))
+    })
+}
+
+/// The log id of a request, which is kept in the extensions of the context and in the
+/// [`MetaInfo`] of the task serving it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LogId(FastStr);
+
+impl LogId {
+    pub fn new(log_id: impl Into<FastStr>) -> Self {
+        Self(log_id.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> FastStr {
+        self.0
+    }
+}
+
+impl fmt::Display for LogId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Accesses the [`LogId`] in the extensions of the context.
+pub trait LogIdExt {
+    /// Returns the log id of the request, which is set by the servers and the clients before
+    /// calling the services.
+    fn log_id(&self) -> Option<&FastStr>;
+
+    /// Sets the log id of the request, e.g. in tests, which is sent by a client instead of the
+    /// one of the current task.
+    fn set_log_id(&mut self, log_id: impl Into<FastStr>);
+}
+
+impl<Cx: Context + ?Sized> LogIdExt for Cx {
+    fn log_id(&self) -> Option<&FastStr> {
+        self.extensions().get::<LogId>().map(|id| &id.0)
+    }
+
+    fn set_log_id(&mut self, log_id: impl Into<FastStr>) {
+        self.extensions_mut().insert(LogId(log_id.into()));
+    }
+}
+
+/// Returns the log id of the current task, i.e. the one of the request being served, or set by
+/// [`scope`].
+pub fn current() -> Option<FastStr> {
+    METAINFO
+        .try_with(|mi| mi.borrow().get::<LogId>().map(|id| id.0.clone()))
+        .ok()
+        .flatten()
+}
+
+/// Runs the future with the log id, so the calls made by it send the log id, e.g. in tests or
+/// the tasks not serving any request.
+pub async fn scope<F: Future>(log_id: impl Into<FastStr>, future: F) -> F::Output {
+    let mut mi = MetaInfo::default();
+    mi.insert(LogId(log_id.into()));
+    METAINFO.scope(RefCell::new(mi), future).await
+}
+
+fn is_valid(log_id: &str) -> bool {
+    !log_id.is_empty() && log_id.len() <= MAX_LEN && log_id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// Sets the log id of the request received by a server, which is generated if it's absent or
+/// malformed, and returns it.
+///
+/// The log id is kept in the context and in the [`MetaInfo`] of the current task, so the
+/// downstream calls send it, and recorded as the field `log_id` of the current span.
+///
+/// This is used by the servers.
+pub fn accept<Cx: Context + ?Sized>(cx: &mut Cx, received: Option<&str>) -> Option<FastStr> {
+    let config = LogIdConfig::global();
+    let log_id = match received.map(str::trim).filter(|id| is_valid(id)) {
+        Some(id) => FastStr::new(id),
+        None if config.generate => config.generate_id(),
+        None => return None,
+    };
+    cx.set_log_id(log_id.clone());
+    let _ = METAINFO.try_with(|mi| mi.borrow_mut().insert(LogId(log_id.clone())));
+    Span::current().record("log_id", log_id.as_str());
+    Some(log_id)
+}
+
+/// Returns the log id sent by a client, which is the one of the context, or the one of the
+/// current task, or generated, and keeps it in the context so the retries send the same one.
+///
+/// This is used by the clients.
+pub fn outgoing<Cx: Context + ?Sized>(cx: &mut Cx) -> Option<FastStr> {
+    if let Some(log_id) = cx.log_id() {
+        return Some(log_id.clone());
+    }
+    let config = LogIdConfig::global();
+    let log_id = current().or_else(|| config.generate.then(|| config.generate_id()))?;
+    cx.set_log_id(log_id.clone());
+    Span::current().record("log_id", log_id.as_str());
+    Some(log_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::{Endpoint, Reusable, Role, RpcCx, RpcInfo};
+
+    #[derive(Debug, Default)]
+    struct Config;
+
+    impl Reusable for Config {
+        fn clear(&mut self) {}
+    }
+
+    type Cx = RpcCx<(), Config>;
+
+    fn cx(role: Role) -> Cx {
+        RpcCx::new(
+            RpcInfo::new(
+                role,
+                "Echo".into(),
+                Endpoint::new("caller".into()),
+                Endpoint::new("callee".into()),
+                Config,
+            ),
+            (),
+        )
+    }
+
+    #[test]
+    fn generate() {
+        let config = LogIdConfig::default().with_ip(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 10)));
+        let id = config.generate_id();
+        assert_eq!(id.len(), 14 + 8 + 6, "{id}");
+        assert_eq!(&id[14..22], "C0A8010A");
+        assert!(id[..14].bytes().all(|b| b.is_ascii_digit()));
+        assert_ne!(config.generate_id(), id);
+
+        let id = config.with_format(LogIdFormat::UuidV7).generate_id();
+        assert_eq!(id.len(), 36, "{id}");
+        assert_eq!(&id[14..15], "7");
+        assert!(matches!(&id[19..20], "8" | "9" | "a" | "b"), "{id}");
+    }
+
+    #[test]
+    fn civil() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+        assert_eq!(civil_from_days(19724), (2024, 1, 1));
+    }
+
+    #[tokio::test]
+    async fn accept_and_propagate() {
+        METAINFO
+            .scope(RefCell::new(MetaInfo::default()), async {
+                let mut server = cx(Role::Server);
+                let id = accept(&mut server, Some(" upstream-id ")).unwrap();
+                assert_eq!(id, "upstream-id");
+                assert_eq!(server.log_id().unwrap(), "upstream-id");
+                assert_eq!(current().unwrap(), "upstream-id");
+
+                // the downstream call of the handler, including the spawned ones
+                let mut client = cx(Role::Client);
+                assert_eq!(outgoing(&mut client).unwrap(), "upstream-id");
+                let spawned =
+                    crate::context::spawn_inherit(async { outgoing(&mut cx(Role::Client)) });
+                assert_eq!(spawned.await.unwrap().unwrap(), "upstream-id");
+
+                // the manual one of the context wins
+                let mut client = cx(Role::Client);
+                client.set_log_id("manual");
+                assert_eq!(outgoing(&mut client).unwrap(), "manual");
+
+                // a malformed one is replaced
+                let mut server = cx(Role::Server);
+                let id = accept(&mut server, Some("bad id")).unwrap();
+                assert_ne!(id, "bad id");
+                assert_eq!(current().unwrap(), id);
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn first_hop() {
+        // a client outside any request generates one, and keeps it for the retries
+        let mut client = cx(Role::Client);
+        let id = outgoing(&mut client).unwrap();
+        assert_eq!(outgoing(&mut client).unwrap(), id);
+        assert_ne!(outgoing(&mut cx(Role::Client)).unwrap(), id);
+
+        let id = scope("scoped", async { outgoing(&mut cx(Role::Client)) }).await;
+        assert_eq!(id.unwrap(), "scoped");
+    }
+
+    #[test]
+    fn recycled_context() {
+        let mut cx = cx(Role::Server);
+        cx.set_log_id("first");
+        cx.reset(());
+        assert!(cx.log_id().is_none());
+    }
+}
@@ -0,0 +1,290 @@
+//! Strongly-typed keys of the values carried by the context.
+//!
+//! A key is declared once by [`metainfo_key!`][crate::metainfo_key], which defines a zero-sized
+//! type with the type of its value, so the value is read and written by [`TypedExt`] without the
+//! string keys and the parsing scattered among the layers:
+//!
+//! ```rust,ignore
+//! volo::metainfo_key!(pub TENANT: String = "tenant");
+//!
+//! cx.set_typed::<TENANT>("foo".to_owned());
+//! assert_eq!(cx.get_typed::<TENANT>().map(|t| t.as_str()), Some("foo"));
+//! ```
+//!
+//! The values are stored in the [`Extensions`][super::Extensions] of the context, so they are
+//! cleared with the context when it is recycled. The values of a task, which are inherited by the
+//! tasks spawned by [`spawn_inherit`][super::spawn_inherit], are stored in the type map of its
+//! [`MetaInfo`] by [`TypedKey::set_in`]. To carry them across the services, they are written to
+//! and read from the strings of the [`MetaInfo`] by [`TypedKey::to_transient`] and
+//! [`TypedKey::from_transient`], with the wire name and the serialization of each key, which
+//! should only be done at the boundary of the process.
+
+use std::marker::PhantomData;
+
+use metainfo::MetaInfo;
+
+use super::Context;
+use crate::FastStr;
+
+/// A key of the values in the context, which is usually declared by
+/// [`metainfo_key!`][crate::metainfo_key].
+pub trait TypedKey: Sized + 'static {
+    type Value: Send + Sync + 'static;
+
+    /// The name of the key, which is also the default name on the wire.
+    const NAME: &'static str;
+
+    /// Returns the name of the key in the [`MetaInfo`].
+    fn as_wire_key() -> &'static str {
+        Self::NAME
+    }
+
+    /// Serializes the value for the [`MetaInfo`].
+    fn encode(value: &Self::Value) -> FastStr;
+
+    /// Deserializes the value from the [`MetaInfo`], or `None` if it is malformed.
+    fn decode(s: &str) -> Option<Self::Value>;
+
+    /// Returns the value in the type map of the [`MetaInfo`], including the one of its parent.
+    fn get_in(mi: &MetaInfo) -> Option<&Self::Value> {
+        mi.get::<Slot<Self>>().map(|slot| &slot.0)
+    }
+
+    /// Sets the value in the type map of the [`MetaInfo`], which is not sent to the next hop.
+    fn set_in(value: Self::Value, mi: &mut MetaInfo) {
+        mi.insert(Slot::<Self>(value, PhantomData));
+    }
+
+    /// Writes the value to the transient part of the [`MetaInfo`], which is sent to the next hop.
+    fn to_transient(value: &Self::Value, mi: &mut MetaInfo) {
+        mi.set_transient(Self::as_wire_key(), Self::encode(value));
+    }
+
+    /// Reads the value from the transient part of the [`MetaInfo`].
+    fn from_transient(mi: &MetaInfo) -> Option<Self::Value> {
+        mi.get_transient(Self::as_wire_key())
+            .and_then(|s| Self::decode(s))
+    }
+
+    /// Reads the value sent by the upstream by [`to_transient`](Self::to_transient), which is in
+    /// the upstream part of the [`MetaInfo`] of the server.
+    fn from_upstream(mi: &MetaInfo) -> Option<Self::Value> {
+        mi.get_upstream(Self::as_wire_key())
+            .and_then(|s| Self::decode(s))
+    }
+}
+
+/// The wrapper of the value in the extensions, so that two keys of the same value type don't
+/// collide.
+struct Slot<K: TypedKey>(K::Value, PhantomData<fn() -> K>);
+
+/// Reads and writes the values of the [`TypedKey`]s in the context.
+pub trait TypedExt {
+    /// Returns the value of the key, or `None` if it is not set.
+    fn get_typed<K: TypedKey>(&self) -> Option<&K::Value>;
+
+    /// Returns the mutable value of the key, or `None` if it is not set.
+    fn get_typed_mut<K: TypedKey>(&mut self) -> Option<&mut K::Value>;
+
+    /// Sets the value of the key, replacing the old one.
+    fn set_typed<K: TypedKey>(&mut self, value: K::Value);
+
+    /// Removes the value of the key, and returns it if it was set.
+    fn remove_typed<K: TypedKey>(&mut self) -> Option<K::Value>;
+}
+
+impl<Cx: Context + ?Sized> TypedExt for Cx {
+    #[inline]
+    fn get_typed<K: TypedKey>(&self) -> Option<&K::Value> {
+        self.extensions().get::<Slot<K>>().map(|slot| &slot.0)
+    }
+
+    #[inline]
+    fn get_typed_mut<K: TypedKey>(&mut self) -> Option<&mut K::Value> {
+        self.extensions_mut()
+            .get_mut::<Slot<K>>()
+            .map(|slot| &mut slot.0)
+    }
+
+    #[inline]
+    fn set_typed<K: TypedKey>(&mut self, value: K::Value) {
+        self.extensions_mut().insert(Slot::<K>(value, PhantomData));
+    }
+
+    #[inline]
+    fn remove_typed<K: TypedKey>(&mut self) -> Option<K::Value> {
+        self.extensions_mut().remove::<Slot<K>>().map(|slot| slot.0)
+    }
+}
+
+/// Declares a [`TypedKey`] as a zero-sized type, whose value is serialized by its `Display` and
+/// `FromStr`.
+///
+/// The name on the wire is the name of the key, unless it is given after `=`.
+///
+/// ```rust,ignore
+/// volo::metainfo_key!(pub RETRY_ATTEMPT: u32);
+/// volo::metainfo_key!(pub(crate) TENANT: String = "tenant");
+/// ```
+#[macro_export]
+macro_rules! metainfo_key {
+    ($(#[$attrs:meta])* $vis:vis $name:ident: $ty:ty) => {
+        $crate::metainfo_key!($(#[$attrs])* $vis $name: $ty = stringify!($name));
+    };
+    ($(#[$attrs:meta])* $vis:vis $name:ident: $ty:ty = $wire:expr) => {
+        $(#[$attrs])*
+        #[allow(non_camel_case_types)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+        $vis struct $name;
+
+        impl $crate::context::typed::TypedKey for $name {
+            type Value = $ty;
+
+            const NAME: &'static str = $wire;
+
+            #[inline]
+            fn encode(value: &Self::Value) -> $crate::FastStr {
+                $crate::FastStr::new(::std::string::ToString::to_string(value))
+            }
+
+            #[inline]
+            fn decode(s: &str) -> ::std::option::Option<Self::Value> {
+                ::std::str::FromStr::from_str(s).ok()
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use motore::{layer::Layer, service::Service};
+
+    use super::*;
+    use crate::context::{Endpoint, Reusable, Role, RpcCx, RpcInfo};
+
+    #[derive(Debug, Default)]
+    struct Config;
+
+    impl Reusable for Config {
+        fn clear(&mut self) {}
+    }
+
+    type Cx = RpcCx<(), Config>;
+
+    fn cx() -> Cx {
+        RpcCx::new(
+            RpcInfo::new(
+                Role::Server,
+                "test".into(),
+                Endpoint::new("caller".into()),
+                Endpoint::new("callee".into()),
+                Config,
+            ),
+            (),
+        )
+    }
+
+    crate::metainfo_key!(ATTEMPT: u32);
+    crate::metainfo_key!(SEEN: u32 = "seen");
+    crate::metainfo_key!(TENANT: String = "tenant");
+
+    /// Sets the tenant and bumps the attempt before calling the inner.
+    struct Outer<S>(S);
+
+    impl<S: Service<Cx, (), Response = Option<u32>>> Service<Cx, ()> for Outer<S> {
+        type Response = Option<u32>;
+        type Error = S::Error;
+
+        async fn call<'s, 'cx>(
+            &'s self,
+            cx: &'cx mut Cx,
+            req: (),
+        ) -> Result<Option<u32>, S::Error> {
+            cx.set_typed::<TENANT>("foo".to_owned());
+            *cx.get_typed_mut::<ATTEMPT>().unwrap() += 1;
+            self.0.call(cx, req).await
+        }
+    }
+
+    struct OuterLayer;
+
+    impl<S> Layer<S> for OuterLayer {
+        type Service = Outer<S>;
+
+        fn layer(self, inner: S) -> Self::Service {
+            Outer(inner)
+        }
+    }
+
+    struct Inner;
+
+    impl Service<Cx, ()> for Inner {
+        type Response = Option<u32>;
+        type Error = ();
+
+        async fn call<'s, 'cx>(&'s self, cx: &'cx mut Cx, _req: ()) -> Result<Option<u32>, ()> {
+            assert_eq!(cx.get_typed::<TENANT>().map(|t| t.as_str()), Some("foo"));
+            // the keys of the same value type don't collide
+            assert_eq!(cx.get_typed::<SEEN>(), None);
+            cx.set_typed::<SEEN>(7);
+            Ok(cx.get_typed::<ATTEMPT>().copied())
+        }
+    }
+
+    #[test]
+    fn cross_layers() {
+        let svc = OuterLayer.layer(Inner);
+        let mut cx = cx();
+        cx.set_typed::<ATTEMPT>(1);
+
+        let attempt = futures::executor::block_on(svc.call(&mut cx, ())).unwrap();
+        assert_eq!(attempt, Some(2));
+        assert_eq!(cx.get_typed::<SEEN>(), Some(&7));
+        assert_eq!(cx.remove_typed::<SEEN>(), Some(7));
+        assert_eq!(cx.get_typed::<SEEN>(), None);
+    }
+
+    #[test]
+    fn recycled() {
+        let mut cx = cx();
+        cx.set_typed::<ATTEMPT>(3);
+        cx.set_typed::<TENANT>("foo".to_owned());
+        cx.reset(());
+        assert_eq!(cx.get_typed::<ATTEMPT>(), None);
+        assert_eq!(cx.get_typed::<TENANT>(), None);
+    }
+
+    #[test]
+    fn wire() {
+        assert_eq!(ATTEMPT::as_wire_key(), "ATTEMPT");
+        assert_eq!(TENANT::as_wire_key(), "tenant");
+
+        let mut mi = MetaInfo::default();
+        ATTEMPT::to_transient(&3, &mut mi);
+        assert_eq!(mi.get_transient("ATTEMPT").map(|s| s.as_str()), Some("3"));
+        assert_eq!(ATTEMPT::from_transient(&mi), Some(3));
+
+        mi.set_transient("seen", "not a number");
+        assert_eq!(SEEN::from_transient(&mi), None);
+
+        // received by the server
+        mi.set_upstream("ATTEMPT", "4");
+        assert_eq!(ATTEMPT::from_upstream(&mi), Some(4));
+    }
+
+    #[test]
+    fn metainfo() {
+        let mut mi = MetaInfo::default();
+        TENANT::set_in("foo".to_owned(), &mut mi);
+        SEEN::set_in(7, &mut mi);
+        assert_eq!(TENANT::get_in(&mi).map(String::as_str), Some("foo"));
+        assert_eq!(SEEN::get_in(&mi), Some(&7));
+        // not in the strings sent to the next hop
+        assert_eq!(ATTEMPT::get_in(&mi), None);
+        assert!(mi.get_transient("tenant").is_none());
+
+        // inherited from the parent
+        let child = MetaInfo::from(std::sync::Arc::new(mi));
+        assert_eq!(TENANT::get_in(&child).map(String::as_str), Some("foo"));
+    }
+}
@@ -0,0 +1,273 @@
+use std::{any::Any, sync::Arc};
+
+use motore::{layer::Layer, service::Service};
+
+use super::{Acquired, DedupCache, DedupConfig, DedupProtocol};
+use crate::context::Context;
+
+/// A layer that deduplicates the requests by the idempotency keys, see the
+/// [module docs](super).
+///
+/// The [`DedupCache`] is shared by all the clones of the service created by this layer.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use volo::dedup::{DedupConfig, DedupLayer};
+/// use volo_grpc::layer::dedup::GrpcDedup;
+///
+/// Server::new()
+///     .layer_front(DedupLayer::new(
+///         DedupConfig::new().ttl(Duration::from_secs(30)),
+///         GrpcDedup::new("x-idempotency-key"),
+///     ))
+///     .add_service(ServiceBuilder::new(OrderServer::new(S)).build())
+///     .run(addr)
+///     .await
+///     .unwrap();
+/// ```
+pub struct DedupLayer<P> {
+    cache: DedupCache<AnyCached>,
+    protocol: P,
+}
+
+/// The cached responses of the different protocols and methods are kept in the same cache.
+type AnyCached = Arc<dyn Any + Send + Sync>;
+
+impl<P> DedupLayer<P> {
+    pub fn new(config: DedupConfig, protocol: P) -> Self {
+        Self {
+            cache: DedupCache::new(config),
+            protocol,
+        }
+    }
+}
+
+impl<P: Clone> Clone for DedupLayer<P> {
+    fn clone(&self) -> Self {
+        Self {
+            cache: self.cache.clone(),
+            protocol: self.protocol.clone(),
+        }
+    }
+}
+
+impl<S, P> Layer<S> for DedupLayer<P> {
+    type Service = DedupService<S, P>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        DedupService {
+            inner,
+            cache: self.cache,
+            protocol: Arc::new(self.protocol),
+        }
+    }
+}
+
+/// The service created by [`DedupLayer`].
+pub struct DedupService<S, P> {
+    inner: S,
+    cache: DedupCache<AnyCached>,
+    protocol: Arc<P>,
+}
+
+impl<S: Clone, P> Clone for DedupService<S, P> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            cache: self.cache.clone(),
+            protocol: self.protocol.clone(),
+        }
+    }
+}
+
+impl<S, P> DedupService<S, P> {
+    fn restore<Cx, Req, Resp, E>(&self, cx: &mut Cx, cached: Option<AnyCached>) -> Result<Resp, E>
+    where
+        P: DedupProtocol<Cx, Req, Resp, E>,
+        Cx: Context,
+    {
+        match cached
+            .as_deref()
+            .and_then(|cached| cached.downcast_ref::<P::Cached>())
+        {
+            Some(cached) => Ok(self.protocol.restore(cx, cached)),
+            None => {
+                tracing::debug!(
+                    "[VOLO] duplicate request rejected, rpcinfo: {:?}",
+                    cx.rpc_info()
+                );
+                Err(self.protocol.retry_later(cx))
+            }
+        }
+    }
+}
+
+impl<Cx, Req, S, P> Service<Cx, Req> for DedupService<S, P>
+where
+    Cx: Context + Send + 'static,
+    Req: Send + 'static,
+    S: Service<Cx, Req> + Send + Sync + 'static,
+    S::Response: Send,
+    P: DedupProtocol<Cx, Req, S::Response, S::Error>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call<'s, 'cx>(
+        &'s self,
+        cx: &'cx mut Cx,
+        req: Req,
+    ) -> Result<Self::Response, Self::Error> {
+        let Some(key) = self.protocol.key(cx, &req) else {
+            return self.inner.call(cx, req).await;
+        };
+        // the same key of different methods are different requests
+        let key = format!("{}/{key}", cx.rpc_info().method()).into();
+
+        match self.cache.acquire(key) {
+            Acquired::Done(cached) => self.restore::<_, Req, _, _>(cx, Some(cached)),
+            Acquired::Wait(waiter) => {
+                let cached = waiter.wait().await;
+                self.restore::<_, Req, _, _>(cx, cached)
+            }
+            Acquired::Leader(leader) => {
+                // the leader is dropped if the request fails, so the waiters retry later
+                let resp = self.inner.call(cx, req).await?;
+                let (resp, cached) = self
+                    .protocol
+                    .cache(resp, self.cache.config().get_max_response_size())
+                    .await;
+                leader.complete(cached.map(|cached| Arc::new(cached) as AnyCached));
+                Ok(resp)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use faststr::FastStr;
+
+    use super::*;
+    use crate::context::{Endpoint, Reusable, Role, RpcCx, RpcInfo};
+
+    #[derive(Debug, Default)]
+    struct Config;
+
+    impl Reusable for Config {
+        fn clear(&mut self) {}
+    }
+
+    type Cx = RpcCx<(), Config>;
+
+    fn cx() -> Cx {
+        RpcCx::new(
+            RpcInfo::new(
+                Role::Server,
+                "create".into(),
+                Endpoint::new("caller".into()),
+                Endpoint::new("callee".into()),
+                Config,
+            ),
+            (),
+        )
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct RetryLater;
+
+    /// The key is the request, and the response is its length.
+    struct Protocol;
+
+    impl DedupProtocol<Cx, &'static str, usize, RetryLater> for Protocol {
+        type Cached = usize;
+
+        fn key(&self, _cx: &Cx, req: &&'static str) -> Option<FastStr> {
+            (!req.is_empty()).then(|| FastStr::new(*req))
+        }
+
+        async fn cache(&self, resp: usize, max_size: usize) -> (usize, Option<usize>) {
+            (resp, (resp <= max_size).then_some(resp))
+        }
+
+        fn restore(&self, _cx: &mut Cx, cached: &usize) -> usize {
+            *cached
+        }
+
+        fn retry_later(&self, _cx: &mut Cx) -> RetryLater {
+            RetryLater
+        }
+    }
+
+    #[derive(Default)]
+    struct Handler {
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl Service<Cx, &'static str> for Handler {
+        type Response = usize;
+        type Error = RetryLater;
+
+        async fn call<'s, 'cx>(
+            &'s self,
+            _cx: &'cx mut Cx,
+            req: &'static str,
+        ) -> Result<usize, RetryLater> {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            Ok(req.len())
+        }
+    }
+
+    async fn call_concurrently(
+        svc: &DedupService<Handler, Protocol>,
+        req: &'static str,
+        n: usize,
+    ) -> Vec<Result<usize, RetryLater>> {
+        futures::future::join_all((0..n).map(|_| async move {
+            let mut cx = cx();
+            svc.call(&mut cx, req).await
+        }))
+        .await
+    }
+
+    #[tokio::test]
+    async fn concurrent_duplicates() {
+        let svc = DedupLayer::new(DedupConfig::new(), Protocol).layer(Handler::default());
+        let calls = svc.inner.calls.clone();
+
+        let results = call_concurrently(&svc, "order-1", 16).await;
+        assert!(results.iter().all(|r| r == &Ok(7)));
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+        // the finished one is served from the cache
+        assert_eq!(svc.call(&mut cx(), "order-1").await, Ok(7));
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+        // the requests without keys are not deduplicated
+        call_concurrently(&svc, "", 4).await;
+        assert_eq!(calls.load(Ordering::Relaxed), 5);
+    }
+
+    #[tokio::test]
+    async fn large_response() {
+        let svc = DedupLayer::new(DedupConfig::new().max_response_size(4), Protocol)
+            .layer(Handler::default());
+        let calls = svc.inner.calls.clone();
+
+        let results = call_concurrently(&svc, "order-1", 4).await;
+        assert_eq!(results[0], Ok(7));
+        assert!(results[1..].iter().all(|r| r == &Err(RetryLater)));
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+        // not cached, so the retry is handled again
+        assert_eq!(svc.call(&mut cx(), "order-1").await, Ok(7));
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+    }
+}
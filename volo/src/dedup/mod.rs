@@ -0,0 +1,388 @@
+//! Deduplication of the requests of the servers by the idempotency keys, so the retries of a
+//! non-idempotent call don't execute the handler twice.
+//!
+//! The client puts an idempotency key into the request, e.g. a gRPC metadata or a TTHeader key,
+//! whose name is configurable. The [`DedupLayer`] keeps the responses of the keys in a bounded
+//! [`DedupCache`], and for a duplicate request:
+//! - if the original one has finished, the cached response is returned;
+//! - if the original one is in flight, it waits for its response instead of calling the handler.
+//!
+//! Only the successful responses not larger than [`DedupConfig::max_response_size`] are cached. If
+//! the original one fails, is cancelled, or its response is too large, the waiting duplicates are
+//! rejected by a "retry later" error, and the next request of the key calls the handler again.
+//!
+//! The keys are scoped by the method, and expire after [`DedupConfig::ttl`]. When the cache is
+//! full, the least recently used key is evicted.
+//!
+//! The protocol specific [`DedupProtocol`]s are provided in `volo-thrift` and `volo-grpc`.
+
+mod layer;
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use faststr::FastStr;
+use tokio::sync::watch;
+
+pub use self::layer::{DedupLayer, DedupService};
+
+const DEFAULT_CAPACITY: usize = 10_000;
+const DEFAULT_TTL: Duration = Duration::from_secs(60);
+const DEFAULT_MAX_RESPONSE_SIZE: usize = 64 * 1024;
+
+/// The config of the [`DedupCache`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DedupConfig {
+    capacity: usize,
+    ttl: Duration,
+    max_response_size: usize,
+}
+
+impl Default for DedupConfig {
+    fn default() -> Self {
+        Self {
+            capacity: DEFAULT_CAPACITY,
+            ttl: DEFAULT_TTL,
+            max_response_size: DEFAULT_MAX_RESPONSE_SIZE,
+        }
+    }
+}
+
+impl DedupConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum number of the keys kept, including the ones in flight.
+    ///
+    /// Default is `10000`.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// Sets how long the response of a key is kept after it finishes.
+    ///
+    /// Default is `60s`.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Sets the maximum size of a response to be cached in bytes.
+    ///
+    /// Default is `64KiB`.
+    pub fn max_response_size(mut self, size: usize) -> Self {
+        self.max_response_size = size;
+        self
+    }
+
+    pub fn get_max_response_size(&self) -> usize {
+        self.max_response_size
+    }
+}
+
+/// Adapts the [`DedupLayer`] to a protocol.
+pub trait DedupProtocol<Cx, Req, Resp, E>: Send + Sync {
+    /// The response kept in the cache.
+    type Cached: Clone + Send + Sync + 'static;
+
+    /// Returns the idempotency key of the request, or `None` if the request should not be
+    /// deduplicated.
+    fn key(&self, cx: &Cx, req: &Req) -> Option<FastStr>;
+
+    /// Returns the response to be sent, and the one to be cached if the response is successful
+    /// and not larger than `max_size`.
+    fn cache(
+        &self,
+        resp: Resp,
+        max_size: usize,
+    ) -> impl Future<Output = (Resp, Option<Self::Cached>)> + Send;
+
+    /// Restores the response of a duplicate request from the cache.
+    fn restore(&self, cx: &mut Cx, cached: &Self::Cached) -> Resp;
+
+    /// The error of a duplicate request whose original one fails or is not cacheable, which
+    /// should tell the client to retry later.
+    fn retry_later(&self, cx: &mut Cx) -> E;
+}
+
+enum Slot<T> {
+    InFlight {
+        id: u64,
+        done: watch::Receiver<Option<Option<T>>>,
+    },
+    Done {
+        value: T,
+        expires_at: Instant,
+    },
+}
+
+struct Entry<T> {
+    slot: Slot<T>,
+    tick: u64,
+}
+
+struct State<T> {
+    entries: HashMap<FastStr, Entry<T>>,
+    /// The keys ordered by the last time they were used.
+    lru: BTreeMap<u64, FastStr>,
+    tick: u64,
+}
+
+impl<T> State<T> {
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.lru.remove(&entry.tick);
+        }
+    }
+
+    fn insert(&mut self, key: FastStr, slot: Slot<T>, capacity: usize) {
+        self.remove(&key);
+        while self.entries.len() >= capacity {
+            let Some((_, oldest)) = self.lru.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+        let tick = self.next_tick();
+        self.lru.insert(tick, key.clone());
+        self.entries.insert(key, Entry { slot, tick });
+    }
+}
+
+/// The result of [`DedupCache::acquire`].
+pub enum Acquired<T> {
+    /// The key is new, and the request should be handled.
+    Leader(Leader<T>),
+    /// The original request has finished with the response.
+    Done(T),
+    /// The original request is in flight.
+    Wait(Waiter<T>),
+}
+
+/// The bounded LRU cache of the responses of the idempotency keys with TTL.
+///
+/// The clones of a cache share the same entries.
+pub struct DedupCache<T> {
+    config: DedupConfig,
+    state: Arc<Mutex<State<T>>>,
+}
+
+impl<T> Clone for DedupCache<T> {
+    fn clone(&self) -> Self {
+        Self {
+            config: self.config,
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<T: Clone> DedupCache<T> {
+    pub fn new(config: DedupConfig) -> Self {
+        Self {
+            config,
+            state: Arc::new(Mutex::new(State {
+                entries: HashMap::new(),
+                lru: BTreeMap::new(),
+                tick: 0,
+            })),
+        }
+    }
+
+    pub fn config(&self) -> &DedupConfig {
+        &self.config
+    }
+
+    /// Returns the number of the keys kept.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Looks up the key, and makes the caller the leader of the key if it's new or expired.
+    pub fn acquire(&self, key: FastStr) -> Acquired<T> {
+        let mut state = self.state.lock().unwrap();
+        let tick = state.next_tick();
+        if let Some(entry) = state.entries.get_mut(&key) {
+            let acquired = match &entry.slot {
+                Slot::InFlight { done, .. } => Some(Acquired::Wait(Waiter { done: done.clone() })),
+                Slot::Done { value, expires_at } if Instant::now() < *expires_at => {
+                    Some(Acquired::Done(value.clone()))
+                }
+                Slot::Done { .. } => None,
+            };
+            if let Some(acquired) = acquired {
+                let last = std::mem::replace(&mut entry.tick, tick);
+                state.lru.remove(&last);
+                state.lru.insert(tick, key);
+                return acquired;
+            }
+        }
+
+        let (sender, done) = watch::channel(None);
+        let id = state.next_tick();
+        state.insert(
+            key.clone(),
+            Slot::InFlight { id, done },
+            self.config.capacity,
+        );
+        Acquired::Leader(Leader {
+            cache: self.clone(),
+            key,
+            id,
+            sender,
+            completed: false,
+        })
+    }
+}
+
+impl<T> DedupCache<T> {
+    fn complete(&self, key: &FastStr, id: u64, value: Option<T>) {
+        let mut state = self.state.lock().unwrap();
+        // the entry may have been evicted, or replaced after being evicted
+        let ours = state
+            .entries
+            .get(key)
+            .is_some_and(|entry| matches!(entry.slot, Slot::InFlight { id: i, .. } if i == id));
+        if !ours {
+            return;
+        }
+        match value {
+            Some(value) => {
+                let slot = Slot::Done {
+                    value,
+                    expires_at: Instant::now() + self.config.ttl,
+                };
+                state.insert(key.clone(), slot, self.config.capacity);
+            }
+            None => state.remove(key),
+        }
+    }
+}
+
+/// The handler of a new key, which should [`complete`](Leader::complete) it after the request is
+/// handled.
+///
+/// Dropping it without completing, e.g. when the request fails or is cancelled, removes the key,
+/// and the waiting duplicates are told to retry later.
+pub struct Leader<T> {
+    cache: DedupCache<T>,
+    key: FastStr,
+    id: u64,
+    sender: watch::Sender<Option<Option<T>>>,
+    completed: bool,
+}
+
+impl<T: Clone> Leader<T> {
+    /// Completes the key with the response to be cached, or `None` if it's not cacheable.
+    pub fn complete(mut self, value: Option<T>) {
+        self.completed = true;
+        self.cache.complete(&self.key, self.id, value.clone());
+        let _ = self.sender.send(Some(value));
+    }
+}
+
+impl<T> Drop for Leader<T> {
+    fn drop(&mut self) {
+        if !self.completed {
+            self.cache.complete(&self.key, self.id, None);
+            let _ = self.sender.send(Some(None));
+        }
+    }
+}
+
+/// Waits for the request in flight of the same key.
+pub struct Waiter<T> {
+    done: watch::Receiver<Option<Option<T>>>,
+}
+
+impl<T: Clone> Waiter<T> {
+    /// Returns the cached response of the original request, or `None` if it's not cacheable.
+    pub async fn wait(mut self) -> Option<T> {
+        loop {
+            if let Some(done) = &*self.done.borrow_and_update() {
+                return done.clone();
+            }
+            if self.done.changed().await.is_err() {
+                return None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(capacity: usize, ttl: Duration) -> DedupCache<u32> {
+        DedupCache::new(DedupConfig::new().capacity(capacity).ttl(ttl))
+    }
+
+    #[tokio::test]
+    async fn leader_and_waiters() {
+        let cache = cache(16, Duration::from_secs(60));
+        let Acquired::Leader(leader) = cache.acquire("a".into()) else {
+            panic!("the first one should lead");
+        };
+        let Acquired::Wait(waiter) = cache.acquire("a".into()) else {
+            panic!("the duplicate should wait");
+        };
+        let waiting = tokio::spawn(waiter.wait());
+        leader.complete(Some(1));
+        assert_eq!(waiting.await.unwrap(), Some(1));
+        assert!(matches!(cache.acquire("a".into()), Acquired::Done(1)));
+
+        // a failed leader removes the key
+        let Acquired::Leader(leader) = cache.acquire("b".into()) else {
+            panic!("the first one should lead");
+        };
+        let Acquired::Wait(waiter) = cache.acquire("b".into()) else {
+            panic!("the duplicate should wait");
+        };
+        drop(leader);
+        assert_eq!(waiter.wait().await, None);
+        assert!(matches!(cache.acquire("b".into()), Acquired::Leader(_)));
+    }
+
+    #[test]
+    fn ttl_and_lru() {
+        let cache = cache(2, Duration::ZERO);
+        let Acquired::Leader(leader) = cache.acquire("a".into()) else {
+            panic!("the first one should lead");
+        };
+        leader.complete(Some(1));
+        // expired
+        assert!(matches!(cache.acquire("a".into()), Acquired::Leader(_)));
+
+        let cache = self::cache(2, Duration::from_secs(60));
+        for (key, value) in [("a", 1), ("b", 2)] {
+            let Acquired::Leader(leader) = cache.acquire(key.into()) else {
+                panic!("the first one should lead");
+            };
+            leader.complete(Some(value));
+        }
+        // `a` is used more recently than `b`
+        assert!(matches!(cache.acquire("a".into()), Acquired::Done(1)));
+        let Acquired::Leader(leader) = cache.acquire("c".into()) else {
+            panic!("the first one should lead");
+        };
+        leader.complete(Some(3));
+        assert_eq!(cache.len(), 2);
+        assert!(matches!(cache.acquire("a".into()), Acquired::Done(1)));
+        assert!(matches!(cache.acquire("c".into()), Acquired::Done(3)));
+        assert!(matches!(cache.acquire("b".into()), Acquired::Leader(_)));
+    }
+}
@@ -0,0 +1,188 @@
+use std::{collections::HashMap, sync::Arc};
+
+use motore::{layer::Layer, service::Service};
+
+use super::MethodDescriptor;
+use crate::context::Context;
+
+/// A layer that inserts the [`MethodDescriptor`] of each request into the extensions of the
+/// context, see [`MethodDescriptorExt`](super::MethodDescriptorExt).
+///
+/// The descriptors are indexed by their paths when the layer is created, so each request costs
+/// only one lookup of the hash map.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use volo::descriptor::DescriptorLayer;
+///
+/// Server::new()
+///     .layer_front(DescriptorLayer::new(volo_gen::registry().methods()))
+///     .add_service(ServiceBuilder::new(GreeterServer::new(S)).build())
+///     .run(addr)
+///     .await
+///     .unwrap();
+/// ```
+#[derive(Clone)]
+pub struct DescriptorLayer {
+    index: Arc<HashMap<&'static str, &'static MethodDescriptor>>,
+}
+
+impl DescriptorLayer {
+    pub fn new(methods: impl IntoIterator<Item = &'static MethodDescriptor>) -> Self {
+        Self {
+            index: Arc::new(methods.into_iter().map(|m| (m.path, m)).collect()),
+        }
+    }
+}
+
+impl<S> Layer<S> for DescriptorLayer {
+    type Service = DescriptorService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        DescriptorService {
+            inner,
+            index: self.index,
+        }
+    }
+}
+
+/// The service created by [`DescriptorLayer`].
+#[derive(Clone)]
+pub struct DescriptorService<S> {
+    inner: S,
+    index: Arc<HashMap<&'static str, &'static MethodDescriptor>>,
+}
+
+impl<Cx, Req, S> Service<Cx, Req> for DescriptorService<S>
+where
+    Cx: Context + Send + 'static,
+    Req: Send + 'static,
+    S: Service<Cx, Req> + Send + Sync + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call<'s, 'cx>(
+        &'s self,
+        cx: &'cx mut Cx,
+        req: Req,
+    ) -> Result<Self::Response, Self::Error> {
+        if let Some(descriptor) = self.index.get(cx.rpc_info().method().as_str()) {
+            cx.extensions_mut().insert(*descriptor);
+        }
+        self.inner.call(cx, req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        context::{Endpoint, Reusable, Role, RpcCx, RpcInfo},
+        descriptor::MethodDescriptorExt,
+    };
+
+    #[derive(Debug, Default)]
+    struct Config;
+
+    impl Reusable for Config {
+        fn clear(&mut self) {}
+    }
+
+    type Cx = RpcCx<(), Config>;
+
+    fn cx(method: &'static str) -> Cx {
+        RpcCx::new(
+            RpcInfo::new(
+                Role::Server,
+                method.into(),
+                Endpoint::new("caller".into()),
+                Endpoint::new("callee".into()),
+                Config,
+            ),
+            (),
+        )
+    }
+
+    const fn method(
+        name: &'static str,
+        annotations: &'static [(&'static str, &'static str)],
+    ) -> MethodDescriptor {
+        MethodDescriptor {
+            service: "Item",
+            name,
+            path: name,
+            client_streaming: false,
+            server_streaming: false,
+            request_type: "volo_gen::item::GetItemRequest",
+            response_type: "volo_gen::item::GetItemResponse",
+            annotations,
+            request_schema: None,
+        }
+    }
+
+    static METHODS: &[MethodDescriptor] = &[
+        method("GetItem", &[("auth", "public"), ("idempotent", "true")]),
+        method("SetItem", &[("auth", "admin")]),
+        method("DeleteItem", &[]),
+    ];
+
+    #[derive(Debug, PartialEq)]
+    struct Denied(&'static str);
+
+    /// Denies the methods without the `auth = "public"` annotation.
+    struct PublicOnly<S>(S);
+
+    impl<S> Service<Cx, ()> for PublicOnly<S>
+    where
+        S: Service<Cx, (), Error = Denied> + Send + Sync,
+    {
+        type Response = S::Response;
+        type Error = Denied;
+
+        async fn call(&self, cx: &mut Cx, req: ()) -> Result<Self::Response, Self::Error> {
+            match cx.method_descriptor() {
+                Some(m) if m.annotation("auth") == Some("public") => self.0.call(cx, req).await,
+                Some(m) => Err(Denied(m.name)),
+                None => Err(Denied("unknown")),
+            }
+        }
+    }
+
+    struct Handler;
+
+    impl Service<Cx, ()> for Handler {
+        type Response = ();
+        type Error = Denied;
+
+        async fn call(&self, _cx: &mut Cx, _req: ()) -> Result<(), Denied> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn deny_non_public_methods() {
+        let service = DescriptorLayer::new(METHODS).layer(PublicOnly(Handler));
+
+        assert!(service.call(&mut cx("GetItem"), ()).await.is_ok());
+        assert_eq!(
+            service.call(&mut cx("SetItem"), ()).await,
+            Err(Denied("SetItem"))
+        );
+        assert_eq!(
+            service.call(&mut cx("DeleteItem"), ()).await,
+            Err(Denied("DeleteItem"))
+        );
+        assert_eq!(
+            service.call(&mut cx("Unknown"), ()).await,
+            Err(Denied("unknown"))
+        );
+
+        let mut cx = cx("GetItem");
+        service.call(&mut cx, ()).await.unwrap();
+        let descriptor = cx.method_descriptor().unwrap();
+        assert_eq!(descriptor.annotation("idempotent"), Some("true"));
+        assert!(!descriptor.is_streaming());
+    }
+}
@@ -0,0 +1,180 @@
+//! The metadata of the services and methods generated by `volo-build`, for the generic
+//! middlewares deciding by the methods, e.g. logging allowlists or authorization policies.
+//!
+//! The generated code has a `pub static {SERVICE}_METHODS: &[MethodDescriptor]` and a
+//! `pub static {SERVICE}_SERVICE: ServiceDescriptor` for each service, and a crate level
+//! `volo_gen::registry()` of all the services.
+//!
+//! The thrift methods also have the [`StructSchema`] of their arguments generated from the IDL,
+//! e.g. for validating the requests before they are decoded.
+//!
+//! The [`DescriptorLayer`] finds the descriptor of each request by an index built when the server
+//! is built, and inserts it into the extensions of the context, where the layers after it can
+//! get it by [`MethodDescriptorExt`].
+
+mod layer;
+
+use std::{fmt, ptr};
+
+pub use self::layer::{DescriptorLayer, DescriptorService};
+use crate::context::Context;
+
+/// The metadata of a method in the IDL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MethodDescriptor {
+    /// The name of the service in the IDL, e.g. `helloworld.Greeter` for gRPC.
+    pub service: &'static str,
+    /// The name of the method in the IDL, e.g. `SayHello`.
+    pub name: &'static str,
+    /// The method in the `RpcInfo` of the servers, which is `/{service}/{method}` for gRPC and
+    /// the name of the method for thrift.
+    pub path: &'static str,
+    pub client_streaming: bool,
+    pub server_streaming: bool,
+    /// The Rust type of the request in the generated code, e.g. `HelloRequest`, which is the
+    /// struct of the arguments for thrift.
+    pub request_type: &'static str,
+    /// The Rust type of the response in the generated code.
+    pub response_type: &'static str,
+    /// The annotations of the method in the IDL, in their order in the IDL.
+    ///
+    /// These are the thrift annotations, e.g. `(auth = "public")`, and the protobuf methods
+    /// have none, as the custom options are not kept by the parser.
+    pub annotations: &'static [(&'static str, &'static str)],
+    /// The schema of the struct of the arguments, which is only generated for thrift.
+    pub request_schema: Option<&'static StructSchema>,
+}
+
+impl MethodDescriptor {
+    /// Returns the value of the first annotation of the key.
+    pub fn annotation(&self, key: &str) -> Option<&'static str> {
+        self.annotations
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| *v)
+    }
+
+    pub fn is_streaming(&self) -> bool {
+        self.client_streaming || self.server_streaming
+    }
+}
+
+impl fmt::Display for MethodDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.path)
+    }
+}
+
+/// The declared fields of a struct in the IDL, including the unions whose fields are the
+/// variants.
+///
+/// The schemas may be recursive, so they are compared by their addresses, and the [`Debug`] of a
+/// field of a struct type only shows the name of the struct.
+#[derive(Debug)]
+pub struct StructSchema {
+    pub name: &'static str,
+    pub fields: &'static [FieldSchema],
+}
+
+impl PartialEq for StructSchema {
+    fn eq(&self, other: &Self) -> bool {
+        ptr::eq(self, other)
+    }
+}
+
+impl Eq for StructSchema {}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct FieldSchema {
+    pub id: i16,
+    pub required: bool,
+    pub ty: TypeSchema,
+}
+
+/// The declared values of an enum in the IDL.
+#[derive(Debug, PartialEq, Eq)]
+pub struct EnumSchema {
+    pub name: &'static str,
+    pub values: &'static [i32],
+}
+
+/// The type of a field in a [`StructSchema`].
+#[derive(PartialEq, Eq)]
+pub enum TypeSchema {
+    /// A type without a schema, e.g. the base types.
+    Value,
+    Struct(&'static StructSchema),
+    Enum(&'static EnumSchema),
+    List(&'static TypeSchema),
+    Set(&'static TypeSchema),
+    Map(&'static TypeSchema, &'static TypeSchema),
+}
+
+impl fmt::Debug for TypeSchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Value => f.write_str("Value"),
+            Self::Struct(s) => f.debug_tuple("Struct").field(&s.name).finish(),
+            Self::Enum(e) => f.debug_tuple("Enum").field(&e.name).finish(),
+            Self::List(ty) => f.debug_tuple("List").field(ty).finish(),
+            Self::Set(ty) => f.debug_tuple("Set").field(ty).finish(),
+            Self::Map(k, v) => f.debug_tuple("Map").field(k).field(v).finish(),
+        }
+    }
+}
+
+/// The metadata of a service in the IDL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServiceDescriptor {
+    /// The name of the service in the IDL, e.g. `helloworld.Greeter` for gRPC.
+    pub name: &'static str,
+    pub methods: &'static [MethodDescriptor],
+}
+
+impl ServiceDescriptor {
+    pub fn method(&self, name: &str) -> Option<&'static MethodDescriptor> {
+        self.methods.iter().find(|m| m.name == name)
+    }
+}
+
+/// All the services generated in a crate, returned by `volo_gen::registry()`.
+#[derive(Debug, Clone, Copy)]
+pub struct Registry {
+    services: &'static [&'static ServiceDescriptor],
+}
+
+impl Registry {
+    pub const fn new(services: &'static [&'static ServiceDescriptor]) -> Self {
+        Self { services }
+    }
+
+    pub fn services(&self) -> &'static [&'static ServiceDescriptor] {
+        self.services
+    }
+
+    pub fn service(&self, name: &str) -> Option<&'static ServiceDescriptor> {
+        self.services.iter().find(|s| s.name == name).copied()
+    }
+
+    /// The methods of all the services.
+    pub fn methods(&self) -> impl Iterator<Item = &'static MethodDescriptor> {
+        let services = self.services;
+        services.iter().flat_map(|s| s.methods.iter())
+    }
+}
+
+/// Accesses the [`MethodDescriptor`] in the extensions of the context, which is inserted by the
+/// [`DescriptorLayer`].
+pub trait MethodDescriptorExt {
+    /// Returns the descriptor of the method of the request, or `None` if the method is unknown
+    /// or there is no [`DescriptorLayer`] before.
+    fn method_descriptor(&self) -> Option<&'static MethodDescriptor>;
+}
+
+impl<Cx: Context + ?Sized> MethodDescriptorExt for Cx {
+    fn method_descriptor(&self) -> Option<&'static MethodDescriptor> {
+        self.extensions()
+            .get::<&'static MethodDescriptor>()
+            .copied()
+    }
+}
@@ -0,0 +1,658 @@
+//! A [`Discover`] reading from two discovers and comparing them, for migrating from one registry
+//! to another safely.
+//!
+//! The instances are served from the primary one, and the ones of the secondary are only
+//! compared with them. When the instances of a service diverge beyond the tolerance, the
+//! divergence is logged, counted and published as [`Event::DiscoveryDiverged`] once until it
+//! changes again.
+//!
+//! The primary can be switched at runtime by [`CompareDiscover::set_primary`], which notifies the
+//! load balancers with only the instances added, updated and removed between the two, so the
+//! connections pooled to the instances in both are kept.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use volo::discovery::{CompareDiscover, Side};
+//!
+//! let discover = CompareDiscover::new(consul, registry)
+//!     .tolerance(1)
+//!     .event_bus(bus.clone());
+//! let client = volo_gen::proto_gen::hello::GreeterClientBuilder::new("hello")
+//!     .discover(discover.clone())
+//!     .build()?;
+//!
+//! // after the new registry is verified
+//! discover.set_primary(Side::Second);
+//! ```
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, Weak,
+    },
+};
+
+use async_broadcast::{InactiveReceiver, Receiver, Sender};
+use faststr::FastStr;
+
+use super::{diff, Change, Discover, Instance};
+use crate::{
+    context::Endpoint,
+    event::{Event, EventBus},
+    loadbalance::error::LoadBalanceError,
+    net::Address,
+};
+
+const CHANNEL_CAPACITY: usize = 16;
+
+/// One of the two discovers of a [`CompareDiscover`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Side {
+    First,
+    Second,
+}
+
+impl Side {
+    fn other(self) -> Self {
+        match self {
+            Self::First => Self::Second,
+            Self::Second => Self::First,
+        }
+    }
+}
+
+/// The difference between the instances of a service discovered by the primary and the
+/// secondary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub service: FastStr,
+    pub only_in_primary: Vec<Address>,
+    pub only_in_secondary: Vec<Address>,
+    /// The instances in both with different weights, and the weights in the primary and the
+    /// secondary.
+    pub weight_mismatches: Vec<(Address, u32, u32)>,
+}
+
+impl Divergence {
+    fn new(service: FastStr, primary: &[Arc<Instance>], secondary: &[Arc<Instance>]) -> Self {
+        let weights = |instances: &[Arc<Instance>]| {
+            instances
+                .iter()
+                .map(|i| (i.address.clone(), i.weight))
+                .collect::<HashMap<_, _>>()
+        };
+        let (primary_weights, secondary_weights) = (weights(primary), weights(secondary));
+
+        let mut only_in_primary = Vec::new();
+        let mut weight_mismatches = Vec::new();
+        for i in primary {
+            match secondary_weights.get(&i.address) {
+                None => only_in_primary.push(i.address.clone()),
+                Some(&weight) if weight != i.weight => {
+                    weight_mismatches.push((i.address.clone(), i.weight, weight))
+                }
+                Some(_) => {}
+            }
+        }
+        let only_in_secondary = secondary
+            .iter()
+            .filter(|i| !primary_weights.contains_key(&i.address))
+            .map(|i| i.address.clone())
+            .collect();
+        Self {
+            service,
+            only_in_primary,
+            only_in_secondary,
+            weight_mismatches,
+        }
+    }
+
+    /// The number of the instances only in one of the discovers.
+    pub fn missing(&self) -> usize {
+        self.only_in_primary.len() + self.only_in_secondary.len()
+    }
+}
+
+/// A discover serving the instances from the primary of two discovers, and comparing them with
+/// the secondary, see the [module level documentation](self).
+///
+/// The clones share the primary and the state of the comparison.
+pub struct CompareDiscover<A, B: Discover> {
+    first: A,
+    second: B,
+    shared: Arc<Shared<B::Key>>,
+}
+
+struct Shared<K> {
+    tolerance: usize,
+    events: Option<EventBus>,
+    state: Mutex<State<K>>,
+    divergences: AtomicU64,
+    watching: AtomicBool,
+    sender: Sender<Change<K>>,
+    // keeps the channel open when there is no receiver
+    _receiver: InactiveReceiver<Change<K>>,
+}
+
+struct State<K> {
+    primary: Side,
+    keys: HashMap<K, KeyState>,
+}
+
+#[derive(Default)]
+struct KeyState {
+    service: FastStr,
+    first: Option<Vec<Arc<Instance>>>,
+    second: Option<Vec<Arc<Instance>>>,
+    /// The instances last served to the load balancer.
+    served: Vec<Arc<Instance>>,
+    /// The divergence last reported, which is not reported again until it changes.
+    diverged: Option<Divergence>,
+}
+
+impl KeyState {
+    fn instances(&mut self, side: Side) -> &mut Option<Vec<Arc<Instance>>> {
+        match side {
+            Side::First => &mut self.first,
+            Side::Second => &mut self.second,
+        }
+    }
+}
+
+impl<A, B> CompareDiscover<A, B>
+where
+    A: Discover,
+    B: Discover<Key = A::Key>,
+{
+    /// Creates a discover serving the instances from `first`, and comparing them with `second`.
+    pub fn new(first: A, second: B) -> Self {
+        let (mut sender, receiver) = async_broadcast::broadcast(CHANNEL_CAPACITY);
+        sender.set_overflow(true);
+        sender.set_await_active(false);
+        Self {
+            first,
+            second,
+            shared: Arc::new(Shared {
+                tolerance: 0,
+                events: None,
+                state: Mutex::new(State {
+                    primary: Side::First,
+                    keys: HashMap::new(),
+                }),
+                divergences: AtomicU64::new(0),
+                watching: AtomicBool::new(false),
+                sender,
+                _receiver: receiver.deactivate(),
+            }),
+        }
+    }
+
+    /// Sets the number of the instances only in one of the discovers which is tolerated, while
+    /// the different weights are never tolerated.
+    ///
+    /// This should be set before the discover is cloned.
+    ///
+    /// Default is `0`.
+    pub fn tolerance(mut self, tolerance: usize) -> Self {
+        self.shared_mut().tolerance = tolerance;
+        self
+    }
+
+    /// Publishes the divergences as [`Event::DiscoveryDiverged`] to the bus.
+    ///
+    /// This should be set before the discover is cloned.
+    pub fn event_bus(mut self, bus: EventBus) -> Self {
+        self.shared_mut().events = Some(bus);
+        self
+    }
+
+    fn shared_mut(&mut self) -> &mut Shared<A::Key> {
+        Arc::get_mut(&mut self.shared).expect("CompareDiscover is configured after cloned")
+    }
+
+    pub fn primary(&self) -> Side {
+        self.shared.state.lock().unwrap().primary
+    }
+
+    /// Switches the primary, and notifies the watchers with the differences between the
+    /// instances served before and the ones of the new primary.
+    ///
+    /// The services never discovered by the new primary, e.g. because of its errors, are still
+    /// served by the instances before until the new primary changes.
+    pub fn set_primary(&self, primary: Side) {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.primary == primary {
+            return;
+        }
+        tracing::info!("[VOLO] CompareDiscover: switch the primary to {primary:?}");
+        state.primary = primary;
+        for (key, entry) in state.keys.iter_mut() {
+            let Some(instances) = entry.instances(primary).clone() else {
+                tracing::warn!(
+                    "[VOLO] CompareDiscover: keep the instances of {}, which are not discovered \
+                     by {primary:?}",
+                    entry.service
+                );
+                continue;
+            };
+            let served = std::mem::replace(&mut entry.served, instances.clone());
+            if let Some(change) = diff(key.clone(), served, instances) {
+                let _ = self.shared.sender.try_broadcast(change);
+            }
+            // the primary and the secondary are swapped
+            entry.diverged = None;
+            self.shared.compare(primary, entry);
+        }
+    }
+
+    /// Returns the number of the divergences reported.
+    pub fn divergences(&self) -> u64 {
+        self.shared.divergences.load(Ordering::Relaxed)
+    }
+
+    /// Returns the divergences of the services currently diverging.
+    pub fn diverged(&self) -> Vec<Divergence> {
+        let state = self.shared.state.lock().unwrap();
+        state
+            .keys
+            .values()
+            .filter_map(|entry| entry.diverged.clone())
+            .collect()
+    }
+}
+
+impl<K: Clone> Shared<K> {
+    /// Compares the instances of the primary and the secondary, and reports the divergence if
+    /// it's beyond the tolerance and differs from the last one.
+    fn compare(&self, primary: Side, entry: &mut KeyState) {
+        let (Some(first), Some(second)) = (&entry.first, &entry.second) else {
+            return;
+        };
+        let (primary_instances, secondary_instances) = match primary {
+            Side::First => (first, second),
+            Side::Second => (second, first),
+        };
+        let divergence = Divergence::new(
+            entry.service.clone(),
+            primary_instances,
+            secondary_instances,
+        );
+        if divergence.missing() <= self.tolerance && divergence.weight_mismatches.is_empty() {
+            if entry.diverged.take().is_some() {
+                tracing::info!(
+                    "[VOLO] CompareDiscover: instances of {} converged",
+                    entry.service
+                );
+            }
+            return;
+        }
+        if entry.diverged.as_ref() == Some(&divergence) {
+            return;
+        }
+        self.divergences.fetch_add(1, Ordering::Relaxed);
+        tracing::warn!(
+            "[VOLO] CompareDiscover: instances of {} diverged, only in {primary:?}: {:?}, only in \
+             {:?}: {:?}, weight mismatches: {:?}",
+            divergence.service,
+            divergence.only_in_primary,
+            primary.other(),
+            divergence.only_in_secondary,
+            divergence.weight_mismatches,
+        );
+        if let Some(events) = &self.events {
+            events.publish(Event::DiscoveryDiverged {
+                service: divergence.service.clone(),
+                only_in_primary: divergence.only_in_primary.clone(),
+                only_in_secondary: divergence.only_in_secondary.clone(),
+                weight_mismatches: divergence.weight_mismatches.clone(),
+            });
+        }
+        entry.diverged = Some(divergence);
+    }
+}
+
+impl<K> Shared<K>
+where
+    K: std::hash::Hash + Eq + Clone + Send + Sync + 'static,
+{
+    /// Updates the instances of a side by its change, and forwards it if it's the primary.
+    fn update(&self, side: Side, change: Change<K>) {
+        let mut state = self.state.lock().unwrap();
+        let primary = state.primary;
+        let entry = state.keys.entry(change.key.clone()).or_default();
+        *entry.instances(side) = Some(change.all.clone());
+        if side == primary {
+            let served = std::mem::replace(&mut entry.served, change.all.clone());
+            if let Some(change) = diff(change.key, served, change.all) {
+                let _ = self.sender.try_broadcast(change);
+            }
+        }
+        self.compare(primary, entry);
+    }
+}
+
+/// Receives the changes of both the discovers until they are closed or the discover is dropped.
+async fn forward<K>(
+    shared: Weak<Shared<K>>,
+    mut first: Option<Receiver<Change<K>>>,
+    mut second: Option<Receiver<Change<K>>>,
+) where
+    K: std::hash::Hash + Eq + Clone + Send + Sync + 'static,
+{
+    async fn recv<K: Clone>(receiver: &mut Option<Receiver<Change<K>>>) -> Option<Change<K>> {
+        loop {
+            match receiver.as_mut()?.recv().await {
+                Ok(change) => return Some(change),
+                Err(async_broadcast::RecvError::Overflowed(_)) => {}
+                Err(async_broadcast::RecvError::Closed) => {
+                    *receiver = None;
+                    return None;
+                }
+            }
+        }
+    }
+
+    while first.is_some() || second.is_some() {
+        let (side, change) = tokio::select! {
+            Some(change) = recv(&mut first) => (Side::First, change),
+            Some(change) = recv(&mut second) => (Side::Second, change),
+            else => break,
+        };
+        let Some(shared) = shared.upgrade() else {
+            break;
+        };
+        shared.update(side, change);
+    }
+}
+
+impl<A, B> Discover for CompareDiscover<A, B>
+where
+    A: Discover,
+    B: Discover<Key = A::Key>,
+{
+    type Key = A::Key;
+    type Error = LoadBalanceError;
+
+    async fn discover<'s>(
+        &'s self,
+        endpoint: &'s Endpoint,
+    ) -> Result<Vec<Arc<Instance>>, Self::Error> {
+        let (first, second) = futures::join!(
+            self.first.discover(endpoint),
+            self.second.discover(endpoint)
+        );
+        let results = [
+            (Side::First, first.map_err(Into::into)),
+            (Side::Second, second.map_err(Into::into)),
+        ];
+
+        let key = self.first.key(endpoint);
+        let mut state = self.shared.state.lock().unwrap();
+        let primary = state.primary;
+        let entry = state.keys.entry(key).or_default();
+        entry.service = endpoint.service_name();
+        let mut served = None;
+        for (side, result) in results {
+            match &result {
+                Ok(instances) => *entry.instances(side) = Some(instances.clone()),
+                Err(err) if side != primary => tracing::warn!(
+                    "[VOLO] CompareDiscover: failed to discover {} by the secondary: {}",
+                    entry.service,
+                    err
+                ),
+                Err(_) => {}
+            }
+            if side == primary {
+                served = Some(result);
+            }
+        }
+        let served = served.expect("the primary is discovered")?;
+        entry.served.clone_from(&served);
+        self.shared.compare(primary, entry);
+        Ok(served)
+    }
+
+    fn key(&self, endpoint: &Endpoint) -> Self::Key {
+        self.first.key(endpoint)
+    }
+
+    fn report(&self, address: &Address, healthy: bool) {
+        self.first.report(address, healthy);
+        self.second.report(address, healthy);
+    }
+
+    fn watch(&self, _keys: Option<&[Self::Key]>) -> Option<Receiver<Change<Self::Key>>> {
+        if !self.shared.watching.swap(true, Ordering::AcqRel) {
+            tokio::spawn(forward(
+                Arc::downgrade(&self.shared),
+                self.first.watch(None),
+                self.second.watch(None),
+            ));
+        }
+        Some(self.shared.sender.new_receiver())
+    }
+}
+
+impl<A: Clone, B: Clone + Discover> Clone for CompareDiscover<A, B> {
+    fn clone(&self) -> Self {
+        Self {
+            first: self.first.clone(),
+            second: self.second.clone(),
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl<A, B> crate::metrics::Source for CompareDiscover<A, B>
+where
+    A: Send + Sync,
+    B: Discover,
+{
+    fn collect(&self, visitor: &mut dyn crate::metrics::Visitor) {
+        use crate::metrics::{Desc, Value};
+
+        visitor.visit(
+            &Desc::new(
+                "volo_discovery_divergences_total",
+                "The divergences between the compared discovers reported.",
+            ),
+            &[],
+            Value::Counter(self.shared.divergences.load(Ordering::Relaxed)),
+        );
+        let state = self.shared.state.lock().unwrap();
+        let diverged = state
+            .keys
+            .values()
+            .filter_map(|entry| entry.diverged.as_ref())
+            .collect::<Vec<_>>();
+        let desc = Desc::new(
+            "volo_discovery_diverged_instances",
+            "The instances only in one of the compared discovers or with different weights.",
+        );
+        for divergence in diverged {
+            visitor.visit(
+                &desc,
+                &[("service", &divergence.service)],
+                Value::Gauge((divergence.missing() + divergence.weight_mismatches.len()) as f64),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, convert::Infallible, net::SocketAddr};
+
+    use super::*;
+
+    fn addresses(instances: &[Arc<Instance>]) -> HashSet<Address> {
+        instances.iter().map(|i| i.address.clone()).collect()
+    }
+
+    fn addr(port: u16) -> Address {
+        Address::from(SocketAddr::from(([127, 0, 0, 1], port)))
+    }
+
+    fn instance(port: u16, weight: u32) -> Arc<Instance> {
+        Arc::new(Instance {
+            address: addr(port),
+            weight,
+            tags: Default::default(),
+        })
+    }
+
+    /// A discover of a single service whose instances are updated by the tests.
+    #[derive(Clone)]
+    struct Scripted {
+        instances: Arc<Mutex<Vec<Arc<Instance>>>>,
+        sender: Sender<Change<FastStr>>,
+        _receiver: Arc<InactiveReceiver<Change<FastStr>>>,
+    }
+
+    impl Scripted {
+        fn new(instances: Vec<Arc<Instance>>) -> Self {
+            let (mut sender, receiver) = async_broadcast::broadcast(16);
+            sender.set_await_active(false);
+            Self {
+                instances: Arc::new(Mutex::new(instances)),
+                sender,
+                _receiver: Arc::new(receiver.deactivate()),
+            }
+        }
+
+        fn set(&self, next: Vec<Arc<Instance>>) {
+            let prev = std::mem::replace(&mut *self.instances.lock().unwrap(), next.clone());
+            if let Some(change) = diff("hello".into(), prev, next) {
+                self.sender.try_broadcast(change).unwrap();
+            }
+        }
+    }
+
+    impl Discover for Scripted {
+        type Key = FastStr;
+        type Error = Infallible;
+
+        async fn discover<'s>(&'s self, _: &'s Endpoint) -> Result<Vec<Arc<Instance>>, Infallible> {
+            Ok(self.instances.lock().unwrap().clone())
+        }
+
+        fn key(&self, endpoint: &Endpoint) -> FastStr {
+            endpoint.service_name()
+        }
+
+        fn watch(&self, _: Option<&[FastStr]>) -> Option<Receiver<Change<FastStr>>> {
+            Some(self.sender.new_receiver())
+        }
+    }
+
+    #[tokio::test]
+    async fn divergence() {
+        let bus = EventBus::new(16);
+        let mut events = bus.subscribe();
+        let first = Scripted::new(vec![instance(1, 10), instance(2, 10), instance(3, 10)]);
+        let second = Scripted::new(vec![instance(1, 10), instance(2, 20), instance(4, 10)]);
+        let discover = CompareDiscover::new(first.clone(), second.clone())
+            .tolerance(1)
+            .event_bus(bus);
+        let endpoint = Endpoint::new("hello".into());
+
+        let instances = discover.discover(&endpoint).await.unwrap();
+        assert_eq!(
+            addresses(&instances),
+            HashSet::from([addr(1), addr(2), addr(3)])
+        );
+        let expected = Event::DiscoveryDiverged {
+            service: "hello".into(),
+            only_in_primary: vec![addr(3)],
+            only_in_secondary: vec![addr(4)],
+            weight_mismatches: vec![(addr(2), 10, 20)],
+        };
+        assert_eq!(events.try_recv().unwrap(), expected);
+
+        // the same divergence is reported once
+        discover.discover(&endpoint).await.unwrap();
+        assert!(events.try_recv().is_err());
+        assert_eq!(discover.divergences(), 1);
+
+        // within the tolerance
+        second.set(vec![
+            instance(1, 10),
+            instance(2, 10),
+            instance(3, 10),
+            instance(4, 10),
+        ]);
+        discover.discover(&endpoint).await.unwrap();
+        assert!(discover.diverged().is_empty());
+
+        // beyond the tolerance
+        second.set(vec![instance(1, 10), instance(4, 10)]);
+        discover.discover(&endpoint).await.unwrap();
+        assert_eq!(
+            events.try_recv().unwrap(),
+            Event::DiscoveryDiverged {
+                service: "hello".into(),
+                only_in_primary: vec![addr(2), addr(3)],
+                only_in_secondary: vec![addr(4)],
+                weight_mismatches: vec![],
+            }
+        );
+        assert_eq!(discover.divergences(), 2);
+    }
+
+    #[tokio::test]
+    async fn flip_primary() {
+        let first = Scripted::new(vec![instance(1, 10), instance(2, 10), instance(3, 10)]);
+        let second = Scripted::new(vec![instance(1, 10), instance(2, 20), instance(4, 10)]);
+        let discover = CompareDiscover::new(first.clone(), second.clone());
+        let endpoint = Endpoint::new("hello".into());
+        let mut changes = discover.watch(None).unwrap();
+        discover.discover(&endpoint).await.unwrap();
+
+        // the changes of the primary are forwarded
+        first.set(vec![
+            instance(1, 10),
+            instance(2, 10),
+            instance(3, 10),
+            instance(5, 10),
+        ]);
+        let change = changes.recv().await.unwrap();
+        assert_eq!(change.added, vec![instance(5, 10)]);
+        assert!(change.removed.is_empty());
+
+        // only the differences are notified, so the connections to the instance 1 are kept
+        discover.set_primary(Side::Second);
+        assert_eq!(discover.primary(), Side::Second);
+        let change = changes.recv().await.unwrap();
+        assert_eq!(change.key, "hello");
+        assert_eq!(change.added, vec![instance(4, 10)]);
+        assert_eq!(change.updated, vec![instance(2, 20)]);
+        assert_eq!(
+            addresses(&change.removed),
+            HashSet::from([addr(3), addr(5)])
+        );
+        assert_eq!(
+            addresses(&change.all),
+            HashSet::from([addr(1), addr(2), addr(4)])
+        );
+        assert_eq!(
+            addresses(&discover.discover(&endpoint).await.unwrap()),
+            HashSet::from([addr(1), addr(2), addr(4)])
+        );
+
+        // the changes of the first are only compared now
+        first.set(vec![instance(1, 10)]);
+        second.set(vec![instance(1, 10), instance(2, 20)]);
+        let change = changes.recv().await.unwrap();
+        assert!(change.added.is_empty());
+        assert_eq!(change.removed, vec![instance(4, 10)]);
+        tokio::task::yield_now().await;
+        assert!(changes.try_recv().is_err());
+
+        // switching to the same primary notifies nothing
+        discover.set_primary(Side::Second);
+        assert!(changes.try_recv().is_err());
+    }
+}
@@ -0,0 +1,486 @@
+//! A [`Discover`] reading the instances from a local file, and reloading it when it changes.
+//!
+//! The file is in JSON, or in YAML if its extension is `.yaml` or `.yml`. It is either a list of
+//! instances shared by all the services, or a map from the service names to their instances:
+//!
+//! ```yaml
+//! hello:
+//!   - 127.0.0.1:8080
+//!   - address: 127.0.0.2:8080
+//!     weight: 20
+//!     tags:
+//!       zone: az1
+//!   - unix:/var/run/hello.sock
+//! ```
+//!
+//! The weight defaults to `10`, and the addresses prefixed by `unix:` are unix domain sockets.
+//!
+//! The directory of the file is watched, so that replacing the file by renaming, or by swapping
+//! the symlinks as kubernetes does for the mounted config maps, is also picked up. When the file
+//! changes, the load balancer is notified with the instances added, updated and removed of each
+//! service discovered before. If the new content is invalid, the last valid one is kept.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use volo::discovery::file::FileDiscover;
+//!
+//! let client = volo_gen::proto_gen::hello::GreeterClientBuilder::new("hello")
+//!     .discover(FileDiscover::new("/etc/volo/endpoints.yaml").unwrap())
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    convert::Infallible,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, Weak},
+};
+
+use async_broadcast::{InactiveReceiver, Receiver, Sender};
+use faststr::FastStr;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Deserialize;
+
+use super::{diff, Change, Discover, Instance};
+use crate::{
+    context::Endpoint,
+    event::{Event, EventBus},
+    net::Address,
+};
+
+const DEFAULT_WEIGHT: u32 = 10;
+const CHANNEL_CAPACITY: usize = 16;
+#[cfg(target_family = "unix")]
+const UNIX_PREFIX: &str = "unix:";
+
+/// The error of loading the file of [`FileDiscover`].
+#[derive(Debug, thiserror::Error)]
+pub enum FileDiscoverError {
+    #[error("failed to read {path}: {source}")]
+    Io { path: PathBuf, source: io::Error },
+    #[error("failed to parse {path}: {message}")]
+    Parse { path: PathBuf, message: String },
+    #[error("invalid address `{0}`")]
+    InvalidAddress(String),
+    #[error("failed to watch {path}: {source}")]
+    Watch {
+        path: PathBuf,
+        source: notify::Error,
+    },
+}
+
+/// A service discover reading the instances from a local file.
+///
+/// The clones of a [`FileDiscover`] share the watcher of the file, which stops after all the
+/// clones are dropped.
+#[derive(Clone)]
+pub struct FileDiscover {
+    shared: Arc<Shared>,
+    _watcher: Arc<RecommendedWatcher>,
+}
+
+struct Shared {
+    path: PathBuf,
+    state: Mutex<State>,
+    sender: Sender<Change<FastStr>>,
+    // keeps the channel open when there is no receiver
+    _receiver: InactiveReceiver<Change<FastStr>>,
+    events: Option<EventBus>,
+}
+
+struct State {
+    snapshot: Snapshot,
+    /// The services discovered, whose changes will be broadcast.
+    discovered: HashSet<FastStr>,
+}
+
+/// The instances loaded from the file.
+#[derive(Debug, Default, PartialEq)]
+struct Snapshot {
+    /// The instances of the services not in `services`.
+    all: Vec<Arc<Instance>>,
+    services: HashMap<FastStr, Vec<Arc<Instance>>>,
+}
+
+impl FileDiscover {
+    /// Loads the file and watches its changes.
+    ///
+    /// Fails if the file can not be loaded or watched.
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self, FileDiscoverError> {
+        Self::load(path.into(), None)
+    }
+
+    /// Loads the file and watches its changes, and publishes [`Event::ConfigReloaded`] to the
+    /// bus each time the file is reloaded with a change.
+    pub fn with_event_bus(
+        path: impl Into<PathBuf>,
+        bus: EventBus,
+    ) -> Result<Self, FileDiscoverError> {
+        Self::load(path.into(), Some(bus))
+    }
+
+    fn load(path: PathBuf, events: Option<EventBus>) -> Result<Self, FileDiscoverError> {
+        let snapshot = Snapshot::load(&path)?;
+
+        let (mut sender, receiver) = async_broadcast::broadcast(CHANNEL_CAPACITY);
+        sender.set_overflow(true);
+        sender.set_await_active(false);
+        let shared = Arc::new(Shared {
+            path,
+            state: Mutex::new(State {
+                snapshot,
+                discovered: HashSet::new(),
+            }),
+            sender,
+            _receiver: receiver.deactivate(),
+            events,
+        });
+
+        let weak = Arc::downgrade(&shared);
+        let watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+                Ok(event) if matches!(event.kind, EventKind::Access(_)) => {}
+                Ok(_) => reload(&weak),
+                Err(err) => tracing::warn!("[VOLO] FileDiscover: failed to watch: {}", err),
+            })
+            .and_then(|mut watcher| {
+                watcher.watch(watched_dir(&shared.path), RecursiveMode::NonRecursive)?;
+                Ok(watcher)
+            })
+            .map_err(|source| FileDiscoverError::Watch {
+                path: shared.path.clone(),
+                source,
+            })?;
+        // the changes between loading and watching
+        reload(&Arc::downgrade(&shared));
+
+        Ok(Self {
+            shared,
+            _watcher: Arc::new(watcher),
+        })
+    }
+
+    /// Returns the path of the file.
+    pub fn path(&self) -> &Path {
+        &self.shared.path
+    }
+}
+
+/// The directory to watch, since the file may be replaced instead of being written in place.
+fn watched_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+fn reload(shared: &Weak<Shared>) {
+    let Some(shared) = shared.upgrade() else {
+        return;
+    };
+    let next = match Snapshot::load(&shared.path) {
+        Ok(next) => next,
+        Err(err) => {
+            tracing::warn!("[VOLO] FileDiscover: keep the last instances: {}", err);
+            return;
+        }
+    };
+
+    let changes = {
+        let mut state = shared.state.lock().unwrap();
+        if state.snapshot == next {
+            return;
+        }
+        let changes = state
+            .discovered
+            .iter()
+            .filter_map(|key| {
+                diff(
+                    key.clone(),
+                    state.snapshot.instances(key),
+                    next.instances(key),
+                )
+            })
+            .collect::<Vec<_>>();
+        state.snapshot = next;
+        changes
+    };
+    if let Some(events) = &shared.events {
+        events.publish(Event::ConfigReloaded {
+            source: shared.path.to_string_lossy().into_owned().into(),
+        });
+    }
+    for change in changes {
+        tracing::info!(
+            "[VOLO] FileDiscover: instances of {} changed, added: {:?}, updated: {:?}, removed: \
+             {:?}",
+            change.key,
+            change.added,
+            change.updated,
+            change.removed
+        );
+        let _ = shared.sender.try_broadcast(change);
+    }
+}
+
+impl Snapshot {
+    fn load(path: &Path) -> Result<Self, FileDiscoverError> {
+        let content = std::fs::read(path).map_err(|source| FileDiscoverError::Io {
+            path: path.to_owned(),
+            source,
+        })?;
+        let yaml = matches!(
+            path.extension().and_then(|e| e.to_str()),
+            Some("yaml" | "yml")
+        );
+        let file: File = if yaml {
+            serde_yaml::from_slice(&content).map_err(|e| e.to_string())
+        } else {
+            serde_json::from_slice(&content).map_err(|e| e.to_string())
+        }
+        .map_err(|message| FileDiscoverError::Parse {
+            path: path.to_owned(),
+            message,
+        })?;
+        Self::from_file(file)
+    }
+
+    fn from_file(file: File) -> Result<Self, FileDiscoverError> {
+        let instances = |entries: Vec<Entry>| {
+            entries
+                .into_iter()
+                .map(|e| e.into_instance().map(Arc::new))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(match file {
+            File::All(entries) => Self {
+                all: instances(entries)?,
+                services: HashMap::new(),
+            },
+            File::Services(services) => Self {
+                all: Vec::new(),
+                services: services
+                    .into_iter()
+                    .map(|(name, entries)| Ok((FastStr::new(name), instances(entries)?)))
+                    .collect::<Result<_, FileDiscoverError>>()?,
+            },
+        })
+    }
+
+    fn instances(&self, key: &str) -> Vec<Arc<Instance>> {
+        self.services.get(key).unwrap_or(&self.all).clone()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum File {
+    All(Vec<Entry>),
+    Services(HashMap<String, Vec<Entry>>),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Entry {
+    Address(String),
+    Instance {
+        address: String,
+        #[serde(default)]
+        weight: Option<u32>,
+        #[serde(default)]
+        tags: HashMap<String, String>,
+    },
+}
+
+impl Entry {
+    fn into_instance(self) -> Result<Instance, FileDiscoverError> {
+        let (address, weight, tags) = match self {
+            Self::Address(address) => (address, None, HashMap::new()),
+            Self::Instance {
+                address,
+                weight,
+                tags,
+            } => (address, weight, tags),
+        };
+        Ok(Instance {
+            address: parse_address(&address)?,
+            weight: weight.unwrap_or(DEFAULT_WEIGHT),
+            tags: tags
+                .into_iter()
+                .map(|(k, v)| (Cow::Owned(k), Cow::Owned(v)))
+                .collect(),
+        })
+    }
+}
+
+fn parse_address(address: &str) -> Result<Address, FileDiscoverError> {
+    let invalid = || FileDiscoverError::InvalidAddress(address.to_owned());
+    #[cfg(target_family = "unix")]
+    if let Some(path) = address.strip_prefix(UNIX_PREFIX) {
+        return std::os::unix::net::SocketAddr::from_pathname(path)
+            .map(Address::from)
+            .map_err(|_| invalid());
+    }
+    address.parse().map(Address::Ip).map_err(|_| invalid())
+}
+
+impl Discover for FileDiscover {
+    type Key = FastStr;
+    type Error = Infallible;
+
+    async fn discover<'s>(
+        &'s self,
+        endpoint: &'s Endpoint,
+    ) -> Result<Vec<Arc<Instance>>, Self::Error> {
+        if let Some(address) = endpoint.address() {
+            return Ok(vec![Arc::new(Instance {
+                address,
+                weight: DEFAULT_WEIGHT,
+                tags: Default::default(),
+            })]);
+        }
+        let key = endpoint.service_name();
+        let mut state = self.shared.state.lock().unwrap();
+        let instances = state.snapshot.instances(&key);
+        state.discovered.insert(key);
+        Ok(instances)
+    }
+
+    fn key(&self, endpoint: &Endpoint) -> Self::Key {
+        endpoint.service_name()
+    }
+
+    fn watch(&self, _keys: Option<&[Self::Key]>) -> Option<Receiver<Change<Self::Key>>> {
+        Some(self.shared.sender.new_receiver())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn instance(address: &str, weight: u32) -> Arc<Instance> {
+        Arc::new(Instance {
+            address: parse_address(address).unwrap(),
+            weight,
+            tags: Default::default(),
+        })
+    }
+
+    fn parse_yaml(s: &str) -> Snapshot {
+        Snapshot::from_file(serde_yaml::from_str(s).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        let snapshot = parse_yaml(
+            "
+hello:
+  - 127.0.0.1:8080
+  - address: 127.0.0.2:8080
+    weight: 20
+    tags:
+      zone: az1
+  - unix:/var/run/hello.sock
+",
+        );
+        let hello = snapshot.instances("hello");
+        assert_eq!(hello.len(), 3);
+        assert_eq!(hello[0], instance("127.0.0.1:8080", DEFAULT_WEIGHT));
+        assert_eq!(hello[1].weight, 20);
+        assert_eq!(hello[1].tags.get("zone").map(|z| z.as_ref()), Some("az1"));
+        assert_eq!(
+            hello[2],
+            instance("unix:/var/run/hello.sock", DEFAULT_WEIGHT)
+        );
+        assert!(snapshot.instances("other").is_empty());
+
+        let file = serde_json::from_str(r#"["127.0.0.1:8080"]"#).unwrap();
+        let snapshot = Snapshot::from_file(file).unwrap();
+        assert_eq!(
+            snapshot.instances("any"),
+            vec![instance("127.0.0.1:8080", DEFAULT_WEIGHT)]
+        );
+
+        let file = serde_json::from_str(r#"["localhost"]"#).unwrap();
+        assert!(matches!(
+            Snapshot::from_file(file),
+            Err(FileDiscoverError::InvalidAddress(_))
+        ));
+    }
+
+    #[test]
+    fn diff_instances() {
+        let prev = vec![
+            instance("127.0.0.1:8080", 10),
+            instance("127.0.0.2:8080", 10),
+        ];
+        assert!(diff("hello".into(), prev.clone(), prev.clone()).is_none());
+
+        let next = vec![
+            instance("127.0.0.2:8080", 20),
+            instance("127.0.0.3:8080", 10),
+        ];
+        let change = diff("hello".into(), prev, next.clone()).unwrap();
+        assert_eq!(change.all, next);
+        assert_eq!(change.added, vec![instance("127.0.0.3:8080", 10)]);
+        assert_eq!(change.updated, vec![instance("127.0.0.2:8080", 20)]);
+        assert_eq!(change.removed, vec![instance("127.0.0.1:8080", 10)]);
+    }
+
+    #[tokio::test]
+    async fn watch_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("endpoints.json");
+        std::fs::write(&path, r#"{"hello": ["127.0.0.1:8080"]}"#).unwrap();
+
+        let bus = EventBus::new(16);
+        let mut events = bus.subscribe();
+        let discover = FileDiscover::with_event_bus(&path, bus).unwrap();
+        let mut changes = discover.watch(None).unwrap();
+        let endpoint = Endpoint::new("hello".into());
+        assert_eq!(
+            discover.discover(&endpoint).await.unwrap(),
+            vec![instance("127.0.0.1:8080", DEFAULT_WEIGHT)]
+        );
+
+        // replaced by renaming
+        let tmp = dir.path().join("endpoints.json.tmp");
+        std::fs::write(&tmp, r#"{"hello": ["127.0.0.2:8080"]}"#).unwrap();
+        std::fs::rename(&tmp, &path).unwrap();
+
+        let change = tokio::time::timeout(Duration::from_secs(5), changes.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(change.key.as_str(), "hello");
+        assert_eq!(
+            change.added,
+            vec![instance("127.0.0.2:8080", DEFAULT_WEIGHT)]
+        );
+        assert_eq!(
+            change.removed,
+            vec![instance("127.0.0.1:8080", DEFAULT_WEIGHT)]
+        );
+        assert_eq!(
+            events.recv().await.unwrap(),
+            Event::ConfigReloaded {
+                source: path.to_string_lossy().into_owned().into()
+            }
+        );
+
+        // the invalid content is ignored
+        std::fs::write(&path, "{").unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(
+            discover.discover(&endpoint).await.unwrap(),
+            vec![instance("127.0.0.2:8080", DEFAULT_WEIGHT)]
+        );
+    }
+}
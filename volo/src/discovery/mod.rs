@@ -3,6 +3,12 @@
 //! We encourage users to use these traits to implement their own service discovery and
 //! loadbalancer, so that we are able to reuse the same service discovery and loadbalancer
 //! implementation.
+
+mod compare;
+#[cfg(feature = "file-discover")]
+#[cfg_attr(docsrs, doc(cfg(feature = "file-discover")))]
+pub mod file;
+
 use std::{
     borrow::Cow,
     collections::{HashMap, HashSet},
@@ -15,7 +21,19 @@ use std::{
 
 use async_broadcast::Receiver;
 
-use crate::{context::Endpoint, loadbalance::error::LoadBalanceError, net::Address};
+pub use self::compare::{CompareDiscover, Divergence, Side};
+use crate::{
+    context::Endpoint,
+    event::{Event, EventBus},
+    loadbalance::error::LoadBalanceError,
+    net::Address,
+};
+
+/// The tag of an [`Instance`] holding its priority, where a smaller value means a higher
+/// priority, as in the DNS SRV records.
+///
+/// The instances without the tag are of priority `0`.
+pub const PRIORITY_TAG: &str = "priority";
 
 /// [`Instance`] contains information of an instance from the target service.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -42,6 +60,13 @@ pub trait Discover: Send + Sync + 'static {
     /// `watch` should return a [`async_broadcast::Receiver`] which can be used to subscribe
     /// [`Change`].
     fn watch(&self, keys: Option<&[Self::Key]>) -> Option<Receiver<Change<Self::Key>>>;
+    /// `report` tells the result of a call sent to an instance discovered, where `healthy` is
+    /// `false` if the error means the instance is unhealthy, e.g. for the
+    /// [`OutlierEjection`](crate::loadbalance::policy::OutlierEjection) stage of a
+    /// [`PolicyDiscover`](crate::loadbalance::policy::PolicyDiscover) to eject the failing ones.
+    ///
+    /// Default does nothing.
+    fn report(&self, _address: &Address, _healthy: bool) {}
 }
 
 /// Change indicates the change of the service discover.
@@ -118,6 +143,46 @@ where
     )
 }
 
+/// Compares the instances by the address, where an instance with the same address but a
+/// different weight or tags is updated, and returns `None` if nothing changes.
+pub(crate) fn diff<K>(
+    key: K,
+    prev: Vec<Arc<Instance>>,
+    next: Vec<Arc<Instance>>,
+) -> Option<Change<K>> {
+    let prev_by_address = prev
+        .iter()
+        .map(|i| (&i.address, i))
+        .collect::<HashMap<_, _>>();
+    let next_addresses = next.iter().map(|i| &i.address).collect::<HashSet<_>>();
+
+    let mut added = Vec::new();
+    let mut updated = Vec::new();
+    for i in next.iter() {
+        match prev_by_address.get(&i.address) {
+            None => added.push(i.clone()),
+            Some(p) if **p != *i => updated.push(i.clone()),
+            Some(_) => {}
+        }
+    }
+    let removed = prev
+        .iter()
+        .filter(|i| !next_addresses.contains(&i.address))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    if added.is_empty() && updated.is_empty() && removed.is_empty() {
+        return None;
+    }
+    Some(Change {
+        key,
+        all: next,
+        added,
+        updated,
+        removed,
+    })
+}
+
 /// [`StaticDiscover`] is a simple implementation of [`Discover`] that returns a static list of
 /// instances.
 #[derive(Clone)]
@@ -184,6 +249,70 @@ impl Discover for DummyDiscover {
     }
 }
 
+/// Wraps a [`Discover`] to publish the instances added and removed by its changes as
+/// [`Event::InstanceSetChanged`] to the [`EventBus`].
+///
+/// The changes are forwarded to the watchers as is.
+#[derive(Clone)]
+pub struct EventDiscover<D> {
+    inner: D,
+    bus: EventBus,
+}
+
+impl<D> EventDiscover<D> {
+    pub fn new(inner: D, bus: EventBus) -> Self {
+        Self { inner, bus }
+    }
+}
+
+impl<D: Discover> Discover for EventDiscover<D> {
+    type Key = D::Key;
+    type Error = D::Error;
+
+    fn discover<'s>(
+        &'s self,
+        endpoint: &'s Endpoint,
+    ) -> impl Future<Output = Result<Vec<Arc<Instance>>, Self::Error>> + Send {
+        self.inner.discover(endpoint)
+    }
+
+    fn key(&self, endpoint: &Endpoint) -> Self::Key {
+        self.inner.key(endpoint)
+    }
+
+    fn report(&self, address: &Address, healthy: bool) {
+        self.inner.report(address, healthy)
+    }
+
+    fn watch(&self, keys: Option<&[Self::Key]>) -> Option<Receiver<Change<Self::Key>>> {
+        let mut changes = self.inner.watch(keys)?;
+        let (mut sender, receiver) = async_broadcast::broadcast(changes.capacity());
+        sender.set_overflow(true);
+        let bus = self.bus.clone();
+        tokio::spawn(async move {
+            loop {
+                match changes.recv().await {
+                    Ok(change) => {
+                        if !change.added.is_empty() || !change.removed.is_empty() {
+                            bus.publish(Event::InstanceSetChanged {
+                                added: change.added.iter().map(|i| i.address.clone()).collect(),
+                                removed: change.removed.iter().map(|i| i.address.clone()).collect(),
+                            });
+                        }
+                        // all the watchers are dropped
+                        if sender.broadcast(change).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(async_broadcast::RecvError::Closed) => break,
+                    Err(async_broadcast::RecvError::Overflowed(_)) => {}
+                }
+            }
+        });
+        Some(receiver)
+    }
+}
+
 impl From<Infallible> for LoadBalanceError {
     fn from(_: Infallible) -> Self {
         unreachable!()
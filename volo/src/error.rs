@@ -0,0 +1,406 @@
+//! Stable codes of the errors, for alerting and branching on the errors without matching their
+//! messages.
+//!
+//! Every public error of `volo`, `volo-thrift`, `volo-grpc` and `volo-http` has an [`ErrorCode`]
+//! like `VOLO_THRIFT_0001`, which is returned by [`CodedError::code`]. The code of an error is
+//! kept when it is wrapped or converted to another error of volo, e.g. a [`LoadBalanceError`]
+//! converted to a gRPC `Status` still has the code of the former.
+//!
+//! The codes of each crate are defined by [`error_codes!`](crate::error_codes), and listed in the
+//! `ERROR_CODES.md` of the crate, which is checked by [`check_registry`] in the tests. A code is
+//! never removed or renumbered, and a code which is no longer used stays in the registry.
+
+use std::{collections::HashSet, error::Error, fmt, path::Path, str::FromStr};
+
+use crate::{config::ConfigError, loadbalance::error::LoadBalanceError};
+
+/// The crate an [`ErrorCode`] belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[non_exhaustive]
+pub enum Namespace {
+    Volo,
+    Thrift,
+    Grpc,
+    Http,
+}
+
+impl Namespace {
+    const ALL: [Self; 4] = [Self::Volo, Self::Thrift, Self::Grpc, Self::Http];
+
+    /// Returns the prefix of the codes, e.g. `VOLO_THRIFT`.
+    pub const fn prefix(self) -> &'static str {
+        match self {
+            Self::Volo => "VOLO",
+            Self::Thrift => "VOLO_THRIFT",
+            Self::Grpc => "VOLO_GRPC",
+            Self::Http => "VOLO_HTTP",
+        }
+    }
+}
+
+/// A stable code of an error, which is formatted as `<namespace>_<number>`, e.g.
+/// `VOLO_THRIFT_0001`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ErrorCode {
+    namespace: Namespace,
+    number: u16,
+}
+
+impl ErrorCode {
+    /// Creates an [`ErrorCode`], and `number` must be less than `10000`.
+    pub const fn new(namespace: Namespace, number: u16) -> Self {
+        assert!(number < 10000, "the number of an error code has 4 digits");
+        Self { namespace, number }
+    }
+
+    pub fn namespace(&self) -> Namespace {
+        self.namespace
+    }
+
+    pub fn number(&self) -> u16 {
+        self.number
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}_{:04}", self.namespace.prefix(), self.number)
+    }
+}
+
+/// The error of parsing an [`ErrorCode`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseErrorCodeError;
+
+impl fmt::Display for ParseErrorCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid error code")
+    }
+}
+
+impl Error for ParseErrorCodeError {}
+
+impl FromStr for ErrorCode {
+    type Err = ParseErrorCodeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (prefix, number) = s.rsplit_once('_').ok_or(ParseErrorCodeError)?;
+        if number.len() != 4 || !number.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ParseErrorCodeError);
+        }
+        let namespace = Namespace::ALL
+            .into_iter()
+            .find(|ns| ns.prefix() == prefix)
+            .ok_or(ParseErrorCodeError)?;
+        Ok(Self::new(
+            namespace,
+            number.parse().map_err(|_| ParseErrorCodeError)?,
+        ))
+    }
+}
+
+/// An error with a stable [`ErrorCode`].
+pub trait CodedError: Error {
+    fn code(&self) -> ErrorCode;
+
+    /// Returns if the call failed with the error may succeed when retried, which is only a hint,
+    /// e.g. for the alerting or the retry policies of the users.
+    ///
+    /// Default is `false`.
+    fn is_retryable(&self) -> bool {
+        false
+    }
+}
+
+/// Returns the code of the first error of volo in the chain of the sources, which starts from
+/// `err` itself.
+///
+/// The errors of the other crates are recognized by `f`, which returns the code if the error is
+/// one of them.
+pub fn find_code(
+    err: &(dyn Error + 'static),
+    f: impl Fn(&(dyn Error + 'static)) -> Option<ErrorCode>,
+) -> Option<ErrorCode> {
+    let mut next = Some(err);
+    while let Some(err) = next {
+        if let Some(code) = f(err) {
+            return Some(code);
+        }
+        if let Some(e) = err.downcast_ref::<ConfigError>() {
+            return Some(e.code());
+        }
+        if let Some(e) = err.downcast_ref::<LoadBalanceError>() {
+            return Some(e.code());
+        }
+        next = err.source();
+    }
+    None
+}
+
+/// Prefixes the message with the code, e.g. `[VOLO_0002] load balance retry reaches end`, which
+/// keeps the code when the error is converted to one without a code, e.g. the
+/// `ApplicationException` of Thrift.
+///
+/// The message which has been tagged is returned as is.
+pub fn tag(code: ErrorCode, msg: impl fmt::Display) -> String {
+    let msg = msg.to_string();
+    if tagged(&msg).is_some() {
+        return msg;
+    }
+    format!("[{code}] {msg}")
+}
+
+/// Returns the code the message is tagged with by [`tag`].
+pub fn tagged(msg: &str) -> Option<ErrorCode> {
+    let (code, _) = msg.strip_prefix('[')?.split_once(']')?;
+    code.parse().ok()
+}
+
+/// Defines the error codes of a crate as constants, and the `REGISTRY` of them.
+///
+/// The doc comment of a code is its description in the registry.
+///
+/// ```rust,ignore
+/// volo::error_codes! {
+///     Namespace::Thrift;
+///     /// Transport exception, e.g. the connection is reset.
+///     TRANSPORT = 1,
+/// }
+/// ```
+#[macro_export]
+macro_rules! error_codes {
+    ($namespace:expr; $($(#[doc = $doc:literal])+ $name:ident = $number:literal,)+) => {
+        $(
+            $(#[doc = $doc])+
+            pub const $name: $crate::error::ErrorCode =
+                $crate::error::ErrorCode::new($namespace, $number);
+        )+
+
+        /// All the error codes, which are listed in the checked-in `ERROR_CODES.md`.
+        pub const REGISTRY: &[$crate::error::ErrorCodeEntry] = &[
+            $(
+                $crate::error::ErrorCodeEntry {
+                    code: $name,
+                    name: stringify!($name),
+                    description: concat!($($doc),+),
+                },
+            )+
+        ];
+    };
+}
+
+/// An error code in the registry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorCodeEntry {
+    pub code: ErrorCode,
+    pub name: &'static str,
+    pub description: &'static str,
+}
+
+/// The environment variable which makes [`check_registry`] rewrite the registry file.
+pub const UPDATE_REGISTRY_ENV: &str = "VOLO_UPDATE_ERROR_CODES";
+
+/// Renders the registry of the crate as a markdown table.
+pub fn render_registry(crate_name: &str, entries: &[ErrorCodeEntry]) -> String {
+    let mut out = format!(
+        "# Error codes of `{crate_name}`\n\n<!-- Generated by the tests with \
+         `{UPDATE_REGISTRY_ENV}=1`, do not edit by hand. -->\n\n| Code | Name | Description |\n| \
+         --- | --- | --- |\n"
+    );
+    for entry in entries {
+        out.push_str(&format!(
+            "| {} | {} | {} |\n",
+            entry.code,
+            entry.name,
+            entry.description.trim()
+        ));
+    }
+    out
+}
+
+/// Checks the registry of the crate against the checked-in file, so a code is never reused or
+/// renamed silently:
+/// - the codes and the names are unique, and in the same namespace;
+/// - every code in the file is still in the registry with the same name;
+/// - the file is the same as the rendered registry.
+///
+/// If [`UPDATE_REGISTRY_ENV`] is set, the file is rewritten after the first two checks pass.
+pub fn check_registry(
+    crate_name: &str,
+    entries: &[ErrorCodeEntry],
+    path: impl AsRef<Path>,
+) -> Result<(), String> {
+    let path = path.as_ref();
+    let mut codes = HashSet::new();
+    let mut names = HashSet::new();
+    for entry in entries {
+        if entry.code.namespace() != entries[0].code.namespace() {
+            return Err(format!("{} is in another namespace", entry.code));
+        }
+        if !codes.insert(entry.code) {
+            return Err(format!("{} is defined twice", entry.code));
+        }
+        if !names.insert(entry.name) {
+            return Err(format!("{} is defined twice", entry.name));
+        }
+    }
+
+    let file = std::fs::read_to_string(path).unwrap_or_default();
+    for row in file.lines().filter_map(|line| line.strip_prefix("| ")) {
+        let mut cols = row.split(" | ");
+        let (Some(Ok(code)), Some(name)) = (cols.next().map(str::parse::<ErrorCode>), cols.next())
+        else {
+            continue;
+        };
+        match entries.iter().find(|e| e.code == code) {
+            Some(e) if e.name == name => {}
+            Some(e) => return Err(format!("{code} is renamed from {name} to {}", e.name)),
+            None => return Err(format!("{code} ({name}) is removed, which must be kept")),
+        }
+    }
+
+    let rendered = render_registry(crate_name, entries);
+    if std::env::var_os(UPDATE_REGISTRY_ENV).is_some() {
+        return std::fs::write(path, rendered).map_err(|e| e.to_string());
+    }
+    if file != rendered {
+        return Err(format!(
+            "{} is outdated, run the tests with `{UPDATE_REGISTRY_ENV}=1` to update it",
+            path.display()
+        ));
+    }
+    Ok(())
+}
+
+pub mod code {
+    //! The error codes of `volo`.
+
+    use super::Namespace;
+
+    crate::error_codes! {
+        Namespace::Volo;
+        /// Invalid configuration of a client or a server.
+        CONFIG = 1,
+        /// The load balancer has no more instances to retry.
+        LOAD_BALANCE_RETRY = 2,
+        /// The service discovery failed.
+        LOAD_BALANCE_DISCOVER = 3,
+        /// The request hash is missing for the consistent hash load balancer.
+        LOAD_BALANCE_MISSING_REQUEST_HASH = 4,
+        /// Another profile is being captured.
+        PROFILE_BUSY = 5,
+        /// Failed to capture the profile.
+        PROFILE_FAILED = 6,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_and_parse() {
+        let code = ErrorCode::new(Namespace::Thrift, 42);
+        assert_eq!(code.to_string(), "VOLO_THRIFT_0042");
+        assert_eq!("VOLO_THRIFT_0042".parse(), Ok(code));
+        assert_eq!("VOLO_0001".parse(), Ok(code::CONFIG));
+        assert!("VOLO_THRIFT_42".parse::<ErrorCode>().is_err());
+        assert!("VOLO_QUIC_0001".parse::<ErrorCode>().is_err());
+
+        let msg = tag(code, "connection reset");
+        assert_eq!(msg, "[VOLO_THRIFT_0042] connection reset");
+        assert_eq!(tagged(&msg), Some(code));
+        assert_eq!(tag(code::CONFIG, &msg), msg);
+        assert_eq!(tagged("[oops] connection reset"), None);
+    }
+
+    #[test]
+    fn codes() {
+        assert_eq!(ConfigError::new().code(), code::CONFIG);
+        assert_eq!(LoadBalanceError::Retry.code(), code::LOAD_BALANCE_RETRY);
+        assert_eq!(
+            LoadBalanceError::Discover("dns".into()).code(),
+            code::LOAD_BALANCE_DISCOVER
+        );
+        assert!(LoadBalanceError::Discover("dns".into()).is_retryable());
+        assert_eq!(
+            LoadBalanceError::MissRequestHash.code(),
+            code::LOAD_BALANCE_MISSING_REQUEST_HASH
+        );
+
+        #[derive(Debug)]
+        struct Wrapper(LoadBalanceError);
+
+        impl fmt::Display for Wrapper {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "wrapped: {}", self.0)
+            }
+        }
+
+        impl Error for Wrapper {
+            fn source(&self) -> Option<&(dyn Error + 'static)> {
+                Some(&self.0)
+            }
+        }
+
+        // the code of the source is found through the wrappers
+        let err = Wrapper(LoadBalanceError::Retry);
+        assert_eq!(find_code(&err, |_| None), Some(code::LOAD_BALANCE_RETRY));
+        assert_eq!(find_code(&std::io::Error::other("oops"), |_| None), None);
+    }
+
+    #[test]
+    fn registry() {
+        check_registry(
+            "volo",
+            code::REGISTRY,
+            concat!(env!("CARGO_MANIFEST_DIR"), "/ERROR_CODES.md"),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn registry_changes() {
+        if std::env::var_os(UPDATE_REGISTRY_ENV).is_some() {
+            // the file is rewritten instead of checked
+            return;
+        }
+        let dir = std::env::temp_dir().join(format!("volo-error-codes-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("ERROR_CODES.md");
+        let entry = |number, name| ErrorCodeEntry {
+            code: ErrorCode::new(Namespace::Volo, number),
+            name,
+            description: "",
+        };
+        std::fs::write(
+            &path,
+            render_registry("volo", &[entry(1, "A"), entry(2, "B")]),
+        )
+        .unwrap();
+
+        assert!(check_registry("volo", &[entry(1, "A"), entry(2, "B")], &path).is_ok());
+        // a new code must be added to the file
+        assert!(check_registry(
+            "volo",
+            &[entry(1, "A"), entry(2, "B"), entry(3, "C")],
+            &path
+        )
+        .unwrap_err()
+        .contains("outdated"));
+        assert!(
+            check_registry("volo", &[entry(1, "A"), entry(2, "C")], &path)
+                .unwrap_err()
+                .contains("renamed")
+        );
+        assert!(check_registry("volo", &[entry(1, "A")], &path)
+            .unwrap_err()
+            .contains("removed"));
+        assert!(
+            check_registry("volo", &[entry(1, "A"), entry(1, "B")], &path)
+                .unwrap_err()
+                .contains("twice")
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
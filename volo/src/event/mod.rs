@@ -0,0 +1,363 @@
+//! A typed event bus of the changes of the components, e.g. the instances discovered, the
+//! circuits of the instances and the config reloaded.
+//!
+//! The components publish the [`Event`]s to an [`EventBus`] shared by them, and the subscribers,
+//! e.g. the [`EventRecorder`] serving the recent events in the admin endpoint, the tracing by
+//! [`EventBus::trace`], or the tests asserting on the sequence of the events, receive them in
+//! order:
+//!
+//! ```rust,ignore
+//! use volo::{
+//!     discovery::EventDiscover,
+//!     event::{EventBus, EventRecorder},
+//!     loadbalance::{
+//!         outlier::{OutlierDetection, OutlierDetector},
+//!         policy::{OutlierEjection, PolicyChain, PolicyDiscover},
+//!     },
+//! };
+//!
+//! let bus = EventBus::new(1024);
+//! bus.trace();
+//! let recorder = EventRecorder::new(256);
+//! recorder.record(&bus);
+//!
+//! let detector =
+//!     Arc::new(OutlierDetector::new(OutlierDetection::default()).event_bus(bus.clone()));
+//! let client = volo_gen::volo::example::ItemServiceClientBuilder::new("item")
+//!     .discover(PolicyDiscover::new(
+//!         EventDiscover::new(discover, bus.clone()),
+//!         PolicyChain::new().stage(OutlierEjection::new(detector)),
+//!     ))
+//!     .build()?;
+//! ```
+//!
+//! Publishing never blocks: if a subscriber falls behind and the channel is full, the event is
+//! dropped and counted by [`EventBus::dropped`].
+
+mod recorder;
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use async_broadcast::{InactiveReceiver, Receiver, Sender, TrySendError};
+use faststr::FastStr;
+
+pub(crate) use self::recorder::push_str;
+pub use self::recorder::EventRecorder;
+use crate::net::Address;
+
+/// The state of the circuit of an instance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CircuitState {
+    /// The requests are sent as usual.
+    Closed,
+    /// No request is sent.
+    Open,
+    /// A probe request is sent to decide whether the circuit is closed again.
+    HalfOpen,
+}
+
+impl CircuitState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Closed => "closed",
+            Self::Open => "open",
+            Self::HalfOpen => "half_open",
+        }
+    }
+}
+
+impl fmt::Display for CircuitState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An event published to the [`EventBus`].
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Event {
+    /// The instances watched by a discover are added or removed.
+    InstanceSetChanged {
+        added: Vec<Address>,
+        removed: Vec<Address>,
+    },
+    /// The circuit of an instance changes, where `key` is the address of the instance.
+    CircuitStateChanged {
+        key: FastStr,
+        from: CircuitState,
+        to: CircuitState,
+    },
+    /// The config loaded from `source`, e.g. the path of a file, is reloaded.
+    ConfigReloaded { source: FastStr },
+    /// The instances of a service discovered by the two discovers compared by a
+    /// [`CompareDiscover`](crate::discovery::CompareDiscover) diverge, where the weights are
+    /// those in the primary and the secondary.
+    DiscoveryDiverged {
+        service: FastStr,
+        only_in_primary: Vec<Address>,
+        only_in_secondary: Vec<Address>,
+        weight_mismatches: Vec<(Address, u32, u32)>,
+    },
+}
+
+impl Event {
+    /// The name of the kind of the event, e.g. `circuit_state_changed`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::InstanceSetChanged { .. } => "instance_set_changed",
+            Self::CircuitStateChanged { .. } => "circuit_state_changed",
+            Self::ConfigReloaded { .. } => "config_reloaded",
+            Self::DiscoveryDiverged { .. } => "discovery_diverged",
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InstanceSetChanged { added, removed } => {
+                write!(f, "instances added: {added:?}, removed: {removed:?}")
+            }
+            Self::CircuitStateChanged { key, from, to } => {
+                write!(f, "circuit of {key} changed from {from} to {to}")
+            }
+            Self::ConfigReloaded { source } => write!(f, "config reloaded from {source}"),
+            Self::DiscoveryDiverged {
+                service,
+                only_in_primary,
+                only_in_secondary,
+                weight_mismatches,
+            } => write!(
+                f,
+                "instances of {service} diverged, only in primary: {only_in_primary:?}, only in \
+                 secondary: {only_in_secondary:?}, weight mismatches: {weight_mismatches:?}"
+            ),
+        }
+    }
+}
+
+/// A bounded broadcast channel of the [`Event`]s.
+///
+/// The clones of a bus share the same channel.
+#[derive(Clone)]
+pub struct EventBus {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    sender: Sender<Event>,
+    // keeps the channel open when there is no subscriber
+    _receiver: InactiveReceiver<Event>,
+    dropped: AtomicU64,
+}
+
+impl EventBus {
+    /// Creates a bus buffering at most `capacity` events for each subscriber.
+    pub fn new(capacity: usize) -> Self {
+        let (mut sender, receiver) = async_broadcast::broadcast(capacity.max(1));
+        sender.set_await_active(false);
+        Self {
+            inner: Arc::new(Inner {
+                sender,
+                _receiver: receiver.deactivate(),
+                dropped: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Publishes the event without blocking.
+    ///
+    /// The event is dropped if a subscriber is full, and it's simply discarded if there is no
+    /// subscriber.
+    pub fn publish(&self, event: Event) {
+        match self.inner.sender.try_broadcast(event) {
+            Ok(_) | Err(TrySendError::Inactive(_)) => {}
+            Err(TrySendError::Full(event)) => {
+                self.inner.dropped.fetch_add(1, Ordering::Relaxed);
+                tracing::debug!("[VOLO] event bus is full, dropped: {event}");
+            }
+            Err(TrySendError::Closed(_)) => {}
+        }
+    }
+
+    /// Subscribes the events published after now.
+    pub fn subscribe(&self) -> Receiver<Event> {
+        self.inner.sender.new_receiver()
+    }
+
+    /// Returns the number of the events dropped because a subscriber is full.
+    pub fn dropped(&self) -> u64 {
+        self.inner.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Emits a tracing event for each event published, until the bus is dropped.
+    pub fn trace(&self) -> tokio::task::JoinHandle<()> {
+        let mut receiver = self.subscribe();
+        tokio::spawn(async move {
+            while let Ok(event) = receiver.recv().await {
+                tracing::info!(target: "volo::event", kind = event.kind(), "[VOLO] {event}");
+            }
+        })
+    }
+}
+
+impl fmt::Debug for EventBus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventBus")
+            .field("capacity", &self.inner.sender.capacity())
+            .field("dropped", &self.dropped())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use super::*;
+    use crate::{
+        context::Endpoint,
+        discovery::{Change, Discover, EventDiscover, Instance, StaticDiscover},
+        loadbalance::outlier::{OutlierDetection, OutlierDetector},
+    };
+
+    fn addr(port: u16) -> Address {
+        Address::from(std::net::SocketAddr::from(([127, 0, 0, 1], port)))
+    }
+
+    #[test]
+    fn drop_when_full() {
+        let bus = EventBus::new(1);
+        // no subscriber
+        bus.publish(Event::ConfigReloaded {
+            source: "a.yaml".into(),
+        });
+        assert_eq!(bus.dropped(), 0);
+
+        let mut receiver = bus.subscribe();
+        for source in ["a.yaml", "b.yaml"] {
+            bus.publish(Event::ConfigReloaded {
+                source: source.into(),
+            });
+        }
+        assert_eq!(bus.dropped(), 1);
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            Event::ConfigReloaded {
+                source: "a.yaml".into()
+            }
+        );
+        assert!(receiver.try_recv().is_err());
+    }
+
+    /// A discover whose changes are sent by the test.
+    struct ScriptedDiscover {
+        changes: async_broadcast::Receiver<Change<()>>,
+    }
+
+    impl Discover for ScriptedDiscover {
+        type Key = ();
+        type Error = std::convert::Infallible;
+
+        async fn discover<'s>(
+            &'s self,
+            _: &'s Endpoint,
+        ) -> Result<Vec<Arc<Instance>>, Self::Error> {
+            Ok(Vec::new())
+        }
+
+        fn key(&self, _: &Endpoint) {}
+
+        fn watch(&self, _: Option<&[()]>) -> Option<async_broadcast::Receiver<Change<()>>> {
+            Some(self.changes.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn scripted_sequence() {
+        let bus = EventBus::new(16);
+        let mut events = bus.subscribe();
+        let (a, b) = (addr(8000), addr(8001));
+
+        let (sender, changes) = async_broadcast::broadcast(4);
+        let discover = EventDiscover::new(ScriptedDiscover { changes }, bus.clone());
+        let mut watched = discover.watch(None).unwrap();
+        let detector = OutlierDetector::new(
+            OutlierDetection::default()
+                .consecutive_failures(1)
+                .ejection_time(Duration::from_millis(10), Duration::from_millis(10)),
+        )
+        .event_bus(bus.clone());
+
+        // the instance is removed
+        let instance = |address: &Address| {
+            Arc::new(Instance {
+                address: address.clone(),
+                weight: 1,
+                tags: Default::default(),
+            })
+        };
+        sender
+            .broadcast(Change {
+                key: (),
+                all: vec![instance(&b)],
+                added: Vec::new(),
+                updated: Vec::new(),
+                removed: vec![instance(&a)],
+            })
+            .await
+            .unwrap();
+        // the change is still forwarded to the load balancer
+        assert_eq!(watched.recv().await.unwrap().removed.len(), 1);
+
+        // the circuit of the other one opens
+        detector.report(&b, false);
+        assert!(!detector.admit(&b));
+
+        // and recovers by the probe
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(detector.admit(&b));
+        detector.report(&b, true);
+
+        let key = FastStr::from(b.to_string());
+        let expected = vec![
+            Event::InstanceSetChanged {
+                added: Vec::new(),
+                removed: vec![a],
+            },
+            Event::CircuitStateChanged {
+                key: key.clone(),
+                from: CircuitState::Closed,
+                to: CircuitState::Open,
+            },
+            Event::CircuitStateChanged {
+                key: key.clone(),
+                from: CircuitState::Open,
+                to: CircuitState::HalfOpen,
+            },
+            Event::CircuitStateChanged {
+                key,
+                from: CircuitState::HalfOpen,
+                to: CircuitState::Closed,
+            },
+        ];
+        let mut received = Vec::new();
+        for _ in 0..expected.len() {
+            received.push(events.recv().await.unwrap());
+        }
+        assert_eq!(received, expected);
+        assert!(events.try_recv().is_err());
+        assert_eq!(bus.dropped(), 0);
+
+        // the static discover has nothing to watch
+        assert!(EventDiscover::new(StaticDiscover::new(Vec::new()), bus)
+            .watch(None)
+            .is_none());
+    }
+}
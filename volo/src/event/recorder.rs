@@ -0,0 +1,213 @@
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{Event, EventBus};
+
+/// Keeps the recent events of an [`EventBus`] in a ring buffer, e.g. to be served by the admin
+/// endpoint.
+///
+/// The clones of a recorder share the same buffer.
+#[derive(Debug, Clone)]
+pub struct EventRecorder {
+    capacity: usize,
+    events: Arc<Mutex<VecDeque<(SystemTime, Event)>>>,
+}
+
+impl EventRecorder {
+    /// Creates a recorder keeping at most `capacity` recent events.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            events: Arc::new(Mutex::new(VecDeque::with_capacity(capacity.max(1)))),
+        }
+    }
+
+    /// Records the events published to the bus from now on, until the bus is dropped.
+    pub fn record(&self, bus: &EventBus) -> tokio::task::JoinHandle<()> {
+        let mut receiver = bus.subscribe();
+        let recorder = self.clone();
+        tokio::spawn(async move {
+            while let Ok(event) = receiver.recv().await {
+                recorder.push(SystemTime::now(), event);
+            }
+        })
+    }
+
+    fn push(&self, at: SystemTime, event: Event) {
+        let mut events = self.events.lock().unwrap();
+        if events.len() == self.capacity {
+            events.pop_front();
+        }
+        events.push_back((at, event));
+    }
+
+    /// Returns the recent events with the time they are recorded, the oldest first.
+    pub fn recent(&self) -> Vec<(SystemTime, Event)> {
+        self.events.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Returns the recent events as a JSON array, the oldest first, e.g.
+    ///
+    /// ```json
+    /// [{"at_ms":1700000000000,"kind":"circuit_state_changed","key":"127.0.0.1:8000","from":"closed","to":"open"}]
+    /// ```
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("[");
+        for (i, (at, event)) in self.recent().iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            let at = at
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis())
+                .unwrap_or_default();
+            let _ = write!(json, r#"{{"at_ms":{at},"kind":"{}""#, event.kind());
+            match event {
+                Event::InstanceSetChanged { added, removed } => {
+                    json.push_str(r#","added":"#);
+                    push_addresses(&mut json, added);
+                    json.push_str(r#","removed":"#);
+                    push_addresses(&mut json, removed);
+                }
+                Event::CircuitStateChanged { key, from, to } => {
+                    json.push_str(r#","key":"#);
+                    push_str(&mut json, key);
+                    let _ = write!(json, r#","from":"{from}","to":"{to}""#);
+                }
+                Event::ConfigReloaded { source } => {
+                    json.push_str(r#","source":"#);
+                    push_str(&mut json, source);
+                }
+                Event::DiscoveryDiverged {
+                    service,
+                    only_in_primary,
+                    only_in_secondary,
+                    weight_mismatches,
+                } => {
+                    json.push_str(r#","service":"#);
+                    push_str(&mut json, service);
+                    json.push_str(r#","only_in_primary":"#);
+                    push_addresses(&mut json, only_in_primary);
+                    json.push_str(r#","only_in_secondary":"#);
+                    push_addresses(&mut json, only_in_secondary);
+                    json.push_str(r#","weight_mismatches":["#);
+                    for (i, (addr, primary, secondary)) in weight_mismatches.iter().enumerate() {
+                        if i > 0 {
+                            json.push(',');
+                        }
+                        json.push_str(r#"{"address":"#);
+                        push_str(&mut json, &addr.to_string());
+                        let _ = write!(json, r#","primary":{primary},"secondary":{secondary}}}"#);
+                    }
+                    json.push(']');
+                }
+            }
+            json.push('}');
+        }
+        json.push(']');
+        json
+    }
+}
+
+fn push_addresses(json: &mut String, addrs: &[crate::net::Address]) {
+    json.push('[');
+    for (i, addr) in addrs.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        push_str(json, &addr.to_string());
+    }
+    json.push(']');
+}
+
+pub(crate) fn push_str(json: &mut String, s: &str) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::event::CircuitState;
+
+    #[test]
+    fn ring_buffer_and_json() {
+        let recorder = EventRecorder::new(2);
+        let at = UNIX_EPOCH + Duration::from_millis(1500);
+        for source in ["a.yaml", "b.yaml", "c.yaml"] {
+            recorder.push(
+                at,
+                Event::ConfigReloaded {
+                    source: source.into(),
+                },
+            );
+        }
+        assert_eq!(
+            recorder.to_json(),
+            r#"[{"at_ms":1500,"kind":"config_reloaded","source":"b.yaml"},{"at_ms":1500,"kind":"config_reloaded","source":"c.yaml"}]"#
+        );
+
+        let recorder = EventRecorder::new(4);
+        recorder.push(
+            at,
+            Event::CircuitStateChanged {
+                key: "127.0.0.1:8000".into(),
+                from: CircuitState::Open,
+                to: CircuitState::HalfOpen,
+            },
+        );
+        recorder.push(
+            at,
+            Event::InstanceSetChanged {
+                added: vec!["127.0.0.1:8001"
+                    .parse::<std::net::SocketAddr>()
+                    .unwrap()
+                    .into()],
+                removed: Vec::new(),
+            },
+        );
+        recorder.push(
+            at,
+            Event::ConfigReloaded {
+                source: "a\"\n.yaml".into(),
+            },
+        );
+        assert_eq!(
+            recorder.to_json(),
+            concat!(
+                r#"[{"at_ms":1500,"kind":"circuit_state_changed","key":"127.0.0.1:8000","from":"open","to":"half_open"},"#,
+                r#"{"at_ms":1500,"kind":"instance_set_changed","added":["127.0.0.1:8001"],"removed":[]},"#,
+                r#"{"at_ms":1500,"kind":"config_reloaded","source":"a\"\u000a.yaml"}]"#,
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn record() {
+        let bus = EventBus::new(4);
+        let recorder = EventRecorder::new(4);
+        let handle = recorder.record(&bus);
+        bus.publish(Event::ConfigReloaded {
+            source: "a.yaml".into(),
+        });
+        drop(bus);
+        handle.await.unwrap();
+        assert_eq!(recorder.recent().len(), 1);
+    }
+}
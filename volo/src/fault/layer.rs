@@ -0,0 +1,191 @@
+use std::sync::Arc;
+
+use motore::{layer::Layer, service::Service};
+
+use super::{DropPoint, Fault, FaultError, FaultInjector};
+use crate::context::Context;
+
+/// A layer that injects the faults into the calls of a client.
+///
+/// It should be put in the inner layers of the client, so each attempt of the retries is subject
+/// to the faults.
+#[derive(Debug, Clone)]
+pub struct FaultLayer {
+    injector: Arc<FaultInjector>,
+}
+
+impl FaultLayer {
+    pub fn new(injector: Arc<FaultInjector>) -> Self {
+        Self { injector }
+    }
+}
+
+impl<S> Layer<S> for FaultLayer {
+    type Service = FaultService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        FaultService {
+            inner,
+            injector: self.injector,
+        }
+    }
+}
+
+/// The service created by [`FaultLayer`].
+#[derive(Debug, Clone)]
+pub struct FaultService<S> {
+    inner: S,
+    injector: Arc<FaultInjector>,
+}
+
+impl<Cx, Req, S> Service<Cx, Req> for FaultService<S>
+where
+    Cx: Context + Send + 'static,
+    Req: Send + 'static,
+    S: Service<Cx, Req> + Send + Sync + 'static,
+    S::Error: From<FaultError>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call<'s, 'cx>(
+        &'s self,
+        cx: &'cx mut Cx,
+        req: Req,
+    ) -> Result<Self::Response, Self::Error> {
+        let fault = self.injector.fault_of_call(cx.rpc_info().method());
+        match fault {
+            None => self.inner.call(cx, req).await,
+            Some(Fault::Delay(delay)) => {
+                tokio::time::sleep(delay).await;
+                self.inner.call(cx, req).await
+            }
+            Some(Fault::Drop(DropPoint::AfterSend)) => {
+                let _ = self.inner.call(cx, req).await;
+                Err(FaultError::Dropped.into())
+            }
+            Some(Fault::Drop(_)) => Err(FaultError::Dropped.into()),
+            Some(Fault::Error { code, message }) => {
+                Err(FaultError::Injected { code, message }.into())
+            }
+            // not applied to the calls
+            Some(Fault::Corrupt { .. }) => self.inner.call(cx, req).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::{
+        context::{Endpoint, Reusable, Role, RpcCx, RpcInfo},
+        fault::{FaultRule, Matcher},
+        retry::{Classification, RetryLayer, RetryPolicy, RetryStrategy},
+    };
+
+    #[derive(Debug, Default)]
+    struct Config;
+
+    impl Reusable for Config {
+        fn clear(&mut self) {}
+    }
+
+    type Cx = RpcCx<(), Config>;
+
+    fn cx() -> Cx {
+        RpcCx::new(
+            RpcInfo::new(
+                Role::Client,
+                "get".into(),
+                Endpoint::new("caller".into()),
+                Endpoint::new("callee".into()),
+                Config,
+            ),
+            (),
+        )
+    }
+
+    /// Retries the dropped connections without backoff.
+    struct Strategy;
+
+    impl RetryStrategy<Cx, (), (), FaultError> for Strategy {
+        fn policy(&self, _cx: &Cx) -> Option<RetryPolicy> {
+            None
+        }
+
+        fn clone_request(&self, _req: &()) -> Option<()> {
+            Some(())
+        }
+
+        fn classify(&self, _cx: &mut Cx, result: &Result<(), FaultError>) -> Classification {
+            match result {
+                Err(FaultError::Dropped) => Classification::RetryAfter(Duration::ZERO),
+                _ => Classification::Done,
+            }
+        }
+    }
+
+    #[derive(Default)]
+    struct Server {
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl Service<Cx, ()> for Server {
+        type Response = ();
+        type Error = FaultError;
+
+        async fn call<'s, 'cx>(&'s self, _cx: &'cx mut Cx, _req: ()) -> Result<(), FaultError> {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn retry_recovers_from_drop_after_send() {
+        let injector = Arc::new(FaultInjector::with_seed(0));
+        injector.set_rules(vec![FaultRule::new(Fault::Drop(DropPoint::AfterSend))
+            .matcher(Matcher::Method("get".into()))
+            .limit(1)]);
+
+        let server = Server::default();
+        let calls = server.calls.clone();
+        let svc = RetryLayer::new(Strategy)
+            .policy(RetryPolicy::new(3))
+            .layer(FaultLayer::new(injector.clone()).layer(server));
+
+        futures::executor::block_on(svc.call(&mut cx(), ())).unwrap();
+        // the request reached the server twice, and only the first response was dropped
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+        assert_eq!(injector.stats().drops(), 1);
+    }
+
+    #[test]
+    fn before_send_and_error() {
+        let injector = Arc::new(FaultInjector::new());
+        injector.set_rules(vec![
+            FaultRule::new(Fault::Drop(DropPoint::BeforeSend)).limit(1),
+            FaultRule::new(Fault::Error {
+                code: 14,
+                message: "unavailable".into(),
+            }),
+        ]);
+        let svc = FaultLayer::new(injector).layer(Server::default());
+
+        let result = futures::executor::block_on(svc.call(&mut cx(), ()));
+        assert_eq!(result, Err(FaultError::Dropped));
+        let result = futures::executor::block_on(svc.call(&mut cx(), ()));
+        assert_eq!(
+            result,
+            Err(FaultError::Injected {
+                code: 14,
+                message: "unavailable".into(),
+            })
+        );
+        assert_eq!(svc.inner.calls.load(Ordering::Relaxed), 0);
+    }
+}
@@ -0,0 +1,416 @@
+//! Fault injection for testing the resilience of the clients and servers, e.g. retries, circuit
+//! breakers and reconnecting.
+//!
+//! This module is only available with the `fault` feature, and should only be enabled in tests or
+//! staging environments.
+//!
+//! The faults are described by [`FaultRule`]s, which are held by a [`FaultInjector`] and can be
+//! replaced at runtime. The injector can be shared by:
+//! - [`FaultLayer`], which injects the faults into the calls of a client, matching the rules by the
+//!   method of the call.
+//! - [`FaultStream`] and [`FaultIncoming`], which inject the faults into the connections, e.g. the
+//!   connections accepted by a server, or the in-memory streams of the tests. Only the rules
+//!   matching [`Matcher::Any`] are applied to the connections, since there is no method known at
+//!   the transport level.
+//!
+//! The randomness of the probability triggers comes from a seedable RNG, so a test run can be
+//! reproduced with the same seed.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use std::sync::Arc;
+//!
+//! use volo::fault::{DropPoint, Fault, FaultInjector, FaultLayer, FaultRule, Matcher, Trigger};
+//!
+//! let injector = Arc::new(FaultInjector::with_seed(42));
+//! injector.set_rules(vec![
+//!     FaultRule::new(Fault::Drop(DropPoint::AfterSend))
+//!         .matcher(Matcher::Method("GetItem".into()))
+//!         .trigger(Trigger::Probability(0.1)),
+//! ]);
+//!
+//! let client = volo_gen::volo::example::ItemServiceClientBuilder::new("item")
+//!     .layer_inner(FaultLayer::new(injector.clone()))
+//!     .build()
+//!     .unwrap();
+//!
+//! // ...
+//! println!("dropped: {}", injector.stats().drops());
+//! ```
+
+mod layer;
+mod stream;
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::Duration,
+};
+
+use faststr::FastStr;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+pub use self::{
+    layer::{FaultLayer, FaultService},
+    stream::{FaultIncoming, FaultStream},
+};
+
+/// Which calls or connections a [`FaultRule`] applies to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Matcher {
+    /// Matches all the calls and connections.
+    Any,
+    /// Matches the calls of the method.
+    Method(FastStr),
+    /// Matches the calls whose method starts with the prefix, e.g. the path of a gRPC service.
+    Prefix(FastStr),
+}
+
+impl Matcher {
+    fn matches(&self, method: Option<&str>) -> bool {
+        match (self, method) {
+            (Self::Any, _) => true,
+            (Self::Method(m), Some(method)) => m.as_str() == method,
+            (Self::Prefix(p), Some(method)) => method.starts_with(p.as_str()),
+            (_, None) => false,
+        }
+    }
+}
+
+/// When a matched [`FaultRule`] injects its fault.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trigger {
+    /// Injects the fault with the probability in `[0, 1]`.
+    Probability(f64),
+    /// Injects the fault on every nth match, e.g. `EveryNth(3)` injects on the 3rd, 6th, ...
+    /// matches.
+    EveryNth(u64),
+}
+
+/// Where the connection is dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropPoint {
+    /// The request is never sent.
+    BeforeSend,
+    /// The request has been sent, but the response is lost.
+    AfterSend,
+    /// The connection is closed after the bytes have been written, e.g. a server writing a
+    /// partial response. This is only applied to the connections.
+    ///
+    /// Dropping before the trailers of a gRPC response can be simulated by the length of the
+    /// response without its trailers.
+    AfterBytes(usize),
+}
+
+/// The fault injected by a [`FaultRule`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fault {
+    /// Delays the call, or the first write of the connection.
+    Delay(Duration),
+    /// Drops the connection.
+    Drop(DropPoint),
+    /// Flips the bits of the byte at the offset of the written bytes. This is only applied to the
+    /// connections.
+    Corrupt { offset: usize },
+    /// Fails the call with a synthetic error, which is converted to the error of the protocol,
+    /// e.g. the `ApplicationException` of thrift or the `Status` of gRPC. This is only applied to
+    /// the calls.
+    Error { code: i32, message: FastStr },
+}
+
+impl Fault {
+    fn applies_to(&self, point: Point) -> bool {
+        match (self, point) {
+            (Self::Delay(_), _) => true,
+            (Self::Drop(DropPoint::AfterBytes(_)), Point::Call) => false,
+            (Self::Drop(_), _) => true,
+            (Self::Corrupt { .. }, point) => point == Point::Connection,
+            (Self::Error { .. }, point) => point == Point::Call,
+        }
+    }
+}
+
+/// Where the faults are injected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Point {
+    Call,
+    Connection,
+}
+
+/// A rule of injecting a fault.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaultRule {
+    matcher: Matcher,
+    trigger: Trigger,
+    fault: Fault,
+    limit: Option<u64>,
+}
+
+impl FaultRule {
+    /// Creates a rule injecting the fault into all the matches.
+    pub fn new(fault: Fault) -> Self {
+        Self {
+            matcher: Matcher::Any,
+            trigger: Trigger::Probability(1.0),
+            fault,
+            limit: None,
+        }
+    }
+
+    /// Sets the matcher, the default is [`Matcher::Any`].
+    pub fn matcher(mut self, matcher: Matcher) -> Self {
+        self.matcher = matcher;
+        self
+    }
+
+    /// Sets the trigger, the default is injecting into all the matches.
+    pub fn trigger(mut self, trigger: Trigger) -> Self {
+        self.trigger = trigger;
+        self
+    }
+
+    /// Sets the maximum number of the injections of the rule.
+    pub fn limit(mut self, limit: u64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+}
+
+/// The error of the injected faults, which should be converted to the error of the protocol.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum FaultError {
+    #[error("connection dropped by fault injection")]
+    Dropped,
+    #[error("injected error, code: {code}, message: {message}")]
+    Injected { code: i32, message: FastStr },
+}
+
+/// The counters of the injected faults.
+#[derive(Debug, Default)]
+pub struct FaultStats {
+    delays: AtomicU64,
+    drops: AtomicU64,
+    corruptions: AtomicU64,
+    errors: AtomicU64,
+}
+
+impl FaultStats {
+    pub fn delays(&self) -> u64 {
+        self.delays.load(Ordering::Relaxed)
+    }
+
+    pub fn drops(&self) -> u64 {
+        self.drops.load(Ordering::Relaxed)
+    }
+
+    pub fn corruptions(&self) -> u64 {
+        self.corruptions.load(Ordering::Relaxed)
+    }
+
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    /// Returns the total number of the injected faults.
+    pub fn total(&self) -> u64 {
+        self.delays() + self.drops() + self.corruptions() + self.errors()
+    }
+
+    fn record(&self, fault: &Fault) {
+        let counter = match fault {
+            Fault::Delay(_) => &self.delays,
+            Fault::Drop(_) => &self.drops,
+            Fault::Corrupt { .. } => &self.corruptions,
+            Fault::Error { .. } => &self.errors,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// A rule and its counters.
+#[derive(Debug)]
+struct RuleState {
+    rule: FaultRule,
+    matched: AtomicU64,
+    injected: AtomicU64,
+}
+
+/// Decides which fault to inject by the rules.
+///
+/// The rules are checked in order, and the first triggered one wins.
+#[derive(Debug)]
+pub struct FaultInjector {
+    rules: RwLock<Arc<[RuleState]>>,
+    rng: Mutex<StdRng>,
+    stats: FaultStats,
+}
+
+impl Default for FaultInjector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FaultInjector {
+    /// Creates an injector without rules, whose RNG is seeded by the entropy of the system.
+    pub fn new() -> Self {
+        Self::with_rng(StdRng::from_entropy())
+    }
+
+    /// Creates an injector without rules, whose RNG is seeded by the seed, so the faults are
+    /// reproducible for the same sequence of calls.
+    pub fn with_seed(seed: u64) -> Self {
+        Self::with_rng(StdRng::seed_from_u64(seed))
+    }
+
+    fn with_rng(rng: StdRng) -> Self {
+        Self {
+            rules: RwLock::new(Arc::new([])),
+            rng: Mutex::new(rng),
+            stats: FaultStats::default(),
+        }
+    }
+
+    /// Replaces the rules, and resets the counters of the rules.
+    pub fn set_rules(&self, rules: Vec<FaultRule>) {
+        let rules: Arc<[RuleState]> = rules
+            .into_iter()
+            .map(|rule| RuleState {
+                rule,
+                matched: AtomicU64::new(0),
+                injected: AtomicU64::new(0),
+            })
+            .collect();
+        *self.rules.write().unwrap() = rules;
+    }
+
+    /// Returns the current rules.
+    pub fn rules(&self) -> Vec<FaultRule> {
+        let rules = self.rules.read().unwrap().clone();
+        rules.iter().map(|state| state.rule.clone()).collect()
+    }
+
+    pub fn stats(&self) -> &FaultStats {
+        &self.stats
+    }
+
+    /// Returns the fault to inject into a call of the method.
+    pub fn fault_of_call(&self, method: &str) -> Option<Fault> {
+        self.pick(Some(method), Point::Call)
+    }
+
+    /// Returns the fault to inject into a new connection.
+    pub fn fault_of_connection(&self) -> Option<Fault> {
+        self.pick(None, Point::Connection)
+    }
+
+    fn pick(&self, method: Option<&str>, point: Point) -> Option<Fault> {
+        let rules = self.rules.read().unwrap().clone();
+        for state in rules.iter() {
+            let rule = &state.rule;
+            if !rule.fault.applies_to(point) || !rule.matcher.matches(method) {
+                continue;
+            }
+            let matched = state.matched.fetch_add(1, Ordering::Relaxed) + 1;
+            let triggered = match rule.trigger {
+                Trigger::Probability(p) => self.rng.lock().unwrap().gen_bool(p.clamp(0.0, 1.0)),
+                Trigger::EveryNth(n) => n != 0 && matched % n == 0,
+            };
+            if !triggered {
+                continue;
+            }
+            let limit = rule.limit.unwrap_or(u64::MAX);
+            if state
+                .injected
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+                    (n < limit).then_some(n + 1)
+                })
+                .is_err()
+            {
+                continue;
+            }
+            self.stats.record(&rule.fault);
+            tracing::debug!(
+                "[VOLO] injecting fault: {:?}, method: {:?}",
+                rule.fault,
+                method
+            );
+            return Some(rule.fault.clone());
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error() -> Fault {
+        Fault::Error {
+            code: 1,
+            message: "injected".into(),
+        }
+    }
+
+    #[test]
+    fn every_nth_with_limit() {
+        let injector = FaultInjector::new();
+        injector.set_rules(vec![FaultRule::new(error())
+            .matcher(Matcher::Method("get".into()))
+            .trigger(Trigger::EveryNth(2))
+            .limit(2)]);
+
+        let faults: Vec<_> = (0..8)
+            .map(|_| injector.fault_of_call("get").is_some())
+            .collect();
+        assert_eq!(
+            faults,
+            [false, true, false, true, false, false, false, false]
+        );
+        assert!(injector.fault_of_call("set").is_none());
+        assert_eq!(injector.stats().errors(), 2);
+    }
+
+    #[test]
+    fn seeded_probability_is_reproducible() {
+        let run = |seed| {
+            let injector = FaultInjector::with_seed(seed);
+            injector.set_rules(vec![
+                FaultRule::new(error()).trigger(Trigger::Probability(0.5))
+            ]);
+            (0..64)
+                .map(|_| injector.fault_of_call("get").is_some())
+                .collect::<Vec<_>>()
+        };
+        let faults = run(42);
+        assert_eq!(faults, run(42));
+        assert!(faults.contains(&true) && faults.contains(&false));
+    }
+
+    #[test]
+    fn rules_apply_to_their_points() {
+        let injector = FaultInjector::new();
+        injector.set_rules(vec![
+            FaultRule::new(Fault::Corrupt { offset: 0 }),
+            FaultRule::new(error()).matcher(Matcher::Prefix("/hello.Greeter/".into())),
+        ]);
+
+        assert_eq!(
+            injector.fault_of_call("/hello.Greeter/SayHello"),
+            Some(error())
+        );
+        assert!(injector.fault_of_call("/hello.Other/SayHello").is_none());
+        assert_eq!(
+            injector.fault_of_connection(),
+            Some(Fault::Corrupt { offset: 0 })
+        );
+
+        // the rules can be replaced at runtime
+        injector.set_rules(vec![]);
+        assert!(injector.fault_of_connection().is_none());
+        assert_eq!(injector.stats().total(), 2);
+    }
+}
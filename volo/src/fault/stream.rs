@@ -0,0 +1,259 @@
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures::ready;
+use pin_project::pin_project;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::Sleep,
+};
+
+use super::{DropPoint, Fault, FaultError, FaultInjector};
+use crate::net::{
+    conn::{Conn, ConnStream},
+    incoming::{Incoming, MakeIncoming},
+};
+
+fn dropped() -> io::Error {
+    io::Error::new(io::ErrorKind::ConnectionReset, FaultError::Dropped)
+}
+
+/// A stream that injects the fault into the connection, which is decided by the [`FaultInjector`]
+/// when the stream is created.
+///
+/// It wraps any [`AsyncRead`] and [`AsyncWrite`], so it can be used with the in-memory streams,
+/// e.g. [`tokio::io::duplex`], in the tests.
+#[pin_project]
+#[derive(Debug)]
+pub struct FaultStream<S> {
+    #[pin]
+    inner: S,
+    fault: Option<Fault>,
+    written: usize,
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl<S> FaultStream<S> {
+    /// Creates a stream with the fault of a new connection by the injector.
+    pub fn new(inner: S, injector: &FaultInjector) -> Self {
+        Self::with_fault(inner, injector.fault_of_connection())
+    }
+
+    /// Creates a stream with the fault.
+    pub fn with_fault(inner: S, fault: Option<Fault>) -> Self {
+        Self {
+            inner,
+            fault,
+            written: 0,
+            delay: None,
+        }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S> AsyncRead for FaultStream<S>
+where
+    S: AsyncRead,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.project();
+        if *this.fault == Some(Fault::Drop(DropPoint::AfterSend)) && *this.written > 0 {
+            return Poll::Ready(Err(dropped()));
+        }
+        this.inner.poll_read(cx, buf)
+    }
+}
+
+impl<S> AsyncWrite for FaultStream<S>
+where
+    S: AsyncWrite,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.project();
+        let n = match this.fault {
+            None | Some(Fault::Drop(DropPoint::AfterSend)) | Some(Fault::Error { .. }) => {
+                ready!(this.inner.poll_write(cx, buf))?
+            }
+            Some(Fault::Delay(delay)) => {
+                let sleep = this
+                    .delay
+                    .get_or_insert_with(|| Box::pin(tokio::time::sleep(*delay)));
+                ready!(sleep.as_mut().poll(cx));
+                *this.delay = None;
+                *this.fault = None;
+                ready!(this.inner.poll_write(cx, buf))?
+            }
+            Some(Fault::Drop(DropPoint::BeforeSend)) => return Poll::Ready(Err(dropped())),
+            Some(Fault::Drop(DropPoint::AfterBytes(limit))) => {
+                let remaining = limit.saturating_sub(*this.written);
+                if remaining == 0 {
+                    return Poll::Ready(Err(dropped()));
+                }
+                let len = buf.len().min(remaining);
+                ready!(this.inner.poll_write(cx, &buf[..len]))?
+            }
+            Some(Fault::Corrupt { offset }) => {
+                let Some(pos) = offset
+                    .checked_sub(*this.written)
+                    .filter(|pos| *pos < buf.len())
+                else {
+                    let n = ready!(this.inner.poll_write(cx, buf))?;
+                    *this.written += n;
+                    return Poll::Ready(Ok(n));
+                };
+                let mut corrupted = buf.to_vec();
+                corrupted[pos] = !corrupted[pos];
+                let n = ready!(this.inner.poll_write(cx, &corrupted))?;
+                if n > pos {
+                    *this.fault = None;
+                }
+                n
+            }
+        };
+        *this.written += n;
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_shutdown(cx)
+    }
+}
+
+/// Wraps the connections accepted by the server with [`FaultStream`].
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let injector = Arc::new(FaultInjector::with_seed(42));
+/// let incoming = FaultIncoming::new(addr, injector.clone());
+///
+/// volo_gen::volo::example::ItemServiceServer::new(S)
+///     .run(incoming)
+///     .await
+///     .unwrap();
+/// ```
+#[derive(Debug)]
+pub struct FaultIncoming<I> {
+    inner: I,
+    injector: Arc<FaultInjector>,
+}
+
+impl<I> FaultIncoming<I> {
+    pub fn new(inner: I, injector: Arc<FaultInjector>) -> Self {
+        Self { inner, injector }
+    }
+}
+
+impl<M> MakeIncoming for FaultIncoming<M>
+where
+    M: MakeIncoming + Send,
+{
+    type Incoming = FaultIncoming<M::Incoming>;
+
+    async fn make_incoming(self) -> io::Result<Self::Incoming> {
+        Ok(FaultIncoming {
+            inner: self.inner.make_incoming().await?,
+            injector: self.injector,
+        })
+    }
+}
+
+impl<I> Incoming for FaultIncoming<I>
+where
+    I: Incoming,
+{
+    async fn accept(&mut self) -> io::Result<Option<Conn>> {
+        let Some(mut conn) = self.inner.accept().await? else {
+            return Ok(None);
+        };
+        if let Some(fault) = self.injector.fault_of_connection() {
+            conn.stream =
+                ConnStream::Fault(Box::pin(FaultStream::with_fault(conn.stream, Some(fault))));
+        }
+        Ok(Some(conn))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    async fn transfer(fault: Fault, data: &[u8]) -> (io::Result<()>, Vec<u8>) {
+        let (client, mut server) = tokio::io::duplex(64);
+        let mut client = FaultStream::with_fault(client, Some(fault));
+        let result = client.write_all(data).await;
+        drop(client);
+
+        let mut received = Vec::new();
+        server.read_to_end(&mut received).await.unwrap();
+        (result, received)
+    }
+
+    #[tokio::test]
+    async fn drop_after_bytes() {
+        let (result, received) = transfer(Fault::Drop(DropPoint::AfterBytes(3)), b"hello").await;
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::ConnectionReset);
+        assert_eq!(received, b"hel");
+
+        let (result, received) = transfer(Fault::Drop(DropPoint::BeforeSend), b"hello").await;
+        assert!(result.is_err());
+        assert!(received.is_empty());
+    }
+
+    #[tokio::test]
+    async fn corrupt_byte() {
+        let (result, received) = transfer(Fault::Corrupt { offset: 1 }, b"hello").await;
+        result.unwrap();
+        assert_eq!(received, [b'h', !b'e', b'l', b'l', b'o']);
+    }
+
+    #[tokio::test]
+    async fn delay_first_write() {
+        let start = tokio::time::Instant::now();
+        let (result, received) = transfer(Fault::Delay(Duration::from_millis(50)), b"hello").await;
+        result.unwrap();
+        assert_eq!(received, b"hello");
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn drop_response_after_send() {
+        let (client, mut server) = tokio::io::duplex(64);
+        let mut client = FaultStream::with_fault(client, Some(Fault::Drop(DropPoint::AfterSend)));
+        client.write_all(b"ping").await.unwrap();
+        server.write_all(b"pong").await.unwrap();
+
+        let mut buf = [0; 4];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+        assert!(client.read_exact(&mut buf).await.is_err());
+    }
+}
@@ -1,5 +1,5 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     error::Error,
     fmt::Display,
     io::{IoSlice, IoSliceMut},
@@ -96,6 +96,8 @@ enum HotRestartMessage {
 pub struct HotRestart {
     state: Arc<Mutex<HotRestartState>>,
     listener_fds: Arc<StdMutex<HashMap<String, RawFd>>>,
+    // the addresses of the listeners passed to the child
+    passed_fds: Arc<StdMutex<HashSet<String>>>,
     dup_listener_num: AtomicI32,
     listener_num: AtomicI32,
     parent_sock_path: OnceLock<PathBuf>,
@@ -114,6 +116,7 @@ impl HotRestart {
         HotRestart {
             state: Arc::new(Mutex::new(HotRestartState::Uninitalized)),
             listener_fds: Arc::new(StdMutex::new(HashMap::new())),
+            passed_fds: Arc::new(StdMutex::new(HashSet::new())),
             listener_num: AtomicI32::new(0),
             dup_listener_num: AtomicI32::new(0),
             parent_sock_path: OnceLock::new(),
@@ -179,6 +182,7 @@ impl HotRestart {
             domain_sock,
             self.child_sock_path.get().unwrap().clone(),
             fds,
+            self.passed_fds.clone(),
         ));
 
         Ok(())
@@ -188,6 +192,7 @@ impl HotRestart {
         parent_sock: UnixDatagram,
         child_sock_path: PathBuf,
         fds: Arc<StdMutex<HashMap<String, RawFd>>>,
+        passed_fds: Arc<StdMutex<HashSet<String>>>,
     ) -> io::Result<()> {
         tracing::info!("hot_restart parent_handle");
         loop {
@@ -202,6 +207,7 @@ impl HotRestart {
                             HotRestartMsgType::PassFdResponse,
                             HotRestartMessage::PassFdResponse(*fd),
                         )?;
+                        passed_fds.lock().unwrap().insert(addr);
                     }
                 }
                 Ok(HotRestartMessage::TerminateParentRequest) => {
@@ -333,6 +339,11 @@ impl HotRestart {
         listener_fds.insert(addr, raw_fd);
     }
 
+    /// Returns whether the listener of the address has been passed to the child process.
+    pub fn is_passed_to_child(&self, addr: &str) -> bool {
+        self.passed_fds.lock().unwrap().contains(addr)
+    }
+
     pub async fn dup_parent_listener_sock(&self, addr: String) -> io::Result<Option<RawFd>> {
         let mut state = self.state.lock().await;
         if *state != HotRestartState::ChildInitialized {
@@ -7,11 +7,37 @@
 pub use motore::{layer, layer::Layer, service, Service};
 pub use tokio::main;
 
+pub mod acl;
 pub mod catch_panic;
+pub mod circuit_breaker;
+pub mod config;
 pub mod context;
+pub mod dedup;
+pub mod descriptor;
 pub mod discovery;
+pub mod error;
+pub mod event;
+#[cfg(feature = "fault")]
+#[cfg_attr(docsrs, doc(cfg(feature = "fault")))]
+pub mod fault;
+pub mod load_shed;
 pub mod loadbalance;
+#[cfg(feature = "metrics")]
+#[cfg_attr(docsrs, doc(cfg(feature = "metrics")))]
+pub mod metrics;
 pub mod net;
+#[cfg(feature = "otel")]
+#[cfg_attr(docsrs, doc(cfg(feature = "otel")))]
+pub mod otel;
+pub mod preset;
+pub mod profile;
+pub mod rate_limit;
+pub mod readiness;
+pub mod retry;
+pub mod route;
+pub mod script;
+pub mod server;
+pub mod stats;
 pub mod util;
 pub use hack::Unwrap;
 #[cfg(target_family = "unix")]
@@ -26,19 +52,12 @@ pub use faststr::FastStr;
 pub use metainfo::METAINFO;
 
 /// `volo::spawn` will spawn a task and derive the metainfo
+///
+/// The tracing span and the deadline are inherited as well, see [`context::spawn_inherit`].
 pub fn spawn<T>(future: T) -> tokio::task::JoinHandle<T::Output>
 where
     T: futures::Future + Send + 'static,
     T::Output: Send + 'static,
 {
-    let mi = METAINFO
-        .try_with(|m| {
-            let prev_mi = m.take();
-            let (m1, m2) = prev_mi.derive();
-            m.replace(m1);
-            m2
-        })
-        .unwrap_or_else(|_| metainfo::MetaInfo::new());
-
-    tokio::spawn(METAINFO.scope(std::cell::RefCell::new(mi), future))
+    context::spawn_inherit(future)
 }
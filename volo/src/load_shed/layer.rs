@@ -0,0 +1,101 @@
+use std::sync::Arc;
+
+use motore::{layer::Layer, service::Service};
+
+use super::{LoadShedRejection, LoadShedder};
+use crate::context::Context;
+
+/// A layer that rejects the requests beyond the concurrency limit of the [`LoadShedder`].
+///
+/// This layer should be put in the outer layers of the server, so that the rejected requests cost
+/// as little as possible, and the latency of the other layers is also controlled. The
+/// [`LoadShedder`] is shared by all the clones of the service created by this layer.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use volo::load_shed::{LoadShedLayer, LoadShedder};
+/// use volo_grpc::layer::load_shed::GrpcLoadShedRejection;
+///
+/// let shedder = LoadShedder::new(Duration::from_millis(50)).max_queue(100);
+///
+/// Server::new()
+///     .layer_front(LoadShedLayer::new(shedder, GrpcLoadShedRejection))
+///     .add_service(ServiceBuilder::new(GreeterServer::new(S)).build())
+///     .run(addr)
+///     .await
+///     .unwrap();
+/// ```
+#[derive(Clone)]
+pub struct LoadShedLayer<R> {
+    shedder: Arc<LoadShedder>,
+    rejection: R,
+}
+
+impl<R> LoadShedLayer<R> {
+    pub fn new(shedder: impl Into<Arc<LoadShedder>>, rejection: R) -> Self {
+        Self {
+            shedder: shedder.into(),
+            rejection,
+        }
+    }
+}
+
+impl<S, R> Layer<S> for LoadShedLayer<R> {
+    type Service = LoadShedService<S, R>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        LoadShedService {
+            inner,
+            shedder: self.shedder,
+            rejection: Arc::new(self.rejection),
+        }
+    }
+}
+
+/// The service created by [`LoadShedLayer`].
+pub struct LoadShedService<S, R> {
+    inner: S,
+    shedder: Arc<LoadShedder>,
+    rejection: Arc<R>,
+}
+
+impl<S: Clone, R> Clone for LoadShedService<S, R> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            shedder: self.shedder.clone(),
+            rejection: self.rejection.clone(),
+        }
+    }
+}
+
+impl<Cx, Req, S, R> Service<Cx, Req> for LoadShedService<S, R>
+where
+    Cx: Context + Send + 'static,
+    Req: Send + 'static,
+    S: Service<Cx, Req> + Send + Sync + 'static,
+    R: LoadShedRejection<Cx, S::Error> + Send + Sync,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call<'s, 'cx>(
+        &'s self,
+        cx: &'cx mut Cx,
+        req: Req,
+    ) -> Result<Self::Response, Self::Error> {
+        let _permit = match self.shedder.acquire() {
+            Ok(permit) => permit,
+            Err(overloaded) => {
+                tracing::debug!(
+                    "[VOLO] request rejected by load shedding, limit: {}, rpcinfo: {:?}",
+                    overloaded.limit,
+                    cx.rpc_info()
+                );
+                return Err(self.rejection.reject(cx, overloaded));
+            }
+        };
+        self.inner.call(cx, req).await
+    }
+}
@@ -0,0 +1,486 @@
+//! Adaptive load shedding of the servers for overload protection.
+//!
+//! The [`LoadShedder`] limits the concurrency of the handlers, and adjusts the limit by the
+//! latency of the handlers in the recent window, which is the same span as the `process_start_at`
+//! and `process_end_at` stats of the contexts, in the way of the gradient algorithm:
+//!
+//! - when the p99 latency exceeds the target, or the estimated queue exceeds the maximum, the limit
+//!   is decreased by the ratio of the excess, at most by half in a window;
+//! - otherwise the limit is increased by its square root if the limit is being used, so the
+//!   throughput recovers after the latency drops.
+//!
+//! A request beyond the limit is rejected immediately instead of waiting in a queue, since the
+//! queued requests only make the latency of all the requests worse under overload. The
+//! [`LoadShedLayer`] rejects them by the protocol specific [`LoadShedRejection`], which are
+//! provided in `volo-thrift` and `volo-grpc`.
+//!
+//! The state is kept in atomics, and the limit is adjusted by the request which closes a window,
+//! so the shedder can be shared by all the connections without locks.
+//!
+//! With the `metrics` feature, [`LoadShedder`] is also a [`Source`](crate::metrics::Source)
+//! exposing the gauges of the limit, the inflight requests and the recent p99 latency.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use volo::load_shed::{LoadShedLayer, LoadShedder};
+//! use volo_thrift::server::layer::load_shed::ThriftLoadShedRejection;
+//!
+//! let shedder = Arc::new(LoadShedder::new(Duration::from_millis(50)));
+//! registry.register(shedder.clone());
+//!
+//! server
+//!     .layer_front(LoadShedLayer::new(shedder, ThriftLoadShedRejection))
+//!     .run(addr)
+//!     .await
+//!     .unwrap();
+//! ```
+
+mod layer;
+
+use std::{
+    sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+pub use self::layer::{LoadShedLayer, LoadShedService};
+
+const DEFAULT_INITIAL_LIMIT: usize = 100;
+const DEFAULT_MIN_LIMIT: usize = 8;
+const DEFAULT_MAX_LIMIT: usize = 1000;
+const DEFAULT_WINDOW: Duration = Duration::from_millis(100);
+const DEFAULT_MIN_SAMPLES: u64 = 20;
+const DEFAULT_SMOOTHING: f64 = 0.2;
+
+/// The minimum ratio the limit is multiplied by in a window.
+const MIN_GRADIENT: f64 = 0.5;
+
+/// The latency histogram has 4 buckets per power of 2 of microseconds, i.e. an error of at most
+/// 19%, up to about 9 hours.
+const BUCKETS_PER_OCTAVE: f64 = 4.0;
+const BUCKETS: usize = 128;
+
+/// The error of a request rejected by the [`LoadShedder`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("overloaded, concurrency limit {limit} reached")]
+pub struct Overloaded {
+    /// The concurrency limit when the request is rejected.
+    pub limit: usize,
+}
+
+/// The protocol specific part of load shedding, which makes the error of a rejected request.
+pub trait LoadShedRejection<Cx, E> {
+    /// Makes the error returned for the rejected request, and sets the hints in the context if
+    /// needed.
+    fn reject(&self, cx: &mut Cx, overloaded: Overloaded) -> E;
+}
+
+/// The gauges of a [`LoadShedder`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoadShedStats {
+    /// The current concurrency limit.
+    pub limit: usize,
+    /// The requests being handled.
+    pub inflight: usize,
+    /// The p99 latency of the last window.
+    pub p99: Duration,
+    /// The requests rejected since the shedder is created.
+    pub rejected: u64,
+}
+
+/// The samples of the current window.
+#[derive(Debug)]
+struct Window {
+    start: AtomicU64,
+    count: AtomicU64,
+    sum_us: AtomicU64,
+    min_us: AtomicU64,
+    max_inflight: AtomicUsize,
+    buckets: [AtomicU32; BUCKETS],
+}
+
+/// The concurrency limiter adjusted by the latency, see the [module docs](self).
+#[derive(Debug)]
+pub struct LoadShedder {
+    epoch: Instant,
+    target: Duration,
+    max_queue: Option<f64>,
+    min_limit: usize,
+    max_limit: usize,
+    window_size: Duration,
+    min_samples: u64,
+    smoothing: f64,
+
+    /// The bits of the `f64` limit.
+    limit: AtomicU64,
+    inflight: AtomicUsize,
+    rejected: AtomicU64,
+    p99_us: AtomicU64,
+    window: Window,
+}
+
+impl LoadShedder {
+    /// Creates a [`LoadShedder`] keeping the p99 latency of the handlers under the target.
+    pub fn new(target: Duration) -> Self {
+        Self {
+            epoch: Instant::now(),
+            target,
+            max_queue: None,
+            min_limit: DEFAULT_MIN_LIMIT,
+            max_limit: DEFAULT_MAX_LIMIT,
+            window_size: DEFAULT_WINDOW,
+            min_samples: DEFAULT_MIN_SAMPLES,
+            smoothing: DEFAULT_SMOOTHING,
+            limit: AtomicU64::new((DEFAULT_INITIAL_LIMIT as f64).to_bits()),
+            inflight: AtomicUsize::new(0),
+            rejected: AtomicU64::new(0),
+            p99_us: AtomicU64::new(0),
+            window: Window {
+                start: AtomicU64::new(0),
+                count: AtomicU64::new(0),
+                sum_us: AtomicU64::new(0),
+                min_us: AtomicU64::new(u64::MAX),
+                max_inflight: AtomicUsize::new(0),
+                buckets: std::array::from_fn(|_| AtomicU32::new(0)),
+            },
+        }
+    }
+
+    /// Sets the concurrency limit before any window is closed, which is clamped by the minimum
+    /// and the maximum.
+    ///
+    /// Default is `100`.
+    pub fn initial_limit(self, limit: usize) -> Self {
+        let limit = limit.clamp(self.min_limit, self.max_limit) as f64;
+        self.limit.store(limit.to_bits(), Ordering::Relaxed);
+        self
+    }
+
+    /// Sets the bounds of the concurrency limit, the minimum is at least 1.
+    ///
+    /// Default is `8..=1000`.
+    pub fn limits(mut self, min: usize, max: usize) -> Self {
+        self.min_limit = min.max(1);
+        self.max_limit = max.max(self.min_limit);
+        let limit = self
+            .limit_f64()
+            .clamp(self.min_limit as f64, self.max_limit as f64);
+        self.limit.store(limit.to_bits(), Ordering::Relaxed);
+        self
+    }
+
+    /// Also decreases the limit when the requests waiting in the handlers, which is estimated by
+    /// the Little's law as `throughput * (avg_latency - min_latency)`, exceed the maximum.
+    ///
+    /// Default is not to estimate the queue.
+    pub fn max_queue(mut self, max_queue: usize) -> Self {
+        self.max_queue = Some(max_queue.max(1) as f64);
+        self
+    }
+
+    /// Sets the window in which the latency is sampled, and the window is only closed when it
+    /// has at least `min_samples` requests.
+    ///
+    /// Default is `100ms` and `20`.
+    pub fn window(mut self, window: Duration, min_samples: u64) -> Self {
+        self.window_size = window;
+        self.min_samples = min_samples.max(1);
+        self
+    }
+
+    /// Sets the weight of the new limit of a window, in `(0, 1]`, the smaller the smoother.
+    ///
+    /// Default is `0.2`.
+    pub fn smoothing(mut self, smoothing: f64) -> Self {
+        self.smoothing = smoothing.clamp(f64::EPSILON, 1.0);
+        self
+    }
+
+    /// Admits a request if the concurrency limit is not reached, and the returned permit records
+    /// the latency when it is dropped.
+    pub fn acquire(&self) -> Result<LoadShedPermit<'_>, Overloaded> {
+        self.admit().map(|()| LoadShedPermit {
+            shedder: self,
+            start: self.now(),
+        })
+    }
+
+    /// Returns the current gauges.
+    pub fn stats(&self) -> LoadShedStats {
+        LoadShedStats {
+            limit: self.limit(),
+            inflight: self.inflight.load(Ordering::Relaxed),
+            p99: Duration::from_micros(self.p99_us.load(Ordering::Relaxed)),
+            rejected: self.rejected.load(Ordering::Relaxed),
+        }
+    }
+
+    fn now(&self) -> u64 {
+        self.epoch.elapsed().as_nanos() as u64
+    }
+
+    fn limit_f64(&self) -> f64 {
+        f64::from_bits(self.limit.load(Ordering::Relaxed))
+    }
+
+    fn limit(&self) -> usize {
+        self.limit_f64() as usize
+    }
+
+    fn admit(&self) -> Result<(), Overloaded> {
+        let limit = self.limit();
+        match self
+            .inflight
+            .fetch_update(Ordering::AcqRel, Ordering::Relaxed, |n| {
+                (n < limit).then_some(n + 1)
+            }) {
+            Ok(n) => {
+                self.window.max_inflight.fetch_max(n + 1, Ordering::Relaxed);
+                Ok(())
+            }
+            Err(_) => {
+                self.rejected.fetch_add(1, Ordering::Relaxed);
+                Err(Overloaded { limit })
+            }
+        }
+    }
+
+    fn release_at(&self, now: u64, start: u64) {
+        self.inflight.fetch_sub(1, Ordering::AcqRel);
+
+        let latency_us = now.saturating_sub(start) / 1000;
+        let w = &self.window;
+        w.buckets[bucket(latency_us)].fetch_add(1, Ordering::Relaxed);
+        w.sum_us.fetch_add(latency_us, Ordering::Relaxed);
+        w.min_us.fetch_min(latency_us, Ordering::Relaxed);
+        let count = w.count.fetch_add(1, Ordering::Relaxed) + 1;
+
+        let window_start = w.start.load(Ordering::Acquire);
+        if count < self.min_samples
+            || now.saturating_sub(window_start) < self.window_size.as_nanos() as u64
+        {
+            return;
+        }
+        // only the request which wins the race closes the window, the samples recorded by the
+        // others during the closing go into the next window
+        if w.start
+            .compare_exchange(window_start, now, Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok()
+        {
+            self.close_window(now.saturating_sub(window_start));
+        }
+    }
+
+    fn close_window(&self, elapsed: u64) {
+        let w = &self.window;
+        let count = w.count.swap(0, Ordering::Relaxed);
+        let sum_us = w.sum_us.swap(0, Ordering::Relaxed);
+        let min_us = w.min_us.swap(u64::MAX, Ordering::Relaxed);
+        let max_inflight = w.max_inflight.swap(0, Ordering::Relaxed);
+        let mut buckets = [0u32; BUCKETS];
+        for (b, counter) in buckets.iter_mut().zip(w.buckets.iter()) {
+            *b = counter.swap(0, Ordering::Relaxed);
+        }
+        if count == 0 {
+            return;
+        }
+
+        let p99_us = quantile(&buckets, 0.99);
+        self.p99_us.store(p99_us, Ordering::Relaxed);
+
+        let target_us = self.target.as_micros().max(1) as f64;
+        let mut gradient = (target_us / p99_us.max(1) as f64).clamp(MIN_GRADIENT, 1.0);
+        if let Some(max_queue) = self.max_queue {
+            // Little's law, the requests in the handlers beyond the ones being processed
+            let throughput = count as f64 / (elapsed.max(1) as f64 / 1e6);
+            let avg_us = sum_us as f64 / count as f64;
+            let queue = throughput * (avg_us - min_us as f64).max(0.0);
+            if queue > max_queue {
+                gradient = gradient.min((max_queue / queue).clamp(MIN_GRADIENT, 1.0));
+            }
+        }
+
+        let limit = self.limit_f64();
+        // the limit is not growing if it is not being used, or it may grow without bound
+        if gradient >= 1.0 && (max_inflight as f64) < limit / 2.0 {
+            return;
+        }
+        let new_limit = limit * gradient + limit.sqrt();
+        let limit = (limit * (1.0 - self.smoothing) + new_limit * self.smoothing)
+            .clamp(self.min_limit as f64, self.max_limit as f64);
+        self.limit.store(limit.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Returns the bucket of the latency.
+fn bucket(latency_us: u64) -> usize {
+    (((latency_us + 1) as f64).log2() * BUCKETS_PER_OCTAVE) as usize
+}
+
+/// Returns the upper bound of the bucket where the quantile falls.
+fn quantile(buckets: &[u32; BUCKETS], q: f64) -> u64 {
+    let total = buckets.iter().map(|b| *b as u64).sum::<u64>();
+    let rank = ((total as f64) * q).ceil() as u64;
+    let mut seen = 0;
+    for (i, b) in buckets.iter().enumerate() {
+        seen += *b as u64;
+        if seen >= rank {
+            return (2f64.powf((i + 1) as f64 / BUCKETS_PER_OCTAVE) - 1.0) as u64;
+        }
+    }
+    0
+}
+
+/// A request admitted by the [`LoadShedder`], which records the latency when it is dropped.
+#[must_use]
+#[derive(Debug)]
+pub struct LoadShedPermit<'a> {
+    shedder: &'a LoadShedder,
+    start: u64,
+}
+
+impl Drop for LoadShedPermit<'_> {
+    fn drop(&mut self) {
+        self.shedder.release_at(self.shedder.now(), self.start);
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl crate::metrics::Source for LoadShedder {
+    fn collect(&self, visitor: &mut dyn crate::metrics::Visitor) {
+        use crate::metrics::{Desc, Value};
+
+        let stats = self.stats();
+        visitor.visit(
+            &Desc::new("volo_load_shed_limit", "The concurrency limit."),
+            &[],
+            Value::Gauge(stats.limit as f64),
+        );
+        visitor.visit(
+            &Desc::new("volo_load_shed_inflight", "The requests being handled."),
+            &[],
+            Value::Gauge(stats.inflight as f64),
+        );
+        visitor.visit(
+            &Desc::new(
+                "volo_load_shed_latency_p99_seconds",
+                "The p99 latency of the handlers in the last window.",
+            ),
+            &[],
+            Value::Gauge(stats.p99.as_secs_f64()),
+        );
+        visitor.visit(
+            &Desc::new("volo_load_shed_rejected_total", "The rejected requests."),
+            &[],
+            Value::Counter(stats.rejected),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cmp::Reverse, collections::BinaryHeap};
+
+    use super::*;
+
+    const MS: u64 = 1_000_000;
+
+    #[test]
+    fn limit() {
+        let shedder = LoadShedder::new(Duration::from_millis(10))
+            .limits(1, 10)
+            .initial_limit(2);
+        let a = shedder.acquire().unwrap();
+        let _b = shedder.acquire().unwrap();
+        assert_eq!(shedder.acquire().unwrap_err(), Overloaded { limit: 2 });
+        drop(a);
+        assert!(shedder.acquire().is_ok());
+        assert_eq!(shedder.stats().rejected, 1);
+    }
+
+    #[test]
+    fn quantiles() {
+        let mut buckets = [0; BUCKETS];
+        for us in 1..=1000 {
+            buckets[bucket(us)] += 1;
+        }
+        let p99 = quantile(&buckets, 0.99);
+        assert!((990..=1200).contains(&p99), "p99: {p99}");
+        let p50 = quantile(&buckets, 0.5);
+        assert!((500..=600).contains(&p50), "p50: {p50}");
+    }
+
+    /// Simulates a server with 20 workers receiving 1 request per millisecond, whose latency
+    /// grows with the concurrency beyond the workers, and the base latency of the handler spikes
+    /// from 10ms to 30ms for a while.
+    #[test]
+    fn shed_and_recover() {
+        const TARGET: Duration = Duration::from_millis(50);
+        const WORKERS: u64 = 20;
+
+        struct Phase {
+            accepted: u64,
+            rejected: u64,
+            latency_sum: u64,
+        }
+
+        let shedder = LoadShedder::new(TARGET);
+        let mut running = BinaryHeap::<Reverse<(u64, u64)>>::new();
+        let mut phases = Vec::new();
+        let mut now = 0;
+        // (base latency, duration), the second half of each phase is measured
+        for (base, duration) in [(10, 2000), (30, 6000), (10, 4000)] {
+            let mut phase = Phase {
+                accepted: 0,
+                rejected: 0,
+                latency_sum: 0,
+            };
+            for tick in 0..duration {
+                now += MS;
+                while let Some(&Reverse((end, start))) = running.peek() {
+                    if end > now {
+                        break;
+                    }
+                    running.pop();
+                    shedder.release_at(end, start);
+                }
+
+                let measured = tick >= duration / 2;
+                match shedder.admit() {
+                    Ok(()) => {
+                        let inflight = running.len() as u64 + 1;
+                        let latency = base * MS * inflight.max(WORKERS) / WORKERS;
+                        running.push(Reverse((now + latency, now)));
+                        if measured {
+                            phase.accepted += 1;
+                            phase.latency_sum += latency;
+                        }
+                    }
+                    Err(_) => {
+                        if measured {
+                            phase.rejected += 1;
+                        }
+                    }
+                }
+            }
+            phases.push(phase);
+        }
+
+        // nothing is shed before the spike
+        assert_eq!(phases[0].rejected, 0);
+        // during the spike, the latency is kept around the target by shedding, otherwise the
+        // requests pile up without bound
+        let spike = &phases[1];
+        assert!(
+            spike.rejected * 10 > spike.accepted + spike.rejected,
+            "accepted: {}, rejected: {}",
+            spike.accepted,
+            spike.rejected
+        );
+        let avg = spike.latency_sum / spike.accepted;
+        assert!(avg < 2 * TARGET.as_nanos() as u64, "avg latency: {avg}ns");
+        // recovered after the spike
+        assert_eq!(phases[2].rejected, 0);
+        assert!(shedder.stats().p99 < TARGET);
+    }
+}
@@ -1,6 +1,6 @@
 use std::{cmp::min, collections::HashSet, hash::Hash, sync::Arc};
 
-use dashmap::{mapref::entry::Entry, DashMap};
+use dashmap::{mapref::entry::Entry, DashMap, DashSet};
 
 use super::{error::LoadBalanceError, LoadBalance, RequestHash};
 use crate::{
@@ -88,6 +88,12 @@ struct WeightedInstances {
     virtual_nodes: Vec<VirtualNode>,
 }
 
+/// The picker of [`ConsistentHashBalance`], which returns the instance of the request hash on the
+/// ring first, and then the following distinct instances on the ring as the replicas.
+///
+/// The instances marked as unhealthy are skipped, so the requests fall back to the next instance
+/// on the ring. They will still be returned after all the healthy ones, in case that no healthy
+/// instance is available.
 #[derive(Debug)]
 pub struct InstancePicker {
     shared_instances: Arc<WeightedInstances>,
@@ -95,6 +101,9 @@ pub struct InstancePicker {
     /// used for searching the virtual node
     request_hash: RequestHash,
 
+    /// The index of the first virtual node to search
+    start: Option<usize>,
+
     /// The index of the last selected virtual node
     last_pick: Option<usize>,
 
@@ -103,6 +112,13 @@ pub struct InstancePicker {
 
     /// The number of replicas to pick, min(option.replicas, real_nodes.len())
     replicas: usize,
+
+    /// The instances that should be avoided
+    unhealthy: Arc<DashSet<Address>>,
+
+    /// Whether the unhealthy instances can be picked, which happens after all the healthy ones
+    /// have been picked
+    fallback: bool,
 }
 
 impl Iterator for InstancePicker {
@@ -110,7 +126,7 @@ impl Iterator for InstancePicker {
 
     fn next(&mut self) -> Option<Self::Item> {
         let virtual_nodes = &self.shared_instances.virtual_nodes;
-        if self.shared_instances.real_nodes.is_empty() {
+        if virtual_nodes.is_empty() {
             return None;
         }
 
@@ -119,43 +135,35 @@ impl Iterator for InstancePicker {
             return None;
         }
 
-        match self.last_pick {
-            None => {
-                // init states
-                self.replicas = min(self.replicas, self.shared_instances.real_nodes.len());
-                // find the first virtual node whose hash is greater than request_hash
-                let mut index = virtual_nodes.partition_point(|vn| vn.hash < self.request_hash.0);
-                if index == virtual_nodes.len() {
-                    index = 0;
+        let request_hash = self.request_hash.0;
+        let start = *self.start.get_or_insert_with(|| {
+            // find the first virtual node whose hash is greater than request_hash
+            let index = virtual_nodes.partition_point(|vn| vn.hash < request_hash);
+            if index == virtual_nodes.len() {
+                0
+            } else {
+                index
+            }
+        });
+        loop {
+            let from = self.last_pick.map_or(start, |last_pick| last_pick + 1);
+            // find the next virtual node which is not used
+            for offset in 0..virtual_nodes.len() {
+                let index = (from + offset) % virtual_nodes.len();
+                let addr = &virtual_nodes[index].real_node.0.address;
+                if self.used.contains(addr) || (!self.fallback && self.unhealthy.contains(addr)) {
+                    continue;
                 }
                 self.last_pick = Some(index);
-                let addr = virtual_nodes
-                    .get(index)
-                    .unwrap()
-                    .real_node
-                    .0
-                    .address
-                    .clone();
                 self.used.insert(addr.clone());
-                Some(addr)
+                return Some(addr.clone());
             }
-            Some(last_pick) => {
-                let mut index = last_pick;
-                // find the next virtual node which is not used
-                for _ in 0..virtual_nodes.len() {
-                    index += 1;
-                    if index == virtual_nodes.len() {
-                        index = 0;
-                    }
-                    let addr = &virtual_nodes.get(index).unwrap().real_node.0.address;
-                    if !self.used.contains(addr) {
-                        self.last_pick = Some(index);
-                        self.used.insert(addr.clone());
-                        return Some(addr.clone());
-                    }
-                }
-                None
+            if self.fallback || self.unhealthy.is_empty() {
+                return None;
             }
+            // search the ring again for the unhealthy ones
+            self.fallback = true;
+            self.last_pick = None;
         }
     }
 }
@@ -167,6 +175,7 @@ where
 {
     option: ConsistentHashOption,
     router: DashMap<K, Arc<WeightedInstances>>,
+    unhealthy: Arc<DashSet<Address>>,
 }
 
 impl<K> ConsistentHashBalance<K>
@@ -184,9 +193,21 @@ where
         Self {
             option,
             router: DashMap::new(),
+            unhealthy: Arc::new(DashSet::new()),
         }
     }
 
+    /// Marks the instance as unhealthy, e.g. by a health checker, so the requests hashed to it
+    /// will fall back to the next instance on the ring until it is marked as healthy again.
+    pub fn mark_unhealthy(&self, address: Address) {
+        self.unhealthy.insert(address);
+    }
+
+    /// Marks the instance as healthy.
+    pub fn mark_healthy(&self, address: &Address) {
+        self.unhealthy.remove(address);
+    }
+
     fn build_weighted_instances(&self, instances: Vec<Arc<Instance>>) -> WeightedInstances {
         let mut real_nodes = Vec::with_capacity(instances.len());
         // total number of virtual nodes
@@ -201,40 +222,8 @@ where
         let mut virtual_nodes = Vec::with_capacity(sum_of_nodes);
         for instance in instances {
             let real_node = Arc::new(RealNode::from((*instance).clone()));
-            real_nodes.push(real_node.clone());
-            let mut weight = 1;
-            if self.option.weighted {
-                weight = instance.weight;
-            }
-            let str = instance.address.to_string();
-            let vnode_lens = virtual_factor * weight;
-            // try to reuse the buffer
-            let mut buf = format!("{}#{}", str, vnode_lens).into_bytes();
-            let mut sharp_pos = 0;
-            for (i, bytei) in buf.iter().enumerate() {
-                if *bytei == b'#' {
-                    sharp_pos = i;
-                    break;
-                }
-            }
-            for i in 0..(virtual_factor * weight) {
-                let mut serial = i;
-                let mut pos = buf.len();
-                while serial > 0 {
-                    pos -= 1;
-                    buf[pos] = b'0' + (serial % 10) as u8;
-                    serial /= 10;
-                }
-                for bytej in buf.iter_mut().take(pos).skip(sharp_pos + 1) {
-                    *bytej = b'0';
-                }
-                // get address#i with leading zeros
-                let hash = mur3::murmurhash3_x64_128(&buf, 0).0;
-                virtual_nodes.push(VirtualNode {
-                    real_node: real_node.clone(),
-                    hash,
-                });
-            }
+            self.build_virtual_nodes(&real_node, &mut virtual_nodes);
+            real_nodes.push(real_node);
         }
         virtual_nodes.sort_unstable();
         WeightedInstances {
@@ -242,6 +231,94 @@ where
             virtual_nodes,
         }
     }
+
+    /// Updates the ring by the changes, which only rehashes the added and updated instances.
+    fn update_weighted_instances(
+        &self,
+        prev: &WeightedInstances,
+        changes: &Change<K>,
+    ) -> WeightedInstances {
+        let stale: HashSet<&Address> = changes
+            .removed
+            .iter()
+            .chain(changes.updated.iter())
+            .map(|instance| &instance.address)
+            .collect();
+        let mut real_nodes: Vec<_> = prev
+            .real_nodes
+            .iter()
+            .filter(|node| !stale.contains(&node.0.address))
+            .cloned()
+            .collect();
+        let mut fresh = Vec::new();
+        for instance in changes.added.iter().chain(changes.updated.iter()) {
+            let real_node = Arc::new(RealNode::from((**instance).clone()));
+            self.build_virtual_nodes(&real_node, &mut fresh);
+            real_nodes.push(real_node);
+        }
+        if real_nodes.len() != changes.all.len() {
+            // the changes are not consistent with the ring, e.g. an instance is added twice
+            return self.build_weighted_instances(changes.all.clone());
+        }
+        fresh.sort_unstable();
+
+        // merge the remaining virtual nodes and the fresh ones, both of them are sorted
+        let mut virtual_nodes = Vec::with_capacity(prev.virtual_nodes.len() + fresh.len());
+        let mut fresh = fresh.into_iter().peekable();
+        for node in prev
+            .virtual_nodes
+            .iter()
+            .filter(|node| !stale.contains(&node.real_node.0.address))
+        {
+            while let Some(f) = fresh.next_if(|f| f.hash < node.hash) {
+                virtual_nodes.push(f);
+            }
+            virtual_nodes.push(node.clone());
+        }
+        virtual_nodes.extend(fresh);
+        WeightedInstances {
+            real_nodes,
+            virtual_nodes,
+        }
+    }
+
+    fn build_virtual_nodes(&self, real_node: &Arc<RealNode>, virtual_nodes: &mut Vec<VirtualNode>) {
+        let instance = &real_node.0;
+        let virtual_factor = self.option.virtual_factor;
+        let mut weight = 1;
+        if self.option.weighted {
+            weight = instance.weight;
+        }
+        let str = instance.address.to_string();
+        let vnode_lens = virtual_factor * weight;
+        // try to reuse the buffer
+        let mut buf = format!("{}#{}", str, vnode_lens).into_bytes();
+        let mut sharp_pos = 0;
+        for (i, bytei) in buf.iter().enumerate() {
+            if *bytei == b'#' {
+                sharp_pos = i;
+                break;
+            }
+        }
+        for i in 0..(virtual_factor * weight) {
+            let mut serial = i;
+            let mut pos = buf.len();
+            while serial > 0 {
+                pos -= 1;
+                buf[pos] = b'0' + (serial % 10) as u8;
+                serial /= 10;
+            }
+            for bytej in buf.iter_mut().take(pos).skip(sharp_pos + 1) {
+                *bytej = b'0';
+            }
+            // get address#i with leading zeros
+            let hash = mur3::murmurhash3_x64_128(&buf, 0).0;
+            virtual_nodes.push(VirtualNode {
+                real_node: real_node.clone(),
+                hash,
+            });
+        }
+    }
 }
 
 impl<D> LoadBalance<D> for ConsistentHashBalance<D::Key>
@@ -276,18 +353,26 @@ where
                 e.insert(instances).value().clone()
             }
         };
+        let replicas = min(self.option.replicas, weighted_list.real_nodes.len());
         Ok(InstancePicker {
             shared_instances: weighted_list,
             request_hash,
+            start: None,
             last_pick: None,
             used: HashSet::new(),
-            replicas: self.option.replicas,
+            replicas,
+            unhealthy: self.unhealthy.clone(),
+            fallback: false,
         })
     }
 
     fn rebalance(&self, changes: Change<<D as Discover>::Key>) {
-        if let Entry::Occupied(entry) = self.router.entry(changes.key.clone()) {
-            entry.replace_entry(Arc::new(self.build_weighted_instances(changes.all)));
+        for instance in &changes.removed {
+            self.unhealthy.remove(&instance.address);
+        }
+        if let Entry::Occupied(mut entry) = self.router.entry(changes.key.clone()) {
+            let next = self.update_weighted_instances(entry.get(), &changes);
+            entry.insert(Arc::new(next));
         }
     }
 }
@@ -304,10 +389,10 @@ mod tests {
     use metainfo::{MetaInfo, METAINFO};
     use rand::Rng;
 
-    use super::{ConsistentHashBalance, ConsistentHashOption, LoadBalance};
+    use super::{ConsistentHashBalance, ConsistentHashOption, LoadBalance, WeightedInstances};
     use crate::{
         context::Endpoint,
-        discovery::{Instance, StaticDiscover},
+        discovery::{Change, Instance, StaticDiscover},
         loadbalance::RequestHash,
         net::Address,
     };
@@ -526,4 +611,83 @@ mod tests {
             assert!(virtual_nodes.contains(&node));
         }
     }
+
+    #[tokio::test]
+    async fn test_consistent_hash_unhealthy() {
+        test_with_meta_info(|| consistent_hash_unhealthy_tests()).await;
+    }
+
+    async fn consistent_hash_unhealthy_tests() {
+        let empty = empty_endpoint();
+        let instances: Vec<_> = (0..5)
+            .map(|i| new_instance(format!("127.0.0.1:{}", 8000 + i), 10))
+            .collect();
+        let discovery = StaticDiscover::new(instances);
+        let opt = ConsistentHashOption {
+            replicas: 5,
+            virtual_factor: 10,
+            weighted: true,
+        };
+        let lb = ConsistentHashBalance::new(opt);
+        set_request_hash(RequestHash::from_key("key").0);
+        let all = lb
+            .get_picker(&empty, &discovery)
+            .await
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert_eq!(all.len(), 5);
+
+        // the unhealthy instance is skipped, and returned after the healthy ones
+        lb.mark_unhealthy(all[0].clone());
+        let picked = lb
+            .get_picker(&empty, &discovery)
+            .await
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert_eq!(picked[..4], all[1..]);
+        assert_eq!(picked[4], all[0]);
+
+        lb.mark_healthy(&all[0]);
+        let picked = lb
+            .get_picker(&empty, &discovery)
+            .await
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert_eq!(picked, all);
+    }
+
+    #[test]
+    fn test_consistent_hash_incremental_update() {
+        let instances: Vec<_> = (0..10)
+            .map(|i| new_instance(format!("127.0.0.1:{}", 8000 + i), 10 + i))
+            .collect();
+        let lb = ConsistentHashBalance::<()>::new(ConsistentHashOption::default());
+        let prev = lb.build_weighted_instances(instances.clone());
+
+        let mut all = instances.clone();
+        let removed = all.remove(3);
+        let updated = new_instance("127.0.0.1:8005".to_owned(), 50);
+        all[4] = updated.clone();
+        let added = new_instance("127.0.0.1:9000".to_owned(), 20);
+        all.push(added.clone());
+        let changes = Change {
+            key: (),
+            all: all.clone(),
+            added: vec![added],
+            updated: vec![updated],
+            removed: vec![removed],
+        };
+
+        let next = lb.update_weighted_instances(&prev, &changes);
+        let expected = lb.build_weighted_instances(all);
+        assert_eq!(next.real_nodes.len(), expected.real_nodes.len());
+        let hashes = |instances: &WeightedInstances| {
+            instances
+                .virtual_nodes
+                .iter()
+                .map(|node| (node.hash, node.real_node.0.address.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(hashes(&next), hashes(&expected));
+    }
 }
@@ -1,6 +1,8 @@
 use motore::BoxError;
 use thiserror::Error;
 
+use crate::error::{code, CodedError, ErrorCode};
+
 #[derive(Error, Debug)]
 pub enum LoadBalanceError {
     #[error("load balance retry reaches end")]
@@ -11,6 +13,21 @@ pub enum LoadBalanceError {
     MissRequestHash,
 }
 
+impl CodedError for LoadBalanceError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::Retry => code::LOAD_BALANCE_RETRY,
+            Self::Discover(_) => code::LOAD_BALANCE_DISCOVER,
+            Self::MissRequestHash => code::LOAD_BALANCE_MISSING_REQUEST_HASH,
+        }
+    }
+
+    fn is_retryable(&self) -> bool {
+        // the discovery may recover, e.g. the registry is restarted
+        matches!(self, Self::Discover(_))
+    }
+}
+
 pub trait Retryable {
     fn retryable(&self) -> bool {
         false
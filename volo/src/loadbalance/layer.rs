@@ -1,9 +1,13 @@
 use std::{fmt::Debug, sync::Arc};
 
+use async_broadcast::RecvError;
 use motore::Service;
 use tracing::warn;
 
-use super::error::{LoadBalanceError, Retryable};
+use super::{
+    error::{LoadBalanceError, Retryable},
+    outlier::OutlierFailure,
+};
 use crate::{context::Context, discovery::Discover, loadbalance::LoadBalance, Layer};
 
 #[derive(Clone)]
@@ -34,6 +38,7 @@ where
                 loop {
                     match channel.recv().await {
                         Ok(recv) => lb.rebalance(recv),
+                        Err(RecvError::Closed) => break,
                         Err(err) => warn!("[VOLO] discovering subscription error: {:?}", err),
                     }
                 }
@@ -50,7 +55,7 @@ where
     LB: LoadBalance<D>,
     S: Service<Cx, Req> + 'static + Send + Sync,
     LoadBalanceError: Into<S::Error>,
-    S::Error: Debug + Retryable,
+    S::Error: Debug + Retryable + OutlierFailure,
     Req: Clone + Send + Sync + 'static,
 {
     type Response = S::Response;
@@ -81,10 +86,12 @@ where
 
             match self.service.call(cx, req.clone()).await {
                 Ok(resp) => {
+                    self.discover.report(&addr, true);
                     return Ok(resp);
                 }
                 Err(err) => {
                     warn!("[VOLO] call rpcinfo: {:?}, error: {:?}", cx.rpc_info(), err);
+                    self.discover.report(&addr, !err.is_outlier_failure());
                     if !err.retryable() {
                         return Err(err);
                     }
@@ -112,7 +119,7 @@ where
     }
 }
 
-#[derive(Clone, Default, Copy)]
+#[derive(Clone, Default)]
 pub struct LoadBalanceLayer<D, LB> {
     discover: D,
     load_balance: LB,
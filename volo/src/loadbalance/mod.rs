@@ -1,7 +1,12 @@
 pub mod consistent_hash;
 pub mod error;
 mod layer;
+pub mod outlier;
+pub mod policy;
 pub mod random;
+pub mod request_hash;
+pub mod round_robin;
+pub mod sticky;
 
 use std::future::Future;
 
@@ -12,9 +17,20 @@ use crate::{
     net::Address,
 };
 
+/// The hash of a request, which is used by [`consistent_hash::ConsistentHashBalance`] to pick the
+/// instance, and should be put into the `METAINFO` before the load balancing, e.g. by
+/// [`request_hash::RequestHashLayer`].
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Ord, Eq, Hash)]
 pub struct RequestHash(pub u64);
 
+impl RequestHash {
+    /// Hashes the key, e.g. the key of a cache, so the requests of the same key will be sent to
+    /// the same instance.
+    pub fn from_key(key: impl AsRef<[u8]>) -> Self {
+        Self(mur3::murmurhash3_x64_128(key.as_ref(), 0).0)
+    }
+}
+
 /// [`LoadBalance`] promise the feature of the load balance policy.
 pub trait LoadBalance<D>: Send + Sync + 'static
 where
@@ -71,6 +87,11 @@ impl<L, DISC> LbConfig<L, DISC> {
         }
     }
 
+    /// Returns the discover, e.g. to resolve the instances before the first requests.
+    pub fn get_discover(&self) -> &DISC {
+        &self.discover
+    }
+
     /// Sets the retry count of the client.
     pub fn retry_count(mut self, count: usize) -> Self {
         self.retry_count = count;
@@ -0,0 +1,372 @@
+//! Passive outlier detection, which ejects the instances failing consecutively from the load
+//! balancing for a while.
+//!
+//! An ejected instance is re-admitted after the ejection time to be probed. If the probe succeeds
+//! the instance is healthy again, otherwise it is ejected again with doubled ejection time, until
+//! the maximum.
+//!
+//! The detector is applied to the load balancing by the
+//! [`OutlierEjection`][super::policy::OutlierEjection] stage of the policy pipeline, which is told
+//! the results of the calls by [`Discover::report`][crate::discovery::Discover::report].
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use volo::loadbalance::{
+//!     outlier::{OutlierDetection, OutlierDetector},
+//!     policy::{OutlierEjection, PolicyChain, PolicyDiscover},
+//! };
+//!
+//! let detector = Arc::new(OutlierDetector::new(
+//!     OutlierDetection::default().consecutive_failures(3),
+//! ));
+//!
+//! let client = volo_gen::volo::example::ItemServiceClientBuilder::new("item")
+//!     .discover(PolicyDiscover::new(
+//!         discover,
+//!         PolicyChain::new().stage(OutlierEjection::new(detector)),
+//!     ))
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+
+use crate::{
+    event::{CircuitState, Event, EventBus},
+    net::Address,
+};
+
+const DEFAULT_CONSECUTIVE_FAILURES: u32 = 5;
+const DEFAULT_EJECTION_TIME: Duration = Duration::from_secs(30);
+const DEFAULT_MAX_EJECTION_TIME: Duration = Duration::from_secs(300);
+
+/// Whether an error means the instance is unhealthy, e.g. the connection is refused, which is
+/// counted by the [`OutlierDetector`].
+///
+/// The errors returned by a healthy instance, e.g. the business errors, should not be counted.
+pub trait OutlierFailure {
+    fn is_outlier_failure(&self) -> bool {
+        false
+    }
+}
+
+/// The config of the [`OutlierDetector`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutlierDetection {
+    consecutive_failures: u32,
+    ejection_time: Duration,
+    max_ejection_time: Duration,
+}
+
+impl Default for OutlierDetection {
+    fn default() -> Self {
+        Self {
+            consecutive_failures: DEFAULT_CONSECUTIVE_FAILURES,
+            ejection_time: DEFAULT_EJECTION_TIME,
+            max_ejection_time: DEFAULT_MAX_EJECTION_TIME,
+        }
+    }
+}
+
+impl OutlierDetection {
+    /// Sets the number of consecutive failures to eject an instance.
+    ///
+    /// Default is `5`.
+    pub fn consecutive_failures(mut self, failures: u32) -> Self {
+        self.consecutive_failures = failures.max(1);
+        self
+    }
+
+    /// Sets the ejection time, which starts from `ejection_time` and doubles each time the probe
+    /// fails until `max_ejection_time`.
+    ///
+    /// Default is from `30s` to `300s`.
+    pub fn ejection_time(mut self, ejection_time: Duration, max_ejection_time: Duration) -> Self {
+        self.ejection_time = ejection_time;
+        self.max_ejection_time = max_ejection_time;
+        self
+    }
+
+    fn ejection_time_of(&self, ejections: u32) -> Duration {
+        self.ejection_time
+            .checked_mul(1u32 << ejections.saturating_sub(1).min(31))
+            .unwrap_or(self.max_ejection_time)
+            .min(self.max_ejection_time)
+    }
+}
+
+#[derive(Debug, Default)]
+struct InstanceState {
+    consecutive_failures: u32,
+    /// The number of ejections since the instance was healthy.
+    ejections: u32,
+    ejected_until: Option<Instant>,
+    probing_since: Option<Instant>,
+}
+
+impl InstanceState {
+    fn circuit(&self) -> CircuitState {
+        match (self.ejected_until, self.probing_since) {
+            (None, _) => CircuitState::Closed,
+            (Some(_), None) => CircuitState::Open,
+            (Some(_), Some(_)) => CircuitState::HalfOpen,
+        }
+    }
+}
+
+/// Tracks the consecutive failures of the instances and decides which are ejected.
+///
+/// It is shared by all the clones of a client, and can also be shared by multiple clients of the
+/// same instances.
+#[derive(Debug, Default)]
+pub struct OutlierDetector {
+    config: OutlierDetection,
+    states: DashMap<Address, InstanceState>,
+    events: Option<EventBus>,
+    /// Bumped each time an instance is ejected or healthy again.
+    generation: AtomicU64,
+}
+
+impl OutlierDetector {
+    pub fn new(config: OutlierDetection) -> Self {
+        Self {
+            config,
+            states: DashMap::new(),
+            events: None,
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /// Publishes the ejections and the probes of the instances as
+    /// [`Event::CircuitStateChanged`] to the bus, where an ejected instance is open and an
+    /// instance being probed is half-open.
+    pub fn event_bus(mut self, bus: EventBus) -> Self {
+        self.events = Some(bus);
+        self
+    }
+
+    pub fn config(&self) -> &OutlierDetection {
+        &self.config
+    }
+
+    /// Returns whether the instance is ejected now, including the ones waiting for the probe.
+    pub fn is_ejected(&self, addr: &Address) -> bool {
+        self.states
+            .get(addr)
+            .is_some_and(|state| state.ejected_until.is_some())
+    }
+
+    /// Returns when the ejection of the instance is over, after which it admits the probes.
+    pub fn ejected_until(&self, addr: &Address) -> Option<Instant> {
+        self.states.get(addr).and_then(|state| state.ejected_until)
+    }
+
+    /// Returns the generation of the ejections, which changes each time an instance is ejected or
+    /// healthy again, e.g. for the [`OutlierEjection`](super::policy::OutlierEjection) stage to
+    /// re-evaluate the instances.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// Returns whether a request can be sent to the instance.
+    ///
+    /// When the ejection time of the instance is over, it admits one probe request at a time.
+    pub fn admit(&self, addr: &Address) -> bool {
+        self.admit_at(addr, Instant::now())
+    }
+
+    /// Reports the result of a request sent to the instance.
+    pub fn report(&self, addr: &Address, success: bool) {
+        self.report_at(addr, success, Instant::now())
+    }
+
+    /// Ejects the instance now as if it failed consecutively, e.g. when the circuit breaker of the
+    /// instance opens.
+    ///
+    /// The instance is re-admitted with the probes in the same way.
+    pub fn eject(&self, addr: &Address) {
+        self.eject_at(addr, Instant::now())
+    }
+
+    /// Forgets the state of the instance, e.g. when it is removed by the discovery.
+    pub fn forget(&self, addr: &Address) {
+        self.states.remove(addr);
+    }
+
+    fn admit_at(&self, addr: &Address, now: Instant) -> bool {
+        let admitted = {
+            let Some(mut state) = self.states.get_mut(addr) else {
+                return true;
+            };
+            self.admit_state(&mut state, now)
+        };
+        if admitted == Some(CircuitState::Open) {
+            self.publish(addr, CircuitState::Open, CircuitState::HalfOpen);
+        }
+        admitted.is_some()
+    }
+
+    /// Returns the circuit before the admission if the request is admitted.
+    fn admit_state(&self, state: &mut InstanceState, now: Instant) -> Option<CircuitState> {
+        let from = state.circuit();
+        let Some(until) = state.ejected_until else {
+            return Some(from);
+        };
+        if now < until {
+            return None;
+        }
+        // the result of the probe may never be reported, e.g. the request is cancelled, so another
+        // probe is allowed after a while
+        if state
+            .probing_since
+            .is_some_and(|since| now.saturating_duration_since(since) < self.config.ejection_time)
+        {
+            return None;
+        }
+        state.probing_since = Some(now);
+        Some(from)
+    }
+
+    fn report_at(&self, addr: &Address, success: bool, now: Instant) {
+        if success {
+            // avoid the write lock for the healthy instances
+            if self.states.contains_key(addr) {
+                if let Some((_, state)) = self.states.remove(addr) {
+                    let from = state.circuit();
+                    if from != CircuitState::Closed {
+                        self.generation.fetch_add(1, Ordering::AcqRel);
+                        self.publish(addr, from, CircuitState::Closed);
+                    }
+                }
+            }
+            return;
+        }
+
+        let ejected_from = {
+            let mut state = self.states.entry(addr.clone()).or_default();
+            let from = state.circuit();
+            match state.ejected_until {
+                // the failures of the requests sent before the ejection
+                Some(until) if now < until => None,
+                // the probe failed
+                Some(_) => {
+                    self.eject_state(&mut state, now);
+                    Some(from)
+                }
+                None => {
+                    state.consecutive_failures += 1;
+                    if state.consecutive_failures >= self.config.consecutive_failures {
+                        self.eject_state(&mut state, now);
+                        Some(from)
+                    } else {
+                        None
+                    }
+                }
+            }
+        };
+        if let Some(from) = ejected_from.filter(|from| *from != CircuitState::Open) {
+            self.publish(addr, from, CircuitState::Open);
+        }
+    }
+
+    fn eject_at(&self, addr: &Address, now: Instant) {
+        let from = {
+            let mut state = self.states.entry(addr.clone()).or_default();
+            if state.ejected_until.is_some_and(|until| now < until) {
+                return;
+            }
+            let from = state.circuit();
+            self.eject_state(&mut state, now);
+            from
+        };
+        if from != CircuitState::Open {
+            self.publish(addr, from, CircuitState::Open);
+        }
+    }
+
+    fn publish(&self, addr: &Address, from: CircuitState, to: CircuitState) {
+        if let Some(events) = &self.events {
+            events.publish(Event::CircuitStateChanged {
+                key: addr.to_string().into(),
+                from,
+                to,
+            });
+        }
+    }
+
+    fn eject_state(&self, state: &mut InstanceState, now: Instant) {
+        state.consecutive_failures = 0;
+        state.ejections += 1;
+        state.ejected_until = Some(now + self.config.ejection_time_of(state.ejections));
+        state.probing_since = None;
+        self.generation.fetch_add(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(port: u16) -> Address {
+        Address::from(std::net::SocketAddr::from(([127, 0, 0, 1], port)))
+    }
+
+    fn detector() -> OutlierDetector {
+        OutlierDetector::new(
+            OutlierDetection::default()
+                .consecutive_failures(2)
+                .ejection_time(Duration::from_secs(1), Duration::from_secs(3)),
+        )
+    }
+
+    #[test]
+    fn eject_and_probe() {
+        let detector = detector();
+        let a = addr(8000);
+        let now = Instant::now();
+
+        detector.report_at(&a, false, now);
+        // a success resets the consecutive failures
+        detector.report_at(&a, true, now);
+        detector.report_at(&a, false, now);
+        assert!(detector.admit_at(&a, now));
+        detector.report_at(&a, false, now);
+        assert!(detector.is_ejected(&a));
+        assert!(!detector.admit_at(&a, now));
+
+        // only one probe at a time after the ejection time
+        let now = now + Duration::from_secs(1);
+        assert!(detector.admit_at(&a, now));
+        assert!(!detector.admit_at(&a, now));
+
+        // the probe failed, the ejection time is doubled
+        detector.report_at(&a, false, now);
+        assert!(!detector.admit_at(&a, now + Duration::from_secs(1)));
+        let now = now + Duration::from_secs(2);
+        assert!(detector.admit_at(&a, now));
+
+        // the probe succeeded
+        detector.report_at(&a, true, now);
+        assert!(!detector.is_ejected(&a));
+        assert!(detector.admit_at(&a, now));
+    }
+
+    #[test]
+    fn max_ejection_time() {
+        let config = OutlierDetection::default()
+            .ejection_time(Duration::from_secs(1), Duration::from_secs(3));
+        assert_eq!(config.ejection_time_of(1), Duration::from_secs(1));
+        assert_eq!(config.ejection_time_of(2), Duration::from_secs(2));
+        assert_eq!(config.ejection_time_of(3), Duration::from_secs(3));
+        assert_eq!(config.ejection_time_of(100), Duration::from_secs(3));
+    }
+}
@@ -0,0 +1,1332 @@
+//! The pipeline of the policies adjusting the instances for the load balancing, e.g. ejecting the
+//! outliers, splitting the canary traffic, preferring the local instances and ramping up the new
+//! ones.
+//!
+//! A [`PolicyChain`] is an ordered chain of [`InstancePolicy`] stages, each of which transforms
+//! the instances and the weights left by the previous one, so how they interact is defined by
+//! the order. It's applied by the [`PolicyDiscover`] wrapping the discover of a client, so any
+//! load balance picks from the instances adjusted:
+//!
+//! ```rust,ignore
+//! use volo::loadbalance::{
+//!     outlier::{OutlierDetection, OutlierDetector},
+//!     policy::{Canary, Locality, OutlierEjection, PolicyChain, PolicyDiscover, SlowStart},
+//! };
+//!
+//! let detector = Arc::new(OutlierDetector::new(OutlierDetection::default()));
+//! let chain = PolicyChain::new()
+//!     .stage(OutlierEjection::new(detector))
+//!     .stage(Canary::new("lane", "canary", 5.0))
+//!     .stage(Locality::new("zone", "us-east-1a"))
+//!     .stage(SlowStart::new(Duration::from_secs(60)));
+//! let discover = PolicyDiscover::new(discover, chain);
+//! let client = volo_gen::volo::example::ItemServiceClientBuilder::new("item")
+//!     .discover(discover.clone())
+//!     .build()?;
+//!
+//! // e.g. served on the admin endpoint
+//! let json = discover.snapshot().to_json();
+//! ```
+//!
+//! The unhealthy instances should be removed first, then the subsets selected, and the weights
+//! adjusted last, so the weights are ramped among the instances actually picked from.
+//!
+//! The results of the calls are told to the stages by [`Discover::report`], which is called by
+//! the load balancing of the clients, so the [`OutlierEjection`] needs no other wiring.
+//!
+//! # Invariants
+//!
+//! - A stage leaving no instance of a positive weight is skipped, so the chain never empties the
+//!   instances unless the discovery does.
+//! - The instances of weight `0` are dropped from the result, unless all of them are.
+//! - The result only depends on the instances discovered, the routing keys of the request, the
+//!   state of the instances and the time, so it's the same for the same inputs.
+//!
+//! # Caching
+//!
+//! The result is cached for each key of the discover and the
+//! [routing keys](InstancePolicy::routing_key) of the request, and only re-evaluated when the
+//! instances discovered change, the [version](InstancePolicy::version) of a stage changes, e.g.
+//! an instance is ejected, or the time a stage [expires](PolicyContext::expire_at) its result
+//! comes. The load balancers are told the changes by [`Discover::watch`], which checks the
+//! versions and the expiries every [refresh interval](PolicyDiscover::refresh_interval).
+
+use std::{
+    borrow::Cow,
+    collections::{hash_map::DefaultHasher, HashMap},
+    fmt::{self, Write},
+    hash::{Hash, Hasher},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, Weak,
+    },
+    time::{Duration, Instant},
+};
+
+use async_broadcast::{InactiveReceiver, Receiver, Sender};
+use faststr::FastStr;
+
+use super::{error::LoadBalanceError, outlier::OutlierDetector};
+use crate::{
+    context::Endpoint,
+    discovery::{diff, Change, Discover, Instance},
+    event::push_str,
+    net::Address,
+    route::Percentage,
+};
+
+const CHANNEL_CAPACITY: usize = 16;
+
+/// The default interval of checking the versions and the expiries of the results cached.
+pub const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// An instance and its weight adjusted by the stages so far.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub instance: Arc<Instance>,
+    pub weight: u32,
+}
+
+/// The context of applying a stage.
+#[derive(Debug)]
+pub struct PolicyContext<'a> {
+    service: &'a FastStr,
+    routing_keys: &'a [Option<FastStr>],
+    stage: usize,
+    first_seen: &'a HashMap<Address, Option<Instant>>,
+    now: Instant,
+    expires: Option<Instant>,
+}
+
+impl PolicyContext<'_> {
+    /// Returns the service name of the callee.
+    pub fn service(&self) -> &FastStr {
+        self.service
+    }
+
+    /// Returns the routing key of the request for the stage, see
+    /// [`InstancePolicy::routing_key`].
+    pub fn routing_key(&self) -> Option<&FastStr> {
+        self.routing_keys.get(self.stage)?.as_ref()
+    }
+
+    /// Returns when the instance was discovered, or `None` if it's in the first instances
+    /// discovered, which are not new.
+    pub fn first_seen(&self, addr: &Address) -> Option<Instant> {
+        self.first_seen.get(addr).copied().flatten()
+    }
+
+    /// Returns the time of the evaluation, which should be used instead of [`Instant::now`].
+    pub fn now(&self) -> Instant {
+        self.now
+    }
+
+    /// Tells that the result of the stage changes at the time, so it's re-evaluated after then.
+    pub fn expire_at(&mut self, at: Instant) {
+        self.expires = Some(self.expires.map_or(at, |expires| expires.min(at)));
+    }
+}
+
+/// A stage of the [`PolicyChain`], see the [module level documentation](self).
+pub trait InstancePolicy: Send + Sync + 'static {
+    /// The name of the stage in the [`PolicySnapshot`].
+    fn name(&self) -> &'static str;
+
+    /// Transforms the instances and their weights, e.g. removes some of them or adjusts the
+    /// weights.
+    ///
+    /// It should only depend on the context and the instances, so the result can be cached.
+    fn apply(&self, cx: &mut PolicyContext<'_>, instances: &mut Vec<Candidate>);
+
+    /// Returns the key of the request the stage depends on, e.g. the subset asked by the request,
+    /// whose result is cached for each key, so the keys should be few.
+    ///
+    /// Default is `None`.
+    fn routing_key(&self, _endpoint: &Endpoint) -> Option<FastStr> {
+        None
+    }
+
+    /// Returns the version of the config and the state of the stage, whose change re-evaluates
+    /// the results cached.
+    ///
+    /// Default is `0`.
+    fn version(&self) -> u64 {
+        0
+    }
+
+    /// Observes the result of a call sent to the instance, see [`Discover::report`].
+    ///
+    /// Default does nothing.
+    fn report(&self, _address: &Address, _healthy: bool) {}
+
+    /// Forgets the state of the instance, which is removed by the discovery.
+    ///
+    /// Default does nothing.
+    fn forget(&self, _address: &Address) {}
+}
+
+/// What a stage did in an evaluation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StageStats {
+    pub name: &'static str,
+    /// The number of the instances removed by the stage.
+    pub removed: usize,
+    /// The number of the instances whose weights are changed by the stage.
+    pub reweighted: usize,
+    /// Whether the stage is skipped as it left no instance of a positive weight.
+    pub skipped: bool,
+}
+
+impl StageStats {
+    fn new(name: &'static str, before: &[Candidate], after: &[Candidate], skipped: bool) -> Self {
+        let weights = after
+            .iter()
+            .map(|c| (&c.instance.address, c.weight))
+            .collect::<HashMap<_, _>>();
+        let removed = before
+            .iter()
+            .filter(|c| !weights.contains_key(&c.instance.address))
+            .count();
+        let reweighted = before
+            .iter()
+            .filter(|c| {
+                weights
+                    .get(&c.instance.address)
+                    .is_some_and(|weight| *weight != c.weight)
+            })
+            .count();
+        Self {
+            name,
+            removed,
+            reweighted,
+            skipped,
+        }
+    }
+}
+
+/// An ordered chain of [`InstancePolicy`] stages, see the [module level documentation](self).
+#[derive(Default)]
+pub struct PolicyChain {
+    stages: Vec<Box<dyn InstancePolicy>>,
+    generation: AtomicU64,
+}
+
+impl fmt::Debug for PolicyChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PolicyChain")
+            .field(
+                "stages",
+                &self.stages.iter().map(|s| s.name()).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl PolicyChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a stage, which is applied after the ones appended before.
+    pub fn stage(mut self, stage: impl InstancePolicy) -> Self {
+        self.stages.push(Box::new(stage));
+        self
+    }
+
+    /// Re-evaluates all the results cached, e.g. after the config of a stage is updated without
+    /// changing its [version](InstancePolicy::version).
+    pub fn invalidate(&self) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+    }
+
+    fn version(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.generation.load(Ordering::Acquire).hash(&mut hasher);
+        for stage in &self.stages {
+            stage.version().hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    /// Returns the routing keys of the stages, which is empty if none of them has one.
+    fn routing_keys(&self, endpoint: &Endpoint) -> Vec<Option<FastStr>> {
+        let keys = self
+            .stages
+            .iter()
+            .map(|s| s.routing_key(endpoint))
+            .collect::<Vec<_>>();
+        if keys.iter().all(Option::is_none) {
+            return Vec::new();
+        }
+        keys
+    }
+
+    /// Applies the stages to the instances in order.
+    fn evaluate(
+        &self,
+        cx: &mut PolicyContext<'_>,
+        instances: &[Arc<Instance>],
+    ) -> (Vec<Arc<Instance>>, Vec<StageStats>) {
+        fn has_weight(candidates: &[Candidate]) -> bool {
+            candidates.iter().any(|c| c.weight > 0)
+        }
+
+        let mut current = instances
+            .iter()
+            .map(|i| Candidate {
+                instance: i.clone(),
+                weight: i.weight,
+            })
+            .collect::<Vec<_>>();
+        let mut stats = Vec::with_capacity(self.stages.len());
+        for (i, stage) in self.stages.iter().enumerate() {
+            cx.stage = i;
+            let mut next = current.clone();
+            stage.apply(cx, &mut next);
+
+            let skipped = has_weight(&current) && !has_weight(&next);
+            stats.push(StageStats::new(stage.name(), &current, &next, skipped));
+            if !skipped {
+                current = next;
+            }
+        }
+
+        if has_weight(&current) {
+            current.retain(|c| c.weight > 0);
+        }
+        let instances = current
+            .into_iter()
+            .map(|c| {
+                if c.weight == c.instance.weight {
+                    c.instance
+                } else {
+                    Arc::new(Instance {
+                        weight: c.weight,
+                        ..(*c.instance).clone()
+                    })
+                }
+            })
+            .collect();
+        (instances, stats)
+    }
+}
+
+/// The key of the [`PolicyDiscover`], which is the key of the inner discover and the routing keys
+/// of the request.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PolicyKey<K> {
+    pub key: K,
+    /// The routing keys of the stages, which is empty if none of them has one.
+    pub routing_keys: Vec<Option<FastStr>>,
+}
+
+/// The result cached for a [`PolicyKey`].
+struct Entry {
+    service: FastStr,
+    discovered: Vec<Arc<Instance>>,
+    /// When the instances were discovered, where the first ones are `None`.
+    first_seen: HashMap<Address, Option<Instant>>,
+    /// The instances last served to the load balancer.
+    served: Vec<Arc<Instance>>,
+    stages: Vec<StageStats>,
+    version: u64,
+    expires: Option<Instant>,
+}
+
+impl Entry {
+    fn new(service: FastStr, discovered: Vec<Arc<Instance>>) -> Self {
+        let mut entry = Self {
+            service,
+            discovered: Vec::new(),
+            first_seen: HashMap::new(),
+            served: Vec::new(),
+            stages: Vec::new(),
+            version: 0,
+            expires: None,
+        };
+        entry.set_discovered(discovered, None);
+        entry
+    }
+
+    fn set_discovered(&mut self, instances: Vec<Arc<Instance>>, now: Option<Instant>) {
+        let mut first_seen = HashMap::with_capacity(instances.len());
+        for i in &instances {
+            let seen = self.first_seen.get(&i.address).copied().unwrap_or(now);
+            first_seen.insert(i.address.clone(), seen);
+        }
+        self.first_seen = first_seen;
+        self.discovered = instances;
+    }
+
+    fn is_stale(&self, version: u64, now: Instant) -> bool {
+        self.version != version || self.expires.is_some_and(|expires| now >= expires)
+    }
+
+    /// Evaluates the chain, and returns the instances served before.
+    fn evaluate(
+        &mut self,
+        chain: &PolicyChain,
+        routing_keys: &[Option<FastStr>],
+        now: Instant,
+    ) -> Vec<Arc<Instance>> {
+        // read before the evaluation, so a change during it is evaluated again
+        let version = chain.version();
+        let mut cx = PolicyContext {
+            service: &self.service,
+            routing_keys,
+            stage: 0,
+            first_seen: &self.first_seen,
+            now,
+            expires: None,
+        };
+        let (served, stages) = chain.evaluate(&mut cx, &self.discovered);
+        self.expires = cx.expires;
+        self.version = version;
+        self.stages = stages;
+        std::mem::replace(&mut self.served, served)
+    }
+}
+
+struct Shared<K> {
+    chain: PolicyChain,
+    entries: Mutex<HashMap<PolicyKey<K>, Entry>>,
+    watching: AtomicBool,
+    sender: Sender<Change<PolicyKey<K>>>,
+    // keeps the channel open when there is no receiver
+    _receiver: InactiveReceiver<Change<PolicyKey<K>>>,
+}
+
+impl<K> Shared<K>
+where
+    K: Hash + Eq + Clone + Send + Sync + 'static,
+{
+    /// Evaluates the entry, and tells the load balancers if the instances served change.
+    fn evaluate(&self, key: &PolicyKey<K>, entry: &mut Entry, now: Instant) {
+        let prev = entry.evaluate(&self.chain, &key.routing_keys, now);
+        if let Some(change) = diff(key.clone(), prev, entry.served.clone()) {
+            let _ = self.sender.try_broadcast(change);
+        }
+    }
+
+    /// Updates the entries of the key by the change of the inner discover.
+    fn update(&self, change: Change<K>, now: Instant) {
+        for instance in &change.removed {
+            for stage in &self.chain.stages {
+                stage.forget(&instance.address);
+            }
+        }
+        let mut entries = self.entries.lock().unwrap();
+        for (key, entry) in entries.iter_mut().filter(|(key, _)| key.key == change.key) {
+            entry.set_discovered(change.all.clone(), Some(now));
+            self.evaluate(key, entry, now);
+        }
+    }
+
+    /// Re-evaluates the entries whose versions or expiries are stale.
+    fn refresh(&self, now: Instant) {
+        let version = self.chain.version();
+        let mut entries = self.entries.lock().unwrap();
+        for (key, entry) in entries.iter_mut() {
+            if entry.is_stale(version, now) {
+                self.evaluate(key, entry, now);
+            }
+        }
+    }
+}
+
+/// Receives the changes of the inner discover, and refreshes the entries every interval, until the
+/// discover is dropped.
+async fn forward<K>(
+    shared: Weak<Shared<K>>,
+    mut inner: Option<Receiver<Change<K>>>,
+    interval: Duration,
+) where
+    K: Hash + Eq + Clone + Send + Sync + 'static,
+{
+    async fn recv<K: Clone>(receiver: &mut Option<Receiver<Change<K>>>) -> Option<Change<K>> {
+        loop {
+            match receiver.as_mut()?.recv().await {
+                Ok(change) => return Some(change),
+                Err(async_broadcast::RecvError::Overflowed(_)) => {}
+                Err(async_broadcast::RecvError::Closed) => {
+                    *receiver = None;
+                    return None;
+                }
+            }
+        }
+    }
+
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        // a closed inner discover only refreshes by the ticker
+        let change = tokio::select! {
+            change = recv(&mut inner), if inner.is_some() => change,
+            _ = ticker.tick() => None,
+        };
+        let Some(shared) = shared.upgrade() else {
+            break;
+        };
+        match change {
+            Some(change) => shared.update(change, Instant::now()),
+            None => shared.refresh(Instant::now()),
+        }
+    }
+}
+
+/// A discover applying the [`PolicyChain`] to the instances of the inner discover, see the
+/// [module level documentation](self).
+///
+/// The clones share the chain and the results cached.
+pub struct PolicyDiscover<D: Discover> {
+    inner: D,
+    shared: Arc<Shared<D::Key>>,
+    refresh_interval: Duration,
+}
+
+impl<D: Discover> PolicyDiscover<D> {
+    pub fn new(inner: D, chain: PolicyChain) -> Self {
+        let (mut sender, receiver) = async_broadcast::broadcast(CHANNEL_CAPACITY);
+        sender.set_overflow(true);
+        sender.set_await_active(false);
+        Self {
+            inner,
+            shared: Arc::new(Shared {
+                chain,
+                entries: Mutex::new(HashMap::new()),
+                watching: AtomicBool::new(false),
+                sender,
+                _receiver: receiver.deactivate(),
+            }),
+            refresh_interval: DEFAULT_REFRESH_INTERVAL,
+        }
+    }
+
+    /// Sets the interval of checking the versions and the expiries of the results cached, which
+    /// bounds how late the load balancers see the changes not from the discovery, e.g. the
+    /// ejections and the steps of the slow start.
+    ///
+    /// Default is [`DEFAULT_REFRESH_INTERVAL`].
+    pub fn refresh_interval(mut self, interval: Duration) -> Self {
+        self.refresh_interval = interval;
+        self
+    }
+
+    pub fn chain(&self) -> &PolicyChain {
+        &self.shared.chain
+    }
+
+    /// Returns the instances served and what each stage did for each key.
+    pub fn snapshot(&self) -> PolicySnapshot {
+        let entries = self.shared.entries.lock().unwrap();
+        let mut snapshots = entries
+            .iter()
+            .map(|(key, entry)| EntrySnapshot {
+                service: entry.service.clone(),
+                routing_keys: key.routing_keys.iter().flatten().cloned().collect(),
+                instances: entry
+                    .served
+                    .iter()
+                    .map(|i| (i.address.clone(), i.weight))
+                    .collect(),
+                stages: entry.stages.clone(),
+            })
+            .collect::<Vec<_>>();
+        snapshots.sort_by(|a, b| {
+            let key = |e: &EntrySnapshot| {
+                (
+                    e.service.to_string(),
+                    e.routing_keys
+                        .iter()
+                        .map(|k| k.to_string())
+                        .collect::<Vec<_>>(),
+                )
+            };
+            key(a).cmp(&key(b))
+        });
+        PolicySnapshot { entries: snapshots }
+    }
+}
+
+impl<D: Discover + Clone> Clone for PolicyDiscover<D> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            shared: self.shared.clone(),
+            refresh_interval: self.refresh_interval,
+        }
+    }
+}
+
+impl<D: Discover> Discover for PolicyDiscover<D> {
+    type Key = PolicyKey<D::Key>;
+    type Error = LoadBalanceError;
+
+    async fn discover<'s>(
+        &'s self,
+        endpoint: &'s Endpoint,
+    ) -> Result<Vec<Arc<Instance>>, Self::Error> {
+        let key = self.key(endpoint);
+        let now = Instant::now();
+        {
+            let mut entries = self.shared.entries.lock().unwrap();
+            if let Some(entry) = entries.get_mut(&key) {
+                if entry.is_stale(self.shared.chain.version(), now) {
+                    self.shared.evaluate(&key, entry, now);
+                }
+                return Ok(entry.served.clone());
+            }
+        }
+
+        let discovered = self.inner.discover(endpoint).await.map_err(Into::into)?;
+        let mut entries = self.shared.entries.lock().unwrap();
+        let entry = entries
+            .entry(key.clone())
+            .or_insert_with(|| Entry::new(endpoint.service_name(), discovered));
+        entry.evaluate(&self.shared.chain, &key.routing_keys, now);
+        Ok(entry.served.clone())
+    }
+
+    fn key(&self, endpoint: &Endpoint) -> Self::Key {
+        PolicyKey {
+            key: self.inner.key(endpoint),
+            routing_keys: self.shared.chain.routing_keys(endpoint),
+        }
+    }
+
+    fn report(&self, address: &Address, healthy: bool) {
+        for stage in &self.shared.chain.stages {
+            stage.report(address, healthy);
+        }
+        self.inner.report(address, healthy);
+    }
+
+    fn watch(&self, _keys: Option<&[Self::Key]>) -> Option<Receiver<Change<Self::Key>>> {
+        if !self.shared.watching.swap(true, Ordering::AcqRel) {
+            tokio::spawn(forward(
+                Arc::downgrade(&self.shared),
+                self.inner.watch(None),
+                self.refresh_interval,
+            ));
+        }
+        Some(self.shared.sender.new_receiver())
+    }
+}
+
+/// The instances served and what each stage did, of all the keys of a [`PolicyDiscover`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicySnapshot {
+    pub entries: Vec<EntrySnapshot>,
+}
+
+/// The instances served and what each stage did in the last evaluation of a key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntrySnapshot {
+    pub service: FastStr,
+    pub routing_keys: Vec<FastStr>,
+    /// The addresses and the weights of the instances served.
+    pub instances: Vec<(Address, u32)>,
+    pub stages: Vec<StageStats>,
+}
+
+impl PolicySnapshot {
+    /// Returns the snapshot as JSON, e.g. `{"entries":[{"service":"item","routing_keys":[],
+    /// "instances":[{"address":"10.0.0.1:8888","weight":10}],"stages":[{"name":"slow_start",
+    /// "removed":0,"reweighted":1,"skipped":false}]}]}`.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from(r#"{"entries":["#);
+        for (i, entry) in self.entries.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            json.push_str(r#"{"service":"#);
+            push_str(&mut json, &entry.service);
+            json.push_str(r#","routing_keys":["#);
+            for (i, key) in entry.routing_keys.iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+                push_str(&mut json, key);
+            }
+            json.push_str(r#"],"instances":["#);
+            for (i, (address, weight)) in entry.instances.iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+                json.push_str(r#"{"address":"#);
+                push_str(&mut json, &address.to_string());
+                let _ = write!(json, r#","weight":{weight}}}"#);
+            }
+            json.push_str(r#"],"stages":["#);
+            for (i, stage) in entry.stages.iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+                let _ = write!(
+                    json,
+                    r#"{{"name":"{}","removed":{},"reweighted":{},"skipped":{}}}"#,
+                    stage.name, stage.removed, stage.reweighted, stage.skipped
+                );
+            }
+            json.push_str("]}");
+        }
+        json.push_str("]}");
+        json
+    }
+}
+
+/// The weight of an instance being probed after its ejection.
+const PROBE_WEIGHT: u32 = 1;
+
+/// Ejects the instances failing consecutively by the [`OutlierDetector`], which is told the
+/// results of the calls by [`Discover::report`].
+///
+/// An ejected instance is removed until its ejection time is over, after which it's probed with
+/// the minimum weight, until a success makes it healthy again or a failure ejects it again.
+#[derive(Debug, Clone)]
+pub struct OutlierEjection {
+    detector: Arc<OutlierDetector>,
+}
+
+impl OutlierEjection {
+    /// Creates the stage of the detector, which can be shared by multiple clients of the same
+    /// instances.
+    pub fn new(detector: Arc<OutlierDetector>) -> Self {
+        Self { detector }
+    }
+}
+
+impl InstancePolicy for OutlierEjection {
+    fn name(&self) -> &'static str {
+        "outlier_ejection"
+    }
+
+    fn apply(&self, cx: &mut PolicyContext<'_>, instances: &mut Vec<Candidate>) {
+        let now = cx.now();
+        instances.retain_mut(|c| match self.detector.ejected_until(&c.instance.address) {
+            Some(until) if until > now => {
+                cx.expire_at(until);
+                false
+            }
+            Some(_) => {
+                c.weight = c.weight.min(PROBE_WEIGHT);
+                true
+            }
+            None => true,
+        });
+    }
+
+    fn version(&self) -> u64 {
+        self.detector.generation()
+    }
+
+    fn report(&self, address: &Address, healthy: bool) {
+        self.detector.report(address, healthy);
+    }
+
+    fn forget(&self, address: &Address) {
+        self.detector.forget(address);
+    }
+}
+
+/// The routing key of the requests split to the canary instances by the [`Canary`].
+const CANARY_KEY: &str = "canary";
+
+/// Splits a percentage of the requests to the canary instances told by a tag of the instances,
+/// and the other requests to the other instances.
+///
+/// The split is decided for each request by its [routing key](InstancePolicy::routing_key), so
+/// the results are cached for two keys only. The canary requests are sent to all the instances if
+/// there's no canary instance.
+#[derive(Debug, Clone)]
+pub struct Canary {
+    tag: Cow<'static, str>,
+    value: Cow<'static, str>,
+    percentage: Percentage,
+}
+
+impl Canary {
+    /// Creates the stage sending `percent` of the requests to the instances whose `tag` is
+    /// `value`.
+    pub fn new(
+        tag: impl Into<Cow<'static, str>>,
+        value: impl Into<Cow<'static, str>>,
+        percent: f64,
+    ) -> Self {
+        Self {
+            tag: tag.into(),
+            value: value.into(),
+            percentage: Percentage::new(percent),
+        }
+    }
+}
+
+impl InstancePolicy for Canary {
+    fn name(&self) -> &'static str {
+        "canary"
+    }
+
+    fn apply(&self, cx: &mut PolicyContext<'_>, instances: &mut Vec<Candidate>) {
+        let is_canary = |c: &Candidate| c.instance.tags.get(&self.tag) == Some(&self.value);
+        if cx.routing_key().is_none() {
+            instances.retain(|c| !is_canary(c));
+        } else if instances.iter().any(is_canary) {
+            instances.retain(is_canary);
+        }
+    }
+
+    fn routing_key(&self, _endpoint: &Endpoint) -> Option<FastStr> {
+        self.percentage
+            .hit()
+            .then_some(FastStr::from_static_str(CANARY_KEY))
+    }
+}
+
+/// Selects the instances of the subset asked by the request, e.g. the canary instances, by a tag
+/// of the instances and the [`SubsetKey`] in the tags of the callee.
+///
+/// All the instances are kept if the request asks no subset, or none is in the subset.
+#[derive(Debug, Clone)]
+pub struct Subset {
+    tag: Cow<'static, str>,
+}
+
+/// The subset asked by the request for the [`Subset`] stage, which is inserted into the tags of
+/// the callee, e.g. by a layer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubsetKey(pub FastStr);
+
+impl Subset {
+    pub fn new(tag: impl Into<Cow<'static, str>>) -> Self {
+        Self { tag: tag.into() }
+    }
+}
+
+impl InstancePolicy for Subset {
+    fn name(&self) -> &'static str {
+        "subset"
+    }
+
+    fn apply(&self, cx: &mut PolicyContext<'_>, instances: &mut Vec<Candidate>) {
+        let Some(subset) = cx.routing_key() else {
+            return;
+        };
+        let in_subset = |c: &Candidate| {
+            c.instance.tags.get(&self.tag).map(|v| v.as_ref()) == Some(subset.as_str())
+        };
+        if instances.iter().any(in_subset) {
+            instances.retain(in_subset);
+        }
+    }
+
+    fn routing_key(&self, endpoint: &Endpoint) -> Option<FastStr> {
+        endpoint.get::<SubsetKey>().map(|key| key.0.clone())
+    }
+}
+
+/// Prefers the instances of the same locality, e.g. the same zone, by a tag of the instances.
+///
+/// All the instances are kept if the local ones are fewer than the minimum.
+#[derive(Debug, Clone)]
+pub struct Locality {
+    tag: Cow<'static, str>,
+    local: Cow<'static, str>,
+    min_instances: usize,
+}
+
+impl Locality {
+    /// Creates the stage preferring the instances whose `tag` is `local`.
+    pub fn new(tag: impl Into<Cow<'static, str>>, local: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            tag: tag.into(),
+            local: local.into(),
+            min_instances: 1,
+        }
+    }
+
+    /// Sets the minimum number of the local instances to prefer them.
+    ///
+    /// Default is `1`.
+    pub fn min_instances(mut self, min: usize) -> Self {
+        self.min_instances = min.max(1);
+        self
+    }
+}
+
+impl InstancePolicy for Locality {
+    fn name(&self) -> &'static str {
+        "locality"
+    }
+
+    fn apply(&self, _cx: &mut PolicyContext<'_>, instances: &mut Vec<Candidate>) {
+        let is_local = |c: &Candidate| c.instance.tags.get(&self.tag) == Some(&self.local);
+        if instances.iter().filter(|c| is_local(c)).count() >= self.min_instances {
+            instances.retain(is_local);
+        }
+    }
+}
+
+/// The steps of the ramp of the [`SlowStart`].
+const SLOW_START_STEPS: u32 = 10;
+
+/// Ramps up the weights of the instances discovered after the first ones, from the minimum ratio
+/// of their weights to the full weights in steps during the window, so they're warmed up by a
+/// part of the traffic first.
+#[derive(Debug, Clone)]
+pub struct SlowStart {
+    window: Duration,
+    min_ratio: f64,
+}
+
+impl SlowStart {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            min_ratio: 0.1,
+        }
+    }
+
+    /// Sets the ratio of the weights the new instances start from.
+    ///
+    /// Default is `0.1`.
+    pub fn min_ratio(mut self, ratio: f64) -> Self {
+        self.min_ratio = ratio.clamp(0.0, 1.0);
+        self
+    }
+}
+
+impl InstancePolicy for SlowStart {
+    fn name(&self) -> &'static str {
+        "slow_start"
+    }
+
+    fn apply(&self, cx: &mut PolicyContext<'_>, instances: &mut Vec<Candidate>) {
+        for c in instances.iter_mut() {
+            let Some(since) = cx.first_seen(&c.instance.address) else {
+                continue;
+            };
+            let elapsed = cx.now().saturating_duration_since(since);
+            if elapsed >= self.window || c.weight == 0 {
+                continue;
+            }
+            let step =
+                (elapsed.as_nanos() * SLOW_START_STEPS as u128 / self.window.as_nanos()) as u32;
+            let ratio = (step as f64 / SLOW_START_STEPS as f64).max(self.min_ratio);
+            c.weight = ((c.weight as f64 * ratio) as u32).clamp(1, c.weight);
+            cx.expire_at(since + self.window * (step + 1) / SLOW_START_STEPS);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, net::SocketAddr};
+
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::loadbalance::outlier::OutlierDetection;
+
+    fn addr(port: u16) -> Address {
+        Address::from(SocketAddr::from(([127, 0, 0, 1], port)))
+    }
+
+    fn instance(port: u16, weight: u32, zone: &'static str) -> Arc<Instance> {
+        Arc::new(Instance {
+            address: addr(port),
+            weight,
+            tags: [("zone".into(), zone.into())].into(),
+        })
+    }
+
+    fn weights(instances: &[Arc<Instance>]) -> Vec<(u16, u32)> {
+        instances
+            .iter()
+            .map(|i| match &i.address {
+                Address::Ip(addr) => (addr.port(), i.weight),
+                #[cfg(target_family = "unix")]
+                Address::Unix(_) => unreachable!(),
+            })
+            .collect()
+    }
+
+    fn evaluate(
+        chain: &PolicyChain,
+        routing_keys: &[Option<FastStr>],
+        first_seen: &HashMap<Address, Option<Instant>>,
+        now: Instant,
+        instances: &[Arc<Instance>],
+    ) -> (Vec<Arc<Instance>>, Vec<StageStats>, Option<Instant>) {
+        let service = FastStr::from_static_str("item");
+        let mut cx = PolicyContext {
+            service: &service,
+            routing_keys,
+            stage: 0,
+            first_seen,
+            now,
+            expires: None,
+        };
+        let (instances, stats) = chain.evaluate(&mut cx, instances);
+        (instances, stats, cx.expires)
+    }
+
+    #[test]
+    fn stages_in_order() {
+        let detector = Arc::new(OutlierDetector::new(
+            OutlierDetection::default().consecutive_failures(1),
+        ));
+        let chain = PolicyChain::new()
+            .stage(OutlierEjection::new(detector.clone()))
+            .stage(Locality::new("zone", "a"))
+            .stage(SlowStart::new(Duration::from_secs(10)));
+        let instances = [
+            instance(1, 100, "a"),
+            instance(2, 100, "a"),
+            instance(3, 100, "b"),
+        ];
+        let now = Instant::now();
+        let first_seen = HashMap::from([
+            (addr(1), None),
+            (addr(2), Some(now - Duration::from_secs(3))),
+            (addr(3), None),
+        ]);
+
+        let (served, stats, expires) = evaluate(&chain, &[], &first_seen, now, &instances);
+        assert_eq!(weights(&served), [(1, 100), (2, 30)]);
+        assert_eq!(
+            stats
+                .iter()
+                .map(|s| (s.removed, s.reweighted))
+                .collect::<Vec<_>>(),
+            [(0, 0), (1, 0), (0, 1)]
+        );
+        // the next step of the ramp
+        assert_eq!(expires, Some(now + Duration::from_secs(1)));
+
+        // the ejection is applied before the locality, which falls back to all the instances
+        let version = chain.version();
+        detector.report(&addr(1), false);
+        detector.report(&addr(2), false);
+        assert_ne!(chain.version(), version);
+        let (served, stats, expires) = evaluate(&chain, &[], &first_seen, now, &instances);
+        assert_eq!(weights(&served), [(3, 100)]);
+        assert_eq!(stats[0].removed, 2);
+        assert_eq!(stats[1].removed, 0);
+        assert!(expires.is_some_and(|expires| expires > now + Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn canary_split() {
+        let chain = PolicyChain::new().stage(Canary::new("zone", "canary", 20.0));
+        let endpoint = Endpoint::new("item".into());
+        let n = 10_000;
+        let canary = (0..n)
+            .filter(|_| !chain.routing_keys(&endpoint).is_empty())
+            .count() as f64
+            / n as f64;
+        assert!((0.18..0.22).contains(&canary), "{canary}");
+
+        let instances = [
+            instance(1, 100, "a"),
+            instance(2, 100, "b"),
+            instance(3, 100, "canary"),
+        ];
+        let now = Instant::now();
+        let canary_keys = [Some(FastStr::from_static_str(CANARY_KEY))];
+        let (served, ..) = evaluate(&chain, &canary_keys, &HashMap::new(), now, &instances);
+        assert_eq!(weights(&served), [(3, 100)]);
+        let (served, ..) = evaluate(&chain, &[], &HashMap::new(), now, &instances);
+        assert_eq!(weights(&served), [(1, 100), (2, 100)]);
+
+        // without any canary instance, the canary requests are sent to all of them
+        let (served, ..) = evaluate(&chain, &canary_keys, &HashMap::new(), now, &instances[..2]);
+        assert_eq!(weights(&served), [(1, 100), (2, 100)]);
+    }
+
+    /// A stage removing all the instances.
+    struct Drain;
+
+    impl InstancePolicy for Drain {
+        fn name(&self) -> &'static str {
+            "drain"
+        }
+
+        fn apply(&self, _cx: &mut PolicyContext<'_>, instances: &mut Vec<Candidate>) {
+            instances.clear();
+        }
+    }
+
+    #[test]
+    fn never_empty() {
+        let chain = PolicyChain::new()
+            .stage(Locality::new("zone", "a").min_instances(3))
+            .stage(Subset::new("zone"))
+            .stage(Drain);
+        let instances = [instance(1, 10, "a"), instance(2, 10, "b")];
+        let routing_keys = [None, Some(FastStr::from_static_str("c")), None];
+        let (served, stats, _) = evaluate(
+            &chain,
+            &routing_keys,
+            &HashMap::new(),
+            Instant::now(),
+            &instances,
+        );
+        // neither has the instances asked, so both keep all of them
+        assert_eq!(served, instances);
+        assert!(stats[..2].iter().all(|s| s.removed == 0 && !s.skipped));
+        // and the stage emptying the instances is skipped
+        assert_eq!(stats[2].removed, 2);
+        assert!(stats[2].skipped);
+    }
+
+    /// A stage removing and reweighting the instances by the seed, which may leave nothing.
+    struct Scramble(u64);
+
+    impl InstancePolicy for Scramble {
+        fn name(&self) -> &'static str {
+            "scramble"
+        }
+
+        fn apply(&self, _cx: &mut PolicyContext<'_>, instances: &mut Vec<Candidate>) {
+            let mut rng = StdRng::seed_from_u64(self.0);
+            instances.retain(|_| rng.gen_bool(0.7));
+            for c in instances.iter_mut() {
+                if rng.gen_bool(0.5) {
+                    c.weight = rng.gen_range(0..5);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn composition_invariants() {
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        for _ in 0..1000 {
+            let instances = (0..rng.gen_range(0..8))
+                .map(|port| {
+                    instance(
+                        port,
+                        rng.gen_range(0..4),
+                        if rng.gen_bool(0.5) { "a" } else { "b" },
+                    )
+                })
+                .collect::<Vec<_>>();
+            let now = Instant::now();
+            let first_seen = instances
+                .iter()
+                .map(|i| {
+                    let seen = rng
+                        .gen_bool(0.5)
+                        .then(|| now - Duration::from_millis(rng.gen_range(0..2000)));
+                    (i.address.clone(), seen)
+                })
+                .collect::<HashMap<_, _>>();
+
+            let mut chain = PolicyChain::new();
+            let mut routing_keys = Vec::new();
+            for _ in 0..rng.gen_range(0..5) {
+                let key = match rng.gen_range(0..4) {
+                    0 => {
+                        chain = chain.stage(Scramble(rng.gen()));
+                        None
+                    }
+                    1 => {
+                        chain = chain.stage(Locality::new("zone", "a").min_instances(2));
+                        None
+                    }
+                    2 => {
+                        chain = chain.stage(Subset::new("zone"));
+                        Some(FastStr::from_static_str("b"))
+                    }
+                    _ => {
+                        chain = chain.stage(SlowStart::new(Duration::from_secs(1)));
+                        None
+                    }
+                };
+                routing_keys.push(key);
+            }
+
+            let (served, stats, expires) =
+                evaluate(&chain, &routing_keys, &first_seen, now, &instances);
+            assert_eq!(stats.len(), routing_keys.len());
+            // never emptied
+            if instances.iter().any(|i| i.weight > 0) {
+                assert!(!served.is_empty());
+            }
+            // only the instances of positive weights if any
+            if served.iter().any(|i| i.weight > 0) {
+                assert!(served.iter().all(|i| i.weight > 0));
+            }
+            // only the instances discovered
+            assert!(served
+                .iter()
+                .all(|s| instances.iter().any(|i| i.address == s.address)));
+            // deterministic
+            assert_eq!(
+                evaluate(&chain, &routing_keys, &first_seen, now, &instances),
+                (served, stats, expires)
+            );
+        }
+    }
+
+    /// A discover of a single service whose instances are updated by the tests.
+    #[derive(Clone)]
+    struct Scripted {
+        instances: Arc<Mutex<Vec<Arc<Instance>>>>,
+        sender: Sender<Change<FastStr>>,
+        _receiver: Arc<InactiveReceiver<Change<FastStr>>>,
+    }
+
+    impl Scripted {
+        fn new(instances: Vec<Arc<Instance>>) -> Self {
+            let (mut sender, receiver) = async_broadcast::broadcast(16);
+            sender.set_await_active(false);
+            Self {
+                instances: Arc::new(Mutex::new(instances)),
+                sender,
+                _receiver: Arc::new(receiver.deactivate()),
+            }
+        }
+
+        fn set(&self, next: Vec<Arc<Instance>>) {
+            let prev = std::mem::replace(&mut *self.instances.lock().unwrap(), next.clone());
+            if let Some(change) = diff("item".into(), prev, next) {
+                self.sender.try_broadcast(change).unwrap();
+            }
+        }
+    }
+
+    impl Discover for Scripted {
+        type Key = FastStr;
+        type Error = Infallible;
+
+        async fn discover<'s>(&'s self, _: &'s Endpoint) -> Result<Vec<Arc<Instance>>, Infallible> {
+            Ok(self.instances.lock().unwrap().clone())
+        }
+
+        fn key(&self, endpoint: &Endpoint) -> FastStr {
+            endpoint.service_name()
+        }
+
+        fn watch(&self, _: Option<&[FastStr]>) -> Option<Receiver<Change<FastStr>>> {
+            Some(self.sender.new_receiver())
+        }
+    }
+
+    /// Counts the evaluations.
+    struct Counting(Arc<AtomicU64>);
+
+    impl InstancePolicy for Counting {
+        fn name(&self) -> &'static str {
+            "counting"
+        }
+
+        fn apply(&self, _cx: &mut PolicyContext<'_>, _instances: &mut Vec<Candidate>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[tokio::test]
+    async fn outlier_ejection_by_reports() {
+        let detector = Arc::new(OutlierDetector::new(
+            OutlierDetection::default()
+                .consecutive_failures(1)
+                .ejection_time(Duration::from_millis(20), Duration::from_millis(20)),
+        ));
+        let inner = Scripted::new(vec![instance(1, 100, "a"), instance(2, 100, "a")]);
+        let discover = PolicyDiscover::new(
+            inner.clone(),
+            PolicyChain::new().stage(OutlierEjection::new(detector.clone())),
+        );
+        let endpoint = Endpoint::new("item".into());
+        let served = discover.discover(&endpoint).await.unwrap();
+        assert_eq!(weights(&served), [(1, 100), (2, 100)]);
+
+        // the failure reported by the load balancing ejects the instance
+        discover.report(&addr(1), false);
+        assert!(detector.is_ejected(&addr(1)));
+        let served = discover.discover(&endpoint).await.unwrap();
+        assert_eq!(weights(&served), [(2, 100)]);
+
+        // probed after the ejection time, and healthy again after a success
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        let served = discover.discover(&endpoint).await.unwrap();
+        assert_eq!(weights(&served), [(1, PROBE_WEIGHT), (2, 100)]);
+        discover.report(&addr(1), true);
+        let served = discover.discover(&endpoint).await.unwrap();
+        assert_eq!(weights(&served), [(1, 100), (2, 100)]);
+
+        // the state is forgotten once the instance is removed by the discovery
+        discover.report(&addr(2), false);
+        assert!(detector.is_ejected(&addr(2)));
+        let mut changes = discover.watch(None).unwrap();
+        inner.set(vec![instance(1, 100, "a"), instance(3, 100, "a")]);
+        changes.recv().await.unwrap();
+        assert!(!detector.is_ejected(&addr(2)));
+    }
+
+    #[tokio::test]
+    async fn cache_and_refresh() {
+        let evaluations = Arc::new(AtomicU64::new(0));
+        let inner = Scripted::new(vec![instance(1, 100, "a"), instance(2, 100, "b")]);
+        let discover = PolicyDiscover::new(
+            inner.clone(),
+            PolicyChain::new()
+                .stage(Counting(evaluations.clone()))
+                .stage(Subset::new("zone"))
+                .stage(SlowStart::new(Duration::from_millis(200))),
+        )
+        .refresh_interval(Duration::from_millis(10));
+
+        let endpoint = Endpoint::new("item".into());
+        let mut canary = Endpoint::new("item".into());
+        canary.insert(SubsetKey("b".into()));
+        assert_ne!(discover.key(&endpoint), discover.key(&canary));
+
+        let served = discover.discover(&endpoint).await.unwrap();
+        assert_eq!(weights(&served), [(1, 100), (2, 100)]);
+        let served = discover.discover(&canary).await.unwrap();
+        assert_eq!(weights(&served), [(2, 100)]);
+        // cached
+        discover.discover(&endpoint).await.unwrap();
+        assert_eq!(evaluations.load(Ordering::Relaxed), 2);
+        discover.chain().invalidate();
+        discover.discover(&endpoint).await.unwrap();
+        assert_eq!(evaluations.load(Ordering::Relaxed), 3);
+
+        let mut changes = discover.watch(None).unwrap();
+
+        // the new instance is ramped up, only for the key without the subset
+        inner.set(vec![
+            instance(1, 100, "a"),
+            instance(2, 100, "b"),
+            instance(3, 100, "a"),
+        ]);
+        let change = changes.recv().await.unwrap();
+        assert_eq!(change.key, discover.key(&endpoint));
+        assert_eq!(weights(&change.added), [(3, 10)]);
+        let mut weight = 10;
+        while weight < 100 {
+            let change = tokio::time::timeout(Duration::from_secs(1), changes.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(change.key, discover.key(&endpoint));
+            let (_, next) = weights(&change.updated)[0];
+            assert!(next > weight, "{next} <= {weight}");
+            weight = next;
+        }
+
+        let snapshot = discover.snapshot();
+        assert_eq!(snapshot.entries.len(), 2);
+        assert_eq!(
+            snapshot.entries[1].instances,
+            [(addr(2), 100)],
+            "{snapshot:?}"
+        );
+        assert_eq!(
+            snapshot.to_json(),
+            r#"{"entries":[{"service":"item","routing_keys":[],"instances":[{"address":"127.0.0.1:1","weight":100},{"address":"127.0.0.1:2","weight":100},{"address":"127.0.0.1:3","weight":100}],"stages":[{"name":"counting","removed":0,"reweighted":0,"skipped":false},{"name":"subset","removed":0,"reweighted":0,"skipped":false},{"name":"slow_start","removed":0,"reweighted":0,"skipped":false}]},{"service":"item","routing_keys":["b"],"instances":[{"address":"127.0.0.1:2","weight":100}],"stages":[{"name":"counting","removed":0,"reweighted":0,"skipped":false},{"name":"subset","removed":2,"reweighted":0,"skipped":false},{"name":"slow_start","removed":0,"reweighted":0,"skipped":false}]}]}"#
+        );
+    }
+}
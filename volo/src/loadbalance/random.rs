@@ -1,5 +1,5 @@
 use core::cell::OnceCell;
-use std::{hash::Hash, sync::Arc};
+use std::{hash::Hash, ops::Range, sync::Arc};
 
 use dashmap::{mapref::entry::Entry, DashMap};
 use rand::Rng;
@@ -7,7 +7,7 @@ use rand::Rng;
 use super::{error::LoadBalanceError, LoadBalance};
 use crate::{
     context::Endpoint,
-    discovery::{Change, Discover, Instance},
+    discovery::{Change, Discover, Instance, PRIORITY_TAG},
     net::Address,
 };
 
@@ -26,9 +26,16 @@ fn pick_one(weight: isize, iter: &[Arc<Instance>]) -> Option<(usize, Arc<Instanc
     None
 }
 
+/// Picks the instances by weight, from the highest priority to the lowest.
+///
+/// The instances of a lower priority are yielded only after all the ones of the higher priorities,
+/// so they're used only when the higher ones are all down, e.g. ejected by the outlier detection.
+/// See [`PRIORITY_TAG`].
 #[derive(Debug)]
 pub struct InstancePicker {
     shared_instances: Arc<WeightedInstances>,
+    /// The index of the priority tier being picked.
+    tier: usize,
     sum_of_weights: isize,
     owned_instances: OnceCell<Vec<Arc<Instance>>>,
     last_pick: Option<(usize, Arc<Instance>)>,
@@ -38,48 +45,85 @@ impl Iterator for InstancePicker {
     type Item = Address;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let shared_instances = &self.shared_instances.instances;
-        if shared_instances.is_empty() {
-            return None;
-        }
-
-        match &mut self.last_pick {
-            None => {
-                let (offset, instance) = pick_one(self.sum_of_weights, shared_instances)?;
-                self.last_pick = Some((offset, instance.clone()));
-                Some(instance.address.clone())
-            }
-            Some((last_offset, last_pick)) => {
-                self.owned_instances
-                    .get_or_init(|| shared_instances.to_vec());
-                let owned = self.owned_instances.get_mut().unwrap();
+        loop {
+            let tier = self.shared_instances.tiers.get(self.tier)?;
+            let shared_instances = &self.shared_instances.instances[tier.range.clone()];
 
-                self.sum_of_weights -= last_pick.weight as isize;
-                owned.remove(*last_offset);
+            let picked = match &mut self.last_pick {
+                None => pick_one(self.sum_of_weights, shared_instances),
+                Some((last_offset, last_pick)) => {
+                    self.owned_instances
+                        .get_or_init(|| shared_instances.to_vec());
+                    let owned = self.owned_instances.get_mut().unwrap();
 
-                (*last_offset, *last_pick) = pick_one(self.sum_of_weights, owned)?;
+                    self.sum_of_weights -= last_pick.weight as isize;
+                    owned.remove(*last_offset);
 
-                Some(last_pick.clone().address.clone())
+                    pick_one(self.sum_of_weights, owned)
+                }
+            };
+            if let Some((offset, instance)) = picked {
+                let address = instance.address.clone();
+                self.last_pick = Some((offset, instance));
+                return Some(address);
             }
+
+            // the tier is exhausted, fall to the next one
+            self.tier += 1;
+            self.last_pick = None;
+            self.owned_instances = OnceCell::new();
+            self.sum_of_weights = self
+                .shared_instances
+                .tiers
+                .get(self.tier)
+                .map_or(0, |tier| tier.sum_of_weights);
         }
     }
 }
 
 #[derive(Debug, Clone)]
-struct WeightedInstances {
+struct Tier {
+    range: Range<usize>,
     sum_of_weights: isize,
+}
+
+/// The instances sorted by priority, and split into tiers of the same priority.
+#[derive(Debug, Clone)]
+struct WeightedInstances {
+    tiers: Vec<Tier>,
     instances: Vec<Arc<Instance>>,
 }
 
+fn priority(instance: &Instance) -> u32 {
+    instance
+        .tags
+        .get(PRIORITY_TAG)
+        .and_then(|priority| priority.parse().ok())
+        .unwrap_or(0)
+}
+
 impl From<Vec<Arc<Instance>>> for WeightedInstances {
-    fn from(instances: Vec<Arc<Instance>>) -> Self {
-        let sum_of_weights = instances
-            .iter()
-            .fold(0, |lhs, rhs| lhs + rhs.weight as isize);
-        Self {
-            instances,
-            sum_of_weights,
+    fn from(mut instances: Vec<Arc<Instance>>) -> Self {
+        instances.sort_by_cached_key(|instance| priority(instance));
+        let mut tiers = Vec::new();
+        let mut start = 0;
+        while start < instances.len() {
+            let current = priority(&instances[start]);
+            let len = instances[start..]
+                .iter()
+                .take_while(|instance| priority(instance) == current)
+                .count();
+            let end = start + len;
+            let sum_of_weights = instances[start..end]
+                .iter()
+                .fold(0, |lhs, rhs| lhs + rhs.weight as isize);
+            tiers.push(Tier {
+                range: start..end,
+                sum_of_weights,
+            });
+            start = end;
         }
+        Self { tiers, instances }
     }
 }
 
@@ -135,11 +179,15 @@ where
                 e.insert(instances).value().clone()
             }
         };
-        let sum_of_weights = weighted_list.sum_of_weights;
+        let sum_of_weights = weighted_list
+            .tiers
+            .first()
+            .map_or(0, |tier| tier.sum_of_weights);
         Ok(InstancePicker {
             owned_instances: OnceCell::new(),
             last_pick: None,
             shared_instances: weighted_list,
+            tier: 0,
             sum_of_weights,
         })
     }
@@ -153,8 +201,14 @@ where
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
     use super::{LoadBalance, WeightedRandomBalance};
-    use crate::{context::Endpoint, discovery::StaticDiscover};
+    use crate::{
+        context::Endpoint,
+        discovery::{Instance, StaticDiscover, PRIORITY_TAG},
+        net::Address,
+    };
 
     #[tokio::test]
     async fn test_weighted_random() {
@@ -169,4 +223,36 @@ mod tests {
         assert_eq!(all.len(), 2);
         assert_ne!(all[0], all[1]);
     }
+
+    #[tokio::test]
+    async fn test_priority_tiers() {
+        let instance = |port: u16, priority: &'static str| {
+            Arc::new(Instance {
+                address: Address::from(std::net::SocketAddr::from(([127, 0, 0, 1], port))),
+                weight: 10,
+                tags: [(PRIORITY_TAG.into(), priority.into())].into(),
+            })
+        };
+        let discover = StaticDiscover::new(vec![
+            instance(3, "20"),
+            instance(1, "10"),
+            instance(4, "20"),
+            instance(2, "10"),
+        ]);
+        let empty = Endpoint::new("".into());
+        let lb = WeightedRandomBalance::with_discover(&discover);
+        for _ in 0..10 {
+            let picker = lb.get_picker(&empty, &discover).await.unwrap();
+            let ports = picker
+                .map(|addr| match addr {
+                    Address::Ip(addr) => addr.port(),
+                    #[cfg(target_family = "unix")]
+                    Address::Unix(_) => unreachable!(),
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(ports.len(), 4);
+            assert!(ports[..2].iter().all(|port| *port <= 2), "{ports:?}");
+            assert!(ports[2..].iter().all(|port| *port > 2), "{ports:?}");
+        }
+    }
 }
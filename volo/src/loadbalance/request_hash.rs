@@ -0,0 +1,143 @@
+//! Computing the [`RequestHash`] of the requests for the consistent hashing.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use volo::loadbalance::{
+//!     consistent_hash::{ConsistentHashBalance, ConsistentHashOption},
+//!     request_hash::RequestHashLayer,
+//!     RequestHash,
+//! };
+//!
+//! let client = volo_gen::volo::example::ItemServiceClientBuilder::new("item")
+//!     .load_balance(ConsistentHashBalance::new(ConsistentHashOption::default()))
+//!     .layer_outer(RequestHashLayer::new(
+//!         |_cx: &ClientContext, req: &GetItemRequest| Some(RequestHash::from_key(&req.key)),
+//!     ))
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use std::{cell::RefCell, sync::Arc};
+
+use metainfo::{MetaInfo, METAINFO};
+use motore::{layer::Layer, service::Service};
+
+use super::RequestHash;
+
+/// Computes the [`RequestHash`] from the context and the request.
+///
+/// Returning `None` leaves the hash in the `METAINFO` as it is, which may be set by the caller.
+pub trait RequestHasher<Cx, Req>: Send + Sync + 'static {
+    fn request_hash(&self, cx: &Cx, req: &Req) -> Option<RequestHash>;
+}
+
+impl<Cx, Req, F> RequestHasher<Cx, Req> for F
+where
+    F: Fn(&Cx, &Req) -> Option<RequestHash> + Send + Sync + 'static,
+{
+    fn request_hash(&self, cx: &Cx, req: &Req) -> Option<RequestHash> {
+        self(cx, req)
+    }
+}
+
+/// A layer that puts the [`RequestHash`] computed by the [`RequestHasher`] into the `METAINFO`.
+///
+/// It should be put in the outer layers of the client, so the hash is set before the load
+/// balancing.
+#[derive(Clone)]
+pub struct RequestHashLayer<H> {
+    hasher: Arc<H>,
+}
+
+impl<H> RequestHashLayer<H> {
+    pub fn new(hasher: H) -> Self {
+        Self {
+            hasher: Arc::new(hasher),
+        }
+    }
+}
+
+impl<S, H> Layer<S> for RequestHashLayer<H> {
+    type Service = RequestHashService<S, H>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        RequestHashService {
+            inner,
+            hasher: self.hasher,
+        }
+    }
+}
+
+/// The service created by [`RequestHashLayer`].
+pub struct RequestHashService<S, H> {
+    inner: S,
+    hasher: Arc<H>,
+}
+
+impl<S: Clone, H> Clone for RequestHashService<S, H> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            hasher: self.hasher.clone(),
+        }
+    }
+}
+
+impl<Cx, Req, S, H> Service<Cx, Req> for RequestHashService<S, H>
+where
+    Cx: Send + 'static,
+    Req: Send + 'static,
+    S: Service<Cx, Req> + Send + Sync + 'static,
+    H: RequestHasher<Cx, Req>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call<'s, 'cx>(
+        &'s self,
+        cx: &'cx mut Cx,
+        req: Req,
+    ) -> Result<Self::Response, Self::Error> {
+        let Some(hash) = self.hasher.request_hash(cx, &req) else {
+            return self.inner.call(cx, req).await;
+        };
+        if METAINFO.try_with(|m| m.borrow_mut().insert(hash)).is_err() {
+            // not in the scope of a `METAINFO`, e.g. the client is called by a plain task
+            let mut mi = MetaInfo::new();
+            mi.insert(hash);
+            return METAINFO
+                .scope(RefCell::new(mi), self.inner.call(cx, req))
+                .await;
+        }
+        self.inner.call(cx, req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use motore::{layer::Layer, service::service_fn, Service};
+
+    use super::{RequestHash, RequestHashLayer};
+
+    /// Returns the hash in the `METAINFO`, which is read by the load balancer.
+    async fn handle(_cx: &mut (), _req: String) -> Result<Option<RequestHash>, ()> {
+        Ok(metainfo::METAINFO
+            .try_with(|m| m.borrow().get::<RequestHash>().copied())
+            .ok()
+            .flatten())
+    }
+
+    #[test]
+    fn set_request_hash() {
+        let svc = RequestHashLayer::new(|_cx: &(), req: &String| {
+            (!req.is_empty()).then(|| RequestHash::from_key(req))
+        })
+        .layer(service_fn(handle));
+
+        let hash = futures::executor::block_on(svc.call(&mut (), "key".to_owned())).unwrap();
+        assert_eq!(hash, Some(RequestHash::from_key("key")));
+        let hash = futures::executor::block_on(svc.call(&mut (), String::new())).unwrap();
+        assert_eq!(hash, None);
+    }
+}
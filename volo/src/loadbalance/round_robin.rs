@@ -0,0 +1,275 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{Arc, Mutex},
+};
+
+use dashmap::{mapref::entry::Entry, DashMap};
+
+use super::{error::LoadBalanceError, LoadBalance};
+use crate::{
+    context::Endpoint,
+    discovery::{Change, Discover, Instance},
+    net::Address,
+};
+
+/// The instances of a key and their current weights of the smooth weighted round-robin.
+#[derive(Debug)]
+struct RoundRobinInstances {
+    /// The instances with non-zero weights.
+    instances: Arc<Vec<Arc<Instance>>>,
+    current_weights: Vec<i64>,
+    sum_of_weights: i64,
+}
+
+impl RoundRobinInstances {
+    /// Creates the instances, and keeps the current weights of the instances in `prev`, so the
+    /// changes of discovery will not reset the distribution of the remaining instances.
+    fn new(instances: Vec<Arc<Instance>>, prev: Option<&RoundRobinInstances>) -> Self {
+        let instances: Vec<_> = instances.into_iter().filter(|i| i.weight > 0).collect();
+        let prev: HashMap<&Address, i64> = prev
+            .map(|prev| {
+                prev.instances
+                    .iter()
+                    .map(|i| &i.address)
+                    .zip(prev.current_weights.iter().copied())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let current_weights = instances
+            .iter()
+            .map(|i| prev.get(&i.address).copied().unwrap_or_default())
+            .collect();
+        let sum_of_weights = instances.iter().map(|i| i.weight as i64).sum();
+        Self {
+            instances: Arc::new(instances),
+            current_weights,
+            sum_of_weights,
+        }
+    }
+
+    /// Picks an instance by the smooth weighted round-robin of nginx: each instance increases its
+    /// current weight by its weight, and the one with the largest current weight is picked and
+    /// decreased by the sum of weights.
+    fn pick(&mut self) -> Option<usize> {
+        if self.instances.is_empty() {
+            return None;
+        }
+        let mut picked = 0;
+        for (offset, instance) in self.instances.iter().enumerate() {
+            self.current_weights[offset] += instance.weight as i64;
+            if self.current_weights[offset] > self.current_weights[picked] {
+                picked = offset;
+            }
+        }
+        self.current_weights[picked] -= self.sum_of_weights;
+        Some(picked)
+    }
+}
+
+/// The picker of [`WeightedRoundRobin`], which returns the picked instance first, and then the
+/// others in order for retrying.
+#[derive(Debug)]
+pub struct RoundRobinPicker {
+    instances: Arc<Vec<Arc<Instance>>>,
+    start: usize,
+    picked: usize,
+}
+
+impl Iterator for RoundRobinPicker {
+    type Item = Address;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.picked >= self.instances.len() {
+            return None;
+        }
+        let offset = (self.start + self.picked) % self.instances.len();
+        self.picked += 1;
+        Some(self.instances[offset].address.clone())
+    }
+}
+
+/// The smooth weighted round-robin load balance, which distributes the requests evenly by the
+/// weights even in a short period, e.g. the weights `{a: 5, b: 1, c: 1}` produce
+/// `a, a, b, a, c, a, a`.
+///
+/// Instances with zero weight will never be picked.
+#[derive(Debug)]
+pub struct WeightedRoundRobin<K>
+where
+    K: Hash + PartialEq + Eq + Send + Sync + 'static,
+{
+    router: DashMap<K, Arc<Mutex<RoundRobinInstances>>>,
+}
+
+impl<K> WeightedRoundRobin<K>
+where
+    K: Hash + PartialEq + Eq + Send + Sync + 'static,
+{
+    pub fn with_discover<D>(_: &D) -> Self
+    where
+        D: Discover<Key = K>,
+    {
+        Self::new()
+    }
+
+    pub fn new() -> Self {
+        Self {
+            router: DashMap::new(),
+        }
+    }
+}
+
+impl<K> Default for WeightedRoundRobin<K>
+where
+    K: Hash + PartialEq + Eq + Send + Sync + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D> LoadBalance<D> for WeightedRoundRobin<D::Key>
+where
+    D: Discover,
+{
+    type InstanceIter = RoundRobinPicker;
+
+    async fn get_picker<'future>(
+        &'future self,
+        endpoint: &'future Endpoint,
+        discover: &'future D,
+    ) -> Result<Self::InstanceIter, LoadBalanceError> {
+        let key = discover.key(endpoint);
+        // the guard of the map should not be held across the discovering
+        let cached = self
+            .router
+            .get(&key)
+            .map(|instances| instances.value().clone());
+        let instances = match cached {
+            Some(instances) => instances,
+            None => {
+                let discovered = discover
+                    .discover(endpoint)
+                    .await
+                    .map_err(|err| err.into())?;
+                self.router
+                    .entry(key)
+                    .or_insert_with(|| {
+                        Arc::new(Mutex::new(RoundRobinInstances::new(discovered, None)))
+                    })
+                    .value()
+                    .clone()
+            }
+        };
+
+        let mut instances = instances.lock().unwrap();
+        let start = instances.pick().unwrap_or_default();
+        Ok(RoundRobinPicker {
+            instances: instances.instances.clone(),
+            start,
+            picked: 0,
+        })
+    }
+
+    fn rebalance(&self, changes: Change<D::Key>) {
+        if let Entry::Occupied(entry) = self.router.entry(changes.key.clone()) {
+            let mut instances = entry.get().lock().unwrap();
+            let next = RoundRobinInstances::new(changes.all, Some(&*instances));
+            *instances = next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{LoadBalance, WeightedRoundRobin};
+    use crate::{
+        context::Endpoint,
+        discovery::{Change, Instance, StaticDiscover},
+        net::Address,
+    };
+
+    fn instance(addr: &str, weight: u32) -> Arc<Instance> {
+        Arc::new(Instance {
+            address: Address::from(addr.parse::<std::net::SocketAddr>().unwrap()),
+            weight,
+            tags: Default::default(),
+        })
+    }
+
+    async fn pick_n(
+        lb: &WeightedRoundRobin<()>,
+        discover: &StaticDiscover,
+        n: usize,
+    ) -> Vec<String> {
+        let empty = Endpoint::new("".into());
+        let mut picked = Vec::with_capacity(n);
+        for _ in 0..n {
+            let mut picker = lb.get_picker(&empty, discover).await.unwrap();
+            picked.push(picker.next().unwrap().to_string());
+        }
+        picked
+    }
+
+    #[tokio::test]
+    async fn test_smooth_weighted_round_robin() {
+        let discover = StaticDiscover::new(vec![
+            instance("127.0.0.1:8000", 5),
+            instance("127.0.0.2:8000", 1),
+            instance("127.0.0.3:8000", 1),
+            instance("127.0.0.4:8000", 0),
+        ]);
+        let lb = WeightedRoundRobin::with_discover(&discover);
+
+        let picked = pick_n(&lb, &discover, 7).await;
+        assert_eq!(
+            picked,
+            [
+                "127.0.0.1:8000",
+                "127.0.0.1:8000",
+                "127.0.0.2:8000",
+                "127.0.0.1:8000",
+                "127.0.0.3:8000",
+                "127.0.0.1:8000",
+                "127.0.0.1:8000",
+            ]
+        );
+
+        // the picker returns the other instances with non-zero weight for retrying
+        let empty = Endpoint::new("".into());
+        let picker = lb.get_picker(&empty, &discover).await.unwrap();
+        assert_eq!(picker.count(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_rebalance_keeps_current_weights() {
+        let discover = StaticDiscover::new(vec![
+            instance("127.0.0.1:8000", 1),
+            instance("127.0.0.2:8000", 1),
+        ]);
+        let lb = WeightedRoundRobin::with_discover(&discover);
+        assert_eq!(pick_n(&lb, &discover, 1).await, ["127.0.0.1:8000"]);
+
+        // adding an instance should not make the next pick go back to the first one
+        LoadBalance::<StaticDiscover>::rebalance(
+            &lb,
+            Change {
+                key: (),
+                all: vec![
+                    instance("127.0.0.1:8000", 1),
+                    instance("127.0.0.2:8000", 1),
+                    instance("127.0.0.3:8000", 1),
+                ],
+                added: vec![instance("127.0.0.3:8000", 1)],
+                updated: vec![],
+                removed: vec![],
+            },
+        );
+        let picked = pick_n(&lb, &discover, 3).await;
+        assert_eq!(picked[0], "127.0.0.2:8000");
+        assert!(picked.contains(&"127.0.0.3:8000".to_owned()));
+    }
+}
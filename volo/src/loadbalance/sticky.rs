@@ -0,0 +1,554 @@
+//! The session affinity of the clients, which sends the requests of a session to the same
+//! instance until it's gone.
+//!
+//! [`StickyBalance`] wraps another [`LoadBalance`]. The first request with an affinity key is
+//! balanced by the inner one, and the picked instance is kept for the key. The later requests of
+//! the key are sent to the kept instance as long as the discovery still lists it and it's not
+//! ejected by the [`OutlierDetector`], or they are balanced again and the key is kept with the
+//! new instance.
+//!
+//! The affinity key is read from the callee tag [`AffinityKey`], which can be set by the callopt
+//! of a call, or from the [`TypedKey`] of the `METAINFO` set by [`StickyBalance::metainfo_key`].
+//! The requests without the key are balanced by the inner one directly.
+//!
+//! The keys expire after [`StickyBalance::ttl`] since they are last used, and the least recently
+//! used one is evicted when there are [`StickyBalance::capacity`] keys.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use volo::loadbalance::{
+//!     round_robin::WeightedRoundRobin,
+//!     sticky::{AffinityKey, StickyBalance},
+//! };
+//!
+//! let client = volo_gen::item::ItemServiceClientBuilder::new("item")
+//!     .load_balance(StickyBalance::new(WeightedRoundRobin::new()).ttl(Duration::from_secs(600)))
+//!     .build()?;
+//!
+//! let mut callopt = CallOpt::default();
+//! callopt.callee_faststr_tags.insert::<AffinityKey>(session_id);
+//! client.with_callopt(callopt).get_item(req).await?;
+//! ```
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+use faststr::FastStr;
+
+use metainfo::MetaInfo;
+
+use super::{error::LoadBalanceError, outlier::OutlierDetector, LoadBalance};
+use crate::{
+    context::{typed::TypedKey, Endpoint},
+    discovery::{Change, Discover},
+    net::Address,
+};
+
+const DEFAULT_CAPACITY: usize = 10_000;
+const DEFAULT_TTL: Duration = Duration::from_secs(30 * 60);
+
+/// The tag of the callee endpoint for the affinity key of the request, which is usually set by
+/// the `callee_faststr_tags` of the callopt.
+pub struct AffinityKey;
+
+struct Session {
+    address: Address,
+    expires_at: Instant,
+    tick: u64,
+}
+
+struct Sessions<K> {
+    entries: HashMap<(K, FastStr), Session>,
+    /// The keys ordered by the last time they were used.
+    lru: BTreeMap<u64, (K, FastStr)>,
+    tick: u64,
+}
+
+impl<K: Hash + Eq + Clone> Sessions<K> {
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    /// Returns the instance of the key if it's not expired, and renews the key.
+    fn get(&mut self, key: &(K, FastStr), ttl: Duration, now: Instant) -> Option<Address> {
+        let tick = self.next_tick();
+        let session = self.entries.get_mut(key)?;
+        if now >= session.expires_at {
+            self.remove(key);
+            return None;
+        }
+        session.expires_at = now + ttl;
+        let last = std::mem::replace(&mut session.tick, tick);
+        let address = session.address.clone();
+        self.lru.remove(&last);
+        self.lru.insert(tick, key.clone());
+        Some(address)
+    }
+
+    fn remove(&mut self, key: &(K, FastStr)) {
+        if let Some(session) = self.entries.remove(key) {
+            self.lru.remove(&session.tick);
+        }
+    }
+
+    fn insert(
+        &mut self,
+        key: (K, FastStr),
+        address: Address,
+        expires_at: Instant,
+        capacity: usize,
+    ) {
+        self.remove(&key);
+        while self.entries.len() >= capacity {
+            let Some((_, oldest)) = self.lru.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+        let tick = self.next_tick();
+        self.lru.insert(tick, key.clone());
+        self.entries.insert(
+            key,
+            Session {
+                address,
+                expires_at,
+                tick,
+            },
+        );
+    }
+}
+
+/// The load balance keeping the instance of each affinity key, see the
+/// [module level documentation](self).
+pub struct StickyBalance<LB, K>
+where
+    K: Hash + PartialEq + Eq + Send + Sync + 'static,
+{
+    inner: LB,
+    capacity: usize,
+    ttl: Duration,
+    metainfo_key: Option<fn(&MetaInfo) -> Option<FastStr>>,
+    outlier: Option<Arc<OutlierDetector>>,
+    sessions: Mutex<Sessions<K>>,
+    /// The addresses of the instances listed by the discovery.
+    instances: DashMap<K, Arc<HashSet<Address>>>,
+}
+
+impl<LB, K> StickyBalance<LB, K>
+where
+    K: Hash + PartialEq + Eq + Clone + Send + Sync + 'static,
+{
+    pub fn new(inner: LB) -> Self {
+        Self {
+            inner,
+            capacity: DEFAULT_CAPACITY,
+            ttl: DEFAULT_TTL,
+            metainfo_key: None,
+            outlier: None,
+            sessions: Mutex::new(Sessions {
+                entries: HashMap::new(),
+                lru: BTreeMap::new(),
+                tick: 0,
+            }),
+            instances: DashMap::new(),
+        }
+    }
+
+    /// Sets the maximum number of the affinity keys kept.
+    ///
+    /// Default is `10000`.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// Sets how long an affinity key is kept since it's last used.
+    ///
+    /// Default is `30min`.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Reads the affinity key from the `METAINFO` by the key if the callee has no [`AffinityKey`]
+    /// tag, e.g. a session id passed through by the upstream.
+    ///
+    /// The value set by [`TypedKey::set_in`] is read first, and then the persistent or the
+    /// transient value received from the upstream by the wire name of the key.
+    pub fn metainfo_key<T: TypedKey>(mut self) -> Self {
+        self.metainfo_key = Some(|mi| {
+            if let Some(value) = T::get_in(mi) {
+                return Some(T::encode(value));
+            }
+            let wire = T::as_wire_key();
+            mi.get_persistent(wire)
+                .or_else(|| mi.get_transient(wire))
+                .cloned()
+        });
+        self
+    }
+
+    /// Balances the keys again when their instances are ejected by the detector, which should be
+    /// the one of the client.
+    pub fn outlier_detector(mut self, detector: Arc<OutlierDetector>) -> Self {
+        self.outlier = Some(detector);
+        self
+    }
+
+    /// Forgets the instance of the affinity key, so its next request is balanced again.
+    pub fn invalidate(&self, key: &str) {
+        let mut sessions = self.sessions.lock().unwrap();
+        let keys = sessions
+            .entries
+            .keys()
+            .filter(|(_, k)| k == key)
+            .cloned()
+            .collect::<Vec<_>>();
+        for key in keys {
+            sessions.remove(&key);
+        }
+    }
+
+    /// Returns the number of the affinity keys kept, including the expired ones not evicted yet.
+    pub fn len(&self) -> usize {
+        self.sessions.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn affinity_key(&self, endpoint: &Endpoint) -> Option<FastStr> {
+        if let Some(key) = endpoint.get_faststr::<AffinityKey>() {
+            return Some(key.clone());
+        }
+        let read = self.metainfo_key?;
+        metainfo::METAINFO
+            .try_with(|m| read(&m.borrow()))
+            .ok()
+            .flatten()
+    }
+
+    fn is_ejected(&self, address: &Address) -> bool {
+        self.outlier
+            .as_ref()
+            .is_some_and(|outlier| outlier.is_ejected(address))
+    }
+}
+
+impl<D, LB> LoadBalance<D> for StickyBalance<LB, D::Key>
+where
+    D: Discover,
+    LB: LoadBalance<D>,
+{
+    type InstanceIter = StickyPicker<LB::InstanceIter>;
+
+    async fn get_picker<'future>(
+        &'future self,
+        endpoint: &'future Endpoint,
+        discover: &'future D,
+    ) -> Result<Self::InstanceIter, LoadBalanceError> {
+        let Some(affinity) = self.affinity_key(endpoint) else {
+            return Ok(StickyPicker::new(
+                None,
+                Vec::new(),
+                self.inner.get_picker(endpoint, discover).await?,
+            ));
+        };
+        let key = (discover.key(endpoint), affinity);
+
+        let kept = self
+            .sessions
+            .lock()
+            .unwrap()
+            .get(&key, self.ttl, Instant::now());
+        if let Some(address) = kept {
+            // the guard of the map should not be held across the discovering
+            let cached = self
+                .instances
+                .get(&key.0)
+                .map(|instances| instances.value().clone());
+            let listed = match cached {
+                Some(instances) => instances,
+                None => {
+                    let discovered = discover
+                        .discover(endpoint)
+                        .await
+                        .map_err(|err| err.into())?;
+                    self.instances
+                        .entry(key.0.clone())
+                        .or_insert_with(|| {
+                            Arc::new(discovered.iter().map(|i| i.address.clone()).collect())
+                        })
+                        .value()
+                        .clone()
+                }
+            };
+            if listed.contains(&address) && !self.is_ejected(&address) {
+                let picker = self.inner.get_picker(endpoint, discover).await?;
+                return Ok(StickyPicker::new(Some(address), Vec::new(), picker));
+            }
+            tracing::debug!(
+                "[VOLO] sticky instance {address} of {} is gone, balance again",
+                key.1
+            );
+        }
+
+        // keep the first instance not ejected, and the ejected ones are still tried after it
+        let mut picker = self.inner.get_picker(endpoint, discover).await?;
+        let mut skipped = Vec::new();
+        let picked = loop {
+            match picker.next() {
+                Some(address) if self.is_ejected(&address) => skipped.push(address),
+                Some(address) => break Some(address),
+                None => break None,
+            }
+        };
+        let Some(picked) = picked.or_else(|| (!skipped.is_empty()).then(|| skipped.remove(0)))
+        else {
+            return Ok(StickyPicker::new(None, Vec::new(), picker));
+        };
+        self.sessions.lock().unwrap().insert(
+            key,
+            picked.clone(),
+            Instant::now() + self.ttl,
+            self.capacity,
+        );
+        Ok(StickyPicker::new(Some(picked), skipped, picker))
+    }
+
+    fn rebalance(&self, changes: Change<D::Key>) {
+        self.instances.insert(
+            changes.key.clone(),
+            Arc::new(changes.all.iter().map(|i| i.address.clone()).collect()),
+        );
+        self.inner.rebalance(changes);
+    }
+}
+
+impl<LB, K> std::fmt::Debug for StickyBalance<LB, K>
+where
+    LB: std::fmt::Debug,
+    K: Hash + PartialEq + Eq + Send + Sync + 'static,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StickyBalance")
+            .field("inner", &self.inner)
+            .field("capacity", &self.capacity)
+            .field("ttl", &self.ttl)
+            .finish()
+    }
+}
+
+/// The picker of [`StickyBalance`], which returns the kept instance first, and then the others of
+/// the inner picker for retrying.
+#[derive(Debug)]
+pub struct StickyPicker<I> {
+    kept: Option<Address>,
+    started: bool,
+    skipped: std::vec::IntoIter<Address>,
+    inner: I,
+}
+
+impl<I> StickyPicker<I> {
+    fn new(kept: Option<Address>, skipped: Vec<Address>, inner: I) -> Self {
+        Self {
+            kept,
+            started: false,
+            skipped: skipped.into_iter(),
+            inner,
+        }
+    }
+}
+
+impl<I: Iterator<Item = Address>> Iterator for StickyPicker<I> {
+    type Item = Address;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.started {
+            self.started = true;
+            if let Some(kept) = &self.kept {
+                return Some(kept.clone());
+            }
+        }
+        if let Some(address) = self.skipped.next() {
+            return Some(address);
+        }
+        // the kept instance is not returned twice
+        let kept = self.kept.as_ref();
+        self.inner.by_ref().find(|address| Some(address) != kept)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, sync::Arc, time::Duration};
+
+    use metainfo::{MetaInfo, METAINFO};
+
+    use super::{AffinityKey, LoadBalance, StickyBalance};
+    use crate::{
+        context::{typed::TypedKey, Endpoint},
+        discovery::{Change, Instance, StaticDiscover},
+        loadbalance::{
+            outlier::{OutlierDetection, OutlierDetector},
+            round_robin::WeightedRoundRobin,
+        },
+        net::Address,
+    };
+
+    type Sticky = StickyBalance<WeightedRoundRobin<()>, ()>;
+
+    crate::metainfo_key!(SESSION_ID: String);
+
+    fn instance(port: u16) -> Arc<Instance> {
+        Arc::new(Instance {
+            address: addr(port),
+            weight: 1,
+            tags: Default::default(),
+        })
+    }
+
+    fn addr(port: u16) -> Address {
+        Address::from(std::net::SocketAddr::from(([127, 0, 0, 1], port)))
+    }
+
+    fn endpoint(key: Option<&'static str>) -> Endpoint {
+        let mut endpoint = Endpoint::new("item".into());
+        if let Some(key) = key {
+            endpoint.insert_faststr::<AffinityKey>(key.into());
+        }
+        endpoint
+    }
+
+    async fn pick(lb: &Sticky, discover: &StaticDiscover, key: Option<&'static str>) -> Address {
+        lb.get_picker(&endpoint(key), discover)
+            .await
+            .unwrap()
+            .next()
+            .unwrap()
+    }
+
+    fn change(ports: &[u16], removed: &[u16]) -> Change<()> {
+        Change {
+            key: (),
+            all: ports.iter().map(|p| instance(*p)).collect(),
+            added: vec![],
+            updated: vec![],
+            removed: removed.iter().map(|p| instance(*p)).collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn keep_instance_until_removed() {
+        let discover = StaticDiscover::new(vec![instance(1), instance(2), instance(3)]);
+        let lb = Sticky::new(WeightedRoundRobin::new());
+
+        let kept = pick(&lb, &discover, Some("session")).await;
+        for _ in 0..5 {
+            assert_eq!(pick(&lb, &discover, Some("session")).await, kept);
+        }
+        // the requests without the key are still balanced
+        assert_ne!(
+            pick(&lb, &discover, None).await,
+            pick(&lb, &discover, None).await
+        );
+
+        // the picker returns the others for retrying
+        let picker = lb
+            .get_picker(&endpoint(Some("session")), &discover)
+            .await
+            .unwrap();
+        let picked = picker.collect::<Vec<_>>();
+        assert_eq!(picked.len(), 3);
+        assert_eq!(picked[0], kept);
+
+        // the instance is removed in the middle of the session
+        let remaining = [1, 2, 3]
+            .into_iter()
+            .filter(|p| addr(*p) != kept)
+            .collect::<Vec<_>>();
+        let removed = [1, 2, 3]
+            .into_iter()
+            .filter(|p| addr(*p) == kept)
+            .collect::<Vec<_>>();
+        LoadBalance::<StaticDiscover>::rebalance(&lb, change(&remaining, &removed));
+        let next = pick(&lb, &discover, Some("session")).await;
+        assert_ne!(next, kept);
+        for _ in 0..5 {
+            assert_eq!(pick(&lb, &discover, Some("session")).await, next);
+        }
+
+        lb.invalidate("session");
+        assert!(lb.is_empty());
+    }
+
+    #[tokio::test]
+    async fn balance_again_when_ejected() {
+        let discover = StaticDiscover::new(vec![instance(1), instance(2)]);
+        let outlier = Arc::new(OutlierDetector::new(OutlierDetection::default()));
+        let lb = Sticky::new(WeightedRoundRobin::new()).outlier_detector(outlier.clone());
+
+        let kept = pick(&lb, &discover, Some("session")).await;
+        outlier.eject(&kept);
+        let next = pick(&lb, &discover, Some("session")).await;
+        assert_ne!(next, kept);
+        assert_eq!(pick(&lb, &discover, Some("session")).await, next);
+    }
+
+    #[tokio::test]
+    async fn evict_least_recently_used() {
+        let discover = StaticDiscover::new(vec![instance(1), instance(2), instance(3)]);
+        let lb = Sticky::new(WeightedRoundRobin::new()).capacity(2);
+
+        let a = pick(&lb, &discover, Some("a")).await;
+        pick(&lb, &discover, Some("b")).await;
+        // `a` is used after `b`, so `b` is evicted by `c`
+        assert_eq!(pick(&lb, &discover, Some("a")).await, a);
+        pick(&lb, &discover, Some("c")).await;
+        assert_eq!(lb.len(), 2);
+        let sessions = lb.sessions.lock().unwrap();
+        assert!(sessions.entries.contains_key(&((), "a".into())));
+        assert!(!sessions.entries.contains_key(&((), "b".into())));
+        assert!(sessions.entries.contains_key(&((), "c".into())));
+    }
+
+    #[tokio::test]
+    async fn expire_and_metainfo_key() {
+        let discover = StaticDiscover::new(vec![instance(1), instance(2)]);
+        let lb = Sticky::new(WeightedRoundRobin::new())
+            .ttl(Duration::ZERO)
+            .metainfo_key::<SESSION_ID>();
+
+        let mut mi = MetaInfo::new();
+        mi.set_persistent("SESSION_ID", "session");
+        METAINFO
+            .scope(RefCell::new(mi), async {
+                let first = pick(&lb, &discover, None).await;
+                assert_eq!(lb.len(), 1);
+                // expired immediately, so it's balanced again by the round-robin
+                assert_ne!(pick(&lb, &discover, None).await, first);
+            })
+            .await;
+
+        // the typed value set in the process
+        let lb = Sticky::new(WeightedRoundRobin::new()).metainfo_key::<SESSION_ID>();
+        let mut mi = MetaInfo::new();
+        SESSION_ID::set_in("typed".to_owned(), &mut mi);
+        METAINFO
+            .scope(RefCell::new(mi), async {
+                let first = pick(&lb, &discover, None).await;
+                assert_eq!(pick(&lb, &discover, None).await, first);
+                let sessions = lb.sessions.lock().unwrap();
+                assert!(sessions.entries.contains_key(&((), "typed".into())));
+            })
+            .await;
+    }
+}
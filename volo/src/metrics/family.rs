@@ -0,0 +1,197 @@
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc,
+};
+
+use dashmap::{mapref::entry::Entry, DashMap};
+use faststr::FastStr;
+
+use super::{Desc, Metric, Source, Visitor, OTHER};
+
+/// A set of metrics with the same name, distinguished by the values of the labels.
+///
+/// The number of the series is bounded by [`Registry::max_series`](super::Registry::max_series),
+/// and the label values exceeding the limit are aggregated into a series with all the labels set
+/// to [`OTHER`].
+///
+/// Looking up a series allocates, so the metrics on the hot path should be looked up once and
+/// kept, e.g. `let hello = requests.with(&["hello"]);`.
+pub struct Family<M> {
+    inner: Arc<Inner<M>>,
+}
+
+struct Inner<M> {
+    desc: Desc,
+    label_names: Box<[FastStr]>,
+    series: DashMap<Box<[FastStr]>, Arc<M>>,
+    count: AtomicUsize,
+    max_series: usize,
+    other: Arc<M>,
+    overflowed: AtomicBool,
+    make: Box<dyn Fn() -> M + Send + Sync>,
+}
+
+impl<M> Clone for Family<M> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<M: Metric> Family<M> {
+    pub(super) fn new(
+        desc: Desc,
+        label_names: &[&str],
+        max_series: usize,
+        make: impl Fn() -> M + Send + Sync + 'static,
+    ) -> Self {
+        for name in label_names {
+            assert!(
+                super::is_valid_label_name(name),
+                "invalid label name `{name}` of metric `{}`",
+                desc.name
+            );
+        }
+        Self {
+            inner: Arc::new(Inner {
+                desc,
+                label_names: label_names.iter().map(FastStr::new).collect(),
+                series: DashMap::new(),
+                count: AtomicUsize::new(0),
+                max_series,
+                other: Arc::new(make()),
+                overflowed: AtomicBool::new(false),
+                make: Box::new(make),
+            }),
+        }
+    }
+
+    /// Returns the metric of the label values, which are in the order of the label names.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of the values doesn't match the label names.
+    pub fn with(&self, values: &[&str]) -> Arc<M> {
+        let inner = &self.inner;
+        assert_eq!(
+            values.len(),
+            inner.label_names.len(),
+            "wrong number of label values of metric `{}`",
+            inner.desc.name
+        );
+        let key: Box<[FastStr]> = values.iter().map(FastStr::new).collect();
+        if let Some(metric) = inner.series.get(&key) {
+            return metric.clone();
+        }
+        // Reserve the room first, so that the size is strictly bounded under contention.
+        if inner.count.fetch_add(1, Ordering::Relaxed) >= inner.max_series {
+            inner.count.fetch_sub(1, Ordering::Relaxed);
+            if !inner.overflowed.swap(true, Ordering::Relaxed) {
+                tracing::warn!(
+                    "[VOLO] metric `{}` exceeds {} series, the others are aggregated into `{}`",
+                    inner.desc.name,
+                    inner.max_series,
+                    OTHER
+                );
+            }
+            return inner.other.clone();
+        }
+        match inner.series.entry(key) {
+            Entry::Occupied(e) => {
+                inner.count.fetch_sub(1, Ordering::Relaxed);
+                e.get().clone()
+            }
+            Entry::Vacant(e) => e.insert(Arc::new((inner.make)())).clone(),
+        }
+    }
+
+    pub fn desc(&self) -> &Desc {
+        &self.inner.desc
+    }
+}
+
+impl<M: Metric> Source for Family<M> {
+    fn collect(&self, visitor: &mut dyn Visitor) {
+        let inner = &self.inner;
+        let mut series = inner
+            .series
+            .iter()
+            .map(|e| (e.key().clone(), e.value().clone()))
+            .collect::<Vec<_>>();
+        series.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut visit = |values: &[&str], metric: &M| {
+            let labels = inner
+                .label_names
+                .iter()
+                .map(|n| n.as_str())
+                .zip(values.iter().copied())
+                .collect::<Vec<_>>();
+            metric.sample(&mut |value| visitor.visit(&inner.desc, &labels, value));
+        };
+        for (values, metric) in series.iter() {
+            let values = values.iter().map(|v| v.as_str()).collect::<Vec<_>>();
+            visit(&values, metric);
+        }
+        if inner.overflowed.load(Ordering::Relaxed) {
+            visit(&vec![OTHER; inner.label_names.len()], &inner.other);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::{Counter, Value};
+
+    #[derive(Default)]
+    struct Collected(Vec<(String, u64)>);
+
+    impl Visitor for Collected {
+        fn visit(&mut self, _: &Desc, labels: &[(&str, &str)], value: Value<'_>) {
+            let Value::Counter(v) = value else {
+                unreachable!()
+            };
+            self.0.push((labels[0].1.to_owned(), v));
+        }
+    }
+
+    #[test]
+    fn cardinality_guard() {
+        let family = Family::new(
+            Desc::new("requests_total", ""),
+            &["method"],
+            2,
+            Counter::new,
+        );
+        family.with(&["b"]).inc();
+        family.with(&["a"]).add(2);
+        family.with(&["c"]).inc();
+        family.with(&["d"]).inc();
+        family.with(&["a"]).inc();
+
+        let mut collected = Collected::default();
+        family.collect(&mut collected);
+        assert_eq!(
+            collected.0,
+            vec![
+                ("a".to_owned(), 3),
+                ("b".to_owned(), 1),
+                (OTHER.to_owned(), 2)
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn wrong_label_values() {
+        let family = Family::new(
+            Desc::new("requests_total", ""),
+            &["method"],
+            2,
+            Counter::new,
+        );
+        family.with(&["a", "b"]);
+    }
+}
@@ -0,0 +1,292 @@
+use std::{
+    sync::{
+        atomic::{AtomicI64, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// The number of shards of a [`Counter`] or a [`Histogram`].
+const SHARDS: usize = 16;
+
+/// The default buckets of a [`Histogram`], in seconds.
+pub const DEFAULT_BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Returns the shard of the current thread, the threads are assigned to the shards in turn.
+fn shard() -> usize {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    thread_local! {
+        static SHARD: usize = NEXT.fetch_add(1, Ordering::Relaxed) % SHARDS;
+    }
+    SHARD.with(|shard| *shard)
+}
+
+/// Aligned to the cache line, so that the shards updated by different threads never share a line.
+#[derive(Debug, Default)]
+#[repr(align(128))]
+struct Padded<T>(T);
+
+/// The value of a metric passed to a [`Visitor`](super::Visitor).
+#[derive(Debug, Clone, Copy)]
+pub enum Value<'a> {
+    Counter(u64),
+    Gauge(f64),
+    Histogram(&'a HistogramSnapshot),
+}
+
+/// A metric which can be put into a [`Family`](super::Family).
+pub trait Metric: Send + Sync + 'static {
+    /// Reads the current value of the metric.
+    fn sample(&self, f: &mut dyn FnMut(Value<'_>));
+}
+
+/// A monotonically increasing counter.
+///
+/// The counter is split into shards by threads, so the increments from different threads don't
+/// contend with each other.
+#[derive(Debug)]
+pub struct Counter {
+    shards: [Padded<AtomicU64>; SHARDS],
+}
+
+impl Default for Counter {
+    fn default() -> Self {
+        Self {
+            shards: std::array::from_fn(|_| Padded::default()),
+        }
+    }
+}
+
+impl Counter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    #[inline]
+    pub fn add(&self, n: u64) {
+        self.shards[shard()].0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.shards
+            .iter()
+            .map(|shard| shard.0.load(Ordering::Relaxed))
+            .sum()
+    }
+}
+
+impl Metric for Counter {
+    fn sample(&self, f: &mut dyn FnMut(Value<'_>)) {
+        f(Value::Counter(self.get()))
+    }
+}
+
+/// A value which can go up and down.
+///
+/// Unlike [`Counter`], a gauge is not sharded since it can be set.
+#[derive(Debug, Default)]
+pub struct Gauge {
+    value: AtomicI64,
+}
+
+impl Gauge {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn set(&self, value: i64) {
+        self.value.store(value, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    #[inline]
+    pub fn dec(&self) {
+        self.sub(1);
+    }
+
+    #[inline]
+    pub fn add(&self, n: i64) {
+        self.value.fetch_add(n, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn sub(&self, n: i64) {
+        self.value.fetch_sub(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+impl Metric for Gauge {
+    fn sample(&self, f: &mut dyn FnMut(Value<'_>)) {
+        f(Value::Gauge(self.get() as f64))
+    }
+}
+
+/// A histogram with fixed buckets.
+///
+/// Like [`Counter`], the histogram is split into shards by threads.
+#[derive(Debug)]
+pub struct Histogram {
+    /// The upper bounds of the buckets, sorted in ascending order, without `+Inf`.
+    bounds: Arc<[f64]>,
+    shards: Box<[Padded<HistogramShard>]>,
+}
+
+#[derive(Debug)]
+struct HistogramShard {
+    /// The non-cumulative count of each bucket, the last one is for `+Inf`.
+    buckets: Box<[AtomicU64]>,
+    /// The bits of the `f64` sum.
+    sum: AtomicU64,
+}
+
+impl Histogram {
+    /// Creates a histogram with the upper bounds of the buckets.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bounds are not finite or not sorted in strictly ascending order.
+    pub fn new(bounds: impl Into<Arc<[f64]>>) -> Self {
+        let bounds = bounds.into();
+        check_bounds(&bounds);
+        let shards = (0..SHARDS)
+            .map(|_| {
+                Padded(HistogramShard {
+                    buckets: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+                    sum: AtomicU64::new(0f64.to_bits()),
+                })
+            })
+            .collect();
+        Self { bounds, shards }
+    }
+
+    #[inline]
+    pub fn observe(&self, value: f64) {
+        let shard = &self.shards[shard()].0;
+        let bucket = self.bounds.partition_point(|bound| *bound < value);
+        shard.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        let _ = shard
+            .sum
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |sum| {
+                Some((f64::from_bits(sum) + value).to_bits())
+            });
+    }
+
+    /// Observes the duration in seconds.
+    #[inline]
+    pub fn observe_duration(&self, duration: Duration) {
+        self.observe(duration.as_secs_f64());
+    }
+
+    /// Reads the buckets without blocking the writers, so the snapshot may be slightly
+    /// inconsistent with the sum under concurrent updates.
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        let mut counts = vec![0u64; self.bounds.len() + 1];
+        let mut sum = 0f64;
+        for shard in self.shards.iter() {
+            for (count, bucket) in counts.iter_mut().zip(shard.0.buckets.iter()) {
+                *count += bucket.load(Ordering::Relaxed);
+            }
+            sum += f64::from_bits(shard.0.sum.load(Ordering::Relaxed));
+        }
+
+        let mut cumulative = 0;
+        let mut buckets = Vec::with_capacity(self.bounds.len());
+        for (bound, count) in self.bounds.iter().zip(counts.iter()) {
+            cumulative += count;
+            buckets.push((*bound, cumulative));
+        }
+        HistogramSnapshot {
+            buckets,
+            count: cumulative + counts[self.bounds.len()],
+            sum,
+        }
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new(DEFAULT_BUCKETS)
+    }
+}
+
+impl Metric for Histogram {
+    fn sample(&self, f: &mut dyn FnMut(Value<'_>)) {
+        f(Value::Histogram(&self.snapshot()))
+    }
+}
+
+pub(super) fn check_bounds(bounds: &[f64]) {
+    assert!(
+        bounds.iter().all(|bound| bound.is_finite()) && bounds.windows(2).all(|w| w[0] < w[1]),
+        "the bounds of the histogram buckets must be finite and strictly ascending"
+    );
+}
+
+/// A snapshot of a [`Histogram`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistogramSnapshot {
+    /// The upper bound and the cumulative count of each bucket, without `+Inf`.
+    pub buckets: Vec<(f64, u64)>,
+    /// The count of all the observations, i.e. the count of the `+Inf` bucket.
+    pub count: u64,
+    pub sum: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counter() {
+        let counter = Arc::new(Counter::new());
+        let threads = (0..8)
+            .map(|_| {
+                let counter = counter.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..10_000 {
+                        counter.inc();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        counter.add(5);
+        assert_eq!(counter.get(), 80_005);
+    }
+
+    #[test]
+    fn histogram() {
+        let histogram = Histogram::new([0.1, 1.0]);
+        for value in [0.05, 0.1, 0.5, 2.0] {
+            histogram.observe(value);
+        }
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.buckets, vec![(0.1, 2), (1.0, 3)]);
+        assert_eq!(snapshot.count, 4);
+        assert!((snapshot.sum - 2.65).abs() < 1e-9);
+    }
+
+    #[test]
+    #[should_panic]
+    fn unsorted_bounds() {
+        Histogram::new([1.0, 0.1]);
+    }
+}
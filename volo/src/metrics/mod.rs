@@ -0,0 +1,228 @@
+//! A lightweight metrics facade with the Prometheus text exposition.
+//!
+//! The metrics are registered in a [`Registry`] as [`Counter`]s, [`Gauge`]s and [`Histogram`]s,
+//! optionally with labels by [`Family`]. The updates on the hot path are lock-free, and the
+//! counters and histograms are sharded by threads to avoid the contention.
+//!
+//! The registry can be rendered in the Prometheus text format by [`Registry::render`], which
+//! reads the metrics without pausing the writers, for mounting on an admin router, or served on a
+//! standalone listener by [`serve`].
+//!
+//! Other components expose their metrics by implementing [`Source`], which can be registered into
+//! a [`Registry`] by [`Registry::register`], or collected into any other metrics system by
+//! implementing a [`Visitor`] for it.
+//!
+//! The standard metrics of the RPC servers and clients are recorded by the layer in [`rpc`].
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use volo::metrics::Registry;
+//!
+//! let registry = Registry::new();
+//! let requests = registry.counter_vec("app_requests_total", "The requests.", &["method"]);
+//! let hello = requests.with(&["hello"]);
+//! hello.inc();
+//!
+//! // on a standalone listener
+//! tokio::spawn(volo::metrics::serve(registry.clone(), "[::]:9090".parse().unwrap()));
+//!
+//! // or on the router of volo-http
+//! let router = Router::new().route(
+//!     "/metrics",
+//!     get(move || {
+//!         let registry = registry.clone();
+//!         async move { registry.render() }
+//!     }),
+//! );
+//! ```
+
+mod family;
+mod metric;
+pub mod rpc;
+mod text;
+
+use std::sync::{Arc, RwLock};
+
+use faststr::FastStr;
+
+pub use self::{
+    family::Family,
+    metric::{Counter, Gauge, Histogram, HistogramSnapshot, Metric, Value, DEFAULT_BUCKETS},
+    text::{serve, serve_with_listener, TextEncoder, CONTENT_TYPE},
+};
+
+/// The label value of the aggregated series exceeding the cardinality limit.
+pub const OTHER: &str = "<other>";
+
+const DEFAULT_MAX_SERIES: usize = 1000;
+
+/// The name and the help of a metric.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Desc {
+    pub name: FastStr,
+    pub help: FastStr,
+}
+
+impl Desc {
+    /// # Panics
+    ///
+    /// Panics if the name is not a valid Prometheus metric name.
+    pub fn new(name: impl Into<FastStr>, help: impl Into<FastStr>) -> Self {
+        let name = name.into();
+        assert!(is_valid_metric_name(&name), "invalid metric name `{name}`");
+        Self {
+            name,
+            help: help.into(),
+        }
+    }
+}
+
+/// Receives the samples collected from a [`Source`].
+pub trait Visitor {
+    /// Visits a sample of the metric with the labels.
+    fn visit(&mut self, desc: &Desc, labels: &[(&str, &str)], value: Value<'_>);
+}
+
+/// A source of metrics.
+pub trait Source: Send + Sync {
+    /// Visits all the samples, where the samples of the same metric must be visited contiguously.
+    fn collect(&self, visitor: &mut dyn Visitor);
+}
+
+impl<T: Source + ?Sized> Source for Arc<T> {
+    fn collect(&self, visitor: &mut dyn Visitor) {
+        (**self).collect(visitor)
+    }
+}
+
+/// A set of metric sources, which can be cloned and shared cheaply.
+#[derive(Clone)]
+pub struct Registry {
+    sources: Arc<RwLock<Vec<Arc<dyn Source>>>>,
+    max_series: usize,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self {
+            sources: Default::default(),
+            max_series: DEFAULT_MAX_SERIES,
+        }
+    }
+
+    /// Sets the maximum number of the series of each [`Family`] created by the registry
+    /// afterwards.
+    ///
+    /// Default is `1000`.
+    pub fn max_series(mut self, max: usize) -> Self {
+        self.max_series = max;
+        self
+    }
+
+    /// Registers a source, whose metrics will be collected with the registry.
+    pub fn register(&self, source: impl Source + 'static) {
+        self.sources.write().unwrap().push(Arc::new(source));
+    }
+
+    pub fn counter(&self, name: impl Into<FastStr>, help: impl Into<FastStr>) -> Arc<Counter> {
+        self.counter_vec(name, help, &[]).with(&[])
+    }
+
+    pub fn counter_vec(
+        &self,
+        name: impl Into<FastStr>,
+        help: impl Into<FastStr>,
+        labels: &[&str],
+    ) -> Family<Counter> {
+        self.family(Desc::new(name, help), labels, Counter::new)
+    }
+
+    pub fn gauge(&self, name: impl Into<FastStr>, help: impl Into<FastStr>) -> Arc<Gauge> {
+        self.gauge_vec(name, help, &[]).with(&[])
+    }
+
+    pub fn gauge_vec(
+        &self,
+        name: impl Into<FastStr>,
+        help: impl Into<FastStr>,
+        labels: &[&str],
+    ) -> Family<Gauge> {
+        self.family(Desc::new(name, help), labels, Gauge::new)
+    }
+
+    /// Registers a histogram with the upper bounds of the buckets, e.g. [`DEFAULT_BUCKETS`].
+    pub fn histogram(
+        &self,
+        name: impl Into<FastStr>,
+        help: impl Into<FastStr>,
+        bounds: &[f64],
+    ) -> Arc<Histogram> {
+        self.histogram_vec(name, help, &[], bounds).with(&[])
+    }
+
+    pub fn histogram_vec(
+        &self,
+        name: impl Into<FastStr>,
+        help: impl Into<FastStr>,
+        labels: &[&str],
+        bounds: &[f64],
+    ) -> Family<Histogram> {
+        assert!(
+            !labels.contains(&"le"),
+            "the label `le` is reserved for the histogram buckets"
+        );
+        metric::check_bounds(bounds);
+        let bounds: Arc<[f64]> = bounds.into();
+        self.family(Desc::new(name, help), labels, move || {
+            Histogram::new(bounds.clone())
+        })
+    }
+
+    fn family<M: Metric>(
+        &self,
+        desc: Desc,
+        labels: &[&str],
+        make: impl Fn() -> M + Send + Sync + 'static,
+    ) -> Family<M> {
+        let family = Family::new(desc, labels, self.max_series, make);
+        self.register(family.clone());
+        family
+    }
+
+    /// Renders all the metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut encoder = TextEncoder::new();
+        self.collect(&mut encoder);
+        encoder.finish()
+    }
+}
+
+impl Source for Registry {
+    fn collect(&self, visitor: &mut dyn Visitor) {
+        // clone the sources so that registering is not blocked by collecting
+        let sources = self.sources.read().unwrap().clone();
+        for source in sources {
+            source.collect(visitor);
+        }
+    }
+}
+
+fn is_valid_metric_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == ':')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+}
+
+fn is_valid_label_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !name.starts_with("__")
+}
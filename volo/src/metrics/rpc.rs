@@ -0,0 +1,557 @@
+//! The standard metrics of the RPC servers and clients, recorded by the [`RpcMetricsLayer`].
+//!
+//! | Name | Type | Labels |
+//! | --- | --- | --- |
+//! | `rpc_{side}_requests_total` | counter | `service`, `method`, `caller`, `callee`, `status` |
+//! | `rpc_{side}_errors_total` | counter | `service`, `method`, `caller`, `callee`, `status` |
+//! | `rpc_{side}_in_flight_requests` | gauge | `service`, `method`, `caller`, `callee` |
+//! | `rpc_{side}_duration_seconds` | histogram | `service`, `method`, `caller`, `callee`, `status` |
+//! | `rpc_server_handle_duration_seconds` | histogram | `service`, `method`, `caller`, `callee`, `status` |
+//! | `rpc_{side}_request_size_bytes` | histogram | `service`, `method`, `caller`, `callee` |
+//! | `rpc_{side}_response_size_bytes` | histogram | `service`, `method`, `caller`, `callee` |
+//!
+//! where `{side}` is `server` or `client`. At the server side, the handle duration is the time
+//! spent in the layers after it, and the duration is measured from the arrival of the request if
+//! the protocol knows it, e.g. by the timestamps in the stats of the context.
+//!
+//! The `method` label comes from the [`MethodDescriptor`]s of the registered methods, never from
+//! the requests, so a server must tell the layer the methods by [`RpcMetricsLayer::methods`] or a
+//! [`DescriptorLayer`] before it, otherwise the method is labeled [`OTHER`]. The `status` label is
+//! one of a bounded set decided by the protocol, e.g. the gRPC codes. The other labels are bounded
+//! by [`Registry::max_series`](super::Registry::max_series) as usual.
+//!
+//! The protocols are adapted by [`RpcMetricsProtocol`], e.g. `volo_thrift::metrics::ThriftMetrics`
+//! and `volo_grpc::layer::metrics::GrpcMetrics`.
+//!
+//! ```rust,ignore
+//! use volo::metrics::{
+//!     rpc::{RpcMetrics, RpcMetricsLayer},
+//!     Registry,
+//! };
+//!
+//! let registry = Registry::new();
+//! let metrics = RpcMetrics::server(&registry);
+//! Server::new()
+//!     .layer_front(RpcMetricsLayer::new(metrics, GrpcMetrics).methods(volo_gen::registry().methods()))
+//!     .add_service(ServiceBuilder::new(GreeterServer::new(S)).build())
+//!     .run(addr)
+//!     .await
+//!     .unwrap();
+//! ```
+//!
+//! [`DescriptorLayer`]: crate::descriptor::DescriptorLayer
+
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Instant, SystemTime},
+};
+
+use faststr::FastStr;
+use motore::{layer::Layer, service::Service};
+
+use super::{Counter, Family, Gauge, Histogram, Registry, DEFAULT_BUCKETS, OTHER};
+use crate::{
+    context::{Context, Role},
+    descriptor::{MethodDescriptor, MethodDescriptorExt},
+};
+
+/// The upper bounds of the buckets of the size histograms in bytes, from 64B to 16MiB.
+pub const SIZE_BUCKETS: &[f64] = &[
+    64.0, 256.0, 1024.0, 4096.0, 16384.0, 65536.0, 262144.0, 1048576.0, 4194304.0, 16777216.0,
+];
+
+const LABELS: &[&str] = &["service", "method", "caller", "callee"];
+const STATUS_LABELS: &[&str] = &["service", "method", "caller", "callee", "status"];
+
+/// Adapts the [`RpcMetricsLayer`] to a protocol.
+pub trait RpcMetricsProtocol<Cx, Resp, E>: Send + Sync {
+    /// The `status` label of the result, which must be one of a bounded set, e.g. the names of
+    /// the gRPC codes.
+    fn status(&self, cx: &Cx, result: &Result<Resp, E>) -> FastStr;
+
+    /// When the request arrived at the server, from which the duration is measured.
+    fn arrive_at(&self, cx: &Cx) -> Option<SystemTime> {
+        let _ = cx;
+        None
+    }
+
+    /// The size of the request already received before the layer, i.e. at the server side.
+    ///
+    /// The sizes known later are observed by the [`MessageSizes`] in the context.
+    fn received_request_size(&self, cx: &Cx) -> Option<usize> {
+        let _ = cx;
+        None
+    }
+}
+
+/// The metrics of the servers or the clients registered in a [`Registry`], see the
+/// [module docs](self).
+///
+/// They should be registered once for each side in a registry, and the clones share the same
+/// metrics.
+#[derive(Clone)]
+pub struct RpcMetrics {
+    requests: Family<Counter>,
+    errors: Family<Counter>,
+    in_flight: Family<Gauge>,
+    duration: Family<Histogram>,
+    handle_duration: Option<Family<Histogram>>,
+    request_size: Family<Histogram>,
+    response_size: Family<Histogram>,
+}
+
+impl RpcMetrics {
+    /// Registers the metrics of the servers, named `rpc_server_*`.
+    pub fn server(registry: &Registry) -> Self {
+        let mut metrics = Self::new(registry, "server");
+        metrics.handle_duration = Some(registry.histogram_vec(
+            "rpc_server_handle_duration_seconds",
+            "The time spent in handling the requests.",
+            STATUS_LABELS,
+            DEFAULT_BUCKETS,
+        ));
+        metrics
+    }
+
+    /// Registers the metrics of the clients, named `rpc_client_*`.
+    pub fn client(registry: &Registry) -> Self {
+        Self::new(registry, "client")
+    }
+
+    fn new(registry: &Registry, side: &str) -> Self {
+        Self {
+            requests: registry.counter_vec(
+                format!("rpc_{side}_requests_total"),
+                "The finished requests.",
+                STATUS_LABELS,
+            ),
+            errors: registry.counter_vec(
+                format!("rpc_{side}_errors_total"),
+                "The failed requests.",
+                STATUS_LABELS,
+            ),
+            in_flight: registry.gauge_vec(
+                format!("rpc_{side}_in_flight_requests"),
+                "The requests being processed.",
+                LABELS,
+            ),
+            duration: registry.histogram_vec(
+                format!("rpc_{side}_duration_seconds"),
+                "The total time of the requests.",
+                STATUS_LABELS,
+                DEFAULT_BUCKETS,
+            ),
+            handle_duration: None,
+            request_size: registry.histogram_vec(
+                format!("rpc_{side}_request_size_bytes"),
+                "The sizes of the request messages.",
+                LABELS,
+                SIZE_BUCKETS,
+            ),
+            response_size: registry.histogram_vec(
+                format!("rpc_{side}_response_size_bytes"),
+                "The sizes of the response messages.",
+                LABELS,
+                SIZE_BUCKETS,
+            ),
+        }
+    }
+}
+
+/// The size histograms of a request, inserted into the extensions of the context by the
+/// [`RpcMetricsLayer`], where the transports observe the sizes of the messages once known.
+#[derive(Debug, Clone)]
+pub struct MessageSizes {
+    request: Arc<Histogram>,
+    response: Arc<Histogram>,
+}
+
+impl MessageSizes {
+    pub fn observe_request(&self, size: usize) {
+        self.request.observe(size as f64);
+    }
+
+    pub fn observe_response(&self, size: usize) {
+        self.response.observe(size as f64);
+    }
+
+    /// Observes the size of a message sent by the side of the role.
+    pub fn observe_sent(&self, role: Role, size: usize) {
+        match role {
+            Role::Client => self.observe_request(size),
+            Role::Server => self.observe_response(size),
+        }
+    }
+
+    /// Observes the size of a message received by the side of the role.
+    pub fn observe_received(&self, role: Role, size: usize) {
+        match role {
+            Role::Client => self.observe_response(size),
+            Role::Server => self.observe_request(size),
+        }
+    }
+}
+
+/// A layer that records the [`RpcMetrics`] of each request, see the [module docs](self).
+///
+/// It should be the outermost layer, e.g. added by `layer_front` of the servers or `layer_outer`
+/// of the clients, so the time covers the other layers.
+#[derive(Clone)]
+pub struct RpcMetricsLayer<P> {
+    metrics: RpcMetrics,
+    protocol: P,
+    methods: Option<Arc<HashMap<&'static str, &'static MethodDescriptor>>>,
+}
+
+impl<P> RpcMetricsLayer<P> {
+    pub fn new(metrics: RpcMetrics, protocol: P) -> Self {
+        Self {
+            metrics,
+            protocol,
+            methods: None,
+        }
+    }
+
+    /// Sets the registered methods, e.g. `volo_gen::registry().methods()`, by which the `method`
+    /// label is decided at the server side.
+    ///
+    /// It's unnecessary if there is a [`DescriptorLayer`](crate::descriptor::DescriptorLayer)
+    /// before.
+    pub fn methods(mut self, methods: impl IntoIterator<Item = &'static MethodDescriptor>) -> Self {
+        self.methods = Some(Arc::new(methods.into_iter().map(|m| (m.path, m)).collect()));
+        self
+    }
+}
+
+impl<S, P> Layer<S> for RpcMetricsLayer<P> {
+    type Service = RpcMetricsService<S, P>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        RpcMetricsService {
+            inner,
+            metrics: self.metrics,
+            protocol: Arc::new(self.protocol),
+            methods: self.methods,
+        }
+    }
+}
+
+/// The service created by [`RpcMetricsLayer`].
+pub struct RpcMetricsService<S, P> {
+    inner: S,
+    metrics: RpcMetrics,
+    protocol: Arc<P>,
+    methods: Option<Arc<HashMap<&'static str, &'static MethodDescriptor>>>,
+}
+
+impl<S: Clone, P> Clone for RpcMetricsService<S, P> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            metrics: self.metrics.clone(),
+            protocol: self.protocol.clone(),
+            methods: self.methods.clone(),
+        }
+    }
+}
+
+impl<S, P> RpcMetricsService<S, P> {
+    /// The `service` and `method` labels of the request.
+    fn method<Cx: Context>(&self, cx: &Cx) -> (FastStr, FastStr) {
+        let descriptor = cx.method_descriptor().or_else(|| {
+            self.methods
+                .as_ref()?
+                .get(cx.rpc_info().method().as_str())
+                .copied()
+        });
+        match descriptor {
+            Some(descriptor) => (
+                FastStr::from_static_str(descriptor.service),
+                FastStr::from_static_str(descriptor.path),
+            ),
+            // the methods of the clients are the ones of the generated code
+            None if cx.rpc_info().role() == Role::Client => (
+                cx.rpc_info().callee().service_name(),
+                cx.rpc_info().method().clone(),
+            ),
+            None => (
+                FastStr::from_static_str(OTHER),
+                FastStr::from_static_str(OTHER),
+            ),
+        }
+    }
+}
+
+/// Decreases the in-flight gauge when the request is finished or cancelled.
+struct InFlight(Arc<Gauge>);
+
+impl InFlight {
+    fn new(gauge: Arc<Gauge>) -> Self {
+        gauge.inc();
+        Self(gauge)
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+impl<Cx, Req, S, P> Service<Cx, Req> for RpcMetricsService<S, P>
+where
+    Cx: Context + Send + 'static,
+    Req: Send + 'static,
+    S: Service<Cx, Req> + Send + Sync + 'static,
+    P: RpcMetricsProtocol<Cx, S::Response, S::Error>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call<'s, 'cx>(
+        &'s self,
+        cx: &'cx mut Cx,
+        req: Req,
+    ) -> Result<Self::Response, Self::Error> {
+        let start = Instant::now();
+        let (service, method) = self.method(cx);
+        let caller = cx.rpc_info().caller().service_name();
+        let callee = cx.rpc_info().callee().service_name();
+        let labels = [
+            service.as_str(),
+            method.as_str(),
+            caller.as_str(),
+            callee.as_str(),
+        ];
+
+        let metrics = &self.metrics;
+        let in_flight = InFlight::new(metrics.in_flight.with(&labels));
+        let sizes = MessageSizes {
+            request: metrics.request_size.with(&labels),
+            response: metrics.response_size.with(&labels),
+        };
+        if let Some(size) = self.protocol.received_request_size(cx) {
+            sizes.observe_request(size);
+        }
+        cx.extensions_mut().insert(sizes);
+
+        let result = self.inner.call(cx, req).await;
+        let elapsed = start.elapsed();
+        drop(in_flight);
+
+        let status = self.protocol.status(cx, &result);
+        let labels = [
+            service.as_str(),
+            method.as_str(),
+            caller.as_str(),
+            callee.as_str(),
+            status.as_str(),
+        ];
+        metrics.requests.with(&labels).inc();
+        if result.is_err() {
+            metrics.errors.with(&labels).inc();
+        }
+        let duration = match &metrics.handle_duration {
+            Some(handle_duration) => {
+                handle_duration.with(&labels).observe_duration(elapsed);
+                self.protocol
+                    .arrive_at(cx)
+                    .and_then(|t| SystemTime::now().duration_since(t).ok())
+                    .map_or(elapsed, |total| total.max(elapsed))
+            }
+            None => elapsed,
+        };
+        metrics.duration.with(&labels).observe_duration(duration);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use prometheus_parse::{Scrape, Value};
+
+    use super::*;
+    use crate::context::{Endpoint, Reusable, RpcCx, RpcInfo};
+
+    #[derive(Debug, Default)]
+    struct Config;
+
+    impl Reusable for Config {
+        fn clear(&mut self) {}
+    }
+
+    type Cx = RpcCx<Option<SystemTime>, Config>;
+
+    fn cx(role: Role, method: &'static str, arrive_at: Option<SystemTime>) -> Cx {
+        RpcCx::new(
+            RpcInfo::new(
+                role,
+                method.into(),
+                Endpoint::new("gateway".into()),
+                Endpoint::new("item".into()),
+                Config,
+            ),
+            arrive_at,
+        )
+    }
+
+    /// The status is the error message, and the request is 16 bytes.
+    struct Protocol;
+
+    impl RpcMetricsProtocol<Cx, (), &'static str> for Protocol {
+        fn status(&self, _cx: &Cx, result: &Result<(), &'static str>) -> FastStr {
+            FastStr::from_static_str(result.as_ref().err().copied().unwrap_or("ok"))
+        }
+
+        fn arrive_at(&self, cx: &Cx) -> Option<SystemTime> {
+            cx.inner
+        }
+
+        fn received_request_size(&self, cx: &Cx) -> Option<usize> {
+            (cx.rpc_info().role() == Role::Server).then_some(16)
+        }
+    }
+
+    /// Sends a message of 32 bytes.
+    #[derive(Clone)]
+    struct Handler;
+
+    impl Service<Cx, Result<(), &'static str>> for Handler {
+        type Response = ();
+        type Error = &'static str;
+
+        async fn call(
+            &self,
+            cx: &mut Cx,
+            req: Result<(), &'static str>,
+        ) -> Result<Self::Response, Self::Error> {
+            let sizes = cx.extensions().get::<MessageSizes>().unwrap();
+            sizes.observe_sent(cx.rpc_info().role(), 32);
+            req
+        }
+    }
+
+    const fn method(name: &'static str, path: &'static str) -> MethodDescriptor {
+        MethodDescriptor {
+            service: "Item",
+            name,
+            path,
+            client_streaming: false,
+            server_streaming: false,
+            request_type: "volo_gen::item::GetItemRequest",
+            response_type: "volo_gen::item::GetItemResponse",
+            annotations: &[],
+            request_schema: None,
+        }
+    }
+
+    static METHODS: &[MethodDescriptor] = &[method("GetItem", "GetItem")];
+
+    fn scrape(registry: &Registry) -> Scrape {
+        let text = registry.render();
+        Scrape::parse(text.lines().map(|l| Ok(l.to_owned()))).unwrap()
+    }
+
+    fn value(scrape: &Scrape, name: &str, labels: &[(&str, &str)]) -> Option<f64> {
+        let sample = scrape.samples.iter().find(|s| {
+            s.metric == name && labels.iter().all(|(k, v)| s.labels.get(k) == Some(*v))
+        })?;
+        match &sample.value {
+            Value::Counter(v) | Value::Gauge(v) => Some(*v),
+            Value::Histogram(buckets) => buckets.last().map(|b| b.count),
+            _ => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn server() {
+        let registry = Registry::new();
+        let service = RpcMetricsLayer::new(RpcMetrics::server(&registry), Protocol)
+            .methods(METHODS)
+            .layer(Handler);
+
+        let arrive_at = SystemTime::now() - Duration::from_secs(3);
+        for req in [Ok(()), Ok(()), Err("not_found")] {
+            let mut cx = cx(Role::Server, "GetItem", Some(arrive_at));
+            let _ = service.call(&mut cx, req).await;
+        }
+        // not registered
+        let mut cx = cx(Role::Server, "RandomMethod", None);
+        service.call(&mut cx, Ok(())).await.unwrap();
+
+        let scrape = scrape(&registry);
+        let get = |name: &str, labels: &[(&str, &str)]| value(&scrape, name, labels);
+        let item = [
+            ("service", "Item"),
+            ("method", "GetItem"),
+            ("caller", "gateway"),
+            ("callee", "item"),
+        ];
+        let ok = [&item[..], &[("status", "ok")]].concat();
+        let not_found = [&item[..], &[("status", "not_found")]].concat();
+
+        assert_eq!(get("rpc_server_requests_total", &ok), Some(2.0));
+        assert_eq!(get("rpc_server_requests_total", &not_found), Some(1.0));
+        assert_eq!(get("rpc_server_errors_total", &not_found), Some(1.0));
+        assert_eq!(get("rpc_server_errors_total", &ok), None);
+        assert_eq!(get("rpc_server_in_flight_requests", &item), Some(0.0));
+        assert_eq!(get("rpc_server_handle_duration_seconds", &ok), Some(2.0));
+        assert_eq!(get("rpc_server_request_size_bytes", &item), Some(3.0));
+        assert_eq!(get("rpc_server_response_size_bytes", &item), Some(3.0));
+        assert_eq!(
+            get(
+                "rpc_server_requests_total",
+                &[("method", OTHER), ("service", OTHER)]
+            ),
+            Some(1.0)
+        );
+
+        // the total time is from the arrival 3s ago
+        let bucket = |name: &str, le: f64| {
+            let sample = scrape
+                .samples
+                .iter()
+                .find(|s| s.metric == name && s.labels.get("status") == Some("ok"))
+                .unwrap();
+            let Value::Histogram(buckets) = &sample.value else {
+                panic!("{name} is not a histogram");
+            };
+            buckets.iter().find(|b| b.less_than == le).unwrap().count
+        };
+        assert_eq!(bucket("rpc_server_duration_seconds", 2.5), 0.0);
+        assert_eq!(bucket("rpc_server_duration_seconds", 5.0), 2.0);
+        assert_eq!(bucket("rpc_server_handle_duration_seconds", 1.0), 2.0);
+    }
+
+    #[tokio::test]
+    async fn client() {
+        let registry = Registry::new();
+        let service = RpcMetricsLayer::new(RpcMetrics::client(&registry), Protocol).layer(Handler);
+
+        let mut cx = cx(Role::Client, "GetItem", None);
+        service.call(&mut cx, Ok(())).await.unwrap();
+
+        let scrape = scrape(&registry);
+        let get = |name: &str, labels: &[(&str, &str)]| value(&scrape, name, labels);
+        // the service of the clients is the callee without the descriptors
+        let item = [
+            ("service", "item"),
+            ("method", "GetItem"),
+            ("caller", "gateway"),
+            ("callee", "item"),
+        ];
+        assert_eq!(
+            get(
+                "rpc_client_requests_total",
+                &[&item[..], &[("status", "ok")]].concat()
+            ),
+            Some(1.0)
+        );
+        assert_eq!(get("rpc_client_request_size_bytes", &item), Some(1.0));
+        assert_eq!(get("rpc_client_response_size_bytes", &item), Some(0.0));
+        assert!(!registry.render().contains("handle_duration"));
+    }
+}
@@ -0,0 +1,314 @@
+use std::{fmt::Write as _, io, net::SocketAddr, time::Duration};
+
+use faststr::FastStr;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use super::{Desc, Registry, Value, Visitor};
+
+/// The content type of the Prometheus text format.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+const PATH: &str = "/metrics";
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A [`Visitor`] writing the samples in the Prometheus text format.
+#[derive(Debug, Default)]
+pub struct TextEncoder {
+    out: String,
+    /// The name of the metric being written.
+    current: Option<FastStr>,
+}
+
+impl TextEncoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the text written.
+    pub fn finish(self) -> String {
+        self.out
+    }
+
+    fn write_sample(
+        &mut self,
+        name: &str,
+        suffix: &str,
+        labels: &[(&str, &str)],
+        le: Option<f64>,
+        value: impl std::fmt::Display,
+    ) {
+        let out = &mut self.out;
+        out.push_str(name);
+        out.push_str(suffix);
+        if !labels.is_empty() || le.is_some() {
+            out.push('{');
+            for (i, (name, value)) in labels.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(name);
+                out.push_str("=\"");
+                escape(out, value, true);
+                out.push('"');
+            }
+            if let Some(le) = le {
+                if !labels.is_empty() {
+                    out.push(',');
+                }
+                out.push_str("le=\"");
+                write_float(out, le);
+                out.push('"');
+            }
+            out.push('}');
+        }
+        let _ = writeln!(out, " {value}");
+    }
+}
+
+impl Visitor for TextEncoder {
+    fn visit(&mut self, desc: &Desc, labels: &[(&str, &str)], value: Value<'_>) {
+        if self.current.as_ref() != Some(&desc.name) {
+            let kind = match value {
+                Value::Counter(_) => "counter",
+                Value::Gauge(_) => "gauge",
+                Value::Histogram(_) => "histogram",
+            };
+            if !desc.help.is_empty() {
+                let _ = write!(self.out, "# HELP {} ", desc.name);
+                escape(&mut self.out, &desc.help, false);
+                self.out.push('\n');
+            }
+            let _ = writeln!(self.out, "# TYPE {} {kind}", desc.name);
+            self.current = Some(desc.name.clone());
+        }
+
+        match value {
+            Value::Counter(v) => self.write_sample(&desc.name, "", labels, None, v),
+            Value::Gauge(v) => self.write_sample(&desc.name, "", labels, None, Float(v)),
+            Value::Histogram(h) => {
+                for (bound, count) in h.buckets.iter() {
+                    self.write_sample(&desc.name, "_bucket", labels, Some(*bound), count);
+                }
+                self.write_sample(&desc.name, "_bucket", labels, Some(f64::INFINITY), h.count);
+                self.write_sample(&desc.name, "_sum", labels, None, Float(h.sum));
+                self.write_sample(&desc.name, "_count", labels, None, h.count);
+            }
+        }
+    }
+}
+
+/// Escapes the backslashes and the line feeds, and the double quotes in label values.
+fn escape(out: &mut String, s: &str, quote: bool) {
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '"' if quote => out.push_str("\\\""),
+            c => out.push(c),
+        }
+    }
+}
+
+fn write_float(out: &mut String, v: f64) {
+    let _ = write!(out, "{}", Float(v));
+}
+
+struct Float(f64);
+
+impl std::fmt::Display for Float {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            v if v.is_nan() => f.write_str("NaN"),
+            v if v == f64::INFINITY => f.write_str("+Inf"),
+            v if v == f64::NEG_INFINITY => f.write_str("-Inf"),
+            v => write!(f, "{v}"),
+        }
+    }
+}
+
+/// Serves the metrics of the registry at `/metrics` on the address.
+pub async fn serve(registry: Registry, addr: SocketAddr) -> io::Result<()> {
+    serve_with_listener(registry, TcpListener::bind(addr).await?).await
+}
+
+/// Serves the metrics of the registry at `/metrics` on the listener.
+///
+/// This is a minimal HTTP/1.1 server answering one request per connection, which is enough for
+/// the Prometheus scrapers.
+pub async fn serve_with_listener(registry: Registry, listener: TcpListener) -> io::Result<()> {
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let registry = registry.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle(stream, &registry).await {
+                tracing::debug!("[VOLO] failed to serve metrics to {peer}: {e}");
+            }
+        });
+    }
+}
+
+async fn handle(mut stream: TcpStream, registry: &Registry) -> io::Result<()> {
+    let mut head = Vec::with_capacity(1024);
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        if head.len() > MAX_REQUEST_HEAD {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "request head too large",
+            ));
+        }
+        let n = tokio::time::timeout(READ_TIMEOUT, stream.read(&mut buf))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "read request timeout"))??;
+        if n == 0 {
+            return Ok(());
+        }
+        head.extend_from_slice(&buf[..n]);
+    }
+
+    let line = head.split(|b| *b == b'\r').next().unwrap_or_default();
+    let mut parts = line.split(|b| *b == b' ');
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+    let path = path.split(|b| *b == b'?').next().unwrap_or_default();
+
+    let (status, content_type, body) = match (method, path) {
+        (b"GET", p) if p == PATH.as_bytes() => ("200 OK", CONTENT_TYPE, registry.render()),
+        (b"GET", _) => ("404 Not Found", "text/plain", "not found\n".to_owned()),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "method not allowed\n".to_owned(),
+        ),
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\ncontent-type: {content_type}\r\ncontent-length: {}\r\nconnection: \
+         close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::{Registry, DEFAULT_BUCKETS};
+
+    fn registry() -> Registry {
+        let registry = Registry::new();
+        registry.counter("requests_total", "The requests.").add(3);
+        let connections = registry.gauge_vec(
+            "connections",
+            "The connections.\nIncluding \\idle ones.",
+            &["peer", "state"],
+        );
+        connections.with(&["10.0.0.1", "idle"]).set(2);
+        connections.with(&["10.0.0.2", "busy"]).dec();
+        let latency = registry.histogram_vec("latency_seconds", "", &["method"], &[0.1, 1.0]);
+        let hello = latency.with(&["hello"]);
+        hello.observe(0.0625);
+        hello.observe(0.5);
+        hello.observe(4.0);
+        registry.histogram("empty_seconds", "Empty.", DEFAULT_BUCKETS);
+        registry
+    }
+
+    #[test]
+    fn render() {
+        let registry = registry();
+        registry
+            .gauge_vec("escaped", "", &["value"])
+            .with(&["with \"quotes\"\n"])
+            .set(1);
+        let text = registry.render();
+        let expected = "# HELP requests_total The requests.
+# TYPE requests_total counter
+requests_total 3
+# HELP connections The connections.\\nIncluding \\\\idle ones.
+# TYPE connections gauge
+connections{peer=\"10.0.0.1\",state=\"idle\"} 2
+connections{peer=\"10.0.0.2\",state=\"busy\"} -1
+# TYPE latency_seconds histogram
+latency_seconds_bucket{method=\"hello\",le=\"0.1\"} 1
+latency_seconds_bucket{method=\"hello\",le=\"1\"} 2
+latency_seconds_bucket{method=\"hello\",le=\"+Inf\"} 3
+latency_seconds_sum{method=\"hello\"} 4.5625
+latency_seconds_count{method=\"hello\"} 3
+";
+        assert!(text.starts_with(expected), "{text}");
+        assert!(text.contains("empty_seconds_bucket{le=\"+Inf\"} 0\n"));
+        assert!(text.ends_with("escaped{value=\"with \\\"quotes\\\"\\n\"} 1\n"));
+    }
+
+    #[test]
+    fn parse() {
+        use prometheus_parse::{Scrape, Value as PValue};
+
+        let text = registry().render();
+        let scrape = Scrape::parse(text.lines().map(|l| Ok(l.to_owned()))).unwrap();
+
+        let sample = |name: &str| {
+            scrape
+                .samples
+                .iter()
+                .find(|s| s.metric == name)
+                .unwrap_or_else(|| panic!("missing {name}"))
+        };
+        assert!(matches!(sample("requests_total").value, PValue::Counter(v) if v == 3.0));
+        assert_eq!(
+            scrape.docs.get("requests_total").map(String::as_str),
+            Some("The requests.")
+        );
+
+        let idle = scrape
+            .samples
+            .iter()
+            .find(|s| s.metric == "connections" && s.labels.get("state") == Some("idle"))
+            .unwrap();
+        assert_eq!(idle.labels.get("peer"), Some("10.0.0.1"));
+        assert!(matches!(idle.value, PValue::Gauge(v) if v == 2.0));
+
+        let PValue::Histogram(buckets) = &sample("latency_seconds").value else {
+            panic!("latency_seconds is not a histogram");
+        };
+        let counts = buckets
+            .iter()
+            .map(|b| (b.less_than, b.count))
+            .collect::<Vec<_>>();
+        assert_eq!(counts, vec![(0.1, 1.0), (1.0, 2.0), (f64::INFINITY, 3.0)]);
+    }
+
+    #[tokio::test]
+    async fn serve_metrics() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_with_listener(registry(), listener));
+
+        let get = |path: &'static str| async move {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            stream
+                .write_all(format!("GET {path} HTTP/1.1\r\nhost: localhost\r\n\r\n").as_bytes())
+                .await
+                .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        };
+
+        let response = get("/metrics?name[]=x").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+        assert!(response.contains(CONTENT_TYPE));
+        assert!(response.ends_with(&registry().render()));
+
+        let response = get("/").await;
+        assert!(
+            response.starts_with("HTTP/1.1 404 Not Found\r\n"),
+            "{response}"
+        );
+    }
+}
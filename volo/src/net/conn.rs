@@ -1,6 +1,7 @@
 use std::{
     io,
     pin::Pin,
+    sync::Arc,
     task::{Context, Poll},
 };
 
@@ -12,11 +13,25 @@ use tokio::{
     net::{tcp, TcpStream},
 };
 
-use super::Address;
+#[cfg(all(target_os = "linux", feature = "kernel-timestamp"))]
+use super::timestamp::TimestampedReadHalf;
+use super::{timestamp::RecvTimestamp, Address};
+#[cfg(feature = "fault")]
+use crate::fault::FaultStream;
+use crate::{
+    context::identity::{TlsIdentity, TlsInfo},
+    FastStr,
+};
 
 #[derive(Clone)]
 pub struct ConnInfo {
     pub peer_addr: Option<Address>,
+    /// The name of the [`Listener`](super::listener::Listener) accepting the connection, which is
+    /// `None` if the connection is not accepted by [`Listeners`](super::listener::Listeners).
+    pub listener: Option<FastStr>,
+    /// The duration of the TLS handshake done by the [`Listener`](super::listener::Listener),
+    /// which is `None` if the connection is not TLS or the handshake is done by the server.
+    pub handshake: Option<std::time::Duration>,
 }
 
 pub trait DynStream: AsyncRead + AsyncWrite + Send + 'static {}
@@ -35,6 +50,9 @@ pub enum ConnStream {
     #[cfg(feature = "native-tls")]
     #[cfg_attr(docsrs, doc(cfg(feature = "native-tls")))]
     NativeTls(#[pin] tokio_native_tls::TlsStream<TcpStream>),
+    #[cfg(feature = "fault")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "fault")))]
+    Fault(#[pin] Pin<Box<FaultStream<ConnStream>>>),
 }
 
 #[cfg(feature = "rustls")]
@@ -43,6 +61,9 @@ type RustlsWriteHalf = tokio::io::WriteHalf<tokio_rustls::TlsStream<TcpStream>>;
 #[cfg(feature = "native-tls")]
 type NativeTlsWriteHalf = tokio::io::WriteHalf<tokio_native_tls::TlsStream<TcpStream>>;
 
+#[cfg(feature = "fault")]
+type FaultWriteHalf = tokio::io::WriteHalf<Pin<Box<FaultStream<ConnStream>>>>;
+
 #[pin_project(project = OwnedWriteHalfProj)]
 pub enum OwnedWriteHalf {
     Tcp(#[pin] tcp::OwnedWriteHalf),
@@ -55,6 +76,9 @@ pub enum OwnedWriteHalf {
     #[cfg(feature = "native-tls")]
     #[cfg_attr(docsrs, doc(cfg(feature = "native-tls")))]
     NativeTls(#[pin] NativeTlsWriteHalf),
+    #[cfg(feature = "fault")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "fault")))]
+    Fault(#[pin] FaultWriteHalf),
 }
 
 impl AsyncWrite for OwnedWriteHalf {
@@ -72,6 +96,8 @@ impl AsyncWrite for OwnedWriteHalf {
             OwnedWriteHalfProj::Rustls(half) => half.poll_write(cx, buf),
             #[cfg(feature = "native-tls")]
             OwnedWriteHalfProj::NativeTls(half) => half.poll_write(cx, buf),
+            #[cfg(feature = "fault")]
+            OwnedWriteHalfProj::Fault(half) => half.poll_write(cx, buf),
         }
     }
 
@@ -85,6 +111,8 @@ impl AsyncWrite for OwnedWriteHalf {
             OwnedWriteHalfProj::Rustls(half) => half.poll_flush(cx),
             #[cfg(feature = "native-tls")]
             OwnedWriteHalfProj::NativeTls(half) => half.poll_flush(cx),
+            #[cfg(feature = "fault")]
+            OwnedWriteHalfProj::Fault(half) => half.poll_flush(cx),
         }
     }
 
@@ -98,6 +126,8 @@ impl AsyncWrite for OwnedWriteHalf {
             OwnedWriteHalfProj::Rustls(half) => half.poll_shutdown(cx),
             #[cfg(feature = "native-tls")]
             OwnedWriteHalfProj::NativeTls(half) => half.poll_shutdown(cx),
+            #[cfg(feature = "fault")]
+            OwnedWriteHalfProj::Fault(half) => half.poll_shutdown(cx),
         }
     }
 
@@ -115,6 +145,8 @@ impl AsyncWrite for OwnedWriteHalf {
             OwnedWriteHalfProj::Rustls(half) => half.poll_write_vectored(cx, bufs),
             #[cfg(feature = "native-tls")]
             OwnedWriteHalfProj::NativeTls(half) => half.poll_write_vectored(cx, bufs),
+            #[cfg(feature = "fault")]
+            OwnedWriteHalfProj::Fault(half) => half.poll_write_vectored(cx, bufs),
         }
     }
 
@@ -128,6 +160,8 @@ impl AsyncWrite for OwnedWriteHalf {
             Self::Rustls(half) => half.is_write_vectored(),
             #[cfg(feature = "native-tls")]
             Self::NativeTls(half) => half.is_write_vectored(),
+            #[cfg(feature = "fault")]
+            Self::Fault(half) => half.is_write_vectored(),
         }
     }
 }
@@ -138,9 +172,18 @@ type RustlsReadHalf = tokio::io::ReadHalf<tokio_rustls::TlsStream<TcpStream>>;
 #[cfg(feature = "native-tls")]
 type NativeTlsReadHalf = tokio::io::ReadHalf<tokio_native_tls::TlsStream<TcpStream>>;
 
+#[cfg(feature = "fault")]
+type FaultReadHalf = tokio::io::ReadHalf<Pin<Box<FaultStream<ConnStream>>>>;
+
 #[pin_project(project = OwnedReadHalfProj)]
 pub enum OwnedReadHalf {
     Tcp(#[pin] tcp::OwnedReadHalf),
+    #[cfg(all(target_os = "linux", feature = "kernel-timestamp"))]
+    #[cfg_attr(
+        docsrs,
+        doc(cfg(all(target_os = "linux", feature = "kernel-timestamp")))
+    )]
+    TimestampedTcp(#[pin] TimestampedReadHalf),
     #[cfg(target_family = "unix")]
     #[cfg_attr(docsrs, doc(cfg(target_family = "unix")))]
     Unix(#[pin] unix::OwnedReadHalf),
@@ -150,6 +193,9 @@ pub enum OwnedReadHalf {
     #[cfg(feature = "native-tls")]
     #[cfg_attr(docsrs, doc(cfg(feature = "native-tls")))]
     NativeTls(#[pin] NativeTlsReadHalf),
+    #[cfg(feature = "fault")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "fault")))]
+    Fault(#[pin] FaultReadHalf),
 }
 
 impl AsyncRead for OwnedReadHalf {
@@ -161,13 +207,29 @@ impl AsyncRead for OwnedReadHalf {
     ) -> Poll<Result<(), io::Error>> {
         match self.project() {
             OwnedReadHalfProj::Tcp(half) => half.poll_read(cx, buf),
+            #[cfg(all(target_os = "linux", feature = "kernel-timestamp"))]
+            OwnedReadHalfProj::TimestampedTcp(half) => half.poll_read(cx, buf),
             #[cfg(target_family = "unix")]
             OwnedReadHalfProj::Unix(half) => half.poll_read(cx, buf),
             #[cfg(feature = "rustls")]
             OwnedReadHalfProj::Rustls(half) => half.poll_read(cx, buf),
             #[cfg(feature = "native-tls")]
             OwnedReadHalfProj::NativeTls(half) => half.poll_read(cx, buf),
+            #[cfg(feature = "fault")]
+            OwnedReadHalfProj::Fault(half) => half.poll_read(cx, buf),
+        }
+    }
+}
+
+impl OwnedReadHalf {
+    /// Returns the kernel receive timestamp of the connection, which is only available for TCP
+    /// with the `kernel-timestamp` feature on Linux.
+    pub fn recv_timestamp(&self) -> Option<RecvTimestamp> {
+        #[cfg(all(target_os = "linux", feature = "kernel-timestamp"))]
+        if let Self::TimestampedTcp(half) = self {
+            return Some(half.recv_timestamp().clone());
         }
+        None
     }
 }
 
@@ -176,7 +238,11 @@ impl ConnStream {
         match self {
             Self::Tcp(stream) => {
                 let (rh, wh) = stream.into_split();
-                (OwnedReadHalf::Tcp(rh), OwnedWriteHalf::Tcp(wh))
+                #[cfg(all(target_os = "linux", feature = "kernel-timestamp"))]
+                let rh = OwnedReadHalf::TimestampedTcp(TimestampedReadHalf::new(rh));
+                #[cfg(not(all(target_os = "linux", feature = "kernel-timestamp")))]
+                let rh = OwnedReadHalf::Tcp(rh);
+                (rh, OwnedWriteHalf::Tcp(wh))
             }
             #[cfg(target_family = "unix")]
             Self::Unix(stream) => {
@@ -193,6 +259,11 @@ impl ConnStream {
                 let (rh, wh) = tokio::io::split(stream);
                 (OwnedReadHalf::NativeTls(rh), OwnedWriteHalf::NativeTls(wh))
             }
+            #[cfg(feature = "fault")]
+            Self::Fault(stream) => {
+                let (rh, wh) = tokio::io::split(stream);
+                (OwnedReadHalf::Fault(rh), OwnedWriteHalf::Fault(wh))
+            }
         }
     }
 }
@@ -247,6 +318,8 @@ impl AsyncRead for ConnStream {
             IoStreamProj::Rustls(s) => s.poll_read(cx, buf),
             #[cfg(feature = "native-tls")]
             IoStreamProj::NativeTls(s) => s.poll_read(cx, buf),
+            #[cfg(feature = "fault")]
+            IoStreamProj::Fault(s) => s.poll_read(cx, buf),
         }
     }
 }
@@ -266,6 +339,8 @@ impl AsyncWrite for ConnStream {
             IoStreamProj::Rustls(s) => s.poll_write(cx, buf),
             #[cfg(feature = "native-tls")]
             IoStreamProj::NativeTls(s) => s.poll_write(cx, buf),
+            #[cfg(feature = "fault")]
+            IoStreamProj::Fault(s) => s.poll_write(cx, buf),
         }
     }
 
@@ -279,6 +354,8 @@ impl AsyncWrite for ConnStream {
             IoStreamProj::Rustls(s) => s.poll_flush(cx),
             #[cfg(feature = "native-tls")]
             IoStreamProj::NativeTls(s) => s.poll_flush(cx),
+            #[cfg(feature = "fault")]
+            IoStreamProj::Fault(s) => s.poll_flush(cx),
         }
     }
 
@@ -292,6 +369,8 @@ impl AsyncWrite for ConnStream {
             IoStreamProj::Rustls(s) => s.poll_shutdown(cx),
             #[cfg(feature = "native-tls")]
             IoStreamProj::NativeTls(s) => s.poll_shutdown(cx),
+            #[cfg(feature = "fault")]
+            IoStreamProj::Fault(s) => s.poll_shutdown(cx),
         }
     }
 
@@ -309,6 +388,8 @@ impl AsyncWrite for ConnStream {
             IoStreamProj::Rustls(s) => s.poll_write_vectored(cx, bufs),
             #[cfg(feature = "native-tls")]
             IoStreamProj::NativeTls(s) => s.poll_write_vectored(cx, bufs),
+            #[cfg(feature = "fault")]
+            IoStreamProj::Fault(s) => s.poll_write_vectored(cx, bufs),
         }
     }
 
@@ -322,6 +403,8 @@ impl AsyncWrite for ConnStream {
             Self::Rustls(s) => s.is_write_vectored(),
             #[cfg(feature = "native-tls")]
             Self::NativeTls(s) => s.is_write_vectored(),
+            #[cfg(feature = "fault")]
+            Self::Fault(s) => s.is_write_vectored(),
         }
     }
 }
@@ -343,6 +426,55 @@ impl ConnStream {
                 .peer_addr()
                 .map(Address::from)
                 .ok(),
+            #[cfg(feature = "fault")]
+            Self::Fault(s) => s.get_ref().peer_addr(),
+        }
+    }
+
+    /// Returns the identity told by the certificate of the peer, if the stream is TLS and the
+    /// peer has sent a certificate.
+    pub fn tls_identity(&self) -> Option<TlsIdentity> {
+        self.tls_info()?.identity().cloned()
+    }
+
+    /// Returns the parameters negotiated by the TLS handshake, or `None` without any cost if
+    /// the stream is plaintext.
+    pub fn tls_info(&self) -> Option<Arc<TlsInfo>> {
+        match self {
+            #[cfg(feature = "rustls")]
+            Self::Rustls(s) => {
+                let server_name = match s {
+                    tokio_rustls::TlsStream::Server(s) => {
+                        s.get_ref().1.server_name().map(FastStr::new)
+                    }
+                    tokio_rustls::TlsStream::Client(_) => None,
+                };
+                let conn = s.get_ref().1;
+                Some(Arc::new(TlsInfo::new(
+                    server_name,
+                    conn.alpn_protocol().map(<[u8]>::to_vec),
+                    conn.peer_certificates()
+                        .map(|certs| certs.iter().map(|cert| cert.to_vec()).collect())
+                        .unwrap_or_default(),
+                )))
+            }
+            #[cfg(feature = "native-tls")]
+            Self::NativeTls(s) => {
+                let tls = s.get_ref();
+                Some(Arc::new(TlsInfo::new(
+                    None,
+                    tls.negotiated_alpn().ok().flatten(),
+                    tls.peer_certificate()
+                        .ok()
+                        .flatten()
+                        .and_then(|cert| cert.to_der().ok())
+                        .into_iter()
+                        .collect(),
+                )))
+            }
+            #[cfg(feature = "fault")]
+            Self::Fault(s) => s.get_ref().tls_info(),
+            _ => None,
         }
     }
 }
@@ -366,7 +498,14 @@ where
     fn from(i: T) -> Self {
         let i = i.into();
         let peer_addr = i.peer_addr();
-        Conn::new(i, ConnInfo { peer_addr })
+        Conn::new(
+            i,
+            ConnInfo {
+                peer_addr,
+                listener: None,
+                handshake: None,
+            },
+        )
     }
 }
 
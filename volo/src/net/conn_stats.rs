@@ -0,0 +1,209 @@
+//! The stats of the connections accepted by a server, for the alerts on the connection leaks and
+//! the slow TLS handshakes which the stats of the requests don't cover.
+//!
+//! A [`ConnStats`] is registered on a server, e.g. by `conn_stats` of the thrift server, which
+//! counts each connection from the accepting to the end of serving it. With the `metrics` feature,
+//! it's also a [`Source`](crate::metrics::Source) of the metrics:
+//!
+//! | Name | Type |
+//! | --- | --- |
+//! | `volo_server_connections_active` | gauge |
+//! | `volo_server_connections_accepted_total` | counter |
+//! | `volo_server_connections_closed_total` | counter |
+//! | `volo_server_tls_handshake_seconds` | histogram |
+//!
+//! ```rust,ignore
+//! let stats = ConnStats::new();
+//! registry.register(stats.clone());
+//!
+//! ItemServiceServer::new(S)
+//!     .conn_stats(stats)
+//!     .run(listeners)
+//!     .await
+//!     .unwrap();
+//! ```
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use super::conn::ConnInfo;
+
+/// The stats of the connections of a server, see the [module docs](self).
+///
+/// The clones share the same stats.
+#[derive(Clone, Debug, Default)]
+pub struct ConnStats {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    active: AtomicUsize,
+    accepted: AtomicU64,
+    closed: AtomicU64,
+    handshakes: AtomicU64,
+    handshake_nanos: AtomicU64,
+    #[cfg(feature = "metrics")]
+    handshake_seconds: crate::metrics::Histogram,
+}
+
+impl ConnStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of the connections being served.
+    pub fn active(&self) -> usize {
+        self.inner.active.load(Ordering::Relaxed)
+    }
+
+    pub fn accepted(&self) -> u64 {
+        self.inner.accepted.load(Ordering::Relaxed)
+    }
+
+    pub fn closed(&self) -> u64 {
+        self.inner.closed.load(Ordering::Relaxed)
+    }
+
+    /// The number of the TLS handshakes done by the listeners.
+    pub fn handshakes(&self) -> u64 {
+        self.inner.handshakes.load(Ordering::Relaxed)
+    }
+
+    /// The total time of the TLS handshakes.
+    pub fn handshake_time(&self) -> Duration {
+        Duration::from_nanos(self.inner.handshake_nanos.load(Ordering::Relaxed))
+    }
+
+    /// Counts an accepted connection, which is counted as active until the returned guard is
+    /// dropped at the end of serving it.
+    pub fn accept(&self, info: &ConnInfo) -> ConnGuard {
+        let inner = &self.inner;
+        inner.accepted.fetch_add(1, Ordering::Relaxed);
+        inner.active.fetch_add(1, Ordering::Relaxed);
+        if let Some(handshake) = info.handshake {
+            inner.handshakes.fetch_add(1, Ordering::Relaxed);
+            inner
+                .handshake_nanos
+                .fetch_add(handshake.as_nanos() as u64, Ordering::Relaxed);
+            #[cfg(feature = "metrics")]
+            inner.handshake_seconds.observe_duration(handshake);
+        }
+        ConnGuard(self.clone())
+    }
+}
+
+/// Counts a connection as active until it's dropped, returned by [`ConnStats::accept`].
+#[derive(Debug)]
+pub struct ConnGuard(ConnStats);
+
+impl Drop for ConnGuard {
+    fn drop(&mut self) {
+        let inner = &self.0.inner;
+        inner.active.fetch_sub(1, Ordering::Relaxed);
+        inner.closed.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl crate::metrics::Source for ConnStats {
+    fn collect(&self, visitor: &mut dyn crate::metrics::Visitor) {
+        use crate::metrics::{Desc, Value};
+
+        visitor.visit(
+            &Desc::new(
+                "volo_server_connections_active",
+                "The connections being served.",
+            ),
+            &[],
+            Value::Gauge(self.active() as f64),
+        );
+        visitor.visit(
+            &Desc::new(
+                "volo_server_connections_accepted_total",
+                "The accepted connections.",
+            ),
+            &[],
+            Value::Counter(self.accepted()),
+        );
+        visitor.visit(
+            &Desc::new(
+                "volo_server_connections_closed_total",
+                "The connections closed after served.",
+            ),
+            &[],
+            Value::Counter(self.closed()),
+        );
+        visitor.visit(
+            &Desc::new(
+                "volo_server_tls_handshake_seconds",
+                "The time of the TLS handshakes done by the listeners.",
+            ),
+            &[],
+            Value::Histogram(&self.inner.handshake_seconds.snapshot()),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(handshake: Option<Duration>) -> ConnInfo {
+        ConnInfo {
+            peer_addr: None,
+            listener: None,
+            handshake,
+        }
+    }
+
+    #[test]
+    fn lifecycle() {
+        let stats = ConnStats::new();
+        let plain = stats.accept(&info(None));
+        let tls = stats.clone().accept(&info(Some(Duration::from_millis(3))));
+        assert_eq!(
+            (stats.active(), stats.accepted(), stats.closed()),
+            (2, 2, 0)
+        );
+        assert_eq!(stats.handshakes(), 1);
+        assert_eq!(stats.handshake_time(), Duration::from_millis(3));
+
+        drop(plain);
+        assert_eq!(
+            (stats.active(), stats.accepted(), stats.closed()),
+            (1, 2, 1)
+        );
+        drop(tls);
+        assert_eq!(
+            (stats.active(), stats.accepted(), stats.closed()),
+            (0, 2, 2)
+        );
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn metrics() {
+        let registry = crate::metrics::Registry::new();
+        let stats = ConnStats::new();
+        registry.register(stats.clone());
+        let _conn = stats.accept(&info(Some(Duration::from_millis(3))));
+        drop(stats.accept(&info(None)));
+
+        let text = registry.render();
+        for line in [
+            "volo_server_connections_active 1\n",
+            "volo_server_connections_accepted_total 2\n",
+            "volo_server_connections_closed_total 1\n",
+            "volo_server_tls_handshake_seconds_bucket{le=\"0.005\"} 1\n",
+            "volo_server_tls_handshake_seconds_count 1\n",
+        ] {
+            assert!(text.contains(line), "missing {line} in {text}");
+        }
+    }
+}
@@ -14,6 +14,10 @@ use super::{
     conn::{Conn, OwnedReadHalf, OwnedWriteHalf},
     Address,
 };
+use crate::util::backoff::Backoff;
+
+const DEFAULT_CONNECT_BACKOFF: Duration = Duration::from_millis(10);
+const DEFAULT_MAX_CONNECT_BACKOFF: Duration = Duration::from_secs(1);
 
 /// [`MakeTransport`] creates an [`AsyncRead`] and an [`AsyncWrite`] for the given [`Address`].
 pub trait MakeTransport: Clone + Send + Sync + 'static {
@@ -27,6 +31,102 @@ pub trait MakeTransport: Clone + Send + Sync + 'static {
     fn set_connect_timeout(&mut self, timeout: Option<Duration>);
     fn set_read_timeout(&mut self, timeout: Option<Duration>);
     fn set_write_timeout(&mut self, timeout: Option<Duration>);
+    /// Sets the retry of the failed connects, which is ignored by default.
+    fn set_connect_retry(&mut self, _retry: Option<ConnectRetry>) {}
+}
+
+/// The retry of establishing the connections, e.g. when the connection is refused during the
+/// deployment of the server.
+///
+/// It only retries before any bytes are written to the connection, so it's independent of the
+/// retry of the calls, see [`crate::retry`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectRetry {
+    attempts: usize,
+    backoff: Backoff,
+}
+
+impl ConnectRetry {
+    /// Creates a retry connecting at most `attempts` times, including the first one.
+    ///
+    /// The backoff between the attempts is from `10ms` to `1s` with the full jitter by default.
+    pub fn new(attempts: usize) -> Self {
+        Self {
+            attempts: attempts.max(1),
+            backoff: Backoff::new(DEFAULT_CONNECT_BACKOFF, DEFAULT_MAX_CONNECT_BACKOFF)
+                .full_jitter(),
+        }
+    }
+
+    /// Sets the backoff between the attempts.
+    pub fn backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    pub fn attempts(&self) -> usize {
+        self.attempts
+    }
+
+    /// Connects by `connect` until it succeeds, or fails with an error not worth retrying, or
+    /// all the attempts fail.
+    ///
+    /// The error of the last attempt is wrapped in a [`ConnectError`] when there are retries.
+    pub async fn connect<T, F, Fut>(&self, mut connect: F) -> io::Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = io::Result<T>>,
+    {
+        let mut delays = self.backoff.iter();
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let err = match connect().await {
+                Ok(conn) => return Ok(conn),
+                Err(err) => err,
+            };
+            if !is_retryable(&err) || attempts >= self.attempts {
+                if attempts == 1 {
+                    return Err(err);
+                }
+                return Err(io::Error::new(
+                    err.kind(),
+                    ConnectError {
+                        attempts,
+                        source: err,
+                    },
+                ));
+            }
+            let delay = delays.next().unwrap_or_default();
+            tracing::debug!(
+                "[VOLO] connect failed at attempt {attempts}, retry after {delay:?}: {err}"
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+/// Whether the connect may succeed if retried, e.g. the server is restarting.
+fn is_retryable(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::TimedOut
+            // the unix domain socket has not been created yet
+            | io::ErrorKind::NotFound
+    )
+}
+
+/// The error of the last attempt when the [`ConnectRetry`] is exhausted, which is the inner error
+/// of the returned [`io::Error`].
+#[derive(Debug, thiserror::Error)]
+#[error("failed to connect after {attempts} attempts: {source}")]
+pub struct ConnectError {
+    pub attempts: usize,
+    #[source]
+    pub source: io::Error,
 }
 
 #[derive(Default, Debug, Clone, Copy)]
@@ -39,6 +139,7 @@ pub struct Config {
     pub connect_timeout: Option<Duration>,
     pub read_timeout: Option<Duration>,
     pub write_timeout: Option<Duration>,
+    pub connect_retry: Option<ConnectRetry>,
 }
 
 impl Config {
@@ -51,6 +152,7 @@ impl Config {
             connect_timeout,
             read_timeout,
             write_timeout,
+            connect_retry: None,
         }
     }
 
@@ -68,12 +170,21 @@ impl Config {
         self.write_timeout = timeout;
         self
     }
+
+    pub fn with_connect_retry(mut self, retry: Option<ConnectRetry>) -> Self {
+        self.connect_retry = retry;
+        self
+    }
 }
 
 impl DefaultMakeTransport {
     pub fn new() -> Self {
         Self::default()
     }
+
+    pub fn config(&self) -> &Config {
+        &self.cfg
+    }
 }
 
 impl MakeTransport for DefaultMakeTransport {
@@ -98,12 +209,32 @@ impl MakeTransport for DefaultMakeTransport {
     fn set_write_timeout(&mut self, timeout: Option<Duration>) {
         self.cfg = self.cfg.with_write_timeout(timeout);
     }
+
+    fn set_connect_retry(&mut self, retry: Option<ConnectRetry>) {
+        self.cfg = self.cfg.with_connect_retry(retry);
+    }
+}
+
+/// Connects by `connect` with the [`ConnectRetry`] of the config if any.
+pub(super) async fn retry_connect<T, F, Fut>(cfg: &Config, mut connect: F) -> io::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = io::Result<T>>,
+{
+    match &cfg.connect_retry {
+        Some(retry) => retry.connect(connect).await,
+        None => connect().await,
+    }
 }
 
 pub(super) async fn make_tcp_connection(
     cfg: &Config,
     addr: SocketAddr,
 ) -> Result<TcpStream, io::Error> {
+    retry_connect(cfg, || connect_tcp(cfg, addr)).await
+}
+
+async fn connect_tcp(cfg: &Config, addr: SocketAddr) -> Result<TcpStream, io::Error> {
     let domain = Domain::for_address(addr);
     let socket = Socket::new(domain, Type::STREAM, Some(Protocol::TCP))?;
     socket.set_nonblocking(true)?;
@@ -142,14 +273,96 @@ impl UnaryService<Address> for DefaultMakeTransport {
                 Ok(Conn::from(stream))
             }
             #[cfg(target_family = "unix")]
-            Address::Unix(addr) => UnixStream::connect(addr.as_pathname().ok_or_else(|| {
-                io::Error::new(
-                    io::ErrorKind::AddrNotAvailable,
-                    "cannot connect to unnamed socket",
-                )
-            })?)
-            .await
-            .map(Conn::from),
+            Address::Unix(addr) => make_unix_connection(&self.cfg, &addr).await.map(Conn::from),
         }
     }
 }
+
+#[cfg(target_family = "unix")]
+pub(super) async fn make_unix_connection(
+    cfg: &Config,
+    addr: &std::os::unix::net::SocketAddr,
+) -> Result<UnixStream, io::Error> {
+    let path = addr.as_pathname().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::AddrNotAvailable,
+            "cannot connect to unnamed socket",
+        )
+    })?;
+    retry_connect(cfg, || UnixStream::connect(path)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    async fn unused_addr() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap()
+    }
+
+    fn retry(attempts: usize) -> ConnectRetry {
+        ConnectRetry::new(attempts).backoff(
+            Backoff::new(Duration::from_millis(10), Duration::from_millis(20)).full_jitter(),
+        )
+    }
+
+    #[tokio::test]
+    async fn retry_until_accepting() {
+        let addr = unused_addr().await;
+        let mut mt = DefaultMakeTransport::new();
+        assert_eq!(
+            mt.make_connection(Address::Ip(addr))
+                .await
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::ConnectionRefused
+        );
+
+        // the listener starts accepting after a while
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            let listener = TcpListener::bind(addr).await.unwrap();
+            let _conn = listener.accept().await.unwrap();
+        });
+        mt.set_connect_retry(Some(retry(100)));
+        mt.make_connection(Address::Ip(addr)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn retry_exhausted() {
+        let addr = unused_addr().await;
+        let mut mt = DefaultMakeTransport::new();
+        mt.set_connect_retry(Some(retry(3)));
+        let err = mt.make_connection(Address::Ip(addr)).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+        let inner = err
+            .get_ref()
+            .unwrap()
+            .downcast_ref::<ConnectError>()
+            .unwrap();
+        assert_eq!(inner.attempts, 3);
+        assert_eq!(inner.source.kind(), io::ErrorKind::ConnectionRefused);
+    }
+
+    #[tokio::test]
+    async fn no_retry_for_other_errors() {
+        let attempts = std::sync::atomic::AtomicUsize::new(0);
+        let err = retry(3)
+            .connect(|| async {
+                attempts.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                Err::<(), _>(io::Error::new(io::ErrorKind::InvalidInput, "invalid"))
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err
+            .get_ref()
+            .unwrap()
+            .downcast_ref::<ConnectError>()
+            .is_none());
+        assert_eq!(attempts.into_inner(), 1);
+    }
+}
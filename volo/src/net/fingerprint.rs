@@ -0,0 +1,292 @@
+//! Fingerprints of the protocols that are commonly found on a misconfigured port.
+//!
+//! When a client fails to speak its protocol with the peer, e.g. a thrift client pointed at the
+//! port of a gRPC server, the error it gets from the codec is usually a cryptic decode failure.
+//! The helpers here look at the first bytes the peer sends back and tell what is actually
+//! listening there, so that the clients can surface a [`ProtocolMismatch`] instead.
+//!
+//! Nothing here runs on the happy path: the clients only [`diagnose`] a target after a call to
+//! it has already failed.
+
+use std::{fmt, io, time::Duration};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use super::{
+    dial::{DefaultMakeTransport, MakeTransport},
+    Address,
+};
+
+/// The max number of the first bytes from the peer kept in a [`ProtocolMismatch`].
+pub const MAX_HEAD_LEN: usize = 32;
+
+/// The default timeout of [`diagnose`], covering both the connecting and the reading.
+pub const DEFAULT_DIAGNOSE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// The connection preface of HTTP/2, which is also what the probe of [`diagnose`] sends.
+///
+/// An HTTP/1 server rejects it with a response, a TLS server with an alert, and an HTTP/2
+/// server answers it with its SETTINGS.
+const H2_PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// The protocol spoken by the peer, as told by the first bytes it sends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// Plain HTTP/1, e.g. the port of an HTTP server or a proxy.
+    Http1,
+    /// Plaintext HTTP/2, e.g. the port of a gRPC server.
+    Http2,
+    /// TLS, e.g. a port that requires the client to enable TLS.
+    Tls,
+}
+
+impl Protocol {
+    /// Detects the protocol from the first bytes sent by the peer.
+    ///
+    /// Returns `None` if the bytes are too short or match none of the known protocols.
+    pub fn detect(head: &[u8]) -> Option<Self> {
+        if head.starts_with(b"HTTP/1.") {
+            return Some(Protocol::Http1);
+        }
+        // TLS record header: content type, then the major version which is always 3.
+        // The content type is an alert (21) when the server rejects what we sent, or a
+        // handshake (22) if it speaks first.
+        if let [21 | 22, 3, minor, ..] = head {
+            if *minor <= 4 {
+                return Some(Protocol::Tls);
+            }
+        }
+        if head.starts_with(H2_PREFACE) {
+            return Some(Protocol::Http2);
+        }
+        // HTTP/2 frame header: 24-bit length, type, flags, 31-bit stream id. A server always
+        // starts with a SETTINGS frame (4), or a GOAWAY (7) if it rejects the preface, both of
+        // which are on the stream 0.
+        if let [_, _, _, 4 | 7, _, 0, 0, 0, 0, ..] = head {
+            return Some(Protocol::Http2);
+        }
+        None
+    }
+
+    fn hint(&self) -> &'static str {
+        match self {
+            Protocol::Http1 => {
+                "the address is probably an HTTP/1 server or proxy, check the port of the service"
+            }
+            Protocol::Http2 => {
+                "the address is probably a gRPC or other HTTP/2 server, check the port and the \
+                 protocol of the service"
+            }
+            Protocol::Tls => {
+                "the server requires TLS, enable TLS on the client or use the plaintext port"
+            }
+        }
+    }
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Protocol::Http1 => f.write_str("HTTP/1"),
+            Protocol::Http2 => f.write_str("HTTP/2"),
+            Protocol::Tls => f.write_str("TLS"),
+        }
+    }
+}
+
+/// The peer speaks another protocol than the one the client expects.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolMismatch {
+    expected: &'static str,
+    found: Protocol,
+    head: Vec<u8>,
+}
+
+impl ProtocolMismatch {
+    /// Creates a mismatch from the first bytes sent by the peer, of which at most
+    /// [`MAX_HEAD_LEN`] are kept.
+    pub fn new(expected: &'static str, found: Protocol, head: &[u8]) -> Self {
+        Self {
+            expected,
+            found,
+            head: head[..head.len().min(MAX_HEAD_LEN)].to_vec(),
+        }
+    }
+
+    /// Detects the protocol from the first bytes sent by the peer, and returns the mismatch if
+    /// it is detected as any protocol other than `exclude`, which is the one the client speaks
+    /// if it is a known one.
+    pub fn detect(expected: &'static str, head: &[u8], exclude: Option<Protocol>) -> Option<Self> {
+        match Protocol::detect(head) {
+            Some(found) if Some(found) != exclude => Some(Self::new(expected, found, head)),
+            _ => None,
+        }
+    }
+
+    /// The protocol expected by the client.
+    pub fn expected(&self) -> &'static str {
+        self.expected
+    }
+
+    /// The protocol spoken by the peer.
+    pub fn found(&self) -> Protocol {
+        self.found
+    }
+
+    /// The first bytes sent by the peer, at most [`MAX_HEAD_LEN`] of them.
+    pub fn head(&self) -> &[u8] {
+        &self.head
+    }
+}
+
+impl fmt::Display for ProtocolMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "expected {} but the peer speaks {}: {}, first bytes from the peer: [",
+            self.expected,
+            self.found,
+            self.found.hint()
+        )?;
+        for (i, b) in self.head.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{b:02x}")?;
+        }
+        f.write_str("]")
+    }
+}
+
+impl std::error::Error for ProtocolMismatch {}
+
+/// Reads the first bytes the peer at `target` sends back to a probe on a new connection, and
+/// detects the protocol from them.
+///
+/// The probe is the HTTP/2 connection preface, which every known [`Protocol`] answers. See
+/// [`ProtocolMismatch::detect`] for `exclude`.
+///
+/// This opens a connection of its own, and should only be called after a call to the target has
+/// failed to decode. Returns `None` if the peer could not be probed within `timeout`, or if it is
+/// not recognized.
+pub async fn diagnose(
+    target: &Address,
+    expected: &'static str,
+    exclude: Option<Protocol>,
+    timeout: Duration,
+) -> Option<ProtocolMismatch> {
+    let head = tokio::time::timeout(timeout, read_head(target, timeout))
+        .await
+        .ok()?
+        .ok()?;
+    ProtocolMismatch::detect(expected, &head, exclude)
+}
+
+async fn read_head(target: &Address, timeout: Duration) -> io::Result<Vec<u8>> {
+    let make_transport = {
+        let mut mt = DefaultMakeTransport::new();
+        mt.set_connect_timeout(Some(timeout));
+        mt
+    };
+    let (mut rh, mut wh) = make_transport.make_transport(target.clone()).await?;
+    // the peer may have closed the connection before reading the probe, e.g. an HTTP/2 server
+    // that sends its SETTINGS first, so the error of writing is ignored
+    let _ = wh.write_all(H2_PREFACE).await;
+
+    let mut head = Vec::with_capacity(MAX_HEAD_LEN);
+    let mut buf = [0u8; MAX_HEAD_LEN];
+    while head.len() < MAX_HEAD_LEN {
+        match rh.read(&mut buf[..MAX_HEAD_LEN - head.len()]).await {
+            Ok(0) => break,
+            Ok(n) => head.extend_from_slice(&buf[..n]),
+            Err(e) if head.is_empty() => return Err(e),
+            Err(_) => break,
+        }
+        if Protocol::detect(&head).is_some() {
+            break;
+        }
+    }
+    Ok(head)
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    const HTTP1: &[u8] = b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n";
+    const TLS_ALERT: &[u8] = &[0x15, 0x03, 0x03, 0x00, 0x02, 0x02, 0x46];
+    const H2_SETTINGS: &[u8] = &[
+        0x00, 0x00, 0x06, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x64,
+    ];
+
+    async fn stub(answer: &'static [u8]) -> Address {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut conn, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 64];
+                    let _ = conn.read(&mut buf).await;
+                    let _ = conn.write_all(answer).await;
+                });
+            }
+        });
+        Address::from(addr)
+    }
+
+    #[test]
+    fn detect() {
+        assert_eq!(Protocol::detect(HTTP1), Some(Protocol::Http1));
+        assert_eq!(Protocol::detect(TLS_ALERT), Some(Protocol::Tls));
+        assert_eq!(Protocol::detect(H2_SETTINGS), Some(Protocol::Http2));
+        assert_eq!(Protocol::detect(H2_PREFACE), Some(Protocol::Http2));
+        // a framed thrift message
+        assert_eq!(
+            Protocol::detect(&[0x00, 0x00, 0x00, 0x10, 0x80, 0x01, 0x00, 0x02, 0x00]),
+            None
+        );
+        assert_eq!(Protocol::detect(b""), None);
+    }
+
+    #[test]
+    fn display() {
+        let head = [0xab; 64];
+        let mismatch = ProtocolMismatch::new("thrift", Protocol::Tls, &head);
+        assert_eq!(mismatch.head().len(), MAX_HEAD_LEN);
+        let msg = mismatch.to_string();
+        assert!(msg.starts_with("expected thrift but the peer speaks TLS: "));
+        assert!(msg.ends_with(&format!("[{}ab]", "ab ".repeat(MAX_HEAD_LEN - 1))));
+    }
+
+    #[tokio::test]
+    async fn diagnose_stubs() {
+        for (answer, found) in [
+            (HTTP1, Protocol::Http1),
+            (TLS_ALERT, Protocol::Tls),
+            (H2_SETTINGS, Protocol::Http2),
+        ] {
+            let target = stub(answer).await;
+            let mismatch = diagnose(&target, "thrift", None, DEFAULT_DIAGNOSE_TIMEOUT)
+                .await
+                .unwrap();
+            assert_eq!(mismatch.found(), found);
+            assert_eq!(mismatch.head(), &answer[..answer.len().min(MAX_HEAD_LEN)]);
+        }
+
+        // the protocol of the client itself is not a mismatch
+        let target = stub(H2_SETTINGS).await;
+        assert!(diagnose(
+            &target,
+            "gRPC",
+            Some(Protocol::Http2),
+            DEFAULT_DIAGNOSE_TIMEOUT
+        )
+        .await
+        .is_none());
+    }
+}
@@ -22,7 +22,7 @@ pub enum DefaultIncoming {
     Tcp(#[pin] TcpListenerStream),
     #[cfg(target_family = "unix")]
     #[cfg_attr(docsrs, doc(cfg(target_family = "unix")))]
-    Unix(#[pin] UnixListenerStream),
+    Unix(#[pin] UnixListenerStream, UnixSocketFile),
 }
 
 impl MakeIncoming for DefaultIncoming {
@@ -37,7 +37,57 @@ impl MakeIncoming for DefaultIncoming {
 #[cfg_attr(docsrs, doc(cfg(target_family = "unix")))]
 impl From<UnixListener> for DefaultIncoming {
     fn from(l: UnixListener) -> Self {
-        DefaultIncoming::Unix(UnixListenerStream::new(l))
+        DefaultIncoming::Unix(UnixListenerStream::new(l), UnixSocketFile::default())
+    }
+}
+
+/// The socket file of a bound [`DefaultIncoming::Unix`], which is unlinked when the incoming is
+/// dropped, e.g. when the server is shut down cleanly.
+///
+/// The file is kept if the listener has been passed to the new process by the hot restart, or if
+/// the path has been bound again by another socket.
+#[cfg(target_family = "unix")]
+#[cfg_attr(docsrs, doc(cfg(target_family = "unix")))]
+#[derive(Debug, Default)]
+pub struct UnixSocketFile {
+    // the path, and the device and inode numbers of the file bound by us
+    bound: Option<(std::path::PathBuf, u64, u64)>,
+}
+
+#[cfg(target_family = "unix")]
+impl UnixSocketFile {
+    fn bound(path: &std::path::Path) -> Self {
+        use std::os::unix::fs::MetadataExt;
+
+        Self {
+            bound: std::fs::metadata(path)
+                .ok()
+                .map(|meta| (path.to_owned(), meta.dev(), meta.ino())),
+        }
+    }
+}
+
+#[cfg(target_family = "unix")]
+impl Drop for UnixSocketFile {
+    fn drop(&mut self) {
+        use std::os::unix::fs::MetadataExt;
+
+        let Some((path, dev, ino)) = self.bound.take() else {
+            return;
+        };
+        if path
+            .to_str()
+            .is_some_and(|path| crate::hotrestart::DEFAULT_HOT_RESTART.is_passed_to_child(path))
+        {
+            return;
+        }
+        let ours =
+            std::fs::metadata(&path).is_ok_and(|meta| meta.dev() == dev && meta.ino() == ino);
+        if ours {
+            if let Err(e) = std::fs::remove_file(&path) {
+                tracing::warn!("[VOLO] failed to unlink the socket file {path:?}: {e}");
+            }
+        }
     }
 }
 
@@ -68,42 +118,44 @@ pub trait MakeIncoming {
     fn make_incoming(self) -> impl Future<Output = io::Result<Self::Incoming>> + Send;
 }
 
-#[cfg(target_family = "unix")]
 impl MakeIncoming for Address {
     type Incoming = DefaultIncoming;
 
     async fn make_incoming(self) -> io::Result<Self::Incoming> {
-        match self {
-            Address::Ip(addr) => {
-                let listener = unix_helper::create_tcp_listener_with_max_backlog(addr).await;
-                TcpListener::from_std(listener?).map(DefaultIncoming::from)
-            }
-            Address::Unix(addr) => {
-                let listener = unix_helper::create_unix_listener_with_max_backlog(
-                    addr.as_pathname().ok_or_else(|| {
-                        io::Error::new(
-                            io::ErrorKind::AddrNotAvailable,
-                            "cannot create unnamed socket",
-                        )
-                    })?,
+        bind(self, true).await
+    }
+}
+
+/// Binds the address, where `reuse_port` sets `SO_REUSEPORT` on the TCP socket.
+#[cfg(target_family = "unix")]
+pub(crate) async fn bind(addr: Address, reuse_port: bool) -> io::Result<DefaultIncoming> {
+    match addr {
+        Address::Ip(addr) => {
+            let listener =
+                unix_helper::create_tcp_listener_with_max_backlog(addr, reuse_port).await;
+            TcpListener::from_std(listener?).map(DefaultIncoming::from)
+        }
+        Address::Unix(addr) => {
+            let path = addr.as_pathname().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::AddrNotAvailable,
+                    "cannot create unnamed socket",
                 )
-                .await;
-                UnixListener::from_std(listener?).map(DefaultIncoming::from)
-            }
+            })?;
+            let listener = unix_helper::create_unix_listener_with_max_backlog(path).await;
+            let listener = UnixListener::from_std(listener?)?;
+            Ok(DefaultIncoming::Unix(
+                UnixListenerStream::new(listener),
+                UnixSocketFile::bound(path),
+            ))
         }
     }
 }
 
 #[cfg(not(target_family = "unix"))]
-impl MakeIncoming for Address {
-    type Incoming = DefaultIncoming;
-
-    async fn make_incoming(self) -> io::Result<Self::Incoming> {
-        match self {
-            Address::Ip(addr) => TcpListener::bind(addr).await.map(DefaultIncoming::from),
-            #[cfg(target_family = "unix")]
-            Address::Unix(addr) => UnixListener::bind(addr).map(DefaultIncoming::from),
-        }
+pub(crate) async fn bind(addr: Address, _reuse_port: bool) -> io::Result<DefaultIncoming> {
+    match addr {
+        Address::Ip(addr) => TcpListener::bind(addr).await.map(DefaultIncoming::from),
     }
 }
 
@@ -114,7 +166,7 @@ impl Stream for DefaultIncoming {
         match self.project() {
             IncomingProj::Tcp(s) => s.poll_next(cx).map_ok(Conn::from),
             #[cfg(target_family = "unix")]
-            IncomingProj::Unix(s) => s.poll_next(cx).map_ok(Conn::from),
+            IncomingProj::Unix(s, _) => s.poll_next(cx).map_ok(Conn::from),
         }
     }
 }
@@ -252,6 +304,7 @@ mod unix_helper {
 
     pub async fn create_tcp_listener_with_max_backlog(
         addr: SocketAddr,
+        reuse_port: bool,
     ) -> std::io::Result<TcpListener> {
         if let Ok(Some(raw_fd)) = DEFAULT_HOT_RESTART
             .dup_parent_listener_sock(addr.to_string())
@@ -271,7 +324,7 @@ mod unix_helper {
         let socket = Socket::new(domain, Type::STREAM, Some(Protocol::TCP))?;
         socket.set_reuse_address(true)?;
         socket.set_nonblocking(true)?;
-        socket.set_reuse_port(true)?;
+        socket.set_reuse_port(reuse_port)?;
         socket.set_cloexec(true)?;
 
         socket.bind(&socket2::SockAddr::from(addr))?;
@@ -328,3 +381,32 @@ mod unix_helper {
         }
     }
 }
+
+#[cfg(all(test, target_family = "unix"))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn unlink_unix_socket_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.sock");
+
+        let incoming = Address::unix(&path).unwrap().make_incoming().await.unwrap();
+        assert!(path.exists());
+        drop(incoming);
+        assert!(!path.exists());
+
+        // the path is bound again by another socket
+        let incoming = Address::unix(&path).unwrap().make_incoming().await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let _other = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        drop(incoming);
+        assert!(path.exists());
+
+        // not bound by us
+        let path = dir.path().join("from.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        drop(DefaultIncoming::from(listener));
+        assert!(path.exists());
+    }
+}
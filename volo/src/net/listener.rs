@@ -0,0 +1,321 @@
+//! Listening on multiple addresses with one server.
+//!
+//! [`Listeners`] is a [`MakeIncoming`] binding all of its [`Listener`]s, so it can be passed to the
+//! `run` of any server, and the connections accepted by all the listeners are dispatched into the
+//! same service. Each listener has its own settings, and the name of the listener accepting a
+//! connection is recorded in the [`ConnInfo`](super::conn::ConnInfo) of the connection, and in the
+//! extensions of the context of each request as [`ListenerName`].
+//!
+//! The TLS handshakes of the listeners with a TLS config are done in the background, so a slow
+//! handshake never blocks the other listeners. The servers still do the handshakes of their own
+//! TLS config on the TCP connections, so the TLS should be configured either on the server or on
+//! the listeners, but not both.
+//!
+//! When the server stops accepting for graceful shutdown, all the listeners are closed together,
+//! and the connections of all the listeners are drained by the server in the same way.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use volo::net::listener::{Listener, Listeners};
+//!
+//! let listeners = Listeners::new()
+//!     .listener(Listener::new("external", external_addr).tls_config(tls_config))
+//!     .listener(Listener::new("sidecar", uds_addr));
+//!
+//! Server::new()
+//!     .add_service(ServiceBuilder::new(GreeterServer::new(S)).build())
+//!     .run(listeners)
+//!     .await
+//!     .unwrap();
+//! ```
+
+use std::{fmt, io};
+
+use tokio::{sync::mpsc, task::JoinSet};
+
+#[cfg(feature = "__tls")]
+use super::tls::{Acceptor, ServerTlsConfig};
+use super::{
+    conn::{Conn, ConnStream},
+    incoming::{self, DefaultIncoming, Incoming, MakeIncoming},
+    Address,
+};
+use crate::FastStr;
+
+/// The number of accepted connections waiting for the server.
+const BACKLOG: usize = 128;
+
+/// The name of the listener accepting the connection of a request, which is inserted into the
+/// extensions of the context by the servers.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ListenerName(pub FastStr);
+
+enum Bind {
+    Address(Address),
+    Incoming(DefaultIncoming),
+}
+
+/// A named listener with its own settings.
+pub struct Listener {
+    name: FastStr,
+    bind: Bind,
+    nodelay: bool,
+    reuse_port: bool,
+    #[cfg(feature = "__tls")]
+    tls_config: Option<ServerTlsConfig>,
+}
+
+impl Listener {
+    /// Creates a [`Listener`] binding the address when the server starts.
+    pub fn new(name: impl Into<FastStr>, addr: impl Into<Address>) -> Self {
+        Self::with_bind(name.into(), Bind::Address(addr.into()))
+    }
+
+    /// Creates a [`Listener`] from a bound listener, e.g. a [`tokio::net::TcpListener`].
+    pub fn from_incoming(name: impl Into<FastStr>, incoming: impl Into<DefaultIncoming>) -> Self {
+        Self::with_bind(name.into(), Bind::Incoming(incoming.into()))
+    }
+
+    fn with_bind(name: FastStr, bind: Bind) -> Self {
+        Self {
+            name,
+            bind,
+            nodelay: true,
+            reuse_port: true,
+            #[cfg(feature = "__tls")]
+            tls_config: None,
+        }
+    }
+
+    /// Sets `TCP_NODELAY` on the accepted TCP connections.
+    ///
+    /// Default is `true`.
+    pub fn nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = nodelay;
+        self
+    }
+
+    /// Sets `SO_REUSEPORT` on the TCP socket, which has no effect on the listeners created by
+    /// [`Listener::from_incoming`].
+    ///
+    /// Default is `true`.
+    pub fn reuse_port(mut self, reuse_port: bool) -> Self {
+        self.reuse_port = reuse_port;
+        self
+    }
+
+    /// Accepts the connections by TLS, which is only supported on TCP.
+    ///
+    /// Default is `None`.
+    #[cfg(feature = "__tls")]
+    #[cfg_attr(docsrs, doc(cfg(any(feature = "rustls", feature = "native-tls"))))]
+    pub fn tls_config(mut self, tls_config: ServerTlsConfig) -> Self {
+        self.tls_config = Some(tls_config);
+        self
+    }
+}
+
+/// The listeners of a server, see the [module level documentation](self).
+#[derive(Default)]
+pub struct Listeners {
+    listeners: Vec<Listener>,
+}
+
+impl Listeners {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a listener.
+    pub fn listener(mut self, listener: Listener) -> Self {
+        self.listeners.push(listener);
+        self
+    }
+}
+
+impl MakeIncoming for Listeners {
+    type Incoming = MultiIncoming;
+
+    async fn make_incoming(self) -> io::Result<Self::Incoming> {
+        // binds all the listeners before accepting, so the server fails to start if any of them
+        // fails
+        let mut bound = Vec::with_capacity(self.listeners.len());
+        for listener in self.listeners {
+            let incoming = match listener.bind {
+                Bind::Address(addr) => incoming::bind(addr, listener.reuse_port).await?,
+                Bind::Incoming(incoming) => incoming,
+            };
+            #[cfg(feature = "__tls")]
+            if listener.tls_config.is_some() && !matches!(incoming, DefaultIncoming::Tcp(_)) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("listener {}: TLS is only supported on TCP", listener.name),
+                ));
+            }
+            let settings = Settings {
+                name: listener.name,
+                nodelay: listener.nodelay,
+                #[cfg(feature = "__tls")]
+                tls_config: listener.tls_config,
+            };
+            bound.push((settings, incoming));
+        }
+
+        let (tx, rx) = mpsc::channel(BACKLOG);
+        let mut names = Vec::with_capacity(bound.len());
+        let mut tasks = JoinSet::new();
+        for (settings, incoming) in bound {
+            names.push(settings.name.clone());
+            tasks.spawn(accept(settings, incoming, tx.clone()));
+        }
+
+        Ok(MultiIncoming {
+            names,
+            rx,
+            _tasks: tasks,
+        })
+    }
+}
+
+struct Settings {
+    name: FastStr,
+    nodelay: bool,
+    #[cfg(feature = "__tls")]
+    tls_config: Option<ServerTlsConfig>,
+}
+
+/// Accepts the connections of a listener until the [`MultiIncoming`] is dropped.
+async fn accept(
+    settings: Settings,
+    mut incoming: DefaultIncoming,
+    tx: mpsc::Sender<io::Result<Conn>>,
+) {
+    loop {
+        let mut conn = match incoming.accept().await {
+            Ok(Some(conn)) => conn,
+            Ok(None) => return,
+            Err(e) => {
+                let _ = tx.send(Err(e)).await;
+                return;
+            }
+        };
+        conn.info.listener = Some(settings.name.clone());
+        if let ConnStream::Tcp(stream) = &conn.stream {
+            let _ = stream.set_nodelay(settings.nodelay);
+        }
+
+        #[cfg(feature = "__tls")]
+        let conn = match (conn, &settings.tls_config) {
+            (
+                Conn {
+                    stream: ConnStream::Tcp(stream),
+                    mut info,
+                },
+                Some(tls_config),
+            ) => {
+                let acceptor = tls_config.acceptor.clone();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    let start = std::time::Instant::now();
+                    match acceptor.accept(stream).await {
+                        Ok(stream) => {
+                            info.handshake = Some(start.elapsed());
+                            let _ = tx.send(Ok(Conn { stream, info })).await;
+                        }
+                        Err(e) => tracing::debug!("[VOLO] TLS handshake error: {e:?}"),
+                    }
+                });
+                continue;
+            }
+            (conn, _) => conn,
+        };
+
+        if tx.send(Ok(conn)).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// The [`Incoming`] of [`Listeners`], which accepts the connections of all the listeners.
+///
+/// All the listeners are closed when it is dropped.
+pub struct MultiIncoming {
+    names: Vec<FastStr>,
+    rx: mpsc::Receiver<io::Result<Conn>>,
+    _tasks: JoinSet<()>,
+}
+
+impl fmt::Debug for MultiIncoming {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MultiIncoming")
+            .field("listeners", &self.names)
+            .finish()
+    }
+}
+
+impl Incoming for MultiIncoming {
+    async fn accept(&mut self) -> io::Result<Option<Conn>> {
+        self.rx.recv().await.transpose()
+    }
+}
+
+#[cfg(all(test, target_family = "unix"))]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream, UnixStream},
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn tcp_and_unix() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sidecar.sock");
+
+        let tcp = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let tcp_addr = tcp.local_addr().unwrap();
+        let listeners = Listeners::new()
+            .listener(Listener::from_incoming("external", tcp).nodelay(false))
+            .listener(Listener::new(
+                "sidecar",
+                Address::from(std::os::unix::net::SocketAddr::from_pathname(&path).unwrap()),
+            ));
+        let mut incoming = listeners.make_incoming().await.unwrap();
+        assert_eq!(
+            format!("{incoming:?}"),
+            r#"MultiIncoming { listeners: ["external", "sidecar"] }"#
+        );
+
+        let mut client = TcpStream::connect(tcp_addr).await.unwrap();
+        let mut conn = incoming.accept().await.unwrap().unwrap();
+        assert_eq!(conn.info.listener.as_deref(), Some("external"));
+        let ConnStream::Tcp(stream) = &conn.stream else {
+            panic!("unexpected stream");
+        };
+        assert!(!stream.nodelay().unwrap());
+        client.write_all(b"tcp").await.unwrap();
+        let mut buf = [0u8; 3];
+        conn.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"tcp");
+
+        let mut client = UnixStream::connect(&path).await.unwrap();
+        let mut conn = incoming.accept().await.unwrap().unwrap();
+        assert_eq!(conn.info.listener.as_deref(), Some("sidecar"));
+        assert!(matches!(conn.stream, ConnStream::Unix(_)));
+        client.write_all(b"uds").await.unwrap();
+        conn.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"uds");
+
+        // all the listeners are closed with the incoming
+        drop(incoming);
+        for _ in 0..100 {
+            if TcpStream::connect(tcp_addr).await.is_err() {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("the listener is not closed");
+    }
+}
@@ -1,11 +1,15 @@
 pub mod conn;
+pub mod conn_stats;
 pub mod dial;
+pub mod fingerprint;
 pub mod incoming;
+pub mod listener;
 #[cfg(feature = "__tls")]
 #[cfg_attr(docsrs, doc(cfg(any(feature = "rustls", feature = "native-tls"))))]
 pub mod tls;
 
-mod probe;
+pub mod probe;
+pub mod timestamp;
 
 #[cfg(target_os = "linux")]
 use std::os::linux::net::SocketAddrExt;
@@ -84,6 +88,13 @@ impl Hash for Address {
 }
 
 impl Address {
+    /// Creates the address of the unix domain socket bound to the path.
+    #[cfg(target_family = "unix")]
+    #[cfg_attr(docsrs, doc(cfg(target_family = "unix")))]
+    pub fn unix(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        StdUnixSocketAddr::from_pathname(path).map(Self::Unix)
+    }
+
     pub fn favor_dual_stack(self) -> Self {
         match self {
             Address::Ip(addr) => {
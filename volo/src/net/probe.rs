@@ -1,11 +1,19 @@
+//! Probes for the capabilities of the runtime environment.
+//!
+//! These are used internally to pick sane defaults, and are also exposed so that applications
+//! and `volo doctor` can check the environment before serving.
+
 use lazy_static::lazy_static;
 use socket2::{Domain, Protocol, Socket, Type};
 
+/// The IP stack capability of the host.
 #[derive(Debug)]
 pub struct IpStackCapability {
+    /// Whether an IPv4 socket can be created.
     pub ipv4: bool,
-    #[allow(dead_code)]
+    /// Whether an IPv6 socket can be bound to the loopback address.
     pub ipv6: bool,
+    /// Whether IPv6 sockets accept IPv4-mapped addresses by default.
     pub ipv4_mapped_ipv6: bool,
 }
 
@@ -45,6 +53,7 @@ impl IpStackCapability {
     }
 }
 
+/// Returns the IP stack capability of the host, which is probed only once.
 pub fn probe() -> &'static IpStackCapability {
     lazy_static! {
         static ref CAPABILITY: IpStackCapability = IpStackCapability::probe();
@@ -52,6 +61,42 @@ pub fn probe() -> &'static IpStackCapability {
     &CAPABILITY
 }
 
+/// Returns the soft limit of open file descriptors of the current process.
+///
+/// Returns `None` if the limit is unknown or unlimited.
+#[cfg(target_family = "unix")]
+#[cfg_attr(docsrs, doc(cfg(target_family = "unix")))]
+pub fn fd_limit() -> Option<u64> {
+    let mut rlim = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: `rlim` is a valid pointer to `rlimit`.
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut rlim) } != 0 {
+        return None;
+    }
+    if rlim.rlim_cur == libc::RLIM_INFINITY {
+        return None;
+    }
+    // `rlim_t` is not `u64` on all the platforms.
+    #[allow(clippy::unnecessary_cast)]
+    let limit = rlim.rlim_cur as u64;
+    Some(limit)
+}
+
+/// Checks whether the fd limit of the current process is enough to hold `required` file
+/// descriptors, e.g. the total size of the connection pools and listeners.
+///
+/// Returns the current limit as the error if it is not enough.
+#[cfg(target_family = "unix")]
+#[cfg_attr(docsrs, doc(cfg(target_family = "unix")))]
+pub fn check_fd_limit(required: u64) -> Result<(), u64> {
+    match fd_limit() {
+        Some(limit) if limit < required => Err(limit),
+        _ => Ok(()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,203 @@
+//! Receive timestamps of the connections given by the kernel.
+//!
+//! With the `kernel-timestamp` feature on Linux, `SO_TIMESTAMPNS` is enabled on the TCP
+//! connections, and the read half of a connection records the time when the kernel received the
+//! last segment it reads, which is more accurate than the time the bytes are read by the runtime
+//! since it doesn't include the time waiting for the task to be polled.
+//!
+//! Without the feature, the [`RecvTimestamp`] of a connection is never set.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// The kernel receive timestamp of the last segment read from a connection, shared by the read half
+/// of the connection and the contexts of the requests read from it.
+#[derive(Debug, Clone, Default)]
+pub struct RecvTimestamp(Arc<AtomicU64>);
+
+impl RecvTimestamp {
+    /// Returns the time when the kernel received the last segment read, or `None` if no segment
+    /// has been read with a timestamp.
+    pub fn get(&self) -> Option<SystemTime> {
+        match self.0.load(Ordering::Relaxed) {
+            0 => None,
+            nanos => Some(UNIX_EPOCH + Duration::from_nanos(nanos)),
+        }
+    }
+
+    #[cfg_attr(
+        not(all(target_os = "linux", feature = "kernel-timestamp")),
+        allow(dead_code)
+    )]
+    pub(crate) fn set(&self, time: SystemTime) {
+        if let Ok(nanos) = time.duration_since(UNIX_EPOCH) {
+            self.0.store(nanos.as_nanos() as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "kernel-timestamp"))]
+pub use self::linux::TimestampedReadHalf;
+
+#[cfg(all(target_os = "linux", feature = "kernel-timestamp"))]
+mod linux {
+    use std::{
+        io,
+        os::fd::{AsRawFd, RawFd},
+        pin::Pin,
+        task::{ready, Context, Poll},
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    use tokio::{
+        io::{AsyncRead, Interest, ReadBuf},
+        net::tcp,
+    };
+
+    use super::RecvTimestamp;
+
+    /// The read half of a TCP connection reading by `recvmsg` with the receive timestamps.
+    #[derive(Debug)]
+    pub struct TimestampedReadHalf {
+        inner: tcp::OwnedReadHalf,
+        timestamp: RecvTimestamp,
+    }
+
+    impl TimestampedReadHalf {
+        /// Enables `SO_TIMESTAMPNS` on the connection, the timestamps will be missing if it fails.
+        pub fn new(inner: tcp::OwnedReadHalf) -> Self {
+            let enable: libc::c_int = 1;
+            // SAFETY: the fd is valid and the option value is a c_int
+            let ret = unsafe {
+                libc::setsockopt(
+                    inner.as_ref().as_raw_fd(),
+                    libc::SOL_SOCKET,
+                    libc::SO_TIMESTAMPNS,
+                    &enable as *const _ as *const libc::c_void,
+                    std::mem::size_of_val(&enable) as libc::socklen_t,
+                )
+            };
+            if ret != 0 {
+                tracing::warn!(
+                    "[VOLO] failed to enable SO_TIMESTAMPNS: {}",
+                    io::Error::last_os_error()
+                );
+            }
+            Self {
+                inner,
+                timestamp: RecvTimestamp::default(),
+            }
+        }
+
+        pub fn recv_timestamp(&self) -> &RecvTimestamp {
+            &self.timestamp
+        }
+
+        pub fn into_inner(self) -> tcp::OwnedReadHalf {
+            self.inner
+        }
+    }
+
+    impl AsyncRead for TimestampedReadHalf {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            let this = self.get_mut();
+            let stream = this.inner.as_ref();
+            loop {
+                ready!(stream.poll_read_ready(cx))?;
+                let unfilled = buf.initialize_unfilled();
+                let fd = stream.as_raw_fd();
+                match stream.try_io(Interest::READABLE, || recv_with_timestamp(fd, unfilled)) {
+                    Ok((n, time)) => {
+                        if let Some(time) = time {
+                            this.timestamp.set(time);
+                        }
+                        buf.advance(n);
+                        return Poll::Ready(Ok(()));
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                    Err(e) => return Poll::Ready(Err(e)),
+                }
+            }
+        }
+    }
+
+    /// Reads by `recvmsg`, and returns the timestamp in the control message.
+    fn recv_with_timestamp(fd: RawFd, buf: &mut [u8]) -> io::Result<(usize, Option<SystemTime>)> {
+        let mut iov = libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        };
+        // aligned for `cmsghdr`, and large enough for a `timespec`
+        let mut control = [0u64; 8];
+        // SAFETY: an all-zero `msghdr` is valid
+        let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = std::mem::size_of_val(&control) as _;
+
+        // SAFETY: the buffers in `msg` are valid during the call
+        let n = unsafe { libc::recvmsg(fd, &mut msg, 0) };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut time = None;
+        // SAFETY: the control messages are filled by the kernel and bounded by `msg_controllen`
+        unsafe {
+            let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+            while !cmsg.is_null() {
+                if (*cmsg).cmsg_level == libc::SOL_SOCKET
+                    && (*cmsg).cmsg_type == libc::SCM_TIMESTAMPNS
+                {
+                    let ts =
+                        std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::timespec);
+                    time = Some(UNIX_EPOCH + Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32));
+                }
+                cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+            }
+        }
+        Ok((n as usize, time))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use tokio::{
+            io::{AsyncReadExt, AsyncWriteExt},
+            net::{TcpListener, TcpStream},
+        };
+
+        use super::*;
+
+        #[tokio::test]
+        async fn recv_timestamp() {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let mut client = TcpStream::connect(listener.local_addr().unwrap())
+                .await
+                .unwrap();
+            let (server, _) = listener.accept().await.unwrap();
+            let (rh, _wh) = server.into_split();
+            let mut rh = TimestampedReadHalf::new(rh);
+            assert!(rh.recv_timestamp().get().is_none());
+
+            let before = SystemTime::now();
+            client.write_all(b"hello").await.unwrap();
+            let mut buf = [0u8; 5];
+            rh.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"hello");
+
+            let received = rh.recv_timestamp().get().unwrap();
+            assert!(received >= before - Duration::from_millis(1));
+            assert!(received <= SystemTime::now());
+        }
+    }
+}
@@ -3,21 +3,25 @@ use std::{
     future::Future,
     io::{self, Result},
     path::Path,
+    sync::Arc,
     time::Duration,
 };
 
 use motore::{make::MakeConnection, UnaryService};
 use tokio::net::TcpStream;
-#[cfg(target_family = "unix")]
-use tokio::net::UnixStream;
 
+#[cfg(target_family = "unix")]
+use super::dial::make_unix_connection;
 use super::{
     conn::ConnStream,
-    dial::{make_tcp_connection, Config, MakeTransport},
+    dial::{make_tcp_connection, Config, ConnectRetry, MakeTransport},
 };
-use crate::net::{
-    conn::{Conn, OwnedReadHalf, OwnedWriteHalf},
-    Address,
+use crate::{
+    context::identity::TlsInfo,
+    net::{
+        conn::{Conn, OwnedReadHalf, OwnedWriteHalf},
+        Address,
+    },
 };
 
 #[cfg(feature = "native-tls")]
@@ -28,6 +32,10 @@ mod rustls;
 #[cfg(feature = "native-tls")]
 use self::native_tls::{NativeTlsAcceptor, NativeTlsConnector};
 #[cfg(feature = "rustls")]
+pub use self::rustls::{
+    ReloadableCertResolver, SniCertificate, SniConfig, DEFAULT_SNI_CERTIFICATE,
+};
+#[cfg(feature = "rustls")]
 use self::rustls::{RustlsAcceptor, RustlsConnector};
 
 /// A wrapper around [`tokio_rustls::TlsConnector`] and [`tokio_native_tls::TlsConnector`].
@@ -85,6 +93,19 @@ impl TlsConnector {
     pub fn builder() -> TlsConnectorBuilder {
         TlsConnectorBuilder::default()
     }
+
+    /// Whether any root certificate is trusted, which is false if the connector is built with
+    /// [`TlsConnectorBuilder::enable_default_root_certs`] disabled and without any
+    /// [`TlsConnectorBuilder::add_pem`], so the handshakes with all the servers fail.
+    pub fn has_root_certs(&self) -> bool {
+        match self {
+            #[cfg(feature = "rustls")]
+            Self::Rustls(connector) => connector.has_root_certs(),
+
+            #[cfg(feature = "native-tls")]
+            Self::NativeTls(connector) => !connector.no_root_certs,
+        }
+    }
 }
 
 impl Connector for TlsConnector {
@@ -131,6 +152,39 @@ impl Acceptor for TlsAcceptor {
     }
 }
 
+impl TlsAcceptor {
+    /// Returns the parameters negotiated by the TLS handshake of the stream accepted by this
+    /// acceptor, with the name of the certificate selected by the SNI, see [`SniConfig`].
+    pub fn tls_info(&self, stream: &ConnStream) -> Option<Arc<TlsInfo>> {
+        let info = stream.tls_info()?;
+        match self {
+            #[cfg(feature = "rustls")]
+            Self::Rustls(acceptor) => {
+                let Some(certificate) =
+                    acceptor.certificate(info.server_name().map(|name| name.as_str()))
+                else {
+                    return Some(info);
+                };
+                let info = Arc::try_unwrap(info).unwrap_or_else(|info| (*info).clone());
+                Some(Arc::new(info.with_certificate(certificate)))
+            }
+            #[cfg(feature = "native-tls")]
+            Self::NativeTls(_) => Some(info),
+        }
+    }
+
+    /// Whether there's any certificate to serve the connections, which is false for the acceptor
+    /// built from a [`SniConfig`] without any name or the default certificate.
+    pub fn has_certs(&self) -> bool {
+        match self {
+            #[cfg(feature = "rustls")]
+            Self::Rustls(acceptor) => acceptor.has_certs(),
+            #[cfg(feature = "native-tls")]
+            Self::NativeTls(_) => true,
+        }
+    }
+}
+
 impl fmt::Debug for TlsConnector {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -155,9 +209,14 @@ impl fmt::Debug for TlsAcceptor {
     }
 }
 
+const DEFAULT_SESSION_CACHE_SIZE: usize = 256;
+
 pub struct TlsConnectorBuilder {
     pub(super) default_root_certs: bool,
     pub(super) pems: Vec<Vec<u8>>,
+    pub(super) session_cache_size: usize,
+    #[cfg(feature = "rustls")]
+    pub(super) client_cert_resolver: Option<ReloadableCertResolver>,
 }
 
 impl Default for TlsConnectorBuilder {
@@ -165,6 +224,9 @@ impl Default for TlsConnectorBuilder {
         Self {
             default_root_certs: true,
             pems: Vec::new(),
+            session_cache_size: DEFAULT_SESSION_CACHE_SIZE,
+            #[cfg(feature = "rustls")]
+            client_cert_resolver: None,
         }
     }
 }
@@ -189,6 +251,29 @@ impl TlsConnectorBuilder {
         Ok(self)
     }
 
+    /// Sets the number of the TLS sessions cached for resumption, where `0` disables the
+    /// resumption.
+    ///
+    /// A resumed connection skips the certificate exchange of the full handshake by the session
+    /// ticket of a previous connection to the same server.
+    ///
+    /// This only works for rustls, native-tls manages the sessions by itself.
+    ///
+    /// Default is `256`.
+    pub fn session_cache_size(mut self, size: usize) -> Self {
+        self.session_cache_size = size;
+        self
+    }
+
+    /// Authenticates the client by the certificate of the resolver, which can be updated at
+    /// runtime.
+    #[cfg(feature = "rustls")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "rustls")))]
+    pub fn client_cert_resolver(mut self, resolver: ReloadableCertResolver) -> Self {
+        self.client_cert_resolver = Some(resolver);
+        self
+    }
+
     #[cfg(feature = "rustls")]
     pub fn build(self) -> Result<TlsConnector> {
         Self::build_rustls(self)
@@ -248,6 +333,15 @@ impl ServerTlsConfig {
         let key = std::fs::read(key_path.as_ref())?;
         Self::from_pem(cert, key)
     }
+
+    /// Selects the certificate of each connection by the server name indicated by the client.
+    #[cfg(feature = "rustls")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "rustls")))]
+    pub fn from_sni(config: SniConfig) -> Self {
+        Self {
+            acceptor: config.into(),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -283,14 +377,7 @@ impl UnaryService<Address> for TlsMakeTransport {
                 }
             }
             #[cfg(target_family = "unix")]
-            Address::Unix(addr) => UnixStream::connect(addr.as_pathname().ok_or_else(|| {
-                io::Error::new(
-                    io::ErrorKind::AddrNotAvailable,
-                    "cannot connect to unnamed socket",
-                )
-            })?)
-            .await
-            .map(Conn::from),
+            Address::Unix(addr) => make_unix_connection(&self.cfg, &addr).await.map(Conn::from),
         }
     }
 }
@@ -316,4 +403,8 @@ impl MakeTransport for TlsMakeTransport {
     fn set_write_timeout(&mut self, timeout: Option<Duration>) {
         self.cfg = self.cfg.with_write_timeout(timeout);
     }
+
+    fn set_connect_retry(&mut self, retry: Option<ConnectRetry>) {
+        self.cfg = self.cfg.with_connect_retry(retry);
+    }
 }
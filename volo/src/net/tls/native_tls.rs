@@ -9,7 +9,12 @@ use crate::net::conn::ConnStream;
 
 /// A wrapper for [`tokio_native_tls::TlsConnector`]
 #[derive(Clone)]
-pub struct NativeTlsConnector(pub(super) Arc<TlsConnector>);
+pub struct NativeTlsConnector {
+    pub(super) connector: Arc<TlsConnector>,
+    /// Whether the connector is built without any root certificate, the ones converted from the
+    /// connectors of native-tls are assumed to have.
+    pub(super) no_root_certs: bool,
+}
 
 /// A wrapper for [`tokio_native_tls::TlsAcceptor`]
 #[derive(Clone)]
@@ -23,6 +28,14 @@ impl Default for NativeTlsConnector {
 
 impl Connector for NativeTlsConnector {
     fn build(config: TlsConnectorBuilder) -> Result<Self> {
+        #[cfg(feature = "rustls")]
+        if config.client_cert_resolver.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "client certificate resolver is not supported by native-tls",
+            ));
+        }
+        let no_root_certs = !config.default_root_certs && config.pems.is_empty();
         let mut builder = native_tls::TlsConnector::builder();
         builder.disable_built_in_roots(!config.default_root_certs);
         for pem in config.pems {
@@ -33,12 +46,15 @@ impl Connector for NativeTlsConnector {
         let connector = builder
             .build()
             .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
-        Ok(Self(Arc::new(TlsConnector::from(connector))))
+        Ok(Self {
+            connector: Arc::new(TlsConnector::from(connector)),
+            no_root_certs,
+        })
     }
 
     async fn connect(&self, server_name: &str, tcp_stream: TcpStream) -> Result<Conn> {
         tracing::trace!("NativeTlsConnector::connect({server_name})");
-        self.0
+        self.connector
             .connect(server_name, tcp_stream)
             .await
             .map(Conn::from)
@@ -76,19 +92,28 @@ impl Acceptor for NativeTlsAcceptor {
 
 impl From<native_tls::TlsConnector> for super::TlsConnector {
     fn from(value: native_tls::TlsConnector) -> Self {
-        Self::NativeTls(NativeTlsConnector(Arc::new(TlsConnector::from(value))))
+        Self::NativeTls(NativeTlsConnector {
+            connector: Arc::new(TlsConnector::from(value)),
+            no_root_certs: false,
+        })
     }
 }
 
 impl From<TlsConnector> for super::TlsConnector {
     fn from(value: TlsConnector) -> Self {
-        Self::NativeTls(NativeTlsConnector(Arc::new(value)))
+        Self::NativeTls(NativeTlsConnector {
+            connector: Arc::new(value),
+            no_root_certs: false,
+        })
     }
 }
 
 impl From<Arc<TlsConnector>> for super::TlsConnector {
     fn from(value: Arc<TlsConnector>) -> Self {
-        Self::NativeTls(NativeTlsConnector(value))
+        Self::NativeTls(NativeTlsConnector {
+            connector: value,
+            no_root_certs: false,
+        })
     }
 }
 
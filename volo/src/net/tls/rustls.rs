@@ -1,20 +1,127 @@
-use std::{io, io::Result, path::Path, sync::Arc};
+use std::{
+    collections::HashMap,
+    fmt, io,
+    io::Result,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, SystemTime},
+};
 
-use rustls::{pki_types::ServerName, RootCertStore, ServerConfig};
+use rustls::{
+    client::{ResolvesClientCert, Resumption},
+    pki_types::ServerName,
+    server::{danger::ClientCertVerifier, ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+    RootCertStore, ServerConfig, SignatureScheme,
+};
 use rustls_pki_types::PrivateKeyDer;
 use tokio::net::TcpStream;
-use tokio_rustls::{rustls::ClientConfig, TlsAcceptor, TlsConnector};
+use tokio_rustls::{rustls::ClientConfig, LazyConfigAcceptor, TlsAcceptor, TlsConnector};
 
 use super::{Acceptor, Connector, TlsConnectorBuilder};
-use crate::net::conn::{Conn, ConnStream};
+use crate::{
+    net::conn::{Conn, ConnStream},
+    FastStr,
+};
 
 /// A wrapper for [`tokio_rustls::TlsConnector`]
 #[derive(Clone)]
-pub struct RustlsConnector(pub(super) TlsConnector);
+pub struct RustlsConnector {
+    connector: TlsConnector,
+    reload: Option<Arc<Reload>>,
+    /// Whether the connector is built without any root certificate, the ones converted from the
+    /// configs of rustls are assumed to have.
+    no_root_certs: bool,
+}
+
+impl RustlsConnector {
+    fn new(connector: TlsConnector) -> Self {
+        Self {
+            connector,
+            reload: None,
+            no_root_certs: false,
+        }
+    }
 
-/// A wrapper for [`tokio_rustls::TlsAcceptor`]
+    pub(super) fn has_root_certs(&self) -> bool {
+        !self.no_root_certs
+    }
+
+    fn connector(&self) -> TlsConnector {
+        match &self.reload {
+            Some(reload) => reload.connector(),
+            None => self.connector.clone(),
+        }
+    }
+}
+
+/// Renews the session cache after the client certificate is updated, so that the new connections
+/// are authenticated by the new certificate instead of resuming the sessions of the old one.
+struct Reload {
+    resolver: ReloadableCertResolver,
+    config: Arc<ClientConfig>,
+    session_cache_size: usize,
+    /// The connector of the generation of the certificate.
+    current: RwLock<(u64, TlsConnector)>,
+}
+
+impl Reload {
+    fn connector(&self) -> TlsConnector {
+        let generation = self.resolver.generation();
+        {
+            let current = self.current.read().unwrap();
+            if current.0 == generation {
+                return current.1.clone();
+            }
+        }
+        let mut current = self.current.write().unwrap();
+        if current.0 != generation {
+            let mut config = (*self.config).clone();
+            config.resumption = resumption(self.session_cache_size);
+            *current = (generation, TlsConnector::from(Arc::new(config)));
+        }
+        current.1.clone()
+    }
+}
+
+fn resumption(session_cache_size: usize) -> Resumption {
+    if session_cache_size == 0 {
+        Resumption::disabled()
+    } else {
+        Resumption::in_memory_sessions(session_cache_size)
+    }
+}
+
+/// A wrapper for [`tokio_rustls::TlsAcceptor`], or the acceptor selecting the certificate by the
+/// SNI of each connection.
 #[derive(Clone)]
-pub struct RustlsAcceptor(pub(super) TlsAcceptor);
+pub enum RustlsAcceptor {
+    Single(TlsAcceptor),
+    Sni(Arc<SniAcceptor>),
+}
+
+impl RustlsAcceptor {
+    /// Whether there's any certificate to serve the connections, which is false for an empty
+    /// [`SniConfig`].
+    pub(super) fn has_certs(&self) -> bool {
+        match self {
+            Self::Single(_) => true,
+            Self::Sni(sni) => !sni.names.is_empty() || sni.default.is_some(),
+        }
+    }
+
+    /// Returns the name of the certificate selected for the server name, see
+    /// [`TlsInfo::certificate`](crate::context::identity::TlsInfo::certificate).
+    pub(super) fn certificate(&self, server_name: Option<&str>) -> Option<FastStr> {
+        match self {
+            Self::Single(_) => None,
+            Self::Sni(sni) => sni.select(server_name).map(|entry| entry.name.clone()),
+        }
+    }
+}
 
 impl Default for RustlsConnector {
     fn default() -> Self {
@@ -44,11 +151,29 @@ impl Connector for RustlsConnector {
                 .add(cert)
                 .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
         }
-        let client_config = ClientConfig::builder()
-            .with_root_certificates(certs)
-            .with_no_client_auth();
-        let connector = TlsConnector::from(Arc::new(client_config));
-        Ok(Self(connector))
+        let no_root_certs = certs.is_empty();
+        let client_config = ClientConfig::builder().with_root_certificates(certs);
+        let mut client_config = match &builder.client_cert_resolver {
+            Some(resolver) => client_config.with_client_cert_resolver(Arc::new(resolver.clone())),
+            None => client_config.with_no_client_auth(),
+        };
+        client_config.resumption = resumption(builder.session_cache_size);
+        let client_config = Arc::new(client_config);
+
+        let connector = TlsConnector::from(client_config.clone());
+        let reload = builder.client_cert_resolver.map(|resolver| {
+            Arc::new(Reload {
+                current: RwLock::new((resolver.generation(), connector.clone())),
+                resolver,
+                config: client_config,
+                session_cache_size: builder.session_cache_size,
+            })
+        });
+        Ok(Self {
+            connector,
+            reload,
+            no_root_certs,
+        })
     }
 
     async fn connect(&self, server_name: &str, tcp_stream: TcpStream) -> Result<Conn> {
@@ -56,7 +181,7 @@ impl Connector for RustlsConnector {
             .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
             .to_owned();
         tracing::trace!("RustlsConnector::connect({server_name:?})");
-        self.0
+        self.connector()
             .connect(sni, tcp_stream)
             .await
             .map(tokio_rustls::TlsStream::Client)
@@ -77,7 +202,7 @@ impl Acceptor for RustlsAcceptor {
             .with_single_cert(cert, key)
             .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
         let acceptor = TlsAcceptor::from(Arc::new(server_config));
-        Ok(Self(acceptor))
+        Ok(Self::Single(acceptor))
     }
 
     fn from_pem_file(cert_path: impl AsRef<Path>, key_path: impl AsRef<Path>) -> Result<Self> {
@@ -88,46 +213,838 @@ impl Acceptor for RustlsAcceptor {
 
     async fn accept(&self, tcp_stream: TcpStream) -> Result<ConnStream> {
         tracing::trace!("RustlsAcceptor::accept");
-        self.0
-            .accept(tcp_stream)
-            .await
-            .map(tokio_rustls::TlsStream::Server)
-            .map(ConnStream::from)
+        let stream = match self {
+            Self::Single(acceptor) => acceptor.accept(tcp_stream).await?,
+            Self::Sni(sni) => sni.accept(tcp_stream).await?,
+        };
+        Ok(ConnStream::from(tokio_rustls::TlsStream::Server(stream)))
     }
 }
 
 impl From<ClientConfig> for super::TlsConnector {
     fn from(client_config: ClientConfig) -> Self {
-        Self::Rustls(RustlsConnector(TlsConnector::from(Arc::new(client_config))))
+        Self::Rustls(RustlsConnector::new(TlsConnector::from(Arc::new(
+            client_config,
+        ))))
     }
 }
 
 impl From<Arc<ClientConfig>> for super::TlsConnector {
     fn from(client_config: Arc<ClientConfig>) -> Self {
-        Self::Rustls(RustlsConnector(TlsConnector::from(client_config)))
+        Self::Rustls(RustlsConnector::new(TlsConnector::from(client_config)))
     }
 }
 
 impl From<TlsConnector> for super::TlsConnector {
     fn from(connector: TlsConnector) -> Self {
-        Self::Rustls(RustlsConnector(connector))
+        Self::Rustls(RustlsConnector::new(connector))
     }
 }
 
 impl From<ServerConfig> for super::TlsAcceptor {
     fn from(server_config: ServerConfig) -> Self {
-        Self::Rustls(RustlsAcceptor(TlsAcceptor::from(Arc::new(server_config))))
+        Self::Rustls(RustlsAcceptor::Single(TlsAcceptor::from(Arc::new(
+            server_config,
+        ))))
     }
 }
 
 impl From<Arc<ServerConfig>> for super::TlsAcceptor {
     fn from(server_config: Arc<ServerConfig>) -> Self {
-        Self::Rustls(RustlsAcceptor(TlsAcceptor::from(server_config)))
+        Self::Rustls(RustlsAcceptor::Single(TlsAcceptor::from(server_config)))
     }
 }
 
 impl From<TlsAcceptor> for super::TlsAcceptor {
     fn from(acceptor: TlsAcceptor) -> Self {
-        Self::Rustls(RustlsAcceptor(acceptor))
+        Self::Rustls(RustlsAcceptor::Single(acceptor))
+    }
+}
+
+impl From<SniConfig> for super::TlsAcceptor {
+    fn from(config: SniConfig) -> Self {
+        Self::Rustls(RustlsAcceptor::Sni(Arc::new(SniAcceptor::new(config))))
+    }
+}
+
+/// The certificate of the server names in the [`SniConfig`].
+#[derive(Clone)]
+pub struct SniCertificate {
+    resolver: ReloadableCertResolver,
+    client_cert_verifier: Option<Arc<dyn ClientCertVerifier>>,
+}
+
+impl SniCertificate {
+    /// Creates the certificate by the resolver, which can be updated at runtime, e.g. by
+    /// [`ReloadableCertResolver::watch_files`], without affecting the other certificates.
+    pub fn new(resolver: ReloadableCertResolver) -> Self {
+        Self {
+            resolver,
+            client_cert_verifier: None,
+        }
+    }
+
+    /// Authenticates the clients connecting to the server names of this certificate by the
+    /// verifier.
+    ///
+    /// Default is no client authentication.
+    pub fn client_cert_verifier(mut self, verifier: Arc<dyn ClientCertVerifier>) -> Self {
+        self.client_cert_verifier = Some(verifier);
+        self
+    }
+
+    fn server_config(&self) -> Arc<ServerConfig> {
+        let builder = ServerConfig::builder();
+        let builder = match &self.client_cert_verifier {
+            Some(verifier) => builder.with_client_cert_verifier(verifier.clone()),
+            None => builder.with_no_client_auth(),
+        };
+        Arc::new(builder.with_cert_resolver(Arc::new(self.resolver.clone())))
+    }
+}
+
+impl fmt::Debug for SniCertificate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SniCertificate")
+            .field("resolver", &self.resolver)
+            .field("client_auth", &self.client_cert_verifier.is_some())
+            .finish()
+    }
+}
+
+/// The certificates of a server selected by the server name indicated by the client in the
+/// ClientHello, i.e. the SNI, so that one listener can serve multiple host names.
+///
+/// The names are matched case-insensitively, where a wildcard name like `*.example.com` matches
+/// exactly one label, e.g. `api.example.com` but neither `example.com` nor `a.b.example.com`, and
+/// the exact names take precedence over the wildcard ones.
+///
+/// A connection of an unknown server name, or without the SNI, is served by the default
+/// certificate, or rejected by closing the connection before the handshake if there is no default
+/// one.
+///
+/// The name of the selected certificate is in [`TlsInfo::certificate`], and is logged at the
+/// `debug` level for each handshake.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use volo::net::tls::{ReloadableCertResolver, ServerTlsConfig, SniCertificate, SniConfig};
+///
+/// let api = ReloadableCertResolver::from_pem_file("api.pem", "api.key")?;
+/// api.watch_files("api.pem", "api.key", Duration::from_secs(60));
+/// let admin = ReloadableCertResolver::from_pem_file("admin.pem", "admin.key")?;
+///
+/// let config = SniConfig::new()
+///     .add("*.api.example.com", SniCertificate::new(api.clone()))
+///     .add(
+///         "admin.example.com",
+///         SniCertificate::new(admin).client_cert_verifier(verifier),
+///     )
+///     .default_cert(SniCertificate::new(api));
+/// let server = Server::new().tls_config(ServerTlsConfig::from_sni(config));
+/// ```
+///
+/// [`TlsInfo::certificate`]: crate::context::identity::TlsInfo::certificate
+#[derive(Clone, Debug, Default)]
+pub struct SniConfig {
+    names: Vec<(String, SniCertificate)>,
+    default: Option<SniCertificate>,
+}
+
+impl SniConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serves the connections to the server name by the certificate, where the name can be a
+    /// wildcard one like `*.example.com`.
+    pub fn add(mut self, name: impl AsRef<str>, cert: SniCertificate) -> Self {
+        self.names.push((normalize(name.as_ref()), cert));
+        self
+    }
+
+    /// Serves the connections to the unknown server names or without the SNI by the certificate.
+    ///
+    /// Default is rejecting them.
+    pub fn default_cert(mut self, cert: SniCertificate) -> Self {
+        self.default = Some(cert);
+        self
+    }
+}
+
+fn normalize(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// The name of the default certificate in [`TlsInfo::certificate`].
+///
+/// [`TlsInfo::certificate`]: crate::context::identity::TlsInfo::certificate
+pub const DEFAULT_SNI_CERTIFICATE: &str = "*";
+
+struct SniEntry {
+    name: FastStr,
+    config: Arc<ServerConfig>,
+}
+
+/// The acceptor built from the [`SniConfig`].
+pub struct SniAcceptor {
+    names: HashMap<String, Arc<SniEntry>>,
+    default: Option<Arc<SniEntry>>,
+}
+
+impl SniAcceptor {
+    fn new(config: SniConfig) -> Self {
+        let names = config
+            .names
+            .into_iter()
+            .map(|(name, cert)| {
+                let entry = Arc::new(SniEntry {
+                    name: FastStr::new(&name),
+                    config: cert.server_config(),
+                });
+                (name, entry)
+            })
+            .collect();
+        let default = config.default.map(|cert| {
+            Arc::new(SniEntry {
+                name: FastStr::from_static_str(DEFAULT_SNI_CERTIFICATE),
+                config: cert.server_config(),
+            })
+        });
+        Self { names, default }
+    }
+
+    fn select(&self, server_name: Option<&str>) -> Option<&Arc<SniEntry>> {
+        let Some(server_name) = server_name else {
+            return self.default.as_ref();
+        };
+        let server_name = normalize(server_name);
+        if let Some(entry) = self.names.get(&server_name) {
+            return Some(entry);
+        }
+        server_name
+            .split_once('.')
+            .and_then(|(_, parent)| self.names.get(&format!("*.{parent}")))
+            .or(self.default.as_ref())
+    }
+
+    async fn accept(
+        &self,
+        tcp_stream: TcpStream,
+    ) -> Result<tokio_rustls::server::TlsStream<TcpStream>> {
+        let start =
+            LazyConfigAcceptor::new(rustls::server::Acceptor::default(), tcp_stream).await?;
+        let server_name = start.client_hello().server_name().map(ToOwned::to_owned);
+        let Some(entry) = self.select(server_name.as_deref()) else {
+            tracing::debug!(
+                "[VOLO] TLS handshake rejected for unknown server name {server_name:?}"
+            );
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("no certificate for server name {server_name:?}"),
+            ));
+        };
+        tracing::debug!(
+            "[VOLO] TLS handshake for server name {server_name:?} with certificate {}",
+            entry.name
+        );
+        start.into_stream(entry.config.clone()).await
+    }
+}
+
+impl fmt::Debug for SniAcceptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SniAcceptor")
+            .field("names", &self.names.keys().collect::<Vec<_>>())
+            .field("default", &self.default.is_some())
+            .finish()
+    }
+}
+
+/// A certificate resolver whose certificate and key can be replaced at runtime, e.g. for rotating
+/// the short-lived client certificates without restarting.
+///
+/// The new certificate is used by the handshakes afterwards, and the established connections are
+/// not affected. For a client, the sessions cached for resumption are also dropped after the
+/// update, so that the new connections will not resume the sessions authenticated by the old
+/// certificate.
+///
+/// It also serves the certificates of a server, see [`SniCertificate`].
+///
+/// The clones of a [`ReloadableCertResolver`] share the certificate.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use volo::net::tls::{ReloadableCertResolver, TlsConnector};
+///
+/// let resolver = ReloadableCertResolver::from_pem_file("client.pem", "client.key")?;
+/// resolver.watch_files("client.pem", "client.key", Duration::from_secs(60));
+/// let connector = TlsConnector::builder()
+///     .client_cert_resolver(resolver)
+///     .build()?;
+/// ```
+#[derive(Clone)]
+pub struct ReloadableCertResolver {
+    inner: Arc<ResolverInner>,
+}
+
+struct ResolverInner {
+    key: RwLock<Arc<CertifiedKey>>,
+    /// Increased by each update.
+    generation: AtomicU64,
+}
+
+impl ReloadableCertResolver {
+    /// Creates a resolver by the certificate chain and the private key in PEM.
+    pub fn from_pem(cert: Vec<u8>, key: Vec<u8>) -> Result<Self> {
+        Ok(Self {
+            inner: Arc::new(ResolverInner {
+                key: RwLock::new(certified_key(&cert, &key)?),
+                generation: AtomicU64::new(0),
+            }),
+        })
+    }
+
+    pub fn from_pem_file(cert_path: impl AsRef<Path>, key_path: impl AsRef<Path>) -> Result<Self> {
+        let cert = std::fs::read(cert_path.as_ref())?;
+        let key = std::fs::read(key_path.as_ref())?;
+        Self::from_pem(cert, key)
+    }
+
+    /// Replaces the certificate chain and the private key, which are kept if the new ones are
+    /// invalid.
+    pub fn update(&self, cert: Vec<u8>, key: Vec<u8>) -> Result<()> {
+        let key = certified_key(&cert, &key)?;
+        *self.inner.key.write().unwrap() = key;
+        self.inner.generation.fetch_add(1, Ordering::Release);
+        Ok(())
+    }
+
+    pub fn update_from_pem_file(
+        &self,
+        cert_path: impl AsRef<Path>,
+        key_path: impl AsRef<Path>,
+    ) -> Result<()> {
+        let cert = std::fs::read(cert_path.as_ref())?;
+        let key = std::fs::read(key_path.as_ref())?;
+        self.update(cert, key)
+    }
+
+    /// Checks the modified time of the files every `interval`, and reloads them when they change.
+    ///
+    /// The files should be replaced atomically, e.g. by renaming, since a certificate loaded with
+    /// the key of another one fails the handshakes. The checking stops after all the clones of
+    /// the resolver are dropped.
+    ///
+    /// This must be called in the context of a tokio runtime.
+    pub fn watch_files(
+        &self,
+        cert_path: impl Into<PathBuf>,
+        key_path: impl Into<PathBuf>,
+        interval: Duration,
+    ) {
+        let (cert_path, key_path) = (cert_path.into(), key_path.into());
+        let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        let weak = Arc::downgrade(&self.inner);
+        tokio::spawn(async move {
+            let mut last = (modified(&cert_path), modified(&key_path));
+            loop {
+                tokio::time::sleep(interval).await;
+                let Some(inner) = weak.upgrade() else {
+                    return;
+                };
+                let current = (modified(&cert_path), modified(&key_path));
+                if current == last {
+                    continue;
+                }
+                match (Self { inner }).update_from_pem_file(&cert_path, &key_path) {
+                    Ok(()) => {
+                        tracing::info!("[VOLO] reloaded certificate from {cert_path:?}");
+                        last = current;
+                    }
+                    Err(err) => tracing::warn!(
+                        "[VOLO] failed to reload certificate from {cert_path:?}: {err}"
+                    ),
+                }
+            }
+        });
+    }
+
+    fn generation(&self) -> u64 {
+        self.inner.generation.load(Ordering::Acquire)
+    }
+}
+
+fn certified_key(cert: &[u8], key: &[u8]) -> Result<Arc<CertifiedKey>> {
+    let cert = rustls_pemfile::certs(&mut cert.as_ref()).collect::<Result<Vec<_>>>()?;
+    if cert.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "No certificate found",
+        ));
+    }
+    let key = rustls_pemfile::private_key(&mut key.as_ref())?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No private key found"))?;
+    let key = rustls::crypto::ring::sign::any_supported_type(&key)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    Ok(Arc::new(CertifiedKey::new(cert, key)))
+}
+
+impl ResolvesClientCert for ReloadableCertResolver {
+    fn resolve(
+        &self,
+        _root_hint_subjects: &[&[u8]],
+        _sigschemes: &[SignatureScheme],
+    ) -> Option<Arc<CertifiedKey>> {
+        Some(self.inner.key.read().unwrap().clone())
+    }
+
+    fn has_certs(&self) -> bool {
+        true
+    }
+}
+
+impl ResolvesServerCert for ReloadableCertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.inner.key.read().unwrap().clone())
+    }
+}
+
+impl fmt::Debug for ReloadableCertResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReloadableCertResolver")
+            .field("generation", &self.generation())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    };
+
+    use rustls::{
+        client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        pki_types::{CertificateDer, UnixTime},
+        server::{
+            danger::{ClientCertVerified, ClientCertVerifier},
+            ServerSessionMemoryCache, StoresServerSessions,
+        },
+        DigitallySignedStruct, DistinguishedName,
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+        sync::mpsc,
+    };
+
+    use super::*;
+    use crate::net::tls::TlsAcceptor as VoloTlsAcceptor;
+
+    const CA: &[u8] = include_bytes!("../../../../examples/data/tls/ca.pem");
+    const SERVER_CERT: &[u8] = include_bytes!("../../../../examples/data/tls/server.pem");
+    const SERVER_KEY: &[u8] = include_bytes!("../../../../examples/data/tls/server.key");
+    const CLIENT_CERT: &[u8] = include_bytes!("../../../../examples/data/tls/client.pem");
+    const CLIENT_KEY: &[u8] = include_bytes!("../../../../examples/data/tls/client.key");
+    const SPIFFE_CERT: &[u8] = include_bytes!("../../../../examples/data/tls/spiffe.pem");
+    const SPIFFE_KEY: &[u8] = include_bytes!("../../../../examples/data/tls/spiffe.key");
+
+    /// Accepts any client certificate and records it.
+    #[derive(Debug, Default)]
+    struct RecordingVerifier(Mutex<Vec<Vec<u8>>>);
+
+    impl ClientCertVerifier for RecordingVerifier {
+        fn root_hint_subjects(&self) -> &[DistinguishedName] {
+            &[]
+        }
+
+        fn verify_client_cert(
+            &self,
+            end_entity: &CertificateDer<'_>,
+            _intermediates: &[CertificateDer<'_>],
+            _now: UnixTime,
+        ) -> std::result::Result<ClientCertVerified, rustls::Error> {
+            self.0.lock().unwrap().push(end_entity.to_vec());
+            Ok(ClientCertVerified::assertion())
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            _message: &[u8],
+            _cert: &CertificateDer<'_>,
+            _dss: &DigitallySignedStruct,
+        ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+            Ok(HandshakeSignatureValid::assertion())
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            _message: &[u8],
+            _cert: &CertificateDer<'_>,
+            _dss: &DigitallySignedStruct,
+        ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+            Ok(HandshakeSignatureValid::assertion())
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            rustls::crypto::ring::default_provider()
+                .signature_verification_algorithms
+                .supported_schemes()
+        }
+    }
+
+    /// Accepts any server certificate and records it.
+    #[derive(Debug, Default)]
+    struct RecordingServerVerifier(Mutex<Vec<Vec<u8>>>);
+
+    impl ServerCertVerifier for RecordingServerVerifier {
+        fn verify_server_cert(
+            &self,
+            end_entity: &CertificateDer<'_>,
+            _intermediates: &[CertificateDer<'_>],
+            _server_name: &ServerName<'_>,
+            _ocsp_response: &[u8],
+            _now: UnixTime,
+        ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+            self.0.lock().unwrap().push(end_entity.to_vec());
+            Ok(ServerCertVerified::assertion())
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            _message: &[u8],
+            _cert: &CertificateDer<'_>,
+            _dss: &DigitallySignedStruct,
+        ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+            Ok(HandshakeSignatureValid::assertion())
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            _message: &[u8],
+            _cert: &CertificateDer<'_>,
+            _dss: &DigitallySignedStruct,
+        ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+            Ok(HandshakeSignatureValid::assertion())
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            rustls::crypto::ring::default_provider()
+                .signature_verification_algorithms
+                .supported_schemes()
+        }
+    }
+
+    /// Counts the sessions resumed by the server.
+    #[derive(Debug)]
+    struct CountingSessions {
+        inner: Arc<ServerSessionMemoryCache>,
+        resumed: AtomicUsize,
+    }
+
+    impl StoresServerSessions for CountingSessions {
+        fn put(&self, key: Vec<u8>, value: Vec<u8>) -> bool {
+            self.inner.put(key, value)
+        }
+
+        fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+            let value = self.inner.get(key);
+            if value.is_some() {
+                self.resumed.fetch_add(1, Ordering::Relaxed);
+            }
+            value
+        }
+
+        fn take(&self, key: &[u8]) -> Option<Vec<u8>> {
+            let value = self.inner.take(key);
+            if value.is_some() {
+                self.resumed.fetch_add(1, Ordering::Relaxed);
+            }
+            value
+        }
+
+        fn can_cache(&self) -> bool {
+            self.inner.can_cache()
+        }
+    }
+
+    fn der(pem: &[u8]) -> Vec<u8> {
+        rustls_pemfile::certs(&mut pem.as_ref())
+            .next()
+            .unwrap()
+            .unwrap()
+            .to_vec()
+    }
+
+    #[tokio::test]
+    async fn resumption_and_cert_reload() {
+        let verifier = Arc::new(RecordingVerifier::default());
+        let sessions = Arc::new(CountingSessions {
+            inner: ServerSessionMemoryCache::new(32),
+            resumed: AtomicUsize::new(0),
+        });
+        let key = rustls_pemfile::private_key(&mut SERVER_KEY.as_ref())
+            .unwrap()
+            .unwrap();
+        let certs = rustls_pemfile::certs(&mut SERVER_CERT.as_ref())
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        let mut config = ServerConfig::builder()
+            .with_client_cert_verifier(verifier.clone())
+            .with_single_cert(certs, key)
+            .unwrap();
+        config.session_storage = sessions.clone();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (tcp, _) = listener.accept().await.unwrap();
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    let mut tls = acceptor.accept(tcp).await.unwrap();
+                    tls.write_all(b"x").await.unwrap();
+                    tls.flush().await.unwrap();
+                    // wait for the client to close
+                    let _ = tls.read(&mut [0u8; 1]).await;
+                });
+            }
+        });
+
+        let resolver =
+            ReloadableCertResolver::from_pem(CLIENT_CERT.to_vec(), CLIENT_KEY.to_vec()).unwrap();
+        let connector = RustlsConnector::build(
+            TlsConnectorBuilder::default()
+                .enable_default_root_certs(false)
+                .add_pem(CA.to_vec())
+                .client_cert_resolver(resolver.clone()),
+        )
+        .unwrap();
+        let connect = || async {
+            let tcp = TcpStream::connect(addr).await.unwrap();
+            let mut conn = connector.connect("localhost", tcp).await.unwrap();
+            // receive the session tickets sent after the handshake
+            conn.read_exact(&mut [0u8; 1]).await.unwrap();
+        };
+
+        connect().await;
+        assert_eq!(sessions.resumed.load(Ordering::Relaxed), 0);
+        // the abbreviated handshake doesn't exchange the certificates
+        connect().await;
+        assert_eq!(sessions.resumed.load(Ordering::Relaxed), 1);
+        assert_eq!(*verifier.0.lock().unwrap(), vec![der(CLIENT_CERT)]);
+
+        // the new connections do full handshakes with the new certificate
+        resolver
+            .update(SERVER_CERT.to_vec(), SERVER_KEY.to_vec())
+            .unwrap();
+        connect().await;
+        assert_eq!(sessions.resumed.load(Ordering::Relaxed), 1);
+        assert_eq!(
+            *verifier.0.lock().unwrap(),
+            vec![der(CLIENT_CERT), der(SERVER_CERT)]
+        );
+
+        // invalid certificates are rejected
+        assert!(resolver.update(b"invalid".to_vec(), b"".to_vec()).is_err());
+        connect().await;
+        assert_eq!(sessions.resumed.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn peer_tls_identity() {
+        let key = rustls_pemfile::private_key(&mut SERVER_KEY.as_ref())
+            .unwrap()
+            .unwrap();
+        let certs = rustls_pemfile::certs(&mut SERVER_CERT.as_ref())
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        let config = ServerConfig::builder()
+            .with_client_cert_verifier(Arc::new(RecordingVerifier::default()))
+            .with_single_cert(certs, key)
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let mut tls = acceptor.accept(tcp).await.unwrap();
+            tls.write_all(b"x").await.unwrap();
+            tls.flush().await.unwrap();
+            ConnStream::Rustls(tls.into()).tls_identity()
+        });
+
+        let resolver =
+            ReloadableCertResolver::from_pem(SPIFFE_CERT.to_vec(), SPIFFE_KEY.to_vec()).unwrap();
+        let connector = RustlsConnector::build(
+            TlsConnectorBuilder::default()
+                .enable_default_root_certs(false)
+                .add_pem(CA.to_vec())
+                .client_cert_resolver(resolver),
+        )
+        .unwrap();
+        let tcp = TcpStream::connect(addr).await.unwrap();
+        let mut conn = connector.connect("localhost", tcp).await.unwrap();
+        conn.read_exact(&mut [0u8; 1]).await.unwrap();
+        // the client sees the identity of the server
+        let server_identity = conn.stream.tls_identity().unwrap();
+        assert!(server_identity.spiffe_id.is_none());
+        assert!(server_identity
+            .dns_names
+            .contains(&crate::FastStr::from_static_str("localhost")));
+
+        let identity = server.await.unwrap().unwrap();
+        assert_eq!(
+            identity.spiffe_id.as_deref(),
+            Some("spiffe://example.org/ns/default/sa/greeter")
+        );
+        assert_eq!(identity.service_name().as_deref(), Some("greeter"));
+    }
+
+    /// Serves the connections by the acceptor, and sends the name of the selected certificate of
+    /// each connection, or `Err` if the handshake failed.
+    async fn serve_sni(
+        acceptor: VoloTlsAcceptor,
+    ) -> (
+        std::net::SocketAddr,
+        mpsc::UnboundedReceiver<Result<Option<FastStr>>>,
+    ) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            loop {
+                let (tcp, _) = listener.accept().await.unwrap();
+                let mut stream = match acceptor.accept(tcp).await {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        let _ = tx.send(Err(err));
+                        continue;
+                    }
+                };
+                let info = acceptor.tls_info(&stream).unwrap();
+                let _ = tx.send(Ok(info.certificate().cloned()));
+                stream.write_all(b"x").await.unwrap();
+                stream.flush().await.unwrap();
+            }
+        });
+        (addr, rx)
+    }
+
+    #[tokio::test]
+    async fn sni_certificates() {
+        let example =
+            ReloadableCertResolver::from_pem(SERVER_CERT.to_vec(), SERVER_KEY.to_vec()).unwrap();
+        let greeter =
+            ReloadableCertResolver::from_pem(SPIFFE_CERT.to_vec(), SPIFFE_KEY.to_vec()).unwrap();
+        let fallback =
+            ReloadableCertResolver::from_pem(CLIENT_CERT.to_vec(), CLIENT_KEY.to_vec()).unwrap();
+        let client_verifier = Arc::new(RecordingVerifier::default());
+        let config = SniConfig::new()
+            .add(
+                "example.com.",
+                SniCertificate::new(example).client_cert_verifier(client_verifier.clone()),
+            )
+            .add("*.greeter.test", SniCertificate::new(greeter.clone()))
+            .default_cert(SniCertificate::new(fallback));
+        let (addr, mut certificates) = serve_sni(config.into()).await;
+
+        let server_verifier = Arc::new(RecordingServerVerifier::default());
+        let client_cert =
+            ReloadableCertResolver::from_pem(SPIFFE_CERT.to_vec(), SPIFFE_KEY.to_vec()).unwrap();
+        let mut client_config = ClientConfig::builder()
+            .dangerous()
+            .with_custom_certificate_verifier(server_verifier.clone())
+            .with_client_cert_resolver(Arc::new(client_cert));
+        // every connection presents the certificate by a full handshake
+        client_config.resumption = Resumption::disabled();
+        let connector = TlsConnector::from(Arc::new(client_config));
+        let connect = |name: &'static str| {
+            let connector = connector.clone();
+            async move {
+                let tcp = TcpStream::connect(addr).await.unwrap();
+                let server_name = ServerName::try_from(name).unwrap().to_owned();
+                let mut tls = connector.connect(server_name, tcp).await.unwrap();
+                tls.read_exact(&mut [0u8; 1]).await.unwrap();
+            }
+        };
+        let presented = || server_verifier.0.lock().unwrap().pop().unwrap();
+
+        connect("Example.COM").await;
+        assert_eq!(presented(), der(SERVER_CERT));
+        assert_eq!(
+            certificates.recv().await.unwrap().unwrap().as_deref(),
+            Some("example.com")
+        );
+        // only the clients of `example.com` are authenticated
+        assert_eq!(*client_verifier.0.lock().unwrap(), vec![der(SPIFFE_CERT)]);
+
+        connect("api.greeter.test").await;
+        assert_eq!(presented(), der(SPIFFE_CERT));
+        assert_eq!(
+            certificates.recv().await.unwrap().unwrap().as_deref(),
+            Some("*.greeter.test")
+        );
+
+        // the wildcard matches exactly one label
+        for name in ["a.b.greeter.test", "greeter.test", "127.0.0.1"] {
+            connect(name).await;
+            assert_eq!(presented(), der(CLIENT_CERT));
+            assert_eq!(
+                certificates.recv().await.unwrap().unwrap().as_deref(),
+                Some(DEFAULT_SNI_CERTIFICATE)
+            );
+        }
+        assert_eq!(client_verifier.0.lock().unwrap().len(), 1);
+
+        // the entries are reloaded separately
+        greeter
+            .update(SERVER_CERT.to_vec(), SERVER_KEY.to_vec())
+            .unwrap();
+        connect("api.greeter.test").await;
+        assert_eq!(presented(), der(SERVER_CERT));
+        connect("other.test").await;
+        assert_eq!(presented(), der(CLIENT_CERT));
+    }
+
+    #[tokio::test]
+    async fn sni_reject_unknown() {
+        let example =
+            ReloadableCertResolver::from_pem(SERVER_CERT.to_vec(), SERVER_KEY.to_vec()).unwrap();
+        let config = SniConfig::new().add("*.example.com", SniCertificate::new(example));
+        let (addr, mut certificates) = serve_sni(config.into()).await;
+
+        let mut client_config = ClientConfig::builder()
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(RecordingServerVerifier::default()))
+            .with_no_client_auth();
+        client_config.resumption = Resumption::disabled();
+        let connector = TlsConnector::from(Arc::new(client_config));
+        let connect = |name: &'static str| {
+            let connector = connector.clone();
+            async move {
+                let tcp = TcpStream::connect(addr).await.unwrap();
+                let server_name = ServerName::try_from(name).unwrap().to_owned();
+                connector.connect(server_name, tcp).await
+            }
+        };
+
+        connect("api.example.com").await.unwrap();
+        assert_eq!(
+            certificates.recv().await.unwrap().unwrap().as_deref(),
+            Some("*.example.com")
+        );
+
+        // the connection is closed without any certificate
+        for name in ["example.com", "unknown.test", "127.0.0.1"] {
+            assert!(connect(name).await.is_err());
+            assert!(certificates.recv().await.unwrap().is_err());
+        }
     }
 }
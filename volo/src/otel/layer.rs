@@ -0,0 +1,155 @@
+use std::sync::Arc;
+
+use motore::{layer::Layer, service::Service};
+use tracing::Instrument;
+
+use super::{
+    client_span, inject_span, record_log_id, record_peer, server_span, set_remote_parent,
+    OtelProtocol,
+};
+use crate::context::Context;
+
+/// A layer that starts a server span of each request as the child of the context propagated by
+/// the client, see the [module docs](super).
+///
+/// It should be the outermost layer, e.g. added by `layer_front` of the servers, so the span
+/// covers the other layers.
+#[derive(Clone)]
+pub struct OtelServerLayer<P> {
+    protocol: P,
+}
+
+impl<P> OtelServerLayer<P> {
+    pub fn new(protocol: P) -> Self {
+        Self { protocol }
+    }
+}
+
+impl<S, P> Layer<S> for OtelServerLayer<P> {
+    type Service = OtelServerService<S, P>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        OtelServerService {
+            inner,
+            protocol: Arc::new(self.protocol),
+        }
+    }
+}
+
+/// The service created by [`OtelServerLayer`].
+pub struct OtelServerService<S, P> {
+    inner: S,
+    protocol: Arc<P>,
+}
+
+impl<S: Clone, P> Clone for OtelServerService<S, P> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            protocol: self.protocol.clone(),
+        }
+    }
+}
+
+impl<Cx, Req, S, P> Service<Cx, Req> for OtelServerService<S, P>
+where
+    Cx: Context + Send + 'static,
+    Req: Send + 'static,
+    S: Service<Cx, Req> + Send + Sync + 'static,
+    P: OtelProtocol<Cx, Req, S::Response, S::Error>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call<'s, 'cx>(
+        &'s self,
+        cx: &'cx mut Cx,
+        req: Req,
+    ) -> Result<Self::Response, Self::Error> {
+        let span = server_span();
+        if span.is_disabled() {
+            return self.inner.call(cx, req).await;
+        }
+        self.protocol.record_request(&span, cx, &req);
+        record_log_id(&span, cx);
+        set_remote_parent(&span, |key| self.protocol.extract(cx, &req, key));
+
+        let result = self.inner.call(cx, req).instrument(span.clone()).await;
+        self.protocol.record(&span, &result);
+        result
+    }
+}
+
+/// A layer that starts a client span of each request, and injects its context into the request,
+/// see the [module docs](super).
+///
+/// It should be an outer layer, e.g. added by `layer_outer` of the clients, so the retries are in
+/// the same span.
+#[derive(Clone)]
+pub struct OtelClientLayer<P> {
+    protocol: P,
+}
+
+impl<P> OtelClientLayer<P> {
+    pub fn new(protocol: P) -> Self {
+        Self { protocol }
+    }
+}
+
+impl<S, P> Layer<S> for OtelClientLayer<P> {
+    type Service = OtelClientService<S, P>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        OtelClientService {
+            inner,
+            protocol: Arc::new(self.protocol),
+        }
+    }
+}
+
+/// The service created by [`OtelClientLayer`].
+pub struct OtelClientService<S, P> {
+    inner: S,
+    protocol: Arc<P>,
+}
+
+impl<S: Clone, P> Clone for OtelClientService<S, P> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            protocol: self.protocol.clone(),
+        }
+    }
+}
+
+impl<Cx, Req, S, P> Service<Cx, Req> for OtelClientService<S, P>
+where
+    Cx: Context + Send + 'static,
+    Req: Send + 'static,
+    S: Service<Cx, Req> + Send + Sync + 'static,
+    P: OtelProtocol<Cx, Req, S::Response, S::Error>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call<'s, 'cx>(
+        &'s self,
+        cx: &'cx mut Cx,
+        mut req: Req,
+    ) -> Result<Self::Response, Self::Error> {
+        let span = client_span();
+        if span.is_disabled() {
+            return self.inner.call(cx, req).await;
+        }
+        self.protocol.record_request(&span, cx, &req);
+        inject_span(&span, |key, value| {
+            self.protocol.inject(cx, &mut req, key, value)
+        });
+
+        let result = self.inner.call(cx, req).instrument(span.clone()).await;
+        record_peer(&span, cx);
+        record_log_id(&span, cx);
+        self.protocol.record(&span, &result);
+        result
+    }
+}
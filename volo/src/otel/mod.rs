@@ -0,0 +1,344 @@
+//! The propagation of the W3C trace context, i.e. the `traceparent` and `tracestate` headers, and
+//! the spans of the requests with the semantic attributes of OpenTelemetry.
+//!
+//! The spans are the spans of [`tracing`], which are exported to OpenTelemetry by the layer of
+//! [`tracing_opentelemetry`] in the subscriber:
+//!
+//! - The [`OtelClientLayer`] starts a client span, and injects its context into the request, e.g.
+//!   the metadata of gRPC, the headers of HTTP or the transient metainfo of Thrift.
+//! - The [`OtelServerLayer`] extracts the context from the request, and starts a server span as
+//!   its child.
+//!
+//! Both of the spans have the attributes `rpc.system`, `rpc.service`, `rpc.method` and
+//! `network.peer.address`, and the `log_id` of [`volo::context::log_id`](crate::context::log_id),
+//! and record the status of the response when the request completes.
+//!
+//! Nothing is injected or extracted when the span is disabled, e.g. no subscriber is installed or
+//! the level is filtered out, so the layers cost little more than checking the interest of the
+//! callsite then.
+//!
+//! The protocol specific [`OtelProtocol`]s are provided in `volo-thrift`, `volo-grpc` and
+//! `volo-http`, and the server of `volo-thrift` starts the span by its `SpanProvider` instead.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use tracing_subscriber::prelude::*;
+//! use volo::otel::OtelServerLayer;
+//! use volo_grpc::layer::otel::GrpcOtel;
+//!
+//! tracing_subscriber::registry()
+//!     .with(tracing_opentelemetry::layer().with_tracer(tracer))
+//!     .init();
+//!
+//! Server::new()
+//!     .layer_front(OtelServerLayer::new(GrpcOtel))
+//!     .add_service(ServiceBuilder::new(GreeterServer::new(S)).build())
+//!     .run(addr)
+//!     .await
+//!     .unwrap();
+//! ```
+
+mod layer;
+
+use std::{fmt::Display, str::FromStr};
+
+use faststr::FastStr;
+pub use opentelemetry;
+use opentelemetry::trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState};
+use tracing::{field, Span};
+pub use tracing_opentelemetry;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+pub use self::layer::{OtelClientLayer, OtelClientService, OtelServerLayer, OtelServerService};
+use crate::context::{log_id::LogIdExt, Context, Role};
+
+/// The header of the trace id, the parent span id and the trace flags.
+pub const TRACEPARENT: &str = "traceparent";
+/// The header of the vendor specific trace state.
+pub const TRACESTATE: &str = "tracestate";
+
+/// Adapts the [`OtelClientLayer`] and the [`OtelServerLayer`] to a protocol.
+pub trait OtelProtocol<Cx, Req, Resp, E>: Send + Sync {
+    /// The value of the attribute `rpc.system`, e.g. `grpc`.
+    fn system(&self) -> &'static str;
+
+    /// Records the attributes of the request on the span, which are the ones recorded by
+    /// [`record_rpc`] by default.
+    fn record_request(&self, span: &Span, cx: &Cx, req: &Req)
+    where
+        Cx: Context,
+    {
+        let _ = req;
+        record_rpc(span, self.system(), cx);
+    }
+
+    /// Reads the header `key` of the request at the server side.
+    fn extract(&self, cx: &Cx, req: &Req, key: &str) -> Option<FastStr> {
+        let _ = (cx, req, key);
+        None
+    }
+
+    /// Writes the header `key` into the request at the client side.
+    fn inject(&self, cx: &mut Cx, req: &mut Req, key: &'static str, value: FastStr) {
+        let _ = (cx, req, key, value);
+    }
+
+    /// Records the status of the response on the span, usually by [`record_error`] for the
+    /// errors.
+    fn record(&self, span: &Span, result: &Result<Resp, E>);
+}
+
+macro_rules! rpc_span {
+    ($name:literal, $kind:literal) => {
+        tracing::info_span!(
+            target: "volo::otel",
+            $name,
+            otel.name = field::Empty,
+            otel.kind = $kind,
+            otel.status_code = field::Empty,
+            otel.status_description = field::Empty,
+            rpc.system = field::Empty,
+            rpc.service = field::Empty,
+            rpc.method = field::Empty,
+            rpc.grpc.status_code = field::Empty,
+            http.request.method = field::Empty,
+            http.response.status_code = field::Empty,
+            url.path = field::Empty,
+            network.peer.address = field::Empty,
+            "error.type" = field::Empty,
+            log_id = field::Empty,
+        )
+    };
+}
+
+/// Creates the span of a request at the server side, whose attributes are recorded by
+/// [`record_rpc`].
+pub fn server_span() -> Span {
+    rpc_span!("rpc.server", "server")
+}
+
+/// Creates the span of a request at the client side, whose attributes are recorded by
+/// [`record_rpc`].
+pub fn client_span() -> Span {
+    rpc_span!("rpc.client", "client")
+}
+
+/// Records the system, the service and the method of the request, and the address of the peer if
+/// it is known, on the span created by [`server_span`] or [`client_span`].
+///
+/// The method of the context in the form of `/{service}/{method}`, e.g. the path of gRPC, is split
+/// into the service and the method, otherwise the service is the service name of the callee.
+pub fn record_rpc<Cx: Context>(span: &Span, system: &'static str, cx: &Cx) {
+    let rpc_info = cx.rpc_info();
+    let (service, method) = match rpc_info
+        .method()
+        .strip_prefix('/')
+        .and_then(|path| path.split_once('/'))
+    {
+        Some(path) => path,
+        None => (
+            rpc_info.callee().service_name_ref(),
+            rpc_info.method().as_str(),
+        ),
+    };
+    span.record(
+        "otel.name",
+        field::display(format_args!("{service}/{method}")),
+    );
+    span.record("rpc.system", system);
+    span.record("rpc.service", service);
+    span.record("rpc.method", method);
+    record_peer(span, cx);
+    record_log_id(span, cx);
+}
+
+/// Records the log id of the request on the span, if it has one already.
+///
+/// The log id is also recorded on the current span when it's accepted by a server or sent by a
+/// client, see [`log_id`](crate::context::log_id).
+pub fn record_log_id<Cx: Context>(span: &Span, cx: &Cx) {
+    if let Some(log_id) = cx.log_id() {
+        span.record("log_id", log_id.as_str());
+    }
+}
+
+/// Records the address of the peer, which is only known after the load balancing at the client
+/// side.
+pub fn record_peer<Cx: Context>(span: &Span, cx: &Cx) {
+    let rpc_info = cx.rpc_info();
+    let peer = match rpc_info.role() {
+        Role::Server => rpc_info.caller(),
+        Role::Client => rpc_info.callee(),
+    };
+    if let Some(address) = peer.address() {
+        span.record("network.peer.address", field::display(address));
+    }
+}
+
+/// Marks the span as failed, with the type of the error, e.g. an error code, and its description.
+pub fn record_error(span: &Span, error_type: impl Display, description: impl Display) {
+    span.record("otel.status_code", "ERROR");
+    span.record("error.type", field::display(error_type));
+    span.record("otel.status_description", field::display(description));
+}
+
+/// Sets the remote context read by `get` as the parent of the span, and returns whether it is
+/// found.
+pub fn set_remote_parent(span: &Span, get: impl Fn(&str) -> Option<FastStr>) -> bool {
+    match extract(get) {
+        Some(parent) => {
+            span.set_parent(opentelemetry::Context::new().with_remote_span_context(parent));
+            true
+        }
+        None => false,
+    }
+}
+
+/// Writes the context of the span by `set`, if the span is exported to OpenTelemetry.
+pub fn inject_span(span: &Span, set: impl FnMut(&'static str, FastStr)) {
+    inject(span.context().span().span_context(), set);
+}
+
+/// Writes the span context as the `traceparent` and the `tracestate` by `set`, and nothing is
+/// written if it is not valid.
+pub fn inject(span_context: &SpanContext, mut set: impl FnMut(&'static str, FastStr)) {
+    if !span_context.is_valid() {
+        return;
+    }
+    let flags = span_context.trace_flags() & TraceFlags::SAMPLED;
+    set(
+        TRACEPARENT,
+        format!(
+            "00-{}-{}-{:02x}",
+            span_context.trace_id(),
+            span_context.span_id(),
+            flags.to_u8()
+        )
+        .into(),
+    );
+    let state = span_context.trace_state().header();
+    if !state.is_empty() {
+        set(TRACESTATE, state.into());
+    }
+}
+
+/// Reads the remote span context from the `traceparent` and the `tracestate` by `get`, or `None`
+/// if the `traceparent` is missing or malformed.
+///
+/// A malformed `tracestate` is ignored.
+pub fn extract(get: impl Fn(&str) -> Option<FastStr>) -> Option<SpanContext> {
+    let (trace_id, span_id, flags) = parse_traceparent(get(TRACEPARENT)?.trim())?;
+    let state = get(TRACESTATE)
+        .and_then(|state| TraceState::from_str(state.trim()).ok())
+        .unwrap_or_default();
+    Some(SpanContext::new(trace_id, span_id, flags, true, state))
+}
+
+fn parse_traceparent(s: &str) -> Option<(TraceId, SpanId, TraceFlags)> {
+    let mut parts = s.split('-');
+    let version = parts.next()?;
+    let trace_id = parts.next()?;
+    let span_id = parts.next()?;
+    let flags = parts.next()?;
+    // the future versions may append more fields, but the version 00 has exactly four
+    if version == "ff" || (version == "00" && parts.next().is_some()) {
+        return None;
+    }
+    let is_hex = |s: &str, len: usize| {
+        s.len() == len
+            && s.bytes()
+                .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+    };
+    if !is_hex(version, 2) || !is_hex(trace_id, 32) || !is_hex(span_id, 16) || !is_hex(flags, 2) {
+        return None;
+    }
+
+    let trace_id = TraceId::from_hex(trace_id).ok()?;
+    let span_id = SpanId::from_hex(span_id).ok()?;
+    if trace_id == TraceId::INVALID || span_id == SpanId::INVALID {
+        return None;
+    }
+    let flags = TraceFlags::new(u8::from_str_radix(flags, 16).ok()?) & TraceFlags::SAMPLED;
+    Some((trace_id, span_id, flags))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+    const SPAN_ID: &str = "00f067aa0ba902b7";
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HashMap<&'static str, FastStr> {
+        pairs
+            .iter()
+            .map(|(k, v)| (*k, FastStr::from_static_str(v)))
+            .collect()
+    }
+
+    #[test]
+    fn extract_and_inject() {
+        let traceparent = format!("00-{TRACE_ID}-{SPAN_ID}-01");
+        let mut headers = headers(&[(TRACESTATE, "congo=t61rcWkgMzE,rojo=00f067aa0ba902b7")]);
+        headers.insert(TRACEPARENT, traceparent.clone().into());
+
+        let span_context = extract(|key| headers.get(key).cloned()).unwrap();
+        assert_eq!(
+            span_context.trace_id(),
+            TraceId::from_hex(TRACE_ID).unwrap()
+        );
+        assert_eq!(span_context.span_id(), SpanId::from_hex(SPAN_ID).unwrap());
+        assert!(span_context.is_sampled());
+        assert!(span_context.is_remote());
+        assert_eq!(span_context.trace_state().get("congo"), Some("t61rcWkgMzE"));
+
+        let mut injected = HashMap::new();
+        inject(&span_context, |key, value| {
+            injected.insert(key, value);
+        });
+        assert_eq!(injected, headers);
+
+        // not sampled, and the unknown flags are dropped
+        let span_context = extract(|key| {
+            (key == TRACEPARENT).then(|| format!("00-{TRACE_ID}-{SPAN_ID}-fe").into())
+        })
+        .unwrap();
+        assert!(!span_context.is_sampled());
+        assert_eq!(span_context.trace_state().header(), "");
+    }
+
+    #[test]
+    fn malformed_traceparent() {
+        let cases = [
+            String::new(),
+            format!("00-{TRACE_ID}-{SPAN_ID}"),
+            format!("00-{TRACE_ID}-{SPAN_ID}-01-extra"),
+            format!("ff-{TRACE_ID}-{SPAN_ID}-01"),
+            format!("00-{}-{SPAN_ID}-01", TRACE_ID.to_uppercase()),
+            format!("00-{}-{SPAN_ID}-01", "0".repeat(32)),
+            format!("00-{TRACE_ID}-{}-01", "0".repeat(16)),
+            format!("00-{TRACE_ID}-{SPAN_ID}-1"),
+        ];
+        for traceparent in cases {
+            let traceparent = FastStr::from(traceparent);
+            assert!(
+                extract(|key| (key == TRACEPARENT).then(|| traceparent.clone())).is_none(),
+                "{traceparent}"
+            );
+        }
+
+        // the future versions may have more fields
+        let traceparent = FastStr::from(format!("01-{TRACE_ID}-{SPAN_ID}-01-extra"));
+        assert!(extract(|key| (key == TRACEPARENT).then(|| traceparent.clone())).is_some());
+    }
+
+    #[test]
+    fn invalid_span_context_is_not_injected() {
+        let mut injected = Vec::new();
+        inject(&SpanContext::empty_context(), |key, _| injected.push(key));
+        assert!(injected.is_empty());
+    }
+}
@@ -0,0 +1,220 @@
+use std::{
+    any::Any,
+    future::Future,
+    panic::AssertUnwindSafe,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use futures::FutureExt;
+use motore::{layer::Layer, service::Service};
+
+use super::{Observer, Outcome, Preset, PresetProtocol, RequestRecord};
+use crate::{
+    catch_panic::{self, PanicInfo},
+    context::Context,
+    load_shed::Overloaded,
+};
+
+impl<S, P> Layer<S> for Preset<P> {
+    type Service = PresetService<S, P>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        if self.catch_panic {
+            catch_panic::init_panic_hook();
+        }
+        PresetService {
+            inner,
+            protocol: Arc::new(self.protocol),
+            inflight: Arc::new(AtomicUsize::new(0)),
+            max_concurrency: self.max_concurrency,
+            max_body_size: self.max_body_size,
+            timeout: self.timeout,
+            catch_panic: self.catch_panic,
+            observer: self.observer,
+        }
+    }
+}
+
+/// The service created by [`Preset`].
+pub struct PresetService<S, P> {
+    inner: S,
+    protocol: Arc<P>,
+    inflight: Arc<AtomicUsize>,
+    max_concurrency: Option<usize>,
+    max_body_size: Option<usize>,
+    timeout: Option<Duration>,
+    catch_panic: bool,
+    observer: Option<Observer>,
+}
+
+impl<S: Clone, P> Clone for PresetService<S, P> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            protocol: self.protocol.clone(),
+            inflight: self.inflight.clone(),
+            max_concurrency: self.max_concurrency,
+            max_body_size: self.max_body_size,
+            timeout: self.timeout,
+            catch_panic: self.catch_panic,
+            observer: self.observer.clone(),
+        }
+    }
+}
+
+/// Releases the slot of an inflight request when dropped.
+struct Inflight<'a>(&'a AtomicUsize);
+
+impl Drop for Inflight<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+enum Served<T> {
+    Done(T),
+    TimedOut(Duration),
+    Panicked(Box<dyn Any + Send>),
+}
+
+async fn with_timeout<F: Future>(timeout: Option<Duration>, future: F) -> Served<F::Output> {
+    match timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, future).await {
+            Ok(output) => Served::Done(output),
+            Err(_) => Served::TimedOut(timeout),
+        },
+        None => Served::Done(future.await),
+    }
+}
+
+impl<S, P> PresetService<S, P> {
+    fn acquire(&self) -> Result<Option<Inflight<'_>>, Overloaded> {
+        let Some(limit) = self.max_concurrency else {
+            return Ok(None);
+        };
+        if self.inflight.fetch_add(1, Ordering::Relaxed) >= limit {
+            self.inflight.fetch_sub(1, Ordering::Relaxed);
+            return Err(Overloaded { limit });
+        }
+        Ok(Some(Inflight(&self.inflight)))
+    }
+}
+
+impl<Cx, Req, S, P> PresetService<S, P>
+where
+    Cx: Context + Send + 'static,
+    Req: Send + 'static,
+    S: Service<Cx, Req> + Send + Sync + 'static,
+    P: PresetProtocol<Cx, Req, S::Response, S::Error>,
+{
+    async fn serve(&self, cx: &mut Cx, req: Req) -> (Result<S::Response, S::Error>, Outcome) {
+        let _inflight = match self.acquire() {
+            Ok(inflight) => inflight,
+            Err(overloaded) => {
+                tracing::debug!(
+                    "[VOLO] request rejected by the preset, limit: {}, rpcinfo: {:?}",
+                    overloaded.limit,
+                    cx.rpc_info()
+                );
+                return (
+                    self.protocol.overloaded(cx, overloaded),
+                    Outcome::Overloaded,
+                );
+            }
+        };
+        let req = match self.max_body_size {
+            Some(max_size) => match self.protocol.limit_size(cx, req, max_size) {
+                Ok(req) => req,
+                Err(rejected) => return (rejected, Outcome::TooLarge),
+            },
+            None => req,
+        };
+
+        let served = if self.catch_panic {
+            // `self.inner.call` is used to create the inner future, which is also possible to panic
+            match std::panic::catch_unwind(AssertUnwindSafe(|| self.inner.call(cx, req))) {
+                Ok(future) => AssertUnwindSafe(with_timeout(self.timeout, future))
+                    .catch_unwind()
+                    .await
+                    .unwrap_or_else(Served::Panicked),
+                Err(payload) => Served::Panicked(payload),
+            }
+        } else {
+            with_timeout(self.timeout, self.inner.call(cx, req)).await
+        };
+
+        match served {
+            Served::Done(result) => {
+                let outcome = if result.is_ok() {
+                    Outcome::Ok
+                } else {
+                    Outcome::Error
+                };
+                (result, outcome)
+            }
+            Served::TimedOut(timeout) => {
+                tracing::warn!(
+                    "[VOLO] handler timed out in {timeout:?}, rpcinfo: {:?}",
+                    cx.rpc_info()
+                );
+                (self.protocol.timed_out(cx, timeout), Outcome::TimedOut)
+            }
+            Served::Panicked(payload) => {
+                let panic_info = catch_panic::take_panic_info().unwrap_or_else(|| {
+                    // the hook may be replaced by the users after the preset is created
+                    PanicInfo {
+                        message: payload_message(payload.as_ref()).into(),
+                        location: None,
+                        backtrace: std::backtrace::Backtrace::disabled(),
+                    }
+                });
+                (
+                    self.protocol.panicked(cx, payload, panic_info),
+                    Outcome::Panicked,
+                )
+            }
+        }
+    }
+}
+
+fn payload_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else if let Some(s) = payload.downcast_ref::<&str>() {
+        (*s).to_owned()
+    } else {
+        "Box<dyn Any>".to_owned()
+    }
+}
+
+impl<Cx, Req, S, P> Service<Cx, Req> for PresetService<S, P>
+where
+    Cx: Context + Send + 'static,
+    Req: Send + 'static,
+    S: Service<Cx, Req> + Send + Sync + 'static,
+    P: PresetProtocol<Cx, Req, S::Response, S::Error>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call<'s, 'cx>(
+        &'s self,
+        cx: &'cx mut Cx,
+        req: Req,
+    ) -> Result<Self::Response, Self::Error> {
+        let start = Instant::now();
+        let (result, outcome) = self.serve(cx, req).await;
+        if let Some(observer) = &self.observer {
+            observer(&RequestRecord {
+                method: cx.rpc_info().method(),
+                latency: start.elapsed(),
+                outcome,
+            });
+        }
+        result
+    }
+}
@@ -0,0 +1,184 @@
+//! Presets of the server layers with the production defaults.
+//!
+//! A [`Preset`] is a single layer stacking the protections every server in production is expected
+//! to have, so that a new service doesn't start without them:
+//!
+//! - a cap of the concurrent requests, beyond which the requests are rejected immediately;
+//! - a limit of the size of the request message or body;
+//! - a timeout of the handler;
+//! - the capture of the panics in the handler, which are converted into the internal errors of the
+//!   protocol instead of closing the connection;
+//! - an optional [`Observer`] of every request, e.g. for the access logs or the metrics, which is
+//!   a no-op unless configured.
+//!
+//! Each of them can be changed or removed by the builder methods of [`Preset`]. The protocol
+//! specific parts are the [`PresetProtocol`] implementations, and the presets are created by
+//! `volo_thrift::server::layer::production_defaults`, `volo_grpc::layer::production_defaults` and
+//! `volo_http::server::layer::production_defaults`.
+//!
+//! # Versions
+//!
+//! The defaults are versioned by [`VERSION`], and a change of any default bumps the version, so
+//! the servers keep the same behavior until they upgrade on purpose. The versions are:
+//!
+//! | Version | Max concurrency | Max body size | Timeout | Panics |
+//! |---------|-----------------|---------------|---------|--------|
+//! | 1       | 10,000          | 4 MiB         | 30s     | caught |
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! let preset = volo_grpc::layer::production_defaults()
+//!     .timeout(Some(Duration::from_secs(5)))
+//!     .observer(|record: &volo::preset::RequestRecord<'_>| {
+//!         tracing::info!(method = record.method, latency = ?record.latency, "{:?}", record.outcome);
+//!     });
+//!
+//! Server::new()
+//!     .layer_front(preset)
+//!     .add_service(ServiceBuilder::new(GreeterServer::new(S)).build())
+//!     .run(addr)
+//!     .await
+//!     .unwrap();
+//! ```
+
+mod layer;
+
+use std::{any::Any, sync::Arc, time::Duration};
+
+pub use self::layer::PresetService;
+use crate::{catch_panic::PanicInfo, load_shed::Overloaded};
+
+/// The version of the defaults, see [the module docs](self#versions).
+pub const VERSION: u32 = 1;
+/// The default cap of the concurrent requests.
+pub const DEFAULT_MAX_CONCURRENCY: usize = 10_000;
+/// The default limit of the size of the request message or body in bytes.
+pub const DEFAULT_MAX_BODY_SIZE: usize = 4 * 1024 * 1024;
+/// The default timeout of the handler.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How a request is finished, reported to the [`Observer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The handler returned a response.
+    Ok,
+    /// The handler returned an error.
+    Error,
+    /// The request was rejected because the concurrency cap was reached.
+    Overloaded,
+    /// The request was rejected because its size exceeded the limit.
+    TooLarge,
+    /// The handler didn't finish in the timeout.
+    TimedOut,
+    /// The handler panicked.
+    Panicked,
+}
+
+/// The record of a finished request, reported to the [`Observer`].
+#[derive(Debug, Clone, Copy)]
+pub struct RequestRecord<'a> {
+    /// The method of the request in the rpc info of the context.
+    pub method: &'a str,
+    /// The time from the request entering the preset to its response.
+    pub latency: Duration,
+    pub outcome: Outcome,
+}
+
+/// The hook called with the record of every request after it's finished.
+pub type Observer = Arc<dyn Fn(&RequestRecord<'_>) + Send + Sync>;
+
+/// The protocol specific part of a [`Preset`], which limits the size of the requests and makes the
+/// responses of the requests stopped by the protections.
+pub trait PresetProtocol<Cx, Req, Resp, E>: Send + Sync {
+    /// Checks the size of the request against `max_size`.
+    ///
+    /// Returns the request, of which the body may be wrapped to be limited while being read, or
+    /// the rejection if the request is known to be too large.
+    fn limit_size(&self, cx: &mut Cx, req: Req, max_size: usize) -> Result<Req, Result<Resp, E>>;
+
+    /// Makes the result of the request whose handler didn't finish in `timeout`.
+    fn timed_out(&self, cx: &mut Cx, timeout: Duration) -> Result<Resp, E>;
+
+    /// Makes the result of the request whose handler panicked.
+    fn panicked(
+        &self,
+        cx: &mut Cx,
+        payload: Box<dyn Any + Send>,
+        panic_info: PanicInfo,
+    ) -> Result<Resp, E>;
+
+    /// Makes the result of the request rejected by the concurrency cap.
+    fn overloaded(&self, cx: &mut Cx, overloaded: Overloaded) -> Result<Resp, E>;
+}
+
+/// A layer stacking the server protections with the production defaults.
+///
+/// [`Preset::new`] starts from the defaults of the current [`VERSION`], and each protection can be
+/// changed or removed by the builder methods, where `None` removes it.
+///
+/// This layer should be put in the front of the server, so that it also covers the other layers.
+#[derive(Clone)]
+pub struct Preset<P> {
+    protocol: P,
+    max_concurrency: Option<usize>,
+    max_body_size: Option<usize>,
+    timeout: Option<Duration>,
+    catch_panic: bool,
+    observer: Option<Observer>,
+}
+
+impl<P> Preset<P> {
+    /// Creates a preset with the defaults of the current [`VERSION`].
+    pub fn new(protocol: P) -> Self {
+        Self {
+            protocol,
+            max_concurrency: Some(DEFAULT_MAX_CONCURRENCY),
+            max_body_size: Some(DEFAULT_MAX_BODY_SIZE),
+            timeout: Some(DEFAULT_TIMEOUT),
+            catch_panic: true,
+            observer: None,
+        }
+    }
+
+    /// Sets the cap of the concurrent requests, or removes it by `None`.
+    pub fn max_concurrency(mut self, max_concurrency: Option<usize>) -> Self {
+        self.max_concurrency = max_concurrency;
+        self
+    }
+
+    /// Sets the limit of the size of the request message or body, or removes it by `None`.
+    pub fn max_body_size(mut self, max_body_size: Option<usize>) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    /// Sets the timeout of the handler, or removes it by `None`.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets whether the panics in the handler are caught.
+    ///
+    /// When disabled, a panic unwinds to the server as without the preset.
+    pub fn catch_panic(mut self, catch_panic: bool) -> Self {
+        self.catch_panic = catch_panic;
+        self
+    }
+
+    /// Sets the [`Observer`] called with the record of every request.
+    pub fn observer<F>(mut self, observer: F) -> Self
+    where
+        F: Fn(&RequestRecord<'_>) + Send + Sync + 'static,
+    {
+        self.observer = Some(Arc::new(observer));
+        self
+    }
+
+    /// Removes the [`Observer`].
+    pub fn without_observer(mut self) -> Self {
+        self.observer = None;
+        self
+    }
+}
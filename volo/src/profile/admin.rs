@@ -0,0 +1,282 @@
+use std::{io, net::SocketAddr, time::Duration};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use super::{ProfileError, Profiling};
+use crate::{event::EventRecorder, readiness::Readiness};
+
+const CPU_PATH: &str = "/debug/pprof/profile";
+const HEAP_PATH: &str = "/debug/pprof/heap";
+const EVENTS_PATH: &str = "/debug/events";
+const READY_PATH: &str = "/ready";
+const DEFAULT_SECONDS: u64 = 30;
+const MAX_SECONDS: u64 = 300;
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The admin endpoint capturing the profiles on demand:
+/// - `GET /debug/pprof/profile?seconds=N` captures a CPU profile for `N` seconds, which is `30` by
+///   default and at most `300`;
+/// - `GET /debug/pprof/heap` dumps a heap profile;
+/// - `GET /debug/events` returns the recent events kept by the [`EventRecorder`] as JSON;
+/// - `GET /ready` returns the [`ReadinessReport`] as JSON, with `503 Service Unavailable` if the
+///   service is not ready.
+///
+/// The profiles are returned as the body, e.g. `curl -o cpu.pb.gz
+/// http://127.0.0.1:6060/debug/pprof/profile?seconds=10` and `go tool pprof cpu.pb.gz`. It
+/// answers `409 Conflict` when another profile is being captured.
+///
+/// [`ReadinessReport`]: crate::readiness::ReadinessReport
+#[derive(Debug, Clone, Default)]
+pub struct ProfileAdmin {
+    cpu: Option<Profiling>,
+    heap: Option<Profiling>,
+    events: Option<EventRecorder>,
+    readiness: Option<Readiness>,
+}
+
+impl ProfileAdmin {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the profiling of the CPU profiles, which should be shared with the [`AutoProfile`]
+    /// so the captures are not concurrent.
+    ///
+    /// [`AutoProfile`]: super::AutoProfile
+    pub fn cpu(mut self, profiling: Profiling) -> Self {
+        self.cpu = Some(profiling);
+        self
+    }
+
+    /// Sets the profiling of the heap profiles.
+    pub fn heap(mut self, profiling: Profiling) -> Self {
+        self.heap = Some(profiling);
+        self
+    }
+
+    /// Sets the recorder of the recent events served by `/debug/events`.
+    pub fn events(mut self, recorder: EventRecorder) -> Self {
+        self.events = Some(recorder);
+        self
+    }
+
+    /// Sets the readiness served by `/ready`, which should be evaluated by
+    /// [`Readiness::spawn`] to be kept up to date.
+    pub fn readiness(mut self, readiness: Readiness) -> Self {
+        self.readiness = Some(readiness);
+        self
+    }
+
+    /// Serves the endpoint on the address.
+    pub async fn serve(self, addr: SocketAddr) -> io::Result<()> {
+        self.serve_with_listener(TcpListener::bind(addr).await?)
+            .await
+    }
+
+    /// Serves the endpoint on the listener.
+    ///
+    /// This is a minimal HTTP/1.1 server answering one request per connection, which should not
+    /// be exposed to the public.
+    pub async fn serve_with_listener(self, listener: TcpListener) -> io::Result<()> {
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let admin = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = admin.handle(stream).await {
+                    tracing::debug!("[VOLO] failed to serve profiles to {peer}: {e}");
+                }
+            });
+        }
+    }
+
+    async fn handle(&self, mut stream: TcpStream) -> io::Result<()> {
+        let mut head = Vec::with_capacity(1024);
+        let mut buf = [0u8; 1024];
+        while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+            if head.len() > MAX_REQUEST_HEAD {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "request head too large",
+                ));
+            }
+            let n = tokio::time::timeout(READ_TIMEOUT, stream.read(&mut buf))
+                .await
+                .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "read request timeout"))??;
+            if n == 0 {
+                return Ok(());
+            }
+            head.extend_from_slice(&buf[..n]);
+        }
+
+        let line = head.split(|b| *b == b'\r').next().unwrap_or_default();
+        let line = String::from_utf8_lossy(line);
+        let mut parts = line.split(' ');
+        let method = parts.next().unwrap_or_default();
+        let target = parts.next().unwrap_or_default();
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+        let (status, body) = if method != "GET" {
+            (
+                "405 Method Not Allowed",
+                Err("method not allowed\n".to_owned()),
+            )
+        } else {
+            self.respond(path, query).await
+        };
+        let (content_type, body) = match body {
+            Ok(body) => body,
+            Err(msg) => ("text/plain", msg.into_bytes()),
+        };
+        let head = format!(
+            "HTTP/1.1 {status}\r\ncontent-type: {content_type}\r\ncontent-length: \
+             {}\r\nconnection: close\r\n\r\n",
+            body.len()
+        );
+        stream.write_all(head.as_bytes()).await?;
+        stream.write_all(&body).await?;
+        stream.shutdown().await
+    }
+
+    async fn respond(
+        &self,
+        path: &str,
+        query: &str,
+    ) -> (&'static str, Result<(&'static str, Vec<u8>), String>) {
+        let (profiling, duration) = match path {
+            EVENTS_PATH => {
+                return match &self.events {
+                    Some(recorder) => (
+                        "200 OK",
+                        Ok(("application/json", recorder.to_json().into_bytes())),
+                    ),
+                    None => (
+                        "404 Not Found",
+                        Err("the events are not recorded\n".to_owned()),
+                    ),
+                }
+            }
+            READY_PATH => {
+                let Some(readiness) = &self.readiness else {
+                    return (
+                        "404 Not Found",
+                        Err("the readiness is not enabled\n".to_owned()),
+                    );
+                };
+                let report = readiness.report();
+                let status = if report.ready {
+                    "200 OK"
+                } else {
+                    "503 Service Unavailable"
+                };
+                return (
+                    status,
+                    Ok(("application/json", report.to_json().into_bytes())),
+                );
+            }
+            CPU_PATH => {
+                let seconds = query
+                    .split('&')
+                    .find_map(|kv| kv.strip_prefix("seconds="))
+                    .map(str::parse::<u64>);
+                let seconds = match seconds {
+                    None => DEFAULT_SECONDS,
+                    Some(Ok(seconds)) if (1..=MAX_SECONDS).contains(&seconds) => seconds,
+                    Some(_) => {
+                        return (
+                            "400 Bad Request",
+                            Err(format!("seconds must be in [1, {MAX_SECONDS}]\n")),
+                        )
+                    }
+                };
+                (&self.cpu, Duration::from_secs(seconds))
+            }
+            HEAP_PATH => (&self.heap, Duration::ZERO),
+            _ => return ("404 Not Found", Err("not found\n".to_owned())),
+        };
+        let Some(profiling) = profiling else {
+            return (
+                "404 Not Found",
+                Err("the profile is not enabled\n".to_owned()),
+            );
+        };
+        match profiling.capture(duration).await {
+            Ok(profile) => ("200 OK", Ok(("application/octet-stream", profile))),
+            Err(e @ ProfileError::Busy) => ("409 Conflict", Err(format!("{e}\n"))),
+            Err(e) => ("500 Internal Server Error", Err(format!("{e}\n"))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future::BoxFuture;
+
+    use super::*;
+    use crate::{profile::Profiler, readiness::Criticality};
+
+    struct Echo;
+
+    impl Profiler for Echo {
+        fn capture(&self, duration: Duration) -> BoxFuture<'static, Result<Vec<u8>, ProfileError>> {
+            Box::pin(async move { Ok(duration.as_secs().to_string().into_bytes()) })
+        }
+    }
+
+    async fn get(addr: SocketAddr, target: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(format!("GET {target} HTTP/1.1\r\nhost: admin\r\n\r\n").as_bytes())
+            .await
+            .unwrap();
+        let mut resp = String::new();
+        stream.read_to_string(&mut resp).await.unwrap();
+        resp
+    }
+
+    #[tokio::test]
+    async fn serve() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let readiness = Readiness::new();
+        readiness.register("item", Criticality::Critical, || async {
+            Err::<(), _>("no instances".to_owned())
+        });
+        tokio::spawn(
+            ProfileAdmin::new()
+                .cpu(Profiling::new(Echo))
+                .events(EventRecorder::new(4))
+                .readiness(readiness.clone())
+                .serve_with_listener(listener),
+        );
+
+        let resp = get(addr, "/debug/pprof/profile?seconds=3").await;
+        assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"), "{resp}");
+        assert!(resp.ends_with("\r\n\r\n3"), "{resp}");
+        let resp = get(addr, "/debug/pprof/profile").await;
+        assert!(resp.ends_with("\r\n\r\n30"), "{resp}");
+        let resp = get(addr, "/debug/pprof/profile?seconds=3600").await;
+        assert!(resp.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{resp}");
+        let resp = get(addr, "/debug/pprof/heap").await;
+        assert!(resp.starts_with("HTTP/1.1 404 Not Found\r\n"), "{resp}");
+        let resp = get(addr, "/debug/events").await;
+        assert!(
+            resp.contains("content-type: application/json\r\n"),
+            "{resp}"
+        );
+        assert!(resp.ends_with("\r\n\r\n[]"), "{resp}");
+        readiness.evaluate().await;
+        let resp = get(addr, "/ready").await;
+        assert!(
+            resp.starts_with("HTTP/1.1 503 Service Unavailable\r\n"),
+            "{resp}"
+        );
+        assert!(
+            resp.ends_with(r#""ready":false,"reason":"no instances"}]}"#),
+            "{resp}"
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use std::{io::Write, time::Duration};
+
+use flate2::{write::GzEncoder, Compression};
+use futures::future::BoxFuture;
+use pprof::protos::Message;
+use tokio::sync::oneshot;
+
+use super::{ProfileError, Profiler};
+
+/// The default sampling frequency, which is not a multiple of the common timer frequencies to
+/// avoid sampling in lockstep with them.
+const DEFAULT_FREQUENCY: i32 = 99;
+
+/// Captures the CPU profiles by pprof-rs, which are the gzipped protobuf of pprof.
+///
+/// The sampling is only started when a profile is being captured.
+#[derive(Debug, Clone)]
+pub struct CpuProfiler {
+    frequency: i32,
+}
+
+impl Default for CpuProfiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CpuProfiler {
+    pub fn new() -> Self {
+        Self {
+            frequency: DEFAULT_FREQUENCY,
+        }
+    }
+
+    /// Sets the number of the samples per second.
+    ///
+    /// Default is `99`.
+    pub fn frequency(mut self, frequency: i32) -> Self {
+        self.frequency = frequency;
+        self
+    }
+}
+
+impl Profiler for CpuProfiler {
+    fn capture(&self, duration: Duration) -> BoxFuture<'static, Result<Vec<u8>, ProfileError>> {
+        let frequency = self.frequency;
+        Box::pin(async move {
+            // the sampling is done by a signal handler, and the guard is held by a dedicated
+            // thread so the capture doesn't occupy a worker of the runtime
+            let (tx, rx) = oneshot::channel();
+            std::thread::Builder::new()
+                .name("volo-cpu-profile".to_owned())
+                .spawn(move || {
+                    let _ = tx.send(profile(frequency, duration));
+                })
+                .map_err(failed)?;
+            rx.await.map_err(failed)?
+        })
+    }
+}
+
+fn profile(frequency: i32, duration: Duration) -> Result<Vec<u8>, ProfileError> {
+    let guard = pprof::ProfilerGuardBuilder::default()
+        .frequency(frequency)
+        .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+        .build()
+        .map_err(failed)?;
+    std::thread::sleep(duration);
+    let profile = guard
+        .report()
+        .build()
+        .map_err(failed)?
+        .pprof()
+        .map_err(failed)?;
+    drop(guard);
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(&profile.encode_to_vec())
+        .map_err(failed)?;
+    encoder.finish().map_err(failed)
+}
+
+fn failed(e: impl std::fmt::Display) -> ProfileError {
+    ProfileError::Failed(e.to_string())
+}
@@ -0,0 +1,62 @@
+use std::{ffi::CString, os::raw::c_char, time::Duration};
+
+use futures::future::BoxFuture;
+
+use super::{ProfileError, Profiler};
+
+/// Dumps the heap profiles by the profiling of jemalloc, which are in the format of `jeprof`.
+///
+/// The application must use `tikv-jemallocator` as the global allocator, and be run with
+/// `MALLOC_CONF=prof:true`, whose overhead is controlled by `lg_prof_sample`.
+#[derive(Debug, Clone, Default)]
+pub struct HeapProfiler;
+
+impl HeapProfiler {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Profiler for HeapProfiler {
+    fn capture(&self, _duration: Duration) -> BoxFuture<'static, Result<Vec<u8>, ProfileError>> {
+        Box::pin(async { dump() })
+    }
+
+    fn kind(&self) -> &'static str {
+        "heap"
+    }
+
+    fn extension(&self) -> &'static str {
+        "prof"
+    }
+}
+
+fn dump() -> Result<Vec<u8>, ProfileError> {
+    // SAFETY: `opt.prof` is a read-only `bool`
+    let enabled = unsafe { tikv_jemalloc_ctl::raw::read::<bool>(b"opt.prof\0") }.map_err(failed)?;
+    if !enabled {
+        return Err(ProfileError::Failed(
+            "the profiling of jemalloc is disabled, run with `MALLOC_CONF=prof:true`".to_owned(),
+        ));
+    }
+
+    let path = std::env::temp_dir().join(format!(
+        "volo-heap-{}-{}.prof",
+        std::process::id(),
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos()
+    ));
+    let c_path = CString::new(path.to_string_lossy().as_bytes()).map_err(failed)?;
+    // SAFETY: `prof.dump` takes the path as a nul-terminated string, which outlives the call
+    unsafe { tikv_jemalloc_ctl::raw::write::<*const c_char>(b"prof.dump\0", c_path.as_ptr()) }
+        .map_err(failed)?;
+    let profile = std::fs::read(&path).map_err(failed);
+    let _ = std::fs::remove_file(&path);
+    profile
+}
+
+fn failed(e: impl std::fmt::Display) -> ProfileError {
+    ProfileError::Failed(e.to_string())
+}
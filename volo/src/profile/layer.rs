@@ -0,0 +1,104 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use motore::{layer::Layer, service::Service};
+
+/// Called when a request is slower than the threshold of the [`SlowRequestLayer`].
+pub trait SlowRequestHook<Cx>: Send + Sync + 'static {
+    fn on_slow_request(&self, cx: &Cx, latency: Duration);
+}
+
+impl<Cx, F> SlowRequestHook<Cx> for F
+where
+    F: Fn(&Cx, Duration) + Send + Sync + 'static,
+{
+    fn on_slow_request(&self, cx: &Cx, latency: Duration) {
+        self(cx, latency)
+    }
+}
+
+/// A layer that calls the [`SlowRequestHook`] when a request is slower than the threshold, e.g.
+/// the [`AutoProfile`](super::AutoProfile).
+///
+/// The latency is measured from entering the layer to the response, so it should be put in the
+/// front of the server to cover the other layers.
+#[derive(Debug)]
+pub struct SlowRequestLayer<H> {
+    threshold: Duration,
+    hook: Arc<H>,
+}
+
+impl<H> Clone for SlowRequestLayer<H> {
+    fn clone(&self) -> Self {
+        Self {
+            threshold: self.threshold,
+            hook: self.hook.clone(),
+        }
+    }
+}
+
+impl<H> SlowRequestLayer<H> {
+    pub fn new(threshold: Duration, hook: H) -> Self {
+        Self {
+            threshold,
+            hook: Arc::new(hook),
+        }
+    }
+}
+
+impl<S, H> Layer<S> for SlowRequestLayer<H> {
+    type Service = SlowRequestService<S, H>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        SlowRequestService {
+            inner,
+            threshold: self.threshold,
+            hook: self.hook,
+        }
+    }
+}
+
+/// The service created by [`SlowRequestLayer`].
+#[derive(Debug)]
+pub struct SlowRequestService<S, H> {
+    inner: S,
+    threshold: Duration,
+    hook: Arc<H>,
+}
+
+impl<S: Clone, H> Clone for SlowRequestService<S, H> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            threshold: self.threshold,
+            hook: self.hook.clone(),
+        }
+    }
+}
+
+impl<Cx, Req, S, H> Service<Cx, Req> for SlowRequestService<S, H>
+where
+    Cx: Send + 'static,
+    Req: Send + 'static,
+    S: Service<Cx, Req> + Send + Sync + 'static,
+    H: SlowRequestHook<Cx>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call<'s, 'cx>(
+        &'s self,
+        cx: &'cx mut Cx,
+        req: Req,
+    ) -> Result<Self::Response, Self::Error> {
+        let start = Instant::now();
+        let result = self.inner.call(cx, req).await;
+        let latency = start.elapsed();
+        if latency >= self.threshold {
+            self.hook.on_slow_request(cx, latency);
+        }
+        result
+    }
+}
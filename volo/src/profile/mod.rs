@@ -0,0 +1,425 @@
+//! On-demand profiling, for capturing the profiles of a latency regression without redeploying.
+//!
+//! A [`Profiling`] captures the profiles by a [`Profiler`], one at a time, and can be used in
+//! three ways:
+//! - programmatically by [`Profiling::capture`];
+//! - on the admin endpoint served by [`ProfileAdmin`], e.g. `GET /debug/pprof/profile?seconds=10`;
+//! - automatically by [`AutoProfile`], which captures a profile when too many requests are slow,
+//!   and is installed by the [`SlowRequestLayer`].
+//!
+//! The CPU profiles are captured by [pprof-rs](https://docs.rs/pprof) with the `pprof` feature,
+//! see [`CpuProfiler`], and the heap profiles are dumped by jemalloc with the `jemalloc-profile`
+//! feature, see [`HeapProfiler`].
+//!
+//! Nothing is sampled when no profile is being captured, and the requests which are not slow only
+//! cost a comparison of the latency.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use volo::profile::{AutoProfile, CpuProfiler, ProfileAdmin, Profiling, SlowRequestLayer};
+//!
+//! let cpu = Profiling::new(CpuProfiler::new());
+//!
+//! // capture a CPU profile when 10 requests are slower than 500ms within a minute
+//! let auto = AutoProfile::new(cpu.clone(), "/var/log/app/profiles");
+//! server.layer_front(SlowRequestLayer::new(Duration::from_millis(500), auto));
+//!
+//! // and on the admin endpoint
+//! tokio::spawn(ProfileAdmin::new().cpu(cpu).serve("[::]:6060".parse().unwrap()));
+//! ```
+
+mod admin;
+#[cfg(feature = "pprof")]
+mod cpu;
+#[cfg(feature = "jemalloc-profile")]
+mod heap;
+mod layer;
+
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use futures::future::BoxFuture;
+
+#[cfg(feature = "pprof")]
+#[cfg_attr(docsrs, doc(cfg(feature = "pprof")))]
+pub use self::cpu::CpuProfiler;
+#[cfg(feature = "jemalloc-profile")]
+#[cfg_attr(docsrs, doc(cfg(feature = "jemalloc-profile")))]
+pub use self::heap::HeapProfiler;
+pub use self::{
+    admin::ProfileAdmin,
+    layer::{SlowRequestHook, SlowRequestLayer, SlowRequestService},
+};
+use crate::error::{code, CodedError, ErrorCode};
+
+/// The error of capturing a profile.
+#[derive(Debug, thiserror::Error)]
+pub enum ProfileError {
+    #[error("another profile is being captured")]
+    Busy,
+    #[error("failed to capture the profile: {0}")]
+    Failed(String),
+}
+
+impl CodedError for ProfileError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::Busy => code::PROFILE_BUSY,
+            Self::Failed(_) => code::PROFILE_FAILED,
+        }
+    }
+
+    fn is_retryable(&self) -> bool {
+        matches!(self, Self::Busy)
+    }
+}
+
+/// Captures a profile, e.g. the CPU profile by pprof-rs.
+pub trait Profiler: Send + Sync + 'static {
+    /// Captures a profile for the duration, which is ignored by the profilers of snapshots, e.g.
+    /// the heap profiles.
+    fn capture(&self, duration: Duration) -> BoxFuture<'static, Result<Vec<u8>, ProfileError>>;
+
+    /// The kind of the profiles, which prefixes the names of the files written by
+    /// [`AutoProfile`].
+    ///
+    /// Default is `cpu`.
+    fn kind(&self) -> &'static str {
+        "cpu"
+    }
+
+    /// The extension of the names of the files written by [`AutoProfile`].
+    ///
+    /// Default is `pb.gz`, i.e. the gzipped protobuf of pprof.
+    fn extension(&self) -> &'static str {
+        "pb.gz"
+    }
+}
+
+/// Captures the profiles by a [`Profiler`], and makes sure only one is captured at a time.
+///
+/// It is cheap to clone, and the clones share the guard.
+#[derive(Clone)]
+pub struct Profiling {
+    profiler: Arc<dyn Profiler>,
+    busy: Arc<AtomicBool>,
+}
+
+impl Profiling {
+    pub fn new(profiler: impl Profiler) -> Self {
+        Self {
+            profiler: Arc::new(profiler),
+            busy: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Captures a profile for the duration, or fails with [`ProfileError::Busy`] if another one
+    /// is being captured.
+    pub async fn capture(&self, duration: Duration) -> Result<Vec<u8>, ProfileError> {
+        if self
+            .busy
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            return Err(ProfileError::Busy);
+        }
+        // released even if the capture is cancelled, e.g. the admin request is closed
+        let _guard = BusyGuard(&self.busy);
+        self.profiler.capture(duration).await
+    }
+
+    /// Returns if a profile is being captured.
+    pub fn is_busy(&self) -> bool {
+        self.busy.load(Ordering::Acquire)
+    }
+
+    /// Returns the name of the file of a profile captured at the time, e.g.
+    /// `cpu-1700000000000.pb.gz`.
+    fn file_name(&self, time: SystemTime) -> String {
+        let millis = time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        format!(
+            "{}-{millis}.{}",
+            self.profiler.kind(),
+            self.profiler.extension()
+        )
+    }
+}
+
+impl std::fmt::Debug for Profiling {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Profiling")
+            .field("kind", &self.profiler.kind())
+            .field("busy", &self.is_busy())
+            .finish()
+    }
+}
+
+struct BusyGuard<'a>(&'a AtomicBool);
+
+impl Drop for BusyGuard<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+const DEFAULT_SLOW_REQUESTS: usize = 10;
+const DEFAULT_WINDOW: Duration = Duration::from_secs(60);
+const DEFAULT_COOLDOWN: Duration = Duration::from_secs(30 * 60);
+const DEFAULT_DURATION: Duration = Duration::from_secs(10);
+
+/// Captures a profile automatically when too many requests are slow, which is a
+/// [`SlowRequestHook`].
+///
+/// When the slow requests within a window reach the limit, a profile is captured in the
+/// background and written to the directory with a timestamped name, e.g.
+/// `cpu-1700000000000.pb.gz`. At most one profile is captured in a cool-down period, and none is
+/// captured if another one is being captured, e.g. on the admin endpoint.
+#[derive(Debug)]
+pub struct AutoProfile {
+    profiling: Profiling,
+    dir: PathBuf,
+    slow_requests: usize,
+    window: Duration,
+    cooldown: Duration,
+    duration: Duration,
+    state: Mutex<TriggerState>,
+}
+
+#[derive(Debug, Default)]
+struct TriggerState {
+    window_start: Option<Instant>,
+    count: usize,
+    last_capture: Option<Instant>,
+}
+
+impl AutoProfile {
+    pub fn new(profiling: Profiling, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            profiling,
+            dir: dir.into(),
+            slow_requests: DEFAULT_SLOW_REQUESTS,
+            window: DEFAULT_WINDOW,
+            cooldown: DEFAULT_COOLDOWN,
+            duration: DEFAULT_DURATION,
+            state: Mutex::new(TriggerState::default()),
+        }
+    }
+
+    /// Sets the number of the slow requests within a window which trigger a capture.
+    ///
+    /// Default is `10`.
+    pub fn slow_requests(mut self, slow_requests: usize) -> Self {
+        self.slow_requests = slow_requests.max(1);
+        self
+    }
+
+    /// Sets the window in which the slow requests are counted.
+    ///
+    /// Default is `60s`.
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Sets the minimum interval between two captures.
+    ///
+    /// Default is `30min`.
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Sets the duration of a capture.
+    ///
+    /// Default is `10s`.
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    /// Counts a slow request at the time, and returns if a capture should be started.
+    fn record(&self, now: Instant) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.window_start {
+            Some(start) if now.saturating_duration_since(start) < self.window => {}
+            _ => {
+                state.window_start = Some(now);
+                state.count = 0;
+            }
+        }
+        state.count += 1;
+        if state.count < self.slow_requests {
+            return false;
+        }
+        if let Some(last) = state.last_capture {
+            if now.saturating_duration_since(last) < self.cooldown {
+                return false;
+            }
+        }
+        if self.profiling.is_busy() {
+            return false;
+        }
+        state.last_capture = Some(now);
+        state.window_start = None;
+        true
+    }
+
+    fn spawn_capture(&self) {
+        let profiling = self.profiling.clone();
+        let dir = self.dir.clone();
+        let duration = self.duration;
+        tokio::spawn(async move {
+            let data = match profiling.capture(duration).await {
+                Ok(data) => data,
+                Err(e) => {
+                    tracing::warn!(
+                        "[VOLO] failed to capture the profile triggered by slow requests: {e}, \
+                         code: {}",
+                        e.code()
+                    );
+                    return;
+                }
+            };
+            let path = dir.join(profiling.file_name(SystemTime::now()));
+            // a profile is written at most once per cool-down, so blocking is fine
+            match std::fs::create_dir_all(&dir).and_then(|_| std::fs::write(&path, data)) {
+                Ok(()) => tracing::info!(
+                    "[VOLO] profile triggered by slow requests is written to {}",
+                    path.display()
+                ),
+                Err(e) => tracing::warn!(
+                    "[VOLO] failed to write the profile to {}: {e}",
+                    path.display()
+                ),
+            }
+        });
+    }
+}
+
+impl<Cx> SlowRequestHook<Cx> for AutoProfile {
+    fn on_slow_request(&self, _cx: &Cx, _latency: Duration) {
+        if self.record(Instant::now()) {
+            self.spawn_capture();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+
+    /// Returns the sequence number of the capture as the profile.
+    #[derive(Clone, Default)]
+    struct FakeProfiler {
+        captures: Arc<AtomicUsize>,
+    }
+
+    impl Profiler for FakeProfiler {
+        fn capture(&self, duration: Duration) -> BoxFuture<'static, Result<Vec<u8>, ProfileError>> {
+            let n = self.captures.fetch_add(1, Ordering::Relaxed);
+            Box::pin(async move {
+                tokio::time::sleep(duration).await;
+                Ok(n.to_string().into_bytes())
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn one_at_a_time() {
+        let profiling = Profiling::new(FakeProfiler::default());
+        let slow = profiling.capture(Duration::from_millis(100));
+        let busy = async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            assert!(profiling.is_busy());
+            profiling.capture(Duration::ZERO).await
+        };
+        let (slow, busy) = tokio::join!(slow, busy);
+        assert_eq!(slow.unwrap(), b"0");
+        assert!(matches!(busy, Err(ProfileError::Busy)));
+        assert!(!profiling.is_busy());
+
+        // the guard is released when the capture is cancelled
+        let cancelled = tokio::time::timeout(
+            Duration::from_millis(10),
+            profiling.capture(Duration::from_secs(3600)),
+        )
+        .await;
+        assert!(cancelled.is_err());
+        assert_eq!(profiling.capture(Duration::ZERO).await.unwrap(), b"2");
+    }
+
+    #[test]
+    fn trigger() {
+        let secs = Duration::from_secs;
+        let auto = AutoProfile::new(Profiling::new(FakeProfiler::default()), "")
+            .slow_requests(3)
+            .window(secs(10))
+            .cooldown(secs(100));
+        let t0 = Instant::now();
+
+        // the slow requests in different windows don't trigger
+        assert!(!auto.record(t0));
+        assert!(!auto.record(t0 + secs(5)));
+        assert!(!auto.record(t0 + secs(11)));
+        assert!(!auto.record(t0 + secs(12)));
+        assert!(auto.record(t0 + secs(13)));
+
+        // cooling down
+        for i in 0..10 {
+            assert!(!auto.record(t0 + secs(14 + i)));
+        }
+        assert!(!auto.record(t0 + secs(110)));
+        assert!(!auto.record(t0 + secs(111)));
+        assert!(auto.record(t0 + secs(113)));
+    }
+
+    #[tokio::test]
+    async fn capture_on_slow_requests() {
+        let dir = tempfile::tempdir().unwrap();
+        let profiler = FakeProfiler::default();
+        let profiling = Profiling::new(profiler.clone());
+        let auto = AutoProfile::new(profiling.clone(), dir.path().join("profiles"))
+            .slow_requests(2)
+            .duration(Duration::from_millis(50));
+
+        // not triggered when another profile is being captured
+        let manual = tokio::spawn({
+            let profiling = profiling.clone();
+            async move { profiling.capture(Duration::from_millis(100)).await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        for _ in 0..2 {
+            auto.on_slow_request(&(), Duration::from_secs(1));
+        }
+        assert_eq!(manual.await.unwrap().unwrap(), b"0");
+
+        for _ in 0..4 {
+            auto.on_slow_request(&(), Duration::from_secs(1));
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        // only one capture in the cool-down period
+        assert_eq!(profiler.captures.load(Ordering::Relaxed), 2);
+        let files = std::fs::read_dir(dir.path().join("profiles"))
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].starts_with("cpu-") && files[0].ends_with(".pb.gz"));
+        assert_eq!(
+            std::fs::read(dir.path().join("profiles").join(&files[0])).unwrap(),
+            b"1"
+        );
+    }
+}
@@ -0,0 +1,76 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use super::RateLimit;
+
+/// A token bucket implemented by the generic cell rate algorithm, whose state is a single atomic
+/// timestamp, so acquiring a token never blocks the other threads.
+///
+/// The timestamps are the nanoseconds since the epoch of the [`RateLimiter`](super::RateLimiter).
+#[derive(Debug)]
+pub(super) struct Bucket {
+    /// The interval between two tokens.
+    interval: u64,
+    /// How far the theoretical arrival time can go beyond now, which is the burst.
+    tolerance: u64,
+    /// The theoretical arrival time of the next request if the bucket is full.
+    tat: AtomicU64,
+}
+
+impl Bucket {
+    pub(super) fn new(limit: RateLimit) -> Self {
+        let interval = Duration::from_secs(1).as_nanos() as u64 / limit.qps as u64;
+        Self {
+            interval,
+            tolerance: interval * limit.burst as u64,
+            tat: AtomicU64::new(0),
+        }
+    }
+
+    /// Takes a token at `now`, or returns how long to wait for the next token.
+    pub(super) fn acquire(&self, now: u64) -> Result<(), Duration> {
+        let mut tat = self.tat.load(Ordering::Relaxed);
+        loop {
+            let next = tat.max(now) + self.interval;
+            if next > now + self.tolerance {
+                return Err(Duration::from_nanos(next - now - self.tolerance));
+            }
+            match self
+                .tat
+                .compare_exchange_weak(tat, next, Ordering::Relaxed, Ordering::Relaxed)
+            {
+                Ok(_) => return Ok(()),
+                Err(actual) => tat = actual,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: u64 = 1_000_000;
+
+    #[test]
+    fn burst_and_refill() {
+        // a token every 10ms
+        let bucket = Bucket::new(RateLimit::new(100).burst(3));
+        for _ in 0..3 {
+            assert!(bucket.acquire(0).is_ok());
+        }
+        assert_eq!(bucket.acquire(0), Err(Duration::from_millis(10)));
+        assert_eq!(bucket.acquire(4 * MS), Err(Duration::from_millis(6)));
+
+        assert!(bucket.acquire(10 * MS).is_ok());
+        assert!(bucket.acquire(10 * MS).is_err());
+
+        // the tokens are capped at the burst after being idle
+        for _ in 0..3 {
+            assert!(bucket.acquire(1000 * MS).is_ok());
+        }
+        assert!(bucket.acquire(1000 * MS).is_err());
+    }
+}
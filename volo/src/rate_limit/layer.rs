@@ -0,0 +1,104 @@
+use std::sync::Arc;
+
+use motore::{layer::Layer, service::Service};
+
+use super::{RateLimitRejection, RateLimiter};
+use crate::context::Context;
+
+/// A layer that rejects the requests exceeding the limits of the [`RateLimiter`].
+///
+/// This layer should be put in the outer layers of the server, so that the rejected requests cost
+/// as little as possible. The [`RateLimiter`] is shared by all the clones of the service created
+/// by this layer.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use volo::rate_limit::{RateLimit, RateLimitLayer, RateLimiter};
+/// use volo_grpc::layer::rate_limit::GrpcRateLimitRejection;
+///
+/// let limiter = RateLimiter::new()
+///     .global(RateLimit::new(10000))
+///     .method("/hello.Greeter/SayHello", RateLimit::new(1000).burst(100))
+///     .per_caller(RateLimit::new(500));
+///
+/// Server::new()
+///     .layer_front(RateLimitLayer::new(limiter, GrpcRateLimitRejection))
+///     .add_service(ServiceBuilder::new(GreeterServer::new(S)).build())
+///     .run(addr)
+///     .await
+///     .unwrap();
+/// ```
+#[derive(Clone)]
+pub struct RateLimitLayer<R> {
+    limiter: Arc<RateLimiter>,
+    rejection: R,
+}
+
+impl<R> RateLimitLayer<R> {
+    pub fn new(limiter: impl Into<Arc<RateLimiter>>, rejection: R) -> Self {
+        Self {
+            limiter: limiter.into(),
+            rejection,
+        }
+    }
+}
+
+impl<S, R> Layer<S> for RateLimitLayer<R> {
+    type Service = RateLimitService<S, R>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        RateLimitService {
+            inner,
+            limiter: self.limiter,
+            rejection: Arc::new(self.rejection),
+        }
+    }
+}
+
+/// The service created by [`RateLimitLayer`].
+pub struct RateLimitService<S, R> {
+    inner: S,
+    limiter: Arc<RateLimiter>,
+    rejection: Arc<R>,
+}
+
+impl<S: Clone, R> Clone for RateLimitService<S, R> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            limiter: self.limiter.clone(),
+            rejection: self.rejection.clone(),
+        }
+    }
+}
+
+impl<Cx, Req, S, R> Service<Cx, Req> for RateLimitService<S, R>
+where
+    Cx: Context + Send + 'static,
+    Req: Send + 'static,
+    S: Service<Cx, Req> + Send + Sync + 'static,
+    R: RateLimitRejection<Cx, S::Error> + Send + Sync,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call<'s, 'cx>(
+        &'s self,
+        cx: &'cx mut Cx,
+        req: Req,
+    ) -> Result<Self::Response, Self::Error> {
+        let rpc_info = cx.rpc_info();
+        if let Err(rejected) = self
+            .limiter
+            .acquire(rpc_info.method(), rpc_info.caller().service_name_ref())
+        {
+            tracing::debug!(
+                "[VOLO] request rejected by rate limit, rpcinfo: {:?}",
+                rpc_info
+            );
+            return Err(self.rejection.reject(cx, rejected));
+        }
+        self.inner.call(cx, req).await
+    }
+}
@@ -0,0 +1,262 @@
+//! Rate limiting of the servers for overload protection.
+//!
+//! The [`RateLimiter`] holds the token buckets of the server, which can limit all the requests,
+//! the requests of a method, the requests of each caller, identified by the service name of the
+//! caller in the `RpcInfo`, and the requests of a method from each caller. A request is rejected
+//! if any of the buckets it falls into is empty.
+//!
+//! The buckets are lock-free, and the buckets of the callers are kept in a sharded map, so the
+//! limiter can be shared by all the connections without contention on a global lock.
+//!
+//! The [`RateLimitLayer`] rejects the requests by the protocol specific [`RateLimitRejection`],
+//! which are provided in `volo-thrift` and `volo-grpc`.
+
+mod bucket;
+mod layer;
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+use faststr::FastStr;
+
+use self::bucket::Bucket;
+pub use self::layer::{RateLimitLayer, RateLimitService};
+
+/// The rate of a token bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    qps: u32,
+    burst: u32,
+}
+
+impl RateLimit {
+    /// Creates a new [`RateLimit`] allowing `qps` requests per second, with a burst of `qps`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `qps` is zero.
+    pub fn new(qps: u32) -> Self {
+        assert!(qps > 0, "qps of rate limit must be positive");
+        Self { qps, burst: qps }
+    }
+
+    /// Sets how many requests can be accepted at once after being idle, at least 1.
+    ///
+    /// Default is the `qps`.
+    pub fn burst(mut self, burst: u32) -> Self {
+        self.burst = burst.max(1);
+        self
+    }
+}
+
+/// The error of a request rejected by the [`RateLimiter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("rate limited, retry after {}ms", .retry_after.as_millis())]
+pub struct RateLimited {
+    /// How long to wait for the bucket that rejected the request to have a token.
+    pub retry_after: Duration,
+}
+
+/// The token buckets of a server.
+#[derive(Debug)]
+pub struct RateLimiter {
+    epoch: Instant,
+    global: Option<Bucket>,
+    methods: HashMap<FastStr, Bucket>,
+    per_caller: Option<CallerBuckets>,
+    method_per_caller: HashMap<FastStr, CallerBuckets>,
+}
+
+/// The buckets of the callers created on their first requests.
+#[derive(Debug)]
+struct CallerBuckets {
+    limit: RateLimit,
+    buckets: DashMap<FastStr, Bucket>,
+}
+
+impl CallerBuckets {
+    fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            buckets: DashMap::new(),
+        }
+    }
+
+    fn acquire(&self, now: u64, caller: &str) -> Result<(), Duration> {
+        match self.buckets.get(caller) {
+            Some(bucket) => bucket.acquire(now),
+            None => self
+                .buckets
+                .entry(FastStr::new(caller))
+                .or_insert_with(|| Bucket::new(self.limit))
+                .acquire(now),
+        }
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RateLimiter {
+    /// Creates a new [`RateLimiter`] without any limit.
+    pub fn new() -> Self {
+        Self {
+            epoch: Instant::now(),
+            global: None,
+            methods: HashMap::new(),
+            per_caller: None,
+            method_per_caller: HashMap::new(),
+        }
+    }
+
+    /// Limits all the requests of the server.
+    pub fn global(mut self, limit: RateLimit) -> Self {
+        self.global = Some(Bucket::new(limit));
+        self
+    }
+
+    /// Limits the requests of the method.
+    pub fn method(mut self, method: impl Into<FastStr>, limit: RateLimit) -> Self {
+        self.methods.insert(method.into(), Bucket::new(limit));
+        self
+    }
+
+    /// Limits the requests of each caller separately, and the requests without a caller share one
+    /// bucket.
+    pub fn per_caller(mut self, limit: RateLimit) -> Self {
+        self.per_caller = Some(CallerBuckets::new(limit));
+        self
+    }
+
+    /// Limits the requests of the method from each caller separately, and the requests without a
+    /// caller share one bucket.
+    ///
+    /// This is checked together with the limits of [`RateLimiter::method`] and
+    /// [`RateLimiter::per_caller`], if any.
+    pub fn method_per_caller(mut self, method: impl Into<FastStr>, limit: RateLimit) -> Self {
+        self.method_per_caller
+            .insert(method.into(), CallerBuckets::new(limit));
+        self
+    }
+
+    /// Takes a token from each bucket the request falls into, from the most specific one.
+    ///
+    /// The tokens already taken are not returned if a later bucket rejects the request.
+    pub fn acquire(&self, method: &str, caller: &str) -> Result<(), RateLimited> {
+        let now = self.epoch.elapsed().as_nanos() as u64;
+        self.acquire_at(now, method, caller)
+            .map_err(|retry_after| RateLimited { retry_after })
+    }
+
+    fn acquire_at(&self, now: u64, method: &str, caller: &str) -> Result<(), Duration> {
+        if let Some(callers) = self.method_per_caller.get(method) {
+            callers.acquire(now, caller)?;
+        }
+        if let Some(callers) = &self.per_caller {
+            callers.acquire(now, caller)?;
+        }
+        if let Some(bucket) = self.methods.get(method) {
+            bucket.acquire(now)?;
+        }
+        if let Some(bucket) = &self.global {
+            bucket.acquire(now)?;
+        }
+        Ok(())
+    }
+}
+
+/// The protocol specific part of rate limiting, which makes the error of a rejected request.
+pub trait RateLimitRejection<Cx, E> {
+    /// Makes the error returned for the rejected request, and sets the hints in the context if
+    /// needed.
+    fn reject(&self, cx: &mut Cx, rejected: RateLimited) -> E;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    use super::*;
+
+    #[test]
+    fn buckets() {
+        let limiter = RateLimiter::new()
+            .method("slow", RateLimit::new(1).burst(1))
+            .per_caller(RateLimit::new(1).burst(2));
+
+        assert!(limiter.acquire("slow", "a").is_ok());
+        assert!(limiter.acquire("slow", "b").is_err());
+        assert!(limiter.acquire("fast", "b").is_ok());
+        // the token of the caller is taken by the rejected request
+        assert!(limiter.acquire("fast", "b").is_err());
+        assert!(limiter.acquire("fast", "a").is_ok());
+
+        let rejected = limiter.acquire("fast", "a").unwrap_err();
+        assert!(rejected.retry_after > Duration::from_millis(900));
+        assert!(rejected.retry_after <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn method_per_caller() {
+        let limiter = RateLimiter::new().method_per_caller("slow", RateLimit::new(1).burst(1));
+
+        assert!(limiter.acquire("slow", "a").is_ok());
+        assert!(limiter.acquire("slow", "a").is_err());
+        assert!(limiter.acquire("slow", "b").is_ok());
+        assert!(limiter.acquire("fast", "a").is_ok());
+        assert!(limiter.acquire("fast", "a").is_ok());
+    }
+
+    #[test]
+    fn ceiling_under_load() {
+        const QPS: u32 = 2000;
+        const BURST: u32 = 20;
+
+        let limiter = Arc::new(RateLimiter::new().global(RateLimit::new(QPS).burst(BURST)));
+        let accepted = Arc::new(AtomicU64::new(0));
+        let start = Instant::now();
+        let duration = Duration::from_secs(1);
+
+        let threads = (0..8)
+            .map(|i| {
+                let limiter = limiter.clone();
+                let accepted = accepted.clone();
+                thread::spawn(move || {
+                    let caller = format!("caller-{}", i % 3);
+                    while start.elapsed() < duration {
+                        if limiter.acquire("method", &caller).is_ok() {
+                            accepted.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        let elapsed = start.elapsed().as_secs_f64();
+        let ceiling = QPS as f64 * elapsed + BURST as f64;
+        let accepted = accepted.load(Ordering::Relaxed) as f64;
+        assert!(
+            accepted <= ceiling,
+            "accepted: {accepted}, ceiling: {ceiling}"
+        );
+        assert!(
+            accepted >= ceiling * 0.95,
+            "accepted: {accepted}, ceiling: {ceiling}"
+        );
+    }
+}
@@ -0,0 +1,361 @@
+//! The readiness of a service aggregated from its sources, e.g. the critical downstream clients,
+//! for the health checks and the admin endpoint.
+//!
+//! ```rust,ignore
+//! let readiness = Readiness::new();
+//! readiness.register("item", Criticality::Critical, item_client.0.readiness());
+//! readiness.register("cache", Criticality::NonCritical, cache_client.0.readiness());
+//! // re-evaluates the sources every 5 seconds, so losing all the instances of the item service
+//! // flips the readiness off
+//! readiness.spawn(Duration::from_secs(5));
+//!
+//! tokio::spawn(ProfileAdmin::new().readiness(readiness.clone()).serve(admin_addr));
+//! readiness.wait_ready().await;
+//! ```
+//!
+//! The service is ready when all the critical sources are ready, and the non-critical ones only
+//! show in the [`ReadinessReport`]. Each source is checked within a timeout, so a stuck one is not
+//! ready with the reason instead of hanging the evaluation. A source registered is not ready until
+//! it is checked.
+
+use std::{
+    fmt::{self, Write},
+    future::Future,
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
+};
+
+use faststr::FastStr;
+use futures::future::{self, BoxFuture};
+use tokio::{sync::watch, task::JoinHandle};
+
+use crate::event::push_str;
+
+/// The default timeout of checking each source.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// A source of the readiness, e.g. the readiness of a client.
+///
+/// It's implemented for the closures returning the futures of the checks.
+pub trait ReadinessSource: Send + Sync + 'static {
+    /// Checks if the source is ready, and returns the reason if not.
+    fn check(&self) -> BoxFuture<'_, Result<(), String>>;
+}
+
+impl<F, Fut> ReadinessSource for F
+where
+    F: Fn() -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<(), String>> + Send + 'static,
+{
+    fn check(&self) -> BoxFuture<'_, Result<(), String>> {
+        Box::pin(self())
+    }
+}
+
+/// Whether a source gates the readiness.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Criticality {
+    /// The service is not ready unless the source is ready.
+    Critical,
+    /// The source only shows in the report.
+    NonCritical,
+}
+
+/// The status of a source in the last evaluation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceStatus {
+    pub name: FastStr,
+    pub criticality: Criticality,
+    pub ready: bool,
+    /// Why the source is not ready.
+    pub reason: Option<String>,
+}
+
+/// The result of an evaluation of the sources.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadinessReport {
+    /// Whether all the critical sources are ready.
+    pub ready: bool,
+    pub sources: Vec<SourceStatus>,
+}
+
+impl ReadinessReport {
+    fn new(sources: Vec<SourceStatus>) -> Self {
+        Self {
+            ready: sources
+                .iter()
+                .all(|s| s.ready || s.criticality == Criticality::NonCritical),
+            sources,
+        }
+    }
+
+    /// Returns the sources not ready.
+    pub fn not_ready(&self) -> impl Iterator<Item = &SourceStatus> {
+        self.sources.iter().filter(|s| !s.ready)
+    }
+
+    /// Returns the report as JSON, e.g. `{"ready":false,"sources":[{"name":"item",
+    /// "critical":true,"ready":false,"reason":"no instances"}]}`.
+    pub fn to_json(&self) -> String {
+        let mut json = format!(r#"{{"ready":{},"sources":["#, self.ready);
+        for (i, source) in self.sources.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            json.push_str(r#"{"name":"#);
+            push_str(&mut json, &source.name);
+            let _ = write!(
+                json,
+                r#","critical":{},"ready":{}"#,
+                source.criticality == Criticality::Critical,
+                source.ready
+            );
+            if let Some(reason) = &source.reason {
+                json.push_str(r#","reason":"#);
+                push_str(&mut json, reason);
+            }
+            json.push('}');
+        }
+        json.push_str("]}");
+        json
+    }
+}
+
+struct Source {
+    name: FastStr,
+    criticality: Criticality,
+    source: Box<dyn ReadinessSource>,
+}
+
+struct Inner {
+    sources: Mutex<Vec<Arc<Source>>>,
+    timeout: Duration,
+    report: watch::Sender<ReadinessReport>,
+}
+
+/// The readiness of a service, see the [module docs](self).
+///
+/// The clones share the same sources and report.
+#[derive(Clone)]
+pub struct Readiness {
+    inner: Arc<Inner>,
+}
+
+impl fmt::Debug for Readiness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Readiness")
+            .field("timeout", &self.inner.timeout)
+            .field("report", &*self.inner.report.borrow())
+            .finish()
+    }
+}
+
+impl Default for Readiness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Readiness {
+    /// Creates a [`Readiness`] checking each source within [`DEFAULT_TIMEOUT`].
+    pub fn new() -> Self {
+        Self::with_timeout(DEFAULT_TIMEOUT)
+    }
+
+    /// Creates a [`Readiness`] checking each source within the timeout.
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                sources: Mutex::new(Vec::new()),
+                timeout,
+                report: watch::channel(ReadinessReport::new(Vec::new())).0,
+            }),
+        }
+    }
+
+    /// Registers a source, which is not ready until it is checked by the next evaluation.
+    pub fn register(
+        &self,
+        name: impl Into<FastStr>,
+        criticality: Criticality,
+        source: impl ReadinessSource,
+    ) {
+        let name = name.into();
+        self.inner.sources.lock().unwrap().push(Arc::new(Source {
+            name: name.clone(),
+            criticality,
+            source: Box::new(source),
+        }));
+        self.inner.report.send_modify(|report| {
+            let mut sources = std::mem::take(&mut report.sources);
+            sources.push(SourceStatus {
+                name,
+                criticality,
+                ready: false,
+                reason: Some("not checked yet".to_owned()),
+            });
+            *report = ReadinessReport::new(sources);
+        });
+    }
+
+    /// Checks all the sources concurrently, and updates the readiness by the results.
+    pub async fn evaluate(&self) -> ReadinessReport {
+        let sources = self.inner.sources.lock().unwrap().clone();
+        let timeout = self.inner.timeout;
+        let statuses = future::join_all(sources.iter().map(|s| async move {
+            let result = match tokio::time::timeout(timeout, s.source.check()).await {
+                Ok(result) => result,
+                Err(_) => Err(format!("the check timed out after {timeout:?}")),
+            };
+            SourceStatus {
+                name: s.name.clone(),
+                criticality: s.criticality,
+                ready: result.is_ok(),
+                reason: result.err(),
+            }
+        }))
+        .await;
+
+        let report = ReadinessReport::new(statuses);
+        let previous = self.inner.report.send_replace(report.clone());
+        if previous.ready != report.ready {
+            if report.ready {
+                tracing::info!("[VOLO] the service is ready");
+            } else {
+                let reasons = report
+                    .not_ready()
+                    .filter(|s| s.criticality == Criticality::Critical)
+                    .map(|s| format!("{}: {}", s.name, s.reason.as_deref().unwrap_or_default()))
+                    .collect::<Vec<_>>();
+                tracing::warn!("[VOLO] the service is not ready, {}", reasons.join(", "));
+            }
+        }
+        report
+    }
+
+    /// Returns whether the service is ready by the last evaluation.
+    pub fn is_ready(&self) -> bool {
+        self.inner.report.borrow().ready
+    }
+
+    /// Returns the report of the last evaluation.
+    pub fn report(&self) -> ReadinessReport {
+        self.inner.report.borrow().clone()
+    }
+
+    /// Waits until the service is ready, which never resolves if no evaluation makes it ready.
+    pub async fn wait_ready(&self) {
+        let mut report = self.inner.report.subscribe();
+        // the sender is kept by `self`, so it never fails
+        let _ = report.wait_for(|report| report.ready).await;
+    }
+
+    /// Evaluates the sources every `interval` in the background, which stops when all the clones
+    /// of the [`Readiness`] are dropped.
+    pub fn spawn(&self, interval: Duration) -> JoinHandle<()> {
+        let inner: Weak<Inner> = Arc::downgrade(&self.inner);
+        tokio::spawn(async move {
+            while let Some(inner) = inner.upgrade() {
+                Readiness { inner }.evaluate().await;
+                tokio::time::sleep(interval).await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use super::*;
+
+    /// A source whose readiness is switched by the flag.
+    fn switch(ready: bool) -> (Arc<AtomicBool>, impl ReadinessSource) {
+        let flag = Arc::new(AtomicBool::new(ready));
+        let source = {
+            let flag = flag.clone();
+            move || {
+                let ready = flag.load(Ordering::Relaxed);
+                async move {
+                    if ready {
+                        Ok(())
+                    } else {
+                        Err("no instances".to_owned())
+                    }
+                }
+            }
+        };
+        (flag, source)
+    }
+
+    #[tokio::test]
+    async fn gate_by_critical_sources() {
+        let readiness = Readiness::new();
+        assert!(readiness.is_ready());
+
+        let (item, source) = switch(false);
+        readiness.register("item", Criticality::Critical, source);
+        let (_, source) = switch(false);
+        readiness.register("cache", Criticality::NonCritical, source);
+        // not ready until the critical source is checked
+        assert!(!readiness.is_ready());
+        assert_eq!(
+            readiness.report().sources[0].reason.as_deref(),
+            Some("not checked yet")
+        );
+
+        let report = readiness.evaluate().await;
+        assert!(!report.ready);
+        assert_eq!(report.not_ready().count(), 2);
+
+        // the non-critical source only shows in the report
+        item.store(true, Ordering::Relaxed);
+        let report = readiness.evaluate().await;
+        assert!(report.ready);
+        assert!(readiness.is_ready());
+        assert_eq!(
+            report.to_json(),
+            r#"{"ready":true,"sources":[{"name":"item","critical":true,"ready":true},{"name":"cache","critical":false,"ready":false,"reason":"no instances"}]}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn timeout() {
+        let readiness = Readiness::with_timeout(Duration::from_millis(50));
+        readiness.register("stuck", Criticality::Critical, || {
+            future::pending::<Result<(), String>>()
+        });
+        let report = readiness.evaluate().await;
+        assert!(!report.ready);
+        assert_eq!(
+            report.sources[0].reason.as_deref(),
+            Some("the check timed out after 50ms")
+        );
+    }
+
+    #[tokio::test]
+    async fn recover_and_lose() {
+        let readiness = Readiness::new();
+        let (item, source) = switch(false);
+        readiness.register("item", Criticality::Critical, source);
+        let task = readiness.spawn(Duration::from_millis(20));
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(!readiness.is_ready());
+        item.store(true, Ordering::Relaxed);
+        tokio::time::timeout(Duration::from_secs(1), readiness.wait_ready())
+            .await
+            .unwrap();
+
+        // losing all the instances flips it off by the next evaluation
+        item.store(false, Ordering::Relaxed);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!readiness.is_ready());
+
+        drop(readiness);
+        tokio::time::timeout(Duration::from_secs(1), task)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
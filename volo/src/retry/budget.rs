@@ -0,0 +1,102 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+
+/// The tokens are scaled to support fractional ratios.
+const SCALE: i64 = 1000;
+
+const DEFAULT_RATIO: f64 = 0.2;
+const DEFAULT_MAX_TOKENS: u32 = 10;
+
+/// A token bucket limiting the ratio of retries to requests.
+///
+/// Each request deposits `ratio` tokens and each retry withdraws one token, and the balance is
+/// capped at `max_tokens`. So under sustained failure, the number of retries will never exceed
+/// `max_tokens + ratio * requests`.
+#[derive(Debug)]
+pub struct RetryBudget {
+    deposit: i64,
+    max_balance: i64,
+    balance: AtomicI64,
+}
+
+impl Default for RetryBudget {
+    fn default() -> Self {
+        Self::new(DEFAULT_RATIO, DEFAULT_MAX_TOKENS)
+    }
+}
+
+impl RetryBudget {
+    /// Creates a new [`RetryBudget`] allowing `ratio` of the requests to be retried, with at most
+    /// `max_tokens` retries in a burst.
+    ///
+    /// Default is `0.2` and `10`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ratio` is negative or not finite.
+    pub fn new(ratio: f64, max_tokens: u32) -> Self {
+        assert!(
+            ratio.is_finite() && ratio >= 0.0,
+            "retry ratio must be a non-negative number"
+        );
+        let max_balance = max_tokens as i64 * SCALE;
+        Self {
+            deposit: (ratio * SCALE as f64) as i64,
+            max_balance,
+            balance: AtomicI64::new(max_balance),
+        }
+    }
+
+    /// Deposits tokens for a request.
+    pub fn deposit(&self) {
+        let _ = self
+            .balance
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |b| {
+                Some((b + self.deposit).min(self.max_balance))
+            });
+    }
+
+    /// Withdraws a token for a retry, returns `false` if the budget is exhausted.
+    pub fn withdraw(&self) -> bool {
+        self.balance
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |b| {
+                (b >= SCALE).then_some(b - SCALE)
+            })
+            .is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ratio() {
+        let budget = RetryBudget::new(0.2, 2);
+        assert!(budget.withdraw());
+        assert!(budget.withdraw());
+        assert!(!budget.withdraw());
+
+        for _ in 0..4 {
+            budget.deposit();
+            assert!(!budget.withdraw());
+        }
+        budget.deposit();
+        assert!(budget.withdraw());
+        assert!(!budget.withdraw());
+
+        // the balance is capped
+        for _ in 0..100 {
+            budget.deposit();
+        }
+        assert!(budget.withdraw());
+        assert!(budget.withdraw());
+        assert!(!budget.withdraw());
+    }
+
+    #[test]
+    fn zero() {
+        let budget = RetryBudget::new(0.0, 0);
+        budget.deposit();
+        assert!(!budget.withdraw());
+    }
+}
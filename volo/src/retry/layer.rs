@@ -0,0 +1,281 @@
+use std::sync::Arc;
+
+use motore::{layer::Layer, service::Service};
+
+use super::{Classification, RetryBudget, RetryPolicy, RetryStrategy, RETRY_ATTEMPT};
+use crate::context::{typed::TypedExt, Context, DeadlineExt};
+
+/// A layer that retries the calls classified as retryable by the strategy.
+///
+/// This layer should be put in the outer layers of the client, so that each attempt is governed
+/// by the timeout and may be sent to a different instance picked by the load balancer.
+///
+/// The [`RetryBudget`] is shared by all the clones of the service created by this layer.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let client = volo_gen::volo::example::ItemServiceClientBuilder::new("item")
+///     .layer_outer(
+///         volo::retry::RetryLayer::new(volo_thrift::client::layer::retry::ThriftRetryStrategy)
+///             .policy(volo::retry::RetryPolicy::new(2))
+///             .budget(volo::retry::RetryBudget::new(0.2, 10)),
+///     )
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Clone)]
+pub struct RetryLayer<St> {
+    strategy: St,
+    policy: RetryPolicy,
+    budget: Arc<RetryBudget>,
+}
+
+impl<St> RetryLayer<St> {
+    /// Creates a new [`RetryLayer`] with the given strategy, the default policy and budget.
+    pub fn new(strategy: St) -> Self {
+        Self {
+            strategy,
+            policy: RetryPolicy::default(),
+            budget: Arc::new(RetryBudget::default()),
+        }
+    }
+
+    /// Sets the default policy, which can be overridden per method by the strategy.
+    pub fn policy(mut self, policy: RetryPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Sets the budget of the client.
+    pub fn budget(mut self, budget: RetryBudget) -> Self {
+        self.budget = Arc::new(budget);
+        self
+    }
+}
+
+impl<S, St> Layer<S> for RetryLayer<St> {
+    type Service = RetryService<S, St>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        RetryService {
+            inner,
+            strategy: Arc::new(self.strategy),
+            policy: self.policy,
+            budget: self.budget,
+        }
+    }
+}
+
+/// The service created by [`RetryLayer`].
+pub struct RetryService<S, St> {
+    inner: S,
+    strategy: Arc<St>,
+    policy: RetryPolicy,
+    budget: Arc<RetryBudget>,
+}
+
+impl<S: Clone, St> Clone for RetryService<S, St> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            strategy: self.strategy.clone(),
+            policy: self.policy,
+            budget: self.budget.clone(),
+        }
+    }
+}
+
+impl<Cx, Req, S, St> Service<Cx, Req> for RetryService<S, St>
+where
+    Cx: Context + Send + 'static,
+    Req: Send + 'static,
+    S: Service<Cx, Req> + Send + Sync + 'static,
+    S::Response: Send,
+    S::Error: Send,
+    St: RetryStrategy<Cx, Req, S::Response, S::Error> + Send + Sync + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call<'s, 'cx>(
+        &'s self,
+        cx: &'cx mut Cx,
+        req: Req,
+    ) -> Result<Self::Response, Self::Error> {
+        self.budget.deposit();
+        let policy = self.strategy.policy(cx).unwrap_or(self.policy);
+        // The load balancer will skip picking if the address is set, so the address picked by
+        // the last attempt should be reset to make the retry go to another instance.
+        let address = cx.rpc_info().callee().address.clone();
+
+        let mut req = req;
+        let mut retries = 0;
+        loop {
+            let next = if retries < policy.max_retries() {
+                self.strategy.clone_request(&req)
+            } else {
+                None
+            };
+            cx.set_typed::<RETRY_ATTEMPT>(retries as u32);
+            let result = self.inner.call(cx, req).await;
+            let delay = match self.strategy.classify(cx, &result) {
+                Classification::Done => return result,
+                Classification::Retry => policy.backoff_of(retries),
+                Classification::RetryAfter(delay) => delay,
+                Classification::RetryAfterHint(delay) => delay.min(policy.max_backoff()),
+            };
+            let Some(next) = next else {
+                return result;
+            };
+            // the retry would be sent after the deadline
+            if cx
+                .deadline()
+                .is_some_and(|deadline| deadline.remaining() <= delay)
+            {
+                return result;
+            }
+            if !self.budget.withdraw() {
+                return result;
+            }
+            tracing::debug!(
+                "[VOLO] retrying call, retries: {}, delay: {:?}, rpcinfo: {:?}",
+                retries + 1,
+                delay,
+                cx.rpc_info()
+            );
+            retries += 1;
+            if !delay.is_zero() {
+                tokio::time::sleep(delay).await;
+            }
+            cx.rpc_info_mut().callee_mut().address = address.clone();
+            self.strategy.prepare(cx);
+            req = next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::{Duration, Instant},
+    };
+
+    use motore::{layer::Layer, service::Service};
+
+    use super::*;
+    use crate::context::{Endpoint, Reusable, Role, RpcCx, RpcInfo};
+
+    #[derive(Debug, Default)]
+    struct Config;
+
+    impl Reusable for Config {
+        fn clear(&mut self) {}
+    }
+
+    type Cx = RpcCx<(), Config>;
+
+    fn cx() -> Cx {
+        RpcCx::new(
+            RpcInfo::new(
+                Role::Client,
+                "test".into(),
+                Endpoint::new("caller".into()),
+                Endpoint::new("callee".into()),
+                Config,
+            ),
+            (),
+        )
+    }
+
+    /// Retries all the errors after the delay.
+    struct Strategy(Duration);
+
+    impl RetryStrategy<Cx, (), (), ()> for Strategy {
+        fn policy(&self, _cx: &Cx) -> Option<RetryPolicy> {
+            None
+        }
+
+        fn clone_request(&self, _req: &()) -> Option<()> {
+            Some(())
+        }
+
+        fn classify(&self, _cx: &mut Cx, result: &Result<(), ()>) -> Classification {
+            match result {
+                Ok(_) => Classification::Done,
+                Err(_) => Classification::RetryAfter(self.0),
+            }
+        }
+    }
+
+    struct AlwaysFail {
+        calls: AtomicUsize,
+        attempt: AtomicUsize,
+    }
+
+    impl Service<Cx, ()> for AlwaysFail {
+        type Response = ();
+        type Error = ();
+
+        async fn call<'s, 'cx>(&'s self, cx: &'cx mut Cx, _req: ()) -> Result<(), ()> {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            let attempt = *cx.get_typed::<RETRY_ATTEMPT>().unwrap();
+            self.attempt.store(attempt as usize, Ordering::Relaxed);
+            Err(())
+        }
+    }
+
+    #[test]
+    fn budget_caps_retries_under_sustained_failure() {
+        let svc = RetryLayer::new(Strategy(Duration::ZERO))
+            .policy(RetryPolicy::new(3))
+            .budget(RetryBudget::new(0.2, 10))
+            .layer(AlwaysFail {
+                calls: AtomicUsize::new(0),
+                attempt: AtomicUsize::new(0),
+            });
+
+        let requests = 1000;
+        for _ in 0..requests {
+            let result = futures::executor::block_on(svc.call(&mut cx(), ()));
+            assert!(result.is_err());
+        }
+
+        let retries = svc.inner.calls.load(Ordering::Relaxed) - requests;
+        // without the budget, there would be 3000 retries
+        assert!(retries <= 10 + requests / 5, "retries: {retries}");
+        assert!(retries >= requests / 5, "retries: {retries}");
+    }
+
+    #[test]
+    fn max_retries() {
+        let svc = RetryLayer::new(Strategy(Duration::ZERO))
+            .policy(RetryPolicy::new(3))
+            .layer(AlwaysFail {
+                calls: AtomicUsize::new(0),
+                attempt: AtomicUsize::new(0),
+            });
+
+        let _ = futures::executor::block_on(svc.call(&mut cx(), ()));
+        assert_eq!(svc.inner.calls.load(Ordering::Relaxed), 4);
+        assert_eq!(svc.inner.attempt.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn deadline() {
+        let svc = RetryLayer::new(Strategy(Duration::from_secs(1)))
+            .policy(RetryPolicy::new(3))
+            .layer(AlwaysFail {
+                calls: AtomicUsize::new(0),
+                attempt: AtomicUsize::new(0),
+            });
+
+        let deadline = Instant::now() + Duration::from_millis(100);
+        let _ = futures::executor::block_on(crate::context::with_deadline(
+            deadline,
+            svc.call(&mut cx(), ()),
+        ));
+        assert_eq!(svc.inner.calls.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,213 @@
+//! Retrying governed by error classification and budgets.
+//!
+//! The [`RetryLayer`] only retries a call when the [`RetryStrategy`] classifies its result as
+//! retryable, and when the per-client [`RetryBudget`] still has tokens, which prevents retry storms
+//! when the downstream is overloaded. The server can also push back by hints in the response,
+//! which will either delay the retry or abort it, see [`Pushback`].
+//!
+//! The strategies of thrift and gRPC are provided in `volo-thrift` and `volo-grpc`.
+//!
+//! A retry is never started after the deadline of the call, see [`crate::context::Deadline`].
+//!
+//! The [`RetryPolicy`] can be overridden per method by the `Config` in the context, which can be
+//! set by the `CallOpt`.
+//!
+//! The number of the current attempt is set as [`RETRY_ATTEMPT`] in the context before each
+//! attempt, so the inner layers can tell the retries from the first attempt.
+
+mod budget;
+mod layer;
+
+use std::time::Duration;
+
+pub use self::{
+    budget::RetryBudget,
+    layer::{RetryLayer, RetryService},
+};
+use crate::util::backoff::Backoff;
+
+crate::metainfo_key!(
+    /// The number of the retries before the current attempt, which is `0` for the first attempt.
+    pub RETRY_ATTEMPT: u32 = "retry_attempt"
+);
+
+const DEFAULT_MAX_RETRIES: usize = 2;
+const DEFAULT_BACKOFF: Duration = Duration::from_millis(10);
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The policy of how many times and how long to wait before retrying.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_retries: usize,
+    backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: DEFAULT_MAX_RETRIES,
+            backoff: DEFAULT_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Creates a new [`RetryPolicy`] with at most `max_retries` retries, which means a call will
+    /// be sent at most `max_retries + 1` times.
+    pub fn new(max_retries: usize) -> Self {
+        Self {
+            max_retries,
+            ..Default::default()
+        }
+    }
+
+    /// Creates a [`RetryPolicy`] that never retries.
+    pub fn disabled() -> Self {
+        Self::new(0)
+    }
+
+    /// Sets the exponential backoff between retries, starting from `backoff` and doubling each
+    /// time until `max_backoff`.
+    ///
+    /// Default is from `10ms` to `1s`.
+    pub fn backoff(mut self, backoff: Duration, max_backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Returns the maximum number of retries.
+    pub fn max_retries(&self) -> usize {
+        self.max_retries
+    }
+
+    /// Returns the maximum backoff between retries.
+    pub fn max_backoff(&self) -> Duration {
+        self.max_backoff
+    }
+
+    /// Returns the backoff before the `retry`-th retry, starting from 0.
+    pub fn backoff_of(&self, retry: usize) -> Duration {
+        Backoff::new(self.backoff, self.max_backoff).delay_of(retry)
+    }
+}
+
+/// The hint from the server about retrying, e.g. `grpc-retry-pushback-ms` of gRPC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pushback {
+    /// Retry after the given delay instead of the backoff of the policy.
+    Delay(Duration),
+    /// Do not retry.
+    Abort,
+}
+
+impl Pushback {
+    /// Parses the pushback from milliseconds, a negative or invalid value means
+    /// [`Pushback::Abort`].
+    pub fn parse(ms: &str) -> Self {
+        match ms.trim().parse::<u64>() {
+            Ok(ms) => Self::Delay(Duration::from_millis(ms)),
+            Err(_) => Self::Abort,
+        }
+    }
+
+    /// Returns the value in milliseconds to be sent back, where `-1` means [`Pushback::Abort`].
+    pub fn as_millis(&self) -> i64 {
+        match self {
+            Self::Delay(d) => d.as_millis().min(i64::MAX as u128) as i64,
+            Self::Abort => -1,
+        }
+    }
+}
+
+/// The classification of the result of a call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Classification {
+    /// The call succeeded, or failed with an error that should not be retried.
+    Done,
+    /// The call failed and can be retried after the backoff of the policy.
+    Retry,
+    /// The call failed and can be retried after the delay asked by the server.
+    RetryAfter(Duration),
+    /// The call failed and can be retried after the delay suggested by the server, which is
+    /// bounded by the maximum backoff of the policy.
+    RetryAfterHint(Duration),
+}
+
+impl Classification {
+    /// Classifies a failed call by whether the error is retryable and the pushback from the server.
+    ///
+    /// The pushback never makes a non-retryable error retryable.
+    pub fn new(retryable: bool, pushback: Option<Pushback>) -> Self {
+        match (retryable, pushback) {
+            (false, _) | (true, Some(Pushback::Abort)) => Self::Done,
+            (true, None) => Self::Retry,
+            (true, Some(Pushback::Delay(delay))) => Self::RetryAfter(delay),
+        }
+    }
+}
+
+/// The protocol specific part of retrying, which classifies the results, reads the per-method
+/// policy and duplicates the requests.
+pub trait RetryStrategy<Cx, Req, Resp, E> {
+    /// Returns the policy overriding the one of the [`RetryLayer`] for this call.
+    fn policy(&self, cx: &Cx) -> Option<RetryPolicy>;
+
+    /// Returns a copy of the request to be sent by the next attempt, or `None` if the request can
+    /// not be replayed, e.g. a streaming request.
+    ///
+    /// This is called before each attempt that may be retried.
+    fn clone_request(&self, req: &Req) -> Option<Req>;
+
+    /// Classifies the result of an attempt.
+    ///
+    /// The strategy may record why it made the decision in the context, e.g. for logging.
+    fn classify(&self, cx: &mut Cx, result: &Result<Resp, E>) -> Classification;
+
+    /// Resets the states of the context left by the last attempt before retrying.
+    fn prepare(&self, _cx: &mut Cx) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn backoff() {
+        let policy =
+            RetryPolicy::new(10).backoff(Duration::from_millis(10), Duration::from_millis(50));
+        assert_eq!(policy.backoff_of(0), Duration::from_millis(10));
+        assert_eq!(policy.backoff_of(1), Duration::from_millis(20));
+        assert_eq!(policy.backoff_of(2), Duration::from_millis(40));
+        assert_eq!(policy.backoff_of(3), Duration::from_millis(50));
+        assert_eq!(policy.backoff_of(100), Duration::from_millis(50));
+    }
+
+    #[test]
+    fn pushback() {
+        assert_eq!(
+            Pushback::parse("100"),
+            Pushback::Delay(Duration::from_millis(100))
+        );
+        assert_eq!(Pushback::parse("-1"), Pushback::Abort);
+        assert_eq!(Pushback::parse("abc"), Pushback::Abort);
+
+        assert_eq!(Classification::new(true, None), Classification::Retry);
+        assert_eq!(
+            Classification::new(true, Some(Pushback::parse("0"))),
+            Classification::RetryAfter(Duration::ZERO)
+        );
+        assert_eq!(
+            Classification::new(true, Some(Pushback::Abort)),
+            Classification::Done
+        );
+        assert_eq!(
+            Classification::new(false, Some(Pushback::parse("10"))),
+            Classification::Done
+        );
+    }
+}
@@ -0,0 +1,247 @@
+use std::{fmt::Debug, sync::Arc};
+
+use motore::{layer::Layer, service::Service};
+
+use metainfo::METAINFO;
+
+use super::{RouteDecision, Router, Shadow, ShadowContext, SHADOW};
+use crate::context::{
+    typed::{TypedExt, TypedKey},
+    Context,
+};
+
+/// A layer that routes the calls of a client by the [`Router`].
+///
+/// It should be put in the outer layers of the client, so the decision is made before the
+/// instance is picked by the load balancer.
+#[derive(Debug, Clone)]
+pub struct RouteLayer<R> {
+    router: Arc<R>,
+}
+
+impl<R> RouteLayer<R> {
+    pub fn new(router: R) -> Self {
+        Self {
+            router: Arc::new(router),
+        }
+    }
+}
+
+impl<S, R> Layer<S> for RouteLayer<R> {
+    type Service = RouteService<S, R>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        RouteService {
+            inner: Arc::new(inner),
+            router: self.router,
+        }
+    }
+}
+
+/// The service created by [`RouteLayer`].
+#[derive(Debug)]
+pub struct RouteService<S, R> {
+    inner: Arc<S>,
+    router: Arc<R>,
+}
+
+impl<S, R> Clone for RouteService<S, R> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            router: self.router.clone(),
+        }
+    }
+}
+
+impl<Cx, Req, S, R> Service<Cx, Req> for RouteService<S, R>
+where
+    Cx: Context + ShadowContext + Send + 'static,
+    Req: Send + 'static,
+    S: Service<Cx, Req> + Send + Sync + 'static,
+    S::Response: Send,
+    S::Error: Debug + Send,
+    R: Router<Cx, Req>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call<'s, 'cx>(
+        &'s self,
+        cx: &'cx mut Cx,
+        req: Req,
+    ) -> Result<Self::Response, Self::Error> {
+        let RouteDecision {
+            cluster,
+            address,
+            shadow,
+        } = self.router.route(cx, &req);
+
+        if let Some(shadow) = shadow {
+            match self.router.copy_request(&req) {
+                Some(copy) => self.send_shadow(cx, copy, shadow),
+                None => tracing::debug!(
+                    "[VOLO] shadow call skipped since the request can't be copied, rpcinfo: {:?}",
+                    cx.rpc_info()
+                ),
+            }
+        }
+
+        let callee = cx.rpc_info_mut().callee_mut();
+        if let Some(cluster) = cluster {
+            callee.set_service_name(cluster);
+        }
+        if let Some(address) = address {
+            callee.set_address(address);
+        }
+        self.inner.call(cx, req).await
+    }
+}
+
+impl<S, R> RouteService<S, R> {
+    /// Sends the shadow copy in the background, and discards its result.
+    fn send_shadow<Cx, Req>(&self, cx: &Cx, req: Req, shadow: Shadow)
+    where
+        Cx: Context + ShadowContext + Send + 'static,
+        Req: Send + 'static,
+        S: Service<Cx, Req> + Send + Sync + 'static,
+        S::Response: Send,
+        S::Error: Debug + Send,
+    {
+        let mut shadow_cx = cx.shadow();
+        shadow_cx.set_typed::<SHADOW>(true);
+        let callee = shadow_cx.rpc_info_mut().callee_mut();
+        if let Some(cluster) = shadow.cluster {
+            callee.set_service_name(cluster);
+            // the instance of the shadow cluster is picked by the load balancer
+            callee.address = None;
+        }
+        if let Some(address) = shadow.address {
+            callee.set_address(address);
+        }
+
+        let inner = self.inner.clone();
+        crate::spawn(async move {
+            // the dye is only written as a string for the next hop
+            let _ = METAINFO.try_with(|mi| SHADOW::to_transient(&true, &mut mi.borrow_mut()));
+            let result =
+                tokio::time::timeout(shadow.timeout, inner.call(&mut shadow_cx, req)).await;
+            match result {
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => tracing::debug!(
+                    "[VOLO] shadow call error: {:?}, rpcinfo: {:?}",
+                    e,
+                    shadow_cx.rpc_info()
+                ),
+                Err(_) => tracing::debug!(
+                    "[VOLO] shadow call timeout, rpcinfo: {:?}",
+                    shadow_cx.rpc_info()
+                ),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        },
+        time::Duration,
+    };
+
+    use faststr::FastStr;
+
+    use super::*;
+    use crate::context::{Endpoint, Reusable, Role, RpcCx, RpcInfo};
+
+    #[derive(Debug, Default, Clone)]
+    struct Config;
+
+    impl Reusable for Config {
+        fn clear(&mut self) {}
+    }
+
+    type Cx = RpcCx<(), Config>;
+
+    fn cx() -> Cx {
+        RpcCx::new(
+            RpcInfo::new(
+                Role::Client,
+                "get".into(),
+                Endpoint::new("caller".into()),
+                Endpoint::new("item".into()),
+                Config,
+            ),
+            (),
+        )
+    }
+
+    /// Records the clusters the calls reach, and the shadow cluster always fails or hangs.
+    #[derive(Default)]
+    struct Backend {
+        clusters: Mutex<Vec<FastStr>>,
+        shadow_calls: AtomicUsize,
+        dyed_calls: AtomicUsize,
+    }
+
+    impl Service<Cx, u32> for Backend {
+        type Response = u32;
+        type Error = &'static str;
+
+        async fn call<'s, 'cx>(&'s self, cx: &'cx mut Cx, req: u32) -> Result<u32, &'static str> {
+            let cluster = cx.rpc_info().callee().service_name();
+            let sent = METAINFO
+                .try_with(|mi| SHADOW::from_transient(&mi.borrow()))
+                .ok()
+                .flatten();
+            if cx.get_typed::<SHADOW>() == Some(&true) && sent == Some(true) {
+                self.dyed_calls.fetch_add(1, Ordering::Relaxed);
+            }
+            if cluster.as_str() == "item-shadow" {
+                self.shadow_calls.fetch_add(1, Ordering::Relaxed);
+                if req % 2 == 0 {
+                    return Err("shadow is broken");
+                }
+                tokio::time::sleep(Duration::from_secs(3600)).await;
+            }
+            self.clusters.lock().unwrap().push(cluster);
+            Ok(req)
+        }
+    }
+
+    #[tokio::test]
+    async fn override_and_shadow() {
+        let svc = RouteLayer::new(move |_cx: &Cx, req: &u32| {
+            let decision = RouteDecision::primary()
+                .shadow(Shadow::cluster("item-shadow").timeout(Duration::from_millis(10)));
+            if req % 2 == 1 {
+                decision.cluster("item-odd")
+            } else {
+                decision
+            }
+        })
+        .layer(Backend::default());
+
+        let n = 10_000;
+        for i in 0..n {
+            // the shadow failures and timeouts never surface
+            assert_eq!(svc.call(&mut cx(), i).await, Ok(i));
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let backend = &svc.inner;
+        assert_eq!(backend.shadow_calls.load(Ordering::Relaxed), n as usize);
+        // only the shadow calls are dyed
+        assert_eq!(backend.dyed_calls.load(Ordering::Relaxed), n as usize);
+        let clusters = backend.clusters.lock().unwrap();
+        assert_eq!(clusters.len(), n as usize);
+        let odd = clusters.iter().filter(|c| c.as_str() == "item-odd").count();
+        assert_eq!(odd, n as usize / 2);
+        assert!(clusters
+            .iter()
+            .all(|c| c.as_str() == "item-odd" || c.as_str() == "item"));
+    }
+}
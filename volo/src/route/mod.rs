@@ -0,0 +1,236 @@
+//! Dynamic routing of the calls of a client, e.g. overriding the cluster and traffic shadowing.
+//!
+//! A [`Router`] inspects the context and the request of each call, and returns a
+//! [`RouteDecision`], which may:
+//! - override the cluster, i.e. the service name of the callee, which is the key of the service
+//!   discovery, so the call is sent to the instances of another cluster;
+//! - override the address of the callee, so the call is sent to the address directly;
+//! - send a shadow copy of the call to another target, whose response is discarded.
+//!
+//! The [`RouteLayer`] should be an outer layer of the client, so the decision is made before the
+//! instance is picked by the load balancer:
+//!
+//! ```rust,ignore
+//! use volo::route::{Percentage, RouteDecision, RouteLayer, Shadow};
+//!
+//! let shadow = Percentage::new(1.0);
+//! let client = volo_gen::volo::example::ItemServiceClientBuilder::new("item")
+//!     .layer_outer(RouteLayer::new(move |_cx: &ClientContext, req: &GetItemRequest| {
+//!         let mut decision = RouteDecision::primary();
+//!         if req.id < 0 {
+//!             decision = decision.cluster("item-legacy");
+//!         }
+//!         if shadow.hit() {
+//!             decision = decision.shadow(Shadow::cluster("item-staging"));
+//!         }
+//!         decision
+//!     }))
+//!     .build()?;
+//! ```
+//!
+//! The shadow copies are sent in the background with their own timeout, so they never affect the
+//! latency or the result of the primary calls. They are dyed by [`SHADOW`], so the inner layers
+//! and the downstream can tell them from the primary calls, e.g. to skip the side effects.
+//!
+//! A percentage of the calls is split to the canary instances of the same cluster by the
+//! [`Canary`][crate::loadbalance::policy::Canary] stage of the policy pipeline instead.
+
+mod layer;
+
+use std::time::Duration;
+
+use faststr::FastStr;
+use rand::Rng;
+
+pub use self::layer::{RouteLayer, RouteService};
+use crate::{
+    context::{Endpoint, Reusable, RpcCx, RpcInfo},
+    net::Address,
+};
+
+crate::metainfo_key!(
+    /// Whether the call is a shadow copy, which is set in the context of the shadow calls and sent
+    /// to the next hop in the transient metainfo, where it's read by
+    /// [`TypedKey::from_upstream`][crate::context::typed::TypedKey::from_upstream].
+    pub SHADOW: bool = "volo_shadow"
+);
+
+/// The default timeout of the shadow calls.
+pub const DEFAULT_SHADOW_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Where a call is sent, which is returned by the [`Router`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RouteDecision {
+    cluster: Option<FastStr>,
+    address: Option<Address>,
+    shadow: Option<Shadow>,
+}
+
+impl RouteDecision {
+    /// Sends the call as is, without any override or shadow copy.
+    pub fn primary() -> Self {
+        Self::default()
+    }
+
+    /// Sends the call to the cluster, by overriding the service name of the callee.
+    pub fn cluster(mut self, cluster: impl Into<FastStr>) -> Self {
+        self.cluster = Some(cluster.into());
+        self
+    }
+
+    /// Sends the call to the address directly, bypassing the load balancer.
+    pub fn address(mut self, address: Address) -> Self {
+        self.address = Some(address);
+        self
+    }
+
+    /// Sends a shadow copy of the call.
+    pub fn shadow(mut self, shadow: Shadow) -> Self {
+        self.shadow = Some(shadow);
+        self
+    }
+}
+
+/// Where a shadow copy of a call is sent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shadow {
+    cluster: Option<FastStr>,
+    address: Option<Address>,
+    timeout: Duration,
+}
+
+impl Shadow {
+    /// Sends the shadow copy to the cluster, whose instances are picked by the load balancer.
+    pub fn cluster(cluster: impl Into<FastStr>) -> Self {
+        Self {
+            cluster: Some(cluster.into()),
+            address: None,
+            timeout: DEFAULT_SHADOW_TIMEOUT,
+        }
+    }
+
+    /// Sends the shadow copy to the address directly.
+    pub fn address(address: Address) -> Self {
+        Self {
+            cluster: None,
+            address: Some(address),
+            timeout: DEFAULT_SHADOW_TIMEOUT,
+        }
+    }
+
+    /// Sets the timeout of the shadow call, after which it is abandoned.
+    ///
+    /// Default is [`DEFAULT_SHADOW_TIMEOUT`].
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+/// Decides where a call is sent.
+///
+/// It is implemented for the closures `Fn(&Cx, &Req) -> RouteDecision` when the request is
+/// `Clone`, and the routers of the requests which can't be cloned, e.g. the streaming requests of
+/// gRPC, should implement [`Router::copy_request`] to send the shadow copies.
+pub trait Router<Cx, Req>: Send + Sync + 'static {
+    fn route(&self, cx: &Cx, req: &Req) -> RouteDecision;
+
+    /// Copies the request for the shadow call, and the shadow call is skipped if `None` is
+    /// returned.
+    ///
+    /// Default is `None`.
+    fn copy_request(&self, _req: &Req) -> Option<Req> {
+        None
+    }
+}
+
+impl<Cx, Req, F> Router<Cx, Req> for F
+where
+    Req: Clone,
+    F: Fn(&Cx, &Req) -> RouteDecision + Send + Sync + 'static,
+{
+    fn route(&self, cx: &Cx, req: &Req) -> RouteDecision {
+        self(cx, req)
+    }
+
+    fn copy_request(&self, req: &Req) -> Option<Req> {
+        Some(req.clone())
+    }
+}
+
+/// A context which can be copied for a shadow call.
+///
+/// The copy has the same role, method, config and endpoints, except the tags of the endpoints
+/// and the extensions, which are left empty. It is implemented for the client contexts of
+/// `volo-thrift` and `volo-grpc`.
+pub trait ShadowContext: Sized {
+    fn shadow(&self) -> Self;
+}
+
+impl<I, Config> ShadowContext for RpcCx<I, Config>
+where
+    I: Clone,
+    Config: Reusable + Default + Clone,
+{
+    fn shadow(&self) -> Self {
+        RpcCx::new(shadow_rpc_info(&self.rpc_info), self.inner.clone())
+    }
+}
+
+/// Copies the [`RpcInfo`] for a shadow call, see [`ShadowContext`].
+pub fn shadow_rpc_info<Config>(rpc_info: &RpcInfo<Config>) -> RpcInfo<Config>
+where
+    Config: Reusable + Default + Clone,
+{
+    let copy = |endpoint: &Endpoint| {
+        let mut copy = Endpoint::new(endpoint.service_name());
+        copy.address = endpoint.address();
+        copy
+    };
+    RpcInfo::new(
+        rpc_info.role(),
+        rpc_info.method().clone(),
+        copy(rpc_info.caller()),
+        copy(rpc_info.callee()),
+        rpc_info.config().clone(),
+    )
+}
+
+/// Samples a percentage of the calls, e.g. for shadowing a part of the traffic.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Percentage {
+    ratio: f64,
+}
+
+impl Percentage {
+    /// Creates a [`Percentage`] hitting `percent` of the calls, which is clamped to `[0, 100]`.
+    pub fn new(percent: f64) -> Self {
+        Self {
+            ratio: (percent / 100.0).clamp(0.0, 1.0),
+        }
+    }
+
+    /// Returns if the call is sampled.
+    pub fn hit(&self) -> bool {
+        rand::thread_rng().gen_bool(self.ratio)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentage() {
+        let n = 100_000;
+        let hits = |percent| {
+            let p = Percentage::new(percent);
+            (0..n).filter(|_| p.hit()).count()
+        };
+        assert_eq!(hits(0.0), 0);
+        assert_eq!(hits(100.0), n);
+        assert_eq!(hits(150.0), n);
+        let hits = hits(10.0) as f64 / n as f64;
+        assert!((0.09..0.11).contains(&hits), "{hits}");
+    }
+}
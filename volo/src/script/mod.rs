@@ -0,0 +1,445 @@
+//! Hooks for mutating or rejecting the requests on the server side by small rules, e.g. adding a
+//! header, rewriting the path or rejecting the request with a message, which can be changed
+//! without redeploying the server.
+//!
+//! A [`ScriptHook`] sees a bounded [`RequestView`] of the request, which contains the method, the
+//! path and the selected headers (or metadata), but not the body, and returns a [`Verdict`]. The
+//! servers apply the verdict with their own layers, e.g. `volo_http::server::layer::ScriptLayer`
+//! and `volo_grpc::layer::script::ScriptLayer`.
+//!
+//! The hooks are isolated from the request path: if a hook fails or panics, the error is logged
+//! and the hook is skipped, see [`run_hook`].
+//!
+//! The hooks can be written in native Rust with [`FnHook`], or as WASM modules loaded from a
+//! directory with `wasm::WasmHooks`, which is only available with the `wasm-script` feature.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use volo::script::{FnHook, Mutation, ScriptHooks, Verdict};
+//!
+//! let hooks = ScriptHooks::new()
+//!     .hook(FnHook::new("tenant", |req| {
+//!         if req.header("x-tenant").is_none() {
+//!             return Ok(Verdict::reject(403, "missing tenant"));
+//!         }
+//!         Ok(Verdict::pass())
+//!     }))
+//!     .hook(WasmHooks::load("/etc/gateway/scripts", WasmConfig::default())?);
+//!
+//! let app = Router::new()
+//!     .route("/", get(index))
+//!     .layer(ScriptLayer::new(hooks));
+//! ```
+
+#[cfg(feature = "wasm-script")]
+#[cfg_attr(docsrs, doc(cfg(feature = "wasm-script")))]
+pub mod wasm;
+
+use std::{
+    error::Error,
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::Arc,
+};
+
+#[cfg(feature = "wasm-script")]
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_MAX_HEADERS: usize = 32;
+const DEFAULT_MAX_HEADER_SIZE: usize = 1024;
+
+/// The view of a request seen by the [`ScriptHook`]s.
+///
+/// The header names are in lowercase.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "wasm-script", derive(Serialize, Deserialize))]
+pub struct RequestView {
+    pub method: String,
+    /// The path and query of the request, or the full method name of a gRPC request.
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl RequestView {
+    /// Returns the first value of the header.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Applies the mutation to the view, so the following hooks can see it.
+    pub fn apply(&mut self, mutation: &Mutation) {
+        match mutation {
+            Mutation::SetHeader { name, value } => {
+                self.headers.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+                self.headers
+                    .push((name.to_ascii_lowercase(), value.clone()));
+            }
+            Mutation::RemoveHeader { name } => {
+                self.headers.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+            }
+            Mutation::SetPath { path } => self.path.clone_from(path),
+        }
+    }
+}
+
+/// The limits of the headers copied into the [`RequestView`].
+///
+/// The headers are skipped if they are not valid UTF-8, larger than the limit, or not in the
+/// allowed list when it is set.
+#[derive(Debug, Clone)]
+pub struct ViewLimits {
+    max_headers: usize,
+    max_header_size: usize,
+    allowed_headers: Option<Arc<[String]>>,
+}
+
+impl Default for ViewLimits {
+    fn default() -> Self {
+        Self {
+            max_headers: DEFAULT_MAX_HEADERS,
+            max_header_size: DEFAULT_MAX_HEADER_SIZE,
+            allowed_headers: None,
+        }
+    }
+}
+
+impl ViewLimits {
+    /// Sets the maximum number of headers in the view.
+    ///
+    /// Default is `32`.
+    pub fn max_headers(mut self, max_headers: usize) -> Self {
+        self.max_headers = max_headers;
+        self
+    }
+
+    /// Sets the maximum size of a header, including the name and the value.
+    ///
+    /// Default is `1024`.
+    pub fn max_header_size(mut self, max_header_size: usize) -> Self {
+        self.max_header_size = max_header_size;
+        self
+    }
+
+    /// Only copies the headers with the names into the view.
+    ///
+    /// Default is all the headers.
+    pub fn allowed_headers<I, N>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = N>,
+        N: AsRef<str>,
+    {
+        self.allowed_headers = Some(
+            names
+                .into_iter()
+                .map(|n| n.as_ref().to_ascii_lowercase())
+                .collect(),
+        );
+        self
+    }
+
+    /// Builds the view of a request.
+    pub fn view<'a, H>(&self, method: &str, path: &str, headers: H) -> RequestView
+    where
+        H: IntoIterator<Item = (&'a str, &'a [u8])>,
+    {
+        let headers = headers
+            .into_iter()
+            .filter(|(name, value)| name.len() + value.len() <= self.max_header_size)
+            .filter(|(name, _)| {
+                self.allowed_headers.as_ref().map_or(true, |allowed| {
+                    allowed.iter().any(|a| a.eq_ignore_ascii_case(name))
+                })
+            })
+            .filter_map(|(name, value)| {
+                let value = std::str::from_utf8(value).ok()?;
+                Some((name.to_ascii_lowercase(), value.to_owned()))
+            })
+            .take(self.max_headers)
+            .collect();
+        RequestView {
+            method: method.to_owned(),
+            path: path.to_owned(),
+            headers,
+        }
+    }
+}
+
+/// A mutation of the request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "wasm-script",
+    derive(Serialize, Deserialize),
+    serde(tag = "op", rename_all = "snake_case")
+)]
+pub enum Mutation {
+    /// Sets the header, replacing all the values of it.
+    SetHeader {
+        name: String,
+        value: String,
+    },
+    RemoveHeader {
+        name: String,
+    },
+    /// Rewrites the path and query of the request, which is not supported by gRPC.
+    SetPath {
+        path: String,
+    },
+}
+
+/// The result of a [`ScriptHook`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "wasm-script",
+    derive(Serialize, Deserialize),
+    serde(tag = "action", rename_all = "snake_case")
+)]
+pub enum Verdict {
+    /// Continues the request with the mutations applied in order.
+    Continue {
+        #[cfg_attr(feature = "wasm-script", serde(default))]
+        mutations: Vec<Mutation>,
+    },
+    /// Rejects the request with the HTTP status code and the message.
+    ///
+    /// The gRPC servers map the status code to the closest gRPC code.
+    Reject { status: u16, message: String },
+}
+
+impl Verdict {
+    /// Continues the request without any mutation.
+    pub fn pass() -> Self {
+        Self::Continue {
+            mutations: Vec::new(),
+        }
+    }
+
+    pub fn mutate(mutations: Vec<Mutation>) -> Self {
+        Self::Continue { mutations }
+    }
+
+    pub fn reject(status: u16, message: impl Into<String>) -> Self {
+        Self::Reject {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ScriptError {
+    #[error("out of fuel")]
+    OutOfFuel,
+    #[error("runtime error: {0}")]
+    Runtime(String),
+    #[error("invalid output: {0}")]
+    InvalidOutput(String),
+    #[error(transparent)]
+    Other(Box<dyn Error + Send + Sync>),
+}
+
+/// A hook invoked with the view of each request.
+pub trait ScriptHook: Send + Sync {
+    /// The name of the hook for logging.
+    fn name(&self) -> &str;
+
+    fn on_request(&self, req: &RequestView) -> Result<Verdict, ScriptError>;
+}
+
+impl<H: ScriptHook + ?Sized> ScriptHook for Arc<H> {
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    fn on_request(&self, req: &RequestView) -> Result<Verdict, ScriptError> {
+        (**self).on_request(req)
+    }
+}
+
+/// A native hook implemented by a closure.
+#[derive(Clone)]
+pub struct FnHook<F> {
+    name: String,
+    f: F,
+}
+
+impl<F> FnHook<F>
+where
+    F: Fn(&RequestView) -> Result<Verdict, ScriptError> + Send + Sync,
+{
+    pub fn new(name: impl Into<String>, f: F) -> Self {
+        Self {
+            name: name.into(),
+            f,
+        }
+    }
+}
+
+impl<F> ScriptHook for FnHook<F>
+where
+    F: Fn(&RequestView) -> Result<Verdict, ScriptError> + Send + Sync,
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn on_request(&self, req: &RequestView) -> Result<Verdict, ScriptError> {
+        (self.f)(req)
+    }
+}
+
+/// Invokes the hook, and continues the request without any mutation if the hook fails or panics.
+pub fn run_hook<H: ScriptHook + ?Sized>(hook: &H, req: &RequestView) -> Verdict {
+    match catch_unwind(AssertUnwindSafe(|| hook.on_request(req))) {
+        Ok(Ok(verdict)) => verdict,
+        Ok(Err(e)) => {
+            tracing::warn!(
+                "[VOLO] script hook `{}` failed and is skipped: {e}",
+                hook.name()
+            );
+            Verdict::pass()
+        }
+        Err(_) => {
+            tracing::error!(
+                "[VOLO] script hook `{}` panicked and is skipped",
+                hook.name()
+            );
+            Verdict::pass()
+        }
+    }
+}
+
+/// Runs the hooks in order, each of them sees the mutations of the former ones.
+///
+/// The first rejection stops the following hooks.
+pub(crate) fn run_chain<'a, I>(hooks: I, req: &RequestView) -> Verdict
+where
+    I: IntoIterator<Item = &'a dyn ScriptHook>,
+{
+    let mut view: Option<RequestView> = None;
+    let mut mutations = Vec::new();
+    for hook in hooks {
+        match run_hook(hook, view.as_ref().unwrap_or(req)) {
+            Verdict::Continue { mutations: m } => {
+                if m.is_empty() {
+                    continue;
+                }
+                let view = view.get_or_insert_with(|| req.clone());
+                for mutation in &m {
+                    view.apply(mutation);
+                }
+                mutations.extend(m);
+            }
+            reject @ Verdict::Reject { .. } => return reject,
+        }
+    }
+    Verdict::Continue { mutations }
+}
+
+/// A chain of hooks, which is also a hook.
+#[derive(Clone, Default)]
+pub struct ScriptHooks {
+    hooks: Vec<Arc<dyn ScriptHook>>,
+}
+
+impl ScriptHooks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a hook to the chain.
+    pub fn hook<H: ScriptHook + 'static>(mut self, hook: H) -> Self {
+        self.hooks.push(Arc::new(hook));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+}
+
+impl ScriptHook for ScriptHooks {
+    fn name(&self) -> &str {
+        "hooks"
+    }
+
+    fn on_request(&self, req: &RequestView) -> Result<Verdict, ScriptError> {
+        Ok(run_chain(self.hooks.iter().map(|h| &**h), req))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set_header(name: &str, value: &str) -> Mutation {
+        Mutation::SetHeader {
+            name: name.to_owned(),
+            value: value.to_owned(),
+        }
+    }
+
+    #[test]
+    fn bounded_view() {
+        let limits = ViewLimits::default()
+            .max_headers(2)
+            .max_header_size(16)
+            .allowed_headers(["X-A", "x-b", "x-c", "x-large"]);
+        let headers: [(&str, &[u8]); 5] = [
+            ("x-large", b"0123456789abcdef"),
+            ("x-a", b"1"),
+            ("x-ignored", b"2"),
+            ("x-b", b"\xff"),
+            ("x-c", b"3"),
+        ];
+        let view = limits.view("GET", "/", headers);
+        assert_eq!(
+            view.headers,
+            [
+                ("x-a".to_owned(), "1".to_owned()),
+                ("x-c".to_owned(), "3".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn chain_isolates_failures() {
+        let hooks = ScriptHooks::new()
+            .hook(FnHook::new("add", |_| {
+                Ok(Verdict::mutate(vec![set_header("x-user", "alice")]))
+            }))
+            .hook(FnHook::new("fail", |_| {
+                Err(ScriptError::Runtime("boom".to_owned()))
+            }))
+            .hook(FnHook::new("panic", |_| panic!("boom")))
+            .hook(FnHook::new("see", |req| {
+                assert_eq!(req.header("X-User"), Some("alice"));
+                Ok(Verdict::mutate(vec![Mutation::SetPath {
+                    path: "/v2".to_owned(),
+                }]))
+            }));
+
+        let verdict = run_hook(&hooks, &RequestView::default());
+        assert_eq!(
+            verdict,
+            Verdict::mutate(vec![
+                set_header("x-user", "alice"),
+                Mutation::SetPath {
+                    path: "/v2".to_owned()
+                },
+            ])
+        );
+
+        let hooks = hooks
+            .hook(FnHook::new("reject", |_| {
+                Ok(Verdict::reject(403, "denied"))
+            }))
+            .hook(FnHook::new("unreachable", |_| unreachable!()));
+        assert_eq!(
+            run_hook(&hooks, &RequestView::default()),
+            Verdict::reject(403, "denied")
+        );
+    }
+}
@@ -0,0 +1,445 @@
+//! [`ScriptHook`]s implemented by WASM modules, which are loaded from a directory and can be
+//! reloaded at runtime.
+//!
+//! Each `.wasm` or `.wat` file in the directory is a module, and the modules are run in the order
+//! of the file names. A module must export:
+//! - `memory`: the linear memory;
+//! - `alloc(len: i32) -> i32`: allocates the buffer for the input;
+//! - `on_request(ptr: i32, len: i32) -> i64`: handles the input at the buffer, and returns the
+//!   output as `(ptr << 32) | len`.
+//!
+//! The input is the [`RequestView`] and the output is the [`Verdict`], both in JSON, e.g.
+//!
+//! ```json
+//! {"method":"GET","path":"/item?id=1","headers":[["x-tenant","a"]]}
+//! {"action":"continue","mutations":[{"op":"set_header","name":"x-canary","value":"1"}]}
+//! {"action":"reject","status":403,"message":"tenant is not allowed"}
+//! ```
+//!
+//! A module is instantiated for each request without any import, so it cannot keep states between
+//! requests or access the host. The fuel and the memory of each run are limited by the
+//! [`WasmConfig`], and a module exceeding the limits fails like any other error, which is logged
+//! and skipped.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use wasmtime::{Engine, InstancePre, Linker, Module, Store, StoreLimits, StoreLimitsBuilder, Trap};
+
+use super::{run_chain, RequestView, ScriptError, ScriptHook, Verdict};
+
+const DEFAULT_FUEL: u64 = 1_000_000;
+const DEFAULT_MAX_MEMORY: usize = 16 * 1024 * 1024;
+const DEFAULT_MAX_OUTPUT: usize = 64 * 1024;
+
+/// The limits of running the modules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WasmConfig {
+    fuel: u64,
+    max_memory: usize,
+    max_output: usize,
+}
+
+impl Default for WasmConfig {
+    fn default() -> Self {
+        Self {
+            fuel: DEFAULT_FUEL,
+            max_memory: DEFAULT_MAX_MEMORY,
+            max_output: DEFAULT_MAX_OUTPUT,
+        }
+    }
+}
+
+impl WasmConfig {
+    /// Sets the fuel of a run, which is roughly the number of the executed instructions.
+    ///
+    /// Default is `1_000_000`.
+    pub fn fuel(mut self, fuel: u64) -> Self {
+        self.fuel = fuel;
+        self
+    }
+
+    /// Sets the maximum size of the linear memory of a module in bytes.
+    ///
+    /// Default is `16 MiB`.
+    pub fn max_memory(mut self, max_memory: usize) -> Self {
+        self.max_memory = max_memory;
+        self
+    }
+
+    /// Sets the maximum size of the output of a run in bytes.
+    ///
+    /// Default is `64 KiB`.
+    pub fn max_output(mut self, max_output: usize) -> Self {
+        self.max_output = max_output;
+        self
+    }
+}
+
+struct State {
+    limits: StoreLimits,
+}
+
+/// A compiled module.
+pub struct WasmModule {
+    name: String,
+    config: WasmConfig,
+    engine: Engine,
+    instance_pre: InstancePre<State>,
+}
+
+impl WasmModule {
+    fn compile(
+        engine: &Engine,
+        config: WasmConfig,
+        name: String,
+        path: &Path,
+    ) -> Result<Self, ScriptError> {
+        let module = Module::from_file(engine, path).map_err(runtime_error)?;
+        let instance_pre = Linker::new(engine)
+            .instantiate_pre(&module)
+            .map_err(runtime_error)?;
+        Ok(Self {
+            name,
+            config,
+            engine: engine.clone(),
+            instance_pre,
+        })
+    }
+
+    fn run(&self, input: &[u8]) -> Result<Vec<u8>, ScriptError> {
+        let mut store = Store::new(
+            &self.engine,
+            State {
+                limits: StoreLimitsBuilder::new()
+                    .memory_size(self.config.max_memory)
+                    .instances(1)
+                    .build(),
+            },
+        );
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(self.config.fuel).map_err(runtime_error)?;
+
+        let instance = self
+            .instance_pre
+            .instantiate(&mut store)
+            .map_err(runtime_error)?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| ScriptError::Runtime("missing export `memory`".to_owned()))?;
+        let alloc = instance
+            .get_typed_func::<i32, i32>(&mut store, "alloc")
+            .map_err(runtime_error)?;
+        let on_request = instance
+            .get_typed_func::<(i32, i32), i64>(&mut store, "on_request")
+            .map_err(runtime_error)?;
+
+        let len = i32::try_from(input.len())
+            .map_err(|_| ScriptError::Runtime("input is too large".to_owned()))?;
+        let ptr = alloc.call(&mut store, len).map_err(runtime_error)?;
+        memory
+            .write(&mut store, ptr as u32 as usize, input)
+            .map_err(runtime_error)?;
+        let ret = on_request
+            .call(&mut store, (ptr, len))
+            .map_err(runtime_error)?;
+
+        let (ptr, len) = ((ret >> 32) as u32 as usize, ret as u32 as usize);
+        if len > self.config.max_output {
+            return Err(ScriptError::InvalidOutput(format!(
+                "{len} bytes is larger than the limit {}",
+                self.config.max_output
+            )));
+        }
+        let mut output = vec![0; len];
+        memory
+            .read(&store, ptr, &mut output)
+            .map_err(runtime_error)?;
+        Ok(output)
+    }
+}
+
+impl ScriptHook for WasmModule {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn on_request(&self, req: &RequestView) -> Result<Verdict, ScriptError> {
+        let input = serde_json::to_vec(req).map_err(|e| ScriptError::Other(e.into()))?;
+        let output = self.run(&input)?;
+        serde_json::from_slice(&output).map_err(|e| ScriptError::InvalidOutput(e.to_string()))
+    }
+}
+
+fn runtime_error(err: wasmtime::Error) -> ScriptError {
+    match err.downcast_ref::<Trap>() {
+        Some(Trap::OutOfFuel) => ScriptError::OutOfFuel,
+        _ => ScriptError::Runtime(format!("{err:#}")),
+    }
+}
+
+#[derive(Clone)]
+struct LoadedModule {
+    modified: Option<SystemTime>,
+    len: u64,
+    module: Arc<WasmModule>,
+}
+
+/// The modules loaded from a directory.
+///
+/// The modules are reloaded by [`WasmHooks::reload`], or periodically by [`WasmHooks::watch`]. A
+/// module failing to compile is logged and skipped, and the former version of it is kept if any.
+pub struct WasmHooks {
+    dir: PathBuf,
+    config: WasmConfig,
+    engine: Engine,
+    modules: RwLock<Arc<Vec<(PathBuf, LoadedModule)>>>,
+}
+
+impl WasmHooks {
+    /// Loads the modules from the directory.
+    pub fn load(dir: impl Into<PathBuf>, config: WasmConfig) -> Result<Arc<Self>, ScriptError> {
+        let mut engine_config = wasmtime::Config::new();
+        engine_config.consume_fuel(true);
+        let engine = wasmtime::Engine::new(&engine_config).map_err(runtime_error)?;
+
+        let hooks = Self {
+            dir: dir.into(),
+            config,
+            engine,
+            modules: RwLock::new(Arc::new(Vec::new())),
+        };
+        hooks.reload()?;
+        Ok(Arc::new(hooks))
+    }
+
+    /// Rescans the directory, compiles the new or changed modules and drops the removed ones.
+    ///
+    /// It only fails if the directory cannot be read.
+    pub fn reload(&self) -> Result<(), ScriptError> {
+        let mut paths = fs::read_dir(&self.dir)
+            .map_err(|e| ScriptError::Other(e.into()))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.is_file()
+                    && path
+                        .extension()
+                        .is_some_and(|ext| ext == "wasm" || ext == "wat")
+            })
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        let old = self.modules.read().unwrap().clone();
+        let mut old = old
+            .iter()
+            .map(|(path, loaded)| (path, loaded))
+            .collect::<HashMap<_, _>>();
+
+        let mut modules = Vec::with_capacity(paths.len());
+        for path in paths {
+            let metadata = match fs::metadata(&path) {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    tracing::warn!("[VOLO] failed to read wasm module {}: {e}", path.display());
+                    continue;
+                }
+            };
+            let (modified, len) = (metadata.modified().ok(), metadata.len());
+            let former = old.remove(&path);
+            if let Some(former) = former {
+                if former.modified == modified && former.len == len {
+                    modules.push((path, former.clone()));
+                    continue;
+                }
+            }
+
+            let name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            match WasmModule::compile(&self.engine, self.config, name, &path) {
+                Ok(module) => {
+                    tracing::info!("[VOLO] wasm module {} is loaded", path.display());
+                    modules.push((
+                        path,
+                        LoadedModule {
+                            modified,
+                            len,
+                            module: Arc::new(module),
+                        },
+                    ));
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        "[VOLO] failed to compile wasm module {}, it is skipped: {e}",
+                        path.display()
+                    );
+                    if let Some(former) = former {
+                        modules.push((path, former.clone()));
+                    }
+                }
+            }
+        }
+
+        *self.modules.write().unwrap() = Arc::new(modules);
+        Ok(())
+    }
+
+    /// Reloads the modules periodically until the hooks are dropped.
+    pub fn watch(self: &Arc<Self>, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let hooks = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let Some(hooks) = hooks.upgrade() else {
+                    return;
+                };
+                if let Err(e) = hooks.reload() {
+                    tracing::warn!(
+                        "[VOLO] failed to reload wasm modules from {}: {e}",
+                        hooks.dir.display()
+                    );
+                }
+            }
+        })
+    }
+
+    /// Returns the names of the loaded modules in order.
+    pub fn module_names(&self) -> Vec<String> {
+        self.modules
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(_, loaded)| loaded.module.name.clone())
+            .collect()
+    }
+}
+
+impl ScriptHook for WasmHooks {
+    fn name(&self) -> &str {
+        "wasm"
+    }
+
+    fn on_request(&self, req: &RequestView) -> Result<Verdict, ScriptError> {
+        let modules = self.modules.read().unwrap().clone();
+        Ok(run_chain(
+            modules
+                .iter()
+                .map(|(_, loaded)| &*loaded.module as &dyn ScriptHook),
+            req,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::Mutation;
+
+    /// A module returning the output directly.
+    fn constant_module(output: &str) -> String {
+        format!(
+            r#"(module
+  (memory (export "memory") 1)
+  (data (i32.const 0) "{}")
+  (func (export "alloc") (param i32) (result i32) (i32.const 4096))
+  (func (export "on_request") (param i32 i32) (result i64) (i64.const {})))"#,
+            output.replace('"', "\\\""),
+            output.len(),
+        )
+    }
+
+    fn set_header(value: &str) -> String {
+        constant_module(&format!(
+            r#"{{"action":"continue","mutations":[{{"op":"set_header","name":"x-version","value":"{value}"}}]}}"#
+        ))
+    }
+
+    const LOOP_MODULE: &str = r#"(module
+  (memory (export "memory") 1)
+  (func (export "alloc") (param i32) (result i32) (i32.const 0))
+  (func (export "on_request") (param i32 i32) (result i64)
+    (loop $l (br $l))
+    (i64.const 0)))"#;
+
+    fn version(value: &str) -> Verdict {
+        Verdict::mutate(vec![Mutation::SetHeader {
+            name: "x-version".to_owned(),
+            value: value.to_owned(),
+        }])
+    }
+
+    #[test]
+    fn fuel_exhaustion() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a_loop.wat"), LOOP_MODULE).unwrap();
+        fs::write(dir.path().join("b_header.wat"), set_header("1")).unwrap();
+        let hooks = WasmHooks::load(dir.path(), WasmConfig::default().fuel(10_000)).unwrap();
+
+        let modules = hooks.modules.read().unwrap().clone();
+        let err = modules[0]
+            .1
+            .module
+            .on_request(&RequestView::default())
+            .unwrap_err();
+        assert!(matches!(err, ScriptError::OutOfFuel), "{err}");
+
+        // the failing module is skipped
+        assert_eq!(
+            hooks.on_request(&RequestView::default()).unwrap(),
+            version("1")
+        );
+    }
+
+    #[test]
+    fn hot_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("header.wat");
+        fs::write(&path, set_header("1")).unwrap();
+        let hooks = WasmHooks::load(dir.path(), WasmConfig::default()).unwrap();
+        assert_eq!(
+            hooks.on_request(&RequestView::default()).unwrap(),
+            version("1")
+        );
+
+        // the output is longer, so the change is seen even if the mtime is not
+        fs::write(&path, set_header("22")).unwrap();
+        hooks.reload().unwrap();
+        assert_eq!(
+            hooks.on_request(&RequestView::default()).unwrap(),
+            version("22")
+        );
+
+        // a broken module keeps the former version
+        fs::write(&path, "(module").unwrap();
+        hooks.reload().unwrap();
+        assert_eq!(
+            hooks.on_request(&RequestView::default()).unwrap(),
+            version("22")
+        );
+
+        // the removed module is dropped, and a module exceeding the memory limit is skipped
+        fs::remove_file(&path).unwrap();
+        fs::write(
+            dir.path().join("large.wat"),
+            set_header("3").replace(
+                r#"(memory (export "memory") 1)"#,
+                r#"(memory (export "memory") 32)"#,
+            ),
+        )
+        .unwrap();
+        let hooks = WasmHooks::load(dir.path(), WasmConfig::default().max_memory(1 << 20)).unwrap();
+        assert_eq!(hooks.module_names(), ["large.wat"]);
+        assert_eq!(
+            hooks.on_request(&RequestView::default()).unwrap(),
+            Verdict::pass()
+        );
+    }
+}
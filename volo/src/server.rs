@@ -0,0 +1,195 @@
+//! The handle to shut down a running server, shared by the servers of `volo-thrift`, `volo-grpc`
+//! and `volo-http`.
+//!
+//! A [`ServerHandle`] is taken from the server before it runs, and shuts it down without a signal,
+//! e.g. in the tests or when the server is embedded in another program:
+//!
+//! ```rust,ignore
+//! let server = Server::new().add_service(service);
+//! let handle = server.handle();
+//! tokio::spawn(server.run(addr));
+//!
+//! // stops accepting, drains the connections, and waits for at most 5 seconds
+//! handle.shutdown_timeout(Duration::from_secs(5)).await;
+//! assert_eq!(handle.draining(), 0);
+//! ```
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{sync::watch, time::Instant};
+
+type Counter = Box<dyn Fn() -> usize + Send + Sync>;
+
+/// The handle to shut down a server, see the [module docs](self).
+///
+/// The clones of a handle control the same server.
+#[derive(Clone)]
+pub struct ServerHandle {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    /// `None` until the shutdown is requested, and then the deadline to drain if any.
+    requested: watch::Sender<Option<Option<Instant>>>,
+    finished: watch::Sender<bool>,
+    /// Counts the live connections of the running server.
+    connections: Mutex<Option<Counter>>,
+}
+
+impl Default for ServerHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ServerHandle {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                requested: watch::channel(None).0,
+                finished: watch::channel(false).0,
+                connections: Mutex::new(None),
+            }),
+        }
+    }
+
+    /// Stops accepting, notifies the connections to drain, and resolves when all of them have
+    /// finished.
+    ///
+    /// It never resolves if the server is not running.
+    pub async fn shutdown(&self) {
+        self.request(None);
+        self.finished().await;
+    }
+
+    /// Same as [`shutdown`](Self::shutdown), but the server stops waiting for the connections
+    /// after `timeout`, and the connections still draining are left behind.
+    pub async fn shutdown_timeout(&self, timeout: Duration) {
+        self.request(Some(Instant::now() + timeout));
+        self.finished().await;
+    }
+
+    /// Returns the number of the connections still alive, which are draining after the shutdown
+    /// is requested.
+    pub fn draining(&self) -> usize {
+        self.inner
+            .connections
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map_or(0, |count| count())
+    }
+
+    /// Returns whether the shutdown has been requested.
+    pub fn is_shutdown_requested(&self) -> bool {
+        self.inner.requested.borrow().is_some()
+    }
+
+    fn request(&self, deadline: Option<Instant>) {
+        self.inner.requested.send_if_modified(|requested| {
+            if requested.is_some() {
+                return false;
+            }
+            *requested = Some(deadline);
+            true
+        });
+    }
+
+    async fn finished(&self) {
+        let mut finished = self.inner.finished.subscribe();
+        // the sender is kept by the handle, so it never fails
+        let _ = finished.wait_for(|finished| *finished).await;
+    }
+
+    /// Called by the server when it starts to run, with the counter of its live connections.
+    ///
+    /// The handle is marked finished when the returned guard is dropped, i.e. when the server
+    /// returns.
+    pub fn serving(&self, connections: impl Fn() -> usize + Send + Sync + 'static) -> Serving {
+        *self.inner.connections.lock().unwrap() = Some(Box::new(connections));
+        Serving {
+            handle: self.clone(),
+        }
+    }
+
+    /// Called by the server to wait for the shutdown to be requested, which returns the deadline
+    /// to drain the connections if any.
+    pub async fn requested(&self) -> Option<Instant> {
+        let mut requested = self.inner.requested.subscribe();
+        // the sender is kept by the handle, so it never fails
+        let requested = requested
+            .wait_for(Option::is_some)
+            .await
+            .map(|requested| (*requested).flatten());
+        match requested {
+            Ok(deadline) => deadline,
+            Err(_) => std::future::pending().await,
+        }
+    }
+}
+
+impl fmt::Debug for ServerHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServerHandle")
+            .field("shutdown_requested", &self.is_shutdown_requested())
+            .field("draining", &self.draining())
+            .finish()
+    }
+}
+
+/// The guard of a running server returned by [`ServerHandle::serving`].
+pub struct Serving {
+    handle: ServerHandle,
+}
+
+impl Drop for Serving {
+    fn drop(&mut self) {
+        self.handle.inner.finished.send_replace(true);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn shutdown_and_drain() {
+        let handle = ServerHandle::new();
+        let connections = Arc::new(AtomicUsize::new(2));
+
+        // a server draining its connections one by one
+        let server = {
+            let handle = handle.clone();
+            let connections = connections.clone();
+            tokio::spawn(async move {
+                let counter = connections.clone();
+                let _serving = handle.serving(move || counter.load(Ordering::Relaxed));
+                let deadline = handle.requested().await;
+                assert!(deadline.is_some());
+                while connections.load(Ordering::Relaxed) > 0 {
+                    if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                        return;
+                    }
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            })
+        };
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(handle.draining(), 2);
+        assert!(!handle.is_shutdown_requested());
+        connections.store(1, Ordering::Relaxed);
+
+        // the last connection never finishes
+        handle.shutdown_timeout(Duration::from_millis(50)).await;
+        assert!(handle.is_shutdown_requested());
+        assert_eq!(handle.draining(), 1);
+        server.await.unwrap();
+    }
+}
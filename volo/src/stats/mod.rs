@@ -0,0 +1,873 @@
+//! Lightweight per-method usage statistics for servers.
+//!
+//! [`UsageStats`] records the count, errors and latency of requests per (method, caller) in
+//! rolling windows of `1m`, `10m` and `1h`. It can be queried by [`UsageStats::snapshot`].
+//!
+//! The caller is taken from the caller service name in [`RpcInfo`](crate::context::RpcInfo),
+//! which is filled by the TTHeader of thrift or the metadata of gRPC.
+//!
+//! With the `metrics` feature, [`UsageStats`] is also a [`Source`](crate::metrics::Source)
+//! exposing the windows as gauges, e.g. `volo_usage_requests{method,caller,window}`, and the
+//! snapshot can be rendered as JSON by `Snapshot::to_json` for serving on an admin endpoint.
+//!
+//! # Memory
+//!
+//! The memory is strictly bounded by [`UsageStatsConfig`]: at most `max_methods` methods and
+//! `max_callers` callers per method have their own counters, the others are aggregated into
+//! [`OTHER`]. Each counter costs about `6KB`.
+//!
+//! The ones with their own counters are the top ones by the number of requests, which are tracked
+//! by the space-saving algorithm: a new one takes the place of the one with the fewest requests,
+//! whose statistics are merged into [`OTHER`].
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! let stats = volo::stats::UsageStats::new(Default::default());
+//!
+//! server.layer_front(volo::stats::UsageStatsLayer::new(stats.clone()));
+//!
+//! // in the admin endpoint, with the `metrics` feature
+//! let json = stats.snapshot().to_json();
+//! ```
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+use faststr::FastStr;
+use motore::{layer::Layer, service::Service};
+
+use crate::context::Context;
+
+/// The name of the aggregated entry for methods or callers exceeding the cardinality limit.
+pub const OTHER: &str = "<other>";
+/// The caller name used when the caller service name is unknown.
+pub const UNKNOWN: &str = "<unknown>";
+
+const DEFAULT_MAX_METHODS: usize = 64;
+const DEFAULT_MAX_CALLERS: usize = 8;
+
+/// The number of buckets in each window.
+const BUCKETS: usize = 60;
+
+/// The rolling windows, each of them is split into [`BUCKETS`] buckets.
+const WINDOWS: [(&str, Duration); 3] = [
+    ("1m", Duration::from_secs(60)),
+    ("10m", Duration::from_secs(10 * 60)),
+    ("1h", Duration::from_secs(60 * 60)),
+];
+
+/// Configuration of [`UsageStats`].
+#[derive(Debug, Clone, Copy)]
+pub struct UsageStatsConfig {
+    max_methods: usize,
+    max_callers: usize,
+}
+
+impl Default for UsageStatsConfig {
+    fn default() -> Self {
+        Self {
+            max_methods: DEFAULT_MAX_METHODS,
+            max_callers: DEFAULT_MAX_CALLERS,
+        }
+    }
+}
+
+impl UsageStatsConfig {
+    /// Sets the maximum number of methods to be recorded separately, which are the ones with the
+    /// most requests, and the others will be aggregated into [`OTHER`].
+    ///
+    /// Default is `64`.
+    pub fn max_methods(mut self, max: usize) -> Self {
+        self.max_methods = max;
+        self
+    }
+
+    /// Sets the maximum number of callers to be recorded separately for each method, which are the
+    /// ones with the most requests, and the others will be aggregated into [`OTHER`].
+    ///
+    /// Default is `8`.
+    pub fn max_callers(mut self, max: usize) -> Self {
+        self.max_callers = max;
+        self
+    }
+}
+
+/// The in-process usage statistics, which can be cloned and shared cheaply.
+#[derive(Clone)]
+pub struct UsageStats {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    config: UsageStatsConfig,
+    start: Instant,
+    methods: TopK<MethodStats>,
+}
+
+struct MethodStats {
+    callers: TopK<Counter>,
+}
+
+impl MethodStats {
+    fn new(max_callers: usize) -> Self {
+        Self {
+            callers: TopK::new(max_callers, Counter::new()),
+        }
+    }
+}
+
+impl UsageStats {
+    /// Creates a new [`UsageStats`] with the given config.
+    pub fn new(config: UsageStatsConfig) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                config,
+                start: Instant::now(),
+                methods: TopK::new(config.max_methods, MethodStats::new(config.max_callers)),
+            }),
+        }
+    }
+
+    /// Records a finished request.
+    pub fn record(&self, method: &str, caller: &str, latency: Duration, is_err: bool) {
+        self.record_at(self.now(), method, caller, latency, is_err);
+    }
+
+    /// Takes a snapshot of the statistics of all the windows.
+    pub fn snapshot(&self) -> Snapshot {
+        self.snapshot_at(self.now())
+    }
+
+    fn now(&self) -> Duration {
+        self.inner.start.elapsed()
+    }
+
+    fn record_at(
+        &self,
+        now: Duration,
+        method: &str,
+        caller: &str,
+        latency: Duration,
+        is_err: bool,
+    ) {
+        let caller = if caller.is_empty() { UNKNOWN } else { caller };
+        let max_callers = self.inner.config.max_callers;
+        let method_stats = self
+            .inner
+            .methods
+            .hit(method, 1, || MethodStats::new(max_callers));
+        let counter = method_stats.callers.hit(caller, 1, Counter::new);
+        counter.record(now, latency, is_err);
+    }
+
+    fn snapshot_at(&self, now: Duration) -> Snapshot {
+        let method_snapshot = |method: FastStr, stats: &MethodStats| {
+            let mut callers = stats
+                .callers
+                .entries
+                .iter()
+                .map(|e| CallerSnapshot {
+                    caller: e.key().clone(),
+                    windows: e.value().snapshot(now),
+                })
+                .collect::<Vec<_>>();
+            callers.sort_by(|a, b| a.caller.cmp(&b.caller));
+            if stats.callers.has_other() {
+                callers.push(CallerSnapshot {
+                    caller: FastStr::from_static_str(OTHER),
+                    windows: stats.callers.other.snapshot(now),
+                });
+            }
+            MethodSnapshot { method, callers }
+        };
+
+        let mut methods = self
+            .inner
+            .methods
+            .entries
+            .iter()
+            .map(|e| method_snapshot(e.key().clone(), e.value()))
+            .collect::<Vec<_>>();
+        methods.sort_by(|a, b| a.method.cmp(&b.method));
+        if self.inner.methods.has_other() {
+            methods.push(method_snapshot(
+                FastStr::from_static_str(OTHER),
+                &self.inner.methods.other,
+            ));
+        }
+
+        Snapshot { methods }
+    }
+}
+
+/// The values of the top `max` keys by the hits, and the `other` one aggregating the others.
+///
+/// The keys are tracked by the space-saving algorithm: when there's no room for a new key, the
+/// one with the fewest hits is evicted and merged into `other`, and the new one inherits its hits,
+/// which overestimates the new one by at most the hits of the evicted one. So a key hit more than
+/// `1 / max` of the total is always kept.
+struct TopK<V> {
+    max: usize,
+    entries: DashMap<FastStr, Arc<Tracked<V>>>,
+    other: Tracked<V>,
+    has_other: AtomicBool,
+    /// Serializes the insertions and evictions, the hits of the kept keys don't take it.
+    evicting: Mutex<()>,
+}
+
+struct Tracked<V> {
+    hits: AtomicU64,
+    value: V,
+}
+
+impl<V> std::ops::Deref for Tracked<V> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        &self.value
+    }
+}
+
+impl<V> Tracked<V> {
+    fn new(hits: u64, value: V) -> Self {
+        Self {
+            hits: AtomicU64::new(hits),
+            value,
+        }
+    }
+}
+
+/// The values which can be aggregated into another.
+trait Merge {
+    fn merge_into(&self, other: &Self);
+}
+
+impl<V: Merge> TopK<V> {
+    fn new(max: usize, other: V) -> Self {
+        Self {
+            max,
+            entries: DashMap::new(),
+            other: Tracked::new(0, other),
+            has_other: AtomicBool::new(false),
+            evicting: Mutex::new(()),
+        }
+    }
+
+    fn has_other(&self) -> bool {
+        self.has_other.load(Ordering::Relaxed)
+    }
+
+    /// Adds `hits` to the `key`, and returns the value of it, which is `other` if it's not kept.
+    fn hit(&self, key: &str, hits: u64, make: impl FnOnce() -> V) -> Hit<'_, V> {
+        if let Some(tracked) = self.entries.get(key) {
+            tracked.hits.fetch_add(hits, Ordering::Relaxed);
+            return Hit::Kept(tracked.clone());
+        }
+        if self.max == 0 {
+            self.has_other.store(true, Ordering::Relaxed);
+            return Hit::Other(&self.other);
+        }
+
+        let _guard = self.evicting.lock().unwrap_or_else(|e| e.into_inner());
+        // inserted by others while waiting for the lock
+        if let Some(tracked) = self.entries.get(key) {
+            tracked.hits.fetch_add(hits, Ordering::Relaxed);
+            return Hit::Kept(tracked.clone());
+        }
+        let mut inherited = 0;
+        if self.entries.len() >= self.max {
+            let min = self
+                .entries
+                .iter()
+                .min_by_key(|e| e.hits.load(Ordering::Relaxed))
+                .map(|e| e.key().clone());
+            if let Some((_, evicted)) = min.and_then(|key| self.entries.remove(&key)) {
+                // the records on the evicted one after the merging are lost, which is acceptable
+                // for statistics
+                inherited = evicted.hits.load(Ordering::Relaxed);
+                evicted.merge_into(&self.other);
+                self.other.hits.fetch_add(inherited, Ordering::Relaxed);
+                self.has_other.store(true, Ordering::Relaxed);
+            }
+        }
+        let tracked = Arc::new(Tracked::new(inherited + hits, make()));
+        self.entries.insert(FastStr::new(key), tracked.clone());
+        Hit::Kept(tracked)
+    }
+}
+
+/// The value returned by [`TopK::hit`].
+enum Hit<'a, V> {
+    Kept(Arc<Tracked<V>>),
+    Other(&'a Tracked<V>),
+}
+
+impl<V> std::ops::Deref for Hit<'_, V> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        match self {
+            Hit::Kept(tracked) => tracked,
+            Hit::Other(tracked) => tracked,
+        }
+    }
+}
+
+impl Merge for MethodStats {
+    fn merge_into(&self, other: &Self) {
+        for e in self.callers.entries.iter() {
+            let hits = e.hits.load(Ordering::Relaxed);
+            e.merge_into(&other.callers.hit(e.key(), hits, Counter::new));
+        }
+        if self.callers.has_other() {
+            self.callers.other.merge_into(&other.callers.other);
+            other.callers.has_other.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// The counter of one (method, caller), which holds a ring buffer for each window.
+struct Counter {
+    rings: [Ring; WINDOWS.len()],
+}
+
+impl Counter {
+    fn new() -> Self {
+        Self {
+            rings: WINDOWS.map(|(_, window)| Ring::new(window)),
+        }
+    }
+
+    fn record(&self, now: Duration, latency: Duration, is_err: bool) {
+        let latency = latency.as_micros() as u64;
+        for ring in self.rings.iter() {
+            ring.record(now, latency, is_err);
+        }
+    }
+
+    fn snapshot(&self, now: Duration) -> Vec<WindowSnapshot> {
+        WINDOWS
+            .iter()
+            .zip(self.rings.iter())
+            .map(|((name, window), ring)| ring.snapshot(name, *window, now))
+            .collect()
+    }
+}
+
+impl Merge for Counter {
+    fn merge_into(&self, other: &Self) {
+        for (ring, other) in self.rings.iter().zip(other.rings.iter()) {
+            ring.merge_into(other);
+        }
+    }
+}
+
+struct Ring {
+    bucket_millis: u64,
+    buckets: Box<[Bucket]>,
+}
+
+#[derive(Default)]
+struct Bucket {
+    /// The index of the time slice this bucket is recording, starting from 1 so that an unused
+    /// bucket will never be counted.
+    epoch: AtomicU64,
+    count: AtomicU64,
+    errors: AtomicU64,
+    latency_sum_us: AtomicU64,
+    latency_max_us: AtomicU64,
+}
+
+impl Ring {
+    fn new(window: Duration) -> Self {
+        Self {
+            bucket_millis: (window.as_millis() as u64 / BUCKETS as u64).max(1),
+            buckets: (0..BUCKETS).map(|_| Bucket::default()).collect(),
+        }
+    }
+
+    fn epoch(&self, now: Duration) -> u64 {
+        now.as_millis() as u64 / self.bucket_millis + 1
+    }
+
+    fn record(&self, now: Duration, latency_us: u64, is_err: bool) {
+        let epoch = self.epoch(now);
+        let bucket = &self.buckets[epoch as usize % BUCKETS];
+
+        let old = bucket.epoch.load(Ordering::Relaxed);
+        if old != epoch
+            && bucket
+                .epoch
+                .compare_exchange(old, epoch, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            // The bucket is outdated, only the one who updates the epoch resets it. Other
+            // requests recorded during resetting may be lost, which is acceptable for statistics.
+            bucket.count.store(0, Ordering::Relaxed);
+            bucket.errors.store(0, Ordering::Relaxed);
+            bucket.latency_sum_us.store(0, Ordering::Relaxed);
+            bucket.latency_max_us.store(0, Ordering::Relaxed);
+        }
+
+        bucket.count.fetch_add(1, Ordering::Relaxed);
+        if is_err {
+            bucket.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        bucket
+            .latency_sum_us
+            .fetch_add(latency_us, Ordering::Relaxed);
+        bucket
+            .latency_max_us
+            .fetch_max(latency_us, Ordering::Relaxed);
+    }
+
+    /// Adds the buckets to the ones of the same time slices in `other`, where the outdated ones are
+    /// replaced.
+    fn merge_into(&self, other: &Ring) {
+        for (bucket, other) in self.buckets.iter().zip(other.buckets.iter()) {
+            let epoch = bucket.epoch.load(Ordering::Relaxed);
+            if epoch == 0 {
+                continue;
+            }
+            let other_epoch = other.epoch.load(Ordering::Relaxed);
+            if other_epoch > epoch {
+                continue;
+            }
+            if other_epoch < epoch
+                && other
+                    .epoch
+                    .compare_exchange(other_epoch, epoch, Ordering::Relaxed, Ordering::Relaxed)
+                    .is_ok()
+            {
+                other.count.store(0, Ordering::Relaxed);
+                other.errors.store(0, Ordering::Relaxed);
+                other.latency_sum_us.store(0, Ordering::Relaxed);
+                other.latency_max_us.store(0, Ordering::Relaxed);
+            }
+            other
+                .count
+                .fetch_add(bucket.count.load(Ordering::Relaxed), Ordering::Relaxed);
+            other
+                .errors
+                .fetch_add(bucket.errors.load(Ordering::Relaxed), Ordering::Relaxed);
+            other.latency_sum_us.fetch_add(
+                bucket.latency_sum_us.load(Ordering::Relaxed),
+                Ordering::Relaxed,
+            );
+            other.latency_max_us.fetch_max(
+                bucket.latency_max_us.load(Ordering::Relaxed),
+                Ordering::Relaxed,
+            );
+        }
+    }
+
+    fn snapshot(&self, name: &'static str, window: Duration, now: Duration) -> WindowSnapshot {
+        let current = self.epoch(now);
+        let mut snapshot = WindowSnapshot {
+            window: name,
+            duration: window,
+            count: 0,
+            errors: 0,
+            latency_avg: Duration::ZERO,
+            latency_max: Duration::ZERO,
+        };
+        let mut latency_sum_us = 0u64;
+        let mut latency_max_us = 0u64;
+        for bucket in self.buckets.iter() {
+            let epoch = bucket.epoch.load(Ordering::Relaxed);
+            if epoch == 0 || epoch > current || epoch + (BUCKETS as u64) <= current {
+                continue;
+            }
+            snapshot.count += bucket.count.load(Ordering::Relaxed);
+            snapshot.errors += bucket.errors.load(Ordering::Relaxed);
+            latency_sum_us += bucket.latency_sum_us.load(Ordering::Relaxed);
+            latency_max_us = latency_max_us.max(bucket.latency_max_us.load(Ordering::Relaxed));
+        }
+        if snapshot.count > 0 {
+            snapshot.latency_avg = Duration::from_micros(latency_sum_us / snapshot.count);
+        }
+        snapshot.latency_max = Duration::from_micros(latency_max_us);
+        snapshot
+    }
+}
+
+/// A snapshot of [`UsageStats`].
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    /// The statistics of each method, sorted by method name, with [`OTHER`] at the end if any.
+    pub methods: Vec<MethodSnapshot>,
+}
+
+/// The statistics of a method.
+#[derive(Debug, Clone)]
+pub struct MethodSnapshot {
+    pub method: FastStr,
+    /// The statistics of each caller, sorted by caller name, with [`OTHER`] at the end if any.
+    pub callers: Vec<CallerSnapshot>,
+}
+
+/// The statistics of a caller calling a method.
+#[derive(Debug, Clone)]
+pub struct CallerSnapshot {
+    pub caller: FastStr,
+    /// The statistics of `1m`, `10m` and `1h` windows.
+    pub windows: Vec<WindowSnapshot>,
+}
+
+/// The statistics in a rolling window.
+#[derive(Debug, Clone)]
+pub struct WindowSnapshot {
+    /// The name of the window, e.g. `1m`.
+    pub window: &'static str,
+    pub duration: Duration,
+    pub count: u64,
+    pub errors: u64,
+    pub latency_avg: Duration,
+    pub latency_max: Duration,
+}
+
+#[cfg(feature = "metrics")]
+#[cfg_attr(docsrs, doc(cfg(feature = "metrics")))]
+impl Snapshot {
+    /// Renders the snapshot as JSON.
+    ///
+    /// The latencies are in microseconds.
+    pub fn to_json(&self) -> String {
+        let methods = self
+            .methods
+            .iter()
+            .map(|method| {
+                let callers = method
+                    .callers
+                    .iter()
+                    .map(|caller| {
+                        let windows = caller
+                            .windows
+                            .iter()
+                            .map(|w| {
+                                let window = serde_json::json!({
+                                    "count": w.count,
+                                    "errors": w.errors,
+                                    "latency_avg_us": w.latency_avg.as_micros() as u64,
+                                    "latency_max_us": w.latency_max.as_micros() as u64,
+                                });
+                                (w.window.to_owned(), window)
+                            })
+                            .collect::<serde_json::Map<_, _>>();
+                        serde_json::json!({
+                            "caller": caller.caller.as_str(),
+                            "windows": windows,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                serde_json::json!({
+                    "method": method.method.as_str(),
+                    "callers": callers,
+                })
+            })
+            .collect::<Vec<_>>();
+        serde_json::json!({ "methods": methods }).to_string()
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl crate::metrics::Source for UsageStats {
+    fn collect(&self, visitor: &mut dyn crate::metrics::Visitor) {
+        use crate::metrics::{Desc, Value};
+
+        type Read = fn(&WindowSnapshot) -> f64;
+        let metrics: [(Desc, Read); 4] = [
+            (
+                Desc::new("volo_usage_requests", "The requests in the rolling window."),
+                |w| w.count as f64,
+            ),
+            (
+                Desc::new(
+                    "volo_usage_errors",
+                    "The failed requests in the rolling window.",
+                ),
+                |w| w.errors as f64,
+            ),
+            (
+                Desc::new(
+                    "volo_usage_latency_avg_seconds",
+                    "The average latency in the rolling window.",
+                ),
+                |w| w.latency_avg.as_secs_f64(),
+            ),
+            (
+                Desc::new(
+                    "volo_usage_latency_max_seconds",
+                    "The maximum latency in the rolling window.",
+                ),
+                |w| w.latency_max.as_secs_f64(),
+            ),
+        ];
+
+        let snapshot = self.snapshot();
+        for (desc, read) in metrics.iter() {
+            for method in snapshot.methods.iter() {
+                for caller in method.callers.iter() {
+                    for w in caller.windows.iter() {
+                        let labels = [
+                            ("method", method.method.as_str()),
+                            ("caller", caller.caller.as_str()),
+                            ("window", w.window),
+                        ];
+                        visitor.visit(desc, &labels, Value::Gauge(read(w)));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A layer that records the usage statistics of the server.
+///
+/// This layer should be put in the front so that the latency covers all the other layers.
+#[derive(Clone)]
+pub struct UsageStatsLayer {
+    stats: UsageStats,
+}
+
+impl UsageStatsLayer {
+    /// Creates a new [`UsageStatsLayer`] recording to the given [`UsageStats`].
+    pub fn new(stats: UsageStats) -> Self {
+        Self { stats }
+    }
+}
+
+impl<S> Layer<S> for UsageStatsLayer {
+    type Service = UsageStatsService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        UsageStatsService {
+            inner,
+            stats: self.stats,
+        }
+    }
+}
+
+/// The service created by [`UsageStatsLayer`].
+#[derive(Clone)]
+pub struct UsageStatsService<S> {
+    inner: S,
+    stats: UsageStats,
+}
+
+impl<Cx, Req, S> Service<Cx, Req> for UsageStatsService<S>
+where
+    Cx: Context + Send + Sync + 'static,
+    Req: Send + 'static,
+    S: Service<Cx, Req> + Send + Sync + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn call<'s, 'cx>(
+        &'s self,
+        cx: &'cx mut Cx,
+        req: Req,
+    ) -> Result<Self::Response, Self::Error> {
+        let start = Instant::now();
+        let result = self.inner.call(cx, req).await;
+        let rpc_info = cx.rpc_info();
+        self.stats.record(
+            rpc_info.method(),
+            rpc_info.caller().service_name_ref(),
+            start.elapsed(),
+            result.is_err(),
+        );
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    fn window<'a>(snapshot: &'a Snapshot, method: &str, caller: &str) -> &'a [WindowSnapshot] {
+        &snapshot
+            .methods
+            .iter()
+            .find(|m| m.method == method)
+            .unwrap()
+            .callers
+            .iter()
+            .find(|c| c.caller == caller)
+            .unwrap()
+            .windows
+    }
+
+    #[test]
+    fn window_rollover() {
+        let stats = UsageStats::new(Default::default());
+
+        stats.record_at(Duration::ZERO, "m", "c", 10 * MS, false);
+        stats.record_at(Duration::from_secs(30), "m", "c", 30 * MS, true);
+
+        let snapshot = stats.snapshot_at(Duration::from_secs(59));
+        let w = window(&snapshot, "m", "c");
+        assert_eq!(w[0].count, 2);
+        assert_eq!(w[0].errors, 1);
+        assert_eq!(w[0].latency_avg, 20 * MS);
+        assert_eq!(w[0].latency_max, 30 * MS);
+
+        // The first record rolls out of the `1m` window, but is still in `10m` and `1h`.
+        let snapshot = stats.snapshot_at(Duration::from_secs(61));
+        let w = window(&snapshot, "m", "c");
+        assert_eq!(w[0].count, 1);
+        assert_eq!(w[0].latency_max, 30 * MS);
+        assert_eq!(w[1].count, 2);
+        assert_eq!(w[2].count, 2);
+
+        // Everything rolls out of the `1m` window.
+        let snapshot = stats.snapshot_at(Duration::from_secs(120));
+        let w = window(&snapshot, "m", "c");
+        assert_eq!(w[0].count, 0);
+        assert_eq!(w[0].latency_max, Duration::ZERO);
+        assert_eq!(w[1].count, 2);
+
+        // A bucket is reused after a whole window, and the outdated data must be reset.
+        stats.record_at(Duration::from_secs(600), "m", "c", 5 * MS, false);
+        let snapshot = stats.snapshot_at(Duration::from_secs(600));
+        let w = window(&snapshot, "m", "c");
+        assert_eq!(w[0].count, 1);
+        assert_eq!(w[0].latency_max, 5 * MS);
+        assert_eq!(w[1].count, 2);
+        assert_eq!(w[2].count, 3);
+
+        let snapshot = stats.snapshot_at(Duration::from_secs(4200));
+        let w = window(&snapshot, "m", "c");
+        assert_eq!(w[2].count, 0);
+    }
+
+    #[test]
+    fn cardinality_guard() {
+        let stats = UsageStats::new(UsageStatsConfig::default().max_methods(2).max_callers(3));
+
+        for i in 0..100 {
+            stats.record_at(Duration::ZERO, "m", &format!("caller-{i}"), MS, false);
+        }
+        for i in 0..10 {
+            stats.record_at(Duration::ZERO, &format!("method-{i}"), "c", MS, false);
+        }
+        stats.record_at(Duration::ZERO, "m", "", MS, false);
+
+        let snapshot = stats.snapshot_at(Duration::ZERO);
+        let methods = snapshot
+            .methods
+            .iter()
+            .map(|m| m.method.as_str())
+            .collect::<Vec<_>>();
+        // `m` is hit the most, and the last one takes the place of the others
+        assert_eq!(methods, ["m", "method-9", OTHER]);
+
+        let m = &snapshot.methods[0];
+        assert_eq!(m.callers.len(), 4);
+        assert_eq!(m.callers[3].caller, OTHER);
+        assert!(m.callers.iter().any(|c| c.caller == UNKNOWN));
+        // nothing is lost by the evictions
+        let total: u64 = m.callers.iter().map(|c| c.windows[0].count).sum();
+        assert_eq!(total, 101);
+
+        // the 9 evicted methods are merged
+        assert_eq!(window(&snapshot, OTHER, "c")[0].count, 9);
+        assert_eq!(stats.inner.methods.entries.len(), 2);
+        assert_eq!(stats.inner.methods.other.callers.entries.len(), 1);
+    }
+
+    #[test]
+    fn top_k() {
+        let stats = UsageStats::new(UsageStatsConfig::default().max_callers(2));
+
+        let record = |caller: &str| stats.record_at(Duration::ZERO, "m", caller, MS, false);
+        for i in 0..10 {
+            record(&format!("light-{i}"));
+        }
+        // the slots are taken by the first ones, but the heavy one comes later
+        for _ in 0..30 {
+            record("heavy");
+        }
+        for i in 10..20 {
+            record(&format!("light-{i}"));
+        }
+
+        let snapshot = stats.snapshot_at(Duration::ZERO);
+        assert_eq!(window(&snapshot, "m", "heavy")[0].count, 30);
+        let m = &snapshot.methods[0];
+        assert_eq!(m.callers.len(), 3);
+        let total: u64 = m.callers.iter().map(|c| c.windows[0].count).sum();
+        assert_eq!(total, 50);
+    }
+
+    #[test]
+    fn merge_windows() {
+        let stats = UsageStats::new(UsageStatsConfig::default().max_callers(1));
+
+        stats.record_at(Duration::ZERO, "m", "a", 10 * MS, true);
+        stats.record_at(Duration::from_secs(30), "m", "a", 30 * MS, false);
+        // `a` is evicted and merged into `OTHER`
+        stats.record_at(Duration::from_secs(30), "m", "b", MS, false);
+        stats.record_at(Duration::from_secs(30), "m", "c", MS, false);
+
+        let snapshot = stats.snapshot_at(Duration::from_secs(61));
+        let other = window(&snapshot, "m", OTHER);
+        // the first record of `a` rolls out of `1m`
+        assert_eq!(other[0].count, 2);
+        assert_eq!(other[0].errors, 0);
+        assert_eq!(other[1].count, 3);
+        assert_eq!(other[1].errors, 1);
+        assert_eq!(other[1].latency_max, 30 * MS);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn json() {
+        let stats = UsageStats::new(Default::default());
+        stats.record_at(Duration::ZERO, "m\"", "c", MS, false);
+        let json: serde_json::Value =
+            serde_json::from_str(&stats.snapshot_at(Duration::ZERO).to_json()).unwrap();
+        let window = serde_json::json!({
+            "count": 1,
+            "errors": 0,
+            "latency_avg_us": 1000,
+            "latency_max_us": 1000,
+        });
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "methods": [{
+                    "method": "m\"",
+                    "callers": [{
+                        "caller": "c",
+                        "windows": { "1m": window, "10m": window, "1h": window },
+                    }],
+                }],
+            })
+        );
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn metrics_source() {
+        let stats = UsageStats::new(Default::default());
+        stats.record("m", "c", MS, true);
+        let registry = crate::metrics::Registry::new();
+        registry.register(stats);
+        let text = registry.render();
+        assert!(text.contains("volo_usage_requests{method=\"m\",caller=\"c\",window=\"1m\"} 1\n"));
+        assert!(text.contains("volo_usage_errors{method=\"m\",caller=\"c\",window=\"1h\"} 1\n"));
+    }
+}
@@ -0,0 +1,140 @@
+//! Exponential backoff with optional full jitter, shared by the retries of the calls and of the
+//! connects.
+//!
+//! The `n`-th delay, starting from 0, is `base * 2^n` bounded by `cap`. With the full jitter, the
+//! delay is picked uniformly from `[0, base * 2^n]` instead, which spreads the retries of many
+//! clients failing at the same time, e.g. when the server is being deployed.
+//!
+//! ```rust
+//! use std::time::Duration;
+//!
+//! use volo::util::backoff::Backoff;
+//!
+//! let backoff = Backoff::new(Duration::from_millis(10), Duration::from_millis(50));
+//! let delays = backoff.iter().take(4).collect::<Vec<_>>();
+//! assert_eq!(
+//!     delays,
+//!     [10, 20, 40, 50].map(Duration::from_millis).to_vec()
+//! );
+//! ```
+
+use std::time::Duration;
+
+use futures::Stream;
+use rand::Rng;
+
+/// The exponential backoff, see the [module docs](self).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    base: Duration,
+    cap: Duration,
+    full_jitter: bool,
+}
+
+impl Backoff {
+    /// Creates a backoff starting from `base` and doubling each time until `cap`, without
+    /// jitter.
+    pub fn new(base: Duration, cap: Duration) -> Self {
+        Self {
+            base,
+            cap,
+            full_jitter: false,
+        }
+    }
+
+    /// Picks each delay uniformly from zero to the exponential delay.
+    pub fn full_jitter(mut self) -> Self {
+        self.full_jitter = true;
+        self
+    }
+
+    pub fn base(&self) -> Duration {
+        self.base
+    }
+
+    pub fn cap(&self) -> Duration {
+        self.cap
+    }
+
+    /// Returns the delay before the `n`-th retry, starting from 0.
+    pub fn delay_of(&self, n: usize) -> Duration {
+        let delay = self
+            .base
+            .saturating_mul(1 << n.min(31) as u32)
+            .min(self.cap);
+        if self.full_jitter && !delay.is_zero() {
+            rand::thread_rng().gen_range(Duration::ZERO..=delay)
+        } else {
+            delay
+        }
+    }
+
+    /// Returns the endless iterator of the delays.
+    pub fn iter(&self) -> BackoffIter {
+        BackoffIter {
+            backoff: *self,
+            n: 0,
+        }
+    }
+
+    /// Returns the endless stream yielding the number of the retry, starting from 0, after
+    /// sleeping for its delay.
+    pub fn sleeps(&self) -> impl Stream<Item = usize> + Send + 'static {
+        futures::stream::unfold(self.iter(), |mut iter| async move {
+            let n = iter.n;
+            let delay = iter.next()?;
+            tokio::time::sleep(delay).await;
+            Some((n, iter))
+        })
+    }
+}
+
+/// The iterator of the delays of a [`Backoff`].
+#[derive(Debug, Clone)]
+pub struct BackoffIter {
+    backoff: Backoff,
+    n: usize,
+}
+
+impl Iterator for BackoffIter {
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let delay = self.backoff.delay_of(self.n);
+        self.n = self.n.saturating_add(1);
+        Some(delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+
+    use super::*;
+
+    #[test]
+    fn full_jitter() {
+        let backoff = Backoff::new(Duration::from_millis(10), Duration::from_millis(50));
+        assert_eq!(backoff.delay_of(100), Duration::from_millis(50));
+
+        let backoff = backoff.full_jitter();
+        for (n, delay) in backoff.iter().take(100).enumerate() {
+            assert!(delay <= Duration::from_millis(10 << n.min(3)).min(backoff.cap()));
+        }
+        assert_eq!(
+            Backoff::new(Duration::ZERO, Duration::ZERO)
+                .full_jitter()
+                .delay_of(3),
+            Duration::ZERO
+        );
+    }
+
+    #[tokio::test]
+    async fn sleeps() {
+        let backoff = Backoff::new(Duration::from_millis(10), Duration::from_millis(50));
+        let start = std::time::Instant::now();
+        let retries = backoff.sleeps().take(3).collect::<Vec<_>>().await;
+        assert_eq!(retries, vec![0, 1, 2]);
+        assert!(start.elapsed() >= Duration::from_millis(70));
+    }
+}
@@ -0,0 +1,211 @@
+//! Cooperative yielding in the loops which may not return to the scheduler for a long time.
+//!
+//! The loop serving a connection never returns to the scheduler when the requests are buffered,
+//! e.g. a burst of small pipelined requests, and starves the other tasks on the same worker. The
+//! [`Budget`] makes such loops yield after a number of iterations, which bounds the scheduling
+//! delay of the other tasks at the cost of a yield every `budget` iterations.
+//!
+//! The loops should also refill the budget whenever they return to the scheduler anyway, e.g. by
+//! awaiting the reads by [`Budget::reset_on_pending`], so an idle-ish connection never pays for a
+//! yield which doesn't help any other task.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use pin_project::pin_project;
+
+/// The default number of iterations before yielding.
+///
+/// A yield costs about the same as a small request, so it is negligible for the throughput of a
+/// hot connection, and the other tasks wait for at most 32 requests.
+pub const DEFAULT_YIELD_BUDGET: usize = 32;
+
+/// The number of iterations a loop can run before yielding to the scheduler.
+#[derive(Debug, Clone)]
+pub struct Budget {
+    budget: usize,
+    remaining: usize,
+}
+
+impl Budget {
+    /// Creates a [`Budget`] yielding every `budget` iterations, and `0` means never yield.
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            remaining: budget,
+        }
+    }
+
+    /// Consumes an iteration, and returns `true` if the budget is exhausted, in which case the
+    /// caller should yield, and the budget is refilled.
+    pub fn consume(&mut self) -> bool {
+        if self.budget == 0 {
+            return false;
+        }
+        self.remaining -= 1;
+        if self.remaining == 0 {
+            self.remaining = self.budget;
+            return true;
+        }
+        false
+    }
+
+    /// Refills the budget, which should be called when the task has returned to the scheduler,
+    /// e.g. an I/O is pending.
+    pub fn reset(&mut self) {
+        self.remaining = self.budget;
+    }
+
+    /// Consumes an iteration, and yields to the scheduler if the budget is exhausted.
+    pub fn tick(&mut self) -> Tick {
+        Tick {
+            yielded: !self.consume(),
+        }
+    }
+
+    /// Awaits `fut`, e.g. a read, and refills the budget whenever it is pending, in which case
+    /// the task returns to the scheduler.
+    pub fn reset_on_pending<F: Future>(&mut self, fut: F) -> ResetOnPending<'_, F> {
+        ResetOnPending { budget: self, fut }
+    }
+}
+
+impl Default for Budget {
+    fn default() -> Self {
+        Self::new(DEFAULT_YIELD_BUDGET)
+    }
+}
+
+/// The future of [`Budget::tick`].
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+#[derive(Debug)]
+pub struct Tick {
+    yielded: bool,
+}
+
+impl Future for Tick {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// The future of [`Budget::reset_on_pending`].
+#[pin_project]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+#[derive(Debug)]
+pub struct ResetOnPending<'a, F> {
+    budget: &'a mut Budget,
+    #[pin]
+    fut: F,
+}
+
+impl<F: Future> Future for ResetOnPending<'_, F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let this = self.project();
+        let poll = this.fut.poll(cx);
+        if poll.is_pending() {
+            this.budget.reset();
+        }
+        poll
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::Cell,
+        rc::Rc,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use super::*;
+
+    /// Runs a hot loop which is always ready with the budget, and returns how many times an
+    /// idle-ish task on the same worker is scheduled while the hot loop is running.
+    fn background_runs(budget: usize) -> usize {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let local = tokio::task::LocalSet::new();
+        local.block_on(&rt, async {
+            let done = Rc::new(Cell::new(false));
+            let runs = Arc::new(AtomicUsize::new(0));
+            let background = tokio::task::spawn_local({
+                let done = done.clone();
+                let runs = runs.clone();
+                async move {
+                    while !done.get() {
+                        runs.fetch_add(1, Ordering::Relaxed);
+                        tokio::task::yield_now().await;
+                    }
+                }
+            });
+            // let the background task start
+            tokio::task::yield_now().await;
+            let before = runs.load(Ordering::Relaxed);
+
+            let mut budget = Budget::new(budget);
+            for _ in 0..1024 {
+                // e.g. decoding a buffered request
+                std::future::ready(()).await;
+                budget.tick().await;
+            }
+            let during = runs.load(Ordering::Relaxed) - before;
+            done.set(true);
+            background.await.unwrap();
+            during
+        })
+    }
+
+    // the fairness between the real connections of a server is measured by the `fairness` bench
+    // of `volo-thrift`
+    #[test]
+    fn fairness() {
+        assert_eq!(background_runs(0), 0);
+        // the background task runs about once every 32 iterations of the hot loop, depending on
+        // the order in which the scheduler polls the tasks
+        assert!(background_runs(32) >= 1024 / 32 / 2);
+    }
+
+    #[test]
+    fn consume() {
+        let mut budget = Budget::new(2);
+        assert!(!budget.consume());
+        assert!(budget.consume());
+        assert!(!budget.consume());
+        budget.reset();
+        assert!(!budget.consume());
+        assert!(budget.consume());
+
+        let mut budget = Budget::new(0);
+        assert!((0..100).all(|_| !budget.consume()));
+    }
+
+    #[tokio::test]
+    async fn reset_on_pending() {
+        let mut budget = Budget::new(2);
+        assert!(!budget.consume());
+        budget.reset_on_pending(std::future::ready(())).await;
+        assert!(budget.consume());
+
+        assert!(!budget.consume());
+        budget.reset_on_pending(tokio::task::yield_now()).await;
+        assert!(!budget.consume());
+        assert!(budget.consume());
+    }
+}
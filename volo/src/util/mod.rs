@@ -1,4 +1,9 @@
+pub mod backoff;
+pub mod budget;
 pub mod buf_reader;
+#[cfg(feature = "serde")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+pub mod serde;
 
 use std::{borrow::Borrow, fmt, sync::Arc};
 
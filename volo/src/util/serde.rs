@@ -0,0 +1,183 @@
+//! The serde helpers used by the code generated with the serde derives, see `with_serde` of
+//! `volo-build`.
+
+/// Serializes the binary fields as base64 strings, by `#[serde(with = "...")]`.
+pub mod base64 {
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+    use serde::{de::Error as _, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<T, S>(bytes: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: AsRef<[u8]>,
+        S: Serializer,
+    {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: From<Vec<u8>>,
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        STANDARD.decode(s).map(T::from).map_err(D::Error::custom)
+    }
+
+    /// The optional binary fields.
+    pub mod option {
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<T, S>(bytes: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            T: AsRef<[u8]>,
+            S: Serializer,
+        {
+            match bytes {
+                Some(bytes) => super::serialize(bytes, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+        where
+            T: From<Vec<u8>>,
+            D: Deserializer<'de>,
+        {
+            #[derive(Deserialize)]
+            #[serde(transparent)]
+            struct Base64(#[serde(deserialize_with = "super::deserialize")] Vec<u8>);
+
+            Ok(Option::<Base64>::deserialize(deserializer)?.map(|b| T::from(b.0)))
+        }
+    }
+}
+
+/// Serializes the enums as their names, which are `(name, value)` pairs of the variants.
+///
+/// The values without a name are serialized as the values, and both the names and the values are
+/// accepted when deserializing.
+pub mod enum_name {
+    use std::fmt;
+
+    use serde::{
+        de::{Error, Visitor},
+        Deserializer, Serializer,
+    };
+
+    pub fn serialize<S>(value: i32, names: &[(&str, i32)], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match names.iter().find(|(_, v)| *v == value) {
+            Some((name, _)) => serializer.serialize_str(name),
+            None => serializer.serialize_i32(value),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D, names: &[(&str, i32)]) -> Result<i32, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(EnumVisitor { names })
+    }
+
+    struct EnumVisitor<'a> {
+        names: &'a [(&'a str, i32)],
+    }
+
+    impl<'de> Visitor<'de> for EnumVisitor<'_> {
+        type Value = i32;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("an enum name or value")
+        }
+
+        fn visit_str<E: Error>(self, v: &str) -> Result<i32, E> {
+            self.names
+                .iter()
+                .find(|(name, _)| *name == v)
+                .map(|(_, value)| *value)
+                .ok_or_else(|| E::custom(format!("unknown enum name `{v}`")))
+        }
+
+        fn visit_i64<E: Error>(self, v: i64) -> Result<i32, E> {
+            i32::try_from(v).map_err(E::custom)
+        }
+
+        fn visit_u64<E: Error>(self, v: u64) -> Result<i32, E> {
+            i32::try_from(v).map_err(E::custom)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    /// What the generated code looks like with `SerdeEnumRepr::Name`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct Status(i32);
+
+    const STATUS_NAMES: &[(&str, i32)] = &[("ACTIVE", 1), ("BANNED", 2)];
+
+    impl Serialize for Status {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            super::enum_name::serialize(self.0, STATUS_NAMES, serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Status {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            super::enum_name::deserialize(deserializer, STATUS_NAMES).map(Self)
+        }
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct UserInfo {
+        user_id: i64,
+        #[serde(default, skip_serializing_if = "::std::option::Option::is_none")]
+        nick_name: Option<String>,
+        #[serde(with = "super::base64")]
+        avatar: Vec<u8>,
+        #[serde(
+            default,
+            skip_serializing_if = "::std::option::Option::is_none",
+            with = "super::base64::option"
+        )]
+        cover: Option<Vec<u8>>,
+        status: Status,
+    }
+
+    #[test]
+    fn round_trip() {
+        let user = UserInfo {
+            user_id: 1,
+            nick_name: None,
+            avatar: vec![1, 2, 3],
+            cover: Some(vec![4]),
+            status: Status(1),
+        };
+        let json = serde_json::to_string(&user).unwrap();
+        assert_eq!(
+            json,
+            r#"{"userId":1,"avatar":"AQID","cover":"BA==","status":"ACTIVE"}"#
+        );
+        assert_eq!(serde_json::from_str::<UserInfo>(&json).unwrap(), user);
+
+        // unknown values and missing optional fields
+        let user = UserInfo {
+            cover: None,
+            status: Status(3),
+            ..user
+        };
+        let json = serde_json::to_string(&user).unwrap();
+        assert_eq!(json, r#"{"userId":1,"avatar":"AQID","status":3}"#);
+        assert_eq!(serde_json::from_str::<UserInfo>(&json).unwrap(), user);
+
+        assert!(serde_json::from_str::<Status>(r#""DELETED""#).is_err());
+        assert!(
+            serde_json::from_str::<UserInfo>(r#"{"userId":1,"avatar":"!","status":1}"#).is_err()
+        );
+    }
+}